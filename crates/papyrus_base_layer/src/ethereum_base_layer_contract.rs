@@ -1,5 +1,6 @@
 use std::collections::BTreeMap;
 use std::sync::Arc;
+use std::time::Duration;
 
 use async_trait::async_trait;
 use ethers::abi::{Abi, AbiEncode};
@@ -7,6 +8,7 @@ use ethers::contract::Contract;
 use ethers::prelude::{AbiError, Address, ContractError, Http, Middleware, Provider};
 use ethers::providers::ProviderError;
 use ethers::types::{I256, U256};
+use papyrus_config::converters::deserialize_seconds_to_duration;
 use papyrus_config::dumping::{ser_param, ser_required_param, SerializeConfig};
 use papyrus_config::{ParamPath, ParamPrivacyInput, SerializationType, SerializedParam};
 use serde::{Deserialize, Serialize};
@@ -40,6 +42,9 @@ pub struct EthereumBaseLayerConfig {
     // TODO(yair): consider using types.
     pub node_url: String,
     pub starknet_contract_address: String,
+    pub confirmation_depth: u64,
+    #[serde(deserialize_with = "deserialize_seconds_to_duration")]
+    pub poll_interval: Duration,
 }
 
 impl SerializeConfig for EthereumBaseLayerConfig {
@@ -57,6 +62,22 @@ impl SerializeConfig for EthereumBaseLayerConfig {
                 "Starknet contract address in ethereum.",
                 ParamPrivacyInput::Public,
             ),
+            ser_param(
+                "confirmation_depth",
+                &self.confirmation_depth,
+                "Number of ethereum blocks that must be built on top of the ethereum block from \
+                 which the Starknet contract state is read.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "poll_interval",
+                &self.poll_interval.as_secs(),
+                "Time in seconds to poll the base layer to get the latest proved block. The \
+                 polling backs off while there are no new proved blocks, up to 8 times this \
+                 interval, and returns to this interval once there are. It never polls more \
+                 often than this interval.",
+                ParamPrivacyInput::Public,
+            ),
         ])
     }
 }
@@ -66,6 +87,8 @@ impl Default for EthereumBaseLayerConfig {
         Self {
             node_url: "https://mainnet.infura.io/v3/<your_api_key>".to_string(),
             starknet_contract_address: "0xc662c410C0ECf747543f5bA90660f6ABeBD9C8c4".to_string(),
+            confirmation_depth: 0,
+            poll_interval: Duration::from_secs(10),
         }
     }
 }
@@ -4,26 +4,38 @@ mod block_hash_test;
 
 use std::iter::zip;
 
-use starknet_api::block::{BlockBody, BlockHash, BlockHeader};
+use starknet_api::block::{BlockBody, BlockHash, BlockHeader, StarknetVersion};
+use starknet_api::block_hash::block_hash_calculator::{
+    calculate_block_commitments,
+    BlockHeaderCommitments,
+    TransactionHashingData,
+    TransactionOutputForHash,
+};
 use starknet_api::core::{
     ChainId,
     EventCommitment,
+    ReceiptCommitment,
     SequencerContractAddress,
     TransactionCommitment,
 };
 use starknet_api::crypto::patricia_hash::calculate_root;
-use starknet_api::hash::StarkHash;
+use starknet_api::data_availability::L1DataAvailabilityMode;
+use starknet_api::hash::{starknet_keccak_hash, StarkHash};
+use starknet_api::state::ThinStateDiff;
 use starknet_api::transaction::{
     DeployAccountTransaction,
     Event,
     Transaction,
+    TransactionExecutionStatus,
     TransactionHash,
     TransactionOutput,
+    TransactionSignature,
 };
 use starknet_api::StarknetApiError;
 use starknet_types_core::felt::Felt;
-use starknet_types_core::hash::{Pedersen, StarkHash as CoreStarkHash};
+use starknet_types_core::hash::{Pedersen, Poseidon, StarkHash as CoreStarkHash};
 
+use crate::commitment_tree::CommitmentTree;
 use crate::transaction_hash::{ascii_as_felt, HashChain, ZERO};
 use crate::usize_into_felt;
 
@@ -31,10 +43,18 @@ use crate::usize_into_felt;
 pub enum BlockHashError {
     #[error("Header is missing data (transaction_commitment / event_commitment)")]
     MissingHeaderData,
+    #[error("The block commitments were calculated in a scheme that doesn't support proofs")]
+    UnsupportedCommitmentScheme,
+    #[error("The block body doesn't match the header commitments")]
+    CommitmentMismatch,
     #[error(transparent)]
     StarknetApiError(#[from] StarknetApiError),
 }
 
+// The first Starknet version whose block commitments are Poseidon roots, and whose header has a
+// receipt commitment.
+const POSEIDON_COMMITMENTS_STARKNET_VERSION: [u64; 3] = [0, 13, 2];
+
 #[derive(Clone, Copy, Debug, Eq, PartialEq, PartialOrd, Ord)]
 enum BlockHashVersion {
     V0,
@@ -62,25 +82,220 @@ pub fn validate_body(
     body: &BlockBody,
     transaction_commitment: &TransactionCommitment,
     event_commitment: &EventCommitment,
+) -> Result<bool, BlockHashError> {
+    validate_body_by_legacy_versions(body, Some(transaction_commitment), Some(event_commitment))
+}
+
+/// Validates the body of a starknet block against each of the commitments its header has, in the
+/// scheme of the block's Starknet version. The receipt commitment exists only from Starknet 0.13.2,
+/// so it isn't checked for older blocks.
+pub fn validate_body_commitments(
+    header: &BlockHeader,
+    body: &BlockBody,
+) -> Result<bool, BlockHashError> {
+    if !has_poseidon_commitments(&header.starknet_version) {
+        return validate_body_by_legacy_versions(
+            body,
+            header.transaction_commitment.as_ref(),
+            header.event_commitment.as_ref(),
+        );
+    }
+    let commitments = calculate_body_commitments(body);
+    Ok(header
+        .transaction_commitment
+        .map_or(true, |commitment| commitment == commitments.transaction_commitment)
+        && header
+            .event_commitment
+            .map_or(true, |commitment| commitment == commitments.event_commitment)
+        && header
+            .receipt_commitment
+            .map_or(true, |commitment| commitment == commitments.receipt_commitment))
+}
+
+/// The commitments of a block body in the Poseidon scheme of Starknet 0.13.2.
+#[derive(Clone, Debug, Eq, PartialEq)]
+pub struct BodyCommitments {
+    pub transaction_commitment: TransactionCommitment,
+    pub event_commitment: EventCommitment,
+    pub receipt_commitment: ReceiptCommitment,
+}
+
+/// Calculates the commitments of a block body in the Poseidon scheme of Starknet 0.13.2.
+pub fn calculate_body_commitments(body: &BlockBody) -> BodyCommitments {
+    // The state diff commitment and the counts aren't calculated from the body, so they're
+    // calculated over an empty diff and dropped.
+    let BlockHeaderCommitments {
+        transaction_commitment, event_commitment, receipt_commitment, ..
+    } = calculate_block_commitments(
+        &get_transactions_hashing_data(body),
+        &ThinStateDiff::default(),
+        L1DataAvailabilityMode::default(),
+    );
+    BodyCommitments { transaction_commitment, event_commitment, receipt_commitment }
+}
+
+// Returns true if the given commitments, those that are present, all match the body in one of the
+// block hash versions that preceded Starknet 0.13.2.
+fn validate_body_by_legacy_versions(
+    body: &BlockBody,
+    transaction_commitment: Option<&TransactionCommitment>,
+    event_commitment: Option<&EventCommitment>,
 ) -> Result<bool, BlockHashError> {
     for version in
         [BlockHashVersion::V3, BlockHashVersion::V2, BlockHashVersion::V1, BlockHashVersion::V0]
     {
-        let calculated_transaction_commitment =
-            calculate_transaction_commitment_by_version(body, &version)?;
-        if calculated_transaction_commitment != *transaction_commitment {
-            continue;
+        if let Some(transaction_commitment) = transaction_commitment {
+            let calculated_transaction_commitment =
+                calculate_transaction_commitment_by_version(body, &version)?;
+            if calculated_transaction_commitment != *transaction_commitment {
+                continue;
+            }
         }
-        let calculated_event_commitment =
-            calculate_event_commitment_by_version(&body.transaction_outputs, &version);
-        if calculated_event_commitment != *event_commitment {
-            continue;
+        if let Some(event_commitment) = event_commitment {
+            let calculated_event_commitment =
+                calculate_event_commitment_by_version(&body.transaction_outputs, &version);
+            if calculated_event_commitment != *event_commitment {
+                continue;
+            }
         }
         return Ok(true);
     }
     Ok(false)
 }
 
+// Versions that aren't dot-separated numbers are treated as versions that precede Starknet 0.13.2.
+fn has_poseidon_commitments(starknet_version: &StarknetVersion) -> bool {
+    let Ok(version) =
+        starknet_version.0.split('.').map(str::parse::<u64>).collect::<Result<Vec<_>, _>>()
+    else {
+        return false;
+    };
+    version.as_slice() >= POSEIDON_COMMITMENTS_STARKNET_VERSION.as_slice()
+}
+
+fn get_transactions_hashing_data(body: &BlockBody) -> Vec<TransactionHashingData> {
+    zip(&body.transactions, zip(&body.transaction_outputs, &body.transaction_hashes))
+        .map(|(transaction, (output, transaction_hash))| TransactionHashingData {
+            transaction_signature: get_optional_transaction_signature(transaction),
+            transaction_output: TransactionOutputForHash {
+                actual_fee: output.actual_fee(),
+                events: output.events().to_vec(),
+                execution_status: output.execution_status().clone(),
+                gas_consumed: output.execution_resources().gas_consumed.clone(),
+                messages_sent: output.messages_sent().clone(),
+            },
+            transaction_hash: *transaction_hash,
+        })
+        .collect()
+}
+
+// Transactions without a signature field have no signature in the Poseidon transaction leaf.
+fn get_optional_transaction_signature(transaction: &Transaction) -> Option<TransactionSignature> {
+    match transaction {
+        Transaction::Deploy(_) | Transaction::L1Handler(_) => None,
+        _ => Some(TransactionSignature(get_transaction_signature(transaction))),
+    }
+}
+
+/// The receipt and event commitment trees of a block, in the Poseidon scheme of Starknet 0.13.2.
+#[derive(Clone, Debug)]
+pub struct BlockCommitmentTrees {
+    pub receipts: CommitmentTree,
+    /// The elements that each receipt leaf is the Poseidon hash of.
+    pub receipt_leaf_preimages: Vec<Vec<Felt>>,
+    pub events: CommitmentTree,
+}
+
+/// Recalculates the receipt and event commitment trees of a block, and checks their roots against
+/// the header's commitments and against the commitments calculated by
+/// [`calculate_block_commitments`].
+/// Blocks from before Starknet 0.13.2 have no receipt commitment, so they return
+/// [`BlockHashError::UnsupportedCommitmentScheme`].
+pub fn calculate_commitment_trees(
+    header: &BlockHeader,
+    body: &BlockBody,
+) -> Result<BlockCommitmentTrees, BlockHashError> {
+    if !has_poseidon_commitments(&header.starknet_version) {
+        return Err(BlockHashError::UnsupportedCommitmentScheme);
+    }
+    let receipt_commitment = header.receipt_commitment.ok_or(BlockHashError::MissingHeaderData)?;
+    let event_commitment = header.event_commitment.ok_or(BlockHashError::MissingHeaderData)?;
+    let commitments = calculate_body_commitments(body);
+    if commitments.receipt_commitment != receipt_commitment
+        || commitments.event_commitment != event_commitment
+    {
+        return Err(BlockHashError::CommitmentMismatch);
+    }
+
+    let transactions_hashing_data = get_transactions_hashing_data(body);
+    let receipt_leaf_preimages: Vec<_> =
+        transactions_hashing_data.iter().map(get_receipt_leaf_preimage).collect();
+    let receipts = CommitmentTree::new::<Poseidon>(
+        receipt_leaf_preimages.iter().map(|preimage| Poseidon::hash_array(preimage)).collect(),
+    );
+    let event_leaves = transactions_hashing_data
+        .iter()
+        .flat_map(|transaction_data| {
+            transaction_data
+                .transaction_output
+                .events
+                .iter()
+                .map(|event| get_poseidon_event_leaf(event, &transaction_data.transaction_hash))
+        })
+        .collect();
+    let events = CommitmentTree::new::<Poseidon>(event_leaves);
+    // The leaves are hashed as in starknet_api, so the roots must be the commitments it calculated.
+    if receipts.root() != receipt_commitment.0 || events.root() != event_commitment.0 {
+        return Err(BlockHashError::CommitmentMismatch);
+    }
+    Ok(BlockCommitmentTrees { receipts, receipt_leaf_preimages, events })
+}
+
+// The elements of a receipt leaf: [
+//     transaction_hash, actual_fee, messages_sent_hash, revert_reason_hash, l2_gas_consumed (0),
+//     l1_gas_consumed, l1_data_gas_consumed
+// ].
+fn get_receipt_leaf_preimage(transaction_data: &TransactionHashingData) -> Vec<Felt> {
+    let output = &transaction_data.transaction_output;
+    let mut messages_sent_hash =
+        HashChain::new().chain(&usize_into_felt(output.messages_sent.len()));
+    for message in &output.messages_sent {
+        messages_sent_hash = messages_sent_hash
+            .chain(message.from_address.0.key())
+            .chain(&Felt::from(message.to_address))
+            .chain(&usize_into_felt(message.payload.0.len()))
+            .chain_iter(message.payload.0.iter());
+    }
+    let revert_reason_hash = match &output.execution_status {
+        TransactionExecutionStatus::Succeeded => Felt::ZERO,
+        TransactionExecutionStatus::Reverted(reverted) => {
+            starknet_keccak_hash(reverted.revert_reason.as_bytes())
+        }
+    };
+    vec![
+        transaction_data.transaction_hash.0,
+        Felt::from(output.actual_fee.0),
+        messages_sent_hash.get_poseidon_hash(),
+        revert_reason_hash,
+        Felt::ZERO,
+        Felt::from(output.gas_consumed.l1_gas),
+        Felt::from(output.gas_consumed.l1_data_gas),
+    ]
+}
+
+/// Returns the Poseidon leaf of an event in the event commitment of Starknet 0.13.2.
+pub fn get_poseidon_event_leaf(event: &Event, transaction_hash: &TransactionHash) -> Felt {
+    let event_keys: Vec<_> = event.content.keys.iter().map(|key| key.0).collect();
+    HashChain::new()
+        .chain(event.from_address.0.key())
+        .chain(&transaction_hash.0)
+        .chain(&usize_into_felt(event_keys.len()))
+        .chain_iter(event_keys.iter())
+        .chain(&usize_into_felt(event.content.data.0.len()))
+        .chain_iter(event.content.data.0.iter())
+        .get_poseidon_hash()
+}
+
 // Calculates hash of a starknet block by version, ignoring the block hash field in the given block.
 fn calculate_block_hash_by_version(
     header: &BlockHeader,
@@ -1,15 +1,26 @@
+use std::iter::zip;
+
 use assert_matches::assert_matches;
-use starknet_api::block::Block;
-use starknet_api::core::ChainId;
+use starknet_api::block::{Block, StarknetVersion};
+use starknet_api::core::{ChainId, ReceiptCommitment, TransactionCommitment};
+use starknet_api::transaction::{Event, TransactionOutput};
+use starknet_types_core::felt::Felt;
+use starknet_types_core::hash::{Poseidon, StarkHash};
 use test_utils::read_json_file;
 
 use crate::block_hash::{
     calculate_block_hash_by_version,
+    calculate_body_commitments,
+    calculate_commitment_trees,
     calculate_event_commitment_by_version,
     calculate_transaction_commitment_by_version,
+    get_poseidon_event_leaf,
+    has_poseidon_commitments,
+    validate_body_commitments,
     BlockHashError,
     BlockHashVersion,
 };
+use crate::commitment_tree::verify_proof;
 
 fn validate_block_hash_util(file_name: &str, version: BlockHashVersion) {
     let chain_id = ChainId::Mainnet;
@@ -62,3 +73,125 @@ fn test_missing_header_data() {
 
     assert_matches!(err, BlockHashError::MissingHeaderData);
 }
+
+// Returns the block of the given file with the commitments of Starknet 0.13.2, as calculated by
+// starknet_api.
+fn get_poseidon_block(file_name: &str) -> Block {
+    let mut block: Block = serde_json::from_value(read_json_file(file_name)).unwrap();
+    block.header.starknet_version = StarknetVersion("0.13.2".to_owned());
+    let commitments = calculate_body_commitments(&block.body);
+    block.header.transaction_commitment = Some(commitments.transaction_commitment);
+    block.header.event_commitment = Some(commitments.event_commitment);
+    block.header.receipt_commitment = Some(commitments.receipt_commitment);
+    block
+}
+
+#[test]
+fn test_commitment_proofs() {
+    const STEP: usize = 10;
+    let block = get_poseidon_block("block_hash.json");
+    let trees = calculate_commitment_trees(&block.header, &block.body).unwrap();
+
+    let receipt_commitment = block.header.receipt_commitment.unwrap().0;
+    for (index, (transaction_hash, preimage)) in
+        zip(&block.body.transaction_hashes, &trees.receipt_leaf_preimages).enumerate().step_by(STEP)
+    {
+        assert_eq!(preimage[0], transaction_hash.0);
+        let leaf = Poseidon::hash_array(preimage);
+        let proof = trees.receipts.proof(index).unwrap();
+        assert!(verify_proof::<Poseidon>(receipt_commitment, index, leaf, &proof));
+    }
+
+    let event_commitment = block.header.event_commitment.unwrap().0;
+    let events = zip(&block.body.transaction_outputs, &block.body.transaction_hashes).flat_map(
+        |(output, transaction_hash)| {
+            output.events().iter().map(move |event| (event, transaction_hash))
+        },
+    );
+    for (index, (event, transaction_hash)) in events.enumerate().step_by(STEP) {
+        let leaf = get_poseidon_event_leaf(event, transaction_hash);
+        let proof = trees.events.proof(index).unwrap();
+        assert!(verify_proof::<Poseidon>(event_commitment, index, leaf, &proof));
+    }
+}
+
+#[test]
+fn test_unsupported_commitment_scheme() {
+    // The block precedes Starknet 0.13.2, so it has no receipt commitment to prove against.
+    for file_name in ["block_hash.json", "deprecated_block_hash_v1.json"] {
+        let block: Block = serde_json::from_value(read_json_file(file_name)).unwrap();
+        let err = calculate_commitment_trees(&block.header, &block.body).unwrap_err();
+        assert_matches!(err, BlockHashError::UnsupportedCommitmentScheme);
+    }
+}
+
+#[test]
+fn test_commitment_mismatch() {
+    let mut block = get_poseidon_block("block_hash.json");
+    block.header.receipt_commitment = Some(ReceiptCommitment(Felt::ONE));
+
+    let err = calculate_commitment_trees(&block.header, &block.body).unwrap_err();
+    assert_matches!(err, BlockHashError::CommitmentMismatch);
+}
+
+#[test]
+fn test_validate_each_present_body_commitment() {
+    let mut block: Block = serde_json::from_value(read_json_file("block_hash.json")).unwrap();
+    assert!(validate_body_commitments(&block.header, &block.body).unwrap());
+
+    // The block precedes the receipt commitment, so it isn't checked.
+    block.header.receipt_commitment = Some(ReceiptCommitment(Felt::ONE));
+    assert!(validate_body_commitments(&block.header, &block.body).unwrap());
+
+    block.header.event_commitment = None;
+    assert!(validate_body_commitments(&block.header, &block.body).unwrap());
+    block.header.transaction_commitment = Some(TransactionCommitment(Felt::ONE));
+    assert!(!validate_body_commitments(&block.header, &block.body).unwrap());
+}
+
+#[test]
+fn test_validate_poseidon_body_commitments() {
+    let mut block: Block = serde_json::from_value(read_json_file("block_hash.json")).unwrap();
+    block.header.starknet_version = StarknetVersion("0.13.2".to_owned());
+    assert!(!validate_body_commitments(&block.header, &block.body).unwrap());
+
+    let commitments = calculate_body_commitments(&block.body);
+    block.header.transaction_commitment = Some(commitments.transaction_commitment);
+    block.header.event_commitment = Some(commitments.event_commitment);
+    block.header.receipt_commitment = Some(commitments.receipt_commitment);
+    assert!(validate_body_commitments(&block.header, &block.body).unwrap());
+
+    let mut tampered_block = block.clone();
+    tampered_block.header.receipt_commitment = Some(ReceiptCommitment(Felt::ONE));
+    assert!(!validate_body_commitments(&tampered_block.header, &tampered_block.body).unwrap());
+
+    // A single tampered event amid the valid data of the block.
+    let event = block
+        .body
+        .transaction_outputs
+        .iter_mut()
+        .find_map(|output| output_events_mut(output).first_mut())
+        .expect("The block should have events");
+    event.content.data.0.push(Felt::ONE);
+    assert!(!validate_body_commitments(&block.header, &block.body).unwrap());
+}
+
+fn output_events_mut(output: &mut TransactionOutput) -> &mut Vec<Event> {
+    match output {
+        TransactionOutput::Declare(output) => &mut output.events,
+        TransactionOutput::Deploy(output) => &mut output.events,
+        TransactionOutput::DeployAccount(output) => &mut output.events,
+        TransactionOutput::Invoke(output) => &mut output.events,
+        TransactionOutput::L1Handler(output) => &mut output.events,
+    }
+}
+
+#[test]
+fn test_poseidon_commitments_starknet_versions() {
+    for version in ["0.13.2", "0.13.2.1", "0.13.10", "0.14.0"] {
+        assert!(has_poseidon_commitments(&StarknetVersion(version.to_owned())), "{version}");
+    }
+    for version in ["0.13.1.1", "0.9.0", "0.0.0", "test"] {
+        assert!(!has_poseidon_commitments(&StarknetVersion(version.to_owned())), "{version}");
+    }
+}
@@ -0,0 +1,175 @@
+#[cfg(test)]
+#[path = "commitment_tree_test.rs"]
+mod commitment_tree_test;
+
+use serde::{Deserialize, Serialize};
+use starknet_types_core::felt::Felt;
+use starknet_types_core::hash::StarkHash;
+
+// The height of the Patricia trees of the block commitments. The leaves are keyed by their index.
+const TREE_HEIGHT: u8 = 64;
+
+/// A node on the path from the root of a [`CommitmentTree`] to one of its leaves.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub enum MerkleNode {
+    /// A node with two children. Its hash is `H(left, right)`.
+    Binary { left: Felt, right: Felt },
+    /// A node whose single child is reached by following the `length` bits of `path`. Its hash
+    /// is `H(child, path) + length`.
+    Edge { child: Felt, path: Felt, length: u8 },
+}
+
+#[derive(Clone, Debug)]
+enum Node {
+    Leaf,
+    Binary { left: usize, right: usize },
+    Edge { child: usize, path: u64, length: u8 },
+}
+
+/// The Patricia tree of a commitment of a block, e.g. its receipt or event commitment.
+///
+/// The root is the same as the one returned by
+/// [`calculate_root`](starknet_api::crypto::patricia_hash::calculate_root), but the inner nodes
+/// are kept so that inclusion proofs can be given for the leaves.
+#[derive(Clone, Debug)]
+pub struct CommitmentTree {
+    leaves: Vec<Felt>,
+    // The nodes of the tree with their hashes. The root is the last node.
+    nodes: Vec<(Node, Felt)>,
+}
+
+impl CommitmentTree {
+    pub fn new<H: StarkHash>(leaves: Vec<Felt>) -> Self {
+        let mut tree = Self { leaves, nodes: Vec::new() };
+        if !tree.leaves.is_empty() {
+            let n_leaves = u64::try_from(tree.leaves.len()).expect("Too many leaves");
+            tree.build::<H>(0, n_leaves, 0);
+        }
+        tree
+    }
+
+    /// Returns the commitment of the tree. The commitment of an empty tree is zero.
+    pub fn root(&self) -> Felt {
+        self.nodes.last().map(|(_, hash)| *hash).unwrap_or(Felt::ZERO)
+    }
+
+    pub fn leaves(&self) -> &[Felt] {
+        &self.leaves
+    }
+
+    /// Returns the nodes on the path from the root to the leaf at the given index, or None if
+    /// there is no such leaf.
+    pub fn proof(&self, index: usize) -> Option<Vec<MerkleNode>> {
+        if index >= self.leaves.len() {
+            return None;
+        }
+        let key = u64::try_from(index).ok()?;
+        let mut proof = Vec::new();
+        let mut node_index = self.nodes.len() - 1;
+        let mut height = 0;
+        loop {
+            match self.nodes[node_index].0 {
+                Node::Leaf => return Some(proof),
+                Node::Binary { left, right } => {
+                    proof.push(MerkleNode::Binary {
+                        left: self.nodes[left].1,
+                        right: self.nodes[right].1,
+                    });
+                    node_index = if get_bit(key, height) { right } else { left };
+                    height += 1;
+                }
+                Node::Edge { child, path, length } => {
+                    proof.push(MerkleNode::Edge {
+                        child: self.nodes[child].1,
+                        path: Felt::from(path),
+                        length,
+                    });
+                    node_index = child;
+                    height += length;
+                }
+            }
+        }
+    }
+
+    // Builds the sub tree of the leaves in [start, end), whose keys share their first `height`
+    // bits, and returns the index of its root.
+    fn build<H: StarkHash>(&mut self, start: u64, end: u64, height: u8) -> usize {
+        if height == TREE_HEIGHT {
+            let leaf = self.leaves[usize::try_from(start).expect("Leaf index should fit usize")];
+            return self.push(Node::Leaf, leaf);
+        }
+
+        let last = end - 1;
+        let length = match start ^ last {
+            0 => TREE_HEIGHT - height,
+            diff => u8::try_from(diff.leading_zeros()).expect("At most 64 bits") - height,
+        };
+        if length > 0 {
+            let child = self.build::<H>(start, end, height + length);
+            let path = get_bits(start, height, length);
+            let hash = H::hash(&self.nodes[child].1, &Felt::from(path)) + Felt::from(length);
+            return self.push(Node::Edge { child, path, length }, hash);
+        }
+
+        // The first key has a zero at the current bit and the last key has a one. Since the keys
+        // are consecutive, the right sub tree starts at the first key with a one at that bit.
+        let bit = 1_u64 << (TREE_HEIGHT - 1 - height);
+        let middle = (start & !(bit - 1)) | bit;
+        let left = self.build::<H>(start, middle, height + 1);
+        let right = self.build::<H>(middle, end, height + 1);
+        let hash = H::hash(&self.nodes[left].1, &self.nodes[right].1);
+        self.push(Node::Binary { left, right }, hash)
+    }
+
+    fn push(&mut self, node: Node, hash: Felt) -> usize {
+        self.nodes.push((node, hash));
+        self.nodes.len() - 1
+    }
+}
+
+/// Verifies that the given proof is a path from the root to the leaf at the given index.
+pub fn verify_proof<H: StarkHash>(
+    root: Felt,
+    index: usize,
+    leaf: Felt,
+    proof: &[MerkleNode],
+) -> bool {
+    let Ok(key) = u64::try_from(index) else {
+        return false;
+    };
+    let mut expected_hash = root;
+    let mut height = 0;
+    for node in proof {
+        match node {
+            MerkleNode::Binary { left, right } => {
+                if height == TREE_HEIGHT || H::hash(left, right) != expected_hash {
+                    return false;
+                }
+                expected_hash = if get_bit(key, height) { *right } else { *left };
+                height += 1;
+            }
+            MerkleNode::Edge { child, path, length } => {
+                if *length == 0
+                    || *length > TREE_HEIGHT - height
+                    || *path != Felt::from(get_bits(key, height, *length))
+                    || H::hash(child, path) + Felt::from(*length) != expected_hash
+                {
+                    return false;
+                }
+                expected_hash = *child;
+                height += length;
+            }
+        }
+    }
+    height == TREE_HEIGHT && expected_hash == leaf
+}
+
+// Returns the bit of the key at the given height, where height 0 is the most significant bit.
+fn get_bit(key: u64, height: u8) -> bool {
+    (key >> (TREE_HEIGHT - 1 - height)) & 1 == 1
+}
+
+// Returns the `length` bits of the key that start at the given height, as a number.
+fn get_bits(key: u64, height: u8, length: u8) -> u64 {
+    (key << height) >> (TREE_HEIGHT - length)
+}
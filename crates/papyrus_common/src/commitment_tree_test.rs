@@ -0,0 +1,55 @@
+use starknet_api::crypto::patricia_hash::calculate_root;
+use starknet_types_core::felt::Felt;
+use starknet_types_core::hash::Pedersen;
+
+use crate::commitment_tree::{verify_proof, CommitmentTree, MerkleNode};
+
+fn get_leaves(n_leaves: u64) -> Vec<Felt> {
+    (0..n_leaves).map(|i| Felt::from(i * 7 + 3)).collect()
+}
+
+#[test]
+fn root_matches_calculate_root() {
+    for n_leaves in [0, 1, 2, 3, 4, 5, 8, 17, 100] {
+        let leaves = get_leaves(n_leaves);
+        let tree = CommitmentTree::new::<Pedersen>(leaves.clone());
+        assert_eq!(tree.root(), calculate_root::<Pedersen>(leaves), "n_leaves: {n_leaves}");
+    }
+}
+
+#[test]
+fn proofs_are_verified() {
+    for n_leaves in [1, 2, 3, 5, 17] {
+        let tree = CommitmentTree::new::<Pedersen>(get_leaves(n_leaves));
+        for (index, leaf) in tree.leaves().iter().enumerate() {
+            let proof = tree.proof(index).unwrap();
+            assert!(verify_proof::<Pedersen>(tree.root(), index, *leaf, &proof));
+        }
+        assert!(tree.proof(tree.leaves().len()).is_none());
+    }
+}
+
+#[test]
+fn single_leaf_proof_is_a_full_edge() {
+    let tree = CommitmentTree::new::<Pedersen>(vec![Felt::ONE]);
+    assert_eq!(
+        tree.proof(0).unwrap(),
+        vec![MerkleNode::Edge { child: Felt::ONE, path: Felt::ZERO, length: 64 }]
+    );
+}
+
+#[test]
+fn wrong_proofs_are_rejected() {
+    let tree = CommitmentTree::new::<Pedersen>(get_leaves(5));
+    let leaf = tree.leaves()[2];
+    let proof = tree.proof(2).unwrap();
+
+    // Wrong leaf.
+    assert!(!verify_proof::<Pedersen>(tree.root(), 2, tree.leaves()[3], &proof));
+    // Wrong index.
+    assert!(!verify_proof::<Pedersen>(tree.root(), 3, leaf, &proof));
+    // Wrong root.
+    assert!(!verify_proof::<Pedersen>(Felt::ZERO, 2, leaf, &proof));
+    // Truncated proof.
+    assert!(!verify_proof::<Pedersen>(tree.root(), 2, leaf, &proof[..proof.len() - 1]));
+}
@@ -0,0 +1,56 @@
+//! The live state of the node's consensus, shared between the consensus task and the components
+//! that present it.
+
+use std::collections::BTreeMap;
+use std::sync::{Arc, RwLock};
+
+use serde::{Deserialize, Serialize};
+use starknet_api::block::BlockNumber;
+use starknet_api::core::ContractAddress;
+
+/// The number of consensus messages of a single type the node sent and received.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ConsensusMessageCounts {
+    pub sent: u64,
+    pub received: u64,
+    /// The received messages that were dropped since they were invalid.
+    pub invalid: u64,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ConsensusStatus {
+    /// The id of the node as a validator.
+    pub validator_id: ContractAddress,
+    /// The height consensus is at. None before consensus starts its first height.
+    pub height: Option<BlockNumber>,
+    /// The round of the current height.
+    pub round: u32,
+    /// Whether the node is the proposer of the current round.
+    pub is_proposer: bool,
+    /// Whether the current round made no progress for longer than the stuck round timeout.
+    pub is_stuck: bool,
+    /// The last height consensus decided on.
+    pub last_decided_height: Option<BlockNumber>,
+    /// The time, in seconds, from the start of the deciding round to the last decision.
+    pub last_decision_latency_secs: Option<f64>,
+    /// The counts of the messages by their type.
+    pub messages: BTreeMap<String, ConsensusMessageCounts>,
+}
+
+impl ConsensusStatus {
+    pub fn new(validator_id: ContractAddress) -> Self {
+        Self {
+            validator_id,
+            height: None,
+            round: 0,
+            is_proposer: false,
+            is_stuck: false,
+            last_decided_height: None,
+            last_decision_latency_secs: None,
+            messages: BTreeMap::new(),
+        }
+    }
+}
+
+/// The status of consensus. None while the node doesn't run consensus as a validator.
+pub type SharedConsensusStatus = Arc<RwLock<Option<ConsensusStatus>>>;
@@ -0,0 +1,131 @@
+#[cfg(test)]
+#[path = "global_root_test.rs"]
+mod global_root_test;
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use indexmap::IndexMap;
+use lazy_static::lazy_static;
+use starknet_api::core::{ClassHash, CompiledClassHash, ContractAddress, GlobalRoot, Nonce};
+use starknet_api::state::{StorageKey, ThinStateDiff};
+use starknet_types_core::felt::Felt;
+use starknet_types_core::hash::{Pedersen, Poseidon, StarkHash};
+
+// The height of the Patricia trees of the state. The leaves are keyed by contract addresses,
+// storage keys and class hashes, which have 251 bits.
+const TREE_HEIGHT: usize = 251;
+// The number of the most significant bits of a felt that aren't part of its key.
+const KEY_OFFSET: usize = 256 - TREE_HEIGHT;
+
+lazy_static! {
+    static ref CONTRACT_CLASS_LEAF_V0: Felt = Felt::from_bytes_be_slice(b"CONTRACT_CLASS_LEAF_V0");
+    static ref STARKNET_STATE_V0: Felt = Felt::from_bytes_be_slice(b"STARKNET_STATE_V0");
+}
+
+/// Calculates the global root of the state that the given state diff creates when it's applied to
+/// an empty state.
+pub fn calculate_global_root(state_diff: &ThinStateDiff) -> GlobalRoot {
+    let contracts_root = calculate_contracts_root(state_diff);
+    let classes_root = calculate_classes_root(&state_diff.declared_classes);
+    // The classes tree was added to the state after the contracts tree, so the root of a state
+    // without classes stayed the root of its contracts tree.
+    if classes_root == Felt::ZERO {
+        return GlobalRoot(contracts_root);
+    }
+    GlobalRoot(Poseidon::hash_array(&[*STARKNET_STATE_V0, contracts_root, classes_root]))
+}
+
+fn calculate_contracts_root(state_diff: &ThinStateDiff) -> Felt {
+    let mut class_hashes: BTreeMap<ContractAddress, ClassHash> = BTreeMap::new();
+    class_hashes.extend(state_diff.deployed_contracts.iter().map(|(k, v)| (*k, *v)));
+    class_hashes.extend(state_diff.replaced_classes.iter().map(|(k, v)| (*k, *v)));
+    // Contracts that only have storage or a nonce, such as system contracts, have no class.
+    let addresses = class_hashes
+        .keys()
+        .chain(state_diff.storage_diffs.keys())
+        .chain(state_diff.nonces.keys())
+        .copied()
+        .collect::<BTreeSet<_>>();
+    let leaves = addresses
+        .into_iter()
+        .map(|address| {
+            let class_hash = class_hashes.get(&address).copied().unwrap_or_default();
+            let storage_root = state_diff
+                .storage_diffs
+                .get(&address)
+                .map(calculate_storage_root)
+                .unwrap_or(Felt::ZERO);
+            let nonce = state_diff.nonces.get(&address).copied().unwrap_or_default();
+            (*address.0.key(), contract_state_hash(class_hash, storage_root, nonce))
+        })
+        .collect();
+    calculate_root::<Pedersen>(leaves)
+}
+
+fn calculate_storage_root(storage_diff: &IndexMap<StorageKey, Felt>) -> Felt {
+    calculate_root::<Pedersen>(
+        storage_diff.iter().map(|(key, value)| (*key.0.key(), *value)).collect(),
+    )
+}
+
+fn calculate_classes_root(declared_classes: &IndexMap<ClassHash, CompiledClassHash>) -> Felt {
+    calculate_root::<Poseidon>(
+        declared_classes
+            .iter()
+            .map(|(class_hash, compiled_class_hash)| {
+                (class_hash.0, Poseidon::hash(&CONTRACT_CLASS_LEAF_V0, &compiled_class_hash.0))
+            })
+            .collect(),
+    )
+}
+
+fn contract_state_hash(class_hash: ClassHash, storage_root: Felt, nonce: Nonce) -> Felt {
+    const CONTRACT_STATE_HASH_VERSION: Felt = Felt::ZERO;
+    let hash = Pedersen::hash(&class_hash.0, &storage_root);
+    let hash = Pedersen::hash(&hash, &nonce.0);
+    Pedersen::hash(&hash, &CONTRACT_STATE_HASH_VERSION)
+}
+
+// Calculates the root of a Patricia tree with the given leaves, keyed by their first element.
+// Leaves whose value is zero aren't part of the tree. The root of an empty tree is zero.
+fn calculate_root<H: StarkHash>(leaves: Vec<(Felt, Felt)>) -> Felt {
+    let mut leaves = leaves
+        .into_iter()
+        .filter(|(_, value)| *value != Felt::ZERO)
+        .map(|(key, value)| (key.to_bits_be(), value))
+        .collect::<Vec<_>>();
+    if leaves.is_empty() {
+        return Felt::ZERO;
+    }
+    leaves.sort_by(|(first_key, _), (second_key, _)| first_key.cmp(second_key));
+    subtree_hash::<H>(&leaves, 0)
+}
+
+// Returns the hash of the node at the given height whose subtree contains exactly the given
+// leaves, which are sorted by their keys. A node with one child is an edge node, whose hash is
+// `H(child, path) + length`, where path is the `length` bits of the keys below it. A node with two
+// children is a binary node, whose hash is `H(left, right)`.
+fn subtree_hash<H: StarkHash>(leaves: &[([bool; 256], Felt)], height: usize) -> Felt {
+    if height == TREE_HEIGHT {
+        return leaves.first().expect("A leaf should have a value").1;
+    }
+    let (first_key, _) = leaves.first().expect("A subtree should have leaves");
+    let (last_key, _) = leaves.last().expect("A subtree should have leaves");
+    // The keys are sorted, so the common prefix of all of them is the common prefix of the first
+    // and the last.
+    let first_different_height = (height..TREE_HEIGHT)
+        .find(|height| first_key[KEY_OFFSET + height] != last_key[KEY_OFFSET + height])
+        .unwrap_or(TREE_HEIGHT);
+    if first_different_height == height {
+        let split_index = leaves.partition_point(|(key, _)| !key[KEY_OFFSET + height]);
+        let left = subtree_hash::<H>(&leaves[..split_index], height + 1);
+        let right = subtree_hash::<H>(&leaves[split_index..], height + 1);
+        return H::hash(&left, &right);
+    }
+    let child = subtree_hash::<H>(leaves, first_different_height);
+    let path = first_key[KEY_OFFSET + height..KEY_OFFSET + first_different_height]
+        .iter()
+        .fold(Felt::ZERO, |path, bit| path * Felt::TWO + Felt::from(u8::from(*bit)));
+    let length = first_different_height - height;
+    H::hash(&child, &path) + Felt::from(length)
+}
@@ -0,0 +1,90 @@
+use indexmap::indexmap;
+use starknet_api::core::{ClassHash, CompiledClassHash, ContractAddress, GlobalRoot, Nonce};
+use starknet_api::state::{StorageKey, ThinStateDiff};
+use starknet_types_core::felt::Felt;
+use starknet_types_core::hash::{Pedersen, Poseidon, StarkHash};
+
+use crate::global_root::calculate_global_root;
+
+const HEIGHT: u64 = 251;
+
+fn contract_state_hash(class_hash: Felt, storage_root: Felt, nonce: Felt) -> Felt {
+    let hash = Pedersen::hash(&Pedersen::hash(&class_hash, &storage_root), &nonce);
+    Pedersen::hash(&hash, &Felt::ZERO)
+}
+
+// The root of a tree with a single leaf is an edge node whose path is the whole key of the leaf.
+fn single_leaf_root<H: StarkHash>(key: Felt, value: Felt) -> Felt {
+    H::hash(&value, &key) + Felt::from(HEIGHT)
+}
+
+#[test]
+fn empty_state_has_zero_root() {
+    assert_eq!(calculate_global_root(&ThinStateDiff::default()), GlobalRoot(Felt::ZERO));
+}
+
+#[test]
+fn state_without_classes_has_the_root_of_its_contracts_tree() {
+    let address = ContractAddress::from(5_u128);
+    let storage_key = StorageKey::from(3_u128);
+    let state_diff = ThinStateDiff {
+        deployed_contracts: indexmap! { address => ClassHash(Felt::from(7_u8)) },
+        storage_diffs: indexmap! { address => indexmap! { storage_key => Felt::from(9_u8) } },
+        nonces: indexmap! { address => Nonce(Felt::ONE) },
+        ..Default::default()
+    };
+
+    let storage_root = single_leaf_root::<Pedersen>(Felt::from(3_u8), Felt::from(9_u8));
+    let contract_hash = contract_state_hash(Felt::from(7_u8), storage_root, Felt::ONE);
+    let expected_root = single_leaf_root::<Pedersen>(Felt::from(5_u8), contract_hash);
+    assert_eq!(calculate_global_root(&state_diff), GlobalRoot(expected_root));
+}
+
+#[test]
+fn leaves_that_differ_in_their_last_bit_are_children_of_a_binary_node() {
+    let address = ContractAddress::from(1_u128);
+    let state_diff = ThinStateDiff {
+        storage_diffs: indexmap! {
+            address => indexmap! {
+                StorageKey::from(1_u128) => Felt::from(11_u8),
+                StorageKey::from(0_u128) => Felt::from(10_u8),
+                // Zero values aren't part of the tree.
+                StorageKey::from(2_u128) => Felt::ZERO,
+            },
+        },
+        ..Default::default()
+    };
+
+    // An edge of all the bits but the last, from the root to the binary node.
+    let binary_node = Pedersen::hash(&Felt::from(10_u8), &Felt::from(11_u8));
+    let storage_root = Pedersen::hash(&binary_node, &Felt::ZERO) + Felt::from(HEIGHT - 1);
+    let contract_hash = contract_state_hash(Felt::ZERO, storage_root, Felt::ZERO);
+    let expected_root = single_leaf_root::<Pedersen>(Felt::ONE, contract_hash);
+    assert_eq!(calculate_global_root(&state_diff), GlobalRoot(expected_root));
+}
+
+#[test]
+fn state_with_classes_combines_the_contracts_and_classes_trees() {
+    let address = ContractAddress::from(2_u128);
+    let class_hash = ClassHash(Felt::from(4_u8));
+    let compiled_class_hash = CompiledClassHash(Felt::from(6_u8));
+    let state_diff = ThinStateDiff {
+        deployed_contracts: indexmap! { address => class_hash },
+        declared_classes: indexmap! { class_hash => compiled_class_hash },
+        ..Default::default()
+    };
+
+    let contract_hash = contract_state_hash(Felt::from(4_u8), Felt::ZERO, Felt::ZERO);
+    let contracts_root = single_leaf_root::<Pedersen>(Felt::from(2_u8), contract_hash);
+    let class_leaf = Poseidon::hash(
+        &Felt::from_bytes_be_slice(b"CONTRACT_CLASS_LEAF_V0"),
+        &compiled_class_hash.0,
+    );
+    let classes_root = single_leaf_root::<Poseidon>(Felt::from(4_u8), class_leaf);
+    let expected_root = Poseidon::hash_array(&[
+        Felt::from_bytes_be_slice(b"STARKNET_STATE_V0"),
+        contracts_root,
+        classes_root,
+    ]);
+    assert_eq!(calculate_global_root(&state_diff), GlobalRoot(expected_root));
+}
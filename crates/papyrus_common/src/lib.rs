@@ -4,13 +4,17 @@ use starknet_types_core::felt::Felt;
 
 pub mod block_hash;
 pub mod class_hash;
+pub mod commitment_tree;
+pub mod consensus_status;
 pub mod deprecated_class_abi;
+pub mod global_root;
 pub mod metrics;
 pub mod pending_classes;
 pub mod state;
 pub mod state_diff_commitment;
 pub mod storage_query;
 pub mod transaction_hash;
+pub mod unix_socket;
 
 #[derive(Copy, Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
 pub struct BlockHashAndNumber {
@@ -27,6 +31,30 @@ pub struct TransactionOptions {
     pub only_query: bool,
 }
 
+/// Returns the size in bytes of the JSON serialization of the given value. This is used as an
+/// approximation of the memory the value takes.
+pub fn approximate_size_in_bytes<T: Serialize + ?Sized>(value: &T) -> usize {
+    let mut counter = ByteCounter::default();
+    match serde_json::to_writer(&mut counter, value) {
+        Ok(()) => counter.0,
+        Err(_) => 0,
+    }
+}
+
+#[derive(Default)]
+struct ByteCounter(usize);
+
+impl std::io::Write for ByteCounter {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0 += buf.len();
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
 pub(crate) fn usize_into_felt(u: usize) -> Felt {
     u128::try_from(u).expect("Expect at most 128 bits").into()
 }
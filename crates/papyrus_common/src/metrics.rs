@@ -33,6 +33,221 @@ pub const PAPYRUS_NUM_ACTIVE_INBOUND_SESSIONS: &str = "papyrus_num_active_inboun
 /// The number of active sessions this peer has in which it requests data.
 pub const PAPYRUS_NUM_ACTIVE_OUTBOUND_SESSIONS: &str = "papyrus_num_active_outbound_sessions";
 
+/// The fraction of the limit of inbound sessions that is taken by sessions whose query is served.
+pub const PAPYRUS_INBOUND_SESSION_POOL_UTILIZATION: &str =
+    "papyrus_inbound_session_pool_utilization";
+
+/// The fraction of the limit of outbound sessions that is taken by sent queries.
+pub const PAPYRUS_OUTBOUND_SESSION_POOL_UTILIZATION: &str =
+    "papyrus_outbound_session_pool_utilization";
+
+/// The number of local queries waiting for an outbound session slot. Labeled by the priority.
+pub const PAPYRUS_NUM_PENDING_OUTBOUND_QUERIES: &str = "papyrus_num_pending_outbound_queries";
+
+/// The number of local queries that weren't sent since they shared the session of an identical
+/// query. Labeled by the protocol.
+pub const PAPYRUS_NUM_DEDUPLICATED_OUTBOUND_QUERIES: &str =
+    "papyrus_num_deduplicated_outbound_queries";
+
+/// The number of connections denied since the peer isn't allowed or is denied by the network
+/// config. Labeled by the direction of the connection.
+pub const PAPYRUS_NETWORK_DENIED_CONNECTIONS: &str = "papyrus_network_denied_connections";
+
+/// The time, in seconds, the network manager spent handling each event, during which it didn't
+/// poll the swarm.
+pub const PAPYRUS_NETWORK_LOOP_ITERATION_DURATION_SECS: &str =
+    "papyrus_network_loop_iteration_duration_secs";
+
+/// An overestimate of the memory, in bytes, of the items the network buffered for the node's
+/// components that they didn't take yet.
+pub const PAPYRUS_NETWORK_MEMORY_USAGE_BYTES: &str = "papyrus_network_memory_usage_bytes";
+
+/// The number of bytes of responses to inbound p2p queries whose sending was delayed to keep the
+/// node's serving bandwidth limits.
+pub const PAPYRUS_NETWORK_THROTTLED_BYTES: &str = "papyrus_network_throttled_bytes";
+
+/// The time, in seconds, the last response to an inbound p2p query waited before it was sent to
+/// keep the node's serving bandwidth limits.
+pub const PAPYRUS_NETWORK_THROTTLE_DELAY_SECS: &str = "papyrus_network_throttle_delay_secs";
+
+/// The sizes in bytes of the messages the node sent and received. Labeled by the transport,
+/// gossipsub or sqmr, by the name of the topic or the protocol and by the direction, sent or
+/// received.
+pub const PAPYRUS_NETWORK_MESSAGE_SIZE_BYTES: &str = "papyrus_network_message_size_bytes";
+
+/// The number of messages that were rejected since they exceeded their size limit. Labeled like
+/// the message sizes.
+pub const PAPYRUS_NETWORK_OVERSIZED_MESSAGES: &str = "papyrus_network_oversized_messages";
+
+/// The round trip time, in seconds, of the pings to a peer, smoothed over the recent pings.
+/// Labeled by the peer id.
+pub const PAPYRUS_PEER_PING_RTT_SECS: &str = "papyrus_peer_ping_rtt_secs";
+
+/// The number of pings a peer failed to answer. Labeled by the peer id.
+pub const PAPYRUS_PEER_LOST_PINGS: &str = "papyrus_peer_lost_pings";
+
+/// The approximate size, in bytes, of the pending classes and compiled classes kept in memory.
+pub const PAPYRUS_PENDING_CLASSES_SIZE_BYTES: &str = "papyrus_pending_classes_size_bytes";
+
+/// The approximate size, in bytes, of the transactions of the pending block kept in memory.
+pub const PAPYRUS_PENDING_TRANSACTIONS_SIZE_BYTES: &str = "papyrus_pending_transactions_size_bytes";
+
+/// The time, in seconds, since the pending data was last fetched from the pending source.
+pub const PAPYRUS_PENDING_DATA_AGE_SECONDS: &str = "papyrus_pending_data_age_seconds";
+
+/// The number of pending classes and compiled classes removed from memory to stay under the cap.
+pub const PAPYRUS_PENDING_CLASSES_EVICTIONS: &str = "papyrus_pending_classes_evictions";
+
+/// The duration, in seconds, of each phase of the last proposal built by the consensus dry run.
+/// Labeled by the phase.
+pub const PAPYRUS_CONSENSUS_DRY_RUN_PHASE_DURATION_SECS: &str =
+    "papyrus_consensus_dry_run_phase_duration_secs";
+
+/// The number of transactions in the last proposal built by the consensus dry run.
+pub const PAPYRUS_CONSENSUS_DRY_RUN_NUM_TRANSACTIONS: &str =
+    "papyrus_consensus_dry_run_num_transactions";
+
+/// The size, in bytes, of the encoding of the last proposal built by the consensus dry run.
+pub const PAPYRUS_CONSENSUS_DRY_RUN_PROPOSAL_SIZE_BYTES: &str =
+    "papyrus_consensus_dry_run_proposal_size_bytes";
+
+/// The number of blocks consensus decided on that differ from the synced block of the same height.
+pub const PAPYRUS_CONSENSUS_DECISION_DIVERGENCES: &str = "papyrus_consensus_decision_divergences";
+
+/// The number of heights consensus skipped after learning that the network is ahead of it.
+pub const PAPYRUS_CONSENSUS_SKIPPED_HEIGHTS: &str = "papyrus_consensus_skipped_heights";
+
+/// The number of received consensus messages that were dropped since they weren't signed by the
+/// validator that sent them.
+pub const PAPYRUS_CONSENSUS_UNAUTHENTICATED_MESSAGES: &str =
+    "papyrus_consensus_unauthenticated_messages";
+
+/// The height consensus is at.
+pub const PAPYRUS_CONSENSUS_HEIGHT: &str = "papyrus_consensus_height";
+
+/// The round of the height consensus is at.
+pub const PAPYRUS_CONSENSUS_ROUND: &str = "papyrus_consensus_round";
+
+/// The number of consensus messages the node sent. Labeled by the message type.
+pub const PAPYRUS_CONSENSUS_SENT_MESSAGES: &str = "papyrus_consensus_sent_messages";
+
+/// The number of consensus messages the node received. Labeled by the message type.
+pub const PAPYRUS_CONSENSUS_RECEIVED_MESSAGES: &str = "papyrus_consensus_received_messages";
+
+/// The number of received consensus messages that were dropped since they were invalid. Labeled by
+/// the message type.
+pub const PAPYRUS_CONSENSUS_INVALID_MESSAGES: &str = "papyrus_consensus_invalid_messages";
+
+/// The time, in seconds, from the start of a round to the decision of its height.
+pub const PAPYRUS_CONSENSUS_DECISION_LATENCY_SECS: &str = "papyrus_consensus_decision_latency_secs";
+
+/// 1 if the current consensus round made no progress for longer than the stuck round timeout, and 0
+/// otherwise.
+pub const PAPYRUS_CONSENSUS_STUCK_ROUND: &str = "papyrus_consensus_stuck_round";
+
+/// The number of inbound p2p queries this node served. Labeled by the protocol.
+pub const PAPYRUS_INBOUND_QUERIES: &str = "papyrus_inbound_queries";
+
+/// The number of data items this node sent in response to inbound p2p queries. Labeled by the
+/// protocol.
+pub const PAPYRUS_INBOUND_QUERY_ITEMS_SERVED: &str = "papyrus_inbound_query_items_served";
+
+/// The number of bytes of encoded data items this node sent in response to inbound p2p queries.
+/// Labeled by the protocol.
+pub const PAPYRUS_INBOUND_QUERY_BYTES_SERVED: &str = "papyrus_inbound_query_bytes_served";
+
+/// The number of inbound p2p queries whose response was ended before all the blocks they asked
+/// for because it reached the node's response limits. Labeled by the protocol.
+pub const PAPYRUS_INBOUND_QUERIES_LIMITED: &str = "papyrus_inbound_queries_limited";
+
+/// The number of inbound p2p queries whose response was ended before a block whose data can't be
+/// encoded. Labeled by the protocol.
+pub const PAPYRUS_INBOUND_QUERIES_WITH_UNENCODABLE_DATA: &str =
+    "papyrus_inbound_queries_with_unencodable_data";
+
+/// The number of inbound p2p queries that failed since the storage couldn't be read or is
+/// corrupted, or due to an internal error. Labeled by the protocol and the kind of the failure.
+pub const PAPYRUS_INBOUND_QUERY_STORAGE_FAILURES: &str = "papyrus_inbound_query_storage_failures";
+
+/// Whether the node stopped serving a protocol after a storage failure, until a health check
+/// passes. Labeled by the protocol.
+pub const PAPYRUS_INBOUND_PROTOCOL_STOPPED: &str = "papyrus_inbound_protocol_stopped";
+
+/// The number of blocks of inbound p2p queries that were served from the response cache. Labeled by
+/// the protocol.
+pub const PAPYRUS_INBOUND_QUERY_CACHE_HITS: &str = "papyrus_inbound_query_cache_hits";
+
+/// The number of blocks of inbound p2p queries that weren't in the response cache, so they were
+/// read from the storage. Labeled by the protocol.
+pub const PAPYRUS_INBOUND_QUERY_CACHE_MISSES: &str = "papyrus_inbound_query_cache_misses";
+
+/// The number of inbound p2p queries the server didn't start responding to yet. Labeled by the
+/// protocol.
+pub const PAPYRUS_NUM_PENDING_INBOUND_QUERIES: &str = "papyrus_num_pending_inbound_queries";
+
+/// The number of inbound p2p queries whose session was closed right away since too many queries of
+/// their protocol were pending. Labeled by the protocol.
+pub const PAPYRUS_INBOUND_QUERIES_REJECTED_ON_OVERLOAD: &str =
+    "papyrus_inbound_queries_rejected_on_overload";
+
+/// The number of inbound p2p sessions that were closed right away since the node reached its limit
+/// of inbound sessions. Labeled by the limit, global or per_peer.
+pub const PAPYRUS_INBOUND_SESSIONS_REJECTED_ON_LIMIT: &str =
+    "papyrus_inbound_sessions_rejected_on_limit";
+
+/// The number of shards of blocks whose state diffs the p2p sync is downloading in parallel.
+pub const PAPYRUS_P2P_SYNC_ACTIVE_STATE_DIFF_SHARDS: &str =
+    "papyrus_p2p_sync_active_state_diff_shards";
+
+/// The number of state diffs the p2p sync downloaded that wait for the state diffs of earlier
+/// blocks before they're written to the storage.
+pub const PAPYRUS_P2P_SYNC_STATE_DIFF_COMMIT_BACKLOG: &str =
+    "papyrus_p2p_sync_state_diff_commit_backlog";
+
+/// The number of classes received from peers that were checked against the state diffs that
+/// declared them. Labeled by the result: valid, mismatch, undeclared, or unverified for classes
+/// whose hash can't be computed.
+pub const PAPYRUS_P2P_SYNC_VERIFIED_CLASSES: &str = "papyrus_p2p_sync_verified_classes";
+
+/// The time, in seconds, it took to compute the hash of each class received from peers.
+pub const PAPYRUS_P2P_SYNC_CLASS_VERIFICATION_DURATION_SECS: &str =
+    "papyrus_p2p_sync_class_verification_duration_secs";
+
+/// The first block number after the latest block proved on the base layer, as last read by the p2p
+/// sync. The p2p sync verifies the synced chain against it, so comparing it with the header marker
+/// shows how far the synced chain is ahead of its last possible verification.
+pub const PAPYRUS_P2P_SYNC_BASE_LAYER_PROVED_MARKER: &str =
+    "papyrus_p2p_sync_base_layer_proved_marker";
+
+/// The number of blocks the p2p sync compared in shadow mode with the blocks the central sync
+/// wrote. Labeled by the compared data, header or state_diff, and by the result, matched or
+/// diverged.
+pub const PAPYRUS_P2P_SHADOW_SYNC_COMPARED_BLOCKS: &str = "papyrus_p2p_shadow_sync_compared_blocks";
+
+/// The first block the storage scan didn't check yet.
+pub const PAPYRUS_STORAGE_SCAN_MARKER: &str = "papyrus_storage_scan_marker";
+
+/// The number of blocks with missing data or data that doesn't match their header that the storage
+/// scan found and didn't repair.
+pub const PAPYRUS_STORAGE_SCAN_BLOCKS_WITH_MISSING_DATA: &str =
+    "papyrus_storage_scan_blocks_with_missing_data";
+
+/// The number of requests the central source sent to the feeder gateway. Labeled by the request,
+/// e.g. block or class.
+pub const PAPYRUS_CENTRAL_UPSTREAM_REQUESTS: &str = "papyrus_central_upstream_requests";
+
+/// The number of central source requests that were answered by an identical request already sent
+/// to the feeder gateway. Labeled by the request.
+pub const PAPYRUS_CENTRAL_COALESCED_REQUESTS: &str = "papyrus_central_coalesced_requests";
+
+/// The number of central source requests that were answered from the response cache. Labeled by
+/// the request.
+pub const PAPYRUS_CENTRAL_CACHE_HITS: &str = "papyrus_central_cache_hits";
+
+/// The number of times a Starknet gateway answered with 429 Too Many Requests, after which the
+/// client backed off.
+pub const PAPYRUS_CENTRAL_RATE_LIMIT_BACKOFFS: &str = "papyrus_central_rate_limit_backoffs";
+
 // TODO: consider making this value non static and add a way to change this while the app is
 // running. e.g via a monitoring endpoint.
 /// Global variable set by the main config to enable collecting profiling metrics.
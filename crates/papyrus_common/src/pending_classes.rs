@@ -1,4 +1,8 @@
-use std::collections::HashMap;
+#[cfg(test)]
+#[path = "pending_classes_test.rs"]
+mod pending_classes_test;
+
+use std::collections::{HashMap, HashSet, VecDeque};
 use std::sync::Arc;
 
 use cairo_lang_starknet_classes::casm_contract_class::CasmContractClass;
@@ -6,6 +10,8 @@ use starknet_api::core::ClassHash;
 use starknet_api::deprecated_contract_class::ContractClass as DeprecatedContractClass;
 use starknet_api::state::ContractClass;
 
+use crate::approximate_size_in_bytes;
+
 pub trait PendingClassesTrait {
     // TODO(shahak) Return an Arc to avoid cloning the class. This requires to re-implement
     // From/TryFrom for various structs in a way that the input is passed by reference.
@@ -22,12 +28,92 @@ pub trait PendingClassesTrait {
     fn clear(&mut self);
 }
 
-#[derive(Debug, Default, Eq, PartialEq, Clone)]
+#[derive(Debug, Default, Clone)]
 pub struct PendingClasses {
     // Putting the contracts inside Arc so we won't have to clone them when we clone the entire
     // PendingClasses struct.
     pub classes: HashMap<ClassHash, Arc<ApiContractClass>>,
     pub compiled_classes: HashMap<ClassHash, Arc<CasmContractClass>>,
+    // Classes that were removed in order to stay under the size cap. They are still part of the
+    // pending state, so whoever needs them should fetch them again.
+    evicted_classes: HashSet<ClassHash>,
+    evicted_compiled_classes: HashSet<ClassHash>,
+    // The entries and their approximate sizes in bytes, from the oldest to the newest.
+    entries: VecDeque<(PendingClassEntry, usize)>,
+    size_in_bytes: usize,
+}
+
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+enum PendingClassEntry {
+    Class(ClassHash),
+    CompiledClass(ClassHash),
+}
+
+// The bookkeeping of the sizes depends on the order in which the classes were added, so it's not
+// part of the comparison.
+impl PartialEq for PendingClasses {
+    fn eq(&self, other: &Self) -> bool {
+        self.classes == other.classes
+            && self.compiled_classes == other.compiled_classes
+            && self.evicted_classes == other.evicted_classes
+            && self.evicted_compiled_classes == other.evicted_compiled_classes
+    }
+}
+
+impl Eq for PendingClasses {}
+
+impl PendingClasses {
+    /// Returns the approximate size in bytes of the classes and compiled classes.
+    pub fn size_in_bytes(&self) -> usize {
+        self.size_in_bytes
+    }
+
+    /// Removes the oldest classes and compiled classes until their approximate size is at most
+    /// `max_size_in_bytes`. Returns the number of removed entries.
+    pub fn evict_oldest(&mut self, max_size_in_bytes: usize) -> usize {
+        let mut num_evicted = 0;
+        while self.size_in_bytes > max_size_in_bytes {
+            let Some((entry, size)) = self.entries.pop_front() else {
+                break;
+            };
+            match entry {
+                PendingClassEntry::Class(class_hash) => {
+                    self.classes.remove(&class_hash);
+                    self.evicted_classes.insert(class_hash);
+                }
+                PendingClassEntry::CompiledClass(class_hash) => {
+                    self.compiled_classes.remove(&class_hash);
+                    self.evicted_compiled_classes.insert(class_hash);
+                }
+            }
+            self.size_in_bytes -= size;
+            num_evicted += 1;
+        }
+        num_evicted
+    }
+
+    /// Returns true if the class was added and then removed by [`evict_oldest`].
+    ///
+    /// [`evict_oldest`]: PendingClasses::evict_oldest
+    pub fn is_class_evicted(&self, class_hash: ClassHash) -> bool {
+        self.evicted_classes.contains(&class_hash)
+    }
+
+    /// Returns true if the compiled class was added and then removed by [`evict_oldest`].
+    ///
+    /// [`evict_oldest`]: PendingClasses::evict_oldest
+    pub fn is_compiled_class_evicted(&self, class_hash: ClassHash) -> bool {
+        self.evicted_compiled_classes.contains(&class_hash)
+    }
+
+    fn add_entry(&mut self, entry: PendingClassEntry, size: usize) {
+        if let Some(index) = self.entries.iter().position(|(other, _)| *other == entry) {
+            let (_, old_size) = self.entries.remove(index).expect("Index should be in range");
+            self.size_in_bytes -= old_size;
+        }
+        self.entries.push_back((entry, size));
+        self.size_in_bytes += size;
+    }
 }
 
 #[derive(Debug, Eq, PartialEq, Clone)]
@@ -50,6 +136,13 @@ impl ApiContractClass {
             _ => None,
         }
     }
+
+    fn approximate_size_in_bytes(&self) -> usize {
+        match self {
+            Self::DeprecatedContractClass(class) => approximate_size_in_bytes(class),
+            Self::ContractClass(class) => approximate_size_in_bytes(class),
+        }
+    }
 }
 
 impl PendingClassesTrait for PendingClasses {
@@ -58,6 +151,8 @@ impl PendingClassesTrait for PendingClasses {
     }
 
     fn add_class(&mut self, class_hash: ClassHash, class: ApiContractClass) {
+        self.add_entry(PendingClassEntry::Class(class_hash), class.approximate_size_in_bytes());
+        self.evicted_classes.remove(&class_hash);
         self.classes.insert(class_hash, Arc::new(class));
     }
 
@@ -66,11 +161,20 @@ impl PendingClassesTrait for PendingClasses {
     }
 
     fn add_compiled_class(&mut self, class_hash: ClassHash, compiled_class: CasmContractClass) {
+        self.add_entry(
+            PendingClassEntry::CompiledClass(class_hash),
+            approximate_size_in_bytes(&compiled_class),
+        );
+        self.evicted_compiled_classes.remove(&class_hash);
         self.compiled_classes.insert(class_hash, Arc::new(compiled_class));
     }
 
     fn clear(&mut self) {
         self.classes.clear();
         self.compiled_classes.clear();
+        self.evicted_classes.clear();
+        self.evicted_compiled_classes.clear();
+        self.entries.clear();
+        self.size_in_bytes = 0;
     }
 }
@@ -0,0 +1,69 @@
+use cairo_lang_starknet_classes::casm_contract_class::CasmContractClass;
+use starknet_api::core::ClassHash;
+use starknet_api::deprecated_contract_class::ContractClass as DeprecatedContractClass;
+use starknet_api::state::ContractClass;
+use starknet_types_core::felt::Felt;
+use test_utils::{get_rng, GetTestInstance};
+
+use crate::approximate_size_in_bytes;
+use crate::pending_classes::{ApiContractClass, PendingClasses, PendingClassesTrait};
+
+#[test]
+fn size_accounting() {
+    let mut rng = get_rng();
+    let class = ContractClass::get_test_instance(&mut rng);
+    let compiled_class = CasmContractClass::get_test_instance(&mut rng);
+    let class_size = approximate_size_in_bytes(&class);
+    let compiled_class_size = approximate_size_in_bytes(&compiled_class);
+
+    let mut pending_classes = PendingClasses::default();
+    assert_eq!(pending_classes.size_in_bytes(), 0);
+    pending_classes.add_class(ClassHash(Felt::ONE), ApiContractClass::ContractClass(class.clone()));
+    pending_classes.add_compiled_class(ClassHash(Felt::ONE), compiled_class);
+    assert_eq!(pending_classes.size_in_bytes(), class_size + compiled_class_size);
+
+    // Adding the same class again doesn't count it twice.
+    pending_classes.add_class(ClassHash(Felt::ONE), ApiContractClass::ContractClass(class));
+    assert_eq!(pending_classes.size_in_bytes(), class_size + compiled_class_size);
+
+    pending_classes.clear();
+    assert_eq!(pending_classes.size_in_bytes(), 0);
+}
+
+#[test]
+fn evict_oldest() {
+    let mut rng = get_rng();
+    let first_class_hash = ClassHash(Felt::ONE);
+    let second_class_hash = ClassHash(Felt::TWO);
+    let first_class = ApiContractClass::DeprecatedContractClass(
+        DeprecatedContractClass::get_test_instance(&mut rng),
+    );
+    let second_class = ContractClass::get_test_instance(&mut rng);
+    let second_class_size = approximate_size_in_bytes(&second_class);
+    let second_class = ApiContractClass::ContractClass(second_class);
+    let compiled_class = CasmContractClass::get_test_instance(&mut rng);
+
+    let mut pending_classes = PendingClasses::default();
+    pending_classes.add_class(first_class_hash, first_class);
+    pending_classes.add_compiled_class(first_class_hash, compiled_class.clone());
+    pending_classes.add_class(second_class_hash, second_class.clone());
+
+    // Nothing is evicted while the size is under the cap.
+    assert_eq!(pending_classes.evict_oldest(pending_classes.size_in_bytes()), 0);
+
+    assert_eq!(pending_classes.evict_oldest(second_class_size), 2);
+    assert_eq!(pending_classes.size_in_bytes(), second_class_size);
+    assert!(pending_classes.get_class(first_class_hash).is_none());
+    assert!(pending_classes.get_compiled_class(first_class_hash).is_none());
+    assert_eq!(pending_classes.get_class(second_class_hash), Some(second_class));
+    assert!(pending_classes.is_class_evicted(first_class_hash));
+    assert!(pending_classes.is_compiled_class_evicted(first_class_hash));
+    assert!(!pending_classes.is_class_evicted(second_class_hash));
+
+    // Adding an evicted class again unmarks it.
+    pending_classes.add_compiled_class(first_class_hash, compiled_class);
+    assert!(!pending_classes.is_compiled_class_evicted(first_class_hash));
+
+    pending_classes.clear();
+    assert!(!pending_classes.is_class_evicted(first_class_hash));
+}
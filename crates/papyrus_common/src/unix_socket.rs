@@ -0,0 +1,32 @@
+//! Utils for servers that listen on Unix domain sockets.
+
+#[cfg(test)]
+#[path = "unix_socket_test.rs"]
+mod unix_socket_test;
+
+use std::fs::Permissions;
+use std::io::{Error, ErrorKind};
+use std::os::unix::fs::{FileTypeExt, PermissionsExt};
+use std::os::unix::net::UnixListener;
+use std::path::Path;
+
+/// Binds a non-blocking listener to a Unix domain socket at the given path and sets the socket's
+/// permissions to the given mode. A socket left at the path by a previous run is removed first,
+/// but any other kind of file at the path is an error.
+pub fn bind_unix_socket(path: &Path, mode: u32) -> std::io::Result<UnixListener> {
+    match std::fs::symlink_metadata(path) {
+        Ok(metadata) if metadata.file_type().is_socket() => std::fs::remove_file(path)?,
+        Ok(_) => {
+            return Err(Error::new(
+                ErrorKind::AlreadyExists,
+                format!("{} exists and is not a socket.", path.display()),
+            ));
+        }
+        Err(error) if error.kind() == ErrorKind::NotFound => {}
+        Err(error) => return Err(error),
+    }
+    let listener = UnixListener::bind(path)?;
+    std::fs::set_permissions(path, Permissions::from_mode(mode))?;
+    listener.set_nonblocking(true)?;
+    Ok(listener)
+}
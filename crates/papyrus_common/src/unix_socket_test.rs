@@ -0,0 +1,31 @@
+use std::os::unix::fs::PermissionsExt;
+use std::os::unix::net::UnixStream;
+
+use tempfile::tempdir;
+
+use crate::unix_socket::bind_unix_socket;
+
+#[test]
+fn bind_unix_socket_sets_mode_and_replaces_stale_socket() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("node.sock");
+
+    let listener = bind_unix_socket(&path, 0o660).unwrap();
+    assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o660);
+    // The socket file stays after the listener is dropped, as it would after a crash.
+    drop(listener);
+
+    let _listener = bind_unix_socket(&path, 0o600).unwrap();
+    assert_eq!(std::fs::metadata(&path).unwrap().permissions().mode() & 0o777, 0o600);
+    UnixStream::connect(&path).unwrap();
+}
+
+#[test]
+fn bind_unix_socket_doesnt_remove_regular_files() {
+    let dir = tempdir().unwrap();
+    let path = dir.path().join("node.sock");
+    std::fs::write(&path, "data").unwrap();
+
+    assert!(bind_unix_socket(&path, 0o660).is_err());
+    assert_eq!(std::fs::read_to_string(&path).unwrap(), "data");
+}
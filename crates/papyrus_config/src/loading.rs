@@ -53,7 +53,15 @@ pub fn load_and_process_config<T: for<'a> Deserialize<'a>>(
 ) -> Result<T, ConfigError> {
     let deserialized_default_config: Map<String, Value> =
         serde_json::from_reader(default_config_file)?;
+    load_and_process_config_from_map(deserialized_default_config, command, args)
+}
 
+/// Same as [`load_and_process_config`], for a default config that was already deserialized.
+pub fn load_and_process_config_from_map<T: for<'a> Deserialize<'a>>(
+    deserialized_default_config: Map<String, Value>,
+    command: Command,
+    args: Vec<String>,
+) -> Result<T, ConfigError> {
     // Store the pointers separately from the default values. The pointers will receive a value
     // only at the end of the process.
     let (default_config_map, pointers_map) = split_pointers_map(deserialized_default_config);
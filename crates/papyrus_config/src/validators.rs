@@ -35,6 +35,14 @@ pub fn validate_vec_u256(vec: &[u8]) -> Result<(), ValidationError> {
     Ok(())
 }
 
+/// Custom validation for Unix file permissions given as an octal string, e.g "660".
+pub fn validate_unix_file_mode(mode: &str) -> Result<(), ValidationError> {
+    match u32::from_str_radix(mode, 8) {
+        Ok(mode) if mode <= 0o777 => Ok(()),
+        _ => Err(ValidationError::new("The value is not an octal file mode")),
+    }
+}
+
 /// Struct for parsing a validation error.
 #[derive(Debug)]
 pub struct ParsedValidationError {
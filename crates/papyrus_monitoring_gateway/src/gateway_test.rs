@@ -1,40 +1,200 @@
+use std::collections::{BTreeMap, HashMap, VecDeque};
 use std::net::{SocketAddr, TcpListener};
 use std::sync::Arc;
+use std::time::Duration;
 
 use axum::body::Body;
 use axum::http::{Request, StatusCode};
 use axum::response::Response;
 use axum::Router;
+use futures::channel::mpsc::{unbounded, UnboundedReceiver};
+use futures::StreamExt;
 use http_body::combinators::UnsyncBoxBody;
+use libp2p::{Multiaddr, PeerId, StreamProtocol};
 use metrics::{absolute_counter, describe_counter, register_counter};
 use metrics_exporter_prometheus::PrometheusBuilder;
-use papyrus_storage::{table_names, test_utils};
+use papyrus_common::consensus_status::{
+    ConsensusMessageCounts,
+    ConsensusStatus,
+    SharedConsensusStatus,
+};
+use papyrus_network::network_manager::{
+    NegotiatedProtocolsByPeer,
+    NetworkEvent,
+    NetworkEventKind,
+    NetworkRegistrations,
+    PeerLatencies,
+    PeerLatency,
+    PeerManagerCommand,
+    PeerManagerState,
+    PeerState,
+    RecentNetworkEvents,
+    ServedBytesByPeer,
+};
+use papyrus_network::Protocol;
+use papyrus_protobuf::sync::{FullBlock, SignedBlockHeader};
+use papyrus_storage::header::HeaderStorageReader;
+use papyrus_storage::version::StorageVersionInfo;
+use papyrus_storage::{
+    open_storage,
+    open_storage_read_only,
+    table_names,
+    test_utils,
+    StorageReader,
+    STORAGE_VERSION_BLOCKS,
+    STORAGE_VERSION_STATE,
+};
 use pretty_assertions::assert_eq;
 use serde_json::{json, Value};
+use starknet_api::block::{BlockHeader, BlockNumber, BlockSignature};
+use starknet_api::core::ContractAddress;
 use starknet_client::reader::MockStarknetReader;
 use starknet_client::writer::MockStarknetWriter;
+use tokio::sync::{watch, Mutex};
 use tower::ServiceExt;
-
-use crate::{app, is_ready, MONITORING_PREFIX};
+use validator::Validate;
+
+use crate::{
+    admin_app,
+    app,
+    is_ready,
+    unix_socket_incoming,
+    ComponentState,
+    ComponentStates,
+    ComponentStatus,
+    ConfigPresentation,
+    ConfigReloadOutcome,
+    ConfigReloadRequest,
+    MonitoringGatewayConfig,
+    ADMIN_PREFIX,
+    MONITORING_PREFIX,
+};
 
 const TEST_CONFIG_PRESENTATION: &str = "full_general_config_presentation";
 const PUBLIC_TEST_CONFIG_PRESENTATION: &str = "public_general_config_presentation";
 const SECRET: &str = "abcd";
 const TEST_VERSION: &str = "1.2.3-dev";
 const TEST_PEER_ID: &str = "peer_id";
+const TEST_TOPIC: &str = "consensus";
 
 // TODO(dan): consider using a proper fixture.
 fn setup_app() -> Router {
     let ((storage_reader, _), _temp_dir) = test_utils::get_test_storage();
+    setup_app_with_storage(storage_reader)
+}
+
+fn setup_app_with_storage(storage_reader: StorageReader) -> Router {
+    setup_app_with_network_state(
+        storage_reader,
+        ServedBytesByPeer::default(),
+        NegotiatedProtocolsByPeer::default(),
+        PeerLatencies::default(),
+        RecentNetworkEvents::default(),
+    )
+}
+
+fn setup_app_with_network_state(
+    storage_reader: StorageReader,
+    served_bytes_by_peer: ServedBytesByPeer,
+    negotiated_protocols_by_peer: NegotiatedProtocolsByPeer,
+    peer_latencies: PeerLatencies,
+    recent_network_events: RecentNetworkEvents,
+) -> Router {
+    setup_app_with_state(
+        storage_reader,
+        served_bytes_by_peer,
+        negotiated_protocols_by_peer,
+        peer_latencies,
+        recent_network_events,
+        ComponentStates::default(),
+        SharedConsensusStatus::default(),
+    )
+}
+
+fn setup_app_with_component_states(component_states: ComponentStates) -> Router {
+    let ((storage_reader, _), _temp_dir) = test_utils::get_test_storage();
+    setup_app_with_state(
+        storage_reader,
+        ServedBytesByPeer::default(),
+        NegotiatedProtocolsByPeer::default(),
+        PeerLatencies::default(),
+        RecentNetworkEvents::default(),
+        component_states,
+        SharedConsensusStatus::default(),
+    )
+}
+
+fn setup_app_with_consensus_status(shared_consensus_status: SharedConsensusStatus) -> Router {
+    let ((storage_reader, _), _temp_dir) = test_utils::get_test_storage();
+    setup_app_with_state(
+        storage_reader,
+        ServedBytesByPeer::default(),
+        NegotiatedProtocolsByPeer::default(),
+        PeerLatencies::default(),
+        RecentNetworkEvents::default(),
+        ComponentStates::default(),
+        shared_consensus_status,
+    )
+}
+
+fn test_config_presentation() -> ConfigPresentation {
+    ConfigPresentation {
+        full: serde_json::to_value(TEST_CONFIG_PRESENTATION).unwrap(),
+        public: serde_json::to_value(PUBLIC_TEST_CONFIG_PRESENTATION).unwrap(),
+    }
+}
+
+fn setup_app_with_state(
+    storage_reader: StorageReader,
+    served_bytes_by_peer: ServedBytesByPeer,
+    negotiated_protocols_by_peer: NegotiatedProtocolsByPeer,
+    peer_latencies: PeerLatencies,
+    recent_network_events: RecentNetworkEvents,
+    component_states: ComponentStates,
+    shared_consensus_status: SharedConsensusStatus,
+) -> Router {
+    setup_app_with_config_presentation(
+        storage_reader,
+        served_bytes_by_peer,
+        negotiated_protocols_by_peer,
+        peer_latencies,
+        recent_network_events,
+        component_states,
+        shared_consensus_status,
+        watch::Sender::new(test_config_presentation()).subscribe(),
+    )
+}
+
+#[allow(clippy::too_many_arguments)]
+fn setup_app_with_config_presentation(
+    storage_reader: StorageReader,
+    served_bytes_by_peer: ServedBytesByPeer,
+    negotiated_protocols_by_peer: NegotiatedProtocolsByPeer,
+    peer_latencies: PeerLatencies,
+    recent_network_events: RecentNetworkEvents,
+    component_states: ComponentStates,
+    shared_consensus_status: SharedConsensusStatus,
+    config_presentation: watch::Receiver<ConfigPresentation>,
+) -> Router {
     app(
         String::from("https://default_url"),
         storage_reader,
         TEST_VERSION,
-        serde_json::to_value(TEST_CONFIG_PRESENTATION).unwrap(),
-        serde_json::to_value(PUBLIC_TEST_CONFIG_PRESENTATION).unwrap(),
+        config_presentation,
         SECRET.to_string(),
         None,
         TEST_PEER_ID.to_string(),
+        NetworkRegistrations {
+            sqmr_clients: vec![Protocol::SignedBlockHeader],
+            sqmr_servers: vec![Protocol::SignedBlockHeader, Protocol::Transaction],
+            broadcast_topics: vec![TEST_TOPIC.to_string()],
+        },
+        served_bytes_by_peer,
+        negotiated_protocols_by_peer,
+        peer_latencies,
+        recent_network_events,
+        component_states,
+        shared_consensus_status,
     )
 }
 
@@ -117,6 +277,38 @@ async fn node_config_valid_secret() {
     validate_response(format!("nodeConfigFull/{SECRET}").as_str(), TEST_CONFIG_PRESENTATION).await;
 }
 
+#[tokio::test]
+async fn node_config_is_updated_by_a_new_presentation() {
+    let ((storage_reader, _), _temp_dir) = test_utils::get_test_storage();
+    let config_presentation = watch::Sender::new(test_config_presentation());
+    let app = setup_app_with_config_presentation(
+        storage_reader,
+        ServedBytesByPeer::default(),
+        NegotiatedProtocolsByPeer::default(),
+        PeerLatencies::default(),
+        RecentNetworkEvents::default(),
+        ComponentStates::default(),
+        SharedConsensusStatus::default(),
+        config_presentation.subscribe(),
+    );
+
+    // A reload of the config applied changes to it.
+    config_presentation.send_replace(ConfigPresentation {
+        full: json!({"param": "reloaded", "secret": SECRET}),
+        public: json!({"param": "reloaded"}),
+    });
+
+    let response = request_app(app.clone(), "nodeConfig").await;
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body, json!({"param": "reloaded"}));
+
+    let response = request_app(app, format!("nodeConfigFull/{SECRET}").as_str()).await;
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body, json!({"param": "reloaded", "secret": SECRET}));
+}
+
 #[tokio::test]
 async fn node_config_invalid_secret() {
     let app = setup_app();
@@ -125,6 +317,48 @@ async fn node_config_invalid_secret() {
     assert_eq!(response.status(), StatusCode::FORBIDDEN);
 }
 
+#[tokio::test]
+async fn storage_version() {
+    let app = setup_app();
+    let response = request_app(app, "storageVersion").await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let storage_version_info: StorageVersionInfo = serde_json::from_slice(&body).unwrap();
+    assert_eq!(
+        storage_version_info,
+        StorageVersionInfo {
+            state_version: Some(STORAGE_VERSION_STATE),
+            blocks_version: Some(STORAGE_VERSION_BLOCKS),
+            supported_state_version: STORAGE_VERSION_STATE,
+            supported_blocks_version: STORAGE_VERSION_BLOCKS,
+        }
+    );
+}
+
+#[tokio::test]
+async fn node_mode() {
+    let app = setup_app();
+    let response = request_app(app, "nodeMode").await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    assert_eq!(body, "read_write");
+}
+
+#[tokio::test]
+async fn read_only_node_mode() {
+    let (config, _temp_dir) = test_utils::get_test_config(None);
+    // The storage has to be initialized by a writer before it can be opened as read-only.
+    drop(open_storage(config.clone()).unwrap());
+    let app = setup_app_with_storage(open_storage_read_only(config).unwrap());
+    let response = request_app(app, "nodeMode").await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    assert_eq!(body, "read_only");
+}
+
 #[tokio::test]
 async fn alive() {
     let app = setup_app();
@@ -143,6 +377,115 @@ async fn peer_id() {
     assert_eq!(body, TEST_PEER_ID);
 }
 
+#[tokio::test]
+async fn protocol_versions() {
+    let app = setup_app();
+    let response = request_app(app, "protocolVersions").await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(
+        body,
+        json!({
+            "sqmr_clients": [Protocol::SignedBlockHeader.as_str()],
+            "sqmr_servers": [Protocol::SignedBlockHeader.as_str(), Protocol::Transaction.as_str()],
+            "broadcast_topics": [TEST_TOPIC],
+        })
+    );
+}
+
+#[tokio::test]
+async fn peers() {
+    let ((storage_reader, _), _temp_dir) = test_utils::get_test_storage();
+    let peer_id = PeerId::random();
+    let queried_peer_id = PeerId::random();
+    let served_bytes_by_peer =
+        ServedBytesByPeer::new(std::sync::Mutex::new(HashMap::from([(peer_id, 1234)])));
+    let negotiated_protocols_by_peer =
+        NegotiatedProtocolsByPeer::new(std::sync::Mutex::new(HashMap::from([(
+            queried_peer_id,
+            HashMap::from([(
+                StreamProtocol::new("/starknet/headers/2"),
+                StreamProtocol::new("/starknet/headers/1"),
+            )]),
+        )])));
+    let pinged_peer_id = PeerId::random();
+    let peer_latencies = PeerLatencies::new(std::sync::Mutex::new(HashMap::from([(
+        pinged_peer_id,
+        PeerLatency {
+            smoothed_rtt: Some(Duration::from_millis(42)),
+            lost_pings: 3,
+            consecutive_lost_pings: 0,
+            healthy: true,
+        },
+    )])));
+    let app = setup_app_with_network_state(
+        storage_reader,
+        served_bytes_by_peer,
+        negotiated_protocols_by_peer,
+        peer_latencies,
+        RecentNetworkEvents::default(),
+    );
+    let response = request_app(app, "peers").await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(
+        body,
+        json!({
+            peer_id.to_string(): { "served_bytes": 1234, "negotiated_protocols": [] },
+            queried_peer_id.to_string(): {
+                "served_bytes": 0,
+                "negotiated_protocols": ["/starknet/headers/1"],
+            },
+            pinged_peer_id.to_string(): {
+                "served_bytes": 0,
+                "negotiated_protocols": [],
+                "ping": { "rtt_millis": 42, "lost_pings": 3, "healthy": true },
+            },
+        })
+    );
+}
+
+#[tokio::test]
+async fn network_events() {
+    let ((storage_reader, _), _temp_dir) = test_utils::get_test_storage();
+    let peer_id = PeerId::random();
+    let event = NetworkEvent {
+        sequence_number: 7,
+        time: "2024-01-01T00:00:00+00:00".to_string(),
+        peer_id: Some(peer_id.to_string()),
+        kind: NetworkEventKind::ConnectionEstablished,
+        details: "details".to_string(),
+    };
+    let recent_network_events =
+        RecentNetworkEvents::new(std::sync::Mutex::new(VecDeque::from([event])));
+    let app = setup_app_with_network_state(
+        storage_reader,
+        ServedBytesByPeer::default(),
+        NegotiatedProtocolsByPeer::default(),
+        PeerLatencies::default(),
+        recent_network_events,
+    );
+    let response = request_app(app, "networkEvents").await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(
+        body,
+        json!([{
+            "sequence_number": 7,
+            "time": "2024-01-01T00:00:00+00:00",
+            "peer_id": peer_id.to_string(),
+            "kind": "ConnectionEstablished",
+            "details": "details",
+        }])
+    );
+}
+
 #[tokio::test]
 async fn ready() {
     let mut gateway_client_mock = MockStarknetWriter::new();
@@ -151,9 +494,99 @@ async fn ready() {
     gateway_client_mock.expect_is_alive().times(1).returning(|| true);
     feeder_gateway_client_mock.expect_is_alive().times(1).returning(|| true);
 
-    let response: String =
-        is_ready(Arc::new(gateway_client_mock), Arc::new(feeder_gateway_client_mock)).await;
-    assert_eq!(response, StatusCode::OK.to_string());
+    let response = is_ready(
+        Arc::new(gateway_client_mock),
+        Arc::new(feeder_gateway_client_mock),
+        ComponentStates::default(),
+    )
+    .await;
+    assert_eq!(response, Ok(StatusCode::OK.to_string()));
+}
+
+fn component_states(statuses: &[(&str, ComponentStatus)]) -> ComponentStates {
+    let states = statuses
+        .iter()
+        .map(|(name, status)| {
+            (name.to_string(), ComponentState { status: *status, failures: 0, essential: false })
+        })
+        .collect::<BTreeMap<_, _>>();
+    Arc::new(std::sync::RwLock::new(states))
+}
+
+#[tokio::test]
+async fn not_ready_while_a_component_restarts() {
+    let response = is_ready(
+        Arc::new(MockStarknetWriter::new()),
+        Arc::new(MockStarknetReader::new()),
+        component_states(&[
+            ("rpc", ComponentStatus::Restarting),
+            ("sync", ComponentStatus::Running),
+        ]),
+    )
+    .await;
+
+    let (status_code, body) = response.unwrap_err();
+    assert_eq!(status_code, StatusCode::SERVICE_UNAVAILABLE);
+    assert!(body.contains("rpc"));
+    assert!(!body.contains("sync"));
+}
+
+#[tokio::test]
+async fn not_alive_once_a_component_failed() {
+    let app = setup_app_with_component_states(component_states(&[
+        ("rpc", ComponentStatus::Restarting),
+        ("sync", ComponentStatus::Running),
+    ]));
+    let response = request_app(app, "alive").await;
+    assert_eq!(response.status(), StatusCode::OK);
+
+    let app =
+        setup_app_with_component_states(component_states(&[("rpc", ComponentStatus::Failed)]));
+    let response = request_app(app, "alive").await;
+    assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+}
+
+#[tokio::test]
+async fn components() {
+    let app = setup_app_with_component_states(component_states(&[
+        ("rpc", ComponentStatus::Restarting),
+        ("sync", ComponentStatus::Running),
+    ]));
+    let response = request_app(app, "components").await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(
+        body,
+        json!({
+            "rpc": {"status": "restarting", "failures": 0, "essential": false},
+            "sync": {"status": "running", "failures": 0, "essential": false},
+        })
+    );
+}
+
+#[tokio::test]
+async fn consensus_status() {
+    let app = setup_app_with_consensus_status(SharedConsensusStatus::default());
+    let response = request_app(app, "consensusStatus").await;
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+    let mut consensus_status = ConsensusStatus::new(ContractAddress::from(1_u128));
+    consensus_status.height = Some(BlockNumber(5));
+    consensus_status.is_proposer = true;
+    consensus_status.messages.insert(
+        "proposal".to_string(),
+        ConsensusMessageCounts { sent: 1, received: 2, invalid: 0 },
+    );
+    let shared_consensus_status = Arc::new(std::sync::RwLock::new(Some(consensus_status.clone())));
+    let app = setup_app_with_consensus_status(shared_consensus_status);
+    let response = request_app(app, "consensusStatus").await;
+
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body: ConsensusStatus = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body, consensus_status);
 }
 
 #[tokio::test]
@@ -180,6 +613,13 @@ async fn with_metrics() {
         String::new(),
         Some(prometheus_handle),
         TEST_PEER_ID.to_string(),
+        NetworkRegistrations::default(),
+        ServedBytesByPeer::default(),
+        NegotiatedProtocolsByPeer::default(),
+        PeerLatencies::default(),
+        RecentNetworkEvents::default(),
+        ComponentStates::default(),
+        SharedConsensusStatus::default(),
     );
 
     // Register a metric.
@@ -231,6 +671,269 @@ async fn run_server() {
     assert_eq!(response.status(), StatusCode::OK);
 }
 
+#[tokio::test]
+async fn run_server_on_unix_socket() {
+    let dir = tempfile::tempdir().unwrap();
+    let path = dir.path().join("monitoring.sock");
+    let incoming = unix_socket_incoming(&path, "600").unwrap();
+
+    tokio::spawn(async move {
+        axum::Server::builder(incoming).serve(setup_app().into_make_service()).await.unwrap();
+    });
+
+    let stream = tokio::net::UnixStream::connect(&path).await.unwrap();
+    let (mut sender, connection) = hyper::client::conn::handshake(stream).await.unwrap();
+    tokio::spawn(connection);
+    let response = sender
+        .send_request(
+            Request::builder()
+                .uri(format!("/{MONITORING_PREFIX}/nodeVersion"))
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+
+    assert_eq!(response.status(), StatusCode::OK);
+}
+
+#[tokio::test]
+async fn inject_block() {
+    let ((storage_reader, storage_writer), _temp_dir) = test_utils::get_test_storage();
+    let app = admin_app(Some(Arc::new(Mutex::new(storage_writer))), None, None);
+
+    let block = FullBlock {
+        signed_header: SignedBlockHeader {
+            block_header: BlockHeader { state_diff_length: Some(0), ..Default::default() },
+            signatures: vec![BlockSignature::default()],
+            data_availability: None,
+        },
+        transactions: vec![],
+        transaction_hashes: vec![],
+        state_diff_chunks: vec![],
+    };
+    let inject_block_request = || {
+        Request::builder()
+            .method("POST")
+            .uri(format!("/{ADMIN_PREFIX}/injectBlock").as_str())
+            .body(Body::from(Vec::<u8>::from(block.clone())))
+            .unwrap()
+    };
+
+    let response = app.clone().oneshot(inject_block_request()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    assert_eq!(storage_reader.begin_ro_txn().unwrap().get_header_marker().unwrap(), BlockNumber(1));
+
+    // Injecting the same block again should fail since it's out of order.
+    let response = app.oneshot(inject_block_request()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body = String::from_utf8(body.to_vec()).unwrap();
+    assert!(body.contains("The next block to inject is 1"), "Unexpected error message: {body}");
+}
+
+#[tokio::test]
+async fn admin_server_without_storage_writer_does_not_inject_blocks() {
+    let (peer_manager_command_sender, _peer_manager_command_receiver) = unbounded();
+    let app = admin_app(None, Some(peer_manager_command_sender), None);
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri(format!("/{ADMIN_PREFIX}/injectBlock").as_str())
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::NOT_FOUND);
+}
+
+fn peer_manager_admin_app() -> (Router, UnboundedReceiver<PeerManagerCommand>) {
+    let (peer_manager_command_sender, peer_manager_command_receiver) = unbounded();
+    (admin_app(None, Some(peer_manager_command_sender), None), peer_manager_command_receiver)
+}
+
+#[tokio::test]
+async fn peer_manager_state() {
+    let (app, mut peer_manager_command_receiver) = peer_manager_admin_app();
+    let peer_id = PeerId::random();
+    let banned_peer_id = PeerId::random();
+    let expected_state = PeerManagerState {
+        peers: vec![PeerState {
+            peer_id,
+            multiaddr: Multiaddr::empty(),
+            blocked_until: None,
+            manually_banned: false,
+            num_connections: 1,
+            assigned_sessions: vec![0, 2],
+        }],
+        manual_bans: vec![(banned_peer_id, "2024-01-01T00:00:00+00:00".to_string())],
+    };
+    let state_to_send = expected_state.clone();
+    tokio::spawn(async move {
+        let Some(PeerManagerCommand::GetState(state_sender)) =
+            peer_manager_command_receiver.next().await
+        else {
+            panic!("Expected a GetState command");
+        };
+        state_sender.send(state_to_send).unwrap();
+    });
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .uri(format!("/{ADMIN_PREFIX}/peerManager").as_str())
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body, serde_json::to_value(expected_state).unwrap());
+}
+
+#[tokio::test]
+async fn blacklist_and_unblacklist_peer() {
+    let (app, mut peer_manager_command_receiver) = peer_manager_admin_app();
+    let peer_id = PeerId::random();
+
+    let response = app
+        .clone()
+        .oneshot(
+            Request::builder()
+                .method("POST")
+                .uri(format!("/{ADMIN_PREFIX}/peerManager/blacklist").as_str())
+                .header("content-type", "application/json")
+                .body(Body::from(
+                    json!({"peer_id": peer_id.to_string(), "duration_seconds": 60}).to_string(),
+                ))
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let Some(PeerManagerCommand::Blacklist { peer_id: banned_peer_id, duration }) =
+        peer_manager_command_receiver.next().await
+    else {
+        panic!("Expected a Blacklist command");
+    };
+    assert_eq!(banned_peer_id, peer_id);
+    assert_eq!(duration, std::time::Duration::from_secs(60));
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("DELETE")
+                .uri(format!("/{ADMIN_PREFIX}/peerManager/blacklist/{peer_id}").as_str())
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let Some(PeerManagerCommand::Unblacklist { peer_id: unbanned_peer_id }) =
+        peer_manager_command_receiver.next().await
+    else {
+        panic!("Expected an Unblacklist command");
+    };
+    assert_eq!(unbanned_peer_id, peer_id);
+}
+
+#[tokio::test]
+async fn unblacklist_invalid_peer_id() {
+    let (app, _peer_manager_command_receiver) = peer_manager_admin_app();
+
+    let response = app
+        .oneshot(
+            Request::builder()
+                .method("DELETE")
+                .uri(format!("/{ADMIN_PREFIX}/peerManager/blacklist/not_a_peer_id").as_str())
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+}
+
+// Returns an admin app whose config reloads end with the given result.
+fn config_reload_admin_app(reload_result: Result<ConfigReloadOutcome, String>) -> Router {
+    let (config_reload_sender, mut config_reload_receiver) = unbounded::<ConfigReloadRequest>();
+    tokio::spawn(async move {
+        let outcome_sender = config_reload_receiver.next().await.unwrap();
+        outcome_sender.send(reload_result).unwrap();
+    });
+    admin_app(None, None, Some(config_reload_sender))
+}
+
+fn reload_config_request() -> Request<Body> {
+    Request::builder()
+        .method("POST")
+        .uri(format!("/{ADMIN_PREFIX}/reloadConfig").as_str())
+        .body(Body::empty())
+        .unwrap()
+}
+
+#[tokio::test]
+async fn reload_config_returns_applied_and_rejected_params() {
+    let outcome = ConfigReloadOutcome {
+        applied: vec!["sync.block_propagation_sleep_duration".to_string()],
+        rejected: vec!["monitoring_gateway.server_address".to_string()],
+    };
+    let app = config_reload_admin_app(Ok(outcome.clone()));
+
+    let response = app.oneshot(reload_config_request()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::OK);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body: ConfigReloadOutcome = serde_json::from_slice(&body).unwrap();
+    assert_eq!(body, outcome);
+}
+
+#[tokio::test]
+async fn reload_config_with_invalid_config() {
+    let app = config_reload_admin_app(Err("invalid param".to_string()));
+
+    let response = app.oneshot(reload_config_request()).await.unwrap();
+    assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body = String::from_utf8(body.to_vec()).unwrap();
+    assert!(body.contains("invalid param"), "Unexpected error message: {body}");
+}
+
+#[test]
+fn admin_server_address_must_be_localhost() {
+    let config = MonitoringGatewayConfig {
+        admin_server_address: Some("127.0.0.1:8082".to_string()),
+        ..Default::default()
+    };
+    assert!(config.validate().is_ok());
+
+    let config = MonitoringGatewayConfig {
+        admin_server_address: Some("0.0.0.0:8082".to_string()),
+        ..Default::default()
+    };
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn server_address_and_unix_socket_are_mutually_exclusive() {
+    let config = MonitoringGatewayConfig {
+        unix_socket_path: Some("/tmp/monitoring.sock".into()),
+        ..Default::default()
+    };
+    assert!(config.validate().is_err());
+
+    let config = MonitoringGatewayConfig { server_address: String::new(), ..config };
+    assert!(config.validate().is_ok());
+
+    let config = MonitoringGatewayConfig { unix_socket_path: None, ..config };
+    assert!(config.validate().is_err());
+}
+
 #[test]
 fn serialization_precision() {
     let input =
@@ -7,37 +7,79 @@ mod gateway_test;
 
 use std::collections::{BTreeMap, HashMap};
 use std::fmt::Display;
+use std::future::pending;
 use std::net::SocketAddr;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use std::sync::Arc;
+use std::sync::{Arc, RwLock};
+use std::time::Duration;
 
 use axum::extract::Path;
 use axum::http::StatusCode;
 use axum::response::{IntoResponse, Response};
-use axum::routing::get;
+use axum::routing::{delete, get, post};
 use axum::{Json, Router};
+use futures::channel::mpsc::UnboundedSender;
+use futures::channel::oneshot;
+use futures::future::Either;
+use hyper::server::accept::Accept;
+use libp2p::PeerId;
 use metrics_exporter_prometheus::{BuildError, PrometheusBuilder, PrometheusHandle};
 use metrics_process::Collector;
-use papyrus_config::converters::{deserialize_optional_map, serialize_optional_map};
-use papyrus_config::dumping::{ser_generated_param, ser_param, SerializeConfig};
+use papyrus_common::consensus_status::{ConsensusStatus, SharedConsensusStatus};
+use papyrus_common::unix_socket::bind_unix_socket;
+use papyrus_config::converters::{
+    deserialize_optional_map,
+    deserialize_seconds_to_duration,
+    serialize_optional_map,
+};
+use papyrus_config::dumping::{
+    ser_generated_param,
+    ser_optional_param,
+    ser_param,
+    SerializeConfig,
+};
+use papyrus_config::validators::validate_unix_file_mode;
 use papyrus_config::{ParamPath, ParamPrivacyInput, SerializationType, SerializedParam};
+use papyrus_network::network_manager::{
+    NegotiatedProtocolsByPeer,
+    NetworkEvent,
+    NetworkRegistrations,
+    PeerLatencies,
+    PeerManagerCommand,
+    PeerManagerState,
+    RecentNetworkEvents,
+    ServedBytesByPeer,
+};
+use papyrus_p2p_sync::P2PSyncError;
+use papyrus_protobuf::sync::FullBlock;
 use papyrus_storage::mmap_file::MMapFileStats;
-use papyrus_storage::{DbStats, StorageError, StorageReader};
+use papyrus_storage::version::StorageVersionInfo;
+use papyrus_storage::{DbStats, StorageError, StorageReader, StorageWriter};
 use rand::distributions::Alphanumeric;
 use rand::{thread_rng, Rng};
 use serde::{Deserialize, Serialize};
 use starknet_client::reader::{StarknetFeederGatewayClient, StarknetReader};
 use starknet_client::writer::{StarknetGatewayClient, StarknetWriter};
 use starknet_client::RetryConfig;
+use tokio::net::UnixStream;
+use tokio::sync::{watch, Mutex};
+use tokio_stream::wrappers::UnixListenerStream;
 use tracing::{debug, info, instrument};
-use validator::Validate;
+use validator::{Validate, ValidationError};
 
 const MONITORING_PREFIX: &str = "monitoring";
+const ADMIN_PREFIX: &str = "admin";
 const PROCESS_METRICS_PREFIX: &str = "papyrus_";
 
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Validate)]
+#[validate(schema(function = "validate_monitoring_gateway_config"))]
 pub struct MonitoringGatewayConfig {
+    // Empty if the server listens on a Unix domain socket.
     pub server_address: String,
+    pub unix_socket_path: Option<PathBuf>,
+    #[validate(custom = "validate_unix_file_mode")]
+    pub unix_socket_mode: String,
     pub collect_metrics: bool,
     #[serde(deserialize_with = "deserialize_optional_map")]
     pub metric_labels: Option<HashMap<String, String>>,
@@ -45,6 +87,10 @@ pub struct MonitoringGatewayConfig {
     #[serde(default = "random_secret")]
     pub present_full_config_secret: String,
     pub starknet_url: String,
+    #[validate(custom = "validate_loopback_address")]
+    pub admin_server_address: Option<String>,
+    #[serde(deserialize_with = "deserialize_seconds_to_duration")]
+    pub storage_metrics_update_interval: Duration,
 }
 
 fn random_secret() -> String {
@@ -57,22 +103,32 @@ impl Default for MonitoringGatewayConfig {
     fn default() -> Self {
         MonitoringGatewayConfig {
             server_address: String::from("0.0.0.0:8081"),
+            unix_socket_path: None,
+            unix_socket_mode: String::from("660"),
             collect_metrics: false,
             metric_labels: None,
             // A constant value for testing purposes.
             present_full_config_secret: String::from("qwerty"),
             starknet_url: String::from("https://alpha-mainnet.starknet.io/"),
+            admin_server_address: None,
+            storage_metrics_update_interval: Duration::from_secs(10),
         }
     }
 }
 
 impl SerializeConfig for MonitoringGatewayConfig {
     fn dump(&self) -> BTreeMap<ParamPath, SerializedParam> {
-        BTreeMap::from_iter([
+        let mut config = BTreeMap::from_iter([
             ser_param(
                 "server_address",
                 &self.server_address,
-                "node's monitoring server.",
+                "node's monitoring server. Should be empty if unix_socket_path is set.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "unix_socket_mode",
+                &self.unix_socket_mode,
+                "The permissions of the monitoring server's Unix domain socket, in octal.",
                 ParamPrivacyInput::Public,
             ),
             ser_param(
@@ -99,7 +155,51 @@ impl SerializeConfig for MonitoringGatewayConfig {
                 "The URL of a centralized Starknet gateway.",
                 ParamPrivacyInput::Public,
             ),
-        ])
+            ser_param(
+                "storage_metrics_update_interval",
+                &self.storage_metrics_update_interval.as_secs(),
+                "Time in seconds between updates of the storage metrics, if metrics are collected.",
+                ParamPrivacyInput::Public,
+            ),
+        ]);
+        config.extend(ser_optional_param(
+            &self.admin_server_address,
+            String::from("127.0.0.1:8082"),
+            "admin_server_address",
+            "The localhost address of the node's admin server, which allows managing the node's \
+             peers and injecting blocks into the storage. Blocks can't be injected while the node \
+             is syncing.",
+            ParamPrivacyInput::Public,
+        ));
+        config.extend(ser_optional_param(
+            &self.unix_socket_path,
+            PathBuf::from("/tmp/papyrus_monitoring.sock"),
+            "unix_socket_path",
+            "The path of a Unix domain socket for the monitoring server to listen on instead of \
+             server_address.",
+            ParamPrivacyInput::Public,
+        ));
+        config
+    }
+}
+
+fn validate_monitoring_gateway_config(
+    config: &MonitoringGatewayConfig,
+) -> Result<(), ValidationError> {
+    if config.server_address.is_empty() == config.unix_socket_path.is_none() {
+        return Err(ValidationError::new(
+            "Exactly one of server_address and unix_socket_path should be set",
+        ));
+    }
+    Ok(())
+}
+
+// The admin server can write to the storage and ban peers, so it should only be reachable from the
+// local machine.
+fn validate_loopback_address(address: &str) -> Result<(), ValidationError> {
+    match SocketAddr::from_str(address) {
+        Ok(socket_address) if socket_address.ip().is_loopback() => Ok(()),
+        _ => Err(ValidationError::new("The address is not a valid localhost address")),
     }
 }
 
@@ -112,25 +212,98 @@ impl Display for MonitoringGatewayConfig {
 
 pub struct MonitoringServer {
     config: MonitoringGatewayConfig,
-    // Nested Json presentation of all the parameters in the node config.
-    full_general_config_presentation: serde_json::Value,
-    // Nested Json presentation of the public parameters in the node config.
-    public_general_config_presentation: serde_json::Value,
+    config_presentation: watch::Receiver<ConfigPresentation>,
     storage_reader: StorageReader,
     version: &'static str,
     prometheus_handle: Option<PrometheusHandle>,
     own_peer_id: String,
+    network_registrations: NetworkRegistrations,
+    served_bytes_by_peer: ServedBytesByPeer,
+    negotiated_protocols_by_peer: NegotiatedProtocolsByPeer,
+    peer_latencies: PeerLatencies,
+    recent_network_events: RecentNetworkEvents,
+    component_states: ComponentStates,
+    consensus_status: SharedConsensusStatus,
+    storage_writer: Option<Arc<Mutex<StorageWriter>>>,
+    peer_manager_command_sender: Option<UnboundedSender<PeerManagerCommand>>,
+    config_reload_sender: Option<UnboundedSender<ConfigReloadRequest>>,
 }
 
+/// Nested Json presentations of the node config. A new presentation is sent whenever a reload of
+/// the config applies changes to it.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct ConfigPresentation {
+    /// All the parameters in the node config.
+    pub full: serde_json::Value,
+    /// The public parameters in the node config.
+    pub public: serde_json::Value,
+}
+
+/// The params whose changes were applied when the node's config was reloaded, and the params whose
+/// changes were rejected since they can't be changed while the node is running.
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ConfigReloadOutcome {
+    pub applied: Vec<ParamPath>,
+    pub rejected: Vec<ParamPath>,
+}
+
+/// A request to reload the node's config, with the sender of its outcome. If the reloaded config
+/// can't be loaded or is invalid, an error is sent and the running config stays as is.
+pub type ConfigReloadRequest = oneshot::Sender<Result<ConfigReloadOutcome, String>>;
+
+/// The status of a component of the node, as kept by the node's supervisor.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum ComponentStatus {
+    Running,
+    /// The component failed and waits to be started again.
+    Restarting,
+    /// The component failed and won't be started again.
+    Failed,
+    /// The component ended without failing and won't be started again.
+    Stopped,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct ComponentState {
+    pub status: ComponentStatus,
+    /// The number of times the component failed since the node started.
+    pub failures: usize,
+    /// Whether the node shuts down once the component fails and isn't started again.
+    pub essential: bool,
+}
+
+/// The states of the node's components by their names.
+pub type ComponentStates = Arc<RwLock<BTreeMap<String, ComponentState>>>;
+
 impl MonitoringServer {
+    /// `storage_writer`, `peer_manager_command_sender` and `config_reload_sender` are used by the
+    /// admin server. The storage writer should be given only if `config.admin_server_address` is
+    /// set, and then the admin server allows injecting blocks. The command sender should be given
+    /// if the node runs a p2p network, and then the admin server allows managing the node's peers.
+    /// The reload sender should be given if the node can reload its config.
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         config: MonitoringGatewayConfig,
-        full_general_config_presentation: serde_json::Value,
-        public_general_config_presentation: serde_json::Value,
+        config_presentation: watch::Receiver<ConfigPresentation>,
         storage_reader: StorageReader,
         version: &'static str,
         own_peer_id: String,
+        network_registrations: NetworkRegistrations,
+        served_bytes_by_peer: ServedBytesByPeer,
+        negotiated_protocols_by_peer: NegotiatedProtocolsByPeer,
+        peer_latencies: PeerLatencies,
+        recent_network_events: RecentNetworkEvents,
+        component_states: ComponentStates,
+        consensus_status: SharedConsensusStatus,
+        storage_writer: Option<StorageWriter>,
+        peer_manager_command_sender: Option<UnboundedSender<PeerManagerCommand>>,
+        config_reload_sender: Option<UnboundedSender<ConfigReloadRequest>>,
     ) -> Result<Self, BuildError> {
+        assert!(
+            config.admin_server_address.is_some() || storage_writer.is_none(),
+            "A storage writer should be given only if the admin server is enabled."
+        );
         let prometheus_handle = if config.collect_metrics {
             let mut builder = PrometheusBuilder::new();
             if let Some(metric_labels) = &config.metric_labels {
@@ -145,11 +318,21 @@ impl MonitoringServer {
         Ok(MonitoringServer {
             config,
             storage_reader,
-            full_general_config_presentation,
-            public_general_config_presentation,
+            config_presentation,
             version,
             prometheus_handle,
             own_peer_id,
+            network_registrations,
+            served_bytes_by_peer,
+            negotiated_protocols_by_peer,
+            peer_latencies,
+            recent_network_events,
+            component_states,
+            consensus_status,
+            storage_writer: storage_writer
+                .map(|storage_writer| Arc::new(Mutex::new(storage_writer))),
+            peer_manager_command_sender,
+            config_reload_sender,
         })
     }
 
@@ -158,43 +341,101 @@ impl MonitoringServer {
         tokio::spawn(async move { self.run_server().await })
     }
 
+    /// Runs the monitoring server until it fails. The server can run again after it fails.
+    pub async fn run(&self) -> Result<(), hyper::Error> {
+        self.run_server().await
+    }
+
     #[instrument(
         skip(self),
         fields(
             version = %self.version,
             config = %self.config,
-            full_general_config_presentation = %self.full_general_config_presentation,
-            public_general_config_presentation = %self.public_general_config_presentation,
+            config_presentation = ?*self.config_presentation.borrow(),
             present_full_config_secret = %self.config.present_full_config_secret),
         level = "debug")]
     async fn run_server(&self) -> std::result::Result<(), hyper::Error> {
-        let server_address = SocketAddr::from_str(&self.config.server_address)
-            .expect("Configuration value for monitor server address should be valid");
         let app = app(
             self.config.starknet_url.clone(),
             self.storage_reader.clone(),
             self.version,
-            self.full_general_config_presentation.clone(),
-            self.public_general_config_presentation.clone(),
+            self.config_presentation.clone(),
             self.config.present_full_config_secret.clone(),
             self.prometheus_handle.clone(),
             self.own_peer_id.clone(),
+            self.network_registrations.clone(),
+            self.served_bytes_by_peer.clone(),
+            self.negotiated_protocols_by_peer.clone(),
+            self.peer_latencies.clone(),
+            self.recent_network_events.clone(),
+            self.component_states.clone(),
+            self.consensus_status.clone(),
         );
         debug!("Starting monitoring gateway.");
-        axum::Server::bind(&server_address).serve(app.into_make_service()).await
+        let monitoring_server = match &self.config.unix_socket_path {
+            Some(unix_socket_path) => {
+                let incoming =
+                    unix_socket_incoming(unix_socket_path, &self.config.unix_socket_mode)
+                        .expect("Failed binding the monitoring server to its Unix domain socket");
+                info!(
+                    unix_socket_path = %unix_socket_path.display(),
+                    "Monitoring gateway is running."
+                );
+                Either::Left(axum::Server::builder(incoming).serve(app.into_make_service()))
+            }
+            None => {
+                let server_address = SocketAddr::from_str(&self.config.server_address)
+                    .expect("Configuration value for monitor server address should be valid");
+                info!(%server_address, "Monitoring gateway is running.");
+                Either::Right(axum::Server::bind(&server_address).serve(app.into_make_service()))
+            }
+        };
+
+        let admin_server = async {
+            let Some(admin_server_address) = &self.config.admin_server_address else {
+                return pending().await;
+            };
+            let admin_server_address = SocketAddr::from_str(admin_server_address)
+                .expect("Configuration value for admin server address should be valid");
+            debug!("Starting admin server.");
+            let admin_app = admin_app(
+                self.storage_writer.clone(),
+                self.peer_manager_command_sender.clone(),
+                self.config_reload_sender.clone(),
+            );
+            axum::Server::bind(&admin_server_address).serve(admin_app.into_make_service()).await
+        };
+
+        tokio::try_join!(monitoring_server, admin_server).map(|_| ())
     }
 }
 
+// Accepts the connections of a server that listens on a Unix domain socket.
+fn unix_socket_incoming(
+    path: &Path,
+    mode: &str,
+) -> std::io::Result<impl Accept<Conn = UnixStream, Error = std::io::Error>> {
+    let mode = u32::from_str_radix(mode, 8).expect("The Unix socket mode should be validated");
+    let listener = tokio::net::UnixListener::from_std(bind_unix_socket(path, mode)?)?;
+    Ok(hyper::server::accept::from_stream(UnixListenerStream::new(listener)))
+}
+
 #[allow(clippy::too_many_arguments)]
 fn app(
     starknet_url: String,
     storage_reader: StorageReader,
     version: &'static str,
-    full_general_config_presentation: serde_json::Value,
-    public_general_config_presentation: serde_json::Value,
+    config_presentation: watch::Receiver<ConfigPresentation>,
     present_full_config_secret: String,
     prometheus_handle: Option<PrometheusHandle>,
     own_peer_id: String,
+    network_registrations: NetworkRegistrations,
+    served_bytes_by_peer: ServedBytesByPeer,
+    negotiated_protocols_by_peer: NegotiatedProtocolsByPeer,
+    peer_latencies: PeerLatencies,
+    recent_network_events: RecentNetworkEvents,
+    component_states: ComponentStates,
+    shared_consensus_status: SharedConsensusStatus,
 ) -> Router {
     let is_ready_retry_config =
         RetryConfig { retry_base_millis: 50, retry_max_delay_millis: 1000, max_retries: 0 };
@@ -214,6 +455,11 @@ fn app(
 
     let db_tables_stats_reader = storage_reader.clone();
     let mmap_files_stats_reader = storage_reader.clone();
+    let node_mode_reader = storage_reader.clone();
+    let storage_version_reader = storage_reader.clone();
+    let alive_component_states = component_states.clone();
+    let public_config_presentation = config_presentation.clone();
+    let ready_component_states = component_states.clone();
 
     Router::new()
         .route(
@@ -224,16 +470,18 @@ fn app(
             format!("/{MONITORING_PREFIX}/mmapFilesStats").as_str(),
             get(move || mmap_files_stats(mmap_files_stats_reader)),
         )
+        // The presentations are read on each request, so they're up to date after a reload of the
+        // config.
         .route(
             format!("/{MONITORING_PREFIX}/nodeConfig").as_str(),
-            get(move || node_config(public_general_config_presentation)),
+            get(move || node_config(public_config_presentation.borrow().public.clone())),
         )
         .route(
             // The "*secret" captures the end of the path and stores it in "secret".
             format!("/{MONITORING_PREFIX}/nodeConfigFull/*secret").as_str(),
             get(move |secret| {
                 node_config_by_secret(
-                    full_general_config_presentation,
+                    config_presentation.borrow().full.clone(),
                     secret,
                     present_full_config_secret,
                 )
@@ -243,9 +491,17 @@ fn app(
             format!("/{MONITORING_PREFIX}/nodeVersion").as_str(),
             get(move || node_version(version)),
         )
+        .route(
+            format!("/{MONITORING_PREFIX}/nodeMode").as_str(),
+            get(move || node_mode(node_mode_reader)),
+        )
+        .route(
+            format!("/{MONITORING_PREFIX}/storageVersion").as_str(),
+            get(move || storage_version(storage_version_reader)),
+        )
         .route(
             format!("/{MONITORING_PREFIX}/alive").as_str(),
-            get(move || async { StatusCode::OK.to_string() }),
+            get(move || alive(alive_component_states)),
         )
         .route(
             format!("/{MONITORING_PREFIX}/metrics").as_str(),
@@ -253,22 +509,124 @@ fn app(
         )
         .route(
             format!("/{MONITORING_PREFIX}/ready").as_str(),
-            get(move || is_ready(starknet_client, starknet_feeder_client)),
+            get(move || is_ready(starknet_client, starknet_feeder_client, ready_component_states)),
+        )
+        .route(
+            format!("/{MONITORING_PREFIX}/components").as_str(),
+            get(move || components(component_states)),
         )
         .route(format!("/{MONITORING_PREFIX}/peer_id").as_str(), get(move || async { own_peer_id }))
+        .route(
+            format!("/{MONITORING_PREFIX}/protocolVersions").as_str(),
+            get(move || protocol_versions(network_registrations)),
+        )
+        .route(
+            format!("/{MONITORING_PREFIX}/peers").as_str(),
+            get(move || peers(served_bytes_by_peer, negotiated_protocols_by_peer, peer_latencies)),
+        )
+        .route(
+            format!("/{MONITORING_PREFIX}/networkEvents").as_str(),
+            get(move || network_events(recent_network_events)),
+        )
+        .route(
+            format!("/{MONITORING_PREFIX}/consensusStatus").as_str(),
+            get(move || consensus_status(shared_consensus_status)),
+        )
 }
 
+fn admin_app(
+    storage_writer: Option<Arc<Mutex<StorageWriter>>>,
+    peer_manager_command_sender: Option<UnboundedSender<PeerManagerCommand>>,
+    config_reload_sender: Option<UnboundedSender<ConfigReloadRequest>>,
+) -> Router {
+    let mut router = Router::new();
+    if let Some(storage_writer) = storage_writer {
+        router = router.route(
+            format!("/{ADMIN_PREFIX}/injectBlock").as_str(),
+            post(move |body| inject_block(storage_writer, body)),
+        );
+    }
+    if let Some(peer_manager_command_sender) = peer_manager_command_sender {
+        let blacklist_sender = peer_manager_command_sender.clone();
+        let unblacklist_sender = peer_manager_command_sender.clone();
+        router = router
+            .route(
+                format!("/{ADMIN_PREFIX}/peerManager").as_str(),
+                get(move || peer_manager_state(peer_manager_command_sender)),
+            )
+            .route(
+                format!("/{ADMIN_PREFIX}/peerManager/blacklist").as_str(),
+                post(move |body| blacklist_peer(blacklist_sender, body)),
+            )
+            .route(
+                format!("/{ADMIN_PREFIX}/peerManager/blacklist/:peer_id").as_str(),
+                delete(move |peer_id| unblacklist_peer(unblacklist_sender, peer_id)),
+            );
+    }
+    if let Some(config_reload_sender) = config_reload_sender {
+        router = router.route(
+            format!("/{ADMIN_PREFIX}/reloadConfig").as_str(),
+            post(move || reload_config(config_reload_sender)),
+        );
+    }
+    router
+}
+
+// The node is alive unless one of its components failed for good.
+async fn alive(component_states: ComponentStates) -> Result<String, (StatusCode, String)> {
+    let failed_components =
+        components_with_status(&component_states, |status| status == ComponentStatus::Failed);
+    if !failed_components.is_empty() {
+        return Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("Failed components: {}", failed_components.join(", ")),
+        ));
+    }
+    Ok(StatusCode::OK.to_string())
+}
+
+// The node is ready once all of its components run.
 async fn is_ready<TStarknetWriter: StarknetWriter, TStarknetReader: StarknetReader>(
     starknet_client: Arc<TStarknetWriter>,
     starknet_feeder_client: Arc<TStarknetReader>,
-) -> String {
+    component_states: ComponentStates,
+) -> Result<String, (StatusCode, String)> {
+    let components_not_running =
+        components_with_status(&component_states, |status| status != ComponentStatus::Running);
+    if !components_not_running.is_empty() {
+        return Err((
+            StatusCode::SERVICE_UNAVAILABLE,
+            format!("Components that aren't running: {}", components_not_running.join(", ")),
+        ));
+    }
+
     let response = starknet_feeder_client.is_alive().await;
     assert!(response);
 
     let response = starknet_client.is_alive().await;
     assert!(response);
 
-    StatusCode::OK.to_string()
+    Ok(StatusCode::OK.to_string())
+}
+
+fn components_with_status(
+    component_states: &ComponentStates,
+    predicate: impl Fn(ComponentStatus) -> bool,
+) -> Vec<String> {
+    component_states
+        .read()
+        .expect("Component states lock should not be poisoned")
+        .iter()
+        .filter(|(_, state)| predicate(state.status))
+        .map(|(name, _)| name.clone())
+        .collect()
+}
+
+/// Returns the states of the node's components.
+async fn components(
+    component_states: ComponentStates,
+) -> axum::Json<BTreeMap<String, ComponentState>> {
+    component_states.read().expect("Component states lock should not be poisoned").clone().into()
 }
 
 /// Returns DB statistics.
@@ -320,16 +678,223 @@ async fn metrics(prometheus_handle: Option<PrometheusHandle>) -> Response {
     }
 }
 
+/// Validates the given block and writes it to the storage. The block should be encoded as a
+/// protobuf `Block` message, and it should be the next block after the last block in the storage.
+#[instrument(skip(storage_writer, body), level = "debug", err)]
+async fn inject_block(
+    storage_writer: Arc<Mutex<StorageWriter>>,
+    body: axum::body::Bytes,
+) -> Result<String, ServerError> {
+    let block = FullBlock::try_from(body.to_vec()).map_err(P2PSyncError::from)?;
+    papyrus_p2p_sync::inject_block(&mut *storage_writer.lock().await, block)?;
+    Ok(StatusCode::OK.to_string())
+}
+
+/// Returns the peers the node's peer manager found, whether and until when each of them is blocked,
+/// the sessions that were assigned to it, and the peers that were banned manually.
+#[instrument(skip(peer_manager_command_sender), level = "debug", err)]
+async fn peer_manager_state(
+    peer_manager_command_sender: UnboundedSender<PeerManagerCommand>,
+) -> Result<Json<PeerManagerState>, ServerError> {
+    let (state_sender, state_receiver) = oneshot::channel();
+    peer_manager_command_sender
+        .unbounded_send(PeerManagerCommand::GetState(state_sender))
+        .map_err(|_| ServerError::NetworkNotRunning)?;
+    Ok(state_receiver.await.map_err(|_| ServerError::NetworkNotRunning)?.into())
+}
+
+/// A request to ban a peer manually.
+#[derive(Debug, Deserialize)]
+struct BlacklistRequest {
+    peer_id: String,
+    duration_seconds: u64,
+}
+
+/// Bans the given peer for the given duration, even if it reconnects. Banning the bootstrap peer is
+/// allowed, but the node might not find new peers until the ban is lifted.
+#[instrument(skip(peer_manager_command_sender), level = "debug", err)]
+async fn blacklist_peer(
+    peer_manager_command_sender: UnboundedSender<PeerManagerCommand>,
+    Json(request): Json<BlacklistRequest>,
+) -> Result<String, ServerError> {
+    let peer_id = parse_peer_id(&request.peer_id)?;
+    peer_manager_command_sender
+        .unbounded_send(PeerManagerCommand::Blacklist {
+            peer_id,
+            duration: std::time::Duration::from_secs(request.duration_seconds),
+        })
+        .map_err(|_| ServerError::NetworkNotRunning)?;
+    Ok(StatusCode::OK.to_string())
+}
+
+/// Lifts the ban of the given peer, whether it was banned manually or due to its reputation.
+#[instrument(skip(peer_manager_command_sender), level = "debug", err)]
+async fn unblacklist_peer(
+    peer_manager_command_sender: UnboundedSender<PeerManagerCommand>,
+    Path(peer_id): Path<String>,
+) -> Result<String, ServerError> {
+    let peer_id = parse_peer_id(&peer_id)?;
+    peer_manager_command_sender
+        .unbounded_send(PeerManagerCommand::Unblacklist { peer_id })
+        .map_err(|_| ServerError::NetworkNotRunning)?;
+    Ok(StatusCode::OK.to_string())
+}
+
+fn parse_peer_id(peer_id: &str) -> Result<PeerId, ServerError> {
+    PeerId::from_str(peer_id).map_err(|_| ServerError::InvalidPeerId(peer_id.to_string()))
+}
+
+/// Reloads the node's config from the sources it was loaded from when the node started, and applies
+/// the changes to the params that can change while the node is running. Returns the params whose
+/// changes were applied and the params whose changes were rejected.
+#[instrument(skip(config_reload_sender), level = "debug", err)]
+async fn reload_config(
+    config_reload_sender: UnboundedSender<ConfigReloadRequest>,
+) -> Result<Json<ConfigReloadOutcome>, ServerError> {
+    let (outcome_sender, outcome_receiver) = oneshot::channel();
+    config_reload_sender
+        .unbounded_send(outcome_sender)
+        .map_err(|_| ServerError::ConfigReloaderNotRunning)?;
+    let outcome = outcome_receiver.await.map_err(|_| ServerError::ConfigReloaderNotRunning)?;
+    Ok(outcome.map_err(ServerError::InvalidConfig)?.into())
+}
+
 /// Returns the node version.
 #[instrument(level = "debug", ret)]
 async fn node_version(version: &'static str) -> String {
     version.to_string()
 }
 
+/// Returns the versioned names of the p2p protocols the node sends queries on and answers queries
+/// on, and the topics it broadcasts on.
+#[instrument(level = "debug", ret)]
+async fn protocol_versions(
+    network_registrations: NetworkRegistrations,
+) -> axum::Json<NetworkRegistrations> {
+    network_registrations.into()
+}
+
+/// What the node knows about a peer that queried it or that it queried.
+#[derive(Debug, Default, Serialize)]
+struct PeerInfo {
+    /// The number of bytes the node sent to the peer in response to its queries.
+    served_bytes: u64,
+    /// The versioned names of the protocols the node and the peer agreed on in their sessions,
+    /// sorted.
+    negotiated_protocols: Vec<String>,
+    /// The outcome of the pings to the peer. Missing if the node didn't ping the peer.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ping: Option<PingInfo>,
+}
+
+#[derive(Debug, Serialize)]
+struct PingInfo {
+    /// The round trip time of the pings to the peer in milliseconds, smoothed over the recent
+    /// pings. Missing if the peer didn't answer any ping.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    rtt_millis: Option<u128>,
+    /// The number of pings the peer failed to answer.
+    lost_pings: u64,
+    /// False if the peer failed to answer too many consecutive pings.
+    healthy: bool,
+}
+
+/// Returns the peers that queried the node, that the node queried or that the node pinged, by
+/// their peer id.
+#[instrument(
+    skip(served_bytes_by_peer, negotiated_protocols_by_peer, peer_latencies),
+    level = "debug",
+    ret
+)]
+async fn peers(
+    served_bytes_by_peer: ServedBytesByPeer,
+    negotiated_protocols_by_peer: NegotiatedProtocolsByPeer,
+    peer_latencies: PeerLatencies,
+) -> axum::Json<BTreeMap<String, PeerInfo>> {
+    let mut peers = BTreeMap::<String, PeerInfo>::new();
+    for (peer_id, served_bytes) in
+        served_bytes_by_peer.lock().expect("Served bytes lock should not be poisoned").iter()
+    {
+        peers.entry(peer_id.to_string()).or_default().served_bytes = *served_bytes;
+    }
+    for (peer_id, negotiated_protocols) in negotiated_protocols_by_peer
+        .lock()
+        .expect("Negotiated protocols lock should not be poisoned")
+        .iter()
+    {
+        let mut negotiated_protocols = negotiated_protocols
+            .values()
+            .map(|protocol_name| protocol_name.to_string())
+            .collect::<Vec<_>>();
+        negotiated_protocols.sort();
+        peers.entry(peer_id.to_string()).or_default().negotiated_protocols = negotiated_protocols;
+    }
+    for (peer_id, latency) in
+        peer_latencies.lock().expect("Peer latencies lock should not be poisoned").iter()
+    {
+        peers.entry(peer_id.to_string()).or_default().ping = Some(PingInfo {
+            rtt_millis: latency.smoothed_rtt.map(|smoothed_rtt| smoothed_rtt.as_millis()),
+            lost_pings: latency.lost_pings,
+            healthy: latency.healthy,
+        });
+    }
+    peers.into()
+}
+
+/// Returns the last events of the node's network, oldest first. Events are recorded only if
+/// `network.debug_events` is set or the network events are traced by the log level.
+#[instrument(skip(recent_network_events), level = "debug")]
+async fn network_events(
+    recent_network_events: RecentNetworkEvents,
+) -> axum::Json<Vec<NetworkEvent>> {
+    let recent_network_events =
+        recent_network_events.lock().expect("Recent network events lock should not be poisoned");
+    recent_network_events.iter().cloned().collect::<Vec<_>>().into()
+}
+
+/// Returns the live state of consensus, if the node runs consensus as a validator.
+#[instrument(skip(shared_consensus_status), level = "debug")]
+async fn consensus_status(
+    shared_consensus_status: SharedConsensusStatus,
+) -> Result<Json<ConsensusStatus>, ServerError> {
+    let consensus_status =
+        shared_consensus_status.read().expect("Consensus status lock should not be poisoned");
+    Ok(consensus_status.clone().ok_or(ServerError::ConsensusNotRunning)?.into())
+}
+
+/// Returns whether the node writes to its storage ("read_write") or only reads a storage that is
+/// written by another node ("read_only").
+#[instrument(skip(storage_reader), level = "debug", ret)]
+async fn node_mode(storage_reader: StorageReader) -> String {
+    let mode = if storage_reader.is_read_only() { "read_only" } else { "read_write" };
+    mode.to_string()
+}
+
+/// Returns the schema versions of the storage and the versions the node supports, for checking
+/// whether the storage needs a migration or a re-sync before an upgrade.
+#[instrument(skip(storage_reader), level = "debug", ret)]
+async fn storage_version(
+    storage_reader: StorageReader,
+) -> Result<Json<StorageVersionInfo>, ServerError> {
+    Ok(storage_reader.storage_version_info()?.into())
+}
+
 #[derive(thiserror::Error, Debug)]
 enum ServerError {
     #[error(transparent)]
     StorageError(#[from] StorageError),
+    #[error(transparent)]
+    BlockInjectionError(#[from] P2PSyncError),
+    #[error("Invalid peer id: {0}.")]
+    InvalidPeerId(String),
+    #[error("The node's p2p network isn't running.")]
+    NetworkNotRunning,
+    #[error("Failed reloading the config: {0}")]
+    InvalidConfig(String),
+    #[error("The node's config reloader isn't running.")]
+    ConfigReloaderNotRunning,
+    #[error("The node doesn't run consensus as a validator.")]
+    ConsensusNotRunning,
 }
 
 impl IntoResponse for ServerError {
@@ -337,6 +902,17 @@ impl IntoResponse for ServerError {
         let (status, error_message) = match self {
             // TODO(dan): consider using a generic error message instead.
             ServerError::StorageError(err) => (StatusCode::INTERNAL_SERVER_ERROR, err.to_string()),
+            ServerError::BlockInjectionError(P2PSyncError::StorageError(err)) => {
+                (StatusCode::INTERNAL_SERVER_ERROR, err.to_string())
+            }
+            ServerError::BlockInjectionError(err) => (StatusCode::BAD_REQUEST, err.to_string()),
+            ServerError::InvalidPeerId(_) => (StatusCode::BAD_REQUEST, self.to_string()),
+            ServerError::NetworkNotRunning => (StatusCode::SERVICE_UNAVAILABLE, self.to_string()),
+            ServerError::InvalidConfig(_) => (StatusCode::BAD_REQUEST, self.to_string()),
+            ServerError::ConfigReloaderNotRunning => {
+                (StatusCode::SERVICE_UNAVAILABLE, self.to_string())
+            }
+            ServerError::ConsensusNotRunning => (StatusCode::NOT_FOUND, self.to_string()),
         };
         (status, error_message).into_response()
     }
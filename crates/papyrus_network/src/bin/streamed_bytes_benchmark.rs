@@ -92,7 +92,7 @@ fn create_outbound_sessions_if_all_peers_connected(
         for peer_id in peers_pending_outbound_session {
             for _ in 0..args.num_queries_per_connection {
                 let outbound_session_id =
-                    swarm.behaviour_mut().send_query(vec![], *peer_id, PROTOCOL_NAME).expect(
+                    swarm.behaviour_mut().send_query(vec![], *peer_id, vec![PROTOCOL_NAME]).expect(
                         "There's no connection to a peer immediately after we got a \
                          ConnectionEstablished event",
                     );
@@ -286,6 +286,7 @@ async fn main() {
                 outbound_session_id,
                 data,
                 peer_id: _,
+                protocol_name: _,
             })) => {
                 if data[0] != CONST_BYTE {
                     outbound_session_measurements
@@ -33,6 +33,10 @@ where
         .expect("Error building TCP transport")
         // TODO: quic transpot does not work (failure appears in the command line when running in debug mode)
         // .with_quic()
+        // Resolves the host names of /dns, /dns4 and /dns6 addresses with the system's resolver
+        // config.
+        .with_dns()
+        .expect("Error building DNS transport")
         .with_behaviour(|key| behaviour(key.clone()))
         .expect("Error while building the swarm")
         .with_swarm_config(|cfg| cfg.with_idle_connection_timeout(idle_connection_timeout))
@@ -0,0 +1,156 @@
+//! Denies connections with peers that the config doesn't allow. The connections are denied once
+//! the peer id of the other side is known and before any protocol is negotiated on them.
+
+use std::collections::HashSet;
+use std::convert::Infallible;
+use std::task::{Context, Poll};
+
+use libp2p::core::Endpoint;
+use libp2p::swarm::{
+    dummy,
+    ConnectionDenied,
+    ConnectionId,
+    FromSwarm,
+    NetworkBehaviour,
+    THandler,
+    THandlerInEvent,
+    THandlerOutEvent,
+    ToSwarm,
+};
+use libp2p::{Multiaddr, PeerId};
+use papyrus_common::metrics as papyrus_metrics;
+use tracing::debug;
+
+use crate::mixed_behaviour;
+
+#[cfg(test)]
+mod test;
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum ConnectionGatingError {
+    #[error("Peer {0} is in the deny-list.")]
+    PeerIsDenied(PeerId),
+    #[error("Peer {0} isn't in the allow-list.")]
+    PeerIsNotAllowed(PeerId),
+}
+
+impl ConnectionGatingError {
+    pub fn peer_id(&self) -> PeerId {
+        match self {
+            Self::PeerIsDenied(peer_id) | Self::PeerIsNotAllowed(peer_id) => *peer_id,
+        }
+    }
+}
+
+pub struct Behaviour {
+    // If empty, every peer that isn't denied is allowed.
+    allowed_peers: HashSet<PeerId>,
+    restrict_inbound: bool,
+    denied_peers: HashSet<PeerId>,
+}
+
+impl Behaviour {
+    /// The bootstrap peer is allowed even if it's not in allowed_peers, since the node can't join
+    /// the network without it.
+    pub fn new(
+        allowed_peers: Vec<PeerId>,
+        restrict_inbound: bool,
+        denied_peers: Vec<PeerId>,
+        bootstrap_peer_id: Option<PeerId>,
+    ) -> Self {
+        let mut allowed_peers: HashSet<_> = allowed_peers.into_iter().collect();
+        if !allowed_peers.is_empty() {
+            allowed_peers.extend(bootstrap_peer_id);
+        }
+        Self { allowed_peers, restrict_inbound, denied_peers: denied_peers.into_iter().collect() }
+    }
+
+    fn check_peer(&self, peer_id: PeerId, endpoint: Endpoint) -> Result<(), ConnectionDenied> {
+        let restrict_to_allowed_peers = match endpoint {
+            Endpoint::Dialer => !self.allowed_peers.is_empty(),
+            Endpoint::Listener => self.restrict_inbound,
+        };
+        let error = if self.denied_peers.contains(&peer_id) {
+            ConnectionGatingError::PeerIsDenied(peer_id)
+        } else if restrict_to_allowed_peers && !self.allowed_peers.contains(&peer_id) {
+            ConnectionGatingError::PeerIsNotAllowed(peer_id)
+        } else {
+            return Ok(());
+        };
+        let direction = match endpoint {
+            Endpoint::Dialer => "outbound",
+            Endpoint::Listener => "inbound",
+        };
+        debug!("Denied {direction} connection: {error}");
+        metrics::increment_counter!(
+            papyrus_metrics::PAPYRUS_NETWORK_DENIED_CONNECTIONS,
+            "direction" => direction
+        );
+        Err(ConnectionDenied::new(error))
+    }
+}
+
+impl NetworkBehaviour for Behaviour {
+    type ConnectionHandler = dummy::ConnectionHandler;
+    type ToSwarm = Infallible;
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        _connection_id: ConnectionId,
+        peer: PeerId,
+        _local_addr: &Multiaddr,
+        _remote_addr: &Multiaddr,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        self.check_peer(peer, Endpoint::Listener)?;
+        Ok(dummy::ConnectionHandler)
+    }
+
+    fn handle_pending_outbound_connection(
+        &mut self,
+        _connection_id: ConnectionId,
+        maybe_peer: Option<PeerId>,
+        _addresses: &[Multiaddr],
+        _effective_role: Endpoint,
+    ) -> Result<Vec<Multiaddr>, ConnectionDenied> {
+        // Dials without a peer id are checked once the connection is established.
+        if let Some(peer_id) = maybe_peer {
+            self.check_peer(peer_id, Endpoint::Dialer)?;
+        }
+        Ok(vec![])
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        _connection_id: ConnectionId,
+        peer: PeerId,
+        _addr: &Multiaddr,
+        _role_override: Endpoint,
+    ) -> Result<THandler<Self>, ConnectionDenied> {
+        self.check_peer(peer, Endpoint::Dialer)?;
+        Ok(dummy::ConnectionHandler)
+    }
+
+    fn on_swarm_event(&mut self, _event: FromSwarm<'_>) {}
+
+    fn on_connection_handler_event(
+        &mut self,
+        _peer_id: PeerId,
+        _connection_id: ConnectionId,
+        _event: THandlerOutEvent<Self>,
+    ) {
+        // no events from dummy handler
+    }
+
+    fn poll(
+        &mut self,
+        _cx: &mut Context<'_>,
+    ) -> Poll<ToSwarm<Self::ToSwarm, THandlerInEvent<Self>>> {
+        Poll::Pending
+    }
+}
+
+impl From<Infallible> for mixed_behaviour::Event {
+    fn from(event: Infallible) -> Self {
+        match event {}
+    }
+}
@@ -0,0 +1,98 @@
+use libp2p::core::Endpoint;
+use libp2p::swarm::{ConnectionDenied, ConnectionId, NetworkBehaviour};
+use libp2p::{Multiaddr, PeerId};
+
+use super::{Behaviour, ConnectionGatingError};
+
+// Returns the error of the connection, or None if the connection is allowed.
+fn gating_error(result: Result<impl Sized, ConnectionDenied>) -> Option<ConnectionGatingError> {
+    // ConnectionHandler doesn't implement Debug so we have to check the result like that.
+    result.err().map(|cause| {
+        cause.downcast::<ConnectionGatingError>().expect("Connection was denied by another error")
+    })
+}
+
+fn inbound_error(behaviour: &mut Behaviour, peer_id: PeerId) -> Option<ConnectionGatingError> {
+    gating_error(behaviour.handle_established_inbound_connection(
+        ConnectionId::new_unchecked(0),
+        peer_id,
+        &Multiaddr::empty(),
+        &Multiaddr::empty(),
+    ))
+}
+
+fn outbound_error(behaviour: &mut Behaviour, peer_id: PeerId) -> Option<ConnectionGatingError> {
+    let pending_error = gating_error(behaviour.handle_pending_outbound_connection(
+        ConnectionId::new_unchecked(0),
+        Some(peer_id),
+        &[],
+        Endpoint::Dialer,
+    ));
+    let established_error = gating_error(behaviour.handle_established_outbound_connection(
+        ConnectionId::new_unchecked(0),
+        peer_id,
+        &Multiaddr::empty(),
+        Endpoint::Dialer,
+    ));
+    assert_eq!(pending_error, established_error);
+    pending_error
+}
+
+#[test]
+fn every_peer_is_allowed_by_default() {
+    let mut behaviour = Behaviour::new(vec![], false, vec![], None);
+    let peer_id = PeerId::random();
+    assert_eq!(inbound_error(&mut behaviour, peer_id), None);
+    assert_eq!(outbound_error(&mut behaviour, peer_id), None);
+}
+
+#[test]
+fn allow_list_restricts_inbound_connections_only_if_flag_is_set() {
+    let allowed_peer_id = PeerId::random();
+    let other_peer_id = PeerId::random();
+
+    let mut behaviour = Behaviour::new(vec![allowed_peer_id], false, vec![], None);
+    assert_eq!(outbound_error(&mut behaviour, allowed_peer_id), None);
+    assert_eq!(
+        outbound_error(&mut behaviour, other_peer_id),
+        Some(ConnectionGatingError::PeerIsNotAllowed(other_peer_id))
+    );
+    assert_eq!(inbound_error(&mut behaviour, other_peer_id), None);
+
+    let mut behaviour = Behaviour::new(vec![allowed_peer_id], true, vec![], None);
+    assert_eq!(inbound_error(&mut behaviour, allowed_peer_id), None);
+    assert_eq!(
+        inbound_error(&mut behaviour, other_peer_id),
+        Some(ConnectionGatingError::PeerIsNotAllowed(other_peer_id))
+    );
+}
+
+#[test]
+fn bootstrap_peer_is_implicitly_allowed() {
+    let bootstrap_peer_id = PeerId::random();
+    let mut behaviour =
+        Behaviour::new(vec![PeerId::random()], true, vec![], Some(bootstrap_peer_id));
+    assert_eq!(inbound_error(&mut behaviour, bootstrap_peer_id), None);
+    assert_eq!(outbound_error(&mut behaviour, bootstrap_peer_id), None);
+}
+
+#[test]
+fn denied_peers_are_denied_in_both_directions() {
+    let denied_peer_id = PeerId::random();
+    let mut behaviour = Behaviour::new(vec![denied_peer_id], true, vec![denied_peer_id], None);
+    assert_eq!(
+        inbound_error(&mut behaviour, denied_peer_id),
+        Some(ConnectionGatingError::PeerIsDenied(denied_peer_id))
+    );
+    assert_eq!(
+        outbound_error(&mut behaviour, denied_peer_id),
+        Some(ConnectionGatingError::PeerIsDenied(denied_peer_id))
+    );
+
+    let mut behaviour = Behaviour::new(vec![], false, vec![denied_peer_id], None);
+    assert_eq!(inbound_error(&mut behaviour, PeerId::random()), None);
+    assert_eq!(
+        inbound_error(&mut behaviour, denied_peer_id),
+        Some(ConnectionGatingError::PeerIsDenied(denied_peer_id))
+    );
+}
@@ -0,0 +1,187 @@
+use std::sync::{Arc, Mutex};
+
+use papyrus_common::metrics as papyrus_metrics;
+use papyrus_protobuf::sync::{BlockHashOrNumber, Query};
+use papyrus_storage::header::HeaderStorageReader;
+use papyrus_storage::{db, StorageTxn};
+use starknet_api::block::{BlockHash, BlockNumber};
+
+use super::response_cache::{BlockItems, ResponseCache};
+use super::{utils, DBExecutorError, FetchBlockDataFromDb, QueryStorageReader};
+use crate::Protocol;
+
+/// The result of reading the next chunk of a query's blocks.
+pub(crate) enum CursorRead<Data> {
+    /// The next blocks of the query with the items of each block, or None for a block whose data
+    /// can't be encoded. Never empty.
+    Chunk(Vec<(BlockNumber, Option<BlockItems<Data>>)>),
+    /// All the blocks of the query were read.
+    Done,
+}
+
+/// Reads the blocks of a query in chunks of at most `blocks_per_chunk` blocks. Each chunk is read
+/// in its own read transaction, which is closed before the chunk is returned, so serving a long
+/// query doesn't hold a read transaction while its data is sent. A long-lived read transaction
+/// prevents the storage from reusing the pages freed after it began, which makes the database file
+/// grow.
+///
+/// Each chunk resumes from the block after the last block that was read. Blocks that are in the
+/// response cache are taken from it instead of from the storage. If the last block that was read
+/// was reverted since, reading fails with [`DBExecutorError::Reverted`], since the blocks after it
+/// might not continue the data that was already read.
+///
+/// A read that fails leaves the cursor at the block it failed on, so once the block is written,
+/// e.g. when an open-ended query reached the tip, the cursor resumes from it.
+pub(crate) struct ResumableBlockCursor<Reader> {
+    storage_reader: Reader,
+    query: Query,
+    blocks_per_chunk: u64,
+    protocol: Protocol,
+    response_cache: Arc<Mutex<ResponseCache>>,
+    // Resolved once the first chunk is read, since the query may start from a block hash.
+    start_block_number: Option<u64>,
+    num_blocks_read: u64,
+    // The number and hash of the last block that was read, for detecting that it was reverted.
+    last_read_block: Option<(BlockNumber, Option<BlockHash>)>,
+    // An error hit after some of the blocks of a chunk were read. It's returned on the next read,
+    // so that the blocks before it are returned first.
+    pending_error: Option<DBExecutorError>,
+}
+
+impl<Reader: QueryStorageReader> ResumableBlockCursor<Reader> {
+    pub fn new(
+        storage_reader: Reader,
+        query: Query,
+        blocks_per_chunk: u64,
+        protocol: Protocol,
+        response_cache: Arc<Mutex<ResponseCache>>,
+    ) -> Self {
+        Self {
+            storage_reader,
+            query,
+            blocks_per_chunk: blocks_per_chunk.max(1),
+            protocol,
+            response_cache,
+            start_block_number: None,
+            num_blocks_read: 0,
+            last_read_block: None,
+            pending_error: None,
+        }
+    }
+
+    pub fn next_chunk<Data: FetchBlockDataFromDb>(
+        &mut self,
+    ) -> Result<CursorRead<Data>, DBExecutorError> {
+        if let Some(error) = self.pending_error.take() {
+            return Err(error);
+        }
+        if self.num_blocks_read >= self.query.limit {
+            return Ok(CursorRead::Done);
+        }
+        let storage_reader = self.storage_reader.clone();
+        let txn = storage_reader.begin_ro_txn()?;
+        if let Some((block_number, block_hash)) = self.last_read_block {
+            if txn.get_block_header(block_number)?.map(|header| header.block_hash) != block_hash {
+                return Err(DBExecutorError::Reverted { block_number });
+            }
+        }
+        let start_block_number = match self.start_block_number {
+            Some(start_block_number) => start_block_number,
+            None => {
+                // An unknown hash is answered with an immediate Fin (sent by the caller). A hash
+                // that resolves to a block number is handled exactly like a query that started
+                // from that number.
+                let start_block_number = match self.query.start_block {
+                    BlockHashOrNumber::Number(BlockNumber(num)) => num,
+                    BlockHashOrNumber::Hash(block_hash) => {
+                        txn.get_block_number_by_hash(&block_hash)?
+                            .ok_or(DBExecutorError::NotFound {
+                                block_hash_or_number: BlockHashOrNumber::Hash(block_hash),
+                            })?
+                            .0
+                    }
+                };
+                self.start_block_number = Some(start_block_number);
+                start_block_number
+            }
+        };
+
+        let mut chunk = Vec::new();
+        while self.num_blocks_read < self.query.limit
+            && (chunk.len() as u64) < self.blocks_per_chunk
+        {
+            match self.read_next_block::<Data>(&txn, start_block_number) {
+                Ok((block_number, data)) => {
+                    self.num_blocks_read += 1;
+                    chunk.push((block_number, data));
+                }
+                Err(error) if chunk.is_empty() => return Err(error),
+                Err(error) => {
+                    self.pending_error = Some(error);
+                    break;
+                }
+            }
+        }
+        Ok(CursorRead::Chunk(chunk))
+    }
+
+    // Reads the items of the block after the last block that was read, and records its hash once
+    // it's read.
+    fn read_next_block<Data: FetchBlockDataFromDb>(
+        &mut self,
+        txn: &StorageTxn<'_, db::RO>,
+        start_block_number: u64,
+    ) -> Result<(BlockNumber, Option<BlockItems<Data>>), DBExecutorError> {
+        let block_number = BlockNumber(utils::calculate_block_number(
+            &self.query,
+            start_block_number,
+            self.num_blocks_read,
+        )?);
+        let block_hash = txn.get_block_header(block_number)?.map(|header| header.block_hash);
+
+        // The entry is checked against the hash of the block in the storage, so a block that was
+        // reverted since it was cached is read again.
+        if let Some(block_hash) = block_hash {
+            let cached_items = self
+                .response_cache
+                .lock()
+                .expect("Failed to lock response cache.")
+                .get::<Data>(self.protocol, block_number, block_hash);
+            if cached_items.is_some() {
+                metrics::increment_counter!(
+                    papyrus_metrics::PAPYRUS_INBOUND_QUERY_CACHE_HITS,
+                    "protocol" => self.protocol.as_str()
+                );
+                self.last_read_block = Some((block_number, Some(block_hash)));
+                return Ok((block_number, cached_items));
+            }
+            metrics::increment_counter!(
+                papyrus_metrics::PAPYRUS_INBOUND_QUERY_CACHE_MISSES,
+                "protocol" => self.protocol.as_str()
+            );
+        }
+
+        let data = self.storage_reader.read_block_data::<Data>(block_number, txn)?;
+        self.last_read_block = Some((block_number, block_hash));
+        if !data.iter().all(Data::is_encodable) {
+            return Ok((block_number, None));
+        }
+        let is_cacheable = Data::is_cacheable(&data);
+        let items = data
+            .into_iter()
+            .map(|data| {
+                let num_bytes = data.encoded_len() as u64;
+                (data, num_bytes)
+            })
+            .collect::<BlockItems<Data>>();
+        if let (Some(block_hash), true) = (block_hash, is_cacheable) {
+            self.response_cache.lock().expect("Failed to lock response cache.").insert(
+                self.protocol,
+                block_number,
+                block_hash,
+                items.clone(),
+            );
+        }
+        Ok((block_number, Some(items)))
+    }
+}
@@ -0,0 +1,109 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use papyrus_common::metrics as papyrus_metrics;
+use tokio::time::Instant;
+use tracing::{info, warn};
+
+use super::DBExecutorError;
+use crate::Protocol;
+
+/// How an inbound query is handled, given the health of its protocol.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) enum Admission {
+    /// The protocol is healthy, so the query is served.
+    Serve,
+    /// The protocol was stopped, and the query is served to check if the storage recovered.
+    HealthCheck,
+    /// The protocol was stopped, so the query's session is closed without a response.
+    Reject,
+}
+
+struct StoppedProtocol {
+    stopped_at: Instant,
+    is_health_check_running: bool,
+}
+
+/// Tracks the protocols that stopped being served since reading their data from the storage
+/// failed. Serving a protocol from a storage that fails would only send peers responses that end
+/// early, so its queries are rejected until a health check passes.
+///
+/// Once `health_check_interval` passed since a protocol was stopped, its next query is served as
+/// a health check. If the query doesn't fail on the storage, the protocol is served again.
+/// Otherwise, it's stopped for another interval.
+pub(crate) struct ProtocolHealth {
+    health_check_interval: Duration,
+    stopped_protocols: Mutex<HashMap<Protocol, StoppedProtocol>>,
+}
+
+impl ProtocolHealth {
+    pub fn new(health_check_interval: Duration) -> Self {
+        Self { health_check_interval, stopped_protocols: Mutex::new(HashMap::new()) }
+    }
+
+    pub fn admit(&self, protocol: Protocol) -> Admission {
+        let mut stopped_protocols =
+            self.stopped_protocols.lock().expect("Failed to lock protocol health.");
+        let Some(stopped_protocol) = stopped_protocols.get_mut(&protocol) else {
+            return Admission::Serve;
+        };
+        // Only one query at a time checks the health, so that a failing storage isn't read by
+        // all the queries that arrive once the interval passes.
+        if stopped_protocol.is_health_check_running
+            || stopped_protocol.stopped_at.elapsed() < self.health_check_interval
+        {
+            return Admission::Reject;
+        }
+        stopped_protocol.is_health_check_running = true;
+        Admission::HealthCheck
+    }
+
+    /// Updates the health of the protocol with the result of a query it admitted.
+    pub fn report(
+        &self,
+        protocol: Protocol,
+        admission: Admission,
+        result: &Result<(), DBExecutorError>,
+    ) {
+        let mut stopped_protocols =
+            self.stopped_protocols.lock().expect("Failed to lock protocol health.");
+        match result {
+            Err(error) if error.is_storage_failure() => {
+                warn!(
+                    protocol = protocol.as_str(),
+                    "Stopped serving the protocol since the storage failed: {error}. Retrying in \
+                     {:?}.",
+                    self.health_check_interval
+                );
+                stopped_protocols.insert(
+                    protocol,
+                    StoppedProtocol { stopped_at: Instant::now(), is_health_check_running: false },
+                );
+                metrics::gauge!(
+                    papyrus_metrics::PAPYRUS_INBOUND_PROTOCOL_STOPPED,
+                    1.0,
+                    "protocol" => protocol.as_str()
+                );
+            }
+            _ if admission == Admission::HealthCheck => {
+                info!(protocol = protocol.as_str(), "The storage recovered. Serving the protocol.");
+                stopped_protocols.remove(&protocol);
+                metrics::gauge!(
+                    papyrus_metrics::PAPYRUS_INBOUND_PROTOCOL_STOPPED,
+                    0.0,
+                    "protocol" => protocol.as_str()
+                );
+            }
+            _ => {}
+        }
+    }
+
+    #[cfg(test)]
+    pub fn is_stopped(&self, protocol: Protocol) -> bool {
+        self.stopped_protocols
+            .lock()
+            .expect("Failed to lock protocol health.")
+            .contains_key(&protocol)
+    }
+}
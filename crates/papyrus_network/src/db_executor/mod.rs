@@ -1,72 +1,218 @@
-use std::vec;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use std::{fs, vec};
 
 use futures::channel::mpsc::SendError;
 use futures::{Sink, SinkExt, Stream, StreamExt};
-use papyrus_protobuf::converters::ProtobufConversionError;
+use libp2p::PeerId;
+use papyrus_common::metrics as papyrus_metrics;
+use papyrus_protobuf::converters::{can_encode_transaction_output, ProtobufConversionError};
+pub use papyrus_protobuf::sync::split_thin_state_diff;
 use papyrus_protobuf::sync::{
+    BlockDataAvailability,
     BlockHashOrNumber,
-    ContractDiff,
+    BlockRangeAdvertisement,
     DataOrFin,
-    DeclaredClass,
-    DeprecatedDeclaredClass,
     HeaderQuery,
+    ProtocolBlockRange,
     Query,
+    ResponseLimits,
     SignedBlockHeader,
     StateDiffChunk,
     StateDiffQuery,
     TransactionQuery,
 };
 use papyrus_storage::body::BodyStorageReader;
+use papyrus_storage::db::DbError;
 use papyrus_storage::header::HeaderStorageReader;
 use papyrus_storage::state::StateStorageReader;
-use papyrus_storage::{db, StorageReader, StorageTxn};
+use papyrus_storage::{
+    db,
+    open_storage_read_only,
+    StorageConfig,
+    StorageError,
+    StorageReader,
+    StorageResult,
+    StorageTxn,
+};
 use starknet_api::block::BlockNumber;
-use starknet_api::state::ThinStateDiff;
 use starknet_api::transaction::{Transaction, TransactionOutput};
-use tracing::error;
+use tokio::sync::{watch, OwnedSemaphorePermit, Semaphore};
+use tracing::{debug, error, info, warn};
+
+use self::cursor::{CursorRead, ResumableBlockCursor};
+use self::health::{Admission, ProtocolHealth};
+use self::response_cache::ResponseCache;
+use crate::{InboundQueryLogMode, Protocol};
 
 #[cfg(test)]
 mod test;
 
+mod cursor;
+mod health;
+mod response_cache;
 mod utils;
 
+// The number of items that are read from the storage for a query before they're sent to the peer.
+const BLOCKING_READ_BUFFER_SIZE: usize = 16;
+// Once every this many items, a blocking read checks if its query was cancelled.
+const CANCELLATION_CHECK_INTERVAL: usize = 16;
+// How long a protocol isn't served after its storage failed, before a query checks if it recovered.
+const PROTOCOL_HEALTH_CHECK_INTERVAL: Duration = Duration::from_secs(30);
+// How often an open-ended query at the tip reads the storage for a new block, in addition to
+// reading it once it's notified of a new header.
+const FOLLOW_QUERY_POLL_INTERVAL: Duration = Duration::from_secs(1);
+
+/// Why serving a query failed. The kind of the failure decides what the peer receives: a query
+/// that failed for a benign reason is answered with a Fin, as if the node has no more data for it,
+/// while a response that failed on the node's side ends without a Fin, so that the peer can tell
+/// that the response is incomplete and query another peer.
 #[derive(thiserror::Error, Debug)]
 pub enum DBExecutorError {
-    #[error(transparent)]
-    DBInternalError(#[from] papyrus_storage::StorageError),
-    #[error("Block number is out of range. Query: {query:?}, counter: {counter}")]
-    BlockNumberOutOfRange { query: Query, counter: u64 },
+    /// The storage doesn't have the data, e.g. since the block wasn't synced yet. Benign.
     // TODO: add data type to the error message.
     #[error("Block not found. Block: {block_hash_or_number:?}")]
-    BlockNotFound { block_hash_or_number: BlockHashOrNumber },
-    // This error should be non recoverable.
-    #[error(transparent)]
-    JoinError(#[from] tokio::task::JoinError),
-    // TODO: remove this error, use BlockNotFound instead.
-    // This error should be non recoverable.
-    #[error("Block {block_number:?} is in the storage but its signature isn't.")]
-    SignatureNotFound { block_number: BlockNumber },
+    NotFound { block_hash_or_number: BlockHashOrNumber },
+    /// The query asks for blocks beyond the range of block numbers. Benign.
+    #[error("Block number is out of range. Query: {query:?}, counter: {counter}")]
+    BlockNumberOutOfRange { query: Query, counter: u64 },
+    /// The block was reverted while its query was served, so the blocks after it might not
+    /// continue the data that was sent. Benign.
+    #[error("Block {block_number} was reverted while its query was served.")]
+    Reverted { block_number: BlockNumber },
+    /// The storage holds data that can't be decoded or that contradicts other data. Serious, so
+    /// the protocol isn't served until a health check passes.
+    #[error("The storage is corrupted: {0}")]
+    Corruption(StorageError),
+    /// The storage or its files couldn't be read. Serious, so the protocol isn't served until a
+    /// health check passes.
+    #[error("Failed reading the storage: {0}")]
+    IO(StorageError),
+    /// A bug in the node, e.g. a read that panicked.
+    #[error("Internal error: {0}")]
+    Internal(String),
+    /// The peer closed the session, so the rest of the response can't be sent.
     #[error(transparent)]
     SendError(#[from] futures::channel::mpsc::SendError),
 }
 
+impl From<StorageError> for DBExecutorError {
+    fn from(error: StorageError) -> Self {
+        match error {
+            StorageError::InnerError(DbError::InnerDeserialization)
+            | StorageError::DBInconsistency { .. }
+            | StorageError::MarkerMismatch { .. }
+            | StorageError::SerdeError(_)
+            | StorageError::StorageVersionInconsistency(_) => Self::Corruption(error),
+            // MDBX errors, e.g. a page that can't be found, are treated as failures to read the
+            // storage since they're reported with the same codes whether the file is corrupted or
+            // the read failed.
+            StorageError::InnerError(DbError::Inner(_))
+            | StorageError::InnerError(DbError::FileDoesNotExist(_))
+            | StorageError::IOError(_)
+            | StorageError::MMapFileError(_)
+            | StorageError::UninitializedStorage => Self::IO(error),
+            // The rest are errors of writes or of misuse of the storage.
+            _ => Self::Internal(error.to_string()),
+        }
+    }
+}
+
+impl From<tokio::task::JoinError> for DBExecutorError {
+    fn from(error: tokio::task::JoinError) -> Self {
+        Self::Internal(format!("The storage read of the query failed: {error}"))
+    }
+}
+
 impl DBExecutorError {
-    pub fn should_log_in_error_level(&self) -> bool {
+    /// Whether the query is answered with a Fin, since the node just doesn't have more data for
+    /// it.
+    pub fn is_benign(&self) -> bool {
+        match self {
+            Self::NotFound { .. }
+            | Self::BlockNumberOutOfRange { .. }
+            | Self::Reverted { .. }
+            | Self::SendError(_) => true,
+            Self::Corruption(_) | Self::IO(_) | Self::Internal(_) => false,
+        }
+    }
+
+    /// Whether the storage failed, so the protocol isn't served until a health check passes.
+    pub fn is_storage_failure(&self) -> bool {
+        matches!(self, Self::Corruption(_) | Self::IO(_))
+    }
+
+    fn kind(&self) -> &'static str {
         match self {
-            Self::JoinError(_) | Self::SignatureNotFound { .. } | Self::SendError { .. }
-            // TODO(shahak): Consider returning false for some of the StorageError variants.
-            | Self::DBInternalError { .. } => true,
-            Self::BlockNumberOutOfRange { .. } | Self::BlockNotFound { .. } => false,
+            Self::NotFound { .. } => "not_found",
+            Self::BlockNumberOutOfRange { .. } => "block_number_out_of_range",
+            Self::Reverted { .. } => "reverted",
+            Self::Corruption(_) => "corruption",
+            Self::IO(_) => "io",
+            Self::Internal(_) => "internal",
+            Self::SendError(_) => "send_error",
         }
     }
 }
 
+/// The reads of the storage that serving a query does. Implemented by [`StorageReader`], and by
+/// wrappers of it that inject failures in tests.
+pub trait QueryStorageReader: Clone + Send + Sync + 'static {
+    fn begin_ro_txn(&self) -> StorageResult<StorageTxn<'_, db::RO>>;
+
+    fn read_block_data<Data: FetchBlockDataFromDb>(
+        &self,
+        block_number: BlockNumber,
+        txn: &StorageTxn<'_, db::RO>,
+    ) -> Result<Vec<Data>, DBExecutorError> {
+        Data::fetch_block_data_from_db(block_number, txn)
+    }
+}
+
+impl QueryStorageReader for StorageReader {
+    fn begin_ro_txn(&self) -> StorageResult<StorageTxn<'_, db::RO>> {
+        StorageReader::begin_ro_txn(self)
+    }
+}
+
 /// A DBExecutor receives inbound queries and returns their corresponding data.
-pub struct DBExecutor<HeaderQueryReceiver, StateDiffQueryReceiver, TransactionQueryReceiver> {
-    storage_reader: StorageReader,
+pub struct DBExecutor<
+    HeaderQueryReceiver,
+    StateDiffQueryReceiver,
+    TransactionQueryReceiver,
+    Reader = StorageReader,
+> {
+    // The storage the queries are served from. None while it can't be opened, in which case the
+    // queries are answered with Fin.
+    storage_reader: watch::Receiver<Option<Reader>>,
     header_queries_receiver: HeaderQueryReceiver,
     state_diff_queries_receiver: StateDiffQueryReceiver,
     transaction_queries_receiver: TransactionQueryReceiver,
+    inbound_query_log_mode: InboundQueryLogMode,
+    inbound_query_log_sample_rate: u64,
+    // Used for choosing which queries to log when sampling.
+    num_registered_queries: u64,
+    // Bounds the number of queries that read from the storage concurrently. Storage reads are
+    // synchronous, so each one runs on a blocking thread that holds a permit.
+    blocking_reads_semaphore: Arc<Semaphore>,
+    // The number of blocks read in each read transaction of a query.
+    blocks_per_read_txn: u64,
+    // Shared by all the queries, which consult it before reading a block from the storage.
+    response_cache: Arc<Mutex<ResponseCache>>,
+    // Read for each query, so that the limits can be changed while the node is running.
+    response_limits: watch::Receiver<ResponseLimits>,
+    // The protocols that aren't served since their storage failed.
+    protocol_health: Arc<ProtocolHealth>,
+    // Notifies the open-ended header queries that reached the tip of new headers.
+    header_marker: watch::Receiver<BlockNumber>,
+    // An open-ended query is ended once no new header was written for this long.
+    follow_query_idle_timeout: Duration,
+    // Bounds the number of queries that are served concurrently to the limit of inbound sessions.
+    queries_semaphore: Arc<Semaphore>,
+    // The permit of the next query, which is taken before the query is received. The queries
+    // above the limit wait in their channels instead of being spawned.
+    next_query_permit: Option<OwnedSemaphorePermit>,
 }
 
 impl<
@@ -76,98 +222,348 @@ impl<
     HeaderResponsesSender,
     StateDiffResponsesSender,
     TransactionResponsesSender,
-> DBExecutor<HeaderQueryReceiver, StateDiffQueryReceiver, TransactionQueryReceiver>
+    Reader,
+> DBExecutor<HeaderQueryReceiver, StateDiffQueryReceiver, TransactionQueryReceiver, Reader>
 where
-    HeaderQueryReceiver: Stream<Item = (Result<HeaderQuery, ProtobufConversionError>, HeaderResponsesSender)>
-        + Unpin,
+    Reader: QueryStorageReader,
+    HeaderQueryReceiver: Stream<
+            Item = (
+                Result<HeaderQuery, ProtobufConversionError>,
+                HeaderResponsesSender,
+                PeerId,
+            ),
+        > + Unpin,
     HeaderResponsesSender:
         Sink<DataOrFin<SignedBlockHeader>, Error = SendError> + Unpin + Send + 'static,
-    StateDiffQueryReceiver: Stream<Item = (Result<StateDiffQuery, ProtobufConversionError>, StateDiffResponsesSender)>
-        + Unpin,
+    StateDiffQueryReceiver: Stream<
+            Item = (
+                Result<StateDiffQuery, ProtobufConversionError>,
+                StateDiffResponsesSender,
+                PeerId,
+            ),
+        > + Unpin,
     StateDiffResponsesSender:
         Sink<DataOrFin<StateDiffChunk>, Error = SendError> + Unpin + Send + 'static,
     TransactionQueryReceiver: Stream<
-            Item = (Result<TransactionQuery, ProtobufConversionError>, TransactionResponsesSender),
+            Item = (
+                Result<TransactionQuery, ProtobufConversionError>,
+                TransactionResponsesSender,
+                PeerId,
+            ),
         > + Unpin,
-    TransactionResponsesSender: Sink<DataOrFin<(Transaction, TransactionOutput)>, Error = SendError>
-        + Unpin
+    TransactionResponsesSender: Sink<
+            DataOrFin<(Transaction, Option<TransactionOutput>)>,
+            Error = SendError,
+        > + Unpin
         + Send
         + 'static,
 {
     pub async fn run(mut self) {
         loop {
+            if self.next_query_permit.is_none() {
+                self.next_query_permit = Some(
+                    self.queries_semaphore
+                        .clone()
+                        .acquire_owned()
+                        .await
+                        .expect("The queries semaphore is never closed."),
+                );
+            }
             tokio::select! {
                 result = self.header_queries_receiver.next() => {
-                    let (query_result, response_sender) = result.expect(
+                    let (query_result, response_sender, peer_id) = result.expect(
                         "Header queries sender was unexpectedly dropped."
                     );
-                    // TODO(shahak): Report if query_result is Err.
+                    // A malformed query was already reported when it was decoded, and its session
+                    // is closed.
                     if let Ok(query) = query_result {
-                        self.register_query(query.0, response_sender);
+                        self.register_query(
+                            query.0, response_sender, peer_id, Protocol::SignedBlockHeader
+                        );
                     }
                 }
                 result = self.state_diff_queries_receiver.next() => {
-                    let (query_result, response_sender) = result.expect(
+                    let (query_result, response_sender, peer_id) = result.expect(
                         "State diff queries sender was unexpectedly dropped."
                     );
-                    // TODO(shahak): Report if query_result is Err.
                     if let Ok(query) = query_result {
-                        self.register_query(query.0, response_sender);
+                        self.register_query(
+                            query.0, response_sender, peer_id, Protocol::StateDiff
+                        );
                     }
                 }
                 result = self.transaction_queries_receiver.next() => {
-                    let (query_result, response_sender) = result.expect(
+                    let (query_result, response_sender, peer_id) = result.expect(
                         "Transaction queries sender was unexpectedly dropped."
                     );
-                    // TODO: Report if query_result is Err.
                     if let Ok(query) = query_result {
-                        self.register_query(query.0, response_sender);
+                        self.register_transaction_query(query, response_sender, peer_id);
                     }
                 }
             };
         }
     }
 
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
-        storage_reader: StorageReader,
+        storage_reader: watch::Receiver<Option<Reader>>,
         header_queries_receiver: HeaderQueryReceiver,
         state_diff_queries_receiver: StateDiffQueryReceiver,
         transaction_queries_receiver: TransactionQueryReceiver,
+        inbound_query_log_mode: InboundQueryLogMode,
+        inbound_query_log_sample_rate: u64,
+        max_blocking_reads: usize,
+        blocks_per_read_txn: u64,
+        response_cache_max_bytes: u64,
+        response_limits: watch::Receiver<ResponseLimits>,
+        header_marker: watch::Receiver<BlockNumber>,
+        follow_query_idle_timeout: Duration,
+        max_concurrent_queries: usize,
     ) -> Self {
         Self {
             storage_reader,
             header_queries_receiver,
             state_diff_queries_receiver,
             transaction_queries_receiver,
+            inbound_query_log_mode,
+            inbound_query_log_sample_rate,
+            num_registered_queries: 0,
+            blocking_reads_semaphore: Arc::new(Semaphore::new(max_blocking_reads)),
+            blocks_per_read_txn,
+            response_cache: Arc::new(Mutex::new(ResponseCache::new(response_cache_max_bytes))),
+            response_limits,
+            protocol_health: Arc::new(ProtocolHealth::new(PROTOCOL_HEALTH_CHECK_INTERVAL)),
+            header_marker,
+            follow_query_idle_timeout,
+            queries_semaphore: Arc::new(Semaphore::new(max_concurrent_queries)),
+            next_query_permit: None,
+        }
+    }
+
+    // A query that excludes the outputs is served without reading them from the storage.
+    fn register_transaction_query(
+        &mut self,
+        query: TransactionQuery,
+        response_sender: TransactionResponsesSender,
+        peer_id: PeerId,
+    ) {
+        if query.include_outputs {
+            self.register_query::<(Transaction, Option<TransactionOutput>), _>(
+                query.query,
+                response_sender,
+                peer_id,
+                Protocol::Transaction,
+            );
+        } else {
+            let response_sender = response_sender.with(|data: DataOrFin<Transaction>| {
+                futures::future::ready(Ok::<_, SendError>(DataOrFin(
+                    data.0.map(|transaction| (transaction, None)),
+                )))
+            });
+            self.register_query::<Transaction, _>(
+                query.query,
+                response_sender,
+                peer_id,
+                Protocol::Transaction,
+            );
         }
     }
 
-    fn register_query<Data, Sender>(&self, query: Query, sender: Sender)
-    where
+    fn register_query<Data, Sender>(
+        &mut self,
+        query: Query,
+        sender: Sender,
+        peer_id: PeerId,
+        protocol: Protocol,
+    ) where
         Data: FetchBlockDataFromDb + Send + 'static,
         Sender: Sink<DataOrFin<Data>> + Unpin + Send + 'static,
         DBExecutorError: From<<Sender as Sink<DataOrFin<Data>>>::Error>,
     {
-        let storage_reader_clone = self.storage_reader.clone();
+        let admission = self.protocol_health.admit(protocol);
+        if admission == Admission::Reject {
+            // Dropping the sender closes the session without a Fin, so the peer queries another
+            // peer.
+            debug!(
+                %peer_id,
+                protocol = protocol.as_str(),
+                "Rejected inbound query since the protocol isn't served after a storage failure."
+            );
+            return;
+        }
+        let should_log = self.should_log_next_query();
+        let storage_reader = self.storage_reader.borrow().clone();
+        let blocking_reads_semaphore = self.blocking_reads_semaphore.clone();
+        let blocks_per_read_txn = self.blocks_per_read_txn;
+        let response_cache = self.response_cache.clone();
+        let response_limits = *self.response_limits.borrow();
+        let protocol_health = self.protocol_health.clone();
+        let header_marker = self.header_marker.clone();
+        let follow_query_idle_timeout = self.follow_query_idle_timeout;
+        // Held until the query is served.
+        let query_permit = self.next_query_permit.take();
         tokio::task::spawn(async move {
-            let result = send_data_for_query(storage_reader_clone, query.clone(), sender).await;
-            if let Err(error) = result {
-                if error.should_log_in_error_level() {
-                    error!("Running inbound query {query:?} failed on {error:?}");
+            let _query_permit = query_permit;
+            let start_time = Instant::now();
+            let mut served_data = ServedData::default();
+            let result = match storage_reader {
+                Some(storage_reader) => {
+                    send_data_for_query(
+                        storage_reader,
+                        query.clone(),
+                        sender,
+                        blocking_reads_semaphore,
+                        blocks_per_read_txn,
+                        protocol,
+                        response_cache,
+                        response_limits,
+                        header_marker,
+                        follow_query_idle_timeout,
+                        &mut served_data,
+                    )
+                    .await
+                }
+                None => {
+                    served_data.read_end = ReadEnd::StorageUnavailable;
+                    send_fin(sender).await
                 }
-                Err(error)
-            } else {
-                Ok(())
+            };
+            metrics::increment_counter!(
+                papyrus_metrics::PAPYRUS_INBOUND_QUERIES,
+                "protocol" => protocol.as_str()
+            );
+            metrics::counter!(
+                papyrus_metrics::PAPYRUS_INBOUND_QUERY_ITEMS_SERVED,
+                served_data.num_items,
+                "protocol" => protocol.as_str()
+            );
+            metrics::counter!(
+                papyrus_metrics::PAPYRUS_INBOUND_QUERY_BYTES_SERVED,
+                served_data.num_bytes,
+                "protocol" => protocol.as_str()
+            );
+            match served_data.read_end {
+                ReadEnd::Completed | ReadEnd::StorageUnavailable | ReadEnd::IdleAtTip => {}
+                ReadEnd::ReachedResponseLimits => metrics::increment_counter!(
+                    papyrus_metrics::PAPYRUS_INBOUND_QUERIES_LIMITED,
+                    "protocol" => protocol.as_str()
+                ),
+                ReadEnd::UnencodableBlock(block_number) => {
+                    warn!(
+                        %peer_id,
+                        protocol = protocol.as_str(),
+                        "The data of block {block_number} can't be encoded. Ended the response \
+                         before it."
+                    );
+                    metrics::increment_counter!(
+                        papyrus_metrics::PAPYRUS_INBOUND_QUERIES_WITH_UNENCODABLE_DATA,
+                        "protocol" => protocol.as_str()
+                    );
+                }
+            }
+            // Only the metadata of the query is logged, never the data that was sent.
+            if should_log {
+                info!(
+                    %peer_id,
+                    protocol = protocol.as_str(),
+                    start_block = ?query.start_block,
+                    direction = ?query.direction,
+                    limit = query.limit,
+                    step = query.step,
+                    items_served = served_data.num_items,
+                    bytes_served = served_data.num_bytes,
+                    read_end = ?served_data.read_end,
+                    duration_ms = start_time.elapsed().as_millis() as u64,
+                    succeeded = result.is_ok(),
+                    "Served inbound query."
+                );
             }
+            protocol_health.report(protocol, admission, &result);
+            if let Err(error) = &result {
+                log_query_error(error, &query, peer_id, protocol);
+            }
+            result
         });
     }
 }
 
-pub trait FetchBlockDataFromDb: Sized {
+impl<HeaderQueryReceiver, StateDiffQueryReceiver, TransactionQueryReceiver, Reader>
+    DBExecutor<HeaderQueryReceiver, StateDiffQueryReceiver, TransactionQueryReceiver, Reader>
+{
+    fn should_log_next_query(&mut self) -> bool {
+        let query_index = self.num_registered_queries;
+        self.num_registered_queries += 1;
+        match self.inbound_query_log_mode {
+            InboundQueryLogMode::Disabled => false,
+            InboundQueryLogMode::All => true,
+            InboundQueryLogMode::Sampled => {
+                query_index % self.inbound_query_log_sample_rate.max(1) == 0
+            }
+        }
+    }
+}
+
+fn log_query_error(error: &DBExecutorError, query: &Query, peer_id: PeerId, protocol: Protocol) {
+    match error {
+        DBExecutorError::Reverted { block_number } => warn!(
+            %peer_id,
+            protocol = protocol.as_str(),
+            "Block {block_number} was reverted while its query was served. Ended the response \
+             after it."
+        ),
+        DBExecutorError::Corruption(_) | DBExecutorError::IO(_) => {
+            error!(
+                %peer_id,
+                protocol = protocol.as_str(),
+                "Reading the storage for inbound query {query:?} failed: {error}"
+            );
+        }
+        // A bug, so everything that may help reproduce it is logged.
+        DBExecutorError::Internal(_) => error!(
+            %peer_id,
+            protocol = protocol.as_str(),
+            "Running inbound query {query:?} failed on an internal error: {error:?}"
+        ),
+        DBExecutorError::NotFound { .. }
+        | DBExecutorError::BlockNumberOutOfRange { .. }
+        | DBExecutorError::SendError(_) => {
+            debug!(%peer_id, protocol = protocol.as_str(), "Ended inbound query early: {error}")
+        }
+    }
+    if !error.is_benign() {
+        metrics::increment_counter!(
+            papyrus_metrics::PAPYRUS_INBOUND_QUERY_STORAGE_FAILURES,
+            "protocol" => protocol.as_str(),
+            "kind" => error.kind()
+        );
+    }
+}
+
+pub trait FetchBlockDataFromDb: Clone + Send + Sync + 'static {
     fn fetch_block_data_from_db(
         block_number: BlockNumber,
         txn: &StorageTxn<'_, db::RO>,
     ) -> Result<Vec<Self>, DBExecutorError>;
+
+    /// The number of bytes of the item once it's encoded for sending over the network.
+    fn encoded_len(&self) -> usize;
+
+    /// Whether the item can be encoded in the protocol's schema. Encoding an item that can't be
+    /// encoded fails the session, so such items are never sent.
+    fn is_encodable(&self) -> bool;
+
+    /// Whether the data of a block won't change unless the block is reverted, so it can be served
+    /// from the response cache.
+    fn is_cacheable(_block_data: &[Self]) -> bool {
+        true
+    }
+}
+
+fn encoded_len<Data: Clone>(data: &Data) -> usize
+where
+    Vec<u8>: From<DataOrFin<Data>>,
+{
+    Vec::<u8>::from(DataOrFin(Some(data.clone()))).len()
 }
 
 impl FetchBlockDataFromDb for SignedBlockHeader {
@@ -175,24 +571,53 @@ impl FetchBlockDataFromDb for SignedBlockHeader {
         block_number: BlockNumber,
         txn: &StorageTxn<'_, db::RO>,
     ) -> Result<Vec<Self>, DBExecutorError> {
-        let mut header =
-            txn.get_block_header(block_number)?.ok_or(DBExecutorError::BlockNotFound {
-                block_hash_or_number: BlockHashOrNumber::Number(block_number),
-            })?;
+        let mut header = txn.get_block_header(block_number)?.ok_or(DBExecutorError::NotFound {
+            block_hash_or_number: BlockHashOrNumber::Number(block_number),
+        })?;
         // TODO(shahak) Remove this once central sync fills the state_diff_length field.
         if header.state_diff_length.is_none() {
             header.state_diff_length = Some(
                 txn.get_state_diff(block_number)?
-                    .ok_or(DBExecutorError::BlockNotFound {
+                    .ok_or(DBExecutorError::NotFound {
                         block_hash_or_number: BlockHashOrNumber::Number(block_number),
                     })?
                     .len(),
             );
         }
-        let signature = txn
-            .get_block_signature(block_number)?
-            .ok_or(DBExecutorError::SignatureNotFound { block_number })?;
-        Ok(vec![SignedBlockHeader { block_header: header, signatures: vec![signature] }])
+        // The signature is written with the header, so a header without one means the storage
+        // is corrupted.
+        let signature = txn.get_block_signature(block_number)?.ok_or_else(|| {
+            DBExecutorError::Corruption(StorageError::DBInconsistency {
+                msg: format!("Block {block_number} has a header but no signature."),
+            })
+        })?;
+        // Let the other peer know if it can ask us for the rest of the block.
+        let data_availability = BlockDataAvailability {
+            has_body: txn.get_body_marker()? > block_number,
+            has_state_diff: txn.get_state_marker()? > block_number,
+        };
+        Ok(vec![SignedBlockHeader {
+            block_header: header,
+            signatures: vec![signature],
+            data_availability: Some(data_availability),
+        }])
+    }
+
+    fn encoded_len(&self) -> usize {
+        encoded_len(self)
+    }
+
+    fn is_encodable(&self) -> bool {
+        true
+    }
+
+    // The availability of the rest of the block changes as it's synced.
+    fn is_cacheable(block_data: &[Self]) -> bool {
+        block_data.iter().all(|signed_header| {
+            signed_header.data_availability.as_ref().is_some_and(|data_availability| {
+                data_availability.has_body && data_availability.has_state_diff
+            })
+        })
     }
 }
 
@@ -202,123 +627,461 @@ impl FetchBlockDataFromDb for StateDiffChunk {
         txn: &StorageTxn<'_, db::RO>,
     ) -> Result<Vec<Self>, DBExecutorError> {
         let thin_state_diff =
-            txn.get_state_diff(block_number)?.ok_or(DBExecutorError::BlockNotFound {
+            txn.get_state_diff(block_number)?.ok_or(DBExecutorError::NotFound {
                 block_hash_or_number: BlockHashOrNumber::Number(block_number),
             })?;
         Ok(split_thin_state_diff(thin_state_diff))
     }
+
+    fn encoded_len(&self) -> usize {
+        encoded_len(self)
+    }
+
+    fn is_encodable(&self) -> bool {
+        true
+    }
 }
 
-impl FetchBlockDataFromDb for (Transaction, TransactionOutput) {
+impl FetchBlockDataFromDb for (Transaction, Option<TransactionOutput>) {
     fn fetch_block_data_from_db(
         block_number: BlockNumber,
         txn: &StorageTxn<'_, db::RO>,
     ) -> Result<Vec<Self>, DBExecutorError> {
         let transactions =
-            txn.get_block_transactions(block_number)?.ok_or(DBExecutorError::BlockNotFound {
+            txn.get_block_transactions(block_number)?.ok_or(DBExecutorError::NotFound {
                 block_hash_or_number: BlockHashOrNumber::Number(block_number),
             })?;
-        let transaction_outputs = txn.get_block_transaction_outputs(block_number)?.ok_or(
-            DBExecutorError::BlockNotFound {
+        let transaction_outputs =
+            txn.get_block_transaction_outputs(block_number)?.ok_or(DBExecutorError::NotFound {
                 block_hash_or_number: BlockHashOrNumber::Number(block_number),
-            },
-        )?;
-        let mut result: Vec<(Transaction, TransactionOutput)> = Vec::new();
+            })?;
+        let mut result: Vec<(Transaction, Option<TransactionOutput>)> = Vec::new();
         for (transaction, transaction_output) in
             transactions.into_iter().zip(transaction_outputs.into_iter())
         {
-            result.push((transaction, transaction_output));
+            result.push((transaction, Some(transaction_output)));
         }
         Ok(result)
     }
+
+    fn encoded_len(&self) -> usize {
+        encoded_len(self)
+    }
+
+    // All the transaction types can be encoded, but the execution resources of old blocks might
+    // not fit the receipt's schema.
+    fn is_encodable(&self) -> bool {
+        self.1.as_ref().map_or(true, can_encode_transaction_output)
+    }
 }
 
-pub fn split_thin_state_diff(thin_state_diff: ThinStateDiff) -> Vec<StateDiffChunk> {
-    let mut state_diff_chunks = Vec::new();
-    let mut contract_addresses = std::collections::HashSet::new();
-
-    contract_addresses.extend(
-        thin_state_diff
-            .deployed_contracts
-            .keys()
-            .chain(thin_state_diff.replaced_classes.keys())
-            .chain(thin_state_diff.nonces.keys())
-            .chain(thin_state_diff.storage_diffs.keys()),
-    );
-    for contract_address in contract_addresses {
-        let class_hash = thin_state_diff
-            .deployed_contracts
-            .get(&contract_address)
-            .or_else(|| thin_state_diff.replaced_classes.get(&contract_address))
-            .cloned();
-        let storage_diffs =
-            thin_state_diff.storage_diffs.get(&contract_address).cloned().unwrap_or_default();
-        let nonce = thin_state_diff.nonces.get(&contract_address).cloned();
-        state_diff_chunks.push(StateDiffChunk::ContractDiff(ContractDiff {
-            contract_address,
-            class_hash,
-            nonce,
-            storage_diffs,
-        }));
-    }
-
-    for (class_hash, compiled_class_hash) in thin_state_diff.declared_classes {
-        state_diff_chunks
-            .push(StateDiffChunk::DeclaredClass(DeclaredClass { class_hash, compiled_class_hash }));
-    }
-
-    for class_hash in thin_state_diff.deprecated_declared_classes {
-        state_diff_chunks
-            .push(StateDiffChunk::DeprecatedDeclaredClass(DeprecatedDeclaredClass { class_hash }));
-    }
-    state_diff_chunks
+// The transactions of a query that excludes the outputs. They're sent without outputs.
+impl FetchBlockDataFromDb for Transaction {
+    fn fetch_block_data_from_db(
+        block_number: BlockNumber,
+        txn: &StorageTxn<'_, db::RO>,
+    ) -> Result<Vec<Self>, DBExecutorError> {
+        txn.get_block_transactions(block_number)?.ok_or(DBExecutorError::NotFound {
+            block_hash_or_number: BlockHashOrNumber::Number(block_number),
+        })
+    }
+
+    fn encoded_len(&self) -> usize {
+        encoded_len(&(self.clone(), None::<TransactionOutput>))
+    }
+
+    fn is_encodable(&self) -> bool {
+        true
+    }
+}
+
+/// Broadcasts the ranges of blocks this node can serve on each of the sync protocols, and the
+/// limits on its responses, every `interval`. The limits are read before each advertisement, so
+/// changes to them are advertised. While the storage can't be opened, no blocks are advertised.
+/// Returns once the advertisements can't be sent anymore.
+pub async fn advertise_block_ranges<Sender>(
+    storage_reader: watch::Receiver<Option<StorageReader>>,
+    mut advertisement_sender: Sender,
+    interval: Duration,
+    response_limits: watch::Receiver<ResponseLimits>,
+) where
+    Sender: Sink<BlockRangeAdvertisement> + Unpin,
+{
+    loop {
+        let current_storage_reader = storage_reader.borrow().clone();
+        match get_block_range_advertisement(
+            current_storage_reader.as_ref(),
+            *response_limits.borrow(),
+        ) {
+            Ok(advertisement) => {
+                if advertisement_sender.send(advertisement).await.is_err() {
+                    error!("Failed sending block range advertisement. Stopping to advertise.");
+                    return;
+                }
+            }
+            Err(error) => warn!("Failed reading the block ranges to advertise: {error}"),
+        }
+        tokio::time::sleep(interval).await;
+    }
 }
 
-async fn send_data_for_query<Data, Sender>(
-    storage_reader: StorageReader,
+pub(crate) fn get_block_range_advertisement(
+    storage_reader: Option<&StorageReader>,
+    response_limits: ResponseLimits,
+) -> StorageResult<BlockRangeAdvertisement> {
+    let markers = match storage_reader {
+        Some(storage_reader) => {
+            let txn = storage_reader.begin_ro_txn()?;
+            [txn.get_header_marker()?, txn.get_state_marker()?, txn.get_body_marker()?]
+        }
+        None => [BlockNumber(0); 3],
+    };
+    let ranges = [Protocol::SignedBlockHeader, Protocol::StateDiff, Protocol::Transaction]
+        .into_iter()
+        .zip(markers)
+        .map(|(protocol, marker)| ProtocolBlockRange {
+            protocol: protocol.as_str().to_string(),
+            block_range: BlockNumber(0)..marker,
+        })
+        .collect();
+    Ok(BlockRangeAdvertisement { ranges, response_limits: Some(response_limits) })
+}
+
+/// Serves the queries from a read-only replica of the storage, e.g. one that is refreshed from
+/// snapshots, instead of from the storage the node writes to. The replica is opened with
+/// `replica_config` and sent to the DB executor, and every `reopen_interval` it's reopened if its
+/// files were replaced. While the replica can't be opened, None is sent, which disables serving.
+pub async fn serve_from_storage_replica(
+    replica_config: StorageConfig,
+    reopen_interval: Duration,
+    storage_reader_sender: watch::Sender<Option<StorageReader>>,
+) {
+    let db_file_path = replica_config.db_config.path().join("mdbx.dat");
+    // The modification time of the replica that is served, if one is served.
+    let mut served_version = None;
+    loop {
+        let version = fs::metadata(&db_file_path).and_then(|metadata| metadata.modified()).ok();
+        if version.is_none() || version != served_version {
+            let replica_config = replica_config.clone();
+            let open_result = tokio::task::spawn_blocking(move || {
+                open_storage_read_only(replica_config).map_err(DBExecutorError::from)
+            })
+            .await
+            .map_err(DBExecutorError::from)
+            .and_then(|result| result);
+            match open_result {
+                Ok(storage_reader) => {
+                    info!("Serving sync queries from the storage replica at {db_file_path:?}.");
+                    storage_reader_sender.send_replace(Some(storage_reader));
+                    served_version = version;
+                }
+                Err(error) => {
+                    warn!(
+                        "Failed opening the storage replica at {db_file_path:?}: {error}. \
+                         Answering sync queries with no data until it can be opened."
+                    );
+                    storage_reader_sender.send_replace(None);
+                    served_version = None;
+                }
+            }
+        }
+        tokio::time::sleep(reopen_interval).await;
+    }
+}
+
+// The data that was sent in response to a query.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+struct ServedData {
+    num_items: u64,
+    num_bytes: u64,
+    read_end: ReadEnd,
+}
+
+// Why the read of a query's data stopped.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+enum ReadEnd {
+    // All the blocks of the query were read, or the query was cancelled.
+    #[default]
+    Completed,
+    // The response reached the response limits before the last block of the query.
+    ReachedResponseLimits,
+    // The data of the block can't be encoded, so the response ended before it.
+    UnencodableBlock(BlockNumber),
+    // Serving is disabled since the storage can't be opened, so nothing was read.
+    StorageUnavailable,
+    // The query is open-ended, and no new block was added since the last block that was sent for
+    // the idle timeout.
+    IdleAtTip,
+}
+
+#[allow(clippy::too_many_arguments)]
+async fn send_data_for_query<Data, Sender, Reader>(
+    storage_reader: Reader,
     query: Query,
     mut sender: Sender,
+    blocking_reads_semaphore: Arc<Semaphore>,
+    blocks_per_read_txn: u64,
+    protocol: Protocol,
+    response_cache: Arc<Mutex<ResponseCache>>,
+    response_limits: ResponseLimits,
+    header_marker: watch::Receiver<BlockNumber>,
+    follow_query_idle_timeout: Duration,
+    served_data: &mut ServedData,
 ) -> Result<(), DBExecutorError>
 where
     Data: FetchBlockDataFromDb + Send + 'static,
     Sender: Sink<DataOrFin<Data>> + Unpin + Send + 'static,
     DBExecutorError: From<<Sender as Sink<DataOrFin<Data>>>::Error>,
+    Reader: QueryStorageReader,
+{
+    let result = send_data_without_fin_for_query(
+        storage_reader,
+        query,
+        &mut sender,
+        blocking_reads_semaphore,
+        blocks_per_read_txn,
+        protocol,
+        response_cache,
+        response_limits,
+        header_marker,
+        follow_query_idle_timeout,
+        served_data,
+    )
+    .await;
+    // The response ends with a Fin unless it failed on the node's side. In that case the sender is
+    // dropped, which closes the session without a Fin, so the peer knows the response is
+    // incomplete.
+    match result {
+        Ok(()) => send_fin(sender).await,
+        Err(error) if error.is_benign() => {
+            // A failure to send the Fin only means the peer already closed the session.
+            let _ = send_fin(sender).await;
+            Err(error)
+        }
+        Err(error) => Err(error),
+    }
+}
+
+async fn send_fin<Data, Sender>(mut sender: Sender) -> Result<(), DBExecutorError>
+where
+    Sender: Sink<DataOrFin<Data>> + Unpin,
+    DBExecutorError: From<<Sender as Sink<DataOrFin<Data>>>::Error>,
 {
-    // If this function fails, we still want to send fin before failing.
-    let result = send_data_without_fin_for_query(&storage_reader, query, &mut sender).await;
     sender.feed(DataOrFin(None)).await?;
-    result
+    Ok(())
 }
 
-async fn send_data_without_fin_for_query<Data, Sender>(
-    storage_reader: &StorageReader,
+/// Reads the data of the query on a blocking thread and forwards it to the sender as it's read.
+/// If the sender fails (e.g. the peer closed the session), the read is cancelled.
+///
+/// An open-ended header query isn't ended once it reaches the last header in the storage. Instead,
+/// the blocking thread is released until a new header is written, and then the read resumes. If no
+/// header is written for `follow_query_idle_timeout`, the response ends, so that the session
+/// doesn't time out on the peer's side while it's waiting for the next block.
+#[allow(clippy::too_many_arguments)]
+async fn send_data_without_fin_for_query<Data, Sender, Reader>(
+    storage_reader: Reader,
     query: Query,
     sender: &mut Sender,
+    blocking_reads_semaphore: Arc<Semaphore>,
+    blocks_per_read_txn: u64,
+    protocol: Protocol,
+    response_cache: Arc<Mutex<ResponseCache>>,
+    response_limits: ResponseLimits,
+    mut header_marker: watch::Receiver<BlockNumber>,
+    follow_query_idle_timeout: Duration,
+    served_data: &mut ServedData,
 ) -> Result<(), DBExecutorError>
 where
     Data: FetchBlockDataFromDb + Send + 'static,
     Sender: Sink<DataOrFin<Data>> + Unpin + Send + 'static,
     DBExecutorError: From<<Sender as Sink<DataOrFin<Data>>>::Error>,
+    Reader: QueryStorageReader,
+{
+    let follows_tip = query.is_open_ended() && protocol == Protocol::SignedBlockHeader;
+    let mut cursor = ResumableBlockCursor::new(
+        storage_reader,
+        query,
+        blocks_per_read_txn,
+        protocol,
+        response_cache,
+    );
+    let mut idle_deadline = tokio::time::Instant::now().checked_add(follow_query_idle_timeout);
+    loop {
+        let num_items_served = served_data.num_items;
+        let read_result;
+        (cursor, read_result) = read_and_send_data::<Data, _, _>(
+            cursor,
+            sender,
+            &blocking_reads_semaphore,
+            response_limits,
+            served_data,
+        )
+        .await?;
+        match read_result {
+            // The query reached the tip.
+            Err(DBExecutorError::NotFound {
+                block_hash_or_number: BlockHashOrNumber::Number(block_number),
+            }) if follows_tip => {
+                if served_data.num_items > num_items_served {
+                    idle_deadline =
+                        tokio::time::Instant::now().checked_add(follow_query_idle_timeout);
+                }
+                if !wait_for_header(&mut header_marker, block_number, idle_deadline).await {
+                    served_data.read_end = ReadEnd::IdleAtTip;
+                    return Ok(());
+                }
+            }
+            read_result => {
+                served_data.read_end = read_result?;
+                return Ok(());
+            }
+        }
+    }
+}
+
+// Reads the next blocks of the query on a blocking thread and sends their data as it's read.
+// Returns the cursor, so that the read can be resumed from where it stopped, with the result of
+// the read.
+async fn read_and_send_data<Data, Sender, Reader>(
+    mut cursor: ResumableBlockCursor<Reader>,
+    sender: &mut Sender,
+    blocking_reads_semaphore: &Arc<Semaphore>,
+    response_limits: ResponseLimits,
+    served_data: &mut ServedData,
+) -> Result<(ResumableBlockCursor<Reader>, Result<ReadEnd, DBExecutorError>), DBExecutorError>
+where
+    Data: FetchBlockDataFromDb + Send + 'static,
+    Sender: Sink<DataOrFin<Data>> + Unpin + Send + 'static,
+    DBExecutorError: From<<Sender as Sink<DataOrFin<Data>>>::Error>,
+    Reader: QueryStorageReader,
 {
-    let txn = storage_reader.begin_ro_txn()?;
-    let start_block_number = match query.start_block {
-        BlockHashOrNumber::Number(BlockNumber(num)) => num,
-        BlockHashOrNumber::Hash(block_hash) => {
-            txn.get_block_number_by_hash(&block_hash)?
-                .ok_or(DBExecutorError::BlockNotFound {
-                    block_hash_or_number: BlockHashOrNumber::Hash(block_hash),
-                })?
-                .0
+    let permit = blocking_reads_semaphore
+        .clone()
+        .acquire_owned()
+        .await
+        .expect("The blocking reads semaphore should never be closed.");
+    let (data_sender, mut data_receiver) = tokio::sync::mpsc::channel(BLOCKING_READ_BUFFER_SIZE);
+    let is_cancelled = Arc::new(AtomicBool::new(false));
+    let previously_served_data = *served_data;
+    let read_handle = tokio::task::spawn_blocking({
+        let is_cancelled = is_cancelled.clone();
+        move || {
+            // The permit is released once the read is done.
+            let _permit = permit;
+            let read_result = read_data_for_query::<Data, Reader>(
+                &mut cursor,
+                previously_served_data,
+                response_limits,
+                data_sender,
+                &is_cancelled,
+            );
+            (cursor, read_result)
         }
+    });
+
+    let mut send_result = Ok(());
+    while let Some((data, num_bytes)) = data_receiver.recv().await {
+        if let Err(error) = sender.feed(DataOrFin(Some(data))).await {
+            is_cancelled.store(true, Ordering::Relaxed);
+            send_result = Err(error.into());
+            break;
+        }
+        served_data.num_items += 1;
+        served_data.num_bytes += num_bytes;
+    }
+    // Closing the channel also stops a read that is waiting to send its next item.
+    drop(data_receiver);
+    let (cursor, read_result) = read_handle.await?;
+    send_result?;
+    Ok((cursor, read_result))
+}
+
+// Waits until the header of the given block may have been written. Returns false if the idle
+// deadline passed first.
+async fn wait_for_header(
+    header_marker: &mut watch::Receiver<BlockNumber>,
+    block_number: BlockNumber,
+    idle_deadline: Option<tokio::time::Instant>,
+) -> bool {
+    let header_written = async {
+        // Not every writer of the storage notifies of new headers, e.g. the writer of a storage
+        // replica, so the storage is also polled.
+        let _ = tokio::time::timeout(FOLLOW_QUERY_POLL_INTERVAL, async {
+            let is_notified =
+                header_marker.wait_for(|header_marker| *header_marker > block_number).await.is_ok();
+            if !is_notified {
+                // The notifications stopped, so only polling is left.
+                futures::future::pending::<()>().await;
+            }
+        })
+        .await;
     };
-    for block_counter in 0..query.limit {
-        let block_number =
-            BlockNumber(utils::calculate_block_number(&query, start_block_number, block_counter)?);
-        let data_vec = Data::fetch_block_data_from_db(block_number, &txn)?;
-        for data in data_vec {
-            // TODO: consider implement retry mechanism.
-            sender.feed(DataOrFin(Some(data))).await?;
+    let idle_timeout = async {
+        match idle_deadline {
+            Some(idle_deadline) => tokio::time::sleep_until(idle_deadline).await,
+            None => futures::future::pending().await,
+        }
+    };
+    tokio::select! {
+        _ = header_written => true,
+        _ = idle_timeout => false,
+    }
+}
+
+/// Reads the next blocks of the query from the storage and sends their data through the given
+/// channel with its encoded size. Blocks the current thread, so it shouldn't be called from an
+/// async context. Returns early without an error if the query was cancelled.
+///
+/// The blocks are read in chunks of `blocks_per_read_txn` blocks, each in its own read transaction
+/// that is closed before the chunk is sent, so waiting for the peer to receive the data doesn't
+/// hold a read transaction. If a block that was sent is reverted before the next chunk is read, the
+/// read fails after it. Blocks that are in the response cache are served from it.
+///
+/// Once the data that was served for the query, including `previously_served_data`, reaches
+/// either of the response limits, no more blocks are read. Blocks are never split, so the first
+/// block of the query is always sent, even if it alone exceeds the limits. A block with data that
+/// can't be encoded isn't sent, and the read stops before it. Returns why the read stopped.
+fn read_data_for_query<Data: FetchBlockDataFromDb, Reader: QueryStorageReader>(
+    cursor: &mut ResumableBlockCursor<Reader>,
+    previously_served_data: ServedData,
+    response_limits: ResponseLimits,
+    data_sender: tokio::sync::mpsc::Sender<(Data, u64)>,
+    is_cancelled: &AtomicBool,
+) -> Result<ReadEnd, DBExecutorError> {
+    let mut num_items_read = previously_served_data.num_items as usize;
+    let mut num_bytes_read = previously_served_data.num_bytes;
+    loop {
+        let chunk = match cursor.next_chunk::<Data>()? {
+            CursorRead::Chunk(chunk) => chunk,
+            CursorRead::Done => return Ok(ReadEnd::Completed),
+        };
+        for (block_number, block_items) in chunk {
+            if is_cancelled.load(Ordering::Relaxed) {
+                return Ok(ReadEnd::Completed);
+            }
+            if num_items_read as u64 >= response_limits.max_items
+                || num_bytes_read >= response_limits.max_bytes
+            {
+                return Ok(ReadEnd::ReachedResponseLimits);
+            }
+            let Some(block_items) = block_items else {
+                return Ok(ReadEnd::UnencodableBlock(block_number));
+            };
+            for (data, num_bytes) in block_items {
+                num_items_read += 1;
+                if num_items_read % CANCELLATION_CHECK_INTERVAL == 0
+                    && is_cancelled.load(Ordering::Relaxed)
+                {
+                    return Ok(ReadEnd::Completed);
+                }
+                num_bytes_read += num_bytes;
+                // TODO: consider implement retry mechanism.
+                if data_sender.blocking_send((data, num_bytes)).is_err() {
+                    // The receiving side stopped forwarding the data.
+                    return Ok(ReadEnd::Completed);
+                }
+            }
         }
     }
-    Ok(())
 }
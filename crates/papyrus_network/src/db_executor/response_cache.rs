@@ -0,0 +1,89 @@
+use std::any::{Any, TypeId};
+
+use lru::LruCache;
+use starknet_api::block::{BlockHash, BlockNumber};
+
+use crate::Protocol;
+
+/// The items of a block as they're sent in response to a query, each with its encoded size.
+pub(crate) type BlockItems<Data> = Vec<(Data, u64)>;
+
+type CacheKey = (Protocol, TypeId, BlockNumber);
+
+struct CachedBlock {
+    // The hash of the block when it was cached. The entry is only served while the block with this
+    // number in the storage has the same hash, so a block from a branch that was reverted is never
+    // served.
+    block_hash: BlockHash,
+    // The BlockItems of the data type in the key.
+    items: Box<dyn Any + Send + Sync>,
+    num_bytes: u64,
+}
+
+/// A cache of the responses to queries for recent blocks, shared by all the inbound queries. Most
+/// peers follow the tip of the chain, so they query the same blocks. A cached block is served
+/// without reading it from the storage and without encoding its items to measure them.
+///
+/// The least recently used blocks are evicted once the encoded size of the cached items exceeds
+/// `max_bytes`. If `max_bytes` is 0, nothing is cached.
+pub(crate) struct ResponseCache {
+    // A protocol may serve a block with several data types, e.g. transactions with or without
+    // their outputs, so each type is cached separately.
+    blocks: LruCache<CacheKey, CachedBlock>,
+    max_bytes: u64,
+    num_bytes: u64,
+}
+
+impl ResponseCache {
+    pub fn new(max_bytes: u64) -> Self {
+        Self { blocks: LruCache::unbounded(), max_bytes, num_bytes: 0 }
+    }
+
+    /// Returns the cached items of the block, if they were cached while the block had the given
+    /// hash. An entry of a block that was reverted since it was cached is removed.
+    pub fn get<Data: Clone + 'static>(
+        &mut self,
+        protocol: Protocol,
+        block_number: BlockNumber,
+        block_hash: BlockHash,
+    ) -> Option<BlockItems<Data>> {
+        let key = (protocol, TypeId::of::<Data>(), block_number);
+        let cached_block = self.blocks.get(&key)?;
+        if cached_block.block_hash != block_hash {
+            self.remove(&key);
+            return None;
+        }
+        cached_block.items.downcast_ref::<BlockItems<Data>>().cloned()
+    }
+
+    pub fn insert<Data: Send + Sync + 'static>(
+        &mut self,
+        protocol: Protocol,
+        block_number: BlockNumber,
+        block_hash: BlockHash,
+        items: BlockItems<Data>,
+    ) {
+        // The size of the entry itself is counted too, so that blocks without items are bounded.
+        let num_bytes = items.iter().map(|(_, num_bytes)| num_bytes).sum::<u64>()
+            + std::mem::size_of::<CachedBlock>() as u64;
+        if num_bytes > self.max_bytes {
+            return;
+        }
+        let key = (protocol, TypeId::of::<Data>(), block_number);
+        self.remove(&key);
+        self.blocks.put(key, CachedBlock { block_hash, items: Box::new(items), num_bytes });
+        self.num_bytes += num_bytes;
+        while self.num_bytes > self.max_bytes {
+            let Some((_, evicted_block)) = self.blocks.pop_lru() else {
+                break;
+            };
+            self.num_bytes -= evicted_block.num_bytes;
+        }
+    }
+
+    fn remove(&mut self, key: &CacheKey) {
+        if let Some(removed_block) = self.blocks.pop(key) {
+            self.num_bytes -= removed_block.num_bytes;
+        }
+    }
+}
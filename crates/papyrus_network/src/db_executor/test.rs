@@ -1,37 +1,85 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use assert_matches::assert_matches;
 use futures::channel::mpsc::{Receiver, Sender};
-use futures::StreamExt;
+use futures::{SinkExt, StreamExt};
+use libp2p::PeerId;
 use papyrus_common::state::create_random_state_diff;
 use papyrus_protobuf::converters::ProtobufConversionError;
 use papyrus_protobuf::sync::{
+    BlockDataAvailability,
     BlockHashOrNumber,
+    BlockRangeAdvertisement,
     DataOrFin,
+    DeprecatedDeclaredClass,
     Direction,
     HeaderQuery,
+    ProtocolBlockRange,
     Query,
+    ResponseLimits,
     SignedBlockHeader,
     StateDiffChunk,
     StateDiffQuery,
     TransactionQuery,
 };
+use papyrus_storage::body::BodyStorageWriter;
+use papyrus_storage::db::{DbError, RO};
 use papyrus_storage::header::{HeaderStorageReader, HeaderStorageWriter};
-use papyrus_storage::state::StateStorageWriter;
-use papyrus_storage::test_utils::get_test_storage;
-use papyrus_storage::{StorageReader, StorageWriter};
+use papyrus_storage::state::{StateStorageReader, StateStorageWriter};
+use papyrus_storage::test_utils::{get_test_config, get_test_storage};
+use papyrus_storage::{
+    open_storage,
+    StorageError,
+    StorageReader,
+    StorageResult,
+    StorageTxn,
+    StorageWriter,
+};
 use rand::random;
 use starknet_api::block::{BlockHash, BlockHeader, BlockNumber, BlockSignature};
-use starknet_api::transaction::{Transaction, TransactionOutput};
-use test_utils::get_rng;
+use starknet_api::core::ClassHash;
+use starknet_api::hash::StarkHash;
+use starknet_api::state::ThinStateDiff;
+use starknet_api::transaction::{
+    ExecutionResources,
+    GasVector,
+    Transaction,
+    TransactionHash,
+    TransactionOutput,
+};
+use test_utils::{get_rng, get_test_body};
+use tokio::sync::watch;
 
-use super::DBExecutor;
+use super::health::ProtocolHealth;
+use super::response_cache::ResponseCache;
+use super::{
+    get_block_range_advertisement,
+    serve_from_storage_replica,
+    split_thin_state_diff,
+    DBExecutor,
+    DBExecutorError,
+    FetchBlockDataFromDb,
+    QueryStorageReader,
+};
+use crate::{InboundQueryLogMode, Protocol};
 
 const BUFFER_SIZE: usize = 10;
+const MAX_BLOCKING_READS: usize = 2;
+const BLOCKS_PER_READ_TXN: u64 = 3;
+const RESPONSE_CACHE_MAX_BYTES: u64 = 1 << 20;
+const UNLIMITED_RESPONSE_LIMITS: ResponseLimits =
+    ResponseLimits { max_items: u64::MAX, max_bytes: u64::MAX };
+const FOLLOW_QUERY_IDLE_TIMEOUT: Duration = Duration::from_secs(60);
+const MAX_CONCURRENT_QUERIES: usize = 10;
 
 // TODO: Add test for state_diff and transaction query_positive_flow.
 // TODO(shahak): Change tests to use channels and not register_query
 #[tokio::test]
 async fn header_query_positive_flow() {
     let (
-        db_executor,
+        mut db_executor,
         _storage_reader,
         mut storage_writer,
         _header_queries_sender,
@@ -51,7 +99,12 @@ async fn header_query_positive_flow() {
         step: 1,
     };
     let (sender, data_receiver) = futures::channel::mpsc::channel(BUFFER_SIZE);
-    db_executor.register_query::<SignedBlockHeader, _>(query.clone(), sender);
+    db_executor.register_query::<SignedBlockHeader, _>(
+        query.clone(),
+        sender,
+        PeerId::random(),
+        Protocol::SignedBlockHeader,
+    );
 
     // run the executor and collect query results.
     tokio::select! {
@@ -62,9 +115,12 @@ async fn header_query_positive_flow() {
             assert_eq!(all_data.len(), NUM_OF_BLOCKS as usize + 1);
             assert_eq!(DataOrFin(None), all_data.pop().unwrap());
             for (i, data) in all_data.into_iter().enumerate() {
+                let signed_header = data.0.expect("Received fin too early.");
+                assert_eq!(signed_header.block_header.block_number.0, i as u64);
+                // The test blocks have state diffs but no bodies.
                 assert_eq!(
-                    data.0.expect("Received fin too early.").block_header.block_number.0,
-                    i as u64
+                    signed_header.data_availability,
+                    Some(BlockDataAvailability { has_body: false, has_state_diff: true })
                 );
             }
         }
@@ -102,7 +158,12 @@ async fn header_query_start_block_given_by_hash() {
         limit: NUM_OF_BLOCKS,
         step: 1,
     };
-    db_executor.register_query::<SignedBlockHeader, _>(query, sender);
+    db_executor.register_query::<SignedBlockHeader, _>(
+        query,
+        sender,
+        PeerId::random(),
+        Protocol::SignedBlockHeader,
+    );
 
     // run the executor and collect query results.
     tokio::select! {
@@ -124,10 +185,49 @@ async fn header_query_start_block_given_by_hash() {
     }
 }
 
+#[tokio::test]
+async fn header_query_start_block_hash_not_found() {
+    let (
+        mut db_executor,
+        _storage_reader,
+        mut storage_writer,
+        _header_queries_sender,
+        _state_diff_queries_sender,
+        _transaction_queries_sender,
+    ) = setup();
+
+    const NUM_OF_BLOCKS: u64 = 10;
+    insert_to_storage_test_blocks_up_to(NUM_OF_BLOCKS, &mut storage_writer);
+
+    // register a query with a hash that isn't in the storage.
+    let (sender, receiver) = futures::channel::mpsc::channel(BUFFER_SIZE);
+    let query = Query {
+        start_block: BlockHashOrNumber::Hash(BlockHash(random::<u64>().into())),
+        direction: Direction::Forward,
+        limit: NUM_OF_BLOCKS,
+        step: 1,
+    };
+    db_executor.register_query::<SignedBlockHeader, _>(
+        query,
+        sender,
+        PeerId::random(),
+        Protocol::SignedBlockHeader,
+    );
+
+    tokio::select! {
+        _ = db_executor.run() => {
+            panic!("DB executor should never finish its run.");
+        },
+        res = receiver.collect::<Vec<_>>() => {
+            assert_eq!(res, vec![DataOrFin(None)]);
+        }
+    }
+}
+
 #[tokio::test]
 async fn header_query_some_blocks_are_missing() {
     let (
-        db_executor,
+        mut db_executor,
         _storage_reader,
         mut storage_writer,
         _header_queries_sender,
@@ -147,7 +247,12 @@ async fn header_query_some_blocks_are_missing() {
         limit: NUM_OF_BLOCKS,
         step: 1,
     };
-    db_executor.register_query::<SignedBlockHeader, _>(query, sender);
+    db_executor.register_query::<SignedBlockHeader, _>(
+        query,
+        sender,
+        PeerId::random(),
+        Protocol::SignedBlockHeader,
+    );
 
     tokio::select! {
         _ = db_executor.run() => {
@@ -162,49 +267,364 @@ async fn header_query_some_blocks_are_missing() {
     }
 }
 
+#[tokio::test]
+async fn closing_session_mid_stream_stops_the_storage_read() {
+    let (
+        mut db_executor,
+        _storage_reader,
+        mut storage_writer,
+        _header_queries_sender,
+        _state_diff_queries_sender,
+        _transaction_queries_sender,
+    ) = setup();
+
+    // The query has many more items than the channels can buffer, so the read can only finish by
+    // being cancelled.
+    const NUM_OF_BLOCKS: u64 = 200;
+    insert_to_storage_test_blocks_up_to(NUM_OF_BLOCKS, &mut storage_writer);
+
+    let (sender, mut receiver) = futures::channel::mpsc::channel(BUFFER_SIZE);
+    let query = Query {
+        start_block: BlockHashOrNumber::Number(BlockNumber(0)),
+        direction: Direction::Forward,
+        limit: NUM_OF_BLOCKS,
+        step: 1,
+    };
+    db_executor.register_query::<StateDiffChunk, _>(
+        query,
+        sender,
+        PeerId::random(),
+        Protocol::StateDiff,
+    );
+
+    // Receive a single item and close the session.
+    assert!(receiver.next().await.unwrap().0.is_some());
+    drop(receiver);
+
+    // The blocking read holds a permit until it exits.
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while db_executor.blocking_reads_semaphore.available_permits() < MAX_BLOCKING_READS {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("The storage read wasn't stopped after the session was closed.");
+}
+
+#[tokio::test]
+async fn response_ends_once_it_reaches_the_item_limit() {
+    let (
+        mut db_executor,
+        _storage_reader,
+        mut storage_writer,
+        _header_queries_sender,
+        _state_diff_queries_sender,
+        _transaction_queries_sender,
+    ) = setup();
+    const MAX_ITEMS: u64 = 3;
+    let (response_limits_sender, response_limits_receiver) =
+        watch::channel(UNLIMITED_RESPONSE_LIMITS);
+    db_executor.response_limits = response_limits_receiver;
+    // The limits are read for each query, so a change applies to the following queries.
+    response_limits_sender
+        .send(ResponseLimits { max_items: MAX_ITEMS, ..UNLIMITED_RESPONSE_LIMITS })
+        .unwrap();
+
+    const NUM_OF_BLOCKS: u64 = 10;
+    insert_to_storage_test_blocks_up_to(NUM_OF_BLOCKS, &mut storage_writer);
+
+    let (sender, receiver) = futures::channel::mpsc::channel(BUFFER_SIZE);
+    let query = Query {
+        start_block: BlockHashOrNumber::Number(BlockNumber(0)),
+        direction: Direction::Forward,
+        limit: NUM_OF_BLOCKS,
+        step: 1,
+    };
+    db_executor.register_query::<SignedBlockHeader, _>(
+        query,
+        sender,
+        PeerId::random(),
+        Protocol::SignedBlockHeader,
+    );
+
+    tokio::select! {
+        _ = db_executor.run() => {
+            panic!("DB executor should never finish its run.");
+        },
+        mut res = receiver.collect::<Vec<_>>() => {
+            assert_eq!(res.pop().unwrap(), DataOrFin(None));
+            let block_numbers = res
+                .into_iter()
+                .map(|data| data.0.unwrap().block_header.block_number)
+                .collect::<Vec<_>>();
+            assert_eq!(block_numbers, (0..MAX_ITEMS).map(BlockNumber).collect::<Vec<_>>());
+        }
+    }
+}
+
+// A block is never split, so a budget that is smaller than a single item can't stop the peer from
+// syncing.
+#[tokio::test]
+async fn block_larger_than_the_byte_limit_is_sent_whole() {
+    let (
+        mut db_executor,
+        storage_reader,
+        mut storage_writer,
+        _header_queries_sender,
+        _state_diff_queries_sender,
+        _transaction_queries_sender,
+    ) = setup();
+    db_executor.response_limits =
+        watch::channel(ResponseLimits { max_bytes: 1, ..UNLIMITED_RESPONSE_LIMITS }).1;
+
+    const NUM_OF_BLOCKS: u64 = 10;
+    insert_to_storage_test_blocks_up_to(NUM_OF_BLOCKS, &mut storage_writer);
+    let first_block_state_diff_chunks = split_thin_state_diff(
+        storage_reader.begin_ro_txn().unwrap().get_state_diff(BlockNumber(0)).unwrap().unwrap(),
+    );
+
+    let (sender, receiver) = futures::channel::mpsc::channel(BUFFER_SIZE);
+    let query = Query {
+        start_block: BlockHashOrNumber::Number(BlockNumber(0)),
+        direction: Direction::Forward,
+        limit: NUM_OF_BLOCKS,
+        step: 1,
+    };
+    db_executor.register_query::<StateDiffChunk, _>(
+        query,
+        sender,
+        PeerId::random(),
+        Protocol::StateDiff,
+    );
+
+    tokio::select! {
+        _ = db_executor.run() => {
+            panic!("DB executor should never finish its run.");
+        },
+        mut res = receiver.collect::<Vec<_>>() => {
+            assert_eq!(res.pop().unwrap(), DataOrFin(None));
+            // The order of the contract diffs isn't deterministic.
+            let chunks = res.into_iter().map(|data| data.0.unwrap()).collect::<Vec<_>>();
+            assert_eq!(chunks.len(), first_block_state_diff_chunks.len());
+            for chunk in first_block_state_diff_chunks {
+                assert!(chunks.contains(&chunk));
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn response_ends_before_a_block_that_cant_be_encoded() {
+    let (
+        mut db_executor,
+        _storage_reader,
+        mut storage_writer,
+        _header_queries_sender,
+        _state_diff_queries_sender,
+        _transaction_queries_sender,
+    ) = setup();
+    const NUM_OF_BLOCKS: u64 = 3;
+    const NUM_TRANSACTIONS_PER_BLOCK: u64 = 2;
+    const UNENCODABLE_BLOCK_NUMBER: BlockNumber = BlockNumber(1);
+
+    let mut expected_transactions = Vec::new();
+    for block_number in (0..NUM_OF_BLOCKS).map(BlockNumber) {
+        let mut body = get_test_body(NUM_TRANSACTIONS_PER_BLOCK as usize, None, None, None);
+        // The transaction hashes must be unique across the blocks.
+        body.transaction_hashes = (0..NUM_TRANSACTIONS_PER_BLOCK)
+            .map(|i| {
+                TransactionHash(StarkHash::from(block_number.0 * NUM_TRANSACTIONS_PER_BLOCK + i))
+            })
+            .collect();
+        // Receipts hold the execution steps in 32 bits.
+        let steps = if block_number == UNENCODABLE_BLOCK_NUMBER { u64::MAX } else { 0 };
+        for transaction_output in &mut body.transaction_outputs {
+            set_execution_steps(transaction_output, steps);
+        }
+        if block_number < UNENCODABLE_BLOCK_NUMBER {
+            expected_transactions.extend(
+                body.transactions
+                    .iter()
+                    .cloned()
+                    .zip(body.transaction_outputs.iter().cloned().map(Some)),
+            );
+        }
+        storage_writer
+            .begin_rw_txn()
+            .unwrap()
+            .append_body(block_number, body)
+            .unwrap()
+            .commit()
+            .unwrap();
+    }
+
+    let (sender, receiver) = futures::channel::mpsc::channel(BUFFER_SIZE);
+    let query = Query {
+        start_block: BlockHashOrNumber::Number(BlockNumber(0)),
+        direction: Direction::Forward,
+        limit: NUM_OF_BLOCKS,
+        step: 1,
+    };
+    db_executor.register_query::<(Transaction, Option<TransactionOutput>), _>(
+        query,
+        sender,
+        PeerId::random(),
+        Protocol::Transaction,
+    );
+
+    tokio::select! {
+        _ = db_executor.run() => {
+            panic!("DB executor should never finish its run.");
+        },
+        mut res = receiver.collect::<Vec<_>>() => {
+            assert_eq!(res.pop().unwrap(), DataOrFin(None));
+            let transactions = res.into_iter().map(|data| data.0.unwrap()).collect::<Vec<_>>();
+            assert_eq!(transactions, expected_transactions);
+        }
+    }
+}
+
+#[tokio::test]
+async fn transaction_query_that_excludes_outputs_is_answered_without_them() {
+    let (db_executor, _storage_reader, mut storage_writer, _, _, mut transaction_queries_sender) =
+        setup();
+    const NUM_OF_BLOCKS: u64 = 2;
+    const NUM_TRANSACTIONS_PER_BLOCK: u64 = 2;
+
+    let mut expected_transactions = Vec::new();
+    for block_number in (0..NUM_OF_BLOCKS).map(BlockNumber) {
+        let mut body = get_test_body(NUM_TRANSACTIONS_PER_BLOCK as usize, None, None, None);
+        // The transaction hashes must be unique across the blocks.
+        body.transaction_hashes = (0..NUM_TRANSACTIONS_PER_BLOCK)
+            .map(|i| {
+                TransactionHash(StarkHash::from(block_number.0 * NUM_TRANSACTIONS_PER_BLOCK + i))
+            })
+            .collect();
+        // Outputs that can't be encoded don't matter when they aren't sent.
+        for transaction_output in &mut body.transaction_outputs {
+            set_execution_steps(transaction_output, u64::MAX);
+        }
+        expected_transactions
+            .extend(body.transactions.iter().cloned().map(|transaction| (transaction, None)));
+        storage_writer
+            .begin_rw_txn()
+            .unwrap()
+            .append_body(block_number, body)
+            .unwrap()
+            .commit()
+            .unwrap();
+    }
+
+    let (sender, receiver) = futures::channel::mpsc::channel(BUFFER_SIZE);
+    let query = TransactionQuery {
+        query: Query {
+            start_block: BlockHashOrNumber::Number(BlockNumber(0)),
+            direction: Direction::Forward,
+            limit: NUM_OF_BLOCKS,
+            step: 1,
+        },
+        include_outputs: false,
+    };
+    transaction_queries_sender.send((Ok(query), sender, PeerId::random())).await.unwrap();
+
+    tokio::select! {
+        _ = db_executor.run() => {
+            panic!("DB executor should never finish its run.");
+        },
+        mut res = receiver.collect::<Vec<_>>() => {
+            assert_eq!(res.pop().unwrap(), DataOrFin(None));
+            let transactions = res.into_iter().map(|data| data.0.unwrap()).collect::<Vec<_>>();
+            assert_eq!(transactions, expected_transactions);
+        }
+    }
+}
+
+fn set_execution_steps(transaction_output: &mut TransactionOutput, steps: u64) {
+    let execution_resources = match transaction_output {
+        TransactionOutput::Declare(output) => &mut output.execution_resources,
+        TransactionOutput::Deploy(output) => &mut output.execution_resources,
+        TransactionOutput::DeployAccount(output) => &mut output.execution_resources,
+        TransactionOutput::Invoke(output) => &mut output.execution_resources,
+        TransactionOutput::L1Handler(output) => &mut output.execution_resources,
+    };
+    *execution_resources = ExecutionResources {
+        steps,
+        builtin_instance_counter: HashMap::new(),
+        memory_holes: 0,
+        da_gas_consumed: GasVector::default(),
+        gas_consumed: GasVector::default(),
+    };
+}
+
 #[allow(clippy::type_complexity)]
 fn setup() -> (
     DBExecutor<
         Receiver<(
             Result<HeaderQuery, ProtobufConversionError>,
             Sender<DataOrFin<SignedBlockHeader>>,
+            PeerId,
         )>,
         Receiver<(
             Result<StateDiffQuery, ProtobufConversionError>,
             Sender<DataOrFin<StateDiffChunk>>,
+            PeerId,
         )>,
         Receiver<(
             Result<TransactionQuery, ProtobufConversionError>,
-            Sender<DataOrFin<(Transaction, TransactionOutput)>>,
+            Sender<DataOrFin<(Transaction, Option<TransactionOutput>)>>,
+            PeerId,
         )>,
     >,
     StorageReader,
     StorageWriter,
-    Sender<(Result<HeaderQuery, ProtobufConversionError>, Sender<DataOrFin<SignedBlockHeader>>)>,
-    Sender<(Result<StateDiffQuery, ProtobufConversionError>, Sender<DataOrFin<StateDiffChunk>>)>,
+    Sender<(
+        Result<HeaderQuery, ProtobufConversionError>,
+        Sender<DataOrFin<SignedBlockHeader>>,
+        PeerId,
+    )>,
+    Sender<(
+        Result<StateDiffQuery, ProtobufConversionError>,
+        Sender<DataOrFin<StateDiffChunk>>,
+        PeerId,
+    )>,
     Sender<(
         Result<TransactionQuery, ProtobufConversionError>,
-        Sender<DataOrFin<(Transaction, TransactionOutput)>>,
+        Sender<DataOrFin<(Transaction, Option<TransactionOutput>)>>,
+        PeerId,
     )>,
 ) {
     let ((storage_reader, storage_writer), _temp_dir) = get_test_storage();
     let (header_queries_sender, header_queries_receiver) = futures::channel::mpsc::channel::<(
         Result<HeaderQuery, ProtobufConversionError>,
         Sender<DataOrFin<SignedBlockHeader>>,
+        PeerId,
     )>(BUFFER_SIZE);
-    let (state_diff_queries_sender, state_diff_queries_receiver) = futures::channel::mpsc::channel::<
-        (Result<StateDiffQuery, ProtobufConversionError>, Sender<DataOrFin<StateDiffChunk>>),
-    >(BUFFER_SIZE);
+    let (state_diff_queries_sender, state_diff_queries_receiver) =
+        futures::channel::mpsc::channel::<(
+            Result<StateDiffQuery, ProtobufConversionError>,
+            Sender<DataOrFin<StateDiffChunk>>,
+            PeerId,
+        )>(BUFFER_SIZE);
     let (transaction_sender, transaction_queries_receiver) = futures::channel::mpsc::channel::<(
         Result<TransactionQuery, ProtobufConversionError>,
-        Sender<DataOrFin<(Transaction, TransactionOutput)>>,
+        Sender<DataOrFin<(Transaction, Option<TransactionOutput>)>>,
+        PeerId,
     )>(BUFFER_SIZE);
 
     let db_executor = super::DBExecutor::new(
-        storage_reader.clone(),
+        watch::channel(Some(storage_reader.clone())).1,
         header_queries_receiver,
         state_diff_queries_receiver,
         transaction_queries_receiver,
+        InboundQueryLogMode::Disabled,
+        1,
+        MAX_BLOCKING_READS,
+        BLOCKS_PER_READ_TXN,
+        RESPONSE_CACHE_MAX_BYTES,
+        watch::channel(UNLIMITED_RESPONSE_LIMITS).1,
+        watch::channel(BlockNumber(0)).1,
+        FOLLOW_QUERY_IDLE_TIMEOUT,
+        MAX_CONCURRENT_QUERIES,
     );
     (
         db_executor,
@@ -216,12 +636,311 @@ fn setup() -> (
     )
 }
 
+#[test]
+fn inbound_queries_are_logged_according_to_log_mode() {
+    let (mut db_executor, ..) = setup();
+    assert!(!(0..3).any(|_| db_executor.should_log_next_query()));
+
+    db_executor.inbound_query_log_mode = InboundQueryLogMode::All;
+    assert!((0..3).all(|_| db_executor.should_log_next_query()));
+
+    db_executor.inbound_query_log_mode = InboundQueryLogMode::Sampled;
+    db_executor.inbound_query_log_sample_rate = 3;
+    db_executor.num_registered_queries = 0;
+    let logged_queries = (0..7).map(|_| db_executor.should_log_next_query()).collect::<Vec<_>>();
+    assert_eq!(logged_queries, vec![true, false, false, true, false, false, true]);
+}
+
+#[test]
+fn block_range_advertisement_follows_storage_markers() {
+    let ((storage_reader, mut storage_writer), _temp_dir) = get_test_storage();
+    const NUM_OF_BLOCKS: u64 = 5;
+    insert_to_storage_test_blocks_up_to(NUM_OF_BLOCKS, &mut storage_writer);
+
+    let advertisement =
+        get_block_range_advertisement(Some(&storage_reader), UNLIMITED_RESPONSE_LIMITS).unwrap();
+    assert_eq!(
+        advertisement,
+        BlockRangeAdvertisement {
+            ranges: vec![
+                ProtocolBlockRange {
+                    protocol: Protocol::SignedBlockHeader.as_str().to_string(),
+                    block_range: BlockNumber(0)..BlockNumber(NUM_OF_BLOCKS),
+                },
+                ProtocolBlockRange {
+                    protocol: Protocol::StateDiff.as_str().to_string(),
+                    block_range: BlockNumber(0)..BlockNumber(NUM_OF_BLOCKS),
+                },
+                // The test blocks don't have bodies.
+                ProtocolBlockRange {
+                    protocol: Protocol::Transaction.as_str().to_string(),
+                    block_range: BlockNumber(0)..BlockNumber(0),
+                },
+            ],
+            response_limits: Some(UNLIMITED_RESPONSE_LIMITS),
+        }
+    );
+}
+
+#[tokio::test]
+async fn queries_are_answered_with_fin_while_the_storage_is_unavailable() {
+    let (mut db_executor, ..) = setup();
+    db_executor.storage_reader = watch::channel(None).1;
+
+    let (sender, receiver) = futures::channel::mpsc::channel(BUFFER_SIZE);
+    let query = Query {
+        start_block: BlockHashOrNumber::Number(BlockNumber(0)),
+        direction: Direction::Forward,
+        limit: 1,
+        step: 1,
+    };
+    db_executor.register_query::<SignedBlockHeader, _>(
+        query,
+        sender,
+        PeerId::random(),
+        Protocol::SignedBlockHeader,
+    );
+    assert_eq!(receiver.collect::<Vec<_>>().await, vec![DataOrFin(None)]);
+
+    let advertisement = get_block_range_advertisement(None, UNLIMITED_RESPONSE_LIMITS).unwrap();
+    assert!(advertisement.ranges.iter().all(|range| range.block_range.is_empty()));
+}
+
+#[tokio::test]
+async fn storage_replica_is_served_once_it_can_be_opened() {
+    const NUM_OF_BLOCKS: u64 = 3;
+    // The replica is opened when the server starts, so it isn't reopened during the test.
+    const REOPEN_INTERVAL: Duration = Duration::from_secs(60);
+    let (replica_config, _temp_dir) = get_test_config(None);
+
+    // The replica doesn't exist yet.
+    let (storage_reader_sender, mut storage_reader_receiver) =
+        watch::channel(Some(get_test_storage().0 .0));
+    tokio::select! {
+        _ = serve_from_storage_replica(
+            replica_config.clone(), REOPEN_INTERVAL, storage_reader_sender
+        ) => panic!("The replica server should never finish its run."),
+        _ = storage_reader_receiver.wait_for(Option::is_none) => {}
+    }
+
+    // The replica is written elsewhere and closed before it's served.
+    let (storage_reader, mut storage_writer) = open_storage(replica_config.clone()).unwrap();
+    insert_to_storage_test_blocks_up_to(NUM_OF_BLOCKS, &mut storage_writer);
+    drop((storage_reader, storage_writer));
+
+    let (storage_reader_sender, mut storage_reader_receiver) = watch::channel(None);
+    let served_storage_reader = tokio::select! {
+        _ = serve_from_storage_replica(
+            replica_config, REOPEN_INTERVAL, storage_reader_sender
+        ) => panic!("The replica server should never finish its run."),
+        served_storage_reader = storage_reader_receiver.wait_for(Option::is_some) => {
+            served_storage_reader.unwrap().clone().unwrap()
+        }
+    };
+    let advertisement =
+        get_block_range_advertisement(Some(&served_storage_reader), UNLIMITED_RESPONSE_LIMITS)
+            .unwrap();
+    assert_eq!(advertisement.ranges[0].block_range, BlockNumber(0)..BlockNumber(NUM_OF_BLOCKS));
+}
+
+// Serves a header query over all the blocks in the storage while a block is appended after each
+// received header, and returns how much the database file grew.
+async fn db_file_growth_while_serving_a_query(blocks_per_read_txn: u64) -> u64 {
+    const NUM_OF_BLOCKS: u64 = 100;
+    let (mut db_executor, ..) = setup();
+    let (mut storage_config, _temp_dir) = get_test_config(None);
+    // A small growth step, so that the file size follows the pages that are in use.
+    storage_config.db_config.growth_step = 1 << 16;
+    let db_file_path = storage_config.db_config.path().join("mdbx.dat");
+    let (storage_reader, mut storage_writer) = open_storage(storage_config).unwrap();
+    insert_to_storage_test_blocks_in_range(0..NUM_OF_BLOCKS, &mut storage_writer);
+    db_executor.storage_reader = watch::channel(Some(storage_reader)).1;
+    db_executor.blocks_per_read_txn = blocks_per_read_txn;
+    let initial_size = std::fs::metadata(&db_file_path).unwrap().len();
+
+    let (sender, mut receiver) = futures::channel::mpsc::channel(1);
+    let query = Query {
+        start_block: BlockHashOrNumber::Number(BlockNumber(0)),
+        direction: Direction::Forward,
+        limit: NUM_OF_BLOCKS,
+        step: 1,
+    };
+    db_executor.register_query::<SignedBlockHeader, _>(
+        query,
+        sender,
+        PeerId::random(),
+        Protocol::SignedBlockHeader,
+    );
+    let mut num_of_received_headers = 0;
+    while let Some(DataOrFin(Some(_))) = receiver.next().await {
+        let block_number = NUM_OF_BLOCKS + num_of_received_headers;
+        insert_to_storage_test_blocks_in_range(block_number..block_number + 1, &mut storage_writer);
+        num_of_received_headers += 1;
+    }
+    assert_eq!(num_of_received_headers, NUM_OF_BLOCKS);
+
+    std::fs::metadata(&db_file_path).unwrap().len() - initial_size
+}
+
+#[tokio::test]
+async fn reading_in_chunks_bounds_the_db_file_growth_during_a_query() {
+    // Reading the whole query in a single read transaction keeps every page that is freed while
+    // it's served, so the writes can't reuse them.
+    let single_txn_growth = db_file_growth_while_serving_a_query(u64::MAX).await;
+    let chunked_growth = db_file_growth_while_serving_a_query(BLOCKS_PER_READ_TXN).await;
+    assert!(
+        chunked_growth < single_txn_growth,
+        "The db file grew by {chunked_growth} bytes when read in chunks and by \
+         {single_txn_growth} bytes when read in a single transaction."
+    );
+}
+
+fn test_state_diff_chunk() -> StateDiffChunk {
+    StateDiffChunk::DeprecatedDeclaredClass(DeprecatedDeclaredClass {
+        class_hash: ClassHash(StarkHash::ONE),
+    })
+}
+
+#[test]
+fn response_cache_serves_a_block_only_with_the_hash_it_was_cached_with() {
+    let mut response_cache = ResponseCache::new(RESPONSE_CACHE_MAX_BYTES);
+    let block_hash = BlockHash(StarkHash::ONE);
+    let items = vec![(test_state_diff_chunk(), 10)];
+    response_cache.insert(Protocol::StateDiff, BlockNumber(0), block_hash, items.clone());
+
+    assert_eq!(
+        response_cache.get::<StateDiffChunk>(Protocol::StateDiff, BlockNumber(0), block_hash),
+        Some(items)
+    );
+    assert_eq!(
+        response_cache.get::<SignedBlockHeader>(
+            Protocol::SignedBlockHeader,
+            BlockNumber(0),
+            block_hash
+        ),
+        None
+    );
+    // The block was reverted and replaced by another block, so its entry is removed.
+    assert_eq!(
+        response_cache.get::<StateDiffChunk>(
+            Protocol::StateDiff,
+            BlockNumber(0),
+            BlockHash(StarkHash::TWO)
+        ),
+        None
+    );
+    assert_eq!(
+        response_cache.get::<StateDiffChunk>(Protocol::StateDiff, BlockNumber(0), block_hash),
+        None
+    );
+}
+
+#[test]
+fn response_cache_evicts_the_least_recently_used_blocks_above_its_byte_budget() {
+    const BLOCK_SIZE: u64 = 1000;
+    let mut response_cache = ResponseCache::new(BLOCK_SIZE * 5 / 2);
+    let block_hash = BlockHash(StarkHash::ONE);
+    for block_number in 0..2 {
+        response_cache.insert(
+            Protocol::StateDiff,
+            BlockNumber(block_number),
+            block_hash,
+            vec![(test_state_diff_chunk(), BLOCK_SIZE)],
+        );
+    }
+    // Block 0 is used, so block 1 is evicted once block 2 is cached.
+    assert!(
+        response_cache
+            .get::<StateDiffChunk>(Protocol::StateDiff, BlockNumber(0), block_hash)
+            .is_some()
+    );
+    response_cache.insert(
+        Protocol::StateDiff,
+        BlockNumber(2),
+        block_hash,
+        vec![(test_state_diff_chunk(), BLOCK_SIZE)],
+    );
+    let cached_blocks = (0..3)
+        .filter(|block_number| {
+            response_cache
+                .get::<StateDiffChunk>(Protocol::StateDiff, BlockNumber(*block_number), block_hash)
+                .is_some()
+        })
+        .collect::<Vec<_>>();
+    assert_eq!(cached_blocks, vec![0, 2]);
+}
+
+#[tokio::test]
+async fn cached_block_is_not_served_after_it_was_reverted() {
+    let (mut db_executor, storage_reader, mut storage_writer, ..) = setup();
+    insert_to_storage_test_blocks_up_to(1, &mut storage_writer);
+    let query = Query {
+        start_block: BlockHashOrNumber::Number(BlockNumber(0)),
+        direction: Direction::Forward,
+        limit: 1,
+        step: 1,
+    };
+
+    let (sender, receiver) = futures::channel::mpsc::channel(BUFFER_SIZE);
+    db_executor.register_query::<StateDiffChunk, _>(
+        query.clone(),
+        sender,
+        PeerId::random(),
+        Protocol::StateDiff,
+    );
+    let served_chunks = receiver.collect::<Vec<_>>().await;
+    assert!(served_chunks.len() > 1);
+    let block_hash = storage_reader
+        .begin_ro_txn()
+        .unwrap()
+        .get_block_header(BlockNumber(0))
+        .unwrap()
+        .unwrap()
+        .block_hash;
+    assert!(
+        db_executor
+            .response_cache
+            .lock()
+            .unwrap()
+            .get::<StateDiffChunk>(Protocol::StateDiff, BlockNumber(0), block_hash)
+            .is_some()
+    );
+
+    // Replace the block with a block that has an empty state diff.
+    let (txn, _, _) = storage_writer.begin_rw_txn().unwrap().revert_header(BlockNumber(0)).unwrap();
+    let (txn, _) = txn.revert_state_diff(BlockNumber(0)).unwrap();
+    txn.append_header(
+        BlockNumber(0),
+        &BlockHeader { block_hash: BlockHash(StarkHash::ONE), ..Default::default() },
+    )
+    .unwrap()
+    .append_block_signature(BlockNumber(0), &BlockSignature::default())
+    .unwrap()
+    .append_state_diff(BlockNumber(0), ThinStateDiff::default())
+    .unwrap()
+    .commit()
+    .unwrap();
+
+    let (sender, receiver) = futures::channel::mpsc::channel(BUFFER_SIZE);
+    db_executor.register_query::<StateDiffChunk, _>(
+        query,
+        sender,
+        PeerId::random(),
+        Protocol::StateDiff,
+    );
+    assert_eq!(receiver.collect::<Vec<_>>().await, vec![DataOrFin(None)]);
+}
+
 fn insert_to_storage_test_blocks_up_to(num_of_blocks: u64, storage_writer: &mut StorageWriter) {
-    let mut rng = get_rng();
-    let thin_state_diffs =
-        (0..num_of_blocks).map(|_| create_random_state_diff(&mut rng)).collect::<Vec<_>>();
+    insert_to_storage_test_blocks_in_range(0..num_of_blocks, storage_writer);
+}
 
-    for i in 0..num_of_blocks {
+fn insert_to_storage_test_blocks_in_range(
+    block_numbers: std::ops::Range<u64>,
+    storage_writer: &mut StorageWriter,
+) {
+    let mut rng = get_rng();
+    for i in block_numbers {
         let block_header = BlockHeader {
             block_number: BlockNumber(i),
             block_hash: BlockHash(random::<u64>().into()),
@@ -236,9 +955,338 @@ fn insert_to_storage_test_blocks_up_to(num_of_blocks: u64, storage_writer: &mut
             // right signatures.
             .append_block_signature(BlockNumber(i), &BlockSignature::default())
             .unwrap()
-            .append_state_diff(BlockNumber(i), thin_state_diffs[i as usize].clone())
+            .append_state_diff(BlockNumber(i), create_random_state_diff(&mut rng))
             .unwrap()
             .commit()
             .unwrap();
     }
 }
+
+// Short, so that the tests can wait for a stopped protocol to be health checked.
+const HEALTH_CHECK_INTERVAL: Duration = Duration::from_millis(200);
+
+// A storage reader whose reads fail at the chosen points, for testing how the DB executor handles
+// storage failures.
+#[derive(Clone)]
+struct FaultyStorageReader {
+    storage_reader: StorageReader,
+    // Reading the data of these blocks fails with the given errors.
+    failing_blocks: Arc<Mutex<HashMap<BlockNumber, fn() -> StorageError>>>,
+    // If set, opening a read transaction fails with this error.
+    failing_txn: Arc<Mutex<Option<fn() -> StorageError>>>,
+}
+
+impl QueryStorageReader for FaultyStorageReader {
+    fn begin_ro_txn(&self) -> StorageResult<StorageTxn<'_, RO>> {
+        if let Some(make_error) = *self.failing_txn.lock().unwrap() {
+            return Err(make_error());
+        }
+        self.storage_reader.begin_ro_txn()
+    }
+
+    fn read_block_data<Data: FetchBlockDataFromDb>(
+        &self,
+        block_number: BlockNumber,
+        txn: &StorageTxn<'_, RO>,
+    ) -> Result<Vec<Data>, DBExecutorError> {
+        if let Some(make_error) = self.failing_blocks.lock().unwrap().get(&block_number) {
+            return Err(make_error().into());
+        }
+        Data::fetch_block_data_from_db(block_number, txn)
+    }
+}
+
+type NoQueries<Query, Data> = futures::stream::Pending<(
+    Result<Query, ProtobufConversionError>,
+    Sender<DataOrFin<Data>>,
+    PeerId,
+)>;
+
+#[allow(clippy::type_complexity)]
+fn setup_with_faulty_storage(
+    num_of_blocks: u64,
+) -> (
+    DBExecutor<
+        NoQueries<HeaderQuery, SignedBlockHeader>,
+        NoQueries<StateDiffQuery, StateDiffChunk>,
+        NoQueries<TransactionQuery, (Transaction, Option<TransactionOutput>)>,
+        FaultyStorageReader,
+    >,
+    FaultyStorageReader,
+) {
+    let ((storage_reader, mut storage_writer), _temp_dir) = get_test_storage();
+    insert_to_storage_test_blocks_up_to(num_of_blocks, &mut storage_writer);
+    let faulty_storage_reader = FaultyStorageReader {
+        storage_reader,
+        failing_blocks: Default::default(),
+        failing_txn: Default::default(),
+    };
+    let mut db_executor = DBExecutor::new(
+        watch::channel(Some(faulty_storage_reader.clone())).1,
+        futures::stream::pending(),
+        futures::stream::pending(),
+        futures::stream::pending(),
+        InboundQueryLogMode::Disabled,
+        1,
+        MAX_BLOCKING_READS,
+        BLOCKS_PER_READ_TXN,
+        RESPONSE_CACHE_MAX_BYTES,
+        watch::channel(UNLIMITED_RESPONSE_LIMITS).1,
+        watch::channel(BlockNumber(0)).1,
+        FOLLOW_QUERY_IDLE_TIMEOUT,
+        MAX_CONCURRENT_QUERIES,
+    );
+    db_executor.protocol_health = Arc::new(ProtocolHealth::new(HEALTH_CHECK_INTERVAL));
+    (db_executor, faulty_storage_reader)
+}
+
+fn query_blocks_up_to(num_of_blocks: u64) -> Query {
+    Query {
+        start_block: BlockHashOrNumber::Number(BlockNumber(0)),
+        direction: Direction::Forward,
+        limit: num_of_blocks,
+        step: 1,
+    }
+}
+
+// The protocol's health is updated once the response ended, so it's polled.
+async fn wait_for_protocol_health(protocol_health: &ProtocolHealth, is_stopped: bool) {
+    tokio::time::timeout(Duration::from_secs(5), async {
+        while protocol_health.is_stopped(Protocol::SignedBlockHeader) != is_stopped {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    })
+    .await
+    .expect("The protocol's health wasn't updated.");
+}
+
+#[test]
+fn storage_errors_are_classified_by_their_cause() {
+    assert_matches!(
+        DBExecutorError::from(StorageError::InnerError(DbError::InnerDeserialization)),
+        DBExecutorError::Corruption(_)
+    );
+    assert_matches!(
+        DBExecutorError::from(StorageError::DBInconsistency { msg: "".to_owned() }),
+        DBExecutorError::Corruption(_)
+    );
+    assert_matches!(
+        DBExecutorError::from(StorageError::IOError(std::io::Error::other("read failed"))),
+        DBExecutorError::IO(_)
+    );
+    assert_matches!(
+        DBExecutorError::from(StorageError::InnerError(DbError::Serialization)),
+        DBExecutorError::Internal(_)
+    );
+}
+
+#[test]
+fn header_without_signature_is_a_corruption() {
+    let ((storage_reader, mut storage_writer), _temp_dir) = get_test_storage();
+    let mut rng = get_rng();
+    storage_writer
+        .begin_rw_txn()
+        .unwrap()
+        .append_header(BlockNumber(0), &BlockHeader::default())
+        .unwrap()
+        .append_state_diff(BlockNumber(0), create_random_state_diff(&mut rng))
+        .unwrap()
+        .commit()
+        .unwrap();
+
+    let txn = storage_reader.begin_ro_txn().unwrap();
+    assert_matches!(
+        SignedBlockHeader::fetch_block_data_from_db(BlockNumber(0), &txn),
+        Err(DBExecutorError::Corruption(StorageError::DBInconsistency { .. }))
+    );
+}
+
+#[tokio::test]
+async fn corrupted_block_ends_the_response_without_fin_and_stops_the_protocol() {
+    const NUM_OF_BLOCKS: u64 = 5;
+    const CORRUPTED_BLOCK: u64 = 2;
+    let (mut db_executor, faulty_storage_reader) = setup_with_faulty_storage(NUM_OF_BLOCKS);
+    faulty_storage_reader
+        .failing_blocks
+        .lock()
+        .unwrap()
+        .insert(BlockNumber(CORRUPTED_BLOCK), || {
+            StorageError::InnerError(DbError::InnerDeserialization)
+        });
+
+    let (sender, receiver) = futures::channel::mpsc::channel(BUFFER_SIZE);
+    db_executor.register_query::<SignedBlockHeader, _>(
+        query_blocks_up_to(NUM_OF_BLOCKS),
+        sender,
+        PeerId::random(),
+        Protocol::SignedBlockHeader,
+    );
+    let response = receiver.collect::<Vec<_>>().await;
+    assert_eq!(response.len(), CORRUPTED_BLOCK as usize);
+    assert!(response.iter().all(|data| data.0.is_some()));
+    wait_for_protocol_health(&db_executor.protocol_health, true).await;
+    assert!(!db_executor.protocol_health.is_stopped(Protocol::StateDiff));
+
+    // Until the health check, the protocol's queries are closed without a response.
+    let (sender, receiver) = futures::channel::mpsc::channel(BUFFER_SIZE);
+    db_executor.register_query::<SignedBlockHeader, _>(
+        query_blocks_up_to(NUM_OF_BLOCKS),
+        sender,
+        PeerId::random(),
+        Protocol::SignedBlockHeader,
+    );
+    assert!(receiver.collect::<Vec<_>>().await.is_empty());
+
+    // Once the storage recovered, the health check serves the query and the protocol is served
+    // again.
+    faulty_storage_reader.failing_blocks.lock().unwrap().clear();
+    tokio::time::sleep(HEALTH_CHECK_INTERVAL).await;
+    let (sender, receiver) = futures::channel::mpsc::channel(BUFFER_SIZE);
+    db_executor.register_query::<SignedBlockHeader, _>(
+        query_blocks_up_to(NUM_OF_BLOCKS),
+        sender,
+        PeerId::random(),
+        Protocol::SignedBlockHeader,
+    );
+    let response = receiver.collect::<Vec<_>>().await;
+    assert_eq!(response.len(), NUM_OF_BLOCKS as usize + 1);
+    assert_eq!(response.last(), Some(&DataOrFin(None)));
+    wait_for_protocol_health(&db_executor.protocol_health, false).await;
+}
+
+#[tokio::test]
+async fn failing_health_check_keeps_the_protocol_stopped() {
+    const NUM_OF_BLOCKS: u64 = 3;
+    let (mut db_executor, faulty_storage_reader) = setup_with_faulty_storage(NUM_OF_BLOCKS);
+    *faulty_storage_reader.failing_txn.lock().unwrap() =
+        Some(|| StorageError::IOError(std::io::Error::other("read failed")));
+
+    let (sender, receiver) = futures::channel::mpsc::channel(BUFFER_SIZE);
+    db_executor.register_query::<SignedBlockHeader, _>(
+        query_blocks_up_to(NUM_OF_BLOCKS),
+        sender,
+        PeerId::random(),
+        Protocol::SignedBlockHeader,
+    );
+    assert!(receiver.collect::<Vec<_>>().await.is_empty());
+    wait_for_protocol_health(&db_executor.protocol_health, true).await;
+
+    tokio::time::sleep(HEALTH_CHECK_INTERVAL).await;
+    let (sender, receiver) = futures::channel::mpsc::channel(BUFFER_SIZE);
+    db_executor.register_query::<SignedBlockHeader, _>(
+        query_blocks_up_to(NUM_OF_BLOCKS),
+        sender,
+        PeerId::random(),
+        Protocol::SignedBlockHeader,
+    );
+    assert!(receiver.collect::<Vec<_>>().await.is_empty());
+    // The health check stopped the protocol for another interval.
+    tokio::time::sleep(HEALTH_CHECK_INTERVAL / 4).await;
+    assert!(db_executor.protocol_health.is_stopped(Protocol::SignedBlockHeader));
+}
+
+#[tokio::test]
+async fn internal_error_ends_the_response_without_fin_and_keeps_serving() {
+    const NUM_OF_BLOCKS: u64 = 3;
+    let (mut db_executor, faulty_storage_reader) = setup_with_faulty_storage(NUM_OF_BLOCKS);
+    faulty_storage_reader
+        .failing_blocks
+        .lock()
+        .unwrap()
+        .insert(BlockNumber(1), || StorageError::InnerError(DbError::Serialization));
+
+    let (sender, receiver) = futures::channel::mpsc::channel(BUFFER_SIZE);
+    db_executor.register_query::<SignedBlockHeader, _>(
+        query_blocks_up_to(NUM_OF_BLOCKS),
+        sender,
+        PeerId::random(),
+        Protocol::SignedBlockHeader,
+    );
+    let response = receiver.collect::<Vec<_>>().await;
+    assert_eq!(response.len(), 1);
+    assert!(response[0].0.is_some());
+    assert!(!db_executor.protocol_health.is_stopped(Protocol::SignedBlockHeader));
+}
+
+fn open_ended_query(start_block_number: u64) -> Query {
+    Query {
+        start_block: BlockHashOrNumber::Number(BlockNumber(start_block_number)),
+        direction: Direction::Forward,
+        limit: Query::OPEN_ENDED_LIMIT,
+        step: 1,
+    }
+}
+
+#[tokio::test]
+async fn open_ended_header_query_sends_new_headers_as_they_are_written() {
+    const NUM_OF_BLOCKS: u64 = 3;
+    // Shorter than the poll interval, so the new header is sent once its writer notifies of it.
+    const NOTIFICATION_TIMEOUT: Duration = Duration::from_millis(500);
+    let (mut db_executor, _storage_reader, mut storage_writer, ..) = setup();
+    insert_to_storage_test_blocks_up_to(NUM_OF_BLOCKS, &mut storage_writer);
+    let (header_marker_sender, header_marker_receiver) = watch::channel(BlockNumber(NUM_OF_BLOCKS));
+    db_executor.header_marker = header_marker_receiver;
+
+    let (sender, mut receiver) = futures::channel::mpsc::channel(BUFFER_SIZE);
+    db_executor.register_query::<SignedBlockHeader, _>(
+        open_ended_query(0),
+        sender,
+        PeerId::random(),
+        Protocol::SignedBlockHeader,
+    );
+    for block_number in 0..NUM_OF_BLOCKS {
+        let signed_header = receiver.next().await.unwrap().0.unwrap();
+        assert_eq!(signed_header.block_header.block_number, BlockNumber(block_number));
+    }
+
+    // The session stays open at the tip.
+    tokio::time::sleep(NOTIFICATION_TIMEOUT).await;
+    assert!(receiver.try_next().is_err());
+
+    insert_to_storage_test_blocks_in_range(NUM_OF_BLOCKS..NUM_OF_BLOCKS + 1, &mut storage_writer);
+    header_marker_sender.send_replace(BlockNumber(NUM_OF_BLOCKS + 1));
+    let signed_header = tokio::time::timeout(NOTIFICATION_TIMEOUT, receiver.next())
+        .await
+        .expect("The new header wasn't sent.")
+        .unwrap()
+        .0
+        .unwrap();
+    assert_eq!(signed_header.block_header.block_number, BlockNumber(NUM_OF_BLOCKS));
+}
+
+#[tokio::test]
+async fn open_ended_query_ends_once_no_block_is_added_for_the_idle_timeout() {
+    const NUM_OF_BLOCKS: u64 = 2;
+    let (mut db_executor, _storage_reader, mut storage_writer, ..) = setup();
+    insert_to_storage_test_blocks_up_to(NUM_OF_BLOCKS, &mut storage_writer);
+    db_executor.follow_query_idle_timeout = Duration::from_millis(100);
+
+    let (sender, receiver) = futures::channel::mpsc::channel(BUFFER_SIZE);
+    db_executor.register_query::<SignedBlockHeader, _>(
+        open_ended_query(0),
+        sender,
+        PeerId::random(),
+        Protocol::SignedBlockHeader,
+    );
+    let response = receiver.collect::<Vec<_>>().await;
+    assert_eq!(response.len(), NUM_OF_BLOCKS as usize + 1);
+    assert_eq!(response.last(), Some(&DataOrFin(None)));
+}
+
+// Only headers are followed, since the rest of the block's data is written after its header.
+#[tokio::test]
+async fn open_ended_state_diff_query_ends_at_the_tip() {
+    const NUM_OF_BLOCKS: u64 = 2;
+    let (mut db_executor, _storage_reader, mut storage_writer, ..) = setup();
+    insert_to_storage_test_blocks_up_to(NUM_OF_BLOCKS, &mut storage_writer);
+
+    let (sender, receiver) = futures::channel::mpsc::channel(BUFFER_SIZE);
+    db_executor.register_query::<StateDiffChunk, _>(
+        open_ended_query(0),
+        sender,
+        PeerId::random(),
+        Protocol::StateDiff,
+    );
+    let response = receiver.collect::<Vec<_>>().await;
+    assert_eq!(response.last(), Some(&DataOrFin(None)));
+}
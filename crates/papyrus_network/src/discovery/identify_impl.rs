@@ -1,4 +1,4 @@
-use libp2p::{identify, Multiaddr, PeerId};
+use libp2p::{identify, Multiaddr, PeerId, StreamProtocol};
 
 use crate::mixed_behaviour;
 use crate::mixed_behaviour::BridgedBehaviour;
@@ -7,7 +7,13 @@ pub const IDENTIFY_PROTOCOL_VERSION: &str = "/staknet/identify/0.1.0-rc.0";
 
 #[derive(Debug)]
 pub enum IdentifyToOtherBehaviourEvent {
-    FoundListenAddresses { peer_id: PeerId, listen_addresses: Vec<Multiaddr> },
+    FoundListenAddresses {
+        peer_id: PeerId,
+        listen_addresses: Vec<Multiaddr>,
+        // The software the peer runs and the protocols it supports, as the peer reported them.
+        agent_version: String,
+        protocols: Vec<StreamProtocol>,
+    },
 }
 
 impl From<identify::Event> for mixed_behaviour::Event {
@@ -19,6 +25,8 @@ impl From<identify::Event> for mixed_behaviour::Event {
                         IdentifyToOtherBehaviourEvent::FoundListenAddresses {
                             peer_id,
                             listen_addresses: info.listen_addrs,
+                            agent_version: info.agent_version,
+                            protocols: info.protocols,
                         },
                     ),
                 )
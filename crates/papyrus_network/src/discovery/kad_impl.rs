@@ -1,4 +1,4 @@
-use libp2p::kad;
+use libp2p::{kad, Multiaddr, PeerId};
 use tracing::error;
 
 use super::identify_impl::IdentifyToOtherBehaviourEvent;
@@ -8,6 +8,8 @@ use crate::mixed_behaviour::BridgedBehaviour;
 #[derive(Debug)]
 pub enum KadToOtherBehaviourEvent {
     KadQueryFinished,
+    // Only used for recording the network events.
+    RoutingUpdated { peer_id: PeerId, addresses: Vec<Multiaddr> },
 }
 
 impl From<kad::Event> for mixed_behaviour::Event {
@@ -27,6 +29,16 @@ impl From<kad::Event> for mixed_behaviour::Event {
                     ),
                 )
             }
+            kad::Event::RoutingUpdated { peer, addresses, .. } => {
+                mixed_behaviour::Event::ToOtherBehaviourEvent(
+                    mixed_behaviour::ToOtherBehaviourEvent::Kad(
+                        KadToOtherBehaviourEvent::RoutingUpdated {
+                            peer_id: peer,
+                            addresses: addresses.into_vec(),
+                        },
+                    ),
+                )
+            }
             _ => mixed_behaviour::Event::ToOtherBehaviourEvent(
                 mixed_behaviour::ToOtherBehaviourEvent::NoOp,
             ),
@@ -43,7 +55,11 @@ impl<TStore: kad::store::RecordStore + Send + 'static> BridgedBehaviour for kad:
                 self.get_closest_peers(*peer_id);
             }
             mixed_behaviour::ToOtherBehaviourEvent::Identify(
-                IdentifyToOtherBehaviourEvent::FoundListenAddresses { peer_id, listen_addresses },
+                IdentifyToOtherBehaviourEvent::FoundListenAddresses {
+                    peer_id,
+                    listen_addresses,
+                    ..
+                },
             )
             | mixed_behaviour::ToOtherBehaviourEvent::Discovery(
                 super::ToOtherBehaviourEvent::FoundListenAddresses { peer_id, listen_addresses },
@@ -1,5 +1,10 @@
+use std::marker::PhantomData;
+
 use libp2p::gossipsub::TopicHash;
 use libp2p::{gossipsub, PeerId};
+use papyrus_protobuf::consensus::SignedConsensusMessage;
+use papyrus_protobuf::mempool::MempoolTransaction;
+use papyrus_protobuf::sync::BlockRangeAdvertisement;
 use tracing::error;
 
 use crate::mixed_behaviour;
@@ -11,10 +16,73 @@ pub type Topic = gossipsub::IdentTopic;
 #[cfg(not(test))]
 pub type Topic = gossipsub::Sha256Topic;
 
+/// The topic on which consensus messages are broadcast.
+pub const CONSENSUS_TOPIC: TopicDescriptor<SignedConsensusMessage> =
+    TopicDescriptor::new("consensus");
+
+/// The topic on which nodes broadcast the transactions they received from users.
+pub const MEMPOOL_TRANSACTION_TOPIC: TopicDescriptor<MempoolTransaction> =
+    TopicDescriptor::new("mempool_transactions");
+
+/// The topic on which peers advertise the ranges of blocks they can serve.
+pub const BLOCK_RANGE_ADVERTISEMENT_TOPIC: TopicDescriptor<BlockRangeAdvertisement> =
+    TopicDescriptor::new("block_range_advertisement");
+
+/// A gossipsub topic together with the type of the messages that are broadcast on it, so that
+/// subscribers of the topic can't encode or decode its messages as a different type.
+pub struct TopicDescriptor<T> {
+    name: &'static str,
+    _message_type: PhantomData<fn() -> T>,
+}
+
+impl<T> TopicDescriptor<T> {
+    pub const fn new(name: &'static str) -> Self {
+        Self { name, _message_type: PhantomData }
+    }
+
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+
+    pub(crate) fn topic(&self) -> Topic {
+        Topic::new(self.name)
+    }
+}
+
+// Implemented manually since deriving would require T to implement these traits.
+impl<T> Clone for TopicDescriptor<T> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<T> Copy for TopicDescriptor<T> {}
+
+impl<T> std::fmt::Debug for TopicDescriptor<T> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("TopicDescriptor")
+            .field("name", &self.name)
+            .field("message_type", &std::any::type_name::<T>())
+            .finish()
+    }
+}
+
 #[derive(Debug)]
 pub enum ExternalEvent {
     #[allow(dead_code)]
-    Received { originated_peer_id: PeerId, message: Bytes, topic_hash: TopicHash },
+    Received {
+        originated_peer_id: PeerId,
+        message: Bytes,
+        topic_hash: TopicHash,
+    },
+    PeerSubscribed {
+        peer_id: PeerId,
+        topic_hash: TopicHash,
+    },
+    PeerUnsubscribed {
+        peer_id: PeerId,
+        topic_hash: TopicHash,
+    },
 }
 
 impl From<gossipsub::Event> for mixed_behaviour::Event {
@@ -41,6 +109,16 @@ impl From<gossipsub::Event> for mixed_behaviour::Event {
                     },
                 ))
             }
+            gossipsub::Event::Subscribed { peer_id, topic } => {
+                mixed_behaviour::Event::ExternalEvent(mixed_behaviour::ExternalEvent::GossipSub(
+                    ExternalEvent::PeerSubscribed { peer_id, topic_hash: topic },
+                ))
+            }
+            gossipsub::Event::Unsubscribed { peer_id, topic } => {
+                mixed_behaviour::Event::ExternalEvent(mixed_behaviour::ExternalEvent::GossipSub(
+                    ExternalEvent::PeerUnsubscribed { peer_id, topic_hash: topic },
+                ))
+            }
             _ => mixed_behaviour::Event::ToOtherBehaviourEvent(
                 mixed_behaviour::ToOtherBehaviourEvent::NoOp,
             ),
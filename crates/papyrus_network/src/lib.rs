@@ -3,39 +3,60 @@
 ///
 /// [`Starknet p2p specs`]: https://github.com/starknet-io/starknet-p2p-specs/
 pub mod bin_utils;
+mod connection_gating;
 pub mod db_executor;
 mod discovery;
 pub mod gossipsub_impl;
+mod message_size;
 pub mod mixed_behaviour;
 pub mod network_manager;
 mod peer_manager;
+mod ping_impl;
 pub mod sqmr;
 #[cfg(test)]
 mod test_utils;
 mod utils;
 
+#[cfg(test)]
+#[path = "lib_test.rs"]
+mod lib_test;
+
+use std::borrow::Cow;
 use std::collections::{BTreeMap, HashMap};
+use std::path::PathBuf;
+use std::str::FromStr;
 use std::time::Duration;
 
 use derive_more::Display;
 use enum_iterator::Sequence;
 use lazy_static::lazy_static;
-use libp2p::{Multiaddr, StreamProtocol};
+use libp2p::identity::Keypair;
+use libp2p::multiaddr::Protocol as MultiaddrProtocol;
+use libp2p::{Multiaddr, PeerId, StreamProtocol};
 use papyrus_config::converters::{
     deserialize_optional_vec_u8,
     deserialize_seconds_to_duration,
     serialize_optional_vec_u8,
 };
-use papyrus_config::dumping::{ser_optional_param, ser_param, SerializeConfig};
+use papyrus_config::dumping::{
+    append_sub_config_name,
+    ser_optional_param,
+    ser_param,
+    SerializeConfig,
+};
 use papyrus_config::validators::validate_vec_u256;
 use papyrus_config::{ParamPath, ParamPrivacyInput, SerializedParam};
-use serde::{Deserialize, Serialize};
-use validator::Validate;
+use papyrus_protobuf::sync::{ProtocolVersion, ResponseLimits};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize, Serializer};
+use starknet_api::core::ChainId;
+use validator::{Validate, ValidationError};
 
-pub use crate::network_manager::SqmrSubscriberChannels;
+pub use crate::network_manager::{QueryPriority, SqmrSubscriberChannels};
 
 // TODO: add peer manager config to the network config
 #[derive(Debug, Clone, Deserialize, Serialize, PartialEq, Validate)]
+#[validate(schema(function = "validate_network_config"))]
 pub struct NetworkConfig {
     pub tcp_port: u16,
     pub quic_port: u16,
@@ -44,10 +65,329 @@ pub struct NetworkConfig {
     #[serde(deserialize_with = "deserialize_seconds_to_duration")]
     pub idle_connection_timeout: Duration,
     pub header_buffer_size: usize,
+    pub sqmr_subscriber_buffer_size: usize,
+    #[serde(deserialize_with = "deserialize_seconds_to_duration")]
+    pub block_range_advertisement_interval: Duration,
+    #[serde(deserialize_with = "deserialize_seconds_to_duration")]
+    pub block_range_advertisement_ttl: Duration,
+    #[serde(deserialize_with = "deserialize_seconds_to_duration")]
+    pub peer_dial_deadline: Duration,
+    #[validate(custom = "validate_bootstrap_peer_multiaddr")]
+    #[serde(deserialize_with = "deserialize_optional_multiaddr")]
     pub bootstrap_peer_multiaddr: Option<Multiaddr>,
     #[validate(custom = "validate_vec_u256")]
     #[serde(deserialize_with = "deserialize_optional_vec_u8")]
     pub(crate) secret_key: Option<Vec<u8>>,
+    pub inbound_query_log_mode: InboundQueryLogMode,
+    #[validate(range(min = 1))]
+    pub inbound_query_log_sample_rate: u64,
+    #[validate(range(min = 1))]
+    pub inbound_query_max_blocking_reads: usize,
+    #[validate(range(min = 1))]
+    pub inbound_query_blocks_per_read_txn: u64,
+    pub inbound_query_cache_max_bytes: u64,
+    pub node_role: NodeRole,
+    #[validate(range(min = 1))]
+    pub max_inbound_sessions: Option<usize>,
+    #[validate(range(min = 1))]
+    pub max_inbound_sessions_per_peer: Option<usize>,
+    #[validate(range(min = 1))]
+    pub max_outbound_sessions: Option<usize>,
+    #[validate(range(min = 1))]
+    pub max_outbound_sessions_per_peer: Option<usize>,
+    #[serde(deserialize_with = "deserialize_seconds_to_duration")]
+    pub outbound_query_aging_interval: Duration,
+    #[validate(range(min = 1))]
+    pub total_memory_budget_bytes: u64,
+    pub advertise_legacy_protocol_names: bool,
+    #[serde(deserialize_with = "deserialize_protocol_versions")]
+    pub disabled_protocol_versions: Vec<ProtocolVersion>,
+    pub debug_events: bool,
+    #[validate(range(min = 1))]
+    pub debug_events_buffer_size: usize,
+    #[validate(range(min = 1))]
+    pub max_response_items: u64,
+    #[validate(range(min = 1))]
+    pub max_response_bytes: u64,
+    pub max_serving_bytes_per_sec: u64,
+    pub max_serving_bytes_per_sec_per_peer: u64,
+    pub secondary_storage_path_prefix: Option<PathBuf>,
+    #[serde(deserialize_with = "deserialize_seconds_to_duration")]
+    pub secondary_storage_reopen_interval: Duration,
+    #[validate(range(min = 1))]
+    pub max_listen_attempts: usize,
+    pub max_network_restarts: usize,
+    #[serde(deserialize_with = "deserialize_peer_ids")]
+    pub allowed_peers: Vec<PeerId>,
+    pub restrict_inbound_to_allowed_peers: bool,
+    #[serde(deserialize_with = "deserialize_peer_ids")]
+    pub denied_peers: Vec<PeerId>,
+    #[validate(range(min = 0.0, max = 1.0))]
+    pub message_size_warning_fraction: f64,
+    #[serde(deserialize_with = "deserialize_seconds_to_duration")]
+    pub ping_interval: Duration,
+    #[validate(range(min = 1))]
+    pub max_consecutive_lost_pings: u32,
+    #[validate]
+    pub header_inbound_query_queue: InboundQueryQueueConfig,
+    #[validate]
+    pub state_diff_inbound_query_queue: InboundQueryQueueConfig,
+    #[validate]
+    pub transaction_inbound_query_queue: InboundQueryQueueConfig,
+}
+
+fn validate_network_config(config: &NetworkConfig) -> Result<(), ValidationError> {
+    if config.restrict_inbound_to_allowed_peers && config.allowed_peers.is_empty() {
+        return Err(ValidationError::new(
+            "allowed_peers can't be empty when restrict_inbound_to_allowed_peers is set",
+        ));
+    }
+    if enum_iterator::all::<Protocol>().any(|protocol| {
+        protocol
+            .supported_versions()
+            .iter()
+            .all(|version| config.disabled_protocol_versions.contains(version))
+    }) {
+        return Err(ValidationError::new(
+            "disabled_protocol_versions can't disable all the versions of a protocol",
+        ));
+    }
+    if config.ping_interval.is_zero() {
+        return Err(ValidationError::new("ping_interval must be at least one second"));
+    }
+    Ok(())
+}
+
+// The node dials its bootstrap peer over TCP, after resolving the host name if the address has
+// one, and the discovery needs the peer id of the bootstrap peer to find other peers through it.
+// The error's message names the problem, and the address is added to it by the validation.
+fn validate_bootstrap_peer_multiaddr(multiaddr: &Multiaddr) -> Result<(), ValidationError> {
+    let mut components = multiaddr.iter();
+    match components.next() {
+        Some(
+            MultiaddrProtocol::Ip4(_)
+            | MultiaddrProtocol::Ip6(_)
+            | MultiaddrProtocol::Dns(_)
+            | MultiaddrProtocol::Dns4(_)
+            | MultiaddrProtocol::Dns6(_),
+        ) => {}
+        Some(MultiaddrProtocol::Dnsaddr(_)) => {
+            return Err(invalid_bootstrap_peer_multiaddr(
+                "/dnsaddr addresses aren't supported, use /dns4/<host>/tcp/<port>/p2p/<peer id> \
+                 instead.",
+            ));
+        }
+        _ => {
+            return Err(invalid_bootstrap_peer_multiaddr(
+                "The address should start with the host of the peer: /ip4, /ip6, /dns, /dns4 or \
+                 /dns6.",
+            ));
+        }
+    }
+    match components.next() {
+        Some(MultiaddrProtocol::Tcp(port)) if port != 0 => {}
+        Some(MultiaddrProtocol::Tcp(_)) => {
+            return Err(invalid_bootstrap_peer_multiaddr("The TCP port of the address is 0."));
+        }
+        Some(MultiaddrProtocol::Udp(_)) => {
+            return Err(invalid_bootstrap_peer_multiaddr(
+                "QUIC isn't supported yet, use a /tcp/<port> address instead.",
+            ));
+        }
+        _ => {
+            return Err(invalid_bootstrap_peer_multiaddr(
+                "The address should have a /tcp/<port> component after the host.",
+            ));
+        }
+    }
+    match components.next() {
+        Some(MultiaddrProtocol::P2p(_)) => {}
+        None => {
+            return Err(invalid_bootstrap_peer_multiaddr(
+                "The address should end with the peer id of the bootstrap peer: /p2p/<peer id>.",
+            ));
+        }
+        Some(component) => {
+            return Err(invalid_bootstrap_peer_multiaddr(format!(
+                "The component {component} isn't supported. The address should be of the form \
+                 /<ip4|ip6|dns|dns4|dns6>/<host>/tcp/<port>/p2p/<peer id>."
+            )));
+        }
+    }
+    if components.next().is_some() {
+        return Err(invalid_bootstrap_peer_multiaddr(
+            "The address shouldn't have components after the peer id.",
+        ));
+    }
+    Ok(())
+}
+
+fn invalid_bootstrap_peer_multiaddr(message: impl Into<Cow<'static, str>>) -> ValidationError {
+    let mut error = ValidationError::new("invalid bootstrap peer address");
+    error.message = Some(message.into());
+    error
+}
+
+// Deserializes the address with an error that names it if it's invalid. Whitespace around the
+// address and a trailing slash, which are common when the address is copied, are removed.
+fn deserialize_optional_multiaddr<'de, D>(de: D) -> Result<Option<Multiaddr>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let raw_multiaddr: Option<String> = Deserialize::deserialize(de)?;
+    raw_multiaddr
+        .map(|raw_multiaddr| {
+            let trimmed_multiaddr = raw_multiaddr.trim();
+            let trimmed_multiaddr =
+                trimmed_multiaddr.strip_suffix('/').unwrap_or(trimmed_multiaddr);
+            Multiaddr::from_str(trimmed_multiaddr).map_err(|error| {
+                D::Error::custom(format!("Couldn't parse multiaddr \"{raw_multiaddr}\": {error}"))
+            })
+        })
+        .transpose()
+}
+
+// Serializes the peer ids to a string of the peer ids separated by spaces.
+fn serialize_peer_ids(peer_ids: &[PeerId]) -> String {
+    peer_ids.iter().map(PeerId::to_string).collect::<Vec<_>>().join(" ")
+}
+
+// Deserializes the peer ids from a string of the peer ids separated by spaces.
+fn deserialize_peer_ids<'de, D>(de: D) -> Result<Vec<PeerId>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let raw_str: String = Deserialize::deserialize(de)?;
+    raw_str
+        .split_whitespace()
+        .map(|raw_peer_id| {
+            PeerId::from_str(raw_peer_id).map_err(|error| {
+                D::Error::custom(format!("Couldn't parse peer id \"{raw_peer_id}\": {error}"))
+            })
+        })
+        .collect()
+}
+
+// Serializes the protocol versions to a string of the versions separated by spaces.
+fn serialize_protocol_versions(versions: &[ProtocolVersion]) -> String {
+    versions.iter().map(ProtocolVersion::to_string).collect::<Vec<_>>().join(" ")
+}
+
+// Deserializes the protocol versions from a string of the versions separated by spaces.
+fn deserialize_protocol_versions<'de, D>(de: D) -> Result<Vec<ProtocolVersion>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let raw_str: String = Deserialize::deserialize(de)?;
+    raw_str
+        .split_whitespace()
+        .map(|raw_version| {
+            raw_version.parse().map_err(|error| {
+                D::Error::custom(format!(
+                    "Couldn't parse protocol version \"{raw_version}\": {error}"
+                ))
+            })
+        })
+        .collect()
+}
+
+/// Which of the inbound queries served by the node are logged. Only the metadata of a query is
+/// logged, never the data that was sent for it.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub enum InboundQueryLogMode {
+    #[default]
+    Disabled,
+    All,
+    /// Logs one out of every `inbound_query_log_sample_rate` queries.
+    Sampled,
+}
+
+/// Limits the inbound queries of a protocol that are pending, i.e. that the server didn't start
+/// responding to yet.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq, Validate)]
+pub struct InboundQueryQueueConfig {
+    #[validate(range(min = 1))]
+    pub max_pending_queries: usize,
+    pub overload_policy: InboundQueryOverloadPolicy,
+}
+
+impl Default for InboundQueryQueueConfig {
+    fn default() -> Self {
+        Self { max_pending_queries: 100, overload_policy: InboundQueryOverloadPolicy::Queue }
+    }
+}
+
+impl SerializeConfig for InboundQueryQueueConfig {
+    fn dump(&self) -> BTreeMap<ParamPath, SerializedParam> {
+        BTreeMap::from_iter([
+            ser_param(
+                "max_pending_queries",
+                &self.max_pending_queries,
+                "The number of pending inbound queries of the protocol above which the node is \
+                 overloaded.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "overload_policy",
+                &self.overload_policy,
+                "What's done with new inbound queries of the protocol while the node is \
+                 overloaded. One of Queue or Reject.",
+                ParamPrivacyInput::Public,
+            ),
+        ])
+    }
+}
+
+/// What's done with a new inbound query of a protocol that already has `max_pending_queries`
+/// pending queries.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub enum InboundQueryOverloadPolicy {
+    /// The query waits for the server like any other query.
+    #[default]
+    Queue,
+    /// The session of the query is closed right away, so that the peer can query another node
+    /// instead of waiting until its session times out.
+    Reject,
+}
+
+/// What the node mostly uses its sessions for. The inbound sessions, in which the node serves the
+/// queries of its peers, and the outbound sessions, in which it queries them, are limited
+/// separately, so that one direction can't take the sessions of the other.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub enum NodeRole {
+    /// Mostly serves its peers, and queries them only to keep up with the tip of the chain.
+    Serving,
+    /// Mostly syncs from its peers, and serves them with what's left.
+    Syncing,
+    #[default]
+    Balanced,
+}
+
+impl NodeRole {
+    /// The limits on the inbound sessions that aren't set in the config.
+    pub fn default_inbound_session_limits(self) -> SessionLimits {
+        match self {
+            NodeRole::Serving => SessionLimits { max_sessions: 200, max_sessions_per_peer: 20 },
+            NodeRole::Syncing => SessionLimits { max_sessions: 20, max_sessions_per_peer: 2 },
+            NodeRole::Balanced => SessionLimits { max_sessions: 100, max_sessions_per_peer: 10 },
+        }
+    }
+
+    /// The limits on the outbound sessions that aren't set in the config.
+    pub fn default_outbound_session_limits(self) -> SessionLimits {
+        match self {
+            NodeRole::Serving => SessionLimits { max_sessions: 4, max_sessions_per_peer: 2 },
+            NodeRole::Syncing => SessionLimits { max_sessions: 32, max_sessions_per_peer: 8 },
+            NodeRole::Balanced => SessionLimits { max_sessions: 10, max_sessions_per_peer: 4 },
+        }
+    }
+}
+
+/// The limits on the concurrent sessions of one direction.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct SessionLimits {
+    pub max_sessions: usize,
+    pub max_sessions_per_peer: usize,
 }
 
 /// This is a part of the exposed API of the network manager.
@@ -60,7 +400,12 @@ pub enum Protocol {
     Transaction,
 }
 
+const PROTOCOL_NAME_PREFIX: &str = "/starknet/";
+
 impl Protocol {
+    /// The name of the first version of the protocol without a chain id, which was used before the
+    /// names were scoped by the chain. It's also used for referring to the protocol in logs,
+    /// metrics and block range advertisements.
     pub fn as_str(&self) -> &'static str {
         match self {
             Protocol::SignedBlockHeader => "/starknet/headers/1",
@@ -68,6 +413,44 @@ impl Protocol {
             Protocol::Transaction => "/starknet/transactions/1",
         }
     }
+
+    /// The versions of the protocol this node supports, from the newest to the oldest.
+    pub fn supported_versions(&self) -> &'static [ProtocolVersion] {
+        match self {
+            Protocol::SignedBlockHeader => &[1],
+            Protocol::StateDiff => &[1],
+            Protocol::Transaction => &[1],
+        }
+    }
+
+    /// The name of the given version of the protocol on the given chain, e.g.
+    /// "/starknet/SN_SEPOLIA/headers/1".
+    pub fn chain_scoped_name(
+        &self,
+        chain_id: &ChainId,
+        version: ProtocolVersion,
+    ) -> StreamProtocol {
+        StreamProtocol::try_from_owned(format!(
+            "{PROTOCOL_NAME_PREFIX}{chain_id}/{}/{version}",
+            self.short_name()
+        ))
+        .expect("The protocol name starts with a slash")
+    }
+
+    // The name of the protocol without the prefix and the version, e.g. "headers".
+    fn short_name(&self) -> &'static str {
+        match self {
+            Protocol::SignedBlockHeader => "headers",
+            Protocol::StateDiff => "state_diffs",
+            Protocol::Transaction => "transactions",
+        }
+    }
+}
+
+impl Serialize for Protocol {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
 }
 
 impl From<Protocol> for StreamProtocol {
@@ -76,23 +459,152 @@ impl From<Protocol> for StreamProtocol {
     }
 }
 
-#[derive(thiserror::Error, Debug)]
-#[error("Unknown protocol: {0}")]
-pub struct UnknownProtocolConversionError(String);
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum ProtocolConversionError {
+    #[error("Unknown protocol: {0}")]
+    UnknownProtocol(String),
+    #[error(
+        "Protocol {protocol_name} belongs to chain {chain_id} and not to {expected_chain_id}."
+    )]
+    WrongChain { protocol_name: String, chain_id: String, expected_chain_id: ChainId },
+    #[error("Protocol {0} isn't scoped by a chain and legacy protocol names aren't advertised.")]
+    LegacyProtocolName(String),
+}
 
 lazy_static! {
-    static ref PROTOCOL_NAME_TO_PROTOCOL: HashMap<&'static str, Protocol> =
-        enum_iterator::all::<Protocol>().map(|protocol| (protocol.as_str(), protocol)).collect();
+    static ref PROTOCOL_SHORT_NAME_TO_PROTOCOL: HashMap<&'static str, Protocol> =
+        enum_iterator::all::<Protocol>()
+            .map(|protocol| (protocol.short_name(), protocol))
+            .collect();
 }
 
+// The parts of a protocol name. The chain is None if the name isn't scoped by a chain.
+struct ParsedProtocolName<'a> {
+    protocol: Protocol,
+    version: ProtocolVersion,
+    chain_id: Option<&'a str>,
+}
+
+fn parse_protocol_name(name: &str) -> Result<ParsedProtocolName<'_>, ProtocolConversionError> {
+    let unknown_protocol_error = || ProtocolConversionError::UnknownProtocol(name.to_string());
+    let name_without_prefix =
+        name.strip_prefix(PROTOCOL_NAME_PREFIX).ok_or_else(unknown_protocol_error)?;
+    let (name_without_version, version) =
+        name_without_prefix.rsplit_once('/').ok_or_else(unknown_protocol_error)?;
+    let version = version.parse().map_err(|_| unknown_protocol_error())?;
+    let (chain_id, short_name) = match name_without_version.rsplit_once('/') {
+        Some((chain_id, short_name)) => (Some(chain_id), short_name),
+        None => (None, name_without_version),
+    };
+    let protocol =
+        PROTOCOL_SHORT_NAME_TO_PROTOCOL.get(short_name).ok_or_else(unknown_protocol_error)?;
+    Ok(ParsedProtocolName { protocol: *protocol, version, chain_id })
+}
+
+/// Accepts both the chain scoped names of the protocols and the legacy names, regardless of the
+/// chain and the version. Use [`ProtocolNames::protocol`] for names that should belong to the
+/// node's chain.
 impl TryFrom<StreamProtocol> for Protocol {
-    type Error = UnknownProtocolConversionError;
+    type Error = ProtocolConversionError;
 
     fn try_from(protocol: StreamProtocol) -> Result<Self, Self::Error> {
-        PROTOCOL_NAME_TO_PROTOCOL
-            .get(protocol.as_ref())
-            .ok_or(UnknownProtocolConversionError(protocol.as_ref().to_string()))
+        parse_protocol_name(protocol.as_ref()).map(|parsed_name| parsed_name.protocol)
+    }
+}
+
+/// Converts between the protocols and the names they are negotiated with on the node's chain, so
+/// that peers of other chains never open sessions with the node.
+///
+/// Each protocol has a name for each of its versions. The node proposes all the versions it
+/// supports, from the newest to the oldest, and each session uses the newest version that both
+/// peers support.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct ProtocolNames {
+    chain_id: ChainId,
+    // Whether the names that aren't scoped by a chain are also supported for inbound queries.
+    advertise_legacy_names: bool,
+    // Versions that aren't used even though they're supported, for testing.
+    disabled_versions: Vec<ProtocolVersion>,
+}
+
+impl ProtocolNames {
+    pub fn new(chain_id: ChainId, advertise_legacy_names: bool) -> Self {
+        Self { chain_id, advertise_legacy_names, disabled_versions: Vec::new() }
+    }
+
+    /// Stops using the given versions of all the protocols.
+    pub fn with_disabled_versions(mut self, disabled_versions: Vec<ProtocolVersion>) -> Self {
+        self.disabled_versions = disabled_versions;
+        self
+    }
+
+    /// The versions of the given protocol this node uses, from the newest to the oldest.
+    pub fn versions(&self, protocol: Protocol) -> Vec<ProtocolVersion> {
+        protocol
+            .supported_versions()
+            .iter()
             .copied()
+            .filter(|version| !self.disabled_versions.contains(version))
+            .collect()
+    }
+
+    /// The name of the newest version of the given protocol this node uses. The peers' data about
+    /// the protocol is kept under this name regardless of the version each session negotiated.
+    pub fn stream_protocol(&self, protocol: Protocol) -> StreamProtocol {
+        let version = *self
+            .versions(protocol)
+            .first()
+            .expect("The config validation ensures a version of each protocol is enabled");
+        protocol.chain_scoped_name(&self.chain_id, version)
+    }
+
+    /// The names this node proposes when it sends a query of the given protocol, from the newest
+    /// version to the oldest.
+    pub fn outbound_stream_protocols(&self, protocol: Protocol) -> Vec<StreamProtocol> {
+        self.versions(protocol)
+            .into_iter()
+            .map(|version| protocol.chain_scoped_name(&self.chain_id, version))
+            .collect()
+    }
+
+    /// The names this node answers queries of the given protocol on, from the newest version to
+    /// the oldest.
+    pub fn inbound_stream_protocols(&self, protocol: Protocol) -> Vec<StreamProtocol> {
+        let mut stream_protocols = self.outbound_stream_protocols(protocol);
+        // Only the first version existed before the names were scoped by the chain.
+        if self.advertise_legacy_names && self.versions(protocol).contains(&1) {
+            stream_protocols.push(protocol.into());
+        }
+        stream_protocols
+    }
+
+    /// Returns the protocol of a name that was negotiated with a peer. Fails if the name belongs
+    /// to another chain.
+    pub fn protocol(
+        &self,
+        stream_protocol: &StreamProtocol,
+    ) -> Result<Protocol, ProtocolConversionError> {
+        self.protocol_and_version(stream_protocol).map(|(protocol, _version)| protocol)
+    }
+
+    /// Same as [`protocol`](Self::protocol), but also returns the version of the protocol the
+    /// name was negotiated with.
+    pub fn protocol_and_version(
+        &self,
+        stream_protocol: &StreamProtocol,
+    ) -> Result<(Protocol, ProtocolVersion), ProtocolConversionError> {
+        let name = stream_protocol.as_ref();
+        let ParsedProtocolName { protocol, version, chain_id } = parse_protocol_name(name)?;
+        match chain_id {
+            None if self.advertise_legacy_names => Ok((protocol, version)),
+            None => Err(ProtocolConversionError::LegacyProtocolName(name.to_string())),
+            Some(chain_id) if chain_id == self.chain_id.to_string() => Ok((protocol, version)),
+            Some(chain_id) => Err(ProtocolConversionError::WrongChain {
+                protocol_name: name.to_string(),
+                chain_id: chain_id.to_string(),
+                expected_chain_id: self.chain_id.clone(),
+            }),
+        }
     }
 }
 
@@ -130,12 +642,42 @@ impl SerializeConfig for NetworkConfig {
                 "Size of the buffer for headers read from the storage.",
                 ParamPrivacyInput::Public,
             ),
+            ser_param(
+                "sqmr_subscriber_buffer_size",
+                &self.sqmr_subscriber_buffer_size,
+                "Maximal number of responses buffered for each sqmr subscriber. Once a \
+                 subscriber's buffer is full, the node stops reading from the network until the \
+                 subscriber consumes some of the responses.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "block_range_advertisement_interval",
+                &self.block_range_advertisement_interval.as_secs(),
+                "Time in seconds between advertisements of the range of blocks this node can \
+                 serve.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "block_range_advertisement_ttl",
+                &self.block_range_advertisement_ttl.as_secs(),
+                "Time in seconds after which a block range advertised by a peer is considered \
+                 stale and is no longer used for choosing which peer to query.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "peer_dial_deadline",
+                &self.peer_dial_deadline.as_secs(),
+                "Time in seconds that a query waits for a peer to be connected before it fails, \
+                 if no suitable peer is connected when it's sent. The known peers are dialed and \
+                 the discovery of new peers is resumed meanwhile.",
+                ParamPrivacyInput::Public,
+            ),
         ]);
         config.extend(ser_optional_param(
             &self.bootstrap_peer_multiaddr,
             Multiaddr::empty(),
             "bootstrap_peer_multiaddr",
-            "The multiaddress of the peer node. It should include the peer's id. For more info: https://docs.libp2p.io/concepts/fundamentals/peers/",
+            "The multiaddress of the peer node, of the form /<ip4|ip6|dns|dns4|dns6>/<host>/tcp/<port>/p2p/<peer id>. For more info: https://docs.libp2p.io/concepts/fundamentals/peers/",
             ParamPrivacyInput::Public,
         ));
         config.extend([ser_param(
@@ -145,10 +687,315 @@ impl SerializeConfig for NetworkConfig {
              will be used.",
             ParamPrivacyInput::Private,
         )]);
+        config.extend([
+            ser_param(
+                "inbound_query_log_mode",
+                &self.inbound_query_log_mode,
+                "Which inbound queries to log with their peer, protocol, block range, number of \
+                 items served and duration. One of Disabled, All or Sampled.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "inbound_query_log_sample_rate",
+                &self.inbound_query_log_sample_rate,
+                "When inbound_query_log_mode is Sampled, one out of every this many inbound \
+                 queries is logged.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "inbound_query_max_blocking_reads",
+                &self.inbound_query_max_blocking_reads,
+                "The maximal number of inbound queries whose data is read from the storage \
+                 concurrently. Each read runs on a blocking thread outside of the async runtime.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "inbound_query_blocks_per_read_txn",
+                &self.inbound_query_blocks_per_read_txn,
+                "The maximal number of blocks of an inbound query that are read in a single read \
+                 transaction. The transaction is closed before the blocks are sent, so a slow peer \
+                 doesn't keep it open and make the database grow.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "inbound_query_cache_max_bytes",
+                &self.inbound_query_cache_max_bytes,
+                "The maximal number of bytes of recently served blocks that are cached in memory for \
+                 serving other inbound queries for the same blocks. 0 disables the cache.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "advertise_legacy_protocol_names",
+                &self.advertise_legacy_protocol_names,
+                "If true, the protocol names that aren't scoped by the chain id (e.g. \
+                 /starknet/headers/1) are also advertised, and queries on them are answered, for \
+                 peers that don't use the chain scoped names yet. Queries are always sent on the \
+                 chain scoped names.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "disabled_protocol_versions",
+                &serialize_protocol_versions(&self.disabled_protocol_versions),
+                "The protocol versions, separated by spaces, that this node doesn't use for any \
+                 protocol even though it supports them. For testing the compatibility with peers \
+                 that don't support these versions. At least one version of each protocol must \
+                 stay enabled.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "node_role",
+                &self.node_role,
+                "What the node mostly uses its sessions for, which chooses the session limits that \
+                 aren't set. One of Serving, Syncing or Balanced.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "outbound_query_aging_interval",
+                &self.outbound_query_aging_interval.as_secs(),
+                "Time in seconds after which a waiting query is sent as if it had the next \
+                 priority, so that low priority queries are eventually sent. 0 disables aging.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "total_memory_budget_bytes",
+                &self.total_memory_budget_bytes,
+                "The approximate maximal number of bytes of received responses and broadcasted \
+                 messages that are buffered for the node's components. Once exceeded, the node \
+                 stops reading from its peers and defers sending new queries until the \
+                 components drain their buffers.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "debug_events",
+                &self.debug_events,
+                "If true, every connection, discovery, gossipsub subscription and sqmr session \
+                 event is traced with its peer and a sequence number, and the last events are \
+                 served by the monitoring gateway. For debugging connection issues.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "debug_events_buffer_size",
+                &self.debug_events_buffer_size,
+                "The number of recent network events kept in memory when debug_events is set.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "max_response_items",
+                &self.max_response_items,
+                "The maximal number of items this node sends in response to a single sync query. \
+                 Once a response reaches it, the response ends after the current block even if \
+                 the query asked for more blocks. Advertised to the peers.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "max_response_bytes",
+                &self.max_response_bytes,
+                "The maximal number of encoded bytes this node sends in response to a single sync \
+                 query. Once a response reaches it, the response ends after the current block \
+                 even if the query asked for more blocks. Advertised to the peers.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "max_serving_bytes_per_sec",
+                &self.max_serving_bytes_per_sec,
+                "The maximal number of bytes per second this node sends in response to the sync \
+                 queries of all the peers. Responses beyond it are delayed, not dropped. \
+                 Broadcasted messages, such as consensus messages, aren't limited. 0 disables the \
+                 limit.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "max_serving_bytes_per_sec_per_peer",
+                &self.max_serving_bytes_per_sec_per_peer,
+                "The maximal number of bytes per second this node sends in response to the sync \
+                 queries of a single peer. Responses beyond it are delayed, not dropped. 0 \
+                 disables the limit.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "secondary_storage_reopen_interval",
+                &self.secondary_storage_reopen_interval.as_secs(),
+                "Time in seconds between checks of whether the files of the secondary storage \
+                 were replaced, e.g. by a newer snapshot. If they were, the secondary storage is \
+                 reopened.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "max_listen_attempts",
+                &self.max_listen_attempts,
+                "The number of times the node tries to listen on its address when it starts or \
+                 after listening on it stopped, before giving up and restarting the network.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "max_network_restarts",
+                &self.max_network_restarts,
+                "The number of times the network is restarted after a recoverable error, such as \
+                 failing to listen on the node's address. The node exits on the next error.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "allowed_peers",
+                &serialize_peer_ids(&self.allowed_peers),
+                "The peer ids, separated by spaces, of the only peers this node connects to. If \
+                 empty, the node connects to any peer. The bootstrap peer is always allowed.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "restrict_inbound_to_allowed_peers",
+                &self.restrict_inbound_to_allowed_peers,
+                "If true, connections from peers that aren't in allowed_peers are also denied. \
+                 Requires allowed_peers to be set.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "denied_peers",
+                &serialize_peer_ids(&self.denied_peers),
+                "The peer ids, separated by spaces, of peers this node never connects to and \
+                 whose connections it denies.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "message_size_warning_fraction",
+                &self.message_size_warning_fraction,
+                "The fraction of the size limit of a broadcasted message or an sqmr message above \
+                 which the message is warned about, at most once a minute for each topic or \
+                 protocol and direction.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "ping_interval",
+                &self.ping_interval.as_secs(),
+                "Time in seconds between pings to each connected peer, which measure the round \
+                 trip time to the peer. The round trip time is preferred when choosing between \
+                 otherwise equally suitable peers to query. Can be changed while the node is \
+                 running, and then applies to new connections.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "max_consecutive_lost_pings",
+                &self.max_consecutive_lost_pings,
+                "The number of consecutive pings a peer fails to answer after which it's \
+                 considered unhealthy. The node disconnects from an unhealthy peer, so that its \
+                 queries are sent to other peers, and queries it only if no healthy peer can take \
+                 the query, until the peer answers a ping.",
+                ParamPrivacyInput::Public,
+            ),
+        ]);
+        config.extend(append_sub_config_name(
+            self.header_inbound_query_queue.dump(),
+            "header_inbound_query_queue",
+        ));
+        config.extend(append_sub_config_name(
+            self.state_diff_inbound_query_queue.dump(),
+            "state_diff_inbound_query_queue",
+        ));
+        config.extend(append_sub_config_name(
+            self.transaction_inbound_query_queue.dump(),
+            "transaction_inbound_query_queue",
+        ));
+        let inbound_defaults = self.node_role.default_inbound_session_limits();
+        let outbound_defaults = self.node_role.default_outbound_session_limits();
+        config.extend(ser_optional_param(
+            &self.max_inbound_sessions,
+            inbound_defaults.max_sessions,
+            "max_inbound_sessions",
+            "The maximal number of sessions in which this node serves the queries of its peers \
+             concurrently. Further queries are rejected, so that the peers query other nodes. If \
+             not set, it's chosen by node_role.",
+            ParamPrivacyInput::Public,
+        ));
+        config.extend(ser_optional_param(
+            &self.max_inbound_sessions_per_peer,
+            inbound_defaults.max_sessions_per_peer,
+            "max_inbound_sessions_per_peer",
+            "The maximal number of sessions in which this node serves the queries of a single \
+             peer concurrently. If not set, it's chosen by node_role.",
+            ParamPrivacyInput::Public,
+        ));
+        config.extend(ser_optional_param(
+            &self.max_outbound_sessions,
+            outbound_defaults.max_sessions,
+            "max_outbound_sessions",
+            "The maximal number of queries this node sends to peers concurrently. Further queries \
+             wait and are sent by their priority once a session finishes. If not set, it's chosen \
+             by node_role.",
+            ParamPrivacyInput::Public,
+        ));
+        config.extend(ser_optional_param(
+            &self.max_outbound_sessions_per_peer,
+            outbound_defaults.max_sessions_per_peer,
+            "max_outbound_sessions_per_peer",
+            "The maximal number of queries this node sends to a single peer concurrently. Further \
+             queries are sent to other peers, or wait for a session of the peer to finish. If not \
+             set, it's chosen by node_role.",
+            ParamPrivacyInput::Public,
+        ));
+        config.extend(ser_optional_param(
+            &self.secondary_storage_path_prefix,
+            PathBuf::from("./replica_data"),
+            "secondary_storage_path_prefix",
+            "If set, the sync queries of peers are served from a read-only replica of the storage \
+             at <secondary_storage_path_prefix>/<chain_id> instead of from the storage the node \
+             writes to. If the replica can't be opened, the queries are answered with no data.",
+            ParamPrivacyInput::Public,
+        ));
         config
     }
 }
 
+impl NetworkConfig {
+    /// The limits on each response to a sync query this node serves.
+    pub fn response_limits(&self) -> ResponseLimits {
+        ResponseLimits { max_items: self.max_response_items, max_bytes: self.max_response_bytes }
+    }
+
+    /// The limit on the pending inbound queries of the given protocol.
+    pub fn inbound_query_queue(&self, protocol: Protocol) -> InboundQueryQueueConfig {
+        match protocol {
+            Protocol::SignedBlockHeader => self.header_inbound_query_queue,
+            Protocol::StateDiff => self.state_diff_inbound_query_queue,
+            Protocol::Transaction => self.transaction_inbound_query_queue,
+        }
+    }
+
+    /// The limits on the sessions in which the node serves the queries of its peers.
+    pub fn inbound_session_limits(&self) -> SessionLimits {
+        let defaults = self.node_role.default_inbound_session_limits();
+        SessionLimits {
+            max_sessions: self.max_inbound_sessions.unwrap_or(defaults.max_sessions),
+            max_sessions_per_peer: self
+                .max_inbound_sessions_per_peer
+                .unwrap_or(defaults.max_sessions_per_peer),
+        }
+    }
+
+    /// The limits on the sessions in which the node queries its peers.
+    pub fn outbound_session_limits(&self) -> SessionLimits {
+        let defaults = self.node_role.default_outbound_session_limits();
+        SessionLimits {
+            max_sessions: self.max_outbound_sessions.unwrap_or(defaults.max_sessions),
+            max_sessions_per_peer: self
+                .max_outbound_sessions_per_peer
+                .unwrap_or(defaults.max_sessions_per_peer),
+        }
+    }
+
+    /// Sets the ed25519 secret key of the node, which determines its peer id.
+    pub fn set_secret_key(&mut self, secret_key: Vec<u8>) {
+        self.secret_key = Some(secret_key);
+    }
+
+    /// The peer id of the node, or None if no secret key is set and the node generates one when it
+    /// starts.
+    pub fn peer_id(&self) -> Option<PeerId> {
+        let secret_key = self.secret_key.clone()?;
+        let key_pair =
+            Keypair::ed25519_from_bytes(secret_key).expect("Error while parsing secret key");
+        Some(key_pair.public().to_peer_id())
+    }
+}
+
 impl Default for NetworkConfig {
     fn default() -> Self {
         Self {
@@ -157,8 +1004,45 @@ impl Default for NetworkConfig {
             session_timeout: Duration::from_secs(120),
             idle_connection_timeout: Duration::from_secs(120),
             header_buffer_size: 100000,
+            sqmr_subscriber_buffer_size: 500,
+            block_range_advertisement_interval: Duration::from_secs(60),
+            block_range_advertisement_ttl: Duration::from_secs(300),
+            peer_dial_deadline: Duration::from_secs(60),
             bootstrap_peer_multiaddr: None,
             secret_key: None,
+            inbound_query_log_mode: InboundQueryLogMode::Disabled,
+            inbound_query_log_sample_rate: 100,
+            inbound_query_max_blocking_reads: 8,
+            inbound_query_blocks_per_read_txn: 100,
+            inbound_query_cache_max_bytes: 1 << 25, // 32MB
+            node_role: NodeRole::Balanced,
+            max_inbound_sessions: None,
+            max_inbound_sessions_per_peer: None,
+            max_outbound_sessions: None,
+            max_outbound_sessions_per_peer: None,
+            outbound_query_aging_interval: Duration::from_secs(10),
+            total_memory_budget_bytes: 1 << 30, // 1GB
+            advertise_legacy_protocol_names: true,
+            disabled_protocol_versions: Vec::new(),
+            debug_events: false,
+            debug_events_buffer_size: 1000,
+            max_response_items: 100000,
+            max_response_bytes: 1 << 26,
+            max_serving_bytes_per_sec: 0,
+            max_serving_bytes_per_sec_per_peer: 0,
+            secondary_storage_path_prefix: None,
+            secondary_storage_reopen_interval: Duration::from_secs(60),
+            max_listen_attempts: 5,
+            max_network_restarts: 3,
+            allowed_peers: Vec::new(),
+            restrict_inbound_to_allowed_peers: false,
+            denied_peers: Vec::new(),
+            message_size_warning_fraction: 0.9,
+            ping_interval: Duration::from_secs(15),
+            max_consecutive_lost_pings: 3,
+            header_inbound_query_queue: InboundQueryQueueConfig::default(),
+            state_diff_inbound_query_queue: InboundQueryQueueConfig::default(),
+            transaction_inbound_query_queue: InboundQueryQueueConfig::default(),
         }
     }
 }
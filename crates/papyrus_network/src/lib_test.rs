@@ -0,0 +1,199 @@
+use std::time::Duration;
+
+use libp2p::{PeerId, StreamProtocol};
+use serde::de::value::StrDeserializer;
+use starknet_api::core::ChainId;
+use validator::Validate;
+
+use crate::bin_utils::build_swarm;
+use crate::{
+    deserialize_peer_ids,
+    serialize_peer_ids,
+    NetworkConfig,
+    Protocol,
+    ProtocolConversionError,
+    ProtocolNames,
+};
+
+#[test]
+fn chain_scoped_protocol_name() {
+    assert_eq!(
+        Protocol::SignedBlockHeader.chain_scoped_name(&ChainId::Sepolia, 1).as_ref(),
+        "/starknet/SN_SEPOLIA/headers/1"
+    );
+}
+
+#[test]
+fn protocol_names_round_trip() {
+    let protocol_names = ProtocolNames::new(ChainId::Sepolia, false);
+    for protocol in enum_iterator::all::<Protocol>() {
+        let stream_protocol = protocol_names.stream_protocol(protocol);
+        assert_eq!(protocol_names.protocol(&stream_protocol), Ok(protocol));
+        assert_eq!(Protocol::try_from(stream_protocol), Ok(protocol));
+    }
+}
+
+#[test]
+fn protocol_name_of_another_chain_is_rejected() {
+    let protocol_names = ProtocolNames::new(ChainId::Sepolia, true);
+    let stream_protocol = Protocol::StateDiff.chain_scoped_name(&ChainId::Mainnet, 1);
+    assert_eq!(
+        protocol_names.protocol(&stream_protocol),
+        Err(ProtocolConversionError::WrongChain {
+            protocol_name: stream_protocol.to_string(),
+            chain_id: ChainId::Mainnet.to_string(),
+            expected_chain_id: ChainId::Sepolia,
+        })
+    );
+    // Parsing without a chain still recognizes the protocol.
+    assert_eq!(Protocol::try_from(stream_protocol), Ok(Protocol::StateDiff));
+}
+
+#[test]
+fn legacy_protocol_names_are_supported_only_if_advertised() {
+    let legacy_name: StreamProtocol = Protocol::Transaction.into();
+
+    let protocol_names = ProtocolNames::new(ChainId::Sepolia, true);
+    assert!(protocol_names.inbound_stream_protocols(Protocol::Transaction).contains(&legacy_name));
+    assert_eq!(protocol_names.protocol(&legacy_name), Ok(Protocol::Transaction));
+
+    let protocol_names = ProtocolNames::new(ChainId::Sepolia, false);
+    assert_eq!(
+        protocol_names.inbound_stream_protocols(Protocol::Transaction),
+        vec![protocol_names.stream_protocol(Protocol::Transaction)]
+    );
+    assert_eq!(
+        protocol_names.protocol(&legacy_name),
+        Err(ProtocolConversionError::LegacyProtocolName(legacy_name.to_string()))
+    );
+}
+
+#[test]
+fn protocol_and_version_of_negotiated_name() {
+    let protocol_names = ProtocolNames::new(ChainId::Sepolia, true);
+    for protocol in enum_iterator::all::<Protocol>() {
+        for version in protocol.supported_versions() {
+            let stream_protocol = protocol.chain_scoped_name(&ChainId::Sepolia, *version);
+            assert_eq!(
+                protocol_names.protocol_and_version(&stream_protocol),
+                Ok((protocol, *version))
+            );
+        }
+        assert_eq!(protocol_names.protocol_and_version(&protocol.into()), Ok((protocol, 1)));
+    }
+}
+
+#[test]
+fn disabled_versions_are_neither_proposed_nor_answered() {
+    let protocol = Protocol::SignedBlockHeader;
+    let protocol_names = ProtocolNames::new(ChainId::Sepolia, true);
+    let all_names = protocol_names.inbound_stream_protocols(protocol);
+
+    // Disabling a version the protocol doesn't support changes nothing.
+    let protocol_names = protocol_names.with_disabled_versions(vec![2]);
+    assert_eq!(protocol_names.inbound_stream_protocols(protocol), all_names);
+
+    let protocol_names = protocol_names.with_disabled_versions(vec![1]);
+    assert!(protocol_names.versions(protocol).is_empty());
+    assert!(protocol_names.outbound_stream_protocols(protocol).is_empty());
+    assert!(protocol_names.inbound_stream_protocols(protocol).is_empty());
+}
+
+#[test]
+fn disabling_all_the_versions_of_a_protocol_is_invalid() {
+    let config = NetworkConfig { disabled_protocol_versions: vec![2], ..Default::default() };
+    assert!(config.validate().is_ok());
+
+    let config = NetworkConfig { disabled_protocol_versions: vec![1], ..Default::default() };
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn unknown_protocol_name_is_rejected() {
+    for name in ["/starknet/blocks/1", "/starknet/SN_SEPOLIA/blocks/1", "/other/headers/1"] {
+        assert_eq!(
+            Protocol::try_from(StreamProtocol::new(name)),
+            Err(ProtocolConversionError::UnknownProtocol(name.to_string()))
+        );
+    }
+}
+
+#[tokio::test]
+async fn peer_id_matches_the_peer_id_of_the_swarm() {
+    let mut config = NetworkConfig::default();
+    assert_eq!(config.peer_id(), None);
+
+    let secret_key = vec![7; 32];
+    config.set_secret_key(secret_key.clone());
+    let swarm = build_swarm(Vec::new(), Duration::from_secs(1), Some(secret_key), |_| {
+        libp2p::swarm::dummy::Behaviour
+    });
+    assert_eq!(config.peer_id(), Some(*swarm.local_peer_id()));
+}
+
+#[test]
+fn peer_ids_round_trip() {
+    let peer_ids = vec![PeerId::random(), PeerId::random()];
+    let serialized = serialize_peer_ids(&peer_ids);
+    let deserializer = StrDeserializer::<serde::de::value::Error>::new(&serialized);
+    assert_eq!(deserialize_peer_ids(deserializer).unwrap(), peer_ids);
+
+    let deserializer = StrDeserializer::<serde::de::value::Error>::new("");
+    assert_eq!(deserialize_peer_ids(deserializer).unwrap(), vec![]);
+
+    let deserializer = StrDeserializer::<serde::de::value::Error>::new("not_a_peer_id");
+    assert!(deserialize_peer_ids(deserializer).is_err());
+}
+
+#[test]
+fn restricting_inbound_connections_requires_allowed_peers() {
+    let config = NetworkConfig { restrict_inbound_to_allowed_peers: true, ..Default::default() };
+    assert!(config.validate().is_err());
+
+    let config = NetworkConfig { allowed_peers: vec![PeerId::random()], ..config };
+    assert!(config.validate().is_ok());
+}
+
+#[test]
+fn bootstrap_peer_multiaddr_validation() {
+    let peer_id = PeerId::random();
+    let valid_multiaddrs = [
+        format!("/ip4/127.0.0.1/tcp/10000/p2p/{peer_id}"),
+        format!("/ip6/::1/tcp/10000/p2p/{peer_id}"),
+        format!("/dns/bootstrap.example.com/tcp/10000/p2p/{peer_id}"),
+        format!("/dns4/bootstrap.example.com/tcp/10000/p2p/{peer_id}"),
+        format!("/dns6/bootstrap.example.com/tcp/10000/p2p/{peer_id}"),
+    ];
+    for multiaddr in valid_multiaddrs {
+        let config = NetworkConfig {
+            bootstrap_peer_multiaddr: Some(multiaddr.parse().unwrap()),
+            ..Default::default()
+        };
+        assert!(config.validate().is_ok(), "{multiaddr} should be valid");
+    }
+
+    let invalid_multiaddrs = [
+        ("/ip4/127.0.0.1/tcp/10000".to_owned(), "/p2p/<peer id>"),
+        (format!("/p2p/{peer_id}"), "should start with the host"),
+        (format!("/tcp/10000/p2p/{peer_id}"), "should start with the host"),
+        (format!("/dnsaddr/bootstrap.example.com/p2p/{peer_id}"), "/dnsaddr"),
+        (format!("/ip4/127.0.0.1/p2p/{peer_id}"), "/tcp/<port>"),
+        (format!("/ip4/127.0.0.1/tcp/0/p2p/{peer_id}"), "port of the address is 0"),
+        (format!("/ip4/127.0.0.1/udp/10000/quic-v1/p2p/{peer_id}"), "QUIC"),
+        (format!("/ip4/127.0.0.1/tcp/10000/ws/p2p/{peer_id}"), "/ws isn't supported"),
+        (format!("/ip4/127.0.0.1/tcp/10000/p2p/{peer_id}/p2p/{peer_id}"), "after the peer id"),
+        (String::new(), "should start with the host"),
+    ];
+    for (multiaddr, expected_message) in invalid_multiaddrs {
+        let config = NetworkConfig {
+            bootstrap_peer_multiaddr: Some(multiaddr.parse().unwrap()),
+            ..Default::default()
+        };
+        let errors = config.validate().expect_err(&format!("{multiaddr} should be invalid"));
+        let message = errors.field_errors()["bootstrap_peer_multiaddr"][0].message.clone().unwrap();
+        assert!(
+            message.contains(expected_message),
+            "Unexpected message for {multiaddr}: {message}"
+        );
+    }
+}
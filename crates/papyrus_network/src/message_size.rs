@@ -0,0 +1,206 @@
+//! Metrics of the sizes of the messages the node sends and receives, for tuning the message size
+//! limits. A message whose size gets close to its limit is warned about, at most once a minute for
+//! each topic or protocol and direction, so that the limit can be raised before messages start
+//! being rejected.
+
+#[cfg(test)]
+#[path = "message_size_test.rs"]
+mod message_size_test;
+
+use std::collections::HashMap;
+use std::io;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+use lazy_static::lazy_static;
+use libp2p::gossipsub;
+use libp2p::swarm::StreamProtocol;
+use metrics::{histogram, increment_counter};
+use papyrus_common::metrics as papyrus_metrics;
+use tracing::warn;
+
+use crate::sqmr::messages::{as_message_too_large, MAX_MESSAGE_SIZE};
+
+const NEAR_LIMIT_WARNING_INTERVAL: Duration = Duration::from_secs(60);
+const DEFAULT_NEAR_LIMIT_FRACTION: f64 = 0.9;
+
+lazy_static! {
+    // The gossipsub behaviour is created with the default config.
+    static ref GOSSIPSUB_MAX_TRANSMIT_SIZE: usize = gossipsub::Config::default().max_transmit_size();
+    static ref NEAR_LIMIT_WARNINGS: Mutex<NearLimitWarnings> =
+        Mutex::new(NearLimitWarnings::new(DEFAULT_NEAR_LIMIT_FRACTION, NEAR_LIMIT_WARNING_INTERVAL));
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum Transport {
+    Gossipsub,
+    Sqmr,
+}
+
+impl Transport {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Transport::Gossipsub => "gossipsub",
+            Transport::Sqmr => "sqmr",
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum MessageDirection {
+    Sent,
+    Received,
+}
+
+impl MessageDirection {
+    fn as_str(&self) -> &'static str {
+        match self {
+            MessageDirection::Sent => "sent",
+            MessageDirection::Received => "received",
+        }
+    }
+}
+
+// The messages of a topic or a protocol in one direction, whose sizes are measured together.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct MessageChannel {
+    pub transport: Transport,
+    // The name of the gossipsub topic or of the sqmr protocol.
+    pub name: String,
+    pub direction: MessageDirection,
+}
+
+impl MessageChannel {
+    pub fn new(transport: Transport, name: impl Into<String>, direction: MessageDirection) -> Self {
+        Self { transport, name: name.into(), direction }
+    }
+}
+
+pub(crate) struct NearLimitWarnings {
+    // The fraction of the limit above which a message is near the limit.
+    near_limit_fraction: f64,
+    interval: Duration,
+    last_warning_times: HashMap<MessageChannel, Instant>,
+}
+
+impl NearLimitWarnings {
+    pub fn new(near_limit_fraction: f64, interval: Duration) -> Self {
+        Self { near_limit_fraction, interval, last_warning_times: HashMap::new() }
+    }
+
+    // Whether a message of the given size should be warned about. It should if it's near the limit
+    // and no message of the channel was warned about in the last interval.
+    pub fn should_warn(
+        &mut self,
+        channel: &MessageChannel,
+        size: usize,
+        limit: usize,
+        now: Instant,
+    ) -> bool {
+        if (size as f64) < self.near_limit_fraction * limit as f64 {
+            return false;
+        }
+        match self.last_warning_times.get(channel) {
+            Some(last_warning_time) if now.duration_since(*last_warning_time) < self.interval => {
+                false
+            }
+            _ => {
+                self.last_warning_times.insert(channel.clone(), now);
+                true
+            }
+        }
+    }
+}
+
+/// Sets the fraction of the size limit of a message above which the message is warned about.
+pub(crate) fn set_near_limit_fraction(near_limit_fraction: f64) {
+    NEAR_LIMIT_WARNINGS
+        .lock()
+        .expect("The near limit warnings lock is poisoned")
+        .near_limit_fraction = near_limit_fraction;
+}
+
+// Records the size of a message that was sent or received within its limit.
+fn record_message_size(channel: MessageChannel, size: usize, limit: usize) {
+    histogram!(
+        papyrus_metrics::PAPYRUS_NETWORK_MESSAGE_SIZE_BYTES,
+        size as f64,
+        "transport" => channel.transport.as_str(),
+        "name" => channel.name.clone(),
+        "direction" => channel.direction.as_str()
+    );
+    warn_if_near_limit(&channel, Some(size), limit);
+}
+
+// Records a message that was rejected since it exceeded its limit. The size is None if the message
+// was rejected before it was read.
+fn record_oversized_message(channel: MessageChannel, size: Option<usize>, limit: usize) {
+    increment_counter!(
+        papyrus_metrics::PAPYRUS_NETWORK_OVERSIZED_MESSAGES,
+        "transport" => channel.transport.as_str(),
+        "name" => channel.name.clone(),
+        "direction" => channel.direction.as_str()
+    );
+    warn_if_near_limit(&channel, size, limit);
+}
+
+/// Records an sqmr message of the given protocol by the result of its size. Reading a message fails
+/// with an oversize error if it exceeds the limit, and a written message that exceeds it will be
+/// rejected by the peer.
+pub(crate) fn record_sqmr_message(
+    protocol_name: &StreamProtocol,
+    direction: MessageDirection,
+    size: Result<usize, &io::Error>,
+) {
+    let channel = MessageChannel::new(Transport::Sqmr, protocol_name.as_ref(), direction);
+    match size {
+        Ok(size) if size > MAX_MESSAGE_SIZE => {
+            record_oversized_message(channel, Some(size), MAX_MESSAGE_SIZE)
+        }
+        Ok(size) => record_message_size(channel, size, MAX_MESSAGE_SIZE),
+        Err(error) => {
+            if let Some(message_too_large_error) = as_message_too_large(error) {
+                record_oversized_message(channel, message_too_large_error.size, MAX_MESSAGE_SIZE);
+            }
+        }
+    }
+}
+
+/// Records a gossipsub message of the given topic that was published or received.
+pub(crate) fn record_gossipsub_message(topic_name: &str, direction: MessageDirection, size: usize) {
+    let channel = MessageChannel::new(Transport::Gossipsub, topic_name, direction);
+    record_message_size(channel, size, *GOSSIPSUB_MAX_TRANSMIT_SIZE);
+}
+
+/// Records a gossipsub message of the given topic that wasn't published since it's too large.
+pub(crate) fn record_oversized_gossipsub_message(topic_name: &str, size: usize) {
+    let channel = MessageChannel::new(Transport::Gossipsub, topic_name, MessageDirection::Sent);
+    record_oversized_message(channel, Some(size), *GOSSIPSUB_MAX_TRANSMIT_SIZE);
+}
+
+// The size is None if the message exceeded the limit before it was read.
+fn warn_if_near_limit(channel: &MessageChannel, size: Option<usize>, limit: usize) {
+    let should_warn = NEAR_LIMIT_WARNINGS
+        .lock()
+        .expect("The near limit warnings lock is poisoned")
+        .should_warn(channel, size.unwrap_or(limit.saturating_add(1)), limit, Instant::now());
+    if !should_warn {
+        return;
+    }
+    let size_description = match size {
+        Some(size) => format!("of {size} bytes"),
+        None => "larger than the limit".to_string(),
+    };
+    let channel_kind = match channel.transport {
+        Transport::Gossipsub => "topic",
+        Transport::Sqmr => "protocol",
+    };
+    warn!(
+        "A {} message {size_description} was {} on {channel_kind} {}, whose size limit is {limit} \
+         bytes. Further messages near the limit aren't reported for {} seconds.",
+        channel.transport.as_str(),
+        channel.direction.as_str(),
+        channel.name,
+        NEAR_LIMIT_WARNING_INTERVAL.as_secs(),
+    );
+}
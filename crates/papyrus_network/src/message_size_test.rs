@@ -0,0 +1,50 @@
+use std::time::{Duration, Instant};
+
+use crate::message_size::{MessageChannel, MessageDirection, NearLimitWarnings, Transport};
+
+const LIMIT: usize = 1000;
+const NEAR_LIMIT_FRACTION: f64 = 0.9;
+const INTERVAL: Duration = Duration::from_secs(60);
+
+fn channel(name: &str) -> MessageChannel {
+    MessageChannel::new(Transport::Sqmr, name, MessageDirection::Received)
+}
+
+#[test]
+fn flood_of_near_limit_messages_warns_once_per_interval() {
+    let mut warnings = NearLimitWarnings::new(NEAR_LIMIT_FRACTION, INTERVAL);
+    let start = Instant::now();
+
+    let n_warnings = (0..100)
+        .filter(|i| {
+            warnings.should_warn(&channel("a"), LIMIT - 1, LIMIT, start + Duration::from_millis(*i))
+        })
+        .count();
+    assert_eq!(n_warnings, 1);
+
+    // Once the interval passed since the last warning, the next message is warned about again.
+    assert!(!warnings.should_warn(&channel("a"), LIMIT, LIMIT, start + INTERVAL / 2));
+    assert!(warnings.should_warn(&channel("a"), LIMIT, LIMIT, start + INTERVAL));
+    assert!(!warnings.should_warn(&channel("a"), LIMIT, LIMIT, start + INTERVAL * 3 / 2));
+}
+
+#[test]
+fn only_near_limit_messages_are_warned_about() {
+    let mut warnings = NearLimitWarnings::new(NEAR_LIMIT_FRACTION, INTERVAL);
+    let now = Instant::now();
+
+    assert!(!warnings.should_warn(&channel("a"), LIMIT * 9 / 10 - 1, LIMIT, now));
+    assert!(warnings.should_warn(&channel("a"), LIMIT * 9 / 10, LIMIT, now));
+}
+
+#[test]
+fn channels_are_warned_about_separately() {
+    let mut warnings = NearLimitWarnings::new(NEAR_LIMIT_FRACTION, INTERVAL);
+    let now = Instant::now();
+
+    assert!(warnings.should_warn(&channel("a"), LIMIT, LIMIT, now));
+    assert!(warnings.should_warn(&channel("b"), LIMIT, LIMIT, now));
+    let sent_channel = MessageChannel::new(Transport::Sqmr, "a", MessageDirection::Sent);
+    assert!(warnings.should_warn(&sent_channel, LIMIT, LIMIT, now));
+    assert!(!warnings.should_warn(&channel("a"), LIMIT, LIMIT, now));
+}
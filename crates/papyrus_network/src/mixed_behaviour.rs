@@ -1,24 +1,30 @@
 // TODO(shahak): Erase main_behaviour and make this a separate module.
 
+use std::time::Duration;
+
 use libp2p::identity::Keypair;
 use libp2p::kad::store::MemoryStore;
 use libp2p::swarm::behaviour::toggle::Toggle;
 use libp2p::swarm::dial_opts::DialOpts;
 use libp2p::swarm::NetworkBehaviour;
-use libp2p::{gossipsub, identify, kad, Multiaddr, PeerId};
+use libp2p::{gossipsub, identify, kad, ping, Multiaddr, PeerId};
 
 use crate::discovery::identify_impl::{IdentifyToOtherBehaviourEvent, IDENTIFY_PROTOCOL_VERSION};
 use crate::discovery::kad_impl::KadToOtherBehaviourEvent;
 use crate::peer_manager::PeerManagerConfig;
-use crate::{discovery, gossipsub_impl, peer_manager, sqmr};
+use crate::ping_impl::PingToOtherBehaviourEvent;
+use crate::{connection_gating, discovery, gossipsub_impl, peer_manager, sqmr};
 
 // TODO: consider reducing the pulicity of all behaviour to pub(crate)
 #[derive(NetworkBehaviour)]
 #[behaviour(out_event = "Event")]
 pub struct MixedBehaviour {
+    // Should be first so that denied connections aren't handled by the other behaviours.
+    pub connection_gating: connection_gating::Behaviour,
     pub peer_manager: peer_manager::PeerManager<peer_manager::peer::Peer>,
     pub discovery: Toggle<discovery::Behaviour>,
     pub identify: identify::Behaviour,
+    pub ping: ping::Behaviour,
     // TODO(shahak): Consider using a different store.
     pub kademlia: kad::Behaviour<MemoryStore>,
     pub sqmr: sqmr::Behaviour,
@@ -41,12 +47,43 @@ pub enum ExternalEvent {
 pub enum ToOtherBehaviourEvent {
     NoOp,
     Identify(IdentifyToOtherBehaviourEvent),
+    Ping(PingToOtherBehaviourEvent),
     Kad(KadToOtherBehaviourEvent),
     Discovery(discovery::ToOtherBehaviourEvent),
     PeerManager(peer_manager::ToOtherBehaviourEvent),
     Sqmr(sqmr::ToOtherBehaviourEvent),
 }
 
+/// The peers this node connects to. See the fields of the same names in [`NetworkConfig`].
+///
+/// [`NetworkConfig`]: crate::NetworkConfig
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+pub struct ConnectionGatingConfig {
+    pub allowed_peers: Vec<PeerId>,
+    pub restrict_inbound_to_allowed_peers: bool,
+    pub denied_peers: Vec<PeerId>,
+}
+
+/// How the connected peers are pinged. See the fields of the same names in [`NetworkConfig`].
+///
+/// [`NetworkConfig`]: crate::NetworkConfig
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct PingConfig {
+    pub ping_interval: Duration,
+    pub max_consecutive_lost_pings: u32,
+}
+
+impl Default for PingConfig {
+    fn default() -> Self {
+        Self { ping_interval: Duration::from_secs(15), max_consecutive_lost_pings: 3 }
+    }
+}
+
+// Each ping is a 32 bytes payload that the peer echoes, so the traffic of the pings is negligible.
+pub(crate) fn ping_behaviour(ping_interval: Duration) -> ping::Behaviour {
+    ping::Behaviour::new(ping::Config::new().with_interval(ping_interval))
+}
+
 pub trait BridgedBehaviour {
     fn on_other_behaviour_event(&mut self, event: &ToOtherBehaviourEvent);
 }
@@ -58,25 +95,48 @@ impl MixedBehaviour {
         keypair: Keypair,
         bootstrap_peer_multiaddr: Option<Multiaddr>,
         streamed_bytes_config: sqmr::Config,
+        block_range_advertisement_ttl: Duration,
+        peer_dial_deadline: Duration,
+        max_outbound_sessions_per_peer: usize,
+        connection_gating_config: ConnectionGatingConfig,
+        ping_config: PingConfig,
     ) -> Self {
         let public_key = keypair.public();
         let local_peer_id = PeerId::from_public_key(&public_key);
+        let bootstrap_peer_id = bootstrap_peer_multiaddr.as_ref().map(|bootstrap_peer_multiaddr| {
+            DialOpts::from(bootstrap_peer_multiaddr.clone())
+                .get_peer_id()
+                .expect("bootstrap_peer_multiaddr doesn't have a peer id")
+        });
         Self {
-            peer_manager: peer_manager::PeerManager::new(PeerManagerConfig::default()),
+            connection_gating: connection_gating::Behaviour::new(
+                connection_gating_config.allowed_peers,
+                connection_gating_config.restrict_inbound_to_allowed_peers,
+                connection_gating_config.denied_peers,
+                bootstrap_peer_id,
+            ),
+            peer_manager: peer_manager::PeerManager::new(PeerManagerConfig {
+                block_range_advertisement_ttl: chrono::Duration::from_std(
+                    block_range_advertisement_ttl,
+                )
+                .unwrap_or(chrono::Duration::max_value()),
+                bootstrap_peer_id,
+                dial_deadline: peer_dial_deadline,
+                max_sessions_per_peer: max_outbound_sessions_per_peer,
+                max_consecutive_lost_pings: ping_config.max_consecutive_lost_pings,
+                ..Default::default()
+            }),
             discovery: bootstrap_peer_multiaddr
-                .map(|bootstrap_peer_multiaddr| {
-                    discovery::Behaviour::new(
-                        DialOpts::from(bootstrap_peer_multiaddr.clone())
-                            .get_peer_id()
-                            .expect("bootstrap_peer_multiaddr doesn't have a peer id"),
-                        bootstrap_peer_multiaddr.clone(),
-                    )
+                .zip(bootstrap_peer_id)
+                .map(|(bootstrap_peer_multiaddr, bootstrap_peer_id)| {
+                    discovery::Behaviour::new(bootstrap_peer_id, bootstrap_peer_multiaddr)
                 })
                 .into(),
             identify: identify::Behaviour::new(identify::Config::new(
                 IDENTIFY_PROTOCOL_VERSION.to_string(),
                 public_key,
             )),
+            ping: ping_behaviour(ping_config.ping_interval),
             // TODO: change kademlia protocol name
             kademlia: kad::Behaviour::new(local_peer_id, MemoryStore::new(local_peer_id)),
             sqmr: sqmr::Behaviour::new(streamed_bytes_config),
@@ -0,0 +1,108 @@
+use std::collections::HashMap;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use libp2p::PeerId;
+use metrics::{counter, gauge};
+use papyrus_common::metrics as papyrus_metrics;
+
+// A bucket that fills at a constant rate up to a second's worth of bytes. Taking more bytes than
+// it holds leaves it in debt, and the bytes are sent once the debt is repaid, so a large response
+// is delayed instead of being dropped or starving smaller ones.
+struct TokenBucket {
+    bytes_per_sec: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(bytes_per_sec: u64, now: Instant) -> Self {
+        let bytes_per_sec = bytes_per_sec as f64;
+        Self { bytes_per_sec, tokens: bytes_per_sec, last_refill: now }
+    }
+
+    fn refill(&mut self, now: Instant) {
+        let elapsed = now.saturating_duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.bytes_per_sec).min(self.bytes_per_sec);
+        self.last_refill = now;
+    }
+
+    // Returns how long to wait before the bytes can be sent.
+    fn take(&mut self, num_bytes: u64, now: Instant) -> Duration {
+        self.refill(now);
+        self.tokens -= num_bytes as f64;
+        if self.tokens >= 0.0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs_f64(-self.tokens / self.bytes_per_sec)
+    }
+
+    fn is_full(&self) -> bool {
+        self.tokens >= self.bytes_per_sec
+    }
+}
+
+struct BandwidthThrottleInner {
+    // None if the total bandwidth isn't limited.
+    total: Option<TokenBucket>,
+    // 0 if the bandwidth of each peer isn't limited.
+    max_bytes_per_sec_per_peer: u64,
+    per_peer: HashMap<PeerId, TokenBucket>,
+}
+
+/// Limits the bandwidth of the responses this node sends to inbound queries, in total and for each
+/// peer. Broadcasted messages aren't limited, so serving peers never delays the consensus messages.
+#[derive(Clone)]
+pub(crate) struct BandwidthThrottle {
+    inner: Arc<Mutex<BandwidthThrottleInner>>,
+}
+
+impl BandwidthThrottle {
+    /// A limit of 0 means the bandwidth isn't limited.
+    pub fn new(max_bytes_per_sec: u64, max_bytes_per_sec_per_peer: u64) -> Self {
+        let total =
+            (max_bytes_per_sec > 0).then(|| TokenBucket::new(max_bytes_per_sec, Instant::now()));
+        Self {
+            inner: Arc::new(Mutex::new(BandwidthThrottleInner {
+                total,
+                max_bytes_per_sec_per_peer,
+                per_peer: HashMap::new(),
+            })),
+        }
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        let inner = self.inner.lock().expect("Bandwidth throttle lock should not be poisoned");
+        inner.total.is_some() || inner.max_bytes_per_sec_per_peer > 0
+    }
+
+    /// Counts a response of `num_bytes` bytes sent to the peer, and returns how long to wait before
+    /// sending it so that the limits are kept.
+    pub fn reserve(&self, peer_id: PeerId, num_bytes: usize) -> Duration {
+        let num_bytes = num_bytes as u64;
+        let now = Instant::now();
+        let mut inner = self.inner.lock().expect("Bandwidth throttle lock should not be poisoned");
+        let mut delay =
+            inner.total.as_mut().map_or(Duration::ZERO, |total| total.take(num_bytes, now));
+        if inner.max_bytes_per_sec_per_peer > 0 {
+            let max_bytes_per_sec_per_peer = inner.max_bytes_per_sec_per_peer;
+            // The buckets of peers that didn't get responses for a second are full, so they're
+            // removed instead of kept for every peer that ever queried the node.
+            inner.per_peer.retain(|_, bucket| {
+                bucket.refill(now);
+                !bucket.is_full()
+            });
+            let peer_delay = inner
+                .per_peer
+                .entry(peer_id)
+                .or_insert_with(|| TokenBucket::new(max_bytes_per_sec_per_peer, now))
+                .take(num_bytes, now);
+            delay = delay.max(peer_delay);
+        }
+        if !delay.is_zero() {
+            counter!(papyrus_metrics::PAPYRUS_NETWORK_THROTTLED_BYTES, num_bytes);
+        }
+        gauge!(papyrus_metrics::PAPYRUS_NETWORK_THROTTLE_DELAY_SECS, delay.as_secs_f64());
+        delay
+    }
+}
@@ -0,0 +1,107 @@
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+
+use chrono::Utc;
+use libp2p::PeerId;
+use serde::Serialize;
+use tracing::{trace, Level};
+
+/// The target of the traces of the network events. Setting its level to trace in the dynamic log
+/// level records the events even if `debug_events` isn't set in the network config.
+pub const NETWORK_EVENTS_TRACING_TARGET: &str = "papyrus_network::network_events";
+
+/// The last events the network manager recorded, oldest first.
+pub type RecentNetworkEvents = Arc<Mutex<VecDeque<NetworkEvent>>>;
+
+/// An event of the swarm, recorded for debugging connection issues.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct NetworkEvent {
+    /// Increases by one with each recorded event, so gaps are events that were dropped from the
+    /// buffer.
+    pub sequence_number: u64,
+    /// The time the event was recorded at, in RFC 3339 format.
+    pub time: String,
+    pub peer_id: Option<String>,
+    pub kind: NetworkEventKind,
+    pub details: String,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize)]
+pub enum NetworkEventKind {
+    Dialing,
+    DialFailed,
+    IncomingConnectionFailed,
+    ConnectionDenied,
+    ConnectionEstablished,
+    ConnectionClosed,
+    ListenerClosed,
+    IdentifyReceived,
+    KadRoutingUpdated,
+    GossipsubPeerSubscribed,
+    GossipsubPeerUnsubscribed,
+    SqmrInboundSessionStarted,
+    SqmrOutboundSessionStarted,
+    SqmrOutboundSessionAssigned,
+    SqmrSessionFailed,
+    SqmrSessionFinished,
+}
+
+pub(crate) struct NetworkEventLog {
+    is_enabled_by_config: bool,
+    buffer_size: usize,
+    next_sequence_number: u64,
+    // Shared so that it can be read while the swarm is running.
+    recent_events: RecentNetworkEvents,
+}
+
+impl NetworkEventLog {
+    pub(crate) fn new(is_enabled_by_config: bool, buffer_size: usize) -> Self {
+        Self {
+            is_enabled_by_config,
+            buffer_size,
+            next_sequence_number: 0,
+            recent_events: Arc::new(Mutex::new(VecDeque::new())),
+        }
+    }
+
+    pub(crate) fn recent_events(&self) -> RecentNetworkEvents {
+        self.recent_events.clone()
+    }
+
+    fn is_enabled(&self) -> bool {
+        self.is_enabled_by_config
+            || tracing::enabled!(target: NETWORK_EVENTS_TRACING_TARGET, Level::TRACE)
+    }
+
+    /// Traces the event and adds it to the recent events, dropping the oldest event if the buffer
+    /// is full. The details are only formatted if the log is enabled.
+    pub(crate) fn record(
+        &mut self,
+        kind: NetworkEventKind,
+        peer_id: Option<PeerId>,
+        details: impl FnOnce() -> String,
+    ) {
+        if !self.is_enabled() {
+            return;
+        }
+        let sequence_number = self.next_sequence_number;
+        self.next_sequence_number += 1;
+        let peer_id = peer_id.map(|peer_id| peer_id.to_string());
+        let details = details();
+        trace!(
+            target: NETWORK_EVENTS_TRACING_TARGET,
+            sequence_number,
+            peer_id = peer_id.as_deref().unwrap_or_default(),
+            ?kind,
+            "{details}"
+        );
+        let event =
+            NetworkEvent { sequence_number, time: Utc::now().to_rfc3339(), peer_id, kind, details };
+        let mut recent_events =
+            self.recent_events.lock().expect("Recent network events lock should not be poisoned");
+        if recent_events.len() >= self.buffer_size {
+            recent_events.pop_front();
+        }
+        recent_events.push_back(event);
+    }
+}
@@ -0,0 +1,100 @@
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Arc;
+
+use metrics::gauge;
+use papyrus_common::metrics as papyrus_metrics;
+use tokio::sync::Notify;
+
+// The bytes counted for each buffered item on top of its data, which cover the callbacks, the
+// channel slot and the allocator's overhead. It's larger than their actual size, so that the
+// accounting overestimates the memory in use.
+pub(crate) const ITEM_OVERHEAD_BYTES: u64 = 256;
+
+struct MemoryBudgetInner {
+    max_bytes: u64,
+    used_bytes: AtomicU64,
+    // Notified whenever memory is released.
+    released: Notify,
+}
+
+/// An approximate count of the memory of the items the network buffers for the node's components,
+/// shared by all of them. An item registers its size when it's buffered and holds a
+/// [`MemoryGuard`] that releases it once the component takes the item from its channel.
+///
+/// Registering always succeeds, so the usage can exceed `max_bytes` by the items that were already
+/// read from the network. Once the budget is exhausted, the network manager stops reading from the
+/// network until enough memory is released.
+#[derive(Clone)]
+pub(crate) struct MemoryBudget {
+    inner: Arc<MemoryBudgetInner>,
+}
+
+impl MemoryBudget {
+    pub fn new(max_bytes: u64) -> Self {
+        gauge!(papyrus_metrics::PAPYRUS_NETWORK_MEMORY_USAGE_BYTES, 0f64);
+        Self {
+            inner: Arc::new(MemoryBudgetInner {
+                max_bytes,
+                used_bytes: AtomicU64::new(0),
+                released: Notify::new(),
+            }),
+        }
+    }
+
+    /// Registers an item of `num_bytes` bytes, until the returned guard is dropped.
+    pub fn reserve(&self, num_bytes: usize) -> MemoryGuard {
+        let num_bytes = num_bytes as u64 + ITEM_OVERHEAD_BYTES;
+        let used_bytes = self.inner.used_bytes.fetch_add(num_bytes, Ordering::SeqCst) + num_bytes;
+        gauge!(papyrus_metrics::PAPYRUS_NETWORK_MEMORY_USAGE_BYTES, used_bytes as f64);
+        MemoryGuard { budget: Some(self.inner.clone()), num_bytes }
+    }
+
+    pub fn used_bytes(&self) -> u64 {
+        self.inner.used_bytes.load(Ordering::SeqCst)
+    }
+
+    pub fn is_exhausted(&self) -> bool {
+        self.used_bytes() >= self.inner.max_bytes
+    }
+
+    /// Returns once the budget isn't exhausted.
+    pub async fn wait_for_capacity(&self) {
+        loop {
+            // Created before the check, so that a release between the check and the wait isn't
+            // missed.
+            let released = self.inner.released.notified();
+            if !self.is_exhausted() {
+                return;
+            }
+            released.await;
+        }
+    }
+}
+
+/// The registration of a buffered item in the network's [`MemoryBudget`], which is released when
+/// the guard is dropped.
+pub struct MemoryGuard {
+    // None for items that aren't counted.
+    budget: Option<Arc<MemoryBudgetInner>>,
+    num_bytes: u64,
+}
+
+impl MemoryGuard {
+    /// A guard of an item that isn't counted in any budget, such as an item that a test injects.
+    #[cfg(feature = "testing")]
+    pub(crate) fn untracked() -> Self {
+        Self { budget: None, num_bytes: 0 }
+    }
+}
+
+impl Drop for MemoryGuard {
+    fn drop(&mut self) {
+        let Some(budget) = self.budget.take() else {
+            return;
+        };
+        let used_bytes =
+            budget.used_bytes.fetch_sub(self.num_bytes, Ordering::SeqCst) - self.num_bytes;
+        gauge!(papyrus_metrics::PAPYRUS_NETWORK_MEMORY_USAGE_BYTES, used_bytes as f64);
+        budget.released.notify_waiters();
+    }
+}
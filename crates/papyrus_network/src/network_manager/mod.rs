@@ -1,193 +1,651 @@
+mod bandwidth_throttle;
+mod event_log;
+mod memory_budget;
+mod outbound_query_queue;
 mod swarm_trait;
 
 #[cfg(test)]
 mod test;
 
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::convert::Infallible;
+use std::marker::PhantomData;
+use std::ops::Range;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
 
+use enum_iterator::{all, Sequence};
 use futures::channel::mpsc::{Receiver, SendError, Sender, UnboundedReceiver, UnboundedSender};
-use futures::future::{ready, Ready};
+use futures::channel::oneshot;
+use futures::future::{poll_fn, ready, Ready};
 use futures::sink::With;
 use futures::stream::{self, BoxStream, Map};
 use futures::{SinkExt, StreamExt};
+use libp2p::core::transport::ListenerId;
 use libp2p::gossipsub::{SubscriptionError, TopicHash};
-use libp2p::swarm::SwarmEvent;
-use libp2p::{PeerId, Swarm};
-use metrics::gauge;
+use libp2p::swarm::{ConnectionDenied, DialError, ListenError, SwarmEvent};
+use libp2p::{Multiaddr, PeerId, StreamProtocol, Swarm};
+use metrics::{gauge, histogram, increment_counter};
 use papyrus_common::metrics as papyrus_metrics;
+use papyrus_protobuf::converters::TryFromVersionedBytes;
+use papyrus_protobuf::sync::{
+    BlockRangeAdvertisement,
+    DataOrFin,
+    HeaderQuery,
+    ProtocolVersion,
+    SignedBlockHeader,
+    StateDiffQuery,
+    TransactionQuery,
+};
+use serde::Serialize;
 use sqmr::Bytes;
-use tracing::{debug, error, info, trace};
+use starknet_api::block::BlockNumber;
+use starknet_api::core::ChainId;
+use tokio::sync::watch;
+use tracing::{debug, error, info, trace, warn};
 
+use self::bandwidth_throttle::BandwidthThrottle;
+use self::event_log::NetworkEventLog;
+pub use self::event_log::{
+    NetworkEvent,
+    NetworkEventKind,
+    RecentNetworkEvents,
+    NETWORK_EVENTS_TRACING_TARGET,
+};
+use self::memory_budget::{MemoryBudget, MemoryGuard};
+use self::outbound_query_queue::OutboundQueryQueue;
 use self::swarm_trait::SwarmTrait;
 use crate::bin_utils::build_swarm;
-use crate::gossipsub_impl::Topic;
+use crate::connection_gating::ConnectionGatingError;
+use crate::discovery::identify_impl::IdentifyToOtherBehaviourEvent;
+use crate::discovery::kad_impl::KadToOtherBehaviourEvent;
+use crate::gossipsub_impl::{Topic, TopicDescriptor, BLOCK_RANGE_ADVERTISEMENT_TOPIC};
+use crate::message_size::{
+    record_gossipsub_message,
+    record_oversized_gossipsub_message,
+    set_near_limit_fraction,
+    MessageDirection,
+};
 use crate::mixed_behaviour::{self, BridgedBehaviour};
+pub use crate::peer_manager::{
+    NegotiatedProtocolsByPeer,
+    PeerIdentities,
+    PeerIdentity,
+    PeerLatencies,
+    PeerLatency,
+    PeerManagerCommand,
+    PeerManagerState,
+    PeerState,
+    ServedBytesByPeer,
+};
 use crate::sqmr::{self, InboundSessionId, OutboundSessionId, SessionId};
 use crate::utils::StreamHashMap;
-use crate::{gossipsub_impl, NetworkConfig, Protocol};
+use crate::{
+    gossipsub_impl,
+    peer_manager,
+    InboundQueryOverloadPolicy,
+    InboundQueryQueueConfig,
+    NetworkConfig,
+    Protocol,
+    ProtocolNames,
+    SessionLimits,
+};
 
+/// An error that stopped the network manager. After a [`Recoverable`](NetworkError::Recoverable)
+/// error the network manager can be [restarted](GenericNetworkManager::restart).
 #[derive(thiserror::Error, Debug)]
 pub enum NetworkError {
+    #[error(transparent)]
+    Recoverable(#[from] RecoverableNetworkError),
+    #[error(transparent)]
+    Fatal(#[from] FatalNetworkError),
+}
+
+impl NetworkError {
+    pub fn is_recoverable(&self) -> bool {
+        matches!(self, Self::Recoverable(_))
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum RecoverableNetworkError {
     #[error(transparent)]
     DialError(#[from] libp2p::swarm::DialError),
+    #[error("Failed to listen on {address} after {num_attempts} attempts: {error}")]
+    ListenFailed { address: Multiaddr, num_attempts: usize, error: String },
 }
 
-pub struct GenericNetworkManager<SwarmT: SwarmTrait> {
-    swarm: SwarmT,
-    header_buffer_size: usize,
-    sqmr_inbound_response_receivers:
-        StreamHashMap<InboundSessionId, BoxStream<'static, Option<Bytes>>>,
-    sqmr_inbound_query_senders: HashMap<Protocol, Sender<(Bytes, Sender<Bytes>)>>,
-    // Splitting the response receivers from the query senders in order to poll all
-    // receivers simultaneously.
-    // Each receiver has a matching sender and vice versa (i.e the maps have the same keys).
-    sqmr_outbound_query_receivers: StreamHashMap<Protocol, Receiver<Bytes>>,
-    sqmr_outbound_response_senders: HashMap<Protocol, Sender<(Bytes, ReportCallback)>>,
-    // Splitting the broadcast receivers from the broadcasted senders in order to poll all
-    // receivers simultaneously.
-    // Each receiver has a matching sender and vice versa (i.e the maps have the same keys).
-    messages_to_broadcast_receivers: StreamHashMap<TopicHash, Receiver<Bytes>>,
-    broadcasted_messages_senders: HashMap<TopicHash, Sender<(Bytes, ReportCallback)>>,
-    outbound_session_id_to_protocol: HashMap<OutboundSessionId, Protocol>,
-    reported_peer_receiver: UnboundedReceiver<PeerId>,
-    // We keep this just for giving a clone of it for subscribers.
-    reported_peer_sender: UnboundedSender<PeerId>,
-    // Fields for metrics
-    num_active_inbound_sessions: usize,
-    num_active_outbound_sessions: usize,
+#[derive(thiserror::Error, Debug)]
+pub enum FatalNetworkError {
+    #[error("The network manager can't be restarted since it has no way of rebuilding its swarm.")]
+    RestartNotSupported,
+    #[error("Failed to subscribe to topic '{topic}' after restarting: {error:?}")]
+    ResubscriptionFailed { topic: String, error: SubscriptionError },
 }
 
-impl<SwarmT: SwarmTrait> GenericNetworkManager<SwarmT> {
-    pub async fn run(mut self) -> Result<(), NetworkError> {
-        loop {
-            tokio::select! {
-                Some(event) = self.swarm.next() => self.handle_swarm_event(event),
-                Some(res) = self.sqmr_inbound_response_receivers.next() => self.handle_response_for_inbound_query(res),
-                Some((protocol, query)) = self.sqmr_outbound_query_receivers.next() => {
-                    self.handle_local_sqmr_query(protocol, query)
-                }
-                Some((topic_hash, message)) = self.messages_to_broadcast_receivers.next() => {
-                    self.broadcast_message(message, topic_hash);
-                }
-                Some(peer_id) = self.reported_peer_receiver.next() => self.swarm.report_peer(peer_id),
-            }
+#[derive(thiserror::Error, Debug)]
+pub enum RegistrationError {
+    #[error("Protocol '{0}' has already been registered as a client.")]
+    ProtocolAlreadyRegisteredAsClient(Protocol),
+    #[error("Protocol '{0}' has already been registered as a server.")]
+    ProtocolAlreadyRegisteredAsServer(Protocol),
+    #[error("Protocol '{0}' hasn't been registered as a client.")]
+    ProtocolNotRegisteredAsClient(Protocol),
+    #[error("Topic '{0}' has already been registered.")]
+    TopicAlreadyRegistered(String),
+    #[error(
+        "Topic '{topic}' has already been registered with message type {registered_type}, so it \
+         can't be registered with message type {requested_type}."
+    )]
+    TopicRegisteredWithDifferentType {
+        topic: String,
+        registered_type: &'static str,
+        requested_type: &'static str,
+    },
+    #[error("Failed subscribing to topic: {0:?}.")]
+    SubscriptionError(SubscriptionError),
+    #[error("The network manager has already been built.")]
+    AlreadyBuilt,
+}
+
+/// The protocols and topics that were registered to a network manager.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct NetworkRegistrations {
+    /// The protocols this node sends queries on, in registration order.
+    pub sqmr_clients: Vec<Protocol>,
+    /// The protocols this node answers queries on, in registration order.
+    pub sqmr_servers: Vec<Protocol>,
+    /// The topics this node broadcasts and receives messages on, in registration order.
+    pub broadcast_topics: Vec<String>,
+}
+
+/// Collects the registrations of the node's components to the network before it starts running.
+/// Conflicting registrations are rejected when they are made, and once [`build`](Self::build) is
+/// called no more registrations are accepted.
+pub struct GenericNetworkManagerBuilder<SwarmT: SwarmTrait> {
+    // None once the network manager was built.
+    network_manager: Option<GenericNetworkManager<SwarmT>>,
+    registrations: NetworkRegistrations,
+    // The message type each registered topic was registered with.
+    broadcast_topic_message_types: HashMap<TopicHash, &'static str>,
+}
+
+impl<SwarmT: SwarmTrait> GenericNetworkManagerBuilder<SwarmT> {
+    pub(crate) fn generic_new(
+        swarm: SwarmT,
+        header_buffer_size: usize,
+        sqmr_subscriber_buffer_size: usize,
+        max_concurrent_outbound_sessions: usize,
+        outbound_query_aging_interval: Duration,
+        protocol_names: ProtocolNames,
+        network_event_log: NetworkEventLog,
+    ) -> Self {
+        Self {
+            network_manager: Some(GenericNetworkManager::generic_new(
+                swarm,
+                header_buffer_size,
+                sqmr_subscriber_buffer_size,
+                max_concurrent_outbound_sessions,
+                outbound_query_aging_interval,
+                protocol_names,
+                network_event_log,
+            )),
+            registrations: NetworkRegistrations::default(),
+            broadcast_topic_message_types: HashMap::new(),
         }
     }
 
-    pub(crate) fn generic_new(swarm: SwarmT, header_buffer_size: usize) -> Self {
-        gauge!(papyrus_metrics::PAPYRUS_NUM_CONNECTED_PEERS, 0f64);
-        let (reported_peer_sender, reported_peer_receiver) = futures::channel::mpsc::unbounded();
-        Self {
-            swarm,
-            header_buffer_size,
-            sqmr_inbound_response_receivers: StreamHashMap::new(HashMap::new()),
-            sqmr_inbound_query_senders: HashMap::new(),
-            sqmr_outbound_query_receivers: StreamHashMap::new(HashMap::new()),
-            sqmr_outbound_response_senders: HashMap::new(),
-            messages_to_broadcast_receivers: StreamHashMap::new(HashMap::new()),
-            broadcasted_messages_senders: HashMap::new(),
-            outbound_session_id_to_protocol: HashMap::new(),
-            reported_peer_sender,
-            reported_peer_receiver,
-            num_active_inbound_sessions: 0,
-            num_active_outbound_sessions: 0,
+    /// Makes the network manager listen on `listen_addresses` once it runs. Listening on an
+    /// address is attempted up to `max_listen_attempts` times before the network manager fails.
+    pub(crate) fn with_listen_addresses(
+        mut self,
+        listen_addresses: Vec<Multiaddr>,
+        max_listen_attempts: usize,
+    ) -> Self {
+        if let Some(network_manager) = self.network_manager.as_mut() {
+            network_manager.listen_addresses = listen_addresses;
+            network_manager.max_listen_attempts = max_listen_attempts;
+        }
+        self
+    }
+
+    /// Limits the pending inbound queries of the protocols in `inbound_query_queues`.
+    pub(crate) fn with_inbound_query_queues(
+        mut self,
+        inbound_query_queues: HashMap<Protocol, InboundQueryQueueConfig>,
+    ) -> Self {
+        if let Some(network_manager) = self.network_manager.as_mut() {
+            network_manager.inbound_query_queues = inbound_query_queues;
         }
+        self
+    }
+
+    /// Limits the sessions in which the node serves the queries of its peers. The sessions above
+    /// the limits are closed right away, so that the peers query other nodes. Without it, the
+    /// inbound sessions aren't limited.
+    pub(crate) fn with_inbound_session_limits(mut self, limits: SessionLimits) -> Self {
+        if let Some(network_manager) = self.network_manager.as_mut() {
+            network_manager.inbound_session_limits = limits;
+        }
+        self
+    }
+
+    /// Limits the memory of the items buffered for the node's components to approximately
+    /// `max_bytes`. Without it, the memory is counted but not limited.
+    pub(crate) fn with_memory_budget(mut self, max_bytes: u64) -> Self {
+        if let Some(network_manager) = self.network_manager.as_mut() {
+            network_manager.memory_budget = MemoryBudget::new(max_bytes);
+        }
+        self
+    }
+
+    /// Limits the responses that wait in the backlog of each lane for its subscriber to take the
+    /// responses before them. Without it, DEFAULT_MAX_LANE_BACKLOG_SIZE responses wait at most.
+    #[cfg(test)]
+    pub(crate) fn with_max_lane_backlog_size(mut self, max_lane_backlog_size: usize) -> Self {
+        if let Some(network_manager) = self.network_manager.as_mut() {
+            network_manager.max_lane_backlog_size = max_lane_backlog_size;
+        }
+        self
+    }
+
+    /// Limits the bandwidth of the responses to inbound queries to `max_bytes_per_sec` in total and
+    /// to `max_bytes_per_sec_per_peer` for each peer. A limit of 0 means no limit.
+    pub(crate) fn with_bandwidth_throttle(
+        mut self,
+        max_bytes_per_sec: u64,
+        max_bytes_per_sec_per_peer: u64,
+    ) -> Self {
+        if let Some(network_manager) = self.network_manager.as_mut() {
+            network_manager.bandwidth_throttle =
+                BandwidthThrottle::new(max_bytes_per_sec, max_bytes_per_sec_per_peer);
+        }
+        self
+    }
+
+    /// Changes the interval at which the connected peers are pinged whenever `ping_interval`
+    /// changes. The new interval applies to the connections that are established after the change.
+    pub fn with_ping_interval_updates(mut self, ping_interval: watch::Receiver<Duration>) -> Self {
+        if let Some(network_manager) = self.network_manager.as_mut() {
+            network_manager.ping_interval_updates = Some(ping_interval);
+        }
+        self
+    }
+
+    /// Lets the network manager be [restarted](GenericNetworkManager::restart) with swarms built by
+    /// `swarm_factory`.
+    pub(crate) fn with_swarm_factory(mut self, swarm_factory: SwarmFactory<SwarmT>) -> Self {
+        if let Some(network_manager) = self.network_manager.as_mut() {
+            network_manager.swarm_factory = Some(swarm_factory);
+        }
+        self
+    }
+
+    /// Returns the network manager, which is ready to run, and the registrations that were made.
+    pub fn build(
+        &mut self,
+    ) -> Result<(GenericNetworkManager<SwarmT>, NetworkRegistrations), RegistrationError> {
+        let network_manager = self.network_manager.take().ok_or(RegistrationError::AlreadyBuilt)?;
+        network_manager.update_session_pools_usage();
+        Ok((network_manager, self.registrations.clone()))
     }
 
+    /// Returns the registrations that were made so far.
+    pub fn registrations(&self) -> &NetworkRegistrations {
+        &self.registrations
+    }
+
+    /// Register a new server for receiving queries and sending multiple responses to each of them.
     pub fn register_sqmr_protocol_server<Query, Response>(
         &mut self,
         protocol: Protocol,
-    ) -> SqmrQueryReceiver<Query, Response>
+    ) -> Result<SqmrQueryReceiver<Query, Response>, RegistrationError>
     where
         Bytes: From<Response>,
         Query: TryFrom<Bytes>,
     {
-        let (inbound_query_sender, inbound_query_receiver) =
-            futures::channel::mpsc::channel(self.header_buffer_size);
-        let result = self.sqmr_inbound_query_senders.insert(protocol, inbound_query_sender);
-        if result.is_some() {
-            panic!("Protocol '{}' has already been registered as a server.", protocol);
-        }
-
-        inbound_query_receiver.map(|(query_bytes, response_bytes_sender)| {
-            (
-                Query::try_from(query_bytes),
-                response_bytes_sender.with(|response| ready(Ok(Bytes::from(response)))),
-            )
-        })
+        let network_manager =
+            self.network_manager.as_mut().ok_or(RegistrationError::AlreadyBuilt)?;
+        if network_manager.sqmr_inbound_query_senders.contains_key(&protocol) {
+            return Err(RegistrationError::ProtocolAlreadyRegisteredAsServer(protocol));
+        }
+
+        // The inbound sessions above the limit are closed before their query reaches the server, so
+        // the server's buffer doesn't need to hold more queries than the limit.
+        let (inbound_query_sender, inbound_query_receiver) = futures::channel::mpsc::channel(
+            network_manager
+                .header_buffer_size
+                .min(network_manager.inbound_session_limits.max_sessions),
+        );
+        network_manager.sqmr_inbound_query_senders.insert(protocol, inbound_query_sender);
+        self.registrations.sqmr_servers.push(protocol);
+
+        // The query is decoded by the server's task when it receives it, and not by the network
+        // manager.
+        let query_fn: ReceivedQueryConverterFn<Query, Response> =
+            |(query_bytes, response_bytes_sender, peer_id, report_callback, _memory_guard)| {
+                let query = Query::try_from(query_bytes);
+                // A malformed query closes its session, by dropping the sender of its responses,
+                // and its peer is reported.
+                let response_bytes_sender = if query.is_err() {
+                    warn!("Closing inbound session from {peer_id:?}: received a malformed query");
+                    report_callback();
+                    futures::channel::mpsc::channel(0).0
+                } else {
+                    response_bytes_sender
+                };
+                (
+                    query,
+                    response_bytes_sender.with(|response| ready(Ok(Bytes::from(response)))),
+                    peer_id,
+                )
+            };
+        Ok(inbound_query_receiver.map(query_fn))
     }
 
     // TODO(shahak): rename to register_sqmr_protocol_client.
     /// Register a new subscriber for sending a single query and receiving multiple responses.
-    /// Panics if the given protocol is already subscribed.
+    /// Queries sent through `query_sender` have [`QueryPriority::Normal`], and queries sent through
+    /// `prioritized_query_sender` have the priority they were sent with.
     pub fn register_sqmr_subscriber<Query, Response>(
         &mut self,
         protocol: Protocol,
-    ) -> SqmrSubscriberChannels<Query, Response>
+    ) -> Result<SqmrSubscriberChannels<Query, Response>, RegistrationError>
+    where
+        Bytes: From<Query>,
+        Response: TryFromVersionedBytes,
+    {
+        let mut lanes = self.register_sqmr_subscriber_lanes(protocol, 1)?;
+        Ok(lanes.pop().expect("A single lane was registered"))
+    }
+
+    /// Same as [`register_sqmr_subscriber`](Self::register_sqmr_subscriber), but returns
+    /// `num_lanes` (at least one) independent channels. The responses of a query are sent only to
+    /// the response receiver of the lane the query was sent on, so each lane can have a query in
+    /// flight without mixing its responses with the responses of the other lanes. A query that is
+    /// identical to the query of another lane that didn't get responses yet isn't sent again, and
+    /// both lanes get copies of the same responses.
+    pub fn register_sqmr_subscriber_lanes<Query, Response>(
+        &mut self,
+        protocol: Protocol,
+        num_lanes: usize,
+    ) -> Result<Vec<SqmrSubscriberChannels<Query, Response>>, RegistrationError>
+    where
+        Bytes: From<Query>,
+        Response: TryFromVersionedBytes,
+    {
+        let response_fn: SqmrResponseConverterFn<Response> =
+            |(response, report_callback, _apply_hints_callback, _memory_guard)| {
+                let response =
+                    response.map_err(SqmrResponseError::Query).and_then(|(x, version)| {
+                        Response::try_from_versioned_bytes(x, version)
+                            .map_err(SqmrResponseError::Conversion)
+                    });
+                (response, report_callback)
+            };
+        self.register_sqmr_subscriber_lanes_with_response_fn(protocol, num_lanes, response_fn)
+    }
+
+    // The responses are decoded with the given function by the subscriber's task when it receives
+    // them, and not by the network manager.
+    fn register_sqmr_subscriber_lanes_with_response_fn<Query, Response>(
+        &mut self,
+        protocol: Protocol,
+        num_lanes: usize,
+        response_fn: SqmrResponseConverterFn<Response>,
+    ) -> Result<Vec<SqmrSubscriberChannels<Query, Response>>, RegistrationError>
     where
         Bytes: From<Query>,
-        Response: TryFrom<Bytes>,
+        Response: TryFromVersionedBytes,
     {
-        // TODO(shahak): Remove header_buffer_size from config and add buffer_size as an argument
-        // to this function.
-        let (query_sender, query_receiver) =
-            futures::channel::mpsc::channel(self.header_buffer_size);
-        let (response_sender, response_receiver) =
-            futures::channel::mpsc::channel(self.header_buffer_size);
+        let network_manager =
+            self.network_manager.as_mut().ok_or(RegistrationError::AlreadyBuilt)?;
+        if self.registrations.sqmr_clients.contains(&protocol) {
+            return Err(RegistrationError::ProtocolAlreadyRegisteredAsClient(protocol));
+        }
+        self.registrations.sqmr_clients.push(protocol);
+
+        let mut lanes = Vec::new();
+        for index in 0..num_lanes.max(1) {
+            let lane = SqmrClientLane { protocol, index };
+            let (query_sender, query_receiver) =
+                futures::channel::mpsc::channel(network_manager.sqmr_subscriber_buffer_size);
+            let (response_sender, response_receiver) =
+                futures::channel::mpsc::channel(network_manager.sqmr_subscriber_buffer_size);
+
+            network_manager.sqmr_outbound_query_receivers.insert(lane, query_receiver);
+            network_manager.sqmr_outbound_response_senders.insert(lane, response_sender);
+
+            let query_fn: QueryConverterFn<Query> =
+                |query| ready(Ok((Bytes::from(query), QueryPriority::Normal)));
+            let prioritized_query_fn: PrioritizedQueryConverterFn<Query> =
+                |(query, priority)| ready(Ok((Bytes::from(query), priority)));
+            let prioritized_query_sender = query_sender.clone().with(prioritized_query_fn);
+            let query_sender = query_sender.with(query_fn);
+
+            let response_receiver = response_receiver.map(response_fn);
+
+            lanes.push(SqmrSubscriberChannels {
+                query_sender,
+                prioritized_query_sender,
+                response_receiver,
+            });
+        }
+        Ok(lanes)
+    }
+
+    /// Same as [`register_sqmr_subscriber`](Self::register_sqmr_subscriber), but the responses are
+    /// also inspected for data availability hints. Queries of other protocols will be sent
+    /// preferably to peers that declared they have the data for that protocol.
+    pub fn register_sqmr_subscriber_with_data_availability_hints<Query, Response>(
+        &mut self,
+        protocol: Protocol,
+    ) -> Result<SqmrSubscriberChannels<Query, Response>, RegistrationError>
+    where
+        Bytes: From<Query>,
+        Response: TryFromVersionedBytes + DataAvailabilityHints,
+    {
+        let response_fn: SqmrResponseConverterFn<Response> =
+            |(response, report_callback, apply_hints_callback, _memory_guard)| {
+                let response =
+                    response.map_err(SqmrResponseError::Query).and_then(|(x, version)| {
+                        Response::try_from_versioned_bytes(x, version)
+                            .map_err(SqmrResponseError::Conversion)
+                    });
+                if let Ok(response) = &response {
+                    apply_hints_callback(response.data_availability_hints());
+                }
+                (response, report_callback)
+            };
+        let mut lanes =
+            self.register_sqmr_subscriber_lanes_with_response_fn(protocol, 1, response_fn)?;
+        Ok(lanes.pop().expect("A single lane was registered"))
+    }
 
-        let insert_result = self.sqmr_outbound_query_receivers.insert(protocol, query_receiver);
-        if insert_result.is_some() {
-            panic!("Protocol '{}' has already been registered as a client.", protocol);
+    /// Makes the queries of the given protocol, which must already be registered as a client, be
+    /// sent preferably to peers that advertised they hold all the blocks the query asks for.
+    pub fn register_query_block_range<Query>(
+        &mut self,
+        protocol: Protocol,
+    ) -> Result<(), RegistrationError>
+    where
+        Query: TryFrom<Bytes> + QueryBlockRange,
+    {
+        let network_manager =
+            self.network_manager.as_mut().ok_or(RegistrationError::AlreadyBuilt)?;
+        if !self.registrations.sqmr_clients.contains(&protocol) {
+            return Err(RegistrationError::ProtocolNotRegisteredAsClient(protocol));
         }
-        let insert_result = self.sqmr_outbound_response_senders.insert(protocol, response_sender);
-        if insert_result.is_some() {
-            panic!("Protocol '{}' has already been registered as a client.", protocol);
+        let extract_block_range_fn: QueryBlockRangeFn =
+            |query| Query::try_from(query.clone()).ok().and_then(|query| query.block_range());
+        network_manager
+            .sqmr_outbound_query_block_range_extractors
+            .insert(protocol, extract_block_range_fn);
+        Ok(())
+    }
+
+    /// Makes the responses to the queries of the given protocol, which must already be registered
+    /// as a client, be sent to `recorder` as well, as they were received and along with their
+    /// query. The responses are sent to the recorder even if the client fails to decode them.
+    pub fn record_sqmr_responses(
+        &mut self,
+        protocol: Protocol,
+        recorder: UnboundedSender<RecordedSqmrResponse>,
+    ) -> Result<(), RegistrationError> {
+        let network_manager =
+            self.network_manager.as_mut().ok_or(RegistrationError::AlreadyBuilt)?;
+        if !self.registrations.sqmr_clients.contains(&protocol) {
+            return Err(RegistrationError::ProtocolNotRegisteredAsClient(protocol));
         }
+        network_manager.sqmr_outbound_response_recorders.insert(protocol, recorder);
+        Ok(())
+    }
 
-        let query_fn: fn(Query) -> Ready<Result<Bytes, SendError>> =
-            |query| ready(Ok(Bytes::from(query)));
-        let query_sender = query_sender.with(query_fn);
+    /// Register the sender of this node's advertisements of the ranges of blocks it can serve.
+    /// Once registered, the advertisements of other peers are used for choosing which peer to send
+    /// each query to (see [`register_query_block_range`](Self::register_query_block_range)).
+    pub fn register_block_range_advertiser(
+        &mut self,
+        buffer_size: usize,
+    ) -> Result<SubscriberSender<BlockRangeAdvertisement>, RegistrationError> {
+        let network_manager =
+            self.network_manager.as_mut().ok_or(RegistrationError::AlreadyBuilt)?;
+        let topic = BLOCK_RANGE_ADVERTISEMENT_TOPIC.topic();
+        let topic_hash = topic.hash();
+        if self.broadcast_topic_message_types.contains_key(&topic_hash) {
+            return Err(RegistrationError::TopicAlreadyRegistered(topic.to_string()));
+        }
+        network_manager
+            .swarm
+            .subscribe_to_topic(&topic)
+            .map_err(RegistrationError::SubscriptionError)?;
+        network_manager.subscribed_topics.push(topic.clone());
 
-        let response_fn: ReceivedMessagesConverterFn<Response> =
-            |(x, report_callback)| (Response::try_from(x), report_callback);
-        let response_receiver = response_receiver.map(response_fn);
+        // Received advertisements are handled by the network manager, so there's no receiver of
+        // broadcasted messages for this topic.
+        let (advertisement_sender, advertisement_receiver) =
+            futures::channel::mpsc::channel(buffer_size);
+        network_manager
+            .messages_to_broadcast_receivers
+            .insert(topic_hash.clone(), notify_when_sender_dropped(advertisement_receiver));
+        network_manager.topic_names.insert(topic_hash.clone(), topic.to_string());
+        self.broadcast_topic_message_types
+            .insert(topic_hash, std::any::type_name::<BlockRangeAdvertisement>());
+        self.registrations.broadcast_topics.push(topic.to_string());
 
-        SqmrSubscriberChannels { query_sender, response_receiver }
+        let advertisement_fn: fn(BlockRangeAdvertisement) -> Ready<Result<Bytes, SendError>> =
+            |advertisement| ready(Ok(Bytes::from(advertisement)));
+        Ok(advertisement_sender.with(advertisement_fn))
     }
 
-    /// Register a new subscriber for broadcasting and receiving broadcasts for a given topic.
-    /// Panics if this topic is already subscribed.
+    /// Register a new subscriber for broadcasting and receiving broadcasts for a given topic. The
+    /// messages are converted to and from the topic's message type.
     pub fn register_broadcast_subscriber<T>(
         &mut self,
-        topic: Topic,
+        topic: TopicDescriptor<T>,
         buffer_size: usize,
-    ) -> Result<BroadcastSubscriberChannels<T>, SubscriptionError>
+    ) -> Result<BroadcastSubscriberChannels<T>, RegistrationError>
     where
         T: TryFrom<Bytes>,
         Bytes: From<T>,
     {
-        self.swarm.subscribe_to_topic(&topic)?;
+        self.register_broadcast_subscriber_by_topic(topic.topic(), buffer_size)
+    }
 
+    /// Register a publisher of messages on a given topic, which learns for each message whether it
+    /// was published to any peer. The node doesn't subscribe to the topic, so it doesn't receive
+    /// the messages other peers broadcast on it.
+    pub fn register_broadcast_publisher<T>(
+        &mut self,
+        topic: TopicDescriptor<T>,
+        buffer_size: usize,
+    ) -> Result<BroadcastPublisher<T>, RegistrationError>
+    where
+        Bytes: From<T>,
+    {
+        let network_manager =
+            self.network_manager.as_mut().ok_or(RegistrationError::AlreadyBuilt)?;
+        let topic = topic.topic();
         let topic_hash = topic.hash();
+        let message_type = std::any::type_name::<T>();
+        if let Some(&registered_type) = self.broadcast_topic_message_types.get(&topic_hash) {
+            if registered_type != message_type {
+                return Err(RegistrationError::TopicRegisteredWithDifferentType {
+                    topic: topic.to_string(),
+                    registered_type,
+                    requested_type: message_type,
+                });
+            }
+            return Err(RegistrationError::TopicAlreadyRegistered(topic.to_string()));
+        }
+
+        let (messages_to_publish_sender, messages_to_publish_receiver) =
+            futures::channel::mpsc::channel(buffer_size);
+        network_manager
+            .messages_to_publish_receivers
+            .insert(topic_hash.clone(), messages_to_publish_receiver);
+        network_manager.topic_names.insert(topic_hash.clone(), topic.to_string());
+        self.broadcast_topic_message_types.insert(topic_hash, message_type);
+        self.registrations.broadcast_topics.push(topic.to_string());
+
+        Ok(BroadcastPublisher { sender: messages_to_publish_sender, _message_type: PhantomData })
+    }
+
+    /// Same as [`register_broadcast_subscriber`](Self::register_broadcast_subscriber), for topics
+    /// that don't have a [`TopicDescriptor`]. The messages are given as they were received.
+    pub fn register_raw_broadcast_subscriber(
+        &mut self,
+        topic_name: String,
+        buffer_size: usize,
+    ) -> Result<BroadcastSubscriberChannels<Bytes>, RegistrationError> {
+        self.register_broadcast_subscriber_by_topic(Topic::new(topic_name), buffer_size)
+    }
+
+    fn register_broadcast_subscriber_by_topic<T>(
+        &mut self,
+        topic: Topic,
+        buffer_size: usize,
+    ) -> Result<BroadcastSubscriberChannels<T>, RegistrationError>
+    where
+        T: TryFrom<Bytes>,
+        Bytes: From<T>,
+    {
+        let network_manager =
+            self.network_manager.as_mut().ok_or(RegistrationError::AlreadyBuilt)?;
+        let topic_hash = topic.hash();
+        let message_type = std::any::type_name::<T>();
+        if let Some(&registered_type) = self.broadcast_topic_message_types.get(&topic_hash) {
+            if registered_type != message_type {
+                return Err(RegistrationError::TopicRegisteredWithDifferentType {
+                    topic: topic.to_string(),
+                    registered_type,
+                    requested_type: message_type,
+                });
+            }
+            return Err(RegistrationError::TopicAlreadyRegistered(topic.to_string()));
+        }
+        network_manager
+            .swarm
+            .subscribe_to_topic(&topic)
+            .map_err(RegistrationError::SubscriptionError)?;
+        network_manager.subscribed_topics.push(topic.clone());
 
         let (messages_to_broadcast_sender, messages_to_broadcast_receiver) =
             futures::channel::mpsc::channel(buffer_size);
         let (broadcasted_messages_sender, broadcasted_messages_receiver) =
             futures::channel::mpsc::channel(buffer_size);
 
-        let insert_result = self
+        network_manager
             .messages_to_broadcast_receivers
-            .insert(topic_hash.clone(), messages_to_broadcast_receiver);
-        if insert_result.is_some() {
-            panic!("Topic '{}' has already been registered.", topic);
-        }
-
-        let insert_result = self
+            .insert(topic_hash.clone(), notify_when_sender_dropped(messages_to_broadcast_receiver));
+        network_manager
             .broadcasted_messages_senders
             .insert(topic_hash.clone(), broadcasted_messages_sender);
-        if insert_result.is_some() {
-            panic!("Topic '{}' has already been registered.", topic);
-        }
+        network_manager.topic_names.insert(topic_hash.clone(), topic.to_string());
+        self.broadcast_topic_message_types.insert(topic_hash, message_type);
+        self.registrations.broadcast_topics.push(topic.to_string());
 
         let messages_to_broadcast_fn: fn(T) -> Ready<Result<Bytes, SendError>> =
             |x| ready(Ok(Bytes::from(x)));
@@ -195,7 +653,7 @@ impl<SwarmT: SwarmTrait> GenericNetworkManager<SwarmT> {
             messages_to_broadcast_sender.with(messages_to_broadcast_fn);
 
         let broadcasted_messages_fn: ReceivedMessagesConverterFn<T> =
-            |(x, report_callback)| (T::try_from(x), report_callback);
+            |(x, report_callback, _memory_guard)| (T::try_from(x), report_callback);
         let broadcasted_messages_receiver =
             broadcasted_messages_receiver.map(broadcasted_messages_fn);
 
@@ -204,17 +662,440 @@ impl<SwarmT: SwarmTrait> GenericNetworkManager<SwarmT> {
             broadcasted_messages_receiver,
         })
     }
+}
+
+pub struct GenericNetworkManager<SwarmT: SwarmTrait> {
+    swarm: SwarmT,
+    header_buffer_size: usize,
+    sqmr_subscriber_buffer_size: usize,
+    sqmr_inbound_response_receivers:
+        StreamHashMap<InboundSessionId, BoxStream<'static, Option<Bytes>>>,
+    sqmr_inbound_query_senders: HashMap<Protocol, Sender<ReceivedQuery>>,
+    // The peer that opened each inbound session whose query was given to the server, used for
+    // counting the bytes served to it and the sessions of each peer.
+    inbound_session_id_to_peer_id: HashMap<InboundSessionId, PeerId>,
+    inbound_session_limits: SessionLimits,
+    // Splitting the response receivers from the query senders in order to poll all
+    // receivers simultaneously.
+    // The sender of a topic is removed once its subscriber stops receiving the topic's messages.
+    sqmr_outbound_query_receivers: StreamHashMap<SqmrClientLane, Receiver<(Bytes, QueryPriority)>>,
+    // The local queries that wait for the number of active outbound sessions to drop below
+    // max_concurrent_outbound_sessions.
+    pending_outbound_queries: OutboundQueryQueue<SqmrClientLane>,
+    max_concurrent_outbound_sessions: usize,
+    sqmr_outbound_response_senders: HashMap<SqmrClientLane, Sender<ReceivedResponse>>,
+    // The responses that didn't fit in the buffer of their lane's subscriber, with the session each
+    // of them came from. They're sent before any other response of the lane.
+    sqmr_outbound_response_backlogs:
+        HashMap<SqmrClientLane, VecDeque<(Option<OutboundSessionId>, ReceivedResponse)>>,
+    // The number of responses in a lane's backlog from which the swarm isn't polled.
+    max_lane_backlog_size: usize,
+    // The outbound sessions whose responses aren't read from their peers while some of their
+    // responses wait in the backlogs.
+    paused_outbound_sessions: HashSet<OutboundSessionId>,
+    sqmr_outbound_query_block_range_extractors: HashMap<Protocol, QueryBlockRangeFn>,
+    sqmr_outbound_response_recorders: HashMap<Protocol, UnboundedSender<RecordedSqmrResponse>>,
+    // The query of each outbound session of a recorded protocol, and the session's id in the
+    // records. The record ids aren't reset when the network restarts, unlike the session ids.
+    outbound_session_id_to_recorded_query: HashMap<OutboundSessionId, (u64, Bytes)>,
+    next_recorded_session_id: u64,
+    // Splitting the broadcast receivers from the broadcasted senders in order to poll all
+    // receivers simultaneously.
+    // The sender of a topic is removed once its subscriber stops receiving the topic's messages.
+    messages_to_broadcast_receivers: StreamHashMap<TopicHash, MessagesToBroadcastReceiver>,
+    broadcasted_messages_senders: HashMap<TopicHash, Sender<ReceivedMessage>>,
+    messages_to_publish_receivers: StreamHashMap<TopicHash, Receiver<MessageToPublish>>,
+    // The name of each registered topic, for labeling the metrics of its messages.
+    topic_names: HashMap<TopicHash, String>,
+    outbound_sessions: HashMap<OutboundSessionId, OutboundSession>,
+    // The active outbound sessions that the identical queries of other lanes can still share, by
+    // their protocol and query.
+    shareable_outbound_sessions: HashMap<(Protocol, Bytes), OutboundSessionId>,
+    protocol_names: ProtocolNames,
+    reported_peer_receiver: UnboundedReceiver<PeerId>,
+    // We keep this just for giving a clone of it for subscribers.
+    reported_peer_sender: UnboundedSender<PeerId>,
+    // The data availability hints of the responses, which the subscribers extract while decoding
+    // them.
+    data_availability_hints_receiver: UnboundedReceiver<(PeerId, Vec<DataAvailabilityHint>)>,
+    // We keep this just for giving a clone of it for subscribers.
+    data_availability_hints_sender: UnboundedSender<(PeerId, Vec<DataAvailabilityHint>)>,
+    peer_manager_command_receiver: UnboundedReceiver<PeerManagerCommand>,
+    // We keep this just for giving a clone of it to the node's operator tooling.
+    peer_manager_command_sender: UnboundedSender<PeerManagerCommand>,
+    network_event_log: NetworkEventLog,
+    // The limits on the pending inbound queries of each protocol. The queries of a protocol without
+    // a limit are always queued.
+    inbound_query_queues: HashMap<Protocol, InboundQueryQueueConfig>,
+    // The protocol of each inbound session whose server didn't start responding to its query yet.
+    pending_inbound_queries: HashMap<InboundSessionId, Protocol>,
+    num_pending_inbound_queries: HashMap<Protocol, usize>,
+    // Fields for metrics
+    num_active_inbound_sessions: usize,
+    num_active_outbound_sessions: usize,
+    // Shared so that it can be read while the network manager is running.
+    session_pools_usage: SessionPoolsUsage,
+    // The addresses the swarm listens on. The network manager starts listening on them when it
+    // runs, and listens on them again if their listener closes.
+    listen_addresses: Vec<Multiaddr>,
+    listener_id_to_address: HashMap<ListenerId, Multiaddr>,
+    max_listen_attempts: usize,
+    // The topics the swarm is subscribed to, for subscribing the new swarm to them on a restart.
+    subscribed_topics: Vec<Topic>,
+    // Builds the swarm that replaces the current one on a restart. The swarm it builds doesn't
+    // listen on any address.
+    swarm_factory: Option<SwarmFactory<SwarmT>>,
+    // The interval at which the peers are pinged, if it can change while the network manager runs.
+    ping_interval_updates: Option<watch::Receiver<Duration>>,
+    // The memory of the responses, queries and broadcasted messages that were received and not
+    // taken yet by the node's components.
+    memory_budget: MemoryBudget,
+    // Delays the responses to inbound queries so that they don't exceed the serving bandwidth.
+    bandwidth_throttle: BandwidthThrottle,
+    // The time the last event was read from the swarm, which bounds how long the swarm isn't
+    // polled while the memory budget is exhausted or a lane's backlog is full.
+    last_swarm_event_time: Instant,
+}
+
+impl<SwarmT: SwarmTrait> GenericNetworkManager<SwarmT> {
+    /// Returns a sender of commands to the peer manager, which are applied while the network
+    /// manager runs.
+    pub fn peer_manager_command_sender(&self) -> UnboundedSender<PeerManagerCommand> {
+        self.peer_manager_command_sender.clone()
+    }
+
+    /// Returns a handle to the number of active sessions in each direction and their limits, which
+    /// keeps updating while the network manager runs.
+    pub fn session_pools_usage(&self) -> SessionPoolsUsage {
+        self.session_pools_usage.clone()
+    }
+
+    /// Returns a handle to the last events of the swarm, which keeps updating while the network
+    /// manager runs. It stays empty unless the events are enabled by the config or by the log
+    /// level of [`NETWORK_EVENTS_TRACING_TARGET`].
+    pub fn recent_network_events(&self) -> RecentNetworkEvents {
+        self.network_event_log.recent_events()
+    }
+
+    pub async fn run(mut self) -> Result<(), NetworkError> {
+        Err(self.run_until_error().await)
+    }
+
+    /// Same as [`run`](Self::run), but keeps the network manager so that it can be
+    /// [restarted](Self::restart) if the error is recoverable.
+    pub async fn run_until_error(&mut self) -> NetworkError {
+        match self.run_and_recover().await {
+            Ok(never) => match never {},
+            Err(error) => error,
+        }
+    }
+
+    /// Replaces the swarm with a new one after the network manager stopped on a recoverable error.
+    /// The channels of the registered components stay connected, but the sessions that were
+    /// active end without notifying them, as if they failed.
+    pub fn restart(&mut self) -> Result<(), NetworkError> {
+        let swarm_factory =
+            self.swarm_factory.as_ref().ok_or(FatalNetworkError::RestartNotSupported)?;
+        self.swarm = swarm_factory();
+        // The new swarm pings the peers at the interval it was built with.
+        if let Some(ping_interval_updates) = &self.ping_interval_updates {
+            self.swarm.set_ping_interval(*ping_interval_updates.borrow());
+        }
+        self.sqmr_inbound_response_receivers = StreamHashMap::new(HashMap::new());
+        self.inbound_session_id_to_peer_id.clear();
+        self.pending_inbound_queries.clear();
+        for protocol in self.num_pending_inbound_queries.keys() {
+            gauge!(
+                papyrus_metrics::PAPYRUS_NUM_PENDING_INBOUND_QUERIES,
+                0f64,
+                "protocol" => protocol.as_str()
+            );
+        }
+        self.num_pending_inbound_queries.clear();
+        self.outbound_sessions.clear();
+        self.shareable_outbound_sessions.clear();
+        // The responses in the backlogs are still sent to the lanes.
+        self.paused_outbound_sessions.clear();
+        self.outbound_session_id_to_recorded_query.clear();
+        self.listener_id_to_address.clear();
+        self.num_active_inbound_sessions = 0;
+        self.num_active_outbound_sessions = 0;
+        gauge!(papyrus_metrics::PAPYRUS_NUM_CONNECTED_PEERS, 0f64);
+        gauge!(papyrus_metrics::PAPYRUS_NUM_ACTIVE_INBOUND_SESSIONS, 0f64);
+        gauge!(papyrus_metrics::PAPYRUS_NUM_ACTIVE_OUTBOUND_SESSIONS, 0f64);
+        self.update_session_pools_usage();
+        for topic in &self.subscribed_topics {
+            self.swarm.subscribe_to_topic(topic).map_err(|error| {
+                FatalNetworkError::ResubscriptionFailed { topic: topic.to_string(), error }
+            })?;
+        }
+        self.send_pending_sqmr_queries();
+        Ok(())
+    }
+
+    async fn run_and_recover(&mut self) -> Result<Infallible, NetworkError> {
+        for address in self.listen_addresses.clone() {
+            if !self.listener_id_to_address.values().any(|listened| *listened == address) {
+                self.listen_on(address).await?;
+            }
+        }
+        loop {
+            // While the memory budget is exhausted or a lane's backlog is full the swarm isn't
+            // polled, so no more data is read from the peers until the components take the items
+            // buffered for them.
+            let is_backpressured = self.is_backpressured();
+            let has_full_lane_backlog = self.has_full_lane_backlog();
+            let backpressure_end =
+                tokio::time::Instant::from_std(self.last_swarm_event_time + MAX_BACKPRESSURE_PAUSE);
+            let event = tokio::select! {
+                Some(event) = self.swarm.next(), if !is_backpressured && !has_full_lane_backlog => {
+                    LoopEvent::Swarm(event)
+                }
+                _ = tokio::time::timeout_at(
+                    backpressure_end,
+                    self.memory_budget.wait_for_capacity(),
+                ), if is_backpressured => LoopEvent::BackpressureEnded,
+                // A full backlog is drained by the LaneReadyForBacklog events, so only the end of
+                // the pause is waited for here.
+                _ = tokio::time::sleep_until(backpressure_end),
+                    if has_full_lane_backlog && !is_backpressured => LoopEvent::BackpressureEnded,
+                Some(res) = self.sqmr_inbound_response_receivers.next() => {
+                    LoopEvent::ResponseForInboundQuery(res)
+                }
+                Some((lane, (query, priority))) = self.sqmr_outbound_query_receivers.next() => {
+                    LoopEvent::LocalSqmrQuery { lane, query, priority }
+                }
+                lane = poll_fn(|cx| poll_lane_ready_for_backlog(
+                    &mut self.sqmr_outbound_response_senders,
+                    &self.sqmr_outbound_response_backlogs,
+                    cx,
+                )) => LoopEvent::LaneReadyForBacklog(lane),
+                Some((topic_hash, message)) = self.messages_to_broadcast_receivers.next() => {
+                    match message {
+                        Some(message) => LoopEvent::MessageToBroadcast { topic_hash, message },
+                        None => LoopEvent::BroadcastSenderDropped(topic_hash),
+                    }
+                }
+                Some((topic_hash, message_to_publish)) =
+                    self.messages_to_publish_receivers.next() => {
+                    LoopEvent::MessageToPublish { topic_hash, message_to_publish }
+                }
+                Some(peer_id) = self.reported_peer_receiver.next() => {
+                    LoopEvent::ReportedPeer(peer_id)
+                }
+                Some((peer_id, hints)) = self.data_availability_hints_receiver.next() => {
+                    LoopEvent::DataAvailabilityHints { peer_id, hints }
+                }
+                Some(command) = self.peer_manager_command_receiver.next() => {
+                    LoopEvent::PeerManagerCommand(command)
+                }
+                ping_interval = next_ping_interval(&mut self.ping_interval_updates) => {
+                    LoopEvent::PingIntervalChanged(ping_interval)
+                }
+            };
+            // The swarm isn't polled while an event is handled, so the time it takes is measured.
+            let handling_start = Instant::now();
+            self.handle_loop_event(event).await?;
+            histogram!(
+                papyrus_metrics::PAPYRUS_NETWORK_LOOP_ITERATION_DURATION_SECS,
+                handling_start.elapsed().as_secs_f64()
+            );
+        }
+    }
 
-    fn handle_swarm_event(&mut self, event: SwarmEvent<mixed_behaviour::Event>) {
+    async fn handle_loop_event(&mut self, event: LoopEvent) -> Result<(), NetworkError> {
         match event {
-            SwarmEvent::ConnectionEstablished { peer_id, .. } => {
+            LoopEvent::Swarm(event) => {
+                self.last_swarm_event_time = Instant::now();
+                self.handle_swarm_event(event).await?
+            }
+            LoopEvent::ResponseForInboundQuery(res) => self.handle_response_for_inbound_query(res),
+            LoopEvent::LocalSqmrQuery { lane, query, priority } => {
+                self.handle_local_sqmr_query(lane, query, priority)
+            }
+            LoopEvent::LaneReadyForBacklog(lane) => self.send_lane_backlog(lane),
+            LoopEvent::MessageToBroadcast { topic_hash, message } => {
+                self.broadcast_message(message, topic_hash)
+            }
+            // A subscriber that dropped both of its channels shut down, and the node leaves the
+            // topic. A subscriber that only dropped its sender still receives the topic's messages.
+            LoopEvent::BroadcastSenderDropped(topic_hash) => {
+                if self
+                    .broadcasted_messages_senders
+                    .get(&topic_hash)
+                    .is_some_and(|sender| sender.is_closed())
+                {
+                    self.unsubscribe_from_topic(&topic_hash);
+                }
+            }
+            LoopEvent::MessageToPublish {
+                topic_hash,
+                message_to_publish: (message, result_sender),
+            } => {
+                // The publisher might have stopped waiting for the result.
+                let _ = result_sender.send(self.publish_message(message, topic_hash));
+            }
+            LoopEvent::ReportedPeer(peer_id) => self.swarm.report_peer(peer_id),
+            LoopEvent::DataAvailabilityHints { peer_id, hints } => {
+                for (hinted_protocol, block_number, is_available) in hints {
+                    self.swarm.update_peer_block_availability(
+                        peer_id,
+                        self.protocol_names.stream_protocol(hinted_protocol),
+                        block_number,
+                        is_available,
+                    );
+                }
+            }
+            LoopEvent::PeerManagerCommand(command) => {
+                self.swarm.handle_peer_manager_command(command)
+            }
+            // The queries that were deferred by the backpressure.
+            LoopEvent::BackpressureEnded => self.send_pending_sqmr_queries(),
+            LoopEvent::PingIntervalChanged(ping_interval) => {
+                info!("Pinging the peers every {ping_interval:?} on new connections.");
+                self.swarm.set_ping_interval(ping_interval);
+            }
+        }
+        Ok(())
+    }
+
+    fn generic_new(
+        swarm: SwarmT,
+        header_buffer_size: usize,
+        sqmr_subscriber_buffer_size: usize,
+        max_concurrent_outbound_sessions: usize,
+        outbound_query_aging_interval: Duration,
+        protocol_names: ProtocolNames,
+        network_event_log: NetworkEventLog,
+    ) -> Self {
+        gauge!(papyrus_metrics::PAPYRUS_NUM_CONNECTED_PEERS, 0f64);
+        let (reported_peer_sender, reported_peer_receiver) = futures::channel::mpsc::unbounded();
+        let (data_availability_hints_sender, data_availability_hints_receiver) =
+            futures::channel::mpsc::unbounded();
+        let (peer_manager_command_sender, peer_manager_command_receiver) =
+            futures::channel::mpsc::unbounded();
+        Self {
+            swarm,
+            header_buffer_size,
+            sqmr_subscriber_buffer_size,
+            sqmr_inbound_response_receivers: StreamHashMap::new(HashMap::new()),
+            sqmr_inbound_query_senders: HashMap::new(),
+            inbound_session_id_to_peer_id: HashMap::new(),
+            inbound_session_limits: SessionLimits {
+                max_sessions: usize::MAX,
+                max_sessions_per_peer: usize::MAX,
+            },
+            sqmr_outbound_query_receivers: StreamHashMap::new(HashMap::new()),
+            pending_outbound_queries: OutboundQueryQueue::new(outbound_query_aging_interval),
+            max_concurrent_outbound_sessions,
+            sqmr_outbound_response_senders: HashMap::new(),
+            sqmr_outbound_response_backlogs: HashMap::new(),
+            max_lane_backlog_size: DEFAULT_MAX_LANE_BACKLOG_SIZE,
+            paused_outbound_sessions: HashSet::new(),
+            sqmr_outbound_query_block_range_extractors: HashMap::new(),
+            sqmr_outbound_response_recorders: HashMap::new(),
+            outbound_session_id_to_recorded_query: HashMap::new(),
+            next_recorded_session_id: 0,
+            messages_to_broadcast_receivers: StreamHashMap::new(HashMap::new()),
+            broadcasted_messages_senders: HashMap::new(),
+            messages_to_publish_receivers: StreamHashMap::new(HashMap::new()),
+            outbound_sessions: HashMap::new(),
+            shareable_outbound_sessions: HashMap::new(),
+            protocol_names,
+            reported_peer_sender,
+            reported_peer_receiver,
+            data_availability_hints_receiver,
+            data_availability_hints_sender,
+            peer_manager_command_receiver,
+            peer_manager_command_sender,
+            network_event_log,
+            inbound_query_queues: HashMap::new(),
+            pending_inbound_queries: HashMap::new(),
+            num_pending_inbound_queries: HashMap::new(),
+            num_active_inbound_sessions: 0,
+            num_active_outbound_sessions: 0,
+            session_pools_usage: SessionPoolsUsage::default(),
+            listen_addresses: Vec::new(),
+            listener_id_to_address: HashMap::new(),
+            max_listen_attempts: 1,
+            subscribed_topics: Vec::new(),
+            topic_names: HashMap::new(),
+            swarm_factory: None,
+            ping_interval_updates: None,
+            memory_budget: MemoryBudget::new(u64::MAX),
+            bandwidth_throttle: BandwidthThrottle::new(0, 0),
+            last_swarm_event_time: Instant::now(),
+        }
+    }
+
+    // Listens on the address, retrying if it fails since the failure might be transient (e.g. the
+    // address is still held by a listener that closed). The retries block the network manager.
+    async fn listen_on(&mut self, address: Multiaddr) -> Result<(), NetworkError> {
+        let mut num_attempts = 0;
+        loop {
+            num_attempts += 1;
+            match self.swarm.listen_on(address.clone()) {
+                Ok(listener_id) => {
+                    self.listener_id_to_address.insert(listener_id, address);
+                    return Ok(());
+                }
+                Err(error) if num_attempts < self.max_listen_attempts => {
+                    warn!(
+                        "Failed to listen on {address} (attempt {num_attempts}/{}): {error}. \
+                         Retrying in {LISTEN_RETRY_INTERVAL:?}.",
+                        self.max_listen_attempts
+                    );
+                    tokio::time::sleep(LISTEN_RETRY_INTERVAL).await;
+                }
+                Err(error) => {
+                    return Err(RecoverableNetworkError::ListenFailed {
+                        address,
+                        num_attempts,
+                        error: error.to_string(),
+                    }
+                    .into());
+                }
+            }
+        }
+    }
+
+    // The connections the config doesn't allow are expected, so they aren't logged as errors.
+    fn record_denied_connection(&mut self, cause: &ConnectionDenied) {
+        if let Some(error) = cause.downcast_ref::<ConnectionGatingError>() {
+            self.network_event_log.record(
+                NetworkEventKind::ConnectionDenied,
+                Some(error.peer_id()),
+                || error.to_string(),
+            );
+        }
+    }
+
+    async fn handle_swarm_event(
+        &mut self,
+        event: SwarmEvent<mixed_behaviour::Event>,
+    ) -> Result<(), NetworkError> {
+        match event {
+            SwarmEvent::ConnectionEstablished { peer_id, endpoint, .. } => {
                 debug!("Connected to peer id: {peer_id:?}");
+                self.network_event_log.record(
+                    NetworkEventKind::ConnectionEstablished,
+                    Some(peer_id),
+                    || format!("{endpoint:?}"),
+                );
                 gauge!(
                     papyrus_metrics::PAPYRUS_NUM_CONNECTED_PEERS,
                     self.swarm.num_connected_peers() as f64
                 );
             }
             SwarmEvent::ConnectionClosed { peer_id, cause, .. } => {
+                self.network_event_log.record(
+                    NetworkEventKind::ConnectionClosed,
+                    Some(peer_id),
+                    || match &cause {
+                        Some(connection_error) => format!("Closed due to {connection_error:?}"),
+                        None => "Closed".to_string(),
+                    },
+                );
                 match cause {
                     Some(connection_error) => {
                         debug!("Connection to {peer_id:?} closed due to {connection_error:?}.")
@@ -227,14 +1108,26 @@ impl<SwarmT: SwarmTrait> GenericNetworkManager<SwarmT> {
                 );
             }
             SwarmEvent::Behaviour(event) => {
-                self.handle_behaviour_event(event);
+                self.handle_behaviour_event(event).await;
+            }
+            SwarmEvent::OutgoingConnectionError { error: DialError::Denied { cause }, .. }
+                if cause.downcast_ref::<ConnectionGatingError>().is_some() =>
+            {
+                self.record_denied_connection(&cause);
             }
             SwarmEvent::OutgoingConnectionError { connection_id, peer_id, error } => {
+                self.network_event_log
+                    .record(NetworkEventKind::DialFailed, peer_id, || format!("{error:?}"));
                 error!(
                     "Outgoing connection error. connection id: {connection_id:?}, requested peer \
                      id: {peer_id:?}, error: {error:?}"
                 );
             }
+            SwarmEvent::IncomingConnectionError { error: ListenError::Denied { cause }, .. }
+                if cause.downcast_ref::<ConnectionGatingError>().is_some() =>
+            {
+                self.record_denied_connection(&cause);
+            }
             SwarmEvent::IncomingConnectionError {
                 connection_id,
                 local_addr,
@@ -243,6 +1136,11 @@ impl<SwarmT: SwarmTrait> GenericNetworkManager<SwarmT> {
             } => {
                 // No need to panic here since this is a result of another peer trying to dial to us
                 // and failing. Other peers are welcome to retry.
+                self.network_event_log.record(
+                    NetworkEventKind::IncomingConnectionFailed,
+                    None,
+                    || format!("Send back address: {send_back_addr}, error: {error:?}"),
+                );
                 error!(
                     "Incoming connection error. connection id: {connection_id:?}, local addr: \
                      {local_addr:?}, send back addr: {send_back_addr:?}, error: {error:?}"
@@ -253,19 +1151,43 @@ impl<SwarmT: SwarmTrait> GenericNetworkManager<SwarmT> {
                 // addresses.
                 self.swarm.add_external_address(address);
             }
-            SwarmEvent::IncomingConnection { .. }
-            | SwarmEvent::Dialing { .. }
-            | SwarmEvent::NewExternalAddrCandidate { .. } => {}
+            SwarmEvent::Dialing { peer_id, connection_id } => {
+                // The addresses that were dialed appear in the event of the established connection
+                // or of the dial failure.
+                self.network_event_log.record(NetworkEventKind::Dialing, peer_id, || {
+                    format!("Connection id: {connection_id:?}")
+                });
+            }
+            SwarmEvent::ListenerClosed { listener_id, addresses, reason } => {
+                self.network_event_log.record(NetworkEventKind::ListenerClosed, None, || {
+                    format!("Addresses: {addresses:?}, reason: {reason:?}")
+                });
+                warn!("Stopped listening on {addresses:?}: {reason:?}");
+                // The network manager never removes its listeners, so a listener closes only when
+                // it failed.
+                if let Some(address) = self.listener_id_to_address.remove(&listener_id) {
+                    self.listen_on(address).await?;
+                }
+            }
+            SwarmEvent::ListenerError { listener_id, error } => {
+                warn!("Listener {listener_id:?} failed: {error:?}");
+            }
+            SwarmEvent::ExpiredListenAddr { address, .. } => {
+                debug!("No longer listening on {address}.");
+            }
+            SwarmEvent::IncomingConnection { .. } | SwarmEvent::NewExternalAddrCandidate { .. } => {
+            }
             _ => {
                 panic!("Unexpected event {event:?}");
             }
         }
+        Ok(())
     }
 
-    fn handle_behaviour_event(&mut self, event: mixed_behaviour::Event) {
+    async fn handle_behaviour_event(&mut self, event: mixed_behaviour::Event) {
         match event {
             mixed_behaviour::Event::ExternalEvent(external_event) => {
-                self.handle_behaviour_external_event(external_event);
+                self.handle_behaviour_external_event(external_event).await;
             }
             mixed_behaviour::Event::ToOtherBehaviourEvent(internal_event) => {
                 self.handle_to_other_behaviour_event(internal_event);
@@ -273,10 +1195,10 @@ impl<SwarmT: SwarmTrait> GenericNetworkManager<SwarmT> {
         }
     }
 
-    fn handle_behaviour_external_event(&mut self, event: mixed_behaviour::ExternalEvent) {
+    async fn handle_behaviour_external_event(&mut self, event: mixed_behaviour::ExternalEvent) {
         match event {
             mixed_behaviour::ExternalEvent::Sqmr(event) => {
-                self.handle_sqmr_behaviour_event(event);
+                self.handle_sqmr_behaviour_event(event).await;
             }
             mixed_behaviour::ExternalEvent::GossipSub(event) => {
                 self.handle_gossipsub_behaviour_event(event);
@@ -289,7 +1211,9 @@ impl<SwarmT: SwarmTrait> GenericNetworkManager<SwarmT> {
         if let mixed_behaviour::ToOtherBehaviourEvent::NoOp = event {
             return;
         }
+        self.record_to_other_behaviour_event(&event);
         self.swarm.behaviour_mut().identify.on_other_behaviour_event(&event);
+        self.swarm.behaviour_mut().ping.on_other_behaviour_event(&event);
         self.swarm.behaviour_mut().kademlia.on_other_behaviour_event(&event);
         if let Some(discovery) = self.swarm.behaviour_mut().discovery.as_mut() {
             discovery.on_other_behaviour_event(&event);
@@ -299,81 +1223,261 @@ impl<SwarmT: SwarmTrait> GenericNetworkManager<SwarmT> {
         self.swarm.behaviour_mut().gossipsub.on_other_behaviour_event(&event);
     }
 
-    fn handle_sqmr_behaviour_event(&mut self, event: sqmr::behaviour::ExternalEvent) {
+    fn record_to_other_behaviour_event(&mut self, event: &mixed_behaviour::ToOtherBehaviourEvent) {
+        match event {
+            mixed_behaviour::ToOtherBehaviourEvent::Identify(
+                IdentifyToOtherBehaviourEvent::FoundListenAddresses {
+                    peer_id,
+                    listen_addresses,
+                    agent_version,
+                    ..
+                },
+            ) => self.network_event_log.record(
+                NetworkEventKind::IdentifyReceived,
+                Some(*peer_id),
+                || format!("Listen addresses: {listen_addresses:?}, agent: {agent_version}"),
+            ),
+            mixed_behaviour::ToOtherBehaviourEvent::Kad(
+                KadToOtherBehaviourEvent::RoutingUpdated { peer_id, addresses },
+            ) => self.network_event_log.record(
+                NetworkEventKind::KadRoutingUpdated,
+                Some(*peer_id),
+                || format!("Addresses: {addresses:?}"),
+            ),
+            mixed_behaviour::ToOtherBehaviourEvent::PeerManager(
+                peer_manager::ToOtherBehaviourEvent::SessionAssigned {
+                    outbound_session_id,
+                    peer_id,
+                    connection_id,
+                },
+            ) => self.network_event_log.record(
+                NetworkEventKind::SqmrOutboundSessionAssigned,
+                Some(*peer_id),
+                || format!("Session id: {outbound_session_id:?}, connection id: {connection_id:?}"),
+            ),
+            _ => {}
+        }
+    }
+
+    async fn handle_sqmr_behaviour_event(&mut self, event: sqmr::behaviour::ExternalEvent) {
         // TODO(shahak): Extract the body of each match arm to a separate function.
         match event {
             sqmr::behaviour::ExternalEvent::NewInboundSession {
                 query,
                 inbound_session_id,
-                peer_id: _,
+                peer_id,
                 protocol_name,
             } => {
                 info!(
                     "Received new inbound query: {query:?} for session id: {inbound_session_id:?}"
                 );
+                self.network_event_log.record(
+                    NetworkEventKind::SqmrInboundSessionStarted,
+                    Some(peer_id),
+                    || format!("Session id: {inbound_session_id:?}, protocol: {protocol_name}"),
+                );
                 self.num_active_inbound_sessions += 1;
                 gauge!(
                     papyrus_metrics::PAPYRUS_NUM_ACTIVE_INBOUND_SESSIONS,
                     self.num_active_inbound_sessions as f64
                 );
-                // TODO: consider returning error instead of panic.
-                let protocol =
-                    Protocol::try_from(protocol_name).expect("Encountered unknown protocol");
+                let protocol = match self.protocol_names.protocol(&protocol_name) {
+                    Ok(protocol) => protocol,
+                    Err(error) => {
+                        warn!(
+                            "Closing inbound session {inbound_session_id:?} from {peer_id:?}: \
+                             {error}"
+                        );
+                        if let Err(error) = self.swarm.close_inbound_session(inbound_session_id) {
+                            error!(
+                                "Failed to close inbound session {inbound_session_id:?}: {error:?}"
+                            );
+                        }
+                        self.swarm.report_peer(peer_id);
+                        return;
+                    }
+                };
+                self.swarm.update_peer_negotiated_protocol(
+                    peer_id,
+                    self.protocol_names.stream_protocol(protocol),
+                    protocol_name,
+                );
+                if self.is_overloaded(protocol) {
+                    debug!(
+                        "Rejecting inbound session {inbound_session_id:?} from {peer_id:?}: too \
+                         many pending queries of {protocol}"
+                    );
+                    increment_counter!(
+                        papyrus_metrics::PAPYRUS_INBOUND_QUERIES_REJECTED_ON_OVERLOAD,
+                        "protocol" => protocol.as_str()
+                    );
+                    if let Err(error) = self.swarm.close_inbound_session(inbound_session_id) {
+                        error!("Failed to close inbound session {inbound_session_id:?}: {error:?}");
+                    }
+                    return;
+                }
+                if let Some(limit) = self.reached_inbound_session_limit(peer_id) {
+                    debug!(
+                        "Rejecting inbound session {inbound_session_id:?} from {peer_id:?}: \
+                         reached the limit of inbound sessions ({limit})"
+                    );
+                    increment_counter!(
+                        papyrus_metrics::PAPYRUS_INBOUND_SESSIONS_REJECTED_ON_LIMIT,
+                        "limit" => limit
+                    );
+                    if let Err(error) = self.swarm.close_inbound_session(inbound_session_id) {
+                        error!("Failed to close inbound session {inbound_session_id:?}: {error:?}");
+                    }
+                    return;
+                }
                 let Some(query_sender) = self.sqmr_inbound_query_senders.get_mut(&protocol) else {
                     return;
                 };
                 let (response_sender, response_receiver) =
                     futures::channel::mpsc::channel(self.header_buffer_size);
+                let report_callback = self.create_external_callback_for_received_data(peer_id);
+                let memory_guard = self.memory_budget.reserve(query.len());
                 // TODO(shahak): Close the inbound session if the buffer is full.
                 send_now(
                     query_sender,
-                    (query, response_sender),
+                    (query, response_sender, peer_id, report_callback, memory_guard),
                     format!(
                         "Received an inbound query while the buffer is full. Dropping query for \
                          session {inbound_session_id:?}"
                     ),
                 );
+                self.inbound_session_id_to_peer_id.insert(inbound_session_id, peer_id);
+                // Each response waits for the bandwidth in its session's stream, so the other
+                // sessions and the broadcasted messages aren't delayed by it.
+                let response_receiver = if self.bandwidth_throttle.is_enabled() {
+                    let bandwidth_throttle = self.bandwidth_throttle.clone();
+                    response_receiver
+                        .then(move |data| {
+                            let delay = bandwidth_throttle.reserve(peer_id, data.len());
+                            async move {
+                                if !delay.is_zero() {
+                                    tokio::time::sleep(delay).await;
+                                }
+                                data
+                            }
+                        })
+                        .boxed()
+                } else {
+                    response_receiver.boxed()
+                };
                 self.sqmr_inbound_response_receivers.insert(
                     inbound_session_id,
                     response_receiver.map(Some).chain(stream::once(ready(None))).boxed(),
                 );
+                self.pending_inbound_queries.insert(inbound_session_id, protocol);
+                self.update_num_pending_inbound_queries(protocol, |num_pending| num_pending + 1);
+                self.update_session_pools_usage();
             }
-            sqmr::behaviour::ExternalEvent::ReceivedData { outbound_session_id, data, peer_id } => {
+            sqmr::behaviour::ExternalEvent::ReceivedData {
+                outbound_session_id,
+                data,
+                peer_id,
+                protocol_name,
+            } => {
                 trace!(
                     "Received data from peer for session id: {outbound_session_id:?}. sending to \
                      sync subscriber."
                 );
-                let protocol = self
-                    .outbound_session_id_to_protocol
+                let lanes = self
+                    .outbound_sessions
                     .get(&outbound_session_id)
-                    .expect("Received data from an unknown session id");
-                let report_callback = self.create_external_callback_for_received_data(peer_id);
-                if let Some(response_sender) = self.sqmr_outbound_response_senders.get_mut(protocol)
-                {
-                    // TODO(shahak): Close the channel if the buffer is full.
-                    send_now(
-                        response_sender,
-                        (data, report_callback),
-                        format!(
-                            "Received response for an outbound query while the buffer is full. \
-                             Dropping it. Session: {outbound_session_id:?}"
+                    .expect("Received data from an unknown session id")
+                    .lanes
+                    .clone();
+                let protocol = lanes[0].protocol;
+                // A query that shares the session from now on would miss this response.
+                self.stop_sharing_outbound_session(outbound_session_id);
+                // The peer negotiated one of the names this node proposed for the lane's protocol.
+                let (_, version) = self
+                    .protocol_names
+                    .protocol_and_version(&protocol_name)
+                    .expect("Negotiated a protocol name that wasn't proposed");
+                self.swarm.update_peer_negotiated_protocol(
+                    peer_id,
+                    self.protocol_names.stream_protocol(protocol),
+                    protocol_name,
+                );
+                self.record_sqmr_response(protocol, outbound_session_id, peer_id, &data);
+                let mut data = data;
+                let num_lanes = lanes.len();
+                for (index, lane) in lanes.into_iter().enumerate() {
+                    // The last lane takes the data, and the others get copies of it.
+                    let lane_data = if index + 1 == num_lanes {
+                        std::mem::take(&mut data)
+                    } else {
+                        data.clone()
+                    };
+                    let report_callback = self.create_external_callback_for_received_data(peer_id);
+                    let apply_hints_callback = self.create_apply_hints_callback(peer_id);
+                    // The data was already read, so it's counted even if it exceeds the budget.
+                    // The swarm isn't polled again until the budget is available.
+                    let memory_guard = self.memory_budget.reserve(lane_data.len());
+                    // If the subscriber's buffer is full, the session is paused until it takes
+                    // the response. This stops us from reading from the session's substream, which
+                    // propagates the backpressure to the remote peer instead of buffering the
+                    // responses or dropping them, while the other sessions keep being read.
+                    self.send_to_lane(
+                        lane,
+                        (
+                            Ok((lane_data, version)),
+                            report_callback,
+                            apply_hints_callback,
+                            memory_guard,
                         ),
+                        Some(outbound_session_id),
                     );
                 }
             }
             sqmr::behaviour::ExternalEvent::SessionFailed { session_id, error } => {
                 error!("Session {session_id:?} failed on {error:?}");
+                self.network_event_log.record(NetworkEventKind::SqmrSessionFailed, None, || {
+                    format!("Session id: {session_id:?}, error: {error:?}")
+                });
                 self.report_session_removed_to_metrics(session_id);
                 // TODO: Handle reputation and retry.
-                if let SessionId::OutboundSessionId(outbound_session_id) = session_id {
-                    self.outbound_session_id_to_protocol.remove(&outbound_session_id);
+                match session_id {
+                    SessionId::OutboundSessionId(outbound_session_id) => {
+                        let session = self.remove_outbound_session(outbound_session_id);
+                        // The subscriber detects the other failures by not receiving responses for
+                        // a while, but a query that no peer got can't be answered by waiting.
+                        if let (
+                            Some(session),
+                            sqmr::behaviour::SessionError::NoPeers { dial_deadline },
+                        ) = (session, &error)
+                        {
+                            for lane in session.lanes {
+                                self.send_query_failure(
+                                    lane,
+                                    SqmrQueryError::NoPeers { dial_deadline: *dial_deadline },
+                                );
+                            }
+                        }
+                        self.send_pending_sqmr_queries();
+                    }
+                    SessionId::InboundSessionId(inbound_session_id) => {
+                        self.inbound_session_id_to_peer_id.remove(&inbound_session_id);
+                        self.remove_pending_inbound_query(inbound_session_id);
+                        // Dropping the receiver lets the server stop preparing responses that
+                        // can't be sent.
+                        self.sqmr_inbound_response_receivers.remove(&inbound_session_id);
+                    }
                 }
+                self.update_session_pools_usage();
             }
             sqmr::behaviour::ExternalEvent::SessionFinishedSuccessfully { session_id } => {
                 debug!("Session completed successfully. session_id: {session_id:?}");
+                self.network_event_log.record(NetworkEventKind::SqmrSessionFinished, None, || {
+                    format!("Session id: {session_id:?}")
+                });
                 self.report_session_removed_to_metrics(session_id);
                 if let SessionId::OutboundSessionId(outbound_session_id) = session_id {
-                    self.outbound_session_id_to_protocol.remove(&outbound_session_id);
+                    self.remove_outbound_session(outbound_session_id);
+                    self.send_pending_sqmr_queries();
                 }
             }
         }
@@ -382,6 +1486,15 @@ impl<SwarmT: SwarmTrait> GenericNetworkManager<SwarmT> {
     fn handle_gossipsub_behaviour_event(&mut self, event: gossipsub_impl::ExternalEvent) {
         match event {
             gossipsub_impl::ExternalEvent::Received { originated_peer_id, message, topic_hash } => {
+                record_gossipsub_message(
+                    self.topic_name(&topic_hash),
+                    MessageDirection::Received,
+                    message.len(),
+                );
+                if topic_hash == BLOCK_RANGE_ADVERTISEMENT_TOPIC.topic().hash() {
+                    self.handle_block_range_advertisement(originated_peer_id, message);
+                    return;
+                }
                 let report_callback =
                     self.create_external_callback_for_received_data(originated_peer_id);
                 let Some(sender) = self.broadcasted_messages_senders.get_mut(&topic_hash) else {
@@ -391,10 +1504,13 @@ impl<SwarmT: SwarmTrait> GenericNetworkManager<SwarmT> {
                     );
                     return;
                 };
-                let send_result = sender.try_send((message, report_callback));
+                let memory_guard = self.memory_budget.reserve(message.len());
+                let send_result = sender.try_send((message, report_callback, memory_guard));
                 if let Err(e) = send_result {
                     if e.is_disconnected() {
-                        panic!("Receiver was dropped. This should never happen.")
+                        // The subscriber doesn't receive the topic's messages anymore, though it
+                        // might still broadcast on it.
+                        self.unsubscribe_from_topic(&topic_hash);
                     } else if e.is_full() {
                         error!(
                             "Receiver buffer is full. Dropping broadcasted message for topic with \
@@ -403,21 +1519,72 @@ impl<SwarmT: SwarmTrait> GenericNetworkManager<SwarmT> {
                     }
                 }
             }
+            gossipsub_impl::ExternalEvent::PeerSubscribed { peer_id, topic_hash } => {
+                self.network_event_log.record(
+                    NetworkEventKind::GossipsubPeerSubscribed,
+                    Some(peer_id),
+                    || format!("Topic hash: {topic_hash}"),
+                );
+            }
+            gossipsub_impl::ExternalEvent::PeerUnsubscribed { peer_id, topic_hash } => {
+                self.network_event_log.record(
+                    NetworkEventKind::GossipsubPeerUnsubscribed,
+                    Some(peer_id),
+                    || format!("Topic hash: {topic_hash}"),
+                );
+            }
+        }
+    }
+
+    fn handle_block_range_advertisement(&mut self, peer_id: PeerId, message: Bytes) {
+        let advertisement = match BlockRangeAdvertisement::try_from(message) {
+            Ok(advertisement) => advertisement,
+            Err(error) => {
+                warn!("Received an invalid block range advertisement from {peer_id:?}: {error}");
+                self.swarm.report_peer(peer_id);
+                return;
+            }
+        };
+        for protocol_block_range in advertisement.ranges {
+            // Peers may advertise protocols we don't know, so these are ignored.
+            let Some(protocol) = StreamProtocol::try_from_owned(protocol_block_range.protocol)
+                .ok()
+                .and_then(|protocol| Protocol::try_from(protocol).ok())
+            else {
+                continue;
+            };
+            self.swarm.update_peer_advertised_block_range(
+                peer_id,
+                self.protocol_names.stream_protocol(protocol),
+                protocol_block_range.block_range,
+            );
         }
     }
 
     fn handle_response_for_inbound_query(&mut self, res: (InboundSessionId, Option<Bytes>)) {
         let (inbound_session_id, maybe_data) = res;
+        // The server started responding to the query, or dropped it.
+        self.remove_pending_inbound_query(inbound_session_id);
         match maybe_data {
             Some(data) => {
-                self.swarm.send_data(data, inbound_session_id).unwrap_or_else(|e| {
-                    error!(
+                let num_bytes = data.len() as u64;
+                match self.swarm.send_data(data, inbound_session_id) {
+                    Ok(()) => {
+                        if let Some(peer_id) =
+                            self.inbound_session_id_to_peer_id.get(&inbound_session_id)
+                        {
+                            self.swarm.add_served_bytes(*peer_id, num_bytes);
+                        }
+                    }
+                    Err(e) => error!(
                         "Failed to send data to peer. Session id: {inbound_session_id:?} not \
                          found error: {e:?}"
-                    );
-                });
+                    ),
+                }
             }
             None => {
+                self.inbound_session_id_to_peer_id.remove(&inbound_session_id);
+                self.update_session_pools_usage();
                 self.swarm.close_inbound_session(inbound_session_id).unwrap_or_else(|e| {
                     error!(
                         "Failed to close session after sending all data. Session id: \
@@ -428,16 +1595,94 @@ impl<SwarmT: SwarmTrait> GenericNetworkManager<SwarmT> {
         };
     }
 
-    fn handle_local_sqmr_query(&mut self, protocol: Protocol, query: Bytes) {
-        match self.swarm.send_query(query, PeerId::random(), protocol) {
+    fn handle_local_sqmr_query(
+        &mut self,
+        lane: SqmrClientLane,
+        query: Bytes,
+        priority: QueryPriority,
+    ) {
+        if self.attach_to_identical_session(lane, &query) {
+            return;
+        }
+        self.pending_outbound_queries.push(lane, query, priority, Instant::now());
+        self.send_pending_sqmr_queries();
+    }
+
+    // Sends the pending queries by their priority until the number of active outbound sessions
+    // reaches its limit. While the memory budget is exhausted the queries wait, since their
+    // responses would need more memory.
+    fn send_pending_sqmr_queries(&mut self) {
+        let now = Instant::now();
+        while self.outbound_sessions.len() < self.max_concurrent_outbound_sessions
+            && !self.is_backpressured()
+        {
+            let Some((lane, query)) = self.pending_outbound_queries.pop(now) else {
+                break;
+            };
+            // An identical query might have been sent while the query waited.
+            if !self.attach_to_identical_session(lane, &query) {
+                self.send_sqmr_query(lane, query);
+            }
+        }
+        self.update_session_pools_usage();
+        for priority in all::<QueryPriority>() {
+            gauge!(
+                papyrus_metrics::PAPYRUS_NUM_PENDING_OUTBOUND_QUERIES,
+                self.pending_outbound_queries.len(priority) as f64,
+                "priority" => priority.as_str()
+            );
+        }
+    }
+
+    fn send_sqmr_query(&mut self, lane: SqmrClientLane, query: Bytes) {
+        let block_range = self
+            .sqmr_outbound_query_block_range_extractors
+            .get(&lane.protocol)
+            .and_then(|extract_block_range_fn| extract_block_range_fn(&query));
+        let recorded_query = self
+            .sqmr_outbound_response_recorders
+            .contains_key(&lane.protocol)
+            .then(|| query.clone());
+        let shareable_query = query.clone();
+        match self.swarm.send_query(
+            query,
+            PeerId::random(),
+            self.protocol_names.outbound_stream_protocols(lane.protocol),
+        ) {
             Ok(outbound_session_id) => {
                 debug!("Sent query to peer. outbound_session_id: {outbound_session_id:?}");
+                // The peer is recorded once the session is assigned to it.
+                self.network_event_log.record(
+                    NetworkEventKind::SqmrOutboundSessionStarted,
+                    None,
+                    || format!("Session id: {outbound_session_id:?}, protocol: {}", lane.protocol),
+                );
+                // The session is assigned to a peer only once the swarm is polled, so the block
+                // range is set before the assignment.
+                if let Some(block_range) = block_range {
+                    self.swarm.set_outbound_session_block_range(outbound_session_id, block_range);
+                }
                 self.num_active_outbound_sessions += 1;
                 gauge!(
                     papyrus_metrics::PAPYRUS_NUM_ACTIVE_OUTBOUND_SESSIONS,
                     self.num_active_outbound_sessions as f64
                 );
-                self.outbound_session_id_to_protocol.insert(outbound_session_id, protocol);
+                self.outbound_sessions.insert(
+                    outbound_session_id,
+                    OutboundSession {
+                        lanes: vec![lane],
+                        shareable_query: Some(shareable_query.clone()),
+                    },
+                );
+                // A query that a lane sends again replaces its previous session as the one that
+                // identical queries share.
+                self.shareable_outbound_sessions
+                    .insert((lane.protocol, shareable_query), outbound_session_id);
+                if let Some(query) = recorded_query {
+                    self.outbound_session_id_to_recorded_query
+                        .insert(outbound_session_id, (self.next_recorded_session_id, query));
+                    self.next_recorded_session_id += 1;
+                }
             }
             Err(e) => {
                 info!(
@@ -448,8 +1693,340 @@ impl<SwarmT: SwarmTrait> GenericNetworkManager<SwarmT> {
         }
     }
 
+    // Makes the lane receive the responses of an active session of an identical query, instead of
+    // sending the query again. A query is identical only if all its fields are, including its
+    // direction, start, limit and step. The session is shared only until it receives its first
+    // response, so that the lane doesn't miss any of the responses.
+    fn attach_to_identical_session(&mut self, lane: SqmrClientLane, query: &Bytes) -> bool {
+        let Some((outbound_session_id, session)) = self
+            .shareable_outbound_sessions
+            .get(&(lane.protocol, query.clone()))
+            .and_then(|outbound_session_id| {
+                Some((*outbound_session_id, self.outbound_sessions.get_mut(outbound_session_id)?))
+            })
+        else {
+            return false;
+        };
+        // A lane that sends its query again, such as after it timed out waiting for the responses,
+        // gets a new session.
+        if session.lanes.contains(&lane) {
+            return false;
+        }
+        session.lanes.push(lane);
+        debug!(
+            "Attached a query of {} to the identical query of session {outbound_session_id:?}.",
+            lane.protocol
+        );
+        increment_counter!(
+            papyrus_metrics::PAPYRUS_NUM_DEDUPLICATED_OUTBOUND_QUERIES,
+            "protocol" => lane.protocol.as_str()
+        );
+        true
+    }
+
+    fn stop_sharing_outbound_session(&mut self, outbound_session_id: OutboundSessionId) {
+        let Some(session) = self.outbound_sessions.get_mut(&outbound_session_id) else {
+            return;
+        };
+        let Some(query) = session.shareable_query.take() else {
+            return;
+        };
+        let key = (session.lanes[0].protocol, query);
+        if self.shareable_outbound_sessions.get(&key) == Some(&outbound_session_id) {
+            self.shareable_outbound_sessions.remove(&key);
+        }
+    }
+
+    fn remove_outbound_session(
+        &mut self,
+        outbound_session_id: OutboundSessionId,
+    ) -> Option<OutboundSession> {
+        self.swarm.finish_outbound_session(outbound_session_id);
+        self.stop_sharing_outbound_session(outbound_session_id);
+        self.outbound_session_id_to_recorded_query.remove(&outbound_session_id);
+        self.paused_outbound_sessions.remove(&outbound_session_id);
+        self.outbound_sessions.remove(&outbound_session_id)
+    }
+
+    // Sends the item to the lane without waiting, unless the lane's subscriber dropped its response
+    // receiver. If the subscriber's buffer is full, the item waits in the lane's backlog and the
+    // session it came from is paused until the backlogs of all its lanes are sent. The other lanes
+    // of the sessions the lane shared keep receiving their responses.
+    fn send_to_lane(
+        &mut self,
+        lane: SqmrClientLane,
+        item: ReceivedResponse,
+        outbound_session_id: Option<OutboundSessionId>,
+    ) {
+        let Some(response_sender) = self.sqmr_outbound_response_senders.get_mut(&lane) else {
+            return;
+        };
+        // The items of a lane are sent in the order they were received.
+        let backlog = self.sqmr_outbound_response_backlogs.entry(lane).or_default();
+        let item = if backlog.is_empty() {
+            match response_sender.try_send(item) {
+                Ok(()) => return,
+                Err(error) if error.is_full() => error.into_inner(),
+                Err(_) => {
+                    self.remove_lane(lane);
+                    return;
+                }
+            }
+        } else {
+            item
+        };
+        backlog.push_back((outbound_session_id, item));
+        if let Some(outbound_session_id) = outbound_session_id {
+            if self.paused_outbound_sessions.insert(outbound_session_id) {
+                debug!(
+                    "The subscriber of lane {} of {} is slow. Pausing session \
+                     {outbound_session_id:?}.",
+                    lane.index, lane.protocol
+                );
+                self.swarm.pause_outbound_session(outbound_session_id);
+            }
+        }
+    }
+
+    // Sends the lane's backlog until the subscriber's buffer is full again, and resumes the
+    // sessions that no longer have responses waiting in the backlogs.
+    fn send_lane_backlog(&mut self, lane: SqmrClientLane) {
+        let Some(backlog) = self.sqmr_outbound_response_backlogs.get_mut(&lane) else {
+            return;
+        };
+        let Some(response_sender) = self.sqmr_outbound_response_senders.get_mut(&lane) else {
+            self.remove_lane(lane);
+            return;
+        };
+        while let Some((outbound_session_id, item)) = backlog.pop_front() {
+            match response_sender.try_send(item) {
+                Ok(()) => {}
+                Err(error) if error.is_full() => {
+                    backlog.push_front((outbound_session_id, error.into_inner()));
+                    break;
+                }
+                Err(_) => {
+                    self.remove_lane(lane);
+                    break;
+                }
+            }
+        }
+        if self.sqmr_outbound_response_backlogs.get(&lane).is_some_and(VecDeque::is_empty) {
+            self.sqmr_outbound_response_backlogs.remove(&lane);
+        }
+        self.resume_drained_outbound_sessions();
+    }
+
+    // Stops sending responses to a lane whose subscriber dropped its response receiver.
+    fn remove_lane(&mut self, lane: SqmrClientLane) {
+        warn!(
+            "The subscriber of lane {} of {} dropped its response receiver. Not sending it \
+             responses anymore.",
+            lane.index, lane.protocol
+        );
+        self.sqmr_outbound_response_senders.remove(&lane);
+        self.sqmr_outbound_response_backlogs.remove(&lane);
+        self.resume_drained_outbound_sessions();
+    }
+
+    fn resume_drained_outbound_sessions(&mut self) {
+        let backlogged_sessions: HashSet<OutboundSessionId> = self
+            .sqmr_outbound_response_backlogs
+            .values()
+            .flatten()
+            .filter_map(|(outbound_session_id, _)| *outbound_session_id)
+            .collect();
+        let drained_sessions: Vec<OutboundSessionId> = self
+            .paused_outbound_sessions
+            .iter()
+            .filter(|outbound_session_id| !backlogged_sessions.contains(outbound_session_id))
+            .copied()
+            .collect();
+        for outbound_session_id in drained_sessions {
+            self.paused_outbound_sessions.remove(&outbound_session_id);
+            debug!("Resuming session {outbound_session_id:?}.");
+            self.swarm.resume_outbound_session(outbound_session_id);
+        }
+    }
+
+    fn record_sqmr_response(
+        &mut self,
+        protocol: Protocol,
+        outbound_session_id: OutboundSessionId,
+        peer_id: PeerId,
+        response: &Bytes,
+    ) {
+        let (Some(recorder), Some((session_id, query))) = (
+            self.sqmr_outbound_response_recorders.get(&protocol),
+            self.outbound_session_id_to_recorded_query.get(&outbound_session_id),
+        ) else {
+            return;
+        };
+        let record = RecordedSqmrResponse {
+            protocol,
+            session_id: *session_id,
+            query: query.clone(),
+            peer_id,
+            response: response.clone(),
+        };
+        if recorder.unbounded_send(record).is_err() {
+            warn!("The recorder of the {protocol} responses was dropped. Not recording them.");
+            self.sqmr_outbound_response_recorders.remove(&protocol);
+        }
+    }
+
+    // Sends the failure of a query to the lane it was sent on, in place of its responses. Like the
+    // responses, it waits in the lane's backlog if the subscriber's buffer is full.
+    fn send_query_failure(&mut self, lane: SqmrClientLane, error: SqmrQueryError) {
+        warn!("A query of {} failed: {error}", lane.protocol);
+        let report_callback: ReportCallback = Box::new(|| {});
+        let apply_hints_callback: ApplyHintsCallback = Box::new(|_| {});
+        let memory_guard = self.memory_budget.reserve(0);
+        self.send_to_lane(
+            lane,
+            (Err(error), report_callback, apply_hints_callback, memory_guard),
+            None,
+        );
+    }
+
+    // Leaves the topic's mesh, so that peers stop sending its messages to the node. Broadcasting on
+    // the topic still works, since gossipsub publishes to topics it isn't subscribed to as well.
+    fn unsubscribe_from_topic(&mut self, topic_hash: &TopicHash) {
+        self.broadcasted_messages_senders.remove(topic_hash);
+        let Some(index) =
+            self.subscribed_topics.iter().position(|topic| topic.hash() == *topic_hash)
+        else {
+            return;
+        };
+        let topic = self.subscribed_topics.remove(index);
+        info!("The subscriber of topic {topic} stopped receiving its messages. Unsubscribing.");
+        self.swarm.unsubscribe_from_topic(&topic);
+    }
+
     fn broadcast_message(&mut self, message: Bytes, topic_hash: TopicHash) {
-        self.swarm.broadcast_message(message, topic_hash);
+        if let Err(error) = self.publish_message(message, topic_hash.clone()) {
+            // TODO(shahak): Consider reporting to the subscriber broadcast failures or retrying
+            // upon failure.
+            error!(
+                "Error occured while broadcasting a message to the topic with hash \
+                 {topic_hash:?}: {error}"
+            );
+        }
+    }
+
+    // Publishes the message on the topic and records its size.
+    fn publish_message(
+        &mut self,
+        message: Bytes,
+        topic_hash: TopicHash,
+    ) -> Result<(), BroadcastError> {
+        let size = message.len();
+        let topic_name = self.topic_name(&topic_hash).to_string();
+        let result = self.swarm.broadcast_message(message, topic_hash);
+        match &result {
+            Ok(()) => record_gossipsub_message(&topic_name, MessageDirection::Sent, size),
+            Err(BroadcastError::MessageTooLarge) => {
+                record_oversized_gossipsub_message(&topic_name, size)
+            }
+            Err(_) => {}
+        }
+        result
+    }
+
+    fn topic_name<'a>(&'a self, topic_hash: &'a TopicHash) -> &'a str {
+        self.topic_names.get(topic_hash).map_or(topic_hash.as_str(), String::as_str)
+    }
+
+    // Whether reading from the peers and sending new queries should wait for the components to take
+    // the items buffered for them. The backpressure is lifted once every MAX_BACKPRESSURE_PAUSE,
+    // even if the budget is still exhausted, so that the connections are kept alive and a
+    // component that waits for other data from the network before taking its items can't stall
+    // the network forever.
+    fn is_backpressured(&self) -> bool {
+        self.memory_budget.is_exhausted()
+            && self.last_swarm_event_time.elapsed() < MAX_BACKPRESSURE_PAUSE
+    }
+
+    // Whether reading from the peers should wait for the subscriber of a lane to take the responses
+    // in the lane's backlog. The sessions of a slow subscriber are paused, but the responses their
+    // peers sent before the pause took effect keep arriving, and this limit stops them from piling
+    // up in the backlog. Like the memory budget, it's lifted once every MAX_BACKPRESSURE_PAUSE.
+    fn has_full_lane_backlog(&self) -> bool {
+        self.last_swarm_event_time.elapsed() < MAX_BACKPRESSURE_PAUSE
+            && self
+                .sqmr_outbound_response_backlogs
+                .values()
+                .any(|backlog| backlog.len() >= self.max_lane_backlog_size)
+    }
+
+    // Whether a new inbound query of the protocol should be rejected since too many of its queries
+    // are pending.
+    fn is_overloaded(&self, protocol: Protocol) -> bool {
+        let Some(queue_config) = self.inbound_query_queues.get(&protocol) else {
+            return false;
+        };
+        queue_config.overload_policy == InboundQueryOverloadPolicy::Reject
+            && self.num_pending_inbound_queries.get(&protocol).copied().unwrap_or_default()
+                >= queue_config.max_pending_queries
+    }
+
+    // The limit of inbound sessions a new session of the peer would exceed, if any.
+    fn reached_inbound_session_limit(&self, peer_id: PeerId) -> Option<&'static str> {
+        if self.inbound_session_id_to_peer_id.len() >= self.inbound_session_limits.max_sessions {
+            return Some("global");
+        }
+        let num_peer_sessions = self
+            .inbound_session_id_to_peer_id
+            .values()
+            .filter(|session_peer_id| **session_peer_id == peer_id)
+            .count();
+        (num_peer_sessions >= self.inbound_session_limits.max_sessions_per_peer)
+            .then_some("per_peer")
+    }
+
+    // The inbound pool has the sessions whose query was given to a server, and the outbound pool
+    // has the sessions of the queries that were sent.
+    fn update_session_pools_usage(&self) {
+        let session_pools = SessionPools {
+            inbound: SessionPoolUsage {
+                num_active_sessions: self.inbound_session_id_to_peer_id.len(),
+                max_sessions: self.inbound_session_limits.max_sessions,
+            },
+            outbound: SessionPoolUsage {
+                num_active_sessions: self.outbound_sessions.len(),
+                max_sessions: self.max_concurrent_outbound_sessions,
+            },
+        };
+        gauge!(
+            papyrus_metrics::PAPYRUS_INBOUND_SESSION_POOL_UTILIZATION,
+            session_pools.inbound.utilization()
+        );
+        gauge!(
+            papyrus_metrics::PAPYRUS_OUTBOUND_SESSION_POOL_UTILIZATION,
+            session_pools.outbound.utilization()
+        );
+        *self.session_pools_usage.lock().expect("Session pools lock should not be poisoned") =
+            session_pools;
+    }
+
+    fn remove_pending_inbound_query(&mut self, inbound_session_id: InboundSessionId) {
+        if let Some(protocol) = self.pending_inbound_queries.remove(&inbound_session_id) {
+            self.update_num_pending_inbound_queries(protocol, |num_pending| num_pending - 1);
+        }
+    }
+
+    fn update_num_pending_inbound_queries(
+        &mut self,
+        protocol: Protocol,
+        update_fn: impl FnOnce(usize) -> usize,
+    ) {
+        let num_pending = self.num_pending_inbound_queries.entry(protocol).or_default();
+        *num_pending = update_fn(*num_pending);
+        gauge!(
+            papyrus_metrics::PAPYRUS_NUM_PENDING_INBOUND_QUERIES,
+            *num_pending as f64,
+            "protocol" => protocol.as_str()
+        );
     }
 
     fn report_session_removed_to_metrics(&mut self, session_id: SessionId) {
@@ -462,7 +2039,7 @@ impl<SwarmT: SwarmTrait> GenericNetworkManager<SwarmT> {
                 );
             }
             SessionId::OutboundSessionId(_) => {
-                self.num_active_outbound_sessions += 1;
+                self.num_active_outbound_sessions -= 1;
                 gauge!(
                     papyrus_metrics::PAPYRUS_NUM_ACTIVE_OUTBOUND_SESSIONS,
                     self.num_active_outbound_sessions as f64
@@ -480,47 +2057,236 @@ impl<SwarmT: SwarmTrait> GenericNetworkManager<SwarmT> {
             let _ = reported_peer_sender.unbounded_send(originated_peer_id);
         })
     }
+
+    fn create_apply_hints_callback(&self, peer_id: PeerId) -> ApplyHintsCallback {
+        let data_availability_hints_sender = self.data_availability_hints_sender.clone();
+        Box::new(move |hints| {
+            if !hints.is_empty() {
+                // The network manager might have stopped running.
+                let _ = data_availability_hints_sender.unbounded_send((peer_id, hints));
+            }
+        })
+    }
+}
+
+// An event that the network manager handles while it doesn't poll the swarm.
+enum LoopEvent {
+    Swarm(SwarmEvent<mixed_behaviour::Event>),
+    ResponseForInboundQuery((InboundSessionId, Option<Bytes>)),
+    LocalSqmrQuery { lane: SqmrClientLane, query: Bytes, priority: QueryPriority },
+    // The subscriber of the lane has room for the responses in its backlog, or dropped its
+    // response receiver.
+    LaneReadyForBacklog(SqmrClientLane),
+    MessageToBroadcast { topic_hash: TopicHash, message: Bytes },
+    // The subscriber of the topic dropped the sender of its messages.
+    BroadcastSenderDropped(TopicHash),
+    MessageToPublish { topic_hash: TopicHash, message_to_publish: MessageToPublish },
+    ReportedPeer(PeerId),
+    DataAvailabilityHints { peer_id: PeerId, hints: Vec<DataAvailabilityHint> },
+    PeerManagerCommand(PeerManagerCommand),
+    // The memory budget is available again, or the swarm wasn't polled for
+    // MAX_BACKPRESSURE_PAUSE.
+    BackpressureEnded,
+    PingIntervalChanged(Duration),
+}
+
+// Returns a lane that has responses in its backlog and whose subscriber has room for them, or
+// dropped its response receiver. Pending while there's no such lane.
+fn poll_lane_ready_for_backlog(
+    response_senders: &mut HashMap<SqmrClientLane, Sender<ReceivedResponse>>,
+    backlogs: &HashMap<SqmrClientLane, VecDeque<(Option<OutboundSessionId>, ReceivedResponse)>>,
+    cx: &mut Context<'_>,
+) -> Poll<SqmrClientLane> {
+    for (lane, backlog) in backlogs {
+        if backlog.is_empty() {
+            continue;
+        }
+        let Some(response_sender) = response_senders.get_mut(lane) else {
+            return Poll::Ready(*lane);
+        };
+        if response_sender.poll_ready(cx).is_ready() {
+            return Poll::Ready(*lane);
+        }
+    }
+    Poll::Pending
+}
+
+// Returns the ping interval once it changes. Never returns if the interval can't change.
+async fn next_ping_interval(
+    ping_interval_updates: &mut Option<watch::Receiver<Duration>>,
+) -> Duration {
+    match ping_interval_updates {
+        Some(ping_interval) if ping_interval.changed().await.is_ok() => {
+            *ping_interval.borrow_and_update()
+        }
+        _ => std::future::pending().await,
+    }
 }
 
 pub type NetworkManager = GenericNetworkManager<Swarm<mixed_behaviour::MixedBehaviour>>;
 
 impl NetworkManager {
-    pub fn new(config: NetworkConfig) -> Self {
+    pub fn get_local_peer_id(&self) -> String {
+        self.swarm.local_peer_id().to_string()
+    }
+
+    /// Returns a handle to the number of bytes served to each peer, which keeps updating while the
+    /// network manager runs.
+    pub fn served_bytes_by_peer(&self) -> ServedBytesByPeer {
+        self.swarm.behaviour().peer_manager.served_bytes_by_peer()
+    }
+
+    /// Returns a handle to the protocol name each peer negotiated the sessions of each protocol
+    /// with, which keeps updating while the network manager runs.
+    pub fn negotiated_protocols_by_peer(&self) -> NegotiatedProtocolsByPeer {
+        self.swarm.behaviour().peer_manager.negotiated_protocols_by_peer()
+    }
+
+    /// Returns a handle to what each peer reported about itself in the identify protocol, which
+    /// keeps updating while the network manager runs.
+    pub fn peer_identities(&self) -> PeerIdentities {
+        self.swarm.behaviour().peer_manager.peer_identities()
+    }
+
+    /// Returns a handle to the round trip times and the lost pings of each peer, which keeps
+    /// updating while the network manager runs.
+    pub fn peer_latencies(&self) -> PeerLatencies {
+        self.swarm.behaviour().peer_manager.peer_latencies()
+    }
+}
+
+pub type NetworkManagerBuilder =
+    GenericNetworkManagerBuilder<Swarm<mixed_behaviour::MixedBehaviour>>;
+
+impl NetworkManagerBuilder {
+    pub fn new(config: NetworkConfig, chain_id: ChainId) -> Self {
+        let inbound_query_queues = all::<Protocol>()
+            .map(|protocol| (protocol, config.inbound_query_queue(protocol)))
+            .collect();
+        let inbound_session_limits = config.inbound_session_limits();
+        let outbound_session_limits = config.outbound_session_limits();
         let NetworkConfig {
             tcp_port,
             quic_port: _,
             session_timeout,
             idle_connection_timeout,
             header_buffer_size,
+            sqmr_subscriber_buffer_size,
+            // The advertisements are sent by the node's components.
+            block_range_advertisement_interval: _,
+            block_range_advertisement_ttl,
+            peer_dial_deadline,
             bootstrap_peer_multiaddr,
             secret_key,
+            // The inbound queries are logged by the DB executor.
+            inbound_query_log_mode: _,
+            inbound_query_log_sample_rate: _,
+            inbound_query_max_blocking_reads: _,
+            inbound_query_blocks_per_read_txn: _,
+            inbound_query_cache_max_bytes: _,
+            // Collected by direction above.
+            node_role: _,
+            max_inbound_sessions: _,
+            max_inbound_sessions_per_peer: _,
+            max_outbound_sessions: _,
+            max_outbound_sessions_per_peer: _,
+            outbound_query_aging_interval,
+            total_memory_budget_bytes,
+            advertise_legacy_protocol_names,
+            disabled_protocol_versions,
+            debug_events,
+            debug_events_buffer_size,
+            // The responses are limited by the DB executor.
+            max_response_items: _,
+            max_response_bytes: _,
+            max_serving_bytes_per_sec,
+            max_serving_bytes_per_sec_per_peer,
+            // The secondary storage is opened by the node for the DB executor.
+            secondary_storage_path_prefix: _,
+            secondary_storage_reopen_interval: _,
+            max_listen_attempts,
+            // The network is restarted by the node.
+            max_network_restarts: _,
+            allowed_peers,
+            restrict_inbound_to_allowed_peers,
+            denied_peers,
+            message_size_warning_fraction,
+            ping_interval,
+            max_consecutive_lost_pings,
+            // Collected by protocol above.
+            header_inbound_query_queue: _,
+            state_diff_inbound_query_queue: _,
+            transaction_inbound_query_queue: _,
         } = config;
+        let connection_gating_config = mixed_behaviour::ConnectionGatingConfig {
+            allowed_peers,
+            restrict_inbound_to_allowed_peers,
+            denied_peers,
+        };
+        let ping_config = mixed_behaviour::PingConfig { ping_interval, max_consecutive_lost_pings };
+        let protocol_names = ProtocolNames::new(chain_id, advertise_legacy_protocol_names)
+            .with_disabled_versions(disabled_protocol_versions);
+        set_near_limit_fraction(message_size_warning_fraction);
 
         let listen_addresses = vec![
             // TODO: uncomment once quic transpot works.
             // format!("/ip4/0.0.0.0/udp/{quic_port}/quic-v1"),
             format!("/ip4/0.0.0.0/tcp/{tcp_port}"),
-        ];
-        let swarm = build_swarm(listen_addresses, idle_connection_timeout, secret_key, |key| {
-            mixed_behaviour::MixedBehaviour::new(
-                key,
-                bootstrap_peer_multiaddr.clone(),
-                sqmr::Config {
-                    session_timeout,
-                    supported_inbound_protocols: vec![
-                        Protocol::SignedBlockHeader.into(),
-                        Protocol::StateDiff.into(),
-                        Protocol::Transaction.into(),
-                    ],
-                },
-            )
-        });
-
-        Self::generic_new(swarm, header_buffer_size)
-    }
+        ]
+        .into_iter()
+        .map(|address| {
+            Multiaddr::from_str(&address)
+                .unwrap_or_else(|_| panic!("Unable to parse address {address}"))
+        })
+        .collect();
+        // The swarm that replaces this one on a restart keeps the node's peer id.
+        let secret_key = secret_key
+            .unwrap_or_else(|| libp2p::identity::ed25519::SecretKey::generate().as_ref().to_vec());
+        let swarm_protocol_names = protocol_names.clone();
+        // The network manager listens on the addresses itself, so that it can listen on them again
+        // if their listener closes.
+        let swarm_factory = move || {
+            build_swarm(Vec::new(), idle_connection_timeout, Some(secret_key.clone()), |key| {
+                mixed_behaviour::MixedBehaviour::new(
+                    key,
+                    bootstrap_peer_multiaddr.clone(),
+                    sqmr::Config {
+                        session_timeout,
+                        supported_inbound_protocols: [
+                            Protocol::SignedBlockHeader,
+                            Protocol::StateDiff,
+                            Protocol::Transaction,
+                        ]
+                        .into_iter()
+                        .flat_map(|protocol| {
+                            swarm_protocol_names.inbound_stream_protocols(protocol)
+                        })
+                        .collect(),
+                    },
+                    block_range_advertisement_ttl,
+                    peer_dial_deadline,
+                    outbound_session_limits.max_sessions_per_peer,
+                    connection_gating_config.clone(),
+                    ping_config,
+                )
+            })
+        };
 
-    pub fn get_local_peer_id(&self) -> String {
-        self.swarm.local_peer_id().to_string()
+        Self::generic_new(
+            swarm_factory(),
+            header_buffer_size,
+            sqmr_subscriber_buffer_size,
+            outbound_session_limits.max_sessions,
+            outbound_query_aging_interval,
+            protocol_names,
+            NetworkEventLog::new(debug_events, debug_events_buffer_size),
+        )
+        .with_listen_addresses(listen_addresses, max_listen_attempts)
+        .with_inbound_query_queues(inbound_query_queues)
+        .with_inbound_session_limits(inbound_session_limits)
+        .with_memory_budget(total_memory_budget_bytes)
+        .with_bandwidth_throttle(max_serving_bytes_per_sec, max_serving_bytes_per_sec_per_peer)
+        .with_swarm_factory(Box::new(swarm_factory))
     }
 }
 
@@ -544,14 +2310,15 @@ where
     let messages_to_broadcast_sender = messages_to_broadcast_sender.with(messages_to_broadcast_fn);
 
     let broadcasted_messages_fn: ReceivedMessagesConverterFn<T> =
-        |(x, report_callback)| (T::try_from(x), report_callback);
+        |(x, report_callback, _memory_guard)| (T::try_from(x), report_callback);
     let broadcasted_messages_receiver = broadcasted_messages_receiver.map(broadcasted_messages_fn);
 
     let subscriber_channels =
         BroadcastSubscriberChannels { messages_to_broadcast_sender, broadcasted_messages_receiver };
 
-    let mock_broadcasted_messages_fn: MockBroadcastedMessagesFn<T> =
-        |(x, report_call_back)| ready(Ok((Bytes::from(x), report_call_back)));
+    let mock_broadcasted_messages_fn: MockBroadcastedMessagesFn<T> = |(x, report_call_back)| {
+        ready(Ok((Bytes::from(x), report_call_back, MemoryGuard::untracked())))
+    };
     let mock_broadcasted_messages_sender =
         mock_broadcasted_messages_sender.with(mock_broadcasted_messages_fn);
 
@@ -572,6 +2339,18 @@ where
     Ok(TestSubscriberChannels { subscriber_channels, mock_network })
 }
 
+/// Returns a publisher whose messages go to the returned receiver instead of being published. Each
+/// message is received with the sender through which the test reports its publishing result.
+#[cfg(feature = "testing")]
+pub fn mock_register_broadcast_publisher<T>(
+) -> (BroadcastPublisher<T>, MockPublishedMessagesReceiver)
+where
+    Bytes: From<T>,
+{
+    let (sender, receiver) = futures::channel::mpsc::channel(CHANNEL_BUFFER_SIZE);
+    (BroadcastPublisher { sender, _message_type: PhantomData }, receiver)
+}
+
 #[cfg(feature = "testing")]
 pub fn dummy_report_callback() -> ReportCallback {
     Box::new(|| {})
@@ -580,14 +2359,92 @@ pub fn dummy_report_callback() -> ReportCallback {
 // TODO(shahak): Create a custom struct if Box dyn becomes an overhead.
 pub type ReportCallback = Box<dyn Fn() + Send>;
 
-// TODO(shahak): Add report callback.
+/// A protocol, a block and whether the peer declared it has the data of the block for the
+/// protocol.
+pub type DataAvailabilityHint = (Protocol, BlockNumber, bool);
+
+/// A response that may declare which other protocols the peer that sent it can serve data for.
+pub trait DataAvailabilityHints {
+    /// Returns the hints the response declares, each about the block of the response.
+    fn data_availability_hints(&self) -> Vec<DataAvailabilityHint>;
+}
+
+impl DataAvailabilityHints for DataOrFin<SignedBlockHeader> {
+    fn data_availability_hints(&self) -> Vec<DataAvailabilityHint> {
+        let Some((block_number, data_availability)) = self.0.as_ref().and_then(|signed_header| {
+            Some((signed_header.block_header.block_number, signed_header.data_availability?))
+        }) else {
+            return vec![];
+        };
+        vec![
+            (Protocol::Transaction, block_number, data_availability.has_body),
+            (Protocol::StateDiff, block_number, data_availability.has_state_diff),
+        ]
+    }
+}
+
+/// Applies the data availability hints of a response to the peer that sent it.
+pub type ApplyHintsCallback = Box<dyn FnOnce(Vec<DataAvailabilityHint>) + Send>;
+
+/// A query that asks for a known range of blocks.
+pub trait QueryBlockRange {
+    /// Returns the range of blocks that contains all the blocks the query asks for, or None if
+    /// it's unknown.
+    fn block_range(&self) -> Option<Range<BlockNumber>>;
+}
+
+impl QueryBlockRange for HeaderQuery {
+    // No peer holds all the blocks an open-ended query asks for, since they weren't created yet.
+    // Instead, it's sent preferably to a peer that already reached the query's first block, so that
+    // the peer sends the new blocks as it gets them.
+    fn block_range(&self) -> Option<Range<BlockNumber>> {
+        let block_range = self.0.block_range()?;
+        if self.0.is_open_ended() {
+            return Some(block_range.start..block_range.start);
+        }
+        Some(block_range)
+    }
+}
+
+impl QueryBlockRange for StateDiffQuery {
+    fn block_range(&self) -> Option<Range<BlockNumber>> {
+        self.0.block_range()
+    }
+}
+
+impl QueryBlockRange for TransactionQuery {
+    fn block_range(&self) -> Option<Range<BlockNumber>> {
+        self.query.block_range()
+    }
+}
+
+type QueryBlockRangeFn = fn(&Bytes) -> Option<Range<BlockNumber>>;
+
+type SwarmFactory<SwarmT> = Box<dyn Fn() -> SwarmT + Send>;
+
+// The time between attempts to listen on an address.
+const LISTEN_RETRY_INTERVAL: Duration = Duration::from_secs(1);
+
+// The longest time the swarm isn't polled while the memory budget is exhausted or a lane's backlog
+// is full.
+const MAX_BACKPRESSURE_PAUSE: Duration = Duration::from_secs(1);
+
+// The number of responses in a lane's backlog from which the swarm isn't polled, unless the network
+// manager was built with another limit.
+const DEFAULT_MAX_LANE_BACKLOG_SIZE: usize = 100;
+
+// An inbound query with the sender of its responses, the peer that sent it, a callback for
+// reporting the peer and the registration of the query in the memory budget.
+type ReceivedQuery = (Bytes, Sender<Bytes>, PeerId, ReportCallback, MemoryGuard);
+
 pub type SqmrQueryReceiver<Query, Response> =
-    Map<Receiver<(Bytes, Sender<Bytes>)>, ReceivedQueryConverterFn<Query, Response>>;
+    Map<Receiver<ReceivedQuery>, ReceivedQueryConverterFn<Query, Response>>;
 
 type ReceivedQueryConverterFn<Query, Response> =
     fn(
-        (Bytes, Sender<Bytes>),
-    ) -> (Result<Query, <Query as TryFrom<Bytes>>::Error>, SubscriberSender<Response>);
+        ReceivedQuery,
+    )
+        -> (Result<Query, <Query as TryFrom<Bytes>>::Error>, SubscriberSender<Response>, PeerId);
 
 pub type SubscriberSender<T> = With<
     Sender<Bytes>,
@@ -598,16 +2455,215 @@ pub type SubscriberSender<T> = With<
 >;
 
 // TODO(shahak): rename to ConvertFromBytesReceiver and add an alias called BroadcastReceiver
-pub type SubscriberReceiver<T> =
-    Map<Receiver<(Bytes, ReportCallback)>, ReceivedMessagesConverterFn<T>>;
+pub type SubscriberReceiver<T> = Map<Receiver<ReceivedMessage>, ReceivedMessagesConverterFn<T>>;
 
 type ReceivedMessagesConverterFn<T> =
-    fn((Bytes, ReportCallback)) -> (Result<T, <T as TryFrom<Bytes>>::Error>, ReportCallback);
+    fn(ReceivedMessage) -> (Result<T, <T as TryFrom<Bytes>>::Error>, ReportCallback);
+
+// A broadcasted message, with a callback for reporting the peer that sent it and the registration
+// of the message in the memory budget.
+type ReceivedMessage = (Bytes, ReportCallback, MemoryGuard);
+
+// The messages a subscriber broadcasts on a topic, followed by None once it dropped its sender.
+type MessagesToBroadcastReceiver = stream::Chain<
+    Map<Receiver<Bytes>, fn(Bytes) -> Option<Bytes>>,
+    stream::Once<Ready<Option<Bytes>>>,
+>;
+
+fn notify_when_sender_dropped(receiver: Receiver<Bytes>) -> MessagesToBroadcastReceiver {
+    let to_message: fn(Bytes) -> Option<Bytes> = Some;
+    receiver.map(to_message).chain(stream::once(ready(None)))
+}
+
+// A response of an outbound session with the protocol version the session negotiated, or the
+// failure of the query, a callback for reporting the peer that sent it, a callback for applying the
+// data availability hints of the response and the registration of the response in the memory
+// budget.
+type ReceivedResponse = (
+    Result<(Bytes, ProtocolVersion), SqmrQueryError>,
+    ReportCallback,
+    ApplyHintsCallback,
+    MemoryGuard,
+);
+
+pub type SqmrResponseReceiver<Response> =
+    Map<Receiver<ReceivedResponse>, SqmrResponseConverterFn<Response>>;
+
+type SqmrResponseConverterFn<Response> = fn(
+    ReceivedResponse,
+) -> (
+    Result<Response, SqmrResponseError<<Response as TryFrom<Bytes>>::Error>>,
+    ReportCallback,
+);
+
+/// The number of active sessions in each direction and their limits.
+pub type SessionPoolsUsage = Arc<Mutex<SessionPools>>;
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct SessionPools {
+    /// The sessions in which the node serves the queries of its peers.
+    pub inbound: SessionPoolUsage,
+    /// The sessions in which the node queries its peers.
+    pub outbound: SessionPoolUsage,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct SessionPoolUsage {
+    pub num_active_sessions: usize,
+    pub max_sessions: usize,
+}
+
+impl SessionPoolUsage {
+    /// The fraction of the pool's sessions that are active.
+    pub fn utilization(&self) -> f64 {
+        if self.max_sessions == 0 {
+            return 0.0;
+        }
+        self.num_active_sessions as f64 / self.max_sessions as f64
+    }
+}
+
+/// Why a query of this node failed without being answered.
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SqmrQueryError {
+    #[error("No peer was connected to send the query to within {dial_deadline:?}.")]
+    NoPeers { dial_deadline: Duration },
+}
+
+/// Why an item received from an [`SqmrResponseReceiver`] isn't a response.
+#[derive(thiserror::Error, Debug)]
+pub enum SqmrResponseError<ConversionError> {
+    /// The query failed. It's the last item received for the query.
+    #[error(transparent)]
+    Query(SqmrQueryError),
+    /// The response couldn't be decoded.
+    #[error("{0}")]
+    Conversion(ConversionError),
+}
+
+/// The priority in which a local query is sent once the number of active outbound sessions
+/// allows it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, PartialOrd, Ord, Hash, Sequence)]
+pub enum QueryPriority {
+    Low,
+    #[default]
+    Normal,
+    High,
+}
+
+impl QueryPriority {
+    const NUM_PRIORITIES: usize = 3;
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            QueryPriority::Low => "low",
+            QueryPriority::Normal => "normal",
+            QueryPriority::High => "high",
+        }
+    }
+}
+
+pub type SqmrQuerySender<Query> = With<
+    Sender<(Bytes, QueryPriority)>,
+    (Bytes, QueryPriority),
+    Query,
+    Ready<Result<(Bytes, QueryPriority), SendError>>,
+    QueryConverterFn<Query>,
+>;
+
+type QueryConverterFn<Query> = fn(Query) -> Ready<Result<(Bytes, QueryPriority), SendError>>;
+
+pub type PrioritizedSqmrQuerySender<Query> = With<
+    Sender<(Bytes, QueryPriority)>,
+    (Bytes, QueryPriority),
+    (Query, QueryPriority),
+    Ready<Result<(Bytes, QueryPriority), SendError>>,
+    PrioritizedQueryConverterFn<Query>,
+>;
+
+type PrioritizedQueryConverterFn<Query> =
+    fn((Query, QueryPriority)) -> Ready<Result<(Bytes, QueryPriority), SendError>>;
+
+/// One of the channel pairs of an sqmr client. The responses of a query are sent to the lane the
+/// query was sent on.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+struct SqmrClientLane {
+    protocol: Protocol,
+    index: usize,
+}
+
+// An active outbound session and the lanes it sends its responses to. The first lane sent the
+// query, and the others sent an identical query before the session received any response. Each
+// response is sent to the lanes without waiting for them. The responses a lane's subscriber has
+// no room for wait in the lane's backlog, and the session is paused until they're sent, so it's
+// read at the pace of its slowest lane while the other lanes get the responses already read.
+struct OutboundSession {
+    lanes: Vec<SqmrClientLane>,
+    // The query of the session, while the identical queries of other lanes can share it.
+    shareable_query: Option<Bytes>,
+}
+
+/// A response to a query of this node, as it was received from the peer that sent it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct RecordedSqmrResponse {
+    pub protocol: Protocol,
+    /// Identifies the session of the query. Each time a query is sent, it gets a new session.
+    pub session_id: u64,
+    pub query: Bytes,
+    pub peer_id: PeerId,
+    pub response: Bytes,
+}
 
 // TODO(shahak): Unite channels to a Sender of Query and Receiver of Responses.
 pub struct SqmrSubscriberChannels<Query: Into<Bytes>, Response: TryFrom<Bytes>> {
-    pub query_sender: SubscriberSender<Query>,
-    pub response_receiver: SubscriberReceiver<Response>,
+    pub query_sender: SqmrQuerySender<Query>,
+    pub prioritized_query_sender: PrioritizedSqmrQuerySender<Query>,
+    pub response_receiver: SqmrResponseReceiver<Response>,
+}
+
+/// Why a message wasn't published on its topic.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum BroadcastError {
+    #[error("No connected peer is subscribed to the topic.")]
+    NoPeers,
+    #[error("The message exceeds the maximal size of a broadcasted message.")]
+    MessageTooLarge,
+    #[error("Failed publishing the message: {0}.")]
+    PublishFailed(String),
+    #[error("The network manager stopped running.")]
+    NetworkStopped,
+}
+
+type MessageToPublish = (Bytes, oneshot::Sender<Result<(), BroadcastError>>);
+
+/// Publishes messages on the topic it was registered with (see
+/// [`register_broadcast_publisher`](GenericNetworkManagerBuilder::register_broadcast_publisher)).
+pub struct BroadcastPublisher<T> {
+    sender: Sender<MessageToPublish>,
+    _message_type: PhantomData<fn(T)>,
+}
+
+// Implemented manually since deriving would require T to implement Clone.
+impl<T> Clone for BroadcastPublisher<T> {
+    fn clone(&self) -> Self {
+        Self { sender: self.sender.clone(), _message_type: PhantomData }
+    }
+}
+
+impl<T> BroadcastPublisher<T>
+where
+    Bytes: From<T>,
+{
+    /// Publishes the message and returns once it was published to the peers, or failed to be.
+    pub async fn publish(&self, message: T) -> Result<(), BroadcastError> {
+        let (result_sender, result_receiver) = oneshot::channel();
+        self.sender
+            .clone()
+            .send((Bytes::from(message), result_sender))
+            .await
+            .map_err(|_| BroadcastError::NetworkStopped)?;
+        result_receiver.await.map_err(|_| BroadcastError::NetworkStopped)?
+    }
 }
 
 pub struct BroadcastSubscriberChannels<T: TryFrom<Bytes>> {
@@ -617,15 +2673,15 @@ pub struct BroadcastSubscriberChannels<T: TryFrom<Bytes>> {
 
 #[cfg(feature = "testing")]
 pub type MockBroadcastedMessagesSender<T> = With<
-    Sender<(Bytes, ReportCallback)>,
-    (Bytes, ReportCallback),
+    Sender<ReceivedMessage>,
+    ReceivedMessage,
     (T, ReportCallback),
-    Ready<Result<(Bytes, ReportCallback), SendError>>,
+    Ready<Result<ReceivedMessage, SendError>>,
     MockBroadcastedMessagesFn<T>,
 >;
 #[cfg(feature = "testing")]
 type MockBroadcastedMessagesFn<T> =
-    fn((T, ReportCallback)) -> Ready<Result<(Bytes, ReportCallback), SendError>>;
+    fn((T, ReportCallback)) -> Ready<Result<ReceivedMessage, SendError>>;
 #[cfg(feature = "testing")]
 pub type MockMessagesToBroadcastReceiver<T> = Map<Receiver<Bytes>, fn(Bytes) -> T>;
 #[cfg(feature = "testing")]
@@ -634,6 +2690,8 @@ pub struct BroadcastNetworkMock<T: TryFrom<Bytes>> {
     pub messages_to_broadcast_receiver: MockMessagesToBroadcastReceiver<T>,
 }
 #[cfg(feature = "testing")]
+pub type MockPublishedMessagesReceiver = Receiver<MessageToPublish>;
+#[cfg(feature = "testing")]
 pub struct TestSubscriberChannels<T: TryFrom<Bytes>> {
     pub subscriber_channels: BroadcastSubscriberChannels<T>,
     pub mock_network: BroadcastNetworkMock<T>,
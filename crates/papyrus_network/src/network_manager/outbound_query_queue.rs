@@ -0,0 +1,66 @@
+use std::collections::VecDeque;
+use std::time::{Duration, Instant};
+
+use enum_iterator::all;
+
+use super::QueryPriority;
+use crate::sqmr::Bytes;
+
+struct PendingQuery<Client> {
+    client: Client,
+    query: Bytes,
+    enqueued_at: Instant,
+}
+
+/// The local queries that wait for an outbound session slot, with a FIFO queue for each priority.
+/// Queries are dequeued by their effective priority, which is their priority raised by one level
+/// for every `aging_interval` they waited, so that low priority queries don't starve. Each query is
+/// kept with the client that sent it.
+pub(crate) struct OutboundQueryQueue<Client> {
+    aging_interval: Duration,
+    // Indexed by the priority.
+    queues: [VecDeque<PendingQuery<Client>>; QueryPriority::NUM_PRIORITIES],
+}
+
+impl<Client> OutboundQueryQueue<Client> {
+    pub fn new(aging_interval: Duration) -> Self {
+        Self { aging_interval, queues: Default::default() }
+    }
+
+    pub fn push(&mut self, client: Client, query: Bytes, priority: QueryPriority, now: Instant) {
+        self.queues[priority as usize].push_back(PendingQuery { client, query, enqueued_at: now });
+    }
+
+    /// Removes the query with the highest effective priority. Ties are broken in favor of the
+    /// query that waited longer.
+    pub fn pop(&mut self, now: Instant) -> Option<(Client, Bytes)> {
+        let (_, _, priority) = all::<QueryPriority>()
+            .filter_map(|priority| {
+                let front = self.queues[priority as usize].front()?;
+                let effective_priority = self.effective_priority(priority, front.enqueued_at, now);
+                Some((effective_priority, std::cmp::Reverse(front.enqueued_at), priority))
+            })
+            .max()?;
+        let PendingQuery { client, query, .. } =
+            self.queues[priority as usize].pop_front().expect("The chosen queue is not empty");
+        Some((client, query))
+    }
+
+    pub fn len(&self, priority: QueryPriority) -> usize {
+        self.queues[priority as usize].len()
+    }
+
+    fn effective_priority(
+        &self,
+        priority: QueryPriority,
+        enqueued_at: Instant,
+        now: Instant,
+    ) -> u128 {
+        let waited = now.saturating_duration_since(enqueued_at);
+        let num_aging_intervals = match self.aging_interval.as_nanos() {
+            0 => 0,
+            aging_interval => waited.as_nanos() / aging_interval,
+        };
+        priority as u128 + num_aging_intervals
+    }
+}
@@ -1,15 +1,22 @@
+use std::io;
+use std::ops::Range;
+use std::time::Duration;
+
 use futures::stream::Stream;
-use libp2p::gossipsub::{SubscriptionError, TopicHash};
+use libp2p::core::transport::{ListenerId, TransportError};
+use libp2p::gossipsub::{PublishError, SubscriptionError, TopicHash};
 use libp2p::swarm::dial_opts::DialOpts;
 use libp2p::swarm::{DialError, NetworkBehaviour, SwarmEvent};
-use libp2p::{Multiaddr, PeerId, Swarm};
-use tracing::error;
+use libp2p::{Multiaddr, PeerId, StreamProtocol, Swarm};
+use starknet_api::block::BlockNumber;
+use tracing::warn;
 
+use super::BroadcastError;
 use crate::gossipsub_impl::Topic;
-use crate::peer_manager::ReputationModifier;
+use crate::mixed_behaviour;
+use crate::peer_manager::{PeerManagerCommand, ReputationModifier};
 use crate::sqmr::behaviour::{PeerNotConnected, SessionIdNotFoundError};
 use crate::sqmr::{Bytes, InboundSessionId, OutboundSessionId};
-use crate::{mixed_behaviour, Protocol};
 
 pub type Event = SwarmEvent<<mixed_behaviour::MixedBehaviour as NetworkBehaviour>::ToSwarm>;
 
@@ -20,15 +27,18 @@ pub trait SwarmTrait: Stream<Item = Event> + Unpin {
         inbound_session_id: InboundSessionId,
     ) -> Result<(), SessionIdNotFoundError>;
 
+    /// Sends the query on the first of `protocols` that the assigned peer supports.
     fn send_query(
         &mut self,
         query: Vec<u8>,
         peer_id: PeerId,
-        protocol: Protocol,
+        protocols: Vec<StreamProtocol>,
     ) -> Result<OutboundSessionId, PeerNotConnected>;
 
     fn dial(&mut self, peer_multiaddr: Multiaddr) -> Result<(), DialError>;
 
+    fn listen_on(&mut self, address: Multiaddr) -> Result<ListenerId, TransportError<io::Error>>;
+
     fn num_connected_peers(&self) -> usize;
 
     fn close_inbound_session(
@@ -42,9 +52,61 @@ pub trait SwarmTrait: Stream<Item = Event> + Unpin {
 
     fn subscribe_to_topic(&mut self, topic: &Topic) -> Result<(), SubscriptionError>;
 
-    fn broadcast_message(&mut self, message: Bytes, topic_hash: TopicHash);
+    fn unsubscribe_from_topic(&mut self, topic: &Topic);
+
+    fn broadcast_message(
+        &mut self,
+        message: Bytes,
+        topic_hash: TopicHash,
+    ) -> Result<(), BroadcastError>;
 
     fn report_peer(&mut self, peer_id: PeerId);
+
+    fn handle_peer_manager_command(&mut self, command: PeerManagerCommand);
+
+    /// Pings the peers of the connections that are established from now on at the given interval.
+    fn set_ping_interval(&mut self, ping_interval: Duration);
+
+    fn add_served_bytes(&mut self, peer_id: PeerId, num_bytes: u64);
+
+    fn update_peer_block_availability(
+        &mut self,
+        peer_id: PeerId,
+        protocol: StreamProtocol,
+        block_number: BlockNumber,
+        is_available: bool,
+    );
+
+    /// Records the name the peer negotiated a session of the given protocol with.
+    fn update_peer_negotiated_protocol(
+        &mut self,
+        peer_id: PeerId,
+        protocol: StreamProtocol,
+        negotiated_protocol: StreamProtocol,
+    );
+
+    fn set_outbound_session_block_range(
+        &mut self,
+        outbound_session_id: OutboundSessionId,
+        block_range: Range<BlockNumber>,
+    );
+
+    /// Frees the session's place among the sessions of its peer, once the session finished or
+    /// failed.
+    fn finish_outbound_session(&mut self, outbound_session_id: OutboundSessionId);
+
+    /// Stops reading the responses of the outbound session until it's resumed, so that a slow
+    /// subscriber slows down only the peer of the session.
+    fn pause_outbound_session(&mut self, outbound_session_id: OutboundSessionId);
+
+    fn resume_outbound_session(&mut self, outbound_session_id: OutboundSessionId);
+
+    fn update_peer_advertised_block_range(
+        &mut self,
+        peer_id: PeerId,
+        protocol: StreamProtocol,
+        block_range: Range<BlockNumber>,
+    );
 }
 
 impl SwarmTrait for Swarm<mixed_behaviour::MixedBehaviour> {
@@ -61,15 +123,19 @@ impl SwarmTrait for Swarm<mixed_behaviour::MixedBehaviour> {
         &mut self,
         query: Vec<u8>,
         _peer_id: PeerId,
-        protocol: Protocol,
+        protocols: Vec<StreamProtocol>,
     ) -> Result<OutboundSessionId, PeerNotConnected> {
-        Ok(self.behaviour_mut().sqmr.start_query(query, protocol.into()))
+        Ok(self.behaviour_mut().sqmr.start_query(query, protocols))
     }
 
     fn dial(&mut self, peer_multiaddr: Multiaddr) -> Result<(), DialError> {
         self.dial(DialOpts::from(peer_multiaddr))
     }
 
+    fn listen_on(&mut self, address: Multiaddr) -> Result<ListenerId, TransportError<io::Error>> {
+        self.listen_on(address)
+    }
+
     fn num_connected_peers(&self) -> usize {
         self.network_info().num_peers()
     }
@@ -92,19 +158,103 @@ impl SwarmTrait for Swarm<mixed_behaviour::MixedBehaviour> {
         self.behaviour_mut().gossipsub.subscribe(topic).map(|_| ())
     }
 
-    fn broadcast_message(&mut self, message: Bytes, topic_hash: TopicHash) {
-        let result = self.behaviour_mut().gossipsub.publish(topic_hash.clone(), message);
-        if let Err(err) = result {
-            // TODO(shahak): Consider reporting to the subscriber broadcast failures or retrying
-            // upon failure.
-            error!(
-                "Error occured while broadcasting a message to the topic with hash \
-                 {topic_hash:?}: {err:?}"
-            );
+    fn unsubscribe_from_topic(&mut self, topic: &Topic) {
+        if let Err(error) = self.behaviour_mut().gossipsub.unsubscribe(topic) {
+            warn!("Failed to unsubscribe from topic {topic}: {error:?}");
+        }
+    }
+
+    fn broadcast_message(
+        &mut self,
+        message: Bytes,
+        topic_hash: TopicHash,
+    ) -> Result<(), BroadcastError> {
+        match self.behaviour_mut().gossipsub.publish(topic_hash, message) {
+            // The message was already published, so its peers already have it.
+            Ok(_) | Err(PublishError::Duplicate) => Ok(()),
+            Err(PublishError::InsufficientPeers) => Err(BroadcastError::NoPeers),
+            Err(PublishError::MessageTooLarge) => Err(BroadcastError::MessageTooLarge),
+            Err(error) => Err(BroadcastError::PublishFailed(format!("{error:?}"))),
         }
     }
 
     fn report_peer(&mut self, peer_id: PeerId) {
         let _ = self.behaviour_mut().peer_manager.report_peer(peer_id, ReputationModifier::Bad {});
     }
+
+    fn handle_peer_manager_command(&mut self, command: PeerManagerCommand) {
+        self.behaviour_mut().peer_manager.handle_command(command);
+    }
+
+    // The connections that are already established keep the handlers of the replaced behaviour,
+    // which keep pinging at the previous interval and report to the new behaviour.
+    fn set_ping_interval(&mut self, ping_interval: Duration) {
+        self.behaviour_mut().ping = mixed_behaviour::ping_behaviour(ping_interval);
+    }
+
+    fn add_served_bytes(&mut self, peer_id: PeerId, num_bytes: u64) {
+        self.behaviour_mut().peer_manager.add_served_bytes(peer_id, num_bytes);
+    }
+
+    fn update_peer_block_availability(
+        &mut self,
+        peer_id: PeerId,
+        protocol: StreamProtocol,
+        block_number: BlockNumber,
+        is_available: bool,
+    ) {
+        let _ = self.behaviour_mut().peer_manager.update_peer_block_availability(
+            peer_id,
+            protocol,
+            block_number,
+            is_available,
+        );
+    }
+
+    fn update_peer_negotiated_protocol(
+        &mut self,
+        peer_id: PeerId,
+        protocol: StreamProtocol,
+        negotiated_protocol: StreamProtocol,
+    ) {
+        self.behaviour_mut().peer_manager.update_peer_negotiated_protocol(
+            peer_id,
+            protocol,
+            negotiated_protocol,
+        );
+    }
+
+    fn set_outbound_session_block_range(
+        &mut self,
+        outbound_session_id: OutboundSessionId,
+        block_range: Range<BlockNumber>,
+    ) {
+        self.behaviour_mut().peer_manager.set_session_block_range(outbound_session_id, block_range);
+    }
+
+    fn finish_outbound_session(&mut self, outbound_session_id: OutboundSessionId) {
+        self.behaviour_mut().peer_manager.finish_session(outbound_session_id);
+    }
+
+    // The session might have ended meanwhile, and then there's nothing to pause or resume.
+    fn pause_outbound_session(&mut self, outbound_session_id: OutboundSessionId) {
+        let _ = self.behaviour_mut().sqmr.pause_outbound_session(outbound_session_id);
+    }
+
+    fn resume_outbound_session(&mut self, outbound_session_id: OutboundSessionId) {
+        let _ = self.behaviour_mut().sqmr.resume_outbound_session(outbound_session_id);
+    }
+
+    fn update_peer_advertised_block_range(
+        &mut self,
+        peer_id: PeerId,
+        protocol: StreamProtocol,
+        block_range: Range<BlockNumber>,
+    ) {
+        let _ = self.behaviour_mut().peer_manager.update_peer_advertised_block_range(
+            peer_id,
+            protocol,
+            block_range,
+        );
+    }
 }
@@ -1,31 +1,83 @@
-use std::collections::{HashMap, HashSet};
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::io;
+use std::ops::Range;
 use std::pin::Pin;
+use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::task::{Context, Poll};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 use std::vec;
 
+use assert_matches::assert_matches;
 use deadqueue::unlimited::Queue;
 use futures::channel::mpsc::{unbounded, UnboundedSender};
 use futures::channel::oneshot;
-use futures::future::{poll_fn, FutureExt};
+use futures::future::{join3, poll_fn, FutureExt};
 use futures::stream::Stream;
 use futures::{pin_mut, Future, SinkExt, StreamExt};
 use lazy_static::lazy_static;
+use libp2p::core::transport::{ListenerId, TransportError};
 use libp2p::core::ConnectedPoint;
+use libp2p::multiaddr::Protocol as MultiaddrProtocol;
 use libp2p::gossipsub::{SubscriptionError, TopicHash};
-use libp2p::swarm::ConnectionId;
-use libp2p::{Multiaddr, PeerId};
+use libp2p::swarm::{ConnectionDenied, ConnectionId, DialError, ListenError, SwarmEvent};
+use libp2p::{Multiaddr, PeerId, StreamProtocol};
+use papyrus_protobuf::consensus::ConsensusMessage;
+use papyrus_protobuf::converters::TryFromVersionedBytes;
+use papyrus_protobuf::sync::{
+    BlockHashOrNumber,
+    BlockRangeAdvertisement,
+    Direction,
+    HeaderQuery,
+    ProtocolBlockRange,
+    Query,
+};
+use starknet_api::block::BlockNumber;
+use starknet_api::core::ChainId;
 use tokio::select;
 use tokio::sync::Mutex;
 use tokio::time::sleep;
 
+use super::bandwidth_throttle::BandwidthThrottle;
+use super::event_log::NetworkEventLog;
+use super::memory_budget::ITEM_OVERHEAD_BYTES;
+use super::outbound_query_queue::OutboundQueryQueue;
 use super::swarm_trait::{Event, SwarmTrait};
-use super::{GenericNetworkManager, SqmrSubscriberChannels};
-use crate::gossipsub_impl::{self, Topic};
-use crate::mixed_behaviour;
-use crate::sqmr::behaviour::{PeerNotConnected, SessionIdNotFoundError};
-use crate::sqmr::{Bytes, GenericEvent, InboundSessionId, OutboundSessionId};
+use super::{
+    BroadcastError,
+    DataAvailabilityHint,
+    DataAvailabilityHints,
+    GenericNetworkManager,
+    GenericNetworkManagerBuilder,
+    NetworkError,
+    NetworkEventKind,
+    NetworkManagerBuilder,
+    NetworkRegistrations,
+    PeerManagerCommand,
+    QueryPriority,
+    RecordedSqmrResponse,
+    RecoverableNetworkError,
+    RegistrationError,
+    SessionPoolUsage,
+    SessionPools,
+    SqmrQueryError,
+    SqmrResponseError,
+    SqmrResponseReceiver,
+    SqmrSubscriberChannels,
+};
+use crate::connection_gating::ConnectionGatingError;
+use crate::gossipsub_impl::{self, Topic, TopicDescriptor, BLOCK_RANGE_ADVERTISEMENT_TOPIC};
+use crate::sqmr::behaviour::{PeerNotConnected, SessionError, SessionIdNotFoundError};
+use crate::sqmr::{Bytes, GenericEvent, InboundSessionId, OutboundSessionId, SessionId};
+use crate::{
+    mixed_behaviour,
+    InboundQueryOverloadPolicy,
+    InboundQueryQueueConfig,
+    NetworkConfig,
+    Protocol,
+    ProtocolNames,
+    SessionLimits,
+};
 
 const TIMEOUT: Duration = Duration::from_secs(1);
 
@@ -33,17 +85,52 @@ lazy_static! {
     static ref VEC1: Vec<u8> = vec![1, 2, 3, 4, 5];
     static ref VEC2: Vec<u8> = vec![6, 7, 8];
     static ref VEC3: Vec<u8> = vec![9, 10];
+    static ref PROTOCOL_NAMES: ProtocolNames = ProtocolNames::new(ChainId::Sepolia, false);
 }
 
 #[derive(Default)]
 struct MockSwarm {
-    pub pending_events: Queue<Event>,
+    // Shared so that tests can add events while the network manager runs.
+    pub pending_events: Arc<Queue<Event>>,
     pub subscribed_topics: HashSet<TopicHash>,
     broadcasted_messages_senders: Vec<UnboundedSender<(Bytes, TopicHash)>>,
     reported_peer_senders: Vec<UnboundedSender<PeerId>>,
+    peer_manager_command_senders: Vec<UnboundedSender<PeerManagerCommand>>,
+    served_bytes_senders: Vec<UnboundedSender<(PeerId, u64)>>,
+    block_availability_update_senders: Vec<UnboundedSender<(PeerId, Protocol, BlockNumber, bool)>>,
+    session_block_range_senders: Vec<UnboundedSender<(OutboundSessionId, Range<BlockNumber>)>>,
+    // Get each outbound session that was paused (true) or resumed (false).
+    paused_session_senders: Vec<UnboundedSender<(OutboundSessionId, bool)>>,
+    // The events of each paused outbound session are held until it's resumed, the way the
+    // connection handler stops reading the session's substream.
+    paused_outbound_sessions: HashMap<OutboundSessionId, VecDeque<Event>>,
+    // The held events of the sessions that were resumed, which are polled before the other events.
+    resumed_events: VecDeque<Event>,
+    // If set, pausing a session doesn't hold its events, as if they were all read before the pause
+    // took effect.
+    ignore_pauses: bool,
+    advertised_block_range_update_senders:
+        Vec<UnboundedSender<(PeerId, Protocol, Range<BlockNumber>)>>,
     inbound_session_id_to_response_sender: HashMap<InboundSessionId, UnboundedSender<Bytes>>,
     next_outbound_session_id: usize,
+    // Whether each outbound session finishes once all the responses to its query were received.
+    finish_outbound_sessions: bool,
+    // If set, outbound sessions fail as if no peer was connected within this dial deadline,
+    // instead of receiving responses.
+    no_peers_dial_deadline: Option<Duration>,
+    // If set, the events of outbound sessions are held in this queue instead of being polled, until
+    // the test moves them to the pending events.
+    held_outbound_session_events: Option<Arc<Queue<Event>>>,
     first_polled_event_notifier: Option<oneshot::Sender<()>>,
+    num_polled_events: Arc<AtomicUsize>,
+    // If set, broadcasts fail with this error instead of being sent to the broadcast streams.
+    broadcast_error: Option<BroadcastError>,
+    subscribed_topic_senders: Vec<UnboundedSender<TopicHash>>,
+    unsubscribed_topic_senders: Vec<UnboundedSender<TopicHash>>,
+    listened_address_senders: Vec<UnboundedSender<Multiaddr>>,
+    // The number of listeners that close right after they're opened.
+    num_listeners_to_close: usize,
+    fail_listening: bool,
 }
 
 impl Stream for MockSwarm {
@@ -51,8 +138,50 @@ impl Stream for MockSwarm {
 
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         let mut_self = self.get_mut();
-        let mut fut = mut_self.pending_events.pop().map(Some).boxed();
-        if let Some(sender) = mut_self.first_polled_event_notifier.take() {
+        loop {
+            let result = match mut_self.resumed_events.pop_front() {
+                Some(event) => Poll::Ready(Some(event)),
+                None => mut_self.poll_pending_event(cx),
+            };
+            let Poll::Ready(Some(event)) = result else {
+                return result;
+            };
+            let held_events = match outbound_session_id_of_event(&event) {
+                Some(outbound_session_id) => {
+                    mut_self.paused_outbound_sessions.get_mut(&outbound_session_id)
+                }
+                None => None,
+            };
+            if let Some(held_events) = held_events {
+                held_events.push_back(event);
+                continue;
+            }
+            mut_self.num_polled_events.fetch_add(1, Ordering::SeqCst);
+            return Poll::Ready(Some(event));
+        }
+    }
+}
+
+fn outbound_session_id_of_event(event: &Event) -> Option<OutboundSessionId> {
+    let Event::Behaviour(mixed_behaviour::Event::ExternalEvent(
+        mixed_behaviour::ExternalEvent::Sqmr(sqmr_event),
+    )) = event
+    else {
+        return None;
+    };
+    match sqmr_event {
+        GenericEvent::ReceivedData { outbound_session_id, .. }
+        | GenericEvent::SessionFinishedSuccessfully {
+            session_id: SessionId::OutboundSessionId(outbound_session_id),
+        } => Some(*outbound_session_id),
+        _ => None,
+    }
+}
+
+impl MockSwarm {
+    fn poll_pending_event(&mut self, cx: &mut Context<'_>) -> Poll<Option<Event>> {
+        let mut fut = self.pending_events.pop().map(Some).boxed();
+        if let Some(sender) = self.first_polled_event_notifier.take() {
             fut = fut
                 .then(|res| async {
                     sender.send(()).unwrap();
@@ -66,6 +195,10 @@ impl Stream for MockSwarm {
 }
 
 impl MockSwarm {
+    pub fn get_num_polled_events(&self) -> Arc<AtomicUsize> {
+        self.num_polled_events.clone()
+    }
+
     pub fn get_responses_sent_to_inbound_session(
         &mut self,
         inbound_session_id: InboundSessionId,
@@ -87,24 +220,103 @@ impl MockSwarm {
         receiver
     }
 
+    pub fn stream_subscribed_topics(&mut self) -> impl Stream<Item = TopicHash> {
+        let (sender, receiver) = unbounded();
+        self.subscribed_topic_senders.push(sender);
+        receiver
+    }
+
+    pub fn stream_unsubscribed_topics(&mut self) -> impl Stream<Item = TopicHash> {
+        let (sender, receiver) = unbounded();
+        self.unsubscribed_topic_senders.push(sender);
+        receiver
+    }
+
+    pub fn stream_listened_addresses(&mut self) -> impl Stream<Item = Multiaddr> {
+        let (sender, receiver) = unbounded();
+        self.listened_address_senders.push(sender);
+        receiver
+    }
+
     pub fn get_reported_peers_stream(&mut self) -> impl Stream<Item = PeerId> {
         let (sender, receiver) = unbounded();
         self.reported_peer_senders.push(sender);
         receiver
     }
 
+    pub fn get_served_bytes_stream(&mut self) -> impl Stream<Item = (PeerId, u64)> {
+        let (sender, receiver) = unbounded();
+        self.served_bytes_senders.push(sender);
+        receiver
+    }
+
+    pub fn get_block_availability_updates_stream(
+        &mut self,
+    ) -> impl Stream<Item = (PeerId, Protocol, BlockNumber, bool)> {
+        let (sender, receiver) = unbounded();
+        self.block_availability_update_senders.push(sender);
+        receiver
+    }
+
+    pub fn get_peer_manager_commands_stream(&mut self) -> impl Stream<Item = PeerManagerCommand> {
+        let (sender, receiver) = unbounded();
+        self.peer_manager_command_senders.push(sender);
+        receiver
+    }
+
+    pub fn get_session_block_ranges_stream(
+        &mut self,
+    ) -> impl Stream<Item = (OutboundSessionId, Range<BlockNumber>)> {
+        let (sender, receiver) = unbounded();
+        self.session_block_range_senders.push(sender);
+        receiver
+    }
+
+    pub fn get_paused_sessions_stream(&mut self) -> impl Stream<Item = (OutboundSessionId, bool)> {
+        let (sender, receiver) = unbounded();
+        self.paused_session_senders.push(sender);
+        receiver
+    }
+
+    pub fn get_advertised_block_range_updates_stream(
+        &mut self,
+    ) -> impl Stream<Item = (PeerId, Protocol, Range<BlockNumber>)> {
+        let (sender, receiver) = unbounded();
+        self.advertised_block_range_update_senders.push(sender);
+        receiver
+    }
+
     fn create_response_events_for_query_each_num_becomes_response(
         &self,
         query: Vec<u8>,
         outbound_session_id: OutboundSessionId,
         peer_id: PeerId,
+        protocol_name: StreamProtocol,
     ) {
+        let events = self.held_outbound_session_events.as_ref().unwrap_or(&self.pending_events);
+        if let Some(dial_deadline) = self.no_peers_dial_deadline {
+            events.push(Event::Behaviour(mixed_behaviour::Event::ExternalEvent(
+                mixed_behaviour::ExternalEvent::Sqmr(GenericEvent::SessionFailed {
+                    session_id: SessionId::OutboundSessionId(outbound_session_id),
+                    error: SessionError::NoPeers { dial_deadline },
+                }),
+            )));
+            return;
+        }
         for data in query {
-            self.pending_events.push(Event::Behaviour(mixed_behaviour::Event::ExternalEvent(
+            events.push(Event::Behaviour(mixed_behaviour::Event::ExternalEvent(
                 mixed_behaviour::ExternalEvent::Sqmr(GenericEvent::ReceivedData {
                     data: vec![data],
                     outbound_session_id,
                     peer_id,
+                    protocol_name: protocol_name.clone(),
+                }),
+            )));
+        }
+        if self.finish_outbound_sessions {
+            events.push(Event::Behaviour(mixed_behaviour::Event::ExternalEvent(
+                mixed_behaviour::ExternalEvent::Sqmr(GenericEvent::SessionFinishedSuccessfully {
+                    session_id: SessionId::OutboundSessionId(outbound_session_id),
                 }),
             )));
         }
@@ -129,13 +341,16 @@ impl SwarmTrait for MockSwarm {
         &mut self,
         query: Vec<u8>,
         peer_id: PeerId,
-        _protocol: crate::Protocol,
+        protocols: Vec<StreamProtocol>,
     ) -> Result<OutboundSessionId, PeerNotConnected> {
         let outbound_session_id = OutboundSessionId { value: self.next_outbound_session_id };
+        // The peer supports all the versions, so the newest one is negotiated.
+        let protocol_name = protocols.into_iter().next().expect("No protocol names were proposed");
         self.create_response_events_for_query_each_num_becomes_response(
             query,
             outbound_session_id,
             peer_id,
+            protocol_name,
         );
         self.next_outbound_session_id += 1;
         Ok(outbound_session_id)
@@ -144,6 +359,25 @@ impl SwarmTrait for MockSwarm {
     fn dial(&mut self, _peer: Multiaddr) -> Result<(), libp2p::swarm::DialError> {
         Ok(())
     }
+
+    fn listen_on(&mut self, address: Multiaddr) -> Result<ListenerId, TransportError<io::Error>> {
+        if self.fail_listening {
+            return Err(TransportError::MultiaddrNotSupported(address));
+        }
+        for sender in &self.listened_address_senders {
+            sender.unbounded_send(address.clone()).unwrap();
+        }
+        let listener_id = ListenerId::next();
+        if self.num_listeners_to_close > 0 {
+            self.num_listeners_to_close -= 1;
+            self.pending_events.push(SwarmEvent::ListenerClosed {
+                listener_id,
+                addresses: vec![address],
+                reason: Err(io::Error::other("The listener failed")),
+            });
+        }
+        Ok(listener_id)
+    }
     fn num_connected_peers(&self) -> usize {
         0
     }
@@ -168,13 +402,31 @@ impl SwarmTrait for MockSwarm {
 
     fn subscribe_to_topic(&mut self, topic: &Topic) -> Result<(), SubscriptionError> {
         self.subscribed_topics.insert(topic.hash());
+        for sender in &self.subscribed_topic_senders {
+            sender.unbounded_send(topic.hash()).unwrap();
+        }
         Ok(())
     }
 
-    fn broadcast_message(&mut self, message: Bytes, topic_hash: TopicHash) {
+    fn unsubscribe_from_topic(&mut self, topic: &Topic) {
+        self.subscribed_topics.remove(&topic.hash());
+        for sender in &self.unsubscribed_topic_senders {
+            sender.unbounded_send(topic.hash()).unwrap();
+        }
+    }
+
+    fn broadcast_message(
+        &mut self,
+        message: Bytes,
+        topic_hash: TopicHash,
+    ) -> Result<(), BroadcastError> {
+        if let Some(error) = &self.broadcast_error {
+            return Err(error.clone());
+        }
         for sender in &self.broadcasted_messages_senders {
             sender.unbounded_send((message.clone(), topic_hash.clone())).unwrap();
         }
+        Ok(())
     }
 
     fn report_peer(&mut self, peer_id: PeerId) {
@@ -182,9 +434,90 @@ impl SwarmTrait for MockSwarm {
             sender.unbounded_send(peer_id).unwrap();
         }
     }
+
+    // Commands can't be cloned, so only the first stream gets them.
+    fn handle_peer_manager_command(&mut self, command: PeerManagerCommand) {
+        if let Some(sender) = self.peer_manager_command_senders.first() {
+            sender.unbounded_send(command).unwrap();
+        }
+    }
+
+    fn set_ping_interval(&mut self, _ping_interval: Duration) {}
+
+    fn add_served_bytes(&mut self, peer_id: PeerId, num_bytes: u64) {
+        for sender in &self.served_bytes_senders {
+            sender.unbounded_send((peer_id, num_bytes)).unwrap();
+        }
+    }
+
+    fn update_peer_block_availability(
+        &mut self,
+        peer_id: PeerId,
+        protocol: StreamProtocol,
+        block_number: BlockNumber,
+        is_available: bool,
+    ) {
+        let protocol = PROTOCOL_NAMES.protocol(&protocol).unwrap();
+        for sender in &self.block_availability_update_senders {
+            sender.unbounded_send((peer_id, protocol, block_number, is_available)).unwrap();
+        }
+    }
+
+    fn update_peer_negotiated_protocol(
+        &mut self,
+        _peer_id: PeerId,
+        _protocol: StreamProtocol,
+        _negotiated_protocol: StreamProtocol,
+    ) {
+    }
+
+    fn set_outbound_session_block_range(
+        &mut self,
+        outbound_session_id: OutboundSessionId,
+        block_range: Range<BlockNumber>,
+    ) {
+        for sender in &self.session_block_range_senders {
+            sender.unbounded_send((outbound_session_id, block_range.clone())).unwrap();
+        }
+    }
+
+    fn finish_outbound_session(&mut self, _outbound_session_id: OutboundSessionId) {}
+
+    fn pause_outbound_session(&mut self, outbound_session_id: OutboundSessionId) {
+        if !self.ignore_pauses {
+            self.paused_outbound_sessions.entry(outbound_session_id).or_default();
+        }
+        for sender in &self.paused_session_senders {
+            sender.unbounded_send((outbound_session_id, true)).unwrap();
+        }
+    }
+
+    fn resume_outbound_session(&mut self, outbound_session_id: OutboundSessionId) {
+        if let Some(held_events) = self.paused_outbound_sessions.remove(&outbound_session_id) {
+            self.resumed_events.extend(held_events);
+        }
+        for sender in &self.paused_session_senders {
+            sender.unbounded_send((outbound_session_id, false)).unwrap();
+        }
+    }
+
+    fn update_peer_advertised_block_range(
+        &mut self,
+        peer_id: PeerId,
+        protocol: StreamProtocol,
+        block_range: Range<BlockNumber>,
+    ) {
+        let protocol = PROTOCOL_NAMES.protocol(&protocol).unwrap();
+        for sender in &self.advertised_block_range_update_senders {
+            sender.unbounded_send((peer_id, protocol, block_range.clone())).unwrap();
+        }
+    }
 }
 
 const BUFFER_SIZE: usize = 100;
+const MAX_CONCURRENT_OUTBOUND_SESSIONS: usize = 100;
+const AGING_INTERVAL: Duration = Duration::from_secs(10);
+const TOPIC: TopicDescriptor<Bytes> = TopicDescriptor::new("TOPIC");
 
 #[tokio::test]
 async fn register_sqmr_subscriber_and_use_channels() {
@@ -196,11 +529,22 @@ async fn register_sqmr_subscriber_and_use_channels() {
     mock_swarm.first_polled_event_notifier = Some(event_notifier);
 
     // network manager to register subscriber and send query
-    let mut network_manager = GenericNetworkManager::generic_new(mock_swarm, BUFFER_SIZE);
+    let mut network_manager_builder = GenericNetworkManagerBuilder::generic_new(
+        mock_swarm,
+        BUFFER_SIZE,
+        BUFFER_SIZE,
+        MAX_CONCURRENT_OUTBOUND_SESSIONS,
+        AGING_INTERVAL,
+        PROTOCOL_NAMES.clone(),
+        NetworkEventLog::new(false, BUFFER_SIZE),
+    );
 
     // register subscriber and send query
-    let SqmrSubscriberChannels { mut query_sender, response_receiver } = network_manager
-        .register_sqmr_subscriber::<Vec<u8>, Vec<u8>>(crate::Protocol::SignedBlockHeader);
+    let SqmrSubscriberChannels { mut query_sender, response_receiver, .. } =
+        network_manager_builder
+            .register_sqmr_subscriber::<Vec<u8>, Vec<u8>>(crate::Protocol::SignedBlockHeader)
+            .unwrap();
+    let (network_manager, _registrations) = network_manager_builder.build().unwrap();
 
     let response_receiver_length = Arc::new(Mutex::new(0));
     let cloned_response_receiver_length = Arc::clone(&response_receiver_length);
@@ -228,48 +572,126 @@ async fn register_sqmr_subscriber_and_use_channels() {
     assert_eq!(*response_receiver_length.lock().await, VEC1.len());
 }
 
-// TODO(shahak): Add multiple protocols and multiple queries in the test.
 #[tokio::test]
-async fn process_incoming_query() {
-    // Create data for test.
-    let query = VEC1.clone();
-    let responses = vec![VEC1.clone(), VEC2.clone(), VEC3.clone()];
-    let protocol = crate::Protocol::SignedBlockHeader;
+async fn slow_sqmr_subscriber_pauses_only_its_session() {
+    const SUBSCRIBER_BUFFER_SIZE: usize = 2;
+    const NUM_RESPONSES: usize = 20;
 
-    // Setup mock swarm and tell it to return an event of new inbound query.
     let mut mock_swarm = MockSwarm::default();
-    let inbound_session_id = InboundSessionId { value: 0 };
-    mock_swarm.pending_events.push(Event::Behaviour(mixed_behaviour::Event::ExternalEvent(
-        mixed_behaviour::ExternalEvent::Sqmr(GenericEvent::NewInboundSession {
-            query: query.clone(),
-            inbound_session_id,
-            peer_id: PeerId::random(),
-            protocol_name: protocol.into(),
-        }),
-    )));
-
-    // Create a future that will return when the session is closed with the data sent on the swarm.
-    let get_responses_fut = mock_swarm.get_responses_sent_to_inbound_session(inbound_session_id);
+    mock_swarm.pending_events.push(get_test_connection_established_event(PeerId::random()));
+    let mut paused_sessions = mock_swarm.get_paused_sessions_stream();
+    let num_polled_events = mock_swarm.get_num_polled_events();
 
-    let mut network_manager = GenericNetworkManager::generic_new(mock_swarm, BUFFER_SIZE);
+    let mut network_manager_builder = GenericNetworkManagerBuilder::generic_new(
+        mock_swarm,
+        BUFFER_SIZE,
+        SUBSCRIBER_BUFFER_SIZE,
+        MAX_CONCURRENT_OUTBOUND_SESSIONS,
+        AGING_INTERVAL,
+        PROTOCOL_NAMES.clone(),
+        NetworkEventLog::new(false, BUFFER_SIZE),
+    );
 
-    let mut inbound_query_receiver =
-        network_manager.register_sqmr_protocol_server::<Vec<u8>, Vec<u8>>(protocol);
+    let SqmrSubscriberChannels { mut query_sender, mut response_receiver, .. } =
+        network_manager_builder
+            .register_sqmr_subscriber::<Vec<u8>, Vec<u8>>(Protocol::SignedBlockHeader)
+            .unwrap();
+    let SqmrSubscriberChannels {
+        query_sender: mut other_query_sender,
+        response_receiver: mut other_response_receiver,
+        ..
+    } = network_manager_builder
+        .register_sqmr_subscriber::<Vec<u8>, Vec<u8>>(Protocol::StateDiff)
+        .unwrap();
+    let (network_manager, _registrations) = network_manager_builder.build().unwrap();
+    // The mock swarm turns each number in the query into a response.
+    query_sender.send((0..NUM_RESPONSES as u8).collect()).await.unwrap();
 
-    let responses_clone = responses.clone();
-    select! {
+    tokio::select! {
+        _ = network_manager.run() => panic!("network manager ended"),
         _ = async move {
-            let (query_got, mut responses_sender) = inbound_query_receiver.next().await.unwrap();
-            assert_eq!(query_got.unwrap(), query);
-            for response in responses_clone {
-                responses_sender.feed(response).await.unwrap();
+            // Nobody consumes the responses, so their session is paused once the subscriber's
+            // buffer is full.
+            let slow_session_id = OutboundSessionId { value: 0 };
+            assert_eq!(paused_sessions.next().await.unwrap(), (slow_session_id, true));
+
+            // The network manager keeps serving the other subscribers meanwhile.
+            let other_query = vec![1, 2];
+            other_query_sender.send(other_query.clone()).await.unwrap();
+            assert_responses(&mut other_response_receiver, &other_query).await;
+            // The connection event, the responses of the other session, the responses in the
+            // buffer (a futures channel has an extra slot for each sender) and the response that
+            // waits in the backlog. The rest of the responses weren't read.
+            assert!(
+                num_polled_events.load(Ordering::SeqCst)
+                    <= 1 + other_query.len() + SUBSCRIBER_BUFFER_SIZE + 1 + 1
+            );
+
+            // Once the subscriber consumes the responses, none of them should have been dropped,
+            // and the session is resumed.
+            for i in 0..NUM_RESPONSES {
+                let (response, _report_callback) = response_receiver.next().await.unwrap();
+                assert_eq!(response.unwrap(), vec![i as u8]);
             }
-            responses_sender.close().await.unwrap();
-            assert_eq!(get_responses_fut.await, responses);
+            assert_eq!(paused_sessions.next().await.unwrap(), (slow_session_id, false));
         } => {}
-        _ = network_manager.run() => {
-            panic!("GenericNetworkManager::run finished before the session finished");
+        _ = sleep(Duration::from_secs(5)) => {
+            panic!("Test timed out");
         }
+    }
+}
+
+#[tokio::test]
+async fn full_lane_backlog_stops_network_from_reading_until_it_drains() {
+    const SUBSCRIBER_BUFFER_SIZE: usize = 2;
+    const MAX_LANE_BACKLOG_SIZE: usize = 3;
+    const NUM_RESPONSES: usize = 20;
+
+    // The responses keep arriving after their session is paused, so only the backlog limit stops
+    // the network manager from reading them.
+    let mut mock_swarm = MockSwarm { ignore_pauses: true, ..Default::default() };
+    mock_swarm.pending_events.push(get_test_connection_established_event(PeerId::random()));
+    let num_polled_events = mock_swarm.get_num_polled_events();
+
+    let mut network_manager_builder = GenericNetworkManagerBuilder::generic_new(
+        mock_swarm,
+        BUFFER_SIZE,
+        SUBSCRIBER_BUFFER_SIZE,
+        MAX_CONCURRENT_OUTBOUND_SESSIONS,
+        AGING_INTERVAL,
+        PROTOCOL_NAMES.clone(),
+        NetworkEventLog::new(false, BUFFER_SIZE),
+    )
+    .with_max_lane_backlog_size(MAX_LANE_BACKLOG_SIZE);
+
+    let SqmrSubscriberChannels { mut query_sender, mut response_receiver, .. } =
+        network_manager_builder
+            .register_sqmr_subscriber::<Vec<u8>, Vec<u8>>(Protocol::SignedBlockHeader)
+            .unwrap();
+    let (network_manager, _registrations) = network_manager_builder.build().unwrap();
+    // The mock swarm turns each number in the query into a response.
+    query_sender.send((0..NUM_RESPONSES as u8).collect()).await.unwrap();
+
+    tokio::select! {
+        _ = network_manager.run() => panic!("network manager ended"),
+        _ = async move {
+            // Let the network manager run while nobody consumes the responses.
+            sleep(Duration::from_millis(100)).await;
+            // The connection event, the responses in the buffer (a futures channel has an extra
+            // slot for each sender) and the responses in the full backlog.
+            assert!(
+                num_polled_events.load(Ordering::SeqCst)
+                    <= 1 + SUBSCRIBER_BUFFER_SIZE + 1 + MAX_LANE_BACKLOG_SIZE
+            );
+
+            // The responses the subscriber takes make room in the backlog, so all of them arrive
+            // in order.
+            for i in 0..NUM_RESPONSES {
+                let (response, _report_callback) =
+                    tokio::time::timeout(TIMEOUT, response_receiver.next()).await.unwrap().unwrap();
+                assert_eq!(response.unwrap(), vec![i as u8]);
+            }
+        } => {}
         _ = sleep(Duration::from_secs(5)) => {
             panic!("Test timed out");
         }
@@ -277,72 +699,2035 @@ async fn process_incoming_query() {
 }
 
 #[tokio::test]
-async fn broadcast_message() {
-    let topic = Topic::new("TOPIC");
-    let message = vec![1u8, 2u8, 3u8];
+async fn exhausted_memory_budget_stops_network_from_reading_until_it_drains() {
+    const NUM_RESPONSES_IN_BUDGET: usize = 3;
+    const NUM_RESPONSES: usize = 20;
+    // The mock swarm's responses have a single byte.
+    const MEMORY_BUDGET_BYTES: u64 = NUM_RESPONSES_IN_BUDGET as u64 * (1 + ITEM_OVERHEAD_BYTES);
 
     let mut mock_swarm = MockSwarm::default();
-    let mut messages_we_broadcasted_stream = mock_swarm.stream_messages_we_broadcasted();
+    mock_swarm.pending_events.push(get_test_connection_established_event(PeerId::random()));
+    let num_polled_events = mock_swarm.get_num_polled_events();
 
-    let mut network_manager = GenericNetworkManager::generic_new(mock_swarm, BUFFER_SIZE);
+    // The subscriber's buffer has room for all the responses, so only the memory budget stops the
+    // network manager from reading them.
+    let mut network_manager_builder = GenericNetworkManagerBuilder::generic_new(
+        mock_swarm,
+        BUFFER_SIZE,
+        BUFFER_SIZE,
+        MAX_CONCURRENT_OUTBOUND_SESSIONS,
+        AGING_INTERVAL,
+        PROTOCOL_NAMES.clone(),
+        NetworkEventLog::new(false, BUFFER_SIZE),
+    )
+    .with_memory_budget(MEMORY_BUDGET_BYTES);
 
-    let mut messages_to_broadcast_sender = network_manager
-        .register_broadcast_subscriber(topic.clone(), BUFFER_SIZE)
-        .unwrap()
-        .messages_to_broadcast_sender;
-    messages_to_broadcast_sender.send(message.clone()).await.unwrap();
+    let SqmrSubscriberChannels { mut query_sender, mut response_receiver, .. } =
+        network_manager_builder
+            .register_sqmr_subscriber::<Vec<u8>, Vec<u8>>(Protocol::SignedBlockHeader)
+            .unwrap();
+    let (network_manager, _registrations) = network_manager_builder.build().unwrap();
+    // The mock swarm turns each number in the query into a response.
+    query_sender.send((0..NUM_RESPONSES as u8).collect()).await.unwrap();
 
     tokio::select! {
         _ = network_manager.run() => panic!("network manager ended"),
-        result = tokio::time::timeout(
-            TIMEOUT, messages_we_broadcasted_stream.next()
-        ) => {
-            let (actual_message, topic_hash) = result.unwrap().unwrap();
-            assert_eq!(message, actual_message);
-            assert_eq!(topic.hash(), topic_hash);
+        _ = async move {
+            // Let the network manager run while nobody consumes the responses.
+            sleep(Duration::from_millis(100)).await;
+            // The connection event and the responses that exhausted the budget.
+            assert_eq!(num_polled_events.load(Ordering::SeqCst), 1 + NUM_RESPONSES_IN_BUDGET);
+
+            // Each response the slow subscriber takes releases memory for reading another one, so
+            // all of them arrive.
+            for i in 0..NUM_RESPONSES {
+                let (response, _report_callback) =
+                    tokio::time::timeout(TIMEOUT, response_receiver.next()).await.unwrap().unwrap();
+                assert_eq!(response.unwrap(), vec![i as u8]);
+                sleep(Duration::from_millis(1)).await;
+            }
+            assert_eq!(num_polled_events.load(Ordering::SeqCst), 1 + NUM_RESPONSES);
+        } => {}
+        _ = sleep(Duration::from_secs(5)) => {
+            panic!("Test timed out");
         }
     }
 }
 
+// A response that always declares that the peer has state diffs.
+struct StateDiffAvailabilityResponse;
+
+const HINTED_BLOCK_NUMBER: BlockNumber = BlockNumber(7);
+
+impl TryFrom<Bytes> for StateDiffAvailabilityResponse {
+    type Error = ();
+    fn try_from(_bytes: Bytes) -> Result<Self, Self::Error> {
+        Ok(Self)
+    }
+}
+
+impl TryFromVersionedBytes for StateDiffAvailabilityResponse {}
+
+impl DataAvailabilityHints for StateDiffAvailabilityResponse {
+    fn data_availability_hints(&self) -> Vec<DataAvailabilityHint> {
+        vec![(Protocol::StateDiff, HINTED_BLOCK_NUMBER, true)]
+    }
+}
+
 #[tokio::test]
-async fn receive_broadcasted_message_and_report_it() {
-    let topic = Topic::new("TOPIC");
-    let message = vec![1u8, 2u8, 3u8];
-    let originated_peer_id = PeerId::random();
+async fn sqmr_subscriber_with_data_availability_hints_updates_peers() {
+    let mut mock_swarm = MockSwarm::default();
+    let peer_id = PeerId::random();
+    mock_swarm.pending_events.push(get_test_connection_established_event(peer_id));
+    let mut block_availability_updates = mock_swarm.get_block_availability_updates_stream();
+
+    let mut network_manager_builder = GenericNetworkManagerBuilder::generic_new(
+        mock_swarm,
+        BUFFER_SIZE,
+        BUFFER_SIZE,
+        MAX_CONCURRENT_OUTBOUND_SESSIONS,
+        AGING_INTERVAL,
+        PROTOCOL_NAMES.clone(),
+        NetworkEventLog::new(false, BUFFER_SIZE),
+    );
+
+    let SqmrSubscriberChannels { mut query_sender, mut response_receiver, .. } =
+        network_manager_builder
+            .register_sqmr_subscriber_with_data_availability_hints::<
+                Vec<u8>,
+                StateDiffAvailabilityResponse,
+            >(Protocol::SignedBlockHeader)
+            .unwrap();
+    let (network_manager, _registrations) = network_manager_builder.build().unwrap();
+    query_sender.send(vec![1]).await.unwrap();
+
+    // The hints are extracted when the subscriber decodes the response.
+    let update = async {
+        response_receiver.next().await.unwrap();
+        block_availability_updates.next().await
+    };
+    tokio::select! {
+        _ = network_manager.run() => panic!("network manager ended"),
+        update = tokio::time::timeout(TIMEOUT, update) => {
+            let (_peer_id, protocol, block_number, is_available) = update.unwrap().unwrap();
+            assert_eq!(protocol, Protocol::StateDiff);
+            assert_eq!(block_number, HINTED_BLOCK_NUMBER);
+            assert!(is_available);
+        }
+    }
+}
 
+#[tokio::test]
+async fn sqmr_subscriber_with_query_block_range_sets_session_block_range() {
     let mut mock_swarm = MockSwarm::default();
-    mock_swarm.pending_events.push(Event::Behaviour(mixed_behaviour::Event::ExternalEvent(
-        mixed_behaviour::ExternalEvent::GossipSub(gossipsub_impl::ExternalEvent::Received {
-            originated_peer_id,
-            message: message.clone(),
-            topic_hash: topic.hash(),
-        }),
-    )));
-    let mut reported_peer_receiver = mock_swarm.get_reported_peers_stream();
+    let session_block_ranges = mock_swarm.get_session_block_ranges_stream();
 
-    let mut network_manager = GenericNetworkManager::generic_new(mock_swarm, BUFFER_SIZE);
+    let mut network_manager_builder = GenericNetworkManagerBuilder::generic_new(
+        mock_swarm,
+        BUFFER_SIZE,
+        BUFFER_SIZE,
+        MAX_CONCURRENT_OUTBOUND_SESSIONS,
+        AGING_INTERVAL,
+        PROTOCOL_NAMES.clone(),
+        NetworkEventLog::new(false, BUFFER_SIZE),
+    );
 
-    let mut broadcasted_messages_receiver = network_manager
-        .register_broadcast_subscriber::<Bytes>(topic.clone(), BUFFER_SIZE)
-        .unwrap()
-        .broadcasted_messages_receiver;
+    let SqmrSubscriberChannels { mut query_sender, response_receiver: _response_receiver, .. } =
+        network_manager_builder
+            .register_sqmr_subscriber::<HeaderQuery, Vec<u8>>(Protocol::SignedBlockHeader)
+            .unwrap();
+    network_manager_builder
+        .register_query_block_range::<HeaderQuery>(Protocol::SignedBlockHeader)
+        .unwrap();
+    let (network_manager, _registrations) = network_manager_builder.build().unwrap();
+    query_sender
+        .send(HeaderQuery(Query {
+            start_block: BlockHashOrNumber::Number(BlockNumber(5)),
+            direction: Direction::Forward,
+            limit: 10,
+            step: 1,
+        }))
+        .await
+        .unwrap();
+    // An open-ended query asks for a peer that reached its first block.
+    query_sender
+        .send(HeaderQuery(Query {
+            start_block: BlockHashOrNumber::Number(BlockNumber(15)),
+            direction: Direction::Forward,
+            limit: Query::OPEN_ENDED_LIMIT,
+            step: 1,
+        }))
+        .await
+        .unwrap();
 
+    let block_range_updates = session_block_ranges.take(2).collect::<Vec<_>>();
     tokio::select! {
         _ = network_manager.run() => panic!("network manager ended"),
-        // We need to do the entire calculation in the future here so that the network will keep
-        // running while we call report_callback.
-        reported_peer_result = tokio::time::timeout(TIMEOUT, broadcasted_messages_receiver.next())
-            .then(|result| {
-                let (message_result, report_callback) = result.unwrap().unwrap();
-                assert_eq!(message, message_result.unwrap());
-                report_callback();
-                tokio::time::timeout(TIMEOUT, reported_peer_receiver.next())
-            }) => {
-            assert_eq!(originated_peer_id, reported_peer_result.unwrap().unwrap());
+        updates = tokio::time::timeout(TIMEOUT, block_range_updates) => {
+            let block_ranges = updates
+                .unwrap()
+                .into_iter()
+                .map(|(_, block_range)| block_range)
+                .collect::<Vec<_>>();
+            assert_eq!(
+                block_ranges,
+                vec![BlockNumber(5)..BlockNumber(15), BlockNumber(15)..BlockNumber(15)]
+            );
         }
     }
 }
 
+#[tokio::test]
+async fn outbound_queries_above_the_session_limit_wait_for_a_session_to_finish() {
+    let mut mock_swarm = MockSwarm::default();
+    mock_swarm.pending_events.push(get_test_connection_established_event(PeerId::random()));
+
+    let mut network_manager_builder = GenericNetworkManagerBuilder::generic_new(
+        mock_swarm,
+        BUFFER_SIZE,
+        BUFFER_SIZE,
+        1,
+        AGING_INTERVAL,
+        PROTOCOL_NAMES.clone(),
+        NetworkEventLog::new(false, BUFFER_SIZE),
+    );
+
+    let SqmrSubscriberChannels { mut query_sender, mut response_receiver, .. } =
+        network_manager_builder
+            .register_sqmr_subscriber::<Vec<u8>, Vec<u8>>(Protocol::SignedBlockHeader)
+            .unwrap();
+    let (network_manager, _registrations) = network_manager_builder.build().unwrap();
+    query_sender.send(vec![1]).await.unwrap();
+    query_sender.send(vec![2]).await.unwrap();
+
+    tokio::select! {
+        _ = network_manager.run() => panic!("network manager ended"),
+        _ = async move {
+            let (response, _report_callback) = response_receiver.next().await.unwrap();
+            assert_eq!(response.unwrap(), vec![1]);
+            // The first session never finishes, so the second query isn't sent.
+            assert!(tokio::time::timeout(TIMEOUT, response_receiver.next()).await.is_err());
+        } => {}
+    }
+}
+
+#[tokio::test]
+async fn query_that_no_peer_got_fails_and_frees_its_session() {
+    let mut mock_swarm = MockSwarm::default();
+    mock_swarm.pending_events.push(get_test_connection_established_event(PeerId::random()));
+    mock_swarm.no_peers_dial_deadline = Some(TIMEOUT);
+
+    let mut network_manager_builder = GenericNetworkManagerBuilder::generic_new(
+        mock_swarm,
+        BUFFER_SIZE,
+        BUFFER_SIZE,
+        1,
+        AGING_INTERVAL,
+        PROTOCOL_NAMES.clone(),
+        NetworkEventLog::new(false, BUFFER_SIZE),
+    );
+
+    let SqmrSubscriberChannels { mut query_sender, mut response_receiver, .. } =
+        network_manager_builder
+            .register_sqmr_subscriber::<Vec<u8>, Vec<u8>>(Protocol::SignedBlockHeader)
+            .unwrap();
+    let (network_manager, _registrations) = network_manager_builder.build().unwrap();
+    query_sender.send(vec![1]).await.unwrap();
+    query_sender.send(vec![2]).await.unwrap();
+
+    tokio::select! {
+        _ = network_manager.run() => panic!("network manager ended"),
+        _ = async move {
+            // The second query is sent only once the first session failed.
+            for _ in 0..2 {
+                let (response, _report_callback) = response_receiver.next().await.unwrap();
+                assert_matches!(
+                    response,
+                    Err(SqmrResponseError::Query(SqmrQueryError::NoPeers { dial_deadline }))
+                    if dial_deadline == TIMEOUT
+                );
+            }
+        } => {}
+        _ = sleep(TIMEOUT) => panic!("Test timed out"),
+    }
+}
+
+#[tokio::test]
+async fn query_failures_for_a_slow_subscriber_dont_block_the_network() {
+    const SUBSCRIBER_BUFFER_SIZE: usize = 1;
+    const NUM_QUERIES: u8 = 10;
+
+    let mut mock_swarm = MockSwarm::default();
+    mock_swarm.pending_events.push(get_test_connection_established_event(PeerId::random()));
+    mock_swarm.no_peers_dial_deadline = Some(TIMEOUT);
+
+    let mut network_manager_builder = GenericNetworkManagerBuilder::generic_new(
+        mock_swarm,
+        BUFFER_SIZE,
+        SUBSCRIBER_BUFFER_SIZE,
+        MAX_CONCURRENT_OUTBOUND_SESSIONS,
+        AGING_INTERVAL,
+        PROTOCOL_NAMES.clone(),
+        NetworkEventLog::new(false, BUFFER_SIZE),
+    );
+
+    let SqmrSubscriberChannels { mut query_sender, mut response_receiver, .. } =
+        network_manager_builder
+            .register_sqmr_subscriber::<Vec<u8>, Vec<u8>>(Protocol::SignedBlockHeader)
+            .unwrap();
+    let SqmrSubscriberChannels {
+        query_sender: mut other_query_sender,
+        response_receiver: mut other_response_receiver,
+        ..
+    } = network_manager_builder
+        .register_sqmr_subscriber::<Vec<u8>, Vec<u8>>(Protocol::StateDiff)
+        .unwrap();
+    let (network_manager, _registrations) = network_manager_builder.build().unwrap();
+    for query in 0..NUM_QUERIES {
+        query_sender.send(vec![query]).await.unwrap();
+    }
+
+    tokio::select! {
+        _ = network_manager.run() => panic!("network manager ended"),
+        _ = async move {
+            // Nobody consumes the failures of the first subscriber's queries, but the network
+            // manager keeps handling the queries of the other subscriber.
+            sleep(Duration::from_millis(100)).await;
+            other_query_sender.send(vec![1]).await.unwrap();
+            let (response, _report_callback) = other_response_receiver.next().await.unwrap();
+            assert_matches!(
+                response,
+                Err(SqmrResponseError::Query(SqmrQueryError::NoPeers { .. }))
+            );
+
+            // None of the failures were dropped meanwhile.
+            for _ in 0..NUM_QUERIES {
+                let (response, _report_callback) = response_receiver.next().await.unwrap();
+                assert_matches!(
+                    response,
+                    Err(SqmrResponseError::Query(SqmrQueryError::NoPeers { .. }))
+                );
+            }
+        } => {}
+        _ = sleep(Duration::from_secs(5)) => panic!("Test timed out"),
+    }
+}
+
+#[tokio::test]
+async fn pending_outbound_query_is_sent_once_a_session_finishes() {
+    let mut mock_swarm = MockSwarm::default();
+    mock_swarm.pending_events.push(get_test_connection_established_event(PeerId::random()));
+    mock_swarm.finish_outbound_sessions = true;
+
+    let mut network_manager_builder = GenericNetworkManagerBuilder::generic_new(
+        mock_swarm,
+        BUFFER_SIZE,
+        BUFFER_SIZE,
+        1,
+        AGING_INTERVAL,
+        PROTOCOL_NAMES.clone(),
+        NetworkEventLog::new(false, BUFFER_SIZE),
+    );
+
+    let SqmrSubscriberChannels {
+        mut query_sender,
+        mut prioritized_query_sender,
+        mut response_receiver,
+    } = network_manager_builder
+        .register_sqmr_subscriber::<Vec<u8>, Vec<u8>>(Protocol::SignedBlockHeader)
+        .unwrap();
+    let (network_manager, _registrations) = network_manager_builder.build().unwrap();
+    query_sender.send(vec![1]).await.unwrap();
+    prioritized_query_sender.send((vec![2], QueryPriority::High)).await.unwrap();
+
+    tokio::select! {
+        _ = network_manager.run() => panic!("network manager ended"),
+        _ = async move {
+            for expected_response in [vec![1], vec![2]] {
+                let (response, _report_callback) =
+                    tokio::time::timeout(TIMEOUT, response_receiver.next()).await.unwrap().unwrap();
+                assert_eq!(response.unwrap(), expected_response);
+            }
+        } => {}
+    }
+}
+
+#[tokio::test]
+async fn sqmr_subscriber_lanes_receive_only_the_responses_to_their_queries() {
+    let mut mock_swarm = MockSwarm::default();
+    mock_swarm.pending_events.push(get_test_connection_established_event(PeerId::random()));
+
+    let mut network_manager_builder = GenericNetworkManagerBuilder::generic_new(
+        mock_swarm,
+        BUFFER_SIZE,
+        BUFFER_SIZE,
+        MAX_CONCURRENT_OUTBOUND_SESSIONS,
+        AGING_INTERVAL,
+        PROTOCOL_NAMES.clone(),
+        NetworkEventLog::new(false, BUFFER_SIZE),
+    );
+
+    let lanes = network_manager_builder
+        .register_sqmr_subscriber_lanes::<Vec<u8>, Vec<u8>>(Protocol::StateDiff, 2)
+        .unwrap();
+    assert_eq!(lanes.len(), 2);
+    assert_eq!(network_manager_builder.registrations().sqmr_clients, vec![Protocol::StateDiff]);
+    let (network_manager, _registrations) = network_manager_builder.build().unwrap();
+
+    let queries = [vec![1, 2], vec![3]];
+    let mut response_receivers = Vec::new();
+    for (SqmrSubscriberChannels { mut query_sender, response_receiver, .. }, query) in
+        lanes.into_iter().zip(queries.clone())
+    {
+        query_sender.send(query).await.unwrap();
+        response_receivers.push(response_receiver);
+    }
+
+    tokio::select! {
+        _ = network_manager.run() => panic!("network manager ended"),
+        _ = async move {
+            for (mut response_receiver, query) in response_receivers.into_iter().zip(queries) {
+                for expected_response in query {
+                    let (response, _report_callback) = tokio::time::timeout(
+                        TIMEOUT, response_receiver.next()
+                    ).await.unwrap().unwrap();
+                    assert_eq!(response.unwrap(), vec![expected_response]);
+                }
+                assert!(tokio::time::timeout(TIMEOUT, response_receiver.next()).await.is_err());
+            }
+        } => {}
+    }
+}
+
+// Returns a network manager with a state diff client of `num_lanes` lanes. The events of the
+// outbound sessions of its swarm are held in the returned queue until they're released to the
+// pending events of the swarm, which are returned as well.
+fn network_manager_with_held_outbound_session_events(
+    num_lanes: usize,
+    subscriber_buffer_size: usize,
+) -> (
+    GenericNetworkManager<MockSwarm>,
+    Vec<SqmrSubscriberChannels<Vec<u8>, Vec<u8>>>,
+    Arc<Queue<Event>>,
+    Arc<Queue<Event>>,
+) {
+    let mut mock_swarm = MockSwarm::default();
+    mock_swarm.pending_events.push(get_test_connection_established_event(PeerId::random()));
+    let held_events = Arc::new(Queue::new());
+    mock_swarm.held_outbound_session_events = Some(held_events.clone());
+    let pending_events = mock_swarm.pending_events.clone();
+
+    let mut network_manager_builder = GenericNetworkManagerBuilder::generic_new(
+        mock_swarm,
+        BUFFER_SIZE,
+        subscriber_buffer_size,
+        MAX_CONCURRENT_OUTBOUND_SESSIONS,
+        AGING_INTERVAL,
+        PROTOCOL_NAMES.clone(),
+        NetworkEventLog::new(false, BUFFER_SIZE),
+    );
+    let lanes = network_manager_builder
+        .register_sqmr_subscriber_lanes::<Vec<u8>, Vec<u8>>(Protocol::StateDiff, num_lanes)
+        .unwrap();
+    let (network_manager, _registrations) = network_manager_builder.build().unwrap();
+    (network_manager, lanes, held_events, pending_events)
+}
+
+fn release_held_events(held_events: &Queue<Event>, pending_events: &Queue<Event>) {
+    while let Some(event) = held_events.try_pop() {
+        pending_events.push(event);
+    }
+}
+
+async fn assert_responses(response_receiver: &mut SqmrResponseReceiver<Vec<u8>>, query: &[u8]) {
+    for expected_response in query {
+        let (response, _report_callback) =
+            tokio::time::timeout(TIMEOUT, response_receiver.next()).await.unwrap().unwrap();
+        assert_eq!(response.unwrap(), vec![*expected_response]);
+    }
+}
+
+#[tokio::test]
+async fn identical_queries_of_different_lanes_share_a_session() {
+    let (network_manager, lanes, held_events, pending_events) =
+        network_manager_with_held_outbound_session_events(2, BUFFER_SIZE);
+    let query = vec![1, 2];
+    let mut response_receivers = Vec::new();
+    for SqmrSubscriberChannels { mut query_sender, response_receiver, .. } in lanes {
+        query_sender.send(query.clone()).await.unwrap();
+        response_receivers.push(response_receiver);
+    }
+
+    tokio::select! {
+        _ = network_manager.run() => panic!("network manager ended"),
+        _ = async move {
+            // The second query was handled before the session of the first one received
+            // responses, so a single session was started. It has a response for each element of
+            // the query.
+            sleep(TIMEOUT).await;
+            assert_eq!(held_events.len(), query.len());
+            release_held_events(&held_events, &pending_events);
+
+            for mut response_receiver in response_receivers {
+                assert_responses(&mut response_receiver, &query).await;
+            }
+        } => {}
+    }
+}
+
+#[tokio::test]
+async fn lane_that_dropped_its_receiver_doesnt_stop_the_shared_session() {
+    let (network_manager, lanes, held_events, pending_events) =
+        network_manager_with_held_outbound_session_events(2, BUFFER_SIZE);
+    let query = vec![1, 2];
+    let mut response_receivers = Vec::new();
+    for SqmrSubscriberChannels { mut query_sender, response_receiver, .. } in lanes {
+        query_sender.send(query.clone()).await.unwrap();
+        response_receivers.push(response_receiver);
+    }
+    let mut response_receiver = response_receivers.pop().unwrap();
+
+    tokio::select! {
+        _ = network_manager.run() => panic!("network manager ended"),
+        _ = async move {
+            sleep(TIMEOUT).await;
+            assert_eq!(held_events.len(), query.len());
+            drop(response_receivers);
+            release_held_events(&held_events, &pending_events);
+
+            assert_responses(&mut response_receiver, &query).await;
+        } => {}
+    }
+}
+
+#[tokio::test]
+async fn slow_lane_doesnt_block_the_other_lanes_of_the_shared_session() {
+    const SUBSCRIBER_BUFFER_SIZE: usize = 2;
+    const NUM_RESPONSES: u8 = 20;
+
+    let (mut network_manager, lanes, held_events, pending_events) =
+        network_manager_with_held_outbound_session_events(2, SUBSCRIBER_BUFFER_SIZE);
+    let mut paused_sessions = network_manager.swarm.get_paused_sessions_stream();
+    let query: Vec<u8> = (0..NUM_RESPONSES).collect();
+    let mut response_receivers = Vec::new();
+    for SqmrSubscriberChannels { mut query_sender, response_receiver, .. } in lanes {
+        query_sender.send(query.clone()).await.unwrap();
+        response_receivers.push(response_receiver);
+    }
+    let mut fast_response_receiver = response_receivers.pop().unwrap();
+    let mut slow_response_receiver = response_receivers.pop().unwrap();
+
+    tokio::select! {
+        _ = network_manager.run() => panic!("network manager ended"),
+        _ = async move {
+            sleep(TIMEOUT).await;
+            assert_eq!(held_events.len(), query.len());
+            release_held_events(&held_events, &pending_events);
+
+            // The slow lane doesn't take its responses, so the session is paused, but the fast
+            // lane gets all the responses that were read.
+            let shared_session_id = OutboundSessionId { value: 0 };
+            assert_eq!(paused_sessions.next().await.unwrap(), (shared_session_id, true));
+            assert_responses(&mut fast_response_receiver, &query).await;
+
+            // None of the responses of the slow lane were dropped meanwhile.
+            assert_responses(&mut slow_response_receiver, &query).await;
+            assert_eq!(paused_sessions.next().await.unwrap(), (shared_session_id, false));
+        } => {}
+    }
+}
+
+#[tokio::test]
+async fn query_sent_again_on_the_same_lane_gets_a_new_session() {
+    let (network_manager, mut lanes, held_events, pending_events) =
+        network_manager_with_held_outbound_session_events(1, BUFFER_SIZE);
+    let SqmrSubscriberChannels { mut query_sender, mut response_receiver, .. } =
+        lanes.pop().unwrap();
+    let query = vec![1, 2];
+
+    tokio::select! {
+        _ = network_manager.run() => panic!("network manager ended"),
+        _ = async move {
+            query_sender.send(query.clone()).await.unwrap();
+            sleep(TIMEOUT).await;
+            // The lane gave up on the session before it received responses, and sent the query
+            // again.
+            query_sender.send(query.clone()).await.unwrap();
+            sleep(TIMEOUT).await;
+            assert_eq!(held_events.len(), 2 * query.len());
+            release_held_events(&held_events, &pending_events);
+
+            assert_responses(&mut response_receiver, &query).await;
+            assert_responses(&mut response_receiver, &query).await;
+        } => {}
+    }
+}
+
+#[tokio::test]
+async fn responses_of_recorded_protocol_are_sent_to_the_recorder() {
+    let mut mock_swarm = MockSwarm::default();
+    mock_swarm.pending_events.push(get_test_connection_established_event(PeerId::random()));
+
+    let mut network_manager_builder = GenericNetworkManagerBuilder::generic_new(
+        mock_swarm,
+        BUFFER_SIZE,
+        BUFFER_SIZE,
+        MAX_CONCURRENT_OUTBOUND_SESSIONS,
+        AGING_INTERVAL,
+        PROTOCOL_NAMES.clone(),
+        NetworkEventLog::new(false, BUFFER_SIZE),
+    );
+    let SqmrSubscriberChannels { mut query_sender, mut response_receiver, .. } =
+        network_manager_builder
+            .register_sqmr_subscriber::<Vec<u8>, Vec<u8>>(Protocol::SignedBlockHeader)
+            .unwrap();
+    let (recorder, mut recorded_responses) = unbounded();
+    network_manager_builder.record_sqmr_responses(Protocol::SignedBlockHeader, recorder).unwrap();
+    let (network_manager, _registrations) = network_manager_builder.build().unwrap();
+
+    let queries = [vec![1, 2], vec![3]];
+    for query in queries.clone() {
+        query_sender.send(query).await.unwrap();
+    }
+
+    tokio::select! {
+        _ = network_manager.run() => panic!("network manager ended"),
+        _ = async move {
+            let mut records = Vec::new();
+            for _ in 0..3 {
+                // The subscriber still gets the responses.
+                tokio::time::timeout(TIMEOUT, response_receiver.next()).await.unwrap().unwrap();
+                records.push(
+                    tokio::time::timeout(TIMEOUT, recorded_responses.next()).await.unwrap().unwrap()
+                );
+            }
+            // The mock swarm sends each byte of the query as a response.
+            assert_eq!(
+                records
+                    .into_iter()
+                    .map(|RecordedSqmrResponse { protocol, session_id, query, response, .. }| {
+                        (protocol, session_id, query, response)
+                    })
+                    .collect::<Vec<_>>(),
+                vec![
+                    (Protocol::SignedBlockHeader, 0, queries[0].clone(), vec![1]),
+                    (Protocol::SignedBlockHeader, 0, queries[0].clone(), vec![2]),
+                    (Protocol::SignedBlockHeader, 1, queries[1].clone(), vec![3]),
+                ]
+            );
+        } => {}
+    }
+}
+
+#[test]
+fn record_sqmr_responses_of_unregistered_client_fails() {
+    let mut network_manager_builder = GenericNetworkManagerBuilder::generic_new(
+        MockSwarm::default(),
+        BUFFER_SIZE,
+        BUFFER_SIZE,
+        MAX_CONCURRENT_OUTBOUND_SESSIONS,
+        AGING_INTERVAL,
+        PROTOCOL_NAMES.clone(),
+        NetworkEventLog::new(false, BUFFER_SIZE),
+    );
+
+    assert_matches!(
+        network_manager_builder.record_sqmr_responses(Protocol::StateDiff, unbounded().0),
+        Err(RegistrationError::ProtocolNotRegisteredAsClient(Protocol::StateDiff))
+    );
+}
+
+#[test]
+fn outbound_query_queue_pops_higher_priority_first() {
+    let mut queue = OutboundQueryQueue::new(AGING_INTERVAL);
+    let now = Instant::now();
+    queue.push(Protocol::SignedBlockHeader, vec![1], QueryPriority::Low, now);
+    queue.push(Protocol::StateDiff, vec![2], QueryPriority::Normal, now);
+    queue.push(Protocol::SignedBlockHeader, vec![3], QueryPriority::High, now);
+    queue.push(Protocol::SignedBlockHeader, vec![4], QueryPriority::High, now);
+    assert_eq!(queue.len(QueryPriority::High), 2);
+
+    assert_eq!(queue.pop(now), Some((Protocol::SignedBlockHeader, vec![3])));
+    assert_eq!(queue.pop(now), Some((Protocol::SignedBlockHeader, vec![4])));
+    assert_eq!(queue.pop(now), Some((Protocol::StateDiff, vec![2])));
+    assert_eq!(queue.pop(now), Some((Protocol::SignedBlockHeader, vec![1])));
+    assert_eq!(queue.pop(now), None);
+}
+
+#[test]
+fn outbound_query_queue_ages_waiting_queries() {
+    let mut queue = OutboundQueryQueue::new(AGING_INTERVAL);
+    let start = Instant::now();
+    queue.push(Protocol::SignedBlockHeader, vec![1], QueryPriority::Low, start);
+    let later = start + AGING_INTERVAL;
+    queue.push(Protocol::SignedBlockHeader, vec![2], QueryPriority::Normal, later);
+    queue.push(Protocol::SignedBlockHeader, vec![3], QueryPriority::High, later);
+
+    // After one aging interval the low priority query is as urgent as a new normal one, and it
+    // waited longer.
+    assert_eq!(queue.pop(later), Some((Protocol::SignedBlockHeader, vec![3])));
+    assert_eq!(queue.pop(later), Some((Protocol::SignedBlockHeader, vec![1])));
+    assert_eq!(queue.pop(later), Some((Protocol::SignedBlockHeader, vec![2])));
+}
+
+#[test]
+fn register_query_block_range_of_unregistered_client_fails() {
+    let mut network_manager_builder = GenericNetworkManagerBuilder::generic_new(
+        MockSwarm::default(),
+        BUFFER_SIZE,
+        BUFFER_SIZE,
+        MAX_CONCURRENT_OUTBOUND_SESSIONS,
+        AGING_INTERVAL,
+        PROTOCOL_NAMES.clone(),
+        NetworkEventLog::new(false, BUFFER_SIZE),
+    );
+
+    assert_matches!(
+        network_manager_builder.register_query_block_range::<HeaderQuery>(Protocol::StateDiff),
+        Err(RegistrationError::ProtocolNotRegisteredAsClient(Protocol::StateDiff))
+    );
+}
+
+#[tokio::test]
+async fn receive_block_range_advertisement_and_update_peers() {
+    let peer_id = PeerId::random();
+    let advertisement = BlockRangeAdvertisement {
+        ranges: vec![
+            ProtocolBlockRange {
+                protocol: "/unknown/protocol".to_string(),
+                block_range: BlockNumber(0)..BlockNumber(20),
+            },
+            ProtocolBlockRange {
+                protocol: Protocol::SignedBlockHeader.as_str().to_string(),
+                block_range: BlockNumber(0)..BlockNumber(10),
+            },
+        ],
+        response_limits: None,
+    };
+
+    let mut mock_swarm = MockSwarm::default();
+    mock_swarm.pending_events.push(Event::Behaviour(mixed_behaviour::Event::ExternalEvent(
+        mixed_behaviour::ExternalEvent::GossipSub(gossipsub_impl::ExternalEvent::Received {
+            originated_peer_id: peer_id,
+            message: Bytes::from(advertisement),
+            topic_hash: BLOCK_RANGE_ADVERTISEMENT_TOPIC.topic().hash(),
+        }),
+    )));
+    let mut advertised_block_range_updates = mock_swarm.get_advertised_block_range_updates_stream();
+
+    let mut network_manager_builder = GenericNetworkManagerBuilder::generic_new(
+        mock_swarm,
+        BUFFER_SIZE,
+        BUFFER_SIZE,
+        MAX_CONCURRENT_OUTBOUND_SESSIONS,
+        AGING_INTERVAL,
+        PROTOCOL_NAMES.clone(),
+        NetworkEventLog::new(false, BUFFER_SIZE),
+    );
+    let _advertisement_sender =
+        network_manager_builder.register_block_range_advertiser(BUFFER_SIZE).unwrap();
+    assert_eq!(
+        network_manager_builder.registrations().broadcast_topics,
+        vec![BLOCK_RANGE_ADVERTISEMENT_TOPIC.topic().to_string()]
+    );
+    let (network_manager, _registrations) = network_manager_builder.build().unwrap();
+
+    tokio::select! {
+        _ = network_manager.run() => panic!("network manager ended"),
+        update = tokio::time::timeout(TIMEOUT, advertised_block_range_updates.next()) => {
+            assert_eq!(
+                update.unwrap().unwrap(),
+                (peer_id, Protocol::SignedBlockHeader, BlockNumber(0)..BlockNumber(10))
+            );
+        }
+    }
+}
+
+#[tokio::test]
+async fn receive_invalid_block_range_advertisement_and_report_peer() {
+    let peer_id = PeerId::random();
+
+    let mut mock_swarm = MockSwarm::default();
+    mock_swarm.pending_events.push(Event::Behaviour(mixed_behaviour::Event::ExternalEvent(
+        mixed_behaviour::ExternalEvent::GossipSub(gossipsub_impl::ExternalEvent::Received {
+            originated_peer_id: peer_id,
+            message: vec![0xff, 0xff, 0xff],
+            topic_hash: BLOCK_RANGE_ADVERTISEMENT_TOPIC.topic().hash(),
+        }),
+    )));
+    let mut reported_peer_receiver = mock_swarm.get_reported_peers_stream();
+
+    let mut network_manager_builder = GenericNetworkManagerBuilder::generic_new(
+        mock_swarm,
+        BUFFER_SIZE,
+        BUFFER_SIZE,
+        MAX_CONCURRENT_OUTBOUND_SESSIONS,
+        AGING_INTERVAL,
+        PROTOCOL_NAMES.clone(),
+        NetworkEventLog::new(false, BUFFER_SIZE),
+    );
+    let _advertisement_sender =
+        network_manager_builder.register_block_range_advertiser(BUFFER_SIZE).unwrap();
+    let (network_manager, _registrations) = network_manager_builder.build().unwrap();
+
+    tokio::select! {
+        _ = network_manager.run() => panic!("network manager ended"),
+        reported_peer = tokio::time::timeout(TIMEOUT, reported_peer_receiver.next()) => {
+            assert_eq!(reported_peer.unwrap().unwrap(), peer_id);
+        }
+    }
+}
+
+#[tokio::test]
+async fn peer_manager_commands_are_applied_by_the_swarm() {
+    let mut mock_swarm = MockSwarm::default();
+    let peer_manager_command_receiver = mock_swarm.get_peer_manager_commands_stream();
+
+    let (network_manager, _registrations) = GenericNetworkManagerBuilder::generic_new(
+        mock_swarm,
+        BUFFER_SIZE,
+        BUFFER_SIZE,
+        MAX_CONCURRENT_OUTBOUND_SESSIONS,
+        AGING_INTERVAL,
+        PROTOCOL_NAMES.clone(),
+        NetworkEventLog::new(false, BUFFER_SIZE),
+    )
+    .build()
+    .unwrap();
+    let peer_manager_command_sender = network_manager.peer_manager_command_sender();
+    let peer_id = PeerId::random();
+    peer_manager_command_sender
+        .unbounded_send(PeerManagerCommand::Blacklist { peer_id, duration: TIMEOUT })
+        .unwrap();
+    peer_manager_command_sender
+        .unbounded_send(PeerManagerCommand::Unblacklist { peer_id })
+        .unwrap();
+
+    tokio::select! {
+        _ = network_manager.run() => panic!("network manager ended"),
+        commands = tokio::time::timeout(
+            TIMEOUT, peer_manager_command_receiver.take(2).collect::<Vec<_>>()
+        ) => {
+            let commands = commands.unwrap();
+            assert_matches!(
+                commands[0],
+                PeerManagerCommand::Blacklist { peer_id: banned_peer_id, duration }
+                if banned_peer_id == peer_id && duration == TIMEOUT
+            );
+            assert_matches!(
+                commands[1],
+                PeerManagerCommand::Unblacklist { peer_id: unbanned_peer_id }
+                if unbanned_peer_id == peer_id
+            );
+        }
+    }
+}
+
+// TODO(shahak): Add multiple protocols and multiple queries in the test.
+#[tokio::test]
+async fn process_incoming_query() {
+    // Create data for test.
+    let query = VEC1.clone();
+    let responses = vec![VEC1.clone(), VEC2.clone(), VEC3.clone()];
+    let protocol = crate::Protocol::SignedBlockHeader;
+
+    // Setup mock swarm and tell it to return an event of new inbound query.
+    let mut mock_swarm = MockSwarm::default();
+    let inbound_session_id = InboundSessionId { value: 0 };
+    let peer_id = PeerId::random();
+    mock_swarm.pending_events.push(Event::Behaviour(mixed_behaviour::Event::ExternalEvent(
+        mixed_behaviour::ExternalEvent::Sqmr(GenericEvent::NewInboundSession {
+            query: query.clone(),
+            inbound_session_id,
+            peer_id,
+            protocol_name: PROTOCOL_NAMES.stream_protocol(protocol),
+        }),
+    )));
+
+    // Create a future that will return when the session is closed with the data sent on the swarm.
+    let get_responses_fut = mock_swarm.get_responses_sent_to_inbound_session(inbound_session_id);
+    let served_bytes_stream = mock_swarm.get_served_bytes_stream();
+
+    let mut network_manager_builder = GenericNetworkManagerBuilder::generic_new(
+        mock_swarm,
+        BUFFER_SIZE,
+        BUFFER_SIZE,
+        MAX_CONCURRENT_OUTBOUND_SESSIONS,
+        AGING_INTERVAL,
+        PROTOCOL_NAMES.clone(),
+        NetworkEventLog::new(false, BUFFER_SIZE),
+    );
+
+    let mut inbound_query_receiver = network_manager_builder
+        .register_sqmr_protocol_server::<Vec<u8>, Vec<u8>>(protocol)
+        .unwrap();
+    let (network_manager, _registrations) = network_manager_builder.build().unwrap();
+
+    let responses_clone = responses.clone();
+    select! {
+        _ = async move {
+            let (query_got, mut responses_sender, peer_id_got) =
+                inbound_query_receiver.next().await.unwrap();
+            assert_eq!(query_got.unwrap(), query);
+            assert_eq!(peer_id_got, peer_id);
+            for response in responses_clone {
+                responses_sender.feed(response).await.unwrap();
+            }
+            responses_sender.close().await.unwrap();
+            assert_eq!(get_responses_fut.await, responses);
+            let served_bytes = served_bytes_stream.take(responses.len()).collect::<Vec<_>>().await;
+            let expected_served_bytes = responses
+                .iter()
+                .map(|response| (peer_id, response.len() as u64))
+                .collect::<Vec<_>>();
+            assert_eq!(served_bytes, expected_served_bytes);
+        } => {}
+        _ = network_manager.run() => {
+            panic!("GenericNetworkManager::run finished before the session finished");
+        }
+        _ = sleep(Duration::from_secs(5)) => {
+            panic!("Test timed out");
+        }
+    }
+}
+
+#[tokio::test]
+async fn responses_above_the_bandwidth_limit_are_delayed() {
+    const MAX_BYTES_PER_SEC: u64 = 10000;
+    const RESPONSE_SIZE: usize = 1000;
+    const NUM_RESPONSES: usize = 25;
+    // The first second's worth of bytes is sent right away, and the rest at the limit.
+    const MIN_DURATION: Duration = Duration::from_millis(1500);
+
+    let protocol = Protocol::SignedBlockHeader;
+    let mut mock_swarm = MockSwarm::default();
+    let inbound_session_id = InboundSessionId { value: 0 };
+    mock_swarm.pending_events.push(Event::Behaviour(mixed_behaviour::Event::ExternalEvent(
+        mixed_behaviour::ExternalEvent::Sqmr(GenericEvent::NewInboundSession {
+            query: VEC1.clone(),
+            inbound_session_id,
+            peer_id: PeerId::random(),
+            protocol_name: PROTOCOL_NAMES.stream_protocol(protocol),
+        }),
+    )));
+    let get_responses_fut = mock_swarm.get_responses_sent_to_inbound_session(inbound_session_id);
+
+    let mut network_manager_builder = GenericNetworkManagerBuilder::generic_new(
+        mock_swarm,
+        BUFFER_SIZE,
+        BUFFER_SIZE,
+        MAX_CONCURRENT_OUTBOUND_SESSIONS,
+        AGING_INTERVAL,
+        PROTOCOL_NAMES.clone(),
+        NetworkEventLog::new(false, BUFFER_SIZE),
+    )
+    .with_bandwidth_throttle(MAX_BYTES_PER_SEC, 0);
+    let mut inbound_query_receiver = network_manager_builder
+        .register_sqmr_protocol_server::<Vec<u8>, Vec<u8>>(protocol)
+        .unwrap();
+    let (network_manager, _registrations) = network_manager_builder.build().unwrap();
+
+    select! {
+        _ = async move {
+            let (_query, mut responses_sender, _peer_id) =
+                inbound_query_receiver.next().await.unwrap();
+            let start = Instant::now();
+            for _ in 0..NUM_RESPONSES {
+                responses_sender.feed(vec![0; RESPONSE_SIZE]).await.unwrap();
+            }
+            responses_sender.close().await.unwrap();
+            assert_eq!(get_responses_fut.await.len(), NUM_RESPONSES);
+            assert!(start.elapsed() >= MIN_DURATION);
+        } => {}
+        _ = network_manager.run() => {
+            panic!("GenericNetworkManager::run finished before the session finished");
+        }
+        _ = sleep(Duration::from_secs(5)) => {
+            panic!("Test timed out");
+        }
+    }
+}
+
+#[test]
+fn per_peer_bandwidth_limit_doesnt_delay_other_peers() {
+    const MAX_BYTES_PER_SEC_PER_PEER: u64 = 1000;
+    let bandwidth_throttle = BandwidthThrottle::new(0, MAX_BYTES_PER_SEC_PER_PEER);
+    let peer_id = PeerId::random();
+
+    // A second's worth of bytes is sent right away.
+    assert_eq!(bandwidth_throttle.reserve(peer_id, 1000), Duration::ZERO);
+    assert!(bandwidth_throttle.reserve(peer_id, 500) > Duration::from_millis(400));
+    assert_eq!(bandwidth_throttle.reserve(PeerId::random(), 1000), Duration::ZERO);
+}
+
+#[tokio::test]
+async fn incoming_query_of_another_chain_closes_the_session_and_reports_the_peer() {
+    let protocol = Protocol::SignedBlockHeader;
+    let mut mock_swarm = MockSwarm::default();
+    let inbound_session_id = InboundSessionId { value: 0 };
+    let peer_id = PeerId::random();
+    mock_swarm.pending_events.push(Event::Behaviour(mixed_behaviour::Event::ExternalEvent(
+        mixed_behaviour::ExternalEvent::Sqmr(GenericEvent::NewInboundSession {
+            query: VEC1.clone(),
+            inbound_session_id,
+            peer_id,
+            protocol_name: protocol.chain_scoped_name(&ChainId::Mainnet, 1),
+        }),
+    )));
+    let get_responses_fut = mock_swarm.get_responses_sent_to_inbound_session(inbound_session_id);
+    let mut reported_peers_stream = mock_swarm.get_reported_peers_stream();
+
+    let mut network_manager_builder = GenericNetworkManagerBuilder::generic_new(
+        mock_swarm,
+        BUFFER_SIZE,
+        BUFFER_SIZE,
+        MAX_CONCURRENT_OUTBOUND_SESSIONS,
+        AGING_INTERVAL,
+        PROTOCOL_NAMES.clone(),
+        NetworkEventLog::new(false, BUFFER_SIZE),
+    );
+    let mut inbound_query_receiver = network_manager_builder
+        .register_sqmr_protocol_server::<Vec<u8>, Vec<u8>>(protocol)
+        .unwrap();
+    let (network_manager, _registrations) = network_manager_builder.build().unwrap();
+
+    select! {
+        _ = async move {
+            assert_eq!(reported_peers_stream.next().await, Some(peer_id));
+            assert!(get_responses_fut.await.is_empty());
+            // The query isn't passed to the server.
+            assert!(inbound_query_receiver.next().now_or_never().is_none());
+        } => {}
+        _ = network_manager.run() => {
+            panic!("GenericNetworkManager::run finished before the session was closed");
+        }
+        _ = sleep(TIMEOUT) => {
+            panic!("Test timed out");
+        }
+    }
+}
+
+#[tokio::test]
+async fn malformed_incoming_query_closes_the_session_and_reports_the_peer() {
+    let protocol = Protocol::SignedBlockHeader;
+    let mut mock_swarm = MockSwarm::default();
+    let inbound_session_id = InboundSessionId { value: 0 };
+    let peer_id = PeerId::random();
+    mock_swarm.pending_events.push(Event::Behaviour(mixed_behaviour::Event::ExternalEvent(
+        mixed_behaviour::ExternalEvent::Sqmr(GenericEvent::NewInboundSession {
+            // A truncated varint, which isn't a valid protobuf message.
+            query: vec![0xff],
+            inbound_session_id,
+            peer_id,
+            protocol_name: protocol.chain_scoped_name(&ChainId::Sepolia, 1),
+        }),
+    )));
+    let get_responses_fut = mock_swarm.get_responses_sent_to_inbound_session(inbound_session_id);
+    let mut reported_peers_stream = mock_swarm.get_reported_peers_stream();
+
+    let mut network_manager_builder = GenericNetworkManagerBuilder::generic_new(
+        mock_swarm,
+        BUFFER_SIZE,
+        BUFFER_SIZE,
+        MAX_CONCURRENT_OUTBOUND_SESSIONS,
+        AGING_INTERVAL,
+        PROTOCOL_NAMES.clone(),
+        NetworkEventLog::new(false, BUFFER_SIZE),
+    );
+    let mut inbound_query_receiver = network_manager_builder
+        .register_sqmr_protocol_server::<HeaderQuery, Vec<u8>>(protocol)
+        .unwrap();
+    let (network_manager, _registrations) = network_manager_builder.build().unwrap();
+
+    select! {
+        _ = async move {
+            // The query is decoded by the server's task, which gets the decoding error.
+            let (query, _response_sender, query_peer_id) =
+                inbound_query_receiver.next().await.unwrap();
+            assert!(query.is_err());
+            assert_eq!(query_peer_id, peer_id);
+            assert_eq!(reported_peers_stream.next().await, Some(peer_id));
+            assert!(get_responses_fut.await.is_empty());
+        } => {}
+        _ = network_manager.run() => {
+            panic!("GenericNetworkManager::run finished before the session was closed");
+        }
+        _ = sleep(TIMEOUT) => {
+            panic!("Test timed out");
+        }
+    }
+}
+
+#[tokio::test]
+async fn failed_inbound_session_closes_the_servers_response_sender() {
+    let protocol = Protocol::SignedBlockHeader;
+    let mut mock_swarm = MockSwarm::default();
+    let inbound_session_id = InboundSessionId { value: 0 };
+    let peer_id = PeerId::random();
+    mock_swarm.pending_events.push(Event::Behaviour(mixed_behaviour::Event::ExternalEvent(
+        mixed_behaviour::ExternalEvent::Sqmr(GenericEvent::NewInboundSession {
+            query: VEC1.clone(),
+            inbound_session_id,
+            peer_id,
+            protocol_name: protocol.chain_scoped_name(&ChainId::Sepolia, 1),
+        }),
+    )));
+    mock_swarm.pending_events.push(Event::Behaviour(mixed_behaviour::Event::ExternalEvent(
+        mixed_behaviour::ExternalEvent::Sqmr(GenericEvent::SessionFailed {
+            session_id: inbound_session_id.into(),
+            error: SessionError::Timeout { session_timeout: TIMEOUT },
+        }),
+    )));
+    let _get_responses_fut = mock_swarm.get_responses_sent_to_inbound_session(inbound_session_id);
+
+    let mut network_manager_builder = GenericNetworkManagerBuilder::generic_new(
+        mock_swarm,
+        BUFFER_SIZE,
+        BUFFER_SIZE,
+        MAX_CONCURRENT_OUTBOUND_SESSIONS,
+        AGING_INTERVAL,
+        PROTOCOL_NAMES.clone(),
+        NetworkEventLog::new(false, BUFFER_SIZE),
+    );
+    let mut inbound_query_receiver = network_manager_builder
+        .register_sqmr_protocol_server::<Vec<u8>, Vec<u8>>(protocol)
+        .unwrap();
+    let (network_manager, _registrations) = network_manager_builder.build().unwrap();
+
+    select! {
+        _ = async move {
+            let (_query, mut responses_sender, _peer_id) =
+                inbound_query_receiver.next().await.unwrap();
+            // The responses are accepted until the network manager handles the failure.
+            while responses_sender.send(VEC2.clone()).await.is_ok() {}
+        } => {}
+        _ = network_manager.run() => {
+            panic!("GenericNetworkManager::run finished before the session failed");
+        }
+        _ = sleep(TIMEOUT) => {
+            panic!("Test timed out");
+        }
+    }
+}
+
+#[tokio::test]
+async fn inbound_queries_above_the_pending_limit_are_rejected_until_the_load_drops() {
+    let protocol = Protocol::SignedBlockHeader;
+    let new_inbound_session_event = |value| {
+        Event::Behaviour(mixed_behaviour::Event::ExternalEvent(
+            mixed_behaviour::ExternalEvent::Sqmr(GenericEvent::NewInboundSession {
+                query: VEC1.clone(),
+                inbound_session_id: InboundSessionId { value },
+                peer_id: PeerId::random(),
+                protocol_name: PROTOCOL_NAMES.stream_protocol(protocol),
+            }),
+        ))
+    };
+    let mut mock_swarm = MockSwarm::default();
+    let pending_events = mock_swarm.pending_events.clone();
+    pending_events.push(new_inbound_session_event(0));
+    pending_events.push(new_inbound_session_event(1));
+    let _get_first_responses_fut =
+        mock_swarm.get_responses_sent_to_inbound_session(InboundSessionId { value: 0 });
+    let get_rejected_responses_fut =
+        mock_swarm.get_responses_sent_to_inbound_session(InboundSessionId { value: 1 });
+    let _get_last_responses_fut =
+        mock_swarm.get_responses_sent_to_inbound_session(InboundSessionId { value: 2 });
+    let mut served_bytes_stream = mock_swarm.get_served_bytes_stream();
+
+    let mut network_manager_builder = GenericNetworkManagerBuilder::generic_new(
+        mock_swarm,
+        BUFFER_SIZE,
+        BUFFER_SIZE,
+        MAX_CONCURRENT_OUTBOUND_SESSIONS,
+        AGING_INTERVAL,
+        PROTOCOL_NAMES.clone(),
+        NetworkEventLog::new(false, BUFFER_SIZE),
+    )
+    .with_inbound_query_queues(HashMap::from([(
+        protocol,
+        InboundQueryQueueConfig {
+            max_pending_queries: 1,
+            overload_policy: InboundQueryOverloadPolicy::Reject,
+        },
+    )]));
+    let mut inbound_query_receiver = network_manager_builder
+        .register_sqmr_protocol_server::<Vec<u8>, Vec<u8>>(protocol)
+        .unwrap();
+    let (network_manager, _registrations) = network_manager_builder.build().unwrap();
+
+    select! {
+        _ = async move {
+            let (_query, mut responses_sender, _peer_id) =
+                inbound_query_receiver.next().await.unwrap();
+            // The second query arrived while the first one was pending.
+            assert!(get_rejected_responses_fut.await.is_empty());
+            assert!(inbound_query_receiver.next().now_or_never().is_none());
+
+            // Once the server starts responding to the first query, it's no longer pending.
+            responses_sender.send(VEC2.clone()).await.unwrap();
+            served_bytes_stream.next().await.unwrap();
+            pending_events.push(new_inbound_session_event(2));
+            inbound_query_receiver.next().await.unwrap();
+        } => {}
+        _ = network_manager.run() => {
+            panic!("GenericNetworkManager::run finished before the queries were handled");
+        }
+        _ = sleep(TIMEOUT) => {
+            panic!("Test timed out");
+        }
+    }
+}
+
+fn new_inbound_session_event(value: usize, peer_id: PeerId) -> Event {
+    Event::Behaviour(mixed_behaviour::Event::ExternalEvent(mixed_behaviour::ExternalEvent::Sqmr(
+        GenericEvent::NewInboundSession {
+            query: VEC1.clone(),
+            inbound_session_id: InboundSessionId { value },
+            peer_id,
+            protocol_name: PROTOCOL_NAMES.stream_protocol(Protocol::SignedBlockHeader),
+        },
+    )))
+}
+
+// The sync keeps all of its outbound sessions while the peers' queries take all the inbound
+// sessions, since the two directions don't share their sessions.
+#[tokio::test]
+async fn saturated_inbound_sessions_dont_take_the_outbound_sessions() {
+    const MAX_INBOUND_SESSIONS: usize = 2;
+    const MAX_OUTBOUND_SESSIONS: usize = 3;
+    const NUM_INBOUND_SESSIONS: usize = 5;
+    let protocol = Protocol::SignedBlockHeader;
+    let mut mock_swarm = MockSwarm::default();
+    mock_swarm.pending_events.push(get_test_connection_established_event(PeerId::random()));
+    let mut get_served_responses_futs = Vec::new();
+    let mut get_rejected_responses_futs = Vec::new();
+    for value in 0..NUM_INBOUND_SESSIONS {
+        mock_swarm.pending_events.push(new_inbound_session_event(value, PeerId::random()));
+        let get_responses_fut =
+            mock_swarm.get_responses_sent_to_inbound_session(InboundSessionId { value });
+        if value < MAX_INBOUND_SESSIONS {
+            get_served_responses_futs.push(get_responses_fut);
+        } else {
+            get_rejected_responses_futs.push(get_responses_fut);
+        }
+    }
+
+    let mut network_manager_builder = GenericNetworkManagerBuilder::generic_new(
+        mock_swarm,
+        BUFFER_SIZE,
+        BUFFER_SIZE,
+        MAX_OUTBOUND_SESSIONS,
+        AGING_INTERVAL,
+        PROTOCOL_NAMES.clone(),
+        NetworkEventLog::new(false, BUFFER_SIZE),
+    )
+    .with_inbound_session_limits(SessionLimits {
+        max_sessions: MAX_INBOUND_SESSIONS,
+        max_sessions_per_peer: MAX_INBOUND_SESSIONS,
+    });
+    let mut inbound_query_receiver = network_manager_builder
+        .register_sqmr_protocol_server::<Vec<u8>, Vec<u8>>(protocol)
+        .unwrap();
+    let SqmrSubscriberChannels { mut query_sender, mut response_receiver, .. } =
+        network_manager_builder.register_sqmr_subscriber::<Vec<u8>, Vec<u8>>(protocol).unwrap();
+    let (network_manager, _registrations) = network_manager_builder.build().unwrap();
+    let session_pools_usage = network_manager.session_pools_usage();
+    for query in 0..=MAX_OUTBOUND_SESSIONS {
+        query_sender.send(vec![u8::try_from(query).unwrap()]).await.unwrap();
+    }
+
+    select! {
+        _ = async move {
+            // The served queries are never answered, so they keep their sessions.
+            let mut served_queries = Vec::new();
+            for _ in 0..MAX_INBOUND_SESSIONS {
+                served_queries.push(inbound_query_receiver.next().await.unwrap());
+            }
+            for get_rejected_responses_fut in get_rejected_responses_futs {
+                assert!(get_rejected_responses_fut.await.is_empty());
+            }
+            assert!(inbound_query_receiver.next().now_or_never().is_none());
+
+            for query in 0..MAX_OUTBOUND_SESSIONS {
+                let (response, _report_callback) = response_receiver.next().await.unwrap();
+                assert_eq!(response.unwrap(), vec![u8::try_from(query).unwrap()]);
+            }
+            // Only the last query waits, for an outbound session to finish.
+            assert!(tokio::time::timeout(TIMEOUT, response_receiver.next()).await.is_err());
+            assert_eq!(
+                *session_pools_usage.lock().unwrap(),
+                SessionPools {
+                    inbound: SessionPoolUsage {
+                        num_active_sessions: MAX_INBOUND_SESSIONS,
+                        max_sessions: MAX_INBOUND_SESSIONS,
+                    },
+                    outbound: SessionPoolUsage {
+                        num_active_sessions: MAX_OUTBOUND_SESSIONS,
+                        max_sessions: MAX_OUTBOUND_SESSIONS,
+                    },
+                }
+            );
+            drop(get_served_responses_futs);
+        } => {}
+        _ = network_manager.run() => {
+            panic!("GenericNetworkManager::run finished before the queries were handled");
+        }
+        _ = sleep(TIMEOUT * 2) => {
+            panic!("Test timed out");
+        }
+    }
+}
+
+#[tokio::test]
+async fn inbound_sessions_above_the_peer_limit_are_rejected() {
+    let protocol = Protocol::SignedBlockHeader;
+    let busy_peer_id = PeerId::random();
+    let other_peer_id = PeerId::random();
+    let mut mock_swarm = MockSwarm::default();
+    mock_swarm.pending_events.push(new_inbound_session_event(0, busy_peer_id));
+    mock_swarm.pending_events.push(new_inbound_session_event(1, busy_peer_id));
+    mock_swarm.pending_events.push(new_inbound_session_event(2, other_peer_id));
+    let _get_first_responses_fut =
+        mock_swarm.get_responses_sent_to_inbound_session(InboundSessionId { value: 0 });
+    let get_rejected_responses_fut =
+        mock_swarm.get_responses_sent_to_inbound_session(InboundSessionId { value: 1 });
+    let _get_other_peer_responses_fut =
+        mock_swarm.get_responses_sent_to_inbound_session(InboundSessionId { value: 2 });
+
+    let mut network_manager_builder = GenericNetworkManagerBuilder::generic_new(
+        mock_swarm,
+        BUFFER_SIZE,
+        BUFFER_SIZE,
+        MAX_CONCURRENT_OUTBOUND_SESSIONS,
+        AGING_INTERVAL,
+        PROTOCOL_NAMES.clone(),
+        NetworkEventLog::new(false, BUFFER_SIZE),
+    )
+    .with_inbound_session_limits(SessionLimits { max_sessions: 10, max_sessions_per_peer: 1 });
+    let mut inbound_query_receiver = network_manager_builder
+        .register_sqmr_protocol_server::<Vec<u8>, Vec<u8>>(protocol)
+        .unwrap();
+    let (network_manager, _registrations) = network_manager_builder.build().unwrap();
+
+    select! {
+        _ = async move {
+            let (_query, _responses_sender, peer_id) =
+                inbound_query_receiver.next().await.unwrap();
+            assert_eq!(peer_id, busy_peer_id);
+            let (_query, _responses_sender, peer_id) =
+                inbound_query_receiver.next().await.unwrap();
+            assert_eq!(peer_id, other_peer_id);
+            assert!(get_rejected_responses_fut.await.is_empty());
+        } => {}
+        _ = network_manager.run() => {
+            panic!("GenericNetworkManager::run finished before the queries were handled");
+        }
+        _ = sleep(TIMEOUT) => {
+            panic!("Test timed out");
+        }
+    }
+}
+
+#[tokio::test]
+async fn closed_listener_is_reopened() {
+    let address: Multiaddr = "/ip4/127.0.0.1/tcp/10000".parse().unwrap();
+    let mut mock_swarm = MockSwarm { num_listeners_to_close: 1, ..Default::default() };
+    let mut listened_addresses_stream = mock_swarm.stream_listened_addresses();
+
+    let (network_manager, _registrations) = GenericNetworkManagerBuilder::generic_new(
+        mock_swarm,
+        BUFFER_SIZE,
+        BUFFER_SIZE,
+        MAX_CONCURRENT_OUTBOUND_SESSIONS,
+        AGING_INTERVAL,
+        PROTOCOL_NAMES.clone(),
+        NetworkEventLog::new(false, BUFFER_SIZE),
+    )
+    .with_listen_addresses(vec![address.clone()], 1)
+    .build()
+    .unwrap();
+
+    select! {
+        _ = async move {
+            assert_eq!(listened_addresses_stream.next().await, Some(address.clone()));
+            // Listening again after the listener closed.
+            assert_eq!(listened_addresses_stream.next().await, Some(address));
+            // The network manager keeps running.
+            sleep(TIMEOUT).await;
+        } => {}
+        result = network_manager.run() => {
+            panic!("GenericNetworkManager::run finished after a listener closed: {result:?}");
+        }
+        _ = sleep(TIMEOUT * 2) => {
+            panic!("Test timed out");
+        }
+    }
+}
+
+#[tokio::test]
+async fn failing_to_listen_is_a_recoverable_error() {
+    let address: Multiaddr = "/ip4/127.0.0.1/tcp/10000".parse().unwrap();
+    let mock_swarm = MockSwarm { fail_listening: true, ..Default::default() };
+
+    let (network_manager, _registrations) = GenericNetworkManagerBuilder::generic_new(
+        mock_swarm,
+        BUFFER_SIZE,
+        BUFFER_SIZE,
+        MAX_CONCURRENT_OUTBOUND_SESSIONS,
+        AGING_INTERVAL,
+        PROTOCOL_NAMES.clone(),
+        NetworkEventLog::new(false, BUFFER_SIZE),
+    )
+    .with_listen_addresses(vec![address.clone()], 1)
+    .build()
+    .unwrap();
+
+    let error = tokio::time::timeout(TIMEOUT, network_manager.run()).await.unwrap().unwrap_err();
+    assert!(error.is_recoverable());
+    assert_matches!(
+        error,
+        NetworkError::Recoverable(RecoverableNetworkError::ListenFailed {
+            address: failed_address,
+            num_attempts: 1,
+            ..
+        }) if failed_address == address
+    );
+}
+
+#[tokio::test]
+async fn killing_the_listener_of_a_real_swarm_doesnt_stop_the_network_manager() {
+    let config = NetworkConfig { tcp_port: 0, ..Default::default() };
+    let (mut network_manager, _registrations) =
+        NetworkManagerBuilder::new(config, ChainId::Sepolia).build().unwrap();
+    // Running for a while lets the network manager start listening.
+    assert!(tokio::time::timeout(TIMEOUT, network_manager.run_until_error()).await.is_err());
+    let listener_id = *network_manager.listener_id_to_address.keys().next().unwrap();
+
+    assert!(network_manager.swarm.remove_listener(listener_id));
+    assert!(tokio::time::timeout(TIMEOUT, network_manager.run_until_error()).await.is_err());
+    let new_listener_id = *network_manager.listener_id_to_address.keys().next().unwrap();
+    assert_ne!(listener_id, new_listener_id);
+}
+
+#[tokio::test]
+async fn real_node_that_doesnt_subscribe_to_a_topic_stays_out_of_its_mesh() {
+    let topic_hash = TOPIC.topic().hash();
+    let mut node_builder = NetworkManagerBuilder::new(
+        NetworkConfig { tcp_port: 0, ..Default::default() },
+        ChainId::Sepolia,
+    );
+    let _channels = node_builder.register_broadcast_subscriber(TOPIC, BUFFER_SIZE).unwrap();
+    let (mut node, _registrations) = node_builder.build().unwrap();
+    // Running for a while lets the node start listening.
+    assert!(tokio::time::timeout(TIMEOUT, node.run_until_error()).await.is_err());
+    let node_address = node
+        .swarm
+        .listeners()
+        .find(|address| address.to_string().starts_with("/ip4/127.0.0.1/"))
+        .unwrap()
+        .clone()
+        .with(MultiaddrProtocol::P2p(*node.swarm.local_peer_id()));
+
+    // Both peers connect to the node, and only one of them subscribes to the topic.
+    let peer_config = NetworkConfig {
+        tcp_port: 0,
+        bootstrap_peer_multiaddr: Some(node_address),
+        ..Default::default()
+    };
+    let mut subscribed_peer_builder =
+        NetworkManagerBuilder::new(peer_config.clone(), ChainId::Sepolia);
+    let _peer_channels =
+        subscribed_peer_builder.register_broadcast_subscriber(TOPIC, BUFFER_SIZE).unwrap();
+    let (mut subscribed_peer, _registrations) = subscribed_peer_builder.build().unwrap();
+    let (mut unsubscribed_peer, _registrations) =
+        NetworkManagerBuilder::new(peer_config, ChainId::Sepolia).build().unwrap();
+    let subscribed_peer_id = *subscribed_peer.swarm.local_peer_id();
+    let unsubscribed_peer_id = *unsubscribed_peer.swarm.local_peer_id();
+
+    // The nodes run until the subscribed peer joins the node's mesh of the topic and the
+    // unsubscribed peer is connected to the node's gossipsub.
+    let mut is_mesh_formed = false;
+    for _ in 0..100 {
+        let _ = tokio::time::timeout(
+            Duration::from_millis(100),
+            join3(
+                node.run_until_error(),
+                subscribed_peer.run_until_error(),
+                unsubscribed_peer.run_until_error(),
+            ),
+        )
+        .await;
+        let gossipsub = &node.swarm.behaviour().gossipsub;
+        is_mesh_formed = gossipsub.mesh_peers(&topic_hash).any(|peer| *peer == subscribed_peer_id)
+            && gossipsub.all_peers().any(|(peer, _)| *peer == unsubscribed_peer_id);
+        if is_mesh_formed {
+            break;
+        }
+    }
+    assert!(is_mesh_formed);
+
+    let gossipsub = &node.swarm.behaviour().gossipsub;
+    assert!(!gossipsub.mesh_peers(&topic_hash).any(|peer| *peer == unsubscribed_peer_id));
+    let (_, unsubscribed_peer_topics) =
+        gossipsub.all_peers().find(|(peer, _)| **peer == unsubscribed_peer_id).unwrap();
+    assert!(unsubscribed_peer_topics.is_empty());
+}
+
+#[tokio::test]
+async fn restart_keeps_the_channels_of_the_components() {
+    let message = vec![1u8, 2u8, 3u8];
+    let (broadcasted_messages_sender, mut messages_we_broadcasted_stream) = unbounded();
+    let (subscribed_topics_sender, mut subscribed_topics_stream) = unbounded();
+    let swarm_factory = move || {
+        let mut mock_swarm = MockSwarm::default();
+        mock_swarm.broadcasted_messages_senders.push(broadcasted_messages_sender.clone());
+        mock_swarm.subscribed_topic_senders.push(subscribed_topics_sender.clone());
+        mock_swarm
+    };
+
+    let mut network_manager_builder = GenericNetworkManagerBuilder::generic_new(
+        MockSwarm::default(),
+        BUFFER_SIZE,
+        BUFFER_SIZE,
+        MAX_CONCURRENT_OUTBOUND_SESSIONS,
+        AGING_INTERVAL,
+        PROTOCOL_NAMES.clone(),
+        NetworkEventLog::new(false, BUFFER_SIZE),
+    )
+    .with_swarm_factory(Box::new(swarm_factory));
+    let mut messages_to_broadcast_sender = network_manager_builder
+        .register_broadcast_subscriber(TOPIC, BUFFER_SIZE)
+        .unwrap()
+        .messages_to_broadcast_sender;
+    let (mut network_manager, _registrations) = network_manager_builder.build().unwrap();
+
+    network_manager.restart().unwrap();
+    assert_eq!(subscribed_topics_stream.next().await, Some(TOPIC.topic().hash()));
+    messages_to_broadcast_sender.send(message.clone()).await.unwrap();
+
+    tokio::select! {
+        error = network_manager.run_until_error() => panic!("network manager ended: {error:?}"),
+        result = tokio::time::timeout(
+            TIMEOUT, messages_we_broadcasted_stream.next()
+        ) => {
+            let (actual_message, topic_hash) = result.unwrap().unwrap();
+            assert_eq!(message, actual_message);
+            assert_eq!(TOPIC.topic().hash(), topic_hash);
+        }
+    }
+}
+
+#[tokio::test]
+async fn restart_without_a_swarm_factory_is_fatal() {
+    let (mut network_manager, _registrations) = GenericNetworkManagerBuilder::generic_new(
+        MockSwarm::default(),
+        BUFFER_SIZE,
+        BUFFER_SIZE,
+        MAX_CONCURRENT_OUTBOUND_SESSIONS,
+        AGING_INTERVAL,
+        PROTOCOL_NAMES.clone(),
+        NetworkEventLog::new(false, BUFFER_SIZE),
+    )
+    .build()
+    .unwrap();
+
+    let error = network_manager.restart().unwrap_err();
+    assert!(!error.is_recoverable());
+}
+
+#[tokio::test]
+async fn broadcast_message() {
+    let message = vec![1u8, 2u8, 3u8];
+
+    let mut mock_swarm = MockSwarm::default();
+    let mut messages_we_broadcasted_stream = mock_swarm.stream_messages_we_broadcasted();
+
+    let mut network_manager_builder = GenericNetworkManagerBuilder::generic_new(
+        mock_swarm,
+        BUFFER_SIZE,
+        BUFFER_SIZE,
+        MAX_CONCURRENT_OUTBOUND_SESSIONS,
+        AGING_INTERVAL,
+        PROTOCOL_NAMES.clone(),
+        NetworkEventLog::new(false, BUFFER_SIZE),
+    );
+
+    let mut messages_to_broadcast_sender = network_manager_builder
+        .register_broadcast_subscriber(TOPIC, BUFFER_SIZE)
+        .unwrap()
+        .messages_to_broadcast_sender;
+    let (network_manager, _registrations) = network_manager_builder.build().unwrap();
+    messages_to_broadcast_sender.send(message.clone()).await.unwrap();
+
+    tokio::select! {
+        _ = network_manager.run() => panic!("network manager ended"),
+        result = tokio::time::timeout(
+            TIMEOUT, messages_we_broadcasted_stream.next()
+        ) => {
+            let (actual_message, topic_hash) = result.unwrap().unwrap();
+            assert_eq!(message, actual_message);
+            assert_eq!(TOPIC.topic().hash(), topic_hash);
+        }
+    }
+}
+
+#[tokio::test]
+async fn publish_message_and_report_the_result() {
+    let message = vec![1u8, 2u8, 3u8];
+
+    let mut mock_swarm = MockSwarm::default();
+    let mut messages_we_broadcasted_stream = mock_swarm.stream_messages_we_broadcasted();
+
+    let mut network_manager_builder = GenericNetworkManagerBuilder::generic_new(
+        mock_swarm,
+        BUFFER_SIZE,
+        BUFFER_SIZE,
+        MAX_CONCURRENT_OUTBOUND_SESSIONS,
+        AGING_INTERVAL,
+        PROTOCOL_NAMES.clone(),
+        NetworkEventLog::new(false, BUFFER_SIZE),
+    );
+
+    let publisher =
+        network_manager_builder.register_broadcast_publisher(TOPIC, BUFFER_SIZE).unwrap();
+    let (network_manager, _registrations) = network_manager_builder.build().unwrap();
+
+    tokio::select! {
+        _ = network_manager.run() => panic!("network manager ended"),
+        result = tokio::time::timeout(TIMEOUT, publisher.publish(message.clone())) => {
+            result.unwrap().unwrap();
+            let (actual_message, topic_hash) = messages_we_broadcasted_stream.next().await.unwrap();
+            assert_eq!(message, actual_message);
+            assert_eq!(TOPIC.topic().hash(), topic_hash);
+        }
+    }
+}
+
+#[tokio::test]
+async fn publishing_without_peers_fails() {
+    let mut mock_swarm = MockSwarm::default();
+    mock_swarm.broadcast_error = Some(BroadcastError::NoPeers);
+
+    let mut network_manager_builder = GenericNetworkManagerBuilder::generic_new(
+        mock_swarm,
+        BUFFER_SIZE,
+        BUFFER_SIZE,
+        MAX_CONCURRENT_OUTBOUND_SESSIONS,
+        AGING_INTERVAL,
+        PROTOCOL_NAMES.clone(),
+        NetworkEventLog::new(false, BUFFER_SIZE),
+    );
+
+    let publisher =
+        network_manager_builder.register_broadcast_publisher(TOPIC, BUFFER_SIZE).unwrap();
+    let (network_manager, _registrations) = network_manager_builder.build().unwrap();
+
+    tokio::select! {
+        _ = network_manager.run() => panic!("network manager ended"),
+        result = tokio::time::timeout(TIMEOUT, publisher.publish(vec![1u8])) => {
+            assert_eq!(result.unwrap(), Err(BroadcastError::NoPeers));
+        }
+    }
+
+    // Once the network manager stops, publishing fails immediately.
+    assert_eq!(publisher.publish(vec![1u8]).await, Err(BroadcastError::NetworkStopped));
+}
+
+#[tokio::test]
+async fn receive_broadcasted_message_and_report_it() {
+    let message = vec![1u8, 2u8, 3u8];
+    let originated_peer_id = PeerId::random();
+
+    let mut mock_swarm = MockSwarm::default();
+    mock_swarm.pending_events.push(Event::Behaviour(mixed_behaviour::Event::ExternalEvent(
+        mixed_behaviour::ExternalEvent::GossipSub(gossipsub_impl::ExternalEvent::Received {
+            originated_peer_id,
+            message: message.clone(),
+            topic_hash: TOPIC.topic().hash(),
+        }),
+    )));
+    let mut reported_peer_receiver = mock_swarm.get_reported_peers_stream();
+
+    let mut network_manager_builder = GenericNetworkManagerBuilder::generic_new(
+        mock_swarm,
+        BUFFER_SIZE,
+        BUFFER_SIZE,
+        MAX_CONCURRENT_OUTBOUND_SESSIONS,
+        AGING_INTERVAL,
+        PROTOCOL_NAMES.clone(),
+        NetworkEventLog::new(false, BUFFER_SIZE),
+    );
+
+    let mut broadcasted_messages_receiver = network_manager_builder
+        .register_broadcast_subscriber(TOPIC, BUFFER_SIZE)
+        .unwrap()
+        .broadcasted_messages_receiver;
+    let (network_manager, _registrations) = network_manager_builder.build().unwrap();
+
+    tokio::select! {
+        _ = network_manager.run() => panic!("network manager ended"),
+        // We need to do the entire calculation in the future here so that the network will keep
+        // running while we call report_callback.
+        reported_peer_result = tokio::time::timeout(TIMEOUT, broadcasted_messages_receiver.next())
+            .then(|result| {
+                let (message_result, report_callback) = result.unwrap().unwrap();
+                assert_eq!(message, message_result.unwrap());
+                report_callback();
+                tokio::time::timeout(TIMEOUT, reported_peer_receiver.next())
+            }) => {
+            assert_eq!(originated_peer_id, reported_peer_result.unwrap().unwrap());
+        }
+    }
+}
+
+#[tokio::test]
+async fn dropping_the_subscriber_channels_unsubscribes_from_the_topic() {
+    let mut mock_swarm = MockSwarm::default();
+    let mut unsubscribed_topics_stream = mock_swarm.stream_unsubscribed_topics();
+
+    let mut network_manager_builder = GenericNetworkManagerBuilder::generic_new(
+        mock_swarm,
+        BUFFER_SIZE,
+        BUFFER_SIZE,
+        MAX_CONCURRENT_OUTBOUND_SESSIONS,
+        AGING_INTERVAL,
+        PROTOCOL_NAMES.clone(),
+        NetworkEventLog::new(false, BUFFER_SIZE),
+    );
+
+    let subscriber_channels =
+        network_manager_builder.register_broadcast_subscriber(TOPIC, BUFFER_SIZE).unwrap();
+    let (network_manager, _registrations) = network_manager_builder.build().unwrap();
+    drop(subscriber_channels);
+
+    tokio::select! {
+        _ = network_manager.run() => panic!("network manager ended"),
+        result = tokio::time::timeout(TIMEOUT, unsubscribed_topics_stream.next()) => {
+            assert_eq!(result.unwrap(), Some(TOPIC.topic().hash()));
+        }
+    }
+}
+
+#[tokio::test]
+async fn message_for_a_dropped_receiver_unsubscribes_and_broadcasting_still_works() {
+    let message = vec![1u8, 2u8, 3u8];
+
+    let mut mock_swarm = MockSwarm::default();
+    mock_swarm.pending_events.push(Event::Behaviour(mixed_behaviour::Event::ExternalEvent(
+        mixed_behaviour::ExternalEvent::GossipSub(gossipsub_impl::ExternalEvent::Received {
+            originated_peer_id: PeerId::random(),
+            message: message.clone(),
+            topic_hash: TOPIC.topic().hash(),
+        }),
+    )));
+    let mut unsubscribed_topics_stream = mock_swarm.stream_unsubscribed_topics();
+    let mut messages_we_broadcasted_stream = mock_swarm.stream_messages_we_broadcasted();
+
+    let mut network_manager_builder = GenericNetworkManagerBuilder::generic_new(
+        mock_swarm,
+        BUFFER_SIZE,
+        BUFFER_SIZE,
+        MAX_CONCURRENT_OUTBOUND_SESSIONS,
+        AGING_INTERVAL,
+        PROTOCOL_NAMES.clone(),
+        NetworkEventLog::new(false, BUFFER_SIZE),
+    );
+
+    // The subscriber only broadcasts on the topic.
+    let mut messages_to_broadcast_sender = network_manager_builder
+        .register_broadcast_subscriber(TOPIC, BUFFER_SIZE)
+        .unwrap()
+        .messages_to_broadcast_sender;
+    let (network_manager, _registrations) = network_manager_builder.build().unwrap();
+
+    tokio::select! {
+        _ = network_manager.run() => panic!("network manager ended"),
+        _ = async {
+            let unsubscribed_topic =
+                tokio::time::timeout(TIMEOUT, unsubscribed_topics_stream.next()).await.unwrap();
+            assert_eq!(unsubscribed_topic, Some(TOPIC.topic().hash()));
+            messages_to_broadcast_sender.send(message.clone()).await.unwrap();
+            let (actual_message, topic_hash) =
+                tokio::time::timeout(TIMEOUT, messages_we_broadcasted_stream.next())
+                    .await
+                    .unwrap()
+                    .unwrap();
+            assert_eq!(message, actual_message);
+            assert_eq!(TOPIC.topic().hash(), topic_hash);
+        } => {}
+    }
+}
+
+#[test]
+fn build_returns_registrations() {
+    let mut network_manager_builder = GenericNetworkManagerBuilder::generic_new(
+        MockSwarm::default(),
+        BUFFER_SIZE,
+        BUFFER_SIZE,
+        MAX_CONCURRENT_OUTBOUND_SESSIONS,
+        AGING_INTERVAL,
+        PROTOCOL_NAMES.clone(),
+        NetworkEventLog::new(false, BUFFER_SIZE),
+    );
+
+    network_manager_builder
+        .register_sqmr_subscriber::<Vec<u8>, Vec<u8>>(Protocol::SignedBlockHeader)
+        .unwrap();
+    network_manager_builder
+        .register_sqmr_subscriber::<Vec<u8>, Vec<u8>>(Protocol::StateDiff)
+        .unwrap();
+    // A protocol can be registered both as a client and as a server.
+    network_manager_builder
+        .register_sqmr_protocol_server::<Vec<u8>, Vec<u8>>(Protocol::SignedBlockHeader)
+        .unwrap();
+    network_manager_builder.register_broadcast_subscriber(TOPIC, BUFFER_SIZE).unwrap();
+
+    let expected_registrations = NetworkRegistrations {
+        sqmr_clients: vec![Protocol::SignedBlockHeader, Protocol::StateDiff],
+        sqmr_servers: vec![Protocol::SignedBlockHeader],
+        broadcast_topics: vec![TOPIC.name().to_string()],
+    };
+    assert_eq!(network_manager_builder.registrations(), &expected_registrations);
+    let (network_manager, registrations) = network_manager_builder.build().unwrap();
+    assert_eq!(registrations, expected_registrations);
+    assert!(network_manager.swarm.subscribed_topics.contains(&TOPIC.topic().hash()));
+}
+
+#[test]
+fn conflicting_registrations_return_errors() {
+    let mut network_manager_builder = GenericNetworkManagerBuilder::generic_new(
+        MockSwarm::default(),
+        BUFFER_SIZE,
+        BUFFER_SIZE,
+        MAX_CONCURRENT_OUTBOUND_SESSIONS,
+        AGING_INTERVAL,
+        PROTOCOL_NAMES.clone(),
+        NetworkEventLog::new(false, BUFFER_SIZE),
+    );
+
+    network_manager_builder
+        .register_sqmr_subscriber::<Vec<u8>, Vec<u8>>(Protocol::SignedBlockHeader)
+        .unwrap();
+    assert_matches!(
+        network_manager_builder
+            .register_sqmr_subscriber_with_data_availability_hints::<
+                Vec<u8>,
+                StateDiffAvailabilityResponse,
+            >(Protocol::SignedBlockHeader)
+            .err(),
+        Some(RegistrationError::ProtocolAlreadyRegisteredAsClient(Protocol::SignedBlockHeader))
+    );
+
+    network_manager_builder
+        .register_sqmr_protocol_server::<Vec<u8>, Vec<u8>>(Protocol::StateDiff)
+        .unwrap();
+    assert_matches!(
+        network_manager_builder
+            .register_sqmr_protocol_server::<Vec<u8>, Vec<u8>>(Protocol::StateDiff)
+            .err(),
+        Some(RegistrationError::ProtocolAlreadyRegisteredAsServer(Protocol::StateDiff))
+    );
+
+    network_manager_builder.register_broadcast_subscriber(TOPIC, BUFFER_SIZE).unwrap();
+    assert_matches!(
+        network_manager_builder
+            .register_broadcast_subscriber(TOPIC, BUFFER_SIZE)
+            .err(),
+        Some(RegistrationError::TopicAlreadyRegistered(topic_name))
+            if topic_name == TOPIC.name()
+    );
+    assert_matches!(
+        network_manager_builder
+            .register_raw_broadcast_subscriber(TOPIC.name().to_string(), BUFFER_SIZE)
+            .err(),
+        Some(RegistrationError::TopicAlreadyRegistered(_))
+    );
+    assert_matches!(
+        network_manager_builder
+            .register_broadcast_subscriber(
+                TopicDescriptor::<ConsensusMessage>::new(TOPIC.name()),
+                BUFFER_SIZE
+            )
+            .err(),
+        Some(RegistrationError::TopicRegisteredWithDifferentType { .. })
+    );
+
+    // The rejected registrations aren't listed.
+    let (_network_manager, registrations) = network_manager_builder.build().unwrap();
+    assert_eq!(
+        registrations,
+        NetworkRegistrations {
+            sqmr_clients: vec![Protocol::SignedBlockHeader],
+            sqmr_servers: vec![Protocol::StateDiff],
+            broadcast_topics: vec![TOPIC.name().to_string()],
+        }
+    );
+}
+
+#[test]
+fn registration_after_build_returns_error() {
+    let mut network_manager_builder = GenericNetworkManagerBuilder::generic_new(
+        MockSwarm::default(),
+        BUFFER_SIZE,
+        BUFFER_SIZE,
+        MAX_CONCURRENT_OUTBOUND_SESSIONS,
+        AGING_INTERVAL,
+        PROTOCOL_NAMES.clone(),
+        NetworkEventLog::new(false, BUFFER_SIZE),
+    );
+    network_manager_builder.build().unwrap();
+
+    assert_matches!(
+        network_manager_builder
+            .register_sqmr_subscriber::<Vec<u8>, Vec<u8>>(Protocol::SignedBlockHeader)
+            .err(),
+        Some(RegistrationError::AlreadyBuilt)
+    );
+    assert_matches!(
+        network_manager_builder
+            .register_sqmr_protocol_server::<Vec<u8>, Vec<u8>>(Protocol::SignedBlockHeader)
+            .err(),
+        Some(RegistrationError::AlreadyBuilt)
+    );
+    assert_matches!(
+        network_manager_builder.register_broadcast_subscriber::<Bytes>(TOPIC, 1).err(),
+        Some(RegistrationError::AlreadyBuilt)
+    );
+    assert_matches!(network_manager_builder.build().err(), Some(RegistrationError::AlreadyBuilt));
+}
+
+#[tokio::test]
+async fn network_events_are_recorded_up_to_the_buffer_size() {
+    const NETWORK_EVENTS_BUFFER_SIZE: usize = 2;
+    let peer_ids = [PeerId::random(), PeerId::random(), PeerId::random()];
+    let mock_swarm = MockSwarm::default();
+    for peer_id in peer_ids {
+        mock_swarm.pending_events.push(get_test_connection_established_event(peer_id));
+    }
+
+    let (network_manager, _registrations) = GenericNetworkManagerBuilder::generic_new(
+        mock_swarm,
+        BUFFER_SIZE,
+        BUFFER_SIZE,
+        MAX_CONCURRENT_OUTBOUND_SESSIONS,
+        AGING_INTERVAL,
+        PROTOCOL_NAMES.clone(),
+        NetworkEventLog::new(true, NETWORK_EVENTS_BUFFER_SIZE),
+    )
+    .build()
+    .unwrap();
+    let recent_network_events = network_manager.recent_network_events();
+
+    tokio::select! {
+        _ = network_manager.run() => panic!("network manager ended"),
+        result = tokio::time::timeout(TIMEOUT, async {
+            while recent_network_events.lock().unwrap().back().map(|event| event.sequence_number)
+                != Some(2)
+            {
+                sleep(Duration::from_millis(10)).await;
+            }
+        }) => {
+            result.unwrap();
+        }
+    }
+    // The oldest event was dropped.
+    let recent_network_events = recent_network_events.lock().unwrap();
+    assert_eq!(recent_network_events.len(), NETWORK_EVENTS_BUFFER_SIZE);
+    for (event, (expected_sequence_number, expected_peer_id)) in
+        recent_network_events.iter().zip([(1, peer_ids[1]), (2, peer_ids[2])])
+    {
+        assert_eq!(event.sequence_number, expected_sequence_number);
+        assert_eq!(event.peer_id, Some(expected_peer_id.to_string()));
+        assert_eq!(event.kind, NetworkEventKind::ConnectionEstablished);
+    }
+}
+
+#[tokio::test]
+async fn denied_connections_are_recorded() {
+    let inbound_peer_id = PeerId::random();
+    let outbound_peer_id = PeerId::random();
+    let mock_swarm = MockSwarm::default();
+    mock_swarm.pending_events.push(SwarmEvent::IncomingConnectionError {
+        connection_id: ConnectionId::new_unchecked(0),
+        local_addr: Multiaddr::empty(),
+        send_back_addr: Multiaddr::empty(),
+        error: ListenError::Denied {
+            cause: ConnectionDenied::new(ConnectionGatingError::PeerIsNotAllowed(inbound_peer_id)),
+        },
+    });
+    mock_swarm.pending_events.push(SwarmEvent::OutgoingConnectionError {
+        connection_id: ConnectionId::new_unchecked(1),
+        peer_id: Some(outbound_peer_id),
+        error: DialError::Denied {
+            cause: ConnectionDenied::new(ConnectionGatingError::PeerIsDenied(outbound_peer_id)),
+        },
+    });
+
+    let (network_manager, _registrations) = GenericNetworkManagerBuilder::generic_new(
+        mock_swarm,
+        BUFFER_SIZE,
+        BUFFER_SIZE,
+        MAX_CONCURRENT_OUTBOUND_SESSIONS,
+        AGING_INTERVAL,
+        PROTOCOL_NAMES.clone(),
+        NetworkEventLog::new(true, BUFFER_SIZE),
+    )
+    .build()
+    .unwrap();
+    let recent_network_events = network_manager.recent_network_events();
+
+    tokio::select! {
+        _ = network_manager.run() => panic!("network manager ended"),
+        result = tokio::time::timeout(TIMEOUT, async {
+            while recent_network_events.lock().unwrap().len() < 2 {
+                sleep(Duration::from_millis(10)).await;
+            }
+        }) => {
+            result.unwrap();
+        }
+    }
+    let recent_network_events = recent_network_events.lock().unwrap();
+    for (event, expected_peer_id) in
+        recent_network_events.iter().zip([inbound_peer_id, outbound_peer_id])
+    {
+        assert_eq!(event.peer_id, Some(expected_peer_id.to_string()));
+        assert_eq!(event.kind, NetworkEventKind::ConnectionDenied);
+    }
+}
+
+#[tokio::test]
+async fn network_events_are_not_recorded_when_disabled() {
+    let mock_swarm = MockSwarm::default();
+    mock_swarm.pending_events.push(get_test_connection_established_event(PeerId::random()));
+    let num_polled_events = mock_swarm.get_num_polled_events();
+
+    let (network_manager, _registrations) = GenericNetworkManagerBuilder::generic_new(
+        mock_swarm,
+        BUFFER_SIZE,
+        BUFFER_SIZE,
+        MAX_CONCURRENT_OUTBOUND_SESSIONS,
+        AGING_INTERVAL,
+        PROTOCOL_NAMES.clone(),
+        NetworkEventLog::new(false, BUFFER_SIZE),
+    )
+    .build()
+    .unwrap();
+    let recent_network_events = network_manager.recent_network_events();
+
+    tokio::select! {
+        _ = network_manager.run() => panic!("network manager ended"),
+        _ = async {
+            while num_polled_events.load(Ordering::SeqCst) == 0 {
+                sleep(Duration::from_millis(10)).await;
+            }
+            // Letting the network manager handle the event.
+            sleep(Duration::from_millis(10)).await;
+        } => {}
+    }
+    assert!(recent_network_events.lock().unwrap().is_empty());
+}
+
 fn get_test_connection_established_event(mock_peer_id: PeerId) -> Event {
     Event::ConnectionEstablished {
         peer_id: mock_peer_id,
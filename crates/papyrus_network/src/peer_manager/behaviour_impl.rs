@@ -1,5 +1,7 @@
-use std::task::Poll;
+use std::task::{ready, Poll};
+use std::time::Duration;
 
+use futures::FutureExt;
 use libp2p::swarm::behaviour::ConnectionEstablished;
 use libp2p::swarm::{
     dummy,
@@ -24,6 +26,11 @@ pub enum ToOtherBehaviourEvent {
         peer_id: PeerId,
         connection_id: ConnectionId,
     },
+    /// No peer was connected for the session within the dial deadline, so it wasn't sent.
+    NoPeersForSession {
+        outbound_session_id: OutboundSessionId,
+        dial_deadline: Duration,
+    },
     PauseDiscovery,
     ResumeDiscovery,
 }
@@ -42,6 +49,12 @@ where
         _local_addr: &libp2p::Multiaddr,
         _remote_addr: &libp2p::Multiaddr,
     ) -> Result<libp2p::swarm::THandler<Self>, libp2p::swarm::ConnectionDenied> {
+        // Manually banned peers are denied even if the peer manager didn't find them yet.
+        if self.manual_ban(&inbound_peer_id).is_some() {
+            return Err(libp2p::swarm::ConnectionDenied::new(PeerManagerError::PeerIsBlocked(
+                inbound_peer_id,
+            )));
+        }
         // TODO: consider implementing a better lookup mechanism in case there's a lot of peers this
         // will be slow
         match self
@@ -71,10 +84,16 @@ where
     fn handle_established_outbound_connection(
         &mut self,
         _connection_id: libp2p::swarm::ConnectionId,
-        _peer: libp2p::PeerId,
+        peer: libp2p::PeerId,
         _addr: &libp2p::Multiaddr,
         _role_override: libp2p::core::Endpoint,
     ) -> Result<libp2p::swarm::THandler<Self>, libp2p::swarm::ConnectionDenied> {
+        // Other behaviours (e.g discovery) may dial a manually banned peer.
+        if self.manual_ban(&peer).is_some() {
+            return Err(libp2p::swarm::ConnectionDenied::new(PeerManagerError::PeerIsBlocked(
+                peer,
+            )));
+        }
         Ok(dummy::ConnectionHandler)
     }
 
@@ -108,15 +127,10 @@ where
                 if res.is_err() {
                     error!("Dial failure of an unknow peer. peer id: {}", peer_id)
                 }
-                // Re-assign a peer to the session so that a SessionAssgined Event will be emitted.
-                // TODO: test this case
+                // Re-assign a peer to the sessions that waited for the dial so that a
+                // SessionAssgined Event will be emitted.
                 let queries_to_assign =
-                    self.session_to_peer_map
-                        .iter()
-                        .filter_map(|(outbound_session_id, p_id)| {
-                            if *p_id == peer_id { Some(*outbound_session_id) } else { None }
-                        })
-                        .collect::<Vec<_>>();
+                    self.peers_pending_dial_with_sessions.remove(&peer_id).unwrap_or_default();
                 for outbound_session_id in queries_to_assign {
                     self.assign_peer_to_session(outbound_session_id);
                 }
@@ -127,6 +141,9 @@ where
                 ..
             }) => {
                 if let Some(sessions) = self.peers_pending_dial_with_sessions.remove(&peer_id) {
+                    for outbound_session_id in &sessions {
+                        self.stop_waiting_for_connection(*outbound_session_id);
+                    }
                     self.pending_events.extend(sessions.iter().map(|outbound_session_id| {
                         ToSwarm::GenerateEvent(ToOtherBehaviourEvent::SessionAssigned {
                             outbound_session_id: *outbound_session_id,
@@ -150,6 +167,12 @@ where
                     };
                     peer.add_connection_id(connection_id);
                 }
+                // E.g the peer was dialed by discovery or dialed us.
+                if !self.sessions_without_peer.is_empty()
+                    && self.peers.get(&peer_id).is_some_and(|peer| !peer.is_blocked())
+                {
+                    self.assign_sessions_without_peer();
+                }
             }
             libp2p::swarm::FromSwarm::ConnectionClosed(ConnectionClosed {
                 peer_id,
@@ -175,9 +198,26 @@ where
 
     fn poll(
         &mut self,
-        _cx: &mut std::task::Context<'_>,
+        cx: &mut std::task::Context<'_>,
     ) -> std::task::Poll<libp2p::swarm::ToSwarm<Self::ToSwarm, libp2p::swarm::THandlerInEvent<Self>>>
     {
-        self.pending_events.pop().map(Poll::Ready).unwrap_or(Poll::Pending)
+        loop {
+            if let Some(event) = self.pending_events.pop() {
+                return Poll::Ready(event);
+            }
+            let Some((_, deadline)) = self.sessions_waiting_for_connection.front() else {
+                self.dial_deadline_sleep = None;
+                return Poll::Pending;
+            };
+            // If the session this sleep was created for was assigned in the meantime, the sleep
+            // ends before the first deadline, and a new sleep is created.
+            let deadline = *deadline;
+            let dial_deadline_sleep = self
+                .dial_deadline_sleep
+                .get_or_insert_with(|| tokio::time::sleep_until(deadline).boxed());
+            ready!(dial_deadline_sleep.poll_unpin(cx));
+            self.dial_deadline_sleep = None;
+            self.fail_sessions_past_dial_deadline();
+        }
     }
 }
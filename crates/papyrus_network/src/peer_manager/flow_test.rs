@@ -0,0 +1,142 @@
+use std::time::Duration;
+
+use futures::StreamExt;
+use libp2p::swarm::{NetworkBehaviour, SwarmEvent};
+use libp2p::{PeerId, StreamProtocol, Swarm};
+use libp2p_swarm_test::SwarmExt;
+use starknet_api::block::BlockNumber;
+
+use super::peer::{Peer, PeerTrait};
+use super::{PeerManager, PeerManagerConfig};
+use crate::mixed_behaviour::{self, BridgedBehaviour};
+use crate::sqmr::behaviour::{Event as SqmrEvent, ExternalEvent as SqmrExternalEvent};
+use crate::{sqmr, Protocol};
+
+#[derive(NetworkBehaviour)]
+struct ClientBehaviour {
+    pub peer_manager: PeerManager<Peer>,
+    pub sqmr: sqmr::Behaviour,
+}
+
+fn sqmr_behaviour(protocol: StreamProtocol) -> sqmr::Behaviour {
+    sqmr::Behaviour::new(sqmr::Config {
+        session_timeout: Duration::from_secs(5),
+        supported_inbound_protocols: vec![protocol],
+    })
+}
+
+// Passes the events of the client's behaviours to each other, the way the network manager does.
+fn bridge_client_event(
+    client: &mut Swarm<ClientBehaviour>,
+    event: SwarmEvent<ClientBehaviourEvent>,
+) {
+    match event {
+        SwarmEvent::Behaviour(ClientBehaviourEvent::PeerManager(event)) => {
+            let event = mixed_behaviour::ToOtherBehaviourEvent::PeerManager(event);
+            client.behaviour_mut().sqmr.on_other_behaviour_event(&event);
+        }
+        SwarmEvent::Behaviour(ClientBehaviourEvent::Sqmr(SqmrEvent::ToOtherBehaviourEvent(
+            event,
+        ))) => {
+            let event = mixed_behaviour::ToOtherBehaviourEvent::Sqmr(event);
+            client.behaviour_mut().peer_manager.on_other_behaviour_event(&event);
+        }
+        _ => {}
+    }
+}
+
+fn is_new_inbound_session(event: SwarmEvent<SqmrEvent>) -> bool {
+    matches!(
+        event,
+        SwarmEvent::Behaviour(SqmrEvent::External(SqmrExternalEvent::NewInboundSession { .. }))
+    )
+}
+
+// Starts a query for the given block on the client and returns the peer id of the server the query
+// was sent to.
+async fn query_and_get_serving_peer(
+    client: &mut Swarm<ClientBehaviour>,
+    first_server: &mut Swarm<sqmr::Behaviour>,
+    second_server: &mut Swarm<sqmr::Behaviour>,
+    protocol: StreamProtocol,
+    block_number: BlockNumber,
+) -> PeerId {
+    let outbound_session_id = client.behaviour_mut().sqmr.start_query(vec![0], vec![protocol]);
+    client
+        .behaviour_mut()
+        .peer_manager
+        .set_session_block_range(outbound_session_id, block_number..block_number.unchecked_next());
+    loop {
+        tokio::select! {
+            event = client.select_next_some() => bridge_client_event(client, event),
+            event = first_server.select_next_some() => {
+                if is_new_inbound_session(event) {
+                    return *first_server.local_peer_id();
+                }
+            }
+            event = second_server.select_next_some() => {
+                if is_new_inbound_session(event) {
+                    return *second_server.local_peer_id();
+                }
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn sessions_follow_the_latest_data_availability_hints_of_the_peers() {
+    let protocol: StreamProtocol = Protocol::StateDiff.into();
+    let mut client = Swarm::new_ephemeral(|_| ClientBehaviour {
+        peer_manager: PeerManager::new(PeerManagerConfig::default()),
+        sqmr: sqmr_behaviour(protocol.clone()),
+    });
+    // A peer that holds only headers and a peer that holds the full blocks.
+    let mut headers_only_server = Swarm::new_ephemeral(|_| sqmr_behaviour(protocol.clone()));
+    let mut full_server = Swarm::new_ephemeral(|_| sqmr_behaviour(protocol.clone()));
+    for server in [&mut headers_only_server, &mut full_server] {
+        server.listen().with_memory_addr_external().await;
+        let address = server.external_addresses().next().unwrap().clone();
+        client.behaviour_mut().peer_manager.add_peer(Peer::new(*server.local_peer_id(), address));
+        client.connect(server).await;
+    }
+    let headers_only_peer_id = *headers_only_server.local_peer_id();
+    let full_peer_id = *full_server.local_peer_id();
+
+    let block_number = BlockNumber(3);
+    let peer_manager = &mut client.behaviour_mut().peer_manager;
+    peer_manager
+        .update_peer_block_availability(headers_only_peer_id, protocol.clone(), block_number, false)
+        .unwrap();
+    peer_manager
+        .update_peer_block_availability(full_peer_id, protocol.clone(), block_number, true)
+        .unwrap();
+    for _ in 0..2 {
+        let serving_peer_id = query_and_get_serving_peer(
+            &mut client,
+            &mut headers_only_server,
+            &mut full_server,
+            protocol.clone(),
+            block_number,
+        )
+        .await;
+        assert_eq!(serving_peer_id, full_peer_id);
+    }
+
+    // The full peer pruned its data and the other peer synced it meanwhile.
+    let peer_manager = &mut client.behaviour_mut().peer_manager;
+    peer_manager
+        .update_peer_block_availability(full_peer_id, protocol.clone(), block_number, false)
+        .unwrap();
+    peer_manager
+        .update_peer_block_availability(headers_only_peer_id, protocol.clone(), block_number, true)
+        .unwrap();
+    let serving_peer_id = query_and_get_serving_peer(
+        &mut client,
+        &mut headers_only_server,
+        &mut full_server,
+        protocol,
+        block_number,
+    )
+    .await;
+    assert_eq!(serving_peer_id, headers_only_peer_id);
+}
@@ -1,23 +1,112 @@
-use std::collections::HashMap;
+use std::collections::{HashMap, VecDeque};
+use std::ops::Range;
+use std::sync::{Arc, Mutex};
 
-use chrono::Duration;
+use chrono::{DateTime, Duration, Utc};
+use futures::channel::oneshot;
+use futures::future::BoxFuture;
 use libp2p::swarm::dial_opts::DialOpts;
-use libp2p::swarm::ToSwarm;
-use libp2p::PeerId;
-use tracing::info;
+use libp2p::swarm::{CloseConnection, ToSwarm};
+use libp2p::{Multiaddr, PeerId, StreamProtocol};
+use metrics::{gauge, increment_counter};
+use papyrus_common::metrics as papyrus_metrics;
+use serde::Serialize;
+use starknet_api::block::BlockNumber;
+use tokio::time::Instant;
+use tracing::{info, warn};
 
 pub use self::behaviour_impl::ToOtherBehaviourEvent;
 use self::peer::PeerTrait;
 use crate::discovery::identify_impl::IdentifyToOtherBehaviourEvent;
 use crate::mixed_behaviour::BridgedBehaviour;
+use crate::ping_impl::PingToOtherBehaviourEvent;
 use crate::sqmr::OutboundSessionId;
 use crate::{discovery, mixed_behaviour, sqmr};
 
 pub(crate) mod behaviour_impl;
+#[cfg(test)]
+mod flow_test;
 pub(crate) mod peer;
 #[cfg(test)]
 mod test;
 
+/// The number of bytes the node sent to each peer in response to the peer's queries.
+pub type ServedBytesByPeer = Arc<Mutex<HashMap<PeerId, u64>>>;
+
+/// The name each peer last negotiated a session of each protocol with, by the name of the newest
+/// version of the protocol. The version of the protocol is the last segment of the negotiated name.
+pub type NegotiatedProtocolsByPeer =
+    Arc<Mutex<HashMap<PeerId, HashMap<StreamProtocol, StreamProtocol>>>>;
+
+/// What each peer reported about itself in the identify protocol.
+pub type PeerIdentities = Arc<Mutex<HashMap<PeerId, PeerIdentity>>>;
+
+/// The latency of each peer that was pinged, kept after the peer disconnects.
+pub type PeerLatencies = Arc<Mutex<HashMap<PeerId, PeerLatency>>>;
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PeerLatency {
+    /// The round trip time of the pings to the peer, smoothed over the recent pings. None until the
+    /// peer answers a ping.
+    pub smoothed_rtt: Option<std::time::Duration>,
+    /// The number of pings the peer failed to answer.
+    pub lost_pings: u64,
+    /// The number of pings the peer failed to answer since it last answered one.
+    pub consecutive_lost_pings: u32,
+    /// False once the peer failed to answer too many consecutive pings, until it answers one. An
+    /// unhealthy peer is assigned sessions only if no healthy peer can take them.
+    pub healthy: bool,
+}
+
+impl Default for PeerLatency {
+    fn default() -> Self {
+        Self { smoothed_rtt: None, lost_pings: 0, consecutive_lost_pings: 0, healthy: true }
+    }
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct PeerIdentity {
+    /// The name and version of the software the peer runs.
+    pub agent_version: String,
+    /// The names of the protocols the peer supports.
+    pub protocols: Vec<String>,
+}
+
+/// A command for inspecting or changing the peer manager from outside the swarm task. Commands are
+/// applied by the network manager's task, so the peer manager's state is never shared.
+#[derive(Debug)]
+pub enum PeerManagerCommand {
+    /// Send back a dump of the peer manager's state.
+    GetState(oneshot::Sender<PeerManagerState>),
+    /// Block the peer for the given duration. The ban is kept even if the peer reconnects or is
+    /// found again, and it may be applied before the peer was found.
+    Blacklist { peer_id: PeerId, duration: std::time::Duration },
+    /// Lift the manual ban of the peer and any block it got due to its reputation.
+    Unblacklist { peer_id: PeerId },
+}
+
+/// A dump of the peer manager's state.
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize)]
+pub struct PeerManagerState {
+    /// The peers the peer manager found, sorted by their id.
+    pub peers: Vec<PeerState>,
+    /// The expiry time (RFC 3339) of each manual ban that is still active, including bans of peers
+    /// that weren't found yet.
+    pub manual_bans: Vec<(PeerId, String)>,
+}
+
+#[derive(Clone, Debug, PartialEq, Eq, Serialize)]
+pub struct PeerState {
+    pub peer_id: PeerId,
+    pub multiaddr: Multiaddr,
+    /// The time (RFC 3339) until which the peer is blocked, if it's blocked.
+    pub blocked_until: Option<String>,
+    pub manually_banned: bool,
+    pub num_connections: usize,
+    /// The active outbound sessions that are assigned to the peer.
+    pub assigned_sessions: Vec<usize>,
+}
+
 #[cfg_attr(test, derive(Debug, PartialEq))]
 pub enum ReputationModifier {
     // TODO: Implement this enum
@@ -26,19 +115,49 @@ pub enum ReputationModifier {
 
 pub struct PeerManager<P: PeerTrait + 'static> {
     peers: HashMap<PeerId, P>,
-    // TODO: consider implementing a cleanup mechanism to not store all queries forever
+    // The sessions are removed from these maps once they finish.
     session_to_peer_map: HashMap<OutboundSessionId, PeerId>,
+    session_to_protocol: HashMap<OutboundSessionId, StreamProtocol>,
+    session_to_block_range: HashMap<OutboundSessionId, Range<BlockNumber>>,
     config: PeerManagerConfig,
     last_peer_index: usize,
     pending_events: Vec<ToSwarm<ToOtherBehaviourEvent, libp2p::swarm::THandlerInEvent<Self>>>,
+    // A peer is dialed only if it isn't in this map, so that the sessions that wait for the same
+    // peer share a single dial.
     peers_pending_dial_with_sessions: HashMap<PeerId, Vec<OutboundSessionId>>,
-    sessions_received_when_no_peers: Vec<OutboundSessionId>,
+    // The sessions for which no unblocked peer with capacity was found. They're assigned again
+    // once a peer is found or connected, or once a session of a peer finishes.
+    sessions_without_peer: Vec<OutboundSessionId>,
+    // The sessions that weren't assigned to a connected peer yet, with the time at which they
+    // fail if no peer was connected until then. Sorted by the deadline.
+    sessions_waiting_for_connection: VecDeque<(OutboundSessionId, Instant)>,
+    // Wakes the peer manager once the first session waiting for a connection reaches its deadline.
+    // This needs to be boxed to allow polling it from a &mut.
+    dial_deadline_sleep: Option<BoxFuture<'static, ()>>,
+    // Shared so that it can be read while the swarm is running.
+    served_bytes_by_peer: ServedBytesByPeer,
+    // Shared so that it can be read while the swarm is running.
+    negotiated_protocols_by_peer: NegotiatedProtocolsByPeer,
+    // Shared so that it can be read while the swarm is running.
+    peer_identities: PeerIdentities,
+    // Shared so that it can be read while the swarm is running.
+    peer_latencies: PeerLatencies,
+    // The time until which each manually banned peer is banned.
+    manual_bans: HashMap<PeerId, DateTime<Utc>>,
 }
 
 #[derive(Clone)]
 pub struct PeerManagerConfig {
     target_num_for_peers: usize,
     blacklist_timeout: Duration,
+    pub(crate) block_range_advertisement_ttl: Duration,
+    pub(crate) bootstrap_peer_id: Option<PeerId>,
+    /// How long a session waits for a peer to be connected before it fails.
+    pub(crate) dial_deadline: std::time::Duration,
+    /// The number of active sessions above which a peer isn't assigned more sessions.
+    pub(crate) max_sessions_per_peer: usize,
+    /// The number of consecutive pings a peer fails to answer after which it's unhealthy.
+    pub(crate) max_consecutive_lost_pings: u32,
 }
 
 #[derive(thiserror::Error, Debug)]
@@ -53,7 +172,15 @@ pub(crate) enum PeerManagerError {
 
 impl Default for PeerManagerConfig {
     fn default() -> Self {
-        Self { target_num_for_peers: 100, blacklist_timeout: Duration::max_value() }
+        Self {
+            target_num_for_peers: 100,
+            blacklist_timeout: Duration::max_value(),
+            block_range_advertisement_ttl: Duration::minutes(5),
+            bootstrap_peer_id: None,
+            dial_deadline: std::time::Duration::from_secs(60),
+            max_sessions_per_peer: usize::MAX,
+            max_consecutive_lost_pings: 3,
+        }
     }
 }
 
@@ -67,19 +194,35 @@ where
         Self {
             peers,
             session_to_peer_map: HashMap::new(),
+            session_to_protocol: HashMap::new(),
+            session_to_block_range: HashMap::new(),
             config,
             last_peer_index: 0,
             pending_events: Vec::new(),
             peers_pending_dial_with_sessions: HashMap::new(),
-            sessions_received_when_no_peers: Vec::new(),
+            sessions_without_peer: Vec::new(),
+            sessions_waiting_for_connection: VecDeque::new(),
+            dial_deadline_sleep: None,
+            served_bytes_by_peer: Arc::new(Mutex::new(HashMap::new())),
+            negotiated_protocols_by_peer: Arc::new(Mutex::new(HashMap::new())),
+            peer_identities: Arc::new(Mutex::new(HashMap::new())),
+            peer_latencies: Arc::new(Mutex::new(HashMap::new())),
+            manual_bans: HashMap::new(),
         }
     }
 
     fn add_peer(&mut self, mut peer: P) {
         info!("Peer Manager found new peer {:?}", peer.peer_id());
         peer.set_timeout_duration(self.config.blacklist_timeout);
+        if let Some(banned_until) = self.manual_ban(&peer.peer_id()) {
+            peer.block_until(banned_until);
+        }
         self.peers.insert(peer.peer_id(), peer);
-        for outbound_session_id in std::mem::take(&mut self.sessions_received_when_no_peers) {
+        self.assign_sessions_without_peer();
+    }
+
+    fn assign_sessions_without_peer(&mut self) {
+        for outbound_session_id in std::mem::take(&mut self.sessions_without_peer) {
             self.assign_peer_to_session(outbound_session_id);
         }
     }
@@ -93,53 +236,268 @@ where
     fn assign_peer_to_session(&mut self, outbound_session_id: OutboundSessionId) -> Option<PeerId> {
         // TODO: consider moving this logic to be async (on a different tokio task)
         // until then we can return the assignment even if we use events for the notification.
+        // A reassigned session no longer takes the place of its previous peer.
+        self.session_to_peer_map.remove(&outbound_session_id);
         if self.peers.is_empty() {
-            self.sessions_received_when_no_peers.push(outbound_session_id);
+            self.wait_for_peer(outbound_session_id);
             return None;
         }
-        let peer = self
-            .peers
-            .iter()
-            .skip(self.last_peer_index)
-            .find(|(_, peer)| !peer.is_blocked())
-            .or_else(|| {
-                self.peers.iter().take(self.last_peer_index).find(|(_, peer)| !peer.is_blocked())
-            });
+        // Prefer the peer of a session that asks for the same blocks over another protocol, so that
+        // all the parts of these blocks are downloaded from a single peer. Then prefer peers that
+        // advertised they hold all the blocks the session asks for, then peers that declared they
+        // have the data of these blocks for the session's protocol, then peers that didn't declare
+        // anything about these blocks, and only then peers that declared they don't have them.
+        let block_range = self.session_to_block_range.get(&outbound_session_id);
+        let peer_id = match self.session_to_protocol.get(&outbound_session_id) {
+            Some(protocol) => block_range
+                .and_then(|block_range| {
+                    let sibling_peer_ids =
+                        self.peers_of_sibling_sessions(outbound_session_id, protocol, block_range);
+                    self.find_unblocked_peer(|peer| sibling_peer_ids.contains(&peer.peer_id()))
+                })
+                .or_else(|| {
+                    block_range.and_then(|block_range| {
+                        self.find_unblocked_peer(|peer| {
+                            peer.advertised_block_range(protocol).is_some_and(|advertised_range| {
+                                advertised_range.start <= block_range.start
+                                    && block_range.end <= advertised_range.end
+                            })
+                        })
+                    })
+                })
+                .or_else(|| {
+                    block_range.and_then(|block_range| {
+                        self.find_unblocked_peer(|peer| {
+                            peer.block_availability(protocol, block_range) == Some(true)
+                        })
+                    })
+                })
+                .or_else(|| {
+                    self.find_unblocked_peer(|peer| {
+                        !block_range.is_some_and(|block_range| {
+                            peer.block_availability(protocol, block_range) == Some(false)
+                        })
+                    })
+                })
+                .or_else(|| self.find_unblocked_peer(|_| true))
+                .or_else(|| self.find_unhealthy_peer()),
+            None => self.find_unblocked_peer(|_| true).or_else(|| self.find_unhealthy_peer()),
+        };
         self.last_peer_index = (self.last_peer_index + 1) % self.peers.len();
-        peer.map(|(peer_id, peer)| {
-            // TODO: consider not allowing reassignment of the same session
-            self.session_to_peer_map.insert(outbound_session_id, *peer_id);
-            let peer_connection_ids = peer.connection_ids();
-            if !peer_connection_ids.is_empty() {
-                let connection_id = peer_connection_ids[0];
-                info!(
-                    "Session {:?} assigned to peer {:?} with connection id: {:?}",
-                    outbound_session_id, peer_id, connection_id
-                );
-                self.pending_events.push(ToSwarm::GenerateEvent(
-                    ToOtherBehaviourEvent::SessionAssigned {
-                        outbound_session_id,
-                        peer_id: *peer_id,
-                        connection_id,
-                    },
-                ));
+        let Some((peer_id, peer)) = peer_id.and_then(|peer_id| self.peers.get_key_value(&peer_id))
+        else {
+            self.wait_for_peer(outbound_session_id);
+            return None;
+        };
+        let peer_id = *peer_id;
+        // TODO: consider not allowing reassignment of the same session
+        self.session_to_peer_map.insert(outbound_session_id, peer_id);
+        let peer_connection_ids = peer.connection_ids();
+        if !peer_connection_ids.is_empty() {
+            let connection_id = peer_connection_ids[0];
+            info!(
+                "Session {:?} assigned to peer {:?} with connection id: {:?}",
+                outbound_session_id, peer_id, connection_id
+            );
+            self.stop_waiting_for_connection(outbound_session_id);
+            self.pending_events.push(ToSwarm::GenerateEvent(
+                ToOtherBehaviourEvent::SessionAssigned {
+                    outbound_session_id,
+                    peer_id,
+                    connection_id,
+                },
+            ));
+        } else {
+            // In case we have a race condition where the connection is closed after we added to
+            // the pending list, the reciever will get an error and will need to ask for
+            // re-assignment
+            if let Some(sessions) = self.peers_pending_dial_with_sessions.get_mut(&peer_id) {
+                info!("Session {outbound_session_id:?} waits for the dial to peer {peer_id:?}.");
+                sessions.push(outbound_session_id);
             } else {
-                // In case we have a race condition where the connection is closed after we added to
-                // the pending list, the reciever will get an error and will need to ask for
-                // re-assignment
-                if let Some(sessions) = self.peers_pending_dial_with_sessions.get_mut(peer_id) {
-                    sessions.push(outbound_session_id);
-                } else {
-                    self.peers_pending_dial_with_sessions
-                        .insert(*peer_id, vec![outbound_session_id]);
-                }
+                self.peers_pending_dial_with_sessions.insert(peer_id, vec![outbound_session_id]);
                 info!("Dialing peer {:?} with multiaddr {:?}", peer_id, peer.multiaddr());
                 self.pending_events.push(ToSwarm::Dial {
-                    opts: DialOpts::peer_id(*peer_id).addresses(vec![peer.multiaddr()]).build(),
+                    opts: DialOpts::peer_id(peer_id).addresses(vec![peer.multiaddr()]).build(),
                 });
             }
-            *peer_id
-        })
+            self.start_waiting_for_connection(outbound_session_id);
+        }
+        Some(peer_id)
+    }
+
+    // Holds the session until a peer is found or connected. Discovery might have been paused
+    // since enough peers were found, but none of them can take the session now, so it's resumed.
+    fn wait_for_peer(&mut self, outbound_session_id: OutboundSessionId) {
+        if !self.sessions_without_peer.contains(&outbound_session_id) {
+            self.sessions_without_peer.push(outbound_session_id);
+        }
+        self.start_waiting_for_connection(outbound_session_id);
+        self.pending_events.push(ToSwarm::GenerateEvent(ToOtherBehaviourEvent::ResumeDiscovery));
+    }
+
+    // A session keeps the deadline it got when it started waiting, even if it's reassigned while
+    // it waits, e.g. after a dial failed.
+    fn start_waiting_for_connection(&mut self, outbound_session_id: OutboundSessionId) {
+        if self
+            .sessions_waiting_for_connection
+            .iter()
+            .any(|(waiting_session_id, _)| *waiting_session_id == outbound_session_id)
+        {
+            return;
+        }
+        info!(
+            "No connected peer for session {outbound_session_id:?}. Waiting up to {:?} for one.",
+            self.config.dial_deadline
+        );
+        self.sessions_waiting_for_connection
+            .push_back((outbound_session_id, Instant::now() + self.config.dial_deadline));
+    }
+
+    fn stop_waiting_for_connection(&mut self, outbound_session_id: OutboundSessionId) {
+        self.sessions_waiting_for_connection
+            .retain(|(waiting_session_id, _)| *waiting_session_id != outbound_session_id);
+    }
+
+    // Fails the sessions that reached their deadline without a connected peer.
+    fn fail_sessions_past_dial_deadline(&mut self) {
+        let now = Instant::now();
+        while let Some((outbound_session_id, _)) = self
+            .sessions_waiting_for_connection
+            .front()
+            .filter(|(_, deadline)| *deadline <= now)
+            .copied()
+        {
+            self.sessions_waiting_for_connection.pop_front();
+            warn!(
+                "No peer was connected for session {outbound_session_id:?} within {:?}. Failing \
+                 the session.",
+                self.config.dial_deadline
+            );
+            self.sessions_without_peer.retain(|session_id| *session_id != outbound_session_id);
+            // The dial itself isn't cancelled, since the peer might be used by later sessions.
+            for sessions in self.peers_pending_dial_with_sessions.values_mut() {
+                sessions.retain(|session_id| *session_id != outbound_session_id);
+            }
+            self.session_to_peer_map.remove(&outbound_session_id);
+            self.pending_events.push(ToSwarm::GenerateEvent(
+                ToOtherBehaviourEvent::NoPeersForSession {
+                    outbound_session_id,
+                    dial_deadline: self.config.dial_deadline,
+                },
+            ));
+        }
+    }
+
+    /// Returns the id of a healthy peer that isn't blocked, that can take another session and that
+    /// satisfies the given predicate. Among such peers, the peer with the lowest round trip time is
+    /// returned, and peers whose round trip time wasn't measured yet come after the measured peers.
+    /// Peers with the same round trip time are returned in round robin order.
+    fn find_unblocked_peer(&self, predicate: impl Fn(&P) -> bool) -> Option<PeerId> {
+        self.find_peer(|peer, latency| predicate(peer) && latency.healthy)
+    }
+
+    /// Returns the id of an unhealthy peer that isn't blocked and that can take another session,
+    /// for sessions that no healthy peer can take.
+    fn find_unhealthy_peer(&self) -> Option<PeerId> {
+        self.find_peer(|_, latency| !latency.healthy)
+    }
+
+    fn find_peer(&self, predicate: impl Fn(&P, &PeerLatency) -> bool) -> Option<PeerId> {
+        let peer_latencies =
+            self.peer_latencies.lock().expect("Peer latencies lock should not be poisoned");
+        let unmeasured_latency = PeerLatency::default();
+        self.peers
+            .iter()
+            .skip(self.last_peer_index)
+            .chain(self.peers.iter().take(self.last_peer_index))
+            .map(|(peer_id, peer)| {
+                (peer_id, peer, peer_latencies.get(peer_id).unwrap_or(&unmeasured_latency))
+            })
+            .filter(|(peer_id, peer, latency)| {
+                predicate(peer, latency) && !peer.is_blocked() && self.has_session_capacity(peer_id)
+            })
+            // The first of the peers with the minimal key is returned.
+            .min_by_key(|(_, _, latency)| (latency.smoothed_rtt.is_none(), latency.smoothed_rtt))
+            .map(|(peer_id, _, _)| *peer_id)
+    }
+
+    // Returns the peers of the other sessions that ask for exactly the given blocks over a
+    // different protocol.
+    fn peers_of_sibling_sessions(
+        &self,
+        outbound_session_id: OutboundSessionId,
+        protocol: &StreamProtocol,
+        block_range: &Range<BlockNumber>,
+    ) -> Vec<PeerId> {
+        self.session_to_block_range
+            .iter()
+            .filter(|(other_session_id, other_block_range)| {
+                **other_session_id != outbound_session_id
+                    && *other_block_range == block_range
+                    && self.session_to_protocol.get(other_session_id) != Some(protocol)
+            })
+            .filter_map(|(other_session_id, _)| self.session_to_peer_map.get(other_session_id))
+            .copied()
+            .collect()
+    }
+
+    fn has_session_capacity(&self, peer_id: &PeerId) -> bool {
+        self.session_to_peer_map
+            .values()
+            .filter(|session_peer_id| *session_peer_id == peer_id)
+            .count()
+            < self.config.max_sessions_per_peer
+    }
+
+    /// Forgets the session once it finished or failed, so that its peer can take another session.
+    /// The sessions that waited for a peer with capacity are assigned again.
+    pub(crate) fn finish_session(&mut self, outbound_session_id: OutboundSessionId) {
+        self.session_to_protocol.remove(&outbound_session_id);
+        self.session_to_block_range.remove(&outbound_session_id);
+        if self.session_to_peer_map.remove(&outbound_session_id).is_some()
+            && !self.sessions_without_peer.is_empty()
+        {
+            self.assign_sessions_without_peer();
+        }
+    }
+
+    pub(crate) fn update_peer_block_availability(
+        &mut self,
+        peer_id: PeerId,
+        protocol: StreamProtocol,
+        block_number: BlockNumber,
+        is_available: bool,
+    ) -> Result<(), PeerManagerError> {
+        let peer = self.peers.get_mut(&peer_id).ok_or(PeerManagerError::NoSuchPeer(peer_id))?;
+        peer.set_block_availability(protocol, block_number, is_available);
+        Ok(())
+    }
+
+    /// Set the range of blocks the given session asks for, so that it will be assigned preferably
+    /// to a peer that advertised it holds these blocks. Should be called before the session is
+    /// assigned.
+    pub(crate) fn set_session_block_range(
+        &mut self,
+        outbound_session_id: OutboundSessionId,
+        block_range: Range<BlockNumber>,
+    ) {
+        self.session_to_block_range.insert(outbound_session_id, block_range);
+    }
+
+    pub(crate) fn update_peer_advertised_block_range(
+        &mut self,
+        peer_id: PeerId,
+        protocol: StreamProtocol,
+        block_range: Range<BlockNumber>,
+    ) -> Result<(), PeerManagerError> {
+        let peer = self.peers.get_mut(&peer_id).ok_or(PeerManagerError::NoSuchPeer(peer_id))?;
+        peer.set_advertised_block_range(
+            protocol,
+            block_range,
+            self.config.block_range_advertisement_ttl,
+        );
+        Ok(())
     }
 
     pub(crate) fn report_peer(
@@ -149,22 +507,205 @@ where
     ) -> Result<(), PeerManagerError> {
         // TODO(shahak): Add time blacklisted to log.
         info!("Peer {:?} reported as misbehaving.", peer_id);
+        let manual_ban = self.manual_ban(&peer_id);
         if let Some(peer) = self.peers.get_mut(&peer_id) {
             peer.update_reputation(reason);
+            reapply_manual_ban(peer, manual_ban);
             Ok(())
         } else {
             Err(PeerManagerError::NoSuchPeer(peer_id))
         }
     }
 
+    /// Returns the time until which the peer is manually banned, or None if it isn't banned.
+    pub(crate) fn manual_ban(&self, peer_id: &PeerId) -> Option<DateTime<Utc>> {
+        self.manual_bans.get(peer_id).copied().filter(|banned_until| *banned_until > Utc::now())
+    }
+
+    pub(crate) fn handle_command(&mut self, command: PeerManagerCommand) {
+        match command {
+            PeerManagerCommand::GetState(state_sender) => {
+                // The requester may have given up waiting.
+                let _ = state_sender.send(self.state());
+            }
+            PeerManagerCommand::Blacklist { peer_id, duration } => {
+                self.blacklist_peer(peer_id, duration)
+            }
+            PeerManagerCommand::Unblacklist { peer_id } => self.unblacklist_peer(peer_id),
+        }
+    }
+
+    fn blacklist_peer(&mut self, peer_id: PeerId, duration: std::time::Duration) {
+        let banned_until = Duration::from_std(duration)
+            .ok()
+            .and_then(|duration| Utc::now().checked_add_signed(duration))
+            .unwrap_or(DateTime::<Utc>::MAX_UTC);
+        if self.config.bootstrap_peer_id == Some(peer_id) {
+            warn!(
+                "Manually banning the bootstrap peer {peer_id:?} until {banned_until}. Peer \
+                 discovery starts from this peer, so the node might not find new peers until the \
+                 ban is lifted."
+            );
+        } else {
+            info!("Manually banning peer {peer_id:?} until {banned_until}.");
+        }
+        self.manual_bans.insert(peer_id, banned_until);
+        if let Some(peer) = self.peers.get_mut(&peer_id) {
+            peer.block_until(banned_until);
+        }
+        // Sessions on these connections will fail and be reassigned to other peers.
+        self.pending_events
+            .push(ToSwarm::CloseConnection { peer_id, connection: CloseConnection::All });
+    }
+
+    fn unblacklist_peer(&mut self, peer_id: PeerId) {
+        info!("Lifting the ban of peer {peer_id:?}.");
+        self.manual_bans.remove(&peer_id);
+        if let Some(peer) = self.peers.get_mut(&peer_id) {
+            peer.unblock();
+        }
+        self.assign_sessions_without_peer();
+    }
+
+    fn state(&self) -> PeerManagerState {
+        let mut assigned_sessions = HashMap::<PeerId, Vec<usize>>::new();
+        for (outbound_session_id, peer_id) in &self.session_to_peer_map {
+            assigned_sessions.entry(*peer_id).or_default().push(outbound_session_id.value);
+        }
+        let mut peers = self
+            .peers
+            .iter()
+            .map(|(peer_id, peer)| {
+                let mut assigned_sessions = assigned_sessions.remove(peer_id).unwrap_or_default();
+                assigned_sessions.sort_unstable();
+                PeerState {
+                    peer_id: *peer_id,
+                    multiaddr: peer.multiaddr(),
+                    blocked_until: peer
+                        .blocked_until()
+                        .map(|blocked_until| blocked_until.to_rfc3339()),
+                    manually_banned: self.manual_ban(peer_id).is_some(),
+                    num_connections: peer.connection_ids().len(),
+                    assigned_sessions,
+                }
+            })
+            .collect::<Vec<_>>();
+        peers.sort_by_key(|peer| peer.peer_id.to_string());
+        let mut manual_bans = self
+            .manual_bans
+            .keys()
+            .filter_map(|peer_id| {
+                self.manual_ban(peer_id).map(|banned_until| (*peer_id, banned_until.to_rfc3339()))
+            })
+            .collect::<Vec<_>>();
+        manual_bans.sort_by_key(|(peer_id, _)| peer_id.to_string());
+        PeerManagerState { peers, manual_bans }
+    }
+
+    pub(crate) fn add_served_bytes(&mut self, peer_id: PeerId, num_bytes: u64) {
+        *self
+            .served_bytes_by_peer
+            .lock()
+            .expect("Served bytes lock should not be poisoned")
+            .entry(peer_id)
+            .or_default() += num_bytes;
+    }
+
+    pub(crate) fn served_bytes_by_peer(&self) -> ServedBytesByPeer {
+        self.served_bytes_by_peer.clone()
+    }
+
+    /// Records the name the peer negotiated a session of the given protocol with.
+    pub(crate) fn update_peer_negotiated_protocol(
+        &mut self,
+        peer_id: PeerId,
+        protocol: StreamProtocol,
+        negotiated_protocol: StreamProtocol,
+    ) {
+        self.negotiated_protocols_by_peer
+            .lock()
+            .expect("Negotiated protocols lock should not be poisoned")
+            .entry(peer_id)
+            .or_default()
+            .insert(protocol, negotiated_protocol);
+    }
+
+    pub(crate) fn negotiated_protocols_by_peer(&self) -> NegotiatedProtocolsByPeer {
+        self.negotiated_protocols_by_peer.clone()
+    }
+
+    pub(crate) fn peer_identities(&self) -> PeerIdentities {
+        self.peer_identities.clone()
+    }
+
+    pub(crate) fn peer_latencies(&self) -> PeerLatencies {
+        self.peer_latencies.clone()
+    }
+
+    // Updates the latency of the peer with the outcome of a ping. A peer that becomes unhealthy is
+    // disconnected, so that its sessions fail and are reassigned right away instead of waiting
+    // for their timeout.
+    fn record_ping(&mut self, event: &PingToOtherBehaviourEvent) {
+        let peer_id = match event {
+            PingToOtherBehaviourEvent::Succeeded { peer_id, .. }
+            | PingToOtherBehaviourEvent::Lost { peer_id } => *peer_id,
+        };
+        let mut peer_latencies =
+            self.peer_latencies.lock().expect("Peer latencies lock should not be poisoned");
+        let latency = peer_latencies.entry(peer_id).or_default();
+        match event {
+            PingToOtherBehaviourEvent::Succeeded { rtt, .. } => {
+                // Smoothed like TCP's round trip time, giving the new sample a weight of 1/8.
+                let smoothed_rtt =
+                    latency.smoothed_rtt.map_or(*rtt, |smoothed_rtt| (smoothed_rtt * 7 + *rtt) / 8);
+                latency.smoothed_rtt = Some(smoothed_rtt);
+                latency.consecutive_lost_pings = 0;
+                if !latency.healthy {
+                    info!("Peer {peer_id:?} answers pings again. Considering it healthy.");
+                    latency.healthy = true;
+                }
+                gauge!(
+                    papyrus_metrics::PAPYRUS_PEER_PING_RTT_SECS,
+                    smoothed_rtt.as_secs_f64(),
+                    "peer_id" => peer_id.to_string()
+                );
+            }
+            PingToOtherBehaviourEvent::Lost { .. } => {
+                latency.lost_pings += 1;
+                latency.consecutive_lost_pings += 1;
+                increment_counter!(
+                    papyrus_metrics::PAPYRUS_PEER_LOST_PINGS,
+                    "peer_id" => peer_id.to_string()
+                );
+                if latency.healthy
+                    && latency.consecutive_lost_pings >= self.config.max_consecutive_lost_pings
+                {
+                    warn!(
+                        "Peer {peer_id:?} failed to answer {} consecutive pings. Considering it \
+                         unhealthy and disconnecting from it.",
+                        latency.consecutive_lost_pings
+                    );
+                    latency.healthy = false;
+                    // Sessions on these connections will fail and be reassigned to other peers.
+                    self.pending_events.push(ToSwarm::CloseConnection {
+                        peer_id,
+                        connection: CloseConnection::All,
+                    });
+                }
+            }
+        }
+    }
+
     fn report_session(
         &mut self,
         outbound_session_id: OutboundSessionId,
         reason: ReputationModifier,
     ) -> Result<(), PeerManagerError> {
         if let Some(peer_id) = self.session_to_peer_map.get(&outbound_session_id) {
+            let manual_ban = self.manual_ban(peer_id);
             if let Some(peer) = self.peers.get_mut(peer_id) {
                 peer.update_reputation(reason);
+                reapply_manual_ban(peer, manual_ban);
                 Ok(())
             } else {
                 Err(PeerManagerError::NoSuchPeer(*peer_id))
@@ -181,6 +722,16 @@ where
     }
 }
 
+// A reputation block might end before the peer's manual ban does, so the manual ban is applied
+// again after the peer's reputation changes.
+fn reapply_manual_ban<P: PeerTrait>(peer: &mut P, manual_ban: Option<DateTime<Utc>>) {
+    if let Some(banned_until) = manual_ban {
+        if peer.blocked_until().map_or(true, |blocked_until| blocked_until < banned_until) {
+            peer.block_until(banned_until);
+        }
+    }
+}
+
 impl From<ToOtherBehaviourEvent> for mixed_behaviour::Event {
     fn from(event: ToOtherBehaviourEvent) -> Self {
         Self::ToOtherBehaviourEvent(mixed_behaviour::ToOtherBehaviourEvent::PeerManager(event))
@@ -189,14 +740,46 @@ impl From<ToOtherBehaviourEvent> for mixed_behaviour::Event {
 
 impl<P: PeerTrait + 'static> BridgedBehaviour for PeerManager<P> {
     fn on_other_behaviour_event(&mut self, event: &mixed_behaviour::ToOtherBehaviourEvent) {
+        // The identity is updated on every identify, unlike the peer's address.
+        if let mixed_behaviour::ToOtherBehaviourEvent::Identify(
+            IdentifyToOtherBehaviourEvent::FoundListenAddresses {
+                peer_id,
+                agent_version,
+                protocols,
+                ..
+            },
+        ) = event
+        {
+            self.peer_identities
+                .lock()
+                .expect("Peer identities lock should not be poisoned")
+                .insert(
+                    *peer_id,
+                    PeerIdentity {
+                        agent_version: agent_version.clone(),
+                        protocols: protocols.iter().map(ToString::to_string).collect(),
+                    },
+                );
+        }
         match event {
+            mixed_behaviour::ToOtherBehaviourEvent::Ping(ping_event) => {
+                self.record_ping(ping_event)
+            }
             mixed_behaviour::ToOtherBehaviourEvent::Sqmr(
-                sqmr::ToOtherBehaviourEvent::RequestPeerAssignment { outbound_session_id },
+                sqmr::ToOtherBehaviourEvent::RequestPeerAssignment {
+                    outbound_session_id,
+                    protocol_name,
+                },
             ) => {
+                self.session_to_protocol.insert(*outbound_session_id, protocol_name.clone());
                 self.assign_peer_to_session(*outbound_session_id);
             }
             mixed_behaviour::ToOtherBehaviourEvent::Identify(
-                IdentifyToOtherBehaviourEvent::FoundListenAddresses { peer_id, listen_addresses },
+                IdentifyToOtherBehaviourEvent::FoundListenAddresses {
+                    peer_id,
+                    listen_addresses,
+                    ..
+                },
             )
             | mixed_behaviour::ToOtherBehaviourEvent::Discovery(
                 discovery::ToOtherBehaviourEvent::FoundListenAddresses {
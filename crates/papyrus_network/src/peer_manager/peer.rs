@@ -1,10 +1,14 @@
 // using chrono time and not std since std does not have the ability for std::time::Instance to
 // represent the maximum time of the system.
+use std::collections::{BTreeMap, HashMap};
+use std::ops::Range;
+
 use chrono::{DateTime, Duration, Utc};
 use libp2p::swarm::ConnectionId;
-use libp2p::{Multiaddr, PeerId};
+use libp2p::{Multiaddr, PeerId, StreamProtocol};
 #[cfg(test)]
 use mockall::automock;
+use starknet_api::block::BlockNumber;
 use tracing::debug;
 
 use super::ReputationModifier;
@@ -23,11 +27,50 @@ pub trait PeerTrait {
 
     fn is_blocked(&self) -> bool;
 
+    /// Returns the time until which the peer is blocked, or None if it isn't blocked.
+    fn blocked_until(&self) -> Option<DateTime<Utc>>;
+
+    /// Block the peer until the given time, regardless of its reputation.
+    fn block_until(&mut self, until: DateTime<Utc>);
+
+    fn unblock(&mut self);
+
     fn connection_ids(&self) -> &Vec<ConnectionId>;
 
     fn add_connection_id(&mut self, connection_id: ConnectionId);
 
     fn remove_connection_id(&mut self, connection_id: ConnectionId);
+
+    /// Record whether the peer declared that it can serve the data of the given block for the
+    /// given protocol.
+    fn set_block_availability(
+        &mut self,
+        protocol: StreamProtocol,
+        block_number: BlockNumber,
+        is_available: bool,
+    );
+
+    /// Returns Some(true) if the peer declared it can serve the data of all the given blocks for
+    /// the given protocol, Some(false) if it declared it can't serve the data of any of them, and
+    /// None otherwise.
+    fn block_availability(
+        &self,
+        protocol: &StreamProtocol,
+        block_range: &Range<BlockNumber>,
+    ) -> Option<bool>;
+
+    /// Record the range of blocks the peer advertised it can serve for the given protocol. The
+    /// advertisement is considered stale once the given ttl passes.
+    fn set_advertised_block_range(
+        &mut self,
+        protocol: StreamProtocol,
+        block_range: Range<BlockNumber>,
+        ttl: Duration,
+    );
+
+    /// Returns None if the peer didn't advertise a block range for the given protocol or if its
+    /// advertisement became stale.
+    fn advertised_block_range(&self, protocol: &StreamProtocol) -> Option<Range<BlockNumber>>;
 }
 
 #[derive(Clone)]
@@ -37,6 +80,8 @@ pub struct Peer {
     timed_out_until: Option<DateTime<Utc>>,
     timeout_duration: Option<Duration>,
     connection_ids: Vec<ConnectionId>,
+    block_availability: HashMap<StreamProtocol, BlockAvailability>,
+    advertised_block_ranges: HashMap<StreamProtocol, (Range<BlockNumber>, DateTime<Utc>)>,
 }
 
 impl PeerTrait for Peer {
@@ -47,6 +92,8 @@ impl PeerTrait for Peer {
             timeout_duration: None,
             timed_out_until: None,
             connection_ids: Vec::new(),
+            block_availability: HashMap::new(),
+            advertised_block_ranges: HashMap::new(),
         }
     }
 
@@ -79,6 +126,18 @@ impl PeerTrait for Peer {
         }
     }
 
+    fn blocked_until(&self) -> Option<DateTime<Utc>> {
+        self.timed_out_until.filter(|timed_out_until| *timed_out_until > Utc::now())
+    }
+
+    fn block_until(&mut self, until: DateTime<Utc>) {
+        self.timed_out_until = Some(until);
+    }
+
+    fn unblock(&mut self) {
+        self.timed_out_until = None;
+    }
+
     fn connection_ids(&self) -> &Vec<ConnectionId> {
         &self.connection_ids
     }
@@ -90,4 +149,120 @@ impl PeerTrait for Peer {
     fn remove_connection_id(&mut self, connection_id: ConnectionId) {
         self.connection_ids.retain(|&id| id != connection_id);
     }
+
+    fn set_block_availability(
+        &mut self,
+        protocol: StreamProtocol,
+        block_number: BlockNumber,
+        is_available: bool,
+    ) {
+        self.block_availability.entry(protocol).or_default().set(block_number, is_available);
+    }
+
+    fn block_availability(
+        &self,
+        protocol: &StreamProtocol,
+        block_range: &Range<BlockNumber>,
+    ) -> Option<bool> {
+        self.block_availability.get(protocol)?.get(block_range)
+    }
+
+    fn set_advertised_block_range(
+        &mut self,
+        protocol: StreamProtocol,
+        block_range: Range<BlockNumber>,
+        ttl: Duration,
+    ) {
+        let expires_at = Utc::now().checked_add_signed(ttl).unwrap_or(DateTime::<Utc>::MAX_UTC);
+        self.advertised_block_ranges.insert(protocol, (block_range, expires_at));
+    }
+
+    fn advertised_block_range(&self, protocol: &StreamProtocol) -> Option<Range<BlockNumber>> {
+        self.advertised_block_ranges
+            .get(protocol)
+            .filter(|(_, expires_at)| *expires_at > Utc::now())
+            .map(|(block_range, _)| block_range.clone())
+    }
+}
+
+// The number of runs of blocks whose availability is kept for each protocol of a peer. The runs of
+// the lowest blocks are dropped first, since the sync asks for the blocks in ascending order.
+const MAX_BLOCK_AVAILABILITY_RUNS: usize = 1000;
+
+/// The blocks a peer declared it can or can't serve the data of for a protocol, as disjoint runs of
+/// consecutive blocks with the same declaration. Only the latest declaration of each block is kept,
+/// since a peer may prune its data or sync more blocks after it declared them.
+#[derive(Clone, Default)]
+struct BlockAvailability {
+    // Maps the first block of each run to the end of the run and whether its blocks are available.
+    runs: BTreeMap<BlockNumber, (BlockNumber, bool)>,
+}
+
+impl BlockAvailability {
+    fn set(&mut self, block_number: BlockNumber, is_available: bool) {
+        let next_block_number = block_number.unchecked_next();
+        // Split the run that contains the block.
+        if let Some((&start, &(end, run_is_available))) =
+            self.runs.range(..=block_number).next_back()
+        {
+            if block_number < end {
+                if run_is_available == is_available {
+                    return;
+                }
+                self.runs.remove(&start);
+                if start < block_number {
+                    self.runs.insert(start, (block_number, run_is_available));
+                }
+                if next_block_number < end {
+                    self.runs.insert(next_block_number, (end, run_is_available));
+                }
+            }
+        }
+        // Merge the block with the adjacent runs that have the same declaration.
+        let mut start = block_number;
+        let mut end = next_block_number;
+        if let Some((&previous_start, &(previous_end, previous_is_available))) =
+            self.runs.range(..block_number).next_back()
+        {
+            if previous_end == block_number && previous_is_available == is_available {
+                self.runs.remove(&previous_start);
+                start = previous_start;
+            }
+        }
+        if let Some(&(next_end, next_is_available)) = self.runs.get(&next_block_number) {
+            if next_is_available == is_available {
+                self.runs.remove(&next_block_number);
+                end = next_end;
+            }
+        }
+        self.runs.insert(start, (end, is_available));
+        while self.runs.len() > MAX_BLOCK_AVAILABILITY_RUNS {
+            self.runs.pop_first();
+        }
+    }
+
+    fn get(&self, block_range: &Range<BlockNumber>) -> Option<bool> {
+        if block_range.is_empty() {
+            return None;
+        }
+        let run_before_range = self
+            .runs
+            .range(..block_range.start)
+            .next_back()
+            .filter(|(_, (end, _))| *end > block_range.start);
+        let mut covered_until = block_range.start;
+        let mut is_covered = true;
+        for (&start, &(end, is_available)) in
+            run_before_range.into_iter().chain(self.runs.range(block_range.clone()))
+        {
+            if !is_available {
+                return Some(false);
+            }
+            if start > covered_until {
+                is_covered = false;
+            }
+            covered_until = covered_until.max(end);
+        }
+        (is_covered && covered_until >= block_range.end).then_some(true)
+    }
 }
@@ -4,20 +4,32 @@ use core::{panic, time};
 
 use assert_matches::assert_matches;
 use chrono::Duration;
+use futures::channel::oneshot;
 use futures::future::poll_fn;
+use libp2p::core::ConnectedPoint;
 use libp2p::swarm::behaviour::ConnectionEstablished;
-use libp2p::swarm::{ConnectionId, NetworkBehaviour, ToSwarm};
-use libp2p::{Multiaddr, PeerId};
+use libp2p::swarm::{CloseConnection, ConnectionClosed, ConnectionId, NetworkBehaviour, ToSwarm};
+use libp2p::{Multiaddr, PeerId, StreamProtocol};
 use mockall::predicate::eq;
+use starknet_api::block::BlockNumber;
 use tokio::time::sleep;
 
 use super::behaviour_impl::ToOtherBehaviourEvent;
 use crate::discovery::identify_impl::IdentifyToOtherBehaviourEvent;
-use crate::mixed_behaviour;
 use crate::mixed_behaviour::BridgedBehaviour;
 use crate::peer_manager::peer::{MockPeerTrait, Peer, PeerTrait};
-use crate::peer_manager::{PeerManager, PeerManagerConfig, ReputationModifier};
+use crate::peer_manager::{
+    PeerIdentity,
+    PeerLatency,
+    PeerManager,
+    PeerManagerCommand,
+    PeerManagerConfig,
+    PeerManagerState,
+    ReputationModifier,
+};
+use crate::ping_impl::PingToOtherBehaviourEvent;
 use crate::sqmr::OutboundSessionId;
+use crate::{mixed_behaviour, sqmr, Protocol};
 
 #[test]
 fn peer_assignment_round_robin() {
@@ -113,6 +125,11 @@ fn peer_assignment_no_peers() {
 
     // Assign a peer to the session
     assert_matches!(peer_manager.assign_peer_to_session(outbound_session_id), None);
+    // Discovery is resumed to find a peer for the session.
+    assert_matches!(
+        peer_manager.pending_events.pop(),
+        Some(ToSwarm::GenerateEvent(ToOtherBehaviourEvent::ResumeDiscovery))
+    );
 
     // Now the peer manager finds a new peer and can assign the session.
     let connection_id = ConnectionId::new_unchecked(0);
@@ -151,6 +168,25 @@ fn report_peer_calls_update_reputation() {
     peer_manager.get_mut_peer(peer_id).unwrap().checkpoint();
 }
 
+#[test]
+fn add_served_bytes_accumulates_per_peer() {
+    let mut peer_manager: PeerManager<MockPeerTrait> =
+        PeerManager::new(PeerManagerConfig::default());
+    let served_bytes_by_peer = peer_manager.served_bytes_by_peer();
+
+    // Bytes are counted even for peers the peer manager didn't find, since any peer can query us.
+    let peer_id1 = PeerId::random();
+    let peer_id2 = PeerId::random();
+    peer_manager.add_served_bytes(peer_id1, 10);
+    peer_manager.add_served_bytes(peer_id2, 5);
+    peer_manager.add_served_bytes(peer_id1, 7);
+
+    let served_bytes_by_peer = served_bytes_by_peer.lock().unwrap();
+    assert_eq!(served_bytes_by_peer.len(), 2);
+    assert_eq!(served_bytes_by_peer[&peer_id1], 17);
+    assert_eq!(served_bytes_by_peer[&peer_id2], 5);
+}
+
 #[tokio::test]
 async fn peer_block_realeased_after_timeout() {
     const DURATION_IN_MILLIS: u64 = 50;
@@ -259,6 +295,251 @@ fn timed_out_peer_not_assignable_to_queries() {
     assert_matches!(peer_manager.assign_peer_to_session(outbound_session_id), None);
 }
 
+#[test]
+fn peer_assignment_prefers_peers_that_declared_data_availability() {
+    // Create a new peer manager
+    let mut peer_manager = PeerManager::new(PeerManagerConfig::default());
+
+    // Add a peer that holds only headers and a peer that holds the full blocks.
+    let headers_only_peer = Peer::new(PeerId::random(), Multiaddr::empty());
+    let full_peer = Peer::new(PeerId::random(), Multiaddr::empty());
+    peer_manager.add_peer(headers_only_peer.clone());
+    peer_manager.add_peer(full_peer.clone());
+
+    let protocol: StreamProtocol = Protocol::StateDiff.into();
+    for block_number in 0..4 {
+        peer_manager
+            .update_peer_block_availability(
+                headers_only_peer.peer_id(),
+                protocol.clone(),
+                BlockNumber(block_number),
+                false,
+            )
+            .unwrap();
+        peer_manager
+            .update_peer_block_availability(
+                full_peer.peer_id(),
+                protocol.clone(),
+                BlockNumber(block_number),
+                true,
+            )
+            .unwrap();
+    }
+
+    // All the sessions should be assigned to the full peer, regardless of the round robin order.
+    for value in 0..4 {
+        let outbound_session_id = OutboundSessionId { value };
+        peer_manager.set_session_block_range(
+            outbound_session_id,
+            BlockNumber(value)..BlockNumber(value + 1),
+        );
+        peer_manager.on_other_behaviour_event(&mixed_behaviour::ToOtherBehaviourEvent::Sqmr(
+            sqmr::ToOtherBehaviourEvent::RequestPeerAssignment {
+                outbound_session_id,
+                protocol_name: protocol.clone(),
+            },
+        ));
+        assert_eq!(
+            peer_manager.session_to_peer_map.get(&outbound_session_id),
+            Some(&full_peer.peer_id())
+        );
+    }
+}
+
+#[test]
+fn peer_assignment_follows_the_declared_availability_of_the_queried_blocks() {
+    let mut peer_manager = PeerManager::new(PeerManagerConfig::default());
+    // A peer that has the bodies up to block 10 and a peer that has only the body of block 10.
+    let lagging_peer_id = PeerId::random();
+    let tip_peer_id = PeerId::random();
+    peer_manager.add_peer(Peer::new(lagging_peer_id, Multiaddr::empty()));
+    peer_manager.add_peer(Peer::new(tip_peer_id, Multiaddr::empty()));
+    let protocol: StreamProtocol = Protocol::Transaction.into();
+    for block_number in 0..=10 {
+        peer_manager
+            .update_peer_block_availability(
+                lagging_peer_id,
+                protocol.clone(),
+                BlockNumber(block_number),
+                block_number < 10,
+            )
+            .unwrap();
+    }
+    peer_manager
+        .update_peer_block_availability(tip_peer_id, protocol.clone(), BlockNumber(10), true)
+        .unwrap();
+
+    for (value, block_range, expected_peer_id) in [
+        (0, BlockNumber(0)..BlockNumber(10), lagging_peer_id),
+        (1, BlockNumber(5)..BlockNumber(8), lagging_peer_id),
+        (2, BlockNumber(10)..BlockNumber(11), tip_peer_id),
+        // The lagging peer declared it doesn't have block 10, and the tip peer didn't declare
+        // anything about block 9.
+        (3, BlockNumber(9)..BlockNumber(11), tip_peer_id),
+    ] {
+        let outbound_session_id = OutboundSessionId { value };
+        peer_manager.session_to_protocol.insert(outbound_session_id, protocol.clone());
+        peer_manager.set_session_block_range(outbound_session_id, block_range);
+        assert_eq!(
+            peer_manager.assign_peer_to_session(outbound_session_id),
+            Some(expected_peer_id),
+            "session {value}"
+        );
+    }
+
+    // The lagging peer synced block 10 meanwhile, and a later hint overrides the earlier one.
+    peer_manager
+        .update_peer_block_availability(lagging_peer_id, protocol.clone(), BlockNumber(10), true)
+        .unwrap();
+    let outbound_session_id = OutboundSessionId { value: 4 };
+    peer_manager.session_to_protocol.insert(outbound_session_id, protocol);
+    peer_manager.set_session_block_range(outbound_session_id, BlockNumber(0)..BlockNumber(11));
+    assert_eq!(peer_manager.assign_peer_to_session(outbound_session_id), Some(lagging_peer_id));
+}
+
+#[test]
+fn block_availability_keeps_the_latest_declaration_of_each_block() {
+    let mut peer = Peer::new(PeerId::random(), Multiaddr::empty());
+    let protocol = StreamProtocol::new("/test");
+    for block_number in 0..6 {
+        peer.set_block_availability(protocol.clone(), BlockNumber(block_number), true);
+    }
+    peer.set_block_availability(protocol.clone(), BlockNumber(3), false);
+
+    assert_eq!(peer.block_availability(&protocol, &(BlockNumber(0)..BlockNumber(3))), Some(true));
+    assert_eq!(peer.block_availability(&protocol, &(BlockNumber(4)..BlockNumber(6))), Some(true));
+    assert_eq!(peer.block_availability(&protocol, &(BlockNumber(2)..BlockNumber(5))), Some(false));
+    assert_eq!(peer.block_availability(&protocol, &(BlockNumber(5)..BlockNumber(7))), None);
+    assert_eq!(
+        peer.block_availability(&StreamProtocol::new("/other"), &(BlockNumber(0)..BlockNumber(1))),
+        None
+    );
+
+    peer.set_block_availability(protocol.clone(), BlockNumber(3), true);
+    assert_eq!(peer.block_availability(&protocol, &(BlockNumber(0)..BlockNumber(6))), Some(true));
+}
+
+#[test]
+fn peer_assignment_prefers_peers_that_advertised_block_range() {
+    let mut peer_manager = PeerManager::new(PeerManagerConfig::default());
+
+    let partial_peer = Peer::new(PeerId::random(), Multiaddr::empty());
+    let full_peer = Peer::new(PeerId::random(), Multiaddr::empty());
+    let silent_peer = Peer::new(PeerId::random(), Multiaddr::empty());
+    peer_manager.add_peer(partial_peer.clone());
+    peer_manager.add_peer(full_peer.clone());
+    peer_manager.add_peer(silent_peer.clone());
+
+    let protocol: StreamProtocol = Protocol::SignedBlockHeader.into();
+    peer_manager
+        .update_peer_advertised_block_range(
+            partial_peer.peer_id(),
+            protocol.clone(),
+            BlockNumber(0)..BlockNumber(15),
+        )
+        .unwrap();
+    peer_manager
+        .update_peer_advertised_block_range(
+            full_peer.peer_id(),
+            protocol.clone(),
+            BlockNumber(0)..BlockNumber(100),
+        )
+        .unwrap();
+
+    // All the sessions should be assigned to the full peer, regardless of the round robin order.
+    for value in 0..4 {
+        let outbound_session_id = OutboundSessionId { value };
+        peer_manager.set_session_block_range(outbound_session_id, BlockNumber(10)..BlockNumber(20));
+        peer_manager.on_other_behaviour_event(&mixed_behaviour::ToOtherBehaviourEvent::Sqmr(
+            sqmr::ToOtherBehaviourEvent::RequestPeerAssignment {
+                outbound_session_id,
+                protocol_name: protocol.clone(),
+            },
+        ));
+        assert_eq!(
+            peer_manager.session_to_peer_map.get(&outbound_session_id),
+            Some(&full_peer.peer_id())
+        );
+    }
+}
+
+#[test]
+fn peer_assignment_falls_back_when_no_peer_advertised_block_range() {
+    let mut peer_manager = PeerManager::new(PeerManagerConfig::default());
+
+    let partial_peer = Peer::new(PeerId::random(), Multiaddr::empty());
+    peer_manager.add_peer(partial_peer.clone());
+
+    let protocol: StreamProtocol = Protocol::SignedBlockHeader.into();
+    peer_manager
+        .update_peer_advertised_block_range(
+            partial_peer.peer_id(),
+            protocol.clone(),
+            BlockNumber(0)..BlockNumber(15),
+        )
+        .unwrap();
+
+    let outbound_session_id = OutboundSessionId { value: 0 };
+    peer_manager.set_session_block_range(outbound_session_id, BlockNumber(10)..BlockNumber(20));
+    peer_manager.on_other_behaviour_event(&mixed_behaviour::ToOtherBehaviourEvent::Sqmr(
+        sqmr::ToOtherBehaviourEvent::RequestPeerAssignment {
+            outbound_session_id,
+            protocol_name: protocol,
+        },
+    ));
+    assert_eq!(
+        peer_manager.session_to_peer_map.get(&outbound_session_id),
+        Some(&partial_peer.peer_id())
+    );
+}
+
+#[test]
+fn sessions_for_the_same_blocks_over_different_protocols_are_assigned_to_the_same_peer() {
+    let mut peer_manager = PeerManager::new(PeerManagerConfig::default());
+    for _ in 0..3 {
+        peer_manager.add_peer(Peer::new(PeerId::random(), Multiaddr::empty()));
+    }
+
+    let mut assigned_peer_ids = Vec::new();
+    for (value, protocol) in
+        [Protocol::SignedBlockHeader, Protocol::StateDiff, Protocol::Transaction]
+            .into_iter()
+            .enumerate()
+    {
+        let outbound_session_id = OutboundSessionId { value };
+        peer_manager.set_session_block_range(outbound_session_id, BlockNumber(10)..BlockNumber(20));
+        peer_manager.on_other_behaviour_event(&mixed_behaviour::ToOtherBehaviourEvent::Sqmr(
+            sqmr::ToOtherBehaviourEvent::RequestPeerAssignment {
+                outbound_session_id,
+                protocol_name: protocol.into(),
+            },
+        ));
+        assigned_peer_ids.push(*peer_manager.session_to_peer_map.get(&outbound_session_id).unwrap());
+    }
+    // Without the preference, the round robin order would assign each session to another peer.
+    assert_eq!(assigned_peer_ids, vec![assigned_peer_ids[0]; 3]);
+}
+
+#[test]
+fn stale_block_range_advertisement_is_ignored() {
+    let protocol: StreamProtocol = Protocol::SignedBlockHeader.into();
+    let mut peer = Peer::new(PeerId::random(), Multiaddr::empty());
+
+    peer.set_advertised_block_range(
+        protocol.clone(),
+        BlockNumber(0)..BlockNumber(10),
+        Duration::minutes(1),
+    );
+    assert_eq!(peer.advertised_block_range(&protocol), Some(BlockNumber(0)..BlockNumber(10)));
+
+    peer.set_advertised_block_range(
+        protocol.clone(),
+        BlockNumber(0)..BlockNumber(20),
+        Duration::zero(),
+    );
+    assert_eq!(peer.advertised_block_range(&protocol), None);
+}
+
 #[test]
 fn wrap_around_in_peer_assignment() {
     // Create a new peer manager
@@ -444,6 +725,8 @@ fn identify_on_unknown_peer_is_added_to_peer_manager() {
         IdentifyToOtherBehaviourEvent::FoundListenAddresses {
             peer_id,
             listen_addresses: vec![address.clone()],
+            agent_version: String::new(),
+            protocols: vec![],
         },
     ));
 
@@ -453,6 +736,33 @@ fn identify_on_unknown_peer_is_added_to_peer_manager() {
     assert!(res_peer_id.multiaddr() == address);
 }
 
+#[test]
+fn identify_records_the_identity_of_the_peer() {
+    let mut peer_manager: PeerManager<Peer> = PeerManager::new(PeerManagerConfig::default());
+    let peer_identities = peer_manager.peer_identities();
+
+    let peer_id = PeerId::random();
+    for agent_version in ["papyrus/0.1", "papyrus/0.2"] {
+        peer_manager.on_other_behaviour_event(&mixed_behaviour::ToOtherBehaviourEvent::Identify(
+            IdentifyToOtherBehaviourEvent::FoundListenAddresses {
+                peer_id,
+                listen_addresses: vec![Multiaddr::empty()],
+                agent_version: agent_version.to_owned(),
+                protocols: vec![StreamProtocol::new("/ipfs/id/1.0.0")],
+            },
+        ));
+    }
+
+    // The identity is updated even though the peer is already known.
+    assert_eq!(
+        peer_identities.lock().unwrap().get(&peer_id),
+        Some(&PeerIdentity {
+            agent_version: "papyrus/0.2".to_owned(),
+            protocols: vec!["/ipfs/id/1.0.0".to_owned()],
+        })
+    );
+}
+
 #[test]
 fn no_more_peers_needed_stops_discovery() {
     // Create a new peer manager
@@ -465,6 +775,8 @@ fn no_more_peers_needed_stops_discovery() {
         IdentifyToOtherBehaviourEvent::FoundListenAddresses {
             peer_id,
             listen_addresses: vec![Multiaddr::empty()],
+            agent_version: String::new(),
+            protocols: vec![],
         },
     ));
 
@@ -479,3 +791,399 @@ fn no_more_peers_needed_stops_discovery() {
     }
     panic!("Discovery pause event not emitted");
 }
+
+#[tokio::test]
+async fn manual_ban_blocks_peer_until_unbanned() {
+    // A short blacklist timeout, so that reputation blocks end before the manual ban.
+    let config =
+        PeerManagerConfig { blacklist_timeout: Duration::milliseconds(1), ..Default::default() };
+    let mut peer_manager: PeerManager<Peer> = PeerManager::new(config);
+    let peer_id = PeerId::random();
+    peer_manager.add_peer(Peer::new(peer_id, Multiaddr::empty()));
+
+    peer_manager.handle_command(PeerManagerCommand::Blacklist {
+        peer_id,
+        duration: time::Duration::from_secs(60),
+    });
+    assert!(peer_manager.get_mut_peer(peer_id).unwrap().is_blocked());
+    assert_matches!(
+        poll_fn(|cx| peer_manager.poll(cx)).await,
+        ToSwarm::CloseConnection { peer_id: closed_peer_id, connection: CloseConnection::All }
+        if closed_peer_id == peer_id
+    );
+
+    // Reporting the peer doesn't shorten the ban.
+    peer_manager.report_peer(peer_id, ReputationModifier::Bad {}).unwrap();
+    sleep(time::Duration::from_millis(5)).await;
+    assert!(peer_manager.get_mut_peer(peer_id).unwrap().is_blocked());
+
+    peer_manager.handle_command(PeerManagerCommand::Unblacklist { peer_id });
+    assert!(!peer_manager.get_mut_peer(peer_id).unwrap().is_blocked());
+}
+
+#[test]
+fn manual_ban_survives_reconnecting_and_rediscovery() {
+    let mut peer_manager: PeerManager<Peer> = PeerManager::new(PeerManagerConfig::default());
+    // The peer is banned before the peer manager found it.
+    let peer_id = PeerId::random();
+    peer_manager.handle_command(PeerManagerCommand::Blacklist {
+        peer_id,
+        duration: time::Duration::from_secs(60),
+    });
+
+    peer_manager
+        .handle_established_inbound_connection(
+            ConnectionId::new_unchecked(0),
+            peer_id,
+            &Multiaddr::empty(),
+            &Multiaddr::empty(),
+        )
+        .expect_err("Inbound connection of a banned peer should be denied");
+    peer_manager
+        .handle_established_outbound_connection(
+            ConnectionId::new_unchecked(1),
+            peer_id,
+            &Multiaddr::empty(),
+            libp2p::core::Endpoint::Dialer,
+        )
+        .expect_err("Outbound connection to a banned peer should be denied");
+
+    peer_manager.on_other_behaviour_event(&mixed_behaviour::ToOtherBehaviourEvent::Identify(
+        IdentifyToOtherBehaviourEvent::FoundListenAddresses {
+            peer_id,
+            listen_addresses: vec![Multiaddr::empty()],
+            agent_version: String::new(),
+            protocols: vec![],
+        },
+    ));
+    assert!(peer_manager.get_mut_peer(peer_id).unwrap().is_blocked());
+    assert_eq!(peer_manager.assign_peer_to_session(OutboundSessionId { value: 1 }), None);
+}
+
+#[test]
+fn banning_the_bootstrap_peer_is_allowed() {
+    let bootstrap_peer_id = PeerId::random();
+    let config =
+        PeerManagerConfig { bootstrap_peer_id: Some(bootstrap_peer_id), ..Default::default() };
+    let mut peer_manager: PeerManager<Peer> = PeerManager::new(config);
+    peer_manager.add_peer(Peer::new(bootstrap_peer_id, Multiaddr::empty()));
+
+    peer_manager.handle_command(PeerManagerCommand::Blacklist {
+        peer_id: bootstrap_peer_id,
+        duration: time::Duration::from_secs(60),
+    });
+    assert!(peer_manager.get_mut_peer(bootstrap_peer_id).unwrap().is_blocked());
+}
+
+#[tokio::test]
+async fn get_state_dumps_peers_and_manual_bans() {
+    let mut peer_manager: PeerManager<Peer> = PeerManager::new(PeerManagerConfig::default());
+    let peer_id = PeerId::random();
+    let address = Multiaddr::empty().with_p2p(peer_id).unwrap();
+    peer_manager.add_peer(Peer::new(peer_id, address.clone()));
+    let outbound_session_id = OutboundSessionId { value: 1 };
+    peer_manager.assign_peer_to_session(outbound_session_id);
+    let banned_peer_id = PeerId::random();
+    peer_manager.handle_command(PeerManagerCommand::Blacklist {
+        peer_id: banned_peer_id,
+        duration: time::Duration::from_secs(60),
+    });
+
+    let (state_sender, state_receiver) = oneshot::channel();
+    peer_manager.handle_command(PeerManagerCommand::GetState(state_sender));
+    let PeerManagerState { peers, manual_bans } = state_receiver.await.unwrap();
+
+    assert_eq!(peers.len(), 1);
+    assert_eq!(peers[0].peer_id, peer_id);
+    assert_eq!(peers[0].multiaddr, address);
+    assert_eq!(peers[0].blocked_until, None);
+    assert!(!peers[0].manually_banned);
+    assert_eq!(peers[0].assigned_sessions, vec![outbound_session_id.value]);
+    assert_eq!(manual_bans.len(), 1);
+    assert_eq!(manual_bans[0].0, banned_peer_id);
+}
+
+fn dialer_endpoint() -> ConnectedPoint {
+    ConnectedPoint::Dialer {
+        address: Multiaddr::empty(),
+        role_override: libp2p::core::Endpoint::Dialer,
+    }
+}
+
+fn establish_connection(
+    peer_manager: &mut PeerManager<Peer>,
+    peer_id: PeerId,
+    connection_id: ConnectionId,
+) {
+    peer_manager.on_swarm_event(libp2p::swarm::FromSwarm::ConnectionEstablished(
+        ConnectionEstablished {
+            peer_id,
+            connection_id,
+            endpoint: &dialer_endpoint(),
+            failed_addresses: &[],
+            other_established: 0,
+        },
+    ));
+}
+
+// Returns the sessions of the SessionAssigned events, with the connection they were assigned to.
+fn take_assigned_sessions(
+    peer_manager: &mut PeerManager<Peer>,
+) -> Vec<(OutboundSessionId, ConnectionId)> {
+    let mut assigned_sessions = std::mem::take(&mut peer_manager.pending_events)
+        .into_iter()
+        .filter_map(|event| match event {
+            ToSwarm::GenerateEvent(ToOtherBehaviourEvent::SessionAssigned {
+                outbound_session_id,
+                connection_id,
+                ..
+            }) => Some((outbound_session_id, connection_id)),
+            _ => None,
+        })
+        .collect::<Vec<_>>();
+    assigned_sessions.sort_by_key(|(outbound_session_id, _)| outbound_session_id.value);
+    assigned_sessions
+}
+
+#[test]
+fn sessions_waiting_for_the_same_peer_share_a_dial() {
+    let mut peer_manager: PeerManager<Peer> = PeerManager::new(PeerManagerConfig::default());
+    let peer_id = PeerId::random();
+    peer_manager.add_peer(Peer::new(peer_id, Multiaddr::empty()));
+
+    let outbound_session_ids = (0..3).map(|value| OutboundSessionId { value }).collect::<Vec<_>>();
+    for outbound_session_id in &outbound_session_ids {
+        assert_eq!(peer_manager.assign_peer_to_session(*outbound_session_id), Some(peer_id));
+    }
+    let num_dials = peer_manager
+        .pending_events
+        .iter()
+        .filter(
+            |event| matches!(event, ToSwarm::Dial { opts } if opts.get_peer_id() == Some(peer_id)),
+        )
+        .count();
+    assert_eq!(num_dials, 1);
+
+    // All the sessions are assigned once the dial succeeds.
+    let connection_id = ConnectionId::new_unchecked(0);
+    establish_connection(&mut peer_manager, peer_id, connection_id);
+    assert_eq!(
+        take_assigned_sessions(&mut peer_manager),
+        outbound_session_ids
+            .into_iter()
+            .map(|outbound_session_id| (outbound_session_id, connection_id))
+            .collect::<Vec<_>>()
+    );
+}
+
+// The only peer is disconnected in the middle of the sync, and the query that resumes the sync is
+// sent once the peer is dialed again.
+#[test]
+fn disconnected_peer_is_redialed_for_a_new_session() {
+    let mut peer_manager: PeerManager<Peer> = PeerManager::new(PeerManagerConfig::default());
+    let peer_id = PeerId::random();
+    peer_manager.add_peer(Peer::new(peer_id, Multiaddr::empty()));
+    let first_connection_id = ConnectionId::new_unchecked(0);
+    establish_connection(&mut peer_manager, peer_id, first_connection_id);
+
+    let first_session_id = OutboundSessionId { value: 0 };
+    peer_manager.assign_peer_to_session(first_session_id);
+    assert_eq!(
+        take_assigned_sessions(&mut peer_manager),
+        vec![(first_session_id, first_connection_id)]
+    );
+
+    peer_manager.on_swarm_event(libp2p::swarm::FromSwarm::ConnectionClosed(ConnectionClosed {
+        peer_id,
+        connection_id: first_connection_id,
+        endpoint: &dialer_endpoint(),
+        remaining_established: 0,
+    }));
+
+    let second_session_id = OutboundSessionId { value: 1 };
+    assert_eq!(peer_manager.assign_peer_to_session(second_session_id), Some(peer_id));
+    assert_matches!(
+        peer_manager.pending_events.pop(),
+        Some(ToSwarm::Dial { opts }) if opts.get_peer_id() == Some(peer_id)
+    );
+
+    let second_connection_id = ConnectionId::new_unchecked(1);
+    establish_connection(&mut peer_manager, peer_id, second_connection_id);
+    assert_eq!(
+        take_assigned_sessions(&mut peer_manager),
+        vec![(second_session_id, second_connection_id)]
+    );
+}
+
+#[test]
+fn session_waits_while_all_peers_are_blocked() {
+    let mut peer_manager: PeerManager<Peer> = PeerManager::new(PeerManagerConfig::default());
+    let peer_id = PeerId::random();
+    peer_manager.add_peer(Peer::new(peer_id, Multiaddr::empty()));
+    let connection_id = ConnectionId::new_unchecked(0);
+    establish_connection(&mut peer_manager, peer_id, connection_id);
+    peer_manager.handle_command(PeerManagerCommand::Blacklist {
+        peer_id,
+        duration: time::Duration::from_secs(3600),
+    });
+
+    let outbound_session_id = OutboundSessionId { value: 0 };
+    assert_eq!(peer_manager.assign_peer_to_session(outbound_session_id), None);
+    assert!(take_assigned_sessions(&mut peer_manager).is_empty());
+
+    peer_manager.handle_command(PeerManagerCommand::Unblacklist { peer_id });
+    assert_eq!(
+        take_assigned_sessions(&mut peer_manager),
+        vec![(outbound_session_id, connection_id)]
+    );
+}
+
+// The test runs with a paused clock so that it doesn't wait for the dial deadline.
+#[tokio::test(start_paused = true)]
+async fn session_fails_if_no_peer_is_connected_within_the_dial_deadline() {
+    let dial_deadline = time::Duration::from_secs(60);
+    let config = PeerManagerConfig { dial_deadline, ..Default::default() };
+    let mut peer_manager: PeerManager<Peer> = PeerManager::new(config);
+
+    let outbound_session_id = OutboundSessionId { value: 0 };
+    assert_eq!(peer_manager.assign_peer_to_session(outbound_session_id), None);
+    assert_matches!(
+        poll_fn(|cx| peer_manager.poll(cx)).await,
+        ToSwarm::GenerateEvent(ToOtherBehaviourEvent::ResumeDiscovery)
+    );
+    assert_matches!(
+        poll_fn(|cx| peer_manager.poll(cx)).await,
+        ToSwarm::GenerateEvent(ToOtherBehaviourEvent::NoPeersForSession {
+            outbound_session_id: failed_session_id,
+            dial_deadline: failed_dial_deadline,
+        }) if failed_session_id == outbound_session_id && failed_dial_deadline == dial_deadline
+    );
+
+    // The failed session isn't assigned to a peer found later.
+    let peer_id = PeerId::random();
+    peer_manager.add_peer(Peer::new(peer_id, Multiaddr::empty()));
+    establish_connection(&mut peer_manager, peer_id, ConnectionId::new_unchecked(0));
+    assert!(take_assigned_sessions(&mut peer_manager).is_empty());
+}
+
+#[test]
+fn peer_at_its_session_limit_takes_a_session_once_another_finishes() {
+    let config = PeerManagerConfig { max_sessions_per_peer: 1, ..Default::default() };
+    let mut peer_manager: PeerManager<Peer> = PeerManager::new(config);
+    let peer_id = PeerId::random();
+    peer_manager.add_peer(Peer::new(peer_id, Multiaddr::empty()));
+    let connection_id = ConnectionId::new_unchecked(0);
+    establish_connection(&mut peer_manager, peer_id, connection_id);
+
+    let first_session_id = OutboundSessionId { value: 0 };
+    let second_session_id = OutboundSessionId { value: 1 };
+    assert_eq!(peer_manager.assign_peer_to_session(first_session_id), Some(peer_id));
+    assert_eq!(peer_manager.assign_peer_to_session(second_session_id), None);
+    assert_eq!(take_assigned_sessions(&mut peer_manager), vec![(first_session_id, connection_id)]);
+
+    peer_manager.finish_session(first_session_id);
+    assert_eq!(take_assigned_sessions(&mut peer_manager), vec![(second_session_id, connection_id)]);
+}
+
+fn ping(peer_manager: &mut PeerManager<Peer>, peer_id: PeerId, rtt: Option<time::Duration>) {
+    let ping_event = match rtt {
+        Some(rtt) => PingToOtherBehaviourEvent::Succeeded { peer_id, rtt },
+        None => PingToOtherBehaviourEvent::Lost { peer_id },
+    };
+    peer_manager
+        .on_other_behaviour_event(&mixed_behaviour::ToOtherBehaviourEvent::Ping(ping_event));
+}
+
+#[test]
+fn pings_update_the_smoothed_rtt_and_lost_pings() {
+    let mut peer_manager: PeerManager<Peer> = PeerManager::new(PeerManagerConfig::default());
+    let peer_latencies = peer_manager.peer_latencies();
+    let peer_id = PeerId::random();
+
+    ping(&mut peer_manager, peer_id, Some(time::Duration::from_millis(80)));
+    ping(&mut peer_manager, peer_id, None);
+    ping(&mut peer_manager, peer_id, Some(time::Duration::from_millis(160)));
+
+    assert_eq!(
+        peer_latencies.lock().unwrap().get(&peer_id),
+        Some(&PeerLatency {
+            smoothed_rtt: Some(time::Duration::from_millis(90)),
+            lost_pings: 1,
+            consecutive_lost_pings: 0,
+            healthy: true,
+        })
+    );
+}
+
+#[tokio::test]
+async fn peer_that_loses_consecutive_pings_is_disconnected_and_avoided() {
+    let config = PeerManagerConfig { max_consecutive_lost_pings: 2, ..Default::default() };
+    let mut peer_manager: PeerManager<Peer> = PeerManager::new(config);
+    let peer_latencies = peer_manager.peer_latencies();
+    let unhealthy_peer_id = PeerId::random();
+    peer_manager.add_peer(Peer::new(unhealthy_peer_id, Multiaddr::empty()));
+    establish_connection(&mut peer_manager, unhealthy_peer_id, ConnectionId::new_unchecked(0));
+    peer_manager.pending_events.clear();
+
+    ping(&mut peer_manager, unhealthy_peer_id, None);
+    assert!(peer_manager.pending_events.is_empty());
+    ping(&mut peer_manager, unhealthy_peer_id, None);
+    assert!(!peer_latencies.lock().unwrap()[&unhealthy_peer_id].healthy);
+    assert_matches!(
+        poll_fn(|cx| peer_manager.poll(cx)).await,
+        ToSwarm::CloseConnection { peer_id, connection: CloseConnection::All }
+        if peer_id == unhealthy_peer_id
+    );
+
+    // An unhealthy peer is assigned sessions only if no healthy peer can take them.
+    let healthy_peer_id = PeerId::random();
+    peer_manager.add_peer(Peer::new(healthy_peer_id, Multiaddr::empty()));
+    for value in 0..3 {
+        assert_eq!(
+            peer_manager.assign_peer_to_session(OutboundSessionId { value }),
+            Some(healthy_peer_id)
+        );
+    }
+    peer_manager.handle_command(PeerManagerCommand::Blacklist {
+        peer_id: healthy_peer_id,
+        duration: time::Duration::from_secs(60),
+    });
+    assert_eq!(
+        peer_manager.assign_peer_to_session(OutboundSessionId { value: 3 }),
+        Some(unhealthy_peer_id)
+    );
+
+    // A peer that answers a ping is healthy again.
+    ping(&mut peer_manager, unhealthy_peer_id, Some(time::Duration::from_millis(10)));
+    assert!(peer_latencies.lock().unwrap()[&unhealthy_peer_id].healthy);
+}
+
+#[test]
+fn peer_with_lower_rtt_is_preferred() {
+    let mut peer_manager: PeerManager<Peer> = PeerManager::new(PeerManagerConfig::default());
+    let slow_peer_id = PeerId::random();
+    let fast_peer_id = PeerId::random();
+    let unmeasured_peer_id = PeerId::random();
+    for peer_id in [slow_peer_id, fast_peer_id, unmeasured_peer_id] {
+        peer_manager.add_peer(Peer::new(peer_id, Multiaddr::empty()));
+    }
+    ping(&mut peer_manager, slow_peer_id, Some(time::Duration::from_millis(300)));
+    ping(&mut peer_manager, fast_peer_id, Some(time::Duration::from_millis(20)));
+
+    for value in 0..3 {
+        assert_eq!(
+            peer_manager.assign_peer_to_session(OutboundSessionId { value }),
+            Some(fast_peer_id)
+        );
+    }
+
+    // The round trip time only breaks ties between peers that are equally suitable otherwise.
+    let protocol = StreamProtocol::new("/test");
+    let outbound_session_id = OutboundSessionId { value: 3 };
+    peer_manager.session_to_protocol.insert(outbound_session_id, protocol.clone());
+    peer_manager.set_session_block_range(outbound_session_id, BlockNumber(0)..BlockNumber(1));
+    peer_manager
+        .update_peer_block_availability(slow_peer_id, protocol, BlockNumber(0), true)
+        .unwrap();
+    assert_eq!(peer_manager.assign_peer_to_session(outbound_session_id), Some(slow_peer_id));
+}
@@ -0,0 +1,40 @@
+use std::time::Duration;
+
+use libp2p::{ping, PeerId};
+
+use crate::mixed_behaviour;
+use crate::mixed_behaviour::BridgedBehaviour;
+
+#[derive(Debug)]
+pub enum PingToOtherBehaviourEvent {
+    /// The peer answered a ping after the given round trip time.
+    Succeeded { peer_id: PeerId, rtt: Duration },
+    /// The peer didn't answer a ping in time, or the stream of the ping failed.
+    Lost { peer_id: PeerId },
+}
+
+impl From<ping::Event> for mixed_behaviour::Event {
+    fn from(event: ping::Event) -> Self {
+        let peer_id = event.peer;
+        let ping_event = match event.result {
+            Ok(rtt) => PingToOtherBehaviourEvent::Succeeded { peer_id, rtt },
+            // The peer isn't pinged again on this connection, and since it didn't fail to answer a
+            // ping it isn't considered unhealthy.
+            Err(ping::Failure::Unsupported) => {
+                return mixed_behaviour::Event::ToOtherBehaviourEvent(
+                    mixed_behaviour::ToOtherBehaviourEvent::NoOp,
+                );
+            }
+            Err(ping::Failure::Timeout | ping::Failure::Other { .. }) => {
+                PingToOtherBehaviourEvent::Lost { peer_id }
+            }
+        };
+        mixed_behaviour::Event::ToOtherBehaviourEvent(mixed_behaviour::ToOtherBehaviourEvent::Ping(
+            ping_event,
+        ))
+    }
+}
+
+impl BridgedBehaviour for ping::Behaviour {
+    fn on_other_behaviour_event(&mut self, _event: &mixed_behaviour::ToOtherBehaviourEvent) {}
+}
@@ -49,6 +49,8 @@ pub enum SessionError {
     // idle_connection_timeout to a non-zero number.
     #[error("Connection to remote peer closed.")]
     ConnectionClosed,
+    #[error("No peer was connected to send the query to within {dial_deadline:?}.")]
+    NoPeers { dial_deadline: Duration },
 }
 
 impl From<GenericEvent<HandlerSessionError>> for GenericEvent<SessionError> {
@@ -60,8 +62,8 @@ impl From<GenericEvent<HandlerSessionError>> for GenericEvent<SessionError> {
                 peer_id,
                 protocol_name,
             } => Self::NewInboundSession { query, inbound_session_id, peer_id, protocol_name },
-            GenericEvent::ReceivedData { outbound_session_id, data, peer_id } => {
-                Self::ReceivedData { outbound_session_id, data, peer_id }
+            GenericEvent::ReceivedData { outbound_session_id, data, peer_id, protocol_name } => {
+                Self::ReceivedData { outbound_session_id, data, peer_id, protocol_name }
             }
             GenericEvent::SessionFailed {
                 session_id,
@@ -90,7 +92,7 @@ pub type ExternalEvent = GenericEvent<SessionError>;
 
 #[derive(Debug)]
 pub enum ToOtherBehaviourEvent {
-    RequestPeerAssignment { outbound_session_id: OutboundSessionId },
+    RequestPeerAssignment { outbound_session_id: OutboundSessionId, protocol_name: StreamProtocol },
 }
 
 #[derive(Debug)]
@@ -117,7 +119,8 @@ pub struct Behaviour {
     next_inbound_session_id: Arc<AtomicUsize>,
     dropped_sessions: HashSet<SessionId>,
     wakers_waiting_for_event: Vec<Waker>,
-    outbound_sessions_pending_peer_assignment: HashMap<OutboundSessionId, (Bytes, StreamProtocol)>,
+    outbound_sessions_pending_peer_assignment:
+        HashMap<OutboundSessionId, (Bytes, Vec<StreamProtocol>)>,
 }
 
 impl Behaviour {
@@ -136,13 +139,14 @@ impl Behaviour {
     }
 
     /// Send query to the given peer and start a new outbound session with it. Return the id of the
-    /// new session.
+    /// new session. The session is negotiated with the first of `protocol_names` that the peer
+    /// supports.
     // TODO(shahak) Remove this function once Network manager uses start_query.
     pub fn send_query(
         &mut self,
         query: Bytes,
         peer_id: PeerId,
-        protocol_name: StreamProtocol,
+        protocol_names: Vec<StreamProtocol>,
     ) -> Result<OutboundSessionId, PeerNotConnected> {
         let connection_id =
             *self.connection_ids_map.get(peer_id).iter().next().ok_or(PeerNotConnected)?;
@@ -159,27 +163,31 @@ impl Behaviour {
             event: RequestFromBehaviourEvent::CreateOutboundSession {
                 query,
                 outbound_session_id,
-                protocol_name,
+                protocol_names,
             },
         });
 
         Ok(outbound_session_id)
     }
 
-    /// Assign some peer and start a query. Return the id of the new session.
+    /// Assign some peer and start a query. Return the id of the new session. `protocol_names` are
+    /// the versions of the protocol from the newest to the oldest, and the peer is assigned by the
+    /// first of them.
     pub fn start_query(
         &mut self,
         query: Bytes,
-        protocol_name: StreamProtocol,
+        protocol_names: Vec<StreamProtocol>,
     ) -> OutboundSessionId {
         let outbound_session_id = self.next_outbound_session_id;
         self.next_outbound_session_id.value += 1;
 
+        let protocol_name =
+            protocol_names.first().expect("A query is sent with at least one protocol").clone();
         self.outbound_sessions_pending_peer_assignment
-            .insert(outbound_session_id, (query, protocol_name));
+            .insert(outbound_session_id, (query, protocol_names));
         info!("Requesting peer assignment for outbound session: {:?}.", outbound_session_id);
         self.add_event_to_queue(ToSwarm::GenerateEvent(Event::ToOtherBehaviourEvent(
-            ToOtherBehaviourEvent::RequestPeerAssignment { outbound_session_id },
+            ToOtherBehaviourEvent::RequestPeerAssignment { outbound_session_id, protocol_name },
         )));
 
         outbound_session_id
@@ -232,6 +240,38 @@ impl Behaviour {
         Ok(())
     }
 
+    /// Stops reading the responses of the outbound session until it's
+    /// [resumed](Self::resume_outbound_session), so that the peer stops sending them once the
+    /// substream's window is full. The other sessions of the connection keep being read.
+    pub fn pause_outbound_session(
+        &mut self,
+        outbound_session_id: OutboundSessionId,
+    ) -> Result<(), SessionIdNotFoundError> {
+        let (peer_id, connection_id) =
+            self.get_peer_id_and_connection_id_from_session_id(outbound_session_id.into())?;
+        self.add_event_to_queue(ToSwarm::NotifyHandler {
+            peer_id,
+            handler: NotifyHandler::One(connection_id),
+            event: RequestFromBehaviourEvent::PauseOutboundSession { outbound_session_id },
+        });
+        Ok(())
+    }
+
+    /// Continues reading the responses of an outbound session that was paused.
+    pub fn resume_outbound_session(
+        &mut self,
+        outbound_session_id: OutboundSessionId,
+    ) -> Result<(), SessionIdNotFoundError> {
+        let (peer_id, connection_id) =
+            self.get_peer_id_and_connection_id_from_session_id(outbound_session_id.into())?;
+        self.add_event_to_queue(ToSwarm::NotifyHandler {
+            peer_id,
+            handler: NotifyHandler::One(connection_id),
+            event: RequestFromBehaviourEvent::ResumeOutboundSession { outbound_session_id },
+        });
+        Ok(())
+    }
+
     fn get_peer_id_and_connection_id_from_session_id(
         &self,
         session_id: SessionId,
@@ -364,20 +404,40 @@ impl NetworkBehaviour for Behaviour {
 
 impl BridgedBehaviour for Behaviour {
     fn on_other_behaviour_event(&mut self, event: &mixed_behaviour::ToOtherBehaviourEvent) {
-        let mixed_behaviour::ToOtherBehaviourEvent::PeerManager(
-            peer_manager::ToOtherBehaviourEvent::SessionAssigned {
-                outbound_session_id,
-                peer_id,
-                connection_id,
-            },
-        ) = event
-        else {
-            return;
+        let (outbound_session_id, peer_id, connection_id) = match event {
+            mixed_behaviour::ToOtherBehaviourEvent::PeerManager(
+                peer_manager::ToOtherBehaviourEvent::SessionAssigned {
+                    outbound_session_id,
+                    peer_id,
+                    connection_id,
+                },
+            ) => (outbound_session_id, peer_id, connection_id),
+            mixed_behaviour::ToOtherBehaviourEvent::PeerManager(
+                peer_manager::ToOtherBehaviourEvent::NoPeersForSession {
+                    outbound_session_id,
+                    dial_deadline,
+                },
+            ) => {
+                if self
+                    .outbound_sessions_pending_peer_assignment
+                    .remove(outbound_session_id)
+                    .is_some()
+                {
+                    self.add_event_to_queue(ToSwarm::GenerateEvent(Event::External(
+                        ExternalEvent::SessionFailed {
+                            session_id: (*outbound_session_id).into(),
+                            error: SessionError::NoPeers { dial_deadline: *dial_deadline },
+                        },
+                    )));
+                }
+                return;
+            }
+            _ => return,
         };
         self.session_id_to_peer_id_and_connection_id
             .insert((*outbound_session_id).into(), (*peer_id, *connection_id));
 
-        let Some((query, protocol_name)) =
+        let Some((query, protocol_names)) =
             self.outbound_sessions_pending_peer_assignment.remove(outbound_session_id)
         else {
             error!(
@@ -393,7 +453,7 @@ impl BridgedBehaviour for Behaviour {
             event: RequestFromBehaviourEvent::CreateOutboundSession {
                 query,
                 outbound_session_id: *outbound_session_id,
-                protocol_name,
+                protocol_names,
             },
         });
     }
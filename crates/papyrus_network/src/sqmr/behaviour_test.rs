@@ -106,6 +106,7 @@ fn simulate_received_data(
             data,
             outbound_session_id,
             peer_id,
+            protocol_name: PROTOCOL_NAME.clone(),
         }),
     );
 }
@@ -159,12 +160,12 @@ async fn validate_create_outbound_session_event(
         event,
         ToSwarm::NotifyHandler {
             peer_id: event_peer_id,
-            event: RequestFromBehaviourEvent::CreateOutboundSession { query: event_query, outbound_session_id: event_outbound_session_id, protocol_name },
+            event: RequestFromBehaviourEvent::CreateOutboundSession { query: event_query, outbound_session_id: event_outbound_session_id, protocol_names },
             ..
         } if *peer_id == event_peer_id
             && *outbound_session_id == event_outbound_session_id
             && *query == event_query
-            && protocol_name == PROTOCOL_NAME.clone()
+            && protocol_names == vec![PROTOCOL_NAME.clone()]
     );
 }
 
@@ -201,7 +202,8 @@ async fn validate_received_data_event(
         ToSwarm::GenerateEvent(Event::External(ExternalEvent::ReceivedData {
             data: event_data, outbound_session_id: event_outbound_session_id,
             peer_id: event_peer_id,
-        })) if event_data == *data && event_outbound_session_id == outbound_session_id && peer_id == event_peer_id
+            protocol_name,
+        })) if event_data == *data && event_outbound_session_id == outbound_session_id && peer_id == event_peer_id && protocol_name == PROTOCOL_NAME.clone()
     );
 }
 
@@ -323,7 +325,7 @@ async fn create_and_process_outbound_session() {
 
     simulate_connection_established(&mut behaviour, peer_id);
     let outbound_session_id =
-        behaviour.send_query(QUERY.clone(), peer_id, PROTOCOL_NAME.clone()).unwrap();
+        behaviour.send_query(QUERY.clone(), peer_id, vec![PROTOCOL_NAME.clone()]).unwrap();
 
     validate_create_outbound_session_event(&mut behaviour, &peer_id, &QUERY, &outbound_session_id)
         .await;
@@ -355,7 +357,7 @@ async fn connection_closed() {
     simulate_connection_established(&mut behaviour, peer_id);
 
     let outbound_session_id =
-        behaviour.send_query(QUERY.clone(), peer_id, PROTOCOL_NAME.clone()).unwrap();
+        behaviour.send_query(QUERY.clone(), peer_id, vec![PROTOCOL_NAME.clone()]).unwrap();
 
     // Consume the event to create an outbound session.
     behaviour.next().await.unwrap();
@@ -403,7 +405,7 @@ async fn drop_outbound_session() {
     simulate_connection_established(&mut behaviour, peer_id);
 
     let outbound_session_id =
-        behaviour.send_query(QUERY.clone(), peer_id, PROTOCOL_NAME.clone()).unwrap();
+        behaviour.send_query(QUERY.clone(), peer_id, vec![PROTOCOL_NAME.clone()]).unwrap();
 
     // Consume the event to create an outbound session.
     behaviour.next().await.unwrap();
@@ -474,5 +476,5 @@ fn send_query_peer_not_connected_fails() {
 
     let peer_id = PeerId::random();
 
-    behaviour.send_query(QUERY.clone(), peer_id, PROTOCOL_NAME.clone()).unwrap_err();
+    behaviour.send_query(QUERY.clone(), peer_id, vec![PROTOCOL_NAME.clone()]).unwrap_err();
 }
@@ -7,11 +7,15 @@ use defaultmap::DefaultHashMap;
 use futures::StreamExt;
 use libp2p::swarm::{NetworkBehaviour, StreamProtocol, SwarmEvent};
 use libp2p::{PeerId, Swarm};
+use libp2p_swarm_test::SwarmExt;
+use papyrus_protobuf::sync::ProtocolVersion;
+use starknet_api::core::ChainId;
 
 use super::behaviour::{Behaviour, Event, ExternalEvent};
 use super::{Bytes, Config, InboundSessionId, OutboundSessionId, SessionId};
 use crate::test_utils::create_fully_connected_swarms_stream;
 use crate::utils::StreamHashMap;
+use crate::Protocol;
 
 const NUM_PEERS: usize = 3;
 const NUM_MESSAGES_PER_SESSION: usize = 5;
@@ -70,7 +74,7 @@ fn send_query_and_update_map(
         .send_query(
             get_bytes_from_query_indices(outbound_peer_id, inbound_peer_id),
             inbound_peer_id,
-            PROTOCOL_NAME,
+            vec![PROTOCOL_NAME],
         )
         .unwrap();
     outbound_session_id_to_peer_id.insert((outbound_peer_id, outbound_session_id), inbound_peer_id);
@@ -139,10 +143,12 @@ fn check_received_data_event(
         outbound_session_id: _outbound_session_id,
         data,
         peer_id: inbound_peer_id,
+        protocol_name,
     }) = event
     else {
         panic!("Got unexpected event {:?} when expecting ReceivedData", event);
     };
+    assert_eq!(protocol_name, PROTOCOL_NAME);
     assert_eq!(
         outbound_session_id_to_peer_id[&(outbound_peer_id, _outbound_session_id)],
         inbound_peer_id
@@ -263,3 +269,86 @@ async fn everyone_sends_to_everyone() {
     )
     .await;
 }
+
+fn header_protocol_names(versions: &[ProtocolVersion]) -> Vec<StreamProtocol> {
+    versions
+        .iter()
+        .map(|version| Protocol::SignedBlockHeader.chain_scoped_name(&ChainId::Sepolia, *version))
+        .collect()
+}
+
+// Sends a query from a client that proposes the given names to a server that supports the given
+// names, and returns the name the session was negotiated with on each side.
+async fn negotiate_session(
+    client_protocol_names: Vec<StreamProtocol>,
+    server_protocol_names: Vec<StreamProtocol>,
+) -> (StreamProtocol, StreamProtocol) {
+    let new_behaviour = |supported_inbound_protocols| {
+        Behaviour::new(Config {
+            session_timeout: Duration::from_secs(5),
+            supported_inbound_protocols,
+        })
+    };
+    let mut client = Swarm::new_ephemeral(|_| new_behaviour(client_protocol_names.clone()));
+    let mut server = Swarm::new_ephemeral(|_| new_behaviour(server_protocol_names));
+    server.listen().with_memory_addr_external().await;
+    client.connect(&mut server).await;
+
+    let server_peer_id = *server.local_peer_id();
+    client.behaviour_mut().send_query(vec![0], server_peer_id, client_protocol_names).unwrap();
+
+    let mut server_protocol_name = None;
+    loop {
+        tokio::select! {
+            event = server.select_next_some() => {
+                if let SwarmEvent::Behaviour(Event::External(ExternalEvent::NewInboundSession {
+                    inbound_session_id,
+                    protocol_name,
+                    ..
+                })) = event
+                {
+                    server_protocol_name = Some(protocol_name);
+                    server.behaviour_mut().send_data(vec![1], inbound_session_id).unwrap();
+                }
+            }
+            event = client.select_next_some() => {
+                if let SwarmEvent::Behaviour(Event::External(ExternalEvent::ReceivedData {
+                    protocol_name,
+                    ..
+                })) = event
+                {
+                    let server_protocol_name = server_protocol_name
+                        .expect("The client received data before the server got the query");
+                    return (protocol_name, server_protocol_name);
+                }
+            }
+        }
+    }
+}
+
+#[tokio::test]
+async fn v1_client_negotiates_v1_with_v1_and_v2_server() {
+    let v1 = header_protocol_names(&[1]);
+    let (client_protocol_name, server_protocol_name) =
+        negotiate_session(v1.clone(), header_protocol_names(&[2, 1])).await;
+    assert_eq!(client_protocol_name, v1[0]);
+    assert_eq!(server_protocol_name, v1[0]);
+}
+
+#[tokio::test]
+async fn v1_and_v2_client_negotiates_v1_with_v1_server() {
+    let v1 = header_protocol_names(&[1]);
+    let (client_protocol_name, server_protocol_name) =
+        negotiate_session(header_protocol_names(&[2, 1]), v1.clone()).await;
+    assert_eq!(client_protocol_name, v1[0]);
+    assert_eq!(server_protocol_name, v1[0]);
+}
+
+#[tokio::test]
+async fn newest_common_version_is_negotiated() {
+    let v2 = header_protocol_names(&[2]);
+    let (client_protocol_name, server_protocol_name) =
+        negotiate_session(header_protocol_names(&[2, 1]), header_protocol_names(&[2, 1])).await;
+    assert_eq!(client_protocol_name, v2[0]);
+    assert_eq!(server_protocol_name, v2[0]);
+}
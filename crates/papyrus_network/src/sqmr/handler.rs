@@ -4,15 +4,19 @@ mod handler_test;
 mod inbound_session;
 
 use std::collections::{HashMap, HashSet, VecDeque};
+use std::future::Future;
 use std::io;
+use std::pin::pin;
 use std::sync::atomic::{AtomicUsize, Ordering};
 use std::sync::Arc;
 use std::task::{Context, Poll};
-use std::time::Duration;
+use std::time::{Duration, Instant};
 
 use async_stream::stream;
+use futures::future::{select, Either};
 use futures::stream::BoxStream;
 use futures::{FutureExt, StreamExt};
+use futures_timer::Delay;
 use libp2p::swarm::handler::{
     ConnectionEvent,
     DialUpgradeError,
@@ -33,13 +37,14 @@ use self::inbound_session::InboundSession;
 use super::messages::read_message;
 use super::protocol::{InboundProtocol, OutboundProtocol};
 use super::{Bytes, Config, GenericEvent, InboundSessionId, OutboundSessionId, SessionId};
+use crate::message_size::{record_sqmr_message, MessageDirection};
 
 #[derive(Debug)]
 pub enum RequestFromBehaviourEvent {
     CreateOutboundSession {
         query: Bytes,
         outbound_session_id: OutboundSessionId,
-        protocol_name: StreamProtocol,
+        protocol_names: Vec<StreamProtocol>,
     },
     SendData {
         data: Bytes,
@@ -51,6 +56,15 @@ pub enum RequestFromBehaviourEvent {
     DropSession {
         session_id: SessionId,
     },
+    // Stops reading the responses of the outbound session until it's resumed. The responses that
+    // the peer sends meanwhile wait in the substream, whose flow control stops the peer once its
+    // window is full.
+    PauseOutboundSession {
+        outbound_session_id: OutboundSessionId,
+    },
+    ResumeOutboundSession {
+        outbound_session_id: OutboundSessionId,
+    },
 }
 
 #[derive(Debug)]
@@ -75,19 +89,23 @@ type HandlerEvent<H> = ConnectionHandlerEvent<
     <H as ConnectionHandler>::ToBehaviour,
 >;
 
+// The protocol name an outbound session was negotiated with, and the stream of its responses.
+type OutboundSession = (StreamProtocol, BoxStream<'static, Result<Bytes, SessionError>>);
+
 pub struct Handler {
     // TODO(shahak): Consider changing to Arc<Config> if the config becomes heavy to clone.
     config: Config,
     next_inbound_session_id: Arc<AtomicUsize>,
     peer_id: PeerId,
     id_to_inbound_session: HashMap<InboundSessionId, InboundSession>,
-    id_to_outbound_session:
-        HashMap<OutboundSessionId, BoxStream<'static, Result<Bytes, io::Error>>>,
+    id_to_outbound_session: HashMap<OutboundSessionId, OutboundSession>,
     // TODO(shahak): Use deadqueue if using a VecDeque is a bug (libp2p uses VecDeque, so we opened
     // an issue on it https://github.com/libp2p/rust-libp2p/issues/5147)
     pending_events: VecDeque<HandlerEvent<Self>>,
     inbound_sessions_marked_to_end: HashSet<InboundSessionId>,
     dropped_outbound_sessions_non_negotiated: HashSet<OutboundSessionId>,
+    // The outbound sessions whose responses aren't read until they're resumed.
+    paused_outbound_sessions: HashSet<OutboundSessionId>,
 }
 
 impl Handler {
@@ -102,6 +120,7 @@ impl Handler {
             pending_events: Default::default(),
             inbound_sessions_marked_to_end: Default::default(),
             dropped_outbound_sessions_non_negotiated: Default::default(),
+            paused_outbound_sessions: Default::default(),
         }
     }
 
@@ -114,13 +133,13 @@ impl Handler {
         cx: &mut Context<'_>,
     ) -> bool {
         match inbound_session.poll_unpin(cx) {
-            Poll::Ready(Err(io_error)) => {
+            Poll::Ready(Err(session_error)) => {
                 // No need to wake those waiting for pending events because this function is called
                 // inside `poll`.
                 pending_events.push_back(ConnectionHandlerEvent::NotifyBehaviour(
                     RequestToBehaviourEvent::GenerateEvent(GenericEvent::SessionFailed {
                         session_id: inbound_session_id.into(),
-                        error: SessionError::IOError(io_error),
+                        error: session_error,
                     }),
                 ));
                 true
@@ -194,22 +213,27 @@ impl ConnectionHandler for Handler {
 
         // Handle outbound sessions.
         self.id_to_outbound_session.retain(|outbound_session_id, outbound_session| {
-            match outbound_session.poll_next_unpin(cx) {
+            if self.paused_outbound_sessions.contains(outbound_session_id) {
+                return true;
+            }
+            let (protocol_name, responses) = outbound_session;
+            match responses.poll_next_unpin(cx) {
                 Poll::Ready(Some(Ok(data))) => {
                     self.pending_events.push_back(ConnectionHandlerEvent::NotifyBehaviour(
                         RequestToBehaviourEvent::GenerateEvent(GenericEvent::ReceivedData {
                             outbound_session_id: *outbound_session_id,
                             data,
                             peer_id: self.peer_id,
+                            protocol_name: protocol_name.clone(),
                         }),
                     ));
                     true
                 }
-                Poll::Ready(Some(Err(io_error))) => {
+                Poll::Ready(Some(Err(session_error))) => {
                     self.pending_events.push_back(ConnectionHandlerEvent::NotifyBehaviour(
                         RequestToBehaviourEvent::GenerateEvent(GenericEvent::SessionFailed {
                             session_id: SessionId::OutboundSessionId(*outbound_session_id),
-                            error: SessionError::IOError(io_error),
+                            error: session_error,
                         }),
                     ));
                     false
@@ -241,7 +265,7 @@ impl ConnectionHandler for Handler {
             RequestFromBehaviourEvent::CreateOutboundSession {
                 query,
                 outbound_session_id,
-                protocol_name,
+                protocol_names,
             } => {
                 // TODO(shahak) Consider extracting to a utility function to prevent forgetfulness
                 // of the timeout.
@@ -250,7 +274,7 @@ impl ConnectionHandler for Handler {
                 // on_behaviour_event. See https://github.com/libp2p/rust-libp2p/issues/5147
                 self.pending_events.push_back(ConnectionHandlerEvent::OutboundSubstreamRequest {
                     protocol: SubstreamProtocol::new(
-                        OutboundProtocol { query, protocol_name },
+                        OutboundProtocol { query, protocol_names },
                         outbound_session_id,
                     )
                     .with_timeout(self.config.session_timeout),
@@ -285,6 +309,7 @@ impl ConnectionHandler for Handler {
                 session_id: SessionId::OutboundSessionId(outbound_session_id),
             } => {
                 let remove_result = self.id_to_outbound_session.remove(&outbound_session_id);
+                self.paused_outbound_sessions.remove(&outbound_session_id);
                 if remove_result.is_none() {
                     self.dropped_outbound_sessions_non_negotiated.insert(outbound_session_id);
                 }
@@ -308,6 +333,17 @@ impl ConnectionHandler for Handler {
                     },
                 ));
             }
+            RequestFromBehaviourEvent::PauseOutboundSession { outbound_session_id } => {
+                // The session might have ended before the request arrived.
+                if self.id_to_outbound_session.contains_key(&outbound_session_id) {
+                    self.paused_outbound_sessions.insert(outbound_session_id);
+                }
+            }
+            RequestFromBehaviourEvent::ResumeOutboundSession { outbound_session_id } => {
+                // No need to wake because the swarm guarantees that `poll` will be called after
+                // on_behaviour_event. See https://github.com/libp2p/rust-libp2p/issues/5147
+                self.paused_outbound_sessions.remove(&outbound_session_id);
+            }
         }
     }
 
@@ -323,36 +359,57 @@ impl ConnectionHandler for Handler {
     ) {
         match event {
             ConnectionEvent::FullyNegotiatedOutbound(FullyNegotiatedOutbound {
-                protocol: mut read_stream,
+                protocol: (mut read_stream, protocol_name),
                 info: outbound_session_id,
             }) => {
                 if self.dropped_outbound_sessions_non_negotiated.remove(&outbound_session_id) {
                     return;
                 }
-                self.id_to_outbound_session.insert(
-                    outbound_session_id,
-                    stream! {
-                        loop {
-                            let result_opt = read_message(&mut read_stream).await;
-                            let result = match result_opt {
-                                Ok(Some(data)) => Ok(data),
-                                Ok(None) => break,
-                                Err(error) => Err(error),
-                            };
-                            let is_err = result.is_err();
-                            yield result;
-                            if is_err {
-                                break;
+                let session_timeout = self.config.session_timeout;
+                let protocol_name_for_session = protocol_name.clone();
+                // A peer that stops sending without ending the session fails it, so that it won't
+                // hold the session forever.
+                let outbound_session = stream! {
+                    loop {
+                        let result_opt = with_session_timeout(session_timeout, async {
+                            let read_result = read_message(&mut read_stream).await;
+                            match &read_result {
+                                Ok(Some(data)) => record_sqmr_message(
+                                    &protocol_name_for_session,
+                                    MessageDirection::Received,
+                                    Ok(data.len()),
+                                ),
+                                Ok(None) => {}
+                                Err(error) => record_sqmr_message(
+                                    &protocol_name_for_session,
+                                    MessageDirection::Received,
+                                    Err(error),
+                                ),
                             }
+                            read_result
+                        })
+                        .await;
+                        let result = match result_opt {
+                            Ok(Some(data)) => Ok(data),
+                            Ok(None) => break,
+                            Err(error) => Err(error),
+                        };
+                        let is_err = result.is_err();
+                        yield result;
+                        if is_err {
+                            break;
                         }
                     }
-                    .boxed(),
-                );
+                }
+                .boxed();
+                self.id_to_outbound_session
+                    .insert(outbound_session_id, (protocol_name, outbound_session));
             }
             ConnectionEvent::FullyNegotiatedInbound(FullyNegotiatedInbound {
                 protocol: (query, write_stream, protocol_name),
                 info: inbound_session_id,
             }) => {
+                let protocol_name_for_session = protocol_name.clone();
                 // No need to wake because the swarm guarantees that `poll` will be called after
                 // on_connection_event. See https://github.com/libp2p/rust-libp2p/issues/5147
                 self.pending_events.push_back(ConnectionHandlerEvent::NotifyBehaviour(
@@ -363,8 +420,14 @@ impl ConnectionHandler for Handler {
                         protocol_name,
                     }),
                 ));
-                self.id_to_inbound_session
-                    .insert(inbound_session_id, InboundSession::new(write_stream));
+                self.id_to_inbound_session.insert(
+                    inbound_session_id,
+                    InboundSession::new(
+                        write_stream,
+                        protocol_name_for_session,
+                        self.config.session_timeout,
+                    ),
+                );
             }
             ConnectionEvent::DialUpgradeError(DialUpgradeError {
                 info: outbound_session_id,
@@ -397,3 +460,19 @@ impl ConnectionHandler for Handler {
         }
     }
 }
+
+/// Runs `future`, failing with [`SessionError::Timeout`] if it doesn't finish within
+/// `session_timeout`.
+async fn with_session_timeout<T>(
+    session_timeout: Duration,
+    future: impl Future<Output = Result<T, io::Error>>,
+) -> Result<T, SessionError> {
+    // A timeout too large to be represented never expires.
+    if Instant::now().checked_add(session_timeout).is_none() {
+        return Ok(future.await?);
+    }
+    match select(pin!(future), Delay::new(session_timeout)).await {
+        Either::Left((result, _)) => Ok(result?),
+        Either::Right(((), _)) => Err(SessionError::Timeout { session_timeout }),
+    }
+}
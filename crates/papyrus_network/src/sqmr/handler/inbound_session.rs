@@ -1,41 +1,53 @@
 use std::collections::VecDeque;
 use std::future::Future;
-use std::io;
 use std::pin::Pin;
 use std::task::{ready, Context, Poll, Waker};
+use std::time::Duration;
 
 use futures::future::BoxFuture;
 use futures::io::WriteHalf;
 use futures::{AsyncWriteExt, FutureExt};
-use libp2p::swarm::Stream;
+use libp2p::swarm::{Stream, StreamProtocol};
 use replace_with::replace_with_or_abort;
 
 use super::super::messages::write_message;
 use super::super::Bytes;
+use super::{with_session_timeout, SessionError};
+use crate::message_size::{record_sqmr_message, MessageDirection};
 
 pub(super) struct InboundSession {
     pending_messages: VecDeque<Bytes>,
     current_task: WriteMessageTask,
     wakers_waiting_for_new_message: Vec<Waker>,
+    protocol_name: StreamProtocol,
+    // Writing a message or closing the session fails if it takes longer than this, so that a peer
+    // that stops reading won't hold the session forever.
+    session_timeout: Duration,
 }
 
 enum FinishReason {
-    Error(io::Error),
+    Error(SessionError),
     Closed,
 }
 
 enum WriteMessageTask {
     Waiting(WriteHalf<Stream>),
-    Running(BoxFuture<'static, Result<WriteHalf<Stream>, io::Error>>),
-    Closing(BoxFuture<'static, Result<(), io::Error>>),
+    Running(BoxFuture<'static, Result<WriteHalf<Stream>, SessionError>>),
+    Closing(BoxFuture<'static, Result<(), SessionError>>),
 }
 
 impl InboundSession {
-    pub fn new(write_stream: WriteHalf<Stream>) -> Self {
+    pub fn new(
+        write_stream: WriteHalf<Stream>,
+        protocol_name: StreamProtocol,
+        session_timeout: Duration,
+    ) -> Self {
         Self {
             pending_messages: Default::default(),
             current_task: WriteMessageTask::Waiting(write_stream),
             wakers_waiting_for_new_message: Default::default(),
+            protocol_name,
+            session_timeout,
         }
     }
 
@@ -52,23 +64,33 @@ impl InboundSession {
     }
 
     pub fn start_closing(&mut self) {
+        let session_timeout = self.session_timeout;
         replace_with_or_abort(&mut self.current_task, |current_task| {
             let WriteMessageTask::Waiting(mut write_stream) = current_task else {
                 panic!("Called start_closing while not waiting.");
             };
-            WriteMessageTask::Closing(async move { write_stream.close().await }.boxed())
+            WriteMessageTask::Closing(
+                async move { with_session_timeout(session_timeout, write_stream.close()).await }
+                    .boxed(),
+            )
         })
     }
 
     fn handle_waiting(&mut self, cx: &mut Context<'_>) -> Poll<()> {
         if let Some(data) = self.pending_messages.pop_front() {
+            record_sqmr_message(&self.protocol_name, MessageDirection::Sent, Ok(data.len()));
+            let session_timeout = self.session_timeout;
             replace_with_or_abort(&mut self.current_task, |current_task| {
                 let WriteMessageTask::Waiting(mut write_stream) = current_task else {
                     panic!("Called handle_waiting while not waiting.");
                 };
                 WriteMessageTask::Running(
                     async move {
-                        write_message(&data, &mut write_stream).await?;
+                        with_session_timeout(
+                            session_timeout,
+                            write_message(&data, &mut write_stream),
+                        )
+                        .await?;
                         Ok(write_stream)
                     }
                     .boxed(),
@@ -90,7 +112,7 @@ impl InboundSession {
                 self.current_task = WriteMessageTask::Waiting(write_stream);
                 None
             }
-            Err(io_error) => Some(FinishReason::Error(io_error)),
+            Err(session_error) => Some(FinishReason::Error(session_error)),
         })
     }
 
@@ -100,13 +122,13 @@ impl InboundSession {
         };
         fut.poll_unpin(cx).map(|result| match result {
             Ok(()) => FinishReason::Closed,
-            Err(io_error) => FinishReason::Error(io_error),
+            Err(session_error) => FinishReason::Error(session_error),
         })
     }
 }
 
 impl Future for InboundSession {
-    type Output = Result<(), io::Error>;
+    type Output = Result<(), SessionError>;
 
     fn poll(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
         let unpinned_self = Pin::into_inner(self);
@@ -126,7 +148,7 @@ impl Future for InboundSession {
             }
         };
         match finish_reason {
-            FinishReason::Error(io_error) => Poll::Ready(Err(io_error)),
+            FinishReason::Error(session_error) => Poll::Ready(Err(session_error)),
             FinishReason::Closed => Poll::Ready(Ok(())),
         }
     }
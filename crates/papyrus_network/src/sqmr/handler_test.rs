@@ -69,7 +69,7 @@ fn simulate_request_to_send_query_from_swarm(
     handler.on_behaviour_event(RequestFromBehaviourEvent::CreateOutboundSession {
         query,
         outbound_session_id,
-        protocol_name: PROTOCOL_NAME.clone(),
+        protocol_names: vec![PROTOCOL_NAME.clone()],
     });
 }
 
@@ -103,7 +103,10 @@ fn simulate_negotiated_outbound_session_from_swarm(
     outbound_session_id: OutboundSessionId,
 ) {
     handler.on_connection_event(ConnectionEvent::FullyNegotiatedOutbound(
-        FullyNegotiatedOutbound { protocol: outbound_stream.split().0, info: outbound_session_id },
+        FullyNegotiatedOutbound {
+            protocol: (outbound_stream.split().0, PROTOCOL_NAME.clone()),
+            info: outbound_session_id,
+        },
     ));
 }
 
@@ -153,11 +156,11 @@ async fn validate_received_data_event(
         ConnectionHandlerEvent::NotifyBehaviour(
             RequestToBehaviourEvent::GenerateEvent(
                 GenericEvent::ReceivedData {
-                    data: event_data, outbound_session_id: event_outbound_session_id, peer_id : event_peer_id
+                    data: event_data, outbound_session_id: event_outbound_session_id, peer_id : event_peer_id, protocol_name
 
                 }
             )
-        ) if event_data == *data &&  event_outbound_session_id == outbound_session_id && event_peer_id == handler.peer_id
+        ) if event_data == *data &&  event_outbound_session_id == outbound_session_id && event_peer_id == handler.peer_id && protocol_name == PROTOCOL_NAME.clone()
     );
 }
 
@@ -352,6 +355,43 @@ async fn process_outbound_session() {
     validate_session_finished_successfully_event(&mut handler, outbound_session_id.into()).await;
 }
 
+#[tokio::test]
+async fn paused_outbound_session_is_read_only_once_resumed() {
+    let mut handler =
+        Handler::new(Config::get_test_config(), Arc::new(Default::default()), PeerId::random());
+
+    let (mut inbound_stream, outbound_stream, _) = get_connected_streams().await;
+    let outbound_session_id = OutboundSessionId { value: 1 };
+
+    simulate_request_to_send_query_from_swarm(&mut handler, QUERY.clone(), outbound_session_id);
+    // consume the new outbound session event without reading it.
+    handler.next().await;
+    simulate_negotiated_outbound_session_from_swarm(
+        &mut handler,
+        outbound_stream,
+        outbound_session_id,
+    );
+
+    handler.on_behaviour_event(RequestFromBehaviourEvent::PauseOutboundSession {
+        outbound_session_id,
+    });
+    let dummy_data_vec = dummy_data();
+    for data in &dummy_data_vec {
+        write_message(data, &mut inbound_stream).await.unwrap();
+    }
+    // Need to sleep to make sure that if the handler read the messages, it would have reported
+    // them.
+    tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    validate_no_events(&mut handler);
+
+    handler.on_behaviour_event(RequestFromBehaviourEvent::ResumeOutboundSession {
+        outbound_session_id,
+    });
+    for data in &dummy_data_vec {
+        validate_received_data_event(&mut handler, data, outbound_session_id).await;
+    }
+}
+
 // Extracting to a function because two closures have different types.
 async fn test_outbound_session_negotiation_failure(
     upgrade_error: StreamUpgradeError<io::Error>,
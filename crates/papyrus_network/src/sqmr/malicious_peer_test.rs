@@ -0,0 +1,274 @@
+use std::io;
+use std::time::Duration;
+
+use assert_matches::assert_matches;
+use futures::StreamExt;
+use libp2p::swarm::{StreamProtocol, SwarmEvent};
+use libp2p::Swarm;
+use libp2p_swarm_test::SwarmExt;
+
+use super::behaviour::{Behaviour, Event, ExternalEvent, SessionError};
+use super::messages::MAX_MESSAGE_SIZE;
+use super::{Bytes, Config, SessionId};
+use crate::test_utils::dummy_data;
+use crate::test_utils::malicious_peer::{
+    ClientMisbehaviour,
+    MaliciousPeer,
+    MisbehaviourResult,
+    ServerMisbehaviour,
+};
+
+const PROTOCOL_NAME: StreamProtocol = StreamProtocol::new("/example");
+const SESSION_TIMEOUT: Duration = Duration::from_secs(1);
+// The time the victim has for reacting to a misbehaviour before the test fails.
+const TEST_TIMEOUT: Duration = Duration::from_secs(5);
+
+fn query() -> Bytes {
+    vec![1u8, 2u8, 3u8]
+}
+
+fn create_victim() -> Swarm<Behaviour> {
+    Swarm::new_ephemeral(|_| {
+        Behaviour::new(Config {
+            session_timeout: SESSION_TIMEOUT,
+            supported_inbound_protocols: vec![PROTOCOL_NAME],
+        })
+    })
+}
+
+async fn create_victim_and_malicious_peer() -> (Swarm<Behaviour>, MaliciousPeer) {
+    let mut victim = create_victim();
+    let malicious_peer = MaliciousPeer::connect(&mut victim, vec![PROTOCOL_NAME]).await;
+    (victim, malicious_peer)
+}
+
+async fn next_external_event(victim: &mut Swarm<Behaviour>) -> ExternalEvent {
+    tokio::time::timeout(TEST_TIMEOUT, async {
+        loop {
+            if let SwarmEvent::Behaviour(Event::External(event)) = victim.select_next_some().await {
+                return event;
+            }
+        }
+    })
+    .await
+    .expect("The victim didn't emit an event in time")
+}
+
+/// Polls the victim until the session of the misbehaviour ends. Returns the events the victim
+/// emitted meanwhile and the result of the misbehaviour.
+async fn run_until_session_ends(
+    victim: &mut Swarm<Behaviour>,
+    mut misbehaviour_result: MisbehaviourResult,
+) -> (Vec<ExternalEvent>, io::Result<()>) {
+    tokio::time::timeout(TEST_TIMEOUT, async {
+        let mut events = Vec::new();
+        loop {
+            tokio::select! {
+                swarm_event = victim.select_next_some() => {
+                    if let SwarmEvent::Behaviour(Event::External(event)) = swarm_event {
+                        events.push(event);
+                    }
+                }
+                result = &mut misbehaviour_result => {
+                    return (events, result.expect("The malicious peer stopped running"));
+                }
+            }
+        }
+    })
+    .await
+    .expect("The victim didn't end the session in time")
+}
+
+#[tokio::test]
+async fn silent_server_fails_the_session() {
+    let (mut victim, malicious_peer) = create_victim_and_malicious_peer().await;
+    let _result = malicious_peer.answer_next_query(ServerMisbehaviour::GoSilent);
+    let outbound_session_id = victim
+        .behaviour_mut()
+        .send_query(query(), malicious_peer.peer_id, vec![PROTOCOL_NAME])
+        .unwrap();
+
+    assert_matches!(
+        next_external_event(&mut victim).await,
+        ExternalEvent::SessionFailed { session_id, error: SessionError::Timeout { .. } }
+        if session_id == outbound_session_id.into()
+    );
+}
+
+#[tokio::test]
+async fn server_that_never_ends_fails_the_session_after_its_last_response() {
+    let (mut victim, malicious_peer) = create_victim_and_malicious_peer().await;
+    let responses = dummy_data();
+    let _result = malicious_peer.answer_next_query(ServerMisbehaviour::NeverEnd(responses.clone()));
+    let outbound_session_id = victim
+        .behaviour_mut()
+        .send_query(query(), malicious_peer.peer_id, vec![PROTOCOL_NAME])
+        .unwrap();
+
+    for response in responses {
+        assert_matches!(
+            next_external_event(&mut victim).await,
+            ExternalEvent::ReceivedData { outbound_session_id: session_id, data, .. }
+            if session_id == outbound_session_id && data == response
+        );
+    }
+    assert_matches!(
+        next_external_event(&mut victim).await,
+        ExternalEvent::SessionFailed { session_id, error: SessionError::Timeout { .. } }
+        if session_id == outbound_session_id.into()
+    );
+}
+
+#[tokio::test]
+async fn server_that_ends_immediately_finishes_the_session() {
+    let (mut victim, malicious_peer) = create_victim_and_malicious_peer().await;
+    let _result = malicious_peer.answer_next_query(ServerMisbehaviour::EndImmediately);
+    let outbound_session_id = victim
+        .behaviour_mut()
+        .send_query(query(), malicious_peer.peer_id, vec![PROTOCOL_NAME])
+        .unwrap();
+
+    assert_matches!(
+        next_external_event(&mut victim).await,
+        ExternalEvent::SessionFinishedSuccessfully { session_id }
+        if session_id == outbound_session_id.into()
+    );
+}
+
+// Responses that don't match the protocol's schema or that follow its last response are passed on,
+// since only the subscriber of the protocol can tell them apart.
+#[tokio::test]
+async fn responses_are_passed_on_until_the_server_ends_the_session() {
+    let (mut victim, malicious_peer) = create_victim_and_malicious_peer().await;
+    let responses = vec![vec![0xff], vec![]];
+    let _result = malicious_peer.answer_next_query(ServerMisbehaviour::Respond(responses.clone()));
+    let outbound_session_id = victim
+        .behaviour_mut()
+        .send_query(query(), malicious_peer.peer_id, vec![PROTOCOL_NAME])
+        .unwrap();
+
+    for response in responses {
+        assert_matches!(
+            next_external_event(&mut victim).await,
+            ExternalEvent::ReceivedData { data, .. } if data == response
+        );
+    }
+    assert_matches!(
+        next_external_event(&mut victim).await,
+        ExternalEvent::SessionFinishedSuccessfully { session_id }
+        if session_id == outbound_session_id.into()
+    );
+}
+
+#[tokio::test]
+async fn oversized_response_fails_the_session() {
+    let (mut victim, malicious_peer) = create_victim_and_malicious_peer().await;
+    let _result = malicious_peer.answer_next_query(ServerMisbehaviour::SendOversizedResponse);
+    let outbound_session_id = victim
+        .behaviour_mut()
+        .send_query(query(), malicious_peer.peer_id, vec![PROTOCOL_NAME])
+        .unwrap();
+
+    assert_matches!(
+        next_external_event(&mut victim).await,
+        ExternalEvent::SessionFailed { session_id, error: SessionError::IOError(error) }
+        if session_id == outbound_session_id.into() && error.kind() == io::ErrorKind::InvalidData
+    );
+}
+
+#[tokio::test]
+async fn flooding_server_doesnt_affect_other_sessions() {
+    let mut victim = create_victim();
+    let flooding_peer = MaliciousPeer::connect(&mut victim, vec![PROTOCOL_NAME]).await;
+    let other_peer = MaliciousPeer::connect(&mut victim, vec![PROTOCOL_NAME]).await;
+    let flood_result = flooding_peer.answer_next_query(ServerMisbehaviour::Flood);
+    let _result = other_peer.answer_next_query(ServerMisbehaviour::Respond(dummy_data()));
+    let flood_session_id = victim
+        .behaviour_mut()
+        .send_query(query(), flooding_peer.peer_id, vec![PROTOCOL_NAME])
+        .unwrap();
+    let other_session_id = victim
+        .behaviour_mut()
+        .send_query(query(), other_peer.peer_id, vec![PROTOCOL_NAME])
+        .unwrap();
+
+    let mut other_session_responses = Vec::new();
+    loop {
+        match next_external_event(&mut victim).await {
+            ExternalEvent::ReceivedData { outbound_session_id, data, .. }
+                if outbound_session_id == other_session_id =>
+            {
+                other_session_responses.push(data);
+            }
+            ExternalEvent::ReceivedData { outbound_session_id, data, .. } => {
+                assert_eq!(outbound_session_id, flood_session_id);
+                assert_eq!(data.len(), MAX_MESSAGE_SIZE);
+            }
+            ExternalEvent::SessionFinishedSuccessfully { session_id }
+                if session_id == other_session_id.into() =>
+            {
+                break;
+            }
+            event => panic!("Unexpected event {event:?}"),
+        }
+    }
+    assert_eq!(other_session_responses, dummy_data());
+
+    // Dropping the session stops the flood.
+    victim.behaviour_mut().drop_session(flood_session_id.into()).unwrap();
+    let (_events, flood_result) = run_until_session_ends(&mut victim, flood_result).await;
+    assert!(flood_result.is_err());
+}
+
+#[tokio::test]
+async fn silent_client_is_dropped_without_a_session() {
+    let (mut victim, malicious_peer) = create_victim_and_malicious_peer().await;
+    let result = malicious_peer.open_session(PROTOCOL_NAME, ClientMisbehaviour::GoSilent);
+
+    let (events, _result) = run_until_session_ends(&mut victim, result).await;
+    assert!(events.is_empty(), "Unexpected events {events:?}");
+
+    // The peer can still open sessions.
+    let _result =
+        malicious_peer.open_session(PROTOCOL_NAME, ClientMisbehaviour::NeverRead(query()));
+    assert_matches!(
+        next_external_event(&mut victim).await,
+        ExternalEvent::NewInboundSession { query: received_query, .. } if received_query == query()
+    );
+}
+
+#[tokio::test]
+async fn oversized_query_is_dropped_without_a_session() {
+    let (mut victim, malicious_peer) = create_victim_and_malicious_peer().await;
+    let result = malicious_peer.open_session(PROTOCOL_NAME, ClientMisbehaviour::SendOversizedQuery);
+
+    let (events, _result) = run_until_session_ends(&mut victim, result).await;
+    assert!(events.is_empty(), "Unexpected events {events:?}");
+}
+
+#[tokio::test]
+async fn client_that_doesnt_read_fails_the_session() {
+    let (mut victim, malicious_peer) = create_victim_and_malicious_peer().await;
+    let _result =
+        malicious_peer.open_session(PROTOCOL_NAME, ClientMisbehaviour::NeverRead(query()));
+    let ExternalEvent::NewInboundSession { inbound_session_id, .. } =
+        next_external_event(&mut victim).await
+    else {
+        panic!("Expected a new inbound session");
+    };
+
+    // More than the peer's connection can buffer without reading.
+    for _ in 0..4 {
+        victim.behaviour_mut().send_data(vec![0u8; MAX_MESSAGE_SIZE], inbound_session_id).unwrap();
+    }
+    victim.behaviour_mut().close_inbound_session(inbound_session_id).unwrap();
+
+    assert_matches!(
+        next_external_event(&mut victim).await,
+        ExternalEvent::SessionFailed {
+            session_id: SessionId::InboundSessionId(session_id),
+            error: SessionError::Timeout { .. },
+        }
+        if session_id == inbound_session_id
+    );
+}
@@ -12,6 +12,33 @@ use super::Bytes;
 
 pub const MAX_MESSAGE_SIZE: usize = 1 << 20;
 
+/// The error inside the [`io::Error`] of reading a message that exceeds [`MAX_MESSAGE_SIZE`], so
+/// that the callers can tell it apart from other invalid data.
+#[derive(Debug)]
+pub struct MessageTooLargeError {
+    // None if the message has no length prefix, so its size is unknown.
+    pub size: Option<usize>,
+}
+
+impl std::fmt::Display for MessageTooLargeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.size {
+            Some(size) => write!(
+                f,
+                "Received data size ({size} bytes) exceeds maximum ({MAX_MESSAGE_SIZE} bytes)"
+            ),
+            None => write!(f, "Received data size exceeds maximum ({MAX_MESSAGE_SIZE} bytes)"),
+        }
+    }
+}
+
+impl std::error::Error for MessageTooLargeError {}
+
+/// Returns the oversize error of reading a message if that's why reading it failed.
+pub fn as_message_too_large(error: &io::Error) -> Option<&MessageTooLargeError> {
+    error.get_ref()?.downcast_ref::<MessageTooLargeError>()
+}
+
 pub async fn write_message<Stream: AsyncWrite + Unpin>(
     message: &Bytes,
     io: &mut Stream,
@@ -41,10 +68,7 @@ pub async fn read_message<Stream: AsyncRead + Unpin>(
     if message_len > MAX_MESSAGE_SIZE {
         return Err(io::Error::new(
             io::ErrorKind::InvalidData,
-            format!(
-                "Received data size ({message_len} bytes) exceeds maximum ({MAX_MESSAGE_SIZE} \
-                 bytes)"
-            ),
+            MessageTooLargeError { size: Some(message_len) },
         ));
     }
     let mut buf = vec![0u8; message_len];
@@ -56,10 +80,18 @@ pub async fn read_message_without_length_prefix<Stream: AsyncRead + Unpin>(
     // We require `io` to be ReadHalf<Stream> and not Stream in order to ensure it's not a
     // reference.
     // We want to ensure it's not a reference because this function will make it unusable
-    mut io: ReadHalf<Stream>,
+    io: ReadHalf<Stream>,
 ) -> Result<Bytes, io::Error> {
     let mut buf = vec![];
-    io.read_to_end(&mut buf).await?;
+    // Reading one byte more than the maximum tells apart messages that exceed it, without reading
+    // the rest of them.
+    io.take(MAX_MESSAGE_SIZE as u64 + 1).read_to_end(&mut buf).await?;
+    if buf.len() > MAX_MESSAGE_SIZE {
+        return Err(io::Error::new(
+            io::ErrorKind::InvalidData,
+            MessageTooLargeError { size: None },
+        ));
+    }
     Ok(buf)
 }
 
@@ -1,13 +1,17 @@
+use std::io;
 use std::time::Duration;
 
 use futures::{AsyncReadExt, AsyncWriteExt};
 use pretty_assertions::assert_eq;
 
 use super::{
+    as_message_too_large,
     read_message,
     read_message_without_length_prefix,
     write_message,
     write_message_without_length_prefix,
+    write_usize,
+    MAX_MESSAGE_SIZE,
 };
 use crate::test_utils::{dummy_data, get_connected_streams};
 
@@ -47,3 +51,27 @@ async fn read_message_is_pending_when_other_stream_didnt_send() {
         tokio::time::timeout(Duration::from_millis(10), read_message(&mut stream2)).await.is_err()
     );
 }
+
+#[tokio::test]
+async fn read_message_without_length_prefix_fails_on_oversized_message() {
+    let (stream1, stream2, _) = get_connected_streams().await;
+    let (_read_stream1, write_stream1) = stream1.split();
+    let (read_stream2, _write_stream2) = stream2.split();
+    let message = vec![0u8; MAX_MESSAGE_SIZE + 1];
+    let (_, read_result) = tokio::join!(
+        write_message_without_length_prefix(&message, write_stream1),
+        read_message_without_length_prefix(read_stream2),
+    );
+    let error = read_result.unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+    assert_eq!(as_message_too_large(&error).unwrap().size, None);
+}
+
+#[tokio::test]
+async fn read_message_fails_on_oversized_message_with_its_size() {
+    let (mut stream1, mut stream2, _) = get_connected_streams().await;
+    // Only the length prefix is sent, since the message is rejected before its data is read.
+    write_usize(&mut stream1, MAX_MESSAGE_SIZE + 1).await.unwrap();
+    let error = read_message(&mut stream2).await.unwrap_err();
+    assert_eq!(as_message_too_large(&error).unwrap().size, Some(MAX_MESSAGE_SIZE + 1));
+}
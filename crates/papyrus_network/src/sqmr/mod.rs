@@ -1,10 +1,12 @@
 pub mod behaviour;
 pub mod handler;
-mod messages;
+pub(crate) mod messages;
 pub mod protocol;
 
 #[cfg(test)]
 mod flow_test;
+#[cfg(test)]
+mod malicious_peer_test;
 
 use std::time::Duration;
 
@@ -55,6 +57,8 @@ pub enum GenericEvent<SessionError> {
         outbound_session_id: OutboundSessionId,
         data: Bytes,
         peer_id: PeerId,
+        // The name the session was negotiated with, which determines the version of the protocol.
+        protocol_name: StreamProtocol,
     },
     SessionFailed {
         session_id: SessionId,
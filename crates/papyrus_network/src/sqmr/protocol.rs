@@ -2,7 +2,7 @@
 #[path = "protocol_test.rs"]
 mod protocol_test;
 
-use std::{io, iter};
+use std::io;
 
 use futures::future::BoxFuture;
 use futures::io::{ReadHalf, WriteHalf};
@@ -12,6 +12,7 @@ use libp2p::swarm::StreamProtocol;
 
 use super::messages::{read_message_without_length_prefix, write_message_without_length_prefix};
 use super::Bytes;
+use crate::message_size::{record_sqmr_message, MessageDirection};
 
 pub struct InboundProtocol {
     supported_protocols: Vec<StreamProtocol>,
@@ -43,7 +44,13 @@ where
     fn upgrade_inbound(self, stream: Stream, protocol_name: Self::Info) -> Self::Future {
         async move {
             let (read_half, write_half) = stream.split();
-            let request = read_message_without_length_prefix(read_half).await?;
+            let request_result = read_message_without_length_prefix(read_half).await;
+            record_sqmr_message(
+                &protocol_name,
+                MessageDirection::Received,
+                request_result.as_ref().map(Vec::len),
+            );
+            let request = request_result?;
             Ok((request, write_half, protocol_name))
         }
         .boxed()
@@ -53,15 +60,17 @@ where
 #[derive(Debug)]
 pub struct OutboundProtocol {
     pub query: Bytes,
-    pub protocol_name: StreamProtocol,
+    // The versions of the protocol to propose to the peer, from the newest to the oldest. The
+    // session uses the first one the peer supports.
+    pub protocol_names: Vec<StreamProtocol>,
 }
 
 impl UpgradeInfo for OutboundProtocol {
     type Info = StreamProtocol;
-    type InfoIter = iter::Once<Self::Info>;
+    type InfoIter = Vec<Self::Info>;
 
     fn protocol_info(&self) -> Self::InfoIter {
-        iter::once(self.protocol_name.clone())
+        self.protocol_names.clone()
     }
 }
 
@@ -69,15 +78,16 @@ impl<Stream> OutboundUpgrade<Stream> for OutboundProtocol
 where
     Stream: AsyncRead + AsyncWrite + Unpin + Send + 'static,
 {
-    type Output = ReadHalf<Stream>;
+    type Output = (ReadHalf<Stream>, StreamProtocol);
     type Error = io::Error;
     type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
 
-    fn upgrade_outbound(self, stream: Stream, _: Self::Info) -> Self::Future {
+    fn upgrade_outbound(self, stream: Stream, protocol_name: Self::Info) -> Self::Future {
         async move {
             let (read_half, write_half) = stream.split();
+            record_sqmr_message(&protocol_name, MessageDirection::Sent, Ok(self.query.len()));
             write_message_without_length_prefix(&self.query, write_half).await?;
-            Ok(read_half)
+            Ok((read_half, protocol_name))
         }
         .boxed()
     }
@@ -0,0 +1,405 @@
+//! A peer that misbehaves in sqmr sessions in scripted ways, for testing that the node survives
+//! Byzantine peers. The peer speaks the sqmr protocols over raw streams, so it can break the
+//! protocol in ways an sqmr swarm never would.
+
+use std::collections::VecDeque;
+use std::fmt::Debug;
+use std::io;
+use std::task::{Context, Poll};
+
+use futures::channel::{mpsc, oneshot};
+use futures::future::{pending, BoxFuture};
+use futures::{AsyncReadExt, AsyncWriteExt, FutureExt, StreamExt};
+use libp2p::core::upgrade::{InboundUpgrade, OutboundUpgrade, UpgradeInfo};
+use libp2p::core::Endpoint;
+use libp2p::swarm::handler::{ConnectionEvent, FullyNegotiatedInbound, FullyNegotiatedOutbound};
+use libp2p::swarm::{
+    ConnectionDenied,
+    ConnectionHandler,
+    ConnectionHandlerEvent,
+    ConnectionId,
+    FromSwarm,
+    NetworkBehaviour,
+    NotifyHandler,
+    Stream,
+    StreamProtocol,
+    SubstreamProtocol,
+    Swarm,
+    SwarmEvent,
+    ToSwarm,
+};
+use libp2p::{Multiaddr, PeerId};
+use libp2p_swarm_test::SwarmExt;
+
+use crate::sqmr::messages::{write_message, write_message_without_length_prefix, MAX_MESSAGE_SIZE};
+use crate::sqmr::Bytes;
+
+/// How the malicious peer answers a query it received.
+#[derive(Debug)]
+pub(crate) enum ServerMisbehaviour {
+    /// Sends the given responses and then keeps the session open without sending anything else.
+    NeverEnd(Vec<Bytes>),
+    /// Ends the session without sending any response.
+    EndImmediately,
+    /// Keeps the session open without sending anything.
+    GoSilent,
+    /// Sends the given responses and ends the session. The responses are sent as is, so they can
+    /// be malformed or follow the response that marks the end of the data.
+    Respond(Vec<Bytes>),
+    /// Sends responses of the maximal size until the session is closed by the other side.
+    Flood,
+    /// Sends a response that is larger than the maximal message size.
+    SendOversizedResponse,
+}
+
+/// How the malicious peer behaves in a session it opened.
+#[derive(Debug)]
+pub(crate) enum ClientMisbehaviour {
+    /// Sends the given query and never reads the responses.
+    NeverRead(Bytes),
+    /// Opens the session without sending a query.
+    GoSilent,
+    /// Sends a query that is larger than the maximal message size.
+    SendOversizedQuery,
+}
+
+/// The result of a misbehaviour, which is sent once the session ended. Misbehaviours that keep
+/// the session open forever never send it.
+pub(crate) type MisbehaviourResult = oneshot::Receiver<io::Result<()>>;
+
+type MisbehaviourResultSender = oneshot::Sender<io::Result<()>>;
+
+/// A handle to a malicious peer that runs in the background, connected to a single victim.
+pub(crate) struct MaliciousPeer {
+    pub peer_id: PeerId,
+    server_misbehaviour_sender:
+        mpsc::UnboundedSender<(ServerMisbehaviour, MisbehaviourResultSender)>,
+    client_misbehaviour_sender:
+        mpsc::UnboundedSender<(StreamProtocol, ClientMisbehaviour, MisbehaviourResultSender)>,
+}
+
+impl MaliciousPeer {
+    /// Creates a malicious peer that accepts sessions of `supported_protocols`, connects it to
+    /// `victim` and runs it in the background.
+    pub async fn connect<TBehaviour: NetworkBehaviour + Send>(
+        victim: &mut Swarm<TBehaviour>,
+        supported_protocols: Vec<StreamProtocol>,
+    ) -> Self
+    where
+        <TBehaviour as NetworkBehaviour>::ToSwarm: Debug,
+    {
+        let mut swarm = Swarm::new_ephemeral(|_| Behaviour::new(supported_protocols));
+        swarm.listen().with_memory_addr_external().await;
+        victim.connect(&mut swarm).await;
+
+        let peer_id = *swarm.local_peer_id();
+        let victim_peer_id = *victim.local_peer_id();
+        let (server_misbehaviour_sender, mut server_misbehaviour_receiver) = mpsc::unbounded();
+        let (client_misbehaviour_sender, mut client_misbehaviour_receiver) = mpsc::unbounded();
+        tokio::spawn(async move {
+            // Each inbound session is answered with the misbehaviour that was given first among
+            // those that weren't used yet.
+            let mut inbound_streams = VecDeque::new();
+            let mut server_misbehaviours = VecDeque::new();
+            loop {
+                tokio::select! {
+                    event = swarm.select_next_some() => match event {
+                        SwarmEvent::Behaviour(NegotiatedStream::Inbound(stream)) => {
+                            inbound_streams.push_back(stream);
+                        }
+                        SwarmEvent::Behaviour(NegotiatedStream::Outbound(
+                            stream,
+                            (misbehaviour, result_sender),
+                        )) => {
+                            tokio::spawn(async move {
+                                let _ = result_sender.send(misbehaviour.run(stream).await);
+                            });
+                        }
+                        _ => {}
+                    },
+                    Some(server_misbehaviour) = server_misbehaviour_receiver.next() => {
+                        server_misbehaviours.push_back(server_misbehaviour);
+                    }
+                    Some((protocol, misbehaviour, result_sender)) =
+                        client_misbehaviour_receiver.next() => {
+                        swarm.behaviour_mut().open_stream(
+                            victim_peer_id,
+                            protocol,
+                            (misbehaviour, result_sender),
+                        );
+                    }
+                }
+                while !inbound_streams.is_empty() && !server_misbehaviours.is_empty() {
+                    let stream = inbound_streams.pop_front().expect("Checked not empty");
+                    let (misbehaviour, result_sender) =
+                        server_misbehaviours.pop_front().expect("Checked not empty");
+                    tokio::spawn(async move {
+                        let _ = result_sender.send(misbehaviour.run(stream).await);
+                    });
+                }
+            }
+        });
+
+        Self { peer_id, server_misbehaviour_sender, client_misbehaviour_sender }
+    }
+
+    /// Answers the next query the victim sends with `misbehaviour`.
+    pub fn answer_next_query(&self, misbehaviour: ServerMisbehaviour) -> MisbehaviourResult {
+        let (result_sender, result_receiver) = oneshot::channel();
+        self.server_misbehaviour_sender
+            .unbounded_send((misbehaviour, result_sender))
+            .expect("The malicious peer stopped running");
+        result_receiver
+    }
+
+    /// Opens a session of `protocol` with the victim and behaves in it according to
+    /// `misbehaviour`.
+    pub fn open_session(
+        &self,
+        protocol: StreamProtocol,
+        misbehaviour: ClientMisbehaviour,
+    ) -> MisbehaviourResult {
+        let (result_sender, result_receiver) = oneshot::channel();
+        self.client_misbehaviour_sender
+            .unbounded_send((protocol, misbehaviour, result_sender))
+            .expect("The malicious peer stopped running");
+        result_receiver
+    }
+}
+
+impl ServerMisbehaviour {
+    async fn run(self, stream: Stream) -> io::Result<()> {
+        let (mut read_half, mut write_half) = stream.split();
+        // The query ends when the client closes its side of the stream.
+        read_half.read_to_end(&mut Vec::new()).await?;
+        match self {
+            ServerMisbehaviour::NeverEnd(responses) => {
+                for response in responses {
+                    write_message(&response, &mut write_half).await?;
+                }
+                pending().await
+            }
+            ServerMisbehaviour::EndImmediately => write_half.close().await,
+            ServerMisbehaviour::GoSilent => pending().await,
+            ServerMisbehaviour::Respond(responses) => {
+                for response in responses {
+                    write_message(&response, &mut write_half).await?;
+                }
+                write_half.close().await
+            }
+            ServerMisbehaviour::Flood => {
+                let response = vec![0u8; MAX_MESSAGE_SIZE];
+                loop {
+                    write_message(&response, &mut write_half).await?;
+                }
+            }
+            ServerMisbehaviour::SendOversizedResponse => {
+                write_message(&vec![0u8; MAX_MESSAGE_SIZE + 1], &mut write_half).await?;
+                write_half.close().await
+            }
+        }
+    }
+}
+
+impl ClientMisbehaviour {
+    async fn run(self, mut stream: Stream) -> io::Result<()> {
+        match self {
+            ClientMisbehaviour::NeverRead(query) => {
+                let (_read_half, write_half) = stream.split();
+                write_message_without_length_prefix(&query, write_half).await?;
+                pending().await
+            }
+            // Reading returns once the other side gave up on the session.
+            ClientMisbehaviour::GoSilent => stream.read_to_end(&mut Vec::new()).await.map(|_| ()),
+            ClientMisbehaviour::SendOversizedQuery => {
+                let (mut read_half, write_half) = stream.split();
+                write_message_without_length_prefix(&vec![0u8; MAX_MESSAGE_SIZE + 1], write_half)
+                    .await?;
+                read_half.read_to_end(&mut Vec::new()).await.map(|_| ())
+            }
+        }
+    }
+}
+
+type OutboundStreamInfo = (ClientMisbehaviour, MisbehaviourResultSender);
+
+#[derive(Debug)]
+pub(crate) enum NegotiatedStream {
+    Inbound(Stream),
+    Outbound(Stream, OutboundStreamInfo),
+}
+
+pub(crate) struct Behaviour {
+    supported_protocols: Vec<StreamProtocol>,
+    pending_events: VecDeque<ToSwarm<NegotiatedStream, (StreamProtocol, OutboundStreamInfo)>>,
+}
+
+impl Behaviour {
+    fn new(supported_protocols: Vec<StreamProtocol>) -> Self {
+        Self { supported_protocols, pending_events: Default::default() }
+    }
+
+    fn open_stream(
+        &mut self,
+        peer_id: PeerId,
+        protocol: StreamProtocol,
+        outbound_stream_info: OutboundStreamInfo,
+    ) {
+        self.pending_events.push_back(ToSwarm::NotifyHandler {
+            peer_id,
+            handler: NotifyHandler::Any,
+            event: (protocol, outbound_stream_info),
+        });
+    }
+}
+
+impl NetworkBehaviour for Behaviour {
+    type ConnectionHandler = Handler;
+    type ToSwarm = NegotiatedStream;
+
+    fn handle_established_inbound_connection(
+        &mut self,
+        _connection_id: ConnectionId,
+        _peer: PeerId,
+        _local_addr: &Multiaddr,
+        _remote_addr: &Multiaddr,
+    ) -> Result<Self::ConnectionHandler, ConnectionDenied> {
+        Ok(Handler::new(self.supported_protocols.clone()))
+    }
+
+    fn handle_established_outbound_connection(
+        &mut self,
+        _connection_id: ConnectionId,
+        _peer: PeerId,
+        _addr: &Multiaddr,
+        _role_override: Endpoint,
+    ) -> Result<Self::ConnectionHandler, ConnectionDenied> {
+        Ok(Handler::new(self.supported_protocols.clone()))
+    }
+
+    fn on_swarm_event(&mut self, _event: FromSwarm<'_>) {}
+
+    fn on_connection_handler_event(
+        &mut self,
+        _peer_id: PeerId,
+        _connection_id: ConnectionId,
+        negotiated_stream: <Self::ConnectionHandler as ConnectionHandler>::ToBehaviour,
+    ) {
+        self.pending_events.push_back(ToSwarm::GenerateEvent(negotiated_stream));
+    }
+
+    fn poll(
+        &mut self,
+        _cx: &mut Context<'_>,
+    ) -> Poll<ToSwarm<Self::ToSwarm, <Self::ConnectionHandler as ConnectionHandler>::FromBehaviour>>
+    {
+        match self.pending_events.pop_front() {
+            Some(event) => Poll::Ready(event),
+            None => Poll::Pending,
+        }
+    }
+}
+
+pub(crate) struct Handler {
+    supported_protocols: Vec<StreamProtocol>,
+    pending_events: VecDeque<HandlerEvent>,
+}
+
+type HandlerEvent = ConnectionHandlerEvent<RawProtocol, OutboundStreamInfo, NegotiatedStream>;
+
+impl Handler {
+    fn new(supported_protocols: Vec<StreamProtocol>) -> Self {
+        Self { supported_protocols, pending_events: Default::default() }
+    }
+}
+
+impl ConnectionHandler for Handler {
+    type FromBehaviour = (StreamProtocol, OutboundStreamInfo);
+    type ToBehaviour = NegotiatedStream;
+    type InboundProtocol = RawProtocol;
+    type OutboundProtocol = RawProtocol;
+    type InboundOpenInfo = ();
+    type OutboundOpenInfo = OutboundStreamInfo;
+
+    fn listen_protocol(&self) -> SubstreamProtocol<Self::InboundProtocol, Self::InboundOpenInfo> {
+        SubstreamProtocol::new(RawProtocol(self.supported_protocols.clone()), ())
+    }
+
+    // The victim may close the connection when it has no sessions, but the malicious peer keeps it
+    // open so that it can open more sessions.
+    fn connection_keep_alive(&self) -> bool {
+        true
+    }
+
+    fn poll(&mut self, _cx: &mut Context<'_>) -> Poll<HandlerEvent> {
+        match self.pending_events.pop_front() {
+            Some(event) => Poll::Ready(event),
+            None => Poll::Pending,
+        }
+    }
+
+    fn on_behaviour_event(&mut self, (protocol, outbound_stream_info): Self::FromBehaviour) {
+        self.pending_events.push_back(ConnectionHandlerEvent::OutboundSubstreamRequest {
+            protocol: SubstreamProtocol::new(RawProtocol(vec![protocol]), outbound_stream_info),
+        });
+    }
+
+    fn on_connection_event(
+        &mut self,
+        event: ConnectionEvent<
+            '_,
+            Self::InboundProtocol,
+            Self::OutboundProtocol,
+            Self::InboundOpenInfo,
+            Self::OutboundOpenInfo,
+        >,
+    ) {
+        match event {
+            ConnectionEvent::FullyNegotiatedOutbound(FullyNegotiatedOutbound {
+                protocol: stream,
+                info,
+            }) => self.pending_events.push_back(ConnectionHandlerEvent::NotifyBehaviour(
+                NegotiatedStream::Outbound(stream, info),
+            )),
+            ConnectionEvent::FullyNegotiatedInbound(FullyNegotiatedInbound {
+                protocol: stream,
+                info: (),
+            }) => self.pending_events.push_back(ConnectionHandlerEvent::NotifyBehaviour(
+                NegotiatedStream::Inbound(stream),
+            )),
+            _ => {}
+        }
+    }
+}
+
+/// Negotiates one of the given protocols and hands the stream over as is.
+pub(crate) struct RawProtocol(Vec<StreamProtocol>);
+
+impl UpgradeInfo for RawProtocol {
+    type Info = StreamProtocol;
+    type InfoIter = Vec<Self::Info>;
+
+    fn protocol_info(&self) -> Self::InfoIter {
+        self.0.clone()
+    }
+}
+
+impl OutboundUpgrade<Stream> for RawProtocol {
+    type Output = Stream;
+    type Error = ();
+    type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn upgrade_outbound(self, stream: Stream, _: Self::Info) -> Self::Future {
+        async move { Ok(stream) }.boxed()
+    }
+}
+
+impl InboundUpgrade<Stream> for RawProtocol {
+    type Output = Stream;
+    type Error = ();
+    type Future = BoxFuture<'static, Result<Self::Output, Self::Error>>;
+
+    fn upgrade_inbound(self, stream: Stream, _: Self::Info) -> Self::Future {
+        async move { Ok(stream) }.boxed()
+    }
+}
@@ -1,4 +1,5 @@
 mod get_stream;
+pub(crate) mod malicious_peer;
 
 use std::fmt::Debug;
 use std::pin::Pin;
@@ -1,5 +1,5 @@
 use std::collections::hash_map::{Keys, ValuesMut};
-use std::collections::{HashMap, HashSet};
+use std::collections::HashMap;
 use std::hash::Hash;
 use std::pin::Pin;
 use std::task::{Context, Poll};
@@ -9,15 +9,16 @@ use futures::stream::{Stream, StreamExt};
 // This is an implementation of `StreamMap` from tokio_stream. The reason we're implementing it
 // ourselves is that the implementation in tokio_stream requires that the values implement the
 // Stream trait from tokio_stream and not from futures.
+// Streams that finished are removed from the map, so that a map that gets a new stream for every
+// session doesn't grow forever.
 pub(crate) struct StreamHashMap<K: Unpin + Clone + Eq + Hash, V: Stream + Unpin> {
     map: HashMap<K, V>,
-    finished_streams: HashSet<K>,
 }
 
 impl<K: Unpin + Clone + Eq + Hash, V: Stream + Unpin> StreamHashMap<K, V> {
     #[allow(dead_code)]
     pub fn new(map: HashMap<K, V>) -> Self {
-        Self { map, finished_streams: Default::default() }
+        Self { map }
     }
 
     #[allow(dead_code)]
@@ -38,6 +39,10 @@ impl<K: Unpin + Clone + Eq + Hash, V: Stream + Unpin> StreamHashMap<K, V> {
     pub fn insert(&mut self, key: K, value: V) -> Option<V> {
         self.map.insert(key, value)
     }
+
+    pub fn remove(&mut self, key: &K) -> Option<V> {
+        self.map.remove(key)
+    }
 }
 
 impl<K: Unpin + Clone + Eq + Hash, V: Stream + Unpin> Stream for StreamHashMap<K, V> {
@@ -46,19 +51,28 @@ impl<K: Unpin + Clone + Eq + Hash, V: Stream + Unpin> Stream for StreamHashMap<K
     fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
         let unpinned_self = Pin::into_inner(self);
         let mut finished = true;
+        let mut finished_streams = Vec::new();
+        let mut result = Poll::Pending;
         for (key, stream) in &mut unpinned_self.map {
             match stream.poll_next_unpin(cx) {
                 Poll::Ready(Some(value)) => {
-                    return Poll::Ready(Some((key.clone(), value)));
+                    result = Poll::Ready(Some((key.clone(), value)));
+                    break;
                 }
                 Poll::Ready(None) => {
-                    unpinned_self.finished_streams.insert(key.clone());
+                    finished_streams.push(key.clone());
                 }
                 Poll::Pending => {
                     finished = false;
                 }
             }
         }
+        for key in finished_streams {
+            unpinned_self.map.remove(&key);
+        }
+        if result.is_ready() {
+            return result;
+        }
         if finished {
             // TODO(shahak): Make StreamHashMap not end in order to accept new inserted streams.
             return Poll::Ready(None);
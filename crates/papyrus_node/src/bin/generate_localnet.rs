@@ -0,0 +1,15 @@
+use std::env::args;
+
+use papyrus_node::localnet::run_localnet_command;
+
+/// This executable generates the config files of a local network of nodes. For the details of the
+/// generated network see papyrus_node::localnet.
+///
+/// generate_localnet --num_nodes <n> --output <dir> [--base_port <port>] [--topology star|chain]
+///     [--num_validators <n>] [--seed <seed>]
+fn main() {
+    if let Err(e) = run_localnet_command(args().collect()) {
+        println!("Failed with error: {e}");
+        std::process::exit(1);
+    }
+}
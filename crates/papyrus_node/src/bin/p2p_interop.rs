@@ -0,0 +1,259 @@
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use clap::{Arg, ArgMatches, Command};
+use futures::channel::mpsc::SendError;
+use futures::future::ready;
+use futures::SinkExt;
+use libp2p::Multiaddr;
+use papyrus_network::network_manager::NetworkManagerBuilder;
+use papyrus_network::{NetworkConfig, Protocol};
+use papyrus_node::p2p_interop::{
+    check_inbound_queries,
+    run_client_battery,
+    InteropFixtures,
+    ServerReport,
+};
+use papyrus_p2p_sync::QueryClient;
+use papyrus_protobuf::sync::{
+    DataOrFin,
+    HeaderQuery,
+    Query,
+    SignedBlockHeader,
+    StateDiffChunk,
+    StateDiffQuery,
+    TransactionQuery,
+};
+use starknet_api::core::ChainId;
+use starknet_api::state::ThinStateDiff;
+use starknet_api::transaction::{Transaction, TransactionOutput};
+use validator::Validate;
+
+// The peer to check, if it isn't given in the command line.
+const PEER_ENV_VAR: &str = "P2P_INTEROP_PEER";
+
+/// This executable checks that a peer running another Starknet p2p implementation interoperates
+/// with papyrus on the sync protocols.
+///
+/// Client: p2p_interop client --peer <multiaddr> --fixtures <path> --chain_id <chain id>
+/// Server under test: p2p_interop server [--peer <multiaddr>] --duration <seconds> --chain_id
+/// <chain id>
+///
+/// The peer can also be given in the P2P_INTEROP_PEER environment variable. The report is printed
+/// as JSON, and the executable exits with 1 if a check failed.
+#[tokio::main]
+async fn main() {
+    let matches = get_command().get_matches();
+    let result = match matches.subcommand() {
+        Some(("client", sub_matches)) => run_client(sub_matches).await,
+        Some(("server", sub_matches)) => run_server(sub_matches).await,
+        _ => unreachable!("A subcommand is required."),
+    };
+    match result {
+        Ok(true) => {}
+        Ok(false) => std::process::exit(1),
+        Err(e) => {
+            println!("Failed with error: {e}");
+            std::process::exit(1);
+        }
+    }
+}
+
+async fn run_client(matches: &ArgMatches) -> anyhow::Result<bool> {
+    let fixtures_path = matches.get_one::<PathBuf>("fixtures").expect("Failed parsing fixtures");
+    let fixtures: InteropFixtures =
+        serde_json::from_reader(BufReader::new(File::open(fixtures_path)?))?;
+    let timeout =
+        Duration::from_secs(*matches.get_one::<u64>("timeout").expect("Failed parsing timeout"));
+    let (network_config, chain_id) = get_network_config(matches, true)?;
+
+    let mut network_manager_builder = NetworkManagerBuilder::new(network_config, chain_id);
+    let header_channels = network_manager_builder
+        .register_sqmr_subscriber::<HeaderQuery, DataOrFin<SignedBlockHeader>>(
+            Protocol::SignedBlockHeader,
+        )?;
+    let state_diff_channels = network_manager_builder
+        .register_sqmr_subscriber::<StateDiffQuery, DataOrFin<ThinStateDiff>>(
+            Protocol::StateDiff,
+        )?;
+    let transaction_channels = network_manager_builder.register_sqmr_subscriber::<
+        TransactionQuery,
+        DataOrFin<(Transaction, Option<TransactionOutput>)>,
+    >(Protocol::Transaction)?;
+    let (network_manager, _network_registrations) = network_manager_builder.build()?;
+    let network_handle = tokio::spawn(network_manager.run());
+
+    let mut header_client = QueryClient::new(
+        header_channels
+            .query_sender
+            .with(|query: Query| ready(Ok::<_, SendError>(HeaderQuery(query)))),
+        header_channels.response_receiver,
+        "headers",
+        timeout,
+    );
+    let mut state_diff_client = QueryClient::new(
+        state_diff_channels
+            .query_sender
+            .with(|query: Query| ready(Ok::<_, SendError>(StateDiffQuery(query)))),
+        state_diff_channels.response_receiver,
+        "state diffs",
+        timeout,
+    );
+    let mut transaction_client = QueryClient::new(
+        transaction_channels.query_sender.with(|query: Query| {
+            ready(Ok::<_, SendError>(TransactionQuery { query, include_outputs: true }))
+        }),
+        transaction_channels.response_receiver,
+        "transactions",
+        timeout,
+    );
+    let report = run_client_battery(
+        &fixtures,
+        &mut header_client,
+        &mut state_diff_client,
+        &mut transaction_client,
+    )
+    .await;
+    network_handle.abort();
+
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(report.passed())
+}
+
+async fn run_server(matches: &ArgMatches) -> anyhow::Result<bool> {
+    let duration =
+        Duration::from_secs(*matches.get_one::<u64>("duration").expect("Failed parsing duration"));
+    let (network_config, chain_id) = get_network_config(matches, false)?;
+
+    let mut network_manager_builder = NetworkManagerBuilder::new(network_config, chain_id);
+    let header_queries = network_manager_builder
+        .register_sqmr_protocol_server::<HeaderQuery, DataOrFin<SignedBlockHeader>>(
+            Protocol::SignedBlockHeader,
+        )?;
+    let state_diff_queries = network_manager_builder
+        .register_sqmr_protocol_server::<StateDiffQuery, DataOrFin<StateDiffChunk>>(
+            Protocol::StateDiff,
+        )?;
+    let transaction_queries = network_manager_builder.register_sqmr_protocol_server::<
+        TransactionQuery,
+        DataOrFin<(Transaction, Option<TransactionOutput>)>,
+    >(Protocol::Transaction)?;
+    let (network_manager, _network_registrations) = network_manager_builder.build()?;
+    // The report is printed to stdout, so it can be parsed.
+    eprintln!("Checking the queries of the peer as {}.", network_manager.get_local_peer_id());
+    let network_handle = tokio::spawn(network_manager.run());
+
+    let (header_report, state_diff_report, transaction_report) = futures::join!(
+        check_inbound_queries(Protocol::SignedBlockHeader, header_queries, header_query, duration),
+        check_inbound_queries(Protocol::StateDiff, state_diff_queries, state_diff_query, duration),
+        check_inbound_queries(
+            Protocol::Transaction,
+            transaction_queries,
+            transaction_query,
+            duration
+        ),
+    );
+    network_handle.abort();
+
+    let report =
+        ServerReport { protocols: vec![header_report, state_diff_report, transaction_report] };
+    println!("{}", serde_json::to_string_pretty(&report)?);
+    Ok(report.passed())
+}
+
+fn header_query(query: &HeaderQuery) -> &Query {
+    &query.0
+}
+
+fn state_diff_query(query: &StateDiffQuery) -> &Query {
+    &query.0
+}
+
+fn transaction_query(query: &TransactionQuery) -> &Query {
+    &query.query
+}
+
+// In the server under test mode the peer is optional, since the peer can connect to the harness.
+fn get_network_config(
+    matches: &ArgMatches,
+    peer_required: bool,
+) -> anyhow::Result<(NetworkConfig, ChainId)> {
+    let peer =
+        matches.get_one::<String>("peer").cloned().or_else(|| std::env::var(PEER_ENV_VAR).ok());
+    if peer_required && peer.is_none() {
+        return Err(anyhow!("The peer should be given with --peer or {PEER_ENV_VAR}."));
+    }
+    let mut network_config = NetworkConfig {
+        bootstrap_peer_multiaddr: peer.as_deref().map(Multiaddr::from_str).transpose()?,
+        ..Default::default()
+    };
+    if let Some(tcp_port) = matches.get_one::<u16>("tcp_port") {
+        network_config.tcp_port = *tcp_port;
+    }
+    network_config.validate()?;
+    let chain_id = ChainId::from(
+        matches.get_one::<String>("chain_id").expect("Failed parsing chain_id").clone(),
+    );
+    Ok((network_config, chain_id))
+}
+
+fn get_command() -> Command {
+    let network_args = [
+        Arg::new("peer").long("peer").help(
+            "The multiaddr of the peer, e.g. /ip4/127.0.0.1/tcp/10000/p2p/<peer id>. Defaults to \
+             the P2P_INTEROP_PEER environment variable.",
+        ),
+        Arg::new("chain_id")
+            .short('c')
+            .long("chain_id")
+            .required(true)
+            .help("The chain id of the peer, e.g. SN_MAIN or SN_SEPOLIA."),
+        Arg::new("tcp_port")
+            .long("tcp_port")
+            .value_parser(clap::value_parser!(u16))
+            .help("The TCP port the harness listens on."),
+    ];
+    Command::new("P2P interop")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("client")
+                .about(
+                    "Sends a battery of queries to the peer and checks the responses against the \
+                     fixtures.",
+                )
+                .args(network_args.clone())
+                .arg(
+                    Arg::new("fixtures")
+                        .long("fixtures")
+                        .required(true)
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .help("A JSON file with the expected values of the peer's chain."),
+                )
+                .arg(
+                    Arg::new("timeout")
+                        .long("timeout")
+                        .default_value("30")
+                        .value_parser(clap::value_parser!(u64))
+                        .help("The time in seconds to wait for each response."),
+                ),
+        )
+        .subcommand(
+            Command::new("server")
+                .about(
+                    "Answers the queries of the peer with a Fin and checks that they conform to \
+                     the spec.",
+                )
+                .args(network_args)
+                .arg(
+                    Arg::new("duration")
+                        .long("duration")
+                        .default_value("60")
+                        .value_parser(clap::value_parser!(u64))
+                        .help("The time in seconds to check the queries of the peer for."),
+                ),
+        )
+}
@@ -0,0 +1,165 @@
+use std::path::{Path, PathBuf};
+
+use clap::{Arg, ArgAction, ArgMatches, Command};
+use papyrus_base_layer::ethereum_base_layer_contract::EthereumBaseLayerConfig;
+use papyrus_p2p_sync::snapshot::{export_blocks, import_blocks, verify_snapshot, SnapshotError};
+use papyrus_storage::{open_storage, open_storage_read_only, StorageConfig};
+use papyrus_sync::sources::base_layer::EthereumBaseLayerSource;
+use starknet_api::block::BlockNumber;
+use starknet_api::core::ChainId;
+
+/// This executable exports the synced blocks of a storage into a snapshot directory, or imports
+/// such a snapshot into another storage for a fast bootstrap.
+///
+/// Export: storage_snapshot export --up-to <block> --out <dir>
+/// Import: storage_snapshot import --in <dir> --base_layer_url <url> [--force-from-marker]
+///
+/// Before the import, the snapshot's block at the height of the latest block proved on the base
+/// layer is compared with it. A snapshot that doesn't match is refused, unless --trust-snapshot is
+/// passed, in which case the base layer isn't read at all.
+#[tokio::main]
+async fn main() {
+    let matches = get_command().get_matches();
+    let result = match matches.subcommand() {
+        Some(("export", sub_matches)) => {
+            let storage_config = get_storage_config(sub_matches);
+            let up_to =
+                BlockNumber(*sub_matches.get_one::<u64>("up-to").expect("Failed parsing up-to"));
+            let out_dir = sub_matches.get_one::<PathBuf>("out").expect("Failed parsing out");
+            open_storage_read_only(storage_config)
+                .map_err(Into::into)
+                .and_then(|storage_reader| export_blocks(&storage_reader, up_to, out_dir))
+                .map(|()| format!("Exported blocks up to {up_to} to {}.", out_dir.display()))
+        }
+        Some(("import", sub_matches)) => {
+            let storage_config = get_storage_config(sub_matches);
+            let in_dir = sub_matches.get_one::<PathBuf>("in").expect("Failed parsing in");
+            let force_from_marker = sub_matches.get_flag("force-from-marker");
+            let verification = if sub_matches.get_flag("trust-snapshot") {
+                Ok(())
+            } else {
+                verify_snapshot_against_base_layer(sub_matches, in_dir).await
+            };
+            verification
+                .and_then(|()| open_storage(storage_config).map_err(Into::into))
+                .and_then(|(_, mut storage_writer)| {
+                    import_blocks(&mut storage_writer, in_dir, force_from_marker)
+                })
+                .map(|next_block| format!("Imported blocks up to {next_block}."))
+        }
+        _ => unreachable!("A subcommand is required."),
+    };
+    match result {
+        Ok(message) => println!("{message}"),
+        Err(e) => {
+            println!("Failed with error: {e}");
+            std::process::exit(1);
+        }
+    }
+}
+
+fn get_command() -> Command {
+    let storage_args = [
+        Arg::new("path_prefix")
+            .short('p')
+            .long("path_prefix")
+            .required(true)
+            .value_parser(clap::value_parser!(PathBuf))
+            .help("The path prefix of the storage's database files."),
+        Arg::new("chain_id")
+            .short('c')
+            .long("chain_id")
+            .required(true)
+            .help("The chain id of the storage, e.g. SN_MAIN or SN_SEPOLIA."),
+    ];
+    Command::new("Storage snapshot")
+        .subcommand_required(true)
+        .subcommand(
+            Command::new("export")
+                .about("Exports the blocks below up-to into a snapshot directory.")
+                .args(storage_args.clone())
+                .arg(
+                    Arg::new("up-to")
+                        .long("up-to")
+                        .required(true)
+                        .value_parser(clap::value_parser!(u64))
+                        .help("The block number to export up to (exclusive)."),
+                )
+                .arg(
+                    Arg::new("out")
+                        .long("out")
+                        .required(true)
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .help("The directory to write the snapshot to."),
+                ),
+        )
+        .subcommand(
+            Command::new("import")
+                .about("Imports a snapshot directory into the storage.")
+                .args(storage_args)
+                .arg(
+                    Arg::new("in")
+                        .long("in")
+                        .required(true)
+                        .value_parser(clap::value_parser!(PathBuf))
+                        .help("The directory to read the snapshot from."),
+                )
+                .arg(
+                    Arg::new("base_layer_url")
+                        .long("base_layer_url")
+                        .required_unless_present("trust-snapshot")
+                        .help("The URL of the Ethereum node to read the Starknet contract from."),
+                )
+                .arg(Arg::new("starknet_contract_address").long("starknet_contract_address").help(
+                    "The address of the Starknet contract on Ethereum. Defaults to its address on \
+                     Mainnet.",
+                ))
+                .arg(
+                    Arg::new("trust-snapshot")
+                        .long("trust-snapshot")
+                        .action(ArgAction::SetTrue)
+                        .help(
+                            "Import the snapshot without comparing it with the latest block \
+                             proved on the base layer.",
+                        ),
+                )
+                .arg(
+                    Arg::new("force-from-marker")
+                        .long("force-from-marker")
+                        .action(ArgAction::SetTrue)
+                        .help(
+                            "Import into a non-empty storage, skipping the blocks that are \
+                             already in it.",
+                        ),
+                ),
+        )
+}
+
+fn get_storage_config(matches: &ArgMatches) -> StorageConfig {
+    let mut storage_config = StorageConfig::default();
+    storage_config.db_config.path_prefix =
+        matches.get_one::<PathBuf>("path_prefix").expect("Failed parsing path_prefix").clone();
+    storage_config.db_config.chain_id = ChainId::Other(
+        matches.get_one::<String>("chain_id").expect("Failed parsing chain_id").clone(),
+    );
+    storage_config
+}
+
+async fn verify_snapshot_against_base_layer(
+    matches: &ArgMatches,
+    in_dir: &Path,
+) -> Result<(), SnapshotError> {
+    let mut base_layer_config = EthereumBaseLayerConfig {
+        node_url: matches
+            .get_one::<String>("base_layer_url")
+            .expect("Failed parsing base_layer_url")
+            .clone(),
+        ..Default::default()
+    };
+    if let Some(address) = matches.get_one::<String>("starknet_contract_address") {
+        base_layer_config.starknet_contract_address = address.clone();
+    }
+    let base_layer_source = EthereumBaseLayerSource::new(base_layer_config)
+        .map_err(|err| SnapshotError::BaseLayerError(err.to_string()))?;
+    verify_snapshot(in_dir, &base_layer_source).await.map(|_| ())
+}
@@ -0,0 +1,123 @@
+//! The components the node runs. Each component is compiled into the node only with its cargo
+//! feature, and runs only if it's also enabled in the config. The central sync and the p2p sync
+//! are enabled by their own sub configs (`sync.#is_none` and `p2p_sync.#is_none`), and the rest of
+//! the components by the params here.
+
+use std::collections::BTreeMap;
+
+use papyrus_config::dumping::{ser_param, SerializeConfig};
+use papyrus_config::{ParamPath, ParamPrivacyInput, SerializedParam};
+use serde::{Deserialize, Serialize};
+use validator::ValidationError;
+
+use super::NodeConfig;
+
+/// Enables the components of the node that don't have a sub config that can be turned off.
+#[derive(Clone, Copy, Debug, Deserialize, Serialize, PartialEq, Eq)]
+pub struct ComponentsConfig {
+    pub rpc: bool,
+    pub monitoring_gateway: bool,
+    pub p2p_server: bool,
+    pub consensus: bool,
+}
+
+impl Default for ComponentsConfig {
+    fn default() -> Self {
+        Self { rpc: true, monitoring_gateway: true, p2p_server: true, consensus: true }
+    }
+}
+
+impl SerializeConfig for ComponentsConfig {
+    fn dump(&self) -> BTreeMap<ParamPath, SerializedParam> {
+        BTreeMap::from_iter([
+            ser_param(
+                "rpc",
+                &self.rpc,
+                "Run the JSON-RPC server. Requires the rpc feature.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "monitoring_gateway",
+                &self.monitoring_gateway,
+                "Run the monitoring gateway and its admin server. Requires the monitoring feature.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "p2p_server",
+                &self.p2p_server,
+                "Serve the node's blocks to its peers, if the p2p network is enabled. Requires the \
+                 p2p_server feature.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "consensus",
+                &self.consensus,
+                "Run consensus, if the p2p network is enabled. Requires the consensus feature.",
+                ParamPrivacyInput::Public,
+            ),
+        ])
+    }
+}
+
+impl ComponentsConfig {
+    /// Enables exactly the components that are compiled into the node.
+    pub fn compiled_in() -> Self {
+        Self {
+            rpc: cfg!(feature = "rpc"),
+            monitoring_gateway: cfg!(feature = "monitoring"),
+            p2p_server: cfg!(feature = "p2p_server"),
+            consensus: cfg!(feature = "consensus"),
+        }
+    }
+}
+
+// Rejects a config that enables a component that isn't compiled into the node, rather than running
+// the node without it.
+pub(super) fn validate_compiled_components(config: &NodeConfig) -> Result<(), ValidationError> {
+    let network_enabled = config.network.is_some();
+    // The param that enables each component, whether it's enabled and the feature it requires.
+    let components = [
+        ("components.rpc", config.components.rpc, "rpc", cfg!(feature = "rpc")),
+        (
+            "components.monitoring_gateway",
+            config.components.monitoring_gateway,
+            "monitoring",
+            cfg!(feature = "monitoring"),
+        ),
+        (
+            "components.p2p_server",
+            network_enabled && config.components.p2p_server,
+            "p2p_server",
+            cfg!(feature = "p2p_server"),
+        ),
+        (
+            "components.consensus",
+            network_enabled && config.components.consensus,
+            "consensus",
+            cfg!(feature = "consensus"),
+        ),
+        ("sync", config.sync.is_some(), "central_sync", cfg!(feature = "central_sync")),
+        ("p2p_sync", config.p2p_sync.is_some(), "p2p_sync", cfg!(feature = "p2p_sync")),
+        // The network is used only by the p2p components.
+        (
+            "network",
+            network_enabled,
+            "p2p_sync, p2p_server or consensus",
+            cfg!(any(feature = "p2p_sync", feature = "p2p_server", feature = "consensus")),
+        ),
+    ];
+    for (param, is_enabled, feature, is_compiled_in) in components {
+        if is_enabled && !is_compiled_in {
+            let mut error = ValidationError::new("component isn't compiled in");
+            error.message = Some(
+                format!(
+                    "{param} is enabled, but the node was built without the {feature} feature. \
+                     Disable it or build the node with the feature."
+                )
+                .into(),
+            );
+            return Err(error);
+        }
+    }
+    Ok(())
+}
@@ -6,6 +6,7 @@ use std::io::{BufWriter, Read, Write};
 use std::ops::IndexMut;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use std::time::Duration;
 
 use assert_json_diff::assert_json_eq;
 use colored::Colorize;
@@ -13,8 +14,12 @@ use itertools::Itertools;
 use papyrus_base_layer::ethereum_base_layer_contract::EthereumBaseLayerConfig;
 use papyrus_config::dumping::SerializeConfig;
 use papyrus_config::presentation::get_config_presentation;
+use papyrus_config::validators::config_validate;
 use papyrus_config::{SerializationType, SerializedContent, SerializedParam};
 use papyrus_monitoring_gateway::MonitoringGatewayConfig;
+use papyrus_network::NetworkConfig;
+use papyrus_p2p_sync::P2PSyncConfig;
+use papyrus_sync::SyncConfig;
 use pretty_assertions::assert_eq;
 use serde_json::{json, Map, Value};
 use starknet_api::core::ChainId;
@@ -22,9 +27,11 @@ use tempfile::NamedTempFile;
 use test_utils::get_absolute_path;
 use validator::Validate;
 
+use crate::config::components::ComponentsConfig;
 #[cfg(feature = "rpc")]
 use crate::config::pointers::CONFIG_POINTERS;
-use crate::config::{node_command, NodeConfig, DEFAULT_CONFIG_PATH};
+use crate::config::presets::{chain_consistency_warnings, ChainPreset};
+use crate::config::{deprecated_param_warnings, node_command, NodeConfig, DEFAULT_CONFIG_PATH};
 
 // Returns the required and generated params in default_config.json with the default value from the
 // config presentation.
@@ -53,7 +60,7 @@ fn required_args() -> Vec<String> {
     args
 }
 
-fn get_args(additional_args: Vec<&str>) -> Vec<String> {
+pub(crate) fn get_args(additional_args: Vec<&str>) -> Vec<String> {
     let mut args = vec!["Papyrus".to_owned()];
     args.append(&mut required_args());
     args.append(&mut additional_args.into_iter().map(|s| s.to_owned()).collect());
@@ -80,6 +87,146 @@ fn load_http_headers() {
 
 // insta doesn't work well with features, so if the output between two features are different we
 // can only test one of them. We chose to test rpc over testing not(rpc).
+#[test]
+fn read_only_storage_config_validation() {
+    let mut config =
+        NodeConfig { components: ComponentsConfig::compiled_in(), ..Default::default() };
+    config.storage.db_config.path_prefix = PathBuf::from(".");
+    config.storage.read_only = true;
+    // The default config syncs from the central source.
+    assert!(config.validate().is_err());
+
+    config.sync = None;
+    config.validate().unwrap();
+
+    config.p2p_sync = Some(P2PSyncConfig::default());
+    assert!(config.validate().is_err());
+    config.p2p_sync = None;
+
+    config.monitoring_gateway.admin_server_address = Some("0.0.0.0:8082".to_owned());
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn authoritative_consensus_is_the_only_storage_writer() {
+    let mut config = NodeConfig {
+        sync: None,
+        components: ComponentsConfig::compiled_in(),
+        ..Default::default()
+    };
+    config.storage.db_config.path_prefix = PathBuf::from(".");
+    config.consensus.authoritative = true;
+    config.validate().unwrap();
+
+    config.storage.read_only = true;
+    assert!(config.validate().is_err());
+    config.storage.read_only = false;
+
+    config.sync = Some(SyncConfig::default());
+    assert!(config.validate().is_err());
+    config.sync = None;
+
+    config.monitoring_gateway.admin_server_address = Some("0.0.0.0:8082".to_owned());
+    assert!(config.validate().is_err());
+}
+
+#[cfg(feature = "p2p_sync")]
+#[test]
+fn p2p_sync_record_and_replay_are_exclusive() {
+    let mut config = NodeConfig {
+        sync: None,
+        components: ComponentsConfig::compiled_in(),
+        ..Default::default()
+    };
+    config.storage.db_config.path_prefix = PathBuf::from(".");
+    config.p2p_sync =
+        Some(P2PSyncConfig { record_path: Some(PathBuf::from("./record")), ..Default::default() });
+    config.validate().unwrap();
+
+    config.p2p_sync.as_mut().unwrap().replay_path = Some(PathBuf::from("./record"));
+    assert!(config.validate().is_err());
+}
+
+#[cfg(feature = "p2p_sync")]
+#[test]
+fn combined_block_download_is_not_recorded_or_replayed() {
+    let mut config = NodeConfig {
+        sync: None,
+        components: ComponentsConfig::compiled_in(),
+        ..Default::default()
+    };
+    config.storage.db_config.path_prefix = PathBuf::from(".");
+    config.p2p_sync = Some(P2PSyncConfig { combined_block_download: true, ..Default::default() });
+    config.validate().unwrap();
+
+    config.p2p_sync.as_mut().unwrap().record_path = Some(PathBuf::from("./record"));
+    assert!(config.validate().is_err());
+    config.p2p_sync.as_mut().unwrap().record_path = None;
+
+    config.p2p_sync.as_mut().unwrap().replay_path = Some(PathBuf::from("./record"));
+    assert!(config.validate().is_err());
+}
+
+#[cfg(all(feature = "p2p_sync", feature = "central_sync"))]
+#[test]
+fn p2p_sync_runs_with_the_central_sync_only_in_shadow_mode() {
+    let mut config =
+        NodeConfig { components: ComponentsConfig::compiled_in(), ..Default::default() };
+    config.storage.db_config.path_prefix = PathBuf::from(".");
+    config.p2p_sync = Some(P2PSyncConfig::default());
+    assert!(config.validate().is_err());
+
+    config.p2p_sync.as_mut().unwrap().shadow = true;
+    config.validate().unwrap();
+
+    config.p2p_sync.as_mut().unwrap().record_path = Some(PathBuf::from("./record"));
+    assert!(config.validate().is_err());
+    config.p2p_sync.as_mut().unwrap().record_path = None;
+
+    config.sync = None;
+    assert!(config.validate().is_err());
+}
+
+#[test]
+fn components_that_are_not_compiled_in_are_rejected() {
+    let mut minimal_config = NodeConfig {
+        sync: None,
+        components: ComponentsConfig::compiled_in(),
+        ..Default::default()
+    };
+    minimal_config.storage.db_config.path_prefix = PathBuf::from(".");
+    minimal_config.validate().unwrap();
+
+    // The feature of each component and how it's enabled.
+    let components: [(&str, bool, fn(&mut NodeConfig)); 4] = [
+        ("rpc", cfg!(feature = "rpc"), |config| config.components.rpc = true),
+        ("monitoring", cfg!(feature = "monitoring"), |config| {
+            config.components.monitoring_gateway = true
+        }),
+        ("central_sync", cfg!(feature = "central_sync"), |config| {
+            config.sync = Some(SyncConfig::default())
+        }),
+        ("p2p_sync", cfg!(feature = "p2p_sync"), |config| {
+            config.network = Some(NetworkConfig::default());
+            config.p2p_sync = Some(P2PSyncConfig::default());
+        }),
+    ];
+    for (feature, is_compiled_in, enable_component) in components {
+        let mut config = minimal_config.clone();
+        enable_component(&mut config);
+        let result = config_validate(&config);
+        if is_compiled_in {
+            result.unwrap();
+        } else {
+            let error = result.unwrap_err().to_string();
+            assert!(
+                error.contains(&format!("the node was built without the {feature} feature")),
+                "Unexpected error: {error}"
+            );
+        }
+    }
+}
+
 #[cfg(feature = "rpc")]
 #[test]
 // Regression test which checks that the default config dumping hasn't changed.
@@ -116,6 +263,77 @@ fn test_update_dumped_config_by_command() {
     assert_eq!(config.storage.db_config.path_prefix.to_str(), Some("/abc"));
 }
 
+#[test]
+fn deprecated_base_layer_sleep_duration_sets_the_poll_interval() {
+    env::set_current_dir(get_absolute_path("")).expect("Couldn't set working dir.");
+    let config = NodeConfig::load_and_process(get_args(vec![])).unwrap();
+    assert!(deprecated_param_warnings(&config).is_empty());
+
+    let config = NodeConfig::load_and_process(get_args(vec![
+        "--sync.base_layer_propagation_sleep_duration",
+        "30",
+    ]))
+    .unwrap();
+
+    assert_eq!(config.base_layer.poll_interval, Duration::from_secs(30));
+    assert_eq!(deprecated_param_warnings(&config).len(), 1);
+}
+
+#[test]
+fn chain_preset_sets_default_values() {
+    env::set_current_dir(get_absolute_path("")).expect("Couldn't set working dir.");
+    let config = NodeConfig::load_and_process(get_args(vec!["--chain", "sepolia"])).unwrap();
+
+    let values = ChainPreset::Sepolia.values().unwrap();
+    assert_eq!(config.chain, ChainPreset::Sepolia);
+    assert_eq!(config.storage.db_config.chain_id, ChainId::Sepolia);
+    assert_eq!(config.central.url, values.starknet_url);
+    assert_eq!(config.base_layer.starknet_contract_address, values.base_layer_contract_address);
+    assert!(chain_consistency_warnings(&config).is_empty());
+}
+
+#[test]
+fn explicit_values_override_chain_preset() {
+    env::set_current_dir(get_absolute_path("")).expect("Couldn't set working dir.");
+    let starknet_url = "https://my-feeder-gateway.io/";
+    let config = NodeConfig::load_and_process(get_args(vec![
+        "--chain",
+        "sepolia",
+        "--starknet_url",
+        starknet_url,
+    ]))
+    .unwrap();
+
+    assert_eq!(config.storage.db_config.chain_id, ChainId::Sepolia);
+    assert_eq!(config.central.url, starknet_url);
+}
+
+#[test]
+fn chain_preset_files_match_presets() {
+    env::set_current_dir(get_absolute_path("")).expect("Couldn't set working dir.");
+    for (preset_file, chain) in [
+        ("config/presets/mainnet.json", "mainnet"),
+        ("config/presets/sepolia_testnet.json", "sepolia"),
+        ("config/presets/sepolia_integration.json", "sepolia-integration"),
+    ] {
+        let from_file =
+            NodeConfig::load_and_process(get_args(vec!["--config_file", preset_file])).unwrap();
+        let from_chain = NodeConfig::load_and_process(get_args(vec!["--chain", chain])).unwrap();
+        assert_eq!(from_file, from_chain);
+    }
+}
+
+#[test]
+fn custom_chain_id_with_preset_values_warns() {
+    let mut config = NodeConfig::default();
+    assert!(chain_consistency_warnings(&config).is_empty());
+
+    config.chain = ChainPreset::Custom;
+    config.storage.db_config.chain_id = ChainId::Other("SN_MY_CHAIN".to_owned());
+    // The Starknet URL and the base layer contract address are still the ones of mainnet.
+    assert_eq!(chain_consistency_warnings(&config).len(), 2);
+}
+
 #[cfg(feature = "rpc")]
 #[test]
 fn default_config_file_is_up_to_date() {
@@ -1,7 +1,10 @@
+pub mod components;
 #[cfg(test)]
 mod config_test;
 #[cfg(feature = "rpc")]
 pub mod pointers;
+pub mod presets;
+pub mod reload;
 
 use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
@@ -23,8 +26,9 @@ use papyrus_config::dumping::{
     ser_pointer_target_param,
     SerializeConfig,
 };
-use papyrus_config::loading::load_and_process_config;
+use papyrus_config::loading::load_and_process_config_from_map;
 use papyrus_config::{ConfigError, ParamPath, ParamPrivacyInput, SerializedParam};
+use papyrus_consensus::config::ConsensusConfig;
 use papyrus_monitoring_gateway::MonitoringGatewayConfig;
 use papyrus_network::NetworkConfig;
 use papyrus_p2p_sync::{P2PSync, P2PSyncConfig};
@@ -36,10 +40,17 @@ use papyrus_sync::sources::central::CentralSourceConfig;
 use papyrus_sync::SyncConfig;
 use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value};
+use starknet_api::block::BlockHash;
 use starknet_api::core::ChainId;
 use starknet_client::RetryConfig;
-use validator::Validate;
+use validator::{Validate, ValidationError};
 
+use crate::config::components::{validate_compiled_components, ComponentsConfig};
+use crate::config::presets::ChainPreset;
+use crate::genesis::GenesisStateConfig;
+use crate::logging::LoggingConfig;
+use crate::storage_scan::StorageScanConfig;
+use crate::supervisor::SupervisorConfig;
 use crate::version::VERSION_FULL;
 
 // The path of the default configuration file, provided as part of the crate.
@@ -47,40 +58,62 @@ pub const DEFAULT_CONFIG_PATH: &str = "config/default_config.json";
 
 /// The configurations of the various components of the node.
 #[derive(Debug, Deserialize, Serialize, Clone, PartialEq, Validate)]
+#[validate(schema(function = "validate_node_config"))]
 pub struct NodeConfig {
+    /// The chain whose preset values are the defaults of the chain related params.
+    pub chain: ChainPreset,
+    /// The parent hash of the first block of the chain.
+    pub genesis_hash: BlockHash,
+    /// None if the chain starts from an empty state.
+    pub genesis_state: Option<GenesisStateConfig>,
+    pub components: ComponentsConfig,
     #[cfg(feature = "rpc")]
     #[validate]
     pub rpc: RpcConfig,
     pub central: CentralSourceConfig,
     pub base_layer: EthereumBaseLayerConfig,
+    #[validate]
     pub monitoring_gateway: MonitoringGatewayConfig,
     #[validate]
     pub storage: StorageConfig,
+    #[validate]
+    pub storage_scan: StorageScanConfig,
     /// None if the syncing should be disabled.
     pub sync: Option<SyncConfig>,
-    /// One of p2p_sync or sync must be None.
+    /// One of p2p_sync or sync must be None, unless p2p_sync runs in shadow mode.
     /// If P2P sync is active, then network must be active too.
     // TODO(yair): Change NodeConfig to have an option of enum of SyncConfig or P2PSyncConfig.
     pub p2p_sync: Option<P2PSyncConfig>,
     // TODO(shahak): Make network non-optional once it's developed enough.
     pub network: Option<NetworkConfig>,
+    pub consensus: ConsensusConfig,
+    pub supervisor: SupervisorConfig,
     pub collect_profiling_metrics: bool,
+    pub logging: LoggingConfig,
 }
 
 // Default configuration values.
 impl Default for NodeConfig {
     fn default() -> Self {
         NodeConfig {
+            chain: ChainPreset::Mainnet,
+            genesis_hash: BlockHash::default(),
+            genesis_state: None,
+            components: ComponentsConfig::default(),
             central: CentralSourceConfig::default(),
             base_layer: EthereumBaseLayerConfig::default(),
             #[cfg(feature = "rpc")]
             rpc: RpcConfig::default(),
             monitoring_gateway: MonitoringGatewayConfig::default(),
             storage: StorageConfig::default(),
+            storage_scan: StorageScanConfig::default(),
             sync: Some(SyncConfig::default()),
             p2p_sync: None,
             network: None,
+            consensus: ConsensusConfig::default(),
+            supervisor: SupervisorConfig::default(),
             collect_profiling_metrics: false,
+            logging: LoggingConfig::default(),
         }
     }
 }
@@ -89,20 +122,44 @@ impl SerializeConfig for NodeConfig {
     fn dump(&self) -> BTreeMap<ParamPath, SerializedParam> {
         #[allow(unused_mut)]
         let mut sub_configs = vec![
+            append_sub_config_name(self.components.dump(), "components"),
             append_sub_config_name(self.central.dump(), "central"),
             append_sub_config_name(self.central.dump(), "central"),
             append_sub_config_name(self.base_layer.dump(), "base_layer"),
             append_sub_config_name(self.monitoring_gateway.dump(), "monitoring_gateway"),
             append_sub_config_name(self.storage.dump(), "storage"),
+            append_sub_config_name(self.storage_scan.dump(), "storage_scan"),
+            ser_optional_sub_config(&self.genesis_state, "genesis_state"),
             ser_optional_sub_config(&self.sync, "sync"),
             ser_optional_sub_config(&self.p2p_sync, "p2p_sync"),
             ser_optional_sub_config(&self.network, "network"),
-            BTreeMap::from_iter([ser_param(
-                "collect_profiling_metrics",
-                &self.collect_profiling_metrics,
-                "If true, collect profiling metrics for the node.",
-                ParamPrivacyInput::Public,
-            )]),
+            append_sub_config_name(self.consensus.dump(), "consensus"),
+            append_sub_config_name(self.supervisor.dump(), "supervisor"),
+            append_sub_config_name(self.logging.dump(), "logging"),
+            BTreeMap::from_iter([
+                ser_param(
+                    "chain",
+                    &self.chain,
+                    "The chain to follow, one of mainnet, sepolia, sepolia-integration or custom. \
+                     Except for custom, the values of the chain are used as the defaults of \
+                     chain_id, starknet_url, base_layer.starknet_contract_address, \
+                     network.bootstrap_peer_multiaddr and genesis_hash. Values that are set \
+                     explicitly take precedence.",
+                    ParamPrivacyInput::Public,
+                ),
+                ser_param(
+                    "genesis_hash",
+                    &self.genesis_hash,
+                    "The parent hash of the first block of the chain.",
+                    ParamPrivacyInput::Public,
+                ),
+                ser_param(
+                    "collect_profiling_metrics",
+                    &self.collect_profiling_metrics,
+                    "If true, collect profiling metrics for the node.",
+                    ParamPrivacyInput::Public,
+                ),
+            ]),
         ];
         #[cfg(feature = "rpc")]
         sub_configs.push(append_sub_config_name(self.rpc.dump(), "rpc"));
@@ -111,13 +168,117 @@ impl SerializeConfig for NodeConfig {
     }
 }
 
+fn validate_node_config(config: &NodeConfig) -> Result<(), ValidationError> {
+    validate_compiled_components(config)?;
+    // A node with a read-only storage can't run any of the components that write to the storage.
+    if config.storage.read_only
+        && (config.sync.is_some()
+            || config.p2p_sync.is_some()
+            || config.monitoring_gateway.admin_server_address.is_some())
+    {
+        return Err(ValidationError::new(
+            "sync, p2p_sync and monitoring_gateway.admin_server_address must be disabled when \
+             storage.read_only is set",
+        ));
+    }
+    // Consensus is the only writer of the storage when it's authoritative.
+    if config.consensus.authoritative
+        && (config.storage.read_only
+            || config.sync.is_some()
+            || config.p2p_sync.is_some()
+            || config.monitoring_gateway.admin_server_address.is_some())
+    {
+        return Err(ValidationError::new(
+            "storage.read_only, sync, p2p_sync and monitoring_gateway.admin_server_address must \
+             be disabled when consensus.authoritative is set",
+        ));
+    }
+    if config.p2p_sync.as_ref().is_some_and(|p2p_sync_config| {
+        p2p_sync_config.record_path.is_some() && p2p_sync_config.replay_path.is_some()
+    }) {
+        return Err(ValidationError::new(
+            "p2p_sync.record_path and p2p_sync.replay_path can't be set together, since a replay \
+             doesn't receive responses to record",
+        ));
+    }
+    // The recording, the replay and the shadow sync know only the headers and state diffs.
+    if config.p2p_sync.as_ref().is_some_and(|p2p_sync_config| {
+        p2p_sync_config.combined_block_download
+            && (p2p_sync_config.shadow
+                || p2p_sync_config.record_path.is_some()
+                || p2p_sync_config.replay_path.is_some())
+    }) {
+        return Err(ValidationError::new(
+            "p2p_sync.combined_block_download can't be set with p2p_sync.shadow, \
+             p2p_sync.record_path or p2p_sync.replay_path",
+        ));
+    }
+    // Only the central sync writes to the storage when the two syncs run together. The shadow
+    // sync uses the recorded responses for telling which peer sent each response.
+    match (&config.sync, &config.p2p_sync) {
+        (Some(_), Some(p2p_sync_config)) if !p2p_sync_config.shadow => Err(ValidationError::new(
+            "sync and p2p_sync can't be enabled together, unless p2p_sync.shadow is set",
+        )),
+        (None, Some(p2p_sync_config)) if p2p_sync_config.shadow => Err(ValidationError::new(
+            "p2p_sync.shadow requires sync, since the shadow sync compares the blocks the central \
+             sync writes",
+        )),
+        (_, Some(p2p_sync_config))
+            if p2p_sync_config.shadow
+                && (p2p_sync_config.record_path.is_some()
+                    || p2p_sync_config.replay_path.is_some()) =>
+        {
+            Err(ValidationError::new(
+                "p2p_sync.record_path and p2p_sync.replay_path can't be set with p2p_sync.shadow",
+            ))
+        }
+        _ => Ok(()),
+    }
+}
+
 impl NodeConfig {
     /// Creates a config object. Selects the values from the default file and from resources with
     /// higher priority.
     pub fn load_and_process(args: Vec<String>) -> Result<Self, ConfigError> {
         let default_config_file = std::fs::File::open(Path::new(DEFAULT_CONFIG_PATH))?;
-        load_and_process_config(default_config_file, node_command(), args)
+        let mut default_config: Map<String, Value> = serde_json::from_reader(default_config_file)?;
+        // The chain can be set by any of the config sources, so it's resolved by loading the
+        // config once. Its preset values then replace the default values, so that the values
+        // from the other sources still take precedence over them.
+        let chain = load_and_process_config_from_map::<Self>(
+            default_config.clone(),
+            node_command(),
+            args.clone(),
+        )?
+        .chain;
+        chain.apply_to_default_config(&mut default_config);
+        let mut config: Self =
+            load_and_process_config_from_map(default_config, node_command(), args)?;
+        // The deprecated param is an alias of the base layer poll interval.
+        if let Some(sync_config) = &config.sync {
+            if !sync_config.base_layer_propagation_sleep_duration.is_zero() {
+                config.base_layer.poll_interval = sync_config.base_layer_propagation_sleep_duration;
+            }
+        }
+        Ok(config)
+    }
+}
+
+/// Returns a warning for each deprecated param that is set.
+pub fn deprecated_param_warnings(config: &NodeConfig) -> Vec<String> {
+    let mut warnings = Vec::new();
+    if config
+        .sync
+        .as_ref()
+        .is_some_and(|sync_config| !sync_config.base_layer_propagation_sleep_duration.is_zero())
+    {
+        warnings.push(
+            "sync.base_layer_propagation_sleep_duration is deprecated and replaces \
+             base_layer.poll_interval. Use base_layer.poll_interval instead."
+                .to_owned(),
+        );
     }
+    warnings
 }
 
 /// The command line interface of this node.
@@ -0,0 +1,137 @@
+//! Built-in config values of the chains the node can follow, selected by the `chain` param.
+
+use serde::{Deserialize, Serialize};
+use serde_json::{json, Map, Value};
+
+use super::NodeConfig;
+
+/// A chain whose values are used as the defaults of the chain related params.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "kebab-case")]
+pub enum ChainPreset {
+    #[default]
+    Mainnet,
+    Sepolia,
+    SepoliaIntegration,
+    /// No values are filled in. The chain related params are taken from the config as is.
+    Custom,
+}
+
+/// The values a chain preset fills in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ChainPresetValues {
+    pub chain_id: &'static str,
+    pub starknet_url: &'static str,
+    pub base_layer_contract_address: &'static str,
+    /// None if the chain has no public bootstrap peer.
+    pub bootstrap_peer_multiaddr: Option<&'static str>,
+    pub genesis_hash: &'static str,
+}
+
+const MAINNET: ChainPresetValues = ChainPresetValues {
+    chain_id: "SN_MAIN",
+    starknet_url: "https://alpha-mainnet.starknet.io/",
+    base_layer_contract_address: "0xc662c410C0ECf747543f5bA90660f6ABeBD9C8c4",
+    bootstrap_peer_multiaddr: None,
+    genesis_hash: "0x0",
+};
+
+const SEPOLIA: ChainPresetValues = ChainPresetValues {
+    chain_id: "SN_SEPOLIA",
+    starknet_url: "https://alpha-sepolia.starknet.io/",
+    base_layer_contract_address: "0xe2bb56ee936fd6433dc0f6e7e3b8365c906aa057",
+    bootstrap_peer_multiaddr: None,
+    genesis_hash: "0x0",
+};
+
+const SEPOLIA_INTEGRATION: ChainPresetValues = ChainPresetValues {
+    chain_id: "SN_INTEGRATION_SEPOLIA",
+    starknet_url: "https://integration-sepolia.starknet.io/",
+    base_layer_contract_address: "0x4737c0c1b4d5b1a687b42610ddabee781152359c",
+    bootstrap_peer_multiaddr: None,
+    genesis_hash: "0x0",
+};
+
+impl ChainPreset {
+    /// All the presets except for custom.
+    pub const KNOWN_CHAINS: [ChainPreset; 3] =
+        [ChainPreset::Mainnet, ChainPreset::Sepolia, ChainPreset::SepoliaIntegration];
+
+    /// The name of the preset, as given in the config.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ChainPreset::Mainnet => "mainnet",
+            ChainPreset::Sepolia => "sepolia",
+            ChainPreset::SepoliaIntegration => "sepolia-integration",
+            ChainPreset::Custom => "custom",
+        }
+    }
+
+    /// Returns the values of the preset, or None for a custom chain.
+    pub fn values(&self) -> Option<&'static ChainPresetValues> {
+        match self {
+            ChainPreset::Mainnet => Some(&MAINNET),
+            ChainPreset::Sepolia => Some(&SEPOLIA),
+            ChainPreset::SepoliaIntegration => Some(&SEPOLIA_INTEGRATION),
+            ChainPreset::Custom => None,
+        }
+    }
+
+    // Sets the values of the preset as the default values of the matching params in a dumped
+    // config, so that values from any other source take precedence over them.
+    pub(crate) fn apply_to_default_config(&self, default_config: &mut Map<String, Value>) {
+        let Some(values) = self.values() else {
+            return;
+        };
+        let mut preset_params = vec![
+            ("chain_id", json!(values.chain_id)),
+            ("starknet_url", json!(values.starknet_url)),
+            ("base_layer.starknet_contract_address", json!(values.base_layer_contract_address)),
+            ("genesis_hash", json!(values.genesis_hash)),
+        ];
+        if let Some(bootstrap_peer_multiaddr) = values.bootstrap_peer_multiaddr {
+            preset_params.extend([
+                ("network.bootstrap_peer_multiaddr", json!(bootstrap_peer_multiaddr)),
+                ("network.bootstrap_peer_multiaddr.#is_none", json!(false)),
+            ]);
+        }
+        for (param_path, value) in preset_params {
+            if let Some(Value::Object(param)) = default_config.get_mut(param_path) {
+                param.insert("value".to_owned(), value);
+            }
+        }
+    }
+}
+
+/// Returns a warning for each chain related param whose value belongs to a different chain than
+/// the chain id, e.g. a custom chain id with the mainnet Starknet URL.
+pub fn chain_consistency_warnings(config: &NodeConfig) -> Vec<String> {
+    let chain_id = config.storage.db_config.chain_id.to_string();
+    let mut warnings = Vec::new();
+    for preset in ChainPreset::KNOWN_CHAINS {
+        let values = preset.values().expect("Known chains should have values");
+        if values.chain_id == chain_id {
+            continue;
+        }
+        if config.central.url.trim_end_matches('/') == values.starknet_url.trim_end_matches('/') {
+            warnings.push(format!(
+                "The chain id is {chain_id}, but starknet_url {} belongs to the {} chain.",
+                config.central.url,
+                preset.name()
+            ));
+        }
+        if config
+            .base_layer
+            .starknet_contract_address
+            .eq_ignore_ascii_case(values.base_layer_contract_address)
+        {
+            warnings.push(format!(
+                "The chain id is {chain_id}, but base_layer.starknet_contract_address {} belongs \
+                 to the {} chain.",
+                config.base_layer.starknet_contract_address,
+                preset.name()
+            ));
+        }
+    }
+    warnings
+}
@@ -0,0 +1,229 @@
+#[cfg(test)]
+#[path = "reload_test.rs"]
+mod reload_test;
+
+use std::time::Duration;
+
+use futures::channel::mpsc::UnboundedReceiver;
+use futures::StreamExt;
+use papyrus_config::dumping::SerializeConfig;
+use papyrus_config::presentation::get_config_presentation;
+use papyrus_config::validators::config_validate;
+use papyrus_config::ConfigError;
+use papyrus_monitoring_gateway::{ConfigPresentation, ConfigReloadOutcome, ConfigReloadRequest};
+use papyrus_network::NetworkConfig;
+use papyrus_protobuf::sync::ResponseLimits;
+use papyrus_sync::SyncConfig;
+use tokio::sync::watch;
+use tracing::{error, info, warn};
+
+use crate::config::NodeConfig;
+
+// Copies the value of a param from the reloaded config to the running config.
+type ApplyParam = fn(&mut NodeConfig, &NodeConfig);
+
+// The params that can change while the node is running. Changes to the other params are rejected
+// since they take effect only when the node starts.
+const DYNAMIC_PARAMS: [(&str, ApplyParam); 6] = [
+    ("monitoring_gateway.storage_metrics_update_interval", |running, reloaded| {
+        running.monitoring_gateway.storage_metrics_update_interval =
+            reloaded.monitoring_gateway.storage_metrics_update_interval;
+    }),
+    ("sync.block_propagation_sleep_duration", |running, reloaded| {
+        if let (Some(running), Some(reloaded)) = (&mut running.sync, &reloaded.sync) {
+            running.block_propagation_sleep_duration = reloaded.block_propagation_sleep_duration;
+        }
+    }),
+    ("sync.recoverable_error_sleep_duration", |running, reloaded| {
+        if let (Some(running), Some(reloaded)) = (&mut running.sync, &reloaded.sync) {
+            running.recoverable_error_sleep_duration = reloaded.recoverable_error_sleep_duration;
+        }
+    }),
+    ("network.max_response_items", |running, reloaded| {
+        if let (Some(running), Some(reloaded)) = (&mut running.network, &reloaded.network) {
+            running.max_response_items = reloaded.max_response_items;
+        }
+    }),
+    ("network.max_response_bytes", |running, reloaded| {
+        if let (Some(running), Some(reloaded)) = (&mut running.network, &reloaded.network) {
+            running.max_response_bytes = reloaded.max_response_bytes;
+        }
+    }),
+    ("network.ping_interval", |running, reloaded| {
+        if let (Some(running), Some(reloaded)) = (&mut running.network, &reloaded.network) {
+            running.ping_interval = reloaded.ping_interval;
+        }
+    }),
+];
+
+/// The values of the dynamic params, for the tasks that use them. A value changes whenever a
+/// reload changes its params. The presentation of the config changes whenever a reload applies
+/// changes to it.
+pub struct DynamicConfigReceivers {
+    pub storage_metrics_update_interval: watch::Receiver<Duration>,
+    pub sync: watch::Receiver<SyncConfig>,
+    pub response_limits: watch::Receiver<ResponseLimits>,
+    pub ping_interval: watch::Receiver<Duration>,
+    pub config_presentation: watch::Receiver<ConfigPresentation>,
+}
+
+impl DynamicConfigReceivers {
+    /// Receivers of values that never change, for a node that doesn't reload its config.
+    pub fn unchanging(config: &NodeConfig) -> Result<Self, ConfigError> {
+        Ok(DynamicConfigSenders::new(config)?.subscribe())
+    }
+}
+
+struct DynamicConfigSenders {
+    storage_metrics_update_interval: watch::Sender<Duration>,
+    sync: watch::Sender<SyncConfig>,
+    response_limits: watch::Sender<ResponseLimits>,
+    ping_interval: watch::Sender<Duration>,
+    config_presentation: watch::Sender<ConfigPresentation>,
+}
+
+impl DynamicConfigSenders {
+    fn new(config: &NodeConfig) -> Result<Self, ConfigError> {
+        let (storage_metrics_update_interval, sync, response_limits, ping_interval) =
+            dynamic_values(config);
+        Ok(Self {
+            storage_metrics_update_interval: watch::Sender::new(storage_metrics_update_interval),
+            sync: watch::Sender::new(sync),
+            response_limits: watch::Sender::new(response_limits),
+            ping_interval: watch::Sender::new(ping_interval),
+            config_presentation: watch::Sender::new(config_presentation(config)?),
+        })
+    }
+
+    fn subscribe(&self) -> DynamicConfigReceivers {
+        DynamicConfigReceivers {
+            storage_metrics_update_interval: self.storage_metrics_update_interval.subscribe(),
+            sync: self.sync.subscribe(),
+            response_limits: self.response_limits.subscribe(),
+            ping_interval: self.ping_interval.subscribe(),
+            config_presentation: self.config_presentation.subscribe(),
+        }
+    }
+
+    // Only the values that changed are sent, so that the tasks aren't notified of other changes.
+    fn send(&self, config: &NodeConfig) {
+        let (storage_metrics_update_interval, sync, response_limits, ping_interval) =
+            dynamic_values(config);
+        send_if_changed(&self.storage_metrics_update_interval, storage_metrics_update_interval);
+        send_if_changed(&self.sync, sync);
+        send_if_changed(&self.response_limits, response_limits);
+        send_if_changed(&self.ping_interval, ping_interval);
+    }
+}
+
+// The components that don't run get the default values, which they never read.
+fn dynamic_values(config: &NodeConfig) -> (Duration, SyncConfig, ResponseLimits, Duration) {
+    let network_config = config.network.clone().unwrap_or_default();
+    (
+        config.monitoring_gateway.storage_metrics_update_interval,
+        config.sync.unwrap_or_default(),
+        network_config.response_limits(),
+        network_config.ping_interval,
+    )
+}
+
+// The presentation of all the params of the config, and of its public params.
+fn config_presentation(config: &NodeConfig) -> Result<ConfigPresentation, ConfigError> {
+    Ok(ConfigPresentation {
+        full: get_config_presentation(config, true)?,
+        public: get_config_presentation(config, false)?,
+    })
+}
+
+fn send_if_changed<T: PartialEq>(sender: &watch::Sender<T>, value: T) {
+    sender.send_if_modified(|current_value| {
+        if *current_value == value {
+            return false;
+        }
+        *current_value = value;
+        true
+    });
+}
+
+/// Reloads the node's config on request and applies the changes to the dynamic params. The config
+/// is reloaded from the same sources it was loaded from when the node started, so changes to the
+/// config files take effect.
+pub struct ConfigReloader {
+    args: Vec<String>,
+    running_config: NodeConfig,
+    senders: DynamicConfigSenders,
+}
+
+impl ConfigReloader {
+    /// `args` are the command line arguments the node was started with, and `running_config` is
+    /// the config that was loaded from them.
+    pub fn new(args: Vec<String>, running_config: NodeConfig) -> Result<Self, ConfigError> {
+        let senders = DynamicConfigSenders::new(&running_config)?;
+        Ok(Self { args, running_config, senders })
+    }
+
+    pub fn subscribe(&self) -> DynamicConfigReceivers {
+        self.senders.subscribe()
+    }
+
+    /// Reloads the config for each request until the requests stop.
+    pub async fn run(mut self, mut requests: UnboundedReceiver<ConfigReloadRequest>) {
+        while let Some(outcome_sender) = requests.next().await {
+            let result = self.reload().map_err(|error| error.to_string());
+            match &result {
+                Ok(outcome) if outcome.rejected.is_empty() => {
+                    info!(applied = ?outcome.applied, "Reloaded the config.");
+                }
+                Ok(outcome) => warn!(
+                    applied = ?outcome.applied,
+                    rejected = ?outcome.rejected,
+                    "Reloaded the config. Changes to the rejected params take effect only after a \
+                     restart."
+                ),
+                Err(error) => {
+                    error!("Failed reloading the config, keeping the running config: {error}")
+                }
+            }
+            // The admin server might have stopped waiting for the outcome.
+            let _ = outcome_sender.send(result);
+        }
+    }
+
+    /// Loads and validates the config, and applies the changes to the dynamic params. If the
+    /// config can't be loaded or is invalid, nothing is applied.
+    pub fn reload(&mut self) -> Result<ConfigReloadOutcome, ConfigError> {
+        let reloaded_config = NodeConfig::load_and_process(self.args.clone())?;
+        config_validate(&reloaded_config)?;
+        let outcome = self.apply(&reloaded_config);
+        // Only a reload that applied changes invalidates the presentation. The private params are
+        // left out of the new public presentation as well.
+        if !outcome.applied.is_empty() {
+            let presentation = config_presentation(&self.running_config)?;
+            self.senders.config_presentation.send_replace(presentation);
+        }
+        Ok(outcome)
+    }
+
+    fn apply(&mut self, reloaded_config: &NodeConfig) -> ConfigReloadOutcome {
+        let running_params = self.running_config.dump();
+        let mut outcome = ConfigReloadOutcome::default();
+        for (param_path, reloaded_param) in reloaded_config.dump() {
+            let running_content = running_params.get(&param_path).map(|param| &param.content);
+            if running_content == Some(&reloaded_param.content) {
+                continue;
+            }
+            match DYNAMIC_PARAMS
+                .iter()
+                .find(|(dynamic_param_path, _)| *dynamic_param_path == param_path)
+            {
+                Some((_, apply_param)) => {
+                    apply_param(&mut self.running_config, reloaded_config);
+                    outcome.applied.push(param_path);
+                }
+                None => outcome.rejected.push(param_path),
+            }
+        }
+        self.senders.send(&self.running_config);
+        outcome
+    }
+}
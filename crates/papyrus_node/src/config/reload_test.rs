@@ -0,0 +1,132 @@
+use std::time::Duration;
+use std::{env, fs};
+
+use pretty_assertions::assert_eq;
+use serde_json::{json, Value};
+use tempfile::NamedTempFile;
+use test_utils::get_absolute_path;
+
+use crate::config::config_test::get_args;
+use crate::config::reload::ConfigReloader;
+use crate::config::NodeConfig;
+
+// Returns a reloader of a config that was loaded from an empty config file, and the file.
+fn setup() -> (ConfigReloader, NamedTempFile) {
+    env::set_current_dir(get_absolute_path("")).expect("Couldn't set working dir.");
+    let config_file = NamedTempFile::new().unwrap();
+    fs::write(config_file.path(), "{}").unwrap();
+    let args = get_args(vec!["--config_file", config_file.path().to_str().unwrap()]);
+    let config = NodeConfig::load_and_process(args.clone()).unwrap();
+    (ConfigReloader::new(args, config).unwrap(), config_file)
+}
+
+fn write_config_file(config_file: &NamedTempFile, config: Value) {
+    fs::write(config_file.path(), config.to_string()).unwrap();
+}
+
+#[test]
+fn dynamic_params_are_applied_and_static_params_are_rejected() {
+    let (mut reloader, config_file) = setup();
+    let mut dynamic_config = reloader.subscribe();
+    let config_before_reload = reloader.running_config.clone();
+
+    write_config_file(
+        &config_file,
+        json!({
+            "sync.block_propagation_sleep_duration": 10,
+            "monitoring_gateway.server_address": "0.0.0.0:9999",
+        }),
+    );
+    let outcome = reloader.reload().unwrap();
+
+    assert_eq!(outcome.applied, vec!["sync.block_propagation_sleep_duration"]);
+    assert_eq!(outcome.rejected, vec!["monitoring_gateway.server_address"]);
+    assert!(dynamic_config.sync.has_changed().unwrap());
+    assert_eq!(
+        dynamic_config.sync.borrow_and_update().block_propagation_sleep_duration,
+        Duration::from_secs(10)
+    );
+    assert!(!dynamic_config.response_limits.has_changed().unwrap());
+    assert!(!dynamic_config.storage_metrics_update_interval.has_changed().unwrap());
+    assert_eq!(
+        reloader.running_config.monitoring_gateway.server_address,
+        config_before_reload.monitoring_gateway.server_address
+    );
+}
+
+#[test]
+fn invalid_config_leaves_the_running_config_in_effect() {
+    let (mut reloader, config_file) = setup();
+    let dynamic_config = reloader.subscribe();
+    let config_before_reload = reloader.running_config.clone();
+
+    // A read-only storage can't be synced to, so the config is invalid even though the change to
+    // the sync param is valid on its own.
+    write_config_file(
+        &config_file,
+        json!({
+            "sync.block_propagation_sleep_duration": 10,
+            "storage.read_only": true,
+        }),
+    );
+    reloader.reload().unwrap_err();
+
+    // A config that can't be loaded is rejected as well.
+    write_config_file(&config_file, json!({"sync.block_propagation_sleep_duration": "ten"}));
+    reloader.reload().unwrap_err();
+
+    assert_eq!(reloader.running_config, config_before_reload);
+    assert!(!dynamic_config.sync.has_changed().unwrap());
+    assert!(!dynamic_config.response_limits.has_changed().unwrap());
+    assert!(!dynamic_config.storage_metrics_update_interval.has_changed().unwrap());
+}
+
+#[test]
+fn ping_interval_is_applied() {
+    let (reloader, config_file) = setup();
+    // The network is enabled when the node starts, since it can't be enabled by a reload.
+    write_config_file(&config_file, json!({"network.#is_none": false}));
+    let config = NodeConfig::load_and_process(reloader.args.clone()).unwrap();
+    let mut reloader = ConfigReloader::new(reloader.args, config).unwrap();
+    let mut dynamic_config = reloader.subscribe();
+
+    write_config_file(&config_file, json!({"network.#is_none": false, "network.ping_interval": 5}));
+    let outcome = reloader.reload().unwrap();
+
+    assert_eq!(outcome.applied, vec!["network.ping_interval"]);
+    assert!(outcome.rejected.is_empty());
+    assert_eq!(*dynamic_config.ping_interval.borrow_and_update(), Duration::from_secs(5));
+    assert!(!dynamic_config.response_limits.has_changed().unwrap());
+}
+
+#[test]
+fn presentation_is_updated_once_per_reload_that_applies_changes() {
+    let (mut reloader, config_file) = setup();
+    let mut dynamic_config = reloader.subscribe();
+    let secret = reloader.running_config.monitoring_gateway.present_full_config_secret.clone();
+
+    // The change to the secret is rejected, and it stays out of the public presentation.
+    write_config_file(
+        &config_file,
+        json!({
+            "sync.block_propagation_sleep_duration": 10,
+            "monitoring_gateway.present_full_config_secret": "reloaded_secret",
+        }),
+    );
+    let outcome = reloader.reload().unwrap();
+    assert_eq!(outcome.applied, vec!["sync.block_propagation_sleep_duration"]);
+    assert!(dynamic_config.config_presentation.has_changed().unwrap());
+    let presentation = dynamic_config.config_presentation.borrow_and_update().clone();
+    let sleep_duration = serde_json::to_value(Duration::from_secs(10)).unwrap();
+    assert_eq!(presentation.public["sync"]["block_propagation_sleep_duration"], sleep_duration);
+    assert_eq!(presentation.full["sync"]["block_propagation_sleep_duration"], sleep_duration);
+    assert!(presentation.public["monitoring_gateway"].get("present_full_config_secret").is_none());
+    assert_eq!(
+        presentation.full["monitoring_gateway"]["present_full_config_secret"],
+        json!(secret)
+    );
+
+    // Reloading the same config applies no changes, so the presentation isn't updated again.
+    reloader.reload().unwrap();
+    assert!(!dynamic_config.config_presentation.has_changed().unwrap());
+}
@@ -0,0 +1,614 @@
+//! Exports historical blocks, transactions and events from the storage into columnar files, for
+//! analytics.
+//!
+//! The exported range is split into chunks of blocks and every chunk of every table is written
+//! into its own file, named `<table>_<first block>_<end block>.<format>` (the end is exclusive).
+//! This keeps the memory bounded by the chunk size. A `manifest.json` in the output directory
+//! records the export's parameters, its files and the block it reached, so running the same export
+//! again resumes it from that block.
+//!
+//! # Schema
+//!
+//! Hashes, addresses and felts are written as 0x-prefixed hex strings.
+//!
+//! `blocks`:
+//! - `block_number` (u64)
+//! - `block_hash` (string)
+//! - `parent_hash` (string)
+//! - `timestamp` (u64): seconds since the Unix epoch.
+//! - `sequencer_address` (string)
+//! - `state_root` (string)
+//! - `starknet_version` (string)
+//! - `transaction_count` (u64, nullable): null for old blocks whose header doesn't contain it.
+//! - `event_count` (u64, nullable): null for old blocks whose header doesn't contain it.
+//!
+//! `transactions`:
+//! - `block_number` (u64)
+//! - `transaction_index` (u64): the index of the transaction inside its block.
+//! - `transaction_hash` (string)
+//! - `transaction_type` (string): one of DECLARE, DEPLOY, DEPLOY_ACCOUNT, INVOKE and L1_HANDLER.
+//! - `actual_fee` (string): a decimal number, since it may not fit in a u64.
+//! - `execution_status` (string): either SUCCEEDED or REVERTED.
+//! - `event_count` (u64)
+//!
+//! `events`:
+//! - `block_number` (u64)
+//! - `transaction_index` (u64): the index of the emitting transaction inside its block.
+//! - `transaction_hash` (string)
+//! - `event_index` (u64): the index of the event inside its transaction.
+//! - `from_address` (string)
+//! - `keys` (string): the event's keys, separated by commas.
+//! - `data` (string): the event's data, separated by commas.
+
+#[cfg(test)]
+#[path = "export_test.rs"]
+mod export_test;
+
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use std::sync::Arc;
+
+use arrow::array::{ArrayRef, StringBuilder, UInt64Builder};
+use arrow::datatypes::{DataType, Field, Schema, SchemaRef};
+use arrow::error::ArrowError;
+use arrow::record_batch::RecordBatch;
+use clap::{Arg, ArgMatches, Command};
+use papyrus_storage::body::BodyStorageReader;
+use papyrus_storage::db::RO;
+use papyrus_storage::header::HeaderStorageReader;
+use papyrus_storage::{
+    open_storage_read_only,
+    StorageConfig,
+    StorageError,
+    StorageReader,
+    StorageTxn,
+};
+use parquet::arrow::ArrowWriter;
+use parquet::errors::ParquetError;
+use serde::{Deserialize, Serialize};
+use starknet_api::block::BlockNumber;
+use starknet_api::core::ChainId;
+use starknet_api::hash::StarkHash;
+use starknet_api::transaction::{Transaction, TransactionExecutionStatus};
+use tracing::info;
+
+/// The name of the file inside the output directory that records the progress of the export.
+pub const MANIFEST_FILE_NAME: &str = "manifest.json";
+const DEFAULT_CHUNK_SIZE: &str = "1000";
+
+#[derive(thiserror::Error, Debug)]
+pub enum ExportError {
+    #[error("Invalid block range: from ({from}) is greater than to ({to}).")]
+    InvalidRange { from: BlockNumber, to: BlockNumber },
+    #[error(
+        "Can't export blocks up to {to}. The storage contains the exported data only up to \
+         {synced_up_to}."
+    )]
+    BlocksNotSynced { to: BlockNumber, synced_up_to: BlockNumber },
+    #[error("Block {block_number} is missing its {missing_data} in the storage.")]
+    MissingBlockData { block_number: BlockNumber, missing_data: &'static str },
+    #[error(
+        "The output directory contains a manifest of an export with different parameters. Use \
+         another output directory or the same parameters to resume it."
+    )]
+    ManifestMismatch,
+    #[error(transparent)]
+    ArrowError(#[from] ArrowError),
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    ManifestError(#[from] serde_json::Error),
+    #[error(transparent)]
+    ParquetError(#[from] ParquetError),
+    #[error(transparent)]
+    StorageError(#[from] StorageError),
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportTable {
+    Blocks,
+    Transactions,
+    Events,
+}
+
+impl ExportTable {
+    pub fn name(&self) -> &'static str {
+        match self {
+            ExportTable::Blocks => "blocks",
+            ExportTable::Transactions => "transactions",
+            ExportTable::Events => "events",
+        }
+    }
+
+    fn schema(&self) -> SchemaRef {
+        let fields = match self {
+            ExportTable::Blocks => vec![
+                Field::new("block_number", DataType::UInt64, false),
+                Field::new("block_hash", DataType::Utf8, false),
+                Field::new("parent_hash", DataType::Utf8, false),
+                Field::new("timestamp", DataType::UInt64, false),
+                Field::new("sequencer_address", DataType::Utf8, false),
+                Field::new("state_root", DataType::Utf8, false),
+                Field::new("starknet_version", DataType::Utf8, false),
+                Field::new("transaction_count", DataType::UInt64, true),
+                Field::new("event_count", DataType::UInt64, true),
+            ],
+            ExportTable::Transactions => vec![
+                Field::new("block_number", DataType::UInt64, false),
+                Field::new("transaction_index", DataType::UInt64, false),
+                Field::new("transaction_hash", DataType::Utf8, false),
+                Field::new("transaction_type", DataType::Utf8, false),
+                Field::new("actual_fee", DataType::Utf8, false),
+                Field::new("execution_status", DataType::Utf8, false),
+                Field::new("event_count", DataType::UInt64, false),
+            ],
+            ExportTable::Events => vec![
+                Field::new("block_number", DataType::UInt64, false),
+                Field::new("transaction_index", DataType::UInt64, false),
+                Field::new("transaction_hash", DataType::Utf8, false),
+                Field::new("event_index", DataType::UInt64, false),
+                Field::new("from_address", DataType::Utf8, false),
+                Field::new("keys", DataType::Utf8, false),
+                Field::new("data", DataType::Utf8, false),
+            ],
+        };
+        Arc::new(Schema::new(fields))
+    }
+}
+
+impl FromStr for ExportTable {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "blocks" => Ok(ExportTable::Blocks),
+            "transactions" => Ok(ExportTable::Transactions),
+            "events" => Ok(ExportTable::Events),
+            _ => Err(format!("Unknown table {s}. Expected blocks, transactions or events.")),
+        }
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum ExportFormat {
+    Parquet,
+    Csv,
+}
+
+impl ExportFormat {
+    fn file_extension(&self) -> &'static str {
+        match self {
+            ExportFormat::Parquet => "parquet",
+            ExportFormat::Csv => "csv",
+        }
+    }
+}
+
+impl FromStr for ExportFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "parquet" => Ok(ExportFormat::Parquet),
+            "csv" => Ok(ExportFormat::Csv),
+            _ => Err(format!("Unknown format {s}. Expected parquet or csv.")),
+        }
+    }
+}
+
+#[derive(Clone, Debug)]
+pub struct ExportConfig {
+    pub tables: Vec<ExportTable>,
+    // Inclusive.
+    pub from: BlockNumber,
+    // Exclusive.
+    pub to: BlockNumber,
+    pub format: ExportFormat,
+    pub output_dir: PathBuf,
+    // The number of blocks that are written into each file.
+    pub chunk_size: u64,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+struct Manifest {
+    tables: Vec<ExportTable>,
+    from: BlockNumber,
+    to: BlockNumber,
+    format: ExportFormat,
+    chunk_size: u64,
+    // The first block that wasn't exported yet.
+    next_block: BlockNumber,
+    // The exported files, relative to the output directory, in the order they were written.
+    files: Vec<String>,
+}
+
+impl Manifest {
+    fn matches(&self, config: &ExportConfig) -> bool {
+        self.tables == config.tables
+            && self.from == config.from
+            && self.to == config.to
+            && self.format == config.format
+            && self.chunk_size == config.chunk_size
+    }
+}
+
+/// Exports the blocks in the range of the config into the output directory. If the output
+/// directory contains the manifest of a previous run of the same export, the export resumes from
+/// where that run stopped.
+///
+/// A new read transaction is opened for every chunk, so a long export doesn't hold back the
+/// storage of a node that runs concurrently.
+pub fn export(storage_reader: &StorageReader, config: &ExportConfig) -> Result<(), ExportError> {
+    assert!(config.chunk_size > 0, "chunk_size should be positive.");
+    if config.from > config.to {
+        return Err(ExportError::InvalidRange { from: config.from, to: config.to });
+    }
+    let synced_up_to = get_synced_up_to(&storage_reader.begin_ro_txn()?, &config.tables)?;
+    if config.to > synced_up_to {
+        return Err(ExportError::BlocksNotSynced { to: config.to, synced_up_to });
+    }
+
+    std::fs::create_dir_all(&config.output_dir)?;
+    let manifest_path = config.output_dir.join(MANIFEST_FILE_NAME);
+    let mut manifest = if manifest_path.exists() {
+        let manifest: Manifest = serde_json::from_reader(File::open(&manifest_path)?)?;
+        if !manifest.matches(config) {
+            return Err(ExportError::ManifestMismatch);
+        }
+        info!("Resuming the export from block {}.", manifest.next_block);
+        manifest
+    } else {
+        Manifest {
+            tables: config.tables.clone(),
+            from: config.from,
+            to: config.to,
+            format: config.format,
+            chunk_size: config.chunk_size,
+            next_block: config.from,
+            files: vec![],
+        }
+    };
+
+    while manifest.next_block < config.to {
+        let chunk_start = manifest.next_block;
+        let chunk_end = BlockNumber((chunk_start.0 + config.chunk_size).min(config.to.0));
+        let txn = storage_reader.begin_ro_txn()?;
+        for table in &config.tables {
+            let batch = get_record_batch(&txn, *table, chunk_start, chunk_end)?;
+            let file_name = format!(
+                "{}_{}_{}.{}",
+                table.name(),
+                chunk_start,
+                chunk_end,
+                config.format.file_extension()
+            );
+            write_record_batch(&batch, config.format, &config.output_dir.join(&file_name))?;
+            manifest.files.push(file_name);
+        }
+        manifest.next_block = chunk_end;
+        write_manifest(&manifest, &manifest_path)?;
+        info!(
+            "Exported blocks {chunk_start} to {chunk_end} ({} out of {} blocks).",
+            chunk_end.0 - config.from.0,
+            config.to.0 - config.from.0
+        );
+    }
+    info!(
+        "Finished exporting blocks {} to {} into {}.",
+        config.from,
+        config.to,
+        config.output_dir.display()
+    );
+    Ok(())
+}
+
+/// Runs the export command with the given command line arguments, where the first argument is the
+/// name of the command.
+pub fn run_export_command(args: Vec<String>) -> Result<(), ExportError> {
+    let matches = get_command().get_matches_from(args);
+    let mut storage_config = StorageConfig::default();
+    storage_config.db_config.path_prefix =
+        matches.get_one::<PathBuf>("path_prefix").expect("Failed parsing path_prefix").clone();
+    storage_config.db_config.chain_id = ChainId::Other(
+        matches.get_one::<String>("chain_id").expect("Failed parsing chain_id").clone(),
+    );
+    let config = get_export_config(&matches);
+    // The storage is opened read-only so that the export can run against the storage of a live
+    // node.
+    let storage_reader = open_storage_read_only(storage_config)?;
+    export(&storage_reader, &config)
+}
+
+fn get_command() -> Command {
+    Command::new("export")
+        .about("Exports blocks, transactions and events into Parquet or CSV files.")
+        .arg(
+            Arg::new("tables")
+                .long("tables")
+                .required(true)
+                .value_delimiter(',')
+                .value_parser(ExportTable::from_str)
+                .help(
+                    "Comma separated list of the tables to export: blocks, transactions, events.",
+                ),
+        )
+        .arg(
+            Arg::new("from")
+                .long("from")
+                .required(true)
+                .value_parser(clap::value_parser!(u64))
+                .help("The first block to export."),
+        )
+        .arg(
+            Arg::new("to")
+                .long("to")
+                .required(true)
+                .value_parser(clap::value_parser!(u64))
+                .help("The block to export up to (exclusive)."),
+        )
+        .arg(
+            Arg::new("format")
+                .long("format")
+                .default_value("parquet")
+                .value_parser(ExportFormat::from_str)
+                .help("The format of the exported files: parquet or csv."),
+        )
+        .arg(
+            Arg::new("output")
+                .long("output")
+                .required(true)
+                .value_parser(clap::value_parser!(PathBuf))
+                .help("The directory to write the exported files and the manifest to."),
+        )
+        .arg(
+            Arg::new("chunk_size")
+                .long("chunk_size")
+                .default_value(DEFAULT_CHUNK_SIZE)
+                .value_parser(clap::value_parser!(u64).range(1..))
+                .help("The number of blocks that are written into each file."),
+        )
+        .arg(
+            Arg::new("path_prefix")
+                .short('p')
+                .long("path_prefix")
+                .required(true)
+                .value_parser(clap::value_parser!(PathBuf))
+                .help("The path prefix of the storage's database files."),
+        )
+        .arg(
+            Arg::new("chain_id")
+                .short('c')
+                .long("chain_id")
+                .required(true)
+                .help("The chain id of the storage, e.g. SN_MAIN or SN_SEPOLIA."),
+        )
+}
+
+fn get_export_config(matches: &ArgMatches) -> ExportConfig {
+    let mut tables = Vec::new();
+    for table in matches.get_many::<ExportTable>("tables").expect("Failed parsing tables") {
+        if !tables.contains(table) {
+            tables.push(*table);
+        }
+    }
+    ExportConfig {
+        tables,
+        from: BlockNumber(*matches.get_one::<u64>("from").expect("Failed parsing from")),
+        to: BlockNumber(*matches.get_one::<u64>("to").expect("Failed parsing to")),
+        format: *matches.get_one::<ExportFormat>("format").expect("Failed parsing format"),
+        output_dir: matches.get_one::<PathBuf>("output").expect("Failed parsing output").clone(),
+        chunk_size: *matches.get_one::<u64>("chunk_size").expect("Failed parsing chunk_size"),
+    }
+}
+
+// Returns the block up to which the storage contains all the data of the given tables.
+fn get_synced_up_to(
+    txn: &StorageTxn<'_, RO>,
+    tables: &[ExportTable],
+) -> Result<BlockNumber, ExportError> {
+    let mut synced_up_to = txn.get_header_marker()?;
+    if tables.iter().any(|table| *table != ExportTable::Blocks) {
+        synced_up_to = synced_up_to.min(txn.get_body_marker()?);
+    }
+    Ok(synced_up_to)
+}
+
+fn get_record_batch(
+    txn: &StorageTxn<'_, RO>,
+    table: ExportTable,
+    chunk_start: BlockNumber,
+    chunk_end: BlockNumber,
+) -> Result<RecordBatch, ExportError> {
+    let block_numbers = (chunk_start.0..chunk_end.0).map(BlockNumber);
+    let columns = match table {
+        ExportTable::Blocks => get_blocks_columns(txn, block_numbers)?,
+        ExportTable::Transactions => get_transactions_columns(txn, block_numbers)?,
+        ExportTable::Events => get_events_columns(txn, block_numbers)?,
+    };
+    Ok(RecordBatch::try_new(table.schema(), columns)?)
+}
+
+fn get_blocks_columns(
+    txn: &StorageTxn<'_, RO>,
+    block_numbers: impl Iterator<Item = BlockNumber>,
+) -> Result<Vec<ArrayRef>, ExportError> {
+    let mut block_number_column = UInt64Builder::new();
+    let mut block_hash_column = StringBuilder::new();
+    let mut parent_hash_column = StringBuilder::new();
+    let mut timestamp_column = UInt64Builder::new();
+    let mut sequencer_address_column = StringBuilder::new();
+    let mut state_root_column = StringBuilder::new();
+    let mut starknet_version_column = StringBuilder::new();
+    let mut transaction_count_column = UInt64Builder::new();
+    let mut event_count_column = UInt64Builder::new();
+    for block_number in block_numbers {
+        let header = txn
+            .get_block_header(block_number)?
+            .ok_or(ExportError::MissingBlockData { block_number, missing_data: "header" })?;
+        block_number_column.append_value(block_number.0);
+        block_hash_column.append_value(header.block_hash.0.to_hex_string());
+        parent_hash_column.append_value(header.parent_hash.0.to_hex_string());
+        timestamp_column.append_value(header.timestamp.0);
+        sequencer_address_column.append_value(header.sequencer.0.key().to_hex_string());
+        state_root_column.append_value(header.state_root.0.to_hex_string());
+        starknet_version_column.append_value(header.starknet_version.0);
+        transaction_count_column.append_option(header.n_transactions.map(usize_into_u64));
+        event_count_column.append_option(header.n_events.map(usize_into_u64));
+    }
+    Ok(vec![
+        Arc::new(block_number_column.finish()),
+        Arc::new(block_hash_column.finish()),
+        Arc::new(parent_hash_column.finish()),
+        Arc::new(timestamp_column.finish()),
+        Arc::new(sequencer_address_column.finish()),
+        Arc::new(state_root_column.finish()),
+        Arc::new(starknet_version_column.finish()),
+        Arc::new(transaction_count_column.finish()),
+        Arc::new(event_count_column.finish()),
+    ])
+}
+
+fn get_transactions_columns(
+    txn: &StorageTxn<'_, RO>,
+    block_numbers: impl Iterator<Item = BlockNumber>,
+) -> Result<Vec<ArrayRef>, ExportError> {
+    let mut block_number_column = UInt64Builder::new();
+    let mut transaction_index_column = UInt64Builder::new();
+    let mut transaction_hash_column = StringBuilder::new();
+    let mut transaction_type_column = StringBuilder::new();
+    let mut actual_fee_column = StringBuilder::new();
+    let mut execution_status_column = StringBuilder::new();
+    let mut event_count_column = UInt64Builder::new();
+    for block_number in block_numbers {
+        let transactions = txn
+            .get_block_transactions(block_number)?
+            .ok_or(ExportError::MissingBlockData { block_number, missing_data: "transactions" })?;
+        let transaction_outputs = txn.get_block_transaction_outputs(block_number)?.ok_or(
+            ExportError::MissingBlockData { block_number, missing_data: "transaction outputs" },
+        )?;
+        let transaction_hashes = txn.get_block_transaction_hashes(block_number)?.ok_or(
+            ExportError::MissingBlockData { block_number, missing_data: "transaction hashes" },
+        )?;
+        for (transaction_index, ((transaction, output), transaction_hash)) in
+            transactions.iter().zip(&transaction_outputs).zip(&transaction_hashes).enumerate()
+        {
+            block_number_column.append_value(block_number.0);
+            transaction_index_column.append_value(usize_into_u64(transaction_index));
+            transaction_hash_column.append_value(transaction_hash.0.to_hex_string());
+            transaction_type_column.append_value(get_transaction_type(transaction));
+            actual_fee_column.append_value(output.actual_fee().0.to_string());
+            execution_status_column.append_value(match output.execution_status() {
+                TransactionExecutionStatus::Succeeded => "SUCCEEDED",
+                TransactionExecutionStatus::Reverted(_) => "REVERTED",
+            });
+            event_count_column.append_value(usize_into_u64(output.events().len()));
+        }
+    }
+    Ok(vec![
+        Arc::new(block_number_column.finish()),
+        Arc::new(transaction_index_column.finish()),
+        Arc::new(transaction_hash_column.finish()),
+        Arc::new(transaction_type_column.finish()),
+        Arc::new(actual_fee_column.finish()),
+        Arc::new(execution_status_column.finish()),
+        Arc::new(event_count_column.finish()),
+    ])
+}
+
+fn get_events_columns(
+    txn: &StorageTxn<'_, RO>,
+    block_numbers: impl Iterator<Item = BlockNumber>,
+) -> Result<Vec<ArrayRef>, ExportError> {
+    let mut block_number_column = UInt64Builder::new();
+    let mut transaction_index_column = UInt64Builder::new();
+    let mut transaction_hash_column = StringBuilder::new();
+    let mut event_index_column = UInt64Builder::new();
+    let mut from_address_column = StringBuilder::new();
+    let mut keys_column = StringBuilder::new();
+    let mut data_column = StringBuilder::new();
+    for block_number in block_numbers {
+        let transaction_outputs = txn.get_block_transaction_outputs(block_number)?.ok_or(
+            ExportError::MissingBlockData { block_number, missing_data: "transaction outputs" },
+        )?;
+        let transaction_hashes = txn.get_block_transaction_hashes(block_number)?.ok_or(
+            ExportError::MissingBlockData { block_number, missing_data: "transaction hashes" },
+        )?;
+        for (transaction_index, (output, transaction_hash)) in
+            transaction_outputs.iter().zip(&transaction_hashes).enumerate()
+        {
+            for (event_index, event) in output.events().iter().enumerate() {
+                block_number_column.append_value(block_number.0);
+                transaction_index_column.append_value(usize_into_u64(transaction_index));
+                transaction_hash_column.append_value(transaction_hash.0.to_hex_string());
+                event_index_column.append_value(usize_into_u64(event_index));
+                from_address_column.append_value(event.from_address.0.key().to_hex_string());
+                keys_column.append_value(join_felts(event.content.keys.iter().map(|key| &key.0)));
+                data_column.append_value(join_felts(event.content.data.0.iter()));
+            }
+        }
+    }
+    Ok(vec![
+        Arc::new(block_number_column.finish()),
+        Arc::new(transaction_index_column.finish()),
+        Arc::new(transaction_hash_column.finish()),
+        Arc::new(event_index_column.finish()),
+        Arc::new(from_address_column.finish()),
+        Arc::new(keys_column.finish()),
+        Arc::new(data_column.finish()),
+    ])
+}
+
+fn get_transaction_type(transaction: &Transaction) -> &'static str {
+    match transaction {
+        Transaction::Declare(_) => "DECLARE",
+        Transaction::Deploy(_) => "DEPLOY",
+        Transaction::DeployAccount(_) => "DEPLOY_ACCOUNT",
+        Transaction::Invoke(_) => "INVOKE",
+        Transaction::L1Handler(_) => "L1_HANDLER",
+    }
+}
+
+fn join_felts<'a>(felts: impl Iterator<Item = &'a StarkHash>) -> String {
+    felts.map(|felt| felt.to_hex_string()).collect::<Vec<_>>().join(",")
+}
+
+fn usize_into_u64(value: usize) -> u64 {
+    u64::try_from(value).expect("usize should fit in u64")
+}
+
+fn write_record_batch(
+    batch: &RecordBatch,
+    format: ExportFormat,
+    path: &Path,
+) -> Result<(), ExportError> {
+    let file = File::create(path)?;
+    match format {
+        ExportFormat::Parquet => {
+            let mut writer = ArrowWriter::try_new(file, batch.schema(), None)?;
+            writer.write(batch)?;
+            writer.close()?;
+        }
+        ExportFormat::Csv => {
+            let mut writer = arrow::csv::Writer::new(BufWriter::new(file));
+            writer.write(batch)?;
+            writer.into_inner().flush()?;
+        }
+    }
+    Ok(())
+}
+
+// Writes the manifest into a temporary file and then renames it, so that an interrupted export
+// never leaves a corrupted manifest behind.
+fn write_manifest(manifest: &Manifest, path: &Path) -> Result<(), ExportError> {
+    let temp_path = path.with_extension("json.tmp");
+    let mut writer = BufWriter::new(File::create(&temp_path)?);
+    serde_json::to_writer_pretty(&mut writer, manifest)?;
+    writer.flush()?;
+    drop(writer);
+    std::fs::rename(temp_path, path)?;
+    Ok(())
+}
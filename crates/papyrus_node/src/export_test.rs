@@ -0,0 +1,206 @@
+use std::fs::File;
+use std::path::Path;
+
+use arrow::array::{Array, StringArray, UInt64Array};
+use arrow::csv::ReaderBuilder;
+use arrow::record_batch::RecordBatch;
+use assert_matches::assert_matches;
+use papyrus_storage::body::BodyStorageWriter;
+use papyrus_storage::header::HeaderStorageWriter;
+use papyrus_storage::test_utils::get_test_storage;
+use papyrus_storage::StorageReader;
+use parquet::arrow::arrow_reader::ParquetRecordBatchReaderBuilder;
+use starknet_api::block::{Block, BlockHash, BlockNumber};
+use starknet_api::hash::StarkHash;
+use test_utils::get_test_block;
+
+use crate::export::{
+    export,
+    ExportConfig,
+    ExportError,
+    ExportFormat,
+    ExportTable,
+    Manifest,
+    MANIFEST_FILE_NAME,
+};
+
+const N_BLOCKS: u64 = 3;
+const N_TRANSACTIONS_PER_BLOCK: usize = 2;
+const N_EVENTS_PER_TRANSACTION: usize = 2;
+const CHUNK_SIZE: u64 = 2;
+
+fn create_storage_with_blocks() -> (StorageReader, Vec<Block>, tempfile::TempDir) {
+    let ((storage_reader, mut storage_writer), temp_dir) = get_test_storage();
+    let mut blocks = vec![];
+    for block_number in (0..N_BLOCKS).map(BlockNumber) {
+        let mut block =
+            get_test_block(N_TRANSACTIONS_PER_BLOCK, Some(N_EVENTS_PER_TRANSACTION), None, None);
+        block.header.block_number = block_number;
+        block.header.block_hash = BlockHash(StarkHash::from(block_number.0 + 1));
+        storage_writer
+            .begin_rw_txn()
+            .unwrap()
+            .append_header(block_number, &block.header)
+            .unwrap()
+            .append_body(block_number, block.body.clone())
+            .unwrap()
+            .commit()
+            .unwrap();
+        blocks.push(block);
+    }
+    (storage_reader, blocks, temp_dir)
+}
+
+fn get_config(format: ExportFormat, output_dir: &Path) -> ExportConfig {
+    ExportConfig {
+        tables: vec![ExportTable::Blocks, ExportTable::Transactions, ExportTable::Events],
+        from: BlockNumber(0),
+        to: BlockNumber(N_BLOCKS),
+        format,
+        output_dir: output_dir.to_path_buf(),
+        chunk_size: CHUNK_SIZE,
+    }
+}
+
+// Reads all the exported files of the table, in the order they were written.
+fn read_table(output_dir: &Path, table: ExportTable, format: ExportFormat) -> Vec<RecordBatch> {
+    let manifest: Manifest =
+        serde_json::from_reader(File::open(output_dir.join(MANIFEST_FILE_NAME)).unwrap()).unwrap();
+    let file_prefix = format!("{}_", table.name());
+    let mut batches = vec![];
+    for file_name in manifest.files.iter().filter(|file_name| file_name.starts_with(&file_prefix)) {
+        let file = File::open(output_dir.join(file_name)).unwrap();
+        match format {
+            ExportFormat::Parquet => batches.extend(
+                ParquetRecordBatchReaderBuilder::try_new(file)
+                    .unwrap()
+                    .build()
+                    .unwrap()
+                    .map(Result::unwrap),
+            ),
+            ExportFormat::Csv => batches.extend(
+                ReaderBuilder::new(table.schema())
+                    .with_header(true)
+                    .build(file)
+                    .unwrap()
+                    .map(Result::unwrap),
+            ),
+        }
+    }
+    batches
+}
+
+fn count_rows(batches: &[RecordBatch]) -> usize {
+    batches.iter().map(RecordBatch::num_rows).sum()
+}
+
+fn get_string_column<'a>(batch: &'a RecordBatch, column_name: &str) -> &'a StringArray {
+    batch.column_by_name(column_name).unwrap().as_any().downcast_ref::<StringArray>().unwrap()
+}
+
+fn get_u64_column<'a>(batch: &'a RecordBatch, column_name: &str) -> &'a UInt64Array {
+    batch.column_by_name(column_name).unwrap().as_any().downcast_ref::<UInt64Array>().unwrap()
+}
+
+fn export_and_validate(format: ExportFormat) {
+    let (storage_reader, blocks, _temp_dir) = create_storage_with_blocks();
+    let output_dir = tempfile::tempdir().unwrap();
+    export(&storage_reader, &get_config(format, output_dir.path())).unwrap();
+
+    let block_batches = read_table(output_dir.path(), ExportTable::Blocks, format);
+    let transaction_batches = read_table(output_dir.path(), ExportTable::Transactions, format);
+    let event_batches = read_table(output_dir.path(), ExportTable::Events, format);
+    // The blocks are split into a chunk of 2 blocks and a chunk of 1 block.
+    assert_eq!(block_batches.len(), 2);
+    assert_eq!(count_rows(&block_batches), usize::try_from(N_BLOCKS).unwrap());
+    let n_transactions = usize::try_from(N_BLOCKS).unwrap() * N_TRANSACTIONS_PER_BLOCK;
+    assert_eq!(count_rows(&transaction_batches), n_transactions);
+    assert_eq!(count_rows(&event_batches), n_transactions * N_EVENTS_PER_TRANSACTION);
+
+    // Sample the first row of the second chunk in each table, which belongs to the last block.
+    let block = &blocks[2];
+    let blocks_batch = &block_batches[1];
+    assert_eq!(get_u64_column(blocks_batch, "block_number").value(0), 2);
+    assert_eq!(
+        get_string_column(blocks_batch, "block_hash").value(0),
+        block.header.block_hash.0.to_hex_string()
+    );
+    assert_eq!(get_u64_column(blocks_batch, "timestamp").value(0), block.header.timestamp.0);
+
+    let transactions_batch = &transaction_batches[1];
+    assert_eq!(get_u64_column(transactions_batch, "block_number").value(0), 2);
+    assert_eq!(get_u64_column(transactions_batch, "transaction_index").value(0), 0);
+    assert_eq!(
+        get_string_column(transactions_batch, "transaction_hash").value(0),
+        block.body.transaction_hashes[0].0.to_hex_string()
+    );
+    assert_eq!(
+        get_string_column(transactions_batch, "actual_fee").value(0),
+        block.body.transaction_outputs[0].actual_fee().0.to_string()
+    );
+
+    let events_batch = &event_batches[1];
+    let event = &block.body.transaction_outputs[0].events()[0];
+    assert_eq!(get_u64_column(events_batch, "block_number").value(0), 2);
+    assert_eq!(
+        get_string_column(events_batch, "from_address").value(0),
+        event.from_address.0.key().to_hex_string()
+    );
+    assert_eq!(
+        get_string_column(events_batch, "data").value(0),
+        event.content.data.0.iter().map(|felt| felt.to_hex_string()).collect::<Vec<_>>().join(",")
+    );
+}
+
+#[test]
+fn export_parquet() {
+    export_and_validate(ExportFormat::Parquet);
+}
+
+#[test]
+fn export_csv() {
+    export_and_validate(ExportFormat::Csv);
+}
+
+#[test]
+fn resume_export() {
+    let (storage_reader, _blocks, _temp_dir) = create_storage_with_blocks();
+    let output_dir = tempfile::tempdir().unwrap();
+    let config = get_config(ExportFormat::Csv, output_dir.path());
+    export(&storage_reader, &config).unwrap();
+
+    // Simulate an export that was interrupted after the first chunk.
+    let manifest_path = output_dir.path().join(MANIFEST_FILE_NAME);
+    let mut manifest: Manifest =
+        serde_json::from_reader(File::open(&manifest_path).unwrap()).unwrap();
+    let full_manifest_files = manifest.files.clone();
+    manifest.next_block = BlockNumber(CHUNK_SIZE);
+    manifest.files.truncate(config.tables.len());
+    serde_json::to_writer(File::create(&manifest_path).unwrap(), &manifest).unwrap();
+
+    export(&storage_reader, &config).unwrap();
+    let manifest: Manifest = serde_json::from_reader(File::open(&manifest_path).unwrap()).unwrap();
+    assert_eq!(manifest.next_block, BlockNumber(N_BLOCKS));
+    assert_eq!(manifest.files, full_manifest_files);
+
+    // Resuming with different parameters is refused.
+    let other_config = ExportConfig { format: ExportFormat::Parquet, ..config };
+    assert_matches!(export(&storage_reader, &other_config), Err(ExportError::ManifestMismatch));
+}
+
+#[test]
+fn export_unsynced_blocks() {
+    let (storage_reader, _blocks, _temp_dir) = create_storage_with_blocks();
+    let output_dir = tempfile::tempdir().unwrap();
+    let config = ExportConfig {
+        to: BlockNumber(N_BLOCKS + 1),
+        ..get_config(ExportFormat::Parquet, output_dir.path())
+    };
+    assert_matches!(
+        export(&storage_reader, &config),
+        Err(ExportError::BlocksNotSynced {
+            to: BlockNumber(4),
+            synced_up_to: BlockNumber(N_BLOCKS)
+        })
+    );
+}
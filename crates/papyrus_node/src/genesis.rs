@@ -0,0 +1,199 @@
+//! Checks the storage against the genesis of the configured chain, and commits the genesis state of
+//! the chain to an empty storage.
+//!
+//! Every chain is anchored by the parent hash of its first block, the genesis hash. A chain can
+//! also start from a genesis state that isn't the result of any block, such as the state of a
+//! localnet or of an appchain. The state is given as a snapshot file, which is committed as block 0
+//! before the sync starts, so the sync continues from block 1.
+//!
+//! A storage that was synced from another chain fails fast, instead of failing later in the sync on
+//! the first block whose parent doesn't match.
+
+#[cfg(test)]
+#[path = "genesis_test.rs"]
+mod genesis_test;
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::BufReader;
+use std::path::PathBuf;
+
+use papyrus_common::global_root::calculate_global_root;
+use papyrus_common::state_diff_commitment::{calculate_state_diff_commitment, StateDiffVersion};
+use papyrus_config::dumping::{ser_param, SerializeConfig};
+use papyrus_config::{ParamPath, ParamPrivacyInput, SerializedParam};
+use papyrus_storage::body::BodyStorageWriter;
+use papyrus_storage::class::ClassStorageWriter;
+use papyrus_storage::header::{HeaderStorageReader, HeaderStorageWriter};
+use papyrus_storage::state::StateStorageWriter;
+use papyrus_storage::{StorageError, StorageReader, StorageWriter, StorageWriterComponent};
+use serde::{Deserialize, Serialize};
+use starknet_api::block::{BlockBody, BlockHash, BlockHeader, BlockNumber, BlockSignature};
+use starknet_api::core::GlobalRoot;
+use starknet_api::state::{StateDiff, ThinStateDiff};
+use tracing::info;
+
+/// The genesis state of a chain whose first block starts from a non-empty state.
+#[derive(Clone, Debug, Default, Deserialize, Serialize, PartialEq)]
+pub struct GenesisStateConfig {
+    pub snapshot_path: PathBuf,
+    pub block_hash: BlockHash,
+    pub state_root: GlobalRoot,
+}
+
+impl SerializeConfig for GenesisStateConfig {
+    fn dump(&self) -> BTreeMap<ParamPath, SerializedParam> {
+        BTreeMap::from_iter([
+            ser_param(
+                "snapshot_path",
+                &self.snapshot_path,
+                "The path of a JSON file with the state diff that creates the genesis state from \
+                 an empty state, including the declared classes. It's committed as block 0 to an \
+                 empty storage.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "block_hash",
+                &self.block_hash,
+                "The hash of block 0, the block that commits the genesis state.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "state_root",
+                &self.state_root,
+                "The global root of the genesis state. The snapshot is rejected if its root is \
+                 different.",
+                ParamPrivacyInput::Public,
+            ),
+        ])
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum GenesisError {
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[error("Failed to parse the genesis state snapshot: {0}")]
+    SnapshotError(#[from] serde_json::Error),
+    #[error(transparent)]
+    StorageError(#[from] StorageError),
+    #[error("The genesis state can't be committed to a read-only storage.")]
+    ReadOnlyStorage,
+    #[error(
+        "The parent hash of block 0 in the storage is {stored}, but the configured genesis_hash is \
+         {configured}. The storage belongs to another chain."
+    )]
+    GenesisHashMismatch { stored: BlockHash, configured: BlockHash },
+    #[error(
+        "The hash of block 0 in the storage is {stored}, but the configured \
+         genesis_state.block_hash is {configured}. The storage belongs to another chain."
+    )]
+    BlockHashMismatch { stored: BlockHash, configured: BlockHash },
+    #[error(
+        "The state root of the genesis state is {actual}, but the configured \
+         genesis_state.state_root is {configured}."
+    )]
+    StateRootMismatch { actual: GlobalRoot, configured: GlobalRoot },
+}
+
+/// Checks that the first block in the storage belongs to the configured chain. If the storage is
+/// empty and the chain has a genesis state, commits it as block 0.
+pub fn initialize_genesis(
+    genesis_hash: BlockHash,
+    genesis_state_config: Option<&GenesisStateConfig>,
+    storage_reader: &StorageReader,
+    storage_writer: Option<&mut StorageWriter>,
+) -> Result<(), GenesisError> {
+    if let Some(first_header) = storage_reader.begin_ro_txn()?.get_block_header(BlockNumber(0))? {
+        return verify_first_block(genesis_hash, genesis_state_config, &first_header);
+    }
+    let Some(genesis_state_config) = genesis_state_config else {
+        return Ok(());
+    };
+    let storage_writer = storage_writer.ok_or(GenesisError::ReadOnlyStorage)?;
+    commit_genesis_state(genesis_hash, genesis_state_config, storage_writer)
+}
+
+fn verify_first_block(
+    genesis_hash: BlockHash,
+    genesis_state_config: Option<&GenesisStateConfig>,
+    first_header: &BlockHeader,
+) -> Result<(), GenesisError> {
+    if first_header.parent_hash != genesis_hash {
+        return Err(GenesisError::GenesisHashMismatch {
+            stored: first_header.parent_hash,
+            configured: genesis_hash,
+        });
+    }
+    let Some(genesis_state_config) = genesis_state_config else {
+        return Ok(());
+    };
+    if first_header.block_hash != genesis_state_config.block_hash {
+        return Err(GenesisError::BlockHashMismatch {
+            stored: first_header.block_hash,
+            configured: genesis_state_config.block_hash,
+        });
+    }
+    if first_header.state_root != genesis_state_config.state_root {
+        return Err(GenesisError::StateRootMismatch {
+            actual: first_header.state_root,
+            configured: genesis_state_config.state_root,
+        });
+    }
+    Ok(())
+}
+
+fn commit_genesis_state(
+    genesis_hash: BlockHash,
+    genesis_state_config: &GenesisStateConfig,
+    storage_writer: &mut StorageWriter,
+) -> Result<(), GenesisError> {
+    info!(
+        "Committing the genesis state from {} as block 0.",
+        genesis_state_config.snapshot_path.display()
+    );
+    let snapshot: StateDiff =
+        serde_json::from_reader(BufReader::new(File::open(&genesis_state_config.snapshot_path)?))?;
+    let (thin_state_diff, classes, deprecated_classes) = ThinStateDiff::from_state_diff(snapshot);
+
+    let state_root = calculate_global_root(&thin_state_diff);
+    if state_root != genesis_state_config.state_root {
+        return Err(GenesisError::StateRootMismatch {
+            actual: state_root,
+            configured: genesis_state_config.state_root,
+        });
+    }
+
+    let header = BlockHeader {
+        block_hash: genesis_state_config.block_hash,
+        parent_hash: genesis_hash,
+        block_number: BlockNumber(0),
+        state_root,
+        state_diff_commitment: Some(calculate_state_diff_commitment(
+            &thin_state_diff,
+            StateDiffVersion::V0,
+        )),
+        state_diff_length: Some(thin_state_diff.len()),
+        n_transactions: Some(0),
+        n_events: Some(0),
+        ..Default::default()
+    };
+    let classes =
+        classes.iter().map(|(class_hash, class)| (*class_hash, class)).collect::<Vec<_>>();
+    let deprecated_classes = deprecated_classes
+        .iter()
+        .map(|(class_hash, class)| (*class_hash, class))
+        .collect::<Vec<_>>();
+    storage_writer.set_component(StorageWriterComponent::Genesis);
+    storage_writer
+        .begin_rw_txn()?
+        .append_header(BlockNumber(0), &header)?
+        // Nobody signs the genesis block, but a header is served to peers with its signature.
+        .append_block_signature(BlockNumber(0), &BlockSignature::default())?
+        .append_body(BlockNumber(0), BlockBody::default())?
+        .append_state_diff(BlockNumber(0), thin_state_diff)?
+        .append_classes(BlockNumber(0), &classes, &deprecated_classes)?
+        .commit()?;
+    info!("Committed the genesis state with the state root {state_root}.");
+    Ok(())
+}
@@ -0,0 +1,132 @@
+use std::path::Path;
+
+use papyrus_common::global_root::calculate_global_root;
+use papyrus_storage::header::{HeaderStorageReader, HeaderStorageWriter};
+use papyrus_storage::state::StateStorageReader;
+use papyrus_storage::test_utils::get_test_storage;
+use starknet_api::block::{BlockHash, BlockHeader, BlockNumber};
+use starknet_api::core::{ContractAddress, GlobalRoot, Nonce};
+use starknet_api::hash::StarkHash;
+use starknet_api::state::{StateDiff, StorageKey, ThinStateDiff};
+
+use crate::genesis::{initialize_genesis, GenesisError, GenesisStateConfig};
+
+const GENESIS_HASH: BlockHash = BlockHash(StarkHash::ZERO);
+const BLOCK_HASH: BlockHash = BlockHash(StarkHash::ONE);
+
+fn genesis_state() -> StateDiff {
+    let address = ContractAddress::from(1_u128);
+    StateDiff {
+        storage_diffs: [(address, [(StorageKey::from(2_u128), StarkHash::from(3_u8))].into())]
+            .into_iter()
+            .collect(),
+        nonces: [(address, Nonce(StarkHash::ONE))].into_iter().collect(),
+        ..Default::default()
+    }
+}
+
+fn write_snapshot(dir: &Path, state_diff: &StateDiff) -> GenesisStateConfig {
+    let snapshot_path = dir.join("genesis_state.json");
+    std::fs::write(&snapshot_path, serde_json::to_vec(state_diff).unwrap()).unwrap();
+    let (thin_state_diff, _, _) = ThinStateDiff::from_state_diff(state_diff.clone());
+    GenesisStateConfig {
+        snapshot_path,
+        block_hash: BLOCK_HASH,
+        state_root: calculate_global_root(&thin_state_diff),
+    }
+}
+
+#[test]
+fn genesis_state_is_committed_as_block_0_to_an_empty_storage() {
+    let ((storage_reader, mut storage_writer), _temp_dir) = get_test_storage();
+    let snapshot_dir = tempfile::tempdir().unwrap();
+    let genesis_state_config = write_snapshot(snapshot_dir.path(), &genesis_state());
+
+    initialize_genesis(
+        GENESIS_HASH,
+        Some(&genesis_state_config),
+        &storage_reader,
+        Some(&mut storage_writer),
+    )
+    .unwrap();
+
+    let txn = storage_reader.begin_ro_txn().unwrap();
+    assert_eq!(txn.get_header_marker().unwrap(), BlockNumber(1));
+    assert_eq!(txn.get_state_marker().unwrap(), BlockNumber(1));
+    let header = txn.get_block_header(BlockNumber(0)).unwrap().unwrap();
+    assert_eq!(header.parent_hash, GENESIS_HASH);
+    assert_eq!(header.block_hash, BLOCK_HASH);
+    assert_eq!(header.state_root, genesis_state_config.state_root);
+    let (thin_state_diff, _, _) = ThinStateDiff::from_state_diff(genesis_state());
+    assert_eq!(txn.get_state_diff(BlockNumber(0)).unwrap(), Some(thin_state_diff));
+    drop(txn);
+
+    // The committed block matches the config, so the next start of the node passes.
+    initialize_genesis(
+        GENESIS_HASH,
+        Some(&genesis_state_config),
+        &storage_reader,
+        Some(&mut storage_writer),
+    )
+    .unwrap();
+}
+
+#[test]
+fn snapshot_with_a_different_state_root_is_rejected() {
+    let ((storage_reader, mut storage_writer), _temp_dir) = get_test_storage();
+    let snapshot_dir = tempfile::tempdir().unwrap();
+    let genesis_state_config = GenesisStateConfig {
+        state_root: GlobalRoot(StarkHash::ONE),
+        ..write_snapshot(snapshot_dir.path(), &genesis_state())
+    };
+
+    let result = initialize_genesis(
+        GENESIS_HASH,
+        Some(&genesis_state_config),
+        &storage_reader,
+        Some(&mut storage_writer),
+    );
+
+    assert!(matches!(result, Err(GenesisError::StateRootMismatch { .. })));
+    assert_eq!(storage_reader.begin_ro_txn().unwrap().get_header_marker().unwrap(), BlockNumber(0));
+}
+
+#[test]
+fn storage_of_another_chain_is_rejected() {
+    let ((storage_reader, mut storage_writer), _temp_dir) = get_test_storage();
+    let header = BlockHeader {
+        block_hash: BLOCK_HASH,
+        parent_hash: BlockHash(StarkHash::TWO),
+        ..Default::default()
+    };
+    storage_writer
+        .begin_rw_txn()
+        .unwrap()
+        .append_header(BlockNumber(0), &header)
+        .unwrap()
+        .commit()
+        .unwrap();
+
+    let result = initialize_genesis(GENESIS_HASH, None, &storage_reader, Some(&mut storage_writer));
+    assert!(matches!(result, Err(GenesisError::GenesisHashMismatch { .. })));
+
+    // The genesis state is checked even when the genesis hash matches.
+    let snapshot_dir = tempfile::tempdir().unwrap();
+    let genesis_state_config = write_snapshot(snapshot_dir.path(), &genesis_state());
+    let result = initialize_genesis(
+        header.parent_hash,
+        Some(&genesis_state_config),
+        &storage_reader,
+        Some(&mut storage_writer),
+    );
+    assert!(matches!(result, Err(GenesisError::StateRootMismatch { .. })));
+}
+
+#[test]
+fn empty_storage_without_a_genesis_state_is_left_empty() {
+    let ((storage_reader, _storage_writer), _temp_dir) = get_test_storage();
+
+    initialize_genesis(GENESIS_HASH, None, &storage_reader, None).unwrap();
+
+    assert_eq!(storage_reader.begin_ro_txn().unwrap().get_header_marker().unwrap(), BlockNumber(0));
+}
@@ -4,6 +4,16 @@
 
 #[allow(unused_imports)]
 pub mod config;
+pub mod export;
+pub mod genesis;
+pub mod localnet;
+pub mod logging;
+#[cfg(feature = "rpc")]
+pub mod network_info;
+pub mod p2p_interop;
 #[cfg(test)]
 mod precision_test;
+pub mod storage_scan;
+pub mod supervisor;
+pub mod transaction_broadcast;
 pub mod version;
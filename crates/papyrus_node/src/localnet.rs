@@ -0,0 +1,382 @@
+//! Generates the config files of a local network of nodes, for development and testing.
+//!
+//! Every node gets a directory `node_<index>` in the output directory, which contains the node's
+//! `config.json` and its storage, and is run with `--config_file <node dir>/config.json`. The
+//! config files are complete: they contain every param of the node, serialized the same way
+//! the default config is, so they are accepted by any node built from the same source with the
+//! same features. Only the components that are compiled into the generator are enabled.
+//!
+//! The network is deterministic. Node i uses the `PORTS_PER_NODE` ports that start at
+//! `base_port + PORTS_PER_NODE * i`, and its secret key is derived from the seed and from i, so
+//! generating the same network again gives the nodes the same peer ids.
+//!
+//! A node accepts a single bootstrap peer, so the topology only determines whom each node
+//! bootstraps from. The nodes then find the rest of the network through the discovery of their
+//! bootstrap peer.
+//!
+//! Node 0 is the source of the network's blocks (e.g. they're injected through its admin server)
+//! and doesn't sync. The other nodes sync from their peers over p2p. The first `num_validators`
+//! nodes are consensus validators, whose validator id is their index. Since the validator id isn't
+//! part of the config, they are run with `CONSENSUS_VALIDATOR_ID=<index>`.
+//!
+//! A summary of the nodes is written to `localnet.json` in the output directory.
+
+#[cfg(test)]
+#[path = "localnet_generator_test.rs"]
+mod localnet_generator_test;
+
+use std::fs::{self, File};
+use std::io::{BufWriter, Write};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+
+use clap::{Arg, ArgMatches, Command};
+use papyrus_config::dumping::SerializeConfig;
+use papyrus_config::presentation::get_config_presentation;
+use papyrus_config::SerializedContent;
+use papyrus_monitoring_gateway::MonitoringGatewayConfig;
+use papyrus_network::NetworkConfig;
+use papyrus_p2p_sync::P2PSyncConfig;
+use serde::{Deserialize, Serialize};
+use serde_json::{Map, Value};
+use starknet_api::core::ChainId;
+
+#[cfg(feature = "rpc")]
+use crate::config::components::ComponentsConfig;
+use crate::config::pointers::CONFIG_POINTERS;
+use crate::config::presets::ChainPreset;
+use crate::config::NodeConfig;
+
+/// The name of the file inside the output directory that summarizes the generated nodes.
+pub const SUMMARY_FILE_NAME: &str = "localnet.json";
+/// The name of the config file inside the directory of each node.
+pub const NODE_CONFIG_FILE_NAME: &str = "config.json";
+/// The number of consecutive ports each node uses, starting at its first port. In order, they are
+/// the TCP, QUIC, monitoring, admin and RPC ports.
+pub const PORTS_PER_NODE: u16 = 5;
+/// The chain id of the generated networks.
+pub const LOCALNET_CHAIN_ID: &str = "SN_LOCALNET";
+const LOCALHOST: &str = "127.0.0.1";
+const DEFAULT_BASE_PORT: &str = "20000";
+
+#[derive(thiserror::Error, Debug)]
+pub enum LocalnetError {
+    #[error("A network of {num_nodes} nodes can't have {num_validators} validators.")]
+    TooManyValidators { num_nodes: usize, num_validators: usize },
+    #[error(
+        "The ports of {num_nodes} nodes starting at port {base_port} exceed the maximal port \
+         number."
+    )]
+    PortsOutOfRange { num_nodes: usize, base_port: u16 },
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    SerdeError(#[from] serde_json::Error),
+}
+
+/// Whom each node bootstraps from.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Topology {
+    /// Every node bootstraps from node 0.
+    Star,
+    /// Every node bootstraps from the node before it.
+    Chain,
+}
+
+impl FromStr for Topology {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "star" => Ok(Topology::Star),
+            "chain" => Ok(Topology::Chain),
+            _ => Err(format!("Unknown topology {s}. Expected star or chain.")),
+        }
+    }
+}
+
+/// The parameters of a generated network.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct LocalnetConfig {
+    pub num_nodes: usize,
+    pub base_port: u16,
+    pub topology: Topology,
+    pub num_validators: usize,
+    /// Networks generated with different seeds have different peer ids.
+    pub seed: u64,
+    pub output_dir: PathBuf,
+}
+
+/// The summary of a generated node.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LocalnetNode {
+    pub index: usize,
+    pub peer_id: String,
+    /// The address other nodes dial this node in.
+    pub multiaddr: String,
+    pub config_file: PathBuf,
+    pub monitoring_address: String,
+    pub admin_address: String,
+    /// None if the node was built without the rpc feature.
+    pub rpc_address: Option<String>,
+    /// The value of CONSENSUS_VALIDATOR_ID to run the node with, or None if it isn't a validator.
+    pub validator_id: Option<usize>,
+    /// The index of the node this node bootstraps from, or None for node 0.
+    pub bootstrap_node: Option<usize>,
+}
+
+/// Writes the config files of the nodes and the summary file into the output directory, and
+/// returns the summary.
+pub fn generate_localnet(config: &LocalnetConfig) -> Result<Vec<LocalnetNode>, LocalnetError> {
+    if config.num_validators > config.num_nodes {
+        return Err(LocalnetError::TooManyValidators {
+            num_nodes: config.num_nodes,
+            num_validators: config.num_validators,
+        });
+    }
+    let max_port = u64::from(config.base_port)
+        + u64::from(PORTS_PER_NODE) * u64::try_from(config.num_nodes).expect("usize fits in u64");
+    if max_port > u64::from(u16::MAX) + 1 {
+        return Err(LocalnetError::PortsOutOfRange {
+            num_nodes: config.num_nodes,
+            base_port: config.base_port,
+        });
+    }
+    fs::create_dir_all(&config.output_dir)?;
+    // The storage paths in the config files are absolute, so that the nodes can be run from any
+    // directory.
+    let output_dir = config.output_dir.canonicalize()?;
+
+    let mut nodes: Vec<LocalnetNode> = Vec::with_capacity(config.num_nodes);
+    for index in 0..config.num_nodes {
+        let node_dir = output_dir.join(format!("node_{index}"));
+        fs::create_dir_all(&node_dir)?;
+        let bootstrap_node = get_bootstrap_node(config.topology, index);
+        let bootstrap_multiaddr =
+            bootstrap_node.map(|bootstrap_node| nodes[bootstrap_node].multiaddr.clone());
+        let node_config = node_config(config, index, &node_dir, bootstrap_multiaddr);
+        let config_file = node_dir.join(NODE_CONFIG_FILE_NAME);
+        write_json(&config_file, &config_file_values(&node_config))?;
+
+        let network_config = node_config.network.as_ref().expect("Localnet nodes run a network");
+        let peer_id = network_config.peer_id().expect("Localnet nodes have a secret key");
+        #[cfg(feature = "rpc")]
+        let rpc_address = Some(node_config.rpc.server_address.clone());
+        #[cfg(not(feature = "rpc"))]
+        let rpc_address = None;
+        nodes.push(LocalnetNode {
+            index,
+            peer_id: peer_id.to_string(),
+            multiaddr: format!("/ip4/{LOCALHOST}/tcp/{}/p2p/{peer_id}", network_config.tcp_port),
+            config_file,
+            monitoring_address: node_config.monitoring_gateway.server_address.clone(),
+            admin_address: node_config
+                .monitoring_gateway
+                .admin_server_address
+                .clone()
+                .expect("Localnet nodes run an admin server"),
+            rpc_address,
+            validator_id: (index < config.num_validators).then_some(index),
+            bootstrap_node,
+        });
+    }
+    write_json(&output_dir.join(SUMMARY_FILE_NAME), &nodes)?;
+    Ok(nodes)
+}
+
+/// Runs the generator with the given command line arguments, where the first argument is the name
+/// of the command, and prints how to run the nodes.
+pub fn run_localnet_command(args: Vec<String>) -> Result<(), LocalnetError> {
+    let matches = get_command().get_matches_from(args);
+    let config = get_localnet_config(&matches);
+    let nodes = generate_localnet(&config)?;
+    println!("Generated {} nodes in {}.", nodes.len(), config.output_dir.display());
+    for node in nodes {
+        let env = node
+            .validator_id
+            .map(|validator_id| format!("CONSENSUS_VALIDATOR_ID={validator_id} "))
+            .unwrap_or_default();
+        println!(
+            "Node {} ({}): {env}papyrus_node --config_file {}",
+            node.index,
+            node.peer_id,
+            node.config_file.display()
+        );
+    }
+    Ok(())
+}
+
+fn get_command() -> Command {
+    Command::new("generate_localnet")
+        .about("Generates the config files of a local network of nodes.")
+        .arg(
+            Arg::new("num_nodes")
+                .long("num_nodes")
+                .required(true)
+                .value_parser(clap::value_parser!(usize))
+                .help("The number of nodes in the network."),
+        )
+        .arg(
+            Arg::new("output")
+                .long("output")
+                .required(true)
+                .value_parser(clap::value_parser!(PathBuf))
+                .help("The directory to write the config files, the storages and the summary to."),
+        )
+        .arg(
+            Arg::new("base_port")
+                .long("base_port")
+                .default_value(DEFAULT_BASE_PORT)
+                .value_parser(clap::value_parser!(u16))
+                .help(
+                    "The first port of node 0. The ports of every node start right after the \
+                     ports of the node before it.",
+                ),
+        )
+        .arg(
+            Arg::new("topology")
+                .long("topology")
+                .default_value("star")
+                .value_parser(Topology::from_str)
+                .help(
+                    "Whom each node bootstraps from: star (node 0) or chain (the previous node).",
+                ),
+        )
+        .arg(
+            Arg::new("num_validators")
+                .long("num_validators")
+                .default_value("0")
+                .value_parser(clap::value_parser!(usize))
+                .help(
+                    "The number of consensus validators, which are the first nodes. E.g. 1 for a \
+                     single validator and full nodes.",
+                ),
+        )
+        .arg(
+            Arg::new("seed")
+                .long("seed")
+                .default_value("0")
+                .value_parser(clap::value_parser!(u64))
+                .help("The seed of the secret keys of the nodes."),
+        )
+}
+
+fn get_localnet_config(matches: &ArgMatches) -> LocalnetConfig {
+    LocalnetConfig {
+        num_nodes: *matches.get_one::<usize>("num_nodes").expect("Failed parsing num_nodes"),
+        base_port: *matches.get_one::<u16>("base_port").expect("Failed parsing base_port"),
+        topology: *matches.get_one::<Topology>("topology").expect("Failed parsing topology"),
+        num_validators: *matches
+            .get_one::<usize>("num_validators")
+            .expect("Failed parsing num_validators"),
+        seed: *matches.get_one::<u64>("seed").expect("Failed parsing seed"),
+        output_dir: matches.get_one::<PathBuf>("output").expect("Failed parsing output").clone(),
+    }
+}
+
+fn get_bootstrap_node(topology: Topology, index: usize) -> Option<usize> {
+    let previous_node = index.checked_sub(1)?;
+    match topology {
+        Topology::Star => Some(0),
+        Topology::Chain => Some(previous_node),
+    }
+}
+
+fn node_config(
+    config: &LocalnetConfig,
+    index: usize,
+    node_dir: &Path,
+    bootstrap_multiaddr: Option<String>,
+) -> NodeConfig {
+    let first_port = config.base_port
+        + PORTS_PER_NODE * u16::try_from(index).expect("The ports were checked to fit in u16");
+    let port = |offset: u16| first_port + offset;
+
+    let mut node_config = NodeConfig {
+        chain: ChainPreset::Custom,
+        sync: None,
+        p2p_sync: (index != 0).then(P2PSyncConfig::default),
+        // The nodes run with the binary that generated their configs.
+        components: ComponentsConfig::compiled_in(),
+        ..Default::default()
+    };
+    node_config.storage.db_config.chain_id = ChainId::Other(LOCALNET_CHAIN_ID.to_owned());
+    node_config.storage.db_config.path_prefix = node_dir.join("data");
+
+    let mut network_config = NetworkConfig {
+        tcp_port: port(0),
+        quic_port: port(1),
+        bootstrap_peer_multiaddr: bootstrap_multiaddr
+            .map(|multiaddr| multiaddr.parse().expect("Generated multiaddrs should be valid")),
+        ..Default::default()
+    };
+    network_config.set_secret_key(secret_key(config.seed, index));
+    node_config.network = Some(network_config);
+
+    node_config.monitoring_gateway = MonitoringGatewayConfig {
+        server_address: format!("{LOCALHOST}:{}", port(2)),
+        admin_server_address: Some(format!("{LOCALHOST}:{}", port(3))),
+        ..Default::default()
+    };
+    #[cfg(feature = "rpc")]
+    {
+        node_config.rpc.chain_id = node_config.storage.db_config.chain_id.clone();
+        node_config.rpc.server_address = format!("{LOCALHOST}:{}", port(4));
+    }
+    node_config
+}
+
+// An ed25519 secret key, which is any 32 bytes. The seed and the index of the node are encoded in
+// it, so that every node of every seed has a different key.
+fn secret_key(seed: u64, index: usize) -> Vec<u8> {
+    let index = u64::try_from(index).expect("usize fits in u64");
+    let mut secret_key = vec![0; 32];
+    secret_key[..8].copy_from_slice(&seed.to_be_bytes());
+    secret_key[24..].copy_from_slice(&index.to_be_bytes());
+    secret_key
+}
+
+// Returns the values of all the params of the config, in the format of a config file that is
+// given with --config_file.
+fn config_file_values(config: &NodeConfig) -> Map<String, Value> {
+    // The required and generated params have no value in the dump, so their values are taken from
+    // the config itself.
+    let config_presentation =
+        get_config_presentation(config, true).expect("A config should be serializable");
+    #[allow(unused_mut)]
+    let mut values: Map<String, Value> = config
+        .dump()
+        .into_iter()
+        .filter_map(|(param_path, serialized_param)| {
+            let value = match serialized_param.content {
+                SerializedContent::DefaultValue(value) => value,
+                SerializedContent::ParamType(_) => param_path
+                    .split('.')
+                    .fold(&config_presentation, |entry, config_name| &entry[config_name])
+                    .clone(),
+                SerializedContent::PointerTarget(_) => return None,
+            };
+            Some((param_path, value))
+        })
+        .collect();
+    // The params that point to a shared param can't be set directly, so the shared param is set
+    // instead.
+    #[cfg(feature = "rpc")]
+    for ((target_param, _), pointing_params) in CONFIG_POINTERS.iter() {
+        let mut target_value = None;
+        for pointing_param in pointing_params {
+            target_value = values.remove(pointing_param).or(target_value);
+        }
+        if let Some(target_value) = target_value {
+            values.insert(target_param.clone(), target_value);
+        }
+    }
+    values
+}
+
+fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<(), LocalnetError> {
+    let mut writer = BufWriter::new(File::create(path)?);
+    serde_json::to_writer_pretty(&mut writer, value)?;
+    writer.flush()?;
+    Ok(())
+}
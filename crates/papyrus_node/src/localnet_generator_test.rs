@@ -0,0 +1,131 @@
+use std::collections::HashSet;
+use std::env;
+use std::fs::File;
+
+use assert_matches::assert_matches;
+use pretty_assertions::assert_eq;
+use tempfile::TempDir;
+use test_utils::get_absolute_path;
+
+use crate::config::NodeConfig;
+use crate::localnet::{
+    generate_localnet,
+    node_config,
+    LocalnetConfig,
+    LocalnetError,
+    LocalnetNode,
+    Topology,
+    PORTS_PER_NODE,
+    SUMMARY_FILE_NAME,
+};
+
+const NUM_NODES: usize = 3;
+
+fn localnet_config(output_dir: &TempDir) -> LocalnetConfig {
+    LocalnetConfig {
+        num_nodes: NUM_NODES,
+        base_port: 20000,
+        topology: Topology::Star,
+        num_validators: 1,
+        seed: 0,
+        output_dir: output_dir.path().to_path_buf(),
+    }
+}
+
+#[test]
+fn generated_config_files_are_loaded_by_the_node() {
+    let output_dir = TempDir::new().unwrap();
+    let config = localnet_config(&output_dir);
+    let nodes = generate_localnet(&config).unwrap();
+
+    env::set_current_dir(get_absolute_path("")).expect("Couldn't set working dir.");
+    for node in &nodes {
+        let loaded_config = NodeConfig::load_and_process(vec![
+            "Papyrus".to_owned(),
+            "--config_file".to_owned(),
+            node.config_file.to_str().unwrap().to_owned(),
+        ])
+        .unwrap();
+        let node_dir = node.config_file.parent().unwrap();
+        let bootstrap_multiaddr = node.bootstrap_node.map(|index| nodes[index].multiaddr.clone());
+        assert_eq!(loaded_config, node_config(&config, node.index, node_dir, bootstrap_multiaddr));
+
+        let network_config = loaded_config.network.unwrap();
+        assert_eq!(network_config.peer_id().unwrap().to_string(), node.peer_id);
+        // Only node 0 doesn't sync.
+        assert_eq!(loaded_config.p2p_sync.is_some(), node.index != 0);
+    }
+
+    let summary: Vec<LocalnetNode> =
+        serde_json::from_reader(File::open(output_dir.path().join(SUMMARY_FILE_NAME)).unwrap())
+            .unwrap();
+    assert_eq!(summary, nodes);
+}
+
+#[test]
+fn nodes_have_distinct_ports_storages_and_peer_ids() {
+    let output_dir = TempDir::new().unwrap();
+    let nodes = generate_localnet(&localnet_config(&output_dir)).unwrap();
+
+    let peer_ids: HashSet<_> = nodes.iter().map(|node| &node.peer_id).collect();
+    let multiaddrs: HashSet<_> = nodes.iter().map(|node| &node.multiaddr).collect();
+    let config_files: HashSet<_> = nodes.iter().map(|node| &node.config_file).collect();
+    let server_addresses: HashSet<_> = nodes
+        .iter()
+        .flat_map(|node| [&node.monitoring_address, &node.admin_address])
+        .chain(nodes.iter().filter_map(|node| node.rpc_address.as_ref()))
+        .collect();
+    assert_eq!(peer_ids.len(), NUM_NODES);
+    assert_eq!(multiaddrs.len(), NUM_NODES);
+    assert_eq!(config_files.len(), NUM_NODES);
+    assert_eq!(
+        server_addresses.len(),
+        nodes.iter().map(|node| 2 + usize::from(node.rpc_address.is_some())).sum::<usize>()
+    );
+    assert_eq!(
+        nodes.iter().map(|node| node.validator_id).collect::<Vec<_>>(),
+        vec![Some(0), None, None]
+    );
+}
+
+#[test]
+fn generation_is_deterministic() {
+    let peer_ids = |seed| {
+        let output_dir = TempDir::new().unwrap();
+        let config = LocalnetConfig { seed, ..localnet_config(&output_dir) };
+        generate_localnet(&config)
+            .unwrap()
+            .into_iter()
+            .map(|node| node.peer_id)
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(peer_ids(0), peer_ids(0));
+    assert_ne!(peer_ids(0), peer_ids(1));
+}
+
+#[test]
+fn topology_determines_the_bootstrap_nodes() {
+    for (topology, expected_bootstrap_nodes) in
+        [(Topology::Star, [None, Some(0), Some(0)]), (Topology::Chain, [None, Some(0), Some(1)])]
+    {
+        let output_dir = TempDir::new().unwrap();
+        let config = LocalnetConfig { topology, ..localnet_config(&output_dir) };
+        let bootstrap_nodes: Vec<_> = generate_localnet(&config)
+            .unwrap()
+            .into_iter()
+            .map(|node| node.bootstrap_node)
+            .collect();
+        assert_eq!(bootstrap_nodes, expected_bootstrap_nodes);
+    }
+}
+
+#[test]
+fn invalid_localnet_config() {
+    let output_dir = TempDir::new().unwrap();
+    let config = LocalnetConfig { num_validators: NUM_NODES + 1, ..localnet_config(&output_dir) };
+    assert_matches!(generate_localnet(&config), Err(LocalnetError::TooManyValidators { .. }));
+
+    let config =
+        LocalnetConfig { base_port: u16::MAX - PORTS_PER_NODE, ..localnet_config(&output_dir) };
+    assert_matches!(generate_localnet(&config), Err(LocalnetError::PortsOutOfRange { .. }));
+}
@@ -0,0 +1,530 @@
+// End-to-end tests of localnets of nodes: node A holds blocks in its storage and the other nodes
+// sync them from A over p2p.
+
+use std::future::Future;
+use std::net::{TcpListener, UdpSocket};
+use std::time::{Duration, Instant};
+
+use papyrus_common::block_hash::{calculate_body_commitments, BodyCommitments};
+use papyrus_common::transaction_hash::get_transaction_hash;
+use papyrus_common::TransactionOptions;
+use papyrus_monitoring_gateway::MonitoringGatewayConfig;
+use papyrus_network::NetworkConfig;
+use papyrus_node::config::components::ComponentsConfig;
+use papyrus_node::config::NodeConfig;
+use papyrus_p2p_sync::{inject_block, P2PSyncConfig};
+use papyrus_protobuf::sync::{DeclaredClass, FullBlock, SignedBlockHeader, StateDiffChunk};
+use papyrus_storage::body::{BodyStorageReader, BodyStorageWriter};
+use papyrus_storage::header::{HeaderStorageReader, HeaderStorageWriter};
+use papyrus_storage::state::{StateStorageReader, StateStorageWriter};
+use papyrus_storage::{open_storage, StorageReader, StorageWriter};
+use starknet_api::block::{
+    BlockBody,
+    BlockHash,
+    BlockHeader,
+    BlockNumber,
+    BlockSignature,
+    StarknetVersion,
+};
+use starknet_api::core::{ClassHash, CompiledClassHash, ContractAddress, Nonce};
+use starknet_api::hash::StarkHash;
+use starknet_api::state::ThinStateDiff;
+use starknet_api::transaction::{
+    Event,
+    EventContent,
+    EventData,
+    InvokeTransaction,
+    InvokeTransactionOutput,
+    InvokeTransactionV1,
+    Transaction,
+    TransactionOutput,
+};
+use tempfile::TempDir;
+
+use crate::run_threads_with_storage;
+
+const NUM_BLOCKS: u64 = 30;
+const NUM_TRANSACTIONS_PER_BLOCK: usize = 2;
+const TAMPERED_BLOCK_NUMBER: u64 = 5;
+const LOCALNET_TIMEOUT: Duration = Duration::from_secs(60);
+const POLL_INTERVAL: Duration = Duration::from_millis(100);
+// The wait period of node B's sync before it queries again for new blocks, in the tip latency test.
+const TIP_WAIT_PERIOD: Duration = Duration::from_secs(4);
+
+#[tokio::test]
+async fn p2p_sync_between_two_nodes() {
+    // Node A only serves its blocks.
+    let (mut config_a, _temp_dir_a) = localnet_node_config();
+    config_a.network.as_mut().unwrap().block_range_advertisement_interval = Duration::from_secs(1);
+    let (storage_reader_a, mut storage_writer_a) = open_storage(config_a.storage.clone()).unwrap();
+    populate_storage(&mut storage_writer_a, None);
+    let monitoring_address_a = config_a.monitoring_gateway.server_address.clone();
+    let admin_address_a = config_a.monitoring_gateway.admin_server_address.clone().unwrap();
+    let tcp_port_a = config_a.network.as_ref().unwrap().tcp_port;
+    let node_a =
+        run_threads_with_storage(config_a, storage_reader_a.clone(), Some(storage_writer_a), None);
+    tokio::pin!(node_a);
+
+    let peer_id_a = tokio::select! {
+        res = &mut node_a => panic!("Node A stopped: {res:?}"),
+        peer_id = poll_until_some(|| get_text(&monitoring_address_a, "monitoring/peer_id")) => {
+            peer_id
+        }
+    };
+
+    // Node B syncs from node A, which is its bootstrap peer.
+    let (mut config_b, _temp_dir_b) = localnet_node_config();
+    config_b.p2p_sync = Some(P2PSyncConfig {
+        wait_period_for_new_data: Duration::from_millis(100),
+        ..Default::default()
+    });
+    config_b.network.as_mut().unwrap().bootstrap_peer_multiaddr =
+        Some(format!("/ip4/127.0.0.1/tcp/{tcp_port_a}/p2p/{peer_id_a}").parse().unwrap());
+    let (storage_reader_b, storage_writer_b) = open_storage(config_b.storage.clone()).unwrap();
+    let monitoring_address_b = config_b.monitoring_gateway.server_address.clone();
+    let admin_address_b = config_b.monitoring_gateway.admin_server_address.clone().unwrap();
+    let node_b =
+        run_threads_with_storage(config_b, storage_reader_b.clone(), Some(storage_writer_b), None);
+
+    let assertions = async {
+        poll_until_some(|| async {
+            let (header_marker_b, state_marker_b) = markers(&storage_reader_b);
+            (header_marker_b == BlockNumber(NUM_BLOCKS)
+                && state_marker_b == BlockNumber(NUM_BLOCKS))
+            .then_some(())
+        })
+        .await;
+        assert_eq!(markers(&storage_reader_a), markers(&storage_reader_b));
+
+        let peer_id_b = get_text(&monitoring_address_b, "monitoring/peer_id").await.unwrap();
+        for (admin_address, expected_peer_id) in
+            [(&admin_address_a, &peer_id_b), (&admin_address_b, &peer_id_a)]
+        {
+            let peer_ids = poll_until_some(|| async {
+                let peer_ids = get_peer_ids(admin_address).await?;
+                (!peer_ids.is_empty()).then_some(peer_ids)
+            })
+            .await;
+            assert_eq!(&peer_ids, &[expected_peer_id.clone()]);
+        }
+    };
+
+    tokio::select! {
+        res = &mut node_a => panic!("Node A stopped: {res:?}"),
+        res = node_b => panic!("Node B stopped: {res:?}"),
+        res = tokio::time::timeout(LOCALNET_TIMEOUT, assertions) => {
+            res.expect("Node B didn't sync from node A in time")
+        }
+    };
+}
+
+// Node A serves its blocks, node B syncs them with the combined block download and node C syncs
+// them with the regular pipelined download. B should have the same headers and state diffs as C,
+// and the same bodies as A.
+#[tokio::test]
+async fn combined_block_download_syncs_the_same_storage_as_the_regular_mode() {
+    let (mut config_a, _temp_dir_a) = localnet_node_config();
+    let (storage_reader_a, mut storage_writer_a) = open_storage(config_a.storage.clone()).unwrap();
+    populate_storage(&mut storage_writer_a, None);
+    config_a.network.as_mut().unwrap().block_range_advertisement_interval = Duration::from_secs(1);
+    let monitoring_address_a = config_a.monitoring_gateway.server_address.clone();
+    let tcp_port_a = config_a.network.as_ref().unwrap().tcp_port;
+    let node_a =
+        run_threads_with_storage(config_a, storage_reader_a.clone(), Some(storage_writer_a), None);
+    tokio::pin!(node_a);
+
+    let peer_id_a = tokio::select! {
+        res = &mut node_a => panic!("Node A stopped: {res:?}"),
+        peer_id = poll_until_some(|| get_text(&monitoring_address_a, "monitoring/peer_id")) => {
+            peer_id
+        }
+    };
+
+    let syncing_node_config = |combined_block_download| {
+        let (mut config, temp_dir) = localnet_node_config();
+        config.p2p_sync = Some(P2PSyncConfig {
+            wait_period_for_new_data: Duration::from_millis(100),
+            combined_block_download,
+            ..Default::default()
+        });
+        config.network.as_mut().unwrap().bootstrap_peer_multiaddr =
+            Some(format!("/ip4/127.0.0.1/tcp/{tcp_port_a}/p2p/{peer_id_a}").parse().unwrap());
+        (config, temp_dir)
+    };
+    let (config_b, _temp_dir_b) = syncing_node_config(true);
+    let (storage_reader_b, storage_writer_b) = open_storage(config_b.storage.clone()).unwrap();
+    let node_b =
+        run_threads_with_storage(config_b, storage_reader_b.clone(), Some(storage_writer_b), None);
+    let (config_c, _temp_dir_c) = syncing_node_config(false);
+    let (storage_reader_c, storage_writer_c) = open_storage(config_c.storage.clone()).unwrap();
+    let node_c =
+        run_threads_with_storage(config_c, storage_reader_c.clone(), Some(storage_writer_c), None);
+
+    let assertions = async {
+        let synced_markers = (BlockNumber(NUM_BLOCKS), BlockNumber(NUM_BLOCKS));
+        poll_until_some(|| async {
+            (markers(&storage_reader_b) == synced_markers
+                && markers(&storage_reader_c) == synced_markers)
+                .then_some(())
+        })
+        .await;
+
+        let txn_a = storage_reader_a.begin_ro_txn().unwrap();
+        let txn_b = storage_reader_b.begin_ro_txn().unwrap();
+        let txn_c = storage_reader_c.begin_ro_txn().unwrap();
+        // The combined block download advances the body marker along with the other markers.
+        assert_eq!(txn_b.get_body_marker().unwrap(), BlockNumber(NUM_BLOCKS));
+        for block_number in (0..NUM_BLOCKS).map(BlockNumber) {
+            assert_eq!(
+                txn_b.get_block_header(block_number).unwrap(),
+                txn_c.get_block_header(block_number).unwrap()
+            );
+            assert_eq!(
+                txn_b.get_block_signature(block_number).unwrap(),
+                txn_c.get_block_signature(block_number).unwrap()
+            );
+            assert_eq!(
+                txn_b.get_state_diff(block_number).unwrap(),
+                txn_c.get_state_diff(block_number).unwrap()
+            );
+            assert_eq!(
+                txn_b.get_block_transactions(block_number).unwrap(),
+                txn_a.get_block_transactions(block_number).unwrap()
+            );
+            assert_eq!(
+                txn_b.get_block_transaction_outputs(block_number).unwrap(),
+                txn_a.get_block_transaction_outputs(block_number).unwrap()
+            );
+            assert_eq!(
+                txn_b.get_block_transaction_hashes(block_number).unwrap(),
+                txn_a.get_block_transaction_hashes(block_number).unwrap()
+            );
+        }
+    };
+
+    tokio::select! {
+        res = &mut node_a => panic!("Node A stopped: {res:?}"),
+        res = node_b => panic!("Node B stopped: {res:?}"),
+        res = node_c => panic!("Node C stopped: {res:?}"),
+        res = tokio::time::timeout(LOCALNET_TIMEOUT, assertions) => {
+            res.expect("Nodes B and C didn't sync from node A in time")
+        }
+    };
+}
+
+// Node A holds a block whose events were tampered with, and node B syncs from A with the combined
+// block download. B should store the blocks before the tampered block and reject it, along with
+// the blocks after it.
+#[tokio::test]
+async fn combined_block_download_rejects_a_tampered_block() {
+    let (mut config_a, _temp_dir_a) = localnet_node_config();
+    let (storage_reader_a, mut storage_writer_a) = open_storage(config_a.storage.clone()).unwrap();
+    populate_storage(&mut storage_writer_a, Some(TAMPERED_BLOCK_NUMBER));
+    config_a.network.as_mut().unwrap().block_range_advertisement_interval = Duration::from_secs(1);
+    let monitoring_address_a = config_a.monitoring_gateway.server_address.clone();
+    let tcp_port_a = config_a.network.as_ref().unwrap().tcp_port;
+    let node_a = run_threads_with_storage(config_a, storage_reader_a, Some(storage_writer_a), None);
+    tokio::pin!(node_a);
+
+    let peer_id_a = tokio::select! {
+        res = &mut node_a => panic!("Node A stopped: {res:?}"),
+        peer_id = poll_until_some(|| get_text(&monitoring_address_a, "monitoring/peer_id")) => {
+            peer_id
+        }
+    };
+
+    let (mut config_b, _temp_dir_b) = localnet_node_config();
+    config_b.p2p_sync = Some(P2PSyncConfig {
+        wait_period_for_new_data: Duration::from_millis(100),
+        combined_block_download: true,
+        ..Default::default()
+    });
+    config_b.network.as_mut().unwrap().bootstrap_peer_multiaddr =
+        Some(format!("/ip4/127.0.0.1/tcp/{tcp_port_a}/p2p/{peer_id_a}").parse().unwrap());
+    let (storage_reader_b, storage_writer_b) = open_storage(config_b.storage.clone()).unwrap();
+    let node_b =
+        run_threads_with_storage(config_b, storage_reader_b.clone(), Some(storage_writer_b), None);
+
+    let assertions = async {
+        let tampered_block_number = BlockNumber(TAMPERED_BLOCK_NUMBER);
+        poll_until_some(|| async {
+            (markers(&storage_reader_b).0 == tampered_block_number).then_some(())
+        })
+        .await;
+        // Node B keeps retrying the tampered block meanwhile.
+        tokio::time::sleep(Duration::from_secs(3)).await;
+        let txn_b = storage_reader_b.begin_ro_txn().unwrap();
+        assert_eq!(txn_b.get_header_marker().unwrap(), tampered_block_number);
+        assert_eq!(txn_b.get_body_marker().unwrap(), tampered_block_number);
+        assert_eq!(txn_b.get_state_marker().unwrap(), tampered_block_number);
+    };
+
+    tokio::select! {
+        res = &mut node_a => panic!("Node A stopped: {res:?}"),
+        res = node_b => panic!("Node B stopped: {res:?}"),
+        res = tokio::time::timeout(LOCALNET_TIMEOUT, assertions) => {
+            res.expect("Node B didn't sync the blocks before the tampered block in time")
+        }
+    };
+}
+
+// Measures how long it takes node B to get a block that was added to node A once B reached the tip,
+// with and without following the tip. Without following the tip, B queries A every
+// TIP_WAIT_PERIOD, and the block is added half a second after one of these queries, so B learns of
+// it only TIP_WAIT_PERIOD - 0.5s later. The latencies are compared with each other rather than with
+// fixed bounds, so that a loaded machine slows down both of them.
+#[tokio::test]
+async fn following_the_tip_lowers_the_tip_latency() {
+    let (latency_with_follow, latency_without_follow) =
+        tokio::join!(measure_tip_latency(true), measure_tip_latency(false));
+    assert!(
+        latency_with_follow + TIP_WAIT_PERIOD / 2 < latency_without_follow,
+        "Following the tip took {latency_with_follow:?}, and not following it took \
+         {latency_without_follow:?}."
+    );
+}
+
+// Node A serves blocks and new blocks are injected into it through its admin server. Node B syncs
+// from A.
+async fn measure_tip_latency(follow_tip: bool) -> Duration {
+    let (mut config_a, _temp_dir_a) = localnet_node_config();
+    let (storage_reader_a, mut storage_writer_a) = open_storage(config_a.storage.clone()).unwrap();
+    populate_storage(&mut storage_writer_a, None);
+    config_a.network.as_mut().unwrap().block_range_advertisement_interval = Duration::from_secs(1);
+    let monitoring_address_a = config_a.monitoring_gateway.server_address.clone();
+    let admin_address_a = config_a.monitoring_gateway.admin_server_address.clone().unwrap();
+    let tcp_port_a = config_a.network.as_ref().unwrap().tcp_port;
+    let node_a = run_threads_with_storage(config_a, storage_reader_a, Some(storage_writer_a), None);
+    tokio::pin!(node_a);
+
+    let peer_id_a = tokio::select! {
+        res = &mut node_a => panic!("Node A stopped: {res:?}"),
+        peer_id = poll_until_some(|| get_text(&monitoring_address_a, "monitoring/peer_id")) => {
+            peer_id
+        }
+    };
+
+    let (mut config_b, _temp_dir_b) = localnet_node_config();
+    config_b.p2p_sync = Some(P2PSyncConfig {
+        wait_period_for_new_data: TIP_WAIT_PERIOD,
+        follow_tip,
+        ..Default::default()
+    });
+    config_b.network.as_mut().unwrap().bootstrap_peer_multiaddr =
+        Some(format!("/ip4/127.0.0.1/tcp/{tcp_port_a}/p2p/{peer_id_a}").parse().unwrap());
+    let (storage_reader_b, storage_writer_b) = open_storage(config_b.storage.clone()).unwrap();
+    let node_b =
+        run_threads_with_storage(config_b, storage_reader_b.clone(), Some(storage_writer_b), None);
+
+    let header_marker_b = || markers(&storage_reader_b).0;
+    let measurement = async {
+        poll_until_some(|| async { (header_marker_b() == BlockNumber(NUM_BLOCKS)).then_some(()) })
+            .await;
+        // Once node B reaches the tip, it waits TIP_WAIT_PERIOD before it queries again, and only
+        // then it may start following the tip.
+        tokio::time::sleep(TIP_WAIT_PERIOD + Duration::from_millis(500)).await;
+
+        let new_block = create_block(NUM_BLOCKS, BlockHash(StarkHash::from(NUM_BLOCKS)));
+        let response = reqwest::Client::new()
+            .post(format!("http://{admin_address_a}/admin/injectBlock"))
+            .body(Vec::<u8>::from(new_block))
+            .send()
+            .await
+            .unwrap();
+        assert!(response.status().is_success());
+        let injected_at = Instant::now();
+        poll_until_some(|| async {
+            (header_marker_b() == BlockNumber(NUM_BLOCKS + 1)).then_some(())
+        })
+        .await;
+        injected_at.elapsed()
+    };
+
+    tokio::select! {
+        res = &mut node_a => panic!("Node A stopped: {res:?}"),
+        res = node_b => panic!("Node B stopped: {res:?}"),
+        res = tokio::time::timeout(LOCALNET_TIMEOUT, measurement) => {
+            res.expect("Node B didn't get the new block in time")
+        }
+    }
+}
+
+// A node that doesn't sync from central and listens only on free loopback ports.
+fn localnet_node_config() -> (NodeConfig, TempDir) {
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = NodeConfig {
+        sync: None,
+        components: ComponentsConfig::compiled_in(),
+        ..Default::default()
+    };
+    config.storage.db_config.path_prefix = temp_dir.path().into();
+    config.network = Some(NetworkConfig {
+        tcp_port: free_tcp_port(),
+        quic_port: free_udp_port(),
+        ..Default::default()
+    });
+    config.monitoring_gateway = MonitoringGatewayConfig {
+        server_address: format!("127.0.0.1:{}", free_tcp_port()),
+        admin_server_address: Some(format!("127.0.0.1:{}", free_tcp_port())),
+        ..Default::default()
+    };
+    #[cfg(feature = "rpc")]
+    {
+        config.rpc.server_address = "127.0.0.1:0".to_string();
+    }
+    (config, temp_dir)
+}
+
+// Stores NUM_BLOCKS blocks. The events of the tampered block, if given, are changed after its
+// header was created, so they don't match its event commitment.
+fn populate_storage(storage_writer: &mut StorageWriter, tampered_block_number: Option<u64>) {
+    let mut parent_hash = BlockHash::default();
+    for block_number in 0..NUM_BLOCKS {
+        let block = create_block(block_number, parent_hash);
+        parent_hash = block.signed_header.block_header.block_hash;
+        if tampered_block_number == Some(block_number) {
+            write_tampered_block(storage_writer, block);
+        } else {
+            inject_block(storage_writer, block).unwrap();
+        }
+    }
+}
+
+// Writes the block without the validation of inject_block, which would reject it.
+fn write_tampered_block(storage_writer: &mut StorageWriter, block: FullBlock) {
+    let FullBlock { signed_header, transactions, transaction_hashes, state_diff_chunks } = block;
+    let (transactions, mut transaction_outputs): (Vec<_>, Vec<_>) =
+        transactions.into_iter().unzip();
+    let TransactionOutput::Invoke(output) = &mut transaction_outputs[0] else {
+        panic!("Expected an invoke transaction output");
+    };
+    output.events[0].content.data.0.push(StarkHash::ONE);
+    let body = BlockBody { transactions, transaction_outputs, transaction_hashes };
+    let [state_diff_chunk] = <[StateDiffChunk; 1]>::try_from(state_diff_chunks).unwrap();
+
+    let block_number = signed_header.block_header.block_number;
+    storage_writer
+        .begin_rw_txn()
+        .unwrap()
+        .append_header(block_number, &signed_header.block_header)
+        .unwrap()
+        .append_block_signature(block_number, &signed_header.signatures[0])
+        .unwrap()
+        .append_body(block_number, body)
+        .unwrap()
+        .append_state_diff(block_number, ThinStateDiff::from(state_diff_chunk))
+        .unwrap()
+        .commit()
+        .unwrap();
+}
+
+fn create_block(block_number: u64, parent_hash: BlockHash) -> FullBlock {
+    // The transactions, and so their hashes, must be unique across the blocks.
+    let first_transaction_index = block_number * u64::try_from(NUM_TRANSACTIONS_PER_BLOCK).unwrap();
+    let transactions: Vec<_> = (first_transaction_index..)
+        .take(NUM_TRANSACTIONS_PER_BLOCK)
+        .map(|transaction_index| {
+            Transaction::Invoke(InvokeTransaction::V1(InvokeTransactionV1 {
+                nonce: Nonce(StarkHash::from(transaction_index)),
+                ..Default::default()
+            }))
+        })
+        .collect();
+    // Each transaction emits an event, so that the blocks have a nonempty event commitment.
+    let transaction_outputs = (first_transaction_index..)
+        .take(NUM_TRANSACTIONS_PER_BLOCK)
+        .map(|transaction_index| {
+            TransactionOutput::Invoke(InvokeTransactionOutput {
+                events: vec![Event {
+                    from_address: ContractAddress::default(),
+                    content: EventContent {
+                        keys: vec![],
+                        data: EventData(vec![StarkHash::from(transaction_index)]),
+                    },
+                }],
+                ..Default::default()
+            })
+        })
+        .collect();
+    // The combined block download computes the hashes of the transactions it gets from peers, so
+    // the hashes must be the real ones.
+    let chain_id = NodeConfig::default().storage.db_config.chain_id;
+    let transaction_hashes = transactions
+        .iter()
+        .map(|transaction| {
+            get_transaction_hash(transaction, &chain_id, &TransactionOptions { only_query: false })
+                .unwrap()
+        })
+        .collect();
+    let body = BlockBody { transactions, transaction_outputs, transaction_hashes };
+    // The bodies are validated against the commitments of the headers.
+    let BodyCommitments { transaction_commitment, event_commitment, receipt_commitment } =
+        calculate_body_commitments(&body);
+    FullBlock {
+        signed_header: SignedBlockHeader {
+            block_header: BlockHeader {
+                block_number: BlockNumber(block_number),
+                block_hash: BlockHash(StarkHash::from(block_number + 1)),
+                parent_hash,
+                n_transactions: Some(NUM_TRANSACTIONS_PER_BLOCK),
+                transaction_commitment: Some(transaction_commitment),
+                n_events: Some(NUM_TRANSACTIONS_PER_BLOCK),
+                event_commitment: Some(event_commitment),
+                receipt_commitment: Some(receipt_commitment),
+                state_diff_length: Some(1),
+                starknet_version: StarknetVersion("0.13.2".to_owned()),
+                ..Default::default()
+            },
+            signatures: vec![BlockSignature::default()],
+            data_availability: None,
+        },
+        transactions: body.transactions.into_iter().zip(body.transaction_outputs).collect(),
+        transaction_hashes: body.transaction_hashes,
+        state_diff_chunks: vec![StateDiffChunk::DeclaredClass(DeclaredClass {
+            class_hash: ClassHash(StarkHash::from(block_number)),
+            compiled_class_hash: CompiledClassHash(StarkHash::from(block_number)),
+        })],
+    }
+}
+
+fn markers(storage_reader: &StorageReader) -> (BlockNumber, BlockNumber) {
+    let txn = storage_reader.begin_ro_txn().unwrap();
+    (txn.get_header_marker().unwrap(), txn.get_state_marker().unwrap())
+}
+
+async fn poll_until_some<T, F: Future<Output = Option<T>>>(mut poll: impl FnMut() -> F) -> T {
+    loop {
+        if let Some(value) = poll().await {
+            return value;
+        }
+        tokio::time::sleep(POLL_INTERVAL).await;
+    }
+}
+
+async fn get_text(address: &str, path: &str) -> Option<String> {
+    reqwest::get(format!("http://{address}/{path}")).await.ok()?.text().await.ok()
+}
+
+// Returns the ids of the peers the node's peer manager found.
+async fn get_peer_ids(admin_address: &str) -> Option<Vec<String>> {
+    let state: serde_json::Value =
+        reqwest::get(format!("http://{admin_address}/admin/peerManager"))
+            .await
+            .ok()?
+            .json()
+            .await
+            .ok()?;
+    state["peers"]
+        .as_array()?
+        .iter()
+        .map(|peer| peer["peer_id"].as_str().map(str::to_string))
+        .collect()
+}
+
+fn free_tcp_port() -> u16 {
+    TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
+
+fn free_udp_port() -> u16 {
+    UdpSocket::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port()
+}
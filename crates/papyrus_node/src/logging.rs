@@ -0,0 +1,169 @@
+#[cfg(test)]
+#[path = "logging_test.rs"]
+mod logging_test;
+
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+
+use papyrus_config::dumping::{ser_optional_sub_config, ser_param, SerializeConfig};
+use papyrus_config::{ParamPath, ParamPrivacyInput, SerializedParam};
+use serde::{Deserialize, Serialize};
+use tracing::metadata::LevelFilter;
+use tracing::{info_span, Span, Subscriber};
+use tracing_appender::rolling::{InitError, RollingFileAppender, Rotation};
+use tracing_subscriber::fmt::writer::BoxMakeWriter;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::registry::LookupSpan;
+use tracing_subscriber::{fmt, EnvFilter, Layer};
+
+// TODO(yair): Add to config.
+const DEFAULT_LEVEL: LevelFilter = LevelFilter::INFO;
+
+/// The format of the log lines.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human readable lines.
+    #[default]
+    Compact,
+    /// A JSON object per line. The fields of the spans a line was logged in, such as the
+    /// component, are under the "span" and "spans" keys.
+    Json,
+}
+
+#[derive(Clone, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LoggingConfig {
+    pub format: LogFormat,
+    pub include_target: bool,
+    pub include_thread: bool,
+    /// None if the logs should be written to stdout.
+    pub file: Option<LogFileConfig>,
+}
+
+impl SerializeConfig for LoggingConfig {
+    fn dump(&self) -> BTreeMap<ParamPath, SerializedParam> {
+        let mut dumped_config = BTreeMap::from_iter([
+            ser_param(
+                "format",
+                &self.format,
+                "The format of the logs. Either Compact or Json.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "include_target",
+                &self.include_target,
+                "If true, each log line contains the module path it was logged from.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "include_thread",
+                &self.include_thread,
+                "If true, each log line contains the name and id of the thread it was logged from.",
+                ParamPrivacyInput::Public,
+            ),
+        ]);
+        dumped_config.extend(ser_optional_sub_config(&self.file, "file"));
+        dumped_config
+    }
+}
+
+/// How often the log file is replaced by a new one.
+#[derive(Clone, Copy, Debug, Default, Serialize, Deserialize, PartialEq, Eq)]
+pub enum LogRotation {
+    #[default]
+    Never,
+    Hourly,
+    Daily,
+}
+
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Eq)]
+pub struct LogFileConfig {
+    pub path: PathBuf,
+    pub rotation: LogRotation,
+}
+
+impl Default for LogFileConfig {
+    fn default() -> Self {
+        LogFileConfig { path: PathBuf::from("logs/papyrus.log"), rotation: LogRotation::Never }
+    }
+}
+
+impl SerializeConfig for LogFileConfig {
+    fn dump(&self) -> BTreeMap<ParamPath, SerializedParam> {
+        BTreeMap::from_iter([
+            ser_param(
+                "path",
+                &self.path,
+                "The path of the log file. When the file is rotated, the date and time of its \
+                 creation are appended to this path.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "rotation",
+                &self.rotation,
+                "How often a new log file is created. One of Never, Hourly or Daily.",
+                ParamPrivacyInput::Public,
+            ),
+        ])
+    }
+}
+
+impl LogFileConfig {
+    fn create_appender(&self) -> Result<RollingFileAppender, InitError> {
+        let rotation = match self.rotation {
+            LogRotation::Never => Rotation::NEVER,
+            LogRotation::Hourly => Rotation::HOURLY,
+            LogRotation::Daily => Rotation::DAILY,
+        };
+        let mut builder = RollingFileAppender::builder().rotation(rotation);
+        if let Some(file_name) = self.path.file_name() {
+            builder = builder.filename_prefix(file_name.to_string_lossy());
+        }
+        let directory = self.path.parent().map(PathBuf::from).unwrap_or_default();
+        builder.build(directory)
+    }
+}
+
+// TODO(yair): add dynamic level filtering.
+// TODO(dan): filter out logs from dependencies (happens when RUST_LOG=DEBUG)
+// TODO(yair): define and implement configurable filtering.
+/// Sets the global tracing subscriber according to the config. Should be called before anything
+/// is logged.
+pub fn configure_tracing(config: &LoggingConfig) -> Result<(), InitError> {
+    let fmt_layer = get_fmt_layer(config)?;
+    let level_filter_layer =
+        EnvFilter::builder().with_default_directive(DEFAULT_LEVEL.into()).from_env_lossy();
+
+    // This sets a single subscriber to all of the threads. We may want to implement different
+    // subscriber for some threads and use set_global_default instead of init.
+    tracing_subscriber::registry().with(fmt_layer).with(level_filter_layer).init();
+    Ok(())
+}
+
+fn get_fmt_layer<S>(
+    config: &LoggingConfig,
+) -> Result<Box<dyn Layer<S> + Send + Sync + 'static>, InitError>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    let writer = match &config.file {
+        Some(file_config) => BoxMakeWriter::new(file_config.create_appender()?),
+        None => BoxMakeWriter::new(std::io::stdout),
+    };
+    let fmt_layer = fmt::layer()
+        .with_target(config.include_target)
+        .with_thread_names(config.include_thread)
+        .with_thread_ids(config.include_thread)
+        // Colors are only useful in a terminal.
+        .with_ansi(config.file.is_none())
+        .with_writer(writer);
+    Ok(match config.format {
+        LogFormat::Compact => fmt_layer.compact().boxed(),
+        LogFormat::Json => fmt_layer.json().boxed(),
+    })
+}
+
+/// Returns the span that the task of a component should run in, so that its logs can be filtered
+/// by the component.
+pub fn component_span(component: &'static str) -> Span {
+    info_span!("component", component)
+}
@@ -0,0 +1,51 @@
+use std::fs;
+
+use serde_json::Value;
+use tracing::info;
+use tracing_subscriber::prelude::*;
+use tracing_subscriber::Registry;
+
+use crate::logging::{component_span, get_fmt_layer, LogFileConfig, LogFormat, LoggingConfig};
+
+const MESSAGE: &str = "A message from the network.";
+
+// Logs a message inside the network's component span with the given config and returns what was
+// written to the log file.
+fn log_to_file(format: LogFormat, include_target: bool) -> String {
+    let temp_dir = tempfile::tempdir().unwrap();
+    let path = temp_dir.path().join("papyrus.log");
+    let config = LoggingConfig {
+        format,
+        include_target,
+        include_thread: false,
+        file: Some(LogFileConfig { path: path.clone(), ..Default::default() }),
+    };
+    let subscriber =
+        tracing_subscriber::registry().with(get_fmt_layer::<Registry>(&config).unwrap());
+    tracing::subscriber::with_default(subscriber, || {
+        let _span = component_span("network").entered();
+        info!("{MESSAGE}");
+    });
+    fs::read_to_string(path).unwrap()
+}
+
+#[test]
+fn json_logs_contain_component() {
+    let logs = log_to_file(LogFormat::Json, false);
+    let lines = logs.lines().collect::<Vec<_>>();
+    assert_eq!(lines.len(), 1);
+
+    let line: Value = serde_json::from_str(lines[0]).unwrap();
+    assert_eq!(line["fields"]["message"], MESSAGE);
+    assert_eq!(line["span"]["component"], "network");
+    assert_eq!(line["level"], "INFO");
+    assert!(line.get("target").is_none());
+}
+
+#[test]
+fn compact_logs_contain_component_and_target() {
+    let logs = log_to_file(LogFormat::Compact, true);
+    assert!(logs.contains(MESSAGE));
+    assert!(logs.contains("component=\"network\""));
+    assert!(logs.contains(module_path!()));
+}
@@ -1,85 +1,202 @@
+// The localnet nodes sync from each other and are checked through their monitoring gateways.
+#[cfg(all(test, feature = "p2p_sync", feature = "p2p_server", feature = "monitoring"))]
+mod localnet_test;
 #[cfg(test)]
 mod main_test;
 
-use std::env::{self, args};
+#[cfg(feature = "consensus")]
+use std::env;
+use std::env::args;
 use std::future::{pending, Future};
+use std::path::PathBuf;
 use std::process::exit;
 use std::sync::Arc;
 use std::time::Duration;
 
+use anyhow::anyhow;
+use futures::channel::mpsc::{unbounded, UnboundedReceiver, UnboundedSender};
+#[cfg(any(feature = "consensus", feature = "p2p_sync"))]
+use futures::future::try_join;
+#[cfg(feature = "consensus")]
+use futures::future::try_join3;
 use futures::future::BoxFuture;
-use futures::FutureExt;
+use futures::{FutureExt, TryFutureExt};
 use papyrus_base_layer::ethereum_base_layer_contract::EthereumBaseLayerConfig;
+use papyrus_common::consensus_status::SharedConsensusStatus;
 use papyrus_common::metrics::COLLECT_PROFILING_METRICS;
 use papyrus_common::pending_classes::PendingClasses;
 use papyrus_common::BlockHashAndNumber;
-use papyrus_config::presentation::get_config_presentation;
+#[cfg(feature = "monitoring")]
 use papyrus_config::validators::config_validate;
 use papyrus_config::ConfigError;
+use papyrus_consensus::config::ConsensusConfig;
+#[cfg(feature = "consensus")]
+use papyrus_consensus::consensus_metrics::ConsensusMetrics;
+#[cfg(feature = "consensus")]
+use papyrus_consensus::decisions::{compare_decisions_with_synced_blocks, write_decided_blocks};
+#[cfg(feature = "consensus")]
+use papyrus_consensus::dry_run::run_dry_run;
+#[cfg(feature = "consensus")]
 use papyrus_consensus::papyrus_consensus_context::PapyrusConsensusContext;
+#[cfg(feature = "consensus")]
+use papyrus_consensus::signing::{MessageSigner, MessageVerifier};
 use papyrus_consensus::types::ConsensusError;
+#[cfg(feature = "consensus")]
+use papyrus_consensus::types::ValidatorId;
+use papyrus_monitoring_gateway::{ComponentStates, ConfigPresentation, ConfigReloadRequest};
+#[cfg(feature = "monitoring")]
 use papyrus_monitoring_gateway::MonitoringServer;
-use papyrus_network::db_executor::DBExecutor;
-use papyrus_network::gossipsub_impl::Topic;
+#[cfg(feature = "p2p_server")]
+use papyrus_network::db_executor::{
+    advertise_block_ranges,
+    serve_from_storage_replica,
+    DBExecutor,
+};
+use papyrus_network::gossipsub_impl::{CONSENSUS_TOPIC, MEMPOOL_TRANSACTION_TOPIC};
 use papyrus_network::network_manager::{
+    BroadcastPublisher,
     BroadcastSubscriberChannels,
+    NegotiatedProtocolsByPeer,
     NetworkError,
+    NetworkManager,
+    NetworkManagerBuilder,
+    NetworkRegistrations,
+    PeerIdentities,
+    PeerLatencies,
+    PeerManagerCommand,
+    RecentNetworkEvents,
+    RecordedSqmrResponse,
+    ServedBytesByPeer,
+    SessionPoolsUsage,
     SqmrQueryReceiver,
     SqmrSubscriberChannels,
+    SubscriberSender,
 };
-use papyrus_network::{network_manager, NetworkConfig, Protocol};
-use papyrus_node::config::NodeConfig;
+use papyrus_network::{NetworkConfig, Protocol};
+use papyrus_node::config::presets::chain_consistency_warnings;
+use papyrus_node::config::reload::{ConfigReloader, DynamicConfigReceivers};
+use papyrus_node::config::{deprecated_param_warnings, NodeConfig};
+use papyrus_node::export::run_export_command;
+use papyrus_node::genesis::initialize_genesis;
+use papyrus_node::logging::{component_span, configure_tracing, LoggingConfig};
+#[cfg(feature = "rpc")]
+use papyrus_node::network_info::PeerManagerNetworkInfoReader;
+use papyrus_node::storage_scan::{run_storage_scan, STORAGE_SCAN_REPORT_FILE_NAME};
+use papyrus_node::supervisor::{Component, Supervisor};
+#[cfg(feature = "rpc")]
+use papyrus_node::transaction_broadcast::GossipsubTransactionWriter;
+#[cfg(any(feature = "rpc", feature = "central_sync", feature = "monitoring"))]
 use papyrus_node::version::VERSION_FULL;
-use papyrus_p2p_sync::{P2PSync, P2PSyncConfig, P2PSyncError};
-use papyrus_protobuf::consensus::ConsensusMessage;
+#[cfg(feature = "p2p_sync")]
+use papyrus_p2p_sync::replay::{record_responses, run_replay};
+#[cfg(feature = "p2p_sync")]
+use papyrus_p2p_sync::P2PSync;
+use papyrus_p2p_sync::{BaseLayerCheckpointSource, P2PSyncConfig, P2PSyncError};
+use papyrus_protobuf::consensus::SignedConsensusMessage;
+use papyrus_protobuf::mempool::MempoolTransaction;
 use papyrus_protobuf::sync::{
+    BlockRangeAdvertisement,
     DataOrFin,
     HeaderQuery,
+    ResponseLimits,
     SignedBlockHeader,
     StateDiffChunk,
     StateDiffQuery,
     TransactionQuery,
 };
 #[cfg(feature = "rpc")]
-use papyrus_rpc::run_server;
-use papyrus_storage::{open_storage, update_storage_metrics, StorageReader, StorageWriter};
+use papyrus_rpc::{run_server, NetworkInfoReader, NodeInfo, SyncMode, TransactionSubmission};
+#[cfg(feature = "consensus")]
+use papyrus_storage::body::BodyStorageReader;
+use papyrus_storage::header::HeaderStorageReader;
+#[cfg(feature = "consensus")]
+use papyrus_storage::StorageError;
+use papyrus_storage::{
+    open_storage,
+    open_storage_read_only,
+    update_storage_metrics,
+    StorageReader,
+    StorageWriter,
+    StorageWriterComponent,
+};
 use papyrus_sync::sources::base_layer::{BaseLayerSourceError, EthereumBaseLayerSource};
-use papyrus_sync::sources::central::{CentralError, CentralSource, CentralSourceConfig};
-use papyrus_sync::sources::pending::PendingSource;
-use papyrus_sync::{StateSync, StateSyncError, SyncConfig};
+use papyrus_sync::sources::central::CentralSourceConfig;
+#[cfg(feature = "central_sync")]
+use papyrus_sync::sources::central::{CentralError, CentralSource};
+#[cfg(feature = "central_sync")]
+use papyrus_sync::StateSync;
+use papyrus_sync::{StateSyncError, SyncConfig};
 use starknet_api::block::{BlockHash, BlockNumber};
-use starknet_api::felt;
+use starknet_api::core::ChainId;
 use starknet_api::state::ThinStateDiff;
 use starknet_api::transaction::{Transaction, TransactionOutput};
 use starknet_client::reader::objects::pending_data::{PendingBlock, PendingBlockOrDeprecated};
 use starknet_client::reader::PendingData;
-use tokio::sync::RwLock;
+#[cfg(feature = "rpc")]
+use starknet_client::writer::StarknetWriter;
+use tokio::sync::{watch, RwLock};
 use tokio::task::{JoinError, JoinHandle};
-use tracing::metadata::LevelFilter;
 use tracing::{debug_span, error, info, warn, Instrument};
-use tracing_subscriber::prelude::*;
-use tracing_subscriber::{fmt, EnvFilter};
 
-// TODO(yair): Add to config.
-const DEFAULT_LEVEL: LevelFilter = LevelFilter::INFO;
+// Our own advertisements are sent once per interval, so there's no need to buffer many of them.
+const BLOCK_RANGE_ADVERTISEMENT_BUFFER_SIZE: usize = 1;
+
+const TRANSACTION_BROADCAST_BUFFER_SIZE: usize = 100;
+
+#[cfg(feature = "consensus")]
+const CONSENSUS_VALIDATOR_ID_ENV_VAR: &str = "CONSENSUS_VALIDATOR_ID";
+// The consensus WAL is kept next to the storage files, since it belongs to the same chain.
+const CONSENSUS_WAL_FILE_NAME: &str = "consensus_wal";
 
-// TODO(shahak): Consider adding genesis hash to the config to support chains that have
-// different genesis hash.
-// TODO: Consider moving to a more general place.
-const GENESIS_HASH: &str = "0x0";
+// Running `papyrus_node export ...` exports historical data from the storage instead of running the
+// node.
+const EXPORT_COMMAND: &str = "export";
 
-// TODO(dvir): add this to config.
-// Duration between updates to the storage metrics (those in the collect_storage_metrics function).
-const STORAGE_METRICS_UPDATE_INTERVAL: Duration = Duration::from_secs(10);
+// The handles the monitoring gateway reads the state of the p2p network from. The peer manager's
+// command sender is None if the network isn't running.
+type NetworkStateHandles = (
+    Option<UnboundedSender<PeerManagerCommand>>,
+    ServedBytesByPeer,
+    NegotiatedProtocolsByPeer,
+    PeerIdentities,
+    SessionPoolsUsage,
+);
 
 #[cfg(feature = "rpc")]
 async fn create_rpc_server_future(
-    config: &NodeConfig,
+    config: NodeConfig,
     shared_highest_block: Arc<RwLock<Option<BlockHashAndNumber>>>,
     pending_data: Arc<RwLock<PendingData>>,
     pending_classes: Arc<RwLock<PendingClasses>>,
     storage_reader: StorageReader,
+    transaction_publisher: Option<BroadcastPublisher<MempoolTransaction>>,
+    network_state_handles: NetworkStateHandles,
 ) -> anyhow::Result<impl Future<Output = Result<(), JoinError>>> {
+    let p2p_transaction_writer = transaction_publisher.map(|publisher| {
+        Arc::new(GossipsubTransactionWriter::new(config.rpc.chain_id.clone(), publisher))
+            as Arc<dyn StarknetWriter>
+    });
+    let (
+        peer_manager_command_sender,
+        served_bytes_by_peer,
+        negotiated_protocols_by_peer,
+        peer_identities,
+        session_pools_usage,
+    ) = network_state_handles;
+    let network_info_reader = peer_manager_command_sender.map(|peer_manager_command_sender| {
+        Arc::new(PeerManagerNetworkInfoReader::new(
+            peer_manager_command_sender,
+            served_bytes_by_peer,
+            negotiated_protocols_by_peer,
+            peer_identities,
+            session_pools_usage,
+        )) as Arc<dyn NetworkInfoReader>
+    });
+    let sync_mode = match (&config.sync, &config.p2p_sync) {
+        (Some(_), _) => SyncMode::Central,
+        (None, Some(_)) => SyncMode::P2p,
+        (None, None) => SyncMode::Disabled,
+    };
     let (_, server_handle) = run_server(
         &config.rpc,
         shared_highest_block,
@@ -87,55 +204,418 @@ async fn create_rpc_server_future(
         pending_classes,
         storage_reader,
         VERSION_FULL,
+        p2p_transaction_writer,
+        NodeInfo { sync_mode, network_info_reader },
     )
+    .instrument(component_span("rpc"))
     .await?;
-    Ok(tokio::spawn(server_handle.stopped()))
+    Ok(tokio::spawn(server_handle.stopped().instrument(component_span("rpc"))))
 }
 
 #[cfg(not(feature = "rpc"))]
 async fn create_rpc_server_future(
-    _config: &NodeConfig,
+    _config: NodeConfig,
     _shared_highest_block: Arc<RwLock<Option<BlockHashAndNumber>>>,
     _pending_data: Arc<RwLock<PendingData>>,
     _pending_classes: Arc<RwLock<PendingClasses>>,
     _storage_reader: StorageReader,
+    _transaction_publisher: Option<BroadcastPublisher<MempoolTransaction>>,
+    _network_state_handles: NetworkStateHandles,
 ) -> anyhow::Result<impl Future<Output = Result<(), JoinError>>> {
     Ok(pending())
 }
 
+// The JSON-RPC server submits transactions to the p2p network only if it's configured to.
+#[cfg(feature = "rpc")]
+fn rpc_broadcasts_transactions(config: &NodeConfig) -> bool {
+    config.components.rpc && config.rpc.transaction_submission == TransactionSubmission::Gossipsub
+}
+
+#[cfg(not(feature = "rpc"))]
+fn rpc_broadcasts_transactions(_config: &NodeConfig) -> bool {
+    false
+}
+
+// Consensus runs only on validators and in a dry run, so the other nodes don't subscribe to the
+// consensus topic and don't carry its messages.
+#[cfg(feature = "consensus")]
+fn runs_consensus(config: &NodeConfig) -> bool {
+    config.components.consensus
+        && (config.consensus.dry_run || env::var(CONSENSUS_VALIDATOR_ID_ENV_VAR).is_ok())
+}
+
+#[cfg(not(feature = "consensus"))]
+fn runs_consensus(_config: &NodeConfig) -> bool {
+    false
+}
+
+// Consensus starts from the first block that wasn't synced yet, unless the config sets the height.
+#[cfg(feature = "consensus")]
+fn consensus_start_height(
+    config: &ConsensusConfig,
+    storage_reader: &StorageReader,
+) -> Result<BlockNumber, StorageError> {
+    match config.start_height {
+        Some(start_height) => Ok(start_height),
+        None => storage_reader.begin_ro_txn()?.get_header_marker(),
+    }
+}
+
+// Waits until the synced headers are at most max_blocks_behind_to_start blocks behind the highest
+// block known to sync, since consensus can't decide on a height before the blocks below it are
+// synced.
+#[cfg(feature = "consensus")]
+async fn wait_for_sync_to_catch_up(
+    config: &ConsensusConfig,
+    storage_reader: &StorageReader,
+    shared_highest_block: &RwLock<Option<BlockHashAndNumber>>,
+) -> Result<(), StorageError> {
+    loop {
+        let header_marker = storage_reader.begin_ro_txn()?.get_header_marker()?;
+        let highest_block_number = shared_highest_block
+            .read()
+            .await
+            .as_ref()
+            .map(|highest_block| highest_block.block_number);
+        let blocks_behind = highest_block_number.map_or(0, |highest_block_number| {
+            (highest_block_number.0 + 1).saturating_sub(header_marker.0)
+        });
+        if blocks_behind <= config.max_blocks_behind_to_start {
+            return Ok(());
+        }
+        info!(
+            "Waiting for sync to catch up before starting consensus. Synced up to block \
+             {header_marker}, {blocks_behind} blocks behind the highest known block."
+        );
+        tokio::time::sleep(config.start_poll_interval).await;
+    }
+}
+
+// The decided blocks are written with the storage writer if it's given, and compared with the
+// synced blocks otherwise.
+#[cfg(feature = "consensus")]
+#[allow(clippy::too_many_arguments)]
 fn run_consensus(
+    config: &ConsensusConfig,
     storage_reader: StorageReader,
-    consensus_channels: BroadcastSubscriberChannels<ConsensusMessage>,
+    storage_writer: Option<StorageWriter>,
+    shared_highest_block: Arc<RwLock<Option<BlockHashAndNumber>>>,
+    consensus_channels: BroadcastSubscriberChannels<SignedConsensusMessage>,
+    wal_path: PathBuf,
+    consensus_status: SharedConsensusStatus,
 ) -> anyhow::Result<JoinHandle<Result<(), ConsensusError>>> {
-    let Ok(validator_id) = env::var("CONSENSUS_VALIDATOR_ID") else {
+    let validator_id = env::var(CONSENSUS_VALIDATOR_ID_ENV_VAR).ok();
+    let signer = config.signing_key.as_deref().map(MessageSigner::from_bytes).transpose()?;
+
+    if config.dry_run {
+        // A node that isn't a validator can run a dry run as well.
+        let proposer = match validator_id {
+            Some(validator_id) => validator_id.parse::<u128>()?.into(),
+            None => ValidatorId::default(),
+        };
+        // Build the proposals of the blocks that weren't synced yet, as a validator would.
+        let start_height = storage_reader.begin_ro_txn()?.get_body_marker()?;
+        let context = PapyrusConsensusContext::new(
+            storage_reader,
+            consensus_channels.messages_to_broadcast_sender,
+            signer,
+            None,
+        );
+        info!("Running a consensus dry run from height {start_height}");
+        return Ok(tokio::spawn(
+            run_dry_run(Arc::new(context), start_height, proposer, config.dry_run_interval)
+                .instrument(component_span("consensus")),
+        ));
+    }
+
+    let Some(validator_id) = validator_id else {
         info!("CONSENSUS_VALIDATOR_ID is not set. Not run consensus.");
         return Ok(tokio::spawn(pending()));
     };
     info!("Running consensus as validator {validator_id}");
     let validator_id = validator_id.parse::<u128>()?.into();
+    // The monitoring gateway presents the status the metrics update.
+    let consensus_metrics = ConsensusMetrics::new(validator_id, consensus_status);
     let context = PapyrusConsensusContext::new(
         storage_reader.clone(),
         consensus_channels.messages_to_broadcast_sender,
+        signer,
+        Some(consensus_metrics.clone()),
     );
-    // TODO(dvir): add option to configure this value.
-    let start_height = BlockNumber(0);
-
-    Ok(tokio::spawn(papyrus_consensus::run_consensus(
-        Arc::new(context),
-        start_height,
-        validator_id,
-        consensus_channels.broadcasted_messages_receiver,
-    )))
+    let config = config.clone();
+    let message_verifier = MessageVerifier::new(config.validator_public_keys.clone());
+    // Consensus doesn't wait for the decisions to be written or compared.
+    let (decision_sender, decision_receiver) = unbounded();
+    let handle_decisions = match storage_writer {
+        Some(storage_writer) => {
+            write_decided_blocks(storage_reader.clone(), storage_writer, decision_receiver).boxed()
+        }
+        None => {
+            compare_decisions_with_synced_blocks(storage_reader.clone(), decision_receiver).boxed()
+        }
+    };
+
+    // The wait for sync happens in the spawned task so that the rest of the node runs meanwhile.
+    Ok(tokio::spawn(
+        async move {
+            wait_for_sync_to_catch_up(&config, &storage_reader, &shared_highest_block).await?;
+            let start_height = consensus_start_height(&config, &storage_reader)?;
+            info!("Starting consensus from height {start_height}");
+            try_join3(
+                papyrus_consensus::run_consensus(
+                    Arc::new(context),
+                    start_height,
+                    validator_id,
+                    config.catch_up_timeout,
+                    wal_path,
+                    message_verifier,
+                    consensus_channels.broadcasted_messages_receiver,
+                    decision_sender,
+                ),
+                handle_decisions,
+                consensus_metrics.detect_stuck_rounds(config.stuck_round_timeout).map(Ok),
+            )
+            .map_ok(|_| ())
+            .await
+        }
+        .instrument(component_span("consensus")),
+    ))
 }
 
-async fn run_threads(config: NodeConfig) -> anyhow::Result<()> {
-    let (storage_reader, storage_writer) = open_storage(config.storage.clone())?;
+#[cfg(not(feature = "consensus"))]
+#[allow(clippy::too_many_arguments)]
+fn run_consensus(
+    _config: &ConsensusConfig,
+    _storage_reader: StorageReader,
+    _storage_writer: Option<StorageWriter>,
+    _shared_highest_block: Arc<RwLock<Option<BlockHashAndNumber>>>,
+    _consensus_channels: BroadcastSubscriberChannels<SignedConsensusMessage>,
+    _wal_path: PathBuf,
+    _consensus_status: SharedConsensusStatus,
+) -> anyhow::Result<JoinHandle<Result<(), ConsensusError>>> {
+    Ok(tokio::spawn(pending()))
+}
 
-    let storage_metrics_handle = if config.monitoring_gateway.collect_metrics {
-        spawn_storage_metrics_collector(storage_reader.clone(), STORAGE_METRICS_UPDATE_INTERVAL)
+// The admin server injects blocks only if it's given a storage writer. Returns a function that
+// starts a run of the server, so that the server can run again after it fails.
+#[cfg(feature = "monitoring")]
+#[allow(clippy::too_many_arguments)]
+fn create_monitoring_server(
+    config: &NodeConfig,
+    config_presentation: watch::Receiver<ConfigPresentation>,
+    storage_reader: StorageReader,
+    local_peer_id: String,
+    network_registrations: NetworkRegistrations,
+    served_bytes_by_peer: ServedBytesByPeer,
+    negotiated_protocols_by_peer: NegotiatedProtocolsByPeer,
+    peer_latencies: PeerLatencies,
+    recent_network_events: RecentNetworkEvents,
+    component_states: ComponentStates,
+    consensus_status: SharedConsensusStatus,
+    admin_storage_writer: Option<StorageWriter>,
+    peer_manager_command_sender: Option<UnboundedSender<PeerManagerCommand>>,
+    config_reload_sender: Option<UnboundedSender<ConfigReloadRequest>>,
+) -> anyhow::Result<impl FnMut() -> BoxFuture<'static, anyhow::Result<()>> + Send + 'static> {
+    let monitoring_server = Arc::new(MonitoringServer::new(
+        config.monitoring_gateway.clone(),
+        config_presentation,
+        storage_reader,
+        VERSION_FULL,
+        local_peer_id,
+        network_registrations,
+        served_bytes_by_peer,
+        negotiated_protocols_by_peer,
+        peer_latencies,
+        recent_network_events,
+        component_states,
+        consensus_status,
+        admin_storage_writer,
+        peer_manager_command_sender,
+        config_reload_sender,
+    )?);
+    Ok(move || {
+        let monitoring_server = monitoring_server.clone();
+        async move { anyhow::Ok(monitoring_server.run().await?) }.boxed()
+    })
+}
+
+#[cfg(not(feature = "monitoring"))]
+#[allow(clippy::too_many_arguments)]
+fn create_monitoring_server(
+    _config: &NodeConfig,
+    _config_presentation: watch::Receiver<ConfigPresentation>,
+    _storage_reader: StorageReader,
+    _local_peer_id: String,
+    _network_registrations: NetworkRegistrations,
+    _served_bytes_by_peer: ServedBytesByPeer,
+    _negotiated_protocols_by_peer: NegotiatedProtocolsByPeer,
+    _peer_latencies: PeerLatencies,
+    _recent_network_events: RecentNetworkEvents,
+    _component_states: ComponentStates,
+    _consensus_status: SharedConsensusStatus,
+    _admin_storage_writer: Option<StorageWriter>,
+    _peer_manager_command_sender: Option<UnboundedSender<PeerManagerCommand>>,
+    _config_reload_sender: Option<UnboundedSender<ConfigReloadRequest>>,
+) -> anyhow::Result<impl FnMut() -> BoxFuture<'static, anyhow::Result<()>> + Send + 'static> {
+    Ok(|| pending().boxed())
+}
+
+// Serves the node's blocks to its peers. If the network config sets a secondary storage, the blocks
+// are served from a replica of it instead of from the node's storage.
+#[cfg(feature = "p2p_server")]
+fn create_p2p_sync_server_future(
+    config: &NodeConfig,
+    storage_reader: StorageReader,
+    sync_server_channels: SyncServerChannels,
+    header_marker_receiver: watch::Receiver<BlockNumber>,
+    response_limits: watch::Receiver<ResponseLimits>,
+) -> BoxFuture<'static, ()> {
+    let (
+        header_sync_server_channel,
+        state_diff_sync_server_channel,
+        transaction_server_channel,
+        block_range_advertisement_sender,
+    ) = sync_server_channels;
+    let network_config = config
+        .network
+        .as_ref()
+        .expect("The sync server channels are created only if the network is enabled");
+    let (serving_storage_reader, storage_replica_server) =
+        match &network_config.secondary_storage_path_prefix {
+            Some(path_prefix) => {
+                let mut replica_config = config.storage.clone();
+                replica_config.db_config.path_prefix = path_prefix.clone();
+                let storage_reader_sender = watch::Sender::new(None);
+                let serving_storage_reader = storage_reader_sender.subscribe();
+                let storage_replica_server = serve_from_storage_replica(
+                    replica_config,
+                    network_config.secondary_storage_reopen_interval,
+                    storage_reader_sender,
+                );
+                (serving_storage_reader, storage_replica_server.boxed())
+            }
+            None => (watch::channel(Some(storage_reader)).1, pending().boxed()),
+        };
+    let db_executor = DBExecutor::new(
+        serving_storage_reader.clone(),
+        header_sync_server_channel,
+        state_diff_sync_server_channel,
+        transaction_server_channel,
+        network_config.inbound_query_log_mode,
+        network_config.inbound_query_log_sample_rate,
+        network_config.inbound_query_max_blocking_reads,
+        network_config.inbound_query_blocks_per_read_txn,
+        network_config.inbound_query_cache_max_bytes,
+        response_limits.clone(),
+        header_marker_receiver,
+        // Open-ended header queries are ended before the peer's session times out.
+        network_config.session_timeout / 2,
+        network_config.inbound_session_limits().max_sessions,
+    );
+    let block_range_advertiser = advertise_block_ranges(
+        serving_storage_reader,
+        block_range_advertisement_sender,
+        network_config.block_range_advertisement_interval,
+        response_limits,
+    );
+    futures::future::join3(db_executor.run(), block_range_advertiser, storage_replica_server)
+        .map(|_| ())
+        .boxed()
+}
+
+#[cfg(not(feature = "p2p_server"))]
+fn create_p2p_sync_server_future(
+    _config: &NodeConfig,
+    _storage_reader: StorageReader,
+    _sync_server_channels: SyncServerChannels,
+    _header_marker_receiver: watch::Receiver<BlockNumber>,
+    _response_limits: watch::Receiver<ResponseLimits>,
+) -> BoxFuture<'static, ()> {
+    pending().boxed()
+}
+
+// The config is reloaded on the admin server's requests if a reloader is given.
+async fn run_threads(
+    config: NodeConfig,
+    config_reloader: Option<ConfigReloader>,
+) -> anyhow::Result<()> {
+    // In read-only mode the storage is written by another node, so the components that write to
+    // it don't run.
+    let (storage_reader, storage_writer) = if config.storage.read_only {
+        info!("Opening the storage in read-only mode.");
+        (open_storage_read_only(config.storage.clone())?, None)
     } else {
-        tokio::spawn(pending())
+        let (storage_reader, storage_writer) = open_storage(config.storage.clone())?;
+        (storage_reader, Some(storage_writer))
     };
+    run_threads_with_storage(config, storage_reader, storage_writer, config_reloader).await
+}
+
+// Runs the node's components on a storage that was already opened. A storage writer is given unless
+// the storage is read-only.
+async fn run_threads_with_storage(
+    config: NodeConfig,
+    storage_reader: StorageReader,
+    storage_writer: Option<StorageWriter>,
+    config_reloader: Option<ConfigReloader>,
+) -> anyhow::Result<()> {
+    // A storage of another chain fails before any component starts, and the genesis state is
+    // committed before the syncs start from the block after it.
+    let storage_writer = {
+        let genesis_hash = config.genesis_hash;
+        let genesis_state_config = config.genesis_state.clone();
+        let storage_reader = storage_reader.clone();
+        let mut storage_writer = storage_writer;
+        tokio::task::spawn_blocking(move || {
+            let _span = component_span("genesis").entered();
+            initialize_genesis(
+                genesis_hash,
+                genesis_state_config.as_ref(),
+                &storage_reader,
+                storage_writer.as_mut(),
+            )
+            .map(|()| storage_writer)
+        })
+        .await??
+    };
+
+    // The components are started as soon as they're created, and the node runs until the
+    // supervisor gives up on one of the essential components.
+    let mut supervisor = Supervisor::new(config.supervisor.clone());
+
+    // Config reloader.
+    let dynamic_config = config_reloader.as_ref().map_or_else(
+        || DynamicConfigReceivers::unchanging(&config),
+        |config_reloader| Ok(config_reloader.subscribe()),
+    )?;
+    let config_reload_sender = config_reloader.map(|config_reloader| {
+        let (config_reload_sender, config_reload_receiver) = unbounded();
+        supervisor.spawn_once(
+            Component::ConfigReloader,
+            config_reloader
+                .run(config_reload_receiver)
+                .map(anyhow::Ok)
+                .instrument(component_span("config_reloader")),
+        );
+        config_reload_sender
+    });
+
+    if config.monitoring_gateway.collect_metrics {
+        let storage_reader = storage_reader.clone();
+        let update_interval = dynamic_config.storage_metrics_update_interval.clone();
+        supervisor.spawn_restartable(Component::StorageMetrics, move || {
+            spawn_storage_metrics_collector(storage_reader.clone(), update_interval.clone())
+                .map_err(anyhow::Error::from)
+        });
+    }
+
+    // The responses of peers to the p2p sync are recorded by the network, since it has the raw
+    // bytes and the peer of each response. The shadow sync reads the peer of each response from
+    // them.
+    let (sync_response_recorder, recorded_sync_responses) = unbounded();
+    let record_sync_responses = config.p2p_sync.as_ref().is_some_and(|p2p_sync_config| {
+        p2p_sync_config.record_path.is_some() || p2p_sync_config.shadow
+    });
 
     // P2P network.
     let (
@@ -144,152 +624,337 @@ async fn run_threads(config: NodeConfig) -> anyhow::Result<()> {
         maybe_sync_server_channels,
         maybe_consensus_channels,
         local_peer_id,
-    ) = run_network(config.network.clone())?;
-    let network_handle = tokio::spawn(network_future);
+        network_registrations,
+        served_bytes_by_peer,
+        negotiated_protocols_by_peer,
+        peer_identities,
+        peer_latencies,
+        session_pools_usage,
+        recent_network_events,
+        peer_manager_command_sender,
+        transaction_publisher,
+    ) = run_network(
+        config.network.clone(),
+        config.storage.db_config.chain_id.clone(),
+        // The shadow sync queries the state diffs through a single lane.
+        config
+            .p2p_sync
+            .as_ref()
+            .filter(|p2p_sync_config| !p2p_sync_config.shadow)
+            .map_or(1, |p2p_sync_config| p2p_sync_config.max_parallel_state_diff_sessions),
+        rpc_broadcasts_transactions(&config),
+        config.components.p2p_server,
+        runs_consensus(&config),
+        record_sync_responses.then_some(sync_response_recorder),
+        dynamic_config.ping_interval,
+        config
+            .p2p_sync
+            .as_ref()
+            .is_some_and(|p2p_sync_config| p2p_sync_config.combined_block_download),
+    )?;
+    if config.network.is_some() {
+        supervisor.spawn_once(
+            Component::Network,
+            network_future.map_err(anyhow::Error::from).instrument(component_span("network")),
+        );
+    }
+
+    // The admin server injects blocks by writing to the storage, so it can inject blocks only while
+    // the node isn't syncing.
+    let (storage_writer, admin_storage_writer) =
+        match config.monitoring_gateway.admin_server_address {
+            _ if !config.components.monitoring_gateway => (storage_writer, None),
+            Some(_) if config.sync.is_some() || config.p2p_sync.is_some() => {
+                info!(
+                    "The admin server won't inject blocks since the node is syncing. Turn on \
+                     --sync.#is_none and --p2p_sync.#is_none to inject blocks."
+                );
+                (storage_writer, None)
+            }
+            Some(_) => {
+                let mut storage_writer =
+                    storage_writer.expect("The admin server can't run with a read-only storage");
+                storage_writer.set_component(StorageWriterComponent::BlockInjection);
+                (None, Some(storage_writer))
+            }
+            None => (storage_writer, None),
+        };
+
+    // Set by consensus once it runs as a validator.
+    let consensus_status = SharedConsensusStatus::default();
 
     // Monitoring server.
-    let monitoring_server = MonitoringServer::new(
-        config.monitoring_gateway.clone(),
-        get_config_presentation(&config, true)?,
-        get_config_presentation(&config, false)?,
-        storage_reader.clone(),
-        VERSION_FULL,
-        local_peer_id,
-    )?;
-    let monitoring_server_handle = monitoring_server.spawn_server().await;
+    if config.components.monitoring_gateway {
+        let start_monitoring_server = create_monitoring_server(
+            &config,
+            dynamic_config.config_presentation,
+            storage_reader.clone(),
+            local_peer_id,
+            network_registrations,
+            served_bytes_by_peer.clone(),
+            negotiated_protocols_by_peer.clone(),
+            peer_latencies,
+            recent_network_events,
+            supervisor.component_states(),
+            consensus_status.clone(),
+            admin_storage_writer,
+            peer_manager_command_sender.clone(),
+            config_reload_sender,
+        )?;
+        supervisor.spawn_restartable(Component::MonitoringGateway, start_monitoring_server);
+    }
 
-    // The sync is the only writer of the syncing state.
+    // The highest block is written by the sync that runs, either the central sync or the p2p sync.
     let shared_highest_block = Arc::new(RwLock::new(None));
     let pending_data = Arc::new(RwLock::new(PendingData {
         // The pending data might change later to DeprecatedPendingBlock, depending on the response
         // from the feeder gateway.
         block: PendingBlockOrDeprecated::Current(PendingBlock {
-            parent_block_hash: BlockHash(felt!(GENESIS_HASH)),
+            parent_block_hash: config
+                .genesis_state
+                .as_ref()
+                .map_or(config.genesis_hash, |genesis_state| genesis_state.block_hash),
             ..Default::default()
         }),
         ..Default::default()
     }));
     let pending_classes = Arc::new(RwLock::new(PendingClasses::default()));
 
-    // JSON-RPC server.
-    let server_handle_future = create_rpc_server_future(
-        &config,
-        shared_highest_block.clone(),
-        pending_data.clone(),
-        pending_classes.clone(),
-        storage_reader.clone(),
-    )
-    .await?;
-
-    // P2P Sync Server task.
-    let p2p_sync_server_future = match maybe_sync_server_channels {
-        Some((
-            header_sync_server_channel,
-            state_diff_sync_server_channel,
-            transaction_server_channel,
-        )) => {
-            let db_executor = DBExecutor::new(
+    // JSON-RPC server. It binds its address again whenever it's restarted.
+    if config.components.rpc {
+        let rpc_config = config.clone();
+        let shared_highest_block = shared_highest_block.clone();
+        let pending_data = pending_data.clone();
+        let pending_classes = pending_classes.clone();
+        let storage_reader = storage_reader.clone();
+        let network_state_handles = (
+            peer_manager_command_sender.clone(),
+            served_bytes_by_peer,
+            negotiated_protocols_by_peer,
+            peer_identities,
+            session_pools_usage,
+        );
+        supervisor.spawn_restartable(Component::Rpc, move || {
+            let rpc_server_future = create_rpc_server_future(
+                rpc_config.clone(),
+                shared_highest_block.clone(),
+                pending_data.clone(),
+                pending_classes.clone(),
                 storage_reader.clone(),
-                header_sync_server_channel,
-                state_diff_sync_server_channel,
-                transaction_server_channel,
+                transaction_publisher.clone(),
+                network_state_handles.clone(),
             );
-            db_executor.run().boxed()
-        }
-        None => pending().boxed(),
+            async move { anyhow::Ok(rpc_server_future.await?.await?) }
+        });
+    }
+
+    // The storage scan runs after the monitoring server is up, so that its progress can be
+    // followed, and before the syncs start, so that they download again the blocks it reverts.
+    let storage_writer = if config.storage_scan.run_at_startup {
+        let storage_scan_config = config.storage_scan.clone();
+        let report_path = config.storage.db_config.path().join(STORAGE_SCAN_REPORT_FILE_NAME);
+        let storage_reader = storage_reader.clone();
+        let mut storage_writer = storage_writer;
+        tokio::task::spawn_blocking(move || {
+            let _span = component_span("storage_scan").entered();
+            run_storage_scan(
+                &storage_scan_config,
+                &report_path,
+                &storage_reader,
+                storage_writer.as_mut(),
+            )
+            .map(|_report| storage_writer)
+        })
+        .await??
+    } else {
+        storage_writer
     };
-    let p2p_sync_server_handle = tokio::spawn(p2p_sync_server_future);
+
+    // On chains where consensus is authoritative, the blocks it decides on are written instead of
+    // synced.
+    let (storage_writer, consensus_storage_writer) =
+        if config.consensus.authoritative && maybe_consensus_channels.is_some() {
+            let mut storage_writer =
+                storage_writer.expect("Consensus can't write to a read-only storage");
+            storage_writer.set_component(StorageWriterComponent::Consensus);
+            (None, Some(storage_writer))
+        } else {
+            (storage_writer, None)
+        };
+
+    // The p2p sync notifies the sync server of the headers it writes, so that the server sends them
+    // to the peers that follow the tip. Other writers don't notify, so the server also polls the
+    // storage for new headers.
+    let (header_marker_sender, header_marker_receiver) =
+        watch::channel(storage_reader.begin_ro_txn()?.get_header_marker()?);
+
+    // P2P Sync Server task.
+    if let Some(sync_server_channels) = maybe_sync_server_channels {
+        let p2p_sync_server_future = create_p2p_sync_server_future(
+            &config,
+            storage_reader.clone(),
+            sync_server_channels,
+            header_marker_receiver,
+            dynamic_config.response_limits,
+        );
+        supervisor.spawn_once(
+            Component::P2PSyncServer,
+            p2p_sync_server_future.map(anyhow::Ok).instrument(component_span("p2p_sync")),
+        );
+    }
 
     // Sync task.
     let (sync_future, p2p_sync_client_future) = match (config.sync, config.p2p_sync) {
+        // The central sync writes the blocks, and the p2p sync only compares them with the blocks
+        // of peers.
+        (Some(sync_config), Some(p2p_sync_config)) if p2p_sync_config.shadow => {
+            let configs = (sync_config, config.central, config.base_layer);
+            let mut storage_writer =
+                storage_writer.expect("The storage writer is given to the sync");
+            storage_writer.set_component(StorageWriterComponent::CentralSync);
+            let storage = (storage_reader.clone(), storage_writer);
+            let sync_fut = run_sync(
+                configs,
+                dynamic_config.sync,
+                shared_highest_block.clone(),
+                pending_data,
+                pending_classes,
+                config.genesis_hash,
+                storage,
+            );
+            let (header_channels, mut state_diff_channels, _transaction_channels) =
+                maybe_sync_client_channels
+                    .expect("If p2p sync is enabled, network needs to be enabled too");
+            let p2p_shadow_sync_future = run_p2p_shadow_sync(
+                p2p_sync_config,
+                storage_reader.clone(),
+                header_channels,
+                state_diff_channels.pop().expect("The shadow sync has a single state diff lane"),
+                recorded_sync_responses,
+            );
+            (Some(sync_fut), Some(p2p_shadow_sync_future.boxed()))
+        }
+        // Rejected by the config validation.
         (Some(_), Some(_)) => {
-            panic!("One of --sync.#is_none or --p2p_sync.#is_none must be turned on");
+            return Err(anyhow!(
+                "sync and p2p_sync can't be enabled together, unless p2p_sync.shadow is set"
+            ));
         }
         (Some(sync_config), None) => {
             let configs = (sync_config, config.central, config.base_layer);
+            let mut storage_writer =
+                storage_writer.expect("The storage writer is given to the sync");
+            storage_writer.set_component(StorageWriterComponent::CentralSync);
             let storage = (storage_reader.clone(), storage_writer);
-            let sync_fut =
-                run_sync(configs, shared_highest_block, pending_data, pending_classes, storage);
-            (sync_fut.boxed(), pending().boxed())
+            let sync_fut = run_sync(
+                configs,
+                dynamic_config.sync,
+                shared_highest_block.clone(),
+                pending_data,
+                pending_classes,
+                config.genesis_hash,
+                storage,
+            );
+            (Some(sync_fut), None)
         }
         (None, Some(p2p_sync_config)) => {
-            let (header_channels, state_diff_channels) = maybe_sync_client_channels
-                .expect("If p2p sync is enabled, network needs to be enabled too");
-            (
-                pending().boxed(),
-                run_p2p_sync_client(
+            let mut storage_writer =
+                storage_writer.expect("The storage writer is given to the sync");
+            storage_writer.set_component(StorageWriterComponent::P2PSync);
+            let p2p_sync_client_future = match p2p_sync_config.replay_path.clone() {
+                Some(replay_path) => run_p2p_sync_replay(
                     p2p_sync_config,
+                    replay_path,
                     storage_reader.clone(),
                     storage_writer,
-                    header_channels,
-                    state_diff_channels,
+                    shared_highest_block.clone(),
+                    header_marker_sender,
                 )
                 .boxed(),
-            )
+                None => {
+                    let (header_channels, state_diff_channels, transaction_channels) =
+                        maybe_sync_client_channels
+                            .expect("If p2p sync is enabled, network needs to be enabled too");
+                    // Nothing else anchors the chain synced from peers to Starknet, so the p2p
+                    // sync checks it against the base layer.
+                    let base_layer_source =
+                        EthereumBaseLayerSource::new(config.base_layer.clone()).map_err(|e| {
+                            BaseLayerSourceError::BaseLayerSourceCreationError(e.to_string())
+                        })?;
+                    let base_layer_checkpoint_source = BaseLayerCheckpointSource::new(
+                        base_layer_source,
+                        config.base_layer.poll_interval,
+                        config.base_layer.confirmation_depth,
+                    );
+                    run_p2p_sync_client(
+                        p2p_sync_config,
+                        storage_reader.clone(),
+                        storage_writer,
+                        header_channels,
+                        state_diff_channels,
+                        transaction_channels,
+                        config.storage.db_config.chain_id.clone(),
+                        peer_manager_command_sender,
+                        shared_highest_block.clone(),
+                        base_layer_checkpoint_source,
+                        header_marker_sender,
+                        recorded_sync_responses,
+                    )
+                    .boxed()
+                }
+            };
+            (None, Some(p2p_sync_client_future))
         }
-        (None, None) => (pending().boxed(), pending().boxed()),
+        (None, None) => (None, None),
     };
-    let sync_handle = tokio::spawn(sync_future);
-    let p2p_sync_client_handle = tokio::spawn(p2p_sync_client_future);
+    if let Some(sync_future) = sync_future {
+        supervisor.spawn_once(
+            Component::Sync,
+            sync_future.map_err(anyhow::Error::from).instrument(component_span("sync")),
+        );
+    }
+    if let Some(p2p_sync_client_future) = p2p_sync_client_future {
+        supervisor.spawn_once(
+            Component::P2PSync,
+            p2p_sync_client_future
+                .map_err(anyhow::Error::from)
+                .instrument(component_span("p2p_sync")),
+        );
+    }
 
-    let consensus_handle = if let Some(consensus_channels) = maybe_consensus_channels {
-        run_consensus(storage_reader.clone(), consensus_channels)?
-    } else {
-        tokio::spawn(pending())
-    };
+    if let Some(consensus_channels) = maybe_consensus_channels {
+        let consensus_handle = run_consensus(
+            &config.consensus,
+            storage_reader.clone(),
+            consensus_storage_writer,
+            shared_highest_block,
+            consensus_channels,
+            config.storage.db_config.path().join(CONSENSUS_WAL_FILE_NAME),
+            consensus_status,
+        )?;
+        supervisor
+            .spawn_once(Component::Consensus, consensus_handle.map(|result| anyhow::Ok(result??)));
+    }
 
-    tokio::select! {
-        res = storage_metrics_handle => {
-            error!("collecting storage metrics stopped.");
-            res?
-        }
-        res = server_handle_future => {
-            error!("RPC server stopped.");
-            res?
-        }
-        res = monitoring_server_handle => {
-            error!("Monitoring server stopped.");
-            res??
-        }
-        res = sync_handle => {
-            error!("Sync stopped.");
-            res??
-        }
-        res = p2p_sync_client_handle => {
-            error!("P2P Sync stopped.");
-            res??
-        }
-        res = p2p_sync_server_handle => {
-            error!("P2P Sync server stopped");
-            res?
-        }
-        res = network_handle => {
-            error!("Network stopped.");
-            res??
-        }
-        res = consensus_handle => {
-            error!("Consensus stopped.");
-            res??
-        }
-    };
-    error!("Task ended with unexpected Ok.");
-    return Ok(());
+    return supervisor.run().await;
 
+    #[cfg(feature = "central_sync")]
     async fn run_sync(
         configs: (SyncConfig, CentralSourceConfig, EthereumBaseLayerConfig),
+        sync_config_updates: watch::Receiver<SyncConfig>,
         shared_highest_block: Arc<RwLock<Option<BlockHashAndNumber>>>,
         pending_data: Arc<RwLock<PendingData>>,
         pending_classes: Arc<RwLock<PendingClasses>>,
+        genesis_hash: BlockHash,
         storage: (StorageReader, StorageWriter),
     ) -> Result<(), StateSyncError> {
         let (sync_config, central_config, base_layer_config) = configs;
         let (storage_reader, storage_writer) = storage;
         let central_source =
-            CentralSource::new(central_config.clone(), VERSION_FULL, storage_reader.clone())
+            CentralSource::new(central_config, VERSION_FULL, storage_reader.clone())
                 .map_err(CentralError::ClientCreation)?;
-        let pending_source = PendingSource::new(central_config, VERSION_FULL)
-            .map_err(CentralError::ClientCreation)?;
-        let base_layer_source = EthereumBaseLayerSource::new(base_layer_config)
+        let pending_source = central_source.pending_source();
+        let base_layer_source = EthereumBaseLayerSource::new(base_layer_config.clone())
             .map_err(|e| BaseLayerSourceError::BaseLayerSourceCreationError(e.to_string()))?;
         let mut sync = StateSync::new(
             sync_config,
@@ -299,92 +964,341 @@ async fn run_threads(config: NodeConfig) -> anyhow::Result<()> {
             central_source,
             pending_source,
             base_layer_source,
+            base_layer_config,
             storage_reader.clone(),
             storage_writer,
+            Some(sync_config_updates),
+            genesis_hash,
         );
         sync.run().await
     }
 
+    #[cfg(not(feature = "central_sync"))]
+    async fn run_sync(
+        _configs: (SyncConfig, CentralSourceConfig, EthereumBaseLayerConfig),
+        _sync_config_updates: watch::Receiver<SyncConfig>,
+        _shared_highest_block: Arc<RwLock<Option<BlockHashAndNumber>>>,
+        _pending_data: Arc<RwLock<PendingData>>,
+        _pending_classes: Arc<RwLock<PendingClasses>>,
+        _genesis_hash: BlockHash,
+        _storage: (StorageReader, StorageWriter),
+    ) -> Result<(), StateSyncError> {
+        pending().await
+    }
+
+    #[cfg(feature = "p2p_sync")]
+    #[allow(clippy::too_many_arguments)]
     async fn run_p2p_sync_client(
         p2p_sync_config: P2PSyncConfig,
         storage_reader: StorageReader,
         storage_writer: StorageWriter,
         header_channels: SqmrSubscriberChannels<HeaderQuery, DataOrFin<SignedBlockHeader>>,
-        state_diff_channels: SqmrSubscriberChannels<StateDiffQuery, DataOrFin<ThinStateDiff>>,
+        state_diff_channels: Vec<SqmrSubscriberChannels<StateDiffQuery, DataOrFin<ThinStateDiff>>>,
+        transaction_channels: Option<TransactionClientChannels>,
+        chain_id: ChainId,
+        peer_manager_command_sender: Option<UnboundedSender<PeerManagerCommand>>,
+        shared_highest_block: Arc<RwLock<Option<BlockHashAndNumber>>>,
+        base_layer_checkpoint_source: BaseLayerCheckpointSource,
+        header_marker_sender: watch::Sender<BlockNumber>,
+        recorded_responses: UnboundedReceiver<RecordedSqmrResponse>,
+    ) -> Result<(), P2PSyncError> {
+        let record_path = p2p_sync_config.record_path.clone();
+        let mut sync = P2PSync::new(
+            p2p_sync_config,
+            storage_reader,
+            storage_writer,
+            header_channels.prioritized_query_sender,
+            header_channels.response_receiver,
+            state_diff_channels
+                .into_iter()
+                .map(|channels| (channels.query_sender, channels.response_receiver))
+                .collect(),
+            peer_manager_command_sender,
+            shared_highest_block,
+            Some(base_layer_checkpoint_source),
+            header_marker_sender,
+        );
+        if let Some(transaction_channels) = transaction_channels {
+            sync = sync.with_transaction_channels(
+                Box::pin(transaction_channels.query_sender),
+                Box::pin(transaction_channels.response_receiver),
+                chain_id,
+            );
+        }
+        match record_path {
+            Some(record_path) => {
+                let record_future = record_responses(record_path, recorded_responses)
+                    .map_err(P2PSyncError::from);
+                try_join(sync.run(), record_future).await.map(|_| ())
+            }
+            None => sync.run().await,
+        }
+    }
+
+    #[cfg(feature = "p2p_sync")]
+    async fn run_p2p_sync_replay(
+        p2p_sync_config: P2PSyncConfig,
+        replay_path: PathBuf,
+        storage_reader: StorageReader,
+        storage_writer: StorageWriter,
+        shared_highest_block: Arc<RwLock<Option<BlockHashAndNumber>>>,
+        header_marker_sender: watch::Sender<BlockNumber>,
     ) -> Result<(), P2PSyncError> {
-        let sync = P2PSync::new(
+        run_replay(
             p2p_sync_config,
+            &replay_path,
             storage_reader,
             storage_writer,
+            shared_highest_block,
+            header_marker_sender,
+        )
+        .await
+    }
+
+    #[cfg(feature = "p2p_sync")]
+    async fn run_p2p_shadow_sync(
+        p2p_sync_config: P2PSyncConfig,
+        storage_reader: StorageReader,
+        header_channels: SqmrSubscriberChannels<HeaderQuery, DataOrFin<SignedBlockHeader>>,
+        state_diff_channels: SqmrSubscriberChannels<StateDiffQuery, DataOrFin<ThinStateDiff>>,
+        recorded_responses: UnboundedReceiver<RecordedSqmrResponse>,
+    ) -> Result<(), P2PSyncError> {
+        ShadowSync::new(
+            p2p_sync_config,
+            storage_reader,
             header_channels.query_sender,
             header_channels.response_receiver,
             state_diff_channels.query_sender,
             state_diff_channels.response_receiver,
-        );
-        sync.run().await
+            recorded_responses,
+        )
+        .run()
+        .await
+    }
+
+    #[cfg(not(feature = "p2p_sync"))]
+    #[allow(clippy::too_many_arguments)]
+    async fn run_p2p_sync_client(
+        _p2p_sync_config: P2PSyncConfig,
+        _storage_reader: StorageReader,
+        _storage_writer: StorageWriter,
+        _header_channels: SqmrSubscriberChannels<HeaderQuery, DataOrFin<SignedBlockHeader>>,
+        _state_diff_channels: Vec<SqmrSubscriberChannels<StateDiffQuery, DataOrFin<ThinStateDiff>>>,
+        _transaction_channels: Option<TransactionClientChannels>,
+        _chain_id: ChainId,
+        _peer_manager_command_sender: Option<UnboundedSender<PeerManagerCommand>>,
+        _shared_highest_block: Arc<RwLock<Option<BlockHashAndNumber>>>,
+        _base_layer_checkpoint_source: BaseLayerCheckpointSource,
+        _header_marker_sender: watch::Sender<BlockNumber>,
+        _recorded_responses: UnboundedReceiver<RecordedSqmrResponse>,
+    ) -> Result<(), P2PSyncError> {
+        pending().await
+    }
+
+    #[cfg(not(feature = "p2p_sync"))]
+    async fn run_p2p_shadow_sync(
+        _p2p_sync_config: P2PSyncConfig,
+        _storage_reader: StorageReader,
+        _header_channels: SqmrSubscriberChannels<HeaderQuery, DataOrFin<SignedBlockHeader>>,
+        _state_diff_channels: SqmrSubscriberChannels<StateDiffQuery, DataOrFin<ThinStateDiff>>,
+        _recorded_responses: UnboundedReceiver<RecordedSqmrResponse>,
+    ) -> Result<(), P2PSyncError> {
+        pending().await
+    }
+
+    #[cfg(not(feature = "p2p_sync"))]
+    async fn run_p2p_sync_replay(
+        _p2p_sync_config: P2PSyncConfig,
+        _replay_path: PathBuf,
+        _storage_reader: StorageReader,
+        _storage_writer: StorageWriter,
+        _shared_highest_block: Arc<RwLock<Option<BlockHashAndNumber>>>,
+        _header_marker_sender: watch::Sender<BlockNumber>,
+    ) -> Result<(), P2PSyncError> {
+        pending().await
     }
 }
 
+// The channels the p2p sync server receives the peers' queries through and advertises the node's
+// block ranges through.
+type SyncServerChannels = (
+    SqmrQueryReceiver<HeaderQuery, DataOrFin<SignedBlockHeader>>,
+    SqmrQueryReceiver<StateDiffQuery, DataOrFin<StateDiffChunk>>,
+    SqmrQueryReceiver<TransactionQuery, DataOrFin<(Transaction, Option<TransactionOutput>)>>,
+    SubscriberSender<BlockRangeAdvertisement>,
+);
+
+// The channels the combined block download of the p2p sync queries the transactions through.
+type TransactionClientChannels =
+    SqmrSubscriberChannels<TransactionQuery, DataOrFin<(Transaction, Option<TransactionOutput>)>>;
+
 type NetworkRunReturn = (
     BoxFuture<'static, Result<(), NetworkError>>,
     Option<(
         SqmrSubscriberChannels<HeaderQuery, DataOrFin<SignedBlockHeader>>,
-        SqmrSubscriberChannels<StateDiffQuery, DataOrFin<ThinStateDiff>>,
+        Vec<SqmrSubscriberChannels<StateDiffQuery, DataOrFin<ThinStateDiff>>>,
+        Option<TransactionClientChannels>,
     )>,
-    Option<(
-        SqmrQueryReceiver<HeaderQuery, DataOrFin<SignedBlockHeader>>,
-        SqmrQueryReceiver<StateDiffQuery, DataOrFin<StateDiffChunk>>,
-        SqmrQueryReceiver<TransactionQuery, DataOrFin<(Transaction, TransactionOutput)>>,
-    )>,
-    Option<BroadcastSubscriberChannels<ConsensusMessage>>,
+    Option<SyncServerChannels>,
+    Option<BroadcastSubscriberChannels<SignedConsensusMessage>>,
     String,
+    NetworkRegistrations,
+    ServedBytesByPeer,
+    NegotiatedProtocolsByPeer,
+    PeerIdentities,
+    PeerLatencies,
+    SessionPoolsUsage,
+    RecentNetworkEvents,
+    Option<UnboundedSender<PeerManagerCommand>>,
+    Option<BroadcastPublisher<MempoolTransaction>>,
 );
 
-fn run_network(config: Option<NetworkConfig>) -> anyhow::Result<NetworkRunReturn> {
+// The state diffs are downloaded through `num_state_diff_lanes` lanes, so that the state diffs of
+// different blocks can be downloaded in parallel. The protocols of the sync server and the
+// consensus topic are registered only if the node runs the sync server and consensus. If
+// `sync_response_recorder` is given, the responses to the sync's queries are sent to it as well.
+// The peers are pinged at the latest `ping_interval`. The transactions are queried by the sync
+// only if it downloads whole blocks (`combined_block_download`).
+#[allow(clippy::too_many_arguments)]
+fn run_network(
+    config: Option<NetworkConfig>,
+    chain_id: ChainId,
+    num_state_diff_lanes: usize,
+    broadcast_transactions: bool,
+    serve_sync_queries: bool,
+    run_consensus: bool,
+    sync_response_recorder: Option<UnboundedSender<RecordedSqmrResponse>>,
+    ping_interval: watch::Receiver<Duration>,
+    combined_block_download: bool,
+) -> anyhow::Result<NetworkRunReturn> {
     let Some(network_config) = config else {
-        return Ok((pending().boxed(), None, None, None, "".to_string()));
+        return Ok((
+            pending().boxed(),
+            None,
+            None,
+            None,
+            "".to_string(),
+            NetworkRegistrations::default(),
+            ServedBytesByPeer::default(),
+            NegotiatedProtocolsByPeer::default(),
+            PeerIdentities::default(),
+            PeerLatencies::default(),
+            SessionPoolsUsage::default(),
+            RecentNetworkEvents::default(),
+            None,
+            None,
+        ));
     };
-    let mut network_manager = network_manager::NetworkManager::new(network_config.clone());
-    let local_peer_id = network_manager.get_local_peer_id();
-    let header_client_channels =
-        network_manager.register_sqmr_subscriber(Protocol::SignedBlockHeader);
-    let state_diff_client_channels = network_manager.register_sqmr_subscriber(Protocol::StateDiff);
+    // Each lane has a query in flight, so the lanes above the limit of outbound sessions would only
+    // queue their queries. A session is left for the header queries, and for the transaction
+    // queries of the combined block download.
+    let num_sessions_of_other_protocols = if combined_block_download { 2 } else { 1 };
+    let num_state_diff_lanes = num_state_diff_lanes
+        .min(
+            network_config
+                .outbound_session_limits()
+                .max_sessions
+                .saturating_sub(num_sessions_of_other_protocols),
+        )
+        .max(1);
+    let mut network_manager_builder = NetworkManagerBuilder::new(network_config.clone(), chain_id)
+        .with_ping_interval_updates(ping_interval);
+    let header_client_channels = network_manager_builder
+        .register_sqmr_subscriber_with_data_availability_hints(Protocol::SignedBlockHeader)?;
+    let state_diff_client_channels = network_manager_builder
+        .register_sqmr_subscriber_lanes(Protocol::StateDiff, num_state_diff_lanes)?;
+    network_manager_builder
+        .register_query_block_range::<HeaderQuery>(Protocol::SignedBlockHeader)?;
+    network_manager_builder.register_query_block_range::<StateDiffQuery>(Protocol::StateDiff)?;
+    let transaction_client_channels = if combined_block_download {
+        network_manager_builder
+            .register_query_block_range::<TransactionQuery>(Protocol::Transaction)?;
+        Some(network_manager_builder.register_sqmr_subscriber(Protocol::Transaction)?)
+    } else {
+        None
+    };
+    if let Some(sync_response_recorder) = sync_response_recorder {
+        network_manager_builder
+            .record_sqmr_responses(Protocol::SignedBlockHeader, sync_response_recorder.clone())?;
+        network_manager_builder.record_sqmr_responses(Protocol::StateDiff, sync_response_recorder)?;
+    }
 
-    let header_server_channel =
-        network_manager.register_sqmr_protocol_server(Protocol::SignedBlockHeader);
-    let state_diff_server_channel =
-        network_manager.register_sqmr_protocol_server(Protocol::StateDiff);
-    let transaction_server_channel =
-        network_manager.register_sqmr_protocol_server(Protocol::Transaction);
+    let sync_server_channels = if serve_sync_queries {
+        Some((
+            network_manager_builder.register_sqmr_protocol_server(Protocol::SignedBlockHeader)?,
+            network_manager_builder.register_sqmr_protocol_server(Protocol::StateDiff)?,
+            network_manager_builder.register_sqmr_protocol_server(Protocol::Transaction)?,
+            network_manager_builder
+                .register_block_range_advertiser(BLOCK_RANGE_ADVERTISEMENT_BUFFER_SIZE)?,
+        ))
+    } else {
+        None
+    };
 
-    let consensus_channels =
-        network_manager.register_broadcast_subscriber(Topic::new("consensus"), 100)?;
+    let consensus_channels = if run_consensus {
+        Some(network_manager_builder.register_broadcast_subscriber(CONSENSUS_TOPIC, 100)?)
+    } else {
+        None
+    };
+    let transaction_publisher = if broadcast_transactions {
+        Some(network_manager_builder.register_broadcast_publisher(
+            MEMPOOL_TRANSACTION_TOPIC,
+            TRANSACTION_BROADCAST_BUFFER_SIZE,
+        )?)
+    } else {
+        None
+    };
 
+    let (network_manager, network_registrations) = network_manager_builder.build()?;
+    let local_peer_id = network_manager.get_local_peer_id();
+    let served_bytes_by_peer = network_manager.served_bytes_by_peer();
+    let negotiated_protocols_by_peer = network_manager.negotiated_protocols_by_peer();
+    let peer_identities = network_manager.peer_identities();
+    let peer_latencies = network_manager.peer_latencies();
+    let session_pools_usage = network_manager.session_pools_usage();
+    let recent_network_events = network_manager.recent_network_events();
+    let peer_manager_command_sender = network_manager.peer_manager_command_sender();
     Ok((
-        network_manager.run().boxed(),
-        Some((header_client_channels, state_diff_client_channels)),
-        Some((header_server_channel, state_diff_server_channel, transaction_server_channel)),
-        Some(consensus_channels),
+        run_network_manager(network_manager, network_config.max_network_restarts).boxed(),
+        Some((header_client_channels, state_diff_client_channels, transaction_client_channels)),
+        sync_server_channels,
+        consensus_channels,
         local_peer_id,
+        network_registrations,
+        served_bytes_by_peer,
+        negotiated_protocols_by_peer,
+        peer_identities,
+        peer_latencies,
+        session_pools_usage,
+        recent_network_events,
+        Some(peer_manager_command_sender),
+        transaction_publisher,
     ))
 }
 
-// TODO(yair): add dynamic level filtering.
-// TODO(dan): filter out logs from dependencies (happens when RUST_LOG=DEBUG)
-// TODO(yair): define and implement configurable filtering.
-fn configure_tracing() {
-    let fmt_layer = fmt::layer().compact().with_target(false);
-    let level_filter_layer =
-        EnvFilter::builder().with_default_directive(DEFAULT_LEVEL.into()).from_env_lossy();
-
-    // This sets a single subscriber to all of the threads. We may want to implement different
-    // subscriber for some threads and use set_global_default instead of init.
-    tracing_subscriber::registry().with(fmt_layer).with(level_filter_layer).init();
+// Restarts the network manager after recoverable errors, up to `max_restarts` times. The network
+// manager keeps the channels of the node's components across restarts, so they aren't aware of
+// them.
+async fn run_network_manager(
+    mut network_manager: NetworkManager,
+    max_restarts: usize,
+) -> Result<(), NetworkError> {
+    let mut num_restarts = 0;
+    loop {
+        let error = network_manager.run_until_error().await;
+        if !error.is_recoverable() || num_restarts >= max_restarts {
+            return Err(error);
+        }
+        num_restarts += 1;
+        warn!("Restarting the network ({num_restarts}/{max_restarts}) after an error: {error}");
+        network_manager.restart()?;
+    }
 }
 
+// The interval is read after each update, so that it can change while the node is running.
 fn spawn_storage_metrics_collector(
     storage_reader: StorageReader,
-    update_interval: Duration,
+    update_interval: watch::Receiver<Duration>,
 ) -> JoinHandle<()> {
     tokio::spawn(
         async move {
@@ -392,6 +1306,7 @@ fn spawn_storage_metrics_collector(
                 if let Err(error) = update_storage_metrics(&storage_reader) {
                     warn!("Failed to update storage metrics: {error}");
                 }
+                let update_interval = *update_interval.borrow();
                 tokio::time::sleep(update_interval).await;
             }
         }
@@ -401,23 +1316,39 @@ fn spawn_storage_metrics_collector(
 
 #[tokio::main]
 async fn main() -> anyhow::Result<()> {
-    let config = NodeConfig::load_and_process(args().collect());
-    if let Err(ConfigError::CommandInput(clap_err)) = config {
-        clap_err.exit();
+    let args: Vec<String> = args().collect();
+    if args.get(1).map(String::as_str) == Some(EXPORT_COMMAND) {
+        configure_tracing(&LoggingConfig::default())?;
+        return Ok(run_export_command(args[1..].to_vec())?);
     }
 
-    configure_tracing();
+    let config = match NodeConfig::load_and_process(args.clone()) {
+        Ok(config) => config,
+        Err(ConfigError::CommandInput(clap_err)) => clap_err.exit(),
+        Err(err) => {
+            // The logging config wasn't loaded, so the error is logged in the default format.
+            configure_tracing(&LoggingConfig::default())?;
+            error!("Failed loading the config: {err}");
+            exit(1);
+        }
+    };
+    configure_tracing(&config.logging)?;
 
-    let config = config?;
     if let Err(errors) = config_validate(&config) {
         error!("{}", errors);
         exit(1);
     }
+    for warning in
+        chain_consistency_warnings(&config).into_iter().chain(deprecated_param_warnings(&config))
+    {
+        warn!("{warning}");
+    }
 
     COLLECT_PROFILING_METRICS
         .set(config.collect_profiling_metrics)
         .expect("This should be the first and only time we set this value.");
 
     info!("Booting up.");
-    run_threads(config).await
+    let config_reloader = ConfigReloader::new(args, config.clone())?;
+    run_threads(config, Some(config_reloader)).await
 }
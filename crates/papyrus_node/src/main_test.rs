@@ -1,16 +1,43 @@
 use std::time::Duration;
 
 use metrics_exporter_prometheus::PrometheusBuilder;
+#[cfg(feature = "consensus")]
+use papyrus_common::BlockHashAndNumber;
+use papyrus_config::validators::config_validate;
+#[cfg(feature = "consensus")]
+use papyrus_consensus::config::ConsensusConfig;
+use papyrus_network::NetworkConfig;
+use papyrus_node::config::components::ComponentsConfig;
 use papyrus_node::config::NodeConfig;
+use papyrus_p2p_sync::P2PSyncConfig;
+#[cfg(feature = "consensus")]
+use papyrus_storage::header::HeaderStorageWriter;
+#[cfg(feature = "consensus")]
+use papyrus_storage::test_utils::get_test_storage;
+#[cfg(feature = "consensus")]
+use papyrus_storage::StorageReader;
 use papyrus_storage::{open_storage, StorageConfig};
+#[cfg(feature = "consensus")]
+use starknet_api::block::{BlockHeader, BlockNumber};
 use tempfile::TempDir;
 use test_utils::prometheus_is_contained;
+use tokio::sync::watch;
+#[cfg(feature = "consensus")]
+use tokio::sync::RwLock;
 
+#[cfg(feature = "consensus")]
+use crate::{consensus_start_height, wait_for_sync_to_catch_up};
 use crate::{run_threads, spawn_storage_metrics_collector};
 
+#[cfg(feature = "consensus")]
+const N_SYNCED_BLOCKS: u64 = 50;
+// How long the node should keep running after it boots.
+const BOOT_DURATION: Duration = Duration::from_secs(3);
+
 // The mission of this test is to ensure that if an error is returned from one of the spawned tasks,
 // the node will stop, and this error will be returned. This is done by checking the case of an
 // illegal central URL, which will cause the sync task to return an error.
+#[cfg(feature = "central_sync")]
 #[tokio::test]
 async fn run_threads_stop() {
     let mut config = NodeConfig::default();
@@ -19,7 +46,7 @@ async fn run_threads_stop() {
 
     // Error when not supplying legal central URL.
     config.central.url = "_not_legal_url".to_string();
-    let error = run_threads(config).await.expect_err("Should be an error.");
+    let error = run_threads(config, None).await.expect_err("Should be an error.");
     assert_eq!("relative URL without a base", error.to_string());
 }
 
@@ -35,9 +62,107 @@ async fn storage_metrics_collector() {
 
     assert!(prometheus_is_contained(handle.render(), "storage_free_pages_number", &[]).is_none());
 
-    spawn_storage_metrics_collector(storage_reader, Duration::from_secs(1));
+    spawn_storage_metrics_collector(storage_reader, watch::channel(Duration::from_secs(1)).1);
     // To make sure the metrics in the spawned thread are updated.
     tokio::time::sleep(Duration::from_millis(1)).await;
 
     assert!(prometheus_is_contained(handle.render(), "storage_free_pages_number", &[]).is_some());
 }
+
+#[cfg(feature = "consensus")]
+fn storage_with_synced_headers() -> (StorageReader, TempDir) {
+    let ((storage_reader, mut storage_writer), temp_dir) = get_test_storage();
+    let mut txn = storage_writer.begin_rw_txn().unwrap();
+    for block_number in (0..N_SYNCED_BLOCKS).map(BlockNumber) {
+        txn = txn
+            .append_header(block_number, &BlockHeader { block_number, ..Default::default() })
+            .unwrap();
+    }
+    txn.commit().unwrap();
+    (storage_reader, temp_dir)
+}
+
+#[cfg(feature = "consensus")]
+#[test]
+fn consensus_starts_from_the_first_unsynced_height() {
+    let (storage_reader, _temp_dir) = storage_with_synced_headers();
+
+    let config = ConsensusConfig::default();
+    assert_eq!(
+        consensus_start_height(&config, &storage_reader).unwrap(),
+        BlockNumber(N_SYNCED_BLOCKS)
+    );
+
+    let config = ConsensusConfig { start_height: Some(BlockNumber(7)), ..Default::default() };
+    assert_eq!(consensus_start_height(&config, &storage_reader).unwrap(), BlockNumber(7));
+}
+
+#[cfg(feature = "consensus")]
+#[tokio::test]
+async fn consensus_waits_for_sync_to_catch_up() {
+    let (storage_reader, _temp_dir) = storage_with_synced_headers();
+    let config = ConsensusConfig {
+        max_blocks_behind_to_start: 10,
+        start_poll_interval: Duration::from_millis(1),
+        ..Default::default()
+    };
+    let highest_block = |block_number| {
+        RwLock::new(Some(BlockHashAndNumber {
+            block_number: BlockNumber(block_number),
+            ..Default::default()
+        }))
+    };
+
+    // Nothing is known about the network yet.
+    wait_for_sync_to_catch_up(&config, &storage_reader, &RwLock::new(None)).await.unwrap();
+    // Blocks 50 to 59 are missing.
+    wait_for_sync_to_catch_up(&config, &storage_reader, &highest_block(N_SYNCED_BLOCKS + 9))
+        .await
+        .unwrap();
+    // Blocks 50 to 60 are missing.
+    tokio::time::timeout(
+        Duration::from_millis(50),
+        wait_for_sync_to_catch_up(&config, &storage_reader, &highest_block(N_SYNCED_BLOCKS + 10)),
+    )
+    .await
+    .expect_err("Consensus should wait for the missing blocks to be synced.");
+}
+
+// A config that runs every component compiled into the node and doesn't depend on any service
+// outside of it. The central sync needs the feeder gateway, so it doesn't run.
+fn minimal_config() -> (NodeConfig, TempDir) {
+    let temp_dir = TempDir::new().unwrap();
+    let mut config = NodeConfig {
+        sync: None,
+        components: ComponentsConfig::compiled_in(),
+        ..Default::default()
+    };
+    config.storage.db_config.path_prefix = temp_dir.path().into();
+    if cfg!(any(feature = "p2p_sync", feature = "p2p_server", feature = "consensus")) {
+        config.network = Some(NetworkConfig { tcp_port: 0, quic_port: 0, ..Default::default() });
+    }
+    if cfg!(feature = "p2p_sync") {
+        config.p2p_sync = Some(P2PSyncConfig::default());
+    }
+    config.monitoring_gateway.server_address = "127.0.0.1:0".to_string();
+    #[cfg(feature = "rpc")]
+    {
+        config.rpc.server_address = "127.0.0.1:0".to_string();
+    }
+    (config, temp_dir)
+}
+
+// CI runs this test with each of the feature profiles, to check that the node boots with any of
+// them.
+#[tokio::test]
+async fn node_boots_with_the_compiled_in_components() {
+    let (config, _temp_dir) = minimal_config();
+    config_validate(&config).unwrap();
+
+    let node = tokio::spawn(run_threads(config, None));
+    tokio::time::sleep(BOOT_DURATION).await;
+    if node.is_finished() {
+        panic!("The node stopped while booting: {:?}", node.await);
+    }
+    node.abort();
+}
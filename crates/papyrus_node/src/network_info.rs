@@ -0,0 +1,121 @@
+//! Reports the state of the p2p network to the JSON-RPC server's papyrus namespace. The state is
+//! read from the same handles the monitoring gateway reads, so the two report the same peers.
+
+#[cfg(test)]
+#[path = "network_info_test.rs"]
+mod network_info_test;
+
+use std::collections::BTreeMap;
+
+use anyhow::anyhow;
+use async_trait::async_trait;
+use futures::channel::mpsc::UnboundedSender;
+use futures::channel::oneshot;
+use papyrus_network::network_manager::{
+    NegotiatedProtocolsByPeer,
+    PeerIdentities,
+    PeerManagerCommand,
+    ServedBytesByPeer,
+    SessionPoolUsage,
+    SessionPoolsUsage,
+};
+use papyrus_rpc::{NetworkInfoReader, PeerInfo, SessionPoolStats, SessionPoolsStats};
+
+/// A [`NetworkInfoReader`] that merges the peer manager's state with the counters the network
+/// manager keeps for each peer.
+pub struct PeerManagerNetworkInfoReader {
+    peer_manager_command_sender: UnboundedSender<PeerManagerCommand>,
+    served_bytes_by_peer: ServedBytesByPeer,
+    negotiated_protocols_by_peer: NegotiatedProtocolsByPeer,
+    peer_identities: PeerIdentities,
+    session_pools_usage: SessionPoolsUsage,
+}
+
+impl PeerManagerNetworkInfoReader {
+    pub fn new(
+        peer_manager_command_sender: UnboundedSender<PeerManagerCommand>,
+        served_bytes_by_peer: ServedBytesByPeer,
+        negotiated_protocols_by_peer: NegotiatedProtocolsByPeer,
+        peer_identities: PeerIdentities,
+        session_pools_usage: SessionPoolsUsage,
+    ) -> Self {
+        Self {
+            peer_manager_command_sender,
+            served_bytes_by_peer,
+            negotiated_protocols_by_peer,
+            peer_identities,
+            session_pools_usage,
+        }
+    }
+}
+
+#[async_trait]
+impl NetworkInfoReader for PeerManagerNetworkInfoReader {
+    // A peer may be missing from the peer manager, e.g. if it only queried the node, so the peers
+    // are the union of the peers of all the sources.
+    async fn get_peers(&self) -> anyhow::Result<Vec<PeerInfo>> {
+        let (state_sender, state_receiver) = oneshot::channel();
+        self.peer_manager_command_sender
+            .unbounded_send(PeerManagerCommand::GetState(state_sender))
+            .map_err(|_| anyhow!("The node's p2p network isn't running."))?;
+        let peer_manager_state =
+            state_receiver.await.map_err(|_| anyhow!("The node's p2p network isn't running."))?;
+
+        let mut peers = BTreeMap::<String, PeerInfo>::new();
+        for peer_state in peer_manager_state.peers {
+            let peer = peer_entry(&mut peers, peer_state.peer_id.to_string());
+            peer.addresses = vec![peer_state.multiaddr.to_string()];
+            peer.num_connections = peer_state.num_connections;
+            peer.blocked = peer_state.blocked_until.is_some() || peer_state.manually_banned;
+        }
+        for (peer_id, served_bytes) in self
+            .served_bytes_by_peer
+            .lock()
+            .expect("Served bytes lock should not be poisoned")
+            .iter()
+        {
+            peer_entry(&mut peers, peer_id.to_string()).served_bytes = *served_bytes;
+        }
+        for (peer_id, negotiated_protocols) in self
+            .negotiated_protocols_by_peer
+            .lock()
+            .expect("Negotiated protocols lock should not be poisoned")
+            .iter()
+        {
+            let mut negotiated_protocols = negotiated_protocols
+                .values()
+                .map(|protocol_name| protocol_name.to_string())
+                .collect::<Vec<_>>();
+            negotiated_protocols.sort();
+            peer_entry(&mut peers, peer_id.to_string()).negotiated_protocols = negotiated_protocols;
+        }
+        for (peer_id, peer_identity) in
+            self.peer_identities.lock().expect("Peer identities lock should not be poisoned").iter()
+        {
+            let peer = peer_entry(&mut peers, peer_id.to_string());
+            peer.agent_version = Some(peer_identity.agent_version.clone());
+            peer.protocols = peer_identity.protocols.clone();
+        }
+        Ok(peers.into_values().collect())
+    }
+
+    fn get_session_pools(&self) -> SessionPoolsStats {
+        let session_pools =
+            *self.session_pools_usage.lock().expect("Session pools lock should not be poisoned");
+        SessionPoolsStats {
+            inbound: session_pool_stats(session_pools.inbound),
+            outbound: session_pool_stats(session_pools.outbound),
+        }
+    }
+}
+
+fn session_pool_stats(usage: SessionPoolUsage) -> SessionPoolStats {
+    SessionPoolStats {
+        num_active_sessions: usage.num_active_sessions,
+        max_sessions: usage.max_sessions,
+    }
+}
+
+fn peer_entry(peers: &mut BTreeMap<String, PeerInfo>, peer_id: String) -> &mut PeerInfo {
+    peers.entry(peer_id.clone()).or_insert_with(|| PeerInfo { peer_id, ..Default::default() })
+}
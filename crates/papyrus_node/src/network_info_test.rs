@@ -0,0 +1,120 @@
+use futures::channel::mpsc::unbounded;
+use futures::StreamExt;
+use papyrus_network::network_manager::{
+    NegotiatedProtocolsByPeer,
+    PeerIdentities,
+    PeerIdentity,
+    PeerManagerCommand,
+    PeerManagerState,
+    PeerState,
+    ServedBytesByPeer,
+    SessionPoolUsage,
+    SessionPools,
+    SessionPoolsUsage,
+};
+use papyrus_rpc::{NetworkInfoReader, PeerInfo, SessionPoolStats, SessionPoolsStats};
+
+use super::PeerManagerNetworkInfoReader;
+
+const KNOWN_PEER_ID: &str = "12D3KooW9tHTtS3inCZiYykw4u5G4frbjVFqhkmJX12gSNCVeH3e";
+// A peer that queried the node but isn't known to the peer manager.
+const QUERYING_PEER_ID: &str = "12D3KooW9xCm2jWjNVrwh51SWCQBMYdMyeU3NpT85QhLVkF6PcNM";
+const ADDRESS: &str = "/ip4/127.0.0.1/tcp/10000";
+
+#[tokio::test]
+async fn peers_are_merged_from_all_sources() {
+    let (peer_manager_command_sender, mut peer_manager_command_receiver) = unbounded();
+    let served_bytes_by_peer = ServedBytesByPeer::default();
+    served_bytes_by_peer.lock().unwrap().insert(QUERYING_PEER_ID.parse().unwrap(), 100);
+    let negotiated_protocols_by_peer = NegotiatedProtocolsByPeer::default();
+    let peer_identities = PeerIdentities::default();
+    peer_identities.lock().unwrap().insert(
+        KNOWN_PEER_ID.parse().unwrap(),
+        PeerIdentity {
+            agent_version: "papyrus".to_owned(),
+            protocols: vec!["/ipfs/id/1.0.0".to_owned()],
+        },
+    );
+    let network_info_reader = PeerManagerNetworkInfoReader::new(
+        peer_manager_command_sender,
+        served_bytes_by_peer,
+        negotiated_protocols_by_peer,
+        peer_identities,
+        SessionPoolsUsage::default(),
+    );
+
+    let peer_manager = async move {
+        let Some(PeerManagerCommand::GetState(state_sender)) =
+            peer_manager_command_receiver.next().await
+        else {
+            panic!("Expected a GetState command");
+        };
+        state_sender
+            .send(PeerManagerState {
+                peers: vec![PeerState {
+                    peer_id: KNOWN_PEER_ID.parse().unwrap(),
+                    multiaddr: ADDRESS.parse().unwrap(),
+                    blocked_until: None,
+                    manually_banned: true,
+                    num_connections: 1,
+                    assigned_sessions: vec![],
+                }],
+                manual_bans: vec![],
+            })
+            .unwrap();
+    };
+    let (peers, ()) = tokio::join!(network_info_reader.get_peers(), peer_manager);
+
+    let mut expected_peers = vec![
+        PeerInfo {
+            peer_id: KNOWN_PEER_ID.to_owned(),
+            addresses: vec![ADDRESS.to_owned()],
+            protocols: vec!["/ipfs/id/1.0.0".to_owned()],
+            agent_version: Some("papyrus".to_owned()),
+            num_connections: 1,
+            blocked: true,
+            ..Default::default()
+        },
+        PeerInfo { peer_id: QUERYING_PEER_ID.to_owned(), served_bytes: 100, ..Default::default() },
+    ];
+    expected_peers.sort_by(|a, b| a.peer_id.cmp(&b.peer_id));
+    assert_eq!(peers.unwrap(), expected_peers);
+}
+
+#[tokio::test]
+async fn get_peers_fails_if_the_network_stopped() {
+    let (peer_manager_command_sender, _) = unbounded();
+    let network_info_reader = PeerManagerNetworkInfoReader::new(
+        peer_manager_command_sender,
+        ServedBytesByPeer::default(),
+        NegotiatedProtocolsByPeer::default(),
+        PeerIdentities::default(),
+        SessionPoolsUsage::default(),
+    );
+    assert!(network_info_reader.get_peers().await.is_err());
+}
+
+#[test]
+fn session_pools_are_read_from_the_network_manager() {
+    let (peer_manager_command_sender, _) = unbounded();
+    let session_pools_usage = SessionPoolsUsage::default();
+    let network_info_reader = PeerManagerNetworkInfoReader::new(
+        peer_manager_command_sender,
+        ServedBytesByPeer::default(),
+        NegotiatedProtocolsByPeer::default(),
+        PeerIdentities::default(),
+        session_pools_usage.clone(),
+    );
+    *session_pools_usage.lock().unwrap() = SessionPools {
+        inbound: SessionPoolUsage { num_active_sessions: 3, max_sessions: 100 },
+        outbound: SessionPoolUsage { num_active_sessions: 10, max_sessions: 10 },
+    };
+
+    assert_eq!(
+        network_info_reader.get_session_pools(),
+        SessionPoolsStats {
+            inbound: SessionPoolStats { num_active_sessions: 3, max_sessions: 100 },
+            outbound: SessionPoolStats { num_active_sessions: 10, max_sessions: 10 },
+        }
+    );
+}
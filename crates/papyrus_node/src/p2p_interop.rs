@@ -0,0 +1,375 @@
+//! An interoperability harness that checks a peer running another Starknet p2p implementation
+//! against the sync protocols, without a storage.
+//!
+//! As a client, the harness sends a scripted battery of queries to the peer and validates the
+//! responses against the expected values of a fixtures file. As a server under test, it answers
+//! the queries of the peer with a Fin and checks that the queries conform to the spec. Both modes
+//! produce a report with a pass or fail result per capability.
+
+#[cfg(test)]
+#[path = "p2p_interop_test.rs"]
+mod p2p_interop_test;
+
+use std::fmt::Display;
+use std::time::Duration;
+
+use futures::channel::mpsc::SendError;
+use futures::{Sink, SinkExt, Stream, StreamExt};
+use libp2p::PeerId;
+use papyrus_network::Protocol;
+use papyrus_p2p_sync::{unite_and_validate_state_diff_parts, QueryClient, Response};
+use papyrus_protobuf::sync::{BlockHashOrNumber, DataOrFin, Direction, Query, SignedBlockHeader};
+use serde::{Deserialize, Serialize};
+use starknet_api::block::{BlockHash, BlockHeader, BlockNumber};
+use starknet_api::core::StateDiffCommitment;
+use starknet_api::state::ThinStateDiff;
+use starknet_api::transaction::{Transaction, TransactionOutput};
+
+/// The expected values of the peer's chain that the client battery checks the responses against.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct InteropFixtures {
+    pub headers: HeadersFixture,
+    pub state_diff: StateDiffFixture,
+    pub transactions: TransactionsFixture,
+    /// The limit of the over-limit query, which should be above the number of blocks the peer
+    /// sends in a single response.
+    pub over_limit_query_limit: u64,
+}
+
+/// A range of consecutive blocks, given by their hashes.
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct HeadersFixture {
+    pub start_block_number: BlockNumber,
+    pub block_hashes: Vec<BlockHash>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct StateDiffFixture {
+    pub block_number: BlockNumber,
+    pub length: usize,
+    pub commitment: Option<StateDiffCommitment>,
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct TransactionsFixture {
+    pub block_number: BlockNumber,
+    pub num_transactions: usize,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum Capability {
+    HeaderRange,
+    StateDiff,
+    Transactions,
+    DescendingHeaders,
+    OverLimitQuery,
+    HeadersByHash,
+}
+
+#[derive(Clone, Copy, Debug, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum CheckStatus {
+    Passed,
+    Failed,
+    /// The peer didn't exercise the capability while the harness ran.
+    NotExercised,
+}
+
+#[derive(Clone, Debug, Serialize, PartialEq, Eq)]
+pub struct CapabilityReport {
+    pub capability: Capability,
+    pub status: CheckStatus,
+    /// Why the check failed. Empty if it passed.
+    pub details: String,
+}
+
+/// The result of the client battery.
+#[derive(Clone, Debug, Serialize, PartialEq, Eq)]
+pub struct ClientReport {
+    pub capabilities: Vec<CapabilityReport>,
+}
+
+impl ClientReport {
+    pub fn passed(&self) -> bool {
+        self.capabilities.iter().all(|capability| capability.status == CheckStatus::Passed)
+    }
+}
+
+/// The queries the peer sent on a protocol while the harness was the server under test.
+#[derive(Clone, Debug, Serialize, PartialEq, Eq)]
+pub struct ProtocolQueriesReport {
+    pub protocol: Protocol,
+    pub status: CheckStatus,
+    pub num_queries: usize,
+    /// The queries that don't conform to the spec, with the reason.
+    pub violations: Vec<String>,
+}
+
+/// The result of the server under test mode.
+#[derive(Clone, Debug, Serialize, PartialEq, Eq)]
+pub struct ServerReport {
+    pub protocols: Vec<ProtocolQueriesReport>,
+}
+
+impl ServerReport {
+    /// The protocols the peer didn't query don't fail the report.
+    pub fn passed(&self) -> bool {
+        self.protocols.iter().all(|protocol| protocol.status != CheckStatus::Failed)
+    }
+}
+
+/// Runs the battery of queries against the peer the clients send their queries to. The queries
+/// are sent one after the other, and a failed query doesn't stop the rest of the battery.
+pub async fn run_client_battery<
+    HeaderQuerySender,
+    HeaderResponseReceiver,
+    StateDiffQuerySender,
+    StateDiffResponseReceiver,
+    TransactionQuerySender,
+    TransactionResponseReceiver,
+>(
+    fixtures: &InteropFixtures,
+    header_client: &mut QueryClient<HeaderQuerySender, HeaderResponseReceiver>,
+    state_diff_client: &mut QueryClient<StateDiffQuerySender, StateDiffResponseReceiver>,
+    transaction_client: &mut QueryClient<TransactionQuerySender, TransactionResponseReceiver>,
+) -> ClientReport
+where
+    HeaderQuerySender: Sink<Query, Error = SendError> + Unpin,
+    HeaderResponseReceiver: Stream<Item = Response<SignedBlockHeader>> + Unpin,
+    StateDiffQuerySender: Sink<Query, Error = SendError> + Unpin,
+    StateDiffResponseReceiver: Stream<Item = Response<ThinStateDiff>> + Unpin,
+    TransactionQuerySender: Sink<Query, Error = SendError> + Unpin,
+    TransactionResponseReceiver:
+        Stream<Item = Response<(Transaction, Option<TransactionOutput>)>> + Unpin,
+{
+    let headers_fixture = &fixtures.headers;
+    let num_blocks = u64::try_from(headers_fixture.block_hashes.len())
+        .expect("The number of blocks should fit in a u64");
+    let last_block_number =
+        BlockNumber((headers_fixture.start_block_number.0 + num_blocks).saturating_sub(1));
+
+    let header_range = header_client
+        .query(
+            block_query(headers_fixture.start_block_number, Direction::Forward, num_blocks),
+            None,
+        )
+        .await
+        .map_err(|error| error.to_string())
+        .and_then(|headers| check_headers(&headers, headers_fixture, Some(num_blocks)));
+
+    let descending_headers = header_client
+        .query(block_query(last_block_number, Direction::Backward, num_blocks), None)
+        .await
+        .map_err(|error| error.to_string())
+        .and_then(|mut headers| {
+            headers.reverse();
+            check_headers(&headers, headers_fixture, Some(num_blocks))
+        });
+
+    // The peer may end the response before the limit, but what it sends should start with the
+    // range.
+    let over_limit_query = header_client
+        .query(
+            block_query(
+                headers_fixture.start_block_number,
+                Direction::Forward,
+                fixtures.over_limit_query_limit,
+            ),
+            None,
+        )
+        .await
+        .map_err(|error| error.to_string())
+        .and_then(|headers| {
+            if headers.is_empty() {
+                return Err("The peer didn't send any header.".to_owned());
+            }
+            check_headers(&headers, headers_fixture, None)
+        });
+
+    let headers_by_hash = match headers_fixture.block_hashes.first() {
+        Some(first_block_hash) => header_client
+            .query_headers_by_hash(*first_block_hash, num_blocks)
+            .await
+            .map_err(|error| error.to_string())
+            .and_then(|headers| check_headers(&headers, headers_fixture, Some(num_blocks))),
+        None => Err("The headers fixture has no blocks.".to_owned()),
+    };
+
+    let state_diff = state_diff_client
+        .query(block_query(fixtures.state_diff.block_number, Direction::Forward, 1), None)
+        .await
+        .map_err(|error| error.to_string())
+        .and_then(|state_diff_parts| check_state_diff(state_diff_parts, &fixtures.state_diff));
+
+    let transactions = transaction_client
+        .query(block_query(fixtures.transactions.block_number, Direction::Forward, 1), None)
+        .await
+        .map_err(|error| error.to_string())
+        .and_then(|transactions| check_transactions(&transactions, &fixtures.transactions));
+
+    ClientReport {
+        capabilities: vec![
+            capability_report(Capability::HeaderRange, header_range),
+            capability_report(Capability::StateDiff, state_diff),
+            capability_report(Capability::Transactions, transactions),
+            capability_report(Capability::DescendingHeaders, descending_headers),
+            capability_report(Capability::OverLimitQuery, over_limit_query),
+            capability_report(Capability::HeadersByHash, headers_by_hash),
+        ],
+    }
+}
+
+fn block_query(start_block_number: BlockNumber, direction: Direction, limit: u64) -> Query {
+    Query { start_block: BlockHashOrNumber::Number(start_block_number), direction, limit, step: 1 }
+}
+
+fn capability_report(capability: Capability, result: Result<(), String>) -> CapabilityReport {
+    match result {
+        Ok(()) => CapabilityReport { capability, status: CheckStatus::Passed, details: "".into() },
+        Err(details) => CapabilityReport { capability, status: CheckStatus::Failed, details },
+    }
+}
+
+// Checks that the headers, in ascending order, start with the blocks of the fixture. The order and
+// the linkage of the headers were already checked by the query client.
+fn check_headers(
+    headers: &[SignedBlockHeader],
+    fixture: &HeadersFixture,
+    expected_num_headers: Option<u64>,
+) -> Result<(), String> {
+    let num_headers =
+        u64::try_from(headers.len()).expect("The number of headers should fit in a u64");
+    if let Some(expected_num_headers) = expected_num_headers {
+        if num_headers != expected_num_headers {
+            return Err(format!("Expected {expected_num_headers} headers, got {num_headers}."));
+        }
+    }
+    for (header, expected_block_hash) in headers.iter().zip(&fixture.block_hashes) {
+        let block_header = &header.block_header;
+        if block_header.block_hash != *expected_block_hash {
+            return Err(format!(
+                "Block {} has hash {}, expected {expected_block_hash}.",
+                block_header.block_number, block_header.block_hash
+            ));
+        }
+    }
+    if let Some(header) = headers.first() {
+        if header.block_header.block_number != fixture.start_block_number {
+            return Err(format!(
+                "The first header is of block {}, expected {}.",
+                header.block_header.block_number, fixture.start_block_number
+            ));
+        }
+    }
+    Ok(())
+}
+
+fn check_state_diff(
+    state_diff_parts: Vec<ThinStateDiff>,
+    fixture: &StateDiffFixture,
+) -> Result<(), String> {
+    // The parts are checked the same way the sync checks them, against a header that has the
+    // expected length and commitment.
+    let block_header = BlockHeader {
+        block_number: fixture.block_number,
+        state_diff_length: Some(fixture.length),
+        state_diff_commitment: fixture.commitment,
+        ..Default::default()
+    };
+    unite_and_validate_state_diff_parts(state_diff_parts, &block_header)
+        .map(|_state_diff| ())
+        .map_err(|error| error.to_string())
+}
+
+fn check_transactions(
+    transactions: &[(Transaction, Option<TransactionOutput>)],
+    fixture: &TransactionsFixture,
+) -> Result<(), String> {
+    if transactions.len() != fixture.num_transactions {
+        return Err(format!(
+            "Expected {} transactions in block {}, got {}.",
+            fixture.num_transactions,
+            fixture.block_number,
+            transactions.len()
+        ));
+    }
+    if transactions.iter().any(|(_transaction, output)| output.is_none()) {
+        return Err("A transaction was sent without its output.".to_owned());
+    }
+    Ok(())
+}
+
+/// Why a query received from the peer doesn't conform to the spec.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+pub enum QueryNonConformance {
+    #[error("The query couldn't be decoded: {0}")]
+    Malformed(String),
+    #[error("The query has limit 0.")]
+    ZeroLimit,
+    #[error("The query has step 0.")]
+    ZeroStep,
+}
+
+/// Checks a query that the peer sent against the spec.
+pub fn check_inbound_query(query: &Query) -> Result<(), QueryNonConformance> {
+    if query.limit == 0 {
+        return Err(QueryNonConformance::ZeroLimit);
+    }
+    if query.step == 0 {
+        return Err(QueryNonConformance::ZeroStep);
+    }
+    Ok(())
+}
+
+/// Checks the queries the peer sends on a protocol until `duration` passed, and answers each of
+/// them with a Fin. `to_query` extracts the block query from the protocol's query.
+pub async fn check_inbound_queries<
+    InboundQueries,
+    ProtocolQuery,
+    DecodeError,
+    ResponseSender,
+    Data,
+>(
+    protocol: Protocol,
+    inbound_queries: InboundQueries,
+    to_query: fn(&ProtocolQuery) -> &Query,
+    duration: Duration,
+) -> ProtocolQueriesReport
+where
+    InboundQueries: Stream<Item = (Result<ProtocolQuery, DecodeError>, ResponseSender, PeerId)>,
+    DecodeError: Display,
+    ResponseSender: Sink<DataOrFin<Data>> + Unpin,
+{
+    let mut num_queries = 0;
+    let mut violations = vec![];
+    let mut inbound_queries = Box::pin(inbound_queries.take_until(tokio::time::sleep(duration)));
+    while let Some((maybe_query, mut response_sender, peer_id)) = inbound_queries.next().await {
+        num_queries += 1;
+        let result = match &maybe_query {
+            Ok(query) => {
+                let query = to_query(query);
+                check_inbound_query(query)
+                    .map_err(|violation| format!("{query:?} from {peer_id}: {violation}"))
+            }
+            Err(error) => Err(format!(
+                "A query from {peer_id}: {}",
+                QueryNonConformance::Malformed(error.to_string())
+            )),
+        };
+        if let Err(violation) = result {
+            violations.push(violation);
+        }
+        // The session of a malformed query was already closed by the network manager, so the Fin
+        // might not be sent.
+        let _ = response_sender.send(DataOrFin(None)).await;
+    }
+    let status = if num_queries == 0 {
+        CheckStatus::NotExercised
+    } else if violations.is_empty() {
+        CheckStatus::Passed
+    } else {
+        CheckStatus::Failed
+    };
+    ProtocolQueriesReport { protocol, status, num_queries, violations }
+}
@@ -0,0 +1,285 @@
+use std::iter;
+use std::time::Duration;
+
+use futures::channel::mpsc;
+use futures::{SinkExt, StreamExt};
+use libp2p::PeerId;
+use papyrus_common::state_diff_commitment::{calculate_state_diff_commitment, StateDiffVersion};
+use papyrus_network::Protocol;
+use papyrus_p2p_sync::{QueryClient, Response};
+use papyrus_protobuf::sync::{
+    BlockHashOrNumber,
+    DataOrFin,
+    Direction,
+    HeaderQuery,
+    Query,
+    SignedBlockHeader,
+};
+use starknet_api::block::{BlockHash, BlockHeader, BlockNumber};
+use starknet_api::core::{ClassHash, ContractAddress, Nonce};
+use starknet_api::hash::StarkHash;
+use starknet_api::state::ThinStateDiff;
+use starknet_api::transaction::{
+    InvokeTransaction,
+    InvokeTransactionOutput,
+    InvokeTransactionV1,
+    Transaction,
+    TransactionOutput,
+};
+
+use crate::p2p_interop::{
+    check_inbound_queries,
+    run_client_battery,
+    Capability,
+    CheckStatus,
+    HeadersFixture,
+    InteropFixtures,
+    StateDiffFixture,
+    TransactionsFixture,
+};
+
+const BUFFER_SIZE: usize = 100;
+const TIMEOUT: Duration = Duration::from_secs(1);
+const NUM_BLOCKS: u64 = 10;
+// The number of blocks the fake peer sends in a single response.
+const MAX_RESPONSE_ITEMS: u64 = 4;
+const STATE_DIFF_BLOCK_NUMBER: BlockNumber = BlockNumber(5);
+const NUM_TRANSACTIONS: usize = 3;
+
+type FakePeerClient<T> = QueryClient<mpsc::Sender<Query>, mpsc::Receiver<Response<T>>>;
+
+fn block_hash(block_number: u64) -> BlockHash {
+    BlockHash(StarkHash::from(block_number + 1))
+}
+
+fn chain() -> Vec<SignedBlockHeader> {
+    (0..NUM_BLOCKS)
+        .map(|block_number| SignedBlockHeader {
+            block_header: BlockHeader {
+                block_number: BlockNumber(block_number),
+                block_hash: block_hash(block_number),
+                parent_hash: BlockHash(StarkHash::from(block_number)),
+                ..Default::default()
+            },
+            signatures: vec![],
+            data_availability: None,
+        })
+        .collect()
+}
+
+fn state_diff_parts() -> Vec<ThinStateDiff> {
+    vec![
+        ThinStateDiff {
+            nonces: [(ContractAddress::from(1_u128), Nonce(StarkHash::ONE))].into_iter().collect(),
+            ..Default::default()
+        },
+        ThinStateDiff {
+            deployed_contracts: [(ContractAddress::from(2_u128), ClassHash(StarkHash::TWO))]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        },
+    ]
+}
+
+fn transactions() -> Vec<(Transaction, Option<TransactionOutput>)> {
+    vec![
+        (
+            Transaction::Invoke(InvokeTransaction::V1(InvokeTransactionV1::default())),
+            Some(TransactionOutput::Invoke(InvokeTransactionOutput::default())),
+        );
+        NUM_TRANSACTIONS
+    ]
+}
+
+fn fixtures() -> InteropFixtures {
+    let mut state_diff = ThinStateDiff::default();
+    for part in state_diff_parts() {
+        state_diff.nonces.extend(part.nonces);
+        state_diff.deployed_contracts.extend(part.deployed_contracts);
+    }
+    InteropFixtures {
+        headers: HeadersFixture {
+            start_block_number: BlockNumber(2),
+            block_hashes: (2..5).map(block_hash).collect(),
+        },
+        state_diff: StateDiffFixture {
+            block_number: STATE_DIFF_BLOCK_NUMBER,
+            length: state_diff.len(),
+            commitment: Some(calculate_state_diff_commitment(&state_diff, StateDiffVersion::V0)),
+        },
+        transactions: TransactionsFixture {
+            block_number: BlockNumber(1),
+            num_transactions: NUM_TRANSACTIONS,
+        },
+        over_limit_query_limit: 1000,
+    }
+}
+
+// The block numbers the fake peer answers a query with.
+fn block_numbers(query: &Query, ignores_direction: bool) -> Vec<u64> {
+    let start = match query.start_block {
+        BlockHashOrNumber::Number(BlockNumber(start)) => start,
+        // A hash the peer doesn't know is answered with a Fin only.
+        BlockHashOrNumber::Hash(hash) => {
+            match (0..NUM_BLOCKS).find(|block_number| block_hash(*block_number) == hash) {
+                Some(start) => start,
+                None => return vec![],
+            }
+        }
+    };
+    let step = query.step;
+    let limit = usize::try_from(query.limit.min(MAX_RESPONSE_ITEMS)).unwrap();
+    let block_numbers: Vec<u64> = match query.direction {
+        Direction::Backward if !ignores_direction => {
+            iter::successors(Some(start), |block_number| block_number.checked_sub(step))
+                .take(limit)
+                .collect()
+        }
+        _ => iter::successors(Some(start), |block_number| Some(block_number + step))
+            .take(limit)
+            .collect(),
+    };
+    block_numbers.into_iter().filter(|block_number| *block_number < NUM_BLOCKS).collect()
+}
+
+// Answers each query with the data `answer` returns for it, followed by a Fin.
+fn spawn_fake_peer<T: Send + 'static>(
+    answer: impl Fn(&Query) -> Vec<T> + Send + 'static,
+) -> FakePeerClient<T> {
+    let (query_sender, mut query_receiver) = mpsc::channel::<Query>(BUFFER_SIZE);
+    let (mut response_sender, response_receiver) = mpsc::channel::<Response<T>>(BUFFER_SIZE);
+    tokio::spawn(async move {
+        while let Some(query) = query_receiver.next().await {
+            for data in answer(&query).into_iter().map(Some).chain(iter::once(None)) {
+                response_sender.send((Ok(DataOrFin(data)), Box::new(|| {}))).await.unwrap();
+            }
+        }
+    });
+    QueryClient::new(query_sender, response_receiver, "test data", TIMEOUT)
+}
+
+fn fake_peer_clients(
+    ignores_direction: bool,
+) -> (
+    FakePeerClient<SignedBlockHeader>,
+    FakePeerClient<ThinStateDiff>,
+    FakePeerClient<(Transaction, Option<TransactionOutput>)>,
+) {
+    let header_client = spawn_fake_peer(move |query| {
+        let chain = chain();
+        block_numbers(query, ignores_direction)
+            .into_iter()
+            .map(|block_number| chain[usize::try_from(block_number).unwrap()].clone())
+            .collect()
+    });
+    let state_diff_client = spawn_fake_peer(|query| {
+        if query.start_block == BlockHashOrNumber::Number(STATE_DIFF_BLOCK_NUMBER) {
+            state_diff_parts()
+        } else {
+            vec![]
+        }
+    });
+    let transaction_client = spawn_fake_peer(|_query| transactions());
+    (header_client, state_diff_client, transaction_client)
+}
+
+#[tokio::test]
+async fn conformant_peer_passes_the_battery() {
+    let (mut header_client, mut state_diff_client, mut transaction_client) =
+        fake_peer_clients(false);
+
+    let report = run_client_battery(
+        &fixtures(),
+        &mut header_client,
+        &mut state_diff_client,
+        &mut transaction_client,
+    )
+    .await;
+
+    assert!(report.passed(), "{report:?}");
+    assert_eq!(report.capabilities.len(), 6);
+}
+
+#[tokio::test]
+async fn each_capability_is_reported_separately() {
+    // The peer sends the blocks of descending queries in ascending order.
+    let (mut header_client, mut state_diff_client, mut transaction_client) =
+        fake_peer_clients(true);
+    let mut fixtures = fixtures();
+    fixtures.transactions.num_transactions += 1;
+
+    let report = run_client_battery(
+        &fixtures,
+        &mut header_client,
+        &mut state_diff_client,
+        &mut transaction_client,
+    )
+    .await;
+
+    let failed_capabilities = report
+        .capabilities
+        .iter()
+        .filter(|capability| capability.status == CheckStatus::Failed)
+        .map(|capability| capability.capability)
+        .collect::<Vec<_>>();
+    assert_eq!(failed_capabilities, vec![Capability::Transactions, Capability::DescendingHeaders]);
+}
+
+fn header_query(query: &HeaderQuery) -> &Query {
+    &query.0
+}
+
+#[tokio::test]
+async fn queries_of_the_peer_are_checked_against_the_spec() {
+    let valid_query = Query {
+        start_block: BlockHashOrNumber::Number(BlockNumber(0)),
+        direction: Direction::Forward,
+        limit: 5,
+        step: 1,
+    };
+    let zero_limit_query = Query { limit: 0, ..valid_query.clone() };
+    let (response_sender, mut response_receiver) =
+        mpsc::channel::<DataOrFin<SignedBlockHeader>>(BUFFER_SIZE);
+    let inbound_queries = futures::stream::iter([
+        (Ok(HeaderQuery(valid_query)), response_sender.clone(), PeerId::random()),
+        (Ok(HeaderQuery(zero_limit_query)), response_sender.clone(), PeerId::random()),
+        (Err("malformed"), response_sender, PeerId::random()),
+    ]);
+
+    let report = check_inbound_queries(
+        Protocol::SignedBlockHeader,
+        inbound_queries,
+        header_query,
+        Duration::from_secs(1),
+    )
+    .await;
+
+    assert_eq!(report.status, CheckStatus::Failed);
+    assert_eq!(report.num_queries, 3);
+    assert_eq!(report.violations.len(), 2, "{:?}", report.violations);
+    // Each query was answered with a Fin.
+    for _ in 0..3 {
+        assert_eq!(response_receiver.next().await, Some(DataOrFin(None)));
+    }
+}
+
+#[tokio::test]
+async fn protocol_the_peer_did_not_query_is_not_exercised() {
+    let inbound_queries = futures::stream::empty::<(
+        Result<HeaderQuery, String>,
+        mpsc::Sender<DataOrFin<SignedBlockHeader>>,
+        PeerId,
+    )>();
+
+    let report = check_inbound_queries(
+        Protocol::SignedBlockHeader,
+        inbound_queries,
+        header_query,
+        Duration::from_secs(1),
+    )
+    .await;
+
+    assert_eq!(report.status, CheckStatus::NotExercised);
+    assert_eq!(report.num_queries, 0);
+}
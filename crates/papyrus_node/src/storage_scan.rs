@@ -0,0 +1,344 @@
+//! Scans the storage for blocks whose data is missing or doesn't match their header, and optionally
+//! reverts them so that the sync downloads them again.
+//!
+//! A crash or a partial sync can leave blocks below the markers without some of their data. The
+//! scan checks every block below the header marker:
+//! - Below the body marker, that the number of transactions and events matches the header.
+//! - Below the state marker, that the state diff exists and its length matches the header.
+//! - Below the class marker, that the classes the state diff declares exist.
+//!
+//! The blocks are scanned in chunks, each in its own read transaction, so a long scan doesn't hold
+//! back the storage. The report of the scan is written after every chunk into
+//! [`STORAGE_SCAN_REPORT_FILE_NAME`] next to the storage, so an interrupted scan resumes from the
+//! block it reached.
+//!
+//! The storage can only revert its latest block, so the repair reverts all the blocks from the
+//! first block with missing data up to the header marker. The sync that runs then downloads them
+//! again.
+
+#[cfg(test)]
+#[path = "storage_scan_test.rs"]
+mod storage_scan_test;
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+
+use metrics::gauge;
+use papyrus_common::metrics as papyrus_metrics;
+use papyrus_config::dumping::{ser_param, SerializeConfig};
+use papyrus_config::{ParamPath, ParamPrivacyInput, SerializedParam};
+use papyrus_storage::base_layer::BaseLayerStorageWriter;
+use papyrus_storage::body::{BodyStorageReader, BodyStorageWriter};
+use papyrus_storage::class::ClassStorageReader;
+use papyrus_storage::db::RO;
+use papyrus_storage::header::{HeaderStorageReader, HeaderStorageWriter};
+use papyrus_storage::state::{StateStorageReader, StateStorageWriter};
+use papyrus_storage::{
+    StorageError,
+    StorageReader,
+    StorageScope,
+    StorageTxn,
+    StorageWriter,
+    StorageWriterComponent,
+};
+use serde::{Deserialize, Serialize};
+use starknet_api::block::BlockNumber;
+use tracing::{info, warn};
+use validator::Validate;
+
+/// The name of the file next to the storage that records the progress and the findings of the
+/// storage scan.
+pub const STORAGE_SCAN_REPORT_FILE_NAME: &str = "storage_scan_report.json";
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq, Validate)]
+pub struct StorageScanConfig {
+    pub run_at_startup: bool,
+    #[validate(range(min = 1))]
+    pub chunk_size: u64,
+    pub repair: bool,
+}
+
+impl Default for StorageScanConfig {
+    fn default() -> Self {
+        Self { run_at_startup: false, chunk_size: 1000, repair: false }
+    }
+}
+
+impl SerializeConfig for StorageScanConfig {
+    fn dump(&self) -> BTreeMap<ParamPath, SerializedParam> {
+        BTreeMap::from_iter([
+            ser_param(
+                "run_at_startup",
+                &self.run_at_startup,
+                "If true, the node scans the storage for blocks with missing data before it starts \
+                 syncing.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "chunk_size",
+                &self.chunk_size,
+                "The number of blocks the scan reads in each storage transaction.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "repair",
+                &self.repair,
+                "If true, the blocks from the first block with missing data up to the latest block \
+                 are reverted after the scan, so that the sync downloads them again.",
+                ParamPrivacyInput::Public,
+            ),
+        ])
+    }
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum StorageScanError {
+    #[error(transparent)]
+    IoError(#[from] std::io::Error),
+    #[error(transparent)]
+    ReportError(#[from] serde_json::Error),
+    #[error(transparent)]
+    StorageError(#[from] StorageError),
+}
+
+/// A problem the scan found in the data of a block.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum BlockDataProblem {
+    MissingHeader,
+    TransactionCountMismatch,
+    EventCountMismatch,
+    MissingStateDiff,
+    StateDiffLengthMismatch,
+    MissingClass,
+}
+
+/// A range of consecutive blocks with the same problems.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StorageGap {
+    pub first_block: BlockNumber,
+    // Exclusive.
+    pub end_block: BlockNumber,
+    pub problems: Vec<BlockDataProblem>,
+}
+
+#[derive(Clone, Debug, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct StorageScanReport {
+    // The header marker when the scan started. The scan checks the blocks up to it.
+    pub end_block: BlockNumber,
+    // The first block that wasn't scanned yet.
+    pub scanned_until: BlockNumber,
+    // Sorted by the block number.
+    pub gaps: Vec<StorageGap>,
+}
+
+impl StorageScanReport {
+    pub fn is_finished(&self) -> bool {
+        self.scanned_until >= self.end_block
+    }
+
+    fn add_block(&mut self, block_number: BlockNumber, problems: Vec<BlockDataProblem>) {
+        self.scanned_until = block_number.unchecked_next();
+        if problems.is_empty() {
+            return;
+        }
+        match self.gaps.last_mut() {
+            Some(gap) if gap.end_block == block_number && gap.problems == problems => {
+                gap.end_block = block_number.unchecked_next();
+            }
+            _ => self.gaps.push(StorageGap {
+                first_block: block_number,
+                end_block: block_number.unchecked_next(),
+                problems,
+            }),
+        }
+    }
+
+    fn num_blocks_with_problems(&self) -> u64 {
+        self.gaps.iter().map(|gap| gap.end_block.0 - gap.first_block.0).sum()
+    }
+}
+
+/// Scans the blocks up to the header marker and returns the report of the scan. If the report file
+/// contains a scan that didn't finish, the scan resumes from where it stopped.
+pub fn scan_storage(
+    storage_reader: &StorageReader,
+    chunk_size: u64,
+    report_path: &Path,
+) -> Result<StorageScanReport, StorageScanError> {
+    assert!(chunk_size > 0, "chunk_size should be positive.");
+    let header_marker = storage_reader.begin_ro_txn()?.get_header_marker()?;
+    let mut report = match read_report(report_path)? {
+        Some(mut report) if !report.is_finished() => {
+            // The blocks may have been reverted since the scan was interrupted.
+            report.end_block = report.end_block.min(header_marker);
+            report.scanned_until = report.scanned_until.min(header_marker);
+            report.gaps.retain(|gap| gap.first_block < header_marker);
+            if let Some(gap) = report.gaps.last_mut() {
+                gap.end_block = gap.end_block.min(header_marker);
+            }
+            info!("Resuming the storage scan from block {}.", report.scanned_until);
+            report
+        }
+        _ => StorageScanReport { end_block: header_marker, ..Default::default() },
+    };
+
+    while !report.is_finished() {
+        let chunk_start = report.scanned_until;
+        let chunk_end = BlockNumber((chunk_start.0 + chunk_size).min(report.end_block.0));
+        let txn = storage_reader.begin_ro_txn()?;
+        let has_bodies = storage_reader.get_scope() != StorageScope::StateOnly;
+        for block_number in (chunk_start.0..chunk_end.0).map(BlockNumber) {
+            report.add_block(block_number, get_block_problems(&txn, block_number, has_bodies)?);
+        }
+        drop(txn);
+        write_report(&report, report_path)?;
+        gauge!(papyrus_metrics::PAPYRUS_STORAGE_SCAN_MARKER, chunk_end.0 as f64);
+        gauge!(
+            papyrus_metrics::PAPYRUS_STORAGE_SCAN_BLOCKS_WITH_MISSING_DATA,
+            report.num_blocks_with_problems() as f64
+        );
+        info!("Scanned blocks {chunk_start} to {chunk_end} out of {}.", report.end_block);
+    }
+
+    match report.gaps.first() {
+        None => info!("The storage scan found no missing data up to block {}.", report.end_block),
+        Some(first_gap) => warn!(
+            "The storage scan found {} blocks with missing data, from block {}. The gaps are \
+             listed in {}.",
+            report.num_blocks_with_problems(),
+            first_gap.first_block,
+            report_path.display()
+        ),
+    }
+    Ok(report)
+}
+
+/// Reverts the blocks from the first block with missing data in the report up to the header
+/// marker, so that the sync downloads them again. Every chunk of blocks is reverted in its own
+/// write transaction, from the latest block down.
+pub fn repair_storage(
+    storage_writer: &mut StorageWriter,
+    report: &StorageScanReport,
+    chunk_size: u64,
+) -> Result<(), StorageScanError> {
+    assert!(chunk_size > 0, "chunk_size should be positive.");
+    let Some(first_reverted_block) = report.gaps.first().map(|gap| gap.first_block) else {
+        return Ok(());
+    };
+    storage_writer.set_component(StorageWriterComponent::StorageScan);
+    let mut header_marker = storage_writer.begin_rw_txn()?.get_header_marker()?;
+    while header_marker > first_reverted_block {
+        let chunk_start =
+            BlockNumber(header_marker.0.saturating_sub(chunk_size).max(first_reverted_block.0));
+        let mut txn = storage_writer.begin_rw_txn()?;
+        for reverted_block in (chunk_start.0..header_marker.0).rev().map(BlockNumber) {
+            txn = txn.try_revert_base_layer_marker(reverted_block)?;
+            txn = txn.revert_header(reverted_block)?.0;
+            txn = txn.revert_body(reverted_block)?.0;
+            txn = txn.revert_state_diff(reverted_block)?.0;
+        }
+        txn.commit()?;
+        header_marker = chunk_start;
+        gauge!(papyrus_metrics::PAPYRUS_HEADER_MARKER, header_marker.0 as f64);
+        info!("Reverted the blocks from block {chunk_start} up to the latest block.");
+    }
+    gauge!(papyrus_metrics::PAPYRUS_STORAGE_SCAN_BLOCKS_WITH_MISSING_DATA, 0.0);
+    Ok(())
+}
+
+/// Scans the storage and, if the config asks for it and a storage writer is given, reverts the
+/// blocks with missing data.
+pub fn run_storage_scan(
+    config: &StorageScanConfig,
+    report_path: &Path,
+    storage_reader: &StorageReader,
+    storage_writer: Option<&mut StorageWriter>,
+) -> Result<StorageScanReport, StorageScanError> {
+    let report = scan_storage(storage_reader, config.chunk_size, report_path)?;
+    if !config.repair || report.gaps.is_empty() {
+        return Ok(report);
+    }
+    match storage_writer {
+        Some(storage_writer) => repair_storage(storage_writer, &report, config.chunk_size)?,
+        None => warn!(
+            "The storage scan can't revert the blocks with missing data, since the storage is \
+             read-only or the admin server writes to it."
+        ),
+    }
+    Ok(report)
+}
+
+fn get_block_problems(
+    txn: &StorageTxn<'_, RO>,
+    block_number: BlockNumber,
+    has_bodies: bool,
+) -> Result<Vec<BlockDataProblem>, StorageError> {
+    let Some(header) = txn.get_block_header(block_number)? else {
+        return Ok(vec![BlockDataProblem::MissingHeader]);
+    };
+    let mut problems = vec![];
+
+    if has_bodies && block_number < txn.get_body_marker()? {
+        let n_transactions =
+            txn.get_block_transaction_hashes(block_number)?.unwrap_or_default().len();
+        if header.n_transactions.is_some_and(|expected| expected != n_transactions) {
+            problems.push(BlockDataProblem::TransactionCountMismatch);
+        }
+        let n_events: usize = txn
+            .get_block_transaction_outputs(block_number)?
+            .unwrap_or_default()
+            .iter()
+            .map(|output| output.events().len())
+            .sum();
+        if header.n_events.is_some_and(|expected| expected != n_events) {
+            problems.push(BlockDataProblem::EventCountMismatch);
+        }
+    }
+
+    if block_number < txn.get_state_marker()? {
+        let Some(state_diff) = txn.get_state_diff(block_number)? else {
+            problems.push(BlockDataProblem::MissingStateDiff);
+            return Ok(problems);
+        };
+        if header.state_diff_length.is_some_and(|expected| expected != state_diff.len()) {
+            problems.push(BlockDataProblem::StateDiffLengthMismatch);
+        }
+        if block_number < txn.get_class_marker()? {
+            for class_hash in state_diff.declared_classes.keys() {
+                if txn.get_class(class_hash)?.is_none() {
+                    problems.push(BlockDataProblem::MissingClass);
+                    return Ok(problems);
+                }
+            }
+            for class_hash in &state_diff.deprecated_declared_classes {
+                if txn.get_deprecated_class(class_hash)?.is_none() {
+                    problems.push(BlockDataProblem::MissingClass);
+                    return Ok(problems);
+                }
+            }
+        }
+    }
+    Ok(problems)
+}
+
+fn read_report(path: &Path) -> Result<Option<StorageScanReport>, StorageScanError> {
+    if !path.exists() {
+        return Ok(None);
+    }
+    Ok(Some(serde_json::from_reader(File::open(path)?)?))
+}
+
+// Writes the report into a temporary file and then renames it, so that an interrupted scan never
+// leaves a corrupted report behind.
+fn write_report(report: &StorageScanReport, path: &Path) -> Result<(), StorageScanError> {
+    let temp_path = path.with_extension("json.tmp");
+    let mut writer = BufWriter::new(File::create(&temp_path)?);
+    serde_json::to_writer_pretty(&mut writer, report)?;
+    writer.flush()?;
+    drop(writer);
+    std::fs::rename(temp_path, path)?;
+    Ok(())
+}
@@ -0,0 +1,165 @@
+use std::path::Path;
+
+use papyrus_storage::body::{BodyStorageReader, BodyStorageWriter};
+use papyrus_storage::class::{ClassStorageReader, ClassStorageWriter};
+use papyrus_storage::header::{HeaderStorageReader, HeaderStorageWriter};
+use papyrus_storage::state::{StateStorageReader, StateStorageWriter};
+use papyrus_storage::test_utils::get_test_storage;
+use papyrus_storage::{StorageReader, StorageWriter};
+use starknet_api::block::{BlockHash, BlockNumber};
+use starknet_api::core::{ClassHash, ContractAddress, Nonce};
+use starknet_api::hash::StarkHash;
+use starknet_api::state::ThinStateDiff;
+use test_utils::get_test_block;
+
+use crate::storage_scan::{
+    run_storage_scan,
+    scan_storage,
+    BlockDataProblem,
+    StorageGap,
+    StorageScanConfig,
+    StorageScanReport,
+    STORAGE_SCAN_REPORT_FILE_NAME,
+};
+
+const N_BLOCKS: u64 = 5;
+const N_TRANSACTIONS_PER_BLOCK: usize = 2;
+const N_EVENTS_PER_TRANSACTION: usize = 2;
+const CHUNK_SIZE: u64 = 2;
+
+// Block 0 is intact. Block 1 has fewer transactions than its header, blocks 2 and 3 have state
+// diffs that are shorter than their headers and block 4 declares a class that isn't in the storage.
+fn create_storage_with_gaps() -> ((StorageReader, StorageWriter), tempfile::TempDir) {
+    let ((storage_reader, mut storage_writer), temp_dir) = get_test_storage();
+    for block_number in (0..N_BLOCKS).map(BlockNumber) {
+        let mut block =
+            get_test_block(N_TRANSACTIONS_PER_BLOCK, Some(N_EVENTS_PER_TRANSACTION), None, None);
+        let mut state_diff = ThinStateDiff {
+            nonces: [(ContractAddress::from(1_u128), Nonce(StarkHash::from(block_number.0 + 1)))]
+                .into_iter()
+                .collect(),
+            ..Default::default()
+        };
+        if block_number == BlockNumber(4) {
+            state_diff.deprecated_declared_classes = vec![ClassHash(StarkHash::from(1_u8))];
+        }
+        block.header.block_number = block_number;
+        block.header.block_hash = BlockHash(StarkHash::from(block_number.0 + 1));
+        block.header.n_transactions = Some(N_TRANSACTIONS_PER_BLOCK);
+        block.header.n_events = Some(N_TRANSACTIONS_PER_BLOCK * N_EVENTS_PER_TRANSACTION);
+        block.header.state_diff_length = Some(state_diff.len());
+        match block_number.0 {
+            1 => block.header.n_transactions = Some(N_TRANSACTIONS_PER_BLOCK + 1),
+            2 | 3 => block.header.state_diff_length = Some(state_diff.len() + 1),
+            _ => {}
+        }
+        storage_writer
+            .begin_rw_txn()
+            .unwrap()
+            .append_header(block_number, &block.header)
+            .unwrap()
+            .append_body(block_number, block.body)
+            .unwrap()
+            .append_state_diff(block_number, state_diff)
+            .unwrap()
+            .append_classes(block_number, &[], &[])
+            .unwrap()
+            .commit()
+            .unwrap();
+    }
+    ((storage_reader, storage_writer), temp_dir)
+}
+
+fn gap(first_block: u64, end_block: u64, problems: Vec<BlockDataProblem>) -> StorageGap {
+    StorageGap {
+        first_block: BlockNumber(first_block),
+        end_block: BlockNumber(end_block),
+        problems,
+    }
+}
+
+fn read_report(path: &Path) -> StorageScanReport {
+    serde_json::from_reader(std::fs::File::open(path).unwrap()).unwrap()
+}
+
+#[test]
+fn scan_reports_the_blocks_whose_data_is_missing_or_doesnt_match_the_header() {
+    let ((storage_reader, _storage_writer), _temp_dir) = create_storage_with_gaps();
+    let report_dir = tempfile::tempdir().unwrap();
+    let report_path = report_dir.path().join(STORAGE_SCAN_REPORT_FILE_NAME);
+
+    let report = scan_storage(&storage_reader, CHUNK_SIZE, &report_path).unwrap();
+
+    let expected_report = StorageScanReport {
+        end_block: BlockNumber(N_BLOCKS),
+        scanned_until: BlockNumber(N_BLOCKS),
+        gaps: vec![
+            gap(1, 2, vec![BlockDataProblem::TransactionCountMismatch]),
+            gap(2, 4, vec![BlockDataProblem::StateDiffLengthMismatch]),
+            gap(4, 5, vec![BlockDataProblem::MissingClass]),
+        ],
+    };
+    assert_eq!(report, expected_report);
+    assert_eq!(read_report(&report_path), expected_report);
+}
+
+#[test]
+fn interrupted_scan_resumes_from_its_report() {
+    let ((storage_reader, _storage_writer), _temp_dir) = create_storage_with_gaps();
+    let report_dir = tempfile::tempdir().unwrap();
+    let report_path = report_dir.path().join(STORAGE_SCAN_REPORT_FILE_NAME);
+    // A scan that stopped after the first chunk. Its gap isn't real, so it shows that the blocks
+    // of that chunk aren't scanned again.
+    let interrupted_report = StorageScanReport {
+        end_block: BlockNumber(N_BLOCKS),
+        scanned_until: BlockNumber(CHUNK_SIZE),
+        gaps: vec![gap(0, 1, vec![BlockDataProblem::MissingHeader])],
+    };
+    std::fs::write(&report_path, serde_json::to_vec(&interrupted_report).unwrap()).unwrap();
+
+    let report = scan_storage(&storage_reader, CHUNK_SIZE, &report_path).unwrap();
+
+    assert_eq!(
+        report.gaps,
+        vec![
+            gap(0, 1, vec![BlockDataProblem::MissingHeader]),
+            gap(2, 4, vec![BlockDataProblem::StateDiffLengthMismatch]),
+            gap(4, 5, vec![BlockDataProblem::MissingClass]),
+        ]
+    );
+
+    // A finished scan isn't resumed, so the next scan checks all the blocks again.
+    let report = scan_storage(&storage_reader, CHUNK_SIZE, &report_path).unwrap();
+    assert_eq!(
+        report.gaps.first(),
+        Some(&gap(1, 2, vec![BlockDataProblem::TransactionCountMismatch]))
+    );
+}
+
+#[test]
+fn repair_reverts_the_blocks_from_the_first_gap() {
+    let ((storage_reader, mut storage_writer), _temp_dir) = create_storage_with_gaps();
+    let report_dir = tempfile::tempdir().unwrap();
+    let report_path = report_dir.path().join(STORAGE_SCAN_REPORT_FILE_NAME);
+    let config = StorageScanConfig { run_at_startup: true, chunk_size: CHUNK_SIZE, repair: true };
+
+    run_storage_scan(&config, &report_path, &storage_reader, Some(&mut storage_writer)).unwrap();
+
+    let txn = storage_reader.begin_ro_txn().unwrap();
+    assert_eq!(txn.get_header_marker().unwrap(), BlockNumber(1));
+    assert_eq!(txn.get_body_marker().unwrap(), BlockNumber(1));
+    assert_eq!(txn.get_state_marker().unwrap(), BlockNumber(1));
+    assert_eq!(txn.get_class_marker().unwrap(), BlockNumber(1));
+    drop(txn);
+    let report =
+        run_storage_scan(&config, &report_path, &storage_reader, Some(&mut storage_writer))
+            .unwrap();
+    assert_eq!(
+        report,
+        StorageScanReport {
+            end_block: BlockNumber(1),
+            scanned_until: BlockNumber(1),
+            gaps: vec![],
+        }
+    );
+}
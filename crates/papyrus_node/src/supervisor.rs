@@ -0,0 +1,354 @@
+//! Runs the components of the node and starts them again when they end, according to their restart
+//! policy, so that a component that fails doesn't take down the components that are healthy.
+//!
+//! A component is restarted after a delay that doubles with every restart, up to a maximum. Once a
+//! component failed more than `max_restarts` times, it isn't restarted anymore and stays failed.
+//! The node keeps running without it, unless the component is essential, in which case the node
+//! shuts down.
+//!
+//! Some components own a resource they lose when they end, such as the storage writer of the sync.
+//! They run only once, whatever their restart policy is.
+//!
+//! The state of each component is shared with the monitoring gateway, which reports it in its
+//! health endpoints.
+
+#[cfg(test)]
+#[path = "supervisor_test.rs"]
+mod supervisor_test;
+
+use std::any::Any;
+use std::collections::BTreeMap;
+use std::future::Future;
+use std::panic::AssertUnwindSafe;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use futures::future::BoxFuture;
+use futures::FutureExt;
+use papyrus_config::converters::deserialize_seconds_to_duration;
+use papyrus_config::dumping::{append_sub_config_name, ser_param, SerializeConfig};
+use papyrus_config::{ParamPath, ParamPrivacyInput, SerializedParam};
+use papyrus_monitoring_gateway::{ComponentState, ComponentStates, ComponentStatus};
+use serde::{Deserialize, Serialize};
+use tokio::task::JoinSet;
+use tracing::{error, info, warn};
+
+/// What the supervisor does when a component ends.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+#[serde(rename_all = "snake_case")]
+pub enum RestartPolicy {
+    /// The component isn't restarted.
+    Never,
+    /// The component is restarted if it failed.
+    #[default]
+    OnFailure,
+    /// The component is restarted whenever it ends.
+    Always,
+}
+
+/// The components the supervisor runs.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Component {
+    ConfigReloader,
+    StorageMetrics,
+    Network,
+    MonitoringGateway,
+    Rpc,
+    P2PSyncServer,
+    Sync,
+    P2PSync,
+    Consensus,
+}
+
+impl Component {
+    pub fn name(&self) -> &'static str {
+        match self {
+            Component::ConfigReloader => "config_reloader",
+            Component::StorageMetrics => "storage_metrics",
+            Component::Network => "network",
+            Component::MonitoringGateway => "monitoring_gateway",
+            Component::Rpc => "rpc",
+            Component::P2PSyncServer => "p2p_sync_server",
+            Component::Sync => "sync",
+            Component::P2PSync => "p2p_sync",
+            Component::Consensus => "consensus",
+        }
+    }
+
+    /// Whether the node shuts down once the component ends and isn't restarted. The node is of no
+    /// use without the sync that writes to its storage, while the other components only serve or
+    /// report on it.
+    pub fn is_essential(&self) -> bool {
+        matches!(self, Component::Sync | Component::P2PSync)
+    }
+}
+
+/// The restart policy of each component.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub struct RestartPoliciesConfig {
+    pub config_reloader: RestartPolicy,
+    pub storage_metrics: RestartPolicy,
+    pub network: RestartPolicy,
+    pub monitoring_gateway: RestartPolicy,
+    pub rpc: RestartPolicy,
+    pub p2p_sync_server: RestartPolicy,
+    pub sync: RestartPolicy,
+    pub p2p_sync: RestartPolicy,
+    pub consensus: RestartPolicy,
+}
+
+impl RestartPoliciesConfig {
+    pub fn get(&self, component: Component) -> RestartPolicy {
+        match component {
+            Component::ConfigReloader => self.config_reloader,
+            Component::StorageMetrics => self.storage_metrics,
+            Component::Network => self.network,
+            Component::MonitoringGateway => self.monitoring_gateway,
+            Component::Rpc => self.rpc,
+            Component::P2PSyncServer => self.p2p_sync_server,
+            Component::Sync => self.sync,
+            Component::P2PSync => self.p2p_sync,
+            Component::Consensus => self.consensus,
+        }
+    }
+}
+
+impl SerializeConfig for RestartPoliciesConfig {
+    fn dump(&self) -> BTreeMap<ParamPath, SerializedParam> {
+        let components = [
+            Component::ConfigReloader,
+            Component::StorageMetrics,
+            Component::Network,
+            Component::MonitoringGateway,
+            Component::Rpc,
+            Component::P2PSyncServer,
+            Component::Sync,
+            Component::P2PSync,
+            Component::Consensus,
+        ];
+        components
+            .into_iter()
+            .map(|component| {
+                ser_param(
+                    component.name(),
+                    &self.get(component),
+                    &format!(
+                        "The restart policy of the {} component, one of never, on_failure or \
+                         always.",
+                        component.name()
+                    ),
+                    ParamPrivacyInput::Public,
+                )
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug, Deserialize, Serialize, PartialEq)]
+pub struct SupervisorConfig {
+    pub max_restarts: usize,
+    #[serde(deserialize_with = "deserialize_seconds_to_duration")]
+    pub initial_restart_delay: Duration,
+    #[serde(deserialize_with = "deserialize_seconds_to_duration")]
+    pub max_restart_delay: Duration,
+    pub restart_policy: RestartPoliciesConfig,
+}
+
+impl Default for SupervisorConfig {
+    fn default() -> Self {
+        Self {
+            max_restarts: 5,
+            initial_restart_delay: Duration::from_secs(1),
+            max_restart_delay: Duration::from_secs(60),
+            restart_policy: RestartPoliciesConfig::default(),
+        }
+    }
+}
+
+impl SerializeConfig for SupervisorConfig {
+    fn dump(&self) -> BTreeMap<ParamPath, SerializedParam> {
+        let mut config = BTreeMap::from_iter([
+            ser_param(
+                "max_restarts",
+                &self.max_restarts,
+                "The number of times a component that failed is restarted before it's considered \
+                 failed for good. An essential component that failed for good shuts down the \
+                 node.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "initial_restart_delay",
+                &self.initial_restart_delay.as_secs(),
+                "The time in seconds before the first restart of a component. The delay doubles \
+                 with every restart.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "max_restart_delay",
+                &self.max_restart_delay.as_secs(),
+                "The maximal time in seconds before a restart of a component.",
+                ParamPrivacyInput::Public,
+            ),
+        ]);
+        config.extend(append_sub_config_name(self.restart_policy.dump(), "restart_policy"));
+        config
+    }
+}
+
+type ComponentFuture = BoxFuture<'static, anyhow::Result<()>>;
+
+// Returns the future of the next run of a component, or None if the component can't run again.
+type ComponentStarter = Box<dyn FnMut() -> Option<ComponentFuture> + Send>;
+
+// A component that ended and won't be restarted, with the result of its last run.
+struct ComponentExit {
+    component: Component,
+    result: anyhow::Result<()>,
+}
+
+pub struct Supervisor {
+    config: SupervisorConfig,
+    component_states: ComponentStates,
+    tasks: JoinSet<ComponentExit>,
+}
+
+impl Supervisor {
+    pub fn new(config: SupervisorConfig) -> Self {
+        Self { config, component_states: ComponentStates::default(), tasks: JoinSet::new() }
+    }
+
+    /// The states of the components, updated as they run, fail and restart.
+    pub fn component_states(&self) -> ComponentStates {
+        self.component_states.clone()
+    }
+
+    /// Starts a component that runs again by calling `start` whenever it's restarted.
+    pub fn spawn_restartable<Start, Fut>(&mut self, component: Component, mut start: Start)
+    where
+        Start: FnMut() -> Fut + Send + 'static,
+        Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+    {
+        self.spawn(component, Box::new(move || Some(start().boxed())));
+    }
+
+    /// Starts a component that can't run again once it ends.
+    pub fn spawn_once<Fut>(&mut self, component: Component, future: Fut)
+    where
+        Fut: Future<Output = anyhow::Result<()>> + Send + 'static,
+    {
+        let mut future = Some(future.boxed());
+        self.spawn(component, Box::new(move || future.take()));
+    }
+
+    fn spawn(&mut self, component: Component, start: ComponentStarter) {
+        set_state(&self.component_states, component, ComponentStatus::Running, 0);
+        self.tasks.spawn(supervise(
+            component,
+            self.config.clone(),
+            self.component_states.clone(),
+            start,
+        ));
+    }
+
+    /// Runs until an essential component ends for good, and returns the result of its last run.
+    /// Returns Ok if all the components ended and none of them is essential. The components that
+    /// still run are stopped when the supervisor is dropped.
+    pub async fn run(mut self) -> anyhow::Result<()> {
+        while let Some(exit) = self.tasks.join_next().await {
+            let ComponentExit { component, result } = exit?;
+            if component.is_essential() {
+                error!(
+                    "The essential component {} ended and won't be restarted. Shutting down the \
+                     node.",
+                    component.name()
+                );
+                return result;
+            }
+        }
+        error!("All the components ended.");
+        Ok(())
+    }
+}
+
+// Runs the component until it ends and its restart policy doesn't restart it.
+async fn supervise(
+    component: Component,
+    config: SupervisorConfig,
+    component_states: ComponentStates,
+    mut start: ComponentStarter,
+) -> ComponentExit {
+    let name = component.name();
+    let restart_policy = config.restart_policy.get(component);
+    let mut future = start().expect("A component should be able to run at least once");
+    let mut failures = 0;
+    let mut restarts = 0;
+    loop {
+        set_state(&component_states, component, ComponentStatus::Running, failures);
+        // A component that panics fails like a component that returns an error.
+        let result = AssertUnwindSafe(future)
+            .catch_unwind()
+            .await
+            .unwrap_or_else(|panic| Err(anyhow!("Panicked: {}", panic_message(&*panic))));
+        let failed = result.is_err();
+        match &result {
+            Err(error) => {
+                failures += 1;
+                error!("The {name} component failed ({failures} failures so far): {error}");
+            }
+            Ok(()) => warn!("The {name} component ended."),
+        }
+
+        let restart = match restart_policy {
+            RestartPolicy::Never => false,
+            RestartPolicy::OnFailure => failed,
+            RestartPolicy::Always => true,
+        } && failures <= config.max_restarts;
+        let next_future = if restart { start() } else { None };
+        let Some(next_future) = next_future else {
+            if restart {
+                warn!("The {name} component can't be restarted.");
+            } else if failed && restart_policy != RestartPolicy::Never {
+                error!("The {name} component failed {failures} times and won't be restarted.");
+            }
+            let status = if failed { ComponentStatus::Failed } else { ComponentStatus::Stopped };
+            set_state(&component_states, component, status, failures);
+            return ComponentExit { component, result };
+        };
+
+        set_state(&component_states, component, ComponentStatus::Restarting, failures);
+        let delay = restart_delay(&config, restarts);
+        info!("Restarting the {name} component in {delay:?}.");
+        tokio::time::sleep(delay).await;
+        restarts += 1;
+        future = next_future;
+    }
+}
+
+fn restart_delay(config: &SupervisorConfig, restarts: u32) -> Duration {
+    config
+        .initial_restart_delay
+        .saturating_mul(2_u32.saturating_pow(restarts))
+        .min(config.max_restart_delay)
+}
+
+fn set_state(
+    component_states: &ComponentStates,
+    component: Component,
+    status: ComponentStatus,
+    failures: usize,
+) {
+    component_states.write().expect("Component states lock should not be poisoned").insert(
+        component.name().to_owned(),
+        ComponentState { status, failures, essential: component.is_essential() },
+    );
+}
+
+fn panic_message(panic: &(dyn Any + Send)) -> &str {
+    if let Some(message) = panic.downcast_ref::<&str>() {
+        message
+    } else if let Some(message) = panic.downcast_ref::<String>() {
+        message
+    } else {
+        "unknown panic"
+    }
+}
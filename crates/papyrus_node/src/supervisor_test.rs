@@ -0,0 +1,132 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
+use anyhow::anyhow;
+use futures::future::pending;
+use papyrus_monitoring_gateway::{ComponentState, ComponentStates, ComponentStatus};
+use tokio::sync::oneshot;
+
+use crate::supervisor::{Component, RestartPolicy, Supervisor, SupervisorConfig};
+
+fn config(restart_policy: RestartPolicy) -> SupervisorConfig {
+    let mut config = SupervisorConfig {
+        max_restarts: 2,
+        initial_restart_delay: Duration::from_millis(1),
+        max_restart_delay: Duration::from_millis(2),
+        ..Default::default()
+    };
+    config.restart_policy.rpc = restart_policy;
+    config.restart_policy.sync = restart_policy;
+    config
+}
+
+fn state(component_states: &ComponentStates, name: &str) -> ComponentState {
+    component_states.read().unwrap().get(name).unwrap().clone()
+}
+
+#[tokio::test]
+async fn component_that_fails_twice_is_restarted_until_it_runs() {
+    let mut supervisor = Supervisor::new(config(RestartPolicy::OnFailure));
+    let component_states = supervisor.component_states();
+    let runs = Arc::new(AtomicUsize::new(0));
+    let (running_sender, running_receiver) = oneshot::channel();
+    let mut running_sender = Some(running_sender);
+    let runs_clone = runs.clone();
+    supervisor.spawn_restartable(Component::Rpc, move || {
+        let run = runs_clone.fetch_add(1, Ordering::SeqCst);
+        let running_sender = if run < 2 { None } else { running_sender.take() };
+        async move {
+            let Some(running_sender) = running_sender else {
+                return Err(anyhow!("Failed run {run}."));
+            };
+            running_sender.send(()).unwrap();
+            pending::<anyhow::Result<()>>().await
+        }
+    });
+    let supervisor_handle = tokio::spawn(supervisor.run());
+
+    running_receiver.await.unwrap();
+    assert_eq!(runs.load(Ordering::SeqCst), 3);
+    assert_eq!(
+        state(&component_states, "rpc"),
+        ComponentState { status: ComponentStatus::Running, failures: 2, essential: false }
+    );
+    assert!(!supervisor_handle.is_finished());
+    supervisor_handle.abort();
+}
+
+#[tokio::test]
+async fn essential_component_that_exhausts_its_restarts_shuts_down_the_node() {
+    let mut supervisor = Supervisor::new(config(RestartPolicy::OnFailure));
+    let component_states = supervisor.component_states();
+    let runs = Arc::new(AtomicUsize::new(0));
+    let runs_clone = runs.clone();
+    supervisor.spawn_restartable(Component::Sync, move || {
+        runs_clone.fetch_add(1, Ordering::SeqCst);
+        async { Err::<(), _>(anyhow!("Sync failed.")) }
+    });
+    // A healthy component that is stopped when the node shuts down.
+    let (stopped_sender, stopped_receiver) = oneshot::channel::<()>();
+    supervisor.spawn_once(Component::Rpc, async move {
+        let _stopped_sender = stopped_sender;
+        pending::<anyhow::Result<()>>().await
+    });
+
+    let result = supervisor.run().await;
+
+    assert_eq!(result.unwrap_err().to_string(), "Sync failed.");
+    // The first run and max_restarts restarts.
+    assert_eq!(runs.load(Ordering::SeqCst), 3);
+    assert_eq!(
+        state(&component_states, "sync"),
+        ComponentState { status: ComponentStatus::Failed, failures: 3, essential: true }
+    );
+    assert!(stopped_receiver.await.is_err());
+}
+
+#[tokio::test]
+async fn component_that_is_never_restarted_fails_without_stopping_the_node() {
+    let mut supervisor = Supervisor::new(config(RestartPolicy::Never));
+    let component_states = supervisor.component_states();
+    supervisor.spawn_restartable(Component::Rpc, || async { Err::<(), _>(anyhow!("Rpc failed.")) });
+
+    // The only component ended and isn't essential.
+    supervisor.run().await.unwrap();
+
+    assert_eq!(
+        state(&component_states, "rpc"),
+        ComponentState { status: ComponentStatus::Failed, failures: 1, essential: false }
+    );
+}
+
+#[tokio::test]
+async fn component_that_runs_once_is_not_restarted() {
+    let mut supervisor = Supervisor::new(config(RestartPolicy::Always));
+    let component_states = supervisor.component_states();
+    supervisor.spawn_once(Component::Sync, async { Err::<(), _>(anyhow!("Sync failed.")) });
+
+    assert!(supervisor.run().await.is_err());
+
+    assert_eq!(
+        state(&component_states, "sync"),
+        ComponentState { status: ComponentStatus::Failed, failures: 1, essential: true }
+    );
+}
+
+#[tokio::test]
+async fn failed_storage_metrics_dont_stop_the_node() {
+    let mut supervisor = Supervisor::new(config(RestartPolicy::OnFailure));
+    let component_states = supervisor.component_states();
+    supervisor.spawn_restartable(Component::StorageMetrics, || async {
+        Err::<(), _>(anyhow!("Storage metrics failed."))
+    });
+
+    // The only component exhausted its restarts, but the node can run without its metrics.
+    supervisor.run().await.unwrap();
+
+    assert_eq!(
+        state(&component_states, "storage_metrics"),
+        ComponentState { status: ComponentStatus::Failed, failures: 3, essential: false }
+    );
+}
@@ -0,0 +1,126 @@
+//! Submits the transactions that the JSON-RPC write API receives to the p2p network, for nodes
+//! that don't forward them to the Starknet gateway.
+
+#[cfg(test)]
+#[path = "transaction_broadcast_test.rs"]
+mod transaction_broadcast_test;
+
+use async_trait::async_trait;
+use papyrus_common::transaction_hash::get_transaction_hash;
+use papyrus_common::TransactionOptions;
+use papyrus_network::network_manager::BroadcastPublisher;
+use papyrus_protobuf::mempool::MempoolTransaction;
+use starknet_api::core::{calculate_contract_address, ChainId, ContractAddress};
+use starknet_api::transaction::{Transaction, TransactionHash};
+use starknet_client::starknet_error::{StarknetError, StarknetErrorCode};
+use starknet_client::writer::objects::response::{
+    DeclareResponse,
+    DeployAccountResponse,
+    InvokeResponse,
+    SuccessfulStarknetErrorCode,
+};
+use starknet_client::writer::objects::transaction::{
+    DeclareTransaction,
+    DeployAccountTransaction,
+    InvokeTransaction,
+};
+use starknet_client::writer::{StarknetWriter, WriterClientError, WriterClientResult};
+use starknet_client::ClientError;
+use tracing::debug;
+
+// The error code of the transactions that weren't broadcast. The JSON-RPC server reports errors
+// with unknown codes as unexpected errors with their message.
+const BROADCAST_FAILED_ERROR_CODE: &str = "BROADCAST_FAILED";
+
+/// A [`StarknetWriter`] that broadcasts each transaction on the mempool transactions topic and
+/// returns its hash once the transaction was published to at least one peer.
+pub struct GossipsubTransactionWriter {
+    chain_id: ChainId,
+    publisher: BroadcastPublisher<MempoolTransaction>,
+}
+
+impl GossipsubTransactionWriter {
+    pub fn new(chain_id: ChainId, publisher: BroadcastPublisher<MempoolTransaction>) -> Self {
+        Self { chain_id, publisher }
+    }
+
+    async fn broadcast(&self, transaction: Transaction) -> WriterClientResult<TransactionHash> {
+        let transaction_hash = get_transaction_hash(
+            &transaction,
+            &self.chain_id,
+            &TransactionOptions { only_query: false },
+        )
+        .map_err(|error| broadcast_failed(format!("Invalid transaction: {error}.")))?;
+        self.publisher
+            .publish(MempoolTransaction { transaction, transaction_hash })
+            .await
+            .map_err(|error| broadcast_failed(error.to_string()))?;
+        debug!(?transaction_hash, "Broadcast a transaction.");
+        Ok(transaction_hash)
+    }
+}
+
+#[async_trait]
+impl StarknetWriter for GossipsubTransactionWriter {
+    async fn add_invoke_transaction(
+        &self,
+        tx: &InvokeTransaction,
+    ) -> WriterClientResult<InvokeResponse> {
+        let transaction_hash = self.broadcast(Transaction::Invoke(tx.clone().into())).await?;
+        Ok(InvokeResponse {
+            code: SuccessfulStarknetErrorCode::TransactionReceived,
+            transaction_hash,
+        })
+    }
+
+    // The mempool transaction doesn't contain the declared class, so the peers couldn't execute the
+    // declare transaction.
+    async fn add_declare_transaction(
+        &self,
+        _tx: &DeclareTransaction,
+    ) -> WriterClientResult<DeclareResponse> {
+        Err(broadcast_failed(
+            "Declare transactions can't be submitted through gossipsub.".to_string(),
+        ))
+    }
+
+    async fn add_deploy_account_transaction(
+        &self,
+        tx: &DeployAccountTransaction,
+    ) -> WriterClientResult<DeployAccountResponse> {
+        let (contract_address_salt, class_hash, constructor_calldata) = match tx {
+            DeployAccountTransaction::DeployAccountV1(tx) => {
+                (tx.contract_address_salt, tx.class_hash, &tx.constructor_calldata)
+            }
+            DeployAccountTransaction::DeployAccountV3(tx) => {
+                (tx.contract_address_salt, tx.class_hash, &tx.constructor_calldata)
+            }
+        };
+        let address = calculate_contract_address(
+            contract_address_salt,
+            class_hash,
+            constructor_calldata,
+            ContractAddress::default(),
+        )
+        .map_err(|error| broadcast_failed(format!("Invalid transaction: {error}.")))?;
+        let transaction_hash =
+            self.broadcast(Transaction::DeployAccount(tx.clone().into())).await?;
+        Ok(DeployAccountResponse {
+            code: SuccessfulStarknetErrorCode::TransactionReceived,
+            transaction_hash,
+            address,
+        })
+    }
+
+    async fn is_alive(&self) -> bool {
+        true
+    }
+}
+
+fn broadcast_failed(message: String) -> WriterClientError {
+    ClientError::StarknetError(StarknetError {
+        code: StarknetErrorCode::UnknownErrorCode(BROADCAST_FAILED_ERROR_CODE.to_string()),
+        message,
+    })
+    .into()
+}
@@ -0,0 +1,66 @@
+use assert_matches::assert_matches;
+use futures::StreamExt;
+use papyrus_common::transaction_hash::get_transaction_hash;
+use papyrus_common::TransactionOptions;
+use papyrus_network::network_manager::{mock_register_broadcast_publisher, BroadcastError};
+use papyrus_protobuf::mempool::MempoolTransaction;
+use starknet_api::core::ChainId;
+use starknet_api::transaction::{Transaction, TransactionVersion};
+use starknet_client::writer::objects::transaction::{InvokeTransaction, InvokeV1Transaction};
+use starknet_client::writer::{StarknetWriter, WriterClientError};
+use starknet_client::ClientError;
+
+use super::GossipsubTransactionWriter;
+
+fn invoke_transaction() -> InvokeTransaction {
+    InvokeTransaction::InvokeV1(InvokeV1Transaction {
+        version: TransactionVersion::ONE,
+        ..Default::default()
+    })
+}
+
+#[tokio::test]
+async fn invoke_is_broadcast_with_its_hash() {
+    let (publisher, mut published_messages) = mock_register_broadcast_publisher();
+    let writer = GossipsubTransactionWriter::new(ChainId::Sepolia, publisher);
+
+    let network = async move {
+        let (message, result_sender) = published_messages.next().await.unwrap();
+        result_sender.send(Ok(())).unwrap();
+        MempoolTransaction::try_from(message).unwrap()
+    };
+    let (response, published_transaction) =
+        tokio::join!(writer.add_invoke_transaction(&invoke_transaction()), network);
+
+    let transaction = Transaction::Invoke(invoke_transaction().into());
+    let expected_hash = get_transaction_hash(
+        &transaction,
+        &ChainId::Sepolia,
+        &TransactionOptions { only_query: false },
+    )
+    .unwrap();
+    assert_eq!(response.unwrap().transaction_hash, expected_hash);
+    assert_eq!(
+        published_transaction,
+        MempoolTransaction { transaction, transaction_hash: expected_hash }
+    );
+}
+
+#[tokio::test]
+async fn broadcast_without_peers_fails() {
+    let (publisher, mut published_messages) = mock_register_broadcast_publisher();
+    let writer = GossipsubTransactionWriter::new(ChainId::Sepolia, publisher);
+
+    let network = async move {
+        let (_, result_sender) = published_messages.next().await.unwrap();
+        result_sender.send(Err(BroadcastError::NoPeers)).unwrap();
+    };
+    let (response, ()) =
+        tokio::join!(writer.add_invoke_transaction(&invoke_transaction()), network);
+
+    assert_matches!(
+        response,
+        Err(WriterClientError::ClientError(ClientError::StarknetError(starknet_error)))
+        if starknet_error.message == BroadcastError::NoPeers.to_string()
+    );
+}
@@ -0,0 +1,186 @@
+//! Anchors the chain synced from peers to the Starknet core contract on the base layer. Without it,
+//! peers that eclipse the node could feed it a chain that Starknet never proved.
+
+use std::cmp::min;
+use std::fmt::Display;
+use std::sync::Arc;
+use std::time::Duration;
+
+use async_stream::stream;
+use futures::future::BoxFuture;
+use futures::stream::BoxStream;
+use futures::{FutureExt, StreamExt, TryFutureExt};
+use metrics::gauge;
+use papyrus_base_layer::BaseLayerContract;
+use papyrus_common::metrics as papyrus_metrics;
+use papyrus_storage::base_layer::{BaseLayerStorageReader, BaseLayerStorageWriter};
+use papyrus_storage::body::BodyStorageWriter;
+use papyrus_storage::header::{HeaderStorageReader, HeaderStorageWriter};
+use papyrus_storage::state::StateStorageWriter;
+use papyrus_storage::StorageWriter;
+use starknet_api::block::{BlockHash, BlockNumber};
+use tracing::{debug, error, info, warn};
+
+use crate::P2PSyncError;
+
+const MAX_POLL_INTERVAL_FACTOR: u32 = 8;
+
+// Hides the type of the base layer contract, so that the sync isn't generic over it.
+trait ProvedBlockSource: Send + Sync {
+    fn latest_proved_block(
+        &self,
+        confirmation_depth: u64,
+    ) -> BoxFuture<'_, Result<Option<(BlockNumber, BlockHash)>, String>>;
+}
+
+impl<Contract> ProvedBlockSource for Contract
+where
+    Contract: BaseLayerContract + Send + Sync,
+    Contract::Error: Display,
+{
+    fn latest_proved_block(
+        &self,
+        confirmation_depth: u64,
+    ) -> BoxFuture<'_, Result<Option<(BlockNumber, BlockHash)>, String>> {
+        BaseLayerContract::latest_proved_block(self, Some(confirmation_depth))
+            .map_err(|err| err.to_string())
+            .boxed()
+    }
+}
+
+/// The base layer contract the p2p sync reads the latest proved block from, and how often it's
+/// read.
+pub struct BaseLayerCheckpointSource {
+    source: Arc<dyn ProvedBlockSource>,
+    poll_interval: Duration,
+    confirmation_depth: u64,
+}
+
+impl BaseLayerCheckpointSource {
+    pub fn new<Contract>(
+        contract: Contract,
+        poll_interval: Duration,
+        confirmation_depth: u64,
+    ) -> Self
+    where
+        Contract: BaseLayerContract + Send + Sync + 'static,
+        Contract::Error: Display,
+    {
+        Self { source: Arc::new(contract), poll_interval, confirmation_depth }
+    }
+}
+
+// Yields the latest block proved on the base layer once every poll interval, and backs off while
+// the base layer doesn't prove new blocks. A failure to read the base layer says nothing about the
+// synced chain, so it's logged and the read is retried.
+pub(crate) fn stream_proved_blocks(
+    checkpoint_source: BaseLayerCheckpointSource,
+) -> BoxStream<'static, (BlockNumber, BlockHash)> {
+    stream! {
+        let poll_interval = checkpoint_source.poll_interval;
+        let max_poll_interval = poll_interval * MAX_POLL_INTERVAL_FACTOR;
+        let mut current_poll_interval = poll_interval;
+        let mut last_proved_block = None;
+        loop {
+            match checkpoint_source
+                .source
+                .latest_proved_block(checkpoint_source.confirmation_depth)
+                .await
+            {
+                Ok(Some(proved_block)) => {
+                    current_poll_interval = if last_proved_block == Some(proved_block) {
+                        min(current_poll_interval * 2, max_poll_interval)
+                    } else {
+                        poll_interval
+                    };
+                    last_proved_block = Some(proved_block);
+                    yield proved_block;
+                }
+                Ok(None) => {
+                    debug!("No block is proved on the base layer yet.");
+                    current_poll_interval = min(current_poll_interval * 2, max_poll_interval);
+                }
+                Err(err) => {
+                    warn!("Failed to read the latest block proved on the base layer: {err}");
+                    current_poll_interval = poll_interval;
+                }
+            }
+            tokio::time::sleep(current_poll_interval).await;
+        }
+    }
+    .boxed()
+}
+
+/// Compares a block proved on the base layer with the stored block of the same height. The sync
+/// validates the parent hash of each block, so a match verifies all the blocks up to it, and the
+/// base layer marker is raised past it.
+///
+/// On a mismatch, an error is returned so that the sync halts. If `revert_on_mismatch` is set, the
+/// blocks that weren't verified yet are reverted first, so that they're synced again once the node
+/// is restarted.
+pub(crate) fn check_proved_block(
+    storage_writer: &mut StorageWriter,
+    block_number: BlockNumber,
+    base_layer_hash: BlockHash,
+    revert_on_mismatch: bool,
+) -> Result<(), P2PSyncError> {
+    gauge!(
+        papyrus_metrics::PAPYRUS_P2P_SYNC_BASE_LAYER_PROVED_MARKER,
+        block_number.unchecked_next().0 as f64
+    );
+    let mut txn = storage_writer.begin_rw_txn()?;
+    let header_marker = txn.get_header_marker()?;
+    gauge!(papyrus_metrics::PAPYRUS_HEADER_MARKER, header_marker.0 as f64);
+    let Some(header) = txn.get_block_header(block_number)? else {
+        debug!(
+            "The sync didn't reach block {block_number}, the latest block proved on the base \
+             layer, yet."
+        );
+        return Ok(());
+    };
+    let base_layer_marker = txn.get_base_layer_block_marker()?;
+    if header.block_hash == base_layer_hash {
+        if base_layer_marker != block_number.unchecked_next() {
+            info!("Verified block {block_number} hash against base layer.");
+            txn.update_base_layer_block_marker(&block_number.unchecked_next())?.commit()?;
+            gauge!(
+                papyrus_metrics::PAPYRUS_BASE_LAYER_MARKER,
+                block_number.unchecked_next().0 as f64
+            );
+        }
+        return Ok(());
+    }
+
+    error!(
+        "Block {block_number} in the storage has hash {}, but the base layer proved block \
+         {block_number} with hash {base_layer_hash}. The peers the node synced from may be \
+         serving a chain that Starknet didn't prove. Halting the p2p sync.",
+        header.block_hash
+    );
+    if !revert_on_mismatch {
+        return Err(P2PSyncError::BaseLayerHashMismatch {
+            block_number,
+            base_layer_hash,
+            l2_hash: header.block_hash,
+        });
+    }
+
+    // The blocks before the base layer marker were verified against an earlier proved block, so
+    // only the blocks from the marker on may be fake.
+    let first_reverted_block = min(base_layer_marker, block_number);
+    for reverted_block in (first_reverted_block.0..header_marker.0).rev().map(BlockNumber) {
+        txn = txn.try_revert_base_layer_marker(reverted_block)?;
+        txn = txn.revert_header(reverted_block)?.0;
+        txn = txn.revert_body(reverted_block)?.0;
+        txn = txn.revert_state_diff(reverted_block)?.0;
+    }
+    txn.commit()?;
+    gauge!(papyrus_metrics::PAPYRUS_HEADER_MARKER, first_reverted_block.0 as f64);
+    gauge!(papyrus_metrics::PAPYRUS_BASE_LAYER_MARKER, first_reverted_block.0 as f64);
+    error!(
+        "Reverted blocks {first_reverted_block} to {}, which weren't verified against the base \
+         layer.",
+        header_marker.prev().unwrap_or_default()
+    );
+    Err(P2PSyncError::RevertedToBaseLayerBlock { block_number: first_reverted_block })
+}
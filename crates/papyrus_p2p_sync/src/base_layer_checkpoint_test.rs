@@ -0,0 +1,140 @@
+use assert_matches::assert_matches;
+use papyrus_storage::base_layer::BaseLayerStorageReader;
+use papyrus_storage::header::{HeaderStorageReader, HeaderStorageWriter};
+use papyrus_storage::state::{StateStorageReader, StateStorageWriter};
+use papyrus_storage::test_utils::get_test_storage;
+use papyrus_storage::{StorageReader, StorageWriter};
+use starknet_api::block::{BlockHash, BlockHeader, BlockNumber};
+use starknet_api::state::ThinStateDiff;
+use starknet_types_core::felt::Felt;
+
+use crate::base_layer_checkpoint::check_proved_block;
+use crate::P2PSyncError;
+
+const NUM_BLOCKS: u64 = 5;
+const PROVED_BLOCK_NUMBER: BlockNumber = BlockNumber(3);
+
+fn block_hash(block_number: u64) -> BlockHash {
+    BlockHash(Felt::from(block_number + 1))
+}
+
+// Writes NUM_BLOCKS headers, and the state diffs of all the blocks but the last.
+fn write_blocks(storage_writer: &mut StorageWriter) {
+    let mut txn = storage_writer.begin_rw_txn().unwrap();
+    for block_number in 0..NUM_BLOCKS {
+        let block_header = BlockHeader {
+            block_number: BlockNumber(block_number),
+            block_hash: block_hash(block_number),
+            parent_hash: block_number.checked_sub(1).map(block_hash).unwrap_or_default(),
+            ..Default::default()
+        };
+        txn = txn.append_header(BlockNumber(block_number), &block_header).unwrap();
+        if block_number + 1 < NUM_BLOCKS {
+            txn =
+                txn.append_state_diff(BlockNumber(block_number), ThinStateDiff::default()).unwrap();
+        }
+    }
+    txn.commit().unwrap();
+}
+
+fn markers(storage_reader: &StorageReader) -> (BlockNumber, BlockNumber, BlockNumber) {
+    let txn = storage_reader.begin_ro_txn().unwrap();
+    (
+        txn.get_header_marker().unwrap(),
+        txn.get_state_marker().unwrap(),
+        txn.get_base_layer_block_marker().unwrap(),
+    )
+}
+
+#[test]
+fn matching_block_raises_the_base_layer_marker() {
+    let ((storage_reader, mut storage_writer), _temp_dir) = get_test_storage();
+    write_blocks(&mut storage_writer);
+
+    check_proved_block(
+        &mut storage_writer,
+        PROVED_BLOCK_NUMBER,
+        block_hash(PROVED_BLOCK_NUMBER.0),
+        false,
+    )
+    .unwrap();
+
+    assert_eq!(
+        markers(&storage_reader),
+        (
+            BlockNumber(NUM_BLOCKS),
+            BlockNumber(NUM_BLOCKS - 1),
+            PROVED_BLOCK_NUMBER.unchecked_next()
+        )
+    );
+}
+
+#[test]
+fn block_that_was_not_synced_yet_is_not_checked() {
+    let ((storage_reader, mut storage_writer), _temp_dir) = get_test_storage();
+    write_blocks(&mut storage_writer);
+
+    check_proved_block(&mut storage_writer, BlockNumber(NUM_BLOCKS), BlockHash::default(), false)
+        .unwrap();
+
+    assert_eq!(
+        markers(&storage_reader),
+        (BlockNumber(NUM_BLOCKS), BlockNumber(NUM_BLOCKS - 1), BlockNumber(0))
+    );
+}
+
+#[test]
+fn mismatching_block_halts_the_sync() {
+    let ((storage_reader, mut storage_writer), _temp_dir) = get_test_storage();
+    write_blocks(&mut storage_writer);
+
+    let result =
+        check_proved_block(&mut storage_writer, PROVED_BLOCK_NUMBER, BlockHash::default(), false);
+
+    assert_matches!(
+        result,
+        Err(P2PSyncError::BaseLayerHashMismatch { block_number, base_layer_hash, l2_hash })
+        if block_number == PROVED_BLOCK_NUMBER
+            && base_layer_hash == BlockHash::default()
+            && l2_hash == block_hash(PROVED_BLOCK_NUMBER.0)
+    );
+    assert_eq!(
+        markers(&storage_reader),
+        (BlockNumber(NUM_BLOCKS), BlockNumber(NUM_BLOCKS - 1), BlockNumber(0))
+    );
+}
+
+#[test]
+fn mismatching_block_reverts_the_unverified_blocks() {
+    let ((storage_reader, mut storage_writer), _temp_dir) = get_test_storage();
+    write_blocks(&mut storage_writer);
+    let verified_block_number = BlockNumber(1);
+    check_proved_block(
+        &mut storage_writer,
+        verified_block_number,
+        block_hash(verified_block_number.0),
+        true,
+    )
+    .unwrap();
+
+    let result =
+        check_proved_block(&mut storage_writer, PROVED_BLOCK_NUMBER, BlockHash::default(), true);
+
+    // The blocks up to the previously verified block are kept.
+    let first_reverted_block = verified_block_number.unchecked_next();
+    assert_matches!(
+        result,
+        Err(P2PSyncError::RevertedToBaseLayerBlock { block_number })
+        if block_number == first_reverted_block
+    );
+    assert_eq!(
+        markers(&storage_reader),
+        (first_reverted_block, first_reverted_block, first_reverted_block)
+    );
+    assert!(storage_reader
+        .begin_ro_txn()
+        .unwrap()
+        .get_block_header(verified_block_number)
+        .unwrap()
+        .is_some());
+}
@@ -0,0 +1,138 @@
+use papyrus_common::block_hash::validate_body_commitments;
+use papyrus_common::BlockHashAndNumber;
+use papyrus_protobuf::sync::{FullBlock, SignedBlockHeader};
+use papyrus_storage::body::BodyStorageWriter;
+use papyrus_storage::db::{TransactionKind, RW};
+use papyrus_storage::header::{HeaderStorageReader, HeaderStorageWriter};
+use papyrus_storage::state::StateStorageWriter;
+use papyrus_storage::{StorageError, StorageResult, StorageTxn, StorageWriter};
+use starknet_api::block::{BlockBody, BlockHeader, BlockNumber, BlockSignature};
+use starknet_api::state::ThinStateDiff;
+use starknet_api::transaction::{Transaction, TransactionHash, TransactionOutput};
+use tracing::info;
+
+use crate::header::validate_header;
+use crate::state_diff::unite_and_validate_state_diff_parts;
+use crate::stream_factory::BlockData;
+use crate::P2PSyncError;
+
+/// A block whose header, body and state diff were validated against each other and against the
+/// previous block, and which is written to the storage in a single transaction.
+pub(crate) struct ValidatedBlock {
+    header: BlockHeader,
+    signature: BlockSignature,
+    body: BlockBody,
+    state_diff: ThinStateDiff,
+}
+
+impl ValidatedBlock {
+    fn append_to_storage<'env>(
+        self,
+        txn: StorageTxn<'env, RW>,
+    ) -> StorageResult<StorageTxn<'env, RW>> {
+        let block_number = self.header.block_number;
+        txn.append_header(block_number, &self.header)?
+            .append_block_signature(block_number, &self.signature)?
+            .append_body(block_number, self.body)?
+            .append_state_diff(block_number, self.state_diff)
+    }
+}
+
+impl BlockData for ValidatedBlock {
+    fn write_to_storage(
+        self: Box<Self>,
+        storage_writer: &mut StorageWriter,
+    ) -> Result<(), StorageError> {
+        self.append_to_storage(storage_writer.begin_rw_txn()?)?.commit()
+    }
+
+    fn proven_block(&self) -> Option<BlockHashAndNumber> {
+        Some(BlockHashAndNumber {
+            block_hash: self.header.block_hash,
+            block_number: self.header.block_number,
+        })
+    }
+}
+
+impl BlockData for (BlockBody, BlockNumber) {
+    fn write_to_storage(
+        self: Box<Self>,
+        storage_writer: &mut StorageWriter,
+    ) -> Result<(), StorageError> {
+        storage_writer.begin_rw_txn()?.append_body(self.1, self.0)?.commit()
+    }
+}
+
+/// Validates the parts of a block as a unit: the header against the previous block in the
+/// storage, the number of transactions and their transaction, event and receipt commitments against
+/// the header and the state diff parts against the header's state diff length and commitment.
+pub(crate) fn validate_block<Mode: TransactionKind>(
+    txn: &StorageTxn<'_, Mode>,
+    signed_header: SignedBlockHeader,
+    transactions: Vec<(Transaction, TransactionOutput)>,
+    transaction_hashes: Vec<TransactionHash>,
+    state_diff_parts: impl IntoIterator<Item = ThinStateDiff>,
+) -> Result<ValidatedBlock, P2PSyncError> {
+    let block_number = signed_header.block_header.block_number;
+    validate_header(txn, &signed_header, block_number)?;
+    let SignedBlockHeader { block_header: header, signatures, .. } = signed_header;
+    let body = validate_body(&header, transactions, transaction_hashes)?;
+    let state_diff = unite_and_validate_state_diff_parts(state_diff_parts, &header)?;
+    let signature = signatures
+        .into_iter()
+        .next()
+        .expect("Vec::first should return a value on a vector of size 1");
+    Ok(ValidatedBlock { header, signature, body, state_diff })
+}
+
+/// Validates the transactions of a block against its header through the header's transaction,
+/// event and receipt commitments.
+pub(crate) fn validate_body(
+    header: &BlockHeader,
+    transactions: Vec<(Transaction, TransactionOutput)>,
+    transaction_hashes: Vec<TransactionHash>,
+) -> Result<BlockBody, P2PSyncError> {
+    let block_number = header.block_number;
+    if transactions.len() != transaction_hashes.len() {
+        return Err(P2PSyncError::WrongNumberOfTransactionHashes {
+            block_number,
+            num_transactions: transactions.len(),
+            num_transaction_hashes: transaction_hashes.len(),
+        });
+    }
+    let (transactions, transaction_outputs) = transactions.into_iter().unzip();
+    let body = BlockBody { transactions, transaction_outputs, transaction_hashes };
+    if !validate_body_commitments(header, &body)? {
+        return Err(P2PSyncError::BodyCommitmentMismatch { block_number });
+    }
+    Ok(body)
+}
+
+/// Validates the given block the same way blocks from the network are validated and writes it to
+/// the storage. The block must be the next block after the last block in the storage.
+pub fn inject_block(
+    storage_writer: &mut StorageWriter,
+    block: FullBlock,
+) -> Result<(), P2PSyncError> {
+    let FullBlock { signed_header, transactions, transaction_hashes, state_diff_chunks } = block;
+    let block_number = signed_header.block_header.block_number;
+
+    let txn = storage_writer.begin_rw_txn()?;
+    let expected_block_number = txn.get_header_marker()?;
+    if block_number != expected_block_number {
+        return Err(P2PSyncError::InjectedBlockOutOfOrder {
+            expected_block_number,
+            actual_block_number: block_number,
+        });
+    }
+    let block = validate_block(
+        &txn,
+        signed_header,
+        transactions,
+        transaction_hashes,
+        state_diff_chunks.into_iter().map(ThinStateDiff::from),
+    )?;
+    block.append_to_storage(txn)?.commit()?;
+    info!("Injected block {block_number}.");
+    Ok(())
+}
@@ -0,0 +1,128 @@
+use assert_matches::assert_matches;
+use papyrus_protobuf::sync::{DeclaredClass, FullBlock, SignedBlockHeader, StateDiffChunk};
+use papyrus_storage::header::HeaderStorageReader;
+use papyrus_storage::state::StateStorageReader;
+use papyrus_storage::test_utils::get_test_storage;
+use starknet_api::block::{BlockHash, BlockHeader, BlockNumber, BlockSignature, StarknetVersion};
+use starknet_api::core::{
+    ClassHash,
+    CompiledClassHash,
+    ReceiptCommitment,
+    StateDiffCommitment,
+    TransactionCommitment,
+};
+use starknet_api::hash::PoseidonHash;
+use starknet_types_core::felt::Felt;
+
+use crate::{inject_block, P2PSyncError};
+
+fn create_block(block_number: u64, parent_hash: BlockHash) -> FullBlock {
+    FullBlock {
+        signed_header: SignedBlockHeader {
+            block_header: BlockHeader {
+                block_number: BlockNumber(block_number),
+                block_hash: BlockHash(Felt::from(block_number + 1)),
+                parent_hash,
+                state_diff_length: Some(1),
+                ..Default::default()
+            },
+            signatures: vec![BlockSignature::default()],
+            data_availability: None,
+        },
+        transactions: vec![],
+        transaction_hashes: vec![],
+        state_diff_chunks: vec![StateDiffChunk::DeclaredClass(DeclaredClass {
+            class_hash: ClassHash(Felt::from(block_number)),
+            compiled_class_hash: CompiledClassHash(Felt::from(block_number)),
+        })],
+    }
+}
+
+#[test]
+fn inject_blocks() {
+    let ((storage_reader, mut storage_writer), _temp_dir) = get_test_storage();
+
+    let block_0 = create_block(0, BlockHash::default());
+    let block_1 = create_block(1, block_0.signed_header.block_header.block_hash);
+    inject_block(&mut storage_writer, block_0).unwrap();
+    inject_block(&mut storage_writer, block_1.clone()).unwrap();
+
+    let txn = storage_reader.begin_ro_txn().unwrap();
+    assert_eq!(txn.get_header_marker().unwrap(), BlockNumber(2));
+    assert_eq!(txn.get_state_marker().unwrap(), BlockNumber(2));
+    assert_eq!(
+        txn.get_block_header(BlockNumber(1)).unwrap().unwrap(),
+        block_1.signed_header.block_header
+    );
+    let state_diff = txn.get_state_diff(BlockNumber(1)).unwrap().unwrap();
+    assert_eq!(
+        state_diff.declared_classes.get(&ClassHash(Felt::ONE)),
+        Some(&CompiledClassHash(Felt::ONE))
+    );
+}
+
+#[test]
+fn inject_block_out_of_order() {
+    let ((_storage_reader, mut storage_writer), _temp_dir) = get_test_storage();
+
+    let result = inject_block(&mut storage_writer, create_block(1, BlockHash::default()));
+    assert_matches!(
+        result,
+        Err(P2PSyncError::InjectedBlockOutOfOrder {
+            expected_block_number: BlockNumber(0),
+            actual_block_number: BlockNumber(1),
+        })
+    );
+}
+
+#[test]
+fn inject_block_with_wrong_parent_hash() {
+    let ((_storage_reader, mut storage_writer), _temp_dir) = get_test_storage();
+
+    inject_block(&mut storage_writer, create_block(0, BlockHash::default())).unwrap();
+    let result = inject_block(&mut storage_writer, create_block(1, BlockHash(Felt::TWO)));
+    assert_matches!(
+        result,
+        Err(P2PSyncError::ParentHashMismatch { block_number: BlockNumber(1), .. })
+    );
+}
+
+#[test]
+fn inject_block_with_wrong_state_diff_commitment() {
+    let ((storage_reader, mut storage_writer), _temp_dir) = get_test_storage();
+
+    let mut block = create_block(0, BlockHash::default());
+    block.signed_header.block_header.state_diff_commitment =
+        Some(StateDiffCommitment(PoseidonHash(Felt::ONE)));
+    let result = inject_block(&mut storage_writer, block);
+    assert_matches!(
+        result,
+        Err(P2PSyncError::StateDiffCommitmentMismatch { block_number: BlockNumber(0) })
+    );
+    assert_eq!(storage_reader.begin_ro_txn().unwrap().get_header_marker().unwrap(), BlockNumber(0));
+}
+
+#[test]
+fn inject_block_with_wrong_body_commitments() {
+    let ((storage_reader, mut storage_writer), _temp_dir) = get_test_storage();
+
+    // Each commitment is checked even when the others are missing.
+    let mut block = create_block(0, BlockHash::default());
+    block.signed_header.block_header.transaction_commitment =
+        Some(TransactionCommitment(Felt::ONE));
+    let result = inject_block(&mut storage_writer, block);
+    assert_matches!(
+        result,
+        Err(P2PSyncError::BodyCommitmentMismatch { block_number: BlockNumber(0) })
+    );
+
+    let mut block = create_block(0, BlockHash::default());
+    block.signed_header.block_header.starknet_version = StarknetVersion("0.13.2".to_owned());
+    block.signed_header.block_header.receipt_commitment = Some(ReceiptCommitment(Felt::ONE));
+    let result = inject_block(&mut storage_writer, block);
+    assert_matches!(
+        result,
+        Err(P2PSyncError::BodyCommitmentMismatch { block_number: BlockNumber(0) })
+    );
+    assert_eq!(storage_reader.begin_ro_txn().unwrap().get_header_marker().unwrap(), BlockNumber(0));
+}
@@ -0,0 +1,99 @@
+//! Verification that the classes received from peers match the class hashes declared for them in
+//! the state diffs, so that a class that doesn't match its hash never reaches the storage.
+//!
+//! Only the hash of Cairo 1 classes is computed. The classes are received without their compiled
+//! classes, so their compiled class hash can only be checked once they're compiled, and there's
+//! no implementation of the hash of Cairo 0 classes to check them with.
+
+use std::sync::Arc;
+use std::time::Instant;
+
+use metrics::{histogram, increment_counter};
+use papyrus_common::class_hash::calculate_class_hash;
+use papyrus_common::metrics as papyrus_metrics;
+use papyrus_common::pending_classes::ApiContractClass;
+use starknet_api::block::BlockNumber;
+use starknet_api::core::ClassHash;
+use starknet_api::state::ThinStateDiff;
+use tokio::sync::Semaphore;
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum ClassVerificationError {
+    #[error("Received class {class_hash:?} which isn't declared in block {block_number}.")]
+    UndeclaredClass { block_number: BlockNumber, class_hash: ClassHash },
+    #[error("Received a class for {expected_class_hash:?} whose hash is {actual_class_hash:?}.")]
+    ClassHashMismatch { expected_class_hash: ClassHash, actual_class_hash: ClassHash },
+}
+
+/// Computes the hashes of the received classes on the blocking thread pool, at most
+/// `max_parallel_verifications` at a time, since hashing a Cairo 1 class is CPU heavy.
+#[derive(Clone)]
+pub struct ClassVerifier {
+    semaphore: Arc<Semaphore>,
+}
+
+impl ClassVerifier {
+    pub fn new(max_parallel_verifications: usize) -> Self {
+        assert!(max_parallel_verifications > 0, "max_parallel_verifications should be positive.");
+        Self { semaphore: Arc::new(Semaphore::new(max_parallel_verifications)) }
+    }
+
+    /// Returns the class if it was declared as `class_hash` in the state diff of `block_number`
+    /// and, for Cairo 1 classes, it hashes to `class_hash`. On an error the class should be
+    /// discarded, the peer that sent it reported and the class requested again.
+    pub async fn verify(
+        &self,
+        block_number: BlockNumber,
+        state_diff: &ThinStateDiff,
+        class_hash: ClassHash,
+        class: ApiContractClass,
+    ) -> Result<ApiContractClass, ClassVerificationError> {
+        let is_declared = match &class {
+            ApiContractClass::ContractClass(_) => {
+                state_diff.declared_classes.contains_key(&class_hash)
+            }
+            ApiContractClass::DeprecatedContractClass(_) => {
+                state_diff.deprecated_declared_classes.contains(&class_hash)
+            }
+        };
+        if !is_declared {
+            increment_counter!(
+                papyrus_metrics::PAPYRUS_P2P_SYNC_VERIFIED_CLASSES,
+                "result" => "undeclared"
+            );
+            return Err(ClassVerificationError::UndeclaredClass { block_number, class_hash });
+        }
+        let ApiContractClass::ContractClass(contract_class) = class else {
+            increment_counter!(
+                papyrus_metrics::PAPYRUS_P2P_SYNC_VERIFIED_CLASSES,
+                "result" => "unverified"
+            );
+            return Ok(class);
+        };
+
+        let _permit = self.semaphore.acquire().await.expect("The semaphore is never closed");
+        let start = Instant::now();
+        let (actual_class_hash, contract_class) = tokio::task::spawn_blocking(move || {
+            (calculate_class_hash(&contract_class), contract_class)
+        })
+        .await
+        .expect("Computing the class hash panicked");
+        histogram!(
+            papyrus_metrics::PAPYRUS_P2P_SYNC_CLASS_VERIFICATION_DURATION_SECS,
+            start.elapsed().as_secs_f64()
+        );
+
+        if actual_class_hash != class_hash {
+            increment_counter!(
+                papyrus_metrics::PAPYRUS_P2P_SYNC_VERIFIED_CLASSES,
+                "result" => "mismatch"
+            );
+            return Err(ClassVerificationError::ClassHashMismatch {
+                expected_class_hash: class_hash,
+                actual_class_hash,
+            });
+        }
+        increment_counter!(papyrus_metrics::PAPYRUS_P2P_SYNC_VERIFIED_CLASSES, "result" => "valid");
+        Ok(ApiContractClass::ContractClass(contract_class))
+    }
+}
@@ -0,0 +1,85 @@
+use indexmap::indexmap;
+use papyrus_common::class_hash::calculate_class_hash;
+use papyrus_common::pending_classes::ApiContractClass;
+use starknet_api::block::BlockNumber;
+use starknet_api::core::{ClassHash, CompiledClassHash};
+use starknet_api::deprecated_contract_class::ContractClass as DeprecatedContractClass;
+use starknet_api::state::{ContractClass, ThinStateDiff};
+use starknet_types_core::felt::Felt;
+
+use crate::class_verification::{ClassVerificationError, ClassVerifier};
+
+const BLOCK_NUMBER: BlockNumber = BlockNumber(1);
+const MAX_PARALLEL_VERIFICATIONS: usize = 2;
+
+fn cairo1_class() -> ContractClass {
+    ContractClass { sierra_program: vec![Felt::ONE, Felt::TWO], ..Default::default() }
+}
+
+#[tokio::test]
+async fn class_that_matches_its_hash_is_returned() {
+    let class = ApiContractClass::ContractClass(cairo1_class());
+    let class_hash = calculate_class_hash(&cairo1_class());
+    let state_diff = ThinStateDiff {
+        declared_classes: indexmap! { class_hash => CompiledClassHash::default() },
+        ..Default::default()
+    };
+
+    let verifier = ClassVerifier::new(MAX_PARALLEL_VERIFICATIONS);
+    assert_eq!(
+        verifier.verify(BLOCK_NUMBER, &state_diff, class_hash, class.clone()).await,
+        Ok(class)
+    );
+}
+
+#[tokio::test]
+async fn class_that_does_not_match_its_hash_is_rejected() {
+    let class_hash = ClassHash(Felt::ONE);
+    let state_diff = ThinStateDiff {
+        declared_classes: indexmap! { class_hash => CompiledClassHash::default() },
+        ..Default::default()
+    };
+
+    let verifier = ClassVerifier::new(MAX_PARALLEL_VERIFICATIONS);
+    assert_eq!(
+        verifier
+            .verify(
+                BLOCK_NUMBER,
+                &state_diff,
+                class_hash,
+                ApiContractClass::ContractClass(cairo1_class())
+            )
+            .await,
+        Err(ClassVerificationError::ClassHashMismatch {
+            expected_class_hash: class_hash,
+            actual_class_hash: calculate_class_hash(&cairo1_class()),
+        })
+    );
+}
+
+#[tokio::test]
+async fn class_must_be_declared_with_its_cairo_version() {
+    let class_hash = calculate_class_hash(&cairo1_class());
+    // The class is declared as a Cairo 0 class.
+    let state_diff =
+        ThinStateDiff { deprecated_declared_classes: vec![class_hash], ..Default::default() };
+
+    let verifier = ClassVerifier::new(MAX_PARALLEL_VERIFICATIONS);
+    assert_eq!(
+        verifier
+            .verify(
+                BLOCK_NUMBER,
+                &state_diff,
+                class_hash,
+                ApiContractClass::ContractClass(cairo1_class())
+            )
+            .await,
+        Err(ClassVerificationError::UndeclaredClass { block_number: BLOCK_NUMBER, class_hash })
+    );
+
+    let class = ApiContractClass::DeprecatedContractClass(DeprecatedContractClass::default());
+    assert_eq!(
+        verifier.verify(BLOCK_NUMBER, &state_diff, class_hash, class.clone()).await,
+        Ok(class)
+    );
+}
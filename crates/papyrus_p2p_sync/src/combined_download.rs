@@ -0,0 +1,840 @@
+use std::cmp::{max, min};
+use std::collections::VecDeque;
+use std::iter::zip;
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+use async_stream::stream;
+use futures::channel::mpsc::SendError;
+use futures::stream::BoxStream;
+use futures::{Sink, Stream, StreamExt};
+use papyrus_common::approximate_size_in_bytes;
+use papyrus_common::transaction_hash::get_transaction_hash;
+use papyrus_common::TransactionOptions;
+use papyrus_network::network_manager::{QueryPriority, ReportCallback};
+use papyrus_protobuf::sync::{
+    BlockHashOrNumber,
+    Direction,
+    HeaderQuery,
+    Query,
+    SignedBlockHeader,
+    StateDiffQuery,
+    TransactionQuery,
+};
+use papyrus_storage::body::BodyStorageReader;
+use papyrus_storage::db::TransactionKind;
+use papyrus_storage::header::HeaderStorageReader;
+use papyrus_storage::state::StateStorageReader;
+use papyrus_storage::{StorageReader, StorageTxn};
+use starknet_api::block::{BlockHash, BlockHeader, BlockNumber};
+use starknet_api::core::ChainId;
+use starknet_api::state::ThinStateDiff;
+use starknet_api::transaction::{Transaction, TransactionHash, TransactionOutput};
+use tracing::{debug, info, warn};
+
+use crate::block_injection::{validate_block, validate_body};
+use crate::header::validate_header;
+use crate::query_client::QueryClient;
+use crate::response_validator::ValidatedResponse;
+use crate::state_diff::unite_and_validate_state_diff_parts;
+use crate::stream_factory::{get_previous_block_hash, BlockData};
+use crate::{
+    P2PSyncConfig,
+    P2PSyncError,
+    Response,
+    TransactionQuerySender,
+    TransactionResponseReceiver,
+    NETWORK_DATA_TIMEOUT,
+    STEP,
+};
+
+const HEADERS_TYPE_DESCRIPTION: &str = "headers";
+const STATE_DIFFS_TYPE_DESCRIPTION: &str = "state diffs";
+const TRANSACTIONS_TYPE_DESCRIPTION: &str = "transactions";
+
+pub(crate) enum CombinedDownloadEvent {
+    /// Validated data that should be written to the storage before the next event is requested.
+    /// Usually a whole block, but the parts of a block that doesn't fit in the combined download
+    /// are sent one after the other.
+    Data(Box<dyn BlockData>),
+    /// The sync reached `stop_sync_at_block_number`.
+    Stopped,
+}
+
+/// Downloads the header, transactions and state diff of each block together, so that the block is
+/// validated as a unit and written to the storage in a single transaction, which advances the
+/// header, body and state markers together.
+///
+/// The blocks are downloaded in windows. For each window, a query for the same blocks is sent at
+/// once on each protocol, so that the network sends them to the same peer if it can. A window
+/// whose responses exceed `combined_download_max_window_bytes` is dropped and downloaded again in
+/// smaller windows. A block that fails the validation is downloaded again, with the rest of its
+/// window, after the peers that sent it were reported.
+///
+/// A block that doesn't fit in a window of its own, exceeds `combined_download_max_block_bytes` or
+/// whose header doesn't state the sizes of its parts is downloaded in parts: its header first, and
+/// then its state diff and its transactions, each in a session of its own. The bodies and state
+/// diffs that are behind the headers, e.g. of a node that synced without the combined download,
+/// are caught up the same way before the blocks are downloaded together again.
+pub(crate) struct CombinedBlockDownloader<
+    HeaderQuerySender,
+    HeaderResponseReceiver,
+    StateDiffQuerySender,
+    StateDiffResponseReceiver,
+> {
+    header_client:
+        QueryClient<HeaderQuerySender, HeaderResponseReceiver, (HeaderQuery, QueryPriority)>,
+    state_diff_client: QueryClient<StateDiffQuerySender, StateDiffResponseReceiver, StateDiffQuery>,
+    transaction_client:
+        QueryClient<TransactionQuerySender, TransactionResponseReceiver, TransactionQuery>,
+    storage_reader: StorageReader,
+    chain_id: ChainId,
+    config: P2PSyncConfig,
+    window_len: u64,
+    // The blocks of the current window that weren't validated yet. The first is the block of the
+    // marker the window was downloaded from.
+    downloaded_blocks: VecDeque<DownloadedBlock>,
+}
+
+// The parts of a block that were downloaded together, before they're validated as a unit.
+struct DownloadedBlock {
+    block_number: BlockNumber,
+    parts: BlockParts,
+    // Report the peers that sent the parts if they're invalid.
+    report_callbacks: Vec<ReportCallback>,
+}
+
+enum BlockParts {
+    Whole {
+        signed_header: SignedBlockHeader,
+        transactions: Vec<(Transaction, Option<TransactionOutput>)>,
+        state_diff_parts: Vec<ThinStateDiff>,
+    },
+    Header(SignedBlockHeader),
+    // The parts of a block whose header is already in the storage.
+    StateDiff(Vec<ThinStateDiff>),
+    Transactions(Vec<(Transaction, Option<TransactionOutput>)>),
+}
+
+impl BlockParts {
+    fn description(&self) -> &'static str {
+        match self {
+            BlockParts::Whole { .. } => "block",
+            BlockParts::Header(_) => "header of block",
+            BlockParts::StateDiff(_) => "state diff of block",
+            BlockParts::Transactions(_) => "transactions of block",
+        }
+    }
+}
+
+enum WindowOutcome {
+    Blocks(VecDeque<DownloadedBlock>),
+    // The window should be downloaded again right away.
+    Retry,
+    // There are no new blocks to download yet.
+    Wait,
+    Stopped,
+}
+
+// A response with its approximate size in memory.
+struct SizedResponse<T> {
+    data: T,
+    report_callback: ReportCallback,
+    size_in_bytes: usize,
+}
+
+enum SessionResponses<T> {
+    Complete(Vec<SizedResponse<T>>),
+    // The responses of the window exceeded its limit, so the rest of the session was dropped.
+    OverBudget,
+}
+
+// The responses of a session that belong to a single block.
+struct BlockResponses<T> {
+    data: Vec<T>,
+    report_callbacks: Vec<ReportCallback>,
+    size_in_bytes: usize,
+    // False if the session ended before the block did.
+    is_complete: bool,
+}
+
+impl<HeaderQuerySender, HeaderResponseReceiver, StateDiffQuerySender, StateDiffResponseReceiver>
+    CombinedBlockDownloader<
+        HeaderQuerySender,
+        HeaderResponseReceiver,
+        StateDiffQuerySender,
+        StateDiffResponseReceiver,
+    >
+where
+    HeaderQuerySender: Sink<(HeaderQuery, QueryPriority), Error = SendError> + Unpin,
+    HeaderResponseReceiver: Stream<Item = Response<SignedBlockHeader>> + Unpin,
+    StateDiffQuerySender: Sink<StateDiffQuery, Error = SendError> + Unpin,
+    StateDiffResponseReceiver: Stream<Item = Response<ThinStateDiff>> + Unpin,
+{
+    #[allow(clippy::too_many_arguments)]
+    pub(crate) fn new(
+        header_query_sender: HeaderQuerySender,
+        header_response_receiver: HeaderResponseReceiver,
+        state_diff_query_sender: StateDiffQuerySender,
+        state_diff_response_receiver: StateDiffResponseReceiver,
+        transaction_query_sender: TransactionQuerySender,
+        transaction_response_receiver: TransactionResponseReceiver,
+        storage_reader: StorageReader,
+        chain_id: ChainId,
+        config: P2PSyncConfig,
+    ) -> Self {
+        Self {
+            header_client: QueryClient::with_query_message(
+                header_query_sender,
+                |query| (HeaderQuery(query), QueryPriority::Normal),
+                header_response_receiver,
+                HEADERS_TYPE_DESCRIPTION,
+                NETWORK_DATA_TIMEOUT,
+            ),
+            state_diff_client: QueryClient::with_query_message(
+                state_diff_query_sender,
+                StateDiffQuery,
+                state_diff_response_receiver,
+                STATE_DIFFS_TYPE_DESCRIPTION,
+                NETWORK_DATA_TIMEOUT,
+            ),
+            transaction_client: QueryClient::with_query_message(
+                transaction_query_sender,
+                |query| TransactionQuery { query, include_outputs: true },
+                transaction_response_receiver,
+                TRANSACTIONS_TYPE_DESCRIPTION,
+                NETWORK_DATA_TIMEOUT,
+            ),
+            storage_reader,
+            chain_id,
+            window_len: config.num_block_state_diffs_per_query.max(1),
+            config,
+            downloaded_blocks: VecDeque::new(),
+        }
+    }
+
+    /// Returns the next validated data. Since the validation of a block depends on the previous
+    /// block, each data should be written to the storage before this is called again.
+    pub(crate) async fn next_event(&mut self) -> Result<CombinedDownloadEvent, P2PSyncError> {
+        loop {
+            let Some(block) = self.downloaded_blocks.pop_front() else {
+                match self.download_window().await? {
+                    WindowOutcome::Blocks(blocks) => self.downloaded_blocks = blocks,
+                    WindowOutcome::Retry => {}
+                    WindowOutcome::Wait => {
+                        debug!(
+                            "Combined download is waiting {:?} for new blocks.",
+                            self.config.wait_period_for_new_data
+                        );
+                        tokio::time::sleep(self.config.wait_period_for_new_data).await;
+                    }
+                    WindowOutcome::Stopped => return Ok(CombinedDownloadEvent::Stopped),
+                }
+                continue;
+            };
+            let block_number = block.block_number;
+            let description = block.parts.description();
+            match self.validate(block_number, block.parts) {
+                Ok(data) => {
+                    info!("Added {description} {block_number} downloaded in the combined mode.");
+                    return Ok(CombinedDownloadEvent::Data(data));
+                }
+                Err(error @ P2PSyncError::StorageError(_)) => return Err(error),
+                // The blocks after an invalid block can't be validated, so they're downloaded
+                // again along with it, hopefully from another peer.
+                Err(error) => {
+                    warn!(
+                        "The {description} {block_number} is invalid: {error} Downloading it \
+                         again."
+                    );
+                    for report_callback in block.report_callbacks {
+                        report_callback();
+                    }
+                    self.downloaded_blocks.clear();
+                }
+            }
+        }
+    }
+
+    // Called when the responses of the window of `limit` blocks from `start_block_number` exceeded
+    // the window's limit. `limit` is at least 2, since a window of a single block is downloaded in
+    // parts instead.
+    fn shrink_window(&mut self, start_block_number: BlockNumber, limit: u64) -> WindowOutcome {
+        self.window_len = limit / 2;
+        debug!(
+            "The blocks [{}, {}) exceed the combined download window. Downloading them in windows \
+             of {} blocks.",
+            start_block_number.0,
+            start_block_number.0 + limit,
+            self.window_len,
+        );
+        WindowOutcome::Retry
+    }
+
+    fn validate(
+        &self,
+        block_number: BlockNumber,
+        parts: BlockParts,
+    ) -> Result<Box<dyn BlockData>, P2PSyncError> {
+        let txn = self.storage_reader.begin_ro_txn()?;
+        match parts {
+            BlockParts::Whole { signed_header, transactions, state_diff_parts } => {
+                let (transactions, transaction_hashes) =
+                    self.hash_transactions(block_number, transactions)?;
+                Ok(Box::new(validate_block(
+                    &txn,
+                    signed_header,
+                    transactions,
+                    transaction_hashes,
+                    state_diff_parts,
+                )?))
+            }
+            BlockParts::Header(signed_header) => {
+                validate_header(&txn, &signed_header, block_number)?;
+                Ok(Box::new(signed_header))
+            }
+            BlockParts::StateDiff(state_diff_parts) => {
+                let header = get_stored_header(&txn, block_number)?;
+                let state_diff = unite_and_validate_state_diff_parts(state_diff_parts, &header)?;
+                Ok(Box::new((state_diff, block_number)))
+            }
+            BlockParts::Transactions(transactions) => {
+                let header = get_stored_header(&txn, block_number)?;
+                let (transactions, transaction_hashes) =
+                    self.hash_transactions(block_number, transactions)?;
+                let body = validate_body(&header, transactions, transaction_hashes)?;
+                Ok(Box::new((body, block_number)))
+            }
+        }
+    }
+
+    // Peers don't send the transaction hashes, so they're computed from the transactions and
+    // checked through the header's transaction commitment.
+    fn hash_transactions(
+        &self,
+        block_number: BlockNumber,
+        transactions: Vec<(Transaction, Option<TransactionOutput>)>,
+    ) -> Result<(Vec<(Transaction, TransactionOutput)>, Vec<TransactionHash>), P2PSyncError> {
+        let transactions = transactions
+            .into_iter()
+            .map(|(transaction, output)| {
+                output
+                    .map(|output| (transaction, output))
+                    .ok_or(P2PSyncError::MissingTransactionOutput { block_number })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let transaction_hashes = transactions
+            .iter()
+            .map(|(transaction, _)| {
+                get_transaction_hash(
+                    transaction,
+                    &self.chain_id,
+                    &TransactionOptions { only_query: false },
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok((transactions, transaction_hashes))
+    }
+
+    async fn download_window(&mut self) -> Result<WindowOutcome, P2PSyncError> {
+        let (header_marker, body_marker, state_marker) = {
+            let txn = self.storage_reader.begin_ro_txn()?;
+            (txn.get_header_marker()?, txn.get_body_marker()?, txn.get_state_marker()?)
+        };
+        let stop_sync_at_block_number =
+            self.config.stop_sync_at_block_number.unwrap_or(BlockNumber(u64::MAX));
+        // The blocks are downloaded together only from the block that all the markers point to.
+        let headers_end = min(header_marker, stop_sync_at_block_number);
+        if state_marker < headers_end {
+            return self.download_state_diffs(state_marker, headers_end).await;
+        }
+        if body_marker < headers_end {
+            return self.download_transactions(body_marker, headers_end).await;
+        }
+        if header_marker >= stop_sync_at_block_number {
+            info!("Combined download hit the stop sync block number.");
+            return Ok(WindowOutcome::Stopped);
+        }
+        let parts_end = min(max(body_marker, state_marker), stop_sync_at_block_number);
+        if header_marker < parts_end {
+            let limit = min(self.window_len, parts_end.0 - header_marker.0);
+            return self.download_headers(header_marker, limit).await;
+        }
+
+        let start_block_number = header_marker;
+        let limit = min(self.window_len, stop_sync_at_block_number.0 - start_block_number.0);
+        debug!(
+            "Downloading the headers, transactions and state diffs of blocks [{}, {}) together",
+            start_block_number.0,
+            start_block_number.0 + limit,
+        );
+        let query = window_query(start_block_number, limit);
+        let previous_block_hash =
+            get_previous_block_hash(&self.storage_reader, start_block_number)?;
+        let window_bytes = AtomicUsize::new(0);
+        let max_window_bytes = self.config.combined_download_max_window_bytes;
+        let (headers, state_diff_parts, transactions) = futures::join!(
+            collect_session(
+                &mut self.header_client,
+                query.clone(),
+                previous_block_hash,
+                &window_bytes,
+                max_window_bytes,
+                |signed_header: &SignedBlockHeader| {
+                    approximate_size_in_bytes(&signed_header.block_header)
+                },
+            ),
+            collect_session(
+                &mut self.state_diff_client,
+                query.clone(),
+                None,
+                &window_bytes,
+                max_window_bytes,
+                |state_diff_part: &ThinStateDiff| approximate_size_in_bytes(state_diff_part),
+            ),
+            collect_session(
+                &mut self.transaction_client,
+                query,
+                None,
+                &window_bytes,
+                max_window_bytes,
+                |transaction: &(Transaction, Option<TransactionOutput>)| {
+                    approximate_size_in_bytes(transaction)
+                },
+            ),
+        );
+        let responses = match (headers, state_diff_parts, transactions) {
+            (Err(error), _, _) | (_, Err(error), _) | (_, _, Err(error)) => {
+                return handle_window_error(error, start_block_number);
+            }
+            (
+                Ok(SessionResponses::Complete(headers)),
+                Ok(SessionResponses::Complete(state_diff_parts)),
+                Ok(SessionResponses::Complete(transactions)),
+            ) => (headers, state_diff_parts, transactions),
+            _ if limit == 1 => {
+                info!(
+                    "Block {start_block_number} doesn't fit in the combined download window. \
+                     Downloading its parts separately."
+                );
+                return self.download_headers(start_block_number, 1).await;
+            }
+            _ => return Ok(self.shrink_window(start_block_number, limit)),
+        };
+        let (headers, state_diff_parts, transactions) = responses;
+
+        let mut transactions = transactions.into_iter();
+        let mut state_diff_parts = state_diff_parts.into_iter();
+        let mut blocks = VecDeque::new();
+        let mut largest_block_bytes = 0;
+        for header in headers {
+            let block_header = &header.data.block_header;
+            let block_number = block_header.block_number;
+            let (Some(n_transactions), Some(state_diff_length)) =
+                (block_header.n_transactions, block_header.state_diff_length)
+            else {
+                info!(
+                    "The header of block {block_number} doesn't state the number of transactions \
+                     or the state diff length, so the parts of the blocks can't be told apart. \
+                     Downloading its parts separately."
+                );
+                push_header_if_first(&mut blocks, header);
+                break;
+            };
+            let block_transactions =
+                take_block_responses(&mut transactions, Some(n_transactions), |_| 1);
+            let block_state_diff_parts =
+                take_block_responses(&mut state_diff_parts, Some(state_diff_length), |part| {
+                    part.len()
+                });
+            let block_bytes = header.size_in_bytes
+                + block_transactions.size_in_bytes
+                + block_state_diff_parts.size_in_bytes;
+            if block_bytes > self.config.combined_download_max_block_bytes {
+                info!(
+                    "Block {block_number} is larger than the limit of blocks downloaded in the \
+                     combined mode. Downloading its parts separately."
+                );
+                push_header_if_first(&mut blocks, header);
+                break;
+            }
+            // The peer sent only some of the parts of the block, e.g. because its responses
+            // reached the peer's limits. The block is downloaded again in the next window.
+            if !block_transactions.is_complete || !block_state_diff_parts.is_complete {
+                break;
+            }
+            largest_block_bytes = largest_block_bytes.max(block_bytes);
+            let mut report_callbacks = vec![header.report_callback];
+            report_callbacks.extend(block_transactions.report_callbacks);
+            report_callbacks.extend(block_state_diff_parts.report_callbacks);
+            blocks.push_back(DownloadedBlock {
+                block_number,
+                parts: BlockParts::Whole {
+                    signed_header: header.data,
+                    transactions: block_transactions.data,
+                    state_diff_parts: block_state_diff_parts.data,
+                },
+                report_callbacks,
+            });
+        }
+        if blocks.is_empty() {
+            return Ok(WindowOutcome::Wait);
+        }
+        // The next window is as long as the window limit allows for blocks as large as the largest
+        // block of this window.
+        if largest_block_bytes > 0 {
+            self.window_len = u64::try_from(max_window_bytes / largest_block_bytes)
+                .unwrap_or(u64::MAX)
+                .clamp(1, self.config.num_block_state_diffs_per_query.max(1));
+        }
+        Ok(WindowOutcome::Blocks(blocks))
+    }
+
+    // Downloads only the headers of the window of `limit` blocks from `start_block_number`.
+    async fn download_headers(
+        &mut self,
+        start_block_number: BlockNumber,
+        limit: u64,
+    ) -> Result<WindowOutcome, P2PSyncError> {
+        debug!(
+            "Downloading the headers of blocks [{}, {}) separately",
+            start_block_number.0,
+            start_block_number.0 + limit,
+        );
+        let previous_block_hash =
+            get_previous_block_hash(&self.storage_reader, start_block_number)?;
+        let headers = collect_session(
+            &mut self.header_client,
+            window_query(start_block_number, limit),
+            previous_block_hash,
+            &AtomicUsize::new(0),
+            self.max_catch_up_window_bytes(limit),
+            |signed_header: &SignedBlockHeader| {
+                approximate_size_in_bytes(&signed_header.block_header)
+            },
+        )
+        .await;
+        let headers = match headers {
+            Ok(SessionResponses::Complete(headers)) => headers,
+            Ok(SessionResponses::OverBudget) => {
+                return Ok(self.shrink_window(start_block_number, limit));
+            }
+            Err(error) => return handle_window_error(error, start_block_number),
+        };
+        let blocks = headers.into_iter().map(DownloadedBlock::header).collect::<VecDeque<_>>();
+        Ok(if blocks.is_empty() { WindowOutcome::Wait } else { WindowOutcome::Blocks(blocks) })
+    }
+
+    // Downloads the state diffs of the blocks from `start_block_number` whose headers are in the
+    // storage, up to `end_block_number`.
+    async fn download_state_diffs(
+        &mut self,
+        start_block_number: BlockNumber,
+        end_block_number: BlockNumber,
+    ) -> Result<WindowOutcome, P2PSyncError> {
+        let block_lens = self.get_block_lens(start_block_number, end_block_number, |header| {
+            header.state_diff_length
+        })?;
+        let limit = u64::try_from(block_lens.len()).expect("usize should fit in u64");
+        debug!(
+            "Catching up the state diffs of blocks [{}, {}) with the headers",
+            start_block_number.0,
+            start_block_number.0 + limit,
+        );
+        let state_diff_parts = collect_session(
+            &mut self.state_diff_client,
+            window_query(start_block_number, limit),
+            None,
+            &AtomicUsize::new(0),
+            self.max_catch_up_window_bytes(limit),
+            |state_diff_part: &ThinStateDiff| approximate_size_in_bytes(state_diff_part),
+        )
+        .await;
+        match state_diff_parts {
+            Ok(SessionResponses::Complete(state_diff_parts)) => Ok(split_into_blocks(
+                state_diff_parts,
+                start_block_number,
+                block_lens,
+                |part| part.len(),
+                BlockParts::StateDiff,
+            )),
+            Ok(SessionResponses::OverBudget) => Ok(self.shrink_window(start_block_number, limit)),
+            Err(error) => handle_window_error(error, start_block_number),
+        }
+    }
+
+    // Downloads the transactions of the blocks from `start_block_number` whose headers are in the
+    // storage, up to `end_block_number`.
+    async fn download_transactions(
+        &mut self,
+        start_block_number: BlockNumber,
+        end_block_number: BlockNumber,
+    ) -> Result<WindowOutcome, P2PSyncError> {
+        let block_lens = self
+            .get_block_lens(start_block_number, end_block_number, |header| header.n_transactions)?;
+        let limit = u64::try_from(block_lens.len()).expect("usize should fit in u64");
+        debug!(
+            "Catching up the transactions of blocks [{}, {}) with the headers",
+            start_block_number.0,
+            start_block_number.0 + limit,
+        );
+        let transactions = collect_session(
+            &mut self.transaction_client,
+            window_query(start_block_number, limit),
+            None,
+            &AtomicUsize::new(0),
+            self.max_catch_up_window_bytes(limit),
+            |transaction: &(Transaction, Option<TransactionOutput>)| {
+                approximate_size_in_bytes(transaction)
+            },
+        )
+        .await;
+        match transactions {
+            Ok(SessionResponses::Complete(transactions)) => Ok(split_into_blocks(
+                transactions,
+                start_block_number,
+                block_lens,
+                |_| 1,
+                BlockParts::Transactions,
+            )),
+            Ok(SessionResponses::OverBudget) => Ok(self.shrink_window(start_block_number, limit)),
+            Err(error) => handle_window_error(error, start_block_number),
+        }
+    }
+
+    // Returns the length of each block of the catch-up window from `start_block_number`, as stated
+    // by the headers in the storage. The parts of a block whose header doesn't state its length
+    // can't be told apart from the parts of the next block, so it's caught up in a window of its
+    // own.
+    fn get_block_lens(
+        &self,
+        start_block_number: BlockNumber,
+        end_block_number: BlockNumber,
+        block_len: fn(&BlockHeader) -> Option<usize>,
+    ) -> Result<Vec<Option<usize>>, P2PSyncError> {
+        let txn = self.storage_reader.begin_ro_txn()?;
+        let limit = min(self.window_len, end_block_number.0 - start_block_number.0);
+        let mut block_lens = vec![];
+        for block_number in start_block_number.0..start_block_number.0 + limit {
+            match block_len(&get_stored_header(&txn, BlockNumber(block_number))?) {
+                Some(len) => block_lens.push(Some(len)),
+                None if block_lens.is_empty() => {
+                    block_lens.push(None);
+                    break;
+                }
+                None => break,
+            }
+        }
+        Ok(block_lens)
+    }
+
+    // The parts of a single block can't be split further, so a window of a single block is
+    // downloaded whatever its size.
+    fn max_catch_up_window_bytes(&self, limit: u64) -> usize {
+        if limit == 1 {
+            usize::MAX
+        } else {
+            self.config.combined_download_max_window_bytes
+        }
+    }
+}
+
+fn window_query(start_block_number: BlockNumber, limit: u64) -> Query {
+    Query {
+        start_block: BlockHashOrNumber::Number(start_block_number),
+        direction: Direction::Forward,
+        limit,
+        step: STEP,
+    }
+}
+
+fn get_stored_header<Mode: TransactionKind>(
+    txn: &StorageTxn<'_, Mode>,
+    block_number: BlockNumber,
+) -> Result<BlockHeader, P2PSyncError> {
+    Ok(txn
+        .get_block_header(block_number)?
+        .expect("A header with number lower than the header marker is missing"))
+}
+
+impl DownloadedBlock {
+    fn header(header: SizedResponse<SignedBlockHeader>) -> Self {
+        Self {
+            block_number: header.data.block_header.block_number,
+            parts: BlockParts::Header(header.data),
+            report_callbacks: vec![header.report_callback],
+        }
+    }
+}
+
+// The header of a block whose parts are downloaded separately is written alone if it's the first
+// block of the window. Otherwise, the block is downloaded again as the first block of the next
+// window.
+fn push_header_if_first(
+    blocks: &mut VecDeque<DownloadedBlock>,
+    header: SizedResponse<SignedBlockHeader>,
+) {
+    if blocks.is_empty() {
+        blocks.push_back(DownloadedBlock::header(header));
+    }
+}
+
+// Takes the responses of the next block from the responses of a session, where each response adds
+// `response_len` to the block's length. Without a `block_len`, all the responses are taken.
+fn take_block_responses<T>(
+    responses: &mut impl Iterator<Item = SizedResponse<T>>,
+    block_len: Option<usize>,
+    response_len: fn(&T) -> usize,
+) -> BlockResponses<T> {
+    let mut data = vec![];
+    let mut report_callbacks = vec![];
+    let mut size_in_bytes = 0;
+    let mut len = 0;
+    while block_len.map_or(true, |block_len| len < block_len) {
+        let Some(response) = responses.next() else {
+            break;
+        };
+        len += response_len(&response.data);
+        size_in_bytes += response.size_in_bytes;
+        report_callbacks.push(response.report_callback);
+        data.push(response.data);
+    }
+    let is_complete = block_len.map_or(!data.is_empty(), |block_len| len >= block_len);
+    BlockResponses { data, report_callbacks, size_in_bytes, is_complete }
+}
+
+// Splits the responses of a catch-up session into the parts of the blocks from
+// `start_block_number`, up to the first block the session didn't complete.
+fn split_into_blocks<T>(
+    responses: Vec<SizedResponse<T>>,
+    start_block_number: BlockNumber,
+    block_lens: Vec<Option<usize>>,
+    response_len: fn(&T) -> usize,
+    into_parts: fn(Vec<T>) -> BlockParts,
+) -> WindowOutcome {
+    let mut responses = responses.into_iter();
+    let mut blocks = VecDeque::new();
+    for (block_number, block_len) in zip(start_block_number.0.., block_lens) {
+        let block = take_block_responses(&mut responses, block_len, response_len);
+        if !block.is_complete {
+            break;
+        }
+        blocks.push_back(DownloadedBlock {
+            block_number: BlockNumber(block_number),
+            parts: into_parts(block.data),
+            report_callbacks: block.report_callbacks,
+        });
+    }
+    if blocks.is_empty() {
+        return WindowOutcome::Wait;
+    }
+    WindowOutcome::Blocks(blocks)
+}
+
+// The network notifies us only when no peer could be connected to send the queries to, so other
+// failures are detected by not receiving data for a while. Invalid responses were already
+// reported, and the network sends the next queries to another peer.
+fn handle_window_error(
+    error: P2PSyncError,
+    start_block_number: BlockNumber,
+) -> Result<WindowOutcome, P2PSyncError> {
+    match error {
+        P2PSyncError::NetworkTimeout(_)
+        | P2PSyncError::NoPeers(_)
+        | P2PSyncError::ResponseViolation(_)
+        | P2PSyncError::ProtobufConversionError(_) => {
+            warn!(
+                "Failed downloading the blocks from {start_block_number} on: {error} Downloading \
+                 them again."
+            );
+            Ok(WindowOutcome::Retry)
+        }
+        error => Err(error),
+    }
+}
+
+// Sends the query through the client and collects the responses of its session with their sizes.
+// `window_bytes` is shared by the sessions of the window, so once the responses of all of them
+// exceed `max_window_bytes`, each session drops the rest of its responses.
+async fn collect_session<QuerySender, DataReceiver, QueryMessage, InputFromNetwork>(
+    client: &mut QueryClient<QuerySender, DataReceiver, QueryMessage>,
+    query: Query,
+    previous_block_hash: Option<BlockHash>,
+    window_bytes: &AtomicUsize,
+    max_window_bytes: usize,
+    size_in_bytes: fn(&InputFromNetwork) -> usize,
+) -> Result<SessionResponses<InputFromNetwork>, P2PSyncError>
+where
+    QuerySender: Sink<QueryMessage, Error = SendError> + Unpin,
+    DataReceiver: Stream<Item = Response<InputFromNetwork>> + Unpin,
+    InputFromNetwork: ValidatedResponse,
+{
+    let mut sizes_in_bytes = vec![];
+    let responses = client
+        .query_while(query, previous_block_hash, |data| {
+            let size_in_bytes = size_in_bytes(data);
+            sizes_in_bytes.push(size_in_bytes);
+            window_bytes.fetch_add(size_in_bytes, Ordering::Relaxed) + size_in_bytes
+                <= max_window_bytes
+        })
+        .await?;
+    let Some(responses) = responses else {
+        return Ok(SessionResponses::OverBudget);
+    };
+    Ok(SessionResponses::Complete(
+        zip(responses, sizes_in_bytes)
+            .map(|((data, report_callback), size_in_bytes)| SizedResponse {
+                data,
+                report_callback,
+                size_in_bytes,
+            })
+            .collect(),
+    ))
+}
+
+/// Creates a stream of the blocks downloaded in the combined mode. The state diffs are downloaded
+/// through the first lane only.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn create_combined_stream<
+    HeaderQuerySender,
+    HeaderResponseReceiver,
+    StateDiffQuerySender,
+    StateDiffResponseReceiver,
+>(
+    config: P2PSyncConfig,
+    storage_reader: StorageReader,
+    header_query_sender: HeaderQuerySender,
+    header_response_receiver: HeaderResponseReceiver,
+    mut state_diff_lanes: Vec<(StateDiffQuerySender, StateDiffResponseReceiver)>,
+    transaction_query_sender: TransactionQuerySender,
+    transaction_response_receiver: TransactionResponseReceiver,
+    chain_id: ChainId,
+) -> BoxStream<'static, Result<Box<dyn BlockData>, P2PSyncError>>
+where
+    HeaderQuerySender:
+        Sink<(HeaderQuery, QueryPriority), Error = SendError> + Unpin + Send + 'static,
+    HeaderResponseReceiver: Stream<Item = Response<SignedBlockHeader>> + Unpin + Send + 'static,
+    StateDiffQuerySender: Sink<StateDiffQuery, Error = SendError> + Unpin + Send + 'static,
+    StateDiffResponseReceiver: Stream<Item = Response<ThinStateDiff>> + Unpin + Send + 'static,
+{
+    stream! {
+        let (state_diff_query_sender, state_diff_response_receiver) = state_diff_lanes.remove(0);
+        let mut downloader = CombinedBlockDownloader::new(
+            header_query_sender,
+            header_response_receiver,
+            state_diff_query_sender,
+            state_diff_response_receiver,
+            transaction_query_sender,
+            transaction_response_receiver,
+            storage_reader,
+            chain_id,
+            config,
+        );
+        loop {
+            match downloader.next_event().await? {
+                CombinedDownloadEvent::Data(data) => yield Ok(data),
+                CombinedDownloadEvent::Stopped => return,
+            }
+        }
+    }
+    .boxed()
+}
@@ -0,0 +1,552 @@
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+
+use futures::channel::mpsc::{Receiver, Sender};
+use futures::{SinkExt, StreamExt};
+use indexmap::IndexMap;
+use papyrus_common::transaction_hash::get_transaction_hash;
+use papyrus_common::block_hash::{calculate_body_commitments, BodyCommitments};
+use papyrus_common::{approximate_size_in_bytes, TransactionOptions};
+use papyrus_network::network_manager::{QueryPriority, ReportCallback};
+use papyrus_protobuf::sync::{
+    BlockHashOrNumber,
+    DataOrFin,
+    Direction,
+    HeaderQuery,
+    Query,
+    SignedBlockHeader,
+    StateDiffQuery,
+    TransactionQuery,
+};
+use papyrus_storage::body::BodyStorageReader;
+use papyrus_storage::header::{HeaderStorageReader, HeaderStorageWriter};
+use papyrus_storage::state::{StateStorageReader, StateStorageWriter};
+use papyrus_storage::test_utils::get_test_storage;
+use papyrus_storage::{StorageReader, StorageWriter};
+use starknet_api::block::{BlockBody, BlockHeader, BlockNumber, StarknetVersion};
+use starknet_api::core::{ChainId, ContractAddress, Nonce};
+use starknet_api::state::ThinStateDiff;
+use starknet_api::transaction::{
+    Event,
+    EventContent,
+    EventData,
+    InvokeTransaction,
+    InvokeTransactionOutput,
+    InvokeTransactionV1,
+    Transaction,
+    TransactionOutput,
+};
+use starknet_types_core::felt::Felt;
+use tokio::sync::{watch, RwLock};
+
+use crate::test_utils::{
+    create_block_hashes_and_signatures,
+    get_parent_hash,
+    BUFFER_SIZE,
+    HEADER_QUERY_LENGTH,
+    SLEEP_DURATION_TO_LET_SYNC_ADVANCE,
+    STATE_DIFF_QUERY_LENGTH,
+    WAIT_PERIOD_FOR_NEW_DATA,
+};
+use crate::{P2PSync, P2PSyncConfig, Response};
+
+const NUM_BLOCKS: u64 = STATE_DIFF_QUERY_LENGTH;
+
+type TestP2PSync = P2PSync<
+    Sender<(HeaderQuery, QueryPriority)>,
+    Receiver<Response<SignedBlockHeader>>,
+    Sender<StateDiffQuery>,
+    Receiver<Response<ThinStateDiff>>,
+>;
+
+// The other ends of the channels of the sync.
+struct TestPeer {
+    storage_reader: StorageReader,
+    header_query_receiver: Receiver<(HeaderQuery, QueryPriority)>,
+    state_diff_query_receiver: Receiver<StateDiffQuery>,
+    transaction_query_receiver: Receiver<TransactionQuery>,
+    headers_sender: Sender<Response<SignedBlockHeader>>,
+    state_diffs_sender: Sender<Response<ThinStateDiff>>,
+    transactions_sender: Sender<Response<(Transaction, Option<TransactionOutput>)>>,
+}
+
+fn combined_config() -> P2PSyncConfig {
+    P2PSyncConfig {
+        num_headers_per_query: HEADER_QUERY_LENGTH,
+        num_block_state_diffs_per_query: STATE_DIFF_QUERY_LENGTH,
+        wait_period_for_new_data: WAIT_PERIOD_FOR_NEW_DATA,
+        max_parallel_state_diff_sessions: 1,
+        follow_tip: false,
+        combined_block_download: true,
+        ..Default::default()
+    }
+}
+
+fn setup(config: P2PSyncConfig) -> (TestP2PSync, TestPeer) {
+    setup_with_storage(config, |_storage_writer| {})
+}
+
+// Like `setup`, with the storage prepared by `prepare_storage` before the sync starts.
+fn setup_with_storage(
+    config: P2PSyncConfig,
+    prepare_storage: impl FnOnce(&mut StorageWriter),
+) -> (TestP2PSync, TestPeer) {
+    let ((storage_reader, mut storage_writer), _temp_dir) = get_test_storage();
+    prepare_storage(&mut storage_writer);
+    let (header_query_sender, header_query_receiver) = futures::channel::mpsc::channel(BUFFER_SIZE);
+    let (state_diff_query_sender, state_diff_query_receiver) =
+        futures::channel::mpsc::channel(BUFFER_SIZE);
+    let (transaction_query_sender, transaction_query_receiver) =
+        futures::channel::mpsc::channel(BUFFER_SIZE);
+    let (headers_sender, headers_receiver) = futures::channel::mpsc::channel(BUFFER_SIZE);
+    let (state_diffs_sender, state_diffs_receiver) = futures::channel::mpsc::channel(BUFFER_SIZE);
+    let (transactions_sender, transactions_receiver) = futures::channel::mpsc::channel(BUFFER_SIZE);
+    let (header_marker_sender, _header_marker_receiver) = watch::channel(BlockNumber(0));
+    let p2p_sync = P2PSync::new(
+        config,
+        storage_reader.clone(),
+        storage_writer,
+        header_query_sender,
+        headers_receiver,
+        vec![(state_diff_query_sender, state_diffs_receiver)],
+        None,
+        Arc::new(RwLock::new(None)),
+        None,
+        header_marker_sender,
+    )
+    .with_transaction_channels(
+        Box::pin(transaction_query_sender),
+        transactions_receiver.boxed(),
+        ChainId::Mainnet,
+    );
+    let peer = TestPeer {
+        storage_reader,
+        header_query_receiver,
+        state_diff_query_receiver,
+        transaction_query_receiver,
+        headers_sender,
+        state_diffs_sender,
+        transactions_sender,
+    };
+    (p2p_sync, peer)
+}
+
+// A block with a single transaction and a state diff of length 1.
+struct TestBlock {
+    signed_header: SignedBlockHeader,
+    transaction: Transaction,
+    transaction_output: TransactionOutput,
+    state_diff: ThinStateDiff,
+}
+
+fn create_blocks(num_blocks: u64) -> Vec<TestBlock> {
+    let block_hashes_and_signatures =
+        create_block_hashes_and_signatures(num_blocks.try_into().unwrap());
+    (0..num_blocks)
+        .map(|block_number| {
+            let index = usize::try_from(block_number).unwrap();
+            let (block_hash, signature) = block_hashes_and_signatures[index];
+            TestBlock {
+                signed_header: SignedBlockHeader {
+                    block_header: BlockHeader {
+                        block_number: BlockNumber(block_number),
+                        block_hash,
+                        parent_hash: get_parent_hash(&block_hashes_and_signatures, index),
+                        n_transactions: Some(1),
+                        state_diff_length: Some(1),
+                        ..Default::default()
+                    },
+                    signatures: vec![signature],
+                    data_availability: None,
+                },
+                transaction: Transaction::Invoke(InvokeTransaction::V1(InvokeTransactionV1 {
+                    nonce: Nonce(Felt::from(block_number)),
+                    ..Default::default()
+                })),
+                transaction_output: TransactionOutput::Invoke(InvokeTransactionOutput::default()),
+                state_diff: ThinStateDiff {
+                    nonces: IndexMap::from_iter([(
+                        ContractAddress::from(1_u128),
+                        Nonce(Felt::from(block_number)),
+                    )]),
+                    ..Default::default()
+                },
+            }
+        })
+        .collect()
+}
+
+// Makes the transaction of the block emit an event, and sets the body commitments of the header in
+// the scheme of Starknet 0.13.2, where the header commits to the events and the receipts.
+fn add_event_and_body_commitments(block: &mut TestBlock) {
+    let block_header = &mut block.signed_header.block_header;
+    block.transaction_output = invoke_output_with_event(Felt::from(block_header.block_number.0));
+    let transaction_hash = get_transaction_hash(
+        &block.transaction,
+        &ChainId::Mainnet,
+        &TransactionOptions { only_query: false },
+    )
+    .unwrap();
+    let BodyCommitments { transaction_commitment, event_commitment, receipt_commitment } =
+        calculate_body_commitments(&BlockBody {
+            transactions: vec![block.transaction.clone()],
+            transaction_outputs: vec![block.transaction_output.clone()],
+            transaction_hashes: vec![transaction_hash],
+        });
+    block_header.starknet_version = StarknetVersion("0.13.2".to_owned());
+    block_header.transaction_commitment = Some(transaction_commitment);
+    block_header.n_events = Some(1);
+    block_header.event_commitment = Some(event_commitment);
+    block_header.receipt_commitment = Some(receipt_commitment);
+}
+
+fn invoke_output_with_event(event_data: Felt) -> TransactionOutput {
+    TransactionOutput::Invoke(InvokeTransactionOutput {
+        events: vec![Event {
+            from_address: ContractAddress::from(1_u128),
+            content: EventContent { keys: vec![], data: EventData(vec![event_data]) },
+        }],
+        ..Default::default()
+    })
+}
+
+// Writes the headers and state diffs of the blocks without their bodies, like a node that synced
+// without the combined download.
+fn write_headers_and_state_diffs(storage_writer: &mut StorageWriter, blocks: &[TestBlock]) {
+    for block in blocks {
+        let block_header = &block.signed_header.block_header;
+        storage_writer
+            .begin_rw_txn()
+            .unwrap()
+            .append_header(block_header.block_number, block_header)
+            .unwrap()
+            .append_block_signature(block_header.block_number, &block.signed_header.signatures[0])
+            .unwrap()
+            .append_state_diff(block_header.block_number, block.state_diff.clone())
+            .unwrap()
+            .commit()
+            .unwrap();
+    }
+}
+
+fn block_size_in_bytes(block: &TestBlock) -> usize {
+    approximate_size_in_bytes(&block.signed_header.block_header)
+        + approximate_size_in_bytes(&(
+            block.transaction.clone(),
+            Some(block.transaction_output.clone()),
+        ))
+        + approximate_size_in_bytes(&block.state_diff)
+}
+
+fn ok_response<T>(data: T) -> Response<T> {
+    (Ok(DataOrFin(Some(data))), Box::new(|| {}))
+}
+
+fn fin<T>() -> Response<T> {
+    (Ok(DataOrFin(None)), Box::new(|| {}))
+}
+
+fn window_query(start_block_number: u64, limit: u64) -> Query {
+    Query {
+        start_block: BlockHashOrNumber::Number(BlockNumber(start_block_number)),
+        direction: Direction::Forward,
+        limit,
+        step: 1,
+    }
+}
+
+impl TestPeer {
+    // Receives the queries of a window and checks that the same query was sent on each protocol.
+    async fn receive_window_queries(&mut self) -> Query {
+        let (HeaderQuery(query), _priority) = self.header_query_receiver.next().await.unwrap();
+        assert_eq!(
+            self.state_diff_query_receiver.next().await.unwrap(),
+            StateDiffQuery(query.clone())
+        );
+        assert_eq!(
+            self.transaction_query_receiver.next().await.unwrap(),
+            TransactionQuery { query: query.clone(), include_outputs: true }
+        );
+        query
+    }
+
+    // Sends the blocks and a Fin on each protocol. The transaction of the block at
+    // `invalid_block_index` is sent without its output. Reporting the peer of a header or a
+    // transaction raises `reported`.
+    async fn send_blocks(
+        &mut self,
+        blocks: &[TestBlock],
+        invalid_block_index: Option<usize>,
+        reported: Arc<AtomicBool>,
+    ) {
+        for (index, block) in blocks.iter().enumerate() {
+            let is_invalid = invalid_block_index == Some(index);
+            let report_callback = || -> ReportCallback {
+                let reported = reported.clone();
+                Box::new(move || reported.store(true, Ordering::SeqCst))
+            };
+            self.headers_sender
+                .send((Ok(DataOrFin(Some(block.signed_header.clone()))), report_callback()))
+                .await
+                .unwrap();
+            let output = if is_invalid { None } else { Some(block.transaction_output.clone()) };
+            self.transactions_sender
+                .send((Ok(DataOrFin(Some((block.transaction.clone(), output)))), report_callback()))
+                .await
+                .unwrap();
+            self.state_diffs_sender.send(ok_response(block.state_diff.clone())).await.unwrap();
+        }
+        self.headers_sender.send(fin()).await.unwrap();
+        self.transactions_sender.send(fin()).await.unwrap();
+        self.state_diffs_sender.send(fin()).await.unwrap();
+    }
+
+    // Sends the state diffs of the blocks and a Fin.
+    async fn send_state_diffs(&mut self, blocks: &[TestBlock]) {
+        for block in blocks {
+            self.state_diffs_sender.send(ok_response(block.state_diff.clone())).await.unwrap();
+        }
+        self.state_diffs_sender.send(fin()).await.unwrap();
+    }
+
+    // Sends the transactions of the blocks and a Fin.
+    async fn send_transactions(&mut self, blocks: &[TestBlock]) {
+        for block in blocks {
+            self.transactions_sender
+                .send(ok_response((
+                    block.transaction.clone(),
+                    Some(block.transaction_output.clone()),
+                )))
+                .await
+                .unwrap();
+        }
+        self.transactions_sender.send(fin()).await.unwrap();
+    }
+}
+
+async fn run_test(p2p_sync_run: impl Future<Output = ()>, test_future: impl Future<Output = ()>) {
+    tokio::select! {
+        _ = p2p_sync_run => panic!("P2P sync aborted."),
+        _ = test_future => {}
+    }
+}
+
+fn assert_markers(storage_reader: &StorageReader, expected_marker: BlockNumber) {
+    let txn = storage_reader.begin_ro_txn().unwrap();
+    assert_eq!(txn.get_header_marker().unwrap(), expected_marker);
+    assert_eq!(txn.get_body_marker().unwrap(), expected_marker);
+    assert_eq!(txn.get_state_marker().unwrap(), expected_marker);
+}
+
+#[tokio::test]
+async fn combined_download_writes_whole_blocks() {
+    let (p2p_sync, mut peer) = setup(combined_config());
+    let storage_reader = peer.storage_reader.clone();
+    let blocks = create_blocks(NUM_BLOCKS);
+
+    let test_future = async {
+        let query = peer.receive_window_queries().await;
+        assert_eq!(query, window_query(0, STATE_DIFF_QUERY_LENGTH));
+        peer.send_blocks(&blocks, None, Arc::new(AtomicBool::new(false))).await;
+        tokio::time::sleep(SLEEP_DURATION_TO_LET_SYNC_ADVANCE).await;
+
+        assert_markers(&storage_reader, BlockNumber(NUM_BLOCKS));
+        let txn = storage_reader.begin_ro_txn().unwrap();
+        for block in &blocks {
+            let block_number = block.signed_header.block_header.block_number;
+            assert_eq!(
+                txn.get_block_header(block_number).unwrap().unwrap(),
+                block.signed_header.block_header
+            );
+            assert_eq!(
+                txn.get_block_transactions(block_number).unwrap().unwrap(),
+                vec![block.transaction.clone()]
+            );
+            let transaction_hash = get_transaction_hash(
+                &block.transaction,
+                &ChainId::Mainnet,
+                &TransactionOptions { only_query: false },
+            )
+            .unwrap();
+            assert_eq!(
+                txn.get_block_transaction_hashes(block_number).unwrap().unwrap(),
+                vec![transaction_hash]
+            );
+            assert_eq!(txn.get_state_diff(block_number).unwrap().unwrap(), block.state_diff);
+        }
+    };
+    run_test(async { p2p_sync.run().await.unwrap() }, test_future).await;
+}
+
+#[tokio::test]
+async fn invalid_block_is_downloaded_again() {
+    let (p2p_sync, mut peer) = setup(combined_config());
+    let storage_reader = peer.storage_reader.clone();
+    let blocks = create_blocks(NUM_BLOCKS);
+
+    let test_future = async {
+        peer.receive_window_queries().await;
+        let reported = Arc::new(AtomicBool::new(false));
+        peer.send_blocks(&blocks, Some(1), reported.clone()).await;
+
+        // The blocks before the invalid block are written, and the rest are downloaded again.
+        let query = peer.receive_window_queries().await;
+        assert_eq!(query, window_query(1, STATE_DIFF_QUERY_LENGTH));
+        assert!(reported.load(Ordering::SeqCst));
+        assert_markers(&storage_reader, BlockNumber(1));
+
+        peer.send_blocks(&blocks[1..], None, Arc::new(AtomicBool::new(false))).await;
+        tokio::time::sleep(SLEEP_DURATION_TO_LET_SYNC_ADVANCE).await;
+        assert_markers(&storage_reader, BlockNumber(NUM_BLOCKS));
+    };
+    run_test(async { p2p_sync.run().await.unwrap() }, test_future).await;
+}
+
+#[tokio::test]
+async fn window_is_shrunk_when_it_exceeds_the_memory_limit() {
+    let blocks = create_blocks(NUM_BLOCKS);
+    let max_block_bytes = blocks.iter().map(block_size_in_bytes).max().unwrap();
+    let (p2p_sync, mut peer) = setup(P2PSyncConfig {
+        combined_download_max_window_bytes: 2 * max_block_bytes,
+        ..combined_config()
+    });
+    let storage_reader = peer.storage_reader.clone();
+
+    let test_future = async {
+        peer.receive_window_queries().await;
+        peer.send_blocks(&blocks, None, Arc::new(AtomicBool::new(false))).await;
+
+        // The window of 3 blocks doesn't fit, so it's dropped and downloaded in smaller windows.
+        let query = peer.receive_window_queries().await;
+        assert_eq!(query, window_query(0, 1));
+        assert_markers(&storage_reader, BlockNumber(0));
+        peer.send_blocks(&blocks[..1], None, Arc::new(AtomicBool::new(false))).await;
+
+        // The next window is as long as the memory limit allows for blocks of this size.
+        let query = peer.receive_window_queries().await;
+        assert_eq!(query, window_query(1, 2));
+        assert_markers(&storage_reader, BlockNumber(1));
+    };
+    run_test(async { p2p_sync.run().await.unwrap() }, test_future).await;
+}
+
+#[tokio::test]
+async fn large_block_is_downloaded_in_parts() {
+    let blocks = create_blocks(NUM_BLOCKS);
+    let (p2p_sync, mut peer) = setup(P2PSyncConfig {
+        combined_download_max_block_bytes: block_size_in_bytes(&blocks[0]) - 1,
+        ..combined_config()
+    });
+    let storage_reader = peer.storage_reader.clone();
+
+    let test_future = async {
+        peer.receive_window_queries().await;
+        peer.send_blocks(&blocks, None, Arc::new(AtomicBool::new(false))).await;
+
+        // Only the header of the large block is written, and its state diff and transactions are
+        // downloaded in sessions of their own.
+        let StateDiffQuery(query) = peer.state_diff_query_receiver.next().await.unwrap();
+        assert_eq!(query, window_query(0, 1));
+        assert_eq!(
+            storage_reader.begin_ro_txn().unwrap().get_header_marker().unwrap(),
+            BlockNumber(1)
+        );
+        peer.send_state_diffs(&blocks[..1]).await;
+
+        let TransactionQuery { query, .. } = peer.transaction_query_receiver.next().await.unwrap();
+        assert_eq!(query, window_query(0, 1));
+        peer.send_transactions(&blocks[..1]).await;
+
+        // The next blocks are downloaded together again.
+        let query = peer.receive_window_queries().await;
+        assert_eq!(query, window_query(1, STATE_DIFF_QUERY_LENGTH));
+        assert_markers(&storage_reader, BlockNumber(1));
+        assert_eq!(
+            storage_reader.begin_ro_txn().unwrap().get_block_transactions(BlockNumber(0)).unwrap(),
+            Some(vec![blocks[0].transaction.clone()])
+        );
+    };
+    run_test(async { p2p_sync.run().await.unwrap() }, test_future).await;
+}
+
+#[tokio::test]
+async fn bodies_behind_the_headers_are_caught_up() {
+    let blocks = create_blocks(NUM_BLOCKS);
+    let (p2p_sync, mut peer) = setup_with_storage(combined_config(), |storage_writer| {
+        write_headers_and_state_diffs(storage_writer, &blocks)
+    });
+    let storage_reader = peer.storage_reader.clone();
+
+    let test_future = async {
+        let TransactionQuery { query, .. } = peer.transaction_query_receiver.next().await.unwrap();
+        assert_eq!(query, window_query(0, NUM_BLOCKS));
+        peer.send_transactions(&blocks).await;
+
+        // Once the bodies caught up with the headers, the blocks are downloaded together.
+        let query = peer.receive_window_queries().await;
+        assert_eq!(query, window_query(NUM_BLOCKS, STATE_DIFF_QUERY_LENGTH));
+        assert_markers(&storage_reader, BlockNumber(NUM_BLOCKS));
+    };
+    run_test(async { p2p_sync.run().await.unwrap() }, test_future).await;
+}
+
+#[tokio::test]
+async fn transactions_with_a_tampered_event_are_downloaded_again() {
+    const TAMPERED_BLOCK_INDEX: usize = 1;
+    let mut blocks = create_blocks(NUM_BLOCKS);
+    for block in &mut blocks {
+        add_event_and_body_commitments(block);
+    }
+    let (p2p_sync, mut peer) = setup_with_storage(combined_config(), |storage_writer| {
+        write_headers_and_state_diffs(storage_writer, &blocks)
+    });
+    let storage_reader = peer.storage_reader.clone();
+
+    let test_future = async {
+        let TransactionQuery { query, .. } = peer.transaction_query_receiver.next().await.unwrap();
+        assert_eq!(query, window_query(0, NUM_BLOCKS));
+
+        // The data of a single event is tampered with, and the rest of the data is valid.
+        let reported = Arc::new(AtomicBool::new(false));
+        for (index, block) in blocks.iter().enumerate() {
+            let response = if index == TAMPERED_BLOCK_INDEX {
+                let reported = reported.clone();
+                let output = invoke_output_with_event(Felt::from(u64::MAX));
+                (
+                    Ok(DataOrFin(Some((block.transaction.clone(), Some(output))))),
+                    Box::new(move || reported.store(true, Ordering::SeqCst)) as ReportCallback,
+                )
+            } else {
+                ok_response((block.transaction.clone(), Some(block.transaction_output.clone())))
+            };
+            peer.transactions_sender.send(response).await.unwrap();
+        }
+        peer.transactions_sender.send(fin()).await.unwrap();
+
+        // The bodies before the tampered block are written, and the rest are downloaded again
+        // after the peer that sent the tampered event was reported.
+        let TransactionQuery { query, .. } = peer.transaction_query_receiver.next().await.unwrap();
+        let tampered_block_number = u64::try_from(TAMPERED_BLOCK_INDEX).unwrap();
+        assert_eq!(query, window_query(tampered_block_number, NUM_BLOCKS - tampered_block_number));
+        assert!(reported.load(Ordering::SeqCst));
+        assert_eq!(
+            storage_reader.begin_ro_txn().unwrap().get_body_marker().unwrap(),
+            BlockNumber(tampered_block_number)
+        );
+
+        peer.send_transactions(&blocks[TAMPERED_BLOCK_INDEX..]).await;
+        tokio::time::sleep(SLEEP_DURATION_TO_LET_SYNC_ADVANCE).await;
+        assert_markers(&storage_reader, BlockNumber(NUM_BLOCKS));
+        let txn = storage_reader.begin_ro_txn().unwrap();
+        for block in &blocks {
+            assert_eq!(
+                txn.get_block_transaction_outputs(block.signed_header.block_header.block_number)
+                    .unwrap()
+                    .unwrap(),
+                vec![block.transaction_output.clone()]
+            );
+        }
+    };
+    run_test(async { p2p_sync.run().await.unwrap() }, test_future).await;
+}
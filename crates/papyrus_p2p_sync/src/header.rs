@@ -3,11 +3,15 @@ use std::marker::PhantomData;
 use futures::channel::mpsc::SendError;
 use futures::future::BoxFuture;
 use futures::{FutureExt, Sink, Stream, StreamExt};
+use papyrus_common::BlockHashAndNumber;
+use papyrus_network::network_manager::QueryPriority;
 use papyrus_protobuf::sync::{Query, SignedBlockHeader};
+use papyrus_storage::db::TransactionKind;
 use papyrus_storage::header::{HeaderStorageReader, HeaderStorageWriter};
-use papyrus_storage::{StorageError, StorageReader, StorageWriter};
+use papyrus_storage::{StorageError, StorageReader, StorageTxn, StorageWriter};
 use starknet_api::block::BlockNumber;
 
+use crate::response_validator::ValidatedResponseReceiver;
 use crate::stream_factory::{BlockData, BlockNumberLimit, DataStreamFactory};
 use crate::{P2PSyncError, Response, ALLOWED_SIGNATURES_LENGTH, NETWORK_DATA_TIMEOUT};
 
@@ -31,6 +35,13 @@ impl BlockData for SignedBlockHeader {
             )?
             .commit()
     }
+
+    fn proven_block(&self) -> Option<BlockHashAndNumber> {
+        Some(BlockHashAndNumber {
+            block_hash: self.block_header.block_hash,
+            block_number: self.block_header.block_number,
+        })
+    }
 }
 
 pub(crate) struct HeaderStreamFactory<QuerySender, DataReceiver>(
@@ -40,7 +51,7 @@ pub(crate) struct HeaderStreamFactory<QuerySender, DataReceiver>(
 impl<QuerySender, DataReceiver> DataStreamFactory<QuerySender, DataReceiver, SignedBlockHeader>
     for HeaderStreamFactory<QuerySender, DataReceiver>
 where
-    QuerySender: Sink<Query, Error = SendError> + Unpin + Send + 'static,
+    QuerySender: Sink<(Query, QueryPriority), Error = SendError> + Unpin + Send + 'static,
     DataReceiver: Stream<Item = Response<SignedBlockHeader>> + Unpin + Send + 'static,
 {
     type Output = SignedBlockHeader;
@@ -49,9 +60,9 @@ where
     const BLOCK_NUMBER_LIMIT: BlockNumberLimit = BlockNumberLimit::Unlimited;
 
     fn parse_data_for_block<'a>(
-        signed_headers_receiver: &'a mut DataReceiver,
+        signed_headers_receiver: &'a mut ValidatedResponseReceiver<DataReceiver>,
         block_number: BlockNumber,
-        _storage_reader: &'a StorageReader,
+        storage_reader: &'a StorageReader,
     ) -> BoxFuture<'a, Result<Option<Self::Output>, P2PSyncError>> {
         async move {
             // TODO(shahak): Use the report callback.
@@ -64,19 +75,8 @@ where
             let Some(signed_block_header) = maybe_signed_header?.0 else {
                 return Ok(None);
             };
-            // TODO(shahak): Check that parent_hash is the same as the previous block's hash
-            // and handle reverts.
-            if block_number != signed_block_header.block_header.block_number {
-                return Err(P2PSyncError::HeadersUnordered {
-                    expected_block_number: block_number,
-                    actual_block_number: signed_block_header.block_header.block_number,
-                });
-            }
-            if signed_block_header.signatures.len() != ALLOWED_SIGNATURES_LENGTH {
-                return Err(P2PSyncError::WrongSignaturesLength {
-                    signatures: signed_block_header.signatures,
-                });
-            }
+            // TODO(shahak): Handle reverts.
+            validate_header(&storage_reader.begin_ro_txn()?, &signed_block_header, block_number)?;
             Ok(Some(signed_block_header))
         }
         .boxed()
@@ -85,4 +85,49 @@ where
     fn get_start_block_number(storage_reader: &StorageReader) -> Result<BlockNumber, StorageError> {
         storage_reader.begin_ro_txn()?.get_header_marker()
     }
+
+    // Following the tip is what keeps the node up to date, so it's preferred over backfilling
+    // historical headers.
+    fn query_priority(is_following_tip: bool) -> QueryPriority {
+        if is_following_tip {
+            QueryPriority::High
+        } else {
+            QueryPriority::Low
+        }
+    }
+}
+
+/// Validates a header that should be written to the storage as the block `block_number`. The
+/// header of the previous block should already be in the storage.
+pub(crate) fn validate_header<Mode: TransactionKind>(
+    txn: &StorageTxn<'_, Mode>,
+    signed_block_header: &SignedBlockHeader,
+    block_number: BlockNumber,
+) -> Result<(), P2PSyncError> {
+    let block_header = &signed_block_header.block_header;
+    if block_number != block_header.block_number {
+        return Err(P2PSyncError::HeadersUnordered {
+            expected_block_number: block_number,
+            actual_block_number: block_header.block_number,
+        });
+    }
+    if signed_block_header.signatures.len() != ALLOWED_SIGNATURES_LENGTH {
+        return Err(P2PSyncError::WrongSignaturesLength {
+            signatures: signed_block_header.signatures.clone(),
+        });
+    }
+    if let Some(parent_block_number) = block_number.0.checked_sub(1).map(BlockNumber) {
+        let parent_hash = txn
+            .get_block_header(parent_block_number)?
+            .expect("A header with number lower than the header marker is missing")
+            .block_hash;
+        if parent_hash != block_header.parent_hash {
+            return Err(P2PSyncError::ParentHashMismatch {
+                block_number,
+                expected_parent_hash: parent_hash,
+                actual_parent_hash: block_header.parent_hash,
+            });
+        }
+    }
+    Ok(())
 }
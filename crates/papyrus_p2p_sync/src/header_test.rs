@@ -1,4 +1,10 @@
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+
 use futures::{SinkExt, StreamExt};
+use papyrus_common::BlockHashAndNumber;
+use papyrus_network::network_manager::{QueryPriority, SqmrQueryError, SqmrResponseError};
 use papyrus_protobuf::sync::{
     BlockHashOrNumber,
     DataOrFin,
@@ -13,12 +19,14 @@ use tokio::time::timeout;
 
 use crate::test_utils::{
     create_block_hashes_and_signatures,
+    get_parent_hash,
     setup,
     TestArgs,
     HEADER_QUERY_LENGTH,
     SLEEP_DURATION_TO_LET_SYNC_ADVANCE,
     TIMEOUT_FOR_NEW_QUERY_AFTER_PARTIAL_RESPONSE,
 };
+use crate::Response;
 
 #[tokio::test]
 async fn signed_headers_basic_flow() {
@@ -32,6 +40,8 @@ async fn signed_headers_basic_flow() {
         // The test will fail if we drop these
         state_diff_query_receiver: _state_diff_query_receiver,
         state_diffs_sender: _state_diffs_sender,
+        shared_highest_block,
+        header_marker_receiver,
     } = setup();
     let block_hashes_and_signatures =
         create_block_hashes_and_signatures((NUM_QUERIES * HEADER_QUERY_LENGTH).try_into().unwrap());
@@ -43,7 +53,7 @@ async fn signed_headers_basic_flow() {
             let end_block_number = (query_index + 1) * HEADER_QUERY_LENGTH;
 
             // Receive query and validate it.
-            let query = header_query_receiver.next().await.unwrap();
+            let (query, _priority) = header_query_receiver.next().await.unwrap();
             assert_eq!(
                 query,
                 HeaderQuery(Query {
@@ -67,10 +77,12 @@ async fn signed_headers_basic_flow() {
                             block_header: BlockHeader {
                                 block_number: BlockNumber(i.try_into().unwrap()),
                                 block_hash: *block_hash,
+                                parent_hash: get_parent_hash(&block_hashes_and_signatures, i),
                                 state_diff_length: Some(0),
                                 ..Default::default()
                             },
                             signatures: vec![*block_signature],
+                            data_availability: None,
                         })))),
                         Box::new(|| {}),
                     ))
@@ -91,6 +103,11 @@ async fn signed_headers_basic_flow() {
                 let actual_block_signature =
                     txn.get_block_signature(block_number).unwrap().unwrap();
                 assert_eq!(*block_signature, actual_block_signature);
+                assert_eq!(
+                    *shared_highest_block.read().await,
+                    Some(BlockHashAndNumber { block_hash: *block_hash, block_number })
+                );
+                assert_eq!(*header_marker_receiver.borrow(), block_number.unchecked_next());
             }
             headers_sender.send((Ok(DataOrFin(None)), Box::new(|| {}))).await.unwrap();
         }
@@ -123,19 +140,23 @@ async fn sync_sends_new_header_query_if_it_got_partial_responses() {
 
     // Create a future that will receive a query, send partial responses and receive the next query.
     let parse_queries_future = async move {
-        let _query = header_query_receiver.next().await.unwrap();
+        let (_query, priority) = header_query_receiver.next().await.unwrap();
+        // The sync doesn't know yet if it's behind the tip.
+        assert_eq!(priority, QueryPriority::Low);
 
-        for (i, (block_hash, signature)) in block_hashes_and_signatures.into_iter().enumerate() {
+        for (i, (block_hash, signature)) in block_hashes_and_signatures.iter().enumerate() {
             headers_sender
                 .send((
                     Ok(DataOrFin(Some(SignedBlockHeader {
                         block_header: BlockHeader {
                             block_number: BlockNumber(i.try_into().unwrap()),
-                            block_hash,
+                            block_hash: *block_hash,
+                            parent_hash: get_parent_hash(&block_hashes_and_signatures, i),
                             state_diff_length: Some(0),
                             ..Default::default()
                         },
-                        signatures: vec![signature],
+                        signatures: vec![*signature],
+                        data_availability: None,
                     }))),
                     Box::new(|| {}),
                 ))
@@ -144,8 +165,27 @@ async fn sync_sends_new_header_query_if_it_got_partial_responses() {
         }
         headers_sender.send((Ok(DataOrFin(None)), Box::new(|| {}))).await.unwrap();
 
+        // The peer may have ended the response because of its response limits, so the rest of the
+        // blocks are queried right away.
+        let (query, priority) =
+            timeout(SLEEP_DURATION_TO_LET_SYNC_ADVANCE, header_query_receiver.next())
+                .await
+                .unwrap()
+                .unwrap();
+        assert_eq!(
+            query,
+            HeaderQuery(Query {
+                start_block: BlockHashOrNumber::Number(BlockNumber(NUM_ACTUAL_RESPONSES.into())),
+                direction: Direction::Forward,
+                limit: HEADER_QUERY_LENGTH - u64::from(NUM_ACTUAL_RESPONSES),
+                step: 1,
+            })
+        );
+        assert_eq!(priority, QueryPriority::Low);
+        headers_sender.send((Ok(DataOrFin(None)), Box::new(|| {}))).await.unwrap();
+
         // First unwrap is for the timeout. Second unwrap is for the Option returned from Stream.
-        let query =
+        let (query, priority) =
             timeout(TIMEOUT_FOR_NEW_QUERY_AFTER_PARTIAL_RESPONSE, header_query_receiver.next())
                 .await
                 .unwrap()
@@ -160,6 +200,327 @@ async fn sync_sends_new_header_query_if_it_got_partial_responses() {
                 step: 1,
             })
         );
+        // A response without data means the sync reached the tip.
+        assert_eq!(priority, QueryPriority::High);
+    };
+
+    tokio::select! {
+        sync_result = p2p_sync.run() => {
+            sync_result.unwrap();
+            panic!("P2P sync aborted with no failure.");
+        }
+        _ = parse_queries_future => {}
+    }
+}
+
+#[tokio::test]
+async fn sync_follows_tip_with_open_ended_header_queries() {
+    const NUM_NEW_BLOCKS: u8 = 2;
+
+    let TestArgs {
+        mut p2p_sync,
+        storage_reader,
+        mut header_query_receiver,
+        mut headers_sender,
+        // The test will fail if we drop these
+        state_diff_query_receiver: _state_diff_query_receiver,
+        state_diffs_sender: _state_diffs_sender,
+        ..
+    } = setup();
+    p2p_sync.config.follow_tip = true;
+    let block_hashes_and_signatures = create_block_hashes_and_signatures(NUM_NEW_BLOCKS);
+    let open_ended_query = |start_block_number: u64| {
+        HeaderQuery(Query {
+            start_block: BlockHashOrNumber::Number(BlockNumber(start_block_number)),
+            direction: Direction::Forward,
+            limit: Query::OPEN_ENDED_LIMIT,
+            step: 1,
+        })
+    };
+
+    let parse_queries_future = async move {
+        // The sync doesn't know yet that it reached the tip, so it sends a bounded query.
+        let (query, _priority) = header_query_receiver.next().await.unwrap();
+        assert_eq!(query.0.limit, HEADER_QUERY_LENGTH);
+        headers_sender.send((Ok(DataOrFin(None)), Box::new(|| {}))).await.unwrap();
+
+        let (query, priority) =
+            timeout(TIMEOUT_FOR_NEW_QUERY_AFTER_PARTIAL_RESPONSE, header_query_receiver.next())
+                .await
+                .unwrap()
+                .unwrap();
+        assert_eq!(query, open_ended_query(0));
+        assert_eq!(priority, QueryPriority::High);
+
+        // The peer sends the new blocks as it gets them, and ends the response once it didn't get
+        // a new block for a while.
+        for (i, (block_hash, signature)) in block_hashes_and_signatures.iter().enumerate() {
+            headers_sender
+                .send((
+                    Ok(DataOrFin(Some(SignedBlockHeader {
+                        block_header: BlockHeader {
+                            block_number: BlockNumber(i.try_into().unwrap()),
+                            block_hash: *block_hash,
+                            parent_hash: get_parent_hash(&block_hashes_and_signatures, i),
+                            state_diff_length: Some(0),
+                            ..Default::default()
+                        },
+                        signatures: vec![*signature],
+                        data_availability: None,
+                    }))),
+                    Box::new(|| {}),
+                ))
+                .await
+                .unwrap();
+            tokio::time::sleep(SLEEP_DURATION_TO_LET_SYNC_ADVANCE).await;
+            assert_eq!(
+                storage_reader.begin_ro_txn().unwrap().get_header_marker().unwrap(),
+                BlockNumber((i + 1).try_into().unwrap())
+            );
+        }
+        headers_sender.send((Ok(DataOrFin(None)), Box::new(|| {}))).await.unwrap();
+
+        // The sync keeps following the tip from the next block right away.
+        let (query, _priority) =
+            timeout(SLEEP_DURATION_TO_LET_SYNC_ADVANCE, header_query_receiver.next())
+                .await
+                .unwrap()
+                .unwrap();
+        assert_eq!(query, open_ended_query(NUM_NEW_BLOCKS.into()));
+
+        // A peer that doesn't follow the tip ends the response right away. The next query is sent
+        // only after the wait period, so that such peers aren't flooded with queries.
+        headers_sender.send((Ok(DataOrFin(None)), Box::new(|| {}))).await.unwrap();
+        assert!(
+            timeout(SLEEP_DURATION_TO_LET_SYNC_ADVANCE, header_query_receiver.next())
+                .await
+                .is_err()
+        );
+        let (query, _priority) =
+            timeout(TIMEOUT_FOR_NEW_QUERY_AFTER_PARTIAL_RESPONSE, header_query_receiver.next())
+                .await
+                .unwrap()
+                .unwrap();
+        assert_eq!(query, open_ended_query(NUM_NEW_BLOCKS.into()));
+    };
+
+    tokio::select! {
+        sync_result = p2p_sync.run() => {
+            sync_result.unwrap();
+            panic!("P2P sync aborted with no failure.");
+        }
+        _ = parse_queries_future => {}
+    }
+}
+
+// The test runs with a paused clock so that it doesn't wait for the network data timeout.
+#[tokio::test(start_paused = true)]
+async fn sync_resumes_header_query_after_session_failure() {
+    const NUM_ACTUAL_RESPONSES: u8 = 2;
+    assert!(u64::from(NUM_ACTUAL_RESPONSES) < HEADER_QUERY_LENGTH);
+
+    let TestArgs {
+        p2p_sync,
+        mut header_query_receiver,
+        mut headers_sender,
+        // The test will fail if we drop these
+        state_diff_query_receiver: _state_diff_query_receiver,
+        state_diffs_sender: _state_diffs_sender,
+        ..
+    } = setup();
+    let block_hashes_and_signatures = create_block_hashes_and_signatures(NUM_ACTUAL_RESPONSES);
+
+    // Create a future that will receive a query, send part of the responses and then stop
+    // responding as if the peer was killed, and receive the next query.
+    let parse_queries_future = async move {
+        let (_query, _priority) = header_query_receiver.next().await.unwrap();
+
+        for (i, (block_hash, signature)) in block_hashes_and_signatures.iter().enumerate() {
+            headers_sender
+                .send((
+                    Ok(DataOrFin(Some(SignedBlockHeader {
+                        block_header: BlockHeader {
+                            block_number: BlockNumber(i.try_into().unwrap()),
+                            block_hash: *block_hash,
+                            parent_hash: get_parent_hash(&block_hashes_and_signatures, i),
+                            state_diff_length: Some(0),
+                            ..Default::default()
+                        },
+                        signatures: vec![*signature],
+                        data_availability: None,
+                    }))),
+                    Box::new(|| {}),
+                ))
+                .await
+                .unwrap();
+        }
+
+        // The next query continues the original query from the first block that wasn't received.
+        let (query, _priority) = header_query_receiver.next().await.unwrap();
+        assert_eq!(
+            query,
+            HeaderQuery(Query {
+                start_block: BlockHashOrNumber::Number(BlockNumber(NUM_ACTUAL_RESPONSES.into())),
+                direction: Direction::Forward,
+                limit: HEADER_QUERY_LENGTH - u64::from(NUM_ACTUAL_RESPONSES),
+                step: 1,
+            })
+        );
+    };
+
+    tokio::select! {
+        sync_result = p2p_sync.run() => {
+            sync_result.unwrap();
+            panic!("P2P sync aborted with no failure.");
+        }
+        _ = parse_queries_future => {}
+    }
+}
+
+// The only peer was disconnected in the middle of the query, and the network failed to connect to
+// a peer for the query that resumed it.
+#[tokio::test]
+async fn sync_resumes_header_query_after_no_peers_failure() {
+    const NUM_ACTUAL_RESPONSES: u8 = 2;
+    assert!(u64::from(NUM_ACTUAL_RESPONSES) < HEADER_QUERY_LENGTH);
+
+    let TestArgs {
+        p2p_sync,
+        mut header_query_receiver,
+        mut headers_sender,
+        // The test will fail if we drop these
+        state_diff_query_receiver: _state_diff_query_receiver,
+        state_diffs_sender: _state_diffs_sender,
+        ..
+    } = setup();
+    let block_hashes_and_signatures = create_block_hashes_and_signatures(NUM_ACTUAL_RESPONSES);
+    let no_peers_failure = || -> Response<SignedBlockHeader> {
+        (
+            Err(SqmrResponseError::Query(SqmrQueryError::NoPeers {
+                dial_deadline: Duration::from_secs(60),
+            })),
+            Box::new(|| {}),
+        )
+    };
+
+    let parse_queries_future = async move {
+        let (_query, _priority) = header_query_receiver.next().await.unwrap();
+
+        for (i, (block_hash, signature)) in block_hashes_and_signatures.iter().enumerate() {
+            headers_sender
+                .send((
+                    Ok(DataOrFin(Some(SignedBlockHeader {
+                        block_header: BlockHeader {
+                            block_number: BlockNumber(i.try_into().unwrap()),
+                            block_hash: *block_hash,
+                            parent_hash: get_parent_hash(&block_hashes_and_signatures, i),
+                            state_diff_length: Some(0),
+                            ..Default::default()
+                        },
+                        signatures: vec![*signature],
+                        data_availability: None,
+                    }))),
+                    Box::new(|| {}),
+                ))
+                .await
+                .unwrap();
+        }
+        headers_sender.send(no_peers_failure()).await.unwrap();
+
+        // The query is resumed from the first block that wasn't received without waiting for the
+        // network data timeout, and again after each failure.
+        for _ in 0..2 {
+            let (query, _priority) =
+                timeout(TIMEOUT_FOR_NEW_QUERY_AFTER_PARTIAL_RESPONSE, header_query_receiver.next())
+                    .await
+                    .unwrap()
+                    .unwrap();
+            assert_eq!(
+                query,
+                HeaderQuery(Query {
+                    start_block: BlockHashOrNumber::Number(BlockNumber(
+                        NUM_ACTUAL_RESPONSES.into()
+                    )),
+                    direction: Direction::Forward,
+                    limit: HEADER_QUERY_LENGTH - u64::from(NUM_ACTUAL_RESPONSES),
+                    step: 1,
+                })
+            );
+            headers_sender.send(no_peers_failure()).await.unwrap();
+        }
+    };
+
+    tokio::select! {
+        sync_result = p2p_sync.run() => {
+            sync_result.unwrap();
+            panic!("P2P sync aborted with no failure.");
+        }
+        _ = parse_queries_future => {}
+    }
+}
+
+#[tokio::test]
+async fn sync_reports_out_of_order_header_and_sends_new_query() {
+    let TestArgs {
+        p2p_sync,
+        mut header_query_receiver,
+        mut headers_sender,
+        // The test will fail if we drop these
+        state_diff_query_receiver: _state_diff_query_receiver,
+        state_diffs_sender: _state_diffs_sender,
+        shared_highest_block,
+        ..
+    } = setup();
+    let block_hashes_and_signatures = create_block_hashes_and_signatures(3);
+    let first_block = BlockHashAndNumber {
+        block_hash: block_hashes_and_signatures[0].0,
+        block_number: BlockNumber(0),
+    };
+    let reported = Arc::new(AtomicBool::new(false));
+
+    // Create a future that will receive a query, send the first header and then skip a header, and
+    // receive the next query.
+    let reported_clone = reported.clone();
+    let parse_queries_future = async move {
+        let (_query, _priority) = header_query_receiver.next().await.unwrap();
+
+        for i in [0, 2] {
+            let (block_hash, signature) = block_hashes_and_signatures[i];
+            let reported = reported_clone.clone();
+            headers_sender
+                .send((
+                    Ok(DataOrFin(Some(SignedBlockHeader {
+                        block_header: BlockHeader {
+                            block_number: BlockNumber(i.try_into().unwrap()),
+                            block_hash,
+                            parent_hash: get_parent_hash(&block_hashes_and_signatures, i),
+                            state_diff_length: Some(0),
+                            ..Default::default()
+                        },
+                        signatures: vec![signature],
+                        data_availability: None,
+                    }))),
+                    Box::new(move || reported.store(true, Ordering::SeqCst)),
+                ))
+                .await
+                .unwrap();
+        }
+
+        // The out of order header is discarded and the sync asks for it again.
+        let (query, _priority) =
+            timeout(TIMEOUT_FOR_NEW_QUERY_AFTER_PARTIAL_RESPONSE, header_query_receiver.next())
+                .await
+                .unwrap()
+                .unwrap();
+        assert_eq!(
+            query,
+            HeaderQuery(Query {
+                start_block: BlockHashOrNumber::Number(BlockNumber(1)),
+                direction: Direction::Forward,
+                limit: HEADER_QUERY_LENGTH,
+                step: 1,
+            })
+        );
     };
 
     tokio::select! {
@@ -169,6 +530,9 @@ async fn sync_sends_new_header_query_if_it_got_partial_responses() {
         }
         _ = parse_queries_future => {}
     }
+    assert!(reported.load(Ordering::SeqCst));
+    // The out of order header doesn't raise the highest block.
+    assert_eq!(*shared_highest_block.read().await, Some(first_block));
 }
 
 // TODO(shahak): Add negative tests.
@@ -1,6 +1,36 @@
+mod base_layer_checkpoint;
+#[cfg(test)]
+mod base_layer_checkpoint_test;
+mod block_injection;
+#[cfg(test)]
+mod block_injection_test;
+pub mod class_verification;
+#[cfg(test)]
+mod class_verification_test;
+mod combined_download;
+#[cfg(test)]
+mod combined_download_test;
 mod header;
 #[cfg(test)]
 mod header_test;
+mod query_client;
+#[cfg(test)]
+mod query_client_test;
+pub mod replay;
+#[cfg(test)]
+mod replay_test;
+mod response_validator;
+#[cfg(test)]
+mod response_validator_test;
+mod shadow;
+#[cfg(test)]
+mod shadow_test;
+mod sharded_stream;
+#[cfg(test)]
+mod sharded_stream_test;
+pub mod snapshot;
+#[cfg(test)]
+mod snapshot_test;
 mod state_diff;
 #[cfg(test)]
 mod state_diff_test;
@@ -9,40 +39,81 @@ mod stream_factory;
 mod test_utils;
 
 use std::collections::BTreeMap;
-use std::time::Duration;
+use std::path::PathBuf;
+use std::pin::Pin;
+use std::sync::Arc;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
 
-use futures::channel::mpsc::SendError;
+use futures::channel::mpsc::{SendError, UnboundedSender};
 use futures::future::ready;
+use futures::stream::BoxStream;
 use futures::{Sink, SinkExt, Stream};
+use papyrus_common::block_hash::BlockHashError;
+use papyrus_common::BlockHashAndNumber;
 use papyrus_config::converters::deserialize_seconds_to_duration;
 use papyrus_config::dumping::{ser_optional_param, ser_param, SerializeConfig};
 use papyrus_config::{ParamPath, ParamPrivacyInput, SerializedParam};
-use papyrus_network::network_manager::ReportCallback;
+use papyrus_network::network_manager::{
+    PeerManagerCommand,
+    QueryPriority,
+    ReportCallback,
+    SqmrQueryError,
+    SqmrResponseError,
+};
 use papyrus_protobuf::converters::ProtobufConversionError;
-use papyrus_protobuf::sync::{DataOrFin, HeaderQuery, SignedBlockHeader, StateDiffQuery};
+use papyrus_protobuf::sync::{
+    DataOrFin,
+    HeaderQuery,
+    SignedBlockHeader,
+    StateDiffQuery,
+    TransactionQuery,
+};
 use papyrus_storage::{StorageError, StorageReader, StorageWriter};
 use serde::{Deserialize, Serialize};
-use starknet_api::block::{BlockNumber, BlockSignature};
+use starknet_api::block::{BlockHash, BlockNumber, BlockSignature, BlockTimestamp};
+use starknet_api::core::ChainId;
 use starknet_api::state::ThinStateDiff;
+use starknet_api::transaction::{Transaction, TransactionOutput};
+use starknet_api::StarknetApiError;
+use tokio::sync::{watch, RwLock};
 use tokio_stream::StreamExt;
-use tracing::instrument;
+use tracing::{instrument, warn};
 
+pub use crate::base_layer_checkpoint::BaseLayerCheckpointSource;
+use crate::base_layer_checkpoint::{check_proved_block, stream_proved_blocks};
+pub use crate::block_injection::inject_block;
+use crate::combined_download::create_combined_stream;
 use crate::header::HeaderStreamFactory;
+pub use crate::query_client::QueryClient;
+use crate::replay::ReplayError;
+pub use crate::response_validator::{ResponseViolation, ValidatedResponse};
+pub use crate::shadow::ShadowSync;
+use crate::sharded_stream::create_sharded_stream;
+pub use crate::state_diff::unite_and_validate_state_diff_parts;
 use crate::state_diff::StateDiffStreamFactory;
-use crate::stream_factory::DataStreamFactory;
+use crate::stream_factory::{BlockData, DataStreamFactory};
 
 const STEP: u64 = 1;
 const ALLOWED_SIGNATURES_LENGTH: usize = 1;
 
 const NETWORK_DATA_TIMEOUT: Duration = Duration::from_secs(300);
 
-#[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
 pub struct P2PSyncConfig {
     pub num_headers_per_query: u64,
     pub num_block_state_diffs_per_query: u64,
     #[serde(deserialize_with = "deserialize_seconds_to_duration")]
     pub wait_period_for_new_data: Duration,
     pub stop_sync_at_block_number: Option<BlockNumber>,
+    pub max_parallel_state_diff_sessions: usize,
+    pub revert_on_base_layer_mismatch: bool,
+    pub follow_tip: bool,
+    pub shadow: bool,
+    pub record_path: Option<PathBuf>,
+    pub replay_path: Option<PathBuf>,
+    pub combined_block_download: bool,
+    pub combined_download_max_window_bytes: usize,
+    pub combined_download_max_block_bytes: usize,
 }
 
 impl SerializeConfig for P2PSyncConfig {
@@ -67,6 +138,65 @@ impl SerializeConfig for P2PSyncConfig {
                  new query",
                 ParamPrivacyInput::Public,
             ),
+            ser_param(
+                "max_parallel_state_diff_sessions",
+                &self.max_parallel_state_diff_sessions,
+                "The maximum number of queries for state diffs of different blocks that are sent \
+                 at once, each to a different peer. The blocks are split into queries of \
+                 num_block_state_diffs_per_query blocks.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "revert_on_base_layer_mismatch",
+                &self.revert_on_base_layer_mismatch,
+                "If a block proved on the base layer doesn't match the synced block of the same \
+                 height, revert the synced blocks that weren't verified against the base layer \
+                 before halting the sync, so that they're synced again when the node restarts. \
+                 Otherwise, the sync halts without reverting.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "follow_tip",
+                &self.follow_tip,
+                "Once the headers sync reaches the tip of the chain, ask peers for all the headers \
+                 from the tip on, so that they send each new header as soon as they get it. \
+                 Otherwise, peers are queried for new headers every wait_period_for_new_data.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "shadow",
+                &self.shadow,
+                "Run alongside the central sync, which must be enabled too, without writing to the \
+                 storage. The headers and state diffs the central sync writes are downloaded from \
+                 peers as well and compared with them, and every difference is logged.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "combined_block_download",
+                &self.combined_block_download,
+                "Download the header, transactions and state diff of each block together from the \
+                 same peer, and write them to the storage in a single transaction. Meant for \
+                 chains with small blocks, where the overhead of a session per protocol dominates \
+                 the sync time. A block that exceeds combined_download_max_block_bytes is \
+                 downloaded in parts, as are the bodies and state diffs that are behind the \
+                 headers.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "combined_download_max_window_bytes",
+                &self.combined_download_max_window_bytes,
+                "The maximum approximate size in bytes of the blocks that are downloaded together \
+                 in the combined block download and held in memory until they're written.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "combined_download_max_block_bytes",
+                &self.combined_download_max_block_bytes,
+                "The maximum approximate size in bytes of a block downloaded in the combined block \
+                 download. A larger block is downloaded in parts: its header, then its state diff \
+                 and then its transactions.",
+                ParamPrivacyInput::Public,
+            ),
         ]);
         config.extend(ser_optional_param(
             &self.stop_sync_at_block_number,
@@ -76,6 +206,24 @@ impl SerializeConfig for P2PSyncConfig {
              profiling on the node.",
             ParamPrivacyInput::Public,
         ));
+        config.extend(ser_optional_param(
+            &self.record_path,
+            PathBuf::from("./p2p_sync_record"),
+            "record_path",
+            "If set, every response the sync receives from peers is appended, along with its \
+             query and peer, to a log in this directory, so that the sync can be replayed with \
+             replay_path.",
+            ParamPrivacyInput::Public,
+        ));
+        config.extend(ser_optional_param(
+            &self.replay_path,
+            PathBuf::from("./p2p_sync_record"),
+            "replay_path",
+            "If set, the sync reads the responses from the log recorded in this directory instead \
+             of from the network, and fails if the log doesn't cover a query it sends. The \
+             storage must start from the state the recording started from.",
+            ParamPrivacyInput::Public,
+        ));
         config
     }
 }
@@ -89,6 +237,15 @@ impl Default for P2PSyncConfig {
             num_block_state_diffs_per_query: 100,
             wait_period_for_new_data: Duration::from_secs(5),
             stop_sync_at_block_number: None,
+            max_parallel_state_diff_sessions: 4,
+            revert_on_base_layer_mismatch: false,
+            follow_tip: true,
+            shadow: false,
+            record_path: None,
+            replay_path: None,
+            combined_block_download: false,
+            combined_download_max_window_bytes: 10 * 1024 * 1024,
+            combined_download_max_block_bytes: 1024 * 1024,
         }
     }
 }
@@ -102,6 +259,37 @@ pub enum P2PSyncError {
          {expected_block_number}, got {actual_block_number}."
     )]
     HeadersUnordered { expected_block_number: BlockNumber, actual_block_number: BlockNumber },
+    #[error(
+        "Block {block_number} has parent hash {actual_parent_hash:?}, but the hash of the \
+         previous block is {expected_parent_hash:?}."
+    )]
+    ParentHashMismatch {
+        block_number: BlockNumber,
+        expected_parent_hash: BlockHash,
+        actual_parent_hash: BlockHash,
+    },
+    #[error(
+        "Got block {actual_block_number} out of order. The next block to inject is \
+         {expected_block_number}."
+    )]
+    InjectedBlockOutOfOrder { expected_block_number: BlockNumber, actual_block_number: BlockNumber },
+    #[error(
+        "The state diff of block {block_number} doesn't match the header's state diff commitment."
+    )]
+    StateDiffCommitmentMismatch { block_number: BlockNumber },
+    #[error(
+        "The transactions and events of block {block_number} don't match the header's commitments."
+    )]
+    BodyCommitmentMismatch { block_number: BlockNumber },
+    #[error(
+        "Block {block_number} has {num_transactions} transactions but {num_transaction_hashes} \
+         transaction hashes."
+    )]
+    WrongNumberOfTransactionHashes {
+        block_number: BlockNumber,
+        num_transactions: usize,
+        num_transaction_hashes: usize,
+    },
     #[error("Expected to receive one signature from the network. got {signatures:?} instead.")]
     // TODO(shahak): Remove this and report to network on invalid data once that's possible.
     // Right now we support only one signature. In the future we will support many signatures.
@@ -134,15 +322,57 @@ pub enum P2PSyncError {
     OldHeaderInStorage { block_number: BlockNumber, missing_field: &'static str },
     #[error("The sender end of the response receivers for {type_description:?} was closed.")]
     ReceiverChannelTerminated { type_description: &'static str },
+    #[error(
+        "Block {block_number} has hash {l2_hash}, but the base layer proved it with hash \
+         {base_layer_hash}."
+    )]
+    BaseLayerHashMismatch {
+        block_number: BlockNumber,
+        base_layer_hash: BlockHash,
+        l2_hash: BlockHash,
+    },
+    #[error(
+        "Reverted the blocks from {block_number} on, since they don't match the base layer. \
+         Restart the node to sync them again."
+    )]
+    RevertedToBaseLayerBlock { block_number: BlockNumber },
+    #[error("A transaction of block {block_number} was received without its output.")]
+    MissingTransactionOutput { block_number: BlockNumber },
+    #[error(transparent)]
+    ReplayError(#[from] ReplayError),
+    #[error(transparent)]
+    ResponseViolation(#[from] ResponseViolation),
     #[error(transparent)]
     NetworkTimeout(#[from] tokio::time::error::Elapsed),
     #[error(transparent)]
+    NoPeers(SqmrQueryError),
+    #[error(transparent)]
+    BlockHashError(#[from] BlockHashError),
+    #[error(transparent)]
     StorageError(#[from] StorageError),
     #[error(transparent)]
     SendError(#[from] SendError),
+    #[error(transparent)]
+    StarknetApiError(#[from] StarknetApiError),
+}
+
+impl From<SqmrResponseError<ProtobufConversionError>> for P2PSyncError {
+    fn from(error: SqmrResponseError<ProtobufConversionError>) -> Self {
+        match error {
+            SqmrResponseError::Query(error @ SqmrQueryError::NoPeers { .. }) => {
+                Self::NoPeers(error)
+            }
+            SqmrResponseError::Conversion(error) => Self::ProtobufConversionError(error),
+        }
+    }
 }
 
-type Response<T> = (Result<DataOrFin<T>, ProtobufConversionError>, ReportCallback);
+pub type Response<T> =
+    (Result<DataOrFin<T>, SqmrResponseError<ProtobufConversionError>>, ReportCallback);
+
+pub type TransactionQuerySender = Pin<Box<dyn Sink<TransactionQuery, Error = SendError> + Send>>;
+pub type TransactionResponseReceiver =
+    BoxStream<'static, Response<(Transaction, Option<TransactionOutput>)>>;
 
 pub struct P2PSync<
     HeaderQuerySender,
@@ -155,8 +385,19 @@ pub struct P2PSync<
     storage_writer: StorageWriter,
     header_query_sender: HeaderQuerySender,
     header_response_receiver: HeaderResponseReceiver,
-    state_diff_query_sender: StateDiffQuerySender,
-    state_diff_response_receiver: StateDiffResponseReceiver,
+    // The state diffs of different blocks are downloaded in parallel through different lanes.
+    state_diff_lanes: Vec<(StateDiffQuerySender, StateDiffResponseReceiver)>,
+    peer_manager_command_sender: Option<UnboundedSender<PeerManagerCommand>>,
+    // Raised to the highest block whose header was validated, for the syncing status of the node.
+    shared_highest_block: Arc<RwLock<Option<BlockHashAndNumber>>>,
+    // None if the synced chain isn't checked against the base layer.
+    base_layer_checkpoint_source: Option<BaseLayerCheckpointSource>,
+    // Notifies the node's sync server of new headers, so that it sends them to the peers that
+    // follow the tip.
+    header_marker_sender: watch::Sender<BlockNumber>,
+    // The channels of the transactions protocol and the chain id the transaction hashes are
+    // computed with. Needed only for the combined block download.
+    transaction_channels: Option<(TransactionQuerySender, TransactionResponseReceiver, ChainId)>,
 }
 
 impl<HeaderQuerySender, HeaderResponseReceiver, StateDiffQuerySender, StateDiffResponseReceiver>
@@ -167,20 +408,25 @@ impl<HeaderQuerySender, HeaderResponseReceiver, StateDiffQuerySender, StateDiffR
         StateDiffResponseReceiver,
     >
 where
-    HeaderQuerySender: Sink<HeaderQuery, Error = SendError> + Unpin + Send + 'static,
+    HeaderQuerySender:
+        Sink<(HeaderQuery, QueryPriority), Error = SendError> + Unpin + Send + 'static,
     HeaderResponseReceiver: Stream<Item = Response<SignedBlockHeader>> + Unpin + Send + 'static,
     StateDiffQuerySender: Sink<StateDiffQuery, Error = SendError> + Unpin + Send + 'static,
     // TODO(shahak): Change to StateDiffChunk.
     StateDiffResponseReceiver: Stream<Item = Response<ThinStateDiff>> + Unpin + Send + 'static,
 {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(
         config: P2PSyncConfig,
         storage_reader: StorageReader,
         storage_writer: StorageWriter,
         header_query_sender: HeaderQuerySender,
         header_response_receiver: HeaderResponseReceiver,
-        state_diff_query_sender: StateDiffQuerySender,
-        state_diff_response_receiver: StateDiffResponseReceiver,
+        state_diff_lanes: Vec<(StateDiffQuerySender, StateDiffResponseReceiver)>,
+        peer_manager_command_sender: Option<UnboundedSender<PeerManagerCommand>>,
+        shared_highest_block: Arc<RwLock<Option<BlockHashAndNumber>>>,
+        base_layer_checkpoint_source: Option<BaseLayerCheckpointSource>,
+        header_marker_sender: watch::Sender<BlockNumber>,
     ) -> Self {
         Self {
             config,
@@ -188,36 +434,189 @@ where
             storage_writer,
             header_query_sender,
             header_response_receiver,
-            state_diff_query_sender,
-            state_diff_response_receiver,
+            state_diff_lanes,
+            peer_manager_command_sender,
+            shared_highest_block,
+            base_layer_checkpoint_source,
+            header_marker_sender,
+            transaction_channels: None,
         }
     }
 
+    /// Sets the channels the transactions are downloaded through in the combined block download.
+    pub fn with_transaction_channels(
+        mut self,
+        transaction_query_sender: TransactionQuerySender,
+        transaction_response_receiver: TransactionResponseReceiver,
+        chain_id: ChainId,
+    ) -> Self {
+        self.transaction_channels =
+            Some((transaction_query_sender, transaction_response_receiver, chain_id));
+        self
+    }
+
     #[instrument(skip(self), level = "debug", err)]
     pub async fn run(mut self) -> Result<(), P2PSyncError> {
-        let header_stream = HeaderStreamFactory::create_stream(
-            self.header_query_sender.with(|query| ready(Ok(HeaderQuery(query)))),
-            self.header_response_receiver,
-            self.storage_reader.clone(),
-            self.config.wait_period_for_new_data,
-            self.config.num_headers_per_query,
-            self.config.stop_sync_at_block_number,
-        );
-
-        let state_diff_stream = StateDiffStreamFactory::create_stream(
-            self.state_diff_query_sender.with(|query| ready(Ok(StateDiffQuery(query)))),
-            self.state_diff_response_receiver,
-            self.storage_reader,
-            self.config.wait_period_for_new_data,
-            self.config.num_block_state_diffs_per_query,
-            self.config.stop_sync_at_block_number,
-        );
-
-        let mut data_stream = header_stream.merge(state_diff_stream);
+        let mut data_stream = match (self.config.combined_block_download, self.transaction_channels)
+        {
+            (true, Some((transaction_query_sender, transaction_response_receiver, chain_id))) => {
+                create_combined_stream(
+                    self.config.clone(),
+                    self.storage_reader.clone(),
+                    self.header_query_sender,
+                    self.header_response_receiver,
+                    self.state_diff_lanes,
+                    transaction_query_sender,
+                    transaction_response_receiver,
+                    chain_id,
+                )
+            }
+            (combined_block_download, _) => {
+                if combined_block_download {
+                    warn!(
+                        "The combined block download needs the channels of the transactions \
+                         protocol. Downloading the headers and state diffs separately."
+                    );
+                }
+                create_pipelined_stream(
+                    self.config.clone(),
+                    self.storage_reader.clone(),
+                    self.header_query_sender,
+                    self.header_response_receiver,
+                    self.state_diff_lanes,
+                    self.peer_manager_command_sender,
+                )
+            }
+        };
+        let mut proved_blocks_stream: BoxStream<'static, (BlockNumber, BlockHash)> =
+            match self.base_layer_checkpoint_source {
+                Some(base_layer_checkpoint_source) => {
+                    stream_proved_blocks(base_layer_checkpoint_source)
+                }
+                None => Box::pin(futures::stream::pending()),
+            };
+        // The sync holds the storage writer, so it moves the old blocks to the cold storage.
+        let cold_storage_move_interval = self.storage_writer.cold_storage_move_interval();
+        let mut cold_storage_moves: BoxStream<'static, ()> = match cold_storage_move_interval {
+            Some(move_interval) => Box::pin(futures::stream::unfold((), move |()| async move {
+                tokio::time::sleep(move_interval).await;
+                Some(((), ()))
+            })),
+            None => Box::pin(futures::stream::pending()),
+        };
 
         loop {
-            let data = data_stream.next().await.expect("Sync data stream should never end")?;
-            data.write_to_storage(&mut self.storage_writer)?;
+            tokio::select! {
+                data = data_stream.next() => {
+                    let data = data.expect("Sync data stream should never end")?;
+                    let proven_block = data.proven_block();
+                    data.write_to_storage(&mut self.storage_writer)?;
+                    if let Some(proven_block) = proven_block {
+                        raise_highest_block(&self.shared_highest_block, proven_block).await;
+                        self.header_marker_sender
+                            .send_replace(proven_block.block_number.unchecked_next());
+                    }
+                }
+                Some((block_number, block_hash)) = proved_blocks_stream.next() => {
+                    check_proved_block(
+                        &mut self.storage_writer,
+                        block_number,
+                        block_hash,
+                        self.config.revert_on_base_layer_mismatch,
+                    )?;
+                }
+                Some(()) = cold_storage_moves.next() => {
+                    let now = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .expect("The current time should be after the Unix epoch")
+                        .as_secs();
+                    self.storage_writer.move_blocks_to_cold_storage(BlockTimestamp(now))?;
+                }
+            }
         }
     }
 }
+
+/// Creates a stream of the headers and the state diffs, which are downloaded separately, so that
+/// the state diffs of the blocks whose headers were downloaded are downloaded while the next
+/// headers are.
+fn create_pipelined_stream<
+    HeaderQuerySender,
+    HeaderResponseReceiver,
+    StateDiffQuerySender,
+    StateDiffResponseReceiver,
+>(
+    config: P2PSyncConfig,
+    storage_reader: StorageReader,
+    header_query_sender: HeaderQuerySender,
+    header_response_receiver: HeaderResponseReceiver,
+    state_diff_lanes: Vec<(StateDiffQuerySender, StateDiffResponseReceiver)>,
+    peer_manager_command_sender: Option<UnboundedSender<PeerManagerCommand>>,
+) -> BoxStream<'static, Result<Box<dyn BlockData>, P2PSyncError>>
+where
+    HeaderQuerySender:
+        Sink<(HeaderQuery, QueryPriority), Error = SendError> + Unpin + Send + 'static,
+    HeaderResponseReceiver: Stream<Item = Response<SignedBlockHeader>> + Unpin + Send + 'static,
+    StateDiffQuerySender: Sink<StateDiffQuery, Error = SendError> + Unpin + Send + 'static,
+    StateDiffResponseReceiver: Stream<Item = Response<ThinStateDiff>> + Unpin + Send + 'static,
+{
+    let header_stream = HeaderStreamFactory::create_stream(
+        header_query_sender.with(|(query, priority)| ready(Ok((HeaderQuery(query), priority)))),
+        header_response_receiver,
+        storage_reader.clone(),
+        config.wait_period_for_new_data,
+        config.num_headers_per_query,
+        config.stop_sync_at_block_number,
+        config.follow_tip,
+    );
+
+    // State diff queries are sent with the default priority.
+    let mut state_diff_lanes = state_diff_lanes
+        .into_iter()
+        .map(|(query_sender, response_receiver)| {
+            (
+                query_sender.with(|(query, _priority): (_, QueryPriority)| {
+                    ready(Ok(StateDiffQuery(query)))
+                }),
+                response_receiver,
+            )
+        })
+        .collect::<Vec<_>>();
+    let state_diff_stream = if state_diff_lanes.len() == 1 {
+        let (query_sender, response_receiver) =
+            state_diff_lanes.pop().expect("There is a single lane");
+        StateDiffStreamFactory::create_stream(
+            query_sender,
+            response_receiver,
+            storage_reader,
+            config.wait_period_for_new_data,
+            config.num_block_state_diffs_per_query,
+            config.stop_sync_at_block_number,
+            // Peers follow the tip only for headers.
+            false,
+        )
+    } else {
+        create_sharded_stream::<StateDiffStreamFactory<_, _>, _, _, _>(
+            state_diff_lanes,
+            storage_reader,
+            config.wait_period_for_new_data,
+            config.num_block_state_diffs_per_query,
+            config.stop_sync_at_block_number,
+            peer_manager_command_sender,
+        )
+    };
+
+    Box::pin(header_stream.merge(state_diff_stream))
+}
+
+// The highest block is never lowered, since another writer might know of a higher block than the
+// ones this node already downloaded.
+async fn raise_highest_block(
+    shared_highest_block: &RwLock<Option<BlockHashAndNumber>>,
+    block: BlockHashAndNumber,
+) {
+    let mut highest_block = shared_highest_block.write().await;
+    if highest_block.map_or(true, |highest_block| highest_block.block_number < block.block_number) {
+        *highest_block = Some(block);
+    }
+}
@@ -0,0 +1,154 @@
+use std::time::Duration;
+
+use futures::channel::mpsc::SendError;
+use futures::{Sink, SinkExt, Stream, StreamExt};
+use papyrus_network::network_manager::ReportCallback;
+use papyrus_protobuf::sync::{BlockHashOrNumber, Direction, Query, SignedBlockHeader};
+use starknet_api::block::BlockHash;
+
+use crate::response_validator::{ResponseViolation, ValidatedResponse, ValidatedResponseReceiver};
+use crate::{P2PSyncError, Response, STEP};
+
+/// Sends the queries of a single protocol and collects their responses, without writing them to
+/// a storage. The responses are checked against their query the same way the sync checks them, so
+/// tools that only talk to peers, such as interoperability tests, can reuse that logic.
+pub struct QueryClient<QuerySender, DataReceiver, QueryMessage = Query> {
+    query_sender: QuerySender,
+    // Wraps each query in the message the query sender takes.
+    make_query_message: fn(Query) -> QueryMessage,
+    response_receiver: ValidatedResponseReceiver<DataReceiver>,
+    type_description: &'static str,
+    timeout: Duration,
+}
+
+impl<QuerySender, DataReceiver, InputFromNetwork> QueryClient<QuerySender, DataReceiver>
+where
+    QuerySender: Sink<Query, Error = SendError> + Unpin,
+    DataReceiver: Stream<Item = Response<InputFromNetwork>> + Unpin,
+    InputFromNetwork: ValidatedResponse,
+{
+    /// `timeout` is the longest time to wait for each response.
+    pub fn new(
+        query_sender: QuerySender,
+        response_receiver: DataReceiver,
+        type_description: &'static str,
+        timeout: Duration,
+    ) -> Self {
+        Self::with_query_message(
+            query_sender,
+            |query| query,
+            response_receiver,
+            type_description,
+            timeout,
+        )
+    }
+}
+
+impl<QuerySender, DataReceiver, InputFromNetwork, QueryMessage>
+    QueryClient<QuerySender, DataReceiver, QueryMessage>
+where
+    QuerySender: Sink<QueryMessage, Error = SendError> + Unpin,
+    DataReceiver: Stream<Item = Response<InputFromNetwork>> + Unpin,
+    InputFromNetwork: ValidatedResponse,
+{
+    /// Like [`QueryClient::new`], for a query sender that takes each query wrapped in another
+    /// message, e.g. along with its priority.
+    pub(crate) fn with_query_message(
+        query_sender: QuerySender,
+        make_query_message: fn(Query) -> QueryMessage,
+        response_receiver: DataReceiver,
+        type_description: &'static str,
+        timeout: Duration,
+    ) -> Self {
+        Self {
+            query_sender,
+            make_query_message,
+            response_receiver: ValidatedResponseReceiver::new(response_receiver, type_description),
+            type_description,
+            timeout,
+        }
+    }
+
+    /// Sends the query and returns its responses once the peer ended the session with a Fin.
+    /// `previous_block_hash` is the hash of the block before the query's first block, if it's
+    /// known. A response that violates the query fails the query, and the rest of its session is
+    /// dropped.
+    pub async fn query(
+        &mut self,
+        query: Query,
+        previous_block_hash: Option<BlockHash>,
+    ) -> Result<Vec<InputFromNetwork>, P2PSyncError> {
+        // The session is never cut short, so its responses are always returned.
+        let responses =
+            self.query_while(query, previous_block_hash, |_| true).await?.unwrap_or_default();
+        Ok(responses.into_iter().map(|(data, _report_callback)| data).collect())
+    }
+
+    /// Like [`QueryClient::query`], but returns each response with the callback that reports the
+    /// peer that sent it. `keep_receiving` is called on each response, and once it returns false,
+    /// the rest of the session is dropped and None is returned.
+    pub(crate) async fn query_while(
+        &mut self,
+        query: Query,
+        previous_block_hash: Option<BlockHash>,
+        mut keep_receiving: impl FnMut(&InputFromNetwork) -> bool,
+    ) -> Result<Option<Vec<(InputFromNetwork, ReportCallback)>>, P2PSyncError> {
+        self.response_receiver.start_session(&query, previous_block_hash);
+        self.query_sender.send((self.make_query_message)(query)).await?;
+        let mut responses = vec![];
+        loop {
+            let (maybe_data, report_callback) =
+                tokio::time::timeout(self.timeout, self.response_receiver.next()).await?.ok_or(
+                    P2PSyncError::ReceiverChannelTerminated {
+                        type_description: self.type_description,
+                    },
+                )?;
+            let Some(data) = maybe_data?.0 else {
+                if let Some(violation) = self.response_receiver.take_violation() {
+                    self.response_receiver.skip_rest_of_aborted_session(self.timeout).await;
+                    return Err(violation.into());
+                }
+                return Ok(Some(responses));
+            };
+            if !keep_receiving(&data) {
+                self.response_receiver.skip_rest_of_aborted_session(self.timeout).await;
+                return Ok(None);
+            }
+            responses.push((data, report_callback));
+        }
+    }
+}
+
+impl<QuerySender, DataReceiver, QueryMessage> QueryClient<QuerySender, DataReceiver, QueryMessage>
+where
+    QuerySender: Sink<QueryMessage, Error = SendError> + Unpin,
+    DataReceiver: Stream<Item = Response<SignedBlockHeader>> + Unpin,
+{
+    /// Queries `limit` headers starting from the block with the given hash, e.g. a fork block that
+    /// we know only by its hash. A peer that doesn't know the hash answers with a Fin only, so no
+    /// headers are returned. The block numbers of the responses can't be checked against the query,
+    /// but the first header should have the given hash.
+    pub async fn query_headers_by_hash(
+        &mut self,
+        block_hash: BlockHash,
+        limit: u64,
+    ) -> Result<Vec<SignedBlockHeader>, P2PSyncError> {
+        let query = Query {
+            start_block: BlockHashOrNumber::Hash(block_hash),
+            direction: Direction::Forward,
+            limit,
+            step: STEP,
+        };
+        let headers = self.query(query, None).await?;
+        if let Some(first_header) = headers.first() {
+            if first_header.block_header.block_hash != block_hash {
+                return Err(ResponseViolation::UnexpectedStartBlockHash {
+                    expected_block_hash: block_hash,
+                    actual_block_hash: first_header.block_header.block_hash,
+                }
+                .into());
+            }
+        }
+        Ok(headers)
+    }
+}
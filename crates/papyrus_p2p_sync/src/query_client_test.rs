@@ -0,0 +1,192 @@
+use std::time::Duration;
+
+use assert_matches::assert_matches;
+use futures::channel::mpsc;
+use futures::{SinkExt, StreamExt};
+use papyrus_protobuf::sync::{BlockHashOrNumber, DataOrFin, Direction, Query, SignedBlockHeader};
+use starknet_api::block::{BlockHash, BlockHeader, BlockNumber};
+use starknet_types_core::felt::Felt;
+
+use crate::query_client::QueryClient;
+use crate::response_validator::ResponseViolation;
+use crate::test_utils::BUFFER_SIZE;
+use crate::{P2PSyncError, Response};
+
+const TIMEOUT: Duration = Duration::from_secs(1);
+
+type TestQueryClient =
+    QueryClient<mpsc::Sender<Query>, mpsc::Receiver<Response<SignedBlockHeader>>>;
+
+fn header(block_number: u64) -> SignedBlockHeader {
+    SignedBlockHeader {
+        block_header: BlockHeader {
+            block_number: BlockNumber(block_number),
+            block_hash: BlockHash(Felt::from(block_number + 1)),
+            parent_hash: BlockHash(Felt::from(block_number)),
+            ..Default::default()
+        },
+        signatures: vec![],
+        data_availability: None,
+    }
+}
+
+fn query(start_block_number: u64, direction: Direction, limit: u64) -> Query {
+    Query {
+        start_block: BlockHashOrNumber::Number(BlockNumber(start_block_number)),
+        direction,
+        limit,
+        step: 1,
+    }
+}
+
+fn setup() -> (TestQueryClient, mpsc::Receiver<Query>, mpsc::Sender<Response<SignedBlockHeader>>) {
+    let (query_sender, query_receiver) = mpsc::channel(BUFFER_SIZE);
+    let (response_sender, response_receiver) = mpsc::channel(BUFFER_SIZE);
+    let client = QueryClient::new(query_sender, response_receiver, "headers", TIMEOUT);
+    (client, query_receiver, response_sender)
+}
+
+async fn send_responses(
+    response_sender: &mut mpsc::Sender<Response<SignedBlockHeader>>,
+    headers: Vec<SignedBlockHeader>,
+) {
+    for header in headers {
+        response_sender.send((Ok(DataOrFin(Some(header))), Box::new(|| {}))).await.unwrap();
+    }
+    response_sender.send((Ok(DataOrFin(None)), Box::new(|| {}))).await.unwrap();
+}
+
+#[tokio::test]
+async fn responses_are_collected_until_fin() {
+    let (mut client, mut query_receiver, mut response_sender) = setup();
+    let headers = (3..6).map(header).collect::<Vec<_>>();
+    send_responses(&mut response_sender, headers.clone()).await;
+
+    let query = query(3, Direction::Forward, 3);
+    let responses = client.query(query.clone(), Some(BlockHash(Felt::from(3_u8)))).await.unwrap();
+
+    assert_eq!(responses, headers);
+    assert_eq!(query_receiver.next().await.unwrap(), query);
+}
+
+#[tokio::test]
+async fn descending_responses_are_collected_until_fin() {
+    let (mut client, _query_receiver, mut response_sender) = setup();
+    let headers = (3..6).rev().map(header).collect::<Vec<_>>();
+    send_responses(&mut response_sender, headers.clone()).await;
+
+    let responses = client.query(query(5, Direction::Backward, 3), None).await.unwrap();
+
+    assert_eq!(responses, headers);
+}
+
+#[tokio::test]
+async fn response_that_violates_the_query_fails_it() {
+    let (mut client, _query_receiver, mut response_sender) = setup();
+    send_responses(&mut response_sender, vec![header(3), header(5), header(4)]).await;
+
+    let result = client.query(query(3, Direction::Forward, 3), None).await;
+
+    assert_matches!(
+        result,
+        Err(P2PSyncError::ResponseViolation(ResponseViolation::UnexpectedBlockNumber {
+            expected_block_number: BlockNumber(4),
+            actual_block_number: BlockNumber(5),
+        }))
+    );
+
+    // The rest of the failed query's session isn't checked against the next query.
+    send_responses(&mut response_sender, vec![header(6)]).await;
+    let responses = client.query(query(6, Direction::Forward, 1), None).await.unwrap();
+    assert_eq!(responses, vec![header(6)]);
+}
+
+#[tokio::test]
+async fn descending_response_past_block_0_fails_the_query() {
+    let (mut client, _query_receiver, mut response_sender) = setup();
+    send_responses(&mut response_sender, vec![header(1), header(0), header(0)]).await;
+
+    let result = client.query(query(1, Direction::Backward, 5), None).await;
+
+    assert_matches!(
+        result,
+        Err(P2PSyncError::ResponseViolation(ResponseViolation::BlockBeforeGenesis {
+            actual_block_number: BlockNumber(0),
+        }))
+    );
+}
+
+#[tokio::test(start_paused = true)]
+async fn peer_that_doesnt_respond_fails_the_query() {
+    let (mut client, _query_receiver, _response_sender) = setup();
+
+    let result = client.query(query(3, Direction::Forward, 3), None).await;
+
+    assert_matches!(result, Err(P2PSyncError::NetworkTimeout(_)));
+}
+
+#[tokio::test]
+async fn session_is_dropped_once_the_caller_stops_receiving() {
+    let (mut client, _query_receiver, mut response_sender) = setup();
+    send_responses(&mut response_sender, (3..6).map(header).collect()).await;
+
+    let mut num_received = 0;
+    let result = client
+        .query_while(query(3, Direction::Forward, 3), None, |_| {
+            num_received += 1;
+            num_received < 2
+        })
+        .await
+        .unwrap();
+    assert!(result.is_none());
+
+    // The rest of the dropped session isn't returned for the next query.
+    send_responses(&mut response_sender, vec![header(6)]).await;
+    let responses = client.query(query(6, Direction::Forward, 1), None).await.unwrap();
+    assert_eq!(responses, vec![header(6)]);
+}
+
+#[tokio::test]
+async fn headers_are_queried_by_hash() {
+    let (mut client, mut query_receiver, mut response_sender) = setup();
+    let headers = (3..6).map(header).collect::<Vec<_>>();
+    send_responses(&mut response_sender, headers.clone()).await;
+    let block_hash = headers[0].block_header.block_hash;
+
+    let responses = client.query_headers_by_hash(block_hash, 3).await.unwrap();
+
+    assert_eq!(responses, headers);
+    assert_eq!(
+        query_receiver.next().await.unwrap(),
+        Query {
+            start_block: BlockHashOrNumber::Hash(block_hash),
+            direction: Direction::Forward,
+            limit: 3,
+            step: 1,
+        }
+    );
+}
+
+#[tokio::test]
+async fn hash_unknown_to_the_peer_returns_no_headers() {
+    let (mut client, _query_receiver, mut response_sender) = setup();
+    // A peer that doesn't know the hash answers with a Fin only.
+    send_responses(&mut response_sender, vec![]).await;
+
+    let responses = client.query_headers_by_hash(BlockHash(Felt::from(1000_u64)), 3).await.unwrap();
+
+    assert!(responses.is_empty());
+}
+
+#[tokio::test]
+async fn response_from_another_hash_fails_the_query() {
+    let (mut client, _query_receiver, mut response_sender) = setup();
+    send_responses(&mut response_sender, vec![header(4)]).await;
+
+    let result = client.query_headers_by_hash(header(3).block_header.block_hash, 1).await;
+
+    assert_matches!(
+        result,
+        Err(P2PSyncError::ResponseViolation(ResponseViolation::UnexpectedStartBlockHash { .. }))
+    );
+}
@@ -0,0 +1,352 @@
+//! Records the responses the p2p sync receives from peers, and replays them instead of the
+//! network. A validation failure that happened after hours of syncing can then be reproduced
+//! without the peers that served the data, by replaying the recorded responses into a copy of the
+//! storage the recording started from.
+
+use std::collections::{HashMap, VecDeque};
+use std::fmt::Debug;
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use futures::channel::mpsc::{channel, Sender, UnboundedReceiver};
+use futures::future::{try_join_all, BoxFuture};
+use futures::{FutureExt, SinkExt, Stream, StreamExt};
+use papyrus_common::BlockHashAndNumber;
+use papyrus_network::network_manager::{
+    QueryPriority,
+    RecordedSqmrResponse,
+    ReportCallback,
+    SqmrResponseError,
+};
+use papyrus_network::Protocol;
+use papyrus_protobuf::converters::ProtobufConversionError;
+use papyrus_protobuf::sync::{DataOrFin, HeaderQuery, SignedBlockHeader, StateDiffQuery};
+use papyrus_storage::{StorageReader, StorageWriter};
+use starknet_api::block::BlockNumber;
+use starknet_api::state::ThinStateDiff;
+use tokio::sync::{watch, RwLock};
+use tracing::{debug, info, warn};
+
+use crate::snapshot::{read_message, write_message};
+use crate::{P2PSync, P2PSyncConfig, P2PSyncError, Response};
+
+const SEGMENT_FILE_PREFIX: &str = "responses.";
+const SEGMENT_FILE_EXTENSION: &str = ".log";
+// Once a segment reaches this size, the next responses are written to a new segment.
+const MAX_SEGMENT_SIZE: u64 = 64 * 1024 * 1024;
+const MAX_RESPONSES_PER_FLUSH: usize = 1000;
+const REPLAY_BUFFER_SIZE: usize = 100000;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ReplayError {
+    #[error("No responses were recorded in {path}.")]
+    EmptyLog { path: PathBuf },
+    #[error(
+        "The replayed responses don't cover the {protocol} query {query}. The replay can only \
+         reproduce a sync that starts from the storage the recording started from."
+    )]
+    QueryNotRecorded { protocol: &'static str, query: String },
+    #[error("A recorded response is corrupted: {reason}")]
+    CorruptedRecord { reason: String },
+    #[error(transparent)]
+    IoError(#[from] io::Error),
+}
+
+/// Appends the responses received from `recorded_responses` to the log in `record_dir`. The log is
+/// split into segments of about 64 MiB, and each run of the node starts a new segment after the
+/// existing ones. Segments are never deleted, since a replay needs all the responses from the
+/// start of the recording.
+///
+/// Each response is written along with the protocol, the query, the session of the query, the
+/// peer that sent it and the time it was received. Every field is written as a length-prefixed
+/// message, like the blocks of a snapshot.
+pub async fn record_responses(
+    record_dir: PathBuf,
+    recorded_responses: UnboundedReceiver<RecordedSqmrResponse>,
+) -> Result<(), ReplayError> {
+    let mut recorder = Recorder::new(record_dir)?;
+    info!("Recording the p2p sync responses into {}.", recorder.record_dir.display());
+    // The responses are flushed in batches, so that they're on the disk when the sync fails on
+    // them without a write for each response.
+    let mut recorded_responses = recorded_responses.ready_chunks(MAX_RESPONSES_PER_FLUSH);
+    while let Some(responses) = recorded_responses.next().await {
+        for response in responses {
+            recorder.write(&response)?;
+        }
+        recorder.segment.flush()?;
+    }
+    Ok(())
+}
+
+struct Recorder {
+    record_dir: PathBuf,
+    // Session ids are unique only within a run of the node, so the sessions of each run are
+    // identified by the time the run started recording.
+    run_id: u64,
+    segment: BufWriter<File>,
+    segment_index: u64,
+    segment_size: u64,
+}
+
+impl Recorder {
+    fn new(record_dir: PathBuf) -> io::Result<Self> {
+        fs::create_dir_all(&record_dir)?;
+        let segment_index =
+            list_segments(&record_dir)?.last().map_or(0, |(segment_index, _)| segment_index + 1);
+        let segment = create_segment(&record_dir, segment_index)?;
+        Ok(Self { record_dir, run_id: now_millis(), segment, segment_index, segment_size: 0 })
+    }
+
+    fn write(&mut self, response: &RecordedSqmrResponse) -> io::Result<()> {
+        if self.segment_size >= MAX_SEGMENT_SIZE {
+            self.segment.flush()?;
+            self.segment_index += 1;
+            self.segment = create_segment(&self.record_dir, self.segment_index)?;
+            self.segment_size = 0;
+        }
+        let peer_id = response.peer_id.to_string();
+        let fields: [&[u8]; 7] = [
+            &self.run_id.to_be_bytes(),
+            &response.session_id.to_be_bytes(),
+            &now_millis().to_be_bytes(),
+            response.protocol.as_str().as_bytes(),
+            peer_id.as_bytes(),
+            &response.query,
+            &response.response,
+        ];
+        for field in fields {
+            write_message(&mut self.segment, field)?;
+            self.segment_size += field.len() as u64;
+        }
+        Ok(())
+    }
+}
+
+fn create_segment(record_dir: &Path, segment_index: u64) -> io::Result<BufWriter<File>> {
+    let path =
+        record_dir.join(format!("{SEGMENT_FILE_PREFIX}{segment_index}{SEGMENT_FILE_EXTENSION}"));
+    Ok(BufWriter::new(File::options().write(true).create_new(true).open(path)?))
+}
+
+// Returns the segments in the directory, ordered by their index.
+fn list_segments(record_dir: &Path) -> io::Result<Vec<(u64, PathBuf)>> {
+    let mut segments = Vec::new();
+    for entry in fs::read_dir(record_dir)? {
+        let path = entry?.path();
+        let segment_index = path
+            .file_name()
+            .and_then(|file_name| file_name.to_str())
+            .and_then(|file_name| file_name.strip_prefix(SEGMENT_FILE_PREFIX))
+            .and_then(|file_name| file_name.strip_suffix(SEGMENT_FILE_EXTENSION))
+            .and_then(|segment_index| segment_index.parse::<u64>().ok());
+        if let Some(segment_index) = segment_index {
+            segments.push((segment_index, path));
+        }
+    }
+    segments.sort();
+    Ok(segments)
+}
+
+fn now_millis() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map_or(0, |duration| duration.as_millis() as u64)
+}
+
+/// Runs the p2p sync on the responses recorded in `replay_dir` instead of on the network, through
+/// the same validation and the same writes to the storage.
+///
+/// Each query the sync sends is answered with the responses of the first recorded session of the
+/// same query that wasn't replayed yet. If no such session was recorded, the replay fails, since
+/// the sync diverged from the recorded run. The storage has to start from the state the recording
+/// started from, and a query whose session got no responses fails the replay as well.
+pub async fn run_replay(
+    config: P2PSyncConfig,
+    replay_dir: &Path,
+    storage_reader: StorageReader,
+    storage_writer: StorageWriter,
+    shared_highest_block: Arc<RwLock<Option<BlockHashAndNumber>>>,
+    header_marker_sender: watch::Sender<BlockNumber>,
+) -> Result<(), P2PSyncError> {
+    let replay_log = Arc::new(Mutex::new(ReplayLog::load(replay_dir)?));
+    info!("Replaying the p2p sync responses recorded in {}.", replay_dir.display());
+
+    let (header_query_sender, header_query_receiver) =
+        channel::<(HeaderQuery, QueryPriority)>(REPLAY_BUFFER_SIZE);
+    let (header_response_sender, header_response_receiver) = channel(REPLAY_BUFFER_SIZE);
+    let mut lanes: Vec<BoxFuture<'static, Result<(), ReplayError>>> =
+        vec![replay_lane::<_, SignedBlockHeader>(
+            replay_log.clone(),
+            Protocol::SignedBlockHeader,
+            header_query_receiver.map(|(query, _priority)| query),
+            header_response_sender,
+        )
+        .boxed()];
+    let mut state_diff_lanes = Vec::new();
+    for _ in 0..config.max_parallel_state_diff_sessions {
+        let (query_sender, query_receiver) = channel(REPLAY_BUFFER_SIZE);
+        let (response_sender, response_receiver) = channel(REPLAY_BUFFER_SIZE);
+        lanes.push(
+            replay_lane::<StateDiffQuery, ThinStateDiff>(
+                replay_log.clone(),
+                Protocol::StateDiff,
+                query_receiver,
+                response_sender,
+            )
+            .boxed(),
+        );
+        state_diff_lanes.push((query_sender, response_receiver));
+    }
+
+    let sync = P2PSync::new(
+        config,
+        storage_reader,
+        storage_writer,
+        header_query_sender,
+        header_response_receiver,
+        state_diff_lanes,
+        None,
+        shared_highest_block,
+        // The replay is deterministic only without the base layer, which keeps proving new blocks.
+        None,
+        header_marker_sender,
+    );
+    tokio::select! {
+        result = sync.run() => result,
+        Err(error) = try_join_all(lanes) => Err(error.into()),
+    }
+}
+
+// Answers the queries of a single lane with the recorded responses.
+async fn replay_lane<Query, Data>(
+    replay_log: Arc<Mutex<ReplayLog>>,
+    protocol: Protocol,
+    mut query_receiver: impl Stream<Item = Query> + Unpin,
+    mut response_sender: Sender<Response<Data>>,
+) -> Result<(), ReplayError>
+where
+    Query: Debug,
+    Vec<u8>: From<Query>,
+    DataOrFin<Data>: TryFrom<Vec<u8>, Error = ProtobufConversionError>,
+{
+    while let Some(query) = query_receiver.next().await {
+        let query_description = format!("{query:?}");
+        let session = replay_log
+            .lock()
+            .expect("Failed to lock the replay log.")
+            .pop_session(protocol, Vec::<u8>::from(query))
+            .ok_or_else(|| ReplayError::QueryNotRecorded {
+                protocol: protocol.as_str(),
+                query: query_description.clone(),
+            })?;
+        debug!(
+            "Replaying {} responses of peer {} to the {protocol} query {query_description}.",
+            session.response_offsets.len(),
+            session.peer_id
+        );
+        for (segment_index, offset) in session.response_offsets {
+            let response = replay_log
+                .lock()
+                .expect("Failed to lock the replay log.")
+                .read_response(segment_index, offset)?;
+            let peer_id = session.peer_id.clone();
+            let report_callback: ReportCallback = Box::new(move || {
+                warn!("The sync reported peer {peer_id} for a replayed response.");
+            });
+            if response_sender
+                .send((
+                    DataOrFin::<Data>::try_from(response).map_err(SqmrResponseError::Conversion),
+                    report_callback,
+                ))
+                .await
+                .is_err()
+            {
+                return Ok(());
+            }
+        }
+    }
+    Ok(())
+}
+
+// The responses of a single session, in the order they were received.
+struct RecordedSession {
+    peer_id: String,
+    // The segment and the offset in it of each response.
+    response_offsets: Vec<(usize, u64)>,
+}
+
+// An index of the recorded responses. The responses themselves stay on the disk until they're
+// replayed, since a recording of a long sync doesn't fit in memory.
+struct ReplayLog {
+    segments: Vec<BufReader<File>>,
+    // The sessions of each protocol and query, in the order they were recorded.
+    sessions: HashMap<(&'static str, Vec<u8>), VecDeque<RecordedSession>>,
+}
+
+impl ReplayLog {
+    fn load(replay_dir: &Path) -> Result<Self, ReplayError> {
+        let mut segments = Vec::new();
+        let mut sessions: HashMap<_, VecDeque<RecordedSession>> = HashMap::new();
+        // The protocol, the query and the index in the query's sessions of each recorded session.
+        let mut session_positions = HashMap::new();
+        for (segment_index, (_, path)) in list_segments(replay_dir)?.into_iter().enumerate() {
+            let mut segment = BufReader::new(File::open(path)?);
+            while let Some(run_id) = read_message(&mut segment)? {
+                let session_id = read_field(&mut segment)?;
+                let _timestamp = read_field(&mut segment)?;
+                let protocol = parse_protocol(&read_field(&mut segment)?)?;
+                let peer_id = String::from_utf8(read_field(&mut segment)?)
+                    .map_err(|error| ReplayError::CorruptedRecord { reason: error.to_string() })?;
+                let query = read_field(&mut segment)?;
+                let offset = segment.stream_position()?;
+                read_field(&mut segment)?;
+
+                let (key, position) =
+                    session_positions.entry((run_id, session_id)).or_insert_with(|| {
+                        let key = (protocol, query);
+                        let query_sessions = sessions.entry(key.clone()).or_default();
+                        query_sessions
+                            .push_back(RecordedSession { peer_id, response_offsets: Vec::new() });
+                        (key, query_sessions.len() - 1)
+                    });
+                sessions
+                    .get_mut(key)
+                    .and_then(|query_sessions| query_sessions.get_mut(*position))
+                    .expect("The session was added when its first response was read")
+                    .response_offsets
+                    .push((segment_index, offset));
+            }
+            segments.push(segment);
+        }
+        if sessions.is_empty() {
+            return Err(ReplayError::EmptyLog { path: replay_dir.to_path_buf() });
+        }
+        Ok(Self { segments, sessions })
+    }
+
+    fn pop_session(&mut self, protocol: Protocol, query: Vec<u8>) -> Option<RecordedSession> {
+        self.sessions.get_mut(&(protocol.as_str(), query))?.pop_front()
+    }
+
+    fn read_response(&mut self, segment_index: usize, offset: u64) -> Result<Vec<u8>, ReplayError> {
+        let segment = &mut self.segments[segment_index];
+        segment.seek(SeekFrom::Start(offset))?;
+        Ok(read_field(segment)?)
+    }
+}
+
+// Reads a field of a record, which ends only after its last field.
+fn read_field(segment: &mut BufReader<File>) -> io::Result<Vec<u8>> {
+    read_message(segment)?.ok_or_else(|| io::ErrorKind::UnexpectedEof.into())
+}
+
+fn parse_protocol(protocol: &[u8]) -> Result<&'static str, ReplayError> {
+    [Protocol::SignedBlockHeader, Protocol::StateDiff, Protocol::Transaction]
+        .into_iter()
+        .map(|known_protocol| known_protocol.as_str())
+        .find(|known_protocol| known_protocol.as_bytes() == protocol)
+        .ok_or_else(|| ReplayError::CorruptedRecord {
+            reason: format!("Unknown protocol {}", String::from_utf8_lossy(protocol)),
+        })
+}
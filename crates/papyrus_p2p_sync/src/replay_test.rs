@@ -0,0 +1,169 @@
+use std::path::Path;
+use std::sync::Arc;
+use std::time::Duration;
+
+use assert_matches::assert_matches;
+use futures::channel::mpsc::unbounded;
+use libp2p::PeerId;
+use papyrus_network::network_manager::RecordedSqmrResponse;
+use papyrus_network::Protocol;
+use papyrus_protobuf::sync::{
+    BlockHashOrNumber,
+    DataOrFin,
+    Direction,
+    HeaderQuery,
+    Query,
+    SignedBlockHeader,
+};
+use papyrus_storage::header::HeaderStorageReader;
+use papyrus_storage::test_utils::get_test_storage;
+use starknet_api::block::{BlockHeader, BlockNumber};
+use tokio::sync::{watch, RwLock};
+use tokio::time::timeout;
+
+use crate::replay::{record_responses, run_replay, ReplayError};
+use crate::test_utils::{create_block_hashes_and_signatures, HEADER_QUERY_LENGTH};
+use crate::{P2PSyncConfig, P2PSyncError};
+
+const NUM_BLOCKS: u8 = 3;
+const TIMEOUT_FOR_TEST: Duration = Duration::from_secs(5);
+
+// The sync sends a single query for each data type, since it waits for new data longer than the
+// test runs.
+fn replay_config() -> P2PSyncConfig {
+    P2PSyncConfig {
+        num_headers_per_query: HEADER_QUERY_LENGTH,
+        wait_period_for_new_data: Duration::from_secs(3600),
+        max_parallel_state_diff_sessions: 1,
+        follow_tip: false,
+        ..Default::default()
+    }
+}
+
+fn header_query(start_block_number: u64) -> Vec<u8> {
+    HeaderQuery(Query {
+        start_block: BlockHashOrNumber::Number(BlockNumber(start_block_number)),
+        direction: Direction::Forward,
+        limit: HEADER_QUERY_LENGTH,
+        step: 1,
+    })
+    .into()
+}
+
+// Records a session of the given header query that returns NUM_BLOCKS headers.
+async fn record_header_session(record_dir: &Path, query: Vec<u8>) {
+    let (recorder_sender, recorder_receiver) = unbounded();
+    let peer_id = PeerId::random();
+    let block_hashes_and_signatures = create_block_hashes_and_signatures(NUM_BLOCKS);
+    let mut responses = block_hashes_and_signatures
+        .iter()
+        .enumerate()
+        .map(|(i, (block_hash, block_signature))| {
+            DataOrFin(Some(SignedBlockHeader {
+                block_header: BlockHeader {
+                    block_number: BlockNumber(i.try_into().unwrap()),
+                    block_hash: *block_hash,
+                    parent_hash: i
+                        .checked_sub(1)
+                        .map(|parent| block_hashes_and_signatures[parent].0)
+                        .unwrap_or_default(),
+                    state_diff_length: Some(0),
+                    ..Default::default()
+                },
+                signatures: vec![*block_signature],
+                data_availability: None,
+            }))
+        })
+        .collect::<Vec<_>>();
+    responses.push(DataOrFin(None));
+    for response in responses {
+        recorder_sender
+            .unbounded_send(RecordedSqmrResponse {
+                protocol: Protocol::SignedBlockHeader,
+                session_id: 0,
+                query: query.clone(),
+                peer_id,
+                response: response.into(),
+            })
+            .unwrap();
+    }
+    drop(recorder_sender);
+    record_responses(record_dir.to_path_buf(), recorder_receiver).await.unwrap();
+}
+
+#[tokio::test]
+async fn recorded_responses_are_replayed_into_the_storage() {
+    let record_dir = tempfile::tempdir().unwrap();
+    record_header_session(record_dir.path(), header_query(0)).await;
+
+    let ((storage_reader, storage_writer), _temp_dir) = get_test_storage();
+    let (header_marker_sender, _header_marker_receiver) = watch::channel(BlockNumber(0));
+    let replay = run_replay(
+        replay_config(),
+        record_dir.path(),
+        storage_reader.clone(),
+        storage_writer,
+        Arc::new(RwLock::new(None)),
+        header_marker_sender,
+    );
+    let wait_for_headers = async {
+        while storage_reader.begin_ro_txn().unwrap().get_header_marker().unwrap()
+            < BlockNumber(NUM_BLOCKS.into())
+        {
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    };
+
+    tokio::select! {
+        result = replay => panic!("The replay stopped: {result:?}"),
+        result = timeout(TIMEOUT_FOR_TEST, wait_for_headers) => result.unwrap(),
+    }
+}
+
+#[tokio::test]
+async fn query_that_was_not_recorded_fails_the_replay() {
+    let record_dir = tempfile::tempdir().unwrap();
+    // The sync starts from block 0, so it never sends this query.
+    record_header_session(record_dir.path(), header_query(1)).await;
+
+    let ((storage_reader, storage_writer), _temp_dir) = get_test_storage();
+    let (header_marker_sender, _header_marker_receiver) = watch::channel(BlockNumber(0));
+    let result = timeout(
+        TIMEOUT_FOR_TEST,
+        run_replay(
+            replay_config(),
+            record_dir.path(),
+            storage_reader,
+            storage_writer,
+            Arc::new(RwLock::new(None)),
+            header_marker_sender,
+        ),
+    )
+    .await
+    .unwrap();
+
+    assert_matches!(
+        result,
+        Err(P2PSyncError::ReplayError(ReplayError::QueryNotRecorded { protocol, .. }))
+        if protocol == Protocol::SignedBlockHeader.as_str()
+    );
+}
+
+#[tokio::test]
+async fn replay_of_an_empty_log_fails() {
+    let record_dir = tempfile::tempdir().unwrap();
+    let ((storage_reader, storage_writer), _temp_dir) = get_test_storage();
+    let (header_marker_sender, _header_marker_receiver) = watch::channel(BlockNumber(0));
+
+    let result = run_replay(
+        replay_config(),
+        record_dir.path(),
+        storage_reader,
+        storage_writer,
+        Arc::new(RwLock::new(None)),
+        header_marker_sender,
+    )
+    .await;
+
+    assert_matches!(result, Err(P2PSyncError::ReplayError(ReplayError::EmptyLog { .. })));
+}
@@ -0,0 +1,315 @@
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use std::time::Duration;
+
+use futures::{Stream, StreamExt};
+use papyrus_protobuf::sync::{BlockHashOrNumber, DataOrFin, Direction, Query, SignedBlockHeader};
+use starknet_api::block::{BlockHash, BlockNumber};
+use starknet_api::state::ThinStateDiff;
+use starknet_api::transaction::{Transaction, TransactionOutput};
+use tracing::warn;
+
+use crate::Response;
+
+/// Data received from the network whose position in the query's response stream can be checked
+/// before the data is parsed into blocks.
+pub trait ValidatedResponse {
+    /// Whether the protocol sends exactly one response per block, so a query with limit L should
+    /// get at most L responses before its Fin.
+    const ONE_RESPONSE_PER_BLOCK: bool;
+
+    /// The number of the block the response belongs to, if the response states it.
+    fn block_number(&self) -> Option<BlockNumber>;
+
+    /// The hash of the block the response belongs to and the hash of its parent, if the response
+    /// states them.
+    fn block_hash_and_parent_hash(&self) -> Option<(BlockHash, BlockHash)>;
+}
+
+impl ValidatedResponse for SignedBlockHeader {
+    const ONE_RESPONSE_PER_BLOCK: bool = true;
+
+    fn block_number(&self) -> Option<BlockNumber> {
+        Some(self.block_header.block_number)
+    }
+
+    fn block_hash_and_parent_hash(&self) -> Option<(BlockHash, BlockHash)> {
+        Some((self.block_header.block_hash, self.block_header.parent_hash))
+    }
+}
+
+// State diff parts don't state the block they belong to. They're matched to their block through
+// the state diff length in the block's header.
+// TODO(shahak): Change to StateDiffChunk.
+impl ValidatedResponse for ThinStateDiff {
+    const ONE_RESPONSE_PER_BLOCK: bool = false;
+
+    fn block_number(&self) -> Option<BlockNumber> {
+        None
+    }
+
+    fn block_hash_and_parent_hash(&self) -> Option<(BlockHash, BlockHash)> {
+        None
+    }
+}
+
+// Transactions don't state the block they belong to either. They're matched to their block through
+// the number of transactions in the block's header.
+impl ValidatedResponse for (Transaction, Option<TransactionOutput>) {
+    const ONE_RESPONSE_PER_BLOCK: bool = false;
+
+    fn block_number(&self) -> Option<BlockNumber> {
+        None
+    }
+
+    fn block_hash_and_parent_hash(&self) -> Option<(BlockHash, BlockHash)> {
+        None
+    }
+}
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum ResponseViolation {
+    #[error("Got more than {limit} responses for a query with limit {limit}.")]
+    TooManyResponses { limit: u64 },
+    #[error("Expected a response for block {expected_block_number}, got {actual_block_number}.")]
+    UnexpectedBlockNumber { expected_block_number: BlockNumber, actual_block_number: BlockNumber },
+    #[error(
+        "Block {block_number} has parent hash {actual_parent_hash:?}, but the hash of the \
+         previous block in the response is {expected_parent_hash:?}."
+    )]
+    ParentHashMismatch {
+        block_number: BlockNumber,
+        expected_parent_hash: BlockHash,
+        actual_parent_hash: BlockHash,
+    },
+    #[error(
+        "Block {block_number} has hash {actual_block_hash:?}, but the parent hash of the previous \
+         block in the descending response is {expected_block_hash:?}."
+    )]
+    BlockHashMismatch {
+        block_number: BlockNumber,
+        expected_block_hash: BlockHash,
+        actual_block_hash: BlockHash,
+    },
+    #[error(
+        "Got block {actual_block_number} after block 0 in the response to a descending query."
+    )]
+    BlockBeforeGenesis { actual_block_number: BlockNumber },
+    #[error(
+        "The response to a query from block hash {expected_block_hash:?} starts with block hash \
+         {actual_block_hash:?}."
+    )]
+    UnexpectedStartBlockHash { expected_block_hash: BlockHash, actual_block_hash: BlockHash },
+}
+
+enum SessionState {
+    // Responses pass through unchecked, since we can't tell which blocks they belong to.
+    Unchecked,
+    Checked(Session),
+    Aborted,
+}
+
+struct Session {
+    limit: u64,
+    step: u64,
+    direction: Direction,
+    num_responses: u64,
+    num_blocks: u64,
+    // None once a descending session passed block 0.
+    next_block_number: Option<BlockNumber>,
+    last_block_number: Option<BlockNumber>,
+    // The hash the next block is linked to. In an ascending session it's the hash of the last
+    // block, which should be the parent hash of the next block. In a descending session it's the
+    // parent hash of the last block, which should be the hash of the next block.
+    linked_hash: Option<BlockHash>,
+}
+
+/// Wraps the receiver of a protocol's responses and checks each response against the query that
+/// is currently in flight, before it's handed to the parsing and storage writing steps.
+///
+/// A response that violates the query is reported to the peer manager and discarded, and the
+/// session is aborted: the receiver returns a Fin in place of the response and drops the rest of
+/// the session's responses until the next query is started. Since the network can't tell us which
+/// session a response came from, responses of an aborted session that arrive after the next query
+/// was started are checked against the next query.
+pub(crate) struct ValidatedResponseReceiver<DataReceiver> {
+    data_receiver: DataReceiver,
+    session_state: SessionState,
+    type_description: &'static str,
+    // The violation that aborted the current session.
+    violation: Option<ResponseViolation>,
+}
+
+impl<DataReceiver> ValidatedResponseReceiver<DataReceiver> {
+    pub(crate) fn new(data_receiver: DataReceiver, type_description: &'static str) -> Self {
+        Self {
+            data_receiver,
+            session_state: SessionState::Unchecked,
+            type_description,
+            violation: None,
+        }
+    }
+
+    /// Starts checking the responses against the given query. `previous_block_hash` is the hash
+    /// of the block before the query's first block, if it's known. It's ignored for descending
+    /// queries.
+    pub(crate) fn start_session(&mut self, query: &Query, previous_block_hash: Option<BlockHash>) {
+        self.violation = None;
+        let BlockHashOrNumber::Number(start_block_number) = query.start_block else {
+            self.session_state = SessionState::Unchecked;
+            return;
+        };
+        self.session_state = SessionState::Checked(Session {
+            limit: query.limit,
+            step: query.step,
+            direction: query.direction,
+            num_responses: 0,
+            num_blocks: 0,
+            next_block_number: Some(start_block_number),
+            last_block_number: None,
+            linked_hash: match query.direction {
+                Direction::Forward => previous_block_hash,
+                Direction::Backward => None,
+            },
+        });
+    }
+
+    /// Returns the violation that aborted the current session, if it was aborted. The Fin the
+    /// receiver returned in place of the violating response can be told apart from a Fin of the
+    /// peer this way.
+    pub(crate) fn take_violation(&mut self) -> Option<ResponseViolation> {
+        self.violation.take()
+    }
+}
+
+impl<DataReceiver, InputFromNetwork> ValidatedResponseReceiver<DataReceiver>
+where
+    DataReceiver: Stream<Item = Response<InputFromNetwork>> + Unpin,
+{
+    /// Drops the rest of the responses of the aborted session until the peer ends it, so that they
+    /// aren't checked against the next query. Gives up once no response arrived for `timeout`.
+    pub(crate) async fn skip_rest_of_aborted_session(&mut self, timeout: Duration) {
+        while let Ok(Some((Ok(DataOrFin(Some(_))), _report_callback))) =
+            tokio::time::timeout(timeout, self.data_receiver.next()).await
+        {}
+    }
+}
+
+impl Session {
+    fn validate<InputFromNetwork: ValidatedResponse>(
+        &mut self,
+        data: &InputFromNetwork,
+    ) -> Result<(), ResponseViolation> {
+        self.num_responses += 1;
+        if InputFromNetwork::ONE_RESPONSE_PER_BLOCK && self.num_responses > self.limit {
+            return Err(ResponseViolation::TooManyResponses { limit: self.limit });
+        }
+        let Some(block_number) = data.block_number() else {
+            return Ok(());
+        };
+        let continues_last_block = !InputFromNetwork::ONE_RESPONSE_PER_BLOCK
+            && self.last_block_number == Some(block_number);
+        if !continues_last_block {
+            if self.num_blocks >= self.limit {
+                return Err(ResponseViolation::TooManyResponses { limit: self.limit });
+            }
+            let Some(next_block_number) = self.next_block_number else {
+                return Err(ResponseViolation::BlockBeforeGenesis {
+                    actual_block_number: block_number,
+                });
+            };
+            if block_number != next_block_number {
+                return Err(ResponseViolation::UnexpectedBlockNumber {
+                    expected_block_number: next_block_number,
+                    actual_block_number: block_number,
+                });
+            }
+            if let Some((block_hash, parent_hash)) = data.block_hash_and_parent_hash() {
+                self.validate_linkage(block_number, block_hash, parent_hash)?;
+            }
+            self.num_blocks += 1;
+            self.last_block_number = Some(block_number);
+            self.next_block_number = match self.direction {
+                Direction::Forward => Some(BlockNumber(block_number.0.saturating_add(self.step))),
+                Direction::Backward => block_number.0.checked_sub(self.step).map(BlockNumber),
+            };
+        }
+        Ok(())
+    }
+
+    fn validate_linkage(
+        &mut self,
+        block_number: BlockNumber,
+        block_hash: BlockHash,
+        parent_hash: BlockHash,
+    ) -> Result<(), ResponseViolation> {
+        // Linkage is meaningful only for consecutive blocks.
+        if let (Some(linked_hash), 1) = (self.linked_hash, self.step) {
+            match self.direction {
+                Direction::Forward if parent_hash != linked_hash => {
+                    return Err(ResponseViolation::ParentHashMismatch {
+                        block_number,
+                        expected_parent_hash: linked_hash,
+                        actual_parent_hash: parent_hash,
+                    });
+                }
+                Direction::Backward if block_hash != linked_hash => {
+                    return Err(ResponseViolation::BlockHashMismatch {
+                        block_number,
+                        expected_block_hash: linked_hash,
+                        actual_block_hash: block_hash,
+                    });
+                }
+                _ => {}
+            }
+        }
+        self.linked_hash = Some(match self.direction {
+            Direction::Forward => block_hash,
+            Direction::Backward => parent_hash,
+        });
+        Ok(())
+    }
+}
+
+impl<DataReceiver, InputFromNetwork> Stream for ValidatedResponseReceiver<DataReceiver>
+where
+    DataReceiver: Stream<Item = Response<InputFromNetwork>> + Unpin,
+    InputFromNetwork: ValidatedResponse,
+{
+    type Item = Response<InputFromNetwork>;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<Self::Item>> {
+        let this = &mut *self;
+        loop {
+            let Some((maybe_data, report_callback)) =
+                futures::ready!(Pin::new(&mut this.data_receiver).poll_next(cx))
+            else {
+                return Poll::Ready(None);
+            };
+            let session = match &mut this.session_state {
+                SessionState::Unchecked => {
+                    return Poll::Ready(Some((maybe_data, report_callback)));
+                }
+                SessionState::Checked(session) => session,
+                // The rest of an aborted session.
+                SessionState::Aborted if maybe_data.is_ok() => continue,
+                SessionState::Aborted => return Poll::Ready(Some((maybe_data, report_callback))),
+            };
+            let Ok(DataOrFin(Some(data))) = &maybe_data else {
+                return Poll::Ready(Some((maybe_data, report_callback)));
+            };
+            if let Err(violation) = session.validate(data) {
+                warn!(
+                    "Discarding {} response that violates the query and aborting its session: \
+                     {violation}",
+                    this.type_description
+                );
+                report_callback();
+                this.session_state = SessionState::Aborted;
+                this.violation = Some(violation);
+                return Poll::Ready(Some((Ok(DataOrFin(None)), Box::new(|| {}))));
+            }
+            return Poll::Ready(Some((maybe_data, report_callback)));
+        }
+    }
+}
@@ -0,0 +1,231 @@
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+
+use futures::channel::mpsc::{self, Sender};
+use futures::{FutureExt, SinkExt, StreamExt};
+use papyrus_protobuf::sync::{BlockHashOrNumber, DataOrFin, Direction, Query, SignedBlockHeader};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use starknet_api::block::{BlockHash, BlockHeader, BlockNumber};
+use starknet_api::state::ThinStateDiff;
+use starknet_types_core::felt::Felt;
+use test_utils::get_rng;
+
+use crate::response_validator::ValidatedResponseReceiver;
+use crate::test_utils::BUFFER_SIZE;
+use crate::Response;
+
+const START_BLOCK_NUMBER: BlockNumber = BlockNumber(10);
+const QUERY_LIMIT: u64 = 5;
+const NUM_ITERATIONS: usize = 100;
+
+fn query() -> Query {
+    Query {
+        start_block: BlockHashOrNumber::Number(START_BLOCK_NUMBER),
+        direction: Direction::Forward,
+        limit: QUERY_LIMIT,
+        step: 1,
+    }
+}
+
+fn block_hash(block_number: u64) -> BlockHash {
+    BlockHash(Felt::from(block_number + 1))
+}
+
+// The headers of the given number of blocks starting from START_BLOCK_NUMBER, where each header
+// points to the previous one.
+fn chained_headers(num_blocks: u64) -> Vec<SignedBlockHeader> {
+    (START_BLOCK_NUMBER.0..START_BLOCK_NUMBER.0 + num_blocks)
+        .map(|block_number| SignedBlockHeader {
+            block_header: BlockHeader {
+                block_number: BlockNumber(block_number),
+                block_hash: block_hash(block_number),
+                parent_hash: block_hash(block_number - 1),
+                ..Default::default()
+            },
+            signatures: vec![],
+            data_availability: None,
+        })
+        .collect()
+}
+
+async fn send_responses<T>(
+    sender: &mut Sender<Response<T>>,
+    responses: Vec<DataOrFin<T>>,
+    num_reports: &Arc<AtomicUsize>,
+) {
+    for response in responses {
+        let num_reports = num_reports.clone();
+        sender
+            .send((
+                Ok(response),
+                Box::new(move || {
+                    num_reports.fetch_add(1, Ordering::SeqCst);
+                }),
+            ))
+            .await
+            .unwrap();
+    }
+}
+
+// Returns the data the receiver passed on until it passed on a Fin.
+async fn receive_until_fin<T>(
+    receiver: &mut ValidatedResponseReceiver<mpsc::Receiver<Response<T>>>,
+) -> Vec<T> {
+    let mut result = vec![];
+    loop {
+        let (maybe_data, _report_callback) = receiver.next().await.unwrap();
+        match maybe_data.unwrap().0 {
+            Some(data) => result.push(data),
+            None => return result,
+        }
+    }
+}
+
+#[tokio::test]
+async fn responses_that_match_the_query_pass() {
+    let (mut sender, receiver) = mpsc::channel(BUFFER_SIZE);
+    let mut receiver = ValidatedResponseReceiver::new(receiver, "headers");
+    let num_reports = Arc::new(AtomicUsize::new(0));
+    receiver.start_session(&query(), Some(block_hash(START_BLOCK_NUMBER.0 - 1)));
+
+    let headers = chained_headers(QUERY_LIMIT);
+    let mut responses: Vec<_> =
+        headers.iter().cloned().map(|header| DataOrFin(Some(header))).collect();
+    responses.push(DataOrFin(None));
+    send_responses(&mut sender, responses, &num_reports).await;
+
+    assert_eq!(receive_until_fin(&mut receiver).await, headers);
+    assert_eq!(num_reports.load(Ordering::SeqCst), 0);
+}
+
+// Perturbs a valid response stream at random and checks that the receiver passes on exactly the
+// longest valid prefix of it, and reports the peer if and only if the stream was invalid.
+#[tokio::test]
+async fn shuffled_duplicated_and_excess_headers_are_caught() {
+    let mut rng = get_rng();
+    let valid_headers = chained_headers(QUERY_LIMIT);
+    let excess_header = chained_headers(QUERY_LIMIT + 1).pop().unwrap();
+
+    for _ in 0..NUM_ITERATIONS {
+        let mut headers = valid_headers.clone();
+        match rng.gen_range(0..4) {
+            0 => headers.shuffle(&mut rng),
+            1 => {
+                let index = rng.gen_range(0..headers.len());
+                headers.insert(index + 1, headers[index].clone());
+            }
+            2 => headers.push(excess_header.clone()),
+            _ => {
+                let index = rng.gen_range(0..headers.len());
+                headers[index].block_header.parent_hash = BlockHash(Felt::from(rng.gen::<u64>()));
+            }
+        }
+        let valid_prefix_len = headers
+            .iter()
+            .zip(valid_headers.iter())
+            .take_while(|(header, valid_header)| header == valid_header)
+            .count();
+        let is_valid = valid_prefix_len == headers.len();
+
+        let (mut sender, receiver) = mpsc::channel(BUFFER_SIZE);
+        let mut receiver = ValidatedResponseReceiver::new(receiver, "headers");
+        let num_reports = Arc::new(AtomicUsize::new(0));
+        receiver.start_session(&query(), Some(block_hash(START_BLOCK_NUMBER.0 - 1)));
+        let mut responses: Vec<_> =
+            headers.iter().cloned().map(|header| DataOrFin(Some(header))).collect();
+        responses.push(DataOrFin(None));
+        send_responses(&mut sender, responses, &num_reports).await;
+
+        assert_eq!(receive_until_fin(&mut receiver).await, headers[..valid_prefix_len]);
+        assert_eq!(num_reports.load(Ordering::SeqCst), usize::from(!is_valid));
+    }
+}
+
+#[tokio::test]
+async fn aborted_session_responses_are_dropped_until_next_session() {
+    let (mut sender, receiver) = mpsc::channel(BUFFER_SIZE);
+    let mut receiver = ValidatedResponseReceiver::new(receiver, "headers");
+    let num_reports = Arc::new(AtomicUsize::new(0));
+    receiver.start_session(&query(), None);
+
+    let headers = chained_headers(QUERY_LIMIT);
+    // The second header is missing, so the session is aborted on the third one.
+    send_responses(
+        &mut sender,
+        vec![
+            DataOrFin(Some(headers[0].clone())),
+            DataOrFin(Some(headers[2].clone())),
+            DataOrFin(Some(headers[3].clone())),
+            DataOrFin(None),
+        ],
+        &num_reports,
+    )
+    .await;
+    assert_eq!(receive_until_fin(&mut receiver).await, vec![headers[0].clone()]);
+    assert_eq!(num_reports.load(Ordering::SeqCst), 1);
+    // The rest of the aborted session is dropped without reporting it again.
+    assert!(receiver.next().now_or_never().is_none());
+    assert_eq!(num_reports.load(Ordering::SeqCst), 1);
+
+    let continuation_query = Query {
+        start_block: BlockHashOrNumber::Number(headers[1].block_header.block_number),
+        limit: QUERY_LIMIT - 1,
+        ..query()
+    };
+    receiver.start_session(&continuation_query, Some(headers[0].block_header.block_hash));
+    let mut responses: Vec<_> =
+        headers[1..].iter().cloned().map(|header| DataOrFin(Some(header))).collect();
+    responses.push(DataOrFin(None));
+    send_responses(&mut sender, responses, &num_reports).await;
+    assert_eq!(receive_until_fin(&mut receiver).await, headers[1..]);
+    assert_eq!(num_reports.load(Ordering::SeqCst), 1);
+}
+
+#[tokio::test]
+async fn state_diff_parts_are_not_limited_by_the_number_of_blocks() {
+    let (mut sender, receiver) = mpsc::channel(BUFFER_SIZE);
+    let mut receiver = ValidatedResponseReceiver::new(receiver, "state diffs");
+    let num_reports = Arc::new(AtomicUsize::new(0));
+    receiver.start_session(&query(), None);
+
+    let num_parts = usize::try_from(QUERY_LIMIT).unwrap() * 2;
+    let mut responses = vec![DataOrFin(Some(ThinStateDiff::default())); num_parts];
+    responses.push(DataOrFin(None));
+    send_responses(&mut sender, responses, &num_reports).await;
+
+    assert_eq!(receive_until_fin(&mut receiver).await.len(), num_parts);
+    assert_eq!(num_reports.load(Ordering::SeqCst), 0);
+}
+
+#[tokio::test]
+async fn descending_headers_are_linked_through_their_parent_hashes() {
+    let (mut sender, receiver) = mpsc::channel(BUFFER_SIZE);
+    let mut receiver = ValidatedResponseReceiver::new(receiver, "headers");
+    let num_reports = Arc::new(AtomicUsize::new(0));
+    let descending_query = Query {
+        start_block: BlockHashOrNumber::Number(BlockNumber(START_BLOCK_NUMBER.0 + QUERY_LIMIT - 1)),
+        direction: Direction::Backward,
+        ..query()
+    };
+
+    let mut headers = chained_headers(QUERY_LIMIT);
+    headers.reverse();
+    receiver.start_session(&descending_query, None);
+    let mut responses: Vec<_> =
+        headers.iter().cloned().map(|header| DataOrFin(Some(header))).collect();
+    responses.push(DataOrFin(None));
+    send_responses(&mut sender, responses, &num_reports).await;
+    assert_eq!(receive_until_fin(&mut receiver).await, headers);
+    assert_eq!(num_reports.load(Ordering::SeqCst), 0);
+
+    // The third block isn't the parent of the second one.
+    headers[2].block_header.block_hash = BlockHash(Felt::from(u64::MAX));
+    receiver.start_session(&descending_query, None);
+    let mut responses: Vec<_> =
+        headers.iter().cloned().map(|header| DataOrFin(Some(header))).collect();
+    responses.push(DataOrFin(None));
+    send_responses(&mut sender, responses, &num_reports).await;
+    assert_eq!(receive_until_fin(&mut receiver).await, headers[..2]);
+    assert_eq!(num_reports.load(Ordering::SeqCst), 1);
+}
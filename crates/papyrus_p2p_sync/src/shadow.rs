@@ -0,0 +1,516 @@
+//! Shadow mode of the p2p sync, for checking the p2p sync against the central sync before trusting
+//! it. The central sync writes the blocks, and the shadow sync downloads the headers and state
+//! diffs of the same blocks from peers and compares them with what the central sync wrote. Every
+//! difference is logged with the block, the field, both values and the peer that sent it, so that
+//! it can be reported against whichever implementation is wrong. Nothing is written to the
+//! storage.
+//!
+//! The comparison starts from the blocks the central sync writes after the shadow sync started,
+//! so that a node that already synced the chain doesn't download it again.
+
+use std::collections::{HashMap, HashSet, VecDeque};
+use std::fmt::Debug;
+use std::hash::Hash;
+
+use futures::channel::mpsc::{SendError, UnboundedReceiver};
+use futures::{Sink, SinkExt, Stream, StreamExt};
+use indexmap::IndexMap;
+use libp2p::PeerId;
+use metrics::increment_counter;
+use papyrus_common::metrics as papyrus_metrics;
+use papyrus_network::network_manager::{RecordedSqmrResponse, SqmrResponseError};
+use papyrus_network::Protocol;
+use papyrus_protobuf::sync::{
+    BlockHashOrNumber,
+    DataOrFin,
+    Direction,
+    HeaderQuery,
+    Query,
+    SignedBlockHeader,
+    StateDiffQuery,
+};
+use papyrus_storage::header::HeaderStorageReader;
+use papyrus_storage::state::StateStorageReader;
+use papyrus_storage::StorageReader;
+use starknet_api::block::{BlockHeader, BlockNumber, BlockSignature};
+use starknet_api::state::ThinStateDiff;
+use tracing::{debug, error, info, warn};
+
+use crate::state_diff::unite_state_diffs;
+use crate::{P2PSyncConfig, P2PSyncError, Response, NETWORK_DATA_TIMEOUT, STEP};
+
+/// A field of a block whose value from a peer differs from the value the central sync wrote. A
+/// value that's missing on one side is shown as None.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub(crate) struct FieldDivergence {
+    pub field: String,
+    pub central_value: String,
+    pub peer_value: String,
+}
+
+pub struct ShadowSync<
+    HeaderQuerySender,
+    HeaderResponseReceiver,
+    StateDiffQuerySender,
+    StateDiffResponseReceiver,
+> {
+    config: P2PSyncConfig,
+    storage_reader: StorageReader,
+    header_query_sender: HeaderQuerySender,
+    header_response_receiver: HeaderResponseReceiver,
+    state_diff_query_sender: StateDiffQuerySender,
+    state_diff_response_receiver: StateDiffResponseReceiver,
+    serving_peers: ServingPeers,
+}
+
+impl<HeaderQuerySender, HeaderResponseReceiver, StateDiffQuerySender, StateDiffResponseReceiver>
+    ShadowSync<
+        HeaderQuerySender,
+        HeaderResponseReceiver,
+        StateDiffQuerySender,
+        StateDiffResponseReceiver,
+    >
+where
+    HeaderQuerySender: Sink<HeaderQuery, Error = SendError> + Unpin + Send + 'static,
+    HeaderResponseReceiver: Stream<Item = Response<SignedBlockHeader>> + Unpin + Send + 'static,
+    StateDiffQuerySender: Sink<StateDiffQuery, Error = SendError> + Unpin + Send + 'static,
+    StateDiffResponseReceiver: Stream<Item = Response<ThinStateDiff>> + Unpin + Send + 'static,
+{
+    /// `recorded_responses` should receive the recorded responses of both protocols (see
+    /// `record_sqmr_responses` of the network manager). They're used only for telling which peer
+    /// sent each response, so the state diffs should be queried through a single lane.
+    pub fn new(
+        config: P2PSyncConfig,
+        storage_reader: StorageReader,
+        header_query_sender: HeaderQuerySender,
+        header_response_receiver: HeaderResponseReceiver,
+        state_diff_query_sender: StateDiffQuerySender,
+        state_diff_response_receiver: StateDiffResponseReceiver,
+        recorded_responses: UnboundedReceiver<RecordedSqmrResponse>,
+    ) -> Self {
+        Self {
+            config,
+            storage_reader,
+            header_query_sender,
+            header_response_receiver,
+            state_diff_query_sender,
+            state_diff_response_receiver,
+            serving_peers: ServingPeers { recorded_responses, pending_peers: HashMap::new() },
+        }
+    }
+
+    pub async fn run(mut self) -> Result<(), P2PSyncError> {
+        let txn = self.storage_reader.begin_ro_txn()?;
+        let mut next_header_block_number = txn.get_header_marker()?;
+        let mut next_state_diff_block_number = txn.get_state_marker()?;
+        drop(txn);
+        info!(
+            "Comparing the blocks the central sync writes with the blocks of peers, from header \
+             {next_header_block_number} and state diff {next_state_diff_block_number} on."
+        );
+        loop {
+            let txn = self.storage_reader.begin_ro_txn()?;
+            // The blocks the central sync reverted are compared again once it rewrites them.
+            let header_marker = txn.get_header_marker()?;
+            next_header_block_number = next_header_block_number.min(header_marker);
+            let state_marker = txn.get_state_marker()?;
+            next_state_diff_block_number = next_state_diff_block_number.min(state_marker);
+            drop(txn);
+
+            let mut compared_any_block = false;
+            if next_header_block_number < header_marker {
+                let limit = (header_marker.0 - next_header_block_number.0)
+                    .min(self.config.num_headers_per_query);
+                let compared_until = self.compare_headers(next_header_block_number, limit).await?;
+                compared_any_block |= compared_until > next_header_block_number;
+                next_header_block_number = compared_until;
+            }
+            if next_state_diff_block_number < state_marker {
+                let limit = (state_marker.0 - next_state_diff_block_number.0)
+                    .min(self.config.num_block_state_diffs_per_query);
+                let compared_until =
+                    self.compare_state_diffs(next_state_diff_block_number, limit).await?;
+                compared_any_block |= compared_until > next_state_diff_block_number;
+                next_state_diff_block_number = compared_until;
+            }
+            // Either the central sync didn't write new blocks, or the peers don't have them yet.
+            if !compared_any_block {
+                tokio::time::sleep(self.config.wait_period_for_new_data).await;
+            }
+        }
+    }
+
+    // Queries the headers of the given blocks and compares each header the peer sends. Returns the
+    // first block that wasn't compared.
+    async fn compare_headers(
+        &mut self,
+        start_block_number: BlockNumber,
+        limit: u64,
+    ) -> Result<BlockNumber, P2PSyncError> {
+        debug!("Comparing {limit} headers from block {start_block_number}.");
+        self.header_query_sender.send(HeaderQuery(create_query(start_block_number, limit))).await?;
+        let mut next_block_number = start_block_number;
+        loop {
+            let (signed_header, peer_id) = match next_response(
+                &mut self.header_response_receiver,
+                &mut self.serving_peers,
+                Protocol::SignedBlockHeader,
+            )
+            .await?
+            {
+                ShadowResponse::Data(signed_header, peer_id) => (signed_header, peer_id),
+                ShadowResponse::Fin(_) | ShadowResponse::Failed => return Ok(next_block_number),
+            };
+            // The header is compared with the header of its own block number, so that a late
+            // response to an earlier query isn't compared with a different block.
+            let block_number = signed_header.block_header.block_number;
+            let txn = self.storage_reader.begin_ro_txn()?;
+            let Some(central_header) = txn.get_block_header(block_number)? else {
+                warn!(
+                    "Peer {peer_id} sent the header of block {block_number}, which the central \
+                     sync didn't write yet."
+                );
+                continue;
+            };
+            let central_signature = txn.get_block_signature(block_number)?;
+            let divergences =
+                header_divergences(&central_header, central_signature.as_ref(), &signed_header);
+            report_comparison("header", block_number, Some(peer_id), &divergences);
+            if block_number == next_block_number {
+                next_block_number = next_block_number.unchecked_next();
+            }
+        }
+    }
+
+    // Queries the state diffs of the given blocks and compares the state diff of each block the
+    // peer sends. Returns the first block that wasn't compared.
+    async fn compare_state_diffs(
+        &mut self,
+        start_block_number: BlockNumber,
+        limit: u64,
+    ) -> Result<BlockNumber, P2PSyncError> {
+        debug!("Comparing {limit} state diffs from block {start_block_number}.");
+        self.state_diff_query_sender
+            .send(StateDiffQuery(create_query(start_block_number, limit)))
+            .await?;
+        let end_block_number = BlockNumber(start_block_number.0 + limit);
+        let mut block_number = start_block_number;
+        while block_number < end_block_number {
+            let central_state_diff = self
+                .storage_reader
+                .begin_ro_txn()?
+                .get_state_diff(block_number)?
+                .expect("A state diff below the state marker is missing");
+            // The parts of a state diff aren't numbered, so the parts of a block are received
+            // until they're as long as the state diff the central sync wrote.
+            let mut peer_state_diff = ThinStateDiff::default();
+            let mut serving_peer = None;
+            let mut is_response_finished = false;
+            while peer_state_diff.len() < central_state_diff.len() {
+                match next_response(
+                    &mut self.state_diff_response_receiver,
+                    &mut self.serving_peers,
+                    Protocol::StateDiff,
+                )
+                .await?
+                {
+                    ShadowResponse::Data(state_diff_part, peer_id) => {
+                        serving_peer = Some(peer_id);
+                        if let Err(error) = unite_state_diffs(&mut peer_state_diff, state_diff_part)
+                        {
+                            warn!(
+                                "Peer {peer_id} sent invalid state diff parts for block \
+                                 {block_number}: {error}"
+                            );
+                            return Ok(block_number);
+                        }
+                    }
+                    // The peer doesn't have the block yet.
+                    ShadowResponse::Fin(_) if serving_peer.is_none() => return Ok(block_number),
+                    ShadowResponse::Fin(peer_id) => {
+                        serving_peer = Some(peer_id);
+                        is_response_finished = true;
+                        break;
+                    }
+                    ShadowResponse::Failed => return Ok(block_number),
+                }
+            }
+            let divergences = state_diff_divergences(&central_state_diff, &peer_state_diff);
+            report_comparison("state_diff", block_number, serving_peer, &divergences);
+            block_number = block_number.unchecked_next();
+            if is_response_finished {
+                return Ok(block_number);
+            }
+        }
+        // Consume the Fin, so that it isn't taken as the end of the next response.
+        if let ShadowResponse::Data(_, peer_id) = next_response(
+            &mut self.state_diff_response_receiver,
+            &mut self.serving_peers,
+            Protocol::StateDiff,
+        )
+        .await?
+        {
+            warn!(
+                "Peer {peer_id} sent more state diff parts than the central sync wrote for blocks \
+                 [{start_block_number}, {end_block_number})."
+            );
+        }
+        Ok(block_number)
+    }
+}
+
+// A response the shadow sync received, with the peer that sent it.
+enum ShadowResponse<T> {
+    Data(T, PeerId),
+    Fin(PeerId),
+    // The query should be sent again, since no response arrived in time or the response can't be
+    // read.
+    Failed,
+}
+
+async fn next_response<T, ResponseReceiver>(
+    response_receiver: &mut ResponseReceiver,
+    serving_peers: &mut ServingPeers,
+    protocol: Protocol,
+) -> Result<ShadowResponse<T>, P2PSyncError>
+where
+    ResponseReceiver: Stream<Item = Response<T>> + Unpin,
+{
+    let response = match tokio::time::timeout(NETWORK_DATA_TIMEOUT, response_receiver.next()).await
+    {
+        Ok(Some((response, _report_callback))) => response,
+        Ok(None) => {
+            return Err(P2PSyncError::ReceiverChannelTerminated {
+                type_description: protocol.as_str(),
+            });
+        }
+        Err(_) => {
+            info!("Timed out waiting for a {protocol} response. Sending the query again.");
+            return Ok(ShadowResponse::Failed);
+        }
+    };
+    // A query that wasn't sent to any peer has no recorded response.
+    if let Err(SqmrResponseError::Query(error)) = response {
+        warn!("Failed to send a {protocol} query: {error} Sending it again.");
+        return Ok(ShadowResponse::Failed);
+    }
+    let peer_id = serving_peers.next(protocol).await?;
+    match response {
+        Ok(DataOrFin(Some(data))) => Ok(ShadowResponse::Data(data, peer_id)),
+        Ok(DataOrFin(None)) => Ok(ShadowResponse::Fin(peer_id)),
+        Err(error) => {
+            warn!("Peer {peer_id} sent an invalid {protocol} response: {error}");
+            Ok(ShadowResponse::Failed)
+        }
+    }
+}
+
+// Tells which peer sent each response. The network records each response right before it sends it
+// to the sync, and the responses of each protocol arrive through a single lane, so the recorded
+// responses of a protocol are in the order the sync receives them.
+struct ServingPeers {
+    recorded_responses: UnboundedReceiver<RecordedSqmrResponse>,
+    // The peers of the recorded responses of each protocol that the sync didn't receive yet.
+    pending_peers: HashMap<Protocol, VecDeque<PeerId>>,
+}
+
+impl ServingPeers {
+    async fn next(&mut self, protocol: Protocol) -> Result<PeerId, P2PSyncError> {
+        loop {
+            if let Some(peer_id) =
+                self.pending_peers.get_mut(&protocol).and_then(|peers| peers.pop_front())
+            {
+                return Ok(peer_id);
+            }
+            let recorded_response = self.recorded_responses.next().await.ok_or(
+                P2PSyncError::ReceiverChannelTerminated { type_description: "recorded responses" },
+            )?;
+            self.pending_peers
+                .entry(recorded_response.protocol)
+                .or_default()
+                .push_back(recorded_response.peer_id);
+        }
+    }
+}
+
+fn create_query(start_block_number: BlockNumber, limit: u64) -> Query {
+    Query {
+        start_block: BlockHashOrNumber::Number(start_block_number),
+        direction: Direction::Forward,
+        limit,
+        step: STEP,
+    }
+}
+
+fn report_comparison(
+    data: &'static str,
+    block_number: BlockNumber,
+    serving_peer: Option<PeerId>,
+    divergences: &[FieldDivergence],
+) {
+    let result = if divergences.is_empty() { "matched" } else { "diverged" };
+    increment_counter!(
+        papyrus_metrics::PAPYRUS_P2P_SHADOW_SYNC_COMPARED_BLOCKS,
+        "data" => data,
+        "result" => result
+    );
+    let serving_peer = serving_peer.map_or_else(|| "unknown".to_owned(), |peer| peer.to_string());
+    for FieldDivergence { field, central_value, peer_value } in divergences {
+        error!(
+            "The {data} of block {block_number} from peer {serving_peer} diverged from the \
+             central sync at {field}: the central sync wrote {central_value}, the peer sent \
+             {peer_value}."
+        );
+    }
+}
+
+/// Compares every field of a header a peer sent with the header the central sync wrote. The
+/// central sync doesn't have the optional fields of old blocks, so they're compared only if it
+/// wrote them.
+pub(crate) fn header_divergences(
+    central_header: &BlockHeader,
+    central_signature: Option<&BlockSignature>,
+    peer_header: &SignedBlockHeader,
+) -> Vec<FieldDivergence> {
+    let mut divergences = Vec::new();
+    let block_header = &peer_header.block_header;
+    macro_rules! compare_fields {
+        ($($field:ident),*) => {
+            $(
+                if central_header.$field != block_header.$field {
+                    divergences.push(FieldDivergence {
+                        field: stringify!($field).to_owned(),
+                        central_value: format!("{:?}", central_header.$field),
+                        peer_value: format!("{:?}", block_header.$field),
+                    });
+                }
+            )*
+        };
+    }
+    macro_rules! compare_optional_fields {
+        ($($field:ident),*) => {
+            $(
+                if central_header.$field.is_some() {
+                    compare_fields!($field);
+                }
+            )*
+        };
+    }
+    compare_fields!(
+        block_hash,
+        parent_hash,
+        block_number,
+        l1_gas_price,
+        l1_data_gas_price,
+        state_root,
+        sequencer,
+        timestamp,
+        l1_da_mode,
+        starknet_version
+    );
+    compare_optional_fields!(
+        state_diff_commitment,
+        state_diff_length,
+        transaction_commitment,
+        event_commitment,
+        receipt_commitment,
+        n_transactions,
+        n_events
+    );
+    if let Some(central_signature) = central_signature {
+        if peer_header.signatures.first() != Some(central_signature) {
+            divergences.push(FieldDivergence {
+                field: "signatures".to_owned(),
+                central_value: format!("{:?}", [central_signature]),
+                peer_value: format!("{:?}", peer_header.signatures),
+            });
+        }
+    }
+    divergences
+}
+
+/// Compares every entry of a state diff a peer sent with the state diff the central sync wrote.
+/// An entry that only one of them has is shown as None on the other side.
+pub(crate) fn state_diff_divergences(
+    central_state_diff: &ThinStateDiff,
+    peer_state_diff: &ThinStateDiff,
+) -> Vec<FieldDivergence> {
+    let mut divergences = Vec::new();
+    map_divergences(
+        "deployed_contracts",
+        &central_state_diff.deployed_contracts,
+        &peer_state_diff.deployed_contracts,
+        &mut divergences,
+    );
+    let flatten_storage_diffs = |state_diff: &ThinStateDiff| {
+        state_diff
+            .storage_diffs
+            .iter()
+            .flat_map(|(address, storage_diff)| {
+                storage_diff.iter().map(move |(key, value)| ((*address, *key), *value))
+            })
+            .collect::<IndexMap<_, _>>()
+    };
+    map_divergences(
+        "storage_diffs",
+        &flatten_storage_diffs(central_state_diff),
+        &flatten_storage_diffs(peer_state_diff),
+        &mut divergences,
+    );
+    map_divergences(
+        "declared_classes",
+        &central_state_diff.declared_classes,
+        &peer_state_diff.declared_classes,
+        &mut divergences,
+    );
+    let central_deprecated_declared_classes =
+        central_state_diff.deprecated_declared_classes.iter().collect::<HashSet<_>>();
+    let peer_deprecated_declared_classes =
+        peer_state_diff.deprecated_declared_classes.iter().collect::<HashSet<_>>();
+    for class_hash in
+        central_deprecated_declared_classes.symmetric_difference(&peer_deprecated_declared_classes)
+    {
+        let is_declared = |classes: &HashSet<_>| {
+            let value = if classes.contains(class_hash) { "declared" } else { "None" };
+            value.to_owned()
+        };
+        divergences.push(FieldDivergence {
+            field: format!("deprecated_declared_classes[{class_hash:?}]"),
+            central_value: is_declared(&central_deprecated_declared_classes),
+            peer_value: is_declared(&peer_deprecated_declared_classes),
+        });
+    }
+    map_divergences(
+        "nonces",
+        &central_state_diff.nonces,
+        &peer_state_diff.nonces,
+        &mut divergences,
+    );
+    map_divergences(
+        "replaced_classes",
+        &central_state_diff.replaced_classes,
+        &peer_state_diff.replaced_classes,
+        &mut divergences,
+    );
+    divergences
+}
+
+fn map_divergences<K: Debug + Hash + Eq, V: Debug + PartialEq>(
+    field: &str,
+    central_map: &IndexMap<K, V>,
+    peer_map: &IndexMap<K, V>,
+    divergences: &mut Vec<FieldDivergence>,
+) {
+    let peer_only_keys = peer_map.keys().filter(|key| !central_map.contains_key(*key));
+    for key in central_map.keys().chain(peer_only_keys) {
+        let central_value = central_map.get(key);
+        let peer_value = peer_map.get(key);
+        if central_value != peer_value {
+            divergences.push(FieldDivergence {
+                field: format!("{field}[{key:?}]"),
+                central_value: format!("{central_value:?}"),
+                peer_value: format!("{peer_value:?}"),
+            });
+        }
+    }
+}
@@ -0,0 +1,229 @@
+use std::time::Duration;
+
+use futures::channel::mpsc::{channel, unbounded, Receiver, Sender, UnboundedSender};
+use futures::{SinkExt, StreamExt};
+use indexmap::indexmap;
+use libp2p::PeerId;
+use papyrus_network::network_manager::RecordedSqmrResponse;
+use papyrus_network::Protocol;
+use papyrus_protobuf::sync::{
+    BlockHashOrNumber,
+    DataOrFin,
+    HeaderQuery,
+    SignedBlockHeader,
+    StateDiffQuery,
+};
+use papyrus_storage::header::{HeaderStorageReader, HeaderStorageWriter};
+use papyrus_storage::state::{StateStorageReader, StateStorageWriter};
+use papyrus_storage::test_utils::get_test_storage;
+use papyrus_storage::StorageWriter;
+use starknet_api::block::{BlockHash, BlockHeader, BlockNumber, BlockSignature};
+use starknet_api::core::{ClassHash, ContractAddress, Nonce};
+use starknet_api::state::{StorageKey, ThinStateDiff};
+use starknet_types_core::felt::Felt;
+use tokio::time::timeout;
+
+use crate::shadow::{header_divergences, state_diff_divergences, FieldDivergence, ShadowSync};
+use crate::test_utils::{
+    BUFFER_SIZE,
+    HEADER_QUERY_LENGTH,
+    STATE_DIFF_QUERY_LENGTH,
+    WAIT_PERIOD_FOR_NEW_DATA,
+};
+use crate::{P2PSyncConfig, Response};
+
+const TIMEOUT_FOR_TEST: Duration = Duration::from_secs(5);
+
+fn block_header(block_number: u64) -> BlockHeader {
+    BlockHeader {
+        block_number: BlockNumber(block_number),
+        block_hash: BlockHash(Felt::from(block_number + 1)),
+        parent_hash: BlockHash(Felt::from(block_number)),
+        ..Default::default()
+    }
+}
+
+fn state_diff(block_number: u64) -> ThinStateDiff {
+    ThinStateDiff {
+        nonces: indexmap! { ContractAddress::from(1_u128) => Nonce(Felt::from(block_number + 1)) },
+        ..Default::default()
+    }
+}
+
+fn write_block(storage_writer: &mut StorageWriter, block_number: u64) {
+    storage_writer
+        .begin_rw_txn()
+        .unwrap()
+        .append_header(BlockNumber(block_number), &block_header(block_number))
+        .unwrap()
+        .append_state_diff(BlockNumber(block_number), state_diff(block_number))
+        .unwrap()
+        .commit()
+        .unwrap();
+}
+
+#[test]
+fn header_divergences_are_reported_by_field() {
+    let central_header =
+        BlockHeader { state_diff_length: Some(1), transaction_commitment: None, ..block_header(0) };
+    let central_signature = BlockSignature::default();
+    let peer_header = SignedBlockHeader {
+        block_header: BlockHeader {
+            block_hash: BlockHash(Felt::from(100_u8)),
+            state_diff_length: Some(2),
+            // The central sync doesn't have the transaction commitment, so it isn't compared.
+            transaction_commitment: Some(Default::default()),
+            ..central_header.clone()
+        },
+        signatures: vec![central_signature],
+        data_availability: None,
+    };
+
+    let divergences = header_divergences(&central_header, Some(&central_signature), &peer_header);
+
+    assert_eq!(
+        divergences.iter().map(|divergence| divergence.field.as_str()).collect::<Vec<_>>(),
+        vec!["block_hash", "state_diff_length"]
+    );
+    let matching_header = SignedBlockHeader {
+        block_header: central_header.clone(),
+        signatures: vec![central_signature],
+        data_availability: None,
+    };
+    assert!(
+        header_divergences(&central_header, Some(&central_signature), &matching_header).is_empty()
+    );
+}
+
+#[test]
+fn state_diff_divergences_are_reported_by_entry() {
+    let address = ContractAddress::from(1_u128);
+    let central_state_diff = ThinStateDiff {
+        storage_diffs: indexmap! {
+            address => indexmap! { StorageKey::from(2_u128) => Felt::from(3_u8) },
+        },
+        nonces: indexmap! { address => Nonce(Felt::from(1_u8)) },
+        ..Default::default()
+    };
+    let peer_state_diff = ThinStateDiff {
+        deprecated_declared_classes: vec![ClassHash(Felt::from(4_u8))],
+        nonces: indexmap! { address => Nonce(Felt::from(2_u8)) },
+        ..Default::default()
+    };
+
+    let divergences = state_diff_divergences(&central_state_diff, &peer_state_diff);
+
+    assert_eq!(
+        divergences,
+        vec![
+            FieldDivergence {
+                field: format!("storage_diffs[{:?}]", (address, StorageKey::from(2_u128))),
+                central_value: format!("{:?}", Some(Felt::from(3_u8))),
+                peer_value: "None".to_owned(),
+            },
+            FieldDivergence {
+                field: format!("deprecated_declared_classes[{:?}]", ClassHash(Felt::from(4_u8))),
+                central_value: "None".to_owned(),
+                peer_value: "declared".to_owned(),
+            },
+            FieldDivergence {
+                field: format!("nonces[{address:?}]"),
+                central_value: format!("{:?}", Some(Nonce(Felt::from(1_u8)))),
+                peer_value: format!("{:?}", Some(Nonce(Felt::from(2_u8)))),
+            },
+        ]
+    );
+}
+
+// Plays the network: receives a query, records its responses and sends them to the shadow sync.
+async fn respond<Query, T>(
+    query_receiver: &mut Receiver<Query>,
+    response_sender: &mut Sender<Response<T>>,
+    recorder_sender: &UnboundedSender<RecordedSqmrResponse>,
+    protocol: Protocol,
+    responses: Vec<T>,
+) -> Query {
+    let query = timeout(TIMEOUT_FOR_TEST, query_receiver.next()).await.unwrap().unwrap();
+    let peer_id = PeerId::random();
+    let responses = responses.into_iter().map(|data| DataOrFin(Some(data)));
+    for response in responses.chain(std::iter::once(DataOrFin(None))) {
+        recorder_sender
+            .unbounded_send(RecordedSqmrResponse {
+                protocol,
+                session_id: 0,
+                query: Vec::new(),
+                peer_id,
+                response: Vec::new(),
+            })
+            .unwrap();
+        response_sender.send((Ok(response), Box::new(|| {}))).await.unwrap();
+    }
+    query
+}
+
+#[tokio::test]
+async fn shadow_sync_compares_the_blocks_the_central_sync_writes() {
+    let ((storage_reader, mut storage_writer), _temp_dir) = get_test_storage();
+    let (header_query_sender, mut header_query_receiver) = channel::<HeaderQuery>(BUFFER_SIZE);
+    let (mut headers_sender, headers_receiver) = channel(BUFFER_SIZE);
+    let (state_diff_query_sender, mut state_diff_query_receiver) =
+        channel::<StateDiffQuery>(BUFFER_SIZE);
+    let (mut state_diffs_sender, state_diffs_receiver) = channel(BUFFER_SIZE);
+    let (recorder_sender, recorded_responses) = unbounded();
+    let config = P2PSyncConfig {
+        num_headers_per_query: HEADER_QUERY_LENGTH,
+        num_block_state_diffs_per_query: STATE_DIFF_QUERY_LENGTH,
+        wait_period_for_new_data: WAIT_PERIOD_FOR_NEW_DATA,
+        shadow: true,
+        ..Default::default()
+    };
+    let shadow_sync = ShadowSync::new(
+        config,
+        storage_reader.clone(),
+        header_query_sender,
+        headers_receiver,
+        state_diff_query_sender,
+        state_diffs_receiver,
+        recorded_responses,
+    );
+
+    let network = async {
+        for block_number in 0..2 {
+            write_block(&mut storage_writer, block_number);
+            let HeaderQuery(query) = respond(
+                &mut header_query_receiver,
+                &mut headers_sender,
+                &recorder_sender,
+                Protocol::SignedBlockHeader,
+                vec![SignedBlockHeader {
+                    block_header: block_header(block_number),
+                    signatures: vec![],
+                    data_availability: None,
+                }],
+            )
+            .await;
+            assert_eq!(query.start_block, BlockHashOrNumber::Number(BlockNumber(block_number)));
+            assert_eq!(query.limit, 1);
+            // The peer sends a different state diff, which is only logged.
+            let StateDiffQuery(query) = respond(
+                &mut state_diff_query_receiver,
+                &mut state_diffs_sender,
+                &recorder_sender,
+                Protocol::StateDiff,
+                vec![state_diff(block_number + 1)],
+            )
+            .await;
+            assert_eq!(query.start_block, BlockHashOrNumber::Number(BlockNumber(block_number)));
+            assert_eq!(query.limit, 1);
+        }
+    };
+
+    tokio::select! {
+        result = shadow_sync.run() => panic!("The shadow sync stopped: {result:?}"),
+        _ = network => {}
+    }
+    // Only the central sync wrote to the storage.
+    let txn = storage_reader.begin_ro_txn().unwrap();
+    assert_eq!(txn.get_header_marker().unwrap(), BlockNumber(2));
+    assert_eq!(txn.get_state_diff(BlockNumber(1)).unwrap(), Some(state_diff(1)));
+}
@@ -0,0 +1,312 @@
+use std::cmp::min;
+use std::collections::{BTreeMap, BTreeSet};
+use std::time::Duration;
+
+use async_stream::stream;
+use futures::channel::mpsc::{SendError, UnboundedSender};
+use futures::channel::oneshot;
+use futures::stream::{BoxStream, FuturesUnordered};
+use futures::{Sink, SinkExt, Stream, StreamExt};
+use metrics::gauge;
+use papyrus_common::metrics as papyrus_metrics;
+use papyrus_network::network_manager::{PeerManagerCommand, QueryPriority};
+use papyrus_protobuf::sync::{BlockHashOrNumber, DataOrFin, Direction, Query};
+use papyrus_storage::header::HeaderStorageReader;
+use papyrus_storage::StorageReader;
+use starknet_api::block::BlockNumber;
+use tracing::{debug, info};
+
+use crate::response_validator::{ValidatedResponse, ValidatedResponseReceiver};
+use crate::stream_factory::{get_previous_block_hash, BlockData, DataStreamFactory};
+use crate::{P2PSyncError, Response, STEP};
+
+// The blocks in the range [start, end), whose data is downloaded by a single query.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct Shard {
+    start: BlockNumber,
+    end: BlockNumber,
+}
+
+// The channels of one of the sqmr client lanes of the protocol. A lane has at most one query in
+// flight.
+struct Lane<QuerySender, DataReceiver> {
+    query_sender: QuerySender,
+    data_receiver: ValidatedResponseReceiver<DataReceiver>,
+}
+
+// The data of the first blocks of a shard, and the rest of the shard if it wasn't downloaded.
+struct ShardDownload<Output> {
+    shard: Shard,
+    outputs: Vec<Output>,
+    remaining: Option<Shard>,
+}
+
+/// Creates a stream of the data of the blocks that the storage has headers for, like
+/// [`DataStreamFactory::create_stream`], but splits the blocks into shards of `shard_size` blocks
+/// and downloads up to one shard per lane at once. The shards are yielded strictly in order, so
+/// a shard that was downloaded before the shards preceding it waits for them. A shard whose
+/// download timed out or returned partial data is retried from the first block that's missing,
+/// without affecting the other shards. A shard whose response had no data at all is retried only
+/// after `wait_period_for_new_data`.
+///
+/// If `peer_manager_command_sender` is given, no more shards than the number of unblocked peers
+/// are downloaded at once, so that with a single peer the blocks are downloaded sequentially.
+pub(crate) fn create_sharded_stream<Factory, QuerySender, DataReceiver, InputFromNetwork>(
+    lanes: Vec<(QuerySender, DataReceiver)>,
+    storage_reader: StorageReader,
+    wait_period_for_new_data: Duration,
+    shard_size: u64,
+    stop_sync_at_block_number: Option<BlockNumber>,
+    peer_manager_command_sender: Option<UnboundedSender<PeerManagerCommand>>,
+) -> BoxStream<'static, Result<Box<dyn BlockData>, P2PSyncError>>
+where
+    Factory: DataStreamFactory<QuerySender, DataReceiver, InputFromNetwork>,
+    QuerySender: Sink<(Query, QueryPriority), Error = SendError> + Unpin + Send + 'static,
+    DataReceiver: Stream<Item = Response<InputFromNetwork>> + Unpin + Send + 'static,
+    InputFromNetwork: ValidatedResponse + Send + 'static,
+    DataOrFin<InputFromNetwork>: TryFrom<Vec<u8>>,
+    <DataOrFin<InputFromNetwork> as TryFrom<Vec<u8>>>::Error: Send,
+{
+    stream! {
+        let num_lanes = lanes.len();
+        let mut idle_lanes = lanes
+            .into_iter()
+            .map(|(query_sender, data_receiver)| Lane {
+                query_sender,
+                data_receiver: ValidatedResponseReceiver::new(
+                    data_receiver,
+                    Factory::TYPE_DESCRIPTION,
+                ),
+            })
+            .collect::<Vec<_>>();
+        let mut downloads = FuturesUnordered::new();
+        // The next block to yield.
+        let mut next_block_number = Factory::get_start_block_number(&storage_reader)?;
+        // The first block that wasn't assigned to a shard yet.
+        let mut next_shard_start = next_block_number;
+        let mut shards_to_retry = BTreeSet::new();
+        // The downloaded data that waits for the data of earlier blocks, by the block it starts at.
+        let mut downloaded_outputs = BTreeMap::<BlockNumber, Vec<Factory::Output>>::new();
+
+        loop {
+            let max_active_shards =
+                get_max_active_shards(&peer_manager_command_sender, num_lanes).await;
+            let mut sync_end_block_number = storage_reader.begin_ro_txn()?.get_header_marker()?;
+            if let Some(stop_sync_at_block_number) = stop_sync_at_block_number {
+                sync_end_block_number = min(sync_end_block_number, stop_sync_at_block_number);
+            }
+            while downloads.len() < max_active_shards {
+                let shard = match shards_to_retry.pop_first() {
+                    Some(shard) => shard,
+                    None if next_shard_start < sync_end_block_number => {
+                        let shard = Shard {
+                            start: next_shard_start,
+                            end: BlockNumber(min(
+                                next_shard_start.0 + shard_size,
+                                sync_end_block_number.0,
+                            )),
+                        };
+                        next_shard_start = shard.end;
+                        shard
+                    }
+                    None => break,
+                };
+                let lane = idle_lanes.pop().expect("Each active shard holds a different lane");
+                downloads.push(download_shard::<Factory, _, _, _>(
+                    lane,
+                    shard,
+                    storage_reader.clone(),
+                    wait_period_for_new_data,
+                ));
+            }
+            gauge!(
+                papyrus_metrics::PAPYRUS_P2P_SYNC_ACTIVE_STATE_DIFF_SHARDS,
+                downloads.len() as f64
+            );
+
+            let Some((lane, download)) = downloads.next().await else {
+                if stop_sync_at_block_number.is_some_and(|stop_sync_at_block_number| {
+                    next_block_number >= stop_sync_at_block_number
+                }) {
+                    info!("{:?} hit the stop sync block number.", Factory::TYPE_DESCRIPTION);
+                    return;
+                }
+                debug!("{:?} sync is waiting for a new header", Factory::TYPE_DESCRIPTION);
+                tokio::time::sleep(wait_period_for_new_data).await;
+                continue;
+            };
+            idle_lanes.push(lane);
+            let ShardDownload { shard, outputs, remaining } = download?;
+            if let Some(remaining) = remaining {
+                shards_to_retry.insert(remaining);
+            }
+            if !outputs.is_empty() {
+                downloaded_outputs.insert(shard.start, outputs);
+            }
+
+            while let Some(outputs) = downloaded_outputs.remove(&next_block_number) {
+                for output in outputs {
+                    yield Ok(Box::<dyn BlockData>::from(Box::new(output)));
+                    info!("Added {:?} for block {}.", Factory::TYPE_DESCRIPTION, next_block_number);
+                    next_block_number = next_block_number.unchecked_next();
+                }
+            }
+            gauge!(
+                papyrus_metrics::PAPYRUS_P2P_SYNC_STATE_DIFF_COMMIT_BACKLOG,
+                downloaded_outputs.values().map(Vec::len).sum::<usize>() as f64
+            );
+        }
+    }
+    .boxed()
+}
+
+// Downloads the data of the given shard through the given lane and returns the lane once it has no
+// query in flight.
+async fn download_shard<Factory, QuerySender, DataReceiver, InputFromNetwork>(
+    mut lane: Lane<QuerySender, DataReceiver>,
+    shard: Shard,
+    storage_reader: StorageReader,
+    wait_period_for_new_data: Duration,
+) -> (Lane<QuerySender, DataReceiver>, Result<ShardDownload<Factory::Output>, P2PSyncError>)
+where
+    Factory: DataStreamFactory<QuerySender, DataReceiver, InputFromNetwork>,
+    QuerySender: Sink<(Query, QueryPriority), Error = SendError> + Unpin + Send + 'static,
+    DataReceiver: Stream<Item = Response<InputFromNetwork>> + Unpin + Send + 'static,
+    InputFromNetwork: ValidatedResponse + Send + 'static,
+    DataOrFin<InputFromNetwork>: TryFrom<Vec<u8>>,
+    <DataOrFin<InputFromNetwork> as TryFrom<Vec<u8>>>::Error: Send,
+{
+    let mut outputs = Vec::new();
+    let result = download_shard_into::<Factory, _, _, _>(
+        &mut lane,
+        shard,
+        &storage_reader,
+        wait_period_for_new_data,
+        &mut outputs,
+    )
+    .await
+    .map(|next_block_number| ShardDownload {
+        shard,
+        outputs,
+        remaining: (next_block_number < shard.end)
+            .then_some(Shard { start: next_block_number, end: shard.end }),
+    });
+    (lane, result)
+}
+
+// Downloads the shard's data into `outputs` and returns the first block whose data wasn't
+// downloaded.
+async fn download_shard_into<Factory, QuerySender, DataReceiver, InputFromNetwork>(
+    lane: &mut Lane<QuerySender, DataReceiver>,
+    shard: Shard,
+    storage_reader: &StorageReader,
+    wait_period_for_new_data: Duration,
+    outputs: &mut Vec<Factory::Output>,
+) -> Result<BlockNumber, P2PSyncError>
+where
+    Factory: DataStreamFactory<QuerySender, DataReceiver, InputFromNetwork>,
+    QuerySender: Sink<(Query, QueryPriority), Error = SendError> + Unpin + Send + 'static,
+    DataReceiver: Stream<Item = Response<InputFromNetwork>> + Unpin + Send + 'static,
+    InputFromNetwork: ValidatedResponse + Send + 'static,
+    DataOrFin<InputFromNetwork>: TryFrom<Vec<u8>>,
+    <DataOrFin<InputFromNetwork> as TryFrom<Vec<u8>>>::Error: Send,
+{
+    debug!(
+        "Downloading {:?} for blocks [{}, {})",
+        Factory::TYPE_DESCRIPTION,
+        shard.start.0,
+        shard.end.0,
+    );
+    let query = Query {
+        start_block: BlockHashOrNumber::Number(shard.start),
+        direction: Direction::Forward,
+        limit: shard.end.0 - shard.start.0,
+        step: STEP,
+    };
+    lane.data_receiver.start_session(&query, get_previous_block_hash(storage_reader, shard.start)?);
+    lane.query_sender.send((query, Factory::query_priority(false))).await?;
+
+    let mut block_number = shard.start;
+    while block_number < shard.end {
+        match Factory::parse_data_for_block(&mut lane.data_receiver, block_number, storage_reader)
+            .await
+        {
+            Ok(Some(output)) => outputs.push(output),
+            // The peer sent some of the blocks and ended the response, e.g. because the response
+            // reached the peer's limits. The rest of the shard is retried right away.
+            Ok(None) if block_number > shard.start => {
+                debug!(
+                    "Query for {:?} ended before block {}. Retrying blocks [{}, {}).",
+                    Factory::TYPE_DESCRIPTION,
+                    block_number,
+                    block_number,
+                    shard.end,
+                );
+                return Ok(block_number);
+            }
+            Ok(None) => {
+                debug!(
+                    "Query for {:?} returned with partial data. Waiting {:?} before retrying \
+                     blocks [{}, {}).",
+                    Factory::TYPE_DESCRIPTION,
+                    wait_period_for_new_data,
+                    block_number,
+                    shard.end,
+                );
+                tokio::time::sleep(wait_period_for_new_data).await;
+                return Ok(block_number);
+            }
+            // The network doesn't notify us when the session of the query fails, so a failure is
+            // detected by not receiving data for a while.
+            Err(P2PSyncError::NetworkTimeout(_)) => {
+                info!(
+                    "Timed out waiting for {:?} of block {}. Retrying blocks [{}, {}).",
+                    Factory::TYPE_DESCRIPTION,
+                    block_number,
+                    block_number,
+                    shard.end,
+                );
+                return Ok(block_number);
+            }
+            Err(err) => return Err(err),
+        }
+        block_number = block_number.unchecked_next();
+    }
+
+    // Consume the None message signaling the end of the query.
+    match lane.data_receiver.next().await {
+        Some((Ok(DataOrFin(None)), _report_callback)) => {
+            debug!("Query sent to network for {:?} finished", Factory::TYPE_DESCRIPTION);
+            Ok(block_number)
+        }
+        Some(_) => Err(P2PSyncError::TooManyResponses),
+        None => Err(P2PSyncError::ReceiverChannelTerminated {
+            type_description: Factory::TYPE_DESCRIPTION,
+        }),
+    }
+}
+
+// The number of shards that may be downloaded at once: one per lane, but if the peer manager is
+// known, no more than the number of peers that aren't blocked, so that each peer gets about one
+// shard.
+async fn get_max_active_shards(
+    peer_manager_command_sender: &Option<UnboundedSender<PeerManagerCommand>>,
+    num_lanes: usize,
+) -> usize {
+    let Some(peer_manager_command_sender) = peer_manager_command_sender else {
+        return num_lanes;
+    };
+    let (state_sender, state_receiver) = oneshot::channel();
+    if peer_manager_command_sender
+        .unbounded_send(PeerManagerCommand::GetState(state_sender))
+        .is_err()
+    {
+        return 1;
+    }
+    let Ok(state) = state_receiver.await else {
+        return 1;
+    };
+    let num_unblocked_peers =
+        state.peers.iter().filter(|peer| peer.blocked_until.is_none()).count();
+    num_unblocked_peers.clamp(1, num_lanes.max(1))
+}
@@ -0,0 +1,222 @@
+use std::collections::HashMap;
+use std::time::Duration;
+
+use futures::channel::mpsc::{channel, Receiver, Sender};
+use futures::future::select;
+use futures::stream::BoxStream;
+use futures::{SinkExt, StreamExt};
+use indexmap::indexmap;
+use papyrus_network::network_manager::QueryPriority;
+use papyrus_protobuf::sync::{BlockHashOrNumber, DataOrFin, Direction, Query};
+use papyrus_storage::header::HeaderStorageWriter;
+use papyrus_storage::state::StateStorageReader;
+use papyrus_storage::test_utils::get_test_storage;
+use papyrus_storage::{StorageReader, StorageWriter};
+use starknet_api::block::{BlockHash, BlockHeader, BlockNumber};
+use starknet_api::core::{ContractAddress, Nonce};
+use starknet_api::hash::StarkHash;
+use starknet_api::state::ThinStateDiff;
+use tempfile::TempDir;
+
+use crate::sharded_stream::create_sharded_stream;
+use crate::state_diff::StateDiffStreamFactory;
+use crate::stream_factory::BlockData;
+use crate::test_utils::{
+    BUFFER_SIZE,
+    SLEEP_DURATION_TO_LET_SYNC_ADVANCE,
+    WAIT_PERIOD_FOR_NEW_DATA,
+};
+use crate::{P2PSyncError, Response};
+
+const NUM_BLOCKS: u64 = 4;
+const SHARD_SIZE: u64 = 2;
+const NUM_LANES: usize = 2;
+const TIMEOUT_FOR_TEST: Duration = Duration::from_secs(5);
+
+struct TestLane {
+    query_receiver: Receiver<(Query, QueryPriority)>,
+    response_sender: Sender<Response<ThinStateDiff>>,
+}
+
+// Runs a sharded state diff stream over headers of NUM_BLOCKS blocks and writes its output to the
+// storage.
+fn setup() -> (StorageReader, Vec<TestLane>, TempDir) {
+    let ((storage_reader, mut storage_writer), temp_dir) = get_test_storage();
+    let mut txn = storage_writer.begin_rw_txn().unwrap();
+    for block_number in 0..NUM_BLOCKS {
+        let block_header = BlockHeader {
+            block_number: BlockNumber(block_number),
+            block_hash: BlockHash(StarkHash::from(block_number + 1)),
+            parent_hash: BlockHash(StarkHash::from(block_number)),
+            state_diff_length: Some(1),
+            ..Default::default()
+        };
+        txn = txn.append_header(BlockNumber(block_number), &block_header).unwrap();
+    }
+    txn.commit().unwrap();
+
+    let mut lanes = Vec::new();
+    let mut test_lanes = Vec::new();
+    for _ in 0..NUM_LANES {
+        let (query_sender, query_receiver) = channel(BUFFER_SIZE);
+        let (response_sender, response_receiver) = channel(BUFFER_SIZE);
+        lanes.push((query_sender, response_receiver));
+        test_lanes.push(TestLane { query_receiver, response_sender });
+    }
+    let stream = create_sharded_stream::<StateDiffStreamFactory<_, _>, _, _, _>(
+        lanes,
+        storage_reader.clone(),
+        WAIT_PERIOD_FOR_NEW_DATA,
+        SHARD_SIZE,
+        None,
+        None,
+    );
+    tokio::spawn(write_stream_to_storage(stream, storage_writer));
+    (storage_reader, test_lanes, temp_dir)
+}
+
+async fn write_stream_to_storage(
+    mut stream: BoxStream<'static, Result<Box<dyn BlockData>, P2PSyncError>>,
+    mut storage_writer: StorageWriter,
+) {
+    while let Some(data) = stream.next().await {
+        data.unwrap().write_to_storage(&mut storage_writer).unwrap();
+    }
+}
+
+fn shard_query(start: u64) -> Query {
+    Query {
+        start_block: BlockHashOrNumber::Number(BlockNumber(start)),
+        direction: Direction::Forward,
+        limit: SHARD_SIZE,
+        step: 1,
+    }
+}
+
+fn state_diff(block_number: u64) -> ThinStateDiff {
+    ThinStateDiff {
+        nonces: indexmap! {
+            ContractAddress::from(block_number) => Nonce(StarkHash::from(block_number)),
+        },
+        ..Default::default()
+    }
+}
+
+// Returns the index of the lane each shard's query was sent on, by the shard's first block.
+async fn receive_queries(lanes: &mut [TestLane]) -> HashMap<u64, usize> {
+    let mut shard_to_lane = HashMap::new();
+    for (lane_index, lane) in lanes.iter_mut().enumerate() {
+        let (query, _priority) = tokio::time::timeout(TIMEOUT_FOR_TEST, lane.query_receiver.next())
+            .await
+            .unwrap()
+            .unwrap();
+        let BlockHashOrNumber::Number(BlockNumber(start)) = query.start_block else {
+            panic!("Expected a query by block number");
+        };
+        assert_eq!(query, shard_query(start));
+        shard_to_lane.insert(start, lane_index);
+    }
+    shard_to_lane
+}
+
+async fn send_shard(lane: &mut TestLane, start: u64) {
+    for block_number in start..start + SHARD_SIZE {
+        lane.response_sender
+            .send((Ok(DataOrFin(Some(state_diff(block_number)))), Box::new(|| {})))
+            .await
+            .unwrap();
+    }
+    lane.response_sender.send((Ok(DataOrFin(None)), Box::new(|| {}))).await.unwrap();
+}
+
+#[tokio::test]
+async fn shards_are_downloaded_in_parallel_and_written_in_order() {
+    let (storage_reader, mut lanes, _temp_dir) = setup();
+
+    let shard_to_lane = receive_queries(&mut lanes).await;
+    assert_eq!(shard_to_lane.len(), NUM_LANES);
+
+    // The second shard can't be written before the first.
+    send_shard(&mut lanes[shard_to_lane[&SHARD_SIZE]], SHARD_SIZE).await;
+    tokio::time::sleep(SLEEP_DURATION_TO_LET_SYNC_ADVANCE).await;
+    assert_eq!(storage_reader.begin_ro_txn().unwrap().get_state_marker().unwrap(), BlockNumber(0));
+
+    send_shard(&mut lanes[shard_to_lane[&0]], 0).await;
+    tokio::time::timeout(TIMEOUT_FOR_TEST, async {
+        while storage_reader.begin_ro_txn().unwrap().get_state_marker().unwrap()
+            < BlockNumber(NUM_BLOCKS)
+        {
+            tokio::time::sleep(SLEEP_DURATION_TO_LET_SYNC_ADVANCE).await;
+        }
+    })
+    .await
+    .unwrap();
+    let txn = storage_reader.begin_ro_txn().unwrap();
+    for block_number in 0..NUM_BLOCKS {
+        assert_eq!(
+            txn.get_state_diff(BlockNumber(block_number)).unwrap().unwrap(),
+            state_diff(block_number)
+        );
+    }
+}
+
+#[tokio::test]
+async fn only_the_shard_with_partial_data_is_retried() {
+    let (storage_reader, mut lanes, _temp_dir) = setup();
+
+    let shard_to_lane = receive_queries(&mut lanes).await;
+    let first_shard_lane = shard_to_lane[&0];
+    let second_shard_lane = shard_to_lane[&SHARD_SIZE];
+
+    // The peer of the second shard doesn't have its blocks.
+    lanes[second_shard_lane]
+        .response_sender
+        .send((Ok(DataOrFin(None)), Box::new(|| {})))
+        .await
+        .unwrap();
+    send_shard(&mut lanes[first_shard_lane], 0).await;
+
+    // Only the second shard is queried again, after the wait period for new data.
+    let (first_lane, other_lanes) = lanes.split_at_mut(1);
+    let (query, _priority) = tokio::time::timeout(
+        TIMEOUT_FOR_TEST,
+        select(first_lane[0].query_receiver.next(), other_lanes[0].query_receiver.next()),
+    )
+    .await
+    .unwrap()
+    .factor_first()
+    .0
+    .unwrap();
+    assert_eq!(query, shard_query(SHARD_SIZE));
+    assert_eq!(
+        storage_reader.begin_ro_txn().unwrap().get_state_marker().unwrap(),
+        BlockNumber(SHARD_SIZE)
+    );
+}
+
+#[tokio::test]
+async fn shard_that_ended_early_is_continued_without_waiting() {
+    let (_storage_reader, mut lanes, _temp_dir) = setup();
+
+    let shard_to_lane = receive_queries(&mut lanes).await;
+    let lane = &mut lanes[shard_to_lane[&0]];
+
+    // The peer ended the response after the first block, e.g. because of its response limits.
+    lane.response_sender.send((Ok(DataOrFin(Some(state_diff(0)))), Box::new(|| {}))).await.unwrap();
+    lane.response_sender.send((Ok(DataOrFin(None)), Box::new(|| {}))).await.unwrap();
+
+    let (query, _priority) =
+        tokio::time::timeout(WAIT_PERIOD_FOR_NEW_DATA / 2, lane.query_receiver.next())
+            .await
+            .unwrap()
+            .unwrap();
+    assert_eq!(
+        query,
+        Query {
+            start_block: BlockHashOrNumber::Number(BlockNumber(1)),
+            direction: Direction::Forward,
+            limit: SHARD_SIZE - 1,
+            step: 1,
+        }
+    );
+}
@@ -0,0 +1,252 @@
+use std::fmt::Display;
+use std::fs::File;
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::path::Path;
+
+use papyrus_base_layer::BaseLayerContract;
+use papyrus_network::db_executor::split_thin_state_diff;
+use papyrus_protobuf::converters::ProtobufConversionError;
+use papyrus_protobuf::sync::{FullBlock, SignedBlockHeader};
+use papyrus_storage::body::BodyStorageReader;
+use papyrus_storage::db::RO;
+use papyrus_storage::header::HeaderStorageReader;
+use papyrus_storage::state::StateStorageReader;
+use papyrus_storage::{StorageError, StorageReader, StorageTxn, StorageWriter};
+use starknet_api::block::{BlockHash, BlockNumber};
+use tracing::info;
+use unsigned_varint::encode::usize_buffer;
+
+use crate::{inject_block, P2PSyncError};
+
+/// The name of the file inside a snapshot directory that holds the exported blocks.
+pub const SNAPSHOT_FILE_NAME: &str = "blocks.snapshot";
+
+/// The maximal size of a message in a snapshot file. It bounds the memory a corrupted length
+/// prefix can make the reader allocate.
+pub const MAX_SNAPSHOT_MESSAGE_SIZE: usize = 1 << 28;
+
+#[derive(thiserror::Error, Debug)]
+pub enum SnapshotError {
+    #[error(
+        "Can't export blocks up to {up_to}. The storage is fully synced only up to {synced_up_to}."
+    )]
+    BlocksNotSynced { up_to: BlockNumber, synced_up_to: BlockNumber },
+    #[error("Block {block_number} is missing its {missing_data} in the storage.")]
+    MissingBlockData { block_number: BlockNumber, missing_data: &'static str },
+    #[error(
+        "The storage already contains blocks up to {header_marker}. Pass force_from_marker to \
+         import the blocks that come after them."
+    )]
+    NonEmptyStorage { header_marker: BlockNumber },
+    #[error(
+        "The snapshot ended at block {last_block_in_snapshot} before reaching the storage's next \
+         block {header_marker}."
+    )]
+    SnapshotBehindStorage { last_block_in_snapshot: BlockNumber, header_marker: BlockNumber },
+    #[error(
+        "Block {block_number} in the snapshot has hash {snapshot_hash}, but the base layer proved \
+         block {block_number} with hash {base_layer_hash}."
+    )]
+    BaseLayerHashMismatch {
+        block_number: BlockNumber,
+        base_layer_hash: BlockHash,
+        snapshot_hash: BlockHash,
+    },
+    #[error("Failed to read the latest block proved on the base layer: {0}")]
+    BaseLayerError(String),
+    #[error(transparent)]
+    IoError(#[from] io::Error),
+    #[error(transparent)]
+    P2PSyncError(#[from] P2PSyncError),
+    #[error(transparent)]
+    ProtobufConversionError(#[from] ProtobufConversionError),
+    #[error(transparent)]
+    StorageError(#[from] StorageError),
+}
+
+/// Exports all the blocks below `up_to` into a snapshot file inside `out_dir`. Each block is
+/// written as a length-prefixed protobuf `Block` message, the same encoding that's used when
+/// sending blocks over the network.
+///
+/// The export reads from a single read transaction, so it doesn't block a sync that's running
+/// concurrently on the same storage.
+pub fn export_blocks(
+    storage_reader: &StorageReader,
+    up_to: BlockNumber,
+    out_dir: &Path,
+) -> Result<(), SnapshotError> {
+    let txn = storage_reader.begin_ro_txn()?;
+    let synced_up_to =
+        txn.get_header_marker()?.min(txn.get_body_marker()?).min(txn.get_state_marker()?);
+    if up_to > synced_up_to {
+        return Err(SnapshotError::BlocksNotSynced { up_to, synced_up_to });
+    }
+
+    std::fs::create_dir_all(out_dir)?;
+    let mut writer = BufWriter::new(File::create(out_dir.join(SNAPSHOT_FILE_NAME))?);
+    for block_number in (0..up_to.0).map(BlockNumber) {
+        let block = read_full_block(&txn, block_number)?;
+        write_message(&mut writer, &Vec::<u8>::from(block))?;
+    }
+    writer.flush()?;
+    info!("Exported blocks up to {up_to} into {}.", out_dir.display());
+    Ok(())
+}
+
+/// Imports the blocks of the snapshot inside `in_dir` into the storage. Every block is validated
+/// for continuity and against its header's commitments before it's written.
+///
+/// If the storage isn't empty, the import is refused unless `force_from_marker` is set, in which
+/// case the snapshot's blocks that are already in the storage are skipped.
+pub fn import_blocks(
+    storage_writer: &mut StorageWriter,
+    in_dir: &Path,
+    force_from_marker: bool,
+) -> Result<BlockNumber, SnapshotError> {
+    let header_marker = storage_writer.begin_rw_txn()?.get_header_marker()?;
+    if header_marker > BlockNumber(0) && !force_from_marker {
+        return Err(SnapshotError::NonEmptyStorage { header_marker });
+    }
+
+    let mut reader = BufReader::new(File::open(in_dir.join(SNAPSHOT_FILE_NAME))?);
+    let mut next_block_number = header_marker;
+    let mut last_block_in_snapshot = None;
+    while let Some(message) = read_message(&mut reader)? {
+        let block = FullBlock::try_from(message)?;
+        let block_number = block.signed_header.block_header.block_number;
+        last_block_in_snapshot = Some(block_number);
+        if block_number < header_marker {
+            continue;
+        }
+        inject_block(storage_writer, block)?;
+        next_block_number = block_number.unchecked_next();
+    }
+    if let Some(last_block_in_snapshot) = last_block_in_snapshot {
+        if last_block_in_snapshot.unchecked_next() < header_marker {
+            return Err(SnapshotError::SnapshotBehindStorage {
+                last_block_in_snapshot,
+                header_marker,
+            });
+        }
+    }
+    info!("Imported blocks up to {next_block_number} from {}.", in_dir.display());
+    Ok(next_block_number)
+}
+
+/// Compares the snapshot inside `in_dir` with the latest block proved on the base layer, so that a
+/// snapshot of a chain that Starknet didn't prove isn't imported. Returns the number of the verified
+/// block, or None if the snapshot ends before it. In that case, the sync verifies the imported
+/// blocks once it reaches the proved block.
+pub async fn verify_snapshot<Contract>(
+    in_dir: &Path,
+    base_layer_contract: &Contract,
+) -> Result<Option<BlockNumber>, SnapshotError>
+where
+    Contract: BaseLayerContract + Sync,
+    Contract::Error: Display,
+{
+    let Some((block_number, base_layer_hash)) = base_layer_contract
+        .latest_proved_block(None)
+        .await
+        .map_err(|err| SnapshotError::BaseLayerError(err.to_string()))?
+    else {
+        info!("No block is proved on the base layer yet, so the snapshot can't be verified.");
+        return Ok(None);
+    };
+
+    // The snapshot holds the blocks in order from block 0, so the messages before the proved block
+    // are skipped without decoding them.
+    let mut reader = BufReader::new(File::open(in_dir.join(SNAPSHOT_FILE_NAME))?);
+    for _ in 0..block_number.0 {
+        if read_message(&mut reader)?.is_none() {
+            break;
+        }
+    }
+    let Some(message) = read_message(&mut reader)? else {
+        info!(
+            "The snapshot ends before block {block_number}, the latest block proved on the base \
+             layer. The sync verifies its blocks once it reaches that block."
+        );
+        return Ok(None);
+    };
+    let snapshot_hash = FullBlock::try_from(message)?.signed_header.block_header.block_hash;
+    if snapshot_hash != base_layer_hash {
+        return Err(SnapshotError::BaseLayerHashMismatch {
+            block_number,
+            base_layer_hash,
+            snapshot_hash,
+        });
+    }
+    info!("Verified block {block_number} of the snapshot against the base layer.");
+    Ok(Some(block_number))
+}
+
+fn read_full_block(
+    txn: &StorageTxn<'_, RO>,
+    block_number: BlockNumber,
+) -> Result<FullBlock, SnapshotError> {
+    let missing = |missing_data| SnapshotError::MissingBlockData { block_number, missing_data };
+    let block_header = txn.get_block_header(block_number)?.ok_or_else(|| missing("header"))?;
+    let signature = txn.get_block_signature(block_number)?.ok_or_else(|| missing("signature"))?;
+    let transactions =
+        txn.get_block_transactions(block_number)?.ok_or_else(|| missing("transactions"))?;
+    let transaction_outputs = txn
+        .get_block_transaction_outputs(block_number)?
+        .ok_or_else(|| missing("transaction outputs"))?;
+    let transaction_hashes = txn
+        .get_block_transaction_hashes(block_number)?
+        .ok_or_else(|| missing("transaction hashes"))?;
+    let state_diff = txn.get_state_diff(block_number)?.ok_or_else(|| missing("state diff"))?;
+
+    Ok(FullBlock {
+        signed_header: SignedBlockHeader {
+            block_header,
+            signatures: vec![signature],
+            data_availability: None,
+        },
+        transactions: transactions.into_iter().zip(transaction_outputs).collect(),
+        transaction_hashes,
+        state_diff_chunks: split_thin_state_diff(state_diff),
+    })
+}
+
+pub(crate) fn write_message(writer: &mut impl Write, message: &[u8]) -> io::Result<()> {
+    if message.len() > MAX_SNAPSHOT_MESSAGE_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidInput, message_too_large(message.len())));
+    }
+    let mut buffer = usize_buffer();
+    writer.write_all(unsigned_varint::encode::usize(message.len(), &mut buffer))?;
+    writer.write_all(message)
+}
+
+// Returns None if the reader reached EOF before starting to read the message.
+pub(crate) fn read_message(reader: &mut impl Read) -> io::Result<Option<Vec<u8>>> {
+    let mut buffer = usize_buffer();
+    let mut buffer_len = 0;
+    let message_len = loop {
+        if reader.read(&mut buffer[buffer_len..buffer_len + 1])? == 0 {
+            if buffer_len == 0 {
+                return Ok(None);
+            }
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        buffer_len += 1;
+        match unsigned_varint::decode::usize(&buffer[..buffer_len]) {
+            Ok((len, _)) => break len,
+            Err(unsigned_varint::decode::Error::Insufficient) => {}
+            Err(error) => return Err(io::Error::new(io::ErrorKind::InvalidData, error)),
+        }
+    };
+    if message_len > MAX_SNAPSHOT_MESSAGE_SIZE {
+        return Err(io::Error::new(io::ErrorKind::InvalidData, message_too_large(message_len)));
+    }
+    let mut message = vec![0u8; message_len];
+    reader.read_exact(&mut message)?;
+    Ok(Some(message))
+}
+
+fn message_too_large(message_len: usize) -> String {
+    format!(
+        "Message size ({message_len} bytes) exceeds maximum ({MAX_SNAPSHOT_MESSAGE_SIZE} bytes)"
+    )
+}
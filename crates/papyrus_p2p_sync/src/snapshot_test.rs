@@ -0,0 +1,209 @@
+use std::convert::Infallible;
+use std::io::{self, Cursor};
+
+use assert_matches::assert_matches;
+use async_trait::async_trait;
+use papyrus_base_layer::BaseLayerContract;
+use papyrus_protobuf::sync::{DeclaredClass, FullBlock, SignedBlockHeader, StateDiffChunk};
+use papyrus_storage::header::HeaderStorageReader;
+use papyrus_storage::state::StateStorageReader;
+use papyrus_storage::test_utils::get_test_storage;
+use papyrus_storage::StorageWriter;
+use starknet_api::block::{BlockHash, BlockHeader, BlockNumber, BlockSignature};
+use starknet_api::core::{ClassHash, CompiledClassHash};
+use starknet_types_core::felt::Felt;
+
+use crate::inject_block;
+use crate::snapshot::{
+    export_blocks,
+    import_blocks,
+    read_message,
+    verify_snapshot,
+    SnapshotError,
+    MAX_SNAPSHOT_MESSAGE_SIZE,
+};
+
+const N_BLOCKS: u64 = 3;
+
+fn create_block(block_number: u64) -> FullBlock {
+    let parent_hash =
+        if block_number == 0 { BlockHash::default() } else { BlockHash(Felt::from(block_number)) };
+    FullBlock {
+        signed_header: SignedBlockHeader {
+            block_header: BlockHeader {
+                block_number: BlockNumber(block_number),
+                block_hash: BlockHash(Felt::from(block_number + 1)),
+                parent_hash,
+                state_diff_length: Some(1),
+                ..Default::default()
+            },
+            signatures: vec![BlockSignature::default()],
+            data_availability: None,
+        },
+        transactions: vec![],
+        transaction_hashes: vec![],
+        state_diff_chunks: vec![StateDiffChunk::DeclaredClass(DeclaredClass {
+            class_hash: ClassHash(Felt::from(block_number)),
+            compiled_class_hash: CompiledClassHash(Felt::from(block_number)),
+        })],
+    }
+}
+
+fn inject_blocks(storage_writer: &mut StorageWriter, block_numbers: std::ops::Range<u64>) {
+    for block_number in block_numbers {
+        inject_block(storage_writer, create_block(block_number)).unwrap();
+    }
+}
+
+#[test]
+fn export_and_import() {
+    let ((source_reader, mut source_writer), _source_temp_dir) = get_test_storage();
+    inject_blocks(&mut source_writer, 0..N_BLOCKS);
+    let snapshot_dir = tempfile::tempdir().unwrap();
+    export_blocks(&source_reader, BlockNumber(N_BLOCKS), snapshot_dir.path()).unwrap();
+
+    let ((target_reader, mut target_writer), _target_temp_dir) = get_test_storage();
+    let next_block_number = import_blocks(&mut target_writer, snapshot_dir.path(), false).unwrap();
+    assert_eq!(next_block_number, BlockNumber(N_BLOCKS));
+
+    let source_txn = source_reader.begin_ro_txn().unwrap();
+    let target_txn = target_reader.begin_ro_txn().unwrap();
+    assert_eq!(target_txn.get_header_marker().unwrap(), BlockNumber(N_BLOCKS));
+    assert_eq!(target_txn.get_state_marker().unwrap(), BlockNumber(N_BLOCKS));
+    for block_number in (0..N_BLOCKS).map(BlockNumber) {
+        assert_eq!(
+            target_txn.get_block_header(block_number).unwrap(),
+            source_txn.get_block_header(block_number).unwrap()
+        );
+        assert_eq!(
+            target_txn.get_state_diff(block_number).unwrap(),
+            source_txn.get_state_diff(block_number).unwrap()
+        );
+    }
+}
+
+#[test]
+fn export_unsynced_blocks() {
+    let ((storage_reader, mut storage_writer), _temp_dir) = get_test_storage();
+    inject_blocks(&mut storage_writer, 0..1);
+    let snapshot_dir = tempfile::tempdir().unwrap();
+
+    let result = export_blocks(&storage_reader, BlockNumber(2), snapshot_dir.path());
+    assert_matches!(
+        result,
+        Err(SnapshotError::BlocksNotSynced { up_to: BlockNumber(2), synced_up_to: BlockNumber(1) })
+    );
+}
+
+#[test]
+fn import_into_non_empty_storage() {
+    let ((source_reader, mut source_writer), _source_temp_dir) = get_test_storage();
+    inject_blocks(&mut source_writer, 0..N_BLOCKS);
+    let snapshot_dir = tempfile::tempdir().unwrap();
+    export_blocks(&source_reader, BlockNumber(N_BLOCKS), snapshot_dir.path()).unwrap();
+
+    let ((target_reader, mut target_writer), _target_temp_dir) = get_test_storage();
+    inject_blocks(&mut target_writer, 0..1);
+
+    let result = import_blocks(&mut target_writer, snapshot_dir.path(), false);
+    assert_matches!(result, Err(SnapshotError::NonEmptyStorage { header_marker: BlockNumber(1) }));
+    assert_eq!(target_reader.begin_ro_txn().unwrap().get_header_marker().unwrap(), BlockNumber(1));
+
+    let next_block_number = import_blocks(&mut target_writer, snapshot_dir.path(), true).unwrap();
+    assert_eq!(next_block_number, BlockNumber(N_BLOCKS));
+    assert_eq!(
+        target_reader.begin_ro_txn().unwrap().get_header_marker().unwrap(),
+        BlockNumber(N_BLOCKS)
+    );
+}
+
+#[test]
+fn import_snapshot_behind_storage() {
+    let ((source_reader, mut source_writer), _source_temp_dir) = get_test_storage();
+    inject_blocks(&mut source_writer, 0..1);
+    let snapshot_dir = tempfile::tempdir().unwrap();
+    export_blocks(&source_reader, BlockNumber(1), snapshot_dir.path()).unwrap();
+
+    let ((_target_reader, mut target_writer), _target_temp_dir) = get_test_storage();
+    inject_blocks(&mut target_writer, 0..N_BLOCKS);
+
+    let result = import_blocks(&mut target_writer, snapshot_dir.path(), true);
+    assert_matches!(
+        result,
+        Err(SnapshotError::SnapshotBehindStorage {
+            last_block_in_snapshot: BlockNumber(0),
+            header_marker: BlockNumber(N_BLOCKS),
+        })
+    );
+}
+
+#[test]
+fn read_message_with_oversized_length_prefix() {
+    let mut buffer = unsigned_varint::encode::usize_buffer();
+    let length_prefix =
+        unsigned_varint::encode::usize(MAX_SNAPSHOT_MESSAGE_SIZE + 1, &mut buffer).to_vec();
+
+    let error = read_message(&mut Cursor::new(length_prefix)).unwrap_err();
+    assert_eq!(error.kind(), io::ErrorKind::InvalidData);
+}
+
+// A base layer that proved the given block.
+struct TestBaseLayer(Option<(BlockNumber, BlockHash)>);
+
+#[async_trait]
+impl BaseLayerContract for TestBaseLayer {
+    type Error = Infallible;
+
+    async fn latest_proved_block(
+        &self,
+        _min_confirmations: Option<u64>,
+    ) -> Result<Option<(BlockNumber, BlockHash)>, Self::Error> {
+        Ok(self.0)
+    }
+}
+
+fn export_test_snapshot() -> tempfile::TempDir {
+    let ((source_reader, mut source_writer), _source_temp_dir) = get_test_storage();
+    inject_blocks(&mut source_writer, 0..N_BLOCKS);
+    let snapshot_dir = tempfile::tempdir().unwrap();
+    export_blocks(&source_reader, BlockNumber(N_BLOCKS), snapshot_dir.path()).unwrap();
+    snapshot_dir
+}
+
+#[tokio::test]
+async fn verify_snapshot_matching_the_base_layer() {
+    let snapshot_dir = export_test_snapshot();
+    let proved_block_number = BlockNumber(N_BLOCKS - 2);
+    let proved_block_hash =
+        create_block(proved_block_number.0).signed_header.block_header.block_hash;
+    let base_layer = TestBaseLayer(Some((proved_block_number, proved_block_hash)));
+
+    let verified_block = verify_snapshot(snapshot_dir.path(), &base_layer).await.unwrap();
+    assert_eq!(verified_block, Some(proved_block_number));
+}
+
+#[tokio::test]
+async fn verify_snapshot_mismatching_the_base_layer() {
+    let snapshot_dir = export_test_snapshot();
+    let proved_block_number = BlockNumber(N_BLOCKS - 1);
+    let base_layer_hash = BlockHash(Felt::from(1000_u64));
+    let base_layer = TestBaseLayer(Some((proved_block_number, base_layer_hash)));
+
+    let result = verify_snapshot(snapshot_dir.path(), &base_layer).await;
+    assert_matches!(
+        result,
+        Err(SnapshotError::BaseLayerHashMismatch { block_number, base_layer_hash: hash, .. })
+        if block_number == proved_block_number && hash == base_layer_hash
+    );
+}
+
+#[tokio::test]
+async fn verify_snapshot_shorter_than_the_base_layer() {
+    let snapshot_dir = export_test_snapshot();
+    let base_layer_hash = BlockHash(Felt::from(1000_u64));
+    let base_layer = TestBaseLayer(Some((BlockNumber(N_BLOCKS), base_layer_hash)));
+
+    // The proved block isn't in the snapshot, so it's left for the sync to verify.
+    let verified_block = verify_snapshot(snapshot_dir.path(), &base_layer).await.unwrap();
+    assert_eq!(verified_block, None);
+}
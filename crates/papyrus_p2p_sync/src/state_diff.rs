@@ -6,14 +6,17 @@ use futures::channel::mpsc::SendError;
 use futures::future::BoxFuture;
 use futures::{FutureExt, Sink, Stream, StreamExt};
 use indexmap::IndexMap;
+use papyrus_common::state_diff_commitment::{calculate_state_diff_commitment, StateDiffVersion};
+use papyrus_network::network_manager::QueryPriority;
 use papyrus_proc_macros::latency_histogram;
 use papyrus_protobuf::sync::Query;
 use papyrus_storage::header::HeaderStorageReader;
 use papyrus_storage::state::{StateStorageReader, StateStorageWriter};
 use papyrus_storage::{StorageError, StorageReader, StorageWriter};
-use starknet_api::block::BlockNumber;
+use starknet_api::block::{BlockHeader, BlockNumber};
 use starknet_api::state::ThinStateDiff;
 
+use crate::response_validator::ValidatedResponseReceiver;
 use crate::stream_factory::{BlockData, BlockNumberLimit, DataStreamFactory};
 use crate::{P2PSyncError, Response, NETWORK_DATA_TIMEOUT};
 
@@ -35,7 +38,7 @@ pub(crate) struct StateDiffStreamFactory<QuerySender, DataReceiver>(
 impl<QuerySender, DataReceiver> DataStreamFactory<QuerySender, DataReceiver, ThinStateDiff>
     for StateDiffStreamFactory<QuerySender, DataReceiver>
 where
-    QuerySender: Sink<Query, Error = SendError> + Unpin + Send + 'static,
+    QuerySender: Sink<(Query, QueryPriority), Error = SendError> + Unpin + Send + 'static,
     DataReceiver: Stream<Item = Response<ThinStateDiff>> + Unpin + Send + 'static,
 {
     type Output = (ThinStateDiff, BlockNumber);
@@ -45,7 +48,7 @@ where
 
     #[latency_histogram("p2p_sync_state_diff_parse_data_for_block_latency_seconds", true)]
     fn parse_data_for_block<'a>(
-        state_diffs_receiver: &'a mut DataReceiver,
+        state_diffs_receiver: &'a mut ValidatedResponseReceiver<DataReceiver>,
         block_number: BlockNumber,
         storage_reader: &'a StorageReader,
     ) -> BoxFuture<'a, Result<Option<Self::Output>, P2PSyncError>> {
@@ -53,12 +56,12 @@ where
             let mut result = ThinStateDiff::default();
             let mut prev_result_len = 0;
             let mut current_state_diff_len = 0;
-            let target_state_diff_len = storage_reader
+            let block_header = storage_reader
                 .begin_ro_txn()?
                 .get_block_header(block_number)?
-                .expect("A header with number lower than the header marker is missing")
-                .state_diff_length
-                .ok_or(P2PSyncError::OldHeaderInStorage {
+                .expect("A header with number lower than the header marker is missing");
+            let target_state_diff_len =
+                block_header.state_diff_length.ok_or(P2PSyncError::OldHeaderInStorage {
                     block_number,
                     missing_field: "state_diff_length",
                 })?;
@@ -97,7 +100,7 @@ where
                 });
             }
 
-            validate_deprecated_declared_classes_non_conflicting(&result)?;
+            validate_state_diff(&result, &block_header)?;
             Ok(Some((result, block_number)))
         }
         .boxed()
@@ -108,10 +111,54 @@ where
     }
 }
 
+/// Unites the parts of the state diff of the block with the given header and validates the result.
+/// The parts are validated the same way as parts that are received from the network.
+pub fn unite_and_validate_state_diff_parts(
+    state_diff_parts: impl IntoIterator<Item = ThinStateDiff>,
+    block_header: &BlockHeader,
+) -> Result<ThinStateDiff, P2PSyncError> {
+    let mut result = ThinStateDiff::default();
+    let mut state_diff_len = 0;
+    for state_diff_part in state_diff_parts {
+        if state_diff_part.is_empty() {
+            return Err(P2PSyncError::EmptyStateDiffPart);
+        }
+        state_diff_len += state_diff_part.len();
+        unite_state_diffs(&mut result, state_diff_part)?;
+    }
+    if let Some(expected_length) = block_header.state_diff_length {
+        if state_diff_len != expected_length {
+            return Err(P2PSyncError::WrongStateDiffLength {
+                expected_length,
+                possible_lengths: vec![state_diff_len],
+            });
+        }
+    }
+    validate_state_diff(&result, block_header)?;
+    Ok(result)
+}
+
+/// Validates a full state diff of the block with the given header.
+pub(crate) fn validate_state_diff(
+    state_diff: &ThinStateDiff,
+    block_header: &BlockHeader,
+) -> Result<(), P2PSyncError> {
+    validate_deprecated_declared_classes_non_conflicting(state_diff)?;
+    if let Some(expected_commitment) = block_header.state_diff_commitment {
+        if calculate_state_diff_commitment(state_diff, StateDiffVersion::V0) != expected_commitment
+        {
+            return Err(P2PSyncError::StateDiffCommitmentMismatch {
+                block_number: block_header.block_number,
+            });
+        }
+    }
+    Ok(())
+}
+
 // For performance reasons, this function does not check if a deprecated class was declared twice.
 // That check is done after we get the final state diff.
 #[latency_histogram("p2p_sync_state_diff_unite_state_diffs_latency_seconds", true)]
-fn unite_state_diffs(
+pub(crate) fn unite_state_diffs(
     state_diff: &mut ThinStateDiff,
     other_state_diff: ThinStateDiff,
 ) -> Result<(), P2PSyncError> {
@@ -16,6 +16,7 @@ use test_utils::get_rng;
 
 use crate::test_utils::{
     create_block_hashes_and_signatures,
+    get_parent_hash,
     setup,
     TestArgs,
     HEADER_QUERY_LENGTH,
@@ -78,6 +79,7 @@ async fn state_diff_basic_flow() {
         // We don't need to read the header query in order to know which headers to send, and we
         // already validate the header query in a different test.
         header_query_receiver: _header_query_receiver,
+        ..
     } = setup();
 
     let block_hashes_and_signatures =
@@ -105,10 +107,12 @@ async fn state_diff_basic_flow() {
                         block_header: BlockHeader {
                             block_number: BlockNumber(i.try_into().unwrap()),
                             block_hash: *block_hash,
+                            parent_hash: get_parent_hash(&block_hashes_and_signatures, i),
                             state_diff_length: Some(state_diff.len()),
                             ..Default::default()
                         },
                         signatures: vec![*block_signature],
+                        data_availability: None,
                     }))),
                     Box::new(|| {}),
                 ))
@@ -186,6 +190,7 @@ async fn validate_state_diff_fails(
         // We don't need to read the header query in order to know which headers to send, and we
         // already validate the header query in a different test.
         header_query_receiver: _header_query_receiver,
+        ..
     } = setup();
 
     let (block_hash, block_signature) = *create_block_hashes_and_signatures(1).first().unwrap();
@@ -203,6 +208,7 @@ async fn validate_state_diff_fails(
                         ..Default::default()
                     },
                     signatures: vec![block_signature],
+                    data_availability: None,
                 }))),
                 Box::new(|| {}),
             ))
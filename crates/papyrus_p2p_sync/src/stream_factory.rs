@@ -6,12 +6,16 @@ use futures::channel::mpsc::SendError;
 use futures::future::BoxFuture;
 use futures::stream::BoxStream;
 use futures::{Sink, SinkExt, Stream, StreamExt};
+use papyrus_common::BlockHashAndNumber;
+use papyrus_network::network_manager::QueryPriority;
 use papyrus_protobuf::sync::{BlockHashOrNumber, DataOrFin, Direction, Query};
 use papyrus_storage::header::HeaderStorageReader;
 use papyrus_storage::{StorageError, StorageReader, StorageWriter};
-use starknet_api::block::BlockNumber;
-use tracing::{debug, info};
+use starknet_api::block::{BlockHash, BlockNumber};
+use tokio::time::Instant;
+use tracing::{debug, info, warn};
 
+use crate::response_validator::{ValidatedResponse, ValidatedResponseReceiver};
 use crate::{P2PSyncError, Response, STEP};
 
 pub(crate) trait BlockData: Send {
@@ -20,6 +24,12 @@ pub(crate) trait BlockData: Send {
         self: Box<Self>,
         storage_writer: &mut StorageWriter,
     ) -> Result<(), StorageError>;
+
+    /// The block whose existence this data proves, if any. The data is validated before it's
+    /// returned by the stream, so peers can't make the node believe in blocks that don't exist.
+    fn proven_block(&self) -> Option<BlockHashAndNumber> {
+        None
+    }
 }
 
 pub(crate) enum BlockNumberLimit {
@@ -30,9 +40,9 @@ pub(crate) enum BlockNumberLimit {
 
 pub(crate) trait DataStreamFactory<QuerySender, DataReceiver, InputFromNetwork>
 where
-    QuerySender: Sink<Query, Error = SendError> + Unpin + Send + 'static,
+    QuerySender: Sink<(Query, QueryPriority), Error = SendError> + Unpin + Send + 'static,
     DataReceiver: Stream<Item = Response<InputFromNetwork>> + Unpin + Send + 'static,
-    InputFromNetwork: Send + 'static,
+    InputFromNetwork: ValidatedResponse + Send + 'static,
     DataOrFin<InputFromNetwork>: TryFrom<Vec<u8>>,
     <DataOrFin<InputFromNetwork> as TryFrom<Vec<u8>>>::Error: Send,
 {
@@ -43,25 +53,65 @@ where
 
     // Async functions in trait don't work well with argument references
     fn parse_data_for_block<'a>(
-        data_receiver: &'a mut DataReceiver,
+        data_receiver: &'a mut ValidatedResponseReceiver<DataReceiver>,
         block_number: BlockNumber,
         storage_reader: &'a StorageReader,
     ) -> BoxFuture<'a, Result<Option<Self::Output>, P2PSyncError>>;
 
     fn get_start_block_number(storage_reader: &StorageReader) -> Result<BlockNumber, StorageError>;
 
+    /// Returns the priority of the queries sent by the stream. `is_following_tip` is true when the
+    /// previous query returned partial data, which means the stream reached the tip of the chain.
+    fn query_priority(_is_following_tip: bool) -> QueryPriority {
+        QueryPriority::Normal
+    }
+
+    /// Returns a query for the blocks of the given query that weren't received yet, given that
+    /// all the blocks before `next_block_number` were received and validated. This is used for
+    /// resuming a query whose session failed without downloading the received blocks again.
+    /// Partial data of `next_block_number` is discarded, since it will be sent again. The
+    /// continuation of an open-ended query is open-ended as well.
+    fn create_continuation_query(query: &Query, next_block_number: BlockNumber) -> Query {
+        let num_received_blocks = match query.start_block {
+            BlockHashOrNumber::Number(start_block_number) => {
+                (next_block_number.0 - start_block_number.0) / query.step
+            }
+            // The sync sends only queries by block number, and for a query by hash we can't tell
+            // which blocks were received.
+            BlockHashOrNumber::Hash(_) => 0,
+        };
+        let limit = if query.is_open_ended() {
+            Query::OPEN_ENDED_LIMIT
+        } else {
+            query.limit - num_received_blocks
+        };
+        Query { start_block: BlockHashOrNumber::Number(next_block_number), limit, ..query.clone() }
+    }
+
+    /// Creates a stream of the data of the blocks from the start block on. Once the stream
+    /// reaches the tip of the chain, if `follow_tip` is set, it sends open-ended queries, which
+    /// peers answer with new blocks as soon as they get them, instead of querying again every
+    /// `wait_period_for_new_data`.
+    #[allow(clippy::too_many_arguments)]
     fn create_stream(
         mut query_sender: QuerySender,
-        mut data_receiver: DataReceiver,
+        data_receiver: DataReceiver,
         storage_reader: StorageReader,
         wait_period_for_new_data: Duration,
         num_blocks_per_query: u64,
         stop_sync_at_block_number: Option<BlockNumber>,
+        follow_tip: bool,
     ) -> BoxStream<'static, Result<Box<dyn BlockData>, P2PSyncError>> {
         stream! {
+            let mut data_receiver =
+                ValidatedResponseReceiver::new(data_receiver, Self::TYPE_DESCRIPTION);
             let mut current_block_number = Self::get_start_block_number(&storage_reader)?;
+            let mut is_following_tip = false;
             'send_query_and_parse_responses: loop {
                 let limit = match Self::BLOCK_NUMBER_LIMIT {
+                    BlockNumberLimit::Unlimited if follow_tip && is_following_tip => {
+                        Query::OPEN_ENDED_LIMIT
+                    }
                     BlockNumberLimit::Unlimited => num_blocks_per_query,
                     BlockNumberLimit::HeaderMarker => {
                         let last_block_number = storage_reader.begin_ro_txn()?.get_header_marker()?;
@@ -77,30 +127,69 @@ where
                         limit
                     }
                 };
-                let end_block_number = current_block_number.0 + limit;
+                let end_block_number = current_block_number.0.saturating_add(limit);
                 debug!(
                     "Downloading {:?} for blocks [{}, {})",
                     Self::TYPE_DESCRIPTION,
                     current_block_number.0,
                     end_block_number,
                 );
-                query_sender
-                    .send(
-                        Query {
-                            start_block: BlockHashOrNumber::Number(current_block_number),
-                            direction: Direction::Forward,
-                            limit,
-                            step: STEP,
-                        },
-                    )
-                    .await?;
+                let mut query = Query {
+                    start_block: BlockHashOrNumber::Number(current_block_number),
+                    direction: Direction::Forward,
+                    limit,
+                    step: STEP,
+                };
+                data_receiver.start_session(
+                    &query, get_previous_block_hash(&storage_reader, current_block_number)?
+                );
+                query_sender.send((query.clone(), Self::query_priority(is_following_tip))).await?;
+                let mut query_sent_at = Instant::now();
 
                 while current_block_number.0 < end_block_number {
                     match Self::parse_data_for_block(
                         &mut data_receiver, current_block_number, &storage_reader
-                    ).await? {
-                        Some(output) => yield Ok(Box::<dyn BlockData>::from(Box::new(output))),
-                        None => {
+                    ).await {
+                        Ok(Some(output)) => yield Ok(Box::<dyn BlockData>::from(Box::new(output))),
+                        // The peer sent some of the blocks and ended the response, e.g. because
+                        // the response reached the peer's limits. The rest of the blocks are
+                        // queried right away.
+                        Ok(None)
+                            if query.start_block
+                                != BlockHashOrNumber::Number(current_block_number) =>
+                        {
+                            query = Self::create_continuation_query(&query, current_block_number);
+                            debug!(
+                                "Query for {:?} ended before block {}. Querying the rest of the \
+                                 blocks.",
+                                Self::TYPE_DESCRIPTION,
+                                current_block_number,
+                            );
+                            data_receiver.start_session(
+                                &query,
+                                get_previous_block_hash(&storage_reader, current_block_number)?,
+                            );
+                            query_sender
+                                .send((query.clone(), Self::query_priority(is_following_tip)))
+                                .await?;
+                            query_sent_at = Instant::now();
+                            continue;
+                        }
+                        // A peer that follows the tip ends an open-ended query once no new block
+                        // was added for a while, so it's sent again right away. A peer that
+                        // doesn't follow the tip ends it immediately, so it's sent again only
+                        // once the wait period passed since it was sent.
+                        Ok(None) if query.is_open_ended() => {
+                            debug!(
+                                "Open-ended query for {:?} ended with no new block. Sending \
+                                 another query.",
+                                Self::TYPE_DESCRIPTION,
+                            );
+                            tokio::time::sleep_until(query_sent_at + wait_period_for_new_data)
+                                .await;
+                            continue 'send_query_and_parse_responses;
+                        }
+                        Ok(None) => {
                             debug!(
                                 "Query for {:?} returned with partial data. Waiting {:?} before \
                                  sending another query.",
@@ -108,8 +197,42 @@ where
                                 wait_period_for_new_data
                             );
                             tokio::time::sleep(wait_period_for_new_data).await;
+                            is_following_tip = true;
                             continue 'send_query_and_parse_responses;
                         }
+                        // The network notifies us only when no peer could be connected to send
+                        // the query to, so other failures are detected by not receiving data for a
+                        // while. The network dials peers again for the resumed query.
+                        Err(
+                            error @ (P2PSyncError::NetworkTimeout(_) | P2PSyncError::NoPeers(_))
+                        ) => {
+                            query = Self::create_continuation_query(&query, current_block_number);
+                            if let P2PSyncError::NoPeers(error) = error {
+                                warn!(
+                                    "Failed to query {:?} of block {}: {error} Resuming the query \
+                                     from this block.",
+                                    Self::TYPE_DESCRIPTION,
+                                    current_block_number,
+                                );
+                            } else {
+                                info!(
+                                    "Timed out waiting for {:?} of block {}. Resuming the query \
+                                     from this block.",
+                                    Self::TYPE_DESCRIPTION,
+                                    current_block_number,
+                                );
+                            }
+                            data_receiver.start_session(
+                                &query,
+                                get_previous_block_hash(&storage_reader, current_block_number)?,
+                            );
+                            query_sender
+                                .send((query.clone(), Self::query_priority(is_following_tip)))
+                                .await?;
+                            query_sent_at = Instant::now();
+                            continue;
+                        }
+                        Err(err) => Err(err)?,
                     }
                     info!("Added {:?} for block {}.", Self::TYPE_DESCRIPTION, current_block_number);
                     current_block_number = current_block_number.unchecked_next();
@@ -125,6 +248,9 @@ where
                 match data_receiver.next().await {
                     Some((Ok(DataOrFin(None)), _report_callback)) => {
                         debug!("Query sent to network for {:?} finished", Self::TYPE_DESCRIPTION);
+                        // All the blocks of the query exist, so there may be more blocks to catch
+                        // up on.
+                        is_following_tip = false;
                     },
                     Some(_) => Err(P2PSyncError::TooManyResponses)?,
                     None => Err(P2PSyncError::ReceiverChannelTerminated {
@@ -136,3 +262,17 @@ where
         .boxed()
     }
 }
+
+// Returns the hash of the block before the given block, if its header is in the storage.
+pub(crate) fn get_previous_block_hash(
+    storage_reader: &StorageReader,
+    block_number: BlockNumber,
+) -> Result<Option<BlockHash>, StorageError> {
+    let Some(previous_block_number) = block_number.0.checked_sub(1).map(BlockNumber) else {
+        return Ok(None);
+    };
+    Ok(storage_reader
+        .begin_ro_txn()?
+        .get_block_header(previous_block_number)?
+        .map(|block_header| block_header.block_hash))
+}
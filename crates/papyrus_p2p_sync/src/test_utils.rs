@@ -1,15 +1,19 @@
+use std::sync::Arc;
 use std::time::Duration;
 
 use futures::channel::mpsc::{Receiver, Sender};
 use lazy_static::lazy_static;
+use papyrus_common::BlockHashAndNumber;
+use papyrus_network::network_manager::QueryPriority;
 use papyrus_protobuf::sync::{HeaderQuery, SignedBlockHeader, StateDiffQuery};
 use papyrus_storage::test_utils::get_test_storage;
 use papyrus_storage::StorageReader;
-use starknet_api::block::{BlockHash, BlockSignature};
+use starknet_api::block::{BlockHash, BlockNumber, BlockSignature};
 use starknet_api::crypto::utils::Signature;
 use starknet_api::hash::StarkHash;
 use starknet_api::state::ThinStateDiff;
 use starknet_types_core::felt::Felt;
+use tokio::sync::{watch, RwLock};
 
 use crate::{P2PSync, P2PSyncConfig, Response};
 
@@ -28,22 +32,33 @@ lazy_static! {
         num_block_state_diffs_per_query: STATE_DIFF_QUERY_LENGTH,
         wait_period_for_new_data: WAIT_PERIOD_FOR_NEW_DATA,
         stop_sync_at_block_number: None,
+        max_parallel_state_diff_sessions: 1,
+        revert_on_base_layer_mismatch: false,
+        follow_tip: false,
+        shadow: false,
+        record_path: None,
+        replay_path: None,
+        combined_block_download: false,
+        combined_download_max_window_bytes: 10 * 1024 * 1024,
+        combined_download_max_block_bytes: 1024 * 1024,
     };
 }
 
 pub struct TestArgs {
     #[allow(clippy::type_complexity)]
     pub p2p_sync: P2PSync<
-        Sender<HeaderQuery>,
+        Sender<(HeaderQuery, QueryPriority)>,
         Receiver<Response<SignedBlockHeader>>,
         Sender<StateDiffQuery>,
         Receiver<Response<ThinStateDiff>>,
     >,
     pub storage_reader: StorageReader,
-    pub header_query_receiver: Receiver<HeaderQuery>,
+    pub header_query_receiver: Receiver<(HeaderQuery, QueryPriority)>,
     pub state_diff_query_receiver: Receiver<StateDiffQuery>,
     pub headers_sender: Sender<Response<SignedBlockHeader>>,
     pub state_diffs_sender: Sender<Response<ThinStateDiff>>,
+    pub shared_highest_block: Arc<RwLock<Option<BlockHashAndNumber>>>,
+    pub header_marker_receiver: watch::Receiver<BlockNumber>,
 }
 
 pub fn setup() -> TestArgs {
@@ -53,14 +68,19 @@ pub fn setup() -> TestArgs {
         futures::channel::mpsc::channel(BUFFER_SIZE);
     let (headers_sender, headers_receiver) = futures::channel::mpsc::channel(BUFFER_SIZE);
     let (state_diffs_sender, state_diffs_receiver) = futures::channel::mpsc::channel(BUFFER_SIZE);
+    let shared_highest_block = Arc::new(RwLock::new(None));
+    let (header_marker_sender, header_marker_receiver) = watch::channel(BlockNumber(0));
     let p2p_sync = P2PSync::new(
-        *TEST_CONFIG,
+        TEST_CONFIG.clone(),
         storage_reader.clone(),
         storage_writer,
         header_query_sender,
         headers_receiver,
-        state_diff_query_sender,
-        state_diffs_receiver,
+        vec![(state_diff_query_sender, state_diffs_receiver)],
+        None,
+        shared_highest_block.clone(),
+        None,
+        header_marker_sender,
     );
     TestArgs {
         p2p_sync,
@@ -69,6 +89,8 @@ pub fn setup() -> TestArgs {
         state_diff_query_receiver,
         headers_sender,
         state_diffs_sender,
+        shared_highest_block,
+        header_marker_receiver,
     }
 }
 
@@ -87,3 +109,15 @@ pub fn create_block_hashes_and_signatures(n_blocks: u8) -> Vec<(BlockHash, Block
         })
         .collect()
 }
+
+// The parent hash of the block at the given index, where the blocks' hashes are taken from the
+// given vector.
+pub fn get_parent_hash(
+    block_hashes_and_signatures: &[(BlockHash, BlockSignature)],
+    block_index: usize,
+) -> BlockHash {
+    block_index
+        .checked_sub(1)
+        .map(|parent_index| block_hashes_and_signatures[parent_index].0)
+        .unwrap_or_default()
+}
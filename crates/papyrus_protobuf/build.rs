@@ -33,6 +33,9 @@ fn main() -> Result<()> {
                 "src/proto/p2p/proto/state.proto",
                 "src/proto/p2p/proto/transaction.proto",
                 "src/proto/p2p/proto/consensus.proto",
+                "src/proto/p2p/proto/block.proto",
+                "src/proto/p2p/proto/block_range.proto",
+                "src/proto/p2p/proto/mempool.proto",
             ],
             &["src/proto/"],
         )?;
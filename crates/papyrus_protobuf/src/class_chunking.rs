@@ -0,0 +1,193 @@
+#[cfg(test)]
+#[path = "class_chunking_test.rs"]
+mod class_chunking_test;
+
+use papyrus_common::pending_classes::ApiContractClass;
+use prost::Message;
+use sha3::Digest;
+
+use crate::converters::ProtobufConversionError;
+use crate::protobuf;
+use crate::sync::{ClassChunk, ClassChunkHeader, ClassMessage};
+
+/// An upper bound on the number of bytes a `ClassesResponse` adds on top of the data of the chunk
+/// it holds.
+pub const MAX_CHUNK_OVERHEAD: usize = 64;
+
+#[derive(thiserror::Error, Debug)]
+pub enum ClassChunkingError {
+    #[error("Class of size {size} exceeds the maximum class size {max_class_size}.")]
+    ClassTooLarge { size: u64, max_class_size: u64 },
+    #[error("Received a class chunk without a preceding chunk header.")]
+    ChunkWithoutHeader,
+    #[error("Received an empty class chunk.")]
+    EmptyChunk,
+    #[error("Expected a class chunk at offset {expected_offset}, got one at offset {offset}.")]
+    UnexpectedChunkOffset { expected_offset: u64, offset: u64 },
+    #[error("Class chunk ends at {chunk_end}, after the end of the class at {total_size}.")]
+    ChunkExceedsClassSize { chunk_end: u64, total_size: u64 },
+    #[error(
+        "The class stream was interrupted after {received_size} out of {total_size} bytes of a \
+         chunked class."
+    )]
+    IncompleteClass { received_size: u64, total_size: u64 },
+    #[error("The hash of the reassembled class doesn't match the hash in its chunk header.")]
+    ContentHashMismatch,
+    #[error(transparent)]
+    ProtobufConversionError(#[from] ProtobufConversionError),
+}
+
+/// Splits a class into the messages that should be sent for it. If the class fits in a message of
+/// `max_message_size` bytes it's sent as is. Otherwise, it's sent as a chunk header followed by
+/// chunks of its encoding that each fit in such a message.
+///
+/// `max_message_size` must be larger than [`MAX_CHUNK_OVERHEAD`].
+pub fn split_class_into_messages(
+    class: ApiContractClass,
+    max_message_size: usize,
+    max_class_size: u64,
+) -> Result<Vec<ClassMessage>, ClassChunkingError> {
+    assert!(
+        max_message_size > MAX_CHUNK_OVERHEAD,
+        "max_message_size should be larger than {MAX_CHUNK_OVERHEAD}."
+    );
+    let encoded_class = protobuf::Class::from(class.clone()).encode_to_vec();
+    let total_size = u64::try_from(encoded_class.len()).expect("usize should fit in u64");
+    if total_size > max_class_size {
+        return Err(ClassChunkingError::ClassTooLarge { size: total_size, max_class_size });
+    }
+    if encoded_class.len() + MAX_CHUNK_OVERHEAD <= max_message_size {
+        return Ok(vec![ClassMessage::Class(class)]);
+    }
+
+    let content_hash = sha3::Keccak256::digest(&encoded_class).into();
+    let mut messages =
+        vec![ClassMessage::ChunkHeader(ClassChunkHeader { total_size, content_hash })];
+    let max_chunk_size = max_message_size - MAX_CHUNK_OVERHEAD;
+    let mut offset = 0;
+    for data in encoded_class.chunks(max_chunk_size) {
+        messages.push(ClassMessage::Chunk(ClassChunk { offset, data: data.to_vec() }));
+        offset += u64::try_from(data.len()).expect("usize should fit in u64");
+    }
+    Ok(messages)
+}
+
+/// Rebuilds the classes of a classes response from its messages, verifying the hash of every
+/// chunked class.
+///
+/// A chunked class is kept only until it's complete. If an error occurs or the stream ends before
+/// that, the partially received class is discarded.
+pub struct ClassReassembler {
+    max_class_size: u64,
+    partial_class: Option<PartialClass>,
+}
+
+struct PartialClass {
+    total_size: u64,
+    content_hash: [u8; 32],
+    data: Vec<u8>,
+}
+
+impl ClassReassembler {
+    pub fn new(max_class_size: u64) -> Self {
+        Self { max_class_size, partial_class: None }
+    }
+
+    /// Handles the next message of the stream. Returns the class once all of it was received.
+    pub fn push(
+        &mut self,
+        message: ClassMessage,
+    ) -> Result<Option<ApiContractClass>, ClassChunkingError> {
+        let result = self.handle_message(message);
+        if result.is_err() {
+            self.partial_class = None;
+        }
+        result
+    }
+
+    /// Should be called once the stream ended. Returns an error if the stream ended in the middle
+    /// of a chunked class.
+    pub fn finish(self) -> Result<(), ClassChunkingError> {
+        match self.partial_class {
+            Some(partial_class) => Err(partial_class.incomplete_error()),
+            None => Ok(()),
+        }
+    }
+
+    fn handle_message(
+        &mut self,
+        message: ClassMessage,
+    ) -> Result<Option<ApiContractClass>, ClassChunkingError> {
+        match message {
+            ClassMessage::Class(class) => {
+                if let Some(partial_class) = &self.partial_class {
+                    return Err(partial_class.incomplete_error());
+                }
+                Ok(Some(class))
+            }
+            ClassMessage::ChunkHeader(ClassChunkHeader { total_size, content_hash }) => {
+                if let Some(partial_class) = &self.partial_class {
+                    return Err(partial_class.incomplete_error());
+                }
+                if total_size > self.max_class_size {
+                    return Err(ClassChunkingError::ClassTooLarge {
+                        size: total_size,
+                        max_class_size: self.max_class_size,
+                    });
+                }
+                let capacity = usize::try_from(total_size).expect("u64 should fit in usize");
+                self.partial_class = Some(PartialClass {
+                    total_size,
+                    content_hash,
+                    data: Vec::with_capacity(capacity),
+                });
+                Ok(None)
+            }
+            ClassMessage::Chunk(ClassChunk { offset, data }) => {
+                let partial_class =
+                    self.partial_class.as_mut().ok_or(ClassChunkingError::ChunkWithoutHeader)?;
+                if data.is_empty() {
+                    return Err(ClassChunkingError::EmptyChunk);
+                }
+                let expected_offset =
+                    u64::try_from(partial_class.data.len()).expect("usize should fit in u64");
+                if offset != expected_offset {
+                    return Err(ClassChunkingError::UnexpectedChunkOffset {
+                        expected_offset,
+                        offset,
+                    });
+                }
+                let chunk_end =
+                    offset + u64::try_from(data.len()).expect("usize should fit in u64");
+                if chunk_end > partial_class.total_size {
+                    return Err(ClassChunkingError::ChunkExceedsClassSize {
+                        chunk_end,
+                        total_size: partial_class.total_size,
+                    });
+                }
+                partial_class.data.extend_from_slice(&data);
+                if chunk_end < partial_class.total_size {
+                    return Ok(None);
+                }
+
+                let PartialClass { content_hash, data, .. } =
+                    self.partial_class.take().expect("partial_class was checked to be Some");
+                if <[u8; 32]>::from(sha3::Keccak256::digest(&data)) != content_hash {
+                    return Err(ClassChunkingError::ContentHashMismatch);
+                }
+                let class = protobuf::Class::decode(data.as_slice())
+                    .map_err(ProtobufConversionError::from)?;
+                Ok(Some(class.try_into()?))
+            }
+        }
+    }
+}
+
+impl PartialClass {
+    fn incomplete_error(&self) -> ClassChunkingError {
+        ClassChunkingError::IncompleteClass {
+            received_size: u64::try_from(self.data.len()).expect("usize should fit in u64"),
+            total_size: self.total_size,
+        }
+    }
+}
@@ -0,0 +1,129 @@
+use std::collections::HashMap;
+
+use assert_matches::assert_matches;
+use papyrus_common::pending_classes::ApiContractClass;
+use starknet_api::state::ContractClass;
+use starknet_types_core::felt::Felt;
+
+use crate::class_chunking::{split_class_into_messages, ClassChunkingError, ClassReassembler};
+use crate::sync::{ClassMessage, DataOrFin};
+
+const MB: usize = 1 << 20;
+const MAX_MESSAGE_SIZE: usize = MB;
+const MAX_CLASS_SIZE: u64 = 8 * MB as u64;
+
+fn create_class(abi_size: usize) -> ApiContractClass {
+    ApiContractClass::ContractClass(ContractClass {
+        // The conversion to protobuf reads the class version from the first 6 felts.
+        sierra_program: (0..6_u64).map(Felt::from).collect(),
+        entry_points_by_type: HashMap::new(),
+        abi: "a".repeat(abi_size),
+    })
+}
+
+// Passes the messages through their network encoding, checking that each of them fits in a
+// single message.
+fn send_messages(messages: Vec<ClassMessage>) -> Vec<ClassMessage> {
+    messages
+        .into_iter()
+        .map(|message| {
+            let bytes = Vec::<u8>::from(DataOrFin(Some(message)));
+            assert!(bytes.len() <= MAX_MESSAGE_SIZE);
+            DataOrFin::<ClassMessage>::try_from(bytes).unwrap().0.unwrap()
+        })
+        .collect()
+}
+
+#[test]
+fn small_class_is_sent_whole() {
+    let class = create_class(1000);
+    let messages = split_class_into_messages(class.clone(), MAX_MESSAGE_SIZE, MAX_CLASS_SIZE);
+    assert_eq!(messages.unwrap(), vec![ClassMessage::Class(class)]);
+}
+
+#[test]
+fn large_class_is_chunked_and_reassembled() {
+    let class = create_class(6 * MB);
+    let messages = send_messages(
+        split_class_into_messages(class.clone(), MAX_MESSAGE_SIZE, MAX_CLASS_SIZE).unwrap(),
+    );
+    assert_matches!(messages[0], ClassMessage::ChunkHeader(_));
+    assert!(messages.len() > 7);
+
+    let mut reassembler = ClassReassembler::new(MAX_CLASS_SIZE);
+    let (last_message, first_messages) = messages.split_last().unwrap();
+    for message in first_messages {
+        assert_eq!(reassembler.push(message.clone()).unwrap(), None);
+    }
+    assert_eq!(reassembler.push(last_message.clone()).unwrap(), Some(class));
+    reassembler.finish().unwrap();
+}
+
+#[test]
+fn truncated_stream_is_rejected() {
+    let class = create_class(6 * MB);
+    let mut messages =
+        split_class_into_messages(class.clone(), MAX_MESSAGE_SIZE, MAX_CLASS_SIZE).unwrap();
+    messages.pop();
+
+    let mut reassembler = ClassReassembler::new(MAX_CLASS_SIZE);
+    for message in messages.clone() {
+        assert_eq!(reassembler.push(message).unwrap(), None);
+    }
+    assert_matches!(reassembler.finish(), Err(ClassChunkingError::IncompleteClass { .. }));
+
+    // A class that arrives in the middle of a chunked class is rejected as well, and the partial
+    // class is discarded.
+    let mut reassembler = ClassReassembler::new(MAX_CLASS_SIZE);
+    for message in messages {
+        reassembler.push(message).unwrap();
+    }
+    let small_class = create_class(1000);
+    assert_matches!(
+        reassembler.push(ClassMessage::Class(small_class.clone())),
+        Err(ClassChunkingError::IncompleteClass { .. })
+    );
+    assert_eq!(
+        reassembler.push(ClassMessage::Class(small_class.clone())).unwrap(),
+        Some(small_class)
+    );
+    reassembler.finish().unwrap();
+}
+
+#[test]
+fn tampered_chunk_is_rejected() {
+    let class = create_class(2 * MB);
+    let mut messages = split_class_into_messages(class, MAX_MESSAGE_SIZE, MAX_CLASS_SIZE).unwrap();
+    let Some(ClassMessage::Chunk(chunk)) = messages.last_mut() else {
+        panic!("Expected the class to be chunked");
+    };
+    *chunk.data.last_mut().unwrap() ^= 1;
+
+    let mut reassembler = ClassReassembler::new(MAX_CLASS_SIZE);
+    let (last_message, first_messages) = messages.split_last().unwrap();
+    for message in first_messages {
+        reassembler.push(message.clone()).unwrap();
+    }
+    assert_matches!(
+        reassembler.push(last_message.clone()),
+        Err(ClassChunkingError::ContentHashMismatch)
+    );
+    reassembler.finish().unwrap();
+}
+
+#[test]
+fn class_above_max_class_size_is_rejected() {
+    let class = create_class(2 * MB);
+    let max_class_size = MB as u64;
+    assert_matches!(
+        split_class_into_messages(class.clone(), MAX_MESSAGE_SIZE, max_class_size),
+        Err(ClassChunkingError::ClassTooLarge { .. })
+    );
+
+    let messages = split_class_into_messages(class, MAX_MESSAGE_SIZE, MAX_CLASS_SIZE).unwrap();
+    let mut reassembler = ClassReassembler::new(max_class_size);
+    assert_matches!(
+        reassembler.push(messages[0].clone()),
+        Err(ClassChunkingError::ClassTooLarge { .. })
+    );
+}
@@ -1,5 +1,6 @@
 use starknet_api::block::BlockHash;
 use starknet_api::core::ContractAddress;
+use starknet_api::crypto::utils::Signature;
 use starknet_api::transaction::Transaction;
 
 #[derive(Debug, Clone, Eq, PartialEq)]
@@ -14,3 +15,11 @@ pub struct Proposal {
 pub enum ConsensusMessage {
     Proposal(Proposal),
 }
+
+/// A consensus message as it's broadcasted, with the signature of its sender over the encoding of
+/// the message. The signature is missing if the sender doesn't sign its messages.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct SignedConsensusMessage {
+    pub message: ConsensusMessage,
+    pub signature: Option<Signature>,
+}
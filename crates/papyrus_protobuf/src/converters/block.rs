@@ -0,0 +1,107 @@
+#[cfg(test)]
+#[path = "block_test.rs"]
+mod block_test;
+
+use std::collections::HashMap;
+
+use prost::Message;
+use starknet_api::transaction::{Event, Transaction, TransactionHash, TransactionOutput};
+
+use super::transaction::output_events_mut;
+use super::ProtobufConversionError;
+use crate::sync::{DataOrFin, FullBlock, SignedBlockHeader, StateDiffChunk};
+use crate::{auto_impl_into_and_try_from_vec_u8, protobuf};
+
+impl TryFrom<protobuf::Block> for FullBlock {
+    type Error = ProtobufConversionError;
+    fn try_from(value: protobuf::Block) -> Result<Self, Self::Error> {
+        let signed_header = SignedBlockHeader::try_from(value.header.ok_or(
+            ProtobufConversionError::MissingField { field_description: "Block::header" },
+        )?)?;
+
+        let mut transactions = value
+            .transactions
+            .into_iter()
+            .map(<(Transaction, TransactionOutput)>::try_from)
+            .collect::<Result<Vec<_>, _>>()?;
+
+        let transaction_hashes = value
+            .transaction_hashes
+            .into_iter()
+            .map(|hash| hash.try_into().map(TransactionHash))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // The receipts don't hold the events, so each event is returned to the output of the
+        // transaction that emitted it.
+        let transaction_offsets = transaction_hashes
+            .iter()
+            .enumerate()
+            .map(|(offset, transaction_hash)| (*transaction_hash, offset))
+            .collect::<HashMap<_, _>>();
+        for event in value.events {
+            let (event, transaction_hash) = <(Event, TransactionHash)>::try_from(event)?;
+            let (_transaction, transaction_output) = transaction_offsets
+                .get(&transaction_hash)
+                .and_then(|offset| transactions.get_mut(*offset))
+                .ok_or_else(|| ProtobufConversionError::OutOfRangeValue {
+                    type_description: "Block::events transaction hash",
+                    value_as_str: format!("{transaction_hash:?}"),
+                })?;
+            output_events_mut(transaction_output).push(event);
+        }
+
+        let state_diff_chunks = value
+            .state_diff
+            .into_iter()
+            .map(|state_diffs_response| {
+                DataOrFin::<StateDiffChunk>::try_from(state_diffs_response)?.0.ok_or(
+                    ProtobufConversionError::MissingField {
+                        field_description: "Block::state_diff (got Fin)",
+                    },
+                )
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(Self { signed_header, transactions, transaction_hashes, state_diff_chunks })
+    }
+}
+
+impl From<FullBlock> for protobuf::Block {
+    fn from(value: FullBlock) -> Self {
+        let FullBlock { signed_header, transactions, transaction_hashes, state_diff_chunks } =
+            value;
+        let events = transactions
+            .iter()
+            .zip(transaction_hashes.iter())
+            .flat_map(|((_transaction, transaction_output), transaction_hash)| {
+                transaction_output
+                    .events()
+                    .iter()
+                    .map(|event| protobuf::Event::from((event.clone(), *transaction_hash)))
+            })
+            .collect();
+        let data_availability = signed_header.data_availability;
+        let mut header = protobuf::SignedBlockHeader::from((
+            signed_header.block_header,
+            signed_header.signatures,
+        ));
+        header.data_availability = data_availability.map(Into::into);
+        Self {
+            header: Some(header),
+            // The events are sent apart from the transactions, as the block format always did.
+            transactions: transactions
+                .into_iter()
+                .map(|(transaction, mut transaction_output)| {
+                    output_events_mut(&mut transaction_output).clear();
+                    (transaction, transaction_output).into()
+                })
+                .collect(),
+            transaction_hashes: transaction_hashes.into_iter().map(|hash| hash.0.into()).collect(),
+            state_diff: state_diff_chunks
+                .into_iter()
+                .map(|state_diff_chunk| DataOrFin(Some(state_diff_chunk)).into())
+                .collect(),
+            events,
+        }
+    }
+}
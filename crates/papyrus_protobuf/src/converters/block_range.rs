@@ -0,0 +1,61 @@
+#[cfg(test)]
+#[path = "block_range_test.rs"]
+mod block_range_test;
+
+use prost::Message;
+use starknet_api::block::BlockNumber;
+
+use super::ProtobufConversionError;
+use crate::sync::{BlockRangeAdvertisement, ProtocolBlockRange, ResponseLimits};
+use crate::{auto_impl_into_and_try_from_vec_u8, protobuf};
+
+impl TryFrom<protobuf::BlockRangeAdvertisement> for BlockRangeAdvertisement {
+    type Error = ProtobufConversionError;
+    fn try_from(value: protobuf::BlockRangeAdvertisement) -> Result<Self, Self::Error> {
+        let ranges = value
+            .ranges
+            .into_iter()
+            .map(|range| {
+                if range.first_block > range.end_block {
+                    return Err(ProtobufConversionError::OutOfRangeValue {
+                        type_description: "ProtocolBlockRange::end_block",
+                        value_as_str: range.end_block.to_string(),
+                    });
+                }
+                Ok(ProtocolBlockRange {
+                    protocol: range.protocol,
+                    block_range: BlockNumber(range.first_block)..BlockNumber(range.end_block),
+                })
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+        let response_limits = value.response_limits.map(|response_limits| ResponseLimits {
+            max_items: response_limits.max_items,
+            max_bytes: response_limits.max_bytes,
+        });
+        Ok(Self { ranges, response_limits })
+    }
+}
+
+impl From<BlockRangeAdvertisement> for protobuf::BlockRangeAdvertisement {
+    fn from(value: BlockRangeAdvertisement) -> Self {
+        Self {
+            ranges: value
+                .ranges
+                .into_iter()
+                .map(|range| protobuf::block_range_advertisement::ProtocolBlockRange {
+                    protocol: range.protocol,
+                    first_block: range.block_range.start.0,
+                    end_block: range.block_range.end.0,
+                })
+                .collect(),
+            response_limits: value.response_limits.map(|response_limits| {
+                protobuf::block_range_advertisement::ResponseLimits {
+                    max_items: response_limits.max_items,
+                    max_bytes: response_limits.max_bytes,
+                }
+            }),
+        }
+    }
+}
+
+auto_impl_into_and_try_from_vec_u8!(BlockRangeAdvertisement, protobuf::BlockRangeAdvertisement);
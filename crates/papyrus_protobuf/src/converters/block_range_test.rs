@@ -0,0 +1,54 @@
+use starknet_api::block::BlockNumber;
+
+use crate::protobuf;
+use crate::sync::{BlockRangeAdvertisement, ProtocolBlockRange, ResponseLimits};
+
+#[test]
+fn block_range_advertisement_to_bytes_and_back() {
+    let data = BlockRangeAdvertisement {
+        ranges: vec![
+            ProtocolBlockRange {
+                protocol: "/starknet/headers/1".to_string(),
+                block_range: BlockNumber(0)..BlockNumber(10),
+            },
+            ProtocolBlockRange {
+                protocol: "/starknet/state_diffs/1".to_string(),
+                block_range: BlockNumber(5)..BlockNumber(8),
+            },
+        ],
+        response_limits: Some(ResponseLimits { max_items: 1000, max_bytes: 1 << 20 }),
+    };
+    let bytes_data = Vec::<u8>::from(data.clone());
+
+    let res_data = BlockRangeAdvertisement::try_from(bytes_data).unwrap();
+    assert_eq!(res_data, data);
+}
+
+#[test]
+fn block_range_advertisement_without_response_limits_to_bytes_and_back() {
+    let data = BlockRangeAdvertisement {
+        ranges: vec![ProtocolBlockRange {
+            protocol: "/starknet/headers/1".to_string(),
+            block_range: BlockNumber(0)..BlockNumber(10),
+        }],
+        response_limits: None,
+    };
+    let bytes_data = Vec::<u8>::from(data.clone());
+
+    let res_data = BlockRangeAdvertisement::try_from(bytes_data).unwrap();
+    assert_eq!(res_data, data);
+}
+
+#[test]
+fn block_range_advertisement_with_reversed_range_fails() {
+    let protobuf_data = protobuf::BlockRangeAdvertisement {
+        ranges: vec![protobuf::block_range_advertisement::ProtocolBlockRange {
+            protocol: "/starknet/headers/1".to_string(),
+            first_block: 10,
+            end_block: 5,
+        }],
+        response_limits: None,
+    };
+
+    assert!(BlockRangeAdvertisement::try_from(protobuf_data).is_err());
+}
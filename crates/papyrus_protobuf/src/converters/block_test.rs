@@ -0,0 +1,102 @@
+use starknet_api::block::BlockHeader;
+use starknet_api::core::ContractAddress;
+use starknet_api::transaction::{
+    Builtin,
+    Event,
+    EventContent,
+    ExecutionResources,
+    InvokeTransaction,
+    InvokeTransactionOutput,
+    InvokeTransactionV1,
+    Transaction,
+    TransactionHash,
+    TransactionOutput,
+};
+use starknet_types_core::felt::Felt;
+use test_utils::{get_rng, GetTestInstance};
+
+use crate::sync::{
+    BlockDataAvailability,
+    ContractDiff,
+    DeclaredClass,
+    DeprecatedDeclaredClass,
+    FullBlock,
+    SignedBlockHeader,
+    StateDiffChunk,
+};
+
+#[test]
+fn full_block_to_bytes_and_back() {
+    let mut rng = get_rng();
+    let data = FullBlock {
+        signed_header: SignedBlockHeader {
+            block_header: BlockHeader { state_diff_length: Some(3), ..Default::default() },
+            signatures: vec![],
+            data_availability: Some(BlockDataAvailability { has_body: true, has_state_diff: true }),
+        },
+        // Transaction conversions are tested in transaction_test.
+        transactions: vec![],
+        transaction_hashes: vec![TransactionHash(Felt::ONE)],
+        state_diff_chunks: vec![
+            StateDiffChunk::ContractDiff(ContractDiff::get_test_instance(&mut rng)),
+            StateDiffChunk::DeclaredClass(DeclaredClass::get_test_instance(&mut rng)),
+            StateDiffChunk::DeprecatedDeclaredClass(DeprecatedDeclaredClass::get_test_instance(
+                &mut rng,
+            )),
+        ],
+    };
+    let bytes_data = Vec::<u8>::from(data.clone());
+
+    let res_data = FullBlock::try_from(bytes_data).unwrap();
+    assert_eq!(res_data, data);
+}
+
+#[test]
+fn full_block_events_to_bytes_and_back() {
+    let event = |from_address: u128| Event {
+        from_address: ContractAddress::from(from_address),
+        content: EventContent::default(),
+    };
+    let transaction_output = |events| {
+        TransactionOutput::Invoke(InvokeTransactionOutput {
+            events,
+            execution_resources: ExecutionResources {
+                // The receipts hold a counter for each of these builtins.
+                builtin_instance_counter: [
+                    Builtin::RangeCheck,
+                    Builtin::Pedersen,
+                    Builtin::Poseidon,
+                    Builtin::EcOp,
+                    Builtin::Ecdsa,
+                    Builtin::Bitwise,
+                    Builtin::Keccak,
+                    Builtin::SegmentArena,
+                ]
+                .into_iter()
+                .map(|builtin| (builtin, 0))
+                .collect(),
+                ..Default::default()
+            },
+            ..Default::default()
+        })
+    };
+    let transaction = Transaction::Invoke(InvokeTransaction::V1(InvokeTransactionV1::default()));
+    let data = FullBlock {
+        signed_header: SignedBlockHeader {
+            block_header: BlockHeader::default(),
+            signatures: vec![],
+            data_availability: None,
+        },
+        transactions: vec![
+            (transaction.clone(), transaction_output(vec![event(1), event(2)])),
+            (transaction.clone(), transaction_output(vec![])),
+            (transaction, transaction_output(vec![event(3)])),
+        ],
+        transaction_hashes: (1_u8..=3).map(|hash| TransactionHash(Felt::from(hash))).collect(),
+        state_diff_chunks: vec![],
+    };
+    let bytes_data = Vec::<u8>::from(data.clone());
+
+    let res_data = FullBlock::try_from(bytes_data).unwrap();
+    assert_eq!(res_data, data);
+}
@@ -10,7 +10,7 @@ use starknet_types_core::felt::Felt;
 
 use super::common::volition_domain_to_enum_int;
 use super::ProtobufConversionError;
-use crate::sync::DataOrFin;
+use crate::sync::{ClassChunk, ClassChunkHeader, ClassMessage, DataOrFin};
 use crate::{auto_impl_into_and_try_from_vec_u8, protobuf};
 
 pub const DOMAIN: DataAvailabilityMode = DataAvailabilityMode::L1;
@@ -23,6 +23,13 @@ impl TryFrom<protobuf::ClassesResponse> for DataOrFin<ApiContractClass> {
                 Ok(Self(Some(class.try_into()?)))
             }
             Some(protobuf::classes_response::ClassMessage::Fin(_)) => Ok(Self(None)),
+            Some(
+                protobuf::classes_response::ClassMessage::ChunkHeader(_)
+                | protobuf::classes_response::ClassMessage::Chunk(_),
+            ) => Err(ProtobufConversionError::OutOfRangeValue {
+                type_description: "ClassesResponse::class_message",
+                value_as_str: "a class chunk where a whole class was expected".to_string(),
+            }),
             None => Err(ProtobufConversionError::MissingField {
                 field_description: "ClassesResponse::class_message",
             }),
@@ -46,6 +53,90 @@ impl From<DataOrFin<ApiContractClass>> for protobuf::ClassesResponse {
 
 auto_impl_into_and_try_from_vec_u8!(DataOrFin<ApiContractClass>, protobuf::ClassesResponse);
 
+impl TryFrom<protobuf::ClassesResponse> for DataOrFin<ClassMessage> {
+    type Error = ProtobufConversionError;
+    fn try_from(value: protobuf::ClassesResponse) -> Result<Self, Self::Error> {
+        let class_message = match value.class_message {
+            Some(protobuf::classes_response::ClassMessage::Class(class)) => {
+                ClassMessage::Class(class.try_into()?)
+            }
+            Some(protobuf::classes_response::ClassMessage::ChunkHeader(chunk_header)) => {
+                ClassMessage::ChunkHeader(chunk_header.try_into()?)
+            }
+            Some(protobuf::classes_response::ClassMessage::Chunk(chunk)) => {
+                ClassMessage::Chunk(chunk.into())
+            }
+            Some(protobuf::classes_response::ClassMessage::Fin(_)) => return Ok(Self(None)),
+            None => {
+                return Err(ProtobufConversionError::MissingField {
+                    field_description: "ClassesResponse::class_message",
+                });
+            }
+        };
+        Ok(Self(Some(class_message)))
+    }
+}
+impl From<DataOrFin<ClassMessage>> for protobuf::ClassesResponse {
+    fn from(value: DataOrFin<ClassMessage>) -> Self {
+        let class_message = match value.0 {
+            Some(ClassMessage::Class(class)) => {
+                protobuf::classes_response::ClassMessage::Class(class.into())
+            }
+            Some(ClassMessage::ChunkHeader(chunk_header)) => {
+                protobuf::classes_response::ClassMessage::ChunkHeader(chunk_header.into())
+            }
+            Some(ClassMessage::Chunk(chunk)) => {
+                protobuf::classes_response::ClassMessage::Chunk(chunk.into())
+            }
+            None => protobuf::classes_response::ClassMessage::Fin(protobuf::Fin {}),
+        };
+        protobuf::ClassesResponse { class_message: Some(class_message) }
+    }
+}
+
+auto_impl_into_and_try_from_vec_u8!(DataOrFin<ClassMessage>, protobuf::ClassesResponse);
+
+impl TryFrom<protobuf::ClassChunkHeader> for ClassChunkHeader {
+    type Error = ProtobufConversionError;
+    fn try_from(value: protobuf::ClassChunkHeader) -> Result<Self, Self::Error> {
+        let content_hash = value
+            .content_hash
+            .ok_or(ProtobufConversionError::MissingField {
+                field_description: "ClassChunkHeader::content_hash",
+            })?
+            .elements;
+        let content_hash = <[u8; 32]>::try_from(content_hash.as_slice()).map_err(|_| {
+            ProtobufConversionError::BytesDataLengthMismatch {
+                type_description: "ClassChunkHeader::content_hash",
+                num_expected: 32,
+                value: content_hash.clone(),
+            }
+        })?;
+        Ok(Self { total_size: value.total_size, content_hash })
+    }
+}
+
+impl From<ClassChunkHeader> for protobuf::ClassChunkHeader {
+    fn from(value: ClassChunkHeader) -> Self {
+        protobuf::ClassChunkHeader {
+            total_size: value.total_size,
+            content_hash: Some(protobuf::Hash { elements: value.content_hash.to_vec() }),
+        }
+    }
+}
+
+impl From<protobuf::ClassChunk> for ClassChunk {
+    fn from(value: protobuf::ClassChunk) -> Self {
+        Self { offset: value.offset, data: value.data }
+    }
+}
+
+impl From<ClassChunk> for protobuf::ClassChunk {
+    fn from(value: ClassChunk) -> Self {
+        protobuf::ClassChunk { offset: value.offset, data: value.data }
+    }
+}
+
 impl TryFrom<protobuf::Class> for ApiContractClass {
     type Error = ProtobufConversionError;
     fn try_from(value: protobuf::Class) -> Result<Self, Self::Error> {
@@ -1,11 +1,11 @@
 use std::convert::{TryFrom, TryInto};
 
 use prost::Message;
-use starknet_api::block::BlockHash;
+use starknet_api::block::{BlockHash, BlockSignature};
 use starknet_api::hash::StarkHash;
 use starknet_api::transaction::Transaction;
 
-use crate::consensus::{ConsensusMessage, Proposal};
+use crate::consensus::{ConsensusMessage, Proposal, SignedConsensusMessage};
 use crate::converters::ProtobufConversionError;
 use crate::{auto_impl_into_and_try_from_vec_u8, protobuf};
 
@@ -68,9 +68,34 @@ impl From<ConsensusMessage> for protobuf::ConsensusMessage {
         match value {
             ConsensusMessage::Proposal(proposal) => protobuf::ConsensusMessage {
                 message: Some(protobuf::consensus_message::Message::Proposal(proposal.into())),
+                signature: None,
             },
         }
     }
 }
 
 auto_impl_into_and_try_from_vec_u8!(ConsensusMessage, protobuf::ConsensusMessage);
+
+impl TryFrom<protobuf::ConsensusMessage> for SignedConsensusMessage {
+    type Error = ProtobufConversionError;
+
+    fn try_from(mut value: protobuf::ConsensusMessage) -> Result<Self, Self::Error> {
+        let signature = value
+            .signature
+            .take()
+            .map(|signature| BlockSignature::try_from(signature).map(|signature| signature.0))
+            .transpose()?;
+        Ok(SignedConsensusMessage { message: value.try_into()?, signature })
+    }
+}
+
+impl From<SignedConsensusMessage> for protobuf::ConsensusMessage {
+    fn from(value: SignedConsensusMessage) -> Self {
+        protobuf::ConsensusMessage {
+            signature: value.signature.map(|signature| BlockSignature(signature).into()),
+            ..value.message.into()
+        }
+    }
+}
+
+auto_impl_into_and_try_from_vec_u8!(SignedConsensusMessage, protobuf::ConsensusMessage);
@@ -43,16 +43,24 @@ auto_impl_into_and_try_from_vec_u8!(DataOrFin<(Event, TransactionHash)>, protobu
 
 impl TryFrom<protobuf::Event> for (Event, TransactionHash) {
     type Error = ProtobufConversionError;
-    fn try_from(value: protobuf::Event) -> Result<Self, Self::Error> {
+    fn try_from(mut value: protobuf::Event) -> Result<Self, Self::Error> {
         let transaction_hash = TransactionHash(
             value
                 .transaction_hash
+                .take()
                 .ok_or(ProtobufConversionError::MissingField {
                     field_description: "Event::transaction_hash",
                 })?
                 .try_into()?,
         );
+        Ok((Event::try_from(value)?, transaction_hash))
+    }
+}
 
+// The transaction hash of the event is ignored.
+impl TryFrom<protobuf::Event> for Event {
+    type Error = ProtobufConversionError;
+    fn try_from(value: protobuf::Event) -> Result<Self, Self::Error> {
         let from_address_felt =
             Felt::try_from(value.from_address.ok_or(ProtobufConversionError::MissingField {
                 field_description: "Event::from_address",
@@ -77,18 +85,24 @@ impl TryFrom<protobuf::Event> for (Event, TransactionHash) {
         let data =
             EventData(value.data.into_iter().map(Felt::try_from).collect::<Result<Vec<_>, _>>()?);
 
-        Ok((Event { from_address, content: EventContent { keys, data } }, transaction_hash))
+        Ok(Event { from_address, content: EventContent { keys, data } })
     }
 }
 
 impl From<(Event, TransactionHash)> for protobuf::Event {
     fn from(value: (Event, TransactionHash)) -> Self {
         let (event, transaction_hash) = value;
-        let transaction_hash = Some(transaction_hash.0.into());
+        Self { transaction_hash: Some(transaction_hash.0.into()), ..event.into() }
+    }
+}
+
+// The transaction hash of the event isn't set.
+impl From<Event> for protobuf::Event {
+    fn from(event: Event) -> Self {
         let from_address = Some(Felt::from(event.from_address).into());
         let keys = event.content.keys.into_iter().map(|key| key.0.into()).collect();
         let data =
             event.content.data.0.into_iter().map(protobuf::Felt252::from).collect::<Vec<_>>();
-        Self { transaction_hash, from_address, keys, data }
+        Self { transaction_hash: None, from_address, keys, data }
     }
 }
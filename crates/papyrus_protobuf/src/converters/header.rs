@@ -15,14 +15,17 @@ use starknet_api::block::{
 use starknet_api::core::{
     EventCommitment,
     GlobalRoot,
+    ReceiptCommitment,
     SequencerContractAddress,
+    StateDiffCommitment,
     TransactionCommitment,
 };
 use starknet_api::crypto::utils::Signature;
+use starknet_api::hash::PoseidonHash;
 
 use super::common::{enum_int_to_l1_data_availability_mode, l1_data_availability_mode_to_enum_int};
 use super::ProtobufConversionError;
-use crate::sync::{DataOrFin, HeaderQuery, Query, SignedBlockHeader};
+use crate::sync::{BlockDataAvailability, DataOrFin, HeaderQuery, Query, SignedBlockHeader};
 use crate::{auto_impl_into_and_try_from_vec_u8, protobuf};
 
 impl TryFrom<protobuf::BlockHeadersResponse> for DataOrFin<SignedBlockHeader> {
@@ -128,6 +131,21 @@ impl TryFrom<protobuf::SignedBlockHeader> for SignedBlockHeader {
                 .expect("Failed converting u64 to usize")
         });
 
+        let receipt_commitment = value
+            .receipts
+            .map(|receipts| receipts.try_into().map(ReceiptCommitment))
+            .transpose()?;
+
+        let state_diff_commitment = value
+            .state_diff_commitment
+            .and_then(|state_diff_commitment| state_diff_commitment.root)
+            .map(|root| {
+                Ok::<_, ProtobufConversionError>(StateDiffCommitment(PoseidonHash(
+                    root.try_into()?,
+                )))
+            })
+            .transpose()?;
+
         let l1_da_mode = enum_int_to_l1_data_availability_mode(value.l1_data_availability_mode)?;
 
         let starknet_version = StarknetVersion(value.protocol_version);
@@ -170,6 +188,8 @@ impl TryFrom<protobuf::SignedBlockHeader> for SignedBlockHeader {
             ),
         };
 
+        let data_availability = value.data_availability.map(BlockDataAvailability::from);
+
         Ok(SignedBlockHeader {
             block_header: BlockHeader {
                 block_hash,
@@ -181,15 +201,13 @@ impl TryFrom<protobuf::SignedBlockHeader> for SignedBlockHeader {
                 sequencer,
                 timestamp,
                 l1_da_mode,
-                // TODO(shahak): fill this.
-                state_diff_commitment: None,
+                state_diff_commitment,
                 state_diff_length,
                 transaction_commitment,
                 event_commitment,
                 n_transactions,
                 n_events,
-                // TODO(shahak): fill this.
-                receipt_commitment: None,
+                receipt_commitment,
                 starknet_version,
             },
             // collect will convert from Vec<Result> to Result<Vec>.
@@ -198,10 +216,23 @@ impl TryFrom<protobuf::SignedBlockHeader> for SignedBlockHeader {
                 .into_iter()
                 .map(starknet_api::block::BlockSignature::try_from)
                 .collect::<Result<Vec<_>, _>>()?,
+            data_availability,
         })
     }
 }
 
+impl From<protobuf::BlockDataAvailability> for BlockDataAvailability {
+    fn from(value: protobuf::BlockDataAvailability) -> Self {
+        Self { has_body: value.body, has_state_diff: value.state_diff }
+    }
+}
+
+impl From<BlockDataAvailability> for protobuf::BlockDataAvailability {
+    fn from(value: BlockDataAvailability) -> Self {
+        Self { body: value.has_body, state_diff: value.has_state_diff }
+    }
+}
+
 impl From<DataOrFin<SignedBlockHeader>> for protobuf::BlockHeadersResponse {
     fn from(value: DataOrFin<SignedBlockHeader>) -> Self {
         value.0.into()
@@ -224,8 +255,9 @@ impl From<(BlockHeader, Vec<BlockSignature>)> for protobuf::SignedBlockHeader {
                     .unwrap_or(0)
                     .try_into()
                     .expect("Converting usize to u64 failed"),
-                // TODO: fill this.
-                root: None,
+                root: header
+                    .state_diff_commitment
+                    .map(|state_diff_commitment| state_diff_commitment.0 .0.into()),
             }),
             state_root: Some(header.state_root.0.into()),
             // This will be Some only if both n_transactions and transaction_commitment are Some.
@@ -242,8 +274,9 @@ impl From<(BlockHeader, Vec<BlockSignature>)> for protobuf::SignedBlockHeader {
                     root: Some(event_commitment.0.into()),
                 })
             }),
-            // TODO(shahak): fill this.
-            receipts: None,
+            receipts: header
+                .receipt_commitment
+                .map(|receipt_commitment| receipt_commitment.0.into()),
             protocol_version: header.starknet_version.0,
             gas_price_wei: Some(header.l1_gas_price.price_in_wei.0.into()),
             gas_price_fri: Some(header.l1_gas_price.price_in_fri.0.into()),
@@ -251,6 +284,7 @@ impl From<(BlockHeader, Vec<BlockSignature>)> for protobuf::SignedBlockHeader {
             data_gas_price_fri: Some(header.l1_data_gas_price.price_in_fri.0.into()),
             l1_data_availability_mode: l1_data_availability_mode_to_enum_int(header.l1_da_mode),
             signatures: signatures.iter().map(|signature| (*signature).into()).collect(),
+            data_availability: None,
         }
     }
 }
@@ -284,10 +318,12 @@ impl From<starknet_api::block::BlockSignature> for protobuf::ConsensusSignature
 impl From<Option<SignedBlockHeader>> for protobuf::BlockHeadersResponse {
     fn from(data: Option<SignedBlockHeader>) -> Self {
         match data {
-            Some(SignedBlockHeader { block_header, signatures }) => {
+            Some(SignedBlockHeader { block_header, signatures, data_availability }) => {
+                let mut header = protobuf::SignedBlockHeader::from((block_header, signatures));
+                header.data_availability = data_availability.map(Into::into);
                 protobuf::BlockHeadersResponse {
                     header_message: Some(protobuf::block_headers_response::HeaderMessage::Header(
-                        (block_header, signatures).into(),
+                        header,
                     )),
                 }
             }
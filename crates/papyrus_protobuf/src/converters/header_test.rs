@@ -1,6 +1,17 @@
-use starknet_api::block::{BlockHeader, BlockNumber};
+use starknet_api::block::{BlockHash, BlockHeader, BlockNumber};
+use starknet_api::core::{ReceiptCommitment, StateDiffCommitment};
+use starknet_api::hash::PoseidonHash;
+use starknet_types_core::felt::Felt;
 
-use crate::sync::{BlockHashOrNumber, DataOrFin, Direction, HeaderQuery, Query, SignedBlockHeader};
+use crate::sync::{
+    BlockDataAvailability,
+    BlockHashOrNumber,
+    DataOrFin,
+    Direction,
+    HeaderQuery,
+    Query,
+    SignedBlockHeader,
+};
 
 #[test]
 fn block_header_to_bytes_and_back() {
@@ -9,6 +20,7 @@ fn block_header_to_bytes_and_back() {
         // be None or Some.
         block_header: BlockHeader { state_diff_length: Some(0), ..Default::default() },
         signatures: vec![],
+        data_availability: None,
     }));
     dbg!(&data);
     let bytes_data = Vec::<u8>::from(data.clone());
@@ -17,6 +29,53 @@ fn block_header_to_bytes_and_back() {
     assert_eq!(res_data, data);
 }
 
+#[test]
+fn block_header_with_data_availability_to_bytes_and_back() {
+    let data = DataOrFin(Some(SignedBlockHeader {
+        block_header: BlockHeader { state_diff_length: Some(0), ..Default::default() },
+        signatures: vec![],
+        data_availability: Some(BlockDataAvailability { has_body: false, has_state_diff: true }),
+    }));
+    let bytes_data = Vec::<u8>::from(data.clone());
+
+    let res_data = DataOrFin::try_from(bytes_data).unwrap();
+    assert_eq!(res_data, data);
+}
+
+#[test]
+fn block_header_with_receipt_commitment_to_bytes_and_back() {
+    let data = DataOrFin(Some(SignedBlockHeader {
+        block_header: BlockHeader {
+            state_diff_length: Some(0),
+            receipt_commitment: Some(ReceiptCommitment(Felt::from(7_u8))),
+            ..Default::default()
+        },
+        signatures: vec![],
+        data_availability: None,
+    }));
+    let bytes_data = Vec::<u8>::from(data.clone());
+
+    let res_data = DataOrFin::try_from(bytes_data).unwrap();
+    assert_eq!(res_data, data);
+}
+
+#[test]
+fn block_header_with_state_diff_commitment_to_bytes_and_back() {
+    let data = DataOrFin(Some(SignedBlockHeader {
+        block_header: BlockHeader {
+            state_diff_length: Some(3),
+            state_diff_commitment: Some(StateDiffCommitment(PoseidonHash(Felt::from(5_u8)))),
+            ..Default::default()
+        },
+        signatures: vec![],
+        data_availability: None,
+    }));
+    let bytes_data = Vec::<u8>::from(data.clone());
+
+    let res_data = DataOrFin::try_from(bytes_data).unwrap();
+    assert_eq!(res_data, data);
+}
+
 #[test]
 fn fin_to_bytes_and_back() {
     let bytes_data = Vec::<u8>::from(DataOrFin::<SignedBlockHeader>(None));
@@ -38,3 +97,17 @@ fn header_query_to_bytes_and_back() {
     let res_query = HeaderQuery::try_from(bytes).unwrap();
     assert_eq!(query, res_query);
 }
+
+#[test]
+fn header_query_with_hash_start_to_bytes_and_back() {
+    let query = HeaderQuery(Query {
+        start_block: BlockHashOrNumber::Hash(BlockHash(Felt::from(7_u8))),
+        direction: Direction::Forward,
+        limit: 1,
+        step: 1,
+    });
+
+    let bytes = Vec::<u8>::from(query.clone());
+    let res_query = HeaderQuery::try_from(bytes).unwrap();
+    assert_eq!(query, res_query);
+}
@@ -0,0 +1,37 @@
+#[cfg(test)]
+#[path = "mempool_test.rs"]
+mod mempool_test;
+
+use prost::Message;
+use starknet_api::hash::StarkHash;
+use starknet_api::transaction::TransactionHash;
+
+use super::ProtobufConversionError;
+use crate::mempool::MempoolTransaction;
+use crate::{auto_impl_into_and_try_from_vec_u8, protobuf};
+
+impl TryFrom<protobuf::MempoolTransaction> for MempoolTransaction {
+    type Error = ProtobufConversionError;
+    fn try_from(value: protobuf::MempoolTransaction) -> Result<Self, Self::Error> {
+        let transaction = value
+            .transaction
+            .ok_or(ProtobufConversionError::MissingField { field_description: "transaction" })?
+            .try_into()?;
+        let transaction_hash: StarkHash = value
+            .transaction_hash
+            .ok_or(ProtobufConversionError::MissingField { field_description: "transaction_hash" })?
+            .try_into()?;
+        Ok(MempoolTransaction { transaction, transaction_hash: TransactionHash(transaction_hash) })
+    }
+}
+
+impl From<MempoolTransaction> for protobuf::MempoolTransaction {
+    fn from(value: MempoolTransaction) -> Self {
+        protobuf::MempoolTransaction {
+            transaction: Some(value.transaction.into()),
+            transaction_hash: Some(value.transaction_hash.0.into()),
+        }
+    }
+}
+
+auto_impl_into_and_try_from_vec_u8!(MempoolTransaction, protobuf::MempoolTransaction);
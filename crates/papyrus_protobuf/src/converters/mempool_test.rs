@@ -0,0 +1,25 @@
+use starknet_api::transaction::{
+    InvokeTransaction,
+    InvokeTransactionV1,
+    Transaction,
+    TransactionHash,
+};
+use starknet_types_core::felt::Felt;
+use test_utils::{get_rng, GetTestInstance};
+
+use crate::mempool::MempoolTransaction;
+
+#[test]
+fn mempool_transaction_to_bytes_and_back() {
+    let mut rng = get_rng();
+    let data = MempoolTransaction {
+        transaction: Transaction::Invoke(InvokeTransaction::V1(
+            InvokeTransactionV1::get_test_instance(&mut rng),
+        )),
+        transaction_hash: TransactionHash(Felt::from(7_u8)),
+    };
+    let bytes_data = Vec::<u8>::from(data.clone());
+
+    let res_data = MempoolTransaction::try_from(bytes_data).unwrap();
+    assert_eq!(res_data, data);
+}
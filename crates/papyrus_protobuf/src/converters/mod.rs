@@ -1,15 +1,23 @@
 // TODO(shahak): Internalize this once network doesn't depend on protobuf.
+mod block;
+mod block_range;
 mod class;
 pub mod common;
 pub mod consensus;
 mod event;
 mod header;
+mod mempool;
 mod receipt;
+#[cfg(test)]
+mod spec_conformance_test;
 // TODO(shahak): Internalize this once network doesn't depend on protobuf.
 pub mod state_diff;
 mod transaction;
 
 use prost::DecodeError;
+pub use receipt::can_encode_transaction_output;
+
+use crate::sync::ProtocolVersion;
 
 #[derive(thiserror::Error, Debug)]
 pub enum ProtobufConversionError {
@@ -23,6 +31,22 @@ pub enum ProtobufConversionError {
     DecodeError(#[from] DecodeError),
 }
 
+/// Decoding of a message in the encoding of the protocol version that was negotiated with the peer
+/// that sent it.
+pub trait TryFromVersionedBytes: TryFrom<Vec<u8>> {
+    /// All the versions so far share the same encoding, so by default the version is ignored. A
+    /// message whose encoding changes in a new version should override this.
+    fn try_from_versioned_bytes(
+        bytes: Vec<u8>,
+        _version: ProtocolVersion,
+    ) -> Result<Self, <Self as TryFrom<Vec<u8>>>::Error> {
+        Self::try_from(bytes)
+    }
+}
+
+// Raw bytes are passed as is, regardless of the version.
+impl TryFromVersionedBytes for Vec<u8> {}
+
 #[macro_export]
 macro_rules! auto_impl_into_and_try_from_vec_u8 {
     ($T:ty, $ProtobufT:ty) => {
@@ -47,5 +71,6 @@ macro_rules! auto_impl_try_from_vec_u8 {
                 <$T>::try_from(protobuf_value)
             }
         }
+        impl $crate::converters::TryFromVersionedBytes for $T {}
     };
 }
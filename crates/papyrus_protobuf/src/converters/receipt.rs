@@ -275,6 +275,38 @@ impl TryFrom<ProtobufBuiltinCounter> for HashMap<Builtin, u64> {
     }
 }
 
+// The builtins whose counters are encoded in receipts.
+const ENCODED_BUILTINS: [Builtin; 7] = [
+    Builtin::RangeCheck,
+    Builtin::Pedersen,
+    Builtin::Poseidon,
+    Builtin::EcOp,
+    Builtin::Ecdsa,
+    Builtin::Bitwise,
+    Builtin::Keccak,
+];
+
+/// Returns whether the transaction output can be encoded as a receipt. Receipts hold the steps, the
+/// memory holes and the builtin counters of the execution in 32 bits, so outputs with larger values
+/// can't be encoded.
+pub fn can_encode_transaction_output(value: &TransactionOutput) -> bool {
+    let execution_resources = match value {
+        TransactionOutput::Declare(output) => &output.execution_resources,
+        TransactionOutput::Deploy(output) => &output.execution_resources,
+        TransactionOutput::DeployAccount(output) => &output.execution_resources,
+        TransactionOutput::Invoke(output) => &output.execution_resources,
+        TransactionOutput::L1Handler(output) => &output.execution_resources,
+    };
+    let fits_in_u32 = |value: u64| u32::try_from(value).is_ok();
+    fits_in_u32(execution_resources.steps)
+        && fits_in_u32(execution_resources.memory_holes)
+        && ENCODED_BUILTINS.iter().all(|builtin| {
+            fits_in_u32(
+                execution_resources.builtin_instance_counter.get(builtin).copied().unwrap_or(0),
+            )
+        })
+}
+
 impl From<HashMap<Builtin, u64>> for ProtobufBuiltinCounter {
     fn from(value: HashMap<Builtin, u64>) -> Self {
         let builtin_counter = ProtobufBuiltinCounter {
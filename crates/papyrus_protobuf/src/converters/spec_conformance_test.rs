@@ -0,0 +1,472 @@
+//! Checks that the messages we send are encoded exactly as the Starknet p2p specs define them. The
+//! expected bytes are built from the field numbers and types of the spec's .proto definitions with
+//! a minimal wire format writer, independently of the generated protobuf types, so a change in our
+//! .proto files or in our conversions that breaks compatibility with other implementations fails
+//! these tests.
+
+use std::collections::HashMap;
+use std::sync::Arc;
+
+use indexmap::indexmap;
+use starknet_api::block::{
+    BlockHash,
+    BlockHeader,
+    BlockNumber,
+    BlockSignature,
+    BlockTimestamp,
+    GasPrice,
+    GasPricePerToken,
+    StarknetVersion,
+};
+use starknet_api::core::{
+    ClassHash,
+    CompiledClassHash,
+    EntryPointSelector,
+    EthAddress,
+    GlobalRoot,
+    Nonce,
+    SequencerContractAddress,
+    StateDiffCommitment,
+    TransactionCommitment,
+};
+use starknet_api::crypto::utils::Signature;
+use starknet_api::data_availability::L1DataAvailabilityMode;
+use starknet_api::hash::PoseidonHash;
+use starknet_api::state::StorageKey;
+use starknet_api::transaction::{
+    Builtin,
+    Calldata,
+    ContractAddressSalt,
+    DeployAccountTransaction,
+    DeployAccountTransactionOutput,
+    DeployAccountTransactionV1,
+    ExecutionResources,
+    Fee,
+    GasVector,
+    L1HandlerTransaction,
+    L1HandlerTransactionOutput,
+    L2ToL1Payload,
+    MessageToL1,
+    RevertedTransactionExecutionStatus,
+    Transaction,
+    TransactionExecutionStatus,
+    TransactionOutput,
+    TransactionSignature,
+    TransactionVersion,
+};
+use starknet_api::{contract_address, patricia_key};
+use starknet_types_core::felt::Felt;
+use test_utils::{get_rng, GetTestInstance};
+
+use crate::sync::{
+    BlockDataAvailability,
+    BlockHashOrNumber,
+    ContractDiff,
+    DataOrFin,
+    DeclaredClass,
+    DeprecatedDeclaredClass,
+    Direction,
+    HeaderQuery,
+    Query,
+    SignedBlockHeader,
+    StateDiffChunk,
+    StateDiffQuery,
+    TransactionQuery,
+};
+
+// The largest felt, P - 1 = 2^251 + 17 * 2^192.
+const MAX_FELT_BYTES: [u8; 32] = [
+    0x08, 0, 0, 0, 0, 0, 0, 0x11, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0,
+    0, 0,
+];
+
+const VARINT: u64 = 0;
+const LENGTH_DELIMITED: u64 = 2;
+
+/// The expected encoding of a message, built field by field in field number order. Fields with
+/// implicit presence are omitted when they hold their default value, as proto3 requires.
+#[derive(Clone, Default)]
+struct Message(Vec<u8>);
+
+impl Message {
+    /// An integer, bool or enum field.
+    fn varint(self, field_number: u64, value: u64) -> Self {
+        if value == 0 {
+            self
+        } else {
+            self.present_varint(field_number, value)
+        }
+    }
+
+    /// An integer field with explicit presence, i.e a oneof member.
+    fn present_varint(mut self, field_number: u64, value: u64) -> Self {
+        encode_varint(field_number << 3 | VARINT, &mut self.0);
+        encode_varint(value, &mut self.0);
+        self
+    }
+
+    /// A bytes or string field.
+    fn bytes(self, field_number: u64, value: &[u8]) -> Self {
+        if value.is_empty() {
+            self
+        } else {
+            self.present_bytes(field_number, value)
+        }
+    }
+
+    /// A bytes or string field with explicit presence, i.e an optional field or a oneof member.
+    fn present_bytes(mut self, field_number: u64, value: &[u8]) -> Self {
+        encode_varint(field_number << 3 | LENGTH_DELIMITED, &mut self.0);
+        encode_varint(value.len().try_into().unwrap(), &mut self.0);
+        self.0.extend_from_slice(value);
+        self
+    }
+
+    /// A message field. Message fields are always encoded when they're set, even if they're empty.
+    fn message(self, field_number: u64, value: Message) -> Self {
+        self.present_bytes(field_number, &value.0)
+    }
+
+    fn repeated_message(self, field_number: u64, values: Vec<Message>) -> Self {
+        values.into_iter().fold(self, |message, value| message.message(field_number, value))
+    }
+}
+
+fn encode_varint(mut value: u64, buf: &mut Vec<u8>) {
+    while value >= 0x80 {
+        buf.push(u8::try_from(value & 0x7f).unwrap() | 0x80);
+        value >>= 7;
+    }
+    buf.push(u8::try_from(value).unwrap());
+}
+
+fn felt_bytes(value: u64) -> [u8; 32] {
+    let mut bytes = [0; 32];
+    bytes[24..].copy_from_slice(&value.to_be_bytes());
+    bytes
+}
+
+// Felt252, Hash and Address share the same layout.
+fn felt252(bytes: [u8; 32]) -> Message {
+    Message::default().bytes(1, &bytes)
+}
+
+fn uint128(value: u128) -> Message {
+    Message::default()
+        .varint(1, u64::try_from(value & u128::from(u64::MAX)).unwrap())
+        .varint(2, u64::try_from(value >> 64).unwrap())
+}
+
+fn fin() -> Message {
+    Message::default()
+}
+
+#[test]
+fn queries_match_spec() {
+    let query_by_number = Query {
+        start_block: BlockHashOrNumber::Number(BlockNumber(0)),
+        direction: Direction::Forward,
+        limit: u64::MAX,
+        step: 1,
+    };
+    // The start block is a oneof member, so it's encoded even though it's 0.
+    let iteration_by_number =
+        Message::default().present_varint(1, 0).varint(3, 0).varint(4, u64::MAX).varint(5, 1);
+    let query_by_hash = Query {
+        start_block: BlockHashOrNumber::Hash(BlockHash(Felt::MAX)),
+        direction: Direction::Backward,
+        limit: 10,
+        step: 3,
+    };
+    let iteration_by_hash = Message::default()
+        .message(2, felt252(MAX_FELT_BYTES))
+        .varint(3, 1)
+        .varint(4, 10)
+        .varint(5, 3);
+
+    for (query, iteration) in
+        [(query_by_number, iteration_by_number), (query_by_hash, iteration_by_hash)]
+    {
+        // BlockHeadersRequest, StateDiffsRequest and TransactionsRequest all hold the iteration
+        // in field 1.
+        let expected_bytes = Message::default().message(1, iteration).0;
+        assert_eq!(Vec::<u8>::from(HeaderQuery(query.clone())), expected_bytes);
+        assert_eq!(Vec::<u8>::from(StateDiffQuery(query.clone())), expected_bytes);
+        assert_eq!(
+            Vec::<u8>::from(TransactionQuery { query, include_outputs: true }),
+            expected_bytes
+        );
+    }
+}
+
+#[test]
+fn signed_block_header_matches_spec() {
+    let block_header = BlockHeader {
+        block_hash: BlockHash(Felt::MAX),
+        parent_hash: BlockHash(Felt::from(1_u64)),
+        block_number: BlockNumber(u64::MAX),
+        l1_gas_price: GasPricePerToken {
+            price_in_fri: GasPrice(u128::MAX),
+            price_in_wei: GasPrice(1),
+        },
+        l1_data_gas_price: GasPricePerToken {
+            price_in_fri: GasPrice(0),
+            price_in_wei: GasPrice(1 << 64),
+        },
+        state_root: GlobalRoot(Felt::MAX),
+        sequencer: SequencerContractAddress(contract_address!("0x1234")),
+        timestamp: BlockTimestamp(1_700_000_000),
+        l1_da_mode: L1DataAvailabilityMode::Blob,
+        state_diff_commitment: Some(StateDiffCommitment(PoseidonHash(Felt::MAX))),
+        state_diff_length: Some(3),
+        transaction_commitment: Some(TransactionCommitment(Felt::from(5_u64))),
+        // Without an event commitment the events Patricia isn't sent.
+        event_commitment: None,
+        n_transactions: Some(2),
+        n_events: Some(7),
+        receipt_commitment: None,
+        starknet_version: StarknetVersion("0.13.2".to_string()),
+    };
+    let signed_block_header = SignedBlockHeader {
+        block_header,
+        signatures: vec![BlockSignature(Signature { r: Felt::MAX, s: Felt::from(9_u64) })],
+        data_availability: Some(BlockDataAvailability { has_body: true, has_state_diff: false }),
+    };
+
+    let expected_header = Message::default()
+        .message(1, felt252(MAX_FELT_BYTES))
+        .message(2, felt252(felt_bytes(1)))
+        .varint(3, u64::MAX)
+        .varint(4, 1_700_000_000)
+        .message(5, felt252(felt_bytes(0x1234)))
+        .message(6, felt252(MAX_FELT_BYTES))
+        .message(7, Message::default().varint(1, 3).message(2, felt252(MAX_FELT_BYTES)))
+        .message(8, Message::default().varint(1, 2).message(2, felt252(felt_bytes(5))))
+        .bytes(11, b"0.13.2")
+        .message(12, uint128(u128::MAX))
+        .message(13, uint128(1))
+        .message(14, uint128(0))
+        .message(15, uint128(1 << 64))
+        .varint(16, 1)
+        .repeated_message(
+            17,
+            vec![Message::default()
+                .message(1, felt252(MAX_FELT_BYTES))
+                .message(2, felt252(felt_bytes(9)))],
+        )
+        .message(18, Message::default().varint(1, 1).varint(2, 0));
+    assert_eq!(
+        Vec::<u8>::from(DataOrFin(Some(signed_block_header))),
+        Message::default().message(1, expected_header).0
+    );
+    assert_eq!(
+        Vec::<u8>::from(DataOrFin::<SignedBlockHeader>(None)),
+        Message::default().message(2, fin()).0
+    );
+}
+
+#[test]
+fn state_diff_chunks_match_spec() {
+    let empty_contract_diff =
+        ContractDiff { contract_address: contract_address!("0x1"), ..Default::default() };
+    // The domain is L1, which is the default, so it's omitted.
+    let expected_empty_contract_diff = Message::default().message(1, felt252(felt_bytes(1)));
+
+    let contract_diff = ContractDiff {
+        contract_address: contract_address!("0x2"),
+        class_hash: Some(ClassHash(Felt::MAX)),
+        // A zero nonce is still sent since the nonce has explicit presence.
+        nonce: Some(Nonce(Felt::ZERO)),
+        storage_diffs: indexmap! { StorageKey(patricia_key!("0x1001")) => Felt::MAX },
+    };
+    let expected_contract_diff = Message::default()
+        .message(1, felt252(felt_bytes(2)))
+        .message(2, felt252([0; 32]))
+        .message(3, felt252(MAX_FELT_BYTES))
+        .repeated_message(
+            4,
+            vec![Message::default()
+                .message(1, felt252(felt_bytes(0x1001)))
+                .message(2, felt252(MAX_FELT_BYTES))],
+        );
+
+    let declared_class = DeclaredClass {
+        class_hash: ClassHash(Felt::from(7_u64)),
+        compiled_class_hash: CompiledClassHash(Felt::MAX),
+    };
+    let expected_declared_class =
+        Message::default().message(1, felt252(felt_bytes(7))).message(2, felt252(MAX_FELT_BYTES));
+
+    // Cairo 0 classes have no compiled class hash.
+    let deprecated_declared_class =
+        DeprecatedDeclaredClass { class_hash: ClassHash(Felt::from(8_u64)) };
+    let expected_deprecated_declared_class = Message::default().message(1, felt252(felt_bytes(8)));
+
+    for (state_diff_chunk, expected_response) in [
+        (
+            StateDiffChunk::ContractDiff(empty_contract_diff),
+            Message::default().message(1, expected_empty_contract_diff),
+        ),
+        (
+            StateDiffChunk::ContractDiff(contract_diff),
+            Message::default().message(1, expected_contract_diff),
+        ),
+        (
+            StateDiffChunk::DeclaredClass(declared_class),
+            Message::default().message(2, expected_declared_class),
+        ),
+        (
+            StateDiffChunk::DeprecatedDeclaredClass(deprecated_declared_class),
+            Message::default().message(2, expected_deprecated_declared_class),
+        ),
+    ] {
+        assert_eq!(Vec::<u8>::from(DataOrFin(Some(state_diff_chunk))), expected_response.0);
+    }
+    assert_eq!(
+        Vec::<u8>::from(DataOrFin::<StateDiffChunk>(None)),
+        Message::default().message(3, fin()).0
+    );
+}
+
+#[test]
+fn l1_handler_transaction_matches_spec() {
+    let transaction = Transaction::L1Handler(L1HandlerTransaction {
+        version: TransactionVersion::ZERO,
+        nonce: Nonce(Felt::from(3_u64)),
+        contract_address: contract_address!("0x5"),
+        entry_point_selector: EntryPointSelector(Felt::MAX),
+        calldata: Calldata(Arc::new(vec![Felt::from(0xab_u64), Felt::ZERO])),
+    });
+    let mut output = L1HandlerTransactionOutput::get_test_instance(&mut get_rng());
+    output.actual_fee = Fee(u128::MAX);
+    output.messages_sent = vec![MessageToL1 {
+        from_address: contract_address!("0x5"),
+        to_address: EthAddress(primitive_types::H160([0xab; 20])),
+        payload: L2ToL1Payload(vec![Felt::ONE]),
+    }];
+    output.events = vec![];
+    output.execution_status = TransactionExecutionStatus::Succeeded;
+    output.execution_resources = ExecutionResources {
+        steps: 100,
+        builtin_instance_counter: HashMap::from([(Builtin::RangeCheck, 2)]),
+        memory_holes: 0,
+        da_gas_consumed: GasVector { l1_gas: 0, l1_data_gas: 128 },
+        gas_consumed: GasVector { l1_gas: 5, l1_data_gas: 0 },
+    };
+
+    let expected_transaction = Message::default().message(
+        11,
+        Message::default()
+            .message(1, felt252(felt_bytes(3)))
+            .message(2, felt252(felt_bytes(5)))
+            .message(3, felt252(MAX_FELT_BYTES))
+            .repeated_message(4, vec![felt252(felt_bytes(0xab)), felt252([0; 32])]),
+    );
+    let mut max_u128_bytes = [0; 32];
+    max_u128_bytes[16..].copy_from_slice(&u128::MAX.to_be_bytes());
+    // The price unit is Wei, which is the default, so it's omitted. The revert reason is absent
+    // since the transaction succeeded.
+    let expected_common = Message::default()
+        .message(2, felt252(max_u128_bytes))
+        .repeated_message(
+            4,
+            vec![Message::default()
+                .message(2, felt252(felt_bytes(5)))
+                .repeated_message(3, vec![felt252(felt_bytes(1))])
+                .message(4, Message::default().bytes(1, &[0xab; 20]))],
+        )
+        .message(
+            5,
+            Message::default()
+                .message(1, Message::default().varint(5, 2))
+                .varint(2, 100)
+                .varint(3, 0)
+                .message(4, Message::default().varint(2, 128))
+                .message(5, Message::default().varint(1, 5)),
+        );
+    // TODO(shahak): Add the message hash (field 2) once we send it.
+    let expected_receipt =
+        Message::default().message(2, Message::default().message(1, expected_common));
+
+    assert_eq!(
+        Vec::<u8>::from(DataOrFin(Some((transaction, TransactionOutput::L1Handler(output))))),
+        Message::default()
+            .message(
+                1,
+                Message::default().message(1, expected_transaction).message(2, expected_receipt)
+            )
+            .0
+    );
+}
+
+#[test]
+fn deploy_account_transaction_matches_spec() {
+    let transaction =
+        Transaction::DeployAccount(DeployAccountTransaction::V1(DeployAccountTransactionV1 {
+            max_fee: Fee(0),
+            signature: TransactionSignature(vec![]),
+            nonce: Nonce(Felt::ZERO),
+            class_hash: ClassHash(Felt::MAX),
+            contract_address_salt: ContractAddressSalt(Felt::from(0x5a_u64)),
+            constructor_calldata: Calldata(Arc::new(vec![])),
+        }));
+    let mut output = DeployAccountTransactionOutput::get_test_instance(&mut get_rng());
+    output.actual_fee = Fee(0);
+    output.messages_sent = vec![];
+    output.events = vec![];
+    output.contract_address = contract_address!("0x77");
+    output.execution_status =
+        TransactionExecutionStatus::Reverted(RevertedTransactionExecutionStatus {
+            revert_reason: String::new(),
+        });
+    output.execution_resources = ExecutionResources {
+        steps: 0,
+        builtin_instance_counter: HashMap::new(),
+        memory_holes: 0,
+        da_gas_consumed: GasVector::default(),
+        gas_consumed: GasVector::default(),
+    };
+
+    // Empty felts are still 32 bytes, and the empty signature is still sent.
+    let expected_transaction = Message::default().message(
+        6,
+        Message::default()
+            .message(1, felt252([0; 32]))
+            .message(2, Message::default())
+            .message(3, felt252(MAX_FELT_BYTES))
+            .message(4, felt252([0; 32]))
+            .message(5, felt252(felt_bytes(0x5a))),
+    );
+    // An empty revert reason is still sent since it has explicit presence.
+    let expected_common = Message::default()
+        .message(2, felt252([0; 32]))
+        .message(
+            5,
+            Message::default()
+                .message(1, Message::default())
+                .message(4, Message::default())
+                .message(5, Message::default()),
+        )
+        .present_bytes(6, b"");
+    let expected_receipt = Message::default().message(
+        5,
+        Message::default().message(1, expected_common).message(2, felt252(felt_bytes(0x77))),
+    );
+
+    assert_eq!(
+        Vec::<u8>::from(DataOrFin(Some((
+            transaction,
+            Some(TransactionOutput::DeployAccount(output))
+        )))),
+        Message::default()
+            .message(
+                1,
+                Message::default().message(1, expected_transaction).message(2, expected_receipt)
+            )
+            .0
+    );
+    assert_eq!(
+        Vec::<u8>::from(DataOrFin::<(Transaction, Option<TransactionOutput>)>(None)),
+        Message::default().message(2, fin()).0
+    );
+}
@@ -182,6 +182,45 @@ impl TryFrom<protobuf::DeclaredClass> for ThinStateDiff {
     }
 }
 
+impl From<StateDiffChunk> for ThinStateDiff {
+    fn from(value: StateDiffChunk) -> Self {
+        match value {
+            StateDiffChunk::ContractDiff(contract_diff) => {
+                let contract_address = contract_diff.contract_address;
+                ThinStateDiff {
+                    // Same as in the conversion from protobuf::ContractDiff, we can't separate
+                    // replaced classes from deployed contracts.
+                    deployed_contracts: contract_diff
+                        .class_hash
+                        .map(|class_hash| IndexMap::from_iter([(contract_address, class_hash)]))
+                        .unwrap_or_default(),
+                    storage_diffs: if contract_diff.storage_diffs.is_empty() {
+                        IndexMap::new()
+                    } else {
+                        IndexMap::from_iter([(contract_address, contract_diff.storage_diffs)])
+                    },
+                    nonces: contract_diff
+                        .nonce
+                        .map(|nonce| IndexMap::from_iter([(contract_address, nonce)]))
+                        .unwrap_or_default(),
+                    ..Default::default()
+                }
+            }
+            StateDiffChunk::DeclaredClass(declared_class) => ThinStateDiff {
+                declared_classes: IndexMap::from_iter([(
+                    declared_class.class_hash,
+                    declared_class.compiled_class_hash,
+                )]),
+                ..Default::default()
+            },
+            StateDiffChunk::DeprecatedDeclaredClass(deprecated_declared_class) => ThinStateDiff {
+                deprecated_declared_classes: vec![deprecated_declared_class.class_hash],
+                ..Default::default()
+            },
+        }
+    }
+}
+
 impl TryFrom<protobuf::ContractStoredValue> for (StorageKey, Felt) {
     type Error = ProtobufConversionError;
     fn try_from(entry: protobuf::ContractStoredValue) -> Result<Self, Self::Error> {
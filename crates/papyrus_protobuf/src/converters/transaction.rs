@@ -17,6 +17,7 @@ use starknet_api::transaction::{
     DeployAccountTransactionV1,
     DeployAccountTransactionV3,
     DeployTransaction,
+    Event,
     Fee,
     InvokeTransaction,
     InvokeTransactionV0,
@@ -45,7 +46,9 @@ use super::ProtobufConversionError;
 use crate::sync::{DataOrFin, Query, TransactionQuery};
 use crate::{auto_impl_into_and_try_from_vec_u8, protobuf};
 
-impl TryFrom<protobuf::TransactionsResponse> for DataOrFin<(Transaction, TransactionOutput)> {
+impl TryFrom<protobuf::TransactionsResponse>
+    for DataOrFin<(Transaction, Option<TransactionOutput>)>
+{
     type Error = ProtobufConversionError;
     fn try_from(value: protobuf::TransactionsResponse) -> Result<Self, Self::Error> {
         let Some(transaction_message) = value.transaction_message else {
@@ -58,15 +61,16 @@ impl TryFrom<protobuf::TransactionsResponse> for DataOrFin<(Transaction, Transac
             protobuf::transactions_response::TransactionMessage::TransactionWithReceipt(
                 tx_with_receipt,
             ) => {
-                let result: (Transaction, TransactionOutput) = tx_with_receipt.try_into()?;
+                let result: (Transaction, Option<TransactionOutput>) =
+                    tx_with_receipt.try_into()?;
                 Ok(DataOrFin(Some(result)))
             }
             protobuf::transactions_response::TransactionMessage::Fin(_) => Ok(DataOrFin(None)),
         }
     }
 }
-impl From<DataOrFin<(Transaction, TransactionOutput)>> for protobuf::TransactionsResponse {
-    fn from(value: DataOrFin<(Transaction, TransactionOutput)>) -> Self {
+impl From<DataOrFin<(Transaction, Option<TransactionOutput>)>> for protobuf::TransactionsResponse {
+    fn from(value: DataOrFin<(Transaction, Option<TransactionOutput>)>) -> Self {
         match value.0 {
             Some((transaction, output)) => protobuf::TransactionsResponse {
                 transaction_message: Some(
@@ -85,11 +89,12 @@ impl From<DataOrFin<(Transaction, TransactionOutput)>> for protobuf::Transaction
 }
 
 auto_impl_into_and_try_from_vec_u8!(
-    DataOrFin<(Transaction, TransactionOutput)>,
+    DataOrFin<(Transaction, Option<TransactionOutput>)>,
     protobuf::TransactionsResponse
 );
 
-impl TryFrom<protobuf::TransactionWithReceipt> for (Transaction, TransactionOutput) {
+// The receipt is missing if the transaction was requested without its output.
+impl TryFrom<protobuf::TransactionWithReceipt> for (Transaction, Option<TransactionOutput>) {
     type Error = ProtobufConversionError;
     fn try_from(value: protobuf::TransactionWithReceipt) -> Result<Self, Self::Error> {
         let transaction = Transaction::try_from(value.transaction.ok_or(
@@ -98,21 +103,56 @@ impl TryFrom<protobuf::TransactionWithReceipt> for (Transaction, TransactionOutp
             },
         )?)?;
 
-        let output = TransactionOutput::try_from(value.receipt.ok_or(
-            ProtobufConversionError::MissingField {
-                field_description: "TransactionWithReceipt::output",
-            },
-        )?)?;
+        let mut output = value.receipt.map(TransactionOutput::try_from).transpose()?;
+        if let Some(output) = &mut output {
+            *output_events_mut(output) =
+                value.events.into_iter().map(Event::try_from).collect::<Result<_, _>>()?;
+        }
+        Ok((transaction, output))
+    }
+}
+
+impl TryFrom<protobuf::TransactionWithReceipt> for (Transaction, TransactionOutput) {
+    type Error = ProtobufConversionError;
+    fn try_from(value: protobuf::TransactionWithReceipt) -> Result<Self, Self::Error> {
+        let (transaction, output) = <(Transaction, Option<TransactionOutput>)>::try_from(value)?;
+        let output = output.ok_or(ProtobufConversionError::MissingField {
+            field_description: "TransactionWithReceipt::output",
+        })?;
         Ok((transaction, output))
     }
 }
 
+impl From<(Transaction, Option<TransactionOutput>)> for protobuf::TransactionWithReceipt {
+    fn from(value: (Transaction, Option<TransactionOutput>)) -> Self {
+        let transaction = value.0.into();
+        let events = value
+            .1
+            .as_ref()
+            .map(|output| output.events().iter().cloned().map(protobuf::Event::from).collect())
+            .unwrap_or_default();
+        let receipt = value.1.map(|output| {
+            let mut receipt = output.into();
+            set_price_unit_based_on_transaction(&mut receipt, &transaction);
+            receipt
+        });
+        Self { transaction: Some(transaction), receipt, events }
+    }
+}
+
 impl From<(Transaction, TransactionOutput)> for protobuf::TransactionWithReceipt {
     fn from(value: (Transaction, TransactionOutput)) -> Self {
-        let transaction = value.0.into();
-        let mut receipt = value.1.into();
-        set_price_unit_based_on_transaction(&mut receipt, &transaction);
-        Self { transaction: Some(transaction), receipt: Some(receipt) }
+        (value.0, Some(value.1)).into()
+    }
+}
+
+pub(super) fn output_events_mut(transaction_output: &mut TransactionOutput) -> &mut Vec<Event> {
+    match transaction_output {
+        TransactionOutput::Declare(output) => &mut output.events,
+        TransactionOutput::Deploy(output) => &mut output.events,
+        TransactionOutput::DeployAccount(output) => &mut output.events,
+        TransactionOutput::Invoke(output) => &mut output.events,
+        TransactionOutput::L1Handler(output) => &mut output.events,
     }
 }
 
@@ -1160,33 +1200,39 @@ impl From<L1HandlerTransaction> for protobuf::transaction::L1HandlerV0 {
 impl TryFrom<protobuf::TransactionsRequest> for Query {
     type Error = ProtobufConversionError;
     fn try_from(value: protobuf::TransactionsRequest) -> Result<Self, Self::Error> {
-        Ok(TransactionQuery::try_from(value)?.0)
+        Ok(TransactionQuery::try_from(value)?.query)
     }
 }
 
 impl TryFrom<protobuf::TransactionsRequest> for TransactionQuery {
     type Error = ProtobufConversionError;
     fn try_from(value: protobuf::TransactionsRequest) -> Result<Self, Self::Error> {
-        Ok(TransactionQuery(
-            value
+        Ok(TransactionQuery {
+            query: value
                 .iteration
                 .ok_or(ProtobufConversionError::MissingField {
                     field_description: "TransactionsRequest::iteration",
                 })?
                 .try_into()?,
-        ))
+            include_outputs: value.include_outputs.unwrap_or(true),
+        })
     }
 }
 
 impl From<Query> for protobuf::TransactionsRequest {
     fn from(value: Query) -> Self {
-        protobuf::TransactionsRequest { iteration: Some(value.into()) }
+        protobuf::TransactionsRequest { iteration: Some(value.into()), include_outputs: None }
     }
 }
 
 impl From<TransactionQuery> for protobuf::TransactionsRequest {
     fn from(value: TransactionQuery) -> Self {
-        protobuf::TransactionsRequest { iteration: Some(value.0.into()) }
+        // The field is only set when the outputs are excluded, so that a request for the outputs is
+        // encoded as it was before the field existed.
+        protobuf::TransactionsRequest {
+            iteration: Some(value.query.into()),
+            include_outputs: (!value.include_outputs).then_some(false),
+        }
     }
 }
 
@@ -19,14 +19,15 @@ use starknet_api::transaction::{
 };
 use test_utils::{get_rng, GetTestInstance};
 
-use crate::sync::DataOrFin;
+use crate::converters::can_encode_transaction_output;
+use crate::protobuf;
+use crate::sync::{DataOrFin, Query, TransactionQuery};
 
 macro_rules! create_transaction_output {
     ($tx_output_type:ty, $tx_output_enum_variant:ident) => {{
         let mut rng = get_rng();
         let mut transaction_output = <$tx_output_type>::get_test_instance(&mut rng);
         transaction_output.execution_resources = EXECUTION_RESOURCES.clone();
-        transaction_output.events = vec![];
         TransactionOutput::$tx_output_enum_variant(transaction_output)
     }};
 }
@@ -157,18 +158,78 @@ fn convert_deploy_account_transaction_v3_to_vec_u8_and_back() {
 #[test]
 fn fin_transaction_to_bytes_and_back() {
     let bytes_data =
-        Vec::<u8>::from(DataOrFin::<(StarknetApiTransaction, TransactionOutput)>(None));
+        Vec::<u8>::from(DataOrFin::<(StarknetApiTransaction, Option<TransactionOutput>)>(None));
 
     let res_data =
-        DataOrFin::<(StarknetApiTransaction, TransactionOutput)>::try_from(bytes_data).unwrap();
+        DataOrFin::<(StarknetApiTransaction, Option<TransactionOutput>)>::try_from(bytes_data)
+            .unwrap();
     assert!(res_data.0.is_none());
 }
 
+#[test]
+fn transaction_without_output_to_bytes_and_back() {
+    let mut rng = get_rng();
+    let transaction = starknet_api::transaction::L1HandlerTransaction::get_test_instance(&mut rng);
+    let transaction = StarknetApiTransaction::L1Handler(transaction);
+    let data = DataOrFin(Some((transaction, None)));
+    let bytes_data = Vec::<u8>::from(data.clone());
+    assert_eq!(DataOrFin::try_from(bytes_data).unwrap(), data);
+}
+
+#[test]
+fn transaction_query_includes_outputs_unless_excluded() {
+    let query = Query { limit: 10, ..Default::default() };
+    for include_outputs in [true, false] {
+        let transaction_query = TransactionQuery { query: query.clone(), include_outputs };
+        let bytes_data = Vec::<u8>::from(transaction_query.clone());
+        assert_eq!(TransactionQuery::try_from(bytes_data).unwrap(), transaction_query);
+    }
+
+    // A request of a peer that doesn't know the flag asks for the outputs.
+    assert_eq!(
+        TransactionQuery::try_from(protobuf::TransactionsRequest::from(query.clone())).unwrap(),
+        TransactionQuery { query, include_outputs: true }
+    );
+}
+
+#[test]
+fn transaction_output_with_resources_beyond_32_bits_cant_be_encoded() {
+    let transaction_output = create_transaction_output!(InvokeTransactionOutput, Invoke);
+    assert!(can_encode_transaction_output(&transaction_output));
+
+    let mut execution_resources = EXECUTION_RESOURCES.clone();
+    execution_resources.steps = u64::from(u32::MAX) + 1;
+    let transaction_output = with_execution_resources(transaction_output, execution_resources);
+    assert!(!can_encode_transaction_output(&transaction_output));
+
+    let mut execution_resources = EXECUTION_RESOURCES.clone();
+    execution_resources.builtin_instance_counter.insert(Builtin::Keccak, u64::MAX);
+    let transaction_output = with_execution_resources(transaction_output, execution_resources);
+    assert!(!can_encode_transaction_output(&transaction_output));
+
+    // The counters of builtins that aren't encoded don't matter.
+    let mut execution_resources = EXECUTION_RESOURCES.clone();
+    execution_resources.builtin_instance_counter.insert(Builtin::SegmentArena, u64::MAX);
+    let transaction_output = with_execution_resources(transaction_output, execution_resources);
+    assert!(can_encode_transaction_output(&transaction_output));
+}
+
+fn with_execution_resources(
+    transaction_output: TransactionOutput,
+    execution_resources: ExecutionResources,
+) -> TransactionOutput {
+    let TransactionOutput::Invoke(mut transaction_output) = transaction_output else {
+        panic!("Expected an invoke transaction output");
+    };
+    transaction_output.execution_resources = execution_resources;
+    TransactionOutput::Invoke(transaction_output)
+}
+
 fn convert_transaction_to_vec_u8_and_back(
     transaction: StarknetApiTransaction,
     transaction_output: TransactionOutput,
 ) {
-    let data = DataOrFin(Some((transaction, transaction_output)));
+    let data = DataOrFin(Some((transaction, Some(transaction_output))));
     let bytes_data = Vec::<u8>::from(data.clone());
     let res_data = DataOrFin::try_from(bytes_data).unwrap();
     assert_eq!(data, res_data);
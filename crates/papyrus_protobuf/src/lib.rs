@@ -1,6 +1,8 @@
+pub mod class_chunking;
 // TODO(shahak): Internalize this once network doesn't depend on protobuf.
 pub mod converters;
 // TODO(shahak): Internalize this once network doesn't depend on protobuf.
 pub mod consensus;
+pub mod mempool;
 pub mod protobuf;
 pub mod sync;
@@ -0,0 +1,8 @@
+use starknet_api::transaction::{Transaction, TransactionHash};
+
+/// A transaction a node received from a user and broadcasts to the other nodes.
+#[derive(Debug, Clone, Eq, PartialEq)]
+pub struct MempoolTransaction {
+    pub transaction: Transaction,
+    pub transaction_hash: TransactionHash,
+}
@@ -1,9 +1,17 @@
+#[cfg(test)]
+#[path = "sync_test.rs"]
+mod sync_test;
+
+use std::collections::HashSet;
 use std::fmt::Debug;
+use std::ops::Range;
 
 use indexmap::IndexMap;
+use papyrus_common::pending_classes::ApiContractClass;
 use starknet_api::block::{BlockHash, BlockHeader, BlockNumber, BlockSignature};
 use starknet_api::core::{ClassHash, CompiledClassHash, ContractAddress, Nonce};
-use starknet_api::state::StorageKey;
+use starknet_api::state::{StorageKey, ThinStateDiff};
+use starknet_api::transaction::{Transaction, TransactionHash, TransactionOutput};
 use starknet_types_core::felt::Felt;
 #[cfg(any(feature = "testing", test))]
 use test_utils::{auto_impl_get_test_instance, get_number_of_variants, GetTestInstance};
@@ -24,6 +32,35 @@ pub struct Query {
     pub step: u64,
 }
 
+impl Query {
+    /// The limit of an open-ended query, which asks for all the blocks from its start block on,
+    /// including the blocks that will be added after it was sent. A server that supports it keeps
+    /// the session open once it sent the last block it has, and sends new blocks as they're added.
+    pub const OPEN_ENDED_LIMIT: u64 = u64::MAX;
+
+    pub fn is_open_ended(&self) -> bool {
+        self.limit == Self::OPEN_ENDED_LIMIT
+    }
+
+    /// Returns the range of blocks that contains all the blocks the query asks for. Returns None
+    /// if the query starts from a block hash or if it doesn't ask for any block.
+    pub fn block_range(&self) -> Option<Range<BlockNumber>> {
+        let BlockHashOrNumber::Number(BlockNumber(start)) = self.start_block else {
+            return None;
+        };
+        let distance_to_last_block = self.limit.checked_sub(1)?.saturating_mul(self.step);
+        Some(match self.direction {
+            Direction::Forward => {
+                BlockNumber(start)
+                    ..BlockNumber(start.saturating_add(distance_to_last_block).saturating_add(1))
+            }
+            Direction::Backward => {
+                BlockNumber(start.saturating_sub(distance_to_last_block))..BlockNumber(start + 1)
+            }
+        })
+    }
+}
+
 #[derive(Debug, PartialEq, Eq, Clone, Copy, Hash)]
 pub enum BlockHashOrNumber {
     Hash(BlockHash),
@@ -39,19 +76,104 @@ impl Default for BlockHashOrNumber {
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct DataOrFin<T>(pub Option<T>);
 
+/// The version of a sync protocol, as it appears at the end of the protocol's name.
+pub type ProtocolVersion = u32;
+
 #[derive(Default, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct HeaderQuery(pub Query);
 
 #[derive(Default, Clone, Debug, PartialEq, Eq, Hash)]
 pub struct StateDiffQuery(pub Query);
 
-#[derive(Default, Clone, Debug, PartialEq, Eq, Hash)]
-pub struct TransactionQuery(pub Query);
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct TransactionQuery {
+    pub query: Query,
+    // If false, the transactions are sent without their outputs, which are most of the data. For
+    // peers that don't need the outputs.
+    pub include_outputs: bool,
+}
+
+impl Default for TransactionQuery {
+    fn default() -> Self {
+        Self { query: Query::default(), include_outputs: true }
+    }
+}
 
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct SignedBlockHeader {
     pub block_header: BlockHeader,
     pub signatures: Vec<BlockSignature>,
+    // A hint from the peer that sent this header on which other parts of the block it holds. None
+    // if the peer didn't declare it.
+    pub data_availability: Option<BlockDataAvailability>,
+}
+
+/// The parts of a block (besides its header) that a peer holds.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct BlockDataAvailability {
+    pub has_body: bool,
+    pub has_state_diff: bool,
+}
+
+/// A full block in the sync format. Used for injecting blocks into the storage without getting
+/// them from the network.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FullBlock {
+    pub signed_header: SignedBlockHeader,
+    pub transactions: Vec<(Transaction, TransactionOutput)>,
+    pub transaction_hashes: Vec<TransactionHash>,
+    // The state diff is split into chunks the same way it's split when it's sent over the network.
+    pub state_diff_chunks: Vec<StateDiffChunk>,
+}
+
+/// The ranges of blocks a peer advertised it can serve, per sync protocol.
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct BlockRangeAdvertisement {
+    pub ranges: Vec<ProtocolBlockRange>,
+    // None if the peer doesn't limit its responses.
+    pub response_limits: Option<ResponseLimits>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProtocolBlockRange {
+    // The name of the protocol as it's negotiated on the network.
+    pub protocol: String,
+    // The end of the range is exclusive.
+    pub block_range: Range<BlockNumber>,
+}
+
+/// The limits a peer enforces on each of its responses to sync queries. A response is ended once
+/// it reaches either limit, but only after the block it reached the limit in, so each response
+/// that has data holds at least one full block.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResponseLimits {
+    pub max_items: u64,
+    /// Measured on the encoded messages.
+    pub max_bytes: u64,
+}
+
+/// A single message in a classes response. A class that doesn't fit in a single message is sent
+/// as a `ChunkHeader` followed by the `Chunk`s of its encoding.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ClassMessage {
+    Class(ApiContractClass),
+    ChunkHeader(ClassChunkHeader),
+    Chunk(ClassChunk),
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassChunkHeader {
+    // The size in bytes of the encoded class.
+    pub total_size: u64,
+    // Keccak256 of the encoded class.
+    pub content_hash: [u8; 32],
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClassChunk {
+    // The offset of the chunk's data inside the encoded class.
+    pub offset: u64,
+    pub data: Vec<u8>,
 }
 
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
@@ -110,6 +232,50 @@ impl Default for StateDiffChunk {
     }
 }
 
+/// Splits a state diff into the chunks it's sent in over the network: a chunk per contract and a
+/// chunk per declared class. A deployed contract and a contract whose class was replaced are sent
+/// the same way, with the hash of their new class.
+pub fn split_thin_state_diff(thin_state_diff: ThinStateDiff) -> Vec<StateDiffChunk> {
+    let mut state_diff_chunks = Vec::new();
+    let mut contract_addresses = HashSet::new();
+
+    contract_addresses.extend(
+        thin_state_diff
+            .deployed_contracts
+            .keys()
+            .chain(thin_state_diff.replaced_classes.keys())
+            .chain(thin_state_diff.nonces.keys())
+            .chain(thin_state_diff.storage_diffs.keys()),
+    );
+    for contract_address in contract_addresses {
+        let class_hash = thin_state_diff
+            .deployed_contracts
+            .get(&contract_address)
+            .or_else(|| thin_state_diff.replaced_classes.get(&contract_address))
+            .cloned();
+        let storage_diffs =
+            thin_state_diff.storage_diffs.get(&contract_address).cloned().unwrap_or_default();
+        let nonce = thin_state_diff.nonces.get(&contract_address).cloned();
+        state_diff_chunks.push(StateDiffChunk::ContractDiff(ContractDiff {
+            contract_address,
+            class_hash,
+            nonce,
+            storage_diffs,
+        }));
+    }
+
+    for (class_hash, compiled_class_hash) in thin_state_diff.declared_classes {
+        state_diff_chunks
+            .push(StateDiffChunk::DeclaredClass(DeclaredClass { class_hash, compiled_class_hash }));
+    }
+
+    for class_hash in thin_state_diff.deprecated_declared_classes {
+        state_diff_chunks
+            .push(StateDiffChunk::DeprecatedDeclaredClass(DeprecatedDeclaredClass { class_hash }));
+    }
+    state_diff_chunks
+}
+
 #[cfg(any(feature = "testing", test))]
 auto_impl_get_test_instance! {
     pub enum StateDiffChunk{
@@ -0,0 +1,32 @@
+use starknet_api::block::{BlockHash, BlockNumber};
+
+use crate::sync::{BlockHashOrNumber, Direction, Query};
+
+#[test]
+fn query_block_range() {
+    let forward_query = Query {
+        start_block: BlockHashOrNumber::Number(BlockNumber(10)),
+        direction: Direction::Forward,
+        limit: 5,
+        step: 2,
+    };
+    assert_eq!(forward_query.block_range(), Some(BlockNumber(10)..BlockNumber(19)));
+
+    let backward_query = Query { direction: Direction::Backward, ..forward_query.clone() };
+    assert_eq!(backward_query.block_range(), Some(BlockNumber(2)..BlockNumber(11)));
+
+    let backward_query_past_genesis = Query { limit: 100, ..backward_query };
+    assert_eq!(backward_query_past_genesis.block_range(), Some(BlockNumber(0)..BlockNumber(11)));
+
+    let empty_query = Query { limit: 0, ..forward_query.clone() };
+    assert_eq!(empty_query.block_range(), None);
+
+    let open_ended_query =
+        Query { limit: Query::OPEN_ENDED_LIMIT, step: 1, ..forward_query.clone() };
+    assert!(open_ended_query.is_open_ended());
+    assert_eq!(open_ended_query.block_range(), Some(BlockNumber(10)..BlockNumber(u64::MAX)));
+
+    let hash_query =
+        Query { start_block: BlockHashOrNumber::Hash(BlockHash::default()), ..forward_query };
+    assert_eq!(hash_query.block_range(), None);
+}
@@ -9,7 +9,7 @@ use serde::{Deserialize, Serialize};
 use starknet_api::block::{BlockHash, BlockNumber};
 use starknet_api::core::{ChainId, ContractAddress, EntryPointSelector};
 use starknet_api::transaction::Calldata;
-use starknet_client::reader::PendingData;
+use starknet_client::reader::{PendingData, StarknetReader};
 use starknet_client::writer::StarknetWriter;
 use tokio::sync::RwLock;
 
@@ -64,6 +64,7 @@ pub fn get_methods_from_supported_apis(
     pending_data: Arc<RwLock<PendingData>>,
     pending_classes: Arc<RwLock<PendingClasses>>,
     starknet_writer: Arc<dyn StarknetWriter>,
+    starknet_reader: Arc<dyn StarknetReader + Send + Sync>,
 ) -> Methods {
     let mut methods: Methods = Methods::new();
     let server_gen = JsonRpcServerImplGenerator {
@@ -77,6 +78,7 @@ pub fn get_methods_from_supported_apis(
         pending_data,
         pending_classes,
         starknet_writer,
+        starknet_reader,
     };
     version_config::VERSION_CONFIG
         .iter()
@@ -119,6 +121,7 @@ pub trait JsonRpcServerTrait: Sized {
         pending_data: Arc<RwLock<PendingData>>,
         pending_classes: Arc<RwLock<PendingClasses>>,
         starknet_writer: Arc<dyn StarknetWriter>,
+        starknet_reader: Arc<dyn StarknetReader + Send + Sync>,
     ) -> Self;
 
     fn into_rpc_module(self) -> RpcModule<Self>;
@@ -137,6 +140,8 @@ struct JsonRpcServerImplGenerator {
     pending_classes: Arc<RwLock<PendingClasses>>,
     // TODO(shahak): Change this struct to be with a generic type of StarknetWriter.
     starknet_writer: Arc<dyn StarknetWriter>,
+    // Used for fetching pending classes that were removed from memory.
+    starknet_reader: Arc<dyn StarknetReader + Send + Sync>,
 }
 
 type JsonRpcServerImplParams = (
@@ -150,6 +155,7 @@ type JsonRpcServerImplParams = (
     Arc<RwLock<PendingData>>,
     Arc<RwLock<PendingClasses>>,
     Arc<dyn StarknetWriter>,
+    Arc<dyn StarknetReader + Send + Sync>,
 );
 
 impl JsonRpcServerImplGenerator {
@@ -165,6 +171,7 @@ impl JsonRpcServerImplGenerator {
             self.pending_data,
             self.pending_classes,
             self.starknet_writer,
+            self.starknet_reader,
         )
     }
 
@@ -183,6 +190,7 @@ impl JsonRpcServerImplGenerator {
             pending_data,
             pending_classes,
             starknet_writer,
+            starknet_reader,
         ) = self.get_params();
         Into::<Methods>::into(
             T::new(
@@ -196,6 +204,7 @@ impl JsonRpcServerImplGenerator {
                 pending_data,
                 pending_classes,
                 starknet_writer,
+                starknet_reader,
             )
             .into_rpc_module(),
         )
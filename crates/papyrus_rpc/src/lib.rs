@@ -5,6 +5,7 @@
 mod api;
 mod compression_utils;
 mod middleware;
+mod papyrus_api;
 mod pending;
 mod rpc_metrics;
 #[cfg(test)]
@@ -19,6 +20,7 @@ mod version_config;
 use std::collections::BTreeMap;
 use std::fmt::Display;
 use std::net::SocketAddr;
+use std::path::PathBuf;
 use std::sync::Arc;
 
 use jsonrpsee::core::RpcResult;
@@ -27,9 +29,15 @@ use jsonrpsee::types::error::ErrorCode::InternalError;
 use jsonrpsee::types::error::INTERNAL_ERROR_MSG;
 use jsonrpsee::types::ErrorObjectOwned;
 use papyrus_common::pending_classes::PendingClasses;
+use papyrus_common::unix_socket::bind_unix_socket;
 use papyrus_common::BlockHashAndNumber;
-use papyrus_config::dumping::{append_sub_config_name, ser_param, SerializeConfig};
-use papyrus_config::validators::validate_ascii;
+use papyrus_config::dumping::{
+    append_sub_config_name,
+    ser_optional_param,
+    ser_param,
+    SerializeConfig,
+};
+use papyrus_config::validators::{validate_ascii, validate_unix_file_mode};
 use papyrus_config::{ParamPath, ParamPrivacyInput, SerializedParam};
 use papyrus_execution::ExecutionConfig;
 use papyrus_storage::base_layer::BaseLayerStorageReader;
@@ -41,15 +49,30 @@ use rpc_metrics::MetricLogger;
 use serde::{Deserialize, Serialize};
 use starknet_api::block::{BlockNumber, BlockStatus};
 use starknet_api::core::ChainId;
-use starknet_client::reader::PendingData;
-use starknet_client::writer::StarknetGatewayClient;
+use starknet_client::reader::{PendingData, StarknetFeederGatewayClient};
+use starknet_client::writer::{StarknetGatewayClient, StarknetWriter};
 use starknet_client::RetryConfig;
+use tokio::net::{TcpStream, UnixListener};
 use tokio::sync::RwLock;
-use tracing::{debug, error, info, instrument};
-use validator::Validate;
+use tracing::{debug, error, info, instrument, warn};
+use validator::{Validate, ValidationError};
 
 use crate::api::get_methods_from_supported_apis;
-use crate::middleware::{deny_requests_with_unsupported_path, proxy_rpc_request};
+use crate::middleware::{deny_requests_with_unsupported_path, proxy_rpc_request, RequestIdService};
+pub use crate::papyrus_api::{
+    EventLeafProof,
+    NetworkInfoReader,
+    NetworkStats,
+    NodeInfo,
+    NodeSyncStatus,
+    PeerInfo,
+    ReceiptLeafProof,
+    ReceiptProof,
+    SessionPoolStats,
+    SessionPoolsStats,
+    SyncMode,
+};
+use crate::papyrus_api::{PapyrusApiImpl, PapyrusApiServer};
 use crate::syncing_state::get_last_synced_block;
 pub use crate::v0_6::transaction::{
     InvokeTransaction as InvokeTransactionRPC0_6,
@@ -66,17 +89,37 @@ const GENESIS_HASH: &str = "0x0";
 /// Maximum size of a supported transaction body - 10MB.
 pub const SERVER_MAX_BODY_SIZE: u32 = 10 * 1024 * 1024;
 
+// The address the server listens on internally when it serves a Unix domain socket.
+const UNIX_SOCKET_INTERNAL_ADDRESS: &str = "127.0.0.1:0";
+
 #[derive(Clone, Serialize, Deserialize, Debug, PartialEq, Validate)]
+#[validate(schema(function = "validate_rpc_config"))]
 pub struct RpcConfig {
     #[validate(custom = "validate_ascii")]
     pub chain_id: ChainId,
+    // Empty if the server listens on a Unix domain socket.
     pub server_address: String,
+    pub unix_socket_path: Option<PathBuf>,
+    #[validate(custom = "validate_unix_file_mode")]
+    pub unix_socket_mode: String,
     pub max_events_chunk_size: usize,
     pub max_events_keys: usize,
     pub collect_metrics: bool,
     pub starknet_url: String,
     pub starknet_gateway_retry_config: RetryConfig,
     pub execution_config: ExecutionConfig,
+    pub transaction_submission: TransactionSubmission,
+    pub enable_papyrus_namespace: bool,
+}
+
+/// Where the write API methods submit the transactions they receive.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Serialize, PartialEq, Eq)]
+pub enum TransactionSubmission {
+    /// Forward the transactions to the Starknet gateway at starknet_url.
+    #[default]
+    CentralGateway,
+    /// Broadcast the transactions to the p2p network. Requires the network to be enabled.
+    Gossipsub,
 }
 
 impl Default for RpcConfig {
@@ -84,6 +127,8 @@ impl Default for RpcConfig {
         RpcConfig {
             chain_id: ChainId::Mainnet,
             server_address: String::from("0.0.0.0:8080"),
+            unix_socket_path: None,
+            unix_socket_mode: String::from("660"),
             max_events_chunk_size: 1000,
             max_events_keys: 100,
             collect_metrics: false,
@@ -94,6 +139,8 @@ impl Default for RpcConfig {
                 max_retries: 5,
             },
             execution_config: ExecutionConfig::default(),
+            transaction_submission: TransactionSubmission::default(),
+            enable_papyrus_namespace: false,
         }
     }
 }
@@ -110,7 +157,14 @@ impl SerializeConfig for RpcConfig {
             ser_param(
                 "server_address",
                 &self.server_address,
-                "IP:PORT of the node`s JSON-RPC server.",
+                "IP:PORT of the node`s JSON-RPC server. Should be empty if unix_socket_path is \
+                 set.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "unix_socket_mode",
+                &self.unix_socket_mode,
+                "The permissions of the JSON-RPC server's Unix domain socket, in octal.",
                 ParamPrivacyInput::Public,
             ),
             ser_param(
@@ -134,11 +188,34 @@ impl SerializeConfig for RpcConfig {
             ser_param(
                 "starknet_url",
                 &self.starknet_url,
-                "URL for communicating with Starknet in write_api methods.",
+                "URL for communicating with Starknet in write_api methods and for fetching pending \
+                 classes that were removed from memory.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "transaction_submission",
+                &self.transaction_submission,
+                "Where write_api methods submit transactions. CentralGateway forwards them to \
+                 starknet_url and Gossipsub broadcasts them to the p2p network.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "enable_papyrus_namespace",
+                &self.enable_papyrus_namespace,
+                "If true, serve the papyrus_* methods, which report the node's peers, sync \
+                 progress and network counters. They aren't part of the Starknet specs.",
                 ParamPrivacyInput::Public,
             ),
         ]);
 
+        self_params_dump.extend(ser_optional_param(
+            &self.unix_socket_path,
+            PathBuf::from("/tmp/papyrus_rpc.sock"),
+            "unix_socket_path",
+            "The path of a Unix domain socket for the JSON-RPC server to listen on instead of \
+             server_address.",
+            ParamPrivacyInput::Public,
+        ));
         self_params_dump
             .append(&mut append_sub_config_name(self.execution_config.dump(), "execution_config"));
         let mut retry_config_dump = append_sub_config_name(
@@ -157,6 +234,15 @@ impl SerializeConfig for RpcConfig {
     }
 }
 
+fn validate_rpc_config(config: &RpcConfig) -> Result<(), ValidationError> {
+    if config.server_address.is_empty() == config.unix_socket_path.is_none() {
+        return Err(ValidationError::new(
+            "Exactly one of server_address and unix_socket_path should be set",
+        ));
+    }
+    Ok(())
+}
+
 fn internal_server_error(err: impl Display) -> ErrorObjectOwned {
     error!("{}: {}", INTERNAL_ERROR_MSG, err);
     ErrorObjectOwned::owned(InternalError.code(), INTERNAL_ERROR_MSG, None::<()>)
@@ -200,7 +286,8 @@ fn get_block_status<Mode: TransactionKind>(
 #[derive(Clone, Debug, PartialEq)]
 struct ContinuationTokenAsStruct(EventIndex);
 
-#[instrument(skip(storage_reader), level = "debug", err)]
+#[allow(clippy::too_many_arguments)]
+#[instrument(skip(storage_reader, p2p_transaction_writer, node_info), level = "debug", err)]
 pub async fn run_server(
     config: &RpcConfig,
     shared_highest_block: Arc<RwLock<Option<BlockHashAndNumber>>>,
@@ -208,46 +295,119 @@ pub async fn run_server(
     pending_classes: Arc<RwLock<PendingClasses>>,
     storage_reader: StorageReader,
     node_version: &'static str,
+    p2p_transaction_writer: Option<Arc<dyn StarknetWriter>>,
+    node_info: NodeInfo,
 ) -> anyhow::Result<(SocketAddr, ServerHandle)> {
     let starting_block = get_last_synced_block(storage_reader.clone())?;
     debug!("Starting JSON-RPC.");
-    let methods = get_methods_from_supported_apis(
+    let starknet_writer: Arc<dyn StarknetWriter> = match config.transaction_submission {
+        TransactionSubmission::CentralGateway => Arc::new(StarknetGatewayClient::new(
+            &config.starknet_url,
+            node_version,
+            config.starknet_gateway_retry_config,
+        )?),
+        TransactionSubmission::Gossipsub => p2p_transaction_writer.ok_or_else(|| {
+            anyhow::anyhow!("Submitting transactions through gossipsub requires the p2p network.")
+        })?,
+    };
+    let mut methods = get_methods_from_supported_apis(
         &config.chain_id,
         config.execution_config,
-        storage_reader,
+        storage_reader.clone(),
         config.max_events_chunk_size,
         config.max_events_keys,
         starting_block,
-        shared_highest_block,
+        shared_highest_block.clone(),
         pending_data,
         pending_classes,
-        Arc::new(StarknetGatewayClient::new(
+        starknet_writer,
+        Arc::new(StarknetFeederGatewayClient::new(
             &config.starknet_url,
+            None,
             node_version,
             config.starknet_gateway_retry_config,
         )?),
     );
+    // The papyrus namespace isn't part of any spec version, so it's added to the methods of all the
+    // versions only here.
+    if config.enable_papyrus_namespace {
+        methods.merge(
+            PapyrusApiImpl::new(storage_reader, shared_highest_block, node_info).into_rpc(),
+        )?;
+    }
+    // jsonrpsee can only serve TCP listeners, so when listening on a Unix domain socket the server
+    // listens on an internal local address and the socket's connections are forwarded to it.
+    let server_address = match config.unix_socket_path {
+        Some(_) => UNIX_SOCKET_INTERNAL_ADDRESS,
+        None => config.server_address.as_str(),
+    };
     let addr;
     let handle;
     let server_builder =
         ServerBuilder::default().max_request_body_size(SERVER_MAX_BODY_SIZE).set_middleware(
             tower::ServiceBuilder::new()
+                .layer_fn(RequestIdService::new)
                 .filter_async(deny_requests_with_unsupported_path)
                 .filter_async(proxy_rpc_request),
         );
 
     if config.collect_metrics {
-        let server = server_builder
-            .set_logger(MetricLogger::new(&methods))
-            .build(&config.server_address)
-            .await?;
+        let server =
+            server_builder.set_logger(MetricLogger::new(&methods)).build(server_address).await?;
         addr = server.local_addr()?;
         handle = server.start(methods);
     } else {
-        let server = server_builder.build(&config.server_address).await?;
+        let server = server_builder.build(server_address).await?;
         addr = server.local_addr()?;
         handle = server.start(methods);
     }
-    info!(local_address = %addr, "JSON-RPC is running.");
+    match &config.unix_socket_path {
+        Some(unix_socket_path) => {
+            let mode = u32::from_str_radix(&config.unix_socket_mode, 8)
+                .expect("The Unix socket mode should be validated");
+            let listener = UnixListener::from_std(bind_unix_socket(unix_socket_path, mode)?)?;
+            tokio::spawn(forward_unix_socket_connections(listener, addr, handle.clone()));
+            info!(
+                unix_socket_path = %unix_socket_path.display(),
+                "JSON-RPC is running."
+            );
+        }
+        None => info!(local_address = %addr, "JSON-RPC is running."),
+    }
     Ok((addr, handle))
 }
+
+// Forwards each connection accepted on the Unix domain socket to the server at the given address,
+// until the server stops.
+async fn forward_unix_socket_connections(
+    listener: UnixListener,
+    server_address: SocketAddr,
+    server_handle: ServerHandle,
+) {
+    let accept_loop = async {
+        loop {
+            let mut unix_stream = match listener.accept().await {
+                Ok((unix_stream, _)) => unix_stream,
+                Err(error) => {
+                    warn!("Failed accepting a JSON-RPC connection on the Unix socket: {error}");
+                    continue;
+                }
+            };
+            tokio::spawn(async move {
+                let mut tcp_stream = match TcpStream::connect(server_address).await {
+                    Ok(tcp_stream) => tcp_stream,
+                    Err(error) => {
+                        warn!("Failed forwarding a JSON-RPC connection to the server: {error}");
+                        return;
+                    }
+                };
+                // The connection is closed by either side when its requests are done.
+                let _ = tokio::io::copy_bidirectional(&mut unix_stream, &mut tcp_stream).await;
+            });
+        }
+    };
+    tokio::select! {
+        _ = accept_loop => {},
+        _ = server_handle.stopped() => {},
+    }
+}
@@ -1,9 +1,16 @@
-use hyper::{Body, Request};
+use std::future::Future;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+
+use hyper::header::{HeaderMap, HeaderValue, CONTENT_LENGTH};
+use hyper::{Body, Request, Response};
 use jsonrpsee::core::http_helpers::read_body;
 use regex::Regex;
-use tower::BoxError;
-use tracing::{debug, instrument};
+use serde_json::{Map, Value};
+use tower::{BoxError, Service};
+use tracing::{debug, info_span, instrument, Instrument};
 
+use crate::papyrus_api::PAPYRUS_NAMESPACE_PREFIX;
 use crate::version_config::{VersionState, VERSION_CONFIG, VERSION_PATTERN};
 use crate::SERVER_MAX_BODY_SIZE;
 
@@ -63,6 +70,10 @@ fn add_version_to_method_name_in_body(
     let Ok(vec_body) = vec_body
         .iter_mut()
         .map(|body| {
+            // The papyrus namespace isn't versioned, so its methods are served on every path.
+            if body.method.starts_with(PAPYRUS_NAMESPACE_PREFIX) {
+                return Ok(body);
+            }
             let Some(stripped_method) = strip_starknet_from_method(body.method.as_ref()) else {
                 return Err(BoxError::from("Method name has unexpected format"));
             };
@@ -121,3 +132,122 @@ fn is_supported_path(path: &str) -> bool {
         .expect("should be a valid regex");
     re.is_match(path)
 }
+
+/// The header a client can set to choose the id of its request. The id is echoed back in the same
+/// header of the response.
+pub(crate) const REQUEST_ID_HEADER: &str = "x-request-id";
+/// The key under which the request id is added to the data of error responses.
+pub(crate) const REQUEST_ID_ERROR_DATA_KEY: &str = "request_id";
+const MAX_REQUEST_ID_LENGTH: usize = 128;
+
+/// [`Tower`] middleware that assigns an id to each request and handles the request inside a span
+/// that contains the id, so that every log written while handling it, including logs of the
+/// storage and the execution, can be correlated to the request.
+/// The id is taken from the [`REQUEST_ID_HEADER`] header if it's valid, and generated otherwise.
+/// It is returned in the same header of the response and added to the data of every error in the
+/// response body.
+///
+/// [`Tower`]: https://crates.io/crates/tower
+#[derive(Clone, Debug)]
+pub(crate) struct RequestIdService<S> {
+    inner: S,
+}
+
+impl<S> RequestIdService<S> {
+    pub(crate) fn new(inner: S) -> Self {
+        Self { inner }
+    }
+}
+
+impl<S> Service<Request<Body>> for RequestIdService<S>
+where
+    S: Service<Request<Body>, Response = Response<Body>, Error = BoxError>,
+    S::Future: Send + 'static,
+{
+    type Response = Response<Body>;
+    type Error = BoxError;
+    type Future = Pin<Box<dyn Future<Output = Result<Response<Body>, BoxError>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), BoxError>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        let request_id = get_request_id(req.headers());
+        let span = info_span!("rpc_request", request_id = %request_id);
+        let response_future = span.in_scope(|| self.inner.call(req));
+        Box::pin(
+            async move {
+                let response = response_future.await?;
+                add_request_id_to_response(response, request_id).await
+            }
+            .instrument(span),
+        )
+    }
+}
+
+fn get_request_id(headers: &HeaderMap) -> String {
+    let requested_id = headers.get(REQUEST_ID_HEADER).and_then(|value| value.to_str().ok());
+    match requested_id {
+        Some(request_id) if is_valid_request_id(request_id) => request_id.to_owned(),
+        // Ids that may corrupt the logs are replaced rather than rejected, since the id is only
+        // used for debugging.
+        _ => format!("{:032x}", rand::random::<u128>()),
+    }
+}
+
+fn is_valid_request_id(request_id: &str) -> bool {
+    !request_id.is_empty()
+        && request_id.len() <= MAX_REQUEST_ID_LENGTH
+        && request_id.chars().all(|c| c.is_ascii_alphanumeric() || "-_.:".contains(c))
+}
+
+async fn add_request_id_to_response(
+    response: Response<Body>,
+    request_id: String,
+) -> Result<Response<Body>, BoxError> {
+    let (mut parts, body) = response.into_parts();
+    let body_bytes = hyper::body::to_bytes(body).await?;
+    let body = match add_request_id_to_errors(&body_bytes, &request_id) {
+        Some(new_body_bytes) => {
+            // The length is recalculated by the server from the new body.
+            parts.headers.remove(CONTENT_LENGTH);
+            new_body_bytes.into()
+        }
+        None => body_bytes.into(),
+    };
+    parts.headers.insert(REQUEST_ID_HEADER, HeaderValue::from_str(&request_id)?);
+    Ok(Response::from_parts(parts, body))
+}
+
+// Returns the new body if the given body contains errors.
+fn add_request_id_to_errors(body_bytes: &[u8], request_id: &str) -> Option<Vec<u8>> {
+    // Parsing the body is needed only if it may contain an error. This saves parsing big results,
+    // such as the results of starknet_getEvents.
+    if !body_bytes.windows(7).any(|window| window == b"\"error\"") {
+        return None;
+    }
+    let mut json_body = serde_json::from_slice::<Value>(body_bytes).ok()?;
+    match &mut json_body {
+        Value::Array(batch) => {
+            batch.iter_mut().for_each(|response| add_request_id_to_error(response, request_id))
+        }
+        response => add_request_id_to_error(response, request_id),
+    }
+    serde_json::to_vec(&json_body).ok()
+}
+
+// Errors whose data is neither empty nor an object are left as is, to keep the data in the format
+// that the specs define.
+fn add_request_id_to_error(response: &mut Value, request_id: &str) {
+    let Some(error) = response.get_mut("error").and_then(Value::as_object_mut) else {
+        return;
+    };
+    let data = error.entry("data").or_insert(Value::Null);
+    if data.is_null() {
+        *data = Value::Object(Map::new());
+    }
+    if let Value::Object(data) = data {
+        data.insert(REQUEST_ID_ERROR_DATA_KEY.to_owned(), Value::String(request_id.to_owned()));
+    }
+}
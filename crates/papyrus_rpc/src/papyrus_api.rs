@@ -0,0 +1,387 @@
+//! A custom JSON-RPC namespace for inspecting the node itself, for tooling that speaks only
+//! JSON-RPC to the node. It isn't part of the Starknet specs, so it's served only if
+//! `enable_papyrus_namespace` is set, and its methods aren't versioned.
+
+use std::num::NonZeroUsize;
+use std::sync::{Arc, Mutex};
+
+use async_trait::async_trait;
+use jsonrpsee::core::RpcResult;
+use jsonrpsee::proc_macros::rpc;
+use jsonrpsee::types::ErrorObjectOwned;
+use lru::LruCache;
+use papyrus_common::block_hash::{
+    calculate_commitment_trees,
+    BlockCommitmentTrees,
+    BlockHashError,
+};
+use papyrus_common::commitment_tree::MerkleNode;
+use papyrus_common::BlockHashAndNumber;
+use papyrus_storage::base_layer::BaseLayerStorageReader;
+use papyrus_storage::body::{BodyStorageReader, TransactionIndex};
+use papyrus_storage::class::ClassStorageReader;
+use papyrus_storage::compiled_class::CasmStorageReader;
+use papyrus_storage::header::HeaderStorageReader;
+use papyrus_storage::state::StateStorageReader;
+use papyrus_storage::db::RO;
+use papyrus_storage::{StorageReader, StorageTxn};
+use serde::{Deserialize, Serialize};
+use starknet_api::block::{BlockBody, BlockHash, BlockHeader, BlockNumber};
+use starknet_api::core::{ContractAddress, EventCommitment, ReceiptCommitment};
+use starknet_api::transaction::{
+    EventData,
+    EventKey,
+    TransactionHash,
+    TransactionOffsetInBlock,
+    TransactionOutput,
+};
+use starknet_types_core::felt::Felt;
+use tokio::sync::RwLock;
+
+use crate::v0_7::error::{JsonRpcError, BLOCK_NOT_FOUND, TRANSACTION_HASH_NOT_FOUND};
+use crate::{internal_server_error, internal_server_error_with_msg, verify_storage_scope};
+
+/// The prefix of the names of the namespace's methods.
+pub(crate) const PAPYRUS_NAMESPACE_PREFIX: &str = "papyrus_";
+
+// The number of blocks whose commitment trees are kept for getReceiptProof.
+const COMMITMENT_TREES_CACHE_SIZE: usize = 16;
+
+// Blocks from before Starknet 0.13.2 commit to their transactions and events in other schemes and
+// don't commit to their receipts at all.
+pub(crate) const RECEIPT_PROOF_NOT_SUPPORTED: JsonRpcError<String> = JsonRpcError {
+    code: 10000,
+    message: "The block of the transaction has no receipt commitment to prove against",
+    data: None,
+};
+
+/// The component that writes the synced blocks to the storage.
+#[derive(Copy, Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+#[serde(rename_all = "snake_case")]
+pub enum SyncMode {
+    /// The blocks are synced from the Starknet feeder gateway.
+    Central,
+    /// The blocks are synced from peers of the p2p network.
+    P2p,
+    /// The node doesn't sync, e.g. since it reads a storage that another node writes.
+    #[default]
+    Disabled,
+}
+
+/// What the node knows about a peer of its p2p network.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct PeerInfo {
+    pub peer_id: String,
+    /// The addresses the node dials the peer on.
+    pub addresses: Vec<String>,
+    /// The protocols the peer reported it supports.
+    pub protocols: Vec<String>,
+    /// The software the peer reported it runs. None if the peer didn't report it yet.
+    pub agent_version: Option<String>,
+    /// The versioned names of the protocols the node and the peer agreed on in their sessions,
+    /// sorted.
+    pub negotiated_protocols: Vec<String>,
+    pub num_connections: usize,
+    /// Whether the node doesn't query the peer, either due to its reputation or a manual ban.
+    pub blocked: bool,
+    /// The number of bytes the node sent to the peer in response to its queries.
+    pub served_bytes: u64,
+}
+
+/// Counters of the node's p2p network.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct NetworkStats {
+    pub num_known_peers: usize,
+    pub num_connected_peers: usize,
+    pub num_connections: usize,
+    pub num_blocked_peers: usize,
+    /// The number of bytes the node sent in response to the queries of all peers.
+    pub served_bytes: u64,
+    pub sessions: SessionPoolsStats,
+}
+
+/// The sessions the node serves its peers' queries in, and the sessions it sends its own queries
+/// in. Each direction has its own limit, so one can't take the sessions of the other.
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct SessionPoolsStats {
+    pub inbound: SessionPoolStats,
+    pub outbound: SessionPoolStats,
+}
+
+#[derive(Clone, Copy, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct SessionPoolStats {
+    pub num_active_sessions: usize,
+    pub max_sessions: usize,
+}
+
+/// The progress of each part of the synced data, and the highest block the node knows of.
+#[derive(Clone, Debug, Default, Deserialize, Eq, PartialEq, Serialize)]
+pub struct NodeSyncStatus {
+    pub mode: SyncMode,
+    pub header_marker: BlockNumber,
+    pub body_marker: BlockNumber,
+    pub state_marker: BlockNumber,
+    pub class_marker: BlockNumber,
+    pub compiled_class_marker: BlockNumber,
+    pub base_layer_marker: BlockNumber,
+    /// None if the sync didn't learn of any block yet.
+    pub highest_known_block: Option<BlockHashAndNumber>,
+}
+
+/// A proof that the receipt of a transaction and its events are included in the receipt and event
+/// commitments of its block.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ReceiptProof {
+    pub block_hash: BlockHash,
+    pub block_number: BlockNumber,
+    pub receipt_commitment: ReceiptCommitment,
+    pub receipt: ReceiptLeafProof,
+    pub event_commitment: EventCommitment,
+    pub events: Vec<EventLeafProof>,
+}
+
+/// The path of a receipt in the receipt commitment tree. The leaf is the Poseidon hash of
+/// `leaf_preimage`, which is `[transaction_hash, actual_fee, messages_sent_hash,
+/// revert_reason_hash, l2_gas_consumed, l1_gas_consumed, l1_data_gas_consumed]`.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct ReceiptLeafProof {
+    pub index: usize,
+    pub transaction_hash: TransactionHash,
+    pub leaf_preimage: Vec<Felt>,
+    pub leaf: Felt,
+    pub path: Vec<MerkleNode>,
+}
+
+/// The path of an event in the event commitment tree. The leaf is
+/// `Poseidon(from_address, transaction_hash, num_keys, keys, num_data, data)`.
+#[derive(Clone, Debug, Deserialize, Eq, PartialEq, Serialize)]
+pub struct EventLeafProof {
+    pub index: usize,
+    pub from_address: ContractAddress,
+    pub keys: Vec<EventKey>,
+    pub data: EventData,
+    pub leaf: Felt,
+    pub path: Vec<MerkleNode>,
+}
+
+/// Reads the state of the node's p2p network. It's implemented by the node over the same handles
+/// the monitoring gateway reads, so that the two report the same peers.
+#[async_trait]
+pub trait NetworkInfoReader: Send + Sync {
+    /// Returns the peers the node found, queried or was queried by.
+    async fn get_peers(&self) -> anyhow::Result<Vec<PeerInfo>>;
+
+    /// Returns the usage of the node's inbound and outbound sessions.
+    fn get_session_pools(&self) -> SessionPoolsStats;
+}
+
+/// What the papyrus namespace reports about the components of the node outside the server.
+#[derive(Clone, Default)]
+pub struct NodeInfo {
+    pub sync_mode: SyncMode,
+    /// None if the node's p2p network isn't running.
+    pub network_info_reader: Option<Arc<dyn NetworkInfoReader>>,
+}
+
+#[rpc(server, namespace = "papyrus")]
+pub trait PapyrusApi {
+    /// Returns the peers of the node's p2p network.
+    #[method(name = "getPeers")]
+    async fn get_peers(&self) -> RpcResult<Vec<PeerInfo>>;
+
+    /// Returns the markers of the node's storage, the highest block the node knows of and the
+    /// component that syncs the node.
+    #[method(name = "getSyncStatus")]
+    async fn get_sync_status(&self) -> RpcResult<NodeSyncStatus>;
+
+    /// Returns the connection, bandwidth and session counters of the node's p2p network.
+    #[method(name = "getNetworkStats")]
+    async fn get_network_stats(&self) -> RpcResult<NetworkStats>;
+
+    /// Returns a proof that the receipt of the given transaction and its events are included in
+    /// the receipt and event commitments of its block. Only blocks from Starknet 0.13.2 on have a
+    /// receipt commitment, so the transactions of older blocks return an error.
+    #[method(name = "getReceiptProof")]
+    async fn get_receipt_proof(&self, transaction_hash: TransactionHash)
+        -> RpcResult<ReceiptProof>;
+}
+
+pub(crate) struct PapyrusApiImpl {
+    pub storage_reader: StorageReader,
+    pub shared_highest_block: Arc<RwLock<Option<BlockHashAndNumber>>>,
+    pub node_info: NodeInfo,
+    pub commitment_trees_cache: Mutex<LruCache<BlockHash, Arc<BlockCommitmentTrees>>>,
+}
+
+impl PapyrusApiImpl {
+    pub(crate) fn new(
+        storage_reader: StorageReader,
+        shared_highest_block: Arc<RwLock<Option<BlockHashAndNumber>>>,
+        node_info: NodeInfo,
+    ) -> Self {
+        Self {
+            storage_reader,
+            shared_highest_block,
+            node_info,
+            commitment_trees_cache: Mutex::new(LruCache::new(
+                NonZeroUsize::new(COMMITMENT_TREES_CACHE_SIZE)
+                    .expect("The cache size should be positive"),
+            )),
+        }
+    }
+
+    fn network_info_reader(&self) -> Result<&dyn NetworkInfoReader, ErrorObjectOwned> {
+        self.node_info
+            .network_info_reader
+            .as_deref()
+            .ok_or_else(|| internal_server_error_with_msg("The node's p2p network isn't running."))
+    }
+
+    async fn read_peers(&self) -> Result<Vec<PeerInfo>, ErrorObjectOwned> {
+        self.network_info_reader()?.get_peers().await.map_err(internal_server_error)
+    }
+
+    // Returns the commitment trees of the given block, calculating them if they aren't cached.
+    fn get_commitment_trees(
+        &self,
+        txn: &StorageTxn<'_, RO>,
+        header: &BlockHeader,
+        transaction_outputs: &[TransactionOutput],
+    ) -> Result<Arc<BlockCommitmentTrees>, ErrorObjectOwned> {
+        if let Some(trees) = self
+            .commitment_trees_cache
+            .lock()
+            .expect("The commitment trees cache should be available")
+            .get(&header.block_hash)
+        {
+            return Ok(trees.clone());
+        }
+
+        let body = BlockBody {
+            transactions: txn
+                .get_block_transactions(header.block_number)
+                .map_err(internal_server_error)?
+                .ok_or_else(|| ErrorObjectOwned::from(BLOCK_NOT_FOUND))?,
+            transaction_outputs: transaction_outputs.to_vec(),
+            transaction_hashes: txn
+                .get_block_transaction_hashes(header.block_number)
+                .map_err(internal_server_error)?
+                .ok_or_else(|| ErrorObjectOwned::from(BLOCK_NOT_FOUND))?,
+        };
+        let trees =
+            Arc::new(calculate_commitment_trees(header, &body).map_err(|err| match err {
+                BlockHashError::MissingHeaderData | BlockHashError::UnsupportedCommitmentScheme => {
+                    ErrorObjectOwned::from(RECEIPT_PROOF_NOT_SUPPORTED)
+                }
+                err => internal_server_error(err),
+            })?);
+        self.commitment_trees_cache
+            .lock()
+            .expect("The commitment trees cache should be available")
+            .put(header.block_hash, trees.clone());
+        Ok(trees)
+    }
+}
+
+#[async_trait]
+impl PapyrusApiServer for PapyrusApiImpl {
+    async fn get_peers(&self) -> RpcResult<Vec<PeerInfo>> {
+        self.read_peers().await
+    }
+
+    async fn get_sync_status(&self) -> RpcResult<NodeSyncStatus> {
+        let highest_known_block = *self.shared_highest_block.read().await;
+        let txn = self.storage_reader.begin_ro_txn().map_err(internal_server_error)?;
+        Ok(NodeSyncStatus {
+            mode: self.node_info.sync_mode,
+            header_marker: txn.get_header_marker().map_err(internal_server_error)?,
+            body_marker: txn.get_body_marker().map_err(internal_server_error)?,
+            state_marker: txn.get_state_marker().map_err(internal_server_error)?,
+            class_marker: txn.get_class_marker().map_err(internal_server_error)?,
+            compiled_class_marker: txn
+                .get_compiled_class_marker()
+                .map_err(internal_server_error)?,
+            base_layer_marker: txn.get_base_layer_block_marker().map_err(internal_server_error)?,
+            highest_known_block,
+        })
+    }
+
+    async fn get_network_stats(&self) -> RpcResult<NetworkStats> {
+        // The counters are computed from the same snapshot as getPeers, so the two always agree.
+        let peers = self.read_peers().await?;
+        let sessions = self.network_info_reader()?.get_session_pools();
+        Ok(NetworkStats {
+            num_known_peers: peers.len(),
+            num_connected_peers: peers.iter().filter(|peer| peer.num_connections > 0).count(),
+            num_connections: peers.iter().map(|peer| peer.num_connections).sum(),
+            num_blocked_peers: peers.iter().filter(|peer| peer.blocked).count(),
+            served_bytes: peers.iter().map(|peer| peer.served_bytes).sum(),
+            sessions,
+        })
+    }
+    async fn get_receipt_proof(
+        &self,
+        transaction_hash: TransactionHash,
+    ) -> RpcResult<ReceiptProof> {
+        verify_storage_scope(&self.storage_reader)?;
+
+        let txn = self.storage_reader.begin_ro_txn().map_err(internal_server_error)?;
+        let TransactionIndex(block_number, TransactionOffsetInBlock(offset)) = txn
+            .get_transaction_idx_by_hash(&transaction_hash)
+            .map_err(internal_server_error)?
+            .ok_or_else(|| ErrorObjectOwned::from(TRANSACTION_HASH_NOT_FOUND))?;
+        let header = txn
+            .get_block_header(block_number)
+            .map_err(internal_server_error)?
+            .ok_or_else(|| ErrorObjectOwned::from(BLOCK_NOT_FOUND))?;
+        let (Some(receipt_commitment), Some(event_commitment)) =
+            (header.receipt_commitment, header.event_commitment)
+        else {
+            return Err(RECEIPT_PROOF_NOT_SUPPORTED.into());
+        };
+        let transaction_outputs = txn
+            .get_block_transaction_outputs(block_number)
+            .map_err(internal_server_error)?
+            .ok_or_else(|| ErrorObjectOwned::from(BLOCK_NOT_FOUND))?;
+        let trees = self.get_commitment_trees(&txn, &header, &transaction_outputs)?;
+
+        let receipt = ReceiptLeafProof {
+            index: offset,
+            transaction_hash,
+            leaf_preimage: trees.receipt_leaf_preimages[offset].clone(),
+            leaf: trees.receipts.leaves()[offset],
+            path: trees.receipts.proof(offset).ok_or_else(|| {
+                internal_server_error_with_msg("Receipt is missing from its tree")
+            })?,
+        };
+
+        let first_event_index: usize =
+            transaction_outputs[..offset].iter().map(|output| output.events().len()).sum();
+        let events = transaction_outputs[offset]
+            .events()
+            .iter()
+            .zip(first_event_index..)
+            .map(|(event, index)| {
+                Ok(EventLeafProof {
+                    index,
+                    from_address: event.from_address,
+                    keys: event.content.keys.clone(),
+                    data: event.content.data.clone(),
+                    leaf: trees.events.leaves()[index],
+                    path: trees.events.proof(index).ok_or_else(|| {
+                        internal_server_error_with_msg("Event is missing from its tree")
+                    })?,
+                })
+            })
+            .collect::<RpcResult<Vec<_>>>()?;
+
+        Ok(ReceiptProof {
+            block_hash: header.block_hash,
+            block_number,
+            receipt_commitment,
+            receipt,
+            event_commitment,
+            events,
+        })
+    }
+}
@@ -1,6 +1,13 @@
-use papyrus_common::pending_classes::PendingClasses;
+use jsonrpsee::core::RpcResult;
+use papyrus_common::pending_classes::{ApiContractClass, PendingClasses, PendingClassesTrait};
 use papyrus_execution::objects::PendingData as ExecutionPendingData;
+use starknet_api::core::ClassHash;
 use starknet_client::reader::objects::pending_data::PendingData as ClientPendingData;
+use starknet_client::reader::StarknetReader;
+use tokio::sync::RwLock;
+use tracing::debug;
+
+use crate::internal_server_error;
 
 pub(crate) fn client_pending_data_to_execution_pending_data(
     client_pending_data: ClientPendingData,
@@ -21,3 +28,69 @@ pub(crate) fn client_pending_data_to_execution_pending_data(
         sequencer: client_pending_data.block.sequencer_address(),
     }
 }
+
+// The pending sync removes the oldest pending classes from memory when they exceed the size cap.
+// The functions below fetch such classes again from the central source.
+
+/// Returns the pending class with the given hash, or None if the class isn't part of the pending
+/// classes.
+pub(crate) async fn get_pending_class(
+    pending_classes: &RwLock<PendingClasses>,
+    starknet_reader: &(dyn StarknetReader + Send + Sync),
+    class_hash: ClassHash,
+) -> RpcResult<Option<ApiContractClass>> {
+    {
+        let pending_classes = pending_classes.read().await;
+        if let Some(class) = pending_classes.get_class(class_hash) {
+            return Ok(Some(class));
+        }
+        if !pending_classes.is_class_evicted(class_hash) {
+            return Ok(None);
+        }
+    }
+    debug!("Pending class {class_hash} was removed from memory. Fetching it from Starknet.");
+    let class = starknet_reader.class_by_hash(class_hash).await.map_err(internal_server_error)?;
+    Ok(class.map(ApiContractClass::from))
+}
+
+/// Returns a copy of the pending classes that includes the classes of the given pending data that
+/// were removed from memory.
+pub(crate) async fn read_pending_classes(
+    pending_classes: &RwLock<PendingClasses>,
+    starknet_reader: &(dyn StarknetReader + Send + Sync),
+    client_pending_data: &ClientPendingData,
+) -> RpcResult<PendingClasses> {
+    let mut pending_classes = pending_classes.read().await.clone();
+    let state_diff = &client_pending_data.state_update.state_diff;
+    let declared_class_hashes = state_diff
+        .declared_classes
+        .iter()
+        .map(|entry| entry.class_hash)
+        .chain(state_diff.old_declared_contracts.iter().cloned());
+    for class_hash in declared_class_hashes {
+        if pending_classes.is_class_evicted(class_hash) {
+            debug!(
+                "Pending class {class_hash} was removed from memory. Fetching it from Starknet."
+            );
+            if let Some(class) =
+                starknet_reader.class_by_hash(class_hash).await.map_err(internal_server_error)?
+            {
+                pending_classes.add_class(class_hash, class.into());
+            }
+        }
+        if pending_classes.is_compiled_class_evicted(class_hash) {
+            debug!(
+                "Pending compiled class {class_hash} was removed from memory. Fetching it from \
+                 Starknet."
+            );
+            if let Some(compiled_class) = starknet_reader
+                .compiled_class_by_hash(class_hash)
+                .await
+                .map_err(internal_server_error)?
+            {
+                pending_classes.add_compiled_class(class_hash, compiled_class);
+            }
+        }
+    }
+    Ok(pending_classes)
+}
@@ -10,6 +10,8 @@ use jsonrpsee::types::Params;
 use jsonrpsee::Methods;
 use metrics::{histogram, increment_counter, register_counter, register_histogram};
 
+use crate::papyrus_api::PAPYRUS_NAMESPACE_PREFIX;
+
 // Name of the metrics.
 const INCOMING_REQUEST: &str = "rpc_incoming_requests";
 const FAILED_REQUESTS: &str = "rpc_failed_requests";
@@ -19,6 +21,7 @@ const REQUEST_LATENCY: &str = "rpc_request_latency_seconds";
 const METHOD_LABEL: &str = "method";
 const VERSION_LABEL: &str = "version";
 const ILLEGAL_METHOD: &str = "illegal_method";
+const PAPYRUS_VERSION_LABEL: &str = "papyrus";
 
 // Register the metrics and returns a set of the method names.
 fn init_metrics(methods: &Methods) -> HashSet<String> {
@@ -106,7 +109,12 @@ impl Logger for MetricLogger {
 
 // Given method_name returns (method, version).
 // Example: method_name: starknet_V0_6_0_blockNumber; output: (blockNumber, V0_6_0).
+// The methods of the papyrus namespace aren't versioned, so their version is the namespace.
+// Example: method_name: papyrus_getPeers; output: (getPeers, papyrus).
 fn get_method_and_version(method_name: &str) -> (String, String) {
+    if let Some(method) = method_name.strip_prefix(PAPYRUS_NAMESPACE_PREFIX) {
+        return (method.to_string(), PAPYRUS_VERSION_LABEL.to_string());
+    }
     // The structure of method_name is in the following format: "starknet_V0_6_0_blockNumber".
     // Only method in this format will arrive to this point in the code.
     let last_underscore_index = method_name
@@ -24,13 +24,13 @@ use crate::rpc_metrics::{
     METHOD_LABEL,
     VERSION_LABEL,
 };
-use crate::run_server;
 use crate::test_utils::{
     get_test_highest_block,
     get_test_pending_classes,
     get_test_pending_data,
     get_test_rpc_config,
 };
+use crate::{run_server, NodeInfo};
 
 #[test]
 fn get_method_and_version_test() {
@@ -38,6 +38,10 @@ fn get_method_and_version_test() {
     let (method, version) = get_method_and_version(method_name);
     assert_eq!(method, "blockNumber");
     assert_eq!(version, "V0_6_0");
+
+    let (method, version) = get_method_and_version("papyrus_getPeers");
+    assert_eq!(method, "getPeers");
+    assert_eq!(version, "papyrus");
 }
 
 // Ignored because server_metrics test is running in parallel and we are unable to install multiple
@@ -168,6 +172,8 @@ async fn server_metrics() {
         get_test_pending_classes(),
         storage_reader,
         "NODE VERSION",
+        None,
+        NodeInfo::default(),
     )
     .await
     .unwrap();
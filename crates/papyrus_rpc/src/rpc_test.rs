@@ -1,24 +1,48 @@
 use std::error::Error as StdError;
+use std::io::Write;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
 use std::{panic, vec};
 
 use assert_matches::assert_matches;
+use async_trait::async_trait;
 use futures_util::future::join_all;
 use hyper::{header, Body, Request};
 use jsonrpsee::core::client::ClientT;
 use jsonrpsee::core::http_helpers::read_body;
 use jsonrpsee::core::{Error, RpcResult};
 use jsonrpsee::http_client::HttpClientBuilder;
+use jsonrpsee::server::ServerHandle;
 use jsonrpsee::types::ErrorObjectOwned;
+use papyrus_common::block_hash::calculate_body_commitments;
+use papyrus_common::commitment_tree::verify_proof;
+use papyrus_common::BlockHashAndNumber;
 use papyrus_storage::base_layer::BaseLayerStorageWriter;
+use papyrus_storage::body::BodyStorageWriter;
 use papyrus_storage::header::HeaderStorageWriter;
 use papyrus_storage::test_utils::get_test_storage;
+use papyrus_storage::StorageReader;
 use pretty_assertions::assert_eq;
 use rand::seq::SliceRandom;
-use starknet_api::block::{BlockHash, BlockHeader, BlockNumber, BlockStatus};
-use test_utils::get_rng;
+use serde_json::{json, Value};
+use starknet_api::block::{
+    Block,
+    BlockHash,
+    BlockHeader,
+    BlockNumber,
+    BlockStatus,
+    StarknetVersion,
+};
+use starknet_api::transaction::TransactionHash;
+use starknet_client::writer::MockStarknetWriter;
+use starknet_types_core::hash::{Poseidon, StarkHash};
+use tempfile::TempDir;
+use test_utils::{get_rng, get_test_block, read_json_file};
 use tower::BoxError;
+use tracing::Level;
+use validator::Validate;
 
-use crate::middleware::proxy_rpc_request;
+use crate::middleware::{proxy_rpc_request, REQUEST_ID_ERROR_DATA_KEY, REQUEST_ID_HEADER};
 use crate::test_utils::{
     get_test_highest_block,
     get_test_pending_classes,
@@ -26,7 +50,22 @@ use crate::test_utils::{
     get_test_rpc_config,
 };
 use crate::version_config::VERSION_CONFIG;
-use crate::{get_block_status, run_server, SERVER_MAX_BODY_SIZE};
+use crate::{
+    get_block_status,
+    run_server,
+    NetworkInfoReader,
+    NetworkStats,
+    NodeInfo,
+    NodeSyncStatus,
+    PeerInfo,
+    ReceiptProof,
+    RpcConfig,
+    SessionPoolStats,
+    SessionPoolsStats,
+    SyncMode,
+    TransactionSubmission,
+    SERVER_MAX_BODY_SIZE,
+};
 
 #[tokio::test]
 async fn run_server_no_blocks() {
@@ -42,6 +81,8 @@ async fn run_server_no_blocks() {
         pending_classes,
         storage_reader,
         "NODE VERSION",
+        None,
+        NodeInfo::default(),
     )
     .await
     .unwrap();
@@ -61,6 +102,95 @@ async fn run_server_no_blocks() {
 
 /// Given an HTTP request, using the "read_body" function from jsonrpsee library,
 /// parse the body, make sure it's a formatted JSON and within the MAX_BODY_SIZE length.
+#[tokio::test]
+async fn run_server_on_unix_socket() {
+    let ((storage_reader, _), _temp_dir) = get_test_storage();
+    let socket_dir = tempfile::tempdir().unwrap();
+    let unix_socket_path = socket_dir.path().join("rpc.sock");
+    let config = RpcConfig {
+        server_address: String::new(),
+        unix_socket_path: Some(unix_socket_path.clone()),
+        ..get_test_rpc_config()
+    };
+    let (_addr, _handle) = run_server(
+        &config,
+        get_test_highest_block(),
+        get_test_pending_data(),
+        get_test_pending_classes(),
+        storage_reader,
+        "NODE VERSION",
+        None,
+        NodeInfo::default(),
+    )
+    .await
+    .unwrap();
+
+    let stream = tokio::net::UnixStream::connect(&unix_socket_path).await.unwrap();
+    let (mut sender, connection) = hyper::client::conn::handshake(stream).await.unwrap();
+    tokio::spawn(connection);
+    let body = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "starknet_specVersion",
+        "params": [],
+    });
+    let request = Request::post("/rpc/v0_7")
+        .header(header::HOST, "localhost")
+        .header(header::CONTENT_TYPE, "application/json")
+        .body(Body::from(body.to_string()))
+        .unwrap();
+    let response = sender.send_request(request).await.unwrap();
+    let body = hyper::body::to_bytes(response.into_body()).await.unwrap();
+    let body: Value = serde_json::from_slice(&body).unwrap();
+    assert!(body.get("result").is_some(), "Unexpected response: {body}");
+}
+
+#[test]
+fn server_address_and_unix_socket_are_mutually_exclusive() {
+    let config = RpcConfig { unix_socket_path: Some("/tmp/rpc.sock".into()), ..Default::default() };
+    assert!(config.validate().is_err());
+
+    let config = RpcConfig { server_address: String::new(), ..config };
+    assert!(config.validate().is_ok());
+
+    let config = RpcConfig { unix_socket_path: None, ..config };
+    assert!(config.validate().is_err());
+}
+
+#[tokio::test]
+async fn gossipsub_submission_requires_a_p2p_writer() {
+    let ((storage_reader, _), _temp_dir) = get_test_storage();
+    let config = RpcConfig {
+        transaction_submission: TransactionSubmission::Gossipsub,
+        ..get_test_rpc_config()
+    };
+    let result = run_server(
+        &config,
+        get_test_highest_block(),
+        get_test_pending_data(),
+        get_test_pending_classes(),
+        storage_reader.clone(),
+        "NODE VERSION",
+        None,
+        NodeInfo::default(),
+    )
+    .await;
+    assert!(result.is_err());
+
+    run_server(
+        &config,
+        get_test_highest_block(),
+        get_test_pending_data(),
+        get_test_pending_classes(),
+        storage_reader,
+        "NODE VERSION",
+        Some(Arc::new(MockStarknetWriter::new())),
+        NodeInfo::default(),
+    )
+    .await
+    .unwrap();
+}
+
 async fn get_json_rpc_body(request: Request<Body>) -> Vec<u8> {
     let (res_parts, res_body) = request.into_parts();
     let (body_bytes, _is_single) =
@@ -232,3 +362,388 @@ fn serialization_precision() {
     let deserialized = serde_json::to_string(&serialized).unwrap();
     assert_eq!(input, deserialized);
 }
+
+const REQUEST_ID: &str = "my-request-1";
+
+// A log writer that keeps the logs in memory.
+#[derive(Clone, Default)]
+struct LogsBuffer(Arc<Mutex<Vec<u8>>>);
+
+impl LogsBuffer {
+    fn contents(&self) -> String {
+        String::from_utf8(self.0.lock().unwrap().clone()).unwrap()
+    }
+}
+
+impl Write for LogsBuffer {
+    fn write(&mut self, buf: &[u8]) -> std::io::Result<usize> {
+        self.0.lock().unwrap().extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> std::io::Result<()> {
+        Ok(())
+    }
+}
+
+async fn run_test_server(storage_reader: StorageReader) -> (SocketAddr, ServerHandle) {
+    run_server(
+        &get_test_rpc_config(),
+        get_test_highest_block(),
+        get_test_pending_data(),
+        get_test_pending_classes(),
+        storage_reader,
+        "NODE VERSION",
+        None,
+        NodeInfo::default(),
+    )
+    .await
+    .unwrap()
+}
+
+// Returns the request id header and the body of the response.
+async fn send_get_transaction_by_hash(
+    addr: SocketAddr,
+    request_id: Option<&str>,
+    transaction_hash: TransactionHash,
+) -> (String, Value) {
+    let body = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": "starknet_getTransactionByHash",
+        "params": [transaction_hash],
+    });
+    let mut request = reqwest::Client::new()
+        .post(format!("http://{addr}/rpc/v0_7"))
+        .header(header::CONTENT_TYPE.as_str(), "application/json")
+        .body(body.to_string());
+    if let Some(request_id) = request_id {
+        request = request.header(REQUEST_ID_HEADER, request_id);
+    }
+    let response = request.send().await.unwrap();
+    let response_request_id = response.headers()[REQUEST_ID_HEADER].to_str().unwrap().to_owned();
+    (response_request_id, serde_json::from_str(&response.text().await.unwrap()).unwrap())
+}
+
+#[tokio::test]
+async fn request_id_in_storage_logs() {
+    let ((storage_reader, mut storage_writer), _temp_dir) = get_test_storage();
+    let block = get_test_block(1, None, None, None);
+    storage_writer
+        .begin_rw_txn()
+        .unwrap()
+        .append_header(BlockNumber(0), &block.header)
+        .unwrap()
+        .append_body(BlockNumber(0), block.body.clone())
+        .unwrap()
+        .commit()
+        .unwrap();
+    let (addr, _handle) = run_test_server(storage_reader).await;
+
+    // The test runs on a single thread, so the server logs to this subscriber as well.
+    let logs = LogsBuffer::default();
+    let logs_writer = logs.clone();
+    let subscriber = tracing_subscriber::fmt()
+        .with_max_level(Level::TRACE)
+        .with_ansi(false)
+        .with_writer(move || logs_writer.clone())
+        .finish();
+    let _guard = tracing::subscriber::set_default(subscriber);
+
+    let (response_request_id, response) =
+        send_get_transaction_by_hash(addr, Some(REQUEST_ID), block.body.transaction_hashes[0])
+            .await;
+    assert!(response.get("result").is_some(), "Unexpected response: {response}");
+    assert_eq!(response_request_id, REQUEST_ID);
+
+    // The transaction is read from a file by the storage, deep inside the handling of the request.
+    let logs = logs.contents();
+    let storage_log = logs
+        .lines()
+        .find(|line| line.contains("Reading object at location"))
+        .unwrap_or_else(|| panic!("No log of the storage access, got: {logs}"));
+    assert!(storage_log.contains(&format!("request_id={REQUEST_ID}")), "{storage_log}");
+}
+
+#[tokio::test]
+async fn request_id_in_error_response() {
+    let ((storage_reader, _), _temp_dir) = get_test_storage();
+    let (addr, _handle) = run_test_server(storage_reader).await;
+    let unknown_transaction_hash = TransactionHash::default();
+
+    let (response_request_id, response) =
+        send_get_transaction_by_hash(addr, Some(REQUEST_ID), unknown_transaction_hash).await;
+    assert_eq!(response_request_id, REQUEST_ID);
+    assert_eq!(response["error"]["code"], 29);
+    assert_eq!(response["error"]["data"], json!({ REQUEST_ID_ERROR_DATA_KEY: REQUEST_ID }));
+
+    // Invalid and missing ids are replaced by generated ones.
+    for request_id in [Some("not a valid id"), None] {
+        let (response_request_id, response) =
+            send_get_transaction_by_hash(addr, request_id, unknown_transaction_hash).await;
+        assert_ne!(Some(response_request_id.as_str()), request_id);
+        assert!(!response_request_id.is_empty());
+        assert_eq!(response["error"]["data"][REQUEST_ID_ERROR_DATA_KEY], response_request_id);
+    }
+}
+
+// A network with one connected peer that queried the node and one blocked peer.
+struct TestNetworkInfoReader;
+
+#[async_trait]
+impl NetworkInfoReader for TestNetworkInfoReader {
+    async fn get_peers(&self) -> anyhow::Result<Vec<PeerInfo>> {
+        Ok(vec![
+            PeerInfo {
+                peer_id: "peer1".to_owned(),
+                addresses: vec!["/ip4/127.0.0.1/tcp/10000".to_owned()],
+                protocols: vec!["/starknet/headers/0.1.0-rc.0".to_owned()],
+                agent_version: Some("papyrus".to_owned()),
+                num_connections: 2,
+                served_bytes: 100,
+                ..Default::default()
+            },
+            PeerInfo { peer_id: "peer2".to_owned(), blocked: true, ..Default::default() },
+        ])
+    }
+
+    fn get_session_pools(&self) -> SessionPoolsStats {
+        SessionPoolsStats {
+            inbound: SessionPoolStats { num_active_sessions: 3, max_sessions: 100 },
+            outbound: SessionPoolStats { num_active_sessions: 10, max_sessions: 10 },
+        }
+    }
+}
+
+async fn send_papyrus_request(addr: SocketAddr, method: &str) -> Value {
+    send_papyrus_request_with_params(addr, method, json!([])).await
+}
+
+async fn send_papyrus_request_with_params(addr: SocketAddr, method: &str, params: Value) -> Value {
+    let body = json!({
+        "jsonrpc": "2.0",
+        "id": 1,
+        "method": method,
+        "params": params,
+    });
+    let response = reqwest::Client::new()
+        .post(format!("http://{addr}/rpc/v0_7"))
+        .header(header::CONTENT_TYPE.as_str(), "application/json")
+        .body(body.to_string())
+        .send()
+        .await
+        .unwrap();
+    serde_json::from_str(&response.text().await.unwrap()).unwrap()
+}
+
+#[tokio::test]
+async fn papyrus_namespace_is_disabled_by_default() {
+    let ((storage_reader, _), _temp_dir) = get_test_storage();
+    let (addr, _handle) = run_test_server(storage_reader).await;
+
+    let response = send_papyrus_request(addr, "papyrus_getSyncStatus").await;
+    assert_eq!(response["error"]["code"], -32601, "Unexpected response: {response}");
+}
+
+#[tokio::test]
+async fn papyrus_namespace() {
+    let ((storage_reader, mut storage_writer), _temp_dir) = get_test_storage();
+    storage_writer
+        .begin_rw_txn()
+        .unwrap()
+        .append_header(BlockNumber(0), &BlockHeader::default())
+        .unwrap()
+        .commit()
+        .unwrap();
+    let shared_highest_block = get_test_highest_block();
+    let highest_block =
+        BlockHashAndNumber { block_hash: BlockHash::default(), block_number: BlockNumber(5) };
+    *shared_highest_block.write().await = Some(highest_block);
+    let config = RpcConfig { enable_papyrus_namespace: true, ..get_test_rpc_config() };
+    let (addr, _handle) = run_server(
+        &config,
+        shared_highest_block,
+        get_test_pending_data(),
+        get_test_pending_classes(),
+        storage_reader,
+        "NODE VERSION",
+        None,
+        NodeInfo {
+            sync_mode: SyncMode::P2p,
+            network_info_reader: Some(Arc::new(TestNetworkInfoReader)),
+        },
+    )
+    .await
+    .unwrap();
+
+    let response = send_papyrus_request(addr, "papyrus_getSyncStatus").await;
+    let sync_status: NodeSyncStatus = serde_json::from_value(response["result"].clone()).unwrap();
+    assert_eq!(
+        sync_status,
+        NodeSyncStatus {
+            mode: SyncMode::P2p,
+            header_marker: BlockNumber(1),
+            highest_known_block: Some(highest_block),
+            ..Default::default()
+        }
+    );
+
+    let response = send_papyrus_request(addr, "papyrus_getPeers").await;
+    let peers: Vec<PeerInfo> = serde_json::from_value(response["result"].clone()).unwrap();
+    assert_eq!(peers, TestNetworkInfoReader.get_peers().await.unwrap());
+
+    let response = send_papyrus_request(addr, "papyrus_getNetworkStats").await;
+    let network_stats: NetworkStats = serde_json::from_value(response["result"].clone()).unwrap();
+    assert_eq!(
+        network_stats,
+        NetworkStats {
+            num_known_peers: 2,
+            num_connected_peers: 1,
+            num_connections: 2,
+            num_blocked_peers: 1,
+            served_bytes: 100,
+            sessions: TestNetworkInfoReader.get_session_pools(),
+        }
+    );
+}
+
+#[tokio::test]
+async fn papyrus_network_methods_without_network() {
+    let ((storage_reader, _), _temp_dir) = get_test_storage();
+    let config = RpcConfig { enable_papyrus_namespace: true, ..get_test_rpc_config() };
+    let (addr, _handle) = run_server(
+        &config,
+        get_test_highest_block(),
+        get_test_pending_data(),
+        get_test_pending_classes(),
+        storage_reader,
+        "NODE VERSION",
+        None,
+        NodeInfo::default(),
+    )
+    .await
+    .unwrap();
+
+    for method in ["papyrus_getPeers", "papyrus_getNetworkStats"] {
+        let response = send_papyrus_request(addr, method).await;
+        assert_eq!(response["error"]["code"], -32603, "Unexpected response: {response}");
+    }
+    // The sync status doesn't depend on the network.
+    let response = send_papyrus_request(addr, "papyrus_getSyncStatus").await;
+    assert!(response.get("result").is_some(), "Unexpected response: {response}");
+}
+
+// Runs a server with the papyrus namespace over a storage that holds the given block as block 0.
+async fn run_papyrus_server_with_block(block: &Block) -> (SocketAddr, ServerHandle, TempDir) {
+    let ((storage_reader, mut storage_writer), temp_dir) = get_test_storage();
+    storage_writer
+        .begin_rw_txn()
+        .unwrap()
+        .append_header(BlockNumber(0), &block.header)
+        .unwrap()
+        .append_body(BlockNumber(0), block.body.clone())
+        .unwrap()
+        .commit()
+        .unwrap();
+    let config = RpcConfig { enable_papyrus_namespace: true, ..get_test_rpc_config() };
+    let (addr, handle) = run_server(
+        &config,
+        get_test_highest_block(),
+        get_test_pending_data(),
+        get_test_pending_classes(),
+        storage_reader,
+        "NODE VERSION",
+        None,
+        NodeInfo::default(),
+    )
+    .await
+    .unwrap();
+    (addr, handle, temp_dir)
+}
+
+// A block from before Starknet 0.13.2, from papyrus_common's block hash fixtures.
+fn get_block_hash_fixture() -> Block {
+    let mut block: Block =
+        serde_json::from_value(read_json_file("../../papyrus_common/resources/block_hash.json"))
+            .unwrap();
+    block.header.block_number = BlockNumber(0);
+    block
+}
+
+#[tokio::test]
+async fn papyrus_get_receipt_proof() {
+    // The fixture turned into a 0.13.2 block, whose commitments are calculated by starknet_api.
+    let mut block = get_block_hash_fixture();
+    block.header.starknet_version = StarknetVersion("0.13.2".to_owned());
+    let commitments = calculate_body_commitments(&block.body);
+    block.header.transaction_commitment = Some(commitments.transaction_commitment);
+    block.header.event_commitment = Some(commitments.event_commitment);
+    block.header.receipt_commitment = Some(commitments.receipt_commitment);
+    let (addr, _handle, _temp_dir) = run_papyrus_server_with_block(&block).await;
+
+    for offset in [0, 1, block.body.transaction_hashes.len() - 1] {
+        let transaction_hash = block.body.transaction_hashes[offset];
+        let response = send_papyrus_request_with_params(
+            addr,
+            "papyrus_getReceiptProof",
+            json!([transaction_hash]),
+        )
+        .await;
+        let proof: ReceiptProof = serde_json::from_value(response["result"].clone())
+            .unwrap_or_else(|_| panic!("Unexpected response: {response}"));
+
+        assert_eq!(proof.block_hash, block.header.block_hash);
+        assert_eq!(proof.block_number, BlockNumber(0));
+        assert_eq!(proof.receipt_commitment, commitments.receipt_commitment);
+        assert_eq!(proof.event_commitment, commitments.event_commitment);
+
+        let receipt = &proof.receipt;
+        assert_eq!(receipt.index, offset);
+        assert_eq!(receipt.transaction_hash, transaction_hash);
+        assert_eq!(receipt.leaf_preimage[0], transaction_hash.0);
+        assert_eq!(receipt.leaf, Poseidon::hash_array(&receipt.leaf_preimage));
+        assert!(verify_proof::<Poseidon>(
+            proof.receipt_commitment.0,
+            receipt.index,
+            receipt.leaf,
+            &receipt.path
+        ));
+
+        let events = block.body.transaction_outputs[offset].events();
+        assert_eq!(proof.events.len(), events.len());
+        for (event, event_proof) in events.iter().zip(&proof.events) {
+            assert_eq!(event_proof.from_address, event.from_address);
+            assert_eq!(event_proof.keys, event.content.keys);
+            assert_eq!(event_proof.data, event.content.data);
+            assert!(verify_proof::<Poseidon>(
+                proof.event_commitment.0,
+                event_proof.index,
+                event_proof.leaf,
+                &event_proof.path
+            ));
+        }
+    }
+}
+
+#[tokio::test]
+async fn papyrus_get_receipt_proof_errors() {
+    let block = get_block_hash_fixture();
+    let (addr, _handle, _temp_dir) = run_papyrus_server_with_block(&block).await;
+
+    // The block is from before Starknet 0.13.2, so it has no receipt commitment.
+    let response = send_papyrus_request_with_params(
+        addr,
+        "papyrus_getReceiptProof",
+        json!([block.body.transaction_hashes[0]]),
+    )
+    .await;
+    assert_eq!(response["error"]["code"], 10000, "Unexpected response: {response}");
+
+    let response = send_papyrus_request_with_params(
+        addr,
+        "papyrus_getReceiptProof",
+        json!([TransactionHash::default()]),
+    )
+    .await;
+    assert_eq!(response["error"]["code"], 29, "Unexpected response: {response}");
+}
@@ -17,7 +17,7 @@ use serde::{Deserialize, Serialize};
 use serde_json::{Map, Value};
 use starknet_api::core::{ChainId, ContractAddress, PatriciaKey};
 use starknet_api::{contract_address, felt, patricia_key};
-use starknet_client::reader::PendingData;
+use starknet_client::reader::{MockStarknetReader, PendingData};
 use starknet_client::writer::MockStarknetWriter;
 use strum::IntoEnumIterator;
 use strum_macros::EnumIter;
@@ -66,8 +66,29 @@ pub(crate) fn get_test_rpc_server_and_storage_writer_from_params<T: JsonRpcServe
     pending_data: Option<Arc<RwLock<PendingData>>>,
     pending_classes: Option<Arc<RwLock<PendingClasses>>>,
     storage_scope: Option<StorageScope>,
+) -> (RpcModule<T>, StorageWriter) {
+    get_test_rpc_server_and_storage_writer_from_params_and_reader(
+        mock_client,
+        None,
+        shared_highest_block,
+        pending_data,
+        pending_classes,
+        storage_scope,
+    )
+}
+
+pub(crate) fn get_test_rpc_server_and_storage_writer_from_params_and_reader<
+    T: JsonRpcServerTrait,
+>(
+    mock_client: Option<MockStarknetWriter>,
+    mock_reader: Option<MockStarknetReader>,
+    shared_highest_block: Option<Arc<RwLock<Option<BlockHashAndNumber>>>>,
+    pending_data: Option<Arc<RwLock<PendingData>>>,
+    pending_classes: Option<Arc<RwLock<PendingClasses>>>,
+    storage_scope: Option<StorageScope>,
 ) -> (RpcModule<T>, StorageWriter) {
     let mock_client = mock_client.unwrap_or_default();
+    let mock_reader = mock_reader.unwrap_or_default();
     let shared_highest_block = shared_highest_block.unwrap_or(get_test_highest_block());
     let pending_data = pending_data.unwrap_or(get_test_pending_data());
     let pending_classes = pending_classes.unwrap_or(get_test_pending_classes());
@@ -88,6 +109,7 @@ pub(crate) fn get_test_rpc_server_and_storage_writer_from_params<T: JsonRpcServe
             pending_data,
             pending_classes,
             mock_client_arc,
+            Arc::new(mock_reader),
         )
         .into_rpc_module(),
         storage_writer,
@@ -190,6 +190,7 @@ use crate::{
     internal_server_error_with_msg,
     run_server,
     ContinuationTokenAsStruct,
+    NodeInfo,
     GENESIS_HASH,
 };
 
@@ -3160,6 +3161,8 @@ async fn serialize_returns_valid_json() {
         get_test_pending_classes(),
         storage_reader,
         NODE_VERSION,
+        None,
+        NodeInfo::default(),
     )
     .await
     .unwrap();
@@ -857,6 +857,7 @@ async fn trace_block_transactions_regular_and_pending() {
                 ..Default::default()
             },
         },
+        ..Default::default()
     };
 
     let (module, storage_writer) = get_test_rpc_server_and_storage_writer_from_params::<
@@ -1105,6 +1106,7 @@ async fn pending_trace_block_transactions_and_trace_transaction_execution_contex
                 ..Default::default()
             },
         },
+        ..Default::default()
     };
 
     let (module, storage_writer) = get_test_rpc_server_and_storage_writer_from_params::<
@@ -1538,6 +1540,7 @@ async fn write_block_0_as_pending(
                 replaced_classes: vec![],
             },
         },
+        ..Default::default()
     }
 }
 
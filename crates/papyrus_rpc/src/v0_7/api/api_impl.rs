@@ -5,7 +5,7 @@ use jsonrpsee::core::RpcResult;
 use jsonrpsee::types::ErrorObjectOwned;
 use jsonrpsee::RpcModule;
 use lazy_static::lazy_static;
-use papyrus_common::pending_classes::{PendingClasses, PendingClassesTrait};
+use papyrus_common::pending_classes::PendingClasses;
 use papyrus_execution::objects::{FeeEstimation, PendingData as ExecutionPendingData};
 use papyrus_execution::{
     estimate_fee as exec_estimate_fee,
@@ -43,7 +43,7 @@ use starknet_client::reader::objects::transaction::{
     Transaction as ClientTransaction,
     TransactionReceipt as ClientTransactionReceipt,
 };
-use starknet_client::reader::PendingData;
+use starknet_client::reader::{PendingData, StarknetReader};
 use starknet_client::writer::{StarknetWriter, WriterClientError};
 use starknet_client::ClientError;
 use starknet_types_core::felt::Felt;
@@ -128,13 +128,18 @@ use super::{
     TransactionTraceWithHash,
 };
 use crate::api::{BlockHashOrNumber, JsonRpcServerTrait, Tag};
-use crate::pending::client_pending_data_to_execution_pending_data;
+use crate::pending::{
+    client_pending_data_to_execution_pending_data,
+    get_pending_class,
+    read_pending_classes,
+};
 use crate::syncing_state::{get_last_synced_block, SyncStatus, SyncingState};
 use crate::version_config::VERSION_0_7 as VERSION;
 use crate::{
     get_block_status,
     get_latest_block_number,
     internal_server_error,
+    internal_server_error_with_msg,
     verify_storage_scope,
     ContinuationTokenAsStruct,
     GENESIS_HASH,
@@ -159,6 +164,7 @@ pub struct JsonRpcServerImpl {
     pub pending_data: Arc<RwLock<PendingData>>,
     pub pending_classes: Arc<RwLock<PendingClasses>>,
     pub writer_client: Arc<dyn StarknetWriter>,
+    pub starknet_reader: Arc<dyn StarknetReader + Send + Sync>,
 }
 
 #[async_trait]
@@ -579,9 +585,11 @@ impl JsonRpcServer for JsonRpcServerImpl {
         class_hash: ClassHash,
     ) -> RpcResult<GatewayContractClass> {
         let block_id = if let BlockId::Tag(Tag::Pending) = block_id {
-            let maybe_class = &self.pending_classes.read().await.get_class(class_hash);
+            let maybe_class =
+                get_pending_class(&self.pending_classes, self.starknet_reader.as_ref(), class_hash)
+                    .await?;
             if let Some(class) = maybe_class {
-                return class.clone().try_into().map_err(internal_server_error);
+                return class.try_into().map_err(internal_server_error);
             } else {
                 BlockId::Tag(Tag::Latest)
             }
@@ -747,7 +755,13 @@ impl JsonRpcServer for JsonRpcServerImpl {
         if start_event_index.0.0 <= latest_block_number {
             for ((from_address, event_index), content) in txn
                 .iter_events(filter.address, start_event_index, to_block_number)
-                .map_err(internal_server_error)?
+                .map_err(|err| match err {
+                    // The spec has no error for a storage that doesn't index all the events.
+                    err @ StorageError::EventsInColdStorage { .. } => {
+                        internal_server_error_with_msg(err)
+                    }
+                    err => internal_server_error(err),
+                })?
             {
                 let block_number = (event_index.0).0;
                 if block_number > to_block_number {
@@ -866,9 +880,16 @@ impl JsonRpcServer for JsonRpcServerImpl {
     async fn call(&self, request: CallRequest, block_id: BlockId) -> RpcResult<Vec<Felt>> {
         let txn = self.storage_reader.begin_ro_txn().map_err(internal_server_error)?;
         let maybe_pending_data = if let BlockId::Tag(Tag::Pending) = block_id {
+            let client_pending_data = read_pending_data(&self.pending_data, &txn).await?;
+            let pending_classes = read_pending_classes(
+                &self.pending_classes,
+                self.starknet_reader.as_ref(),
+                &client_pending_data,
+            )
+            .await?;
             Some(client_pending_data_to_execution_pending_data(
-                read_pending_data(&self.pending_data, &txn).await?,
-                self.pending_classes.read().await.clone(),
+                client_pending_data,
+                pending_classes,
             ))
         } else {
             None
@@ -972,9 +993,16 @@ impl JsonRpcServer for JsonRpcServerImpl {
         let storage_txn = self.storage_reader.begin_ro_txn().map_err(internal_server_error)?;
 
         let maybe_pending_data = if let BlockId::Tag(Tag::Pending) = block_id {
+            let client_pending_data = read_pending_data(&self.pending_data, &storage_txn).await?;
+            let pending_classes = read_pending_classes(
+                &self.pending_classes,
+                self.starknet_reader.as_ref(),
+                &client_pending_data,
+            )
+            .await?;
             Some(client_pending_data_to_execution_pending_data(
-                read_pending_data(&self.pending_data, &storage_txn).await?,
-                self.pending_classes.read().await.clone(),
+                client_pending_data,
+                pending_classes,
             ))
         } else {
             None
@@ -1039,9 +1067,16 @@ impl JsonRpcServer for JsonRpcServerImpl {
         let storage_txn = self.storage_reader.begin_ro_txn().map_err(internal_server_error)?;
 
         let maybe_pending_data = if let BlockId::Tag(Tag::Pending) = block_id {
+            let client_pending_data = read_pending_data(&self.pending_data, &storage_txn).await?;
+            let pending_classes = read_pending_classes(
+                &self.pending_classes,
+                self.starknet_reader.as_ref(),
+                &client_pending_data,
+            )
+            .await?;
             Some(client_pending_data_to_execution_pending_data(
-                read_pending_data(&self.pending_data, &storage_txn).await?,
-                self.pending_classes.read().await.clone(),
+                client_pending_data,
+                pending_classes,
             ))
         } else {
             None
@@ -1367,9 +1402,16 @@ impl JsonRpcServer for JsonRpcServerImpl {
         trace!("Estimating fee of message: {:#?}", message);
         let storage_txn = self.storage_reader.begin_ro_txn().map_err(internal_server_error)?;
         let maybe_pending_data = if let BlockId::Tag(Tag::Pending) = block_id {
+            let client_pending_data = read_pending_data(&self.pending_data, &storage_txn).await?;
+            let pending_classes = read_pending_classes(
+                &self.pending_classes,
+                self.starknet_reader.as_ref(),
+                &client_pending_data,
+            )
+            .await?;
             Some(client_pending_data_to_execution_pending_data(
-                read_pending_data(&self.pending_data, &storage_txn).await?,
-                self.pending_classes.read().await.clone(),
+                client_pending_data,
+                pending_classes,
             ))
         } else {
             None
@@ -1482,7 +1524,13 @@ async fn read_pending_data<Mode: TransactionKind>(
         },
     };
     let pending_data = &pending_data.read().await;
-    if pending_data.block.parent_block_hash() == latest_header.block_hash {
+    // Pending data that was never fetched isn't stale. It's replaced below if it doesn't match the
+    // latest block.
+    let is_stale = pending_data.freshness.is_some_and(|freshness| freshness.is_stale());
+    if is_stale {
+        trace!("The pending data is stale. Treating the pending block as empty.");
+    }
+    if pending_data.block.parent_block_hash() == latest_header.block_hash && !is_stale {
         Ok((*pending_data).clone())
     } else {
         Ok(PendingData {
@@ -1499,6 +1547,7 @@ async fn read_pending_data<Mode: TransactionKind>(
                 old_root: latest_header.state_root,
                 state_diff: Default::default(),
             },
+            freshness: None,
         })
     }
 }
@@ -1630,6 +1679,7 @@ impl JsonRpcServerTrait for JsonRpcServerImpl {
         pending_data: Arc<RwLock<PendingData>>,
         pending_classes: Arc<RwLock<PendingClasses>>,
         writer_client: Arc<dyn StarknetWriter>,
+        starknet_reader: Arc<dyn StarknetReader + Send + Sync>,
     ) -> Self {
         Self {
             chain_id,
@@ -1642,6 +1692,7 @@ impl JsonRpcServerTrait for JsonRpcServerImpl {
             pending_data,
             pending_classes,
             writer_client,
+            starknet_reader,
         }
     }
 
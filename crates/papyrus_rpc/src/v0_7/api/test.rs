@@ -3,6 +3,7 @@ use std::fmt::Debug;
 use std::iter;
 use std::net::SocketAddr;
 use std::ops::Index;
+use std::time::{Duration, Instant};
 
 use assert_matches::assert_matches;
 use async_trait::async_trait;
@@ -74,6 +75,7 @@ use starknet_api::{felt, patricia_key};
 use starknet_client::reader::objects::pending_data::{
     DeprecatedPendingBlock,
     PendingBlockOrDeprecated,
+    PendingDataFreshness,
     PendingStateUpdate as ClientPendingStateUpdate,
 };
 use starknet_client::reader::objects::state::{
@@ -87,6 +89,7 @@ use starknet_client::reader::objects::transaction::{
     Transaction as ClientTransaction,
     TransactionReceipt as ClientTransactionReceipt,
 };
+use starknet_client::reader::{GenericContractClass, MockStarknetReader};
 use starknet_client::starknet_error::{KnownStarknetErrorCode, StarknetError, StarknetErrorCode};
 use starknet_client::writer::objects::response::{
     DeclareResponse,
@@ -187,6 +190,7 @@ use crate::test_utils::{
     get_test_rpc_config,
     get_test_rpc_server_and_storage_writer,
     get_test_rpc_server_and_storage_writer_from_params,
+    get_test_rpc_server_and_storage_writer_from_params_and_reader,
     method_name_to_spec_method_name,
     raw_call,
     validate_schema,
@@ -199,6 +203,7 @@ use crate::{
     internal_server_error_with_msg,
     run_server,
     ContinuationTokenAsStruct,
+    NodeInfo,
     GENESIS_HASH,
 };
 
@@ -443,6 +448,19 @@ async fn get_block_transaction_count() {
     let res = module.call::<_, usize>(method_name, [BlockId::Tag(Tag::Pending)]).await.unwrap();
     assert_eq!(res, pending_transaction_count);
 
+    // Ask for pending block when it wasn't fetched for longer than the max staleness.
+    pending_data.write().await.freshness = Some(PendingDataFreshness {
+        last_updated: Instant::now() - Duration::from_secs(2),
+        max_staleness: Duration::from_secs(1),
+    });
+    let res = module.call::<_, usize>(method_name, [BlockId::Tag(Tag::Pending)]).await.unwrap();
+    assert_eq!(res, 0);
+
+    // Ask for pending block when it was fetched recently.
+    pending_data.write().await.freshness = Some(PendingDataFreshness::new(Duration::from_secs(60)));
+    let res = module.call::<_, usize>(method_name, [BlockId::Tag(Tag::Pending)]).await.unwrap();
+    assert_eq!(res, pending_transaction_count);
+
     // Ask for pending block when it's not up to date.
     *pending_data.write().await.block.parent_block_hash_mutable() =
         BlockHash(random::<u64>().into());
@@ -1425,6 +1443,45 @@ async fn get_transaction_receipt() {
     .await;
 }
 
+#[tokio::test]
+async fn get_evicted_pending_class() {
+    let method_name = "starknet_V0_7_getClass";
+    let pending_class_hash = ClassHash(felt!("0x2"));
+    let pending_class = StarknetApiDeprecatedContractClass::get_test_instance(&mut get_rng());
+    let pending_classes = get_test_pending_classes();
+    {
+        let mut pending_classes = pending_classes.write().await;
+        pending_classes.add_class(
+            pending_class_hash,
+            ApiContractClass::DeprecatedContractClass(pending_class.clone()),
+        );
+        pending_classes.evict_oldest(0);
+    }
+    let mut mock_reader = MockStarknetReader::new();
+    let fetched_class = pending_class.clone();
+    mock_reader.expect_class_by_hash().with(eq(pending_class_hash)).times(1).returning(move |_| {
+        Ok(Some(GenericContractClass::Cairo0ContractClass(fetched_class.clone())))
+    });
+    let (module, _storage_writer) =
+        get_test_rpc_server_and_storage_writer_from_params_and_reader::<JsonRpcServerImpl>(
+            None,
+            Some(mock_reader),
+            None,
+            None,
+            Some(pending_classes),
+            None,
+        );
+
+    let res = module
+        .call::<_, GatewayContractClass>(
+            method_name,
+            (BlockId::Tag(Tag::Pending), pending_class_hash),
+        )
+        .await
+        .unwrap();
+    assert_eq!(res, ApiContractClass::DeprecatedContractClass(pending_class).try_into().unwrap());
+}
+
 #[tokio::test]
 async fn get_class_at() {
     let method_name = "starknet_V0_7_getClassAt";
@@ -3360,6 +3417,8 @@ async fn serialize_returns_valid_json() {
         get_test_pending_classes(),
         storage_reader,
         NODE_VERSION,
+        None,
+        NodeInfo::default(),
     )
     .await
     .unwrap();
@@ -893,6 +893,7 @@ async fn trace_block_transactions_regular_and_pending() {
                 ..Default::default()
             },
         },
+        ..Default::default()
     };
 
     let (module, storage_writer) = get_test_rpc_server_and_storage_writer_from_params::<
@@ -1142,6 +1143,7 @@ async fn pending_trace_block_transactions_and_trace_transaction_execution_contex
                 ..Default::default()
             },
         },
+        ..Default::default()
     };
 
     let (module, storage_writer) = get_test_rpc_server_and_storage_writer_from_params::<
@@ -1624,6 +1626,7 @@ async fn write_block_0_as_pending(
                 replaced_classes: vec![],
             },
         },
+        ..Default::default()
     }
 }
 
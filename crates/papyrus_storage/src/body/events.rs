@@ -62,10 +62,11 @@ use starknet_api::transaction::{
 
 use super::TransactionMetadataTable;
 use crate::body::{EventsTableKey, TransactionIndex};
+use crate::cold_storage::ColdStorageReader;
 use crate::db::serialization::{NoVersionValueWrapper, VersionZeroWrapper};
 use crate::db::table_types::{CommonPrefix, DbCursor, DbCursorTrait, NoValue, SimpleTable, Table};
 use crate::db::{DbTransaction, RO};
-use crate::{FileHandlers, StorageResult, StorageTxn, TransactionMetadata};
+use crate::{FileHandlers, StorageError, StorageResult, StorageTxn, TransactionMetadata};
 
 /// An identifier of an event.
 #[derive(Debug, Copy, Clone, Eq, PartialEq, Deserialize, Serialize, PartialOrd, Ord)]
@@ -84,7 +85,10 @@ pub trait EventsReader<'txn, 'env> {
     /// * to_block_number - block number to stop iterate at it.
     ///
     /// # Errors
-    /// Returns [`StorageError`](crate::StorageError) if there was an error.
+    /// Returns [`StorageError::EventsInColdStorage`](crate::StorageError::EventsInColdStorage) if
+    /// the event index is in a block that was moved to the cold storage, since the events of these
+    /// blocks aren't indexed.
+    /// Returns [`StorageError`](crate::StorageError) if there was another error.
     fn iter_events(
         &'env self,
         address: Option<ContractAddress>,
@@ -101,6 +105,11 @@ impl<'txn, 'env> EventsReader<'txn, 'env> for StorageTxn<'env, RO> {
         event_index: EventIndex,
         to_block_number: BlockNumber,
     ) -> StorageResult<EventIter<'txn, 'env>> {
+        let cold_storage_marker = self.get_cold_storage_marker()?;
+        let block_number = event_index.0.0;
+        if block_number < cold_storage_marker {
+            return Err(StorageError::EventsInColdStorage { block_number, cold_storage_marker });
+        }
         if let Some(address) = optional_address {
             return Ok(EventIter::ByContractAddress(
                 self.iter_events_by_contract_address((address, event_index))?,
@@ -170,6 +170,11 @@ impl<'env, Mode: TransactionKind> BodyStorageReader for StorageTxn<'env, Mode> {
         &self,
         transaction_index: TransactionIndex,
     ) -> StorageResult<Option<Transaction>> {
+        if let Some((transaction, _transaction_output)) =
+            self.get_cold_transaction(transaction_index)?
+        {
+            return Ok(Some(transaction));
+        }
         let transaction_metadata_table = self.open_table(&self.tables.transaction_metadata)?;
         let Some(tx_metadata) = transaction_metadata_table.get(&self.txn, &transaction_index)?
         else {
@@ -183,6 +188,11 @@ impl<'env, Mode: TransactionKind> BodyStorageReader for StorageTxn<'env, Mode> {
         &self,
         transaction_index: TransactionIndex,
     ) -> StorageResult<Option<TransactionOutput>> {
+        if let Some((_transaction, transaction_output)) =
+            self.get_cold_transaction(transaction_index)?
+        {
+            return Ok(Some(transaction_output));
+        }
         let transaction_metadata_table = self.open_table(&self.tables.transaction_metadata)?;
         let Some(tx_metadata) = transaction_metadata_table.get(&self.txn, &transaction_index)?
         else {
@@ -209,7 +219,10 @@ impl<'env, Mode: TransactionKind> BodyStorageReader for StorageTxn<'env, Mode> {
     ) -> StorageResult<Option<TransactionHash>> {
         let transaction_metadata_table = self.open_table(&self.tables.transaction_metadata)?;
         let Some(tx_metadata) = transaction_metadata_table.get(&self.txn, tx_index)? else {
-            return Ok(None);
+            let TransactionIndex(block_number, TransactionOffsetInBlock(offset)) = *tx_index;
+            return Ok(self
+                .get_cold_block(block_number)?
+                .and_then(|block| block.transaction_hashes.into_iter().nth(offset)));
         };
         Ok(Some(tx_metadata.tx_hash))
     }
@@ -218,6 +231,11 @@ impl<'env, Mode: TransactionKind> BodyStorageReader for StorageTxn<'env, Mode> {
         &self,
         block_number: BlockNumber,
     ) -> StorageResult<Option<Vec<Transaction>>> {
+        if let Some(block) = self.get_cold_block(block_number)? {
+            return Ok(Some(
+                block.transactions.into_iter().map(|(transaction, _)| transaction).collect(),
+            ));
+        }
         let transaction_metadata_table = self.open_table(&self.tables.transaction_metadata)?;
         self.get_transactions_in_block(block_number, transaction_metadata_table)
     }
@@ -226,6 +244,9 @@ impl<'env, Mode: TransactionKind> BodyStorageReader for StorageTxn<'env, Mode> {
         &self,
         block_number: BlockNumber,
     ) -> StorageResult<Option<Vec<TransactionHash>>> {
+        if let Some(block) = self.get_cold_block(block_number)? {
+            return Ok(Some(block.transaction_hashes));
+        }
         let transaction_metadata_table = self.open_table(&self.tables.transaction_metadata)?;
         self.get_transaction_hashes_in_block(block_number, transaction_metadata_table)
     }
@@ -234,6 +255,15 @@ impl<'env, Mode: TransactionKind> BodyStorageReader for StorageTxn<'env, Mode> {
         &self,
         block_number: BlockNumber,
     ) -> StorageResult<Option<Vec<TransactionOutput>>> {
+        if let Some(block) = self.get_cold_block(block_number)? {
+            return Ok(Some(
+                block
+                    .transactions
+                    .into_iter()
+                    .map(|(_, transaction_output)| transaction_output)
+                    .collect(),
+            ));
+        }
         let transaction_metadata_table = self.open_table(&self.tables.transaction_metadata)?;
         self.get_transaction_outputs_in_block(block_number, transaction_metadata_table)
     }
@@ -247,6 +277,9 @@ impl<'env, Mode: TransactionKind> BodyStorageReader for StorageTxn<'env, Mode> {
         if self.get_body_marker()? <= block_number {
             return Ok(None);
         }
+        if let Some(block) = self.get_cold_block(block_number)? {
+            return Ok(Some(block.transactions.len()));
+        }
 
         let transaction_metadata_table = self.open_table(&self.tables.transaction_metadata)?;
         let mut cursor = transaction_metadata_table.cursor(&self.txn)?;
@@ -269,6 +302,16 @@ impl<'env, Mode: TransactionKind> BodyStorageReader for StorageTxn<'env, Mode> {
 }
 
 impl<'env, Mode: TransactionKind> StorageTxn<'env, Mode> {
+    // Returns the transaction and its output if its block was moved to the cold storage.
+    fn get_cold_transaction(
+        &self,
+        TransactionIndex(block_number, TransactionOffsetInBlock(offset)): TransactionIndex,
+    ) -> StorageResult<Option<(Transaction, TransactionOutput)>> {
+        Ok(self
+            .get_cold_block(block_number)?
+            .and_then(|block| block.transactions.into_iter().nth(offset)))
+    }
+
     // Returns a vector with transaction objects (can be tx hash for example).
     fn get_vector_of_transaction_objects<T>(
         &self,
@@ -383,6 +426,7 @@ impl<'env> BodyStorageWriter for StorageTxn<'env, RW> {
             );
             return Ok((self, None));
         }
+        self.verify_block_is_hot(block_number)?;
 
         let reverted_block_body = 'reverted_block_body: {
             if self.scope == StorageScope::StateOnly {
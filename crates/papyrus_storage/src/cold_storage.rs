@@ -0,0 +1,658 @@
+//! Interface for moving old blocks out of the database into append-only cold files, and for
+//! reading them back.
+//!
+//! A block is moved to the cold storage once it's older than [`ColdStorageConfig::min_age`] and
+//! at least [`ColdStorageConfig::hot_blocks`] blocks came after it. The whole block is appended to
+//! a cold file in the encoding of the snapshot export: a length-prefixed protobuf `Block` message.
+//! Its location in the file is kept in the database and its events are removed from the events
+//! index. The header, the signature and the state tables stay in the database.
+//!
+//! The readers of the block bodies and the state diffs read the blocks below the cold storage
+//! marker from the cold files, so their users don't need to know where a block is kept. The events
+//! of the blocks in the cold storage aren't indexed, so the events readers fail for them.
+//!
+//! The transactions, transaction outputs and state diff of a moved block are deleted from the
+//! database on the next move, and their space in the storage files is freed by punching a hole in
+//! the files, which keeps the offsets of the rest of the data. A reader that started before a move
+//! may still read the block from the storage files, so they are freed only once a whole
+//! [`ColdStorageConfig::move_interval`] passed since. A read transaction must not stay open for
+//! longer than that.
+//!
+//! Reverts only touch the blocks above the cold storage marker. A block is moved only once it was
+//! proved on the base layer and [`ColdStorageConfig::hot_blocks`] is at least [`MIN_HOT_BLOCKS`],
+//! so a reorg never reaches the blocks in the cold storage. A revert that does fails.
+//!
+//! There is a single writer to the storage, so the blocks are moved by whoever holds the
+//! [`StorageWriter`], every [`ColdStorageConfig::move_interval`].
+//! # Example
+//! ```
+//! use papyrus_storage::cold_storage::{ColdStorageConfig, ColdStorageReader};
+//! use papyrus_storage::open_storage;
+//! # use papyrus_storage::{db::DbConfig, StorageConfig};
+//! # use starknet_api::core::ChainId;
+//! use starknet_api::block::{BlockNumber, BlockTimestamp};
+//!
+//! # let dir_handle = tempfile::tempdir().unwrap();
+//! # let dir = dir_handle.path().to_path_buf();
+//! # let db_config = DbConfig {
+//! #     path_prefix: dir.clone(),
+//! #     chain_id: ChainId::Mainnet,
+//! #     enforce_file_exists: false,
+//! #     min_size: 1 << 20,    // 1MB
+//! #     max_size: 1 << 35,    // 32GB
+//! #     growth_step: 1 << 26, // 64MB
+//! # };
+//! let cold_storage = ColdStorageConfig { path: dir.join("cold"), ..Default::default() };
+//! # let storage_config =
+//! #     StorageConfig { db_config, cold_storage: Some(cold_storage), ..Default::default() };
+//! let (reader, mut writer) = open_storage(storage_config)?;
+//! // The storage is empty, so there is nothing to move.
+//! let cold_storage_marker = writer.move_blocks_to_cold_storage(BlockTimestamp(1 << 40))?;
+//! assert_eq!(cold_storage_marker, BlockNumber(0));
+//! assert_eq!(reader.begin_ro_txn()?.get_cold_storage_marker()?, BlockNumber(0));
+//! # Ok::<(), papyrus_storage::StorageError>(())
+//! ```
+
+#[cfg(test)]
+#[path = "cold_storage_test.rs"]
+mod cold_storage_test;
+
+use std::collections::{BTreeMap, HashMap};
+use std::fs::{self, File, OpenOptions};
+use std::io::Write;
+use std::os::unix::fs::FileExt;
+use std::path::PathBuf;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+use integer_encoding::VarInt;
+use metrics::{counter, gauge, histogram};
+use papyrus_config::converters::deserialize_seconds_to_duration;
+use papyrus_config::dumping::{ser_param, SerializeConfig};
+use papyrus_config::{ParamPath, ParamPrivacyInput, SerializedParam};
+use papyrus_protobuf::converters::can_encode_transaction_output;
+use papyrus_protobuf::sync::{
+    split_thin_state_diff,
+    ContractDiff,
+    DeclaredClass,
+    DeprecatedDeclaredClass,
+    FullBlock,
+    SignedBlockHeader,
+    StateDiffChunk,
+};
+use serde::{Deserialize, Serialize};
+use starknet_api::block::{BlockHeader, BlockNumber, BlockTimestamp};
+use starknet_api::state::{StateNumber, ThinStateDiff};
+use starknet_api::transaction::TransactionOffsetInBlock;
+use tokio::runtime::{Handle, RuntimeFlavor};
+use tracing::{debug, warn};
+use validator::{Validate, ValidationError};
+
+use crate::base_layer::BaseLayerStorageReader;
+use crate::body::{BodyStorageReader, TransactionIndex};
+use crate::compiled_class::CasmStorageReader;
+use crate::db::table_types::{DbCursorTrait, Table};
+use crate::db::{TransactionKind, RW};
+use crate::header::HeaderStorageReader;
+use crate::mmap_file::{LocationInFile, MMapFileError};
+use crate::state::StateStorageReader;
+use crate::{
+    FileHandlers,
+    MarkerKind,
+    StorageError,
+    StorageResult,
+    StorageTxn,
+    StorageWriter,
+    StorageWriterComponent,
+};
+
+/// The minimal number of the latest blocks that stay in the database. Starknet doesn't reorg
+/// blocks that are this deep, so the blocks a revert touches are never in the cold storage.
+pub const MIN_HOT_BLOCKS: u64 = 1000;
+
+// The number of consecutive blocks in each cold file.
+const BLOCKS_PER_COLD_FILE: u64 = 100_000;
+
+/// Configuration for moving the old blocks of the storage to cold files.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq, Validate)]
+#[validate(schema(function = "validate_cold_storage_config"))]
+pub struct ColdStorageConfig {
+    /// The directory of the cold files.
+    pub path: PathBuf,
+    /// The number of the latest blocks that stay in the database.
+    pub hot_blocks: u64,
+    /// The minimal age of a block, by its timestamp, before it's moved to the cold storage.
+    #[serde(deserialize_with = "deserialize_seconds_to_duration")]
+    pub min_age: Duration,
+    /// The time between two moves of blocks to the cold storage.
+    #[serde(deserialize_with = "deserialize_seconds_to_duration")]
+    pub move_interval: Duration,
+    /// The maximal number of blocks that are moved in a single write transaction.
+    pub max_blocks_per_move: u64,
+}
+
+impl Default for ColdStorageConfig {
+    fn default() -> Self {
+        Self {
+            path: PathBuf::from("./data/cold"),
+            hot_blocks: 100_000,
+            min_age: Duration::from_secs(30 * 24 * 60 * 60),
+            move_interval: Duration::from_secs(60),
+            max_blocks_per_move: 100,
+        }
+    }
+}
+
+impl SerializeConfig for ColdStorageConfig {
+    fn dump(&self) -> BTreeMap<ParamPath, SerializedParam> {
+        BTreeMap::from_iter([
+            ser_param(
+                "path",
+                &self.path,
+                "The directory of the cold files that hold the blocks moved out of the database. The \
+                 events of these blocks aren't indexed, so event queries that start below the cold \
+                 storage marker fail.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "hot_blocks",
+                &self.hot_blocks,
+                "The number of the latest blocks that stay in the database. Must be at least 1000, \
+                 so that the blocks a reorg reverts are never in the cold files.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "min_age",
+                &self.min_age.as_secs(),
+                "The minimal age in seconds of a block, by its timestamp, before it's moved to the \
+                 cold files.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "move_interval",
+                &self.move_interval.as_secs(),
+                "The time in seconds between two moves of blocks to the cold files.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "max_blocks_per_move",
+                &self.max_blocks_per_move,
+                "The maximal number of blocks that are moved to the cold files in a single write \
+                 transaction.",
+                ParamPrivacyInput::Public,
+            ),
+        ])
+    }
+}
+
+fn validate_cold_storage_config(config: &ColdStorageConfig) -> Result<(), ValidationError> {
+    if config.hot_blocks < MIN_HOT_BLOCKS {
+        return Err(ValidationError::new(
+            "hot_blocks should be at least 1000, so that a reorg never reaches the cold storage",
+        ));
+    }
+    if config.max_blocks_per_move == 0 {
+        return Err(ValidationError::new("max_blocks_per_move should be positive"));
+    }
+    Ok(())
+}
+
+/// Interface for reading data related to the cold storage.
+pub trait ColdStorageReader {
+    /// The cold storage marker is the first block that wasn't moved to the cold storage.
+    fn get_cold_storage_marker(&self) -> StorageResult<BlockNumber>;
+}
+
+impl<'env, Mode: TransactionKind> ColdStorageReader for StorageTxn<'env, Mode> {
+    fn get_cold_storage_marker(&self) -> StorageResult<BlockNumber> {
+        let markers_table = self.open_table(&self.tables.markers)?;
+        Ok(markers_table.get(&self.txn, &MarkerKind::ColdStorage)?.unwrap_or_default())
+    }
+}
+
+impl<'env, Mode: TransactionKind> StorageTxn<'env, Mode> {
+    // Returns the block if it was moved to the cold storage, and None otherwise.
+    pub(crate) fn get_cold_block(
+        &self,
+        block_number: BlockNumber,
+    ) -> StorageResult<Option<FullBlock>> {
+        if block_number >= self.get_cold_storage_marker()? {
+            return Ok(None);
+        }
+        let cold_blocks_table = self.open_table(&self.tables.cold_blocks)?;
+        let location = cold_blocks_table.get(&self.txn, &block_number)?.ok_or_else(|| {
+            StorageError::DBInconsistency {
+                msg: format!("Block {block_number} is in the cold storage but has no location."),
+            }
+        })?;
+        let cold_files = self
+            .cold_files
+            .as_ref()
+            .ok_or(StorageError::ColdStorageNotConfigured { block_number })?;
+        Ok(Some(cold_files.read_block(block_number, location)?))
+    }
+
+    // Returns the state diff of the block if it was moved to the cold storage, and None otherwise.
+    pub(crate) fn get_cold_state_diff(
+        &self,
+        block_number: BlockNumber,
+    ) -> StorageResult<Option<ThinStateDiff>> {
+        let Some(block) = self.get_cold_block(block_number)? else {
+            return Ok(None);
+        };
+        Ok(Some(unite_state_diff_chunks(self, block_number, block.state_diff_chunks)?))
+    }
+
+    // Fails if the block was moved to the cold storage, since a revert can't touch it.
+    pub(crate) fn verify_block_is_hot(&self, block_number: BlockNumber) -> StorageResult<()> {
+        let cold_storage_marker = self.get_cold_storage_marker()?;
+        if block_number < cold_storage_marker {
+            return Err(StorageError::RevertInColdStorage { block_number, cold_storage_marker });
+        }
+        Ok(())
+    }
+}
+
+// The chunks of a state diff don't tell a deployed contract from a contract whose class was
+// replaced. The state tables stay in the database, so a contract that had a class before the block
+// is known to have its class replaced.
+fn unite_state_diff_chunks<Mode: TransactionKind>(
+    txn: &StorageTxn<'_, Mode>,
+    block_number: BlockNumber,
+    state_diff_chunks: Vec<StateDiffChunk>,
+) -> StorageResult<ThinStateDiff> {
+    let state_reader = txn.get_state_reader()?;
+    let state_number = StateNumber::right_before_block(block_number);
+    let mut state_diff = ThinStateDiff::default();
+    for state_diff_chunk in state_diff_chunks {
+        match state_diff_chunk {
+            StateDiffChunk::ContractDiff(ContractDiff {
+                contract_address,
+                class_hash,
+                nonce,
+                storage_diffs,
+            }) => {
+                if let Some(class_hash) = class_hash {
+                    if state_reader.get_class_hash_at(state_number, &contract_address)?.is_some() {
+                        state_diff.replaced_classes.insert(contract_address, class_hash);
+                    } else {
+                        state_diff.deployed_contracts.insert(contract_address, class_hash);
+                    }
+                }
+                if let Some(nonce) = nonce {
+                    state_diff.nonces.insert(contract_address, nonce);
+                }
+                if !storage_diffs.is_empty() {
+                    state_diff.storage_diffs.insert(contract_address, storage_diffs);
+                }
+            }
+            StateDiffChunk::DeclaredClass(DeclaredClass { class_hash, compiled_class_hash }) => {
+                state_diff.declared_classes.insert(class_hash, compiled_class_hash);
+            }
+            StateDiffChunk::DeprecatedDeclaredClass(DeprecatedDeclaredClass { class_hash }) => {
+                state_diff.deprecated_declared_classes.push(class_hash);
+            }
+        }
+    }
+    Ok(state_diff)
+}
+
+impl StorageWriter {
+    /// Returns the time between two moves of blocks to the cold storage, or None if the storage
+    /// has no cold storage.
+    pub fn cold_storage_move_interval(&self) -> Option<Duration> {
+        self.cold_files.as_ref().map(|cold_files| cold_files.config.move_interval)
+    }
+
+    /// Moves the blocks that are old enough at `now` from the database to the cold files, up to
+    /// [`ColdStorageConfig::max_blocks_per_move`] of them, and returns the new cold storage
+    /// marker. Only the blocks whose data was fully written and that were proved on the base
+    /// layer are moved. Does nothing if the storage has no cold storage.
+    pub fn move_blocks_to_cold_storage(
+        &mut self,
+        now: BlockTimestamp,
+    ) -> StorageResult<BlockNumber> {
+        let txn = StorageTxn {
+            writer_component: Some(StorageWriterComponent::ColdStorage),
+            ..self.begin_rw_txn()?
+        };
+        let cold_storage_marker = txn.get_cold_storage_marker()?;
+        let Some(cold_files) = txn.cold_files.clone() else {
+            return Ok(cold_storage_marker);
+        };
+        let config = &cold_files.config;
+        let end = cold_storage_boundary(&txn, config.hot_blocks)?
+            .min(BlockNumber(cold_storage_marker.0.saturating_add(config.max_blocks_per_move)));
+
+        // The blocks of the previous move are deleted from the database only now, so that the
+        // readers that started before it don't lose them.
+        let hot_locations = txn.delete_hot_copies(cold_storage_marker)?;
+
+        let cold_blocks_table = txn.open_table(&txn.tables.cold_blocks)?;
+        let markers_table = txn.open_table(&txn.tables.markers)?;
+        let mut appender = ColdFileAppender::new(&cold_files);
+        let mut block_number = cold_storage_marker;
+        while block_number < end {
+            let header = txn.get_block_header(block_number)?.ok_or_else(|| {
+                StorageError::DBInconsistency {
+                    msg: format!("Missing header of block {block_number} below the header marker."),
+                }
+            })?;
+            if header.timestamp.0.saturating_add(config.min_age.as_secs()) > now.0 {
+                break;
+            }
+            let block = read_hot_block(&txn, header)?;
+            if !block.transactions.iter().all(|(_transaction, transaction_output)| {
+                can_encode_transaction_output(transaction_output)
+            }) {
+                warn!(
+                    "Block {block_number} has transaction outputs that can't be encoded. It and \
+                     the blocks after it stay in the database."
+                );
+                break;
+            }
+            txn.delete_block_events(&block)?;
+            let location = appender.append(block_number, block)?;
+            cold_blocks_table.insert(&txn.txn, &block_number, &location)?;
+            block_number = block_number.unchecked_next();
+        }
+        if block_number == cold_storage_marker && hot_locations.is_none() {
+            return Ok(cold_storage_marker);
+        }
+
+        // The blocks have to be in the cold files before the database points at them.
+        appender.sync()?;
+        markers_table.upsert(&txn.txn, &MarkerKind::ColdStorage, &block_number)?;
+        let file_handlers = txn.file_handlers.clone();
+        txn.commit()?;
+        if let Some(hot_locations) = hot_locations {
+            reclaim_file_space(&file_handlers, hot_locations);
+        }
+        if block_number == cold_storage_marker {
+            return Ok(cold_storage_marker);
+        }
+        debug!("Moved blocks {cold_storage_marker} to {block_number} to the cold storage.");
+        counter!("storage_cold_storage_moved_blocks", block_number.0 - cold_storage_marker.0);
+        gauge!("storage_cold_storage_marker", block_number.0 as f64);
+        Ok(block_number)
+    }
+}
+
+// The first block that can't be moved to the cold storage. A block is moved only once all of its
+// data was written, it was proved on the base layer and `hot_blocks` blocks came after it.
+fn cold_storage_boundary(txn: &StorageTxn<'_, RW>, hot_blocks: u64) -> StorageResult<BlockNumber> {
+    let header_marker = txn.get_header_marker()?;
+    Ok([
+        BlockNumber(header_marker.0.saturating_sub(hot_blocks)),
+        txn.get_body_marker()?,
+        txn.get_state_marker()?,
+        txn.get_compiled_class_marker()?,
+        txn.get_base_layer_block_marker()?,
+    ]
+    .into_iter()
+    .min()
+    .expect("The array isn't empty"))
+}
+
+fn read_hot_block(txn: &StorageTxn<'_, RW>, block_header: BlockHeader) -> StorageResult<FullBlock> {
+    let block_number = block_header.block_number;
+    let missing = |missing_data: &str| StorageError::DBInconsistency {
+        msg: format!("Missing {missing_data} of block {block_number} below its marker."),
+    };
+    let signature = txn.get_block_signature(block_number)?;
+    let transactions =
+        txn.get_block_transactions(block_number)?.ok_or_else(|| missing("transactions"))?;
+    let transaction_outputs = txn
+        .get_block_transaction_outputs(block_number)?
+        .ok_or_else(|| missing("transaction outputs"))?;
+    let transaction_hashes = txn
+        .get_block_transaction_hashes(block_number)?
+        .ok_or_else(|| missing("transaction hashes"))?;
+    let state_diff = txn.get_state_diff(block_number)?.ok_or_else(|| missing("state diff"))?;
+    Ok(FullBlock {
+        signed_header: SignedBlockHeader {
+            block_header,
+            signatures: signature.into_iter().collect(),
+            data_availability: None,
+        },
+        transactions: transactions.into_iter().zip(transaction_outputs).collect(),
+        transaction_hashes,
+        state_diff_chunks: split_thin_state_diff(state_diff),
+    })
+}
+
+impl<'env> StorageTxn<'env, RW> {
+    // Deletes the events of the block from the events index.
+    fn delete_block_events(&self, block: &FullBlock) -> StorageResult<()> {
+        let block_number = block.signed_header.block_header.block_number;
+        let events_table = self.open_table(&self.tables.events)?;
+        for (offset, (_transaction, transaction_output)) in block.transactions.iter().enumerate() {
+            let tx_index = TransactionIndex(block_number, TransactionOffsetInBlock(offset));
+            for event in transaction_output.events() {
+                events_table.delete(&self.txn, &(event.from_address, tx_index))?;
+            }
+        }
+        Ok(())
+    }
+
+    // Deletes the transactions, transaction outputs and state diffs that the database holds for
+    // the blocks below the cold storage marker, and returns their locations in the storage files.
+    // Returns None if they were already deleted.
+    fn delete_hot_copies(
+        &self,
+        cold_storage_marker: BlockNumber,
+    ) -> StorageResult<Option<HotLocations>> {
+        let markers_table = self.open_table(&self.tables.markers)?;
+        let reclaim_marker =
+            markers_table.get(&self.txn, &MarkerKind::ColdStorageReclaim)?.unwrap_or_default();
+        if reclaim_marker >= cold_storage_marker {
+            return Ok(None);
+        }
+        let transaction_metadata_table = self.open_table(&self.tables.transaction_metadata)?;
+        let state_diffs_table = self.open_table(&self.tables.state_diffs)?;
+
+        let mut hot_locations = HotLocations::default();
+        let mut tx_indices = Vec::new();
+        let mut cursor = transaction_metadata_table.cursor(&self.txn)?;
+        let mut current =
+            cursor.lower_bound(&TransactionIndex(reclaim_marker, TransactionOffsetInBlock(0)))?;
+        while let Some((tx_index, tx_metadata)) = current {
+            if tx_index.0 >= cold_storage_marker {
+                break;
+            }
+            tx_indices.push(tx_index);
+            hot_locations.transactions.push(tx_metadata.tx_location);
+            hot_locations.transaction_outputs.push(tx_metadata.tx_output_location);
+            current = cursor.next()?;
+        }
+        for tx_index in tx_indices {
+            transaction_metadata_table.delete(&self.txn, &tx_index)?;
+        }
+        let mut block_number = reclaim_marker;
+        while block_number < cold_storage_marker {
+            if let Some(location) = state_diffs_table.get(&self.txn, &block_number)? {
+                hot_locations.state_diffs.push(location);
+                state_diffs_table.delete(&self.txn, &block_number)?;
+            }
+            block_number = block_number.unchecked_next();
+        }
+        markers_table.upsert(&self.txn, &MarkerKind::ColdStorageReclaim, &cold_storage_marker)?;
+        Ok(Some(hot_locations))
+    }
+}
+
+// The locations in the storage files of the data that the database held for blocks that were
+// moved to the cold storage.
+#[derive(Debug, Default)]
+struct HotLocations {
+    transactions: Vec<LocationInFile>,
+    transaction_outputs: Vec<LocationInFile>,
+    state_diffs: Vec<LocationInFile>,
+}
+
+impl HotLocations {
+    // Frees the space of the data in the storage files.
+    fn reclaim(&self, file_handlers: &FileHandlers<RW>) -> Result<(), MMapFileError> {
+        for location in &self.transactions {
+            file_handlers.transaction.reclaim(*location)?;
+        }
+        for location in &self.transaction_outputs {
+            file_handlers.transaction_output.reclaim(*location)?;
+        }
+        for location in &self.state_diffs {
+            file_handlers.thin_state_diff.reclaim(*location)?;
+        }
+        Ok(())
+    }
+
+    fn total_len(&self) -> usize {
+        self.transactions
+            .iter()
+            .chain(&self.transaction_outputs)
+            .chain(&self.state_diffs)
+            .map(|location| location.len)
+            .sum()
+    }
+}
+
+// Nothing points at the data that was deleted from the database anymore, so a failure to free its
+// space leaves the space taken but doesn't fail the move.
+fn reclaim_file_space(file_handlers: &FileHandlers<RW>, hot_locations: HotLocations) {
+    match hot_locations.reclaim(file_handlers) {
+        Ok(()) => {
+            counter!("storage_cold_storage_reclaimed_bytes", hot_locations.total_len() as u64)
+        }
+        Err(error) => warn!("Failed freeing the space of blocks in the cold storage: {error}."),
+    }
+}
+
+// The cold files of a storage, and the ones that were opened for reading.
+#[derive(Debug)]
+pub(crate) struct ColdFiles {
+    config: ColdStorageConfig,
+    // By the first block of the file.
+    open_files: Mutex<HashMap<BlockNumber, Arc<File>>>,
+}
+
+impl ColdFiles {
+    pub(crate) fn new(config: ColdStorageConfig) -> Self {
+        Self { config, open_files: Mutex::new(HashMap::new()) }
+    }
+
+    fn file_path(&self, first_block: BlockNumber) -> PathBuf {
+        self.config.path.join(format!("blocks_{:012}.snapshot", first_block.0))
+    }
+
+    fn read_block(
+        &self,
+        block_number: BlockNumber,
+        location: LocationInFile,
+    ) -> StorageResult<FullBlock> {
+        let start = Instant::now();
+        let file = self.get_file(first_block_of_file(block_number))?;
+        let mut message = vec![0; location.len];
+        read_blocking(|| file.read_exact_at(&mut message, location.offset as u64))?;
+        let block = decode_block(block_number, &message)?;
+        counter!("storage_cold_storage_reads", 1);
+        counter!("storage_cold_storage_read_bytes", location.len as u64);
+        histogram!("storage_cold_storage_read_latency_seconds", start.elapsed().as_secs_f64());
+        Ok(block)
+    }
+
+    fn get_file(&self, first_block: BlockNumber) -> StorageResult<Arc<File>> {
+        let mut open_files =
+            self.open_files.lock().expect("Cold files lock should not be poisoned");
+        if let Some(file) = open_files.get(&first_block) {
+            return Ok(file.clone());
+        }
+        let file = Arc::new(File::open(self.file_path(first_block))?);
+        open_files.insert(first_block, file.clone());
+        Ok(file)
+    }
+}
+
+// Appends the moved blocks to the cold files.
+struct ColdFileAppender<'a> {
+    cold_files: &'a ColdFiles,
+    // The files that were appended to, by their first block.
+    files: BTreeMap<BlockNumber, File>,
+}
+
+impl<'a> ColdFileAppender<'a> {
+    fn new(cold_files: &'a ColdFiles) -> Self {
+        Self { cold_files, files: BTreeMap::new() }
+    }
+
+    fn append(
+        &mut self,
+        block_number: BlockNumber,
+        block: FullBlock,
+    ) -> StorageResult<LocationInFile> {
+        let first_block = first_block_of_file(block_number);
+        let file = match self.files.entry(first_block) {
+            std::collections::btree_map::Entry::Occupied(entry) => entry.into_mut(),
+            std::collections::btree_map::Entry::Vacant(entry) => {
+                fs::create_dir_all(&self.cold_files.config.path)?;
+                entry.insert(
+                    OpenOptions::new()
+                        .create(true)
+                        .append(true)
+                        .open(self.cold_files.file_path(first_block))?,
+                )
+            }
+        };
+        // The bytes of a move that wasn't committed may be left at the end of the file. Nothing
+        // points at them, so the block is appended after them.
+        let offset = usize::try_from(file.metadata()?.len()).expect("File size should fit usize");
+        let message = encode_block(block);
+        file.write_all(&message)?;
+        Ok(LocationInFile { offset, len: message.len() })
+    }
+
+    fn sync(self) -> StorageResult<()> {
+        for file in self.files.values() {
+            file.sync_data()?;
+        }
+        Ok(())
+    }
+}
+
+fn first_block_of_file(block_number: BlockNumber) -> BlockNumber {
+    BlockNumber(block_number.0 - block_number.0 % BLOCKS_PER_COLD_FILE)
+}
+
+// The encoding of a block in the snapshot export: the protobuf message prefixed by its length as
+// an unsigned varint.
+fn encode_block(block: FullBlock) -> Vec<u8> {
+    let message = Vec::<u8>::from(block);
+    let mut encoded_block = message.len().encode_var_vec();
+    encoded_block.extend(message);
+    encoded_block
+}
+
+fn decode_block(block_number: BlockNumber, encoded_block: &[u8]) -> StorageResult<FullBlock> {
+    let decoding_error =
+        |reason: String| StorageError::ColdBlockDecodingError { block_number, reason };
+    let (message_len, prefix_len) = usize::decode_var(encoded_block)
+        .ok_or_else(|| decoding_error("The length prefix is malformed.".to_owned()))?;
+    if prefix_len + message_len != encoded_block.len() {
+        return Err(decoding_error(format!(
+            "The length prefix is {message_len}, but the message is {} bytes long.",
+            encoded_block.len() - prefix_len
+        )));
+    }
+    FullBlock::try_from(encoded_block[prefix_len..].to_vec())
+        .map_err(|error| decoding_error(error.to_string()))
+}
+
+// A read from the cold files blocks on the disk. Inside a multi-threaded runtime, the thread is
+// handed over to the blocking pool for the read, and the tasks that were waiting for it move to
+// the other threads.
+fn read_blocking<T>(read: impl FnOnce() -> T) -> T {
+    match Handle::try_current() {
+        Ok(handle) if handle.runtime_flavor() == RuntimeFlavor::MultiThread => {
+            tokio::task::block_in_place(read)
+        }
+        _ => read(),
+    }
+}
@@ -0,0 +1,350 @@
+use std::time::Duration;
+
+use assert_matches::assert_matches;
+use indexmap::IndexMap;
+use pretty_assertions::assert_eq;
+use starknet_api::block::{BlockBody, BlockHeader, BlockNumber, BlockTimestamp};
+use starknet_api::core::{ClassHash, ContractAddress, Nonce};
+use starknet_api::state::ThinStateDiff;
+use starknet_api::transaction::{
+    Builtin,
+    Event,
+    EventContent,
+    EventIndexInTransactionOutput,
+    ExecutionResources,
+    InvokeTransaction,
+    InvokeTransactionOutput,
+    InvokeTransactionV1,
+    Transaction,
+    TransactionHash,
+    TransactionOffsetInBlock,
+    TransactionOutput,
+};
+use starknet_types_core::felt::Felt;
+use tempfile::TempDir;
+use validator::Validate;
+
+use crate::base_layer::BaseLayerStorageWriter;
+use crate::body::events::{EventIndex, EventsReader};
+use crate::body::{BodyStorageReader, BodyStorageWriter, TransactionIndex};
+use crate::cold_storage::{ColdStorageConfig, ColdStorageReader, MIN_HOT_BLOCKS};
+use crate::db::table_types::Table;
+use crate::header::{HeaderStorageReader, HeaderStorageWriter};
+use crate::state::{StateStorageReader, StateStorageWriter};
+use crate::test_utils::get_test_config;
+use crate::{open_storage, StorageError, StorageReader, StorageWriter};
+
+const N_BLOCKS: u64 = 6;
+const FIRST_TIMESTAMP: u64 = 1000;
+const MIN_AGE: Duration = Duration::from_secs(100);
+
+// The time at which all the blocks are old enough to move.
+fn late_enough() -> BlockTimestamp {
+    BlockTimestamp(FIRST_TIMESTAMP + N_BLOCKS + MIN_AGE.as_secs())
+}
+
+// The config isn't validated when the storage is opened, so the tests keep fewer blocks in the
+// database than a node can.
+fn get_test_cold_storage(
+    hot_blocks: u64,
+    max_blocks_per_move: u64,
+) -> ((StorageReader, StorageWriter), TempDir) {
+    let (mut config, temp_dir) = get_test_config(None);
+    config.cold_storage = Some(ColdStorageConfig {
+        path: temp_dir.path().join("cold"),
+        hot_blocks,
+        min_age: MIN_AGE,
+        move_interval: Duration::from_secs(1),
+        max_blocks_per_move,
+    });
+    (open_storage(config).unwrap(), temp_dir)
+}
+
+fn contract(address: u128) -> ContractAddress {
+    ContractAddress::from(address)
+}
+
+fn class_hash(hash: u128) -> ClassHash {
+    ClassHash(Felt::from(hash))
+}
+
+// An output that keeps its value in the encoding of the cold storage.
+fn transaction_output(events: Vec<Event>) -> TransactionOutput {
+    TransactionOutput::Invoke(InvokeTransactionOutput {
+        events,
+        execution_resources: ExecutionResources {
+            builtin_instance_counter: [
+                Builtin::RangeCheck,
+                Builtin::Pedersen,
+                Builtin::Poseidon,
+                Builtin::EcOp,
+                Builtin::Ecdsa,
+                Builtin::Bitwise,
+                Builtin::Keccak,
+                Builtin::SegmentArena,
+            ]
+            .into_iter()
+            .map(|builtin| (builtin, 0))
+            .collect(),
+            ..Default::default()
+        },
+        ..Default::default()
+    })
+}
+
+fn event(from_address: u128) -> Event {
+    Event { from_address: contract(from_address), content: EventContent::default() }
+}
+
+fn block_body(block_number: u64) -> BlockBody {
+    let outputs = match block_number {
+        0 => vec![transaction_output(vec![event(1), event(2)]), transaction_output(vec![])],
+        1 => vec![transaction_output(vec![event(1)])],
+        _ => vec![],
+    };
+    let transaction = Transaction::Invoke(InvokeTransaction::V1(InvokeTransactionV1::default()));
+    BlockBody {
+        transactions: vec![transaction; outputs.len()],
+        transaction_hashes: (0..outputs.len())
+            .map(|offset| TransactionHash(Felt::from(block_number * 10 + offset as u64)))
+            .collect(),
+        transaction_outputs: outputs,
+    }
+}
+
+// Block 0 deploys a contract, and block 1 replaces its class and deploys another contract.
+fn state_diff(block_number: u64) -> ThinStateDiff {
+    match block_number {
+        0 => ThinStateDiff {
+            deployed_contracts: IndexMap::from([(contract(1), class_hash(1))]),
+            nonces: IndexMap::from([(contract(1), Nonce(Felt::ONE))]),
+            deprecated_declared_classes: vec![class_hash(1)],
+            ..Default::default()
+        },
+        1 => ThinStateDiff {
+            deployed_contracts: IndexMap::from([(contract(2), class_hash(2))]),
+            replaced_classes: IndexMap::from([(contract(1), class_hash(2))]),
+            storage_diffs: IndexMap::from([(
+                contract(2),
+                IndexMap::from([(Default::default(), Felt::TWO)]),
+            )]),
+            deprecated_declared_classes: vec![class_hash(2)],
+            ..Default::default()
+        },
+        _ => ThinStateDiff::default(),
+    }
+}
+
+// Appends N_BLOCKS blocks that were all proved on the base layer.
+fn append_blocks(writer: &mut StorageWriter) {
+    let mut txn = writer.begin_rw_txn().unwrap();
+    for block_number in 0..N_BLOCKS {
+        let header = BlockHeader {
+            block_number: BlockNumber(block_number),
+            timestamp: BlockTimestamp(FIRST_TIMESTAMP + block_number),
+            ..Default::default()
+        };
+        txn = txn
+            .append_header(BlockNumber(block_number), &header)
+            .unwrap()
+            .append_body(BlockNumber(block_number), block_body(block_number))
+            .unwrap()
+            .append_state_diff(BlockNumber(block_number), state_diff(block_number))
+            .unwrap();
+    }
+    txn.update_base_layer_block_marker(&BlockNumber(N_BLOCKS)).unwrap().commit().unwrap();
+}
+
+#[test]
+fn moved_blocks_are_read_from_the_cold_storage() {
+    let ((reader, mut writer), _temp_dir) = get_test_cold_storage(2, 100);
+    append_blocks(&mut writer);
+
+    let cold_storage_marker = writer.move_blocks_to_cold_storage(late_enough()).unwrap();
+
+    assert_eq!(cold_storage_marker, BlockNumber(N_BLOCKS - 2));
+    let txn = reader.begin_ro_txn().unwrap();
+    assert_eq!(txn.get_cold_storage_marker().unwrap(), cold_storage_marker);
+    for block_number in 0..N_BLOCKS - 2 {
+        let body = block_body(block_number);
+        let block_number = BlockNumber(block_number);
+        assert_eq!(txn.get_block_transactions(block_number).unwrap(), Some(body.transactions));
+        assert_eq!(
+            txn.get_block_transaction_outputs(block_number).unwrap(),
+            Some(body.transaction_outputs.clone())
+        );
+        assert_eq!(
+            txn.get_block_transaction_hashes(block_number).unwrap(),
+            Some(body.transaction_hashes)
+        );
+        assert_eq!(
+            txn.get_block_transactions_count(block_number).unwrap(),
+            Some(body.transaction_outputs.len())
+        );
+        assert_eq!(txn.get_state_diff(block_number).unwrap(), Some(state_diff(block_number.0)));
+    }
+
+    // A single transaction is read through its index, which stays in the database.
+    let transaction_hash = TransactionHash(Felt::ONE);
+    let transaction_index = txn.get_transaction_idx_by_hash(&transaction_hash).unwrap().unwrap();
+    assert_eq!(transaction_index, TransactionIndex(BlockNumber(0), TransactionOffsetInBlock(1)));
+    assert_eq!(
+        txn.get_transaction(transaction_index).unwrap(),
+        Some(block_body(0).transactions[1].clone())
+    );
+    assert_eq!(
+        txn.get_transaction_output(transaction_index).unwrap(),
+        Some(block_body(0).transaction_outputs[1].clone())
+    );
+    assert_eq!(
+        txn.get_transaction_hash_by_idx(&transaction_index).unwrap(),
+        Some(transaction_hash)
+    );
+    let missing_index = TransactionIndex(BlockNumber(0), TransactionOffsetInBlock(2));
+    assert_eq!(txn.get_transaction(missing_index).unwrap(), None);
+}
+
+#[test]
+fn moved_blocks_are_deleted_from_the_database_on_the_next_move() {
+    let ((reader, mut writer), _temp_dir) = get_test_cold_storage(2, 100);
+    append_blocks(&mut writer);
+    let tx_index = TransactionIndex(BlockNumber(0), TransactionOffsetInBlock(0));
+
+    writer.move_blocks_to_cold_storage(late_enough()).unwrap();
+
+    // The events of the moved blocks aren't indexed, but their other data waits for the readers
+    // that started before the move.
+    let txn = reader.begin_ro_txn().unwrap();
+    let transaction_metadata_table = txn.open_table(&txn.tables.transaction_metadata).unwrap();
+    let events_table = txn.open_table(&txn.tables.events).unwrap();
+    let state_diffs_table = txn.open_table(&txn.tables.state_diffs).unwrap();
+    assert!(events_table.get(&txn.txn, &(contract(1), tx_index)).unwrap().is_none());
+    assert!(transaction_metadata_table.get(&txn.txn, &tx_index).unwrap().is_some());
+    assert!(state_diffs_table.get(&txn.txn, &BlockNumber(1)).unwrap().is_some());
+
+    // There are no more blocks to move, but the data of the previous move is deleted.
+    assert_eq!(
+        writer.move_blocks_to_cold_storage(late_enough()).unwrap(),
+        BlockNumber(N_BLOCKS - 2)
+    );
+
+    let txn = reader.begin_ro_txn().unwrap();
+    let transaction_metadata_table = txn.open_table(&txn.tables.transaction_metadata).unwrap();
+    let state_diffs_table = txn.open_table(&txn.tables.state_diffs).unwrap();
+    assert!(transaction_metadata_table.get(&txn.txn, &tx_index).unwrap().is_none());
+    assert!(state_diffs_table.get(&txn.txn, &BlockNumber(1)).unwrap().is_none());
+    // The blocks that weren't moved stay in the database.
+    assert!(state_diffs_table.get(&txn.txn, &BlockNumber(N_BLOCKS - 2)).unwrap().is_some());
+    // The headers stay in the database.
+    assert!(txn.get_block_header(BlockNumber(0)).unwrap().is_some());
+    // The deleted blocks are read from the cold storage.
+    assert_eq!(
+        txn.get_block_transaction_outputs(BlockNumber(0)).unwrap(),
+        Some(block_body(0).transaction_outputs)
+    );
+    assert_eq!(txn.get_transaction(tx_index).unwrap(), Some(block_body(0).transactions[0].clone()));
+    assert_eq!(txn.get_state_diff(BlockNumber(1)).unwrap(), Some(state_diff(1)));
+    // The blocks that weren't moved are still read from the storage files.
+    assert_eq!(
+        txn.get_state_diff(BlockNumber(N_BLOCKS - 2)).unwrap(),
+        Some(state_diff(N_BLOCKS - 2))
+    );
+}
+
+#[test]
+fn events_of_moved_blocks_cant_be_iterated() {
+    let ((reader, mut writer), _temp_dir) = get_test_cold_storage(2, 100);
+    append_blocks(&mut writer);
+    writer.move_blocks_to_cold_storage(late_enough()).unwrap();
+
+    let txn = reader.begin_ro_txn().unwrap();
+    let event_index = |block_number| {
+        EventIndex(
+            TransactionIndex(BlockNumber(block_number), TransactionOffsetInBlock(0)),
+            EventIndexInTransactionOutput(0),
+        )
+    };
+    let result = txn.iter_events(None, event_index(1), BlockNumber(N_BLOCKS - 1));
+    assert_matches!(
+        result,
+        Err(StorageError::EventsInColdStorage { block_number, cold_storage_marker })
+        if block_number == BlockNumber(1) && cold_storage_marker == BlockNumber(N_BLOCKS - 2)
+    );
+    let result = txn.iter_events(Some(contract(1)), event_index(0), BlockNumber(N_BLOCKS - 1));
+    assert_matches!(result, Err(StorageError::EventsInColdStorage { .. }));
+    // The events of the blocks in the database are iterated.
+    assert!(txn.iter_events(None, event_index(N_BLOCKS - 2), BlockNumber(N_BLOCKS - 1)).is_ok());
+}
+
+#[test]
+fn blocks_are_moved_only_when_old_and_proved() {
+    let ((reader, mut writer), _temp_dir) = get_test_cold_storage(2, 100);
+    append_blocks(&mut writer);
+
+    // Only blocks 0 and 1 are old enough.
+    let now = BlockTimestamp(FIRST_TIMESTAMP + 1 + MIN_AGE.as_secs());
+    assert_eq!(writer.move_blocks_to_cold_storage(now).unwrap(), BlockNumber(2));
+
+    // Only blocks up to 2 were proved on the base layer.
+    writer
+        .begin_rw_txn()
+        .unwrap()
+        .update_base_layer_block_marker(&BlockNumber(3))
+        .unwrap()
+        .commit()
+        .unwrap();
+    assert_eq!(writer.move_blocks_to_cold_storage(late_enough()).unwrap(), BlockNumber(3));
+    assert_eq!(reader.begin_ro_txn().unwrap().get_cold_storage_marker().unwrap(), BlockNumber(3));
+}
+
+#[test]
+fn blocks_are_moved_in_batches() {
+    let ((_reader, mut writer), _temp_dir) = get_test_cold_storage(0, 4);
+    append_blocks(&mut writer);
+
+    assert_eq!(writer.move_blocks_to_cold_storage(late_enough()).unwrap(), BlockNumber(4));
+    assert_eq!(writer.move_blocks_to_cold_storage(late_enough()).unwrap(), BlockNumber(N_BLOCKS));
+}
+
+#[test]
+fn revert_of_a_cold_block_fails() {
+    let ((_reader, mut writer), _temp_dir) = get_test_cold_storage(0, 100);
+    append_blocks(&mut writer);
+    writer.move_blocks_to_cold_storage(late_enough()).unwrap();
+
+    let last_block = BlockNumber(N_BLOCKS - 1);
+    let result = writer.begin_rw_txn().unwrap().revert_header(last_block);
+    assert_matches!(
+        result,
+        Err(StorageError::RevertInColdStorage { block_number, cold_storage_marker })
+        if block_number == last_block && cold_storage_marker == BlockNumber(N_BLOCKS)
+    );
+    let result = writer.begin_rw_txn().unwrap().revert_body(last_block);
+    assert_matches!(result, Err(StorageError::RevertInColdStorage { .. }));
+    let result = writer.begin_rw_txn().unwrap().revert_state_diff(last_block);
+    assert_matches!(result, Err(StorageError::RevertInColdStorage { .. }));
+}
+
+#[test]
+fn storage_without_cold_storage_moves_nothing() {
+    let (config, _temp_dir) = get_test_config(None);
+    let (reader, mut writer) = open_storage(config).unwrap();
+    append_blocks(&mut writer);
+
+    assert_eq!(writer.cold_storage_move_interval(), None);
+    assert_eq!(writer.move_blocks_to_cold_storage(late_enough()).unwrap(), BlockNumber(0));
+    let txn = reader.begin_ro_txn().unwrap();
+    assert_eq!(
+        txn.get_block_transactions(BlockNumber(0)).unwrap(),
+        Some(block_body(0).transactions)
+    );
+}
+
+#[test]
+fn config_keeps_enough_hot_blocks() {
+    let config = ColdStorageConfig { hot_blocks: MIN_HOT_BLOCKS - 1, ..Default::default() };
+    assert!(config.validate().is_err());
+    let config = ColdStorageConfig { max_blocks_per_move: 0, ..Default::default() };
+    assert!(config.validate().is_err());
+    assert!(ColdStorageConfig::default().validate().is_ok());
+}
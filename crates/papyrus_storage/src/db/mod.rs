@@ -27,7 +27,7 @@ use std::fmt::Debug;
 use std::marker::PhantomData;
 use std::path::PathBuf;
 use std::result;
-use std::sync::Arc;
+use std::sync::{Arc, Mutex};
 
 use libmdbx::{DatabaseFlags, Geometry, PageSize, WriteMap};
 use papyrus_config::dumping::{ser_param, SerializeConfig};
@@ -42,7 +42,7 @@ use self::table_types::{DbCursor, DbCursorTrait};
 use crate::db::table_types::TableType;
 
 // Maximum number of Sub-Databases.
-const MAX_DBS: usize = 18;
+const MAX_DBS: usize = 20;
 
 // Note that NO_TLS mode is used by default.
 type EnvironmentKind = WriteMap;
@@ -190,33 +190,49 @@ impl KeyAlreadyExistsError {
 /// There is a single non clonable writer instance, to make sure there is only one write transaction
 ///  at any given moment.
 pub(crate) fn open_env(config: &DbConfig) -> DbResult<(DbReader, DbWriter)> {
+    let env = Arc::new(open_environment(config, false)?);
+    Ok((DbReader { env: env.clone() }, DbWriter { env }))
+}
+
+/// Tries to open an existing MDBX environment without write access and returns a reader to it.
+/// The environment may be written concurrently by another process.
+pub(crate) fn open_env_read_only(config: &DbConfig) -> DbResult<DbReader> {
+    let db_file_path = config.path().join("mdbx.dat");
+    if !db_file_path.exists() {
+        return Err(DbError::FileDoesNotExist(db_file_path));
+    }
+    Ok(DbReader { env: Arc::new(open_environment(config, true)?) })
+}
+
+fn open_environment(config: &DbConfig, read_only: bool) -> DbResult<Environment> {
     let db_file_path = config.path().join("mdbx.dat");
     // Checks if path exists if enforce_file_exists is true.
     if config.enforce_file_exists && !db_file_path.exists() {
         return Err(DbError::FileDoesNotExist(db_file_path));
     }
     const MAX_READERS: u32 = 1 << 13; // 8K readers
-    let env = Arc::new(
-        Environment::new()
-            .set_geometry(Geometry {
-                size: Some(config.min_size..config.max_size),
-                growth_step: Some(config.growth_step),
-                page_size: Some(get_page_size(page_size::get())),
-                ..Default::default()
-            })
-            .set_max_tables(MAX_DBS)
-            .set_max_readers(MAX_READERS)
-            .set_flags(DatabaseFlags {
-                // There is no locality of pages in the database almost at all, so readahead will
-                // fill the RAM with garbage.
-                no_rdahead: true,
-                // LIFO policy for recycling a Garbage Collection items should be faster.
-                liforeclaim: true,
-                ..Default::default()
-            })
-            .open(&config.path())?,
-    );
-    Ok((DbReader { env: env.clone() }, DbWriter { env }))
+    let mut flags = DatabaseFlags {
+        // There is no locality of pages in the database almost at all, so readahead will
+        // fill the RAM with garbage.
+        no_rdahead: true,
+        // LIFO policy for recycling a Garbage Collection items should be faster.
+        liforeclaim: true,
+        ..Default::default()
+    };
+    if read_only {
+        flags.mode = libmdbx::Mode::ReadOnly;
+    }
+    Ok(Environment::new()
+        .set_geometry(Geometry {
+            size: Some(config.min_size..config.max_size),
+            growth_step: Some(config.growth_step),
+            page_size: Some(get_page_size(page_size::get())),
+            ..Default::default()
+        })
+        .set_max_tables(MAX_DBS)
+        .set_max_readers(MAX_READERS)
+        .set_flags(flags)
+        .open(&config.path())?)
 }
 
 // Size in bytes.
@@ -246,7 +262,7 @@ pub(crate) struct DbWriter {
 
 impl DbReader {
     pub(crate) fn begin_ro_txn(&self) -> DbResult<DbReadTransaction<'_>> {
-        Ok(DbReadTransaction { txn: self.env.begin_ro_txn()? })
+        Ok(DbReadTransaction { txn: self.env.begin_ro_txn()?, write_stats: None })
     }
 }
 
@@ -254,17 +270,68 @@ type DbReadTransaction<'env> = DbTransaction<'env, RO>;
 
 impl DbWriter {
     pub(crate) fn begin_rw_txn(&mut self) -> DbResult<DbWriteTransaction<'_>> {
-        Ok(DbWriteTransaction { txn: self.env.begin_rw_txn()? })
+        // Collecting the write stats has a cost, so it's done only if someone records the metrics.
+        let write_stats = match metrics::try_recorder() {
+            Some(_) => Some(DbWriteStats {
+                env: self.env.clone(),
+                last_page_number_at_begin: self.env.info()?.last_pgno() as u64,
+                entries_written: Mutex::new(BTreeMap::new()),
+            }),
+            None => None,
+        };
+        Ok(DbWriteTransaction { txn: self.env.begin_rw_txn()?, write_stats })
     }
 }
 
 type DbWriteTransaction<'env> = DbTransaction<'env, RW>;
 
 impl<'a> DbWriteTransaction<'a> {
-    pub(crate) fn commit(self) -> DbResult<()> {
+    /// Commits the transaction. Returns statistics about the committed writes if they were
+    /// collected.
+    pub(crate) fn commit(self) -> DbResult<Option<DbCommitStats>> {
         self.txn.commit()?;
-        Ok(())
+        let Some(write_stats) = self.write_stats else {
+            return Ok(None);
+        };
+        let last_page_number = write_stats.env.info()?.last_pgno() as u64;
+        Ok(Some(DbCommitStats {
+            new_pages: last_page_number.saturating_sub(write_stats.last_page_number_at_begin),
+            entries_written: write_stats
+                .entries_written
+                .into_inner()
+                .expect("Write stats lock should not be poisoned"),
+        }))
     }
+
+    // Counts an entry that was written to the given table, if the write stats are collected.
+    pub(crate) fn count_written_entry(&self, table_name: &'static str) {
+        if let Some(write_stats) = &self.write_stats {
+            *write_stats
+                .entries_written
+                .lock()
+                .expect("Write stats lock should not be poisoned")
+                .entry(table_name)
+                .or_default() += 1;
+        }
+    }
+}
+
+// Statistics about the writes of a transaction, collected until the transaction is committed.
+struct DbWriteStats {
+    env: Arc<Environment>,
+    last_page_number_at_begin: u64,
+    entries_written: Mutex<BTreeMap<&'static str, u64>>,
+}
+
+/// Statistics about the writes of a committed transaction.
+#[derive(Debug, Default, PartialEq, Eq)]
+pub(crate) struct DbCommitStats {
+    // The number of pages the commit appended to the database. The binding doesn't expose the
+    // number of dirty pages of a transaction, so pages that were reused from the free list aren't
+    // counted.
+    pub(crate) new_pages: u64,
+    // The number of entries that were written to each table.
+    pub(crate) entries_written: BTreeMap<&'static str, u64>,
 }
 
 #[doc(hidden)]
@@ -275,6 +342,8 @@ pub trait TransactionKind {
 
 pub(crate) struct DbTransaction<'env, Mode: TransactionKind> {
     txn: libmdbx::Transaction<'env, Mode::Internal, EnvironmentKind>,
+    // Collected only for write transactions and only when metrics are recorded.
+    write_stats: Option<DbWriteStats>,
 }
 
 impl<'a, Mode: TransactionKind> DbTransaction<'a, Mode> {
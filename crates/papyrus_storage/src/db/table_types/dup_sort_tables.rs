@@ -210,6 +210,7 @@ impl<'env, K: KeyTrait + Debug, V: ValueSerde + Debug, T: DupSortTableType + Dup
 
         let mut cursor = txn.txn.cursor(&self.database)?;
         cursor.put(&main_key, &sub_key_value, WriteFlags::UPSERT)?;
+        txn.count_written_entry(self.name);
 
         let sub_key = T::get_sub_key(key)?;
         // TODO(dvir): consider return the cursor to the original position using prev instead of
@@ -257,6 +258,7 @@ impl<'env, K: KeyTrait + Debug, V: ValueSerde + Debug, T: DupSortTableType + Dup
                 _ => err.into(),
             },
         )?;
+        txn.count_written_entry(self.name);
 
         // In the case of existing main-key and sub-key but different values, because the bytes
         // array of the key suffix and value is not present in the table, the put will
@@ -326,6 +328,7 @@ impl<'env, K: KeyTrait + Debug, V: ValueSerde + Debug, T: DupSortTableType + Dup
                         &sub_key_and_value,
                         WriteFlags::APPEND_DUP | WriteFlags::APPEND,
                     )?;
+                    txn.count_written_entry(self.name);
 
                     Ok(())
                 } else {
@@ -333,6 +336,7 @@ impl<'env, K: KeyTrait + Debug, V: ValueSerde + Debug, T: DupSortTableType + Dup
                 }
             }
             Ok(()) => {
+                txn.count_written_entry(self.name);
                 // In the case of overriding the last key with a bigger value, we need to delete the
                 // old entry.
                 if let Some(prev) = cursor.prev_dup::<DbKeyType<'_>, DbValueType<'_>>()? {
@@ -393,6 +397,7 @@ impl<'env, K: KeyTrait + Debug, V: ValueSerde + Debug, T: DupSortTableType + Dup
                 _ => err.into(),
             },
         )?;
+        txn.count_written_entry(self.name);
 
         // This checks the case where the the sub-key is already the last in the sub tree; in this
         // case, we revert the last put and return an error.
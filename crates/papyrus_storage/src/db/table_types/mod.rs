@@ -4,7 +4,7 @@ use std::marker::PhantomData;
 use libmdbx::Cursor;
 
 use super::serialization::{Key as KeyTrait, ValueSerde};
-use super::{DbResult, DbTransaction, TransactionKind, RW};
+use super::{DbReader, DbResult, DbTransaction, DbWriter, TableIdentifier, TransactionKind, RW};
 
 mod dup_sort_tables;
 mod simple_table;
@@ -102,3 +102,90 @@ pub(crate) trait TableType {}
 // A value place holder for tables where we don't need a value.
 #[derive(Clone, Copy, Debug, Eq, PartialEq, PartialOrd, Ord)]
 pub(crate) struct NoValue;
+
+// Gets the identifiers of the storage tables by their names.
+pub(crate) trait TableOpener {
+    fn open_simple_table<K: KeyTrait + Debug, V: ValueSerde + Debug>(
+        &mut self,
+        name: &'static str,
+    ) -> DbResult<TableIdentifier<K, V, SimpleTable>>;
+
+    fn open_common_prefix_table<
+        MainKey: KeyTrait + Debug,
+        SubKey: KeyTrait + Debug,
+        V: ValueSerde + Debug,
+    >(
+        &mut self,
+        name: &'static str,
+    ) -> DbResult<TableIdentifier<(MainKey, SubKey), V, CommonPrefix>>
+    where
+        (MainKey, SubKey): KeyTrait + Debug;
+}
+
+// The writer creates the tables that don't exist yet.
+impl TableOpener for DbWriter {
+    fn open_simple_table<K: KeyTrait + Debug, V: ValueSerde + Debug>(
+        &mut self,
+        name: &'static str,
+    ) -> DbResult<TableIdentifier<K, V, SimpleTable>> {
+        self.create_simple_table(name)
+    }
+
+    fn open_common_prefix_table<
+        MainKey: KeyTrait + Debug,
+        SubKey: KeyTrait + Debug,
+        V: ValueSerde + Debug,
+    >(
+        &mut self,
+        name: &'static str,
+    ) -> DbResult<TableIdentifier<(MainKey, SubKey), V, CommonPrefix>>
+    where
+        (MainKey, SubKey): KeyTrait + Debug,
+    {
+        self.create_common_prefix_table(name)
+    }
+}
+
+// A reader can't create tables, so it fails if a table doesn't exist.
+impl TableOpener for DbReader {
+    fn open_simple_table<K: KeyTrait + Debug, V: ValueSerde + Debug>(
+        &mut self,
+        name: &'static str,
+    ) -> DbResult<TableIdentifier<K, V, SimpleTable>> {
+        self.verify_table_exists(name)?;
+        Ok(TableIdentifier {
+            name,
+            _key_type: PhantomData {},
+            _value_type: PhantomData {},
+            _table_type: PhantomData {},
+        })
+    }
+
+    fn open_common_prefix_table<
+        MainKey: KeyTrait + Debug,
+        SubKey: KeyTrait + Debug,
+        V: ValueSerde + Debug,
+    >(
+        &mut self,
+        name: &'static str,
+    ) -> DbResult<TableIdentifier<(MainKey, SubKey), V, CommonPrefix>>
+    where
+        (MainKey, SubKey): KeyTrait + Debug,
+    {
+        self.verify_table_exists(name)?;
+        Ok(TableIdentifier {
+            name,
+            _key_type: PhantomData {},
+            _value_type: PhantomData {},
+            _table_type: PhantomData {},
+        })
+    }
+}
+
+impl DbReader {
+    fn verify_table_exists(&self, name: &'static str) -> DbResult<()> {
+        let txn = self.env.begin_ro_txn()?;
+        txn.open_table(Some(name))?;
+        Ok(())
+    }
+}
@@ -90,6 +90,7 @@ impl<'env, K: KeyTrait + Debug, V: ValueSerde + Debug> Table<'env>
         let data = <Self::Value>::serialize(value)?;
         let bin_key = key.serialize()?;
         txn.txn.put(&self.database, bin_key, data, WriteFlags::UPSERT)?;
+        txn.count_written_entry(self.name);
         Ok(())
     }
 
@@ -109,6 +110,7 @@ impl<'env, K: KeyTrait + Debug, V: ValueSerde + Debug> Table<'env>
                 _ => err.into(),
             }
         })?;
+        txn.count_written_entry(self.name);
         Ok(())
     }
 
@@ -127,6 +129,7 @@ impl<'env, K: KeyTrait + Debug, V: ValueSerde + Debug> Table<'env>
                 _ => err.into(),
             },
         )?;
+        txn.count_written_entry(self.name);
         Ok(())
     }
 
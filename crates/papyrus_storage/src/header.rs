@@ -57,7 +57,7 @@ use starknet_api::core::{
     TransactionCommitment,
 };
 use starknet_api::data_availability::L1DataAvailabilityMode;
-use tracing::debug;
+use tracing::{debug, warn};
 
 use crate::db::serialization::NoVersionValueWrapper;
 use crate::db::table_types::{DbCursorTrait, SimpleTable, Table};
@@ -134,7 +134,7 @@ where
     ) -> StorageResult<Self>;
 
     /// Removes a block header and its signature (if exists) from the storage and returns the
-    /// removed data.
+    /// removed data. If the header is missing below the header marker, only the marker is lowered.
     fn revert_header(
         self,
         block_number: BlockNumber,
@@ -316,11 +316,17 @@ impl<'env> HeaderStorageWriter for StorageTxn<'env, RW> {
             );
             return Ok((self, None, None));
         };
+        self.verify_block_is_hot(block_number)?;
 
-        let reverted_header = headers_table
-            .get(&self.txn, &block_number)?
-            .expect("Missing header for block {block_number}.");
         markers_table.upsert(&self.txn, &MarkerKind::Header, &block_number)?;
+        let Some(reverted_header) = headers_table.get(&self.txn, &block_number)? else {
+            // A corrupted storage may be missing headers below the marker. Lowering the marker
+            // lets the sync download the header again.
+            warn!("Missing header for block {block_number}. Only its header marker is reverted.");
+            starknet_version_table.delete(&self.txn, &block_number)?;
+            block_signatures_table.delete(&self.txn, &block_number)?;
+            return Ok((self, None, None));
+        };
         headers_table.delete(&self.txn, &block_number)?;
         block_hash_to_number_table.delete(&self.txn, &reverted_header.block_hash)?;
 
@@ -414,3 +420,21 @@ fn update_marker<'env>(
     markers_table.upsert(txn, &MarkerKind::Header, &block_number.unchecked_next())?;
     Ok(())
 }
+
+// Sets the state diff length of the header of the given block if it's missing, as in the headers
+// synced from the central source. Does nothing if the block has no header.
+pub(crate) fn fill_missing_state_diff_length(
+    txn: &StorageTxn<'_, RW>,
+    block_number: BlockNumber,
+    state_diff_length: usize,
+) -> StorageResult<()> {
+    let headers_table = txn.open_table(&txn.tables.headers)?;
+    let Some(mut block_header) = headers_table.get(&txn.txn, &block_number)? else {
+        return Ok(());
+    };
+    if block_header.state_diff_length.is_none() {
+        block_header.state_diff_length = Some(state_diff_length);
+        headers_table.upsert(&txn.txn, &block_number, &block_header)?;
+    }
+    Ok(())
+}
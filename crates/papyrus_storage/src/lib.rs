@@ -72,12 +72,19 @@
 //! - Code: {major: 0, minor: 1}, Database: {major: 0, minor: 0} will succeed since the major
 //!   versions match and the code's minor version is higher.
 //!
+//! When the database has a lower minor version, it's upgraded to the code's version when it's
+//! opened. A minor version bump that changes existing data has a migration, which runs only if
+//! [`StorageConfig::migrate`] is set. Otherwise, opening the storage fails with
+//! [`StorageVersionError::MigrationRequired`](version::StorageVersionError::MigrationRequired). A
+//! storage opened with [`open_storage_read_only`] is never migrated.
+//!
 //! [`Starknet`]: https://starknet.io/
 //! [`libmdbx`]: https://docs.rs/libmdbx/latest/libmdbx/
 
 pub mod base_layer;
 pub mod body;
 pub mod class;
+pub mod cold_storage;
 pub mod compiled_class;
 #[cfg(feature = "document_calls")]
 pub mod document_calls;
@@ -87,13 +94,18 @@ pub mod utils;
 pub mod compression_utils;
 pub mod db;
 pub mod header;
+mod migration;
 pub mod mmap_file;
 mod serialization;
 pub mod state;
-mod version;
+pub mod version;
 
 mod deprecated;
 
+#[cfg(test)]
+#[path = "storage_test.rs"]
+mod storage_test;
+
 #[cfg(test)]
 mod test_instances;
 
@@ -103,6 +115,7 @@ pub mod test_utils;
 use std::collections::{BTreeMap, HashMap};
 use std::fmt::Debug;
 use std::sync::Arc;
+use std::time::Instant;
 
 use body::events::EventIndex;
 use cairo_lang_starknet_classes::casm_contract_class::CasmContractClass;
@@ -118,7 +131,12 @@ use mmap_file::{
     Reader,
     Writer,
 };
-use papyrus_config::dumping::{append_sub_config_name, ser_param, SerializeConfig};
+use papyrus_config::dumping::{
+    append_sub_config_name,
+    ser_optional_sub_config,
+    ser_param,
+    SerializeConfig,
+};
 use papyrus_config::{ParamPath, ParamPrivacyInput, SerializedParam};
 use papyrus_proc_macros::latency_histogram;
 use serde::{Deserialize, Serialize};
@@ -129,13 +147,15 @@ use starknet_api::state::{ContractClass, StateNumber, StorageKey, ThinStateDiff}
 use starknet_api::transaction::{Transaction, TransactionHash, TransactionOutput};
 use starknet_types_core::felt::Felt;
 use tracing::{debug, warn};
-use validator::Validate;
-use version::{StorageVersionError, Version};
+use validator::{Validate, ValidationError};
+use version::{StorageVersionError, StorageVersionInfo, Version, VersionComponent};
 
 use crate::body::TransactionIndex;
-use crate::db::table_types::SimpleTable;
+use crate::cold_storage::{ColdFiles, ColdStorageConfig};
+use crate::db::table_types::{SimpleTable, TableOpener};
 use crate::db::{
     open_env,
+    open_env_read_only,
     DbConfig,
     DbError,
     DbReader,
@@ -148,8 +168,10 @@ use crate::db::{
     RW,
 };
 use crate::header::StorageBlockHeader;
+use crate::migration::{pending_migrations, upgrade_version};
 use crate::mmap_file::MMapFileStats;
 use crate::state::data::IndexedDeprecatedContractClass;
+use crate::utils::update_commit_metrics;
 pub use crate::utils::update_storage_metrics;
 use crate::version::{VersionStorageReader, VersionStorageWriter};
 
@@ -157,62 +179,113 @@ use crate::version::{VersionStorageReader, VersionStorageWriter};
 /// The current version of the storage state code.
 pub const STORAGE_VERSION_STATE: Version = Version { major: 1, minor: 1 };
 /// The current version of the storage blocks code.
-pub const STORAGE_VERSION_BLOCKS: Version = Version { major: 2, minor: 0 };
+pub const STORAGE_VERSION_BLOCKS: Version = Version { major: 2, minor: 2 };
 
 /// Opens a storage and returns a [`StorageReader`] and a [`StorageWriter`].
 pub fn open_storage(
     storage_config: StorageConfig,
 ) -> StorageResult<(StorageReader, StorageWriter)> {
     let (db_reader, mut db_writer) = open_env(&storage_config.db_config)?;
-    let tables = Arc::new(Tables {
-        block_hash_to_number: db_writer.create_simple_table("block_hash_to_number")?,
-        block_signatures: db_writer.create_simple_table("block_signatures")?,
-        casms: db_writer.create_simple_table("casms")?,
-        contract_storage: db_writer.create_common_prefix_table("contract_storage")?,
-        declared_classes: db_writer.create_simple_table("declared_classes")?,
-        declared_classes_block: db_writer.create_simple_table("declared_classes_block")?,
-        deprecated_declared_classes: db_writer
-            .create_simple_table("deprecated_declared_classes")?,
-        deployed_contracts: db_writer.create_simple_table("deployed_contracts")?,
-        events: db_writer.create_common_prefix_table("events")?,
-        headers: db_writer.create_simple_table("headers")?,
-        markers: db_writer.create_simple_table("markers")?,
-        nonces: db_writer.create_common_prefix_table("nonces")?,
-        file_offsets: db_writer.create_simple_table("file_offsets")?,
-        state_diffs: db_writer.create_simple_table("state_diffs")?,
-        transaction_hash_to_idx: db_writer.create_simple_table("transaction_hash_to_idx")?,
-        transaction_metadata: db_writer.create_simple_table("transaction_metadata")?,
-
-        // Version tables
-        starknet_version: db_writer.create_simple_table("starknet_version")?,
-        storage_version: db_writer.create_simple_table("storage_version")?,
-    });
+    let tables = Arc::new(open_tables(&mut db_writer)?);
     let (file_writers, file_readers) = open_storage_files(
         &storage_config.db_config,
         storage_config.mmap_file_config,
         db_reader.clone(),
         &tables.file_offsets,
+        false,
     )?;
+    let cold_files = storage_config.cold_storage.map(|config| Arc::new(ColdFiles::new(config)));
 
     let reader = StorageReader {
         db_reader,
         tables: tables.clone(),
         scope: storage_config.scope,
         file_readers,
+        cold_files: cold_files.clone(),
+        read_only: false,
+    };
+    let writer = StorageWriter {
+        db_writer,
+        tables,
+        scope: storage_config.scope,
+        file_writers,
+        cold_files,
+        component: StorageWriterComponent::default(),
     };
-    let writer = StorageWriter { db_writer, tables, scope: storage_config.scope, file_writers };
 
-    let writer = set_version_if_needed(reader.clone(), writer)?;
+    let writer = set_version_if_needed(reader.clone(), writer, storage_config.migrate)?;
     verify_storage_version(reader.clone())?;
     Ok((reader, writer))
 }
 
+/// Opens an existing storage without write access and returns a [`StorageReader`] to it.
+/// The storage may be written concurrently by another node that opened it with [`open_storage`].
+pub fn open_storage_read_only(storage_config: StorageConfig) -> StorageResult<StorageReader> {
+    let mut db_reader = open_env_read_only(&storage_config.db_config)?;
+    let tables = Arc::new(open_tables(&mut db_reader)?);
+    let (_, file_readers) = open_storage_files(
+        &storage_config.db_config,
+        storage_config.mmap_file_config,
+        db_reader.clone(),
+        &tables.file_offsets,
+        true,
+    )?;
+
+    let reader = StorageReader {
+        db_reader,
+        tables,
+        scope: storage_config.scope,
+        file_readers,
+        cold_files: storage_config.cold_storage.map(|config| Arc::new(ColdFiles::new(config))),
+        read_only: true,
+    };
+    let Some(existing_storage_version) = get_storage_version(reader.clone())? else {
+        return Err(StorageError::UninitializedStorage);
+    };
+    // A read-only storage can't be migrated, so the node that writes it must migrate it first.
+    verify_no_pending_migrations(&existing_storage_version)?;
+    verify_storage_version(reader.clone())?;
+    Ok(reader)
+}
+
+// Opens the tables of the storage. A writer creates the tables that don't exist yet, while a reader
+// fails on them.
+fn open_tables(db: &mut impl TableOpener) -> StorageResult<Tables> {
+    Ok(Tables {
+        block_hash_to_number: db.open_simple_table("block_hash_to_number")?,
+        block_signatures: db.open_simple_table("block_signatures")?,
+        casms: db.open_simple_table("casms")?,
+        cold_blocks: db.open_simple_table("cold_blocks")?,
+        contract_storage: db.open_common_prefix_table("contract_storage")?,
+        declared_classes: db.open_simple_table("declared_classes")?,
+        declared_classes_block: db.open_simple_table("declared_classes_block")?,
+        deprecated_declared_classes: db.open_simple_table("deprecated_declared_classes")?,
+        deployed_contracts: db.open_simple_table("deployed_contracts")?,
+        events: db.open_common_prefix_table("events")?,
+        headers: db.open_simple_table("headers")?,
+        markers: db.open_simple_table("markers")?,
+        nonces: db.open_common_prefix_table("nonces")?,
+        file_offsets: db.open_simple_table("file_offsets")?,
+        state_diffs: db.open_simple_table("state_diffs")?,
+        transaction_hash_to_idx: db.open_simple_table("transaction_hash_to_idx")?,
+        transaction_metadata: db.open_simple_table("transaction_metadata")?,
+        migration_progress: db.open_simple_table("migration_progress")?,
+
+        // Version tables
+        starknet_version: db.open_simple_table("starknet_version")?,
+        storage_version: db.open_simple_table("storage_version")?,
+    })
+}
+
 // In case storage version does not exist, set it to the crate version.
 // Expected to happen once - when the node is launched for the first time.
 // If the storage scope has changed, update accordingly.
+// If the storage version is lower than the crate version, upgrade it. Fails if the upgrade requires
+// migrations and `migrate` is false.
 fn set_version_if_needed(
     reader: StorageReader,
     mut writer: StorageWriter,
+    migrate: bool,
 ) -> StorageResult<StorageWriter> {
     let Some(existing_storage_version) = get_storage_version(reader)? else {
         // Initialize the storage version.
@@ -250,51 +323,60 @@ fn set_version_if_needed(
             }
         }
     }
+    // After a change to state-only, only the state version remains.
+    let existing_storage_version = match existing_storage_version {
+        StorageVersion::FullArchive(FullArchiveVersion { state_version, blocks_version: _ })
+            if writer.scope == StorageScope::StateOnly =>
+        {
+            StorageVersion::StateOnly(StateOnlyVersion { state_version })
+        }
+        existing_storage_version => existing_storage_version,
+    };
     // Update the version if it's lower than the crate version.
-    let mut wtxn = writer.begin_rw_txn()?;
+    if !migrate {
+        verify_no_pending_migrations(&existing_storage_version)?;
+    }
     match existing_storage_version {
         StorageVersion::FullArchive(FullArchiveVersion { state_version, blocks_version }) => {
-            // This allow is for when STORAGE_VERSION_STATE.minor = 0.
-            #[allow(clippy::absurd_extreme_comparisons)]
-            if STORAGE_VERSION_STATE.major == state_version.major
-                && STORAGE_VERSION_STATE.minor > state_version.minor
-            {
-                debug!(
-                    "Updating the storage state version from {:?} to {:?}",
-                    state_version, STORAGE_VERSION_STATE
-                );
-                wtxn = wtxn.set_state_version(&STORAGE_VERSION_STATE)?;
-            }
-            // This allow is for when STORAGE_VERSION_BLOCKS.minor = 0.
-            #[allow(clippy::absurd_extreme_comparisons)]
-            if STORAGE_VERSION_BLOCKS.major == blocks_version.major
-                && STORAGE_VERSION_BLOCKS.minor > blocks_version.minor
-            {
-                debug!(
-                    "Updating the storage blocks version from {:?} to {:?}",
-                    blocks_version, STORAGE_VERSION_BLOCKS
-                );
-                wtxn = wtxn.set_blocks_version(&STORAGE_VERSION_BLOCKS)?;
-            }
+            upgrade_version(&mut writer, VersionComponent::State, &state_version)?;
+            upgrade_version(&mut writer, VersionComponent::Blocks, &blocks_version)?;
         }
         StorageVersion::StateOnly(StateOnlyVersion { state_version }) => {
-            // This allow is for when STORAGE_VERSION_STATE.minor = 0.
-            #[allow(clippy::absurd_extreme_comparisons)]
-            if STORAGE_VERSION_STATE.major == state_version.major
-                && STORAGE_VERSION_STATE.minor > state_version.minor
-            {
-                debug!(
-                    "Updating the storage state version from {:?} to {:?}",
-                    state_version, STORAGE_VERSION_STATE
-                );
-                wtxn = wtxn.set_state_version(&STORAGE_VERSION_STATE)?;
-            }
+            upgrade_version(&mut writer, VersionComponent::State, &state_version)?;
         }
     }
-    wtxn.commit()?;
     Ok(writer)
 }
 
+// Fails if upgrading the storage version to the crate version requires migrations.
+fn verify_no_pending_migrations(storage_version: &StorageVersion) -> StorageResult<()> {
+    let versions = match storage_version {
+        StorageVersion::FullArchive(FullArchiveVersion { state_version, blocks_version }) => {
+            vec![
+                (VersionComponent::State, state_version),
+                (VersionComponent::Blocks, blocks_version),
+            ]
+        }
+        StorageVersion::StateOnly(StateOnlyVersion { state_version }) => {
+            vec![(VersionComponent::State, state_version)]
+        }
+    };
+    for (component, version) in versions {
+        let migrations = pending_migrations(component, version);
+        if !migrations.is_empty() {
+            return Err(StorageError::StorageVersionInconsistency(
+                StorageVersionError::MigrationRequired {
+                    component,
+                    storage_version: version.clone(),
+                    crate_version: component.crate_version(),
+                    migrations: migrations.iter().map(|migration| migration.name).collect(),
+                },
+            ));
+        }
+    }
+    Ok(())
+}
+
 #[derive(Debug)]
 struct FullArchiveVersion {
     state_version: Version,
@@ -399,24 +481,75 @@ pub enum StorageScope {
     StateOnly,
 }
 
+/// The component that writes to the storage. The storage write metrics are labeled by it.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum StorageWriterComponent {
+    /// The writer wasn't assigned to any component.
+    #[default]
+    Unassigned,
+    /// The sync from the central source.
+    CentralSync,
+    /// The sync from other peers.
+    P2PSync,
+    /// The consensus, on chains where it writes the blocks it decides on.
+    Consensus,
+    /// The admin API for injecting blocks.
+    BlockInjection,
+    /// The storage scan that reverts blocks with missing data.
+    StorageScan,
+    /// The commit of the genesis state of a chain to an empty storage.
+    Genesis,
+    /// The move of old blocks to the cold storage.
+    ColdStorage,
+    /// The migration of the storage to the crate's storage version.
+    Migration,
+}
+
+impl StorageWriterComponent {
+    /// Returns the value of the component label in the storage write metrics.
+    pub fn as_label(&self) -> &'static str {
+        match self {
+            StorageWriterComponent::Unassigned => "unassigned",
+            StorageWriterComponent::CentralSync => "central_sync",
+            StorageWriterComponent::P2PSync => "p2p_sync",
+            StorageWriterComponent::Consensus => "consensus",
+            StorageWriterComponent::BlockInjection => "block_injection",
+            StorageWriterComponent::StorageScan => "storage_scan",
+            StorageWriterComponent::Genesis => "genesis",
+            StorageWriterComponent::ColdStorage => "cold_storage",
+            StorageWriterComponent::Migration => "migration",
+        }
+    }
+}
+
 /// A struct for starting RO transactions ([`StorageTxn`]) to the storage.
 #[derive(Clone)]
 pub struct StorageReader {
     db_reader: DbReader,
     file_readers: FileHandlers<RO>,
+    cold_files: Option<Arc<ColdFiles>>,
     tables: Arc<Tables>,
     scope: StorageScope,
+    read_only: bool,
 }
 
 impl StorageReader {
+    /// Returns whether the storage was opened without write access, using
+    /// [`open_storage_read_only`].
+    pub fn is_read_only(&self) -> bool {
+        self.read_only
+    }
+
     /// Takes a snapshot of the current state of the storage and returns a [`StorageTxn`] for
     /// reading data from the storage.
     pub fn begin_ro_txn(&self) -> StorageResult<StorageTxn<'_, RO>> {
         Ok(StorageTxn {
             txn: self.db_reader.begin_ro_txn()?,
             file_handlers: self.file_readers.clone(),
+            cold_files: self.cold_files.clone(),
             tables: self.tables.clone(),
             scope: self.scope,
+            writer_component: None,
         })
     }
 
@@ -438,6 +571,17 @@ impl StorageReader {
     pub fn get_scope(&self) -> StorageScope {
         self.scope
     }
+
+    /// Returns the schema versions of the storage and the versions the storage code supports.
+    pub fn storage_version_info(&self) -> StorageResult<StorageVersionInfo> {
+        let txn = self.begin_ro_txn()?;
+        Ok(StorageVersionInfo {
+            state_version: txn.get_state_version()?,
+            blocks_version: txn.get_blocks_version()?,
+            supported_state_version: STORAGE_VERSION_STATE,
+            supported_blocks_version: STORAGE_VERSION_BLOCKS,
+        })
+    }
 }
 
 /// A struct for starting RW transactions ([`StorageTxn`]) to the storage.
@@ -446,8 +590,10 @@ impl StorageReader {
 pub struct StorageWriter {
     db_writer: DbWriter,
     file_writers: FileHandlers<RW>,
+    cold_files: Option<Arc<ColdFiles>>,
     tables: Arc<Tables>,
     scope: StorageScope,
+    component: StorageWriterComponent,
 }
 
 impl StorageWriter {
@@ -457,10 +603,17 @@ impl StorageWriter {
         Ok(StorageTxn {
             txn: self.db_writer.begin_rw_txn()?,
             file_handlers: self.file_writers.clone(),
+            cold_files: self.cold_files.clone(),
             tables: self.tables.clone(),
             scope: self.scope,
+            writer_component: Some(self.component),
         })
     }
+
+    /// Sets the component that writes to the storage with this writer.
+    pub fn set_component(&mut self, component: StorageWriterComponent) {
+        self.component = component;
+    }
 }
 
 /// A struct for interacting with the storage.
@@ -468,16 +621,28 @@ impl StorageWriter {
 pub struct StorageTxn<'env, Mode: TransactionKind> {
     txn: DbTransaction<'env, Mode>,
     file_handlers: FileHandlers<Mode>,
+    // None if the storage has no cold storage.
+    cold_files: Option<Arc<ColdFiles>>,
     tables: Arc<Tables>,
     scope: StorageScope,
+    // None for read transactions.
+    writer_component: Option<StorageWriterComponent>,
 }
 
 impl<'env> StorageTxn<'env, RW> {
     /// Commits the changes made in the transaction to the storage.
-    #[latency_histogram("storage_commit_latency_seconds", false)]
     pub fn commit(self) -> StorageResult<()> {
+        let start = Instant::now();
         self.file_handlers.flush();
-        Ok(self.txn.commit()?)
+        let commit_stats = self.txn.commit()?;
+        if let Some(commit_stats) = commit_stats {
+            update_commit_metrics(
+                self.writer_component.unwrap_or_default(),
+                start.elapsed(),
+                commit_stats,
+            );
+        }
+        Ok(())
     }
 }
 
@@ -513,6 +678,8 @@ struct_field_names! {
         block_hash_to_number: TableIdentifier<BlockHash, NoVersionValueWrapper<BlockNumber>, SimpleTable>,
         block_signatures: TableIdentifier<BlockNumber, VersionZeroWrapper<BlockSignature>, SimpleTable>,
         casms: TableIdentifier<ClassHash, VersionZeroWrapper<LocationInFile>, SimpleTable>,
+        // The location of each block of the cold storage in its cold file.
+        cold_blocks: TableIdentifier<BlockNumber, VersionZeroWrapper<LocationInFile>, SimpleTable>,
         // Empirically, defining the common prefix as (ContractAddress, StorageKey) is better space-wise than defining the
         // common prefix only as ContractAddress.
         contract_storage: TableIdentifier<((ContractAddress, StorageKey), BlockNumber), NoVersionValueWrapper<Felt>, CommonPrefix>,
@@ -530,6 +697,8 @@ struct_field_names! {
         transaction_hash_to_idx: TableIdentifier<TransactionHash, NoVersionValueWrapper<TransactionIndex>, SimpleTable>,
         // TODO(dvir): consider not saving transaction hash and calculating it from the transaction on demand.
         transaction_metadata: TableIdentifier<TransactionIndex, VersionZeroWrapper<TransactionMetadata>, SimpleTable>,
+        // The block each storage migration that was interrupted continues from, by the migration name.
+        migration_progress: TableIdentifier<String, NoVersionValueWrapper<BlockNumber>, SimpleTable>,
 
         // Version tables
         starknet_version: TableIdentifier<BlockNumber, VersionZeroWrapper<StarknetVersion>, SimpleTable>,
@@ -603,6 +772,22 @@ pub enum StorageError {
          {block_number}."
     )]
     BlockSignatureForNonExistingBlock { block_number: BlockNumber, block_signature: BlockSignature },
+    #[error("The storage wasn't initialized by a node with write access.")]
+    UninitializedStorage,
+    #[error("Block {block_number} is in the cold storage, but the cold storage isn't configured.")]
+    ColdStorageNotConfigured { block_number: BlockNumber },
+    #[error("Failed decoding block {block_number} from the cold storage: {reason}")]
+    ColdBlockDecodingError { block_number: BlockNumber, reason: String },
+    #[error(
+        "Attempt to revert block {block_number}, which was moved to the cold storage (cold \
+         storage marker: {cold_storage_marker})."
+    )]
+    RevertInColdStorage { block_number: BlockNumber, cold_storage_marker: BlockNumber },
+    #[error(
+        "The events of block {block_number} aren't indexed, since it was moved to the cold \
+         storage (cold storage marker: {cold_storage_marker})."
+    )]
+    EventsInColdStorage { block_number: BlockNumber, cold_storage_marker: BlockNumber },
 }
 
 /// A type alias that maps to std::result::Result<T, StorageError>.
@@ -611,25 +796,58 @@ pub type StorageResult<V> = std::result::Result<V, StorageError>;
 /// A struct for the configuration of the storage.
 #[allow(missing_docs)]
 #[derive(Serialize, Debug, Default, Deserialize, Clone, PartialEq, Validate)]
+#[validate(schema(function = "validate_storage_config"))]
 pub struct StorageConfig {
     #[validate]
     pub db_config: DbConfig,
     #[validate]
     pub mmap_file_config: MmapFileConfig,
     pub scope: StorageScope,
+    pub read_only: bool,
+    pub migrate: bool,
+    #[validate]
+    pub cold_storage: Option<ColdStorageConfig>,
+}
+
+fn validate_storage_config(config: &StorageConfig) -> Result<(), ValidationError> {
+    if config.cold_storage.is_some() && config.scope != StorageScope::FullArchive {
+        return Err(ValidationError::new("cold_storage requires the full archive storage scope"));
+    }
+    if config.migrate && config.read_only {
+        return Err(ValidationError::new("migrate requires write access to the storage"));
+    }
+    Ok(())
 }
 
 impl SerializeConfig for StorageConfig {
     fn dump(&self) -> BTreeMap<ParamPath, SerializedParam> {
-        let mut dumped_config = BTreeMap::from_iter([ser_param(
-            "scope",
-            &self.scope,
-            "The categories of data saved in storage.",
-            ParamPrivacyInput::Public,
-        )]);
+        let mut dumped_config = BTreeMap::from_iter([
+            ser_param(
+                "scope",
+                &self.scope,
+                "The categories of data saved in storage.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "read_only",
+                &self.read_only,
+                "If true, the storage is opened without write access and is expected to be \
+                 written by another node.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "migrate",
+                &self.migrate,
+                "If true, a storage with an older version is migrated to the node's storage \
+                 version when it's opened. Otherwise, opening a storage that requires a \
+                 migration fails.",
+                ParamPrivacyInput::Public,
+            ),
+        ]);
         dumped_config
             .extend(append_sub_config_name(self.mmap_file_config.dump(), "mmap_file_config"));
         dumped_config.extend(append_sub_config_name(self.db_config.dump(), "db_config"));
+        dumped_config.extend(ser_optional_sub_config(&self.cold_storage, "cold_storage"));
         dumped_config
     }
 }
@@ -649,6 +867,8 @@ pub struct DbStats {
 // - CompiledClass <= Class <= State <= Header
 // - Body <= Header
 // - BaseLayerBlock <= Header
+// - ColdStorage <= CompiledClass, Body
+// - ColdStorageReclaim <= ColdStorage
 // Event is currently unsupported.
 pub(crate) enum MarkerKind {
     Header,
@@ -658,6 +878,8 @@ pub(crate) enum MarkerKind {
     Class,
     CompiledClass,
     BaseLayerBlock,
+    ColdStorage,
+    ColdStorageReclaim,
 }
 
 pub(crate) type MarkersTable<'env> =
@@ -796,6 +1018,7 @@ fn open_storage_files(
     mmap_file_config: MmapFileConfig,
     db_reader: DbReader,
     file_offsets_table: &TableIdentifier<OffsetKind, NoVersionValueWrapper<usize>, SimpleTable>,
+    read_only: bool,
 ) -> StorageResult<(FileHandlers<RW>, FileHandlers<RO>)> {
     let db_transaction = db_reader.begin_ro_txn()?;
     let table = db_transaction.open_table(file_offsets_table)?;
@@ -807,6 +1030,7 @@ fn open_storage_files(
         mmap_file_config.clone(),
         db_config.path().join("thin_state_diff.dat"),
         thin_state_diff_offset,
+        read_only,
     )?;
 
     let contract_class_offset =
@@ -815,11 +1039,16 @@ fn open_storage_files(
         mmap_file_config.clone(),
         db_config.path().join("contract_class.dat"),
         contract_class_offset,
+        read_only,
     )?;
 
     let casm_offset = table.get(&db_transaction, &OffsetKind::Casm)?.unwrap_or_default();
-    let (casm_writer, casm_reader) =
-        open_file(mmap_file_config.clone(), db_config.path().join("casm.dat"), casm_offset)?;
+    let (casm_writer, casm_reader) = open_file(
+        mmap_file_config.clone(),
+        db_config.path().join("casm.dat"),
+        casm_offset,
+        read_only,
+    )?;
 
     let deprecated_contract_class_offset =
         table.get(&db_transaction, &OffsetKind::DeprecatedContractClass)?.unwrap_or_default();
@@ -827,6 +1056,7 @@ fn open_storage_files(
         mmap_file_config.clone(),
         db_config.path().join("deprecated_contract_class.dat"),
         deprecated_contract_class_offset,
+        read_only,
     )?;
 
     let transaction_output_offset =
@@ -835,12 +1065,17 @@ fn open_storage_files(
         mmap_file_config.clone(),
         db_config.path().join("transaction_output.dat"),
         transaction_output_offset,
+        read_only,
     )?;
 
     let transaction_offset =
         table.get(&db_transaction, &OffsetKind::Transaction)?.unwrap_or_default();
-    let (transaction_writer, transaction_reader) =
-        open_file(mmap_file_config, db_config.path().join("transaction.dat"), transaction_offset)?;
+    let (transaction_writer, transaction_reader) = open_file(
+        mmap_file_config,
+        db_config.path().join("transaction.dat"),
+        transaction_offset,
+        read_only,
+    )?;
 
     Ok((
         FileHandlers {
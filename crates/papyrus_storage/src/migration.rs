@@ -0,0 +1,187 @@
+//! Forward migrations of the storage between minor versions.
+//!
+//! A minor version bump that requires rewriting existing data registers a [`Migration`] in
+//! [`MIGRATIONS`]. A bump without a registered migration only changes the stored version. The
+//! migrations run only when the storage is opened with [`StorageConfig::migrate`], since they may
+//! take long on a big storage.
+//!
+//! A migration runs in batches of blocks, each committed together with the progress of the
+//! migration, so a migration that was interrupted resumes from its last committed batch. The last
+//! batch sets the stored version to the version the migration upgrades to.
+//!
+//! [`StorageConfig::migrate`]: crate::StorageConfig::migrate
+
+#[cfg(test)]
+#[path = "migration_test.rs"]
+mod migration_test;
+
+use starknet_api::block::BlockNumber;
+use tracing::info;
+
+use crate::db::table_types::Table;
+use crate::db::RW;
+use crate::header::fill_missing_state_diff_length;
+use crate::state::StateStorageReader;
+use crate::version::{Version, VersionComponent, VersionStorageWriter};
+use crate::{StorageResult, StorageTxn, StorageWriter, StorageWriterComponent};
+
+// The number of blocks a migration handles in a single transaction.
+pub(crate) const MIGRATION_BATCH_SIZE: u64 = 1000;
+
+// Migrates the blocks of a batch, starting at the given block and with at most the given number of
+// blocks. Returns the block the next batch starts at, or None if the migration is done.
+type MigrateBatch =
+    for<'env> fn(&StorageTxn<'env, RW>, BlockNumber, u64) -> StorageResult<Option<BlockNumber>>;
+
+// A migration of a component of the storage from a minor version to the next one.
+pub(crate) struct Migration {
+    // Identifies the migration in its progress and in the logs.
+    pub(crate) name: &'static str,
+    pub(crate) component: VersionComponent,
+    // The version the migration upgrades from.
+    pub(crate) from: Version,
+    migrate_batch: MigrateBatch,
+}
+
+impl Migration {
+    // The version the migration upgrades to.
+    pub(crate) fn to(&self) -> Version {
+        Version { major: self.from.major, minor: self.from.minor + 1 }
+    }
+}
+
+// The registered migrations, ordered by their component and version.
+pub(crate) const MIGRATIONS: &[Migration] = &[Migration {
+    name: "fill_state_diff_lengths",
+    component: VersionComponent::Blocks,
+    from: Version { major: 2, minor: 1 },
+    migrate_batch: fill_state_diff_lengths,
+}];
+
+// Returns the migrations needed to upgrade the given component from the storage version to the
+// crate version. Empty if the versions have different majors.
+pub(crate) fn pending_migrations(
+    component: VersionComponent,
+    storage_version: &Version,
+) -> Vec<&'static Migration> {
+    let crate_version = component.crate_version();
+    MIGRATIONS
+        .iter()
+        .filter(|migration| {
+            migration.component == component
+                && migration.from.major == storage_version.major
+                && migration.from.major == crate_version.major
+                && migration.from.minor >= storage_version.minor
+                && migration.from.minor < crate_version.minor
+        })
+        .collect()
+}
+
+// Upgrades the given component from the storage version to the crate version, one minor version
+// at a time. Runs the migrations of the versions that have one, and assumes the caller checked that
+// they may run. Does nothing if the versions have different majors.
+pub(crate) fn upgrade_version(
+    writer: &mut StorageWriter,
+    component: VersionComponent,
+    storage_version: &Version,
+) -> StorageResult<()> {
+    let crate_version = component.crate_version();
+    if storage_version.major != crate_version.major {
+        return Ok(());
+    }
+    for minor in storage_version.minor..crate_version.minor {
+        let from = Version { major: storage_version.major, minor };
+        match MIGRATIONS
+            .iter()
+            .find(|migration| migration.component == component && migration.from == from)
+        {
+            Some(migration) => run_migration(writer, migration, MIGRATION_BATCH_SIZE)?,
+            None => {
+                let to = Version { major: from.major, minor: minor + 1 };
+                info!("Updating the {component} storage version from {from} to {to}.");
+                set_version(writer.begin_rw_txn()?, component, &to)?.commit()?;
+            }
+        }
+    }
+    Ok(())
+}
+
+// Runs the given migration to its end, resuming from its progress if it was interrupted.
+pub(crate) fn run_migration(
+    writer: &mut StorageWriter,
+    migration: &Migration,
+    batch_size: u64,
+) -> StorageResult<()> {
+    info!(
+        "Running the storage migration {} of the {} storage version from {} to {}.",
+        migration.name,
+        migration.component,
+        migration.from,
+        migration.to()
+    );
+    let component = writer.component;
+    writer.set_component(StorageWriterComponent::Migration);
+    let result = loop {
+        match run_migration_batch(writer, migration, batch_size) {
+            Ok(true) => break Ok(()),
+            Ok(false) => {}
+            Err(err) => break Err(err),
+        }
+    };
+    writer.set_component(component);
+    result
+}
+
+// Runs the next batch of the given migration. Returns whether the migration is done.
+pub(crate) fn run_migration_batch(
+    writer: &mut StorageWriter,
+    migration: &Migration,
+    batch_size: u64,
+) -> StorageResult<bool> {
+    let txn = writer.begin_rw_txn()?;
+    let progress_table = txn.open_table(&txn.tables.migration_progress)?;
+    let key = migration.name.to_string();
+    let first_block = progress_table.get(&txn.txn, &key)?.unwrap_or_default();
+    match (migration.migrate_batch)(&txn, first_block, batch_size)? {
+        Some(next_block) => {
+            progress_table.upsert(&txn.txn, &key, &next_block)?;
+            txn.commit()?;
+            info!("Storage migration {}: migrated the blocks up to {next_block}.", migration.name);
+            Ok(false)
+        }
+        None => {
+            progress_table.delete(&txn.txn, &key)?;
+            set_version(txn, migration.component, &migration.to())?.commit()?;
+            info!("Storage migration {} is done.", migration.name);
+            Ok(true)
+        }
+    }
+}
+
+fn set_version<'env>(
+    txn: StorageTxn<'env, RW>,
+    component: VersionComponent,
+    version: &Version,
+) -> StorageResult<StorageTxn<'env, RW>> {
+    match component {
+        VersionComponent::State => txn.set_state_version(version),
+        VersionComponent::Blocks => txn.set_blocks_version(version),
+    }
+}
+
+// Blocks 2.1 -> 2.2: sets the state diff length of the headers synced from the central source,
+// which were written without it.
+fn fill_state_diff_lengths(
+    txn: &StorageTxn<'_, RW>,
+    first_block: BlockNumber,
+    batch_size: u64,
+) -> StorageResult<Option<BlockNumber>> {
+    let state_marker = txn.get_state_marker()?;
+    let end_block = BlockNumber(first_block.0.saturating_add(batch_size)).min(state_marker);
+    for block_number in first_block.iter_up_to(end_block) {
+        if let Some(state_diff) = txn.get_state_diff(block_number)? {
+            fill_missing_state_diff_length(txn, block_number, state_diff.len())?;
+        }
+    }
+    Ok(if end_block < state_marker { Some(end_block) } else { None })
+}
@@ -0,0 +1,187 @@
+use assert_matches::assert_matches;
+use indexmap::IndexMap;
+use pretty_assertions::assert_eq;
+use starknet_api::block::{BlockHeader, BlockNumber};
+use starknet_api::core::{ContractAddress, Nonce};
+use starknet_api::state::ThinStateDiff;
+use starknet_types_core::felt::Felt;
+use validator::Validate;
+
+use crate::db::table_types::Table;
+use crate::header::{HeaderStorageReader, HeaderStorageWriter};
+use crate::migration::{pending_migrations, run_migration_batch, Migration, MIGRATIONS};
+use crate::state::StateStorageWriter;
+use crate::test_utils::get_test_config;
+use crate::version::{
+    StorageVersionError,
+    Version,
+    VersionComponent,
+    VersionStorageReader,
+    VERSION_BLOCKS_KEY,
+};
+use crate::{
+    open_storage,
+    open_storage_read_only,
+    StorageError,
+    StorageReader,
+    StorageWriter,
+    STORAGE_VERSION_BLOCKS,
+    STORAGE_VERSION_STATE,
+};
+
+const N_BLOCKS: u64 = 5;
+
+fn fill_state_diff_lengths_migration() -> &'static Migration {
+    MIGRATIONS.iter().find(|migration| migration.name == "fill_state_diff_lengths").unwrap()
+}
+
+// The state diff of each block has a nonce per block before it, so the lengths differ.
+fn state_diff(block_number: u64) -> ThinStateDiff {
+    ThinStateDiff {
+        nonces: IndexMap::from_iter(
+            (0..block_number)
+                .map(|i| (ContractAddress::from(i + 1), Nonce(Felt::from(block_number)))),
+        ),
+        ..Default::default()
+    }
+}
+
+// Appends N_BLOCKS blocks whose headers have no state diff length, as the headers synced from the
+// central source before the blocks version 2.2, and sets the blocks version to the version of the
+// migration.
+fn write_blocks_of_old_version(writer: &mut StorageWriter) {
+    let mut txn = writer.begin_rw_txn().unwrap();
+    for block_number in 0..N_BLOCKS {
+        let header = BlockHeader { block_number: BlockNumber(block_number), ..Default::default() };
+        txn = txn
+            .append_header(BlockNumber(block_number), &header)
+            .unwrap()
+            .append_state_diff(BlockNumber(block_number), state_diff(block_number))
+            .unwrap();
+    }
+    let headers_table = txn.open_table(&txn.tables.headers).unwrap();
+    for block_number in 0..N_BLOCKS {
+        let mut header = headers_table.get(&txn.txn, &BlockNumber(block_number)).unwrap().unwrap();
+        header.state_diff_length = None;
+        headers_table.upsert(&txn.txn, &BlockNumber(block_number), &header).unwrap();
+    }
+    let version_table = txn.open_table(&txn.tables.storage_version).unwrap();
+    version_table
+        .upsert(
+            &txn.txn,
+            &VERSION_BLOCKS_KEY.to_string(),
+            &fill_state_diff_lengths_migration().from,
+        )
+        .unwrap();
+    txn.commit().unwrap();
+}
+
+fn state_diff_lengths(reader: &StorageReader) -> Vec<Option<usize>> {
+    let txn = reader.begin_ro_txn().unwrap();
+    (0..N_BLOCKS)
+        .map(|block_number| {
+            txn.get_block_header(BlockNumber(block_number)).unwrap().unwrap().state_diff_length
+        })
+        .collect()
+}
+
+#[test]
+fn migration_is_required_unless_migrate_is_set() {
+    let (mut config, _temp_dir) = get_test_config(None);
+    let (reader, mut writer) = open_storage(config.clone()).unwrap();
+    write_blocks_of_old_version(&mut writer);
+    drop((reader, writer));
+
+    let migration = fill_state_diff_lengths_migration();
+    let Err(err) = open_storage(config.clone()) else {
+        panic!("Unexpected Ok.");
+    };
+    assert_matches!(
+        err,
+        StorageError::StorageVersionInconsistency(StorageVersionError::MigrationRequired {
+            component: VersionComponent::Blocks,
+            storage_version,
+            crate_version,
+            migrations,
+        })
+        if storage_version == migration.from
+            && crate_version == STORAGE_VERSION_BLOCKS
+            && migrations == vec![migration.name]
+    );
+    // A read-only storage is never migrated.
+    let Err(err) = open_storage_read_only(config.clone()) else {
+        panic!("Unexpected Ok.");
+    };
+    assert_matches!(
+        err,
+        StorageError::StorageVersionInconsistency(StorageVersionError::MigrationRequired { .. })
+    );
+
+    config.migrate = true;
+    let (reader, _writer) = open_storage(config).unwrap();
+    assert_eq!(
+        state_diff_lengths(&reader),
+        (0..N_BLOCKS).map(|block_number| Some(block_number as usize)).collect::<Vec<_>>()
+    );
+    assert_eq!(reader.begin_ro_txn().unwrap().get_blocks_version().unwrap(), Some(migration.to()));
+}
+
+#[test]
+fn interrupted_migration_resumes() {
+    let (mut config, _temp_dir) = get_test_config(None);
+    let (reader, mut writer) = open_storage(config.clone()).unwrap();
+    write_blocks_of_old_version(&mut writer);
+
+    let migration = fill_state_diff_lengths_migration();
+    assert!(!run_migration_batch(&mut writer, migration, 2).unwrap());
+    assert_eq!(state_diff_lengths(&reader), vec![Some(0), Some(1), None, None, None]);
+    let txn = reader.begin_ro_txn().unwrap();
+    let progress_table = txn.open_table(&txn.tables.migration_progress).unwrap();
+    assert_eq!(
+        progress_table.get(&txn.txn, &migration.name.to_string()).unwrap(),
+        Some(BlockNumber(2))
+    );
+    // The version changes only once the migration is done.
+    assert_eq!(txn.get_blocks_version().unwrap(), Some(migration.from.clone()));
+    drop(txn);
+    drop((reader, writer));
+
+    config.migrate = true;
+    let (reader, _writer) = open_storage(config).unwrap();
+    assert_eq!(
+        state_diff_lengths(&reader),
+        (0..N_BLOCKS).map(|block_number| Some(block_number as usize)).collect::<Vec<_>>()
+    );
+    let txn = reader.begin_ro_txn().unwrap();
+    let progress_table = txn.open_table(&txn.tables.migration_progress).unwrap();
+    assert_eq!(progress_table.get(&txn.txn, &migration.name.to_string()).unwrap(), None);
+    assert_eq!(txn.get_blocks_version().unwrap(), Some(STORAGE_VERSION_BLOCKS));
+}
+
+#[test]
+fn pending_migrations_by_version() {
+    let migration = fill_state_diff_lengths_migration();
+    let older_version = Version { major: migration.from.major, minor: 0 };
+    assert_eq!(
+        pending_migrations(VersionComponent::Blocks, &older_version)
+            .iter()
+            .map(|migration| migration.name)
+            .collect::<Vec<_>>(),
+        vec![migration.name]
+    );
+    assert!(pending_migrations(VersionComponent::Blocks, &STORAGE_VERSION_BLOCKS).is_empty());
+    let older_state_version = Version { major: STORAGE_VERSION_STATE.major, minor: 0 };
+    assert!(pending_migrations(VersionComponent::State, &older_state_version).is_empty());
+    // A storage with a different major must be re-synced rather than migrated.
+    let other_major = Version { major: STORAGE_VERSION_BLOCKS.major + 1, minor: 0 };
+    assert!(pending_migrations(VersionComponent::Blocks, &other_major).is_empty());
+}
+
+#[test]
+fn migrate_requires_write_access() {
+    let (mut config, _temp_dir) = get_test_config(None);
+    config.migrate = true;
+    assert!(config.validate().is_ok());
+    config.read_only = true;
+    assert!(config.validate().is_err());
+}
@@ -1,5 +1,6 @@
 use std::sync::Arc;
 
+use assert_matches::assert_matches;
 use pretty_assertions::assert_eq;
 use rand::Rng;
 use tempfile::tempdir;
@@ -32,6 +33,7 @@ fn write_read() {
         get_mmap_file_test_config(),
         dir.path().to_path_buf().join("test_write_read"),
         offset,
+        false,
     )
     .unwrap();
     let data = vec![1, 2, 3];
@@ -54,6 +56,7 @@ fn concurrent_reads() {
         get_mmap_file_test_config(),
         dir.path().to_path_buf().join("test_concurrent_reads"),
         offset,
+        false,
     )
     .unwrap();
     let data = vec![1, 2, 3];
@@ -85,6 +88,7 @@ fn concurrent_reads_single_write() {
         get_mmap_file_test_config(),
         dir.path().to_path_buf().join("test_concurrent_reads_single_write"),
         offset,
+        false,
     )
     .unwrap();
     let first_data = vec![1, 2, 3];
@@ -147,9 +151,13 @@ fn grow_file() {
         // file_size = 0, offset = 0
         assert_eq!(file.metadata().unwrap().len(), 0);
 
-        let (mut writer, _) =
-            open_file::<NoVersionValueWrapper<Vec<u8>>>(config.clone(), file_path.clone(), offset)
-                .unwrap();
+        let (mut writer, _) = open_file::<NoVersionValueWrapper<Vec<u8>>>(
+            config.clone(),
+            file_path.clone(),
+            offset,
+            false,
+        )
+        .unwrap();
         // file_size = 4 (growth_step), offset = 0
         let mut file_size = file.metadata().unwrap().len();
         assert_eq!(file_size, config.growth_step as u64);
@@ -188,7 +196,8 @@ fn grow_file() {
         .open(file_path.clone())
         .unwrap();
     assert_eq!(file.metadata().unwrap().len(), 4 * config.growth_step as u64);
-    let _ = open_file::<NoVersionValueWrapper<Vec<u8>>>(config.clone(), file_path, offset).unwrap();
+    let _ = open_file::<NoVersionValueWrapper<Vec<u8>>>(config.clone(), file_path, offset, false)
+        .unwrap();
     assert_eq!(file.metadata().unwrap().len(), 4 * config.growth_step as u64);
 
     dir.close().unwrap();
@@ -202,6 +211,7 @@ async fn write_read_different_locations() {
         get_mmap_file_test_config(),
         dir.path().to_path_buf().join("test_write_read_different_locations"),
         offset,
+        false,
     )
     .unwrap();
     let mut data = vec![0, 1];
@@ -259,6 +269,7 @@ fn reader_when_writer_is_out_of_scope() {
         get_mmap_file_test_config(),
         dir.path().to_path_buf().join("test_reader_when_writer_is_out_of_scope"),
         offset,
+        false,
     )
     .unwrap();
     let data = vec![1, 2, 3];
@@ -274,6 +285,35 @@ fn reader_when_writer_is_out_of_scope() {
     dir.close().unwrap();
 }
 
+#[test]
+fn reclaimed_object_leaves_the_others_in_place() {
+    let dir = tempdir().unwrap();
+    let offset = 0;
+    let (mut writer, reader) = open_file::<NoVersionValueWrapper<Vec<u8>>>(
+        get_mmap_file_test_config(),
+        dir.path().to_path_buf().join("test_reclaimed_object_leaves_the_others_in_place"),
+        offset,
+        false,
+    )
+    .unwrap();
+    let reclaimed_data = vec![1; 1 << 14];
+    let data = vec![1, 2, 3];
+
+    let reclaimed_location = writer.append(&reclaimed_data);
+    let location_in_file = writer.append(&data);
+    writer.reclaim(reclaimed_location).unwrap();
+
+    let res = reader.get(location_in_file).unwrap().unwrap();
+    assert_eq!(res, data);
+    // The reclaimed bytes are zeroed, so they're read as an empty vector.
+    #[cfg(target_os = "linux")]
+    assert_eq!(reader.get(reclaimed_location).unwrap().unwrap(), Vec::<u8>::new());
+    // New objects are appended after the existing ones.
+    assert_eq!(writer.append(&data).offset, location_in_file.next_offset());
+
+    dir.close().unwrap();
+}
+
 #[test]
 fn storage_serde_test_location_in_file() {
     let item = LocationInFile::get_test_instance(&mut get_rng());
@@ -302,3 +342,47 @@ fn location_in_file_serialization_order() {
     location_256.serialize_into(&mut serialized_256).unwrap();
     assert!(serialized_256 > serialized_1);
 }
+
+#[test]
+fn read_only_file() {
+    let dir = tempdir().unwrap();
+    let file_path = dir.path().to_path_buf().join("test_read_only_file");
+    let (mut writer, _) = open_file::<NoVersionValueWrapper<Vec<u8>>>(
+        get_mmap_file_test_config(),
+        file_path.clone(),
+        0,
+        false,
+    )
+    .unwrap();
+    let data = vec![1, 2, 3];
+    let location_in_file = writer.append(&data);
+    writer.flush();
+
+    let (_, reader) = open_file::<NoVersionValueWrapper<Vec<u8>>>(
+        get_mmap_file_test_config(),
+        file_path,
+        location_in_file.next_offset(),
+        true,
+    )
+    .unwrap();
+    assert_eq!(reader.get(location_in_file).unwrap().unwrap(), data);
+
+    // Data that the writer appends later is visible to the read-only reader.
+    let new_data = vec![4, 5];
+    let new_location_in_file = writer.append(&new_data);
+    assert_eq!(reader.get(new_location_in_file).unwrap().unwrap(), new_data);
+
+    dir.close().unwrap();
+}
+
+#[test]
+fn read_only_file_must_exist() {
+    let dir = tempdir().unwrap();
+    let res = open_file::<NoVersionValueWrapper<Vec<u8>>>(
+        get_mmap_file_test_config(),
+        dir.path().to_path_buf().join("test_read_only_file_must_exist"),
+        0,
+        true,
+    );
+    assert_matches!(res, Err(MMapFileError::IO(_)));
+}
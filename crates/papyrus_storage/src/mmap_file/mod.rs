@@ -15,7 +15,7 @@ use std::path::PathBuf;
 use std::result;
 use std::sync::{Arc, Mutex};
 
-use memmap2::{MmapMut, MmapOptions};
+use memmap2::{Mmap, MmapMut, MmapOptions};
 use papyrus_config::dumping::{ser_param, SerializeConfig};
 use papyrus_config::{ParamPath, ParamPrivacyInput, SerializedParam};
 #[cfg(test)]
@@ -121,9 +121,9 @@ pub(crate) trait Reader<V: ValueSerde> {
 #[derive(Copy, Clone, Debug, Eq, PartialEq)]
 pub struct LocationInFile {
     /// Offset in the file.
-    offset: usize,
+    pub(crate) offset: usize,
     /// Length of the object.
-    len: usize,
+    pub(crate) len: usize,
 }
 
 impl LocationInFile {
@@ -139,12 +139,35 @@ struct MMapFile<V: ValueSerde> {
     config: MmapFileConfig,
     file: File,
     size: usize,
-    mmap: MmapMut,
+    mmap: FileMap,
     offset: usize,
     should_flush: bool,
     _value_type: PhantomData<V>,
 }
 
+/// The memory map of a file. Files of a read-only storage are mapped without write access.
+#[derive(Debug)]
+enum FileMap {
+    ReadWrite(MmapMut),
+    ReadOnly(Mmap),
+}
+
+impl FileMap {
+    fn as_ptr(&self) -> *const u8 {
+        match self {
+            FileMap::ReadWrite(mmap) => mmap.as_ptr(),
+            FileMap::ReadOnly(mmap) => mmap.as_ptr(),
+        }
+    }
+
+    fn writable(&mut self) -> &mut MmapMut {
+        match self {
+            FileMap::ReadWrite(mmap) => mmap,
+            FileMap::ReadOnly(_) => panic!("A read-only file can't be written"),
+        }
+    }
+}
+
 impl<V: ValueSerde> MMapFile<V> {
     /// Grows the file by the growth step.
     fn grow(&mut self) {
@@ -158,21 +181,32 @@ impl<V: ValueSerde> MMapFile<V> {
     /// Flushes the mmap to the file.
     fn flush(&mut self) {
         trace!("Flushing mmap to file");
-        self.mmap.flush().expect("Failed to flush the mmap");
+        self.mmap.writable().flush().expect("Failed to flush the mmap");
         self.should_flush = false;
     }
 }
 
 /// Open a memory mapped file, create it if it doesn't exist.
+/// If `read_only` is true, the file must exist and it's opened without write access, so the
+/// returned writer must not be used.
 #[instrument(level = "debug", err)]
 pub(crate) fn open_file<V: ValueSerde>(
     config: MmapFileConfig,
     path: PathBuf,
     offset: usize,
+    read_only: bool,
 ) -> MmapFileResult<(FileHandler<V, RW>, FileHandler<V, RO>)> {
-    let file = OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+    let (file, mmap) = if read_only {
+        let file = OpenOptions::new().read(true).open(path)?;
+        let mmap = unsafe { MmapOptions::new().len(config.max_size).map(&file)? };
+        (file, FileMap::ReadOnly(mmap))
+    } else {
+        let file =
+            OpenOptions::new().read(true).write(true).create(true).truncate(false).open(path)?;
+        let mmap = unsafe { MmapOptions::new().len(config.max_size).map_mut(&file)? };
+        (file, FileMap::ReadWrite(mmap))
+    };
     let size = file.metadata()?.len();
-    let mmap = unsafe { MmapOptions::new().len(config.max_size).map_mut(&file)? };
     let mmap_ptr = mmap.as_ptr();
     let mmap_file = MMapFile {
         config,
@@ -190,7 +224,9 @@ pub(crate) fn open_file<V: ValueSerde>(
         mmap_file: shared_mmap_file.clone(),
         _mode: PhantomData,
     };
-    write_file_handler.grow_file_if_needed(0);
+    if !read_only {
+        write_file_handler.grow_file_if_needed(0);
+    }
 
     let read_file_handler: FileHandler<V, RO> =
         FileHandler { memory_ptr: mmap_ptr, mmap_file: shared_mmap_file, _mode: PhantomData };
@@ -222,6 +258,39 @@ impl<V: ValueSerde> FileHandler<V, RW> {
     }
 }
 
+impl<V: ValueSerde> FileHandler<V, RW> {
+    /// Frees the disk space of the object at the given location, which must not be read anymore.
+    /// The offsets of the other objects don't change, and the space isn't reused by new objects.
+    pub(crate) fn reclaim(&self, location: LocationInFile) -> MmapFileResult<()> {
+        let mmap_file = self.mmap_file.lock().expect("Lock should not be poisoned");
+        punch_hole(&mmap_file.file, location)
+    }
+}
+
+#[cfg(target_os = "linux")]
+fn punch_hole(file: &File, location: LocationInFile) -> MmapFileResult<()> {
+    use std::os::fd::AsRawFd;
+
+    let result = unsafe {
+        libc::fallocate(
+            file.as_raw_fd(),
+            libc::FALLOC_FL_PUNCH_HOLE | libc::FALLOC_FL_KEEP_SIZE,
+            location.offset.try_into()?,
+            location.len.try_into()?,
+        )
+    };
+    if result != 0 {
+        return Err(std::io::Error::last_os_error().into());
+    }
+    Ok(())
+}
+
+// Holes are punched only on Linux. Elsewhere, the space of the object stays taken.
+#[cfg(not(target_os = "linux"))]
+fn punch_hole(_file: &File, _location: LocationInFile) -> MmapFileResult<()> {
+    Ok(())
+}
+
 impl<V: ValueSerde + Debug> Writer<V> for FileHandler<V, RW> {
     fn append(&mut self, val: &V::Value) -> LocationInFile {
         trace!("Inserting object: {:?}", val);
@@ -232,11 +301,9 @@ impl<V: ValueSerde + Debug> Writer<V> for FileHandler<V, RW> {
             let mut mmap_file = self.mmap_file.lock().expect("Lock should not be poisoned");
             offset = mmap_file.offset;
             trace!("Inserting object at offset: {}", offset);
-            let mmap_slice = &mut mmap_file.mmap[offset..];
-            mmap_slice[..len].copy_from_slice(&serialized);
-            mmap_file
-                .mmap
-                .flush_async_range(offset, len)
+            let mmap = mmap_file.mmap.writable();
+            mmap[offset..][..len].copy_from_slice(&serialized);
+            mmap.flush_async_range(offset, len)
                 .expect("Failed to asynchronously flush the mmap after inserting");
             mmap_file.offset += len;
             mmap_file.should_flush = true;
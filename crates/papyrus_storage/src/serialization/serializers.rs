@@ -315,6 +315,8 @@ auto_storage_serde! {
         Class = 4,
         CompiledClass = 5,
         BaseLayerBlock = 6,
+        ColdStorage = 7,
+        ColdStorageReclaim = 8,
     }
     pub struct MessageToL1 {
         pub to_address: EthAddress,
@@ -64,13 +64,14 @@ use starknet_api::core::{ClassHash, ContractAddress, Nonce};
 use starknet_api::deprecated_contract_class::ContractClass as DeprecatedContractClass;
 use starknet_api::state::{ContractClass, StateNumber, StorageKey, ThinStateDiff};
 use starknet_types_core::felt::Felt;
-use tracing::debug;
+use tracing::{debug, warn};
 
 use crate::db::serialization::{NoVersionValueWrapper, VersionZeroWrapper};
 use crate::db::table_types::{CommonPrefix, DbCursorTrait, SimpleTable, Table};
 use crate::db::{DbTransaction, TableHandle, TransactionKind, RW};
 #[cfg(feature = "document_calls")]
 use crate::document_calls::{add_query, StorageQuery};
+use crate::header::fill_missing_state_diff_length;
 use crate::mmap_file::LocationInFile;
 use crate::state::data::IndexedDeprecatedContractClass;
 use crate::{
@@ -152,7 +153,8 @@ where
         thin_state_diff: ThinStateDiff,
     ) -> StorageResult<Self>;
 
-    /// Removes a state diff from the storage and returns the removed data.
+    /// Removes a state diff from the storage and returns the removed data. If the state diff is
+    /// missing below the state marker, only the markers are lowered.
     fn revert_state_diff(
         self,
         block_number: BlockNumber,
@@ -166,6 +168,9 @@ impl<'env, Mode: TransactionKind> StateStorageReader<Mode> for StorageTxn<'env,
         Ok(markers_table.get(&self.txn, &MarkerKind::State)?.unwrap_or_default())
     }
     fn get_state_diff(&self, block_number: BlockNumber) -> StorageResult<Option<ThinStateDiff>> {
+        if let Some(state_diff) = self.get_cold_state_diff(block_number)? {
+            return Ok(Some(state_diff));
+        }
         let state_diffs_table = self.open_table(&self.tables.state_diffs)?;
         let state_diff_location = state_diffs_table.get(&self.txn, &block_number)?;
         match state_diff_location {
@@ -468,6 +473,9 @@ impl<'env> StateStorageWriter for StorageTxn<'env, RW> {
             declared_classes_block_table.insert(&self.txn, class_hash, &block_number)?;
         }
 
+        // The headers synced from the central source don't have the state diff length.
+        fill_missing_state_diff_length(&self, block_number, thin_state_diff.len())?;
+
         // Write state diff.
         let location = self.file_handlers.append_state_diff(&thin_state_diff);
         state_diffs_table.append(&self.txn, &block_number, &location)?;
@@ -515,10 +523,9 @@ impl<'env> StateStorageWriter for StorageTxn<'env, RW> {
             );
             return Ok((self, None));
         };
+        self.verify_block_is_hot(block_number)?;
 
-        let thin_state_diff = self
-            .get_state_diff(block_number)?
-            .unwrap_or_else(|| panic!("Missing state diff for block {block_number}."));
+        let thin_state_diff = self.get_state_diff(block_number)?;
         markers_table.upsert(&self.txn, &MarkerKind::State, &block_number)?;
         let classes_marker = markers_table.get(&self.txn, &MarkerKind::Class)?.unwrap_or_default();
         if classes_marker == next_block_number {
@@ -529,6 +536,14 @@ impl<'env> StateStorageWriter for StorageTxn<'env, RW> {
         if compiled_classes_marker == next_block_number {
             markers_table.upsert(&self.txn, &MarkerKind::CompiledClass, &block_number)?;
         }
+        // A corrupted storage may be missing state diffs below the marker. Lowering the markers
+        // lets the sync download the state diff again.
+        let Some(thin_state_diff) = thin_state_diff else {
+            warn!(
+                "Missing state diff for block {block_number}. Only its state markers are reverted."
+            );
+            return Ok((self, None));
+        };
         let deleted_classes = delete_declared_classes(
             &self.txn,
             &thin_state_diff,
@@ -0,0 +1,44 @@
+use assert_matches::assert_matches;
+use pretty_assertions::assert_eq;
+use starknet_api::block::{BlockHeader, BlockNumber};
+use starknet_api::state::ThinStateDiff;
+
+use crate::db::DbError;
+use crate::header::{HeaderStorageReader, HeaderStorageWriter};
+use crate::state::{StateStorageReader, StateStorageWriter};
+use crate::test_utils::get_test_config;
+use crate::{open_storage, open_storage_read_only, StorageError};
+
+#[test]
+fn read_only_storage() {
+    let (config, _temp_dir) = get_test_config(None);
+    let (reader, mut writer) = open_storage(config.clone()).unwrap();
+    assert!(!reader.is_read_only());
+    let header = BlockHeader { state_diff_length: Some(0), ..Default::default() };
+    writer
+        .begin_rw_txn()
+        .unwrap()
+        .append_header(BlockNumber(0), &header)
+        .unwrap()
+        .append_state_diff(BlockNumber(0), ThinStateDiff::default())
+        .unwrap()
+        .commit()
+        .unwrap();
+    drop((reader, writer));
+
+    let reader = open_storage_read_only(config).unwrap();
+    assert!(reader.is_read_only());
+    let txn = reader.begin_ro_txn().unwrap();
+    assert_eq!(txn.get_block_header(BlockNumber(0)).unwrap(), Some(header));
+    assert_eq!(txn.get_state_diff(BlockNumber(0)).unwrap(), Some(ThinStateDiff::default()));
+    assert_eq!(txn.get_header_marker().unwrap(), BlockNumber(1));
+}
+
+#[test]
+fn read_only_storage_must_exist() {
+    let (config, _temp_dir) = get_test_config(None);
+    assert_matches!(
+        open_storage_read_only(config),
+        Err(StorageError::InnerError(DbError::FileDoesNotExist(_)))
+    );
+}
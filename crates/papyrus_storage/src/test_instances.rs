@@ -60,6 +60,8 @@ auto_impl_get_test_instance! {
         Class = 4,
         CompiledClass = 5,
         BaseLayerBlock = 6,
+        ColdStorage = 7,
+        ColdStorageReclaim = 8,
     }
     pub enum OffsetKind {
         ThinStateDiff = 0,
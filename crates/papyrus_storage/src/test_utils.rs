@@ -29,6 +29,9 @@ pub fn get_test_config(storage_scope: Option<StorageScope>) -> (StorageConfig, T
             },
             scope: storage_scope,
             mmap_file_config: get_mmap_file_test_config(),
+            read_only: false,
+            migrate: false,
+            cold_storage: None,
         },
         dir,
     )
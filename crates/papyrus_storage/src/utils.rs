@@ -6,8 +6,9 @@ mod utils_test;
 use std::collections::HashMap;
 use std::fs::File;
 use std::io::{BufWriter, Write};
+use std::time::Duration;
 
-use metrics::{absolute_counter, gauge};
+use metrics::{absolute_counter, counter, gauge, histogram};
 use serde::Serialize;
 use starknet_api::block::BlockNumber;
 use starknet_api::core::{ChainId, ClassHash, CompiledClassHash};
@@ -17,9 +18,17 @@ use tracing::debug;
 
 use crate::compiled_class::CasmStorageReader;
 use crate::db::table_types::Table;
-use crate::db::RO;
+use crate::db::{DbCommitStats, RO};
 use crate::state::StateStorageReader;
-use crate::{open_storage, StorageConfig, StorageError, StorageReader, StorageResult, StorageTxn};
+use crate::{
+    open_storage,
+    StorageConfig,
+    StorageError,
+    StorageReader,
+    StorageResult,
+    StorageTxn,
+    StorageWriterComponent,
+};
 
 #[derive(Serialize)]
 struct DumpDeclaredClass {
@@ -100,3 +109,21 @@ pub fn update_storage_metrics(reader: &StorageReader) -> StorageResult<()> {
     absolute_counter!("storage_last_transaction_index", info.last_txnid() as u64);
     Ok(())
 }
+
+// Updates the metrics about a committed write transaction, labeled by the component that wrote it.
+pub(crate) fn update_commit_metrics(
+    component: StorageWriterComponent,
+    commit_duration: Duration,
+    commit_stats: DbCommitStats,
+) {
+    let component = component.as_label();
+    histogram!(
+        "storage_commit_latency_seconds",
+        commit_duration.as_secs_f64(),
+        "component" => component
+    );
+    gauge!("storage_commit_new_pages", commit_stats.new_pages as f64, "component" => component);
+    for (table, entries) in commit_stats.entries_written {
+        counter!("storage_entries_written", entries, "component" => component, "table" => table);
+    }
+}
@@ -1,22 +1,33 @@
 use std::collections::HashMap;
 use std::fs;
+use std::sync::OnceLock;
 
 use indexmap::indexmap;
-use metrics_exporter_prometheus::PrometheusBuilder;
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
 use pretty_assertions::assert_eq;
 use prometheus_parse::Value::{Counter, Gauge};
 use starknet_api::block::BlockNumber;
-use starknet_api::core::{ClassHash, CompiledClassHash};
+use starknet_api::core::{ClassHash, CompiledClassHash, ContractAddress, Nonce, PatriciaKey};
 use starknet_api::hash::StarkHash;
-use starknet_api::state::{ContractClass, ThinStateDiff};
+use starknet_api::state::{ContractClass, StorageKey, ThinStateDiff};
+use starknet_api::{felt, patricia_key};
 use starknet_types_core::felt::Felt;
-use test_utils::prometheus_is_contained;
+use test_utils::{get_test_block, prometheus_is_contained};
 
 use super::update_storage_metrics;
+use crate::body::BodyStorageWriter;
 use crate::class::ClassStorageWriter;
+use crate::header::HeaderStorageWriter;
 use crate::state::StateStorageWriter;
 use crate::test_utils::get_test_storage;
 use crate::utils::{dump_declared_classes_table_by_block_range_internal, DumpDeclaredClass};
+use crate::StorageWriterComponent;
+
+// A metrics recorder can be installed only once in a process, so the tests share it.
+fn get_prometheus_handle() -> PrometheusHandle {
+    static PROMETHEUS_HANDLE: OnceLock<PrometheusHandle> = OnceLock::new();
+    PROMETHEUS_HANDLE.get_or_init(|| PrometheusBuilder::new().install_recorder().unwrap()).clone()
+}
 
 // TODO(yael): fix dump_table_to_file.
 #[test]
@@ -81,7 +92,7 @@ fn test_dump_declared_classes() {
 #[test]
 fn update_storage_metrics_test() {
     let ((reader, _writer), _temp_dir) = get_test_storage();
-    let handle = PrometheusBuilder::new().install_recorder().unwrap();
+    let handle = get_prometheus_handle();
 
     assert!(prometheus_is_contained(handle.render(), "storage_free_pages_number", &[]).is_none());
     assert!(prometheus_is_contained(handle.render(), "storage_last_page_number", &[]).is_none());
@@ -115,3 +126,81 @@ fn update_storage_metrics_test() {
     assert!(0f64 < last_transaction);
     assert!(last_transaction < 100f64);
 }
+
+#[test]
+fn commit_metrics_test() {
+    let handle = get_prometheus_handle();
+    let ((_reader, mut writer), _temp_dir) = get_test_storage();
+    // No other test writes as the central sync, so the metrics of this test aren't mixed with
+    // theirs.
+    writer.set_component(StorageWriterComponent::CentralSync);
+
+    let block = get_test_block(2, Some(1), None, None);
+    let address = ContractAddress(patricia_key!("0x11"));
+    let state_diff = ThinStateDiff {
+        deployed_contracts: indexmap!(address => ClassHash(felt!("0x12"))),
+        storage_diffs: indexmap!(address => indexmap!(
+            StorageKey(patricia_key!("0x13")) => felt!("0x14"),
+            StorageKey(patricia_key!("0x15")) => felt!("0x16"),
+        )),
+        nonces: indexmap!(address => Nonce(felt!("0x1"))),
+        ..Default::default()
+    };
+    writer
+        .begin_rw_txn()
+        .unwrap()
+        .append_header(BlockNumber(0), &block.header)
+        .unwrap()
+        .append_body(BlockNumber(0), block.body)
+        .unwrap()
+        .append_state_diff(BlockNumber(0), state_diff)
+        .unwrap()
+        .commit()
+        .unwrap();
+
+    let expected_entries_written = [
+        // Header, body, event, state and compiled class markers.
+        ("markers", 5.0),
+        ("headers", 1.0),
+        ("block_hash_to_number", 1.0),
+        ("starknet_version", 1.0),
+        // One event in each of the 2 transactions.
+        ("events", 2.0),
+        ("transaction_hash_to_idx", 2.0),
+        ("transaction_metadata", 2.0),
+        ("deployed_contracts", 1.0),
+        ("contract_storage", 2.0),
+        ("nonces", 1.0),
+        ("state_diffs", 1.0),
+        ("file_offsets", 1.0),
+    ];
+    for (table, expected_entries) in expected_entries_written {
+        let Counter(entries) = prometheus_is_contained(
+            handle.render(),
+            "storage_entries_written",
+            &[("component", "central_sync"), ("table", table)],
+        )
+        .unwrap_or_else(|| panic!("No storage_entries_written metric for table {table}")) else {
+            panic!("storage_entries_written is not a Counter")
+        };
+        assert_eq!(entries, expected_entries, "Wrong number of entries written to {table}");
+    }
+    for table in ["block_signatures", "declared_classes_block", "casms"] {
+        assert!(prometheus_is_contained(
+            handle.render(),
+            "storage_entries_written",
+            &[("component", "central_sync"), ("table", table)],
+        )
+        .is_none());
+    }
+
+    assert!(prometheus_is_contained(
+        handle.render(),
+        "storage_commit_new_pages",
+        &[("component", "central_sync")],
+    )
+    .is_some());
+    assert!(handle
+        .render()
+        .contains("storage_commit_latency_seconds_count{component=\"central_sync\"} 1"));
+}
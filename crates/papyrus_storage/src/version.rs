@@ -1,29 +1,100 @@
+//! The versions of the storage schema.
+//!
+//! For more details on the storage version, see the crate documentation.
+
 #[cfg(test)]
 #[path = "version_test.rs"]
 mod version_test;
 
 use std::fmt::Display;
 
+use serde::{Deserialize, Serialize};
+
 use crate::db::table_types::Table;
 use crate::db::{TransactionKind, RW};
-use crate::{StorageError, StorageResult, StorageTxn};
+use crate::{
+    StorageError,
+    StorageResult,
+    StorageTxn,
+    STORAGE_VERSION_BLOCKS,
+    STORAGE_VERSION_STATE,
+};
 
-const VERSION_STATE_KEY: &str = "storage_version_state";
-const VERSION_BLOCKS_KEY: &str = "storage_version_blocks";
+pub(crate) const VERSION_STATE_KEY: &str = "storage_version_state";
+pub(crate) const VERSION_BLOCKS_KEY: &str = "storage_version_blocks";
 
-#[derive(Clone, Debug, Default, Eq, PartialEq)]
+/// A version of the storage schema. A storage with a different major version must be re-synced,
+/// while a storage with a lower minor version can be migrated.
+#[derive(Clone, Debug, Default, Eq, PartialEq, Serialize, Deserialize)]
 pub struct Version {
+    /// The major version.
     pub major: u32,
+    /// The minor version.
     pub minor: u32,
 }
 
+/// The parts of the storage that are versioned separately.
+#[derive(Clone, Copy, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub enum VersionComponent {
+    /// The data describing the state, stored in every storage scope.
+    State,
+    /// The blocks data, stored only in the full-archive scope.
+    Blocks,
+}
+
+impl VersionComponent {
+    /// Returns the version of the component that the storage code supports.
+    pub fn crate_version(&self) -> Version {
+        match self {
+            VersionComponent::State => STORAGE_VERSION_STATE,
+            VersionComponent::Blocks => STORAGE_VERSION_BLOCKS,
+        }
+    }
+}
+
+impl Display for VersionComponent {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            VersionComponent::State => write!(f, "state"),
+            VersionComponent::Blocks => write!(f, "blocks"),
+        }
+    }
+}
+
+/// The schema versions of a storage and the versions the storage code supports.
+#[derive(Clone, Debug, Eq, PartialEq, Serialize, Deserialize)]
+pub struct StorageVersionInfo {
+    /// The version of the stored state. None if the storage wasn't initialized.
+    pub state_version: Option<Version>,
+    /// The version of the stored blocks. None if the storage doesn't store blocks.
+    pub blocks_version: Option<Version>,
+    /// The state version the storage code supports.
+    pub supported_state_version: Version,
+    /// The blocks version the storage code supports.
+    pub supported_blocks_version: Version,
+}
+
+/// Errors of mismatches between the storage version and the storage code.
+#[allow(missing_docs)]
 #[derive(thiserror::Error, Debug)]
 pub enum StorageVersionError {
     #[error(
         "Storage crate version {crate_version} is inconsistent with DB version {storage_version}. \
-         If the major version is different, re-sync is needed."
+         If the major versions are different, re-sync is needed. If the DB minor version is \
+         higher, run a node version that supports it."
     )]
     InconsistentStorageVersion { crate_version: Version, storage_version: Version },
+    #[error(
+        "The {component} storage version {storage_version} must be migrated to {crate_version} \
+         by the migrations {migrations:?}. Run the node with --storage.migrate true to migrate \
+         the storage, or re-sync it."
+    )]
+    MigrationRequired {
+        component: VersionComponent,
+        storage_version: Version,
+        crate_version: Version,
+        migrations: Vec<&'static str>,
+    },
     #[error(
         "The existing storage is operating in state-only mode and cannot support the requested \
          full-archive mode."
@@ -43,18 +114,27 @@ pub enum StorageVersionError {
     SetMajorVersion { crate_version: Version, storage_version: Version },
 }
 
+/// Interface for reading the storage versions.
 pub trait VersionStorageReader {
+    /// Returns the version of the stored state. None if the storage wasn't initialized.
     fn get_state_version(&self) -> StorageResult<Option<Version>>;
+    /// Returns the version of the stored blocks. None if the storage doesn't store blocks.
     fn get_blocks_version(&self) -> StorageResult<Option<Version>>;
 }
 
+/// Interface for updating the storage versions.
 pub trait VersionStorageWriter
 where
     Self: Sized,
 {
     // To enforce that no commit happen after a failure, we consume and return Self on success.
+    /// Sets the state version. Fails unless the version has the major of the existing one and a
+    /// higher minor.
     fn set_state_version(self, version: &Version) -> StorageResult<Self>;
+    /// Sets the blocks version. Fails unless the version has the major of the existing one and a
+    /// higher minor.
     fn set_blocks_version(self, version: &Version) -> StorageResult<Self>;
+    /// Deletes the blocks version, marking the storage as state-only.
     fn delete_blocks_version(self) -> StorageResult<Self>;
 }
 
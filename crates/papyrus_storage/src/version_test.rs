@@ -89,8 +89,9 @@ fn set_state_version_test() {
 
 #[test]
 fn version_migration() {
-    let ((reader, mut writer), config, _temp_dir) =
+    let ((reader, mut writer), mut config, _temp_dir) =
         get_test_storage_with_config_by_scope(StorageScope::FullArchive);
+    config.migrate = true;
 
     // Set the storage version on a lower minor version.
     change_storage_version(
@@ -283,7 +284,7 @@ fn test_set_version_if_needed() {
     reader.scope = StorageScope::FullArchive;
     writer.scope = StorageScope::FullArchive;
     assert!(
-        set_version_if_needed(reader, writer).is_err(),
+        set_version_if_needed(reader, writer, false).is_err(),
         "Should fail, because storage scope cannot shift from state-only to full-archive."
     );
 }
@@ -10,6 +10,7 @@ pub mod sources;
 
 use std::cmp::min;
 use std::collections::BTreeMap;
+use std::future::pending;
 use std::sync::Arc;
 use std::time::Duration;
 
@@ -18,6 +19,7 @@ use cairo_lang_starknet_classes::casm_contract_class::CasmContractClass;
 use chrono::{TimeZone, Utc};
 use futures_util::{pin_mut, select, Stream, StreamExt};
 use indexmap::IndexMap;
+use papyrus_base_layer::ethereum_base_layer_contract::EthereumBaseLayerConfig;
 use papyrus_common::pending_classes::PendingClasses;
 use papyrus_common::{metrics as papyrus_metrics, BlockHashAndNumber};
 use papyrus_config::converters::deserialize_seconds_to_duration;
@@ -34,12 +36,12 @@ use papyrus_storage::state::{StateStorageReader, StateStorageWriter};
 use papyrus_storage::{StorageError, StorageReader, StorageWriter};
 use serde::{Deserialize, Serialize};
 use sources::base_layer::BaseLayerSourceError;
-use starknet_api::block::{Block, BlockHash, BlockNumber, BlockSignature};
+use starknet_api::block::{Block, BlockHash, BlockNumber, BlockSignature, BlockTimestamp};
 use starknet_api::core::{ClassHash, CompiledClassHash, SequencerPublicKey};
 use starknet_api::deprecated_contract_class::ContractClass as DeprecatedContractClass;
 use starknet_api::state::{StateDiff, ThinStateDiff};
 use starknet_client::reader::PendingData;
-use tokio::sync::RwLock;
+use tokio::sync::{watch, RwLock};
 use tracing::{debug, error, info, instrument, trace, warn};
 
 use crate::pending_sync::sync_pending_data;
@@ -47,11 +49,6 @@ use crate::sources::base_layer::{BaseLayerSourceTrait, EthereumBaseLayerSource};
 use crate::sources::central::{CentralError, CentralSource, CentralSourceTrait};
 use crate::sources::pending::{PendingError, PendingSource, PendingSourceTrait};
 
-// TODO(shahak): Consider adding genesis hash to the config to support chains that have
-// different genesis hash.
-// TODO: Consider moving to a more general place.
-const GENESIS_HASH: &str = "0x0";
-
 // TODO(dvir): add to config.
 // Sleep duration between polling for pending data.
 const PENDING_SLEEP_DURATION: Duration = Duration::from_millis(500);
@@ -59,10 +56,15 @@ const PENDING_SLEEP_DURATION: Duration = Duration::from_millis(500);
 // Sleep duration, in seconds, between sync progress checks.
 const SLEEP_TIME_SYNC_PROGRESS: Duration = Duration::from_secs(300);
 
+// While there are no new proved blocks, the base layer poll interval is doubled up to this factor
+// of the configured poll interval.
+const MAX_BASE_LAYER_POLL_INTERVAL_FACTOR: u32 = 8;
+
 #[derive(Clone, Copy, Debug, Serialize, Deserialize, PartialEq)]
 pub struct SyncConfig {
     #[serde(deserialize_with = "deserialize_seconds_to_duration")]
     pub block_propagation_sleep_duration: Duration,
+    /// Deprecated alias of the base layer poll interval, which replaces it when it isn't zero.
     #[serde(deserialize_with = "deserialize_seconds_to_duration")]
     pub base_layer_propagation_sleep_duration: Duration,
     #[serde(deserialize_with = "deserialize_seconds_to_duration")]
@@ -70,6 +72,9 @@ pub struct SyncConfig {
     pub blocks_max_stream_size: u32,
     pub state_updates_max_stream_size: u32,
     pub verify_blocks: bool,
+    pub pending_classes_max_size_bytes: usize,
+    #[serde(deserialize_with = "deserialize_seconds_to_duration")]
+    pub pending_data_max_staleness: Duration,
 }
 
 impl SerializeConfig for SyncConfig {
@@ -84,7 +89,8 @@ impl SerializeConfig for SyncConfig {
             ser_param(
                 "base_layer_propagation_sleep_duration",
                 &self.base_layer_propagation_sleep_duration.as_secs(),
-                "Time in seconds to poll the base layer to get the latest proved block.",
+                "Deprecated, use base_layer.poll_interval instead. If it isn't zero, it replaces \
+                 base_layer.poll_interval.",
                 ParamPrivacyInput::Public,
             ),
             ser_param(
@@ -112,6 +118,21 @@ impl SerializeConfig for SyncConfig {
                 "Whether to verify incoming blocks.",
                 ParamPrivacyInput::Public,
             ),
+            ser_param(
+                "pending_classes_max_size_bytes",
+                &self.pending_classes_max_size_bytes,
+                "Approximate max size in bytes of the pending classes kept in memory. Beyond it, \
+                 the oldest classes are removed and fetched again when needed.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "pending_data_max_staleness",
+                &self.pending_data_max_staleness.as_secs(),
+                "Time in seconds since the pending data was last fetched after which the RPC \
+                 treats it as stale and serves an empty pending block on top of the latest block \
+                 instead. Should be substantially longer than the pending polling interval.",
+                ParamPrivacyInput::Public,
+            ),
         ])
     }
 }
@@ -120,11 +141,13 @@ impl Default for SyncConfig {
     fn default() -> Self {
         SyncConfig {
             block_propagation_sleep_duration: Duration::from_secs(2),
-            base_layer_propagation_sleep_duration: Duration::from_secs(10),
+            base_layer_propagation_sleep_duration: Duration::ZERO,
             recoverable_error_sleep_duration: Duration::from_secs(3),
             blocks_max_stream_size: 1000,
             state_updates_max_stream_size: 1000,
             verify_blocks: true,
+            pending_classes_max_size_bytes: 256 * 1024 * 1024,
+            pending_data_max_staleness: Duration::from_secs(30),
         }
     }
 }
@@ -143,9 +166,14 @@ pub struct GenericStateSync<
     pending_source: Arc<TPendingSource>,
     pending_classes: Arc<RwLock<PendingClasses>>,
     base_layer_source: Arc<TBaseLayerSource>,
+    base_layer_config: EthereumBaseLayerConfig,
     reader: StorageReader,
     writer: StorageWriter,
     sequencer_pub_key: Option<SequencerPublicKey>,
+    // Updates to the config while the node is running. None if the config can't change.
+    config_updates: Option<watch::Receiver<SyncConfig>>,
+    // The parent hash of the first block of the chain.
+    genesis_hash: BlockHash,
 }
 
 pub type StateSyncResult = Result<(), StateSyncError>;
@@ -185,6 +213,11 @@ pub enum StateSyncError {
     },
     #[error("Sequencer public key changed from {old:?} to {new:?}.")]
     SequencerPubKeyChanged { old: SequencerPublicKey, new: SequencerPublicKey },
+    #[error(
+        "The parent hash of the first block is {parent_hash}, but the configured genesis hash is \
+         {genesis_hash}. The node is configured for a different chain than its source."
+    )]
+    GenesisHashMismatch { parent_hash: BlockHash, genesis_hash: BlockHash },
 }
 
 #[allow(clippy::large_enum_variant)]
@@ -215,6 +248,7 @@ pub enum SyncEvent {
         block_number: BlockNumber,
         block_hash: BlockHash,
     },
+    ColdStorageMoveDue,
 }
 
 impl<
@@ -225,8 +259,19 @@ impl<
 {
     pub async fn run(&mut self) -> StateSyncResult {
         info!("State sync started.");
+        let mut config_updates = self.config_updates.take();
         loop {
-            match self.sync_while_ok().await {
+            let sync_result = tokio::select! {
+                sync_result = self.sync_while_ok() => sync_result,
+                // The intervals of the streams are set when they're created, so the sync restarts
+                // to apply the new config.
+                config = next_config_update(&mut config_updates) => {
+                    info!("Sync config was updated. Restarting the sync.");
+                    self.config = config;
+                    continue;
+                }
+            };
+            match sync_result {
                 // A recoverable error occurred. Sleep and try syncing again.
                 Err(err) if is_recoverable(&err) => {
                     warn!("Recoverable error encountered while syncing, error: {}", err);
@@ -244,6 +289,19 @@ impl<
             }
         }
 
+        // Never returns if the config can't change anymore.
+        async fn next_config_update(
+            config_updates: &mut Option<watch::Receiver<SyncConfig>>,
+        ) -> SyncConfig {
+            let Some(config_updates) = config_updates else {
+                return pending().await;
+            };
+            if config_updates.changed().await.is_err() {
+                return pending().await;
+            }
+            *config_updates.borrow_and_update()
+        }
+
         // Whitelisting of errors from which we might be able to recover.
         fn is_recoverable(err: &StateSyncError) -> bool {
             // We don't use here catch-all pattern to enforce conscious decision for each error
@@ -259,7 +317,8 @@ impl<
                 | StateSyncError::ParentBlockHashMismatch { .. }
                 | StateSyncError::BaseLayerHashMismatch { .. }
                 | StateSyncError::BaseLayerBlockWithoutMatchingHeader { .. } => true,
-                StateSyncError::SequencerPubKeyChanged { .. } => false,
+                StateSyncError::SequencerPubKeyChanged { .. }
+                | StateSyncError::GenesisHashMismatch { .. } => false,
             }
         }
     }
@@ -305,8 +364,11 @@ impl<
             self.shared_highest_block.clone(),
             self.pending_data.clone(),
             self.pending_classes.clone(),
+            self.genesis_hash,
             self.config.block_propagation_sleep_duration,
             PENDING_SLEEP_DURATION,
+            self.config.pending_classes_max_size_bytes,
+            self.config.pending_data_max_staleness,
             self.config.blocks_max_stream_size,
         )
         .fuse();
@@ -328,18 +390,22 @@ impl<
         let base_layer_block_stream = stream_new_base_layer_block(
             self.reader.clone(),
             self.base_layer_source.clone(),
-            self.config.base_layer_propagation_sleep_duration,
+            self.base_layer_config.poll_interval,
+            self.base_layer_config.confirmation_depth,
         )
         .fuse();
         // TODO(dvir): try use interval instead of stream.
         // TODO: fix the bug and remove this check.
         let check_sync_progress = check_sync_progress(self.reader.clone()).fuse();
+        let cold_storage_moves =
+            stream_cold_storage_moves(self.writer.cold_storage_move_interval()).fuse();
         pin_mut!(
             block_stream,
             state_diff_stream,
             compiled_class_stream,
             base_layer_block_stream,
-            check_sync_progress
+            check_sync_progress,
+            cold_storage_moves
         );
 
         loop {
@@ -350,6 +416,7 @@ impl<
               res = compiled_class_stream.next() => res,
               res = base_layer_block_stream.next() => res,
               res = check_sync_progress.next() => res,
+              res = cold_storage_moves.next() => res,
               complete => break,
             }
             .expect("Received None as a sync event.")?;
@@ -384,10 +451,18 @@ impl<
             SyncEvent::NewBaseLayerBlock { block_number, block_hash } => {
                 self.store_base_layer_block(block_number, block_hash)
             }
+            SyncEvent::ColdStorageMoveDue => self.move_blocks_to_cold_storage(),
             SyncEvent::NoProgress => Err(StateSyncError::NoProgress),
         }
     }
 
+    // The sync holds the storage writer, so it moves the old blocks to the cold storage.
+    fn move_blocks_to_cold_storage(&mut self) -> StateSyncResult {
+        let now = BlockTimestamp(u64::try_from(Utc::now().timestamp()).unwrap_or_default());
+        self.writer.move_blocks_to_cold_storage(now)?;
+        Ok(())
+    }
+
     #[latency_histogram("sync_store_block_latency_seconds", false)]
     #[instrument(skip(self, block), level = "debug", fields(block_hash = %block.header.block_hash), err)]
     fn store_block(
@@ -532,7 +607,15 @@ impl<
                 l2_hash: expected_hash,
             });
         }
-        if txn.get_base_layer_block_marker()? != block_number.unchecked_next() {
+        let base_layer_marker = txn.get_base_layer_block_marker()?;
+        if base_layer_marker > block_number.unchecked_next() {
+            warn!(
+                "Base layer reorg: block {block_number} is the latest block proved on the base \
+                 layer. Rolling back the base layer marker from {base_layer_marker} to {}.",
+                block_number.unchecked_next()
+            );
+        }
+        if base_layer_marker != block_number.unchecked_next() {
             info!("Verified block {block_number} hash against base layer.");
             txn.update_base_layer_block_marker(&block_number.unchecked_next())?.commit()?;
             metrics::gauge!(
@@ -543,13 +626,20 @@ impl<
         Ok(())
     }
 
-    // Compares the block's parent hash to the stored block.
+    // Compares the block's parent hash to the stored block, or to the genesis hash for the first
+    // block.
     fn verify_parent_block_hash(
         &self,
         block_number: BlockNumber,
         block: &Block,
     ) -> StateSyncResult {
         let prev_block_number = match block_number.prev() {
+            None if block.header.parent_hash != self.genesis_hash => {
+                return Err(StateSyncError::GenesisHashMismatch {
+                    parent_hash: block.header.parent_hash,
+                    genesis_hash: self.genesis_hash,
+                });
+            }
             None => return Ok(()),
             Some(bn) => bn,
         };
@@ -658,8 +748,11 @@ fn stream_new_blocks<
     shared_highest_block: Arc<RwLock<Option<BlockHashAndNumber>>>,
     pending_data: Arc<RwLock<PendingData>>,
     pending_classes: Arc<RwLock<PendingClasses>>,
+    genesis_hash: BlockHash,
     block_propagation_sleep_duration: Duration,
     pending_sleep_duration: Duration,
+    pending_classes_max_size_bytes: usize,
+    pending_data_max_staleness: Duration,
     max_stream_size: u32,
 ) -> impl Stream<Item = Result<SyncEvent, StateSyncError>> {
     try_stream! {
@@ -684,7 +777,10 @@ fn stream_new_blocks<
                         pending_source.clone(),
                         pending_data.clone(),
                         pending_classes.clone(),
+                        genesis_hash,
                         pending_sleep_duration,
+                        pending_classes_max_size_bytes,
+                        pending_data_max_staleness,
                     ).await?;
                 }
                 else{
@@ -764,6 +860,7 @@ pub type StateSync = GenericStateSync<CentralSource, PendingSource, EthereumBase
 
 impl StateSync {
     #[allow(clippy::too_many_arguments)]
+    /// The sync restarts with the config it gets through `config_updates` whenever it changes.
     pub fn new(
         config: SyncConfig,
         shared_highest_block: Arc<RwLock<Option<BlockHashAndNumber>>>,
@@ -772,8 +869,11 @@ impl StateSync {
         central_source: CentralSource,
         pending_source: PendingSource,
         base_layer_source: EthereumBaseLayerSource,
+        base_layer_config: EthereumBaseLayerConfig,
         reader: StorageReader,
         writer: StorageWriter,
+        config_updates: Option<watch::Receiver<SyncConfig>>,
+        genesis_hash: BlockHash,
     ) -> Self {
         Self {
             config,
@@ -783,9 +883,12 @@ impl StateSync {
             central_source: Arc::new(central_source),
             pending_source: Arc::new(pending_source),
             base_layer_source: Arc::new(base_layer_source),
+            base_layer_config,
             reader,
             writer,
             sequencer_pub_key: None,
+            config_updates,
+            genesis_hash,
         }
     }
 }
@@ -839,25 +942,56 @@ fn stream_new_compiled_classes<TCentralSource: CentralSourceTrait + Sync + Send>
 }
 
 // TODO(dvir): consider combine this function and store_base_layer_block.
+// Polls the base layer every poll_interval while it has proved blocks that aren't marked yet, and
+// backs off while the base layer marker is caught up with it. The polling only backs off, up to
+// MAX_BASE_LAYER_POLL_INTERVAL_FACTOR times poll_interval, and never polls more often than
+// poll_interval.
 fn stream_new_base_layer_block<TBaseLayerSource: BaseLayerSourceTrait + Sync>(
     reader: StorageReader,
     base_layer_source: Arc<TBaseLayerSource>,
-    base_layer_propagation_sleep_duration: Duration,
+    poll_interval: Duration,
+    confirmation_depth: u64,
 ) -> impl Stream<Item = Result<SyncEvent, StateSyncError>> {
     try_stream! {
+        let max_poll_interval = poll_interval * MAX_BASE_LAYER_POLL_INTERVAL_FACTOR;
+        let mut current_poll_interval = poll_interval;
+        // A block behind the base layer marker is yielded only if it's returned in two
+        // consecutive polls, so that a single bad response won't roll back the marker.
+        let mut suspected_reorg_block = None;
         loop {
-            tokio::time::sleep(base_layer_propagation_sleep_duration).await;
+            tokio::time::sleep(current_poll_interval).await;
             let txn = reader.begin_ro_txn()?;
             let header_marker = txn.get_header_marker()?;
-            match base_layer_source.latest_proved_block().await? {
+            let base_layer_marker = txn.get_base_layer_block_marker()?;
+            let previous_suspected_reorg_block = suspected_reorg_block.take();
+            match base_layer_source.latest_proved_block(confirmation_depth).await? {
                 Some((block_number, _block_hash)) if header_marker <= block_number => {
                     debug!(
                         "Sync headers ({header_marker}) is behind the base layer tip \
                          ({block_number}), waiting for sync to advance."
                     );
+                    current_poll_interval = poll_interval;
+                }
+                Some((block_number, _block_hash))
+                    if block_number.unchecked_next() == base_layer_marker =>
+                {
+                    debug!("Base layer marker is caught up with the base layer tip.");
+                    current_poll_interval = min(current_poll_interval * 2, max_poll_interval);
+                }
+                Some((block_number, _block_hash))
+                    if block_number.unchecked_next() < base_layer_marker
+                        && previous_suspected_reorg_block != Some(block_number) =>
+                {
+                    warn!(
+                        "The base layer tip ({block_number}) is behind the base layer marker \
+                         ({base_layer_marker}). Checking again for a base layer reorg."
+                    );
+                    suspected_reorg_block = Some(block_number);
+                    current_poll_interval = poll_interval;
                 }
                 Some((block_number, block_hash)) => {
                     debug!("Returns a block from the base layer. Block number: {block_number}.");
+                    current_poll_interval = poll_interval;
                     yield SyncEvent::NewBaseLayerBlock { block_number, block_hash }
                 }
                 None => {
@@ -865,12 +999,28 @@ fn stream_new_base_layer_block<TBaseLayerSource: BaseLayerSourceTrait + Sync>(
                         "No blocks were proved on the base layer, waiting for blockchain to \
                          advance."
                     );
+                    current_poll_interval = min(current_poll_interval * 2, max_poll_interval);
                 }
             }
         }
     }
 }
 
+// Yields an event every move_interval, or never if the storage has no cold storage.
+fn stream_cold_storage_moves(
+    move_interval: Option<Duration>,
+) -> impl Stream<Item = Result<SyncEvent, StateSyncError>> {
+    try_stream! {
+        loop {
+            match move_interval {
+                Some(move_interval) => tokio::time::sleep(move_interval).await,
+                None => pending::<()>().await,
+            }
+            yield SyncEvent::ColdStorageMoveDue;
+        }
+    }
+}
+
 // This function is used to check if the sync is stuck.
 // TODO: fix the bug and remove this function.
 // TODO(dvir): add a test for this scenario.
@@ -5,20 +5,24 @@ use std::time::Duration;
 use futures::stream::FuturesUnordered;
 use futures_util::{FutureExt, StreamExt};
 use papyrus_common::pending_classes::{PendingClasses, PendingClassesTrait};
+use papyrus_common::{approximate_size_in_bytes, metrics as papyrus_metrics};
 use papyrus_storage::header::HeaderStorageReader;
 use papyrus_storage::StorageReader;
 use starknet_api::block::{BlockHash, BlockNumber};
 use starknet_api::core::ClassHash;
+use starknet_client::reader::objects::pending_data::PendingDataFreshness;
 use starknet_client::reader::{DeclaredClassHashEntry, PendingData};
-use starknet_types_core::felt::Felt;
 use tokio::sync::RwLock;
-use tracing::{debug, trace};
+use tracing::{debug, trace, warn};
 
 use crate::sources::central::CentralSourceTrait;
 use crate::sources::pending::PendingSourceTrait;
 use crate::StateSyncError;
 
+const PENDING_DATA_AGE_REPORT_INTERVAL: Duration = Duration::from_secs(1);
+
 // Update the pending data and return when a new block is discovered.
+#[allow(clippy::too_many_arguments)]
 pub(crate) async fn sync_pending_data<
     TPendingSource: PendingSourceTrait + Sync + Send + 'static,
     TCentralSource: CentralSourceTrait + Sync + Send + 'static,
@@ -28,13 +32,16 @@ pub(crate) async fn sync_pending_data<
     pending_source: Arc<TPendingSource>,
     pending_data: Arc<RwLock<PendingData>>,
     pending_classes: Arc<RwLock<PendingClasses>>,
+    genesis_hash: BlockHash,
     sleep_duration: Duration,
+    pending_classes_max_size_bytes: usize,
+    pending_data_max_staleness: Duration,
 ) -> Result<(), StateSyncError> {
     let txn = reader.begin_ro_txn()?;
     let header_marker = txn.get_header_marker()?;
     // TODO: Consider extracting this functionality to different а function.
     let latest_block_hash = match header_marker {
-        BlockNumber(0) => BlockHash(Felt::from_hex_unchecked(crate::GENESIS_HASH)),
+        BlockNumber(0) => genesis_hash,
         _ => {
             txn.get_block_header(
                 header_marker
@@ -53,9 +60,11 @@ pub(crate) async fn sync_pending_data<
             pending_data.clone(),
             pending_classes.clone(),
             Duration::ZERO,
+            pending_data_max_staleness,
         )
         .boxed(),
     );
+    tasks.push(report_pending_data_age(pending_data.clone(), Duration::ZERO).boxed());
     let mut processed_classes = HashSet::new();
     let mut processed_compiled_classes = HashSet::new();
     loop {
@@ -78,6 +87,7 @@ pub(crate) async fn sync_pending_data<
                                 class_hash,
                                 central_source.clone(),
                                 pending_classes.clone(),
+                                pending_classes_max_size_bytes,
                             )
                             .boxed(),
                         );
@@ -88,6 +98,7 @@ pub(crate) async fn sync_pending_data<
                                 class_hash,
                                 central_source.clone(),
                                 pending_classes.clone(),
+                                pending_classes_max_size_bytes,
                             )
                             .boxed(),
                         );
@@ -100,6 +111,7 @@ pub(crate) async fn sync_pending_data<
                                 class_hash,
                                 central_source.clone(),
                                 pending_classes.clone(),
+                                pending_classes_max_size_bytes,
                             )
                             .boxed(),
                         );
@@ -112,6 +124,7 @@ pub(crate) async fn sync_pending_data<
                         pending_data.clone(),
                         pending_classes.clone(),
                         sleep_duration,
+                        pending_data_max_staleness,
                     )
                     .boxed(),
                 )
@@ -123,10 +136,15 @@ pub(crate) async fn sync_pending_data<
                     pending_data.clone(),
                     pending_classes.clone(),
                     sleep_duration,
+                    pending_data_max_staleness,
                 )
                 .boxed(),
             ),
             PendingSyncTaskResult::DownloadedClassOrCompiledClass => {}
+            PendingSyncTaskResult::ReportedPendingDataAge => tasks.push(
+                report_pending_data_age(pending_data.clone(), PENDING_DATA_AGE_REPORT_INTERVAL)
+                    .boxed(),
+            ),
         }
     }
 }
@@ -136,6 +154,7 @@ enum PendingSyncTaskResult {
     DownloadedOldPendingData,
     PendingSyncFinished,
     DownloadedClassOrCompiledClass,
+    ReportedPendingDataAge,
 }
 
 async fn get_pending_data<TPendingSource: PendingSourceTrait + Sync + Send + 'static>(
@@ -143,11 +162,14 @@ async fn get_pending_data<TPendingSource: PendingSourceTrait + Sync + Send + 'st
     pending_source: Arc<TPendingSource>,
     pending_data: Arc<RwLock<PendingData>>,
     pending_classes: Arc<RwLock<PendingClasses>>,
+    genesis_hash: BlockHash,
     sleep_duration: Duration,
+    pending_data_max_staleness: Duration,
 ) -> Result<PendingSyncTaskResult, StateSyncError> {
     tokio::time::sleep(sleep_duration).await;
 
-    let new_pending_data = pending_source.get_pending_data().await?;
+    let mut new_pending_data = pending_source.get_pending_data().await?;
+    let freshness = PendingDataFreshness::new(pending_data_max_staleness);
 
     // In Starknet, if there's no pending block then the latest block is returned. We prefer to
     // treat this case as if the pending block is an empty block on top of the latest block.
@@ -175,22 +197,49 @@ async fn get_pending_data<TPendingSource: PendingSourceTrait + Sync + Send + 'st
         trace!("Pending data: {new_pending_data:#?}.");
         if current_pending_parent_hash != new_pending_parent_hash {
             pending_classes.write().await.clear();
+            metrics::gauge!(papyrus_metrics::PAPYRUS_PENDING_CLASSES_SIZE_BYTES, 0.0);
         }
+        metrics::gauge!(
+            papyrus_metrics::PAPYRUS_PENDING_TRANSACTIONS_SIZE_BYTES,
+            approximate_size_in_bytes(new_pending_data.block.transactions()) as f64
+        );
+        new_pending_data.freshness = Some(freshness);
         *pending_data.write().await = new_pending_data;
         Ok(PendingSyncTaskResult::DownloadedNewPendingData)
     } else {
         debug!("Pending block wasn't updated. Waiting for pending block to be updated.");
+        // The pending data we hold is still up to date.
+        pending_data.write().await.freshness = Some(freshness);
         Ok(PendingSyncTaskResult::DownloadedOldPendingData)
     }
 }
 
+// The age keeps growing while the pending source doesn't respond, so it's reported periodically
+// and not only when the pending data is updated.
+async fn report_pending_data_age(
+    pending_data: Arc<RwLock<PendingData>>,
+    sleep_duration: Duration,
+) -> Result<PendingSyncTaskResult, StateSyncError> {
+    tokio::time::sleep(sleep_duration).await;
+    if let Some(freshness) = pending_data.read().await.freshness {
+        metrics::gauge!(
+            papyrus_metrics::PAPYRUS_PENDING_DATA_AGE_SECONDS,
+            freshness.age().as_secs_f64()
+        );
+    }
+    Ok(PendingSyncTaskResult::ReportedPendingDataAge)
+}
+
 async fn get_pending_class<TCentralSource: CentralSourceTrait + Sync + Send + 'static>(
     class_hash: ClassHash,
     central_source: Arc<TCentralSource>,
     pending_classes: Arc<RwLock<PendingClasses>>,
+    pending_classes_max_size_bytes: usize,
 ) -> Result<PendingSyncTaskResult, StateSyncError> {
     let class = central_source.get_class(class_hash).await?;
-    pending_classes.write().await.add_class(class_hash, class);
+    let mut pending_classes = pending_classes.write().await;
+    pending_classes.add_class(class_hash, class);
+    evict_oldest_pending_classes(&mut pending_classes, pending_classes_max_size_bytes);
     Ok(PendingSyncTaskResult::DownloadedClassOrCompiledClass)
 }
 
@@ -198,8 +247,31 @@ async fn get_pending_compiled_class<TCentralSource: CentralSourceTrait + Sync +
     class_hash: ClassHash,
     central_source: Arc<TCentralSource>,
     pending_classes: Arc<RwLock<PendingClasses>>,
+    pending_classes_max_size_bytes: usize,
 ) -> Result<PendingSyncTaskResult, StateSyncError> {
     let compiled_class = central_source.get_compiled_class(class_hash).await?;
-    pending_classes.write().await.add_compiled_class(class_hash, compiled_class);
+    let mut pending_classes = pending_classes.write().await;
+    pending_classes.add_compiled_class(class_hash, compiled_class);
+    evict_oldest_pending_classes(&mut pending_classes, pending_classes_max_size_bytes);
     Ok(PendingSyncTaskResult::DownloadedClassOrCompiledClass)
 }
+
+// The evicted classes are still part of the pending state. Whoever needs them, like the RPC,
+// fetches them again from the central source.
+fn evict_oldest_pending_classes(
+    pending_classes: &mut PendingClasses,
+    pending_classes_max_size_bytes: usize,
+) {
+    let num_evicted = pending_classes.evict_oldest(pending_classes_max_size_bytes);
+    if num_evicted > 0 {
+        warn!(
+            "Pending classes exceeded {pending_classes_max_size_bytes} bytes. Removed the \
+             {num_evicted} oldest classes from memory."
+        );
+        metrics::counter!(papyrus_metrics::PAPYRUS_PENDING_CLASSES_EVICTIONS, num_evicted as u64);
+    }
+    metrics::gauge!(
+        papyrus_metrics::PAPYRUS_PENDING_CLASSES_SIZE_BYTES,
+        pending_classes.size_in_bytes() as f64
+    );
+}
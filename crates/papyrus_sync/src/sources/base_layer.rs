@@ -22,8 +22,11 @@ impl<Error: std::error::Error + Sync + Send> BaseLayerSourceErrorTrait for Error
 #[cfg_attr(test, automock)]
 #[async_trait]
 pub trait BaseLayerSourceTrait {
+    /// Returns the latest block proved on the base layer, as seen from the base layer block that
+    /// has confirmation_depth blocks on top of it.
     async fn latest_proved_block(
         &self,
+        confirmation_depth: u64,
     ) -> Result<Option<(BlockNumber, BlockHash)>, BaseLayerSourceError>;
 }
 
@@ -35,8 +38,9 @@ impl<
 {
     async fn latest_proved_block(
         &self,
+        confirmation_depth: u64,
     ) -> Result<Option<(BlockNumber, BlockHash)>, BaseLayerSourceError> {
-        self.latest_proved_block(None)
+        self.latest_proved_block(Some(confirmation_depth))
             .await
             .map_err(|e| BaseLayerSourceError::BaseLayerContractError(Box::new(e)))
     }
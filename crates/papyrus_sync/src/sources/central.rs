@@ -1,11 +1,13 @@
 #[cfg(test)]
 #[path = "central_test.rs"]
 mod central_test;
+mod shared_client;
 mod state_update_stream;
 
 use std::collections::{BTreeMap, HashMap};
 use std::num::NonZeroUsize;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 use async_stream::stream;
 use async_trait::async_trait;
@@ -19,7 +21,11 @@ use lru::LruCache;
 use mockall::automock;
 use papyrus_common::pending_classes::ApiContractClass;
 use papyrus_common::BlockHashAndNumber;
-use papyrus_config::converters::{deserialize_optional_map, serialize_optional_map};
+use papyrus_config::converters::{
+    deserialize_optional_map,
+    deserialize_seconds_to_duration,
+    serialize_optional_map,
+};
 use papyrus_config::dumping::{append_sub_config_name, ser_param, SerializeConfig};
 use papyrus_config::{ParamPath, ParamPrivacyInput, SerializedParam};
 use papyrus_storage::state::StateStorageReader;
@@ -35,7 +41,9 @@ use starknet_client::reader::{ReaderClientError, StarknetFeederGatewayClient, St
 use starknet_client::{ClientCreationError, RetryConfig};
 use tracing::{debug, trace};
 
+pub use self::shared_client::SharedStarknetClient;
 use self::state_update_stream::{StateUpdateStream, StateUpdateStreamConfig};
+use super::pending::{GenericPendingSource, PendingSource};
 
 type CentralResult<T> = Result<T, CentralError>;
 #[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
@@ -49,6 +57,10 @@ pub struct CentralSourceConfig {
     pub max_classes_to_download: usize,
     // TODO(dan): validate that class_cache_size is a positive integer.
     pub class_cache_size: usize,
+    pub max_concurrent_upstream_requests: usize,
+    pub response_cache_size: usize,
+    #[serde(deserialize_with = "deserialize_seconds_to_duration")]
+    pub response_cache_ttl: Duration,
     pub retry_config: RetryConfig,
 }
 
@@ -62,6 +74,9 @@ impl Default for CentralSourceConfig {
             max_state_updates_to_store_in_memory: 20,
             max_classes_to_download: 20,
             class_cache_size: 100,
+            max_concurrent_upstream_requests: 50,
+            response_cache_size: 100,
+            response_cache_ttl: Duration::from_secs(10),
             retry_config: RetryConfig {
                 retry_base_millis: 30,
                 retry_max_delay_millis: 30000,
@@ -117,6 +132,27 @@ impl SerializeConfig for CentralSourceConfig {
                 "Size of class cache, must be a positive integer.",
                 ParamPrivacyInput::Public,
             ),
+            ser_param(
+                "max_concurrent_upstream_requests",
+                &self.max_concurrent_upstream_requests,
+                "Maximum number of requests to Starknet feeder-gateway at a given time, over all \
+                 types of data and including the pending data. Must be a positive integer.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "response_cache_size",
+                &self.response_cache_size,
+                "Number of feeder-gateway responses to cache per type of data (blocks, block \
+                 signatures, classes and compiled classes). 0 disables the cache.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "response_cache_ttl",
+                &self.response_cache_ttl.as_secs(),
+                "Time in seconds a cached feeder-gateway response is used for. A reverted block \
+                 may be detected only after this time.",
+                ParamPrivacyInput::Public,
+            ),
         ]);
         chain!(self_params_dump, append_sub_config_name(self.retry_config.dump(), "retry_config"))
             .collect()
@@ -432,7 +468,7 @@ fn client_to_central_block(
     }
 }
 
-pub type CentralSource = GenericCentralSource<StarknetFeederGatewayClient>;
+pub type CentralSource = GenericCentralSource<SharedStarknetClient<StarknetFeederGatewayClient>>;
 
 impl CentralSource {
     pub fn new(
@@ -440,12 +476,7 @@ impl CentralSource {
         node_version: &'static str,
         storage_reader: StorageReader,
     ) -> Result<CentralSource, ClientCreationError> {
-        let starknet_client = StarknetFeederGatewayClient::new(
-            &config.url,
-            config.http_headers,
-            node_version,
-            config.retry_config,
-        )?;
+        let starknet_client = new_shared_client(&config, node_version)?;
 
         Ok(CentralSource {
             concurrent_requests: config.concurrent_requests,
@@ -466,4 +497,29 @@ impl CentralSource {
             ))),
         })
     }
+
+    /// Returns a pending source that shares the client of this source, so that their requests to
+    /// the feeder gateway are limited together.
+    pub fn pending_source(&self) -> PendingSource {
+        GenericPendingSource { starknet_client: self.starknet_client.clone() }
+    }
+}
+
+pub(crate) fn new_shared_client(
+    config: &CentralSourceConfig,
+    node_version: &'static str,
+) -> Result<SharedStarknetClient<StarknetFeederGatewayClient>, ClientCreationError> {
+    let starknet_client = StarknetFeederGatewayClient::new(
+        &config.url,
+        config.http_headers.clone(),
+        node_version,
+        config.retry_config,
+    )?;
+    Ok(SharedStarknetClient::new(
+        starknet_client,
+        NonZeroUsize::new(config.max_concurrent_upstream_requests)
+            .expect("max_concurrent_upstream_requests should be a positive integer."),
+        config.response_cache_size,
+        config.response_cache_ttl,
+    ))
 }
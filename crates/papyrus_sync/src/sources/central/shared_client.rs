@@ -0,0 +1,272 @@
+//! A [`StarknetReader`] shared by the central and pending sources that reduces the load they put on
+//! the feeder gateway. Identical requests that are in flight at the same time are sent upstream
+//! once, responses that don't change are cached for a short while, and the number of requests sent
+//! upstream at the same time is limited.
+
+#[cfg(test)]
+#[path = "shared_client_test.rs"]
+mod shared_client_test;
+
+use std::collections::HashMap;
+use std::future::Future;
+use std::hash::Hash;
+use std::num::NonZeroUsize;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use async_trait::async_trait;
+use cairo_lang_starknet_classes::casm_contract_class::CasmContractClass;
+use lru::LruCache;
+use metrics::increment_counter;
+use papyrus_common::metrics::{
+    PAPYRUS_CENTRAL_CACHE_HITS,
+    PAPYRUS_CENTRAL_COALESCED_REQUESTS,
+    PAPYRUS_CENTRAL_UPSTREAM_REQUESTS,
+};
+use starknet_api::block::BlockNumber;
+use starknet_api::core::{ClassHash, SequencerPublicKey};
+use starknet_client::reader::{
+    BlockOrDeprecated,
+    BlockSignatureData,
+    GenericContractClass,
+    PendingData,
+    ReaderClientResult,
+    StarknetReader,
+    StateUpdate,
+};
+use tokio::sync::{oneshot, Semaphore};
+use tokio::time::Instant;
+
+const REQUEST_LABEL: &str = "request";
+
+/// Wraps the client of the feeder gateway. Blocks, block signatures, classes and compiled classes
+/// are coalesced and cached, state updates are only coalesced since each is needed once, and the
+/// other requests, whose responses change all the time, are only limited.
+///
+/// A cached block may be stale if the block was reverted after it was cached, so the cache TTL
+/// bounds the time it takes to detect a revert.
+pub struct SharedStarknetClient<TStarknetClient: StarknetReader + Send + Sync> {
+    starknet_client: TStarknetClient,
+    upstream_permits: Semaphore,
+    blocks: CoalescedRequests<BlockNumber, BlockOrDeprecated>,
+    block_signatures: CoalescedRequests<BlockNumber, BlockSignatureData>,
+    classes: CoalescedRequests<ClassHash, GenericContractClass>,
+    compiled_classes: CoalescedRequests<ClassHash, CasmContractClass>,
+    state_updates: CoalescedRequests<BlockNumber, StateUpdate>,
+}
+
+impl<TStarknetClient: StarknetReader + Send + Sync> SharedStarknetClient<TStarknetClient> {
+    /// A cache size of 0 disables the caching. The cache size is per type of request.
+    pub fn new(
+        starknet_client: TStarknetClient,
+        max_concurrent_upstream_requests: NonZeroUsize,
+        cache_size: usize,
+        cache_ttl: Duration,
+    ) -> Self {
+        let cache_size = NonZeroUsize::new(cache_size);
+        Self {
+            starknet_client,
+            upstream_permits: Semaphore::new(max_concurrent_upstream_requests.get()),
+            blocks: CoalescedRequests::new("block", cache_size, cache_ttl),
+            block_signatures: CoalescedRequests::new("block_signature", cache_size, cache_ttl),
+            classes: CoalescedRequests::new("class", cache_size, cache_ttl),
+            compiled_classes: CoalescedRequests::new("compiled_class", cache_size, cache_ttl),
+            state_updates: CoalescedRequests::new("state_update", None, cache_ttl),
+        }
+    }
+
+    async fn upstream<T>(&self, request: &'static str, response: impl Future<Output = T>) -> T {
+        let _permit = self
+            .upstream_permits
+            .acquire()
+            .await
+            .expect("The upstream requests semaphore should not be closed.");
+        increment_counter!(PAPYRUS_CENTRAL_UPSTREAM_REQUESTS, REQUEST_LABEL => request);
+        response.await
+    }
+}
+
+#[async_trait]
+impl<TStarknetClient: StarknetReader + Send + Sync> StarknetReader
+    for SharedStarknetClient<TStarknetClient>
+{
+    async fn latest_block(&self) -> ReaderClientResult<Option<BlockOrDeprecated>> {
+        self.upstream("latest_block", self.starknet_client.latest_block()).await
+    }
+
+    async fn block(
+        &self,
+        block_number: BlockNumber,
+    ) -> ReaderClientResult<Option<BlockOrDeprecated>> {
+        let response = self.upstream(self.blocks.label, self.starknet_client.block(block_number));
+        self.blocks.get(block_number, response).await
+    }
+
+    async fn class_by_hash(
+        &self,
+        class_hash: ClassHash,
+    ) -> ReaderClientResult<Option<GenericContractClass>> {
+        let response =
+            self.upstream(self.classes.label, self.starknet_client.class_by_hash(class_hash));
+        self.classes.get(class_hash, response).await
+    }
+
+    async fn compiled_class_by_hash(
+        &self,
+        class_hash: ClassHash,
+    ) -> ReaderClientResult<Option<CasmContractClass>> {
+        let response = self.upstream(
+            self.compiled_classes.label,
+            self.starknet_client.compiled_class_by_hash(class_hash),
+        );
+        self.compiled_classes.get(class_hash, response).await
+    }
+
+    async fn state_update(
+        &self,
+        block_number: BlockNumber,
+    ) -> ReaderClientResult<Option<StateUpdate>> {
+        let response = self
+            .upstream(self.state_updates.label, self.starknet_client.state_update(block_number));
+        self.state_updates.get(block_number, response).await
+    }
+
+    async fn pending_data(&self) -> ReaderClientResult<Option<PendingData>> {
+        self.upstream("pending_data", self.starknet_client.pending_data()).await
+    }
+
+    async fn is_alive(&self) -> bool {
+        self.upstream("is_alive", self.starknet_client.is_alive()).await
+    }
+
+    async fn block_signature(
+        &self,
+        block_number: BlockNumber,
+    ) -> ReaderClientResult<Option<BlockSignatureData>> {
+        let response = self.upstream(
+            self.block_signatures.label,
+            self.starknet_client.block_signature(block_number),
+        );
+        self.block_signatures.get(block_number, response).await
+    }
+
+    async fn sequencer_pub_key(&self) -> ReaderClientResult<SequencerPublicKey> {
+        self.upstream("sequencer_pub_key", self.starknet_client.sequencer_pub_key()).await
+    }
+}
+
+// The requests waiting for the response of an identical request that was sent upstream.
+type Waiters<V> = Vec<oneshot::Sender<Option<V>>>;
+
+// The requests of one type, keyed by their argument.
+struct CoalescedRequests<K, V> {
+    label: &'static str,
+    in_flight: Mutex<HashMap<K, Waiters<V>>>,
+    // Holds only found values, with the time they were cached. None if caching is disabled.
+    cache: Option<Mutex<LruCache<K, (Instant, V)>>>,
+    cache_ttl: Duration,
+}
+
+impl<K: Clone + Eq + Hash, V: Clone> CoalescedRequests<K, V> {
+    fn new(label: &'static str, cache_size: Option<NonZeroUsize>, cache_ttl: Duration) -> Self {
+        Self {
+            label,
+            in_flight: Mutex::new(HashMap::new()),
+            cache: cache_size.map(|cache_size| Mutex::new(LruCache::new(cache_size))),
+            cache_ttl,
+        }
+    }
+
+    // Returns the cached value of the key, or the response of an identical request that is in
+    // flight, and otherwise awaits `upstream_response`. If the request that was in flight fails,
+    // `upstream_response` is awaited as well so that the error is the request's own.
+    async fn get(
+        &self,
+        key: K,
+        upstream_response: impl Future<Output = ReaderClientResult<Option<V>>>,
+    ) -> ReaderClientResult<Option<V>> {
+        if let Some(value) = self.cached(&key) {
+            increment_counter!(PAPYRUS_CENTRAL_CACHE_HITS, REQUEST_LABEL => self.label);
+            return Ok(Some(value));
+        }
+        let waiter = {
+            let mut in_flight =
+                self.in_flight.lock().expect("In flight lock should not be poisoned");
+            match in_flight.get_mut(&key) {
+                Some(waiters) => {
+                    let (sender, receiver) = oneshot::channel();
+                    waiters.push(sender);
+                    Some(receiver)
+                }
+                None => {
+                    in_flight.insert(key.clone(), Vec::new());
+                    None
+                }
+            }
+        };
+        if let Some(waiter) = waiter {
+            if let Ok(response) = waiter.await {
+                increment_counter!(PAPYRUS_CENTRAL_COALESCED_REQUESTS, REQUEST_LABEL => self.label);
+                return Ok(response);
+            }
+            return upstream_response.await;
+        }
+
+        let in_flight_guard = InFlightGuard { in_flight: &self.in_flight, key: Some(key.clone()) };
+        let result = upstream_response.await;
+        // The value is cached before the request stops being in flight, so that an identical
+        // request never misses both.
+        if let (Ok(Some(value)), Some(cache)) = (&result, &self.cache) {
+            cache
+                .lock()
+                .expect("Response cache lock should not be poisoned")
+                .put(key, (Instant::now(), value.clone()));
+        }
+        let waiters = in_flight_guard.finish();
+        if let Ok(response) = &result {
+            for waiter in waiters {
+                // A waiter that was dropped doesn't need the response.
+                let _ = waiter.send(response.clone());
+            }
+        }
+        result
+    }
+
+    fn cached(&self, key: &K) -> Option<V> {
+        let mut cache =
+            self.cache.as_ref()?.lock().expect("Response cache lock should not be poisoned");
+        let (cached_at, _) = cache.peek(key)?;
+        if cached_at.elapsed() >= self.cache_ttl {
+            cache.pop(key);
+            return None;
+        }
+        cache.get(key).map(|(_, value)| value.clone())
+    }
+}
+
+// Removes a request from the requests in flight when it's done, or when it's dropped before that.
+// Dropping it drops the senders of its waiters, which then send the request themselves.
+struct InFlightGuard<'a, K: Eq + Hash, V> {
+    in_flight: &'a Mutex<HashMap<K, Waiters<V>>>,
+    // None once the request is done.
+    key: Option<K>,
+}
+
+impl<K: Eq + Hash, V> InFlightGuard<'_, K, V> {
+    fn finish(mut self) -> Waiters<V> {
+        let key = self.key.take().expect("The request should finish once.");
+        self.in_flight
+            .lock()
+            .expect("In flight lock should not be poisoned")
+            .remove(&key)
+            .unwrap_or_default()
+    }
+}
+
+impl<K: Eq + Hash, V> Drop for InFlightGuard<'_, K, V> {
+    fn drop(&mut self) {
+        if let (Some(key), Ok(mut in_flight)) = (self.key.take(), self.in_flight.lock()) {
+            in_flight.remove(&key);
+        }
+    }
+}
@@ -0,0 +1,237 @@
+use std::num::NonZeroUsize;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use assert_matches::assert_matches;
+use async_trait::async_trait;
+use cairo_lang_starknet_classes::casm_contract_class::CasmContractClass;
+use futures_util::future::join_all;
+use futures_util::StreamExt;
+use lru::LruCache;
+use papyrus_storage::test_utils::get_test_storage;
+use papyrus_storage::StorageReader;
+use reqwest::StatusCode;
+use starknet_api::block::BlockNumber;
+use starknet_api::core::{ClassHash, SequencerPublicKey};
+use starknet_api::crypto::utils::PublicKey;
+use starknet_client::reader::{
+    BlockOrDeprecated,
+    BlockSignatureData,
+    GenericContractClass,
+    PendingData,
+    ReaderClientError,
+    ReaderClientResult,
+    StarknetReader,
+    StateUpdate,
+};
+use starknet_client::ClientError;
+use starknet_types_core::felt::Felt;
+
+use super::SharedStarknetClient;
+use crate::sources::central::state_update_stream::StateUpdateStreamConfig;
+use crate::sources::central::{CentralSourceTrait, GenericCentralSource};
+
+const RESPONSE_DELAY: Duration = Duration::from_millis(10);
+const CACHE_TTL: Duration = Duration::from_secs(10);
+const N_BLOCKS: u64 = 10;
+const CLASS_HASH: ClassHash = ClassHash(Felt::ONE);
+
+// A feeder gateway that counts the requests it gets and answers each after RESPONSE_DELAY.
+#[derive(Default)]
+struct MockGateway {
+    fail: bool,
+    requests: AtomicUsize,
+    in_flight: AtomicUsize,
+    max_in_flight: AtomicUsize,
+}
+
+impl MockGateway {
+    async fn respond<T>(&self, response: T) -> ReaderClientResult<T> {
+        self.requests.fetch_add(1, Ordering::SeqCst);
+        let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+        tokio::time::sleep(RESPONSE_DELAY).await;
+        self.in_flight.fetch_sub(1, Ordering::SeqCst);
+        if self.fail {
+            return Err(ReaderClientError::ClientError(ClientError::BadResponseStatus {
+                code: StatusCode::INTERNAL_SERVER_ERROR,
+                message: String::new(),
+            }));
+        }
+        Ok(response)
+    }
+
+    fn requests(&self) -> usize {
+        self.requests.load(Ordering::SeqCst)
+    }
+}
+
+#[async_trait]
+impl StarknetReader for MockGateway {
+    async fn latest_block(&self) -> ReaderClientResult<Option<BlockOrDeprecated>> {
+        self.respond(None).await
+    }
+
+    async fn block(
+        &self,
+        _block_number: BlockNumber,
+    ) -> ReaderClientResult<Option<BlockOrDeprecated>> {
+        self.respond(Some(BlockOrDeprecated::default())).await
+    }
+
+    async fn class_by_hash(
+        &self,
+        _class_hash: ClassHash,
+    ) -> ReaderClientResult<Option<GenericContractClass>> {
+        self.respond(Some(GenericContractClass::Cairo0ContractClass(Default::default()))).await
+    }
+
+    async fn compiled_class_by_hash(
+        &self,
+        _class_hash: ClassHash,
+    ) -> ReaderClientResult<Option<CasmContractClass>> {
+        self.respond(None).await
+    }
+
+    async fn state_update(
+        &self,
+        _block_number: BlockNumber,
+    ) -> ReaderClientResult<Option<StateUpdate>> {
+        self.respond(None).await
+    }
+
+    async fn pending_data(&self) -> ReaderClientResult<Option<PendingData>> {
+        self.respond(None).await
+    }
+
+    async fn is_alive(&self) -> bool {
+        true
+    }
+
+    async fn block_signature(
+        &self,
+        block_number: BlockNumber,
+    ) -> ReaderClientResult<Option<BlockSignatureData>> {
+        self.respond(Some(BlockSignatureData { block_number, ..Default::default() })).await
+    }
+
+    async fn sequencer_pub_key(&self) -> ReaderClientResult<SequencerPublicKey> {
+        self.respond(SequencerPublicKey(PublicKey(Felt::ZERO))).await
+    }
+}
+
+fn shared_client(
+    gateway: MockGateway,
+    max_concurrent_upstream_requests: usize,
+) -> SharedStarknetClient<MockGateway> {
+    SharedStarknetClient::new(
+        gateway,
+        NonZeroUsize::new(max_concurrent_upstream_requests).unwrap(),
+        100,
+        CACHE_TTL,
+    )
+}
+
+fn central_source<TStarknetClient: StarknetReader + Send + Sync>(
+    starknet_client: Arc<TStarknetClient>,
+    storage_reader: StorageReader,
+) -> GenericCentralSource<TStarknetClient> {
+    GenericCentralSource {
+        concurrent_requests: 5,
+        starknet_client,
+        storage_reader,
+        state_update_stream_config: StateUpdateStreamConfig {
+            max_state_updates_to_download: 5,
+            max_state_updates_to_store_in_memory: 5,
+            max_classes_to_download: 5,
+        },
+        class_cache: Arc::new(Mutex::new(LruCache::new(NonZeroUsize::new(2).unwrap()))),
+        compiled_class_cache: Arc::new(Mutex::new(LruCache::new(NonZeroUsize::new(2).unwrap()))),
+    }
+}
+
+// A round of the sync: the blocks are downloaded, the class declared in them is requested by the
+// state update stream and the pending sync at the same time, and the next round checks the last
+// block for a revert.
+async fn sync_round<TStarknetClient: StarknetReader + Send + Sync + 'static>(
+    central_source: &GenericCentralSource<TStarknetClient>,
+) {
+    let blocks = central_source
+        .stream_new_blocks(BlockNumber(0), BlockNumber(N_BLOCKS))
+        .collect::<Vec<_>>()
+        .await;
+    assert_eq!(blocks.len(), N_BLOCKS as usize);
+    assert!(blocks.iter().all(|block| block.is_ok()));
+    let classes =
+        join_all([central_source.get_class(CLASS_HASH), central_source.get_class(CLASS_HASH)])
+            .await;
+    assert!(classes.iter().all(|class| class.is_ok()));
+    assert!(central_source.get_block_hash(BlockNumber(N_BLOCKS - 1)).await.unwrap().is_some());
+}
+
+#[tokio::test(start_paused = true)]
+async fn sync_round_sends_fewer_requests() {
+    let ((storage_reader, _), _temp_dir) = get_test_storage();
+    let gateway = Arc::new(MockGateway::default());
+    sync_round(&central_source(gateway.clone(), storage_reader.clone())).await;
+    // A block and a signature per block, the class twice and the block of the revert check.
+    assert_eq!(gateway.requests(), 2 * N_BLOCKS as usize + 3);
+
+    let shared_client = Arc::new(shared_client(MockGateway::default(), 50));
+    sync_round(&central_source(shared_client.clone(), storage_reader)).await;
+    assert_eq!(shared_client.starknet_client.requests(), 2 * N_BLOCKS as usize + 1);
+}
+
+#[tokio::test(start_paused = true)]
+async fn identical_concurrent_requests_are_coalesced() {
+    let shared_client = shared_client(MockGateway::default(), 50);
+    let blocks = join_all((0..5).map(|_| shared_client.block(BlockNumber(0)))).await;
+    assert!(blocks.iter().all(|block| block.as_ref().unwrap().is_some()));
+    assert_eq!(shared_client.starknet_client.requests(), 1);
+
+    // Requests for different blocks aren't coalesced.
+    join_all((1..5).map(|block_number| shared_client.block(BlockNumber(block_number)))).await;
+    assert_eq!(shared_client.starknet_client.requests(), 5);
+}
+
+#[tokio::test(start_paused = true)]
+async fn cached_responses_expire() {
+    let shared_client = shared_client(MockGateway::default(), 50);
+    shared_client.block(BlockNumber(0)).await.unwrap();
+    shared_client.block(BlockNumber(0)).await.unwrap();
+    assert_eq!(shared_client.starknet_client.requests(), 1);
+
+    tokio::time::sleep(CACHE_TTL).await;
+    shared_client.block(BlockNumber(0)).await.unwrap();
+    assert_eq!(shared_client.starknet_client.requests(), 2);
+
+    // Requests whose responses change aren't cached.
+    shared_client.pending_data().await.unwrap();
+    shared_client.pending_data().await.unwrap();
+    assert_eq!(shared_client.starknet_client.requests(), 4);
+}
+
+#[tokio::test(start_paused = true)]
+async fn failed_request_is_not_shared() {
+    let shared_client = shared_client(MockGateway { fail: true, ..Default::default() }, 50);
+    let blocks = join_all((0..3).map(|_| shared_client.block(BlockNumber(0)))).await;
+    for block in blocks {
+        assert_matches!(block, Err(ReaderClientError::ClientError(_)));
+    }
+    // The requests that waited for the failed request sent their own requests.
+    assert_eq!(shared_client.starknet_client.requests(), 3);
+}
+
+#[tokio::test(start_paused = true)]
+async fn upstream_requests_are_limited() {
+    const MAX_CONCURRENT_UPSTREAM_REQUESTS: usize = 3;
+    let shared_client = shared_client(MockGateway::default(), MAX_CONCURRENT_UPSTREAM_REQUESTS);
+    join_all((0..10).map(|block_number| shared_client.block_signature(BlockNumber(block_number))))
+        .await;
+    assert_eq!(shared_client.starknet_client.requests(), 10);
+    assert_eq!(
+        shared_client.starknet_client.max_in_flight.load(Ordering::SeqCst),
+        MAX_CONCURRENT_UPSTREAM_REQUESTS
+    );
+}
@@ -7,6 +7,7 @@ use async_trait::async_trait;
 use cairo_lang_starknet_classes::casm_contract_class::CasmContractClass;
 use futures::StreamExt;
 use indexmap::IndexMap;
+use papyrus_base_layer::ethereum_base_layer_contract::EthereumBaseLayerConfig;
 use papyrus_common::pending_classes::{ApiContractClass, PendingClasses};
 use papyrus_common::BlockHashAndNumber;
 use papyrus_storage::base_layer::BaseLayerStorageReader;
@@ -89,11 +90,13 @@ async fn check_storage(
 fn get_test_sync_config(verify_blocks: bool) -> SyncConfig {
     SyncConfig {
         block_propagation_sleep_duration: SYNC_SLEEP_DURATION,
-        base_layer_propagation_sleep_duration: BASE_LAYER_SLEEP_DURATION,
+        base_layer_propagation_sleep_duration: Duration::ZERO,
         recoverable_error_sleep_duration: SYNC_SLEEP_DURATION,
         blocks_max_stream_size: STREAM_SIZE,
         state_updates_max_stream_size: STREAM_SIZE,
         verify_blocks,
+        pending_classes_max_size_bytes: usize::MAX,
+        pending_data_max_staleness: Duration::from_secs(30),
     }
 }
 
@@ -117,9 +120,15 @@ async fn run_sync(
         pending_source: Arc::new(pending_source),
         pending_classes: Arc::new(RwLock::new(PendingClasses::default())),
         base_layer_source: Arc::new(base_layer),
+        base_layer_config: EthereumBaseLayerConfig {
+            poll_interval: BASE_LAYER_SLEEP_DURATION,
+            ..Default::default()
+        },
         reader,
         writer,
         sequencer_pub_key: None,
+        config_updates: None,
+        genesis_hash: BlockHash::default(),
     };
 
     state_sync.run().await?;
@@ -136,7 +145,7 @@ async fn sync_empty_chain() {
 
     // Mock base_layer without any block.
     let mut base_layer_mock = MockBaseLayerSourceTrait::new();
-    base_layer_mock.expect_latest_proved_block().returning(|| Ok(None));
+    base_layer_mock.expect_latest_proved_block().returning(|_| Ok(None));
 
     let ((reader, writer), _temp_dir) = get_test_storage();
     let sync_future = run_sync(
@@ -222,7 +231,7 @@ async fn sync_happy_flow() {
     // TODO(dvir): find a better way to do this.
     let mut base_layer_mock = MockBaseLayerSourceTrait::new();
     let mut base_layer_call_counter = 0;
-    base_layer_mock.expect_latest_proved_block().returning(move || {
+    base_layer_mock.expect_latest_proved_block().returning(move |_| {
         base_layer_call_counter += 1;
         Ok(match base_layer_call_counter {
             1 => None,
@@ -300,7 +309,7 @@ async fn sync_with_revert() {
     // reverted_mutex is true.
     let mock = MockedCentralWithRevert { reverted: reverted_mutex.clone() };
     let mut base_layer_mock = MockBaseLayerSourceTrait::new();
-    base_layer_mock.expect_latest_proved_block().returning(|| Ok(None));
+    base_layer_mock.expect_latest_proved_block().returning(|_| Ok(None));
     let sync_future =
         run_sync(reader.clone(), writer, mock, base_layer_mock, get_test_sync_config(false));
 
@@ -667,7 +676,7 @@ async fn sequencer_pub_key_management() {
 
     // Mock base_layer without any block.
     let mut base_layer_mock = MockBaseLayerSourceTrait::new();
-    base_layer_mock.expect_latest_proved_block().returning(|| Ok(None));
+    base_layer_mock.expect_latest_proved_block().returning(|_| Ok(None));
 
     let ((reader, writer), _temp_dir) = get_test_storage();
     let config = get_test_sync_config(true);
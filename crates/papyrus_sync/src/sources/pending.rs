@@ -16,7 +16,7 @@ use starknet_client::reader::{
 use starknet_client::ClientCreationError;
 
 // TODO(dvir): add pending config.
-use super::central::CentralSourceConfig;
+use super::central::{new_shared_client, CentralSourceConfig, SharedStarknetClient};
 
 pub struct GenericPendingSource<TStarknetClient: StarknetReader + Send + Sync> {
     pub starknet_client: Arc<TStarknetClient>,
@@ -50,19 +50,17 @@ impl<TStarknetClient: StarknetReader + Send + Sync + 'static> PendingSourceTrait
     }
 }
 
-pub type PendingSource = GenericPendingSource<StarknetFeederGatewayClient>;
+pub type PendingSource = GenericPendingSource<SharedStarknetClient<StarknetFeederGatewayClient>>;
 
 impl PendingSource {
+    /// Prefer [`CentralSource::pending_source`], which shares the client of the central source.
+    ///
+    /// [`CentralSource::pending_source`]: super::central::CentralSource::pending_source
     pub fn new(
         config: CentralSourceConfig,
         node_version: &'static str,
     ) -> Result<PendingSource, ClientCreationError> {
-        let starknet_client = StarknetFeederGatewayClient::new(
-            &config.url,
-            config.http_headers,
-            node_version,
-            config.retry_config,
-        )?;
+        let starknet_client = new_shared_client(&config, node_version)?;
 
         Ok(PendingSource { starknet_client: Arc::new(starknet_client) })
     }
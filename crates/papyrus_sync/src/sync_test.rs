@@ -5,13 +5,14 @@ use assert_matches::assert_matches;
 use cairo_lang_starknet_classes::casm_contract_class::CasmContractClass;
 use futures_util::StreamExt;
 use indexmap::IndexMap;
+use papyrus_base_layer::ethereum_base_layer_contract::EthereumBaseLayerConfig;
 use papyrus_common::pending_classes::{ApiContractClass, PendingClasses, PendingClassesTrait};
-use papyrus_storage::base_layer::BaseLayerStorageReader;
+use papyrus_storage::base_layer::{BaseLayerStorageReader, BaseLayerStorageWriter};
 use papyrus_storage::header::HeaderStorageWriter;
 use papyrus_storage::test_utils::get_test_storage;
 use papyrus_storage::{StorageReader, StorageWriter};
 use pretty_assertions::assert_eq;
-use starknet_api::block::{BlockHash, BlockHeader, BlockNumber};
+use starknet_api::block::{Block, BlockHash, BlockHeader, BlockNumber};
 use starknet_api::core::{ClassHash, CompiledClassHash, ContractAddress, Nonce, PatriciaKey};
 use starknet_api::deprecated_contract_class::ContractClass as DeprecatedContractClass;
 use starknet_api::hash::StarkHash;
@@ -40,9 +41,11 @@ use crate::{
     StateSyncError,
     SyncConfig,
     SyncEvent,
-    GENESIS_HASH,
 };
 
+const PENDING_DATA_MAX_STALENESS: Duration = Duration::from_secs(30);
+const GENESIS_HASH: &str = "0x0";
+
 // TODO(anatg): Add a test to check that the sync calls the sort_state_diff function
 // before writing to the storage.
 #[test]
@@ -140,9 +143,9 @@ async fn stream_new_base_layer_block_test_header_marker() {
     let block_numbers = vec![5, 1, 10, 4];
     let mut iter = block_numbers.into_iter().map(|bn| (BlockNumber(bn), BlockHash::default()));
     let mut mock = MockBaseLayerSourceTrait::new();
-    mock.expect_latest_proved_block().times(4).returning(move || Ok(iter.next()));
+    mock.expect_latest_proved_block().times(4).returning(move |_| Ok(iter.next()));
     let mut stream =
-        stream_new_base_layer_block(reader, Arc::new(mock), Duration::from_millis(0)).boxed();
+        stream_new_base_layer_block(reader, Arc::new(mock), Duration::from_millis(0), 0).boxed();
 
     let event = stream.next().await.unwrap().unwrap();
     assert_matches!(event, SyncEvent::NewBaseLayerBlock { block_number: BlockNumber(1), .. });
@@ -162,10 +165,73 @@ async fn stream_new_base_layer_block_no_blocks_on_base_layer() {
     // was found.
     let mut values = vec![None, Some((BlockNumber(1), BlockHash::default()))].into_iter();
     let mut mock = MockBaseLayerSourceTrait::new();
-    mock.expect_latest_proved_block().times(2).returning(move || Ok(values.next().unwrap()));
+    mock.expect_latest_proved_block().times(2).returning(move |_| Ok(values.next().unwrap()));
+
+    let mut stream =
+        stream_new_base_layer_block(reader, Arc::new(mock), Duration::from_millis(0), 0).boxed();
+
+    let event = stream.next().await.unwrap().unwrap();
+    assert_matches!(event, SyncEvent::NewBaseLayerBlock { block_number: BlockNumber(1), .. });
+}
+
+#[tokio::test]
+async fn stream_new_base_layer_block_skips_marked_block() {
+    const CONFIRMATION_DEPTH: u64 = 32;
+    let (reader, mut writer) = get_test_storage().0;
+
+    // Header marker points to to block number 5 and base layer marker to block number 2.
+    add_headers(5, &mut writer);
+    writer
+        .begin_rw_txn()
+        .unwrap()
+        .update_base_layer_block_marker(&BlockNumber(2))
+        .unwrap()
+        .commit()
+        .unwrap();
+
+    // Block 1 is already marked, so only block 3 is returned.
+    let mut values = vec![1, 3].into_iter().map(|bn| Some((BlockNumber(bn), BlockHash::default())));
+    let mut mock = MockBaseLayerSourceTrait::new();
+    mock.expect_latest_proved_block()
+        .withf(|confirmation_depth| *confirmation_depth == CONFIRMATION_DEPTH)
+        .times(2)
+        .returning(move |_| Ok(values.next().unwrap()));
+
+    let mut stream = stream_new_base_layer_block(
+        reader,
+        Arc::new(mock),
+        Duration::from_millis(0),
+        CONFIRMATION_DEPTH,
+    )
+    .boxed();
+
+    let event = stream.next().await.unwrap().unwrap();
+    assert_matches!(event, SyncEvent::NewBaseLayerBlock { block_number: BlockNumber(3), .. });
+}
+
+#[tokio::test]
+async fn stream_new_base_layer_block_reorg() {
+    let (reader, mut writer) = get_test_storage().0;
+
+    // Header marker points to to block number 5 and base layer marker to block number 4.
+    add_headers(5, &mut writer);
+    writer
+        .begin_rw_txn()
+        .unwrap()
+        .update_base_layer_block_marker(&BlockNumber(4))
+        .unwrap()
+        .commit()
+        .unwrap();
+
+    // A block behind the base layer marker is returned only after it's seen in two consecutive
+    // pollings.
+    let mut values =
+        vec![1, 3, 1, 1].into_iter().map(|bn| Some((BlockNumber(bn), BlockHash::default())));
+    let mut mock = MockBaseLayerSourceTrait::new();
+    mock.expect_latest_proved_block().times(4).returning(move |_| Ok(values.next().unwrap()));
 
     let mut stream =
-        stream_new_base_layer_block(reader, Arc::new(mock), Duration::from_millis(0)).boxed();
+        stream_new_base_layer_block(reader, Arc::new(mock), Duration::from_millis(0), 0).boxed();
 
     let event = stream.next().await.unwrap().unwrap();
     assert_matches!(event, SyncEvent::NewBaseLayerBlock { block_number: BlockNumber(1), .. });
@@ -197,9 +263,12 @@ fn store_base_layer_block_test() {
         pending_source: Arc::new(MockPendingSourceTrait::new()),
         pending_classes: Arc::new(RwLock::new(PendingClasses::default())),
         base_layer_source: Arc::new(MockBaseLayerSourceTrait::new()),
+        base_layer_config: EthereumBaseLayerConfig::default(),
         reader,
         writer,
         sequencer_pub_key: None,
+        config_updates: None,
+        genesis_hash: BlockHash::default(),
     };
 
     // Trying to store a block without a header in the storage.
@@ -218,6 +287,39 @@ fn store_base_layer_block_test() {
     assert_eq!(base_layer_marker, BlockNumber(1));
 }
 
+#[test]
+fn first_block_parent_hash_is_verified_against_the_genesis_hash() {
+    let (reader, writer) = get_test_storage().0;
+    let genesis_hash = BlockHash(felt!("0x123"));
+    let gen_state_sync = GenericStateSync {
+        config: SyncConfig::default(),
+        shared_highest_block: Arc::new(RwLock::new(None)),
+        pending_data: Arc::new(RwLock::new(PendingData::default())),
+        central_source: Arc::new(MockCentralSourceTrait::new()),
+        pending_source: Arc::new(MockPendingSourceTrait::new()),
+        pending_classes: Arc::new(RwLock::new(PendingClasses::default())),
+        base_layer_source: Arc::new(MockBaseLayerSourceTrait::new()),
+        base_layer_config: EthereumBaseLayerConfig::default(),
+        reader,
+        writer,
+        sequencer_pub_key: None,
+        config_updates: None,
+        genesis_hash,
+    };
+
+    let mut block = Block::default();
+    block.header.parent_hash = genesis_hash;
+    assert!(gen_state_sync.verify_parent_block_hash(BlockNumber(0), &block).is_ok());
+
+    block.header.parent_hash = BlockHash(felt!(GENESIS_HASH));
+    let res = gen_state_sync.verify_parent_block_hash(BlockNumber(0), &block);
+    assert_matches!(
+        res,
+        Err(StateSyncError::GenesisHashMismatch { parent_hash, genesis_hash: expected_hash })
+            if parent_hash == BlockHash(felt!(GENESIS_HASH)) && expected_hash == genesis_hash
+    );
+}
+
 // Adds to the storage 'headers_num' headers.
 fn add_headers(headers_num: u64, writer: &mut StorageWriter) {
     for i in 0..headers_num {
@@ -279,12 +381,17 @@ async fn test_pending_sync(
         Arc::new(mock_pending_source),
         pending_data_lock.clone(),
         pending_classes_lock.clone(),
+        BlockHash(felt!(GENESIS_HASH)),
         Duration::ZERO,
+        usize::MAX,
+        PENDING_DATA_MAX_STALENESS,
     )
     .await
     .unwrap();
 
-    assert_eq!(pending_data_lock.read().await.clone(), expected_pending_data);
+    // The freshness depends on when the data was fetched, and is checked separately.
+    let pending_data = pending_data_lock.read().await.clone();
+    assert_eq!(PendingData { freshness: None, ..pending_data }, expected_pending_data);
     if let Some(expected_pending_classes) = expected_pending_classes {
         assert_eq!(pending_classes_lock.read().await.clone(), expected_pending_classes);
     }
@@ -643,6 +750,7 @@ async fn pending_sync_classes_request_only_new_classes() {
             },
             ..Default::default()
         },
+        ..Default::default()
     };
     let mut second_new_pending_data = first_new_pending_data.clone();
     second_new_pending_data
@@ -770,3 +878,54 @@ async fn pending_sync_classes_are_cleaned_on_first_pending_data_from_latest_bloc
     )
     .await
 }
+
+#[tokio::test]
+async fn pending_sync_refreshes_freshness_when_data_did_not_change() {
+    let genesis_hash = BlockHash(felt!(GENESIS_HASH));
+    let (reader, _writer) = get_test_storage().0;
+    let pending_data = PendingData {
+        block: PendingBlockOrDeprecated::Deprecated(DeprecatedPendingBlock {
+            parent_block_hash: genesis_hash,
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+    let new_block_pending_data = PendingData {
+        block: PendingBlockOrDeprecated::Deprecated(DeprecatedPendingBlock {
+            parent_block_hash: BlockHash(StarkHash::ONE),
+            ..Default::default()
+        }),
+        ..Default::default()
+    };
+
+    let mut mock_pending_source = MockPendingSourceTrait::new();
+    let unchanged_pending_data = pending_data.clone();
+    mock_pending_source
+        .expect_get_pending_data()
+        .times(1)
+        .return_once(move || Ok(unchanged_pending_data));
+    mock_pending_source
+        .expect_get_pending_data()
+        .times(1)
+        .return_once(move || Ok(new_block_pending_data));
+    // The pending data was never fetched.
+    let pending_data_lock = Arc::new(RwLock::new(pending_data));
+
+    sync_pending_data(
+        reader,
+        Arc::new(MockCentralSourceTrait::new()),
+        Arc::new(mock_pending_source),
+        pending_data_lock.clone(),
+        Arc::new(RwLock::new(PendingClasses::default())),
+        BlockHash(felt!(GENESIS_HASH)),
+        Duration::ZERO,
+        usize::MAX,
+        PENDING_DATA_MAX_STALENESS,
+    )
+    .await
+    .unwrap();
+
+    let freshness = pending_data_lock.read().await.freshness.unwrap();
+    assert_eq!(freshness.max_staleness, PENDING_DATA_MAX_STALENESS);
+    assert!(!freshness.is_stale());
+}
@@ -0,0 +1,199 @@
+//! Configuration of the consensus.
+
+use std::collections::BTreeMap;
+use std::time::Duration;
+
+use papyrus_config::converters::{
+    deserialize_optional_vec_u8,
+    deserialize_seconds_to_duration,
+    serialize_optional_vec_u8,
+};
+use papyrus_config::dumping::{ser_optional_param, ser_param, SerializeConfig};
+use papyrus_config::{ParamPath, ParamPrivacyInput, SerializedParam};
+use serde::de::Error as _;
+use serde::{Deserialize, Deserializer, Serialize};
+use starknet_api::block::BlockNumber;
+use starknet_api::crypto::utils::PublicKey;
+use starknet_types_core::felt::Felt;
+
+use crate::types::ValidatorId;
+
+/// Configuration for consensus.
+#[derive(Clone, Debug, Serialize, Deserialize, PartialEq)]
+pub struct ConsensusConfig {
+    /// If true, the node doesn't participate in consensus. Instead, it repeatedly builds a
+    /// proposal for the next height, reports how long building it took and discards it.
+    pub dry_run: bool,
+    /// If true, the blocks consensus decides on are written to the storage. Otherwise, they're
+    /// only compared with the blocks the node syncs.
+    pub authoritative: bool,
+    /// The time to wait after building a proposal in a dry run before building the next one.
+    #[serde(deserialize_with = "deserialize_seconds_to_duration")]
+    pub dry_run_interval: Duration,
+    /// The maximal time to wait for the blocks consensus missed to be synced, once it learns that
+    /// the network is at a higher height.
+    #[serde(deserialize_with = "deserialize_seconds_to_duration")]
+    pub catch_up_timeout: Duration,
+    /// The height consensus starts from. If None, consensus starts from the first block that
+    /// wasn't synced yet.
+    pub start_height: Option<BlockNumber>,
+    /// Consensus doesn't start while the node is more than this number of blocks behind the
+    /// highest block known to sync.
+    pub max_blocks_behind_to_start: u64,
+    /// The time to wait between checks of the sync progress while consensus waits to start.
+    #[serde(deserialize_with = "deserialize_seconds_to_duration")]
+    pub start_poll_interval: Duration,
+    /// A round that doesn't progress for this long is reported as stuck.
+    #[serde(deserialize_with = "deserialize_seconds_to_duration")]
+    pub stuck_round_timeout: Duration,
+    /// The big-endian bytes of the Stark private key the node signs its consensus messages with.
+    /// If None, the messages aren't signed.
+    #[serde(deserialize_with = "deserialize_optional_vec_u8")]
+    pub signing_key: Option<Vec<u8>>,
+    /// The public keys of the validators, which the received consensus messages are verified
+    /// against. If empty, the received messages aren't verified.
+    #[serde(deserialize_with = "deserialize_validator_public_keys")]
+    pub validator_public_keys: BTreeMap<ValidatorId, PublicKey>,
+}
+
+// Serializes the public keys to a string of 'validator_id:public_key' pairs separated by spaces.
+fn serialize_validator_public_keys(
+    validator_public_keys: &BTreeMap<ValidatorId, PublicKey>,
+) -> String {
+    validator_public_keys
+        .iter()
+        .map(|(validator_id, public_key)| format!("{:#x}:{public_key:#x}", validator_id.0.key()))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+// Deserializes the public keys from a string of 'validator_id:public_key' pairs separated by
+// spaces, where both are hex strings.
+fn deserialize_validator_public_keys<'de, D>(
+    de: D,
+) -> Result<BTreeMap<ValidatorId, PublicKey>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let raw_str: String = Deserialize::deserialize(de)?;
+    raw_str
+        .split_whitespace()
+        .map(|raw_pair| {
+            let parse_error = || {
+                D::Error::custom(format!(
+                    "Couldn't parse \"{raw_pair}\" as 'validator_id:public_key' in hex."
+                ))
+            };
+            let (raw_validator_id, raw_public_key) =
+                raw_pair.split_once(':').ok_or_else(parse_error)?;
+            let validator_id = Felt::from_hex(raw_validator_id)
+                .ok()
+                .and_then(|felt| ValidatorId::try_from(felt).ok())
+                .ok_or_else(parse_error)?;
+            let public_key = Felt::from_hex(raw_public_key).map_err(|_| parse_error())?;
+            Ok((validator_id, PublicKey(public_key)))
+        })
+        .collect()
+}
+
+impl SerializeConfig for ConsensusConfig {
+    fn dump(&self) -> BTreeMap<ParamPath, SerializedParam> {
+        let mut config = BTreeMap::from_iter([
+            ser_param(
+                "dry_run",
+                &self.dry_run,
+                "If true, proposals are built and measured but never broadcasted, and the node \
+                 doesn't participate in consensus. Can be used without being a validator.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "authoritative",
+                &self.authoritative,
+                "If true, the blocks consensus decides on are written to the storage, for chains \
+                 where consensus is authoritative. Requires sync, p2p_sync and \
+                 monitoring_gateway.admin_server_address to be disabled, since they write to the \
+                 storage as well. Otherwise, the decisions are only compared with the synced \
+                 blocks.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "dry_run_interval",
+                &self.dry_run_interval.as_secs(),
+                "Time in seconds to wait between proposals built in a dry run.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "catch_up_timeout",
+                &self.catch_up_timeout.as_secs(),
+                "Maximal time in seconds to wait for sync to fetch the blocks consensus missed \
+                 when it learns that the network is at a higher height. If they aren't synced in \
+                 time, consensus stays at its height until the next message from a higher height.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "max_blocks_behind_to_start",
+                &self.max_blocks_behind_to_start,
+                "Consensus doesn't start while the synced blocks are more than this number of \
+                 blocks behind the highest block known to sync.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "start_poll_interval",
+                &self.start_poll_interval.as_secs(),
+                "Time in seconds to wait between checks of the sync progress while consensus waits \
+                 for sync to catch up before starting.",
+                ParamPrivacyInput::Public,
+            ),
+            ser_param(
+                "stuck_round_timeout",
+                &self.stuck_round_timeout.as_secs(),
+                "Time in seconds a consensus round can go without progress before a warning is \
+                 logged and the papyrus_consensus_stuck_round metric is set.",
+                ParamPrivacyInput::Public,
+            ),
+        ]);
+        config.extend([
+            ser_param(
+                "signing_key",
+                &serialize_optional_vec_u8(&self.signing_key),
+                "The Stark private key the node signs its consensus messages with, as a hex \
+                 string. If it's an empty string the messages aren't signed.",
+                ParamPrivacyInput::Private,
+            ),
+            ser_param(
+                "validator_public_keys",
+                &serialize_validator_public_keys(&self.validator_public_keys),
+                "'validator_id:public_key ...' the Stark public keys of the validators in hex. \
+                 Received consensus messages that aren't signed by the validator that sent them \
+                 are dropped. If it's an empty string the messages aren't verified.",
+                ParamPrivacyInput::Public,
+            ),
+        ]);
+        config.extend(ser_optional_param(
+            &self.start_height,
+            BlockNumber(0),
+            "start_height",
+            "The height consensus starts from. If not set, consensus starts from the first block \
+             that wasn't synced yet.",
+            ParamPrivacyInput::Public,
+        ));
+        config
+    }
+}
+
+impl Default for ConsensusConfig {
+    fn default() -> Self {
+        Self {
+            dry_run: false,
+            authoritative: false,
+            dry_run_interval: Duration::from_secs(10),
+            catch_up_timeout: Duration::from_secs(300),
+            start_height: None,
+            max_blocks_behind_to_start: 10,
+            start_poll_interval: Duration::from_secs(5),
+            stuck_round_timeout: Duration::from_secs(60),
+            signing_key: None,
+            validator_public_keys: BTreeMap::new(),
+        }
+    }
+}
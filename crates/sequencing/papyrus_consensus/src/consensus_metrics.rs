@@ -0,0 +1,158 @@
+//! Reports the progress of consensus through metrics and the [shared consensus
+//! status](`SharedConsensusStatus`).
+
+#[cfg(test)]
+#[path = "consensus_metrics_test.rs"]
+mod consensus_metrics_test;
+
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use metrics::{gauge, histogram, increment_counter};
+use papyrus_common::consensus_status::{ConsensusStatus, SharedConsensusStatus};
+use papyrus_common::metrics as papyrus_metrics;
+use papyrus_protobuf::consensus::ConsensusMessage;
+use starknet_api::block::BlockNumber;
+use tokio::time::Instant;
+use tracing::warn;
+
+use crate::types::ValidatorId;
+
+const MESSAGE_TYPE_LABEL: &str = "message_type";
+
+/// The type of a consensus message, which the message counters are labeled by.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ConsensusMessageType {
+    /// A proposal of a block.
+    Proposal,
+}
+
+impl ConsensusMessageType {
+    fn label(&self) -> &'static str {
+        match self {
+            ConsensusMessageType::Proposal => "proposal",
+        }
+    }
+}
+
+impl From<&ConsensusMessage> for ConsensusMessageType {
+    fn from(message: &ConsensusMessage) -> Self {
+        match message {
+            ConsensusMessage::Proposal(_) => ConsensusMessageType::Proposal,
+        }
+    }
+}
+
+/// The hooks consensus reports its progress to. They update the consensus metrics and the shared
+/// status, which is how the node presents consensus without consensus depending on the node's
+/// components.
+#[derive(Clone)]
+pub struct ConsensusMetrics {
+    status: SharedConsensusStatus,
+    // The time the current round started. None before the first round.
+    round_start: Arc<Mutex<Option<Instant>>>,
+}
+
+impl ConsensusMetrics {
+    /// Sets `status` to the status of a validator with the given id that didn't start a height yet.
+    pub fn new(validator_id: ValidatorId, status: SharedConsensusStatus) -> Self {
+        *status.write().expect("Consensus status lock should not be poisoned") =
+            Some(ConsensusStatus::new(validator_id));
+        Self { status, round_start: Arc::new(Mutex::new(None)) }
+    }
+
+    pub(crate) fn round_started(&self, height: BlockNumber, round: u32, is_proposer: bool) {
+        *self.round_start.lock().expect("Round start lock should not be poisoned") =
+            Some(Instant::now());
+        gauge!(papyrus_metrics::PAPYRUS_CONSENSUS_HEIGHT, height.0 as f64);
+        gauge!(papyrus_metrics::PAPYRUS_CONSENSUS_ROUND, f64::from(round));
+        gauge!(papyrus_metrics::PAPYRUS_CONSENSUS_STUCK_ROUND, 0.0);
+        self.update_status(|status| {
+            status.height = Some(height);
+            status.round = round;
+            status.is_proposer = is_proposer;
+            status.is_stuck = false;
+        });
+    }
+
+    pub(crate) fn message_sent(&self, message_type: ConsensusMessageType) {
+        increment_counter!(
+            papyrus_metrics::PAPYRUS_CONSENSUS_SENT_MESSAGES,
+            MESSAGE_TYPE_LABEL => message_type.label()
+        );
+        self.update_status(|status| {
+            status.messages.entry(message_type.label().to_string()).or_default().sent += 1;
+        });
+    }
+
+    pub(crate) fn message_received(&self, message_type: ConsensusMessageType) {
+        increment_counter!(
+            papyrus_metrics::PAPYRUS_CONSENSUS_RECEIVED_MESSAGES,
+            MESSAGE_TYPE_LABEL => message_type.label()
+        );
+        self.update_status(|status| {
+            status.messages.entry(message_type.label().to_string()).or_default().received += 1;
+        });
+    }
+
+    pub(crate) fn invalid_message(&self, message_type: ConsensusMessageType) {
+        increment_counter!(
+            papyrus_metrics::PAPYRUS_CONSENSUS_INVALID_MESSAGES,
+            MESSAGE_TYPE_LABEL => message_type.label()
+        );
+        self.update_status(|status| {
+            status.messages.entry(message_type.label().to_string()).or_default().invalid += 1;
+        });
+    }
+
+    pub(crate) fn decided(&self, height: BlockNumber) {
+        let round_start =
+            *self.round_start.lock().expect("Round start lock should not be poisoned");
+        let latency = round_start.map(|round_start| round_start.elapsed().as_secs_f64());
+        if let Some(latency) = latency {
+            histogram!(papyrus_metrics::PAPYRUS_CONSENSUS_DECISION_LATENCY_SECS, latency);
+        }
+        self.update_status(|status| {
+            status.last_decided_height = Some(height);
+            status.last_decision_latency_secs = latency;
+        });
+    }
+
+    /// Runs forever. Whenever the current round doesn't progress for `stuck_round_timeout`, a
+    /// warning is logged and the stuck round metric is set until the next round starts.
+    pub async fn detect_stuck_rounds(&self, stuck_round_timeout: Duration) {
+        loop {
+            let round_start =
+                *self.round_start.lock().expect("Round start lock should not be poisoned");
+            let elapsed = round_start.map(|round_start| round_start.elapsed());
+            match elapsed {
+                Some(elapsed) if elapsed >= stuck_round_timeout => {
+                    let (height, round) = self.height_and_round();
+                    warn!(
+                        "Consensus made no progress in round {round} of height {height:?} for \
+                         {elapsed:?}."
+                    );
+                    gauge!(papyrus_metrics::PAPYRUS_CONSENSUS_STUCK_ROUND, 1.0);
+                    self.update_status(|status| status.is_stuck = true);
+                    // The warning repeats while the round is stuck.
+                    tokio::time::sleep(stuck_round_timeout).await;
+                }
+                Some(elapsed) => tokio::time::sleep(stuck_round_timeout - elapsed).await,
+                None => tokio::time::sleep(stuck_round_timeout).await,
+            }
+        }
+    }
+
+    fn height_and_round(&self) -> (Option<BlockNumber>, u32) {
+        let status = self.status.read().expect("Consensus status lock should not be poisoned");
+        status.as_ref().map_or((None, 0), |status| (status.height, status.round))
+    }
+
+    fn update_status(&self, update: impl FnOnce(&mut ConsensusStatus)) {
+        if let Some(status) =
+            self.status.write().expect("Consensus status lock should not be poisoned").as_mut()
+        {
+            update(status);
+        }
+    }
+}
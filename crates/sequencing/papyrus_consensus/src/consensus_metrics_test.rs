@@ -0,0 +1,80 @@
+use std::time::Duration;
+
+use papyrus_common::consensus_status::{
+    ConsensusMessageCounts,
+    ConsensusStatus,
+    SharedConsensusStatus,
+};
+use papyrus_common::metrics as papyrus_metrics;
+use starknet_api::block::BlockNumber;
+use test_utils::prometheus_is_contained;
+
+use crate::consensus_metrics::{ConsensusMessageType, ConsensusMetrics};
+use crate::test_utils::get_prometheus_handle;
+use crate::types::ValidatorId;
+
+const STUCK_ROUND_TIMEOUT: Duration = Duration::from_secs(10);
+
+fn status(shared_status: &SharedConsensusStatus) -> ConsensusStatus {
+    shared_status.read().unwrap().clone().expect("The status should be set")
+}
+
+#[tokio::test]
+async fn hooks_update_the_status() {
+    let prometheus_handle = get_prometheus_handle();
+    let validator_id = ValidatorId::from(1u8);
+    let shared_status = SharedConsensusStatus::default();
+    let metrics = ConsensusMetrics::new(validator_id, shared_status.clone());
+    assert_eq!(status(&shared_status), ConsensusStatus::new(validator_id));
+
+    metrics.round_started(BlockNumber(5), 0, true);
+    metrics.message_sent(ConsensusMessageType::Proposal);
+    metrics.message_received(ConsensusMessageType::Proposal);
+    metrics.message_received(ConsensusMessageType::Proposal);
+    metrics.invalid_message(ConsensusMessageType::Proposal);
+    metrics.decided(BlockNumber(5));
+
+    let status = status(&shared_status);
+    assert_eq!(status.height, Some(BlockNumber(5)));
+    assert_eq!(status.round, 0);
+    assert!(status.is_proposer);
+    assert_eq!(status.last_decided_height, Some(BlockNumber(5)));
+    assert!(status.last_decision_latency_secs.is_some());
+    assert_eq!(
+        status.messages["proposal"],
+        ConsensusMessageCounts { sent: 1, received: 2, invalid: 1 }
+    );
+    assert!(prometheus_is_contained(
+        prometheus_handle.render(),
+        papyrus_metrics::PAPYRUS_CONSENSUS_INVALID_MESSAGES,
+        &[("message_type", "proposal")]
+    )
+    .is_some());
+}
+
+#[tokio::test(start_paused = true)]
+async fn stuck_round_is_reported_until_the_next_round() {
+    let shared_status = SharedConsensusStatus::default();
+    let metrics = ConsensusMetrics::new(ValidatorId::default(), shared_status.clone());
+    metrics.round_started(BlockNumber(1), 0, false);
+    let detector = {
+        let metrics = metrics.clone();
+        tokio::spawn(async move { metrics.detect_stuck_rounds(STUCK_ROUND_TIMEOUT).await })
+    };
+
+    tokio::time::sleep(STUCK_ROUND_TIMEOUT / 2).await;
+    assert!(!status(&shared_status).is_stuck);
+
+    tokio::time::sleep(STUCK_ROUND_TIMEOUT).await;
+    assert!(status(&shared_status).is_stuck);
+
+    metrics.round_started(BlockNumber(2), 0, false);
+    assert!(!status(&shared_status).is_stuck);
+
+    // The new round is reported as stuck only once it doesn't progress for the timeout as well.
+    tokio::time::sleep(STUCK_ROUND_TIMEOUT / 2).await;
+    assert!(!status(&shared_status).is_stuck);
+    tokio::time::sleep(STUCK_ROUND_TIMEOUT).await;
+    assert!(status(&shared_status).is_stuck);
+    detector.abort();
+}
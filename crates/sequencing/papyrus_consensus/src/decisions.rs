@@ -0,0 +1,105 @@
+#[cfg(test)]
+#[path = "decisions_test.rs"]
+mod decisions_test;
+
+use futures::channel::mpsc;
+use futures::StreamExt;
+use papyrus_common::metrics as papyrus_metrics;
+use papyrus_storage::body::{BodyStorageReader, BodyStorageWriter};
+use papyrus_storage::header::{HeaderStorageReader, HeaderStorageWriter};
+use papyrus_storage::{StorageReader, StorageWriter};
+use starknet_api::block::{BlockBody, BlockHeader, BlockNumber};
+use starknet_api::transaction::Transaction;
+use tracing::{debug, error, info};
+
+use crate::papyrus_consensus_context::wait_for_block;
+use crate::types::{ConsensusBlock, ConsensusError, Decision};
+
+/// A block that holds all the data the storage keeps for a block, so that it can be written once
+/// consensus decides on it.
+pub trait StorableBlock: ConsensusBlock<ProposalChunk = Transaction> {
+    /// The header and the body the storage keeps for the block.
+    fn into_header_and_body(self) -> (BlockHeader, BlockBody);
+}
+
+/// Compares each block consensus decides on with the block synced from another source at the same
+/// height, waiting for that block to be synced if needed. A divergence is logged as an error and
+/// counted in a metric. This is a sanity check for running consensus alongside sync.
+pub async fn compare_decisions_with_synced_blocks<BlockT>(
+    storage_reader: StorageReader,
+    mut decision_receiver: mpsc::UnboundedReceiver<Decision<BlockT>>,
+) -> Result<(), ConsensusError>
+where
+    BlockT: ConsensusBlock<ProposalChunk = Transaction>,
+{
+    while let Some(Decision { height, block }) = decision_receiver.next().await {
+        compare_with_synced_block(&storage_reader, height, &block).await?;
+    }
+    Ok(())
+}
+
+/// Writes each block consensus decides on to the storage, on chains where consensus is
+/// authoritative and no sync writes the blocks. A decision at a height the storage already has a
+/// block at, e.g. from a snapshot, is compared with that block instead.
+pub async fn write_decided_blocks<BlockT: StorableBlock>(
+    storage_reader: StorageReader,
+    mut storage_writer: StorageWriter,
+    mut decision_receiver: mpsc::UnboundedReceiver<Decision<BlockT>>,
+) -> Result<(), ConsensusError> {
+    while let Some(Decision { height, block }) = decision_receiver.next().await {
+        let header_marker = storage_reader.begin_ro_txn()?.get_header_marker()?;
+        if height < header_marker {
+            compare_with_synced_block(&storage_reader, height, &block).await?;
+            continue;
+        }
+        if height > header_marker {
+            return Err(ConsensusError::DecisionAboveStorage { height, header_marker });
+        }
+        let (header, body) = block.into_header_and_body();
+        storage_writer
+            .begin_rw_txn()?
+            .append_header(height, &header)?
+            .append_body(height, body)?
+            .commit()?;
+        info!("Wrote the block consensus decided on at height {height}.");
+    }
+    Ok(())
+}
+
+async fn compare_with_synced_block<BlockT>(
+    storage_reader: &StorageReader,
+    height: BlockNumber,
+    block: &BlockT,
+) -> Result<(), ConsensusError>
+where
+    BlockT: ConsensusBlock<ProposalChunk = Transaction>,
+{
+    wait_for_block(storage_reader, height).await?;
+    let txn = storage_reader.begin_ro_txn()?;
+    let synced_block_hash = txn
+        .get_block_header(height)?
+        .expect("A header with number lower than the body marker is missing")
+        .block_hash;
+    let synced_transactions = txn
+        .get_block_transactions(height)?
+        .expect("A body with number lower than the body marker is missing");
+
+    if synced_block_hash != block.id() {
+        error!(
+            "Consensus decided on block {} at height {height}, but the synced block is {}.",
+            block.id(),
+            synced_block_hash
+        );
+    } else if !block.proposal_iter().eq(synced_transactions) {
+        error!(
+            "Consensus decided on block {} at height {height} with different transactions than \
+             the synced block.",
+            block.id()
+        );
+    } else {
+        debug!("The decision at height {height} matches the synced block.");
+        return Ok(());
+    }
+    metrics::increment_counter!(papyrus_metrics::PAPYRUS_CONSENSUS_DECISION_DIVERGENCES);
+    Ok(())
+}
@@ -0,0 +1,112 @@
+use futures::channel::mpsc;
+use papyrus_common::metrics as papyrus_metrics;
+use papyrus_storage::body::{BodyStorageReader, BodyStorageWriter};
+use papyrus_storage::header::{HeaderStorageReader, HeaderStorageWriter};
+use papyrus_storage::test_utils::get_test_storage;
+use prometheus_parse::Value::Counter;
+use starknet_api::block::{BlockHash, BlockNumber};
+use starknet_types_core::felt::Felt;
+use test_utils::{get_test_block, prometheus_is_contained};
+
+use crate::decisions::{compare_decisions_with_synced_blocks, write_decided_blocks};
+use crate::papyrus_consensus_context::PapyrusConsensusBlock;
+use crate::test_utils::get_prometheus_handle;
+use crate::types::{ConsensusError, Decision};
+
+#[tokio::test]
+async fn divergent_decisions_are_counted() {
+    let prometheus_handle = get_prometheus_handle();
+    let ((storage_reader, mut storage_writer), _temp_dir) = get_test_storage();
+    let block = get_test_block(3, None, None, None);
+    let height = block.header.block_number;
+    storage_writer
+        .begin_rw_txn()
+        .unwrap()
+        .append_header(height, &block.header)
+        .unwrap()
+        .append_body(height, block.body.clone())
+        .unwrap()
+        .commit()
+        .unwrap();
+
+    let synced_block =
+        PapyrusConsensusBlock { header: block.header.clone(), body: block.body.clone() };
+    let mut block_with_other_hash = synced_block.clone();
+    block_with_other_hash.header.block_hash = BlockHash(Felt::from(1234_u64));
+    let mut block_with_other_transactions = synced_block.clone();
+    block_with_other_transactions.body.transactions.remove(0);
+    let (decision_sender, decision_receiver) = mpsc::unbounded();
+    for block in [synced_block, block_with_other_hash, block_with_other_transactions] {
+        decision_sender.unbounded_send(Decision { height, block }).unwrap();
+    }
+    decision_sender.close_channel();
+
+    compare_decisions_with_synced_blocks(storage_reader, decision_receiver).await.unwrap();
+
+    assert_eq!(
+        prometheus_is_contained(
+            prometheus_handle.render(),
+            papyrus_metrics::PAPYRUS_CONSENSUS_DECISION_DIVERGENCES,
+            &[]
+        ),
+        Some(Counter(2.0))
+    );
+}
+
+#[tokio::test]
+async fn decisions_at_the_header_marker_are_written() {
+    let ((storage_reader, storage_writer), _temp_dir) = get_test_storage();
+    let first_block = get_test_block(2, None, None, None);
+    let mut second_block = get_test_block(1, None, None, None);
+    second_block.header.block_number = BlockNumber(1);
+    second_block.header.parent_hash = first_block.header.block_hash;
+    second_block.header.block_hash = BlockHash(Felt::from(1234_u64));
+
+    let (decision_sender, decision_receiver) = mpsc::unbounded();
+    for block in [&first_block, &second_block] {
+        let height = block.header.block_number;
+        let block =
+            PapyrusConsensusBlock { header: block.header.clone(), body: block.body.clone() };
+        decision_sender.unbounded_send(Decision { height, block }).unwrap();
+    }
+    // A decision on a block that was already written is compared with it.
+    let height = first_block.header.block_number;
+    let block = PapyrusConsensusBlock {
+        header: first_block.header.clone(),
+        body: first_block.body.clone(),
+    };
+    decision_sender.unbounded_send(Decision { height, block }).unwrap();
+    decision_sender.close_channel();
+
+    write_decided_blocks(storage_reader.clone(), storage_writer, decision_receiver).await.unwrap();
+
+    let txn = storage_reader.begin_ro_txn().unwrap();
+    assert_eq!(txn.get_header_marker().unwrap(), BlockNumber(2));
+    assert_eq!(txn.get_body_marker().unwrap(), BlockNumber(2));
+    for block in [first_block, second_block] {
+        let height = block.header.block_number;
+        assert_eq!(txn.get_block_header(height).unwrap(), Some(block.header));
+        assert_eq!(txn.get_block_transactions(height).unwrap(), Some(block.body.transactions));
+    }
+}
+
+#[tokio::test]
+async fn decisions_above_the_header_marker_are_rejected() {
+    let ((storage_reader, storage_writer), _temp_dir) = get_test_storage();
+    let block = get_test_block(1, None, None, None);
+    let height = BlockNumber(1);
+    let block = PapyrusConsensusBlock { header: block.header, body: block.body };
+    let (decision_sender, decision_receiver) = mpsc::unbounded();
+    decision_sender.unbounded_send(Decision { height, block }).unwrap();
+    decision_sender.close_channel();
+
+    let result = write_decided_blocks(storage_reader, storage_writer, decision_receiver).await;
+
+    assert!(matches!(
+        result,
+        Err(ConsensusError::DecisionAboveStorage {
+            height: BlockNumber(1),
+            header_marker: BlockNumber(0)
+        })
+    ));
+}
@@ -0,0 +1,140 @@
+#[cfg(test)]
+#[path = "dry_run_test.rs"]
+mod dry_run_test;
+
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+
+use futures::StreamExt;
+use papyrus_common::metrics as papyrus_metrics;
+use papyrus_protobuf::consensus::{ConsensusMessage, Proposal};
+use starknet_api::block::BlockNumber;
+use starknet_api::transaction::Transaction;
+use tracing::{info, instrument};
+
+use crate::types::{ConsensusBlock, ConsensusContext, ConsensusError, ValidatorId};
+
+/// A phase of building a proposal, measured by the dry run.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum ProposalPhase {
+    /// Receiving the content of the proposal from the context.
+    CollectTransactions,
+    /// Waiting for the context to finish building the block.
+    BuildBlock,
+    /// Creating the proposal message and encoding it for broadcasting.
+    AssembleProposal,
+}
+
+impl ProposalPhase {
+    /// The name of the phase in the logs and in the metrics.
+    pub fn name(&self) -> &'static str {
+        match self {
+            ProposalPhase::CollectTransactions => "collect_transactions",
+            ProposalPhase::BuildBlock => "build_block",
+            ProposalPhase::AssembleProposal => "assemble_proposal",
+        }
+    }
+}
+
+/// The result of building a proposal in a dry run.
+#[derive(Debug)]
+pub struct DryRunReport {
+    /// The proposal that would have been broadcasted.
+    pub proposal: Proposal,
+    /// The duration of each phase, in the order the phases ran.
+    pub phase_durations: Vec<(ProposalPhase, Duration)>,
+    /// The size in bytes of the proposal message that would have been broadcasted.
+    pub proposal_size: usize,
+}
+
+impl DryRunReport {
+    /// Logs the report and exports it as metrics.
+    pub fn export(&self) {
+        for (phase, duration) in &self.phase_durations {
+            metrics::gauge!(
+                papyrus_metrics::PAPYRUS_CONSENSUS_DRY_RUN_PHASE_DURATION_SECS,
+                duration.as_secs_f64(),
+                "phase" => phase.name()
+            );
+        }
+        metrics::gauge!(
+            papyrus_metrics::PAPYRUS_CONSENSUS_DRY_RUN_NUM_TRANSACTIONS,
+            self.proposal.transactions.len() as f64
+        );
+        metrics::gauge!(
+            papyrus_metrics::PAPYRUS_CONSENSUS_DRY_RUN_PROPOSAL_SIZE_BYTES,
+            self.proposal_size as f64
+        );
+        let phase_durations = self
+            .phase_durations
+            .iter()
+            .map(|(phase, duration)| format!("{}: {duration:?}", phase.name()))
+            .collect::<Vec<_>>()
+            .join(", ");
+        info!(
+            "Dry run built a proposal for height {} with {} transactions and a size of {} bytes. \
+             Phase durations: {phase_durations}.",
+            self.proposal.height,
+            self.proposal.transactions.len(),
+            self.proposal_size,
+        );
+    }
+}
+
+/// Runs the proposer flow for the given height and returns the proposal with the measurements
+/// instead of broadcasting it.
+#[instrument(skip(context), level = "debug", err)]
+pub async fn dry_run_proposal<BlockT>(
+    context: &dyn ConsensusContext<Block = BlockT>,
+    height: BlockNumber,
+    proposer: ValidatorId,
+) -> Result<DryRunReport, ConsensusError>
+where
+    BlockT: ConsensusBlock<ProposalChunk = Transaction>,
+{
+    let mut phase_durations = Vec::new();
+
+    let phase_start = Instant::now();
+    let (mut content_receiver, fin_receiver) = context.build_proposal(height).await;
+    let mut transactions = Vec::new();
+    while let Some(transaction) = content_receiver.next().await {
+        transactions.push(transaction);
+    }
+    phase_durations.push((ProposalPhase::CollectTransactions, phase_start.elapsed()));
+
+    let phase_start = Instant::now();
+    let block = fin_receiver.await?;
+    phase_durations.push((ProposalPhase::BuildBlock, phase_start.elapsed()));
+
+    let phase_start = Instant::now();
+    if !block.proposal_iter().eq(transactions.iter().cloned()) {
+        return Err(ConsensusError::InvalidBuiltProposal(
+            height,
+            "The streamed content differs from the content of the built block".to_string(),
+        ));
+    }
+    let proposal = Proposal { height: height.0, proposer, transactions, block_hash: block.id() };
+    let proposal_size = Vec::<u8>::from(ConsensusMessage::Proposal(proposal.clone())).len();
+    phase_durations.push((ProposalPhase::AssembleProposal, phase_start.elapsed()));
+
+    Ok(DryRunReport { proposal, phase_durations, proposal_size })
+}
+
+/// Builds a proposal for each height starting from `start_height`, waiting `interval` between
+/// proposals. The proposals are reported and discarded, and nothing is broadcasted.
+pub async fn run_dry_run<BlockT>(
+    context: Arc<dyn ConsensusContext<Block = BlockT>>,
+    start_height: BlockNumber,
+    proposer: ValidatorId,
+    interval: Duration,
+) -> Result<(), ConsensusError>
+where
+    BlockT: ConsensusBlock<ProposalChunk = Transaction>,
+{
+    let mut height = start_height;
+    loop {
+        dry_run_proposal(context.as_ref(), height, proposer).await?.export();
+        height = height.unchecked_next();
+        tokio::time::sleep(interval).await;
+    }
+}
@@ -0,0 +1,97 @@
+use futures::{FutureExt, StreamExt};
+use papyrus_common::metrics as papyrus_metrics;
+use papyrus_network::network_manager::mock_register_broadcast_subscriber;
+use papyrus_protobuf::consensus::ConsensusMessage;
+use papyrus_storage::body::BodyStorageWriter;
+use papyrus_storage::header::HeaderStorageWriter;
+use papyrus_storage::test_utils::get_test_storage;
+use prometheus_parse::Value::Gauge;
+use starknet_api::core::ContractAddress;
+use test_utils::{get_test_block, prometheus_is_contained};
+
+use crate::dry_run::{dry_run_proposal, ProposalPhase};
+use crate::papyrus_consensus_context::PapyrusConsensusContext;
+use crate::test_utils::get_prometheus_handle;
+
+const NUM_TRANSACTIONS: usize = 5;
+
+#[tokio::test]
+async fn dry_run_builds_and_discards_proposal() {
+    let prometheus_handle = get_prometheus_handle();
+    let ((storage_reader, mut storage_writer), _temp_dir) = get_test_storage();
+    let block = get_test_block(NUM_TRANSACTIONS, None, None, None);
+    let block_number = block.header.block_number;
+    storage_writer
+        .begin_rw_txn()
+        .unwrap()
+        .append_header(block_number, &block.header)
+        .unwrap()
+        .append_body(block_number, block.body.clone())
+        .unwrap()
+        .commit()
+        .unwrap();
+    let mut test_channels = mock_register_broadcast_subscriber().unwrap();
+    let papyrus_context = PapyrusConsensusContext::new(
+        storage_reader,
+        test_channels.subscriber_channels.messages_to_broadcast_sender,
+        None,
+        None,
+    );
+    let proposer = ContractAddress::default();
+
+    let report = dry_run_proposal(&papyrus_context, block_number, proposer).await.unwrap();
+    report.export();
+
+    let proposal = &report.proposal;
+    assert_eq!(proposal.height, block_number.0);
+    assert_eq!(proposal.proposer, proposer);
+    assert_eq!(proposal.transactions, block.body.transactions);
+    assert_eq!(proposal.block_hash, block.header.block_hash);
+    let phases = report.phase_durations.iter().map(|(phase, _)| *phase).collect::<Vec<_>>();
+    assert_eq!(
+        phases,
+        vec![
+            ProposalPhase::CollectTransactions,
+            ProposalPhase::BuildBlock,
+            ProposalPhase::AssembleProposal
+        ]
+    );
+    assert_eq!(
+        report.proposal_size,
+        Vec::<u8>::from(ConsensusMessage::Proposal(proposal.clone())).len()
+    );
+
+    // Nothing was broadcasted.
+    assert!(test_channels
+        .mock_network
+        .messages_to_broadcast_receiver
+        .next()
+        .now_or_never()
+        .is_none());
+
+    let metrics = prometheus_handle.render();
+    for phase in phases {
+        assert!(prometheus_is_contained(
+            metrics.clone(),
+            papyrus_metrics::PAPYRUS_CONSENSUS_DRY_RUN_PHASE_DURATION_SECS,
+            &[("phase", phase.name())]
+        )
+        .is_some());
+    }
+    assert_eq!(
+        prometheus_is_contained(
+            metrics.clone(),
+            papyrus_metrics::PAPYRUS_CONSENSUS_DRY_RUN_NUM_TRANSACTIONS,
+            &[]
+        ),
+        Some(Gauge(NUM_TRANSACTIONS as f64))
+    );
+    assert_eq!(
+        prometheus_is_contained(
+            metrics,
+            papyrus_metrics::PAPYRUS_CONSENSUS_DRY_RUN_PROPOSAL_SIZE_BYTES,
+            &[]
+        ),
+        Some(Gauge(report.proposal_size as f64))
+    );
+}
@@ -12,19 +12,42 @@
 //! # Quick Start
 //! ...
 
+use std::cmp::Ordering;
+use std::path::PathBuf;
 use std::sync::Arc;
+use std::time::Duration;
 
+use consensus_metrics::ConsensusMessageType;
 use futures::channel::{mpsc, oneshot};
+use papyrus_common::metrics as papyrus_metrics;
 use papyrus_network::network_manager::SubscriberReceiver;
-use papyrus_protobuf::consensus::{ConsensusMessage, Proposal};
+use papyrus_protobuf::consensus::{ConsensusMessage, Proposal, SignedConsensusMessage};
+use signing::MessageVerifier;
 use single_height_consensus::SingleHeightConsensus;
 use starknet_api::block::{BlockHash, BlockNumber};
-use tracing::info;
-use types::{ConsensusBlock, ConsensusContext, ConsensusError, ProposalInit, ValidatorId};
+use tracing::{debug, info, warn};
+use types::{
+    ConsensusBlock,
+    ConsensusContext,
+    ConsensusError,
+    Decision,
+    ProposalInit,
+    ValidatorId,
+};
+use wal::ConsensusWal;
 
+#[cfg(test)]
+#[path = "lib_test.rs"]
+mod lib_test;
+
+pub mod config;
+pub mod consensus_metrics;
+pub mod decisions;
+pub mod dry_run;
 // TODO(matan): Remove dead code allowance at the end of milestone 1.
 #[allow(missing_docs)]
 pub mod papyrus_consensus_context;
+pub mod signing;
 #[allow(dead_code)]
 #[allow(missing_docs)]
 pub mod single_height_consensus;
@@ -33,38 +56,104 @@ pub(crate) mod test_utils;
 #[allow(dead_code)]
 #[allow(missing_docs)]
 pub mod types;
+pub mod wal;
 
 use futures::StreamExt;
 
-// TODO(dvir): add test for this.
-#[allow(missing_docs)]
+/// Runs consensus from `start_height` onward. The block decided on at each height is sent through
+/// `decision_sender`, which never waits for the decisions to be handled.
+///
+/// If a message for a higher height is received, consensus waits up to `catch_up_timeout` for the
+/// node to have the blocks it missed and then continues from that height. No decisions are sent
+/// for the skipped heights.
+///
+/// The proposals are recorded in a [WAL](`ConsensusWal`) at `wal_path`, so that after a restart
+/// the node doesn't propose a different block at a height it already proposed at.
+///
+/// The messages received from the network are handled only if `message_verifier` authenticates
+/// them. Other messages are dropped and their peer is reported.
+#[allow(clippy::too_many_arguments)]
 pub async fn run_consensus<BlockT: ConsensusBlock>(
     context: Arc<dyn ConsensusContext<Block = BlockT>>,
     start_height: BlockNumber,
     validator_id: ValidatorId,
-    mut network_receiver: SubscriberReceiver<ConsensusMessage>,
+    catch_up_timeout: Duration,
+    wal_path: PathBuf,
+    message_verifier: MessageVerifier,
+    mut network_receiver: SubscriberReceiver<SignedConsensusMessage>,
+    decision_sender: mpsc::UnboundedSender<Decision<BlockT>>,
 ) -> Result<(), ConsensusError>
 where
     ProposalWrapper:
         Into<(ProposalInit, mpsc::Receiver<BlockT::ProposalChunk>, oneshot::Receiver<BlockHash>)>,
 {
+    let mut wal = ConsensusWal::open(wal_path)?;
     let mut current_height = start_height;
     loop {
         info!("Starting consensus for height {current_height}");
         let mut shc =
             SingleHeightConsensus::new(current_height, context.clone(), validator_id).await;
 
-        let block = if let Some(block) = shc.start().await? {
+        let block = if let Some(block) = shc.start(&mut wal).await? {
             info!("Proposer flow height {current_height}");
             block
         } else {
             info!("Validator flow height {current_height}");
-            let ConsensusMessage::Proposal(proposal) = network_receiver
-                .next()
-                .await
-                .expect("Failed to receive a message from network")
-                .0
-                .expect("Network receiver closed unexpectedly");
+            let proposal = loop {
+                let (message, report_callback) = network_receiver
+                    .next()
+                    .await
+                    .expect("Failed to receive a message from network");
+                let message = message.expect("Network receiver closed unexpectedly");
+                let message_type = ConsensusMessageType::from(&message.message);
+                if let Some(metrics) = context.metrics() {
+                    metrics.message_received(message_type);
+                }
+                let ConsensusMessage::Proposal(proposal) = match message_verifier.verify(message) {
+                    Ok(message) => message,
+                    Err(error) => {
+                        warn!("Dropping a consensus message: {error}");
+                        metrics::increment_counter!(
+                            papyrus_metrics::PAPYRUS_CONSENSUS_UNAUTHENTICATED_MESSAGES
+                        );
+                        if let Some(metrics) = context.metrics() {
+                            metrics.invalid_message(message_type);
+                        }
+                        report_callback();
+                        continue;
+                    }
+                };
+                let proposal_height = BlockNumber(proposal.height);
+                match proposal_height.cmp(&current_height) {
+                    Ordering::Less => {
+                        debug!(
+                            "Ignoring a proposal for height {proposal_height} while at height \
+                             {current_height}."
+                        );
+                    }
+                    Ordering::Equal => break proposal,
+                    Ordering::Greater => {
+                        if !catch_up(
+                            context.as_ref(),
+                            current_height,
+                            proposal_height,
+                            catch_up_timeout,
+                        )
+                        .await?
+                        {
+                            continue;
+                        }
+                        current_height = proposal_height;
+                        shc = SingleHeightConsensus::new(
+                            current_height,
+                            context.clone(),
+                            validator_id,
+                        )
+                        .await;
+                        break proposal;
+                    }
+                }
+            };
             let (proposal_init, content_receiver, fin_receiver) = ProposalWrapper(proposal).into();
 
             shc.handle_proposal(proposal_init, content_receiver, fin_receiver)
@@ -73,13 +162,49 @@ where
         };
 
         info!(
-            "Finished consensus for height: {start_height}. Agreed on block with id: {}",
+            "Finished consensus for height: {current_height}. Agreed on block with id: {}",
             block.id()
         );
+        if let Some(metrics) = context.metrics() {
+            metrics.decided(current_height);
+        }
+        decision_sender
+            .unbounded_send(Decision { height: current_height, block })
+            .map_err(|error| error.into_send_error())?;
+        wal.compact(current_height)?;
         current_height = current_height.unchecked_next();
     }
 }
 
+// Waits up to `timeout` for the node to have the blocks below `network_height`. Returns false if
+// they weren't available in time, in which case consensus stays at `height`.
+async fn catch_up<BlockT: ConsensusBlock>(
+    context: &dyn ConsensusContext<Block = BlockT>,
+    height: BlockNumber,
+    network_height: BlockNumber,
+    timeout: Duration,
+) -> Result<bool, ConsensusError> {
+    info!("Consensus is at height {height} but the network is at {network_height}. Catching up.");
+    match tokio::time::timeout(timeout, context.catch_up(network_height)).await {
+        Ok(result) => {
+            result?;
+            metrics::counter!(
+                papyrus_metrics::PAPYRUS_CONSENSUS_SKIPPED_HEIGHTS,
+                network_height.0 - height.0
+            );
+            info!("Caught up to height {network_height}.");
+            Ok(true)
+        }
+        Err(_) => {
+            warn!(
+                "The blocks below height {network_height} weren't available within {timeout:?}. \
+                 Staying at height {height}."
+            );
+            Ok(false)
+        }
+    }
+}
+
 // `Proposal` is defined in the protobuf crate so we can't implement `Into` for it because of the
 // orphan rule. This wrapper enables us to implement `Into` for the inner `Proposal`.
 #[allow(missing_docs)]
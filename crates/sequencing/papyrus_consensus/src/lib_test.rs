@@ -0,0 +1,158 @@
+use std::path::PathBuf;
+use std::sync::Arc;
+use std::time::Duration;
+
+use futures::channel::mpsc;
+use futures::{SinkExt, StreamExt};
+use papyrus_common::consensus_status::SharedConsensusStatus;
+use papyrus_network::network_manager::{
+    dummy_report_callback,
+    mock_register_broadcast_subscriber,
+    BroadcastNetworkMock,
+    MockBroadcastedMessagesSender,
+    MockMessagesToBroadcastReceiver,
+    TestSubscriberChannels,
+};
+use papyrus_protobuf::consensus::SignedConsensusMessage;
+use papyrus_storage::body::BodyStorageWriter;
+use papyrus_storage::header::HeaderStorageWriter;
+use papyrus_storage::test_utils::get_test_storage;
+use papyrus_storage::StorageReader;
+use starknet_api::block::{BlockBody, BlockHash, BlockHeader, BlockNumber};
+use starknet_types_core::felt::Felt;
+
+use crate::consensus_metrics::ConsensusMetrics;
+use crate::papyrus_consensus_context::{PapyrusConsensusBlock, PapyrusConsensusContext};
+use crate::run_consensus;
+use crate::signing::MessageVerifier;
+use crate::types::{ConsensusBlock, Decision, ValidatorId};
+
+const NUM_BLOCKS: u64 = 8;
+const CATCH_UP_TIMEOUT: Duration = Duration::from_secs(10);
+const TEST_TIMEOUT: Duration = Duration::from_secs(30);
+
+fn spawn_validator(
+    storage_reader: StorageReader,
+    validator_id: ValidatorId,
+    start_height: BlockNumber,
+    wal_path: PathBuf,
+    metrics: Option<ConsensusMetrics>,
+) -> (
+    BroadcastNetworkMock<SignedConsensusMessage>,
+    mpsc::UnboundedReceiver<Decision<PapyrusConsensusBlock>>,
+) {
+    let TestSubscriberChannels { subscriber_channels, mock_network } =
+        mock_register_broadcast_subscriber().unwrap();
+    let context = PapyrusConsensusContext::new(
+        storage_reader,
+        subscriber_channels.messages_to_broadcast_sender,
+        None,
+        metrics,
+    );
+    let (decision_sender, decision_receiver) = mpsc::unbounded();
+    tokio::spawn(run_consensus(
+        Arc::new(context),
+        start_height,
+        validator_id,
+        CATCH_UP_TIMEOUT,
+        wal_path,
+        MessageVerifier::default(),
+        subscriber_channels.broadcasted_messages_receiver,
+        decision_sender,
+    ));
+    (mock_network, decision_receiver)
+}
+
+// Delivers the messages one validator broadcasts to the other validator.
+fn connect(
+    mut messages_to_broadcast_receiver: MockMessagesToBroadcastReceiver<SignedConsensusMessage>,
+    mut broadcasted_messages_sender: MockBroadcastedMessagesSender<SignedConsensusMessage>,
+) {
+    tokio::spawn(async move {
+        while let Some(message) = messages_to_broadcast_receiver.next().await {
+            broadcasted_messages_sender.send((message, dummy_report_callback())).await.unwrap();
+        }
+    });
+}
+
+async fn next_decisions(
+    decision_receiver: &mut mpsc::UnboundedReceiver<Decision<PapyrusConsensusBlock>>,
+    num_decisions: usize,
+) -> Vec<(BlockNumber, BlockHash)> {
+    tokio::time::timeout(
+        TEST_TIMEOUT,
+        decision_receiver
+            .by_ref()
+            .take(num_decisions)
+            .map(|decision| (decision.height, decision.block.id()))
+            .collect::<Vec<_>>(),
+    )
+    .await
+    .expect("Consensus didn't decide in time")
+}
+
+#[tokio::test]
+async fn late_validator_catches_up_and_rejoins() {
+    // The blocks were already synced, so the validators only need to agree on them.
+    let ((storage_reader, mut storage_writer), temp_dir) = get_test_storage();
+    for height in 0..NUM_BLOCKS {
+        let header = BlockHeader {
+            block_hash: BlockHash(Felt::from(height)),
+            block_number: BlockNumber(height),
+            ..Default::default()
+        };
+        storage_writer
+            .begin_rw_txn()
+            .unwrap()
+            .append_header(BlockNumber(height), &header)
+            .unwrap()
+            .append_body(BlockNumber(height), BlockBody::default())
+            .unwrap()
+            .commit()
+            .unwrap();
+    }
+
+    // The second validator starts several heights behind the first one. Its proposal for height
+    // 1 is stale and ignored by the first validator, and the first validator's proposal for height
+    // 4 makes it catch up.
+    let first_status = SharedConsensusStatus::default();
+    let (first_network, mut first_decisions) = spawn_validator(
+        storage_reader.clone(),
+        0u8.into(),
+        BlockNumber(4),
+        temp_dir.path().join("first_consensus_wal"),
+        Some(ConsensusMetrics::new(0u8.into(), first_status.clone())),
+    );
+    let (second_network, mut second_decisions) = spawn_validator(
+        storage_reader,
+        1u8.into(),
+        BlockNumber(1),
+        temp_dir.path().join("second_consensus_wal"),
+        None,
+    );
+    connect(
+        first_network.messages_to_broadcast_receiver,
+        second_network.broadcasted_messages_sender,
+    );
+    connect(
+        second_network.messages_to_broadcast_receiver,
+        first_network.broadcasted_messages_sender,
+    );
+
+    let expected_decisions = |heights: &[u64]| {
+        heights
+            .iter()
+            .map(|height| (BlockNumber(*height), BlockHash(Felt::from(*height))))
+            .collect::<Vec<_>>()
+    };
+    assert_eq!(next_decisions(&mut first_decisions, 3).await, expected_decisions(&[4, 5, 6]));
+    assert_eq!(next_decisions(&mut second_decisions, 4).await, expected_decisions(&[1, 4, 5, 6]));
+
+    // The first validator proposed heights 4 and 6 and received the proposals of heights 1 and 5.
+    let status = first_status.read().unwrap().clone().unwrap();
+    assert!(status.last_decided_height >= Some(BlockNumber(6)));
+    let proposal_counts = status.messages["proposal"];
+    assert!(proposal_counts.sent >= 2);
+    assert!(proposal_counts.received >= 2);
+    assert_eq!(proposal_counts.invalid, 0);
+}
@@ -11,24 +11,29 @@ use futures::channel::{mpsc, oneshot};
 use futures::sink::SinkExt;
 use futures::StreamExt;
 use papyrus_network::network_manager::SubscriberSender;
-use papyrus_protobuf::consensus::{ConsensusMessage, Proposal};
+use papyrus_protobuf::consensus::{ConsensusMessage, Proposal, SignedConsensusMessage};
 use papyrus_storage::body::BodyStorageReader;
 use papyrus_storage::header::HeaderStorageReader;
 use papyrus_storage::{StorageError, StorageReader};
-use starknet_api::block::{BlockHash, BlockNumber};
+use starknet_api::block::{BlockBody, BlockHash, BlockHeader, BlockNumber};
 use starknet_api::transaction::Transaction;
 use tokio::sync::Mutex;
 use tracing::debug;
 
+use crate::consensus_metrics::ConsensusMetrics;
+use crate::decisions::StorableBlock;
+use crate::signing::MessageSigner;
 use crate::types::{ConsensusBlock, ConsensusContext, ConsensusError, ProposalInit, ValidatorId};
 use crate::ProposalWrapper;
 
 // TODO: add debug messages and span to the tasks.
 
+// The block is read from the node's storage, so it holds the rest of the block's data besides the
+// transactions that are proposed, which the nodes where consensus is authoritative write.
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub struct PapyrusConsensusBlock {
-    content: Vec<Transaction>,
-    id: BlockHash,
+    pub(crate) header: BlockHeader,
+    pub(crate) body: BlockBody,
 }
 
 impl ConsensusBlock for PapyrusConsensusBlock {
@@ -36,17 +41,27 @@ impl ConsensusBlock for PapyrusConsensusBlock {
     type ProposalIter = std::vec::IntoIter<Transaction>;
 
     fn id(&self) -> BlockHash {
-        self.id
+        self.header.block_hash
     }
 
     fn proposal_iter(&self) -> Self::ProposalIter {
-        self.content.clone().into_iter()
+        self.body.transactions.clone().into_iter()
+    }
+}
+
+impl StorableBlock for PapyrusConsensusBlock {
+    fn into_header_and_body(self) -> (BlockHeader, BlockBody) {
+        (self.header, self.body)
     }
 }
 
 pub struct PapyrusConsensusContext {
     storage_reader: StorageReader,
-    broadcast_sender: Arc<Mutex<SubscriberSender<ConsensusMessage>>>,
+    broadcast_sender: Arc<Mutex<SubscriberSender<SignedConsensusMessage>>>,
+    // If None, the broadcasted messages aren't signed.
+    signer: Option<MessageSigner>,
+    // If None, the progress of consensus isn't reported.
+    metrics: Option<ConsensusMetrics>,
 }
 
 impl PapyrusConsensusContext {
@@ -54,9 +69,16 @@ impl PapyrusConsensusContext {
     #[allow(dead_code)]
     pub fn new(
         storage_reader: StorageReader,
-        broadcast_sender: SubscriberSender<ConsensusMessage>,
+        broadcast_sender: SubscriberSender<SignedConsensusMessage>,
+        signer: Option<MessageSigner>,
+        metrics: Option<ConsensusMetrics>,
     ) -> Self {
-        Self { storage_reader, broadcast_sender: Arc::new(Mutex::new(broadcast_sender)) }
+        Self {
+            storage_reader,
+            broadcast_sender: Arc::new(Mutex::new(broadcast_sender)),
+            signer,
+            metrics,
+        }
     }
 }
 
@@ -79,29 +101,13 @@ impl ConsensusContext for PapyrusConsensusContext {
             // block in storage and to getting the transaction was a revert this flow will fail.
             wait_for_block(&storage_reader, height).await.expect("Failed to wait to block");
 
-            let txn = storage_reader.begin_ro_txn().expect("Failed to begin ro txn");
-            let transactions = txn
-                .get_block_transactions(height)
-                .expect("Get transactions from storage failed")
-                .unwrap_or_else(|| {
-                    panic!("Block in {height} was not found in storage despite waiting for it")
-                });
-
-            for tx in transactions.clone() {
+            let block = read_stored_block(&storage_reader, height);
+            for tx in block.body.transactions.clone() {
                 sender.try_send(tx).expect("Send should succeed");
             }
             sender.close_channel();
 
-            let block_hash = txn
-                .get_block_header(height)
-                .expect("Get header from storage failed")
-                .unwrap_or_else(|| {
-                    panic!("Block in {height} was not found in storage despite waiting for it")
-                })
-                .block_hash;
-            fin_sender
-                .send(PapyrusConsensusBlock { content: transactions, id: block_hash })
-                .expect("Send should succeed");
+            fin_sender.send(block).expect("Send should succeed");
         });
 
         (receiver, fin_receiver)
@@ -120,15 +126,8 @@ impl ConsensusContext for PapyrusConsensusContext {
             // block in storage and to getting the transaction was a revert this flow will fail.
             wait_for_block(&storage_reader, height).await.expect("Failed to wait to block");
 
-            let txn = storage_reader.begin_ro_txn().expect("Failed to begin ro txn");
-            let transactions = txn
-                .get_block_transactions(height)
-                .expect("Get transactions from storage failed")
-                .unwrap_or_else(|| {
-                    panic!("Block in {height} was not found in storage despite waiting for it")
-                });
-
-            for tx in transactions.iter() {
+            let block = read_stored_block(&storage_reader, height);
+            for tx in block.body.transactions.iter() {
                 let received_tx = content
                     .next()
                     .await
@@ -142,16 +141,7 @@ impl ConsensusContext for PapyrusConsensusContext {
                 panic!("Received more transactions than expected");
             }
 
-            let block_hash = txn
-                .get_block_header(height)
-                .expect("Get header from storage failed")
-                .unwrap_or_else(|| {
-                    panic!("Block in {height} was not found in storage despite waiting for it")
-                })
-                .block_hash;
-            fin_sender
-                .send(PapyrusConsensusBlock { content: transactions, id: block_hash })
-                .expect("Send should succeed");
+            fin_sender.send(block).expect("Send should succeed");
         });
 
         fin_receiver
@@ -165,6 +155,15 @@ impl ConsensusContext for PapyrusConsensusContext {
         (height.0 % 2).into()
     }
 
+    async fn catch_up(&self, height: BlockNumber) -> Result<(), ConsensusError> {
+        // The missed blocks are fetched from the peers and written to the storage by sync, which
+        // is the only writer of the storage, so consensus waits for them instead of fetching them.
+        let Some(last_missed_height) = height.0.checked_sub(1).map(BlockNumber) else {
+            return Ok(());
+        };
+        Ok(wait_for_block(&self.storage_reader, last_missed_height).await?)
+    }
+
     async fn propose(
         &self,
         init: ProposalInit,
@@ -172,6 +171,7 @@ impl ConsensusContext for PapyrusConsensusContext {
         fin_receiver: oneshot::Receiver<BlockHash>,
     ) -> Result<(), ConsensusError> {
         let broadcast_sender = self.broadcast_sender.clone();
+        let signer = self.signer.clone();
 
         tokio::spawn(async move {
             let mut transactions = Vec::new();
@@ -179,8 +179,11 @@ impl ConsensusContext for PapyrusConsensusContext {
                 transactions.push(tx);
             }
 
-            let block_hash =
-                fin_receiver.await.expect("Failed to get block hash from fin receiver");
+            // Consensus drops the fin sender instead of sending a fin that would equivocate.
+            let Ok(block_hash) = fin_receiver.await else {
+                debug!("The proposal for height {} has no fin. Not broadcasting it.", init.height);
+                return;
+            };
             let proposal = Proposal {
                 height: init.height.0,
                 proposer: init.proposer,
@@ -188,20 +191,51 @@ impl ConsensusContext for PapyrusConsensusContext {
                 block_hash,
             };
 
-            broadcast_sender
-                .lock()
-                .await
-                .send(ConsensusMessage::Proposal(proposal))
-                .await
-                .expect("Failed to send proposal");
+            let message = ConsensusMessage::Proposal(proposal);
+            let message = match &signer {
+                Some(signer) => signer.sign(message),
+                None => SignedConsensusMessage { message, signature: None },
+            };
+
+            broadcast_sender.lock().await.send(message).await.expect("Failed to send proposal");
         });
         Ok(())
     }
+
+    fn metrics(&self) -> Option<&ConsensusMetrics> {
+        self.metrics.as_ref()
+    }
 }
 
 const SLEEP_BETWEEN_CHECK_FOR_BLOCK: Duration = Duration::from_secs(10);
 
-async fn wait_for_block(
+// Reads the block at the given height, which the caller waited for.
+fn read_stored_block(storage_reader: &StorageReader, height: BlockNumber) -> PapyrusConsensusBlock {
+    let txn = storage_reader.begin_ro_txn().expect("Failed to begin ro txn");
+    let missing_block =
+        format!("Block in {height} was not found in storage despite waiting for it");
+    let header = txn
+        .get_block_header(height)
+        .expect("Get header from storage failed")
+        .expect(&missing_block);
+    let body = BlockBody {
+        transactions: txn
+            .get_block_transactions(height)
+            .expect("Get transactions from storage failed")
+            .expect(&missing_block),
+        transaction_outputs: txn
+            .get_block_transaction_outputs(height)
+            .expect("Get transaction outputs from storage failed")
+            .expect(&missing_block),
+        transaction_hashes: txn
+            .get_block_transaction_hashes(height)
+            .expect("Get transaction hashes from storage failed")
+            .expect(&missing_block),
+    };
+    PapyrusConsensusBlock { header, body }
+}
+
+pub(crate) async fn wait_for_block(
     storage_reader: &StorageReader,
     height: BlockNumber,
 ) -> Result<(), StorageError> {
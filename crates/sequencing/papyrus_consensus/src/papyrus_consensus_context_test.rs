@@ -1,16 +1,18 @@
 use futures::channel::{mpsc, oneshot};
 use futures::StreamExt;
 use papyrus_network::network_manager::{mock_register_broadcast_subscriber, BroadcastNetworkMock};
-use papyrus_protobuf::consensus::{ConsensusMessage, Proposal};
+use papyrus_protobuf::consensus::{ConsensusMessage, Proposal, SignedConsensusMessage};
 use papyrus_storage::body::BodyStorageWriter;
 use papyrus_storage::header::HeaderStorageWriter;
 use papyrus_storage::test_utils::get_test_storage;
 use starknet_api::block::Block;
 use starknet_api::core::ContractAddress;
+use starknet_api::hash::StarkHash;
 use starknet_api::transaction::Transaction;
 use test_utils::get_test_block;
 
 use crate::papyrus_consensus_context::PapyrusConsensusContext;
+use crate::signing::{MessageSigner, MessageVerifier};
 use crate::types::{ConsensusBlock, ConsensusContext, ProposalInit};
 
 // TODO(dvir): consider adding tests for times, i.e, the calls are returned immediately and nothing
@@ -20,7 +22,7 @@ const TEST_CHANNEL_SIZE: usize = 10;
 
 #[tokio::test]
 async fn build_proposal() {
-    let (block, papyrus_context, _mock_network) = test_setup();
+    let (block, papyrus_context, _mock_network) = test_setup(None);
     let block_number = block.header.block_number;
 
     let (mut proposal_receiver, fin_receiver) = papyrus_context.build_proposal(block_number).await;
@@ -38,7 +40,7 @@ async fn build_proposal() {
 
 #[tokio::test]
 async fn validate_proposal_success() {
-    let (block, papyrus_context, _mock_network) = test_setup();
+    let (block, papyrus_context, _mock_network) = test_setup(None);
     let block_number = block.header.block_number;
 
     let (mut validate_sender, validate_receiver) = mpsc::channel(TEST_CHANNEL_SIZE);
@@ -56,7 +58,7 @@ async fn validate_proposal_success() {
 
 #[tokio::test]
 async fn validate_proposal_fail() {
-    let (block, papyrus_context, _mock_network) = test_setup();
+    let (block, papyrus_context, _mock_network) = test_setup(None);
     let block_number = block.header.block_number;
 
     let different_block = get_test_block(4, None, None, None);
@@ -72,7 +74,7 @@ async fn validate_proposal_fail() {
 
 #[tokio::test]
 async fn propose() {
-    let (block, papyrus_context, mut mock_network) = test_setup();
+    let (block, papyrus_context, mut mock_network) = test_setup(None);
     let block_number = block.header.block_number;
 
     let (mut content_sender, content_receiver) = mpsc::channel(TEST_CHANNEL_SIZE);
@@ -87,17 +89,43 @@ async fn propose() {
     let proposal_init = ProposalInit { height: block_number, proposer: ContractAddress::default() };
     papyrus_context.propose(proposal_init.clone(), content_receiver, fin_receiver).await.unwrap();
 
-    let expected_message = ConsensusMessage::Proposal(Proposal {
-        height: proposal_init.height.0,
-        proposer: proposal_init.proposer,
-        transactions: block.body.transactions,
-        block_hash: block.header.block_hash,
-    });
+    let expected_message = SignedConsensusMessage {
+        message: ConsensusMessage::Proposal(Proposal {
+            height: proposal_init.height.0,
+            proposer: proposal_init.proposer,
+            transactions: block.body.transactions,
+            block_hash: block.header.block_hash,
+        }),
+        signature: None,
+    };
 
     assert_eq!(mock_network.messages_to_broadcast_receiver.next().await.unwrap(), expected_message);
 }
 
-fn test_setup() -> (Block, PapyrusConsensusContext, BroadcastNetworkMock<ConsensusMessage>) {
+#[tokio::test]
+async fn propose_signs_the_proposal() {
+    let signer = MessageSigner::from_bytes(&[1]).unwrap();
+    let (block, papyrus_context, mut mock_network) = test_setup(Some(signer.clone()));
+    let block_number = block.header.block_number;
+
+    let (content_sender, content_receiver) = mpsc::channel(TEST_CHANNEL_SIZE);
+    drop(content_sender);
+    let (fin_sender, fin_receiver) = oneshot::channel();
+    fin_sender.send(block.header.block_hash).unwrap();
+
+    let proposer = ContractAddress::try_from(StarkHash::ONE).unwrap();
+    let proposal_init = ProposalInit { height: block_number, proposer };
+    papyrus_context.propose(proposal_init, content_receiver, fin_receiver).await.unwrap();
+
+    let message = mock_network.messages_to_broadcast_receiver.next().await.unwrap();
+    assert!(message.signature.is_some());
+    let verifier = MessageVerifier::new([(proposer, signer.public_key())].into());
+    assert_eq!(verifier.verify(message.clone()), Ok(message.message));
+}
+
+fn test_setup(
+    signer: Option<MessageSigner>,
+) -> (Block, PapyrusConsensusContext, BroadcastNetworkMock<SignedConsensusMessage>) {
     let ((storage_reader, mut storage_writer), _temp_dir) = get_test_storage();
     let block = get_test_block(5, None, None, None);
     let block_number = block.header.block_number;
@@ -115,6 +143,8 @@ fn test_setup() -> (Block, PapyrusConsensusContext, BroadcastNetworkMock<Consens
     let papyrus_context = PapyrusConsensusContext::new(
         storage_reader.clone(),
         test_channels.subscriber_channels.messages_to_broadcast_sender,
+        signer,
+        None,
     );
     (block, papyrus_context, test_channels.mock_network)
 }
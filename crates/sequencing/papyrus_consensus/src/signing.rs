@@ -0,0 +1,134 @@
+//! Authentication of the consensus messages. Each validator signs the messages it broadcasts with
+//! its Stark key, and the messages received from the network are verified against the public keys
+//! of the validators before consensus handles them, so that a peer can't send messages in the name
+//! of a validator.
+
+#[cfg(test)]
+#[path = "signing_test.rs"]
+mod signing_test;
+
+use std::collections::BTreeMap;
+
+use papyrus_protobuf::consensus::{ConsensusMessage, SignedConsensusMessage};
+use starknet_api::crypto::utils::{verify_message_hash_signature, PublicKey, Signature};
+use starknet_api::hash::starknet_keccak_hash;
+use starknet_crypto::FieldElement;
+use starknet_types_core::felt::Felt;
+
+use crate::types::ValidatorId;
+
+/// The errors of a received message that isn't authenticated.
+#[allow(missing_docs)]
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum SignatureError {
+    #[error("The message of {0} isn't signed.")]
+    MissingSignature(ValidatorId),
+    #[error("{0} isn't a validator with a known public key.")]
+    UnknownValidator(ValidatorId),
+    #[error("The signature of the message doesn't match the public key of {0}.")]
+    InvalidSignature(ValidatorId),
+}
+
+#[allow(missing_docs)]
+#[derive(thiserror::Error, Debug)]
+pub enum SigningKeyError {
+    #[error("The signing key should be at most 32 bytes but it's {0} bytes.")]
+    TooLong(usize),
+    #[error("The signing key can't be zero.")]
+    Zero,
+}
+
+/// The validator that sent the message.
+pub fn message_sender(message: &ConsensusMessage) -> ValidatorId {
+    match message {
+        ConsensusMessage::Proposal(proposal) => proposal.proposer,
+    }
+}
+
+// The signed hash is the Starknet Keccak of the encoding of the message without a signature. The
+// height is part of the encoding, so a signature can't be replayed at another height.
+fn message_hash(message: &ConsensusMessage) -> Felt {
+    starknet_keccak_hash(&Vec::<u8>::from(message.clone()))
+}
+
+fn to_field_element(felt: &Felt) -> FieldElement {
+    FieldElement::from_bytes_be(&felt.to_bytes_be()).expect("A felt is a valid field element")
+}
+
+fn to_felt(field_element: &FieldElement) -> Felt {
+    Felt::from_bytes_be(&field_element.to_bytes_be())
+}
+
+/// Signs the messages the node broadcasts with the Stark private key of its validator.
+#[derive(Clone)]
+pub struct MessageSigner {
+    private_key: FieldElement,
+}
+
+impl MessageSigner {
+    /// Creates a signer from the big-endian bytes of the private key, as given in the config.
+    pub fn from_bytes(private_key: &[u8]) -> Result<Self, SigningKeyError> {
+        if private_key.len() > 32 {
+            return Err(SigningKeyError::TooLong(private_key.len()));
+        }
+        let private_key = to_field_element(&Felt::from_bytes_be_slice(private_key));
+        if private_key == FieldElement::ZERO {
+            return Err(SigningKeyError::Zero);
+        }
+        Ok(Self { private_key })
+    }
+
+    /// The public key that should be registered for the validator in the other nodes.
+    pub fn public_key(&self) -> PublicKey {
+        PublicKey(to_felt(&starknet_crypto::get_public_key(&self.private_key)))
+    }
+
+    /// Attaches the signature of the validator to the message.
+    pub fn sign(&self, message: ConsensusMessage) -> SignedConsensusMessage {
+        let message_hash = to_field_element(&message_hash(&message));
+        // The nonce is derived from the message and the key as in RFC 6979, so that signing
+        // doesn't depend on a source of randomness.
+        let k = starknet_crypto::rfc6979_generate_k(&message_hash, &self.private_key, None);
+        let signature = starknet_crypto::sign(&self.private_key, &message_hash, &k)
+            .expect("The hash of a message is a valid message to sign");
+        SignedConsensusMessage {
+            message,
+            signature: Some(Signature { r: to_felt(&signature.r), s: to_felt(&signature.s) }),
+        }
+    }
+}
+
+/// Verifies that the received messages were signed by the validators that sent them.
+#[derive(Clone, Debug, Default)]
+pub struct MessageVerifier {
+    // If empty, the messages aren't authenticated and every message is accepted.
+    validator_public_keys: BTreeMap<ValidatorId, PublicKey>,
+}
+
+impl MessageVerifier {
+    /// Creates a verifier of the messages of the validators in `validator_public_keys`.
+    pub fn new(validator_public_keys: BTreeMap<ValidatorId, PublicKey>) -> Self {
+        Self { validator_public_keys }
+    }
+
+    /// Returns the message if it's signed by the validator that sent it.
+    pub fn verify(
+        &self,
+        signed_message: SignedConsensusMessage,
+    ) -> Result<ConsensusMessage, SignatureError> {
+        let SignedConsensusMessage { message, signature } = signed_message;
+        if self.validator_public_keys.is_empty() {
+            return Ok(message);
+        }
+        let sender = message_sender(&message);
+        let signature = signature.ok_or(SignatureError::MissingSignature(sender))?;
+        let public_key = self
+            .validator_public_keys
+            .get(&sender)
+            .ok_or(SignatureError::UnknownValidator(sender))?;
+        match verify_message_hash_signature(&message_hash(&message), &signature, public_key) {
+            Ok(true) => Ok(message),
+            Ok(false) | Err(_) => Err(SignatureError::InvalidSignature(sender)),
+        }
+    }
+}
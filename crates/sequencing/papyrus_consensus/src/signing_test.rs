@@ -0,0 +1,101 @@
+use papyrus_protobuf::consensus::{ConsensusMessage, Proposal, SignedConsensusMessage};
+use starknet_api::block::BlockHash;
+use starknet_api::core::ContractAddress;
+use starknet_api::hash::StarkHash;
+
+use crate::signing::{MessageSigner, MessageVerifier, SignatureError};
+use crate::types::ValidatorId;
+
+const HEIGHT: u64 = 5;
+
+fn validator_id() -> ValidatorId {
+    ContractAddress::try_from(StarkHash::ONE).unwrap()
+}
+
+fn signer() -> MessageSigner {
+    MessageSigner::from_bytes(&[1]).unwrap()
+}
+
+fn verifier() -> MessageVerifier {
+    MessageVerifier::new([(validator_id(), signer().public_key())].into())
+}
+
+fn proposal_message(height: u64) -> ConsensusMessage {
+    ConsensusMessage::Proposal(Proposal {
+        height,
+        proposer: validator_id(),
+        transactions: vec![],
+        block_hash: BlockHash(StarkHash::TWO),
+    })
+}
+
+fn set_height(signed_message: &mut SignedConsensusMessage, height: u64) {
+    let ConsensusMessage::Proposal(proposal) = &mut signed_message.message;
+    proposal.height = height;
+}
+
+#[test]
+fn signed_message_is_verified_after_encoding() {
+    let signed_message = signer().sign(proposal_message(HEIGHT));
+    let decoded_message =
+        SignedConsensusMessage::try_from(Vec::<u8>::from(signed_message.clone())).unwrap();
+    assert_eq!(decoded_message, signed_message);
+    assert_eq!(verifier().verify(decoded_message), Ok(proposal_message(HEIGHT)));
+}
+
+#[test]
+fn tampered_message_is_rejected() {
+    let mut signed_message = signer().sign(proposal_message(HEIGHT));
+    let ConsensusMessage::Proposal(proposal) = &mut signed_message.message;
+    proposal.block_hash = BlockHash(StarkHash::THREE);
+    assert_eq!(
+        verifier().verify(signed_message),
+        Err(SignatureError::InvalidSignature(validator_id()))
+    );
+}
+
+#[test]
+fn message_signed_with_another_key_is_rejected() {
+    let other_signer = MessageSigner::from_bytes(&[2]).unwrap();
+    let signed_message = other_signer.sign(proposal_message(HEIGHT));
+    assert_eq!(
+        verifier().verify(signed_message),
+        Err(SignatureError::InvalidSignature(validator_id()))
+    );
+}
+
+#[test]
+fn message_replayed_at_another_height_is_rejected() {
+    let mut signed_message = signer().sign(proposal_message(HEIGHT));
+    set_height(&mut signed_message, HEIGHT + 1);
+    assert_eq!(
+        verifier().verify(signed_message),
+        Err(SignatureError::InvalidSignature(validator_id()))
+    );
+}
+
+#[test]
+fn unsigned_message_and_unknown_validator_are_rejected() {
+    let unsigned_message =
+        SignedConsensusMessage { message: proposal_message(HEIGHT), signature: None };
+    assert_eq!(
+        verifier().verify(unsigned_message.clone()),
+        Err(SignatureError::MissingSignature(validator_id()))
+    );
+
+    let other_validator_id = ContractAddress::try_from(StarkHash::TWO).unwrap();
+    let verifier = MessageVerifier::new([(other_validator_id, signer().public_key())].into());
+    assert_eq!(
+        verifier.verify(signer().sign(proposal_message(HEIGHT))),
+        Err(SignatureError::UnknownValidator(validator_id()))
+    );
+
+    // Without public keys, messages aren't verified.
+    assert_eq!(MessageVerifier::default().verify(unsigned_message), Ok(proposal_message(HEIGHT)));
+}
+
+#[test]
+fn signing_key_must_be_a_nonzero_felt() {
+    assert!(MessageSigner::from_bytes(&[0; 32]).is_err());
+    assert!(MessageSigner::from_bytes(&[1; 33]).is_err());
+}
@@ -6,8 +6,11 @@ use std::sync::Arc;
 
 use futures::channel::{mpsc, oneshot};
 use starknet_api::block::{BlockHash, BlockNumber};
+use tracing::warn;
 
+use crate::consensus_metrics::ConsensusMessageType;
 use crate::types::{ConsensusBlock, ConsensusContext, ConsensusError, ProposalInit, ValidatorId};
+use crate::wal::ConsensusWal;
 
 /// Struct which represents a single height of consensus. Each height is expected to be begun with a
 /// call to `start`, which is relevant if we are the proposer for this height's first round. SHC
@@ -33,10 +36,19 @@ where
         id: ValidatorId,
     ) -> Self {
         let validators = context.validators(height).await;
+        // Each height is decided in a single round.
+        if let Some(metrics) = context.metrics() {
+            metrics.round_started(height, 0, context.proposer(&validators, height) == id);
+        }
         Self { height, context, validators, id }
     }
 
-    pub(crate) async fn start(&mut self) -> Result<Option<BlockT>, ConsensusError> {
+    /// Returns None if we aren't the proposer, or if we already proposed a different block at this
+    /// height before a restart, in which case the new proposal is left without a fin.
+    pub(crate) async fn start(
+        &mut self,
+        wal: &mut ConsensusWal,
+    ) -> Result<Option<BlockT>, ConsensusError> {
         let proposer_id = self.context.proposer(&self.validators, self.height);
         if proposer_id != self.id {
             return Ok(None);
@@ -51,12 +63,30 @@ where
             .await
             .expect("Failed sending Proposal to Peering");
         let block = block_receiver.await.expect("Block building failed.");
+        // The proposal is recorded before its fin is sent, so that if we restart before the
+        // height is decided we won't send a fin for a different block.
+        match wal.recorded_proposal(self.height) {
+            Some(recorded_block_id) if recorded_block_id != block.id() => {
+                warn!(
+                    "Already proposed block {recorded_block_id:?} at height {} before restarting. \
+                     Not sending the fin of block {:?} to avoid equivocating.",
+                    self.height,
+                    block.id()
+                );
+                return Ok(None);
+            }
+            Some(_) => {}
+            None => wal.record_proposal(self.height, block.id())?,
+        }
         // If we choose to ignore this error, we should carefully consider how this affects
         // Tendermint. The partially synchronous model assumes all messages arrive at some point,
         // and this failure means this proposal will never arrive.
         //
         // TODO(matan): Switch this to the Proposal signature.
         fin_sender.send(block.id()).expect("Failed to send ProposalFin to Peering.");
+        if let Some(metrics) = self.context.metrics() {
+            metrics.message_sent(ConsensusMessageType::Proposal);
+        }
         Ok(Some(block))
     }
 
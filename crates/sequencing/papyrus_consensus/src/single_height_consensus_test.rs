@@ -3,11 +3,13 @@ use std::sync::{Arc, OnceLock};
 use futures::channel::{mpsc, oneshot};
 use starknet_api::block::{BlockHash, BlockNumber};
 use starknet_types_core::felt::Felt;
+use tempfile::TempDir;
 use tokio;
 
 use super::SingleHeightConsensus;
 use crate::test_utils::{MockTestContext, TestBlock};
 use crate::types::{ConsensusBlock, ProposalInit, ValidatorId};
+use crate::wal::ConsensusWal;
 
 #[tokio::test]
 async fn proposer() {
@@ -40,14 +42,86 @@ async fn proposer() {
 
     let mut shc = SingleHeightConsensus::new(BlockNumber(0), Arc::new(context), node_id).await;
 
-    let decision = shc.start().await.unwrap().unwrap();
+    let wal_dir = TempDir::new().unwrap();
+    let mut wal = ConsensusWal::open(wal_dir.path().join("consensus_wal")).unwrap();
+    let decision = shc.start(&mut wal).await.unwrap().unwrap();
     assert_eq!(decision, block);
+    assert_eq!(wal.recorded_proposal(BlockNumber(0)), Some(block.id()));
 
     // Check the fin sent to the network.
     let fin = Arc::into_inner(fin_receiver).unwrap().take().unwrap().await.unwrap();
     assert_eq!(fin, block.id());
 }
 
+// Returns a context in which `node_id` proposes `block` at every height, and a receiver of the fin
+// of its proposal.
+fn proposer_context(
+    node_id: ValidatorId,
+    block: TestBlock,
+) -> (MockTestContext, Arc<OnceLock<oneshot::Receiver<BlockHash>>>) {
+    let mut context = MockTestContext::new();
+    context.expect_validators().returning(move |_| vec![node_id, 2_u32.into()]);
+    context.expect_proposer().returning(move |_, _| node_id);
+    context.expect_build_proposal().returning(move |_| {
+        let (_, content_receiver) = mpsc::channel(1);
+        let (block_sender, block_receiver) = oneshot::channel();
+        block_sender.send(block.clone()).unwrap();
+        (content_receiver, block_receiver)
+    });
+    let fin_receiver = Arc::new(OnceLock::new());
+    let fin_receiver_clone = Arc::clone(&fin_receiver);
+    context.expect_propose().return_once(move |_, _, fin_receiver| {
+        fin_receiver_clone.set(fin_receiver).unwrap();
+        Ok(())
+    });
+    (context, fin_receiver)
+}
+
+#[tokio::test]
+async fn proposer_restarted_after_recording_its_proposal_doesnt_equivocate() {
+    let node_id: ValidatorId = 1_u32.into();
+    let wal_dir = TempDir::new().unwrap();
+    let wal_path = wal_dir.path().join("consensus_wal");
+
+    // The node crashed after recording its proposal and before sending the fin.
+    let mut wal = ConsensusWal::open(wal_path.clone()).unwrap();
+    wal.record_proposal(BlockNumber(0), BlockHash(Felt::ONE)).unwrap();
+    drop(wal);
+
+    // After the restart it builds a different block.
+    let mut wal = ConsensusWal::open(wal_path).unwrap();
+    let block = TestBlock { content: vec![4, 5, 6], id: BlockHash(Felt::TWO) };
+    let (context, fin_receiver) = proposer_context(node_id, block);
+    let mut shc = SingleHeightConsensus::new(BlockNumber(0), Arc::new(context), node_id).await;
+
+    assert_eq!(shc.start(&mut wal).await.unwrap(), None);
+    // No fin is sent for the new block, so the peers don't accept it.
+    Arc::into_inner(fin_receiver).unwrap().take().unwrap().await.unwrap_err();
+    assert_eq!(wal.recorded_proposal(BlockNumber(0)), Some(BlockHash(Felt::ONE)));
+}
+
+#[tokio::test]
+async fn proposer_restarted_after_recording_its_proposal_resends_the_same_block() {
+    let node_id: ValidatorId = 1_u32.into();
+    let wal_dir = TempDir::new().unwrap();
+    let wal_path = wal_dir.path().join("consensus_wal");
+    let block = TestBlock { content: vec![1, 2, 3], id: BlockHash(Felt::ONE) };
+
+    // The node crashed after recording its proposal and before sending the fin.
+    let mut wal = ConsensusWal::open(wal_path.clone()).unwrap();
+    wal.record_proposal(BlockNumber(0), block.id()).unwrap();
+    drop(wal);
+
+    // After the restart it builds the same block, so proposing it again doesn't equivocate.
+    let mut wal = ConsensusWal::open(wal_path).unwrap();
+    let (context, fin_receiver) = proposer_context(node_id, block.clone());
+    let mut shc = SingleHeightConsensus::new(BlockNumber(0), Arc::new(context), node_id).await;
+
+    assert_eq!(shc.start(&mut wal).await.unwrap(), Some(block.clone()));
+    let fin = Arc::into_inner(fin_receiver).unwrap().take().unwrap().await.unwrap();
+    assert_eq!(fin, block.id());
+}
+
 #[tokio::test]
 async fn validator() {
     let mut context = MockTestContext::new();
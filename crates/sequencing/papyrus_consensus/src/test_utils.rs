@@ -1,5 +1,8 @@
+use std::sync::OnceLock;
+
 use async_trait::async_trait;
 use futures::channel::{mpsc, oneshot};
+use metrics_exporter_prometheus::{PrometheusBuilder, PrometheusHandle};
 use mockall::mock;
 use starknet_api::block::{BlockHash, BlockNumber};
 
@@ -48,6 +51,8 @@ mock! {
 
         fn proposer(&self, validators: &[ValidatorId], height: BlockNumber) -> ValidatorId;
 
+        async fn catch_up(&self, height: BlockNumber) -> Result<(), ConsensusError>;
+
         async fn propose(
             &self,
             init: ProposalInit,
@@ -56,3 +61,9 @@ mock! {
         ) -> Result<(), ConsensusError>;
     }
 }
+
+// A metrics recorder can be installed only once in a process, so the tests share it.
+pub fn get_prometheus_handle() -> PrometheusHandle {
+    static PROMETHEUS_HANDLE: OnceLock<PrometheusHandle> = OnceLock::new();
+    PROMETHEUS_HANDLE.get_or_init(|| PrometheusBuilder::new().install_recorder().unwrap()).clone()
+}
@@ -4,9 +4,13 @@ mod types_test;
 
 use async_trait::async_trait;
 use futures::channel::{mpsc, oneshot};
+use papyrus_storage::StorageError;
 use starknet_api::block::{BlockHash, BlockNumber};
 use starknet_api::core::ContractAddress;
 
+use crate::consensus_metrics::ConsensusMetrics;
+use crate::wal::WalError;
+
 /// Used to identify the node by consensus.
 /// 1. This ID is derived from the id registered with Starknet's L2 staking contract.
 /// 2. We must be able to derive the public key associated with this ID for the sake of validating
@@ -129,6 +133,13 @@ pub trait ConsensusContext: Send + Sync {
     /// Calculates the ID of the Proposer based on the inputs.
     fn proposer(&self, validators: &[ValidatorId], height: BlockNumber) -> ValidatorId;
 
+    /// This function is called by consensus when it learns that the network already reached
+    /// `height`, which is above the height consensus is at, e.g. after being offline. Consensus
+    /// skips to `height` once this call returns.
+    ///
+    /// Returns once all the blocks below `height` are available to the node.
+    async fn catch_up(&self, height: BlockNumber) -> Result<(), ConsensusError>;
+
     /// This should be non-blocking. Meaning it returns immediately and waits to receive from the
     /// input channels in parallel (ie on a separate task).
     // TODO(matan): change to just be a generic broadcast function.
@@ -138,6 +149,11 @@ pub trait ConsensusContext: Send + Sync {
         content_receiver: mpsc::Receiver<<Self::Block as ConsensusBlock>::ProposalChunk>,
         fin_receiver: oneshot::Receiver<BlockHash>,
     ) -> Result<(), ConsensusError>;
+
+    /// The hooks consensus reports its progress to. If None, the progress isn't reported.
+    fn metrics(&self) -> Option<&ConsensusMetrics> {
+        None
+    }
 }
 
 #[derive(PartialEq, Debug, Clone)]
@@ -146,10 +162,30 @@ pub struct ProposalInit {
     pub proposer: ValidatorId,
 }
 
+/// A block that consensus decided on.
+#[derive(PartialEq, Debug, Clone)]
+pub struct Decision<BlockT> {
+    pub height: BlockNumber,
+    pub block: BlockT,
+}
+
 #[derive(thiserror::Error, Debug)]
 pub enum ConsensusError {
     #[error(transparent)]
     Canceled(#[from] oneshot::Canceled),
     #[error("Invalid proposal sent by peer {0:?} at height {1}: {2}")]
     InvalidProposal(ValidatorId, BlockNumber, String),
+    #[error("Invalid proposal built at height {0}: {1}")]
+    InvalidBuiltProposal(BlockNumber, String),
+    #[error("The receiver of the decisions was dropped.")]
+    DecisionReceiverDropped(#[from] mpsc::SendError),
+    #[error(transparent)]
+    StorageError(#[from] StorageError),
+    #[error(transparent)]
+    WalError(#[from] WalError),
+    #[error(
+        "Consensus decided on height {height}, but the storage has only the blocks below \
+         {header_marker}."
+    )]
+    DecisionAboveStorage { height: BlockNumber, header_marker: BlockNumber },
 }
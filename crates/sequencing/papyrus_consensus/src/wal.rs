@@ -0,0 +1,126 @@
+//! A write-ahead log of the proposals the node sent.
+//!
+//! A proposal is recorded before its fin is sent, so a node that restarts at a height it already
+//! proposed at can tell whether the block it proposes now conflicts with the one it proposed
+//! before the restart.
+
+#[cfg(test)]
+#[path = "wal_test.rs"]
+mod wal_test;
+
+use std::collections::BTreeMap;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, Write};
+use std::path::{Path, PathBuf};
+
+use starknet_api::block::{BlockHash, BlockNumber};
+use starknet_types_core::felt::Felt;
+
+/// The proposals the node sent at the heights that weren't decided yet, backed by an append-only
+/// file with an entry per line: `<height> <block hash>`.
+pub struct ConsensusWal {
+    path: PathBuf,
+    file: File,
+    proposals: BTreeMap<BlockNumber, BlockHash>,
+}
+
+#[allow(missing_docs)]
+#[derive(thiserror::Error, Debug)]
+pub enum WalError {
+    #[error(transparent)]
+    Io(#[from] io::Error),
+    #[error("Corrupted entry in line {line} of the consensus WAL: {entry:?}.")]
+    CorruptedEntry { line: usize, entry: String },
+}
+
+impl ConsensusWal {
+    /// Opens the WAL at `path`, creating it if it doesn't exist, and loads the recorded proposals.
+    pub fn open(path: PathBuf) -> Result<Self, WalError> {
+        let proposals = match File::open(&path) {
+            Ok(file) => read_entries(file)?,
+            Err(error) if error.kind() == io::ErrorKind::NotFound => BTreeMap::new(),
+            Err(error) => return Err(error.into()),
+        };
+        // Rewriting the file drops an entry that a crash left partially written, so that new
+        // entries aren't appended to it.
+        write_entries_atomically(&path, &proposals)?;
+        let file = OpenOptions::new().append(true).open(&path)?;
+        Ok(Self { path, file, proposals })
+    }
+
+    /// The block the node proposed at `height`, if it proposed at it.
+    pub fn recorded_proposal(&self, height: BlockNumber) -> Option<BlockHash> {
+        self.proposals.get(&height).copied()
+    }
+
+    /// Records the proposal and returns only once it's on the disk.
+    pub fn record_proposal(
+        &mut self,
+        height: BlockNumber,
+        block_hash: BlockHash,
+    ) -> Result<(), WalError> {
+        self.file.write_all(format_entry(height, block_hash).as_bytes())?;
+        self.file.sync_data()?;
+        self.proposals.insert(height, block_hash);
+        Ok(())
+    }
+
+    /// Drops the proposals of `decided_height` and the heights below it, which can't be
+    /// equivocated on anymore.
+    pub fn compact(&mut self, decided_height: BlockNumber) -> Result<(), WalError> {
+        self.proposals = self.proposals.split_off(&decided_height.unchecked_next());
+        write_entries_atomically(&self.path, &self.proposals)?;
+        self.file = OpenOptions::new().append(true).open(&self.path)?;
+        Ok(())
+    }
+}
+
+fn format_entry(height: BlockNumber, block_hash: BlockHash) -> String {
+    format!("{} {}\n", height.0, block_hash.0.to_hex_string())
+}
+
+fn parse_entry(entry: &str) -> Option<(BlockNumber, BlockHash)> {
+    let (height, block_hash) = entry.split_once(' ')?;
+    Some((BlockNumber(height.parse().ok()?), BlockHash(Felt::from_hex(block_hash).ok()?)))
+}
+
+// An entry is written whole or not at all unless the node crashed while writing it, in which case
+// it's the last entry and it doesn't end with a newline. It's dropped, since the fin of its
+// proposal wasn't sent.
+fn read_entries(file: File) -> Result<BTreeMap<BlockNumber, BlockHash>, WalError> {
+    let mut reader = BufReader::new(file);
+    let mut proposals = BTreeMap::new();
+    let mut line = Vec::new();
+    for line_number in 1.. {
+        line.clear();
+        if reader.read_until(b'\n', &mut line)? == 0 || line.last() != Some(&b'\n') {
+            break;
+        }
+        let entry = &line[..line.len() - 1];
+        let Some((height, block_hash)) = std::str::from_utf8(entry).ok().and_then(parse_entry)
+        else {
+            return Err(WalError::CorruptedEntry {
+                line: line_number,
+                entry: String::from_utf8_lossy(entry).into_owned(),
+            });
+        };
+        proposals.insert(height, block_hash);
+    }
+    Ok(proposals)
+}
+
+// Writes to a temporary file that replaces the WAL, so a crash leaves either the old entries or
+// the new ones.
+fn write_entries_atomically(
+    path: &Path,
+    proposals: &BTreeMap<BlockNumber, BlockHash>,
+) -> Result<(), WalError> {
+    let temp_path = path.with_extension("tmp");
+    let mut temp_file = File::create(&temp_path)?;
+    for (height, block_hash) in proposals {
+        temp_file.write_all(format_entry(*height, *block_hash).as_bytes())?;
+    }
+    temp_file.sync_all()?;
+    fs::rename(&temp_path, path)?;
+    Ok(())
+}
@@ -0,0 +1,77 @@
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::PathBuf;
+
+use starknet_api::block::{BlockHash, BlockNumber};
+use starknet_types_core::felt::Felt;
+use tempfile::TempDir;
+
+use super::{ConsensusWal, WalError};
+
+fn wal_path(dir: &TempDir) -> PathBuf {
+    dir.path().join("consensus_wal")
+}
+
+#[test]
+fn recorded_proposals_survive_reopening() {
+    let dir = TempDir::new().unwrap();
+    let mut wal = ConsensusWal::open(wal_path(&dir)).unwrap();
+    assert_eq!(wal.recorded_proposal(BlockNumber(0)), None);
+    wal.record_proposal(BlockNumber(0), BlockHash(Felt::ONE)).unwrap();
+    wal.record_proposal(BlockNumber(2), BlockHash(Felt::TWO)).unwrap();
+    drop(wal);
+
+    let wal = ConsensusWal::open(wal_path(&dir)).unwrap();
+    assert_eq!(wal.recorded_proposal(BlockNumber(0)), Some(BlockHash(Felt::ONE)));
+    assert_eq!(wal.recorded_proposal(BlockNumber(1)), None);
+    assert_eq!(wal.recorded_proposal(BlockNumber(2)), Some(BlockHash(Felt::TWO)));
+}
+
+#[test]
+fn compaction_drops_decided_heights() {
+    let dir = TempDir::new().unwrap();
+    let mut wal = ConsensusWal::open(wal_path(&dir)).unwrap();
+    for height in 0..4 {
+        wal.record_proposal(BlockNumber(height), BlockHash(Felt::from(height))).unwrap();
+    }
+    wal.compact(BlockNumber(1)).unwrap();
+    wal.record_proposal(BlockNumber(4), BlockHash(Felt::from(4_u64))).unwrap();
+    drop(wal);
+
+    let wal = ConsensusWal::open(wal_path(&dir)).unwrap();
+    for height in 0..2 {
+        assert_eq!(wal.recorded_proposal(BlockNumber(height)), None);
+    }
+    for height in 2..5 {
+        assert_eq!(wal.recorded_proposal(BlockNumber(height)), Some(BlockHash(Felt::from(height))));
+    }
+}
+
+#[test]
+fn partially_written_last_entry_is_dropped() {
+    let dir = TempDir::new().unwrap();
+    let mut wal = ConsensusWal::open(wal_path(&dir)).unwrap();
+    wal.record_proposal(BlockNumber(0), BlockHash(Felt::ONE)).unwrap();
+    drop(wal);
+    // A crash in the middle of writing an entry. The truncated hash is still a valid hash.
+    OpenOptions::new().append(true).open(wal_path(&dir)).unwrap().write_all(b"1 0x12").unwrap();
+
+    let mut wal = ConsensusWal::open(wal_path(&dir)).unwrap();
+    assert_eq!(wal.recorded_proposal(BlockNumber(0)), Some(BlockHash(Felt::ONE)));
+    assert_eq!(wal.recorded_proposal(BlockNumber(1)), None);
+
+    // The partial entry was removed, so new entries are readable.
+    wal.record_proposal(BlockNumber(1), BlockHash(Felt::TWO)).unwrap();
+    drop(wal);
+    let wal = ConsensusWal::open(wal_path(&dir)).unwrap();
+    assert_eq!(wal.recorded_proposal(BlockNumber(1)), Some(BlockHash(Felt::TWO)));
+}
+
+#[test]
+fn corrupted_entry_fails_opening() {
+    let dir = TempDir::new().unwrap();
+    fs::write(wal_path(&dir), "0 0x1\nnot an entry\n1 0x2\n").unwrap();
+
+    let error = ConsensusWal::open(wal_path(&dir)).err().unwrap();
+    assert!(matches!(error, WalError::CorruptedEntry { line: 2, .. }), "{error:?}");
+}
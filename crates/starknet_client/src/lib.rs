@@ -18,9 +18,14 @@ mod test_utils;
 pub mod writer;
 
 use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
 
-use reqwest::header::HeaderMap;
+use metrics::increment_counter;
+use papyrus_common::metrics::PAPYRUS_CENTRAL_RATE_LIMIT_BACKOFFS;
+use reqwest::header::{HeaderMap, RETRY_AFTER};
 use reqwest::{Client, RequestBuilder, StatusCode};
+use tokio::time::Instant;
 use tracing::warn;
 
 use self::retry::Retry;
@@ -35,6 +40,9 @@ struct StarknetClient {
     http_headers: HeaderMap,
     pub internal_client: Client,
     retry_config: RetryConfig,
+    // The time until which the gateway asked to stop sending requests, by the Retry-After header
+    // of a 429 response. Every request of the client waits until then.
+    rate_limited_until: Mutex<Option<Instant>>,
 }
 
 /// Errors that might be encountered while creating the client.
@@ -111,6 +119,7 @@ impl StarknetClient {
             http_headers: header_map,
             internal_client: Client::builder().user_agent(app_user_agent).build()?,
             retry_config,
+            rate_limited_until: Mutex::new(None),
         })
     }
 
@@ -189,9 +198,15 @@ impl StarknetClient {
     }
 
     async fn request(&self, request_builder: RequestBuilder) -> ClientResult<String> {
+        self.wait_for_rate_limit().await;
         let res = request_builder.headers(self.http_headers.clone()).send().await;
         let (code, message) = match res {
-            Ok(response) => (response.status(), response.text().await?),
+            Ok(response) => {
+                if response.status() == StatusCode::TOO_MANY_REQUESTS {
+                    self.back_off(retry_after(response.headers()));
+                }
+                (response.status(), response.text().await?)
+            }
             Err(err) => {
                 let msg = err.to_string();
                 (err.status().ok_or(err)?, msg)
@@ -209,4 +224,35 @@ impl StarknetClient {
             _ => Err(ClientError::BadResponseStatus { code, message }),
         }
     }
+
+    async fn wait_for_rate_limit(&self) {
+        let rate_limited_until =
+            *self.rate_limited_until.lock().expect("Rate limit lock should not be poisoned");
+        if let Some(rate_limited_until) = rate_limited_until {
+            tokio::time::sleep_until(rate_limited_until).await;
+        }
+    }
+
+    // Called on a 429 response. Without a Retry-After header, the retry mechanism's exponential
+    // backoff is the only backoff.
+    fn back_off(&self, retry_after: Option<Duration>) {
+        increment_counter!(PAPYRUS_CENTRAL_RATE_LIMIT_BACKOFFS);
+        let Some(retry_after) = retry_after else {
+            return;
+        };
+        warn!("Starknet gateway is rate limiting requests. Pausing them for {retry_after:?}.");
+        let until = Instant::now() + retry_after;
+        let mut rate_limited_until =
+            self.rate_limited_until.lock().expect("Rate limit lock should not be poisoned");
+        if rate_limited_until.map_or(true, |rate_limited_until| rate_limited_until < until) {
+            *rate_limited_until = Some(until);
+        }
+    }
+}
+
+// The delay a response asked for in its Retry-After header. Only the delay-seconds form is
+// supported, an HTTP date is ignored.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let seconds = headers.get(RETRY_AFTER)?.to_str().ok()?.trim().parse().ok()?;
+    Some(Duration::from_secs(seconds))
 }
@@ -1,3 +1,5 @@
+use std::time::{Duration, Instant};
+
 use serde::{Deserialize, Serialize};
 use starknet_api::block::{BlockHash, BlockNumber, BlockTimestamp, GasPrice, GasPricePerToken};
 use starknet_api::core::{GlobalRoot, SequencerContractAddress, TransactionCommitment};
@@ -11,6 +13,31 @@ use crate::reader::StateDiff;
 pub struct PendingData {
     pub block: PendingBlockOrDeprecated,
     pub state_update: PendingStateUpdate,
+    /// How fresh the data is. None if the data was never fetched from the pending source, e.g.
+    /// before the first fetch after startup.
+    #[serde(skip)]
+    pub freshness: Option<PendingDataFreshness>,
+}
+
+/// When pending data was last fetched and when it's no longer considered up to date.
+#[derive(Debug, Clone, Copy, Eq, PartialEq)]
+pub struct PendingDataFreshness {
+    pub last_updated: Instant,
+    pub max_staleness: Duration,
+}
+
+impl PendingDataFreshness {
+    pub fn new(max_staleness: Duration) -> Self {
+        Self { last_updated: Instant::now(), max_staleness }
+    }
+
+    pub fn age(&self) -> Duration {
+        self.last_updated.elapsed()
+    }
+
+    pub fn is_stale(&self) -> bool {
+        self.age() > self.max_staleness
+    }
 }
 
 #[derive(Debug, Deserialize, Clone, Eq, PartialEq)]
@@ -1,3 +1,5 @@
+use std::time::{Duration, Instant};
+
 use assert_matches::assert_matches;
 use mockito::mock;
 use reqwest::StatusCode;
@@ -176,6 +178,36 @@ async fn request_with_retry_starknet_error_success_on_retry() {
     mock_success.assert();
 }
 
+#[tokio::test]
+async fn request_with_retry_waits_for_retry_after() {
+    const BODY: &str = "body";
+    const RETRY_AFTER_SECS: u64 = 1;
+    let starknet_client = StarknetClient::new(None, NODE_VERSION, get_test_config()).unwrap();
+    let mock_rate_limited = mock("GET", URL_SUFFIX)
+        .with_status(StatusCode::TOO_MANY_REQUESTS.as_u16().into())
+        .with_header("retry-after", &RETRY_AFTER_SECS.to_string())
+        .expect(1)
+        .create();
+    let mock_success = mock("GET", URL_SUFFIX).with_status(200).with_body(BODY).expect(2).create();
+    let mut url = mockito::server_url();
+    url.push_str(URL_SUFFIX);
+
+    let start = Instant::now();
+    let result =
+        starknet_client.request_with_retry(starknet_client.internal_client.get(&url)).await;
+    assert_eq!(result.unwrap(), BODY);
+    assert!(start.elapsed() >= Duration::from_secs(RETRY_AFTER_SECS));
+    mock_rate_limited.assert();
+
+    // The backoff is over, so the next request is sent right away.
+    let start = Instant::now();
+    let result =
+        starknet_client.request_with_retry(starknet_client.internal_client.get(&url)).await;
+    assert_eq!(result.unwrap(), BODY);
+    assert!(start.elapsed() < Duration::from_secs(RETRY_AFTER_SECS));
+    mock_success.assert();
+}
+
 #[test]
 fn serialization_precision() {
     let input =
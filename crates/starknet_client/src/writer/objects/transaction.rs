@@ -81,6 +81,12 @@ pub enum ReservedDataAvailabilityMode {
     Reserved = 0,
 }
 
+impl From<ReservedDataAvailabilityMode> for starknet_api::data_availability::DataAvailabilityMode {
+    fn from(_: ReservedDataAvailabilityMode) -> Self {
+        starknet_api::data_availability::DataAvailabilityMode::L1
+    }
+}
+
 /// A deploy account transaction that can be added to Starknet through the Starknet gateway.
 /// It has a serialization format that the Starknet gateway accepts in the `add_transaction`
 /// HTTP method.
@@ -128,6 +134,37 @@ pub enum DeployAccountTransaction {
     DeployAccountV3(DeployAccountV3Transaction),
 }
 
+impl From<DeployAccountTransaction> for starknet_api::transaction::DeployAccountTransaction {
+    fn from(tx: DeployAccountTransaction) -> Self {
+        match tx {
+            DeployAccountTransaction::DeployAccountV1(tx) => {
+                Self::V1(starknet_api::transaction::DeployAccountTransactionV1 {
+                    max_fee: tx.max_fee,
+                    signature: tx.signature,
+                    nonce: tx.nonce,
+                    class_hash: tx.class_hash,
+                    contract_address_salt: tx.contract_address_salt,
+                    constructor_calldata: tx.constructor_calldata,
+                })
+            }
+            DeployAccountTransaction::DeployAccountV3(tx) => {
+                Self::V3(starknet_api::transaction::DeployAccountTransactionV3 {
+                    resource_bounds: tx.resource_bounds,
+                    tip: tx.tip,
+                    signature: tx.signature,
+                    nonce: tx.nonce,
+                    class_hash: tx.class_hash,
+                    contract_address_salt: tx.contract_address_salt,
+                    constructor_calldata: tx.constructor_calldata,
+                    nonce_data_availability_mode: tx.nonce_data_availability_mode.into(),
+                    fee_data_availability_mode: tx.fee_data_availability_mode.into(),
+                    paymaster_data: tx.paymaster_data,
+                })
+            }
+        }
+    }
+}
+
 /// An invoke account transaction that can be added to Starknet through the Starknet gateway.
 /// The invoke is a V0 transaction.
 /// It has a serialization format that the Starknet gateway accepts in the `add_transaction`
@@ -193,6 +230,45 @@ pub enum InvokeTransaction {
     InvokeV1(InvokeV1Transaction),
     InvokeV3(InvokeV3Transaction),
 }
+
+impl From<InvokeTransaction> for starknet_api::transaction::InvokeTransaction {
+    fn from(tx: InvokeTransaction) -> Self {
+        match tx {
+            InvokeTransaction::InvokeV0(tx) => {
+                Self::V0(starknet_api::transaction::InvokeTransactionV0 {
+                    max_fee: tx.max_fee,
+                    signature: tx.signature,
+                    contract_address: tx.contract_address,
+                    entry_point_selector: tx.entry_point_selector,
+                    calldata: tx.calldata,
+                })
+            }
+            InvokeTransaction::InvokeV1(tx) => {
+                Self::V1(starknet_api::transaction::InvokeTransactionV1 {
+                    max_fee: tx.max_fee,
+                    signature: tx.signature,
+                    nonce: tx.nonce,
+                    sender_address: tx.sender_address,
+                    calldata: tx.calldata,
+                })
+            }
+            InvokeTransaction::InvokeV3(tx) => {
+                Self::V3(starknet_api::transaction::InvokeTransactionV3 {
+                    resource_bounds: tx.resource_bounds,
+                    tip: tx.tip,
+                    signature: tx.signature,
+                    nonce: tx.nonce,
+                    sender_address: tx.sender_address,
+                    calldata: tx.calldata,
+                    nonce_data_availability_mode: tx.nonce_data_availability_mode.into(),
+                    fee_data_availability_mode: tx.fee_data_availability_mode.into(),
+                    paymaster_data: tx.paymaster_data,
+                    account_deployment_data: tx.account_deployment_data,
+                })
+            }
+        }
+    }
+}
 /// A declare transaction of a Cairo-v0 (deprecated) contract class that can be added to Starknet
 /// through the Starknet gateway.
 /// It has a serialization format that the Starknet gateway accepts in the `add_transaction`